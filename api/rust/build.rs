@@ -216,6 +216,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .unwrap(),
                 cs_dir.join("api").join("relay.proto").to_str().unwrap(),
                 cs_dir.join("api").join("fuota.proto").to_str().unwrap(),
+                cs_dir
+                    .join("api")
+                    .join("codec_library.proto")
+                    .to_str()
+                    .unwrap(),
+                cs_dir.join("api").join("roaming.proto").to_str().unwrap(),
+                cs_dir
+                    .join("api")
+                    .join("join_server_route.proto")
+                    .to_str()
+                    .unwrap(),
             ],
             &[
                 proto_dir.join("chirpstack").to_str().unwrap(),
@@ -33,6 +33,10 @@ impl Into<String> for LogCode {
             LogCode::RelayNewEndDevice => "RELAY_NEW_END_DEVICE",
             LogCode::FCntDown => "F_CNT_DOWN",
             LogCode::Expired => "EXPIRED",
+            LogCode::RateLimited => "RATE_LIMITED",
+            LogCode::UplinkFCntAnomaly => "UPLINK_F_CNT_ANOMALY",
+            LogCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            LogCode::DeviceState => "DEVICE_STATE",
         }
         .to_string()
     }
@@ -3,22 +3,36 @@ extern crate anyhow;
 
 use std::fs::File;
 use std::io::Read;
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use aes_kw::Kek;
 use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{DateTime, Utc};
-use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
-use reqwest::{Certificate, Identity};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use opentelemetry_http::HeaderInjector;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Certificate, Identity, StatusCode};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot::Receiver;
-use tracing::{debug, error, info, span, trace, Instrument, Level};
+use tracing::{debug, error, info, span, trace, warn, Instrument, Level};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use chirpstack_api::stream;
 
 const PROTOCOL_VERSION: &str = "1.0";
 
+// Request error in case the request was rejected by the rate limiter.
+pub const RATE_LIMIT_EXCEEDED: &str = "Rate limit exceeded";
+
+// Cached OAuth2 access-tokens are refreshed when they expire within this margin.
+const OAUTH2_TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+// HTTP header containing the detached JWS (RFC 7515, Appendix F) of the request body.
+pub const JWS_SIGNATURE_HEADER: &str = "X-JWS-Signature";
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Role {
     FNS,
@@ -46,6 +60,19 @@ pub struct ClientConfig {
     // include a prefix, like Bearer, Key or Basic.
     pub authorization: Option<String>,
 
+    // OAuth2 client-credentials configuration. When set, an access-token is
+    // requested from the token endpoint and used as Bearer token in the
+    // Authorization header. The token is cached until it expires.
+    pub oauth2: Option<OAuth2Config>,
+
+    // API-key configuration. When set, the API-key is added to each request
+    // using the configured header.
+    pub api_key: Option<ApiKeyConfig>,
+
+    // JWS configuration. When set, outbound messages are signed and / or
+    // inbound messages are verified using a detached JWS.
+    pub jws: Option<JwsConfig>,
+
     // AsyncTimeout defines the async timeout. This must be set when RedisClient
     // is set.
     pub async_timeout: Duration,
@@ -55,6 +82,9 @@ pub struct ClientConfig {
 
     // Request log function.
     pub request_log_sender: Option<Sender<stream::BackendInterfacesRequest>>,
+
+    // Max. number of requests per second. Set to 0 to disable rate limiting.
+    pub max_requests_per_second: u32,
 }
 
 impl Default for ClientConfig {
@@ -67,17 +97,111 @@ impl Default for ClientConfig {
             tls_cert: "".into(),
             tls_key: "".into(),
             authorization: None,
+            oauth2: None,
+            api_key: None,
+            jws: None,
             async_timeout: Duration::from_secs(0),
             use_target_role_suffix: false,
             request_log_sender: None,
+            max_requests_per_second: 0,
         }
     }
 }
 
+#[derive(Clone, Default)]
+pub struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+
+    // Space-delimited list of scopes. This is omitted from the token request
+    // when empty.
+    pub scope: String,
+}
+
+#[derive(Clone, Default)]
+pub struct ApiKeyConfig {
+    // Header name, e.g. X-API-Key.
+    pub header: String,
+    pub key: String,
+}
+
+#[derive(Clone, Default)]
+pub struct JwsConfig {
+    // Algorithm, e.g. HS256, RS256 or ES256.
+    pub algorithm: String,
+
+    // Path to the key for signing outbound messages. This is the PEM encoded
+    // private key, or the shared secret in case of a HMAC algorithm. Signing
+    // is disabled when empty.
+    pub signing_key: String,
+
+    // Path to the key for verifying inbound messages. This is the PEM encoded
+    // public key, or the shared secret in case of a HMAC algorithm.
+    // Verification is disabled when empty.
+    pub verification_key: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JwsHeader {
+    alg: Algorithm,
+}
+
+#[derive(Deserialize)]
+struct OAuth2TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+// RateLimiter implements a fixed-window rate limiter, allowing up to limit
+// requests per second.
+pub struct RateLimiter {
+    limit: u32,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32) -> Self {
+        RateLimiter {
+            limit,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    // Returns true if the request is allowed. A limit of 0 disables rate limiting.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    // Returns true if the request at the given time is allowed.
+    fn allow_at(&self, now: Instant) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+
+        let mut window = self.window.lock().unwrap();
+        if now.saturating_duration_since(window.0) >= Duration::from_secs(1) {
+            *window = (now, 0);
+        }
+
+        if window.1 >= self.limit {
+            return false;
+        }
+
+        window.1 += 1;
+        true
+    }
+}
+
 pub struct Client {
     client: reqwest::Client,
     config: ClientConfig,
     headers: HeaderMap,
+    rate_limiter: RateLimiter,
+    oauth2_token: tokio::sync::Mutex<Option<(String, Option<Instant>)>>,
+    jws_signing_key: Option<(Algorithm, EncodingKey)>,
+    jws_verification_key: Option<(Algorithm, DecodingKey)>,
 }
 
 impl Client {
@@ -87,6 +211,13 @@ impl Client {
         if let Some(auth) = &c.authorization {
             headers.insert(AUTHORIZATION, auth.clone().parse()?);
         }
+        if let Some(api_key) = &c.api_key {
+            headers.insert(
+                HeaderName::from_bytes(api_key.header.as_bytes())
+                    .context("Parse api_key header")?,
+                api_key.key.parse().context("Parse api_key")?,
+            );
+        }
 
         let mut client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(5))
@@ -131,13 +262,93 @@ impl Client {
             trace!("No CA certificate configured");
         }
 
+        let mut jws_signing_key: Option<(Algorithm, EncodingKey)> = None;
+        let mut jws_verification_key: Option<(Algorithm, DecodingKey)> = None;
+        if let Some(jws) = &c.jws {
+            let alg: Algorithm = jws.algorithm.parse().context("Parse JWS algorithm")?;
+
+            if !jws.signing_key.is_empty() {
+                trace!(signing_key = %jws.signing_key, "Reading JWS signing key");
+                let b = std::fs::read(&jws.signing_key).context("Read JWS signing_key")?;
+                jws_signing_key = Some((
+                    alg,
+                    get_jws_encoding_key(alg, &b).context("Parse JWS signing_key")?,
+                ));
+            }
+
+            if !jws.verification_key.is_empty() {
+                trace!(verification_key = %jws.verification_key, "Reading JWS verification key");
+                let b =
+                    std::fs::read(&jws.verification_key).context("Read JWS verification_key")?;
+                jws_verification_key = Some((
+                    alg,
+                    get_jws_decoding_key(alg, &b).context("Parse JWS verification_key")?,
+                ));
+            }
+        }
+
         Ok(Client {
+            rate_limiter: RateLimiter::new(c.max_requests_per_second),
             config: c,
             client: client.build()?,
             headers,
+            oauth2_token: tokio::sync::Mutex::new(None),
+            jws_signing_key,
+            jws_verification_key,
         })
     }
 
+    // Verify the detached JWS of the given (inbound) message body. This returns an error in
+    // case verification is configured, but the signature is missing or invalid.
+    pub fn verify_signature(&self, signature: Option<&str>, body: &[u8]) -> Result<()> {
+        let (alg, key) = match &self.jws_verification_key {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let signature = signature.ok_or_else(|| anyhow!("JWS signature is missing"))?;
+        let parts: Vec<&str> = signature.split('.').collect();
+        if parts.len() != 3 || !parts[1].is_empty() {
+            return Err(anyhow!("JWS signature must be a detached JWS"));
+        }
+
+        let header: JwsHeader = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(parts[0])
+                .context("Decode JWS header")?,
+        )
+        .context("Parse JWS header")?;
+        if header.alg != *alg {
+            return Err(anyhow!(
+                "Unexpected JWS algorithm, expected: {:?}, got: {:?}",
+                alg,
+                header.alg
+            ));
+        }
+
+        let message = format!("{}.{}", parts[0], URL_SAFE_NO_PAD.encode(body));
+        if !jsonwebtoken::crypto::verify(parts[2], message.as_bytes(), key, *alg)? {
+            return Err(anyhow!("Invalid JWS signature"));
+        }
+
+        Ok(())
+    }
+
+    // Returns the detached JWS for the given (outbound) message body, or None in case
+    // signing is not configured.
+    pub fn sign(&self, body: &str) -> Result<Option<String>> {
+        let (alg, key) = match &self.jws_signing_key {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&JwsHeader { alg: *alg })?);
+        let message = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(body));
+        let signature = jsonwebtoken::crypto::sign(message.as_bytes(), key, *alg)?;
+
+        Ok(Some(format!("{}..{}", header, signature)))
+    }
+
     pub fn get_sender_id(&self) -> Vec<u8> {
         self.config.sender_id.clone()
     }
@@ -237,6 +448,46 @@ impl Client {
         self.response_request(Some(target_role), pl).await
     }
 
+    pub async fn hr_start_req(
+        &self,
+        target_role: Role,
+        pl: &mut HRStartReqPayload,
+        async_resp: Option<Receiver<Vec<u8>>>,
+    ) -> Result<HRStartAnsPayload> {
+        pl.base.sender_id.clone_from(&self.config.sender_id);
+        pl.base.receiver_id.clone_from(&self.config.receiver_id);
+        pl.base.message_type = MessageType::HRStartReq;
+
+        let mut ans: HRStartAnsPayload = Default::default();
+        self.request(Some(target_role), &pl, &mut ans, async_resp)
+            .await?;
+        Ok(ans)
+    }
+
+    pub async fn hr_start_ans(&self, target_role: Role, pl: &HRStartAnsPayload) -> Result<()> {
+        self.response_request(Some(target_role), pl).await
+    }
+
+    pub async fn hr_stop_req(
+        &self,
+        target_role: Role,
+        pl: &mut HRStopReqPayload,
+        async_resp: Option<Receiver<Vec<u8>>>,
+    ) -> Result<HRStopAnsPayload> {
+        pl.base.sender_id.clone_from(&self.config.sender_id);
+        pl.base.receiver_id.clone_from(&self.config.receiver_id);
+        pl.base.message_type = MessageType::HRStopReq;
+
+        let mut ans: HRStopAnsPayload = Default::default();
+        self.request(Some(target_role), &pl, &mut ans, async_resp)
+            .await?;
+        Ok(ans)
+    }
+
+    pub async fn hr_stop_ans(&self, target_role: Role, pl: &HRStopAnsPayload) -> Result<()> {
+        self.response_request(Some(target_role), pl).await
+    }
+
     pub async fn home_ns_req(
         &self,
         receiver_id: Vec<u8>,
@@ -280,7 +531,120 @@ impl Client {
     where
         S: ?Sized + serde::ser::Serialize + BasePayloadResultProvider,
     {
-        let server = if self.config.use_target_role_suffix {
+        let server = self.get_server(target_role);
+        let bp = pl.base_payload();
+        let body = serde_json::to_string(&pl)?;
+
+        info!(receiver_id = %hex::encode(&bp.base.receiver_id), transaction_id = bp.base.transaction_id, message_type = ?bp.base.message_type, server = %server, "Making request");
+
+        self.send_answer(target_role, body).await
+    }
+
+    // Send the given (JSON encoded) answer payload to the server. This can be used to re-send
+    // async answers for which the previous attempt failed.
+    pub async fn send_answer(&self, target_role: Option<Role>, body: String) -> Result<()> {
+        let server = self.get_server(target_role);
+        debug!(server = %server, "JSON: {}", body);
+
+        self.post(&server, body).await?;
+        Ok(())
+    }
+
+    async fn post(&self, server: &str, body: String) -> Result<reqwest::Response> {
+        let mut headers = self.get_headers().await?;
+        if let Some(signature) = self.sign(&body)? {
+            headers.insert(JWS_SIGNATURE_HEADER, HeaderValue::from_str(&signature)?);
+        }
+
+        // Propagate the trace context. This is a no-op in case no propagator has been configured.
+        let cx = tracing::Span::current().context();
+        opentelemetry::global::get_text_map_propagator(|p| {
+            p.inject_context(&cx, &mut HeaderInjector(&mut headers))
+        });
+
+        let res = self
+            .client
+            .post(server)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        // The cached access-token might have been revoked, in which case a new token must be
+        // requested for the next request.
+        if res.status() == StatusCode::UNAUTHORIZED && self.config.oauth2.is_some() {
+            warn!("Request unauthorized, clearing cached OAuth2 access-token");
+            *self.oauth2_token.lock().await = None;
+        }
+
+        Ok(res.error_for_status()?)
+    }
+
+    async fn get_headers(&self) -> Result<HeaderMap> {
+        let mut headers = self.headers.clone();
+        if self.config.oauth2.is_some() {
+            let token = self.get_oauth2_token().await?;
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token))?,
+            );
+        }
+        Ok(headers)
+    }
+
+    // Returns the cached OAuth2 access-token, or requests a new access-token using the
+    // client-credentials grant in case it is not cached or about to expire.
+    async fn get_oauth2_token(&self) -> Result<String> {
+        let conf = match &self.config.oauth2 {
+            Some(v) => v,
+            None => return Err(anyhow!("OAuth2 is not configured")),
+        };
+
+        let mut token = self.oauth2_token.lock().await;
+        if let Some((access_token, expires_at)) = token.as_ref() {
+            match expires_at {
+                Some(expires_at)
+                    if expires_at.saturating_duration_since(Instant::now())
+                        <= OAUTH2_TOKEN_EXPIRY_MARGIN => {}
+                _ => return Ok(access_token.clone()),
+            }
+        }
+
+        info!(token_url = %conf.token_url, "Requesting OAuth2 access-token");
+
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", conf.client_id.as_str()),
+            ("client_secret", conf.client_secret.as_str()),
+        ];
+        if !conf.scope.is_empty() {
+            form.push(("scope", conf.scope.as_str()));
+        }
+
+        let resp: OAuth2TokenResponse = self
+            .client
+            .post(&conf.token_url)
+            .form(&form)
+            .send()
+            .await
+            .context("OAuth2 token request")?
+            .error_for_status()
+            .context("OAuth2 token request")?
+            .json()
+            .await
+            .context("Decode OAuth2 token response")?;
+
+        *token = Some((
+            resp.access_token.clone(),
+            resp.expires_in
+                .map(|v| Instant::now() + Duration::from_secs(v)),
+        ));
+
+        Ok(resp.access_token)
+    }
+
+    fn get_server(&self, target_role: Option<Role>) -> String {
+        if self.config.use_target_role_suffix {
             match target_role {
                 Some(Role::FNS) => format!("{}/fns", self.config.server),
                 Some(Role::SNS) => format!("{}/sns", self.config.server),
@@ -289,22 +653,7 @@ impl Client {
             }
         } else {
             self.config.server.clone()
-        };
-
-        let bp = pl.base_payload();
-        let body = serde_json::to_string(&pl)?;
-
-        info!(receiver_id = %hex::encode(&bp.base.receiver_id), transaction_id = bp.base.transaction_id, message_type = ?bp.base.message_type, server = %server, "Making request");
-        debug!("JSON: {}", body);
-
-        self.client
-            .post(&server)
-            .headers(self.headers.clone())
-            .body(body)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        }
     }
 
     async fn request<S, D>(
@@ -331,10 +680,16 @@ impl Client {
 
         let span = span!(Level::INFO, "request", message_type = ?bp.message_type, sender_id = %be_req_log.sender_id, receiver_id = %be_req_log.receiver_id, transaction_id = bp.transaction_id);
 
-        let res = self
-            ._request(target_role, pl, ans, async_resp, &mut be_req_log)
-            .instrument(span)
-            .await;
+        let start = Instant::now();
+        let res = if self.rate_limiter.allow() {
+            self._request(target_role, pl, ans, async_resp, &mut be_req_log)
+                .instrument(span)
+                .await
+        } else {
+            warn!(message_type = ?bp.message_type, receiver_id = %be_req_log.receiver_id, "Rate limit exceeded");
+            Err(anyhow!(RATE_LIMIT_EXCEEDED))
+        };
+        be_req_log.duration = Some(start.elapsed().into());
 
         if let Err(e) = &res {
             be_req_log.request_error = format!("{:#}", e);
@@ -364,30 +719,13 @@ impl Client {
         S: ?Sized + serde::ser::Serialize + BasePayloadProvider,
         D: serde::de::DeserializeOwned + BasePayloadResultProvider,
     {
-        let server = if self.config.use_target_role_suffix {
-            match target_role {
-                Some(Role::FNS) => format!("{}/fns", self.config.server),
-                Some(Role::SNS) => format!("{}/sns", self.config.server),
-                Some(Role::HNS) => format!("{}/hns", self.config.server),
-                None => self.config.server.clone(),
-            }
-        } else {
-            self.config.server.clone()
-        };
-
+        let server = self.get_server(target_role);
         let body = serde_json::to_string(&pl)?;
         be_req_log.request_body.clone_from(&body);
 
         info!(server = %server, async_interface = %async_resp.is_some(), "Making request");
 
-        let res = self
-            .client
-            .post(&server)
-            .headers(self.headers.clone())
-            .body(body)
-            .send()
-            .await?
-            .error_for_status()?;
+        let res = self.post(&server, body).await?;
 
         let resp_json = match async_resp {
             Some(rx) => {
@@ -404,7 +742,18 @@ impl Client {
                     }
                 }
             }
-            None => res.text().await?,
+            None => {
+                // The sync answer must be verified before it is applied.
+                let signature = res
+                    .headers()
+                    .get(JWS_SIGNATURE_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string());
+                let body = res.text().await?;
+                self.verify_signature(signature.as_deref(), body.as_bytes())
+                    .context("Verify answer signature")?;
+                body
+            }
         };
 
         be_req_log.response_body.clone_from(&resp_json);
@@ -427,6 +776,34 @@ impl Client {
     }
 }
 
+fn get_jws_encoding_key(alg: Algorithm, b: &[u8]) -> Result<EncodingKey> {
+    Ok(match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => EncodingKey::from_secret(b),
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => EncodingKey::from_rsa_pem(b)?,
+        Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(b)?,
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(b)?,
+    })
+}
+
+fn get_jws_decoding_key(alg: Algorithm, b: &[u8]) -> Result<DecodingKey> {
+    Ok(match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => DecodingKey::from_secret(b),
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => DecodingKey::from_rsa_pem(b)?,
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(b)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(b)?,
+    })
+}
+
 #[derive(Default, Serialize, Deserialize, PartialEq, Eq, Debug, Copy, Clone)]
 pub enum MessageType {
     #[default]
@@ -440,6 +817,10 @@ pub enum MessageType {
     PRStartAns,
     PRStopReq,
     PRStopAns,
+    HRStartReq,
+    HRStartAns,
+    HRStopReq,
+    HRStopAns,
     HomeNSReq,
     HomeNSAns,
     XmitDataReq,
@@ -521,6 +902,8 @@ impl BasePayload {
                 message_type: match self.message_type {
                     MessageType::PRStartReq => MessageType::PRStartAns,
                     MessageType::PRStopReq => MessageType::PRStopAns,
+                    MessageType::HRStartReq => MessageType::HRStartAns,
+                    MessageType::HRStopReq => MessageType::HRStopAns,
                     MessageType::XmitDataReq => MessageType::XmitDataAns,
                     MessageType::HomeNSReq => MessageType::HomeNSAns,
                     _ => self.message_type,
@@ -542,6 +925,8 @@ impl BasePayload {
             | MessageType::AppSKeyAns
             | MessageType::PRStartAns
             | MessageType::PRStopAns
+            | MessageType::HRStartAns
+            | MessageType::HRStopAns
             | MessageType::HomeNSAns
             | MessageType::XmitDataAns => true,
 
@@ -550,6 +935,8 @@ impl BasePayload {
             | MessageType::AppSKeyReq
             | MessageType::PRStartReq
             | MessageType::PRStopReq
+            | MessageType::HRStartReq
+            | MessageType::HRStopReq
             | MessageType::HomeNSReq
             | MessageType::XmitDataReq => false,
         }
@@ -887,6 +1274,103 @@ impl BasePayloadResultProvider for PRStopAnsPayload {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct HRStartReqPayload {
+    #[serde(flatten)]
+    pub base: BasePayload,
+    #[serde(rename = "MACVersion")]
+    pub mac_version: String,
+    #[serde(rename = "PHYPayload", with = "hex_encode")]
+    pub phy_payload: Vec<u8>,
+    #[serde(rename = "ULMetaData")]
+    pub ul_meta_data: ULMetaData,
+    #[serde(rename = "DevAddr", with = "hex_encode")]
+    pub dev_addr: Vec<u8>,
+    #[serde(rename = "DLSettings", with = "hex_encode")]
+    pub dl_settings: Vec<u8>,
+    #[serde(rename = "RxDelay")]
+    pub rx_delay: u8,
+    #[serde(
+        default,
+        rename = "CFList",
+        with = "hex_encode",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub cf_list: Vec<u8>,
+}
+
+impl BasePayloadProvider for &mut HRStartReqPayload {
+    fn base_payload(&self) -> &BasePayload {
+        &self.base
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+pub struct HRStartAnsPayload {
+    #[serde(flatten)]
+    pub base: BasePayloadResult,
+    #[serde(
+        default,
+        rename = "PHYPayload",
+        with = "hex_encode",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub phy_payload: Vec<u8>,
+    #[serde(
+        default,
+        rename = "DevEUI",
+        with = "hex_encode",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub dev_eui: Vec<u8>,
+    #[serde(rename = "Lifetime", skip_serializing_if = "Option::is_none")]
+    pub lifetime: Option<usize>,
+    #[serde(rename = "SNwkSIntKey", skip_serializing_if = "Option::is_none")]
+    pub s_nwk_s_int_key: Option<KeyEnvelope>,
+    #[serde(rename = "FNwkSIntKey", skip_serializing_if = "Option::is_none")]
+    pub f_nwk_s_int_key: Option<KeyEnvelope>,
+    #[serde(rename = "NwkSEncKey", skip_serializing_if = "Option::is_none")]
+    pub nwk_s_enc_key: Option<KeyEnvelope>,
+    #[serde(rename = "NwkSKey", skip_serializing_if = "Option::is_none")]
+    pub nwk_s_key: Option<KeyEnvelope>,
+    #[serde(rename = "ServiceProfile", skip_serializing_if = "Option::is_none")]
+    pub service_profile: Option<ServiceProfile>,
+    #[serde(rename = "DLMetaData", skip_serializing_if = "Option::is_none")]
+    pub dl_meta_data: Option<DLMetaData>,
+}
+
+impl BasePayloadResultProvider for HRStartAnsPayload {
+    fn base_payload(&self) -> &BasePayloadResult {
+        &self.base
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone)]
+pub struct HRStopReqPayload {
+    #[serde(flatten)]
+    pub base: BasePayload,
+    #[serde(rename = "DevEUI", with = "hex_encode")]
+    pub dev_eui: Vec<u8>,
+}
+
+impl BasePayloadProvider for &mut HRStopReqPayload {
+    fn base_payload(&self) -> &BasePayload {
+        &self.base
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone)]
+pub struct HRStopAnsPayload {
+    #[serde(flatten)]
+    pub base: BasePayloadResult,
+}
+
+impl BasePayloadResultProvider for HRStopAnsPayload {
+    fn base_payload(&self) -> &BasePayloadResult {
+        &self.base
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
 pub struct XmitDataReqPayload {
     #[serde(flatten)]
@@ -1232,6 +1716,27 @@ pub mod test {
         assert_eq!(key, ke.unwrap(&kek).unwrap());
     }
 
+    #[test]
+    fn test_rate_limiter() {
+        // disabled
+        let rl = RateLimiter::new(0);
+        for _ in 0..100 {
+            assert!(rl.allow());
+        }
+
+        let now = Instant::now();
+        let rl = RateLimiter::new(2);
+        assert!(rl.allow_at(now));
+        assert!(rl.allow_at(now + Duration::from_millis(500)));
+        assert!(!rl.allow_at(now + Duration::from_millis(900)));
+
+        // next window
+        let now = now + Duration::from_millis(1100);
+        assert!(rl.allow_at(now));
+        assert!(rl.allow_at(now));
+        assert!(!rl.allow_at(now));
+    }
+
     #[tokio::test]
     async fn test_async_request() {
         let server = MockServer::start();
@@ -1433,6 +1938,293 @@ pub mod test {
         assert!(resp.is_err());
     }
 
+    #[tokio::test]
+    async fn test_send_answer() {
+        let server = MockServer::start();
+
+        let c = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url(""),
+            use_target_role_suffix: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let body = r#"{"MessageType":"PRStartAns"}"#.to_string();
+
+        // OK
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/fns").body(&body);
+            then.status(200);
+        });
+        c.send_answer(Some(Role::FNS), body.clone()).await.unwrap();
+        mock.assert();
+        mock.delete();
+
+        // Error status
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/sns").body(&body);
+            then.status(500);
+        });
+        let resp = c.send_answer(Some(Role::SNS), body.clone()).await;
+        mock.assert();
+        mock.delete();
+        assert!(resp.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oauth2() {
+        let server = MockServer::start();
+
+        let c = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url("/"),
+            oauth2: Some(OAuth2Config {
+                token_url: server.url("/token"),
+                client_id: "foo".into(),
+                client_secret: "bar".into(),
+                scope: "backend".into(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let body = r#"{"MessageType":"PRStartAns"}"#.to_string();
+
+        let mut token_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/token")
+                .x_www_form_urlencoded_tuple("grant_type", "client_credentials")
+                .x_www_form_urlencoded_tuple("client_id", "foo")
+                .x_www_form_urlencoded_tuple("client_secret", "bar")
+                .x_www_form_urlencoded_tuple("scope", "backend");
+            then.status(200)
+                .body(r#"{"access_token":"token-1","token_type":"Bearer","expires_in":3600}"#);
+        });
+        let mut mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("Authorization", "Bearer token-1");
+            then.status(200);
+        });
+
+        // The token is requested once and cached.
+        c.send_answer(None, body.clone()).await.unwrap();
+        c.send_answer(None, body.clone()).await.unwrap();
+        token_mock.assert_hits(1);
+        mock.assert_hits(2);
+        mock.delete();
+
+        // The cached token is cleared on unauthorized.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(401);
+        });
+        assert!(c.send_answer(None, body.clone()).await.is_err());
+        mock.delete();
+        assert!(c.oauth2_token.lock().await.is_none());
+        token_mock.delete();
+
+        // Token request error.
+        let mut token_mock = server.mock(|when, then| {
+            when.method(POST).path("/token");
+            then.status(401);
+        });
+        assert!(c.send_answer(None, body.clone()).await.is_err());
+        token_mock.assert();
+        token_mock.delete();
+    }
+
+    #[tokio::test]
+    async fn test_jws() {
+        let server = MockServer::start();
+
+        let key_path = std::env::temp_dir().join("backend_test_jws.key");
+        std::fs::write(&key_path, "secret").unwrap();
+        let key_path = key_path.to_str().unwrap().to_string();
+
+        let c = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url("/"),
+            jws: Some(JwsConfig {
+                algorithm: "HS256".into(),
+                signing_key: key_path.clone(),
+                verification_key: key_path.clone(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let body = r#"{"MessageType":"PRStartAns"}"#.to_string();
+        let signature = c.sign(&body).unwrap().unwrap();
+
+        // The signature is detached.
+        let parts: Vec<&str> = signature.split('.').collect();
+        assert_eq!(3, parts.len());
+        assert!(parts[1].is_empty());
+
+        // Outbound message is signed.
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header(JWS_SIGNATURE_HEADER, &signature);
+            then.status(200);
+        });
+        c.send_answer(None, body.clone()).await.unwrap();
+        mock.assert();
+
+        // Valid signature.
+        c.verify_signature(Some(&signature), body.as_bytes())
+            .unwrap();
+
+        // Missing signature.
+        assert!(c.verify_signature(None, body.as_bytes()).is_err());
+
+        // Modified body.
+        assert!(c
+            .verify_signature(Some(&signature), br#"{"MessageType":"PRStopAns"}"#)
+            .is_err());
+
+        // Signature using a different key.
+        let other_key_path = std::env::temp_dir().join("backend_test_jws_other.key");
+        std::fs::write(&other_key_path, "other-secret").unwrap();
+        let c_other = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url("/"),
+            jws: Some(JwsConfig {
+                algorithm: "HS256".into(),
+                signing_key: other_key_path.to_str().unwrap().to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let other_signature = c_other.sign(&body).unwrap().unwrap();
+        assert!(c
+            .verify_signature(Some(&other_signature), body.as_bytes())
+            .is_err());
+
+        // Verification not configured.
+        c_other.verify_signature(None, body.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_jws_sync_answer() {
+        let server = MockServer::start();
+
+        let key_path = std::env::temp_dir().join("backend_test_jws_sync.key");
+        std::fs::write(&key_path, "secret").unwrap();
+        let key_path = key_path.to_str().unwrap().to_string();
+
+        let c = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url("/"),
+            jws: Some(JwsConfig {
+                algorithm: "HS256".into(),
+                signing_key: key_path.clone(),
+                verification_key: key_path.clone(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut req = HomeNSReqPayload {
+            base: BasePayload {
+                sender_id: vec![1, 2, 3],
+                receiver_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                message_type: MessageType::HomeNSReq,
+                transaction_id: 1234,
+                ..Default::default()
+            },
+            dev_eui: vec![8, 7, 6, 5, 4, 3, 2, 1],
+        };
+
+        let ans = HomeNSAnsPayload {
+            base: BasePayloadResult {
+                base: BasePayload {
+                    sender_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                    receiver_id: vec![1, 2, 3],
+                    message_type: MessageType::HomeNSAns,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                result: ResultPayload {
+                    result_code: ResultCode::Success,
+                    description: "".into(),
+                },
+            },
+            h_net_id: vec![3, 2, 1],
+        };
+        let ans_body = serde_json::to_string(&ans).unwrap();
+        let signature = c.sign(&ans_body).unwrap().unwrap();
+
+        // Signed answer.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.header(JWS_SIGNATURE_HEADER, &signature)
+                .body(&ans_body)
+                .status(200);
+        });
+        let resp = c
+            .home_ns_req(vec![1, 2, 3, 4, 5, 6, 7, 8], &mut req, None)
+            .await
+            .unwrap();
+        mock.assert();
+        mock.delete();
+        assert_eq!(resp, ans);
+
+        // Unsigned answer.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.body(&ans_body).status(200);
+        });
+        let resp = c
+            .home_ns_req(vec![1, 2, 3, 4, 5, 6, 7, 8], &mut req, None)
+            .await;
+        mock.assert();
+        mock.delete();
+        assert!(resp.is_err());
+
+        // Modified answer.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.header(JWS_SIGNATURE_HEADER, &signature)
+                .body(ans_body.replace("030201", "010203"))
+                .status(200);
+        });
+        let resp = c
+            .home_ns_req(vec![1, 2, 3, 4, 5, 6, 7, 8], &mut req, None)
+            .await;
+        mock.assert();
+        mock.delete();
+        assert!(resp.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_api_key() {
+        let server = MockServer::start();
+
+        let c = Client::new(ClientConfig {
+            sender_id: vec![1, 2, 3],
+            server: server.url("/"),
+            api_key: Some(ApiKeyConfig {
+                header: "X-API-Key".into(),
+                key: "secret".into(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let body = r#"{"MessageType":"PRStartAns"}"#.to_string();
+
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/").header("X-API-Key", "secret");
+            then.status(200);
+        });
+        c.send_answer(None, body).await.unwrap();
+        mock.assert();
+    }
+
     #[tokio::test]
     async fn test_log_fn_ok() {
         let (tx, mut rx) = mpsc::channel(1);
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chirpstack::bench::js_decode;
+use chirpstack::config;
+
+const DECODER: &str = r#"
+    function decodeUplink(input) {
+        var buff = new Buffer(input.bytes);
+
+        return {
+            data: {
+                temperature: buff.readInt16BE(0) / 100,
+                humidity: buff.readUInt8(2)
+            }
+        };
+    }
+"#;
+
+fn setup(pool_size: usize) {
+    let mut conf: config::Configuration = Default::default();
+    conf.codec.js.pool_size = pool_size;
+    config::set(conf);
+}
+
+// Benchmarks the JS decode, using the pooled (pre-initialized) runtimes and without pool, in
+// which case the runtime and context are set up on each execution.
+fn bench_js_decode(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let vars: HashMap<String, String> = HashMap::new();
+    let libraries: HashMap<String, String> = HashMap::new();
+    let b = vec![0x08, 0x34, 0x32];
+
+    setup(16);
+    c.bench_function("js_decode_pooled", |bench| {
+        bench.to_async(&rt).iter(|| {
+            js_decode(
+                Utc::now(),
+                10,
+                &vars,
+                &libraries,
+                black_box(DECODER),
+                black_box(&b),
+            )
+        })
+    });
+
+    setup(0);
+    c.bench_function("js_decode_unpooled", |bench| {
+        bench.to_async(&rt).iter(|| {
+            js_decode(
+                Utc::now(),
+                10,
+                &vars,
+                &libraries,
+                black_box(DECODER),
+                black_box(&b),
+            )
+        })
+    });
+}
+
+criterion_group!(benches, bench_js_decode);
+criterion_main!(benches);
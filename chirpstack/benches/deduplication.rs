@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use chirpstack::bench::{deduplicate_collect, deduplicate_put, reset_storage};
+use chirpstack_api::gw;
+
+fn uplink_frames(count: usize) -> Vec<gw::UplinkFrame> {
+    (0..count)
+        .map(|i| gw::UplinkFrame {
+            phy_payload: vec![
+                0x40, 0x04, 0x03, 0x02, 0x01, 0x80, 0x0a, 0x00, 0x01, 0x01, 0x02, 0x03, 0x01, 0x02,
+                0x03, 0x04,
+            ],
+            tx_info: Some(gw::UplinkTxInfo {
+                frequency: 868100000,
+                ..Default::default()
+            }),
+            rx_info: Some(gw::UplinkRxInfo {
+                gateway_id: format!("{:016x}", i),
+                rssi: -80,
+                snr: 5.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .collect()
+}
+
+// Benchmarks the deduplication of the same uplink, received by an increasing number of gateways.
+// The storage is reset on each iteration, as the collect set would otherwise already contain the
+// frames.
+fn bench_deduplication(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let ttl = Duration::from_secs(1);
+
+    let mut group = c.benchmark_group("deduplication");
+    for gateways in [1, 5, 25] {
+        let frames = uplink_frames(gateways);
+        let key = "up:collect:{eu868}";
+        let lock_key = "up:collect:{eu868}:lock";
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(gateways),
+            &frames,
+            |b, frames| {
+                b.to_async(&rt).iter(|| async {
+                    reset_storage();
+
+                    for frame in frames {
+                        deduplicate_put(key, lock_key, ttl, black_box(frame))
+                            .await
+                            .unwrap();
+                    }
+
+                    deduplicate_collect(key).await.unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_deduplication);
+criterion_main!(benches);
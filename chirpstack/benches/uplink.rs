@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chirpstack::adr::{self, Handler};
+use chirpstack::{config, region};
+use chirpstack_api::internal;
+
+fn setup() {
+    let mut conf: config::Configuration = Default::default();
+    conf.network.enabled_regions = vec!["eu868".to_string()];
+    conf.regions = vec![config::Region {
+        id: "eu868".to_string(),
+        common_name: lrwn::region::CommonName::EU868,
+        ..Default::default()
+    }];
+    config::set(conf);
+    region::setup().unwrap();
+}
+
+fn adr_request() -> adr::Request {
+    adr::Request {
+        region_config_id: "eu868".into(),
+        region_common_name: lrwn::region::CommonName::EU868,
+        dev_eui: lrwn::EUI64::from_str("0102030405060708").unwrap(),
+        mac_version: lrwn::region::MacVersion::LORAWAN_1_0_4,
+        reg_params_revision: lrwn::region::Revision::RP002_1_0_3,
+        adr: true,
+        dr: 0,
+        tx_power_index: 0,
+        nb_trans: 1,
+        max_tx_power_index: 7,
+        required_snr_for_dr: -20.0,
+        installation_margin: 10.0,
+        min_dr: 0,
+        max_dr: 5,
+        uplink_history: (0..20)
+            .map(|i| internal::UplinkAdrHistory {
+                f_cnt: i,
+                max_snr: 5.0,
+                max_rssi: -80,
+                tx_power_index: 0,
+                gateway_count: 1,
+            })
+            .collect(),
+        skip_f_cnt_check: false,
+        device_variables: Default::default(),
+    }
+}
+
+fn bench_adr(c: &mut Criterion) {
+    setup();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let req = adr_request();
+
+    let algo = adr::default::Algorithm::new();
+    c.bench_function("adr_default", |b| {
+        b.to_async(&rt).iter(|| algo.handle(black_box(&req)))
+    });
+
+    let algo = adr::lora_lr_fhss::Algorithm::new();
+    c.bench_function("adr_lora_lr_fhss", |b| {
+        b.to_async(&rt).iter(|| algo.handle(black_box(&req)))
+    });
+}
+
+criterion_group!(benches, bench_adr);
+criterion_main!(benches);
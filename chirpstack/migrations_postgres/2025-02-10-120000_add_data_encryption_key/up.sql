@@ -0,0 +1,6 @@
+create table data_encryption_key (
+  id integer primary key,
+  created_at timestamp with time zone not null,
+  backend varchar(20) not null,
+  encrypted_key bytea not null
+);
@@ -0,0 +1,5 @@
+drop index idx_relay_gateway_border_gateway_id;
+
+alter table relay_gateway
+  drop column relay_path,
+  drop column border_gateway_id;
@@ -0,0 +1,8 @@
+alter table relay_gateway
+  add column border_gateway_id bytea null,
+  add column relay_path jsonb not null default '[]';
+
+alter table relay_gateway
+  alter column relay_path drop default;
+
+create index idx_relay_gateway_border_gateway_id on relay_gateway (border_gateway_id);
@@ -0,0 +1,10 @@
+create table gateway_certificate (
+  serial_number bytea primary key,
+  gateway_id bytea not null,
+  created_at timestamp with time zone not null,
+  expires_at timestamp with time zone not null,
+  revoked_at timestamp with time zone null
+);
+
+create index idx_gateway_certificate_gateway_id on gateway_certificate (gateway_id);
+create index idx_gateway_certificate_expires_at on gateway_certificate (expires_at);
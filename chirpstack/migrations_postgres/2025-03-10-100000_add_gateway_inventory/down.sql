@@ -0,0 +1,10 @@
+drop index idx_gateway_gateway_bridge_version;
+drop index idx_gateway_concentratord_version;
+drop index idx_gateway_hal_version;
+drop index idx_gateway_model;
+
+alter table gateway
+  drop column gateway_bridge_version,
+  drop column concentratord_version,
+  drop column hal_version,
+  drop column model;
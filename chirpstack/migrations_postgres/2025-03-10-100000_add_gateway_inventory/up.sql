@@ -0,0 +1,10 @@
+alter table gateway
+  add column model varchar(100) not null default '',
+  add column hal_version varchar(100) not null default '',
+  add column concentratord_version varchar(100) not null default '',
+  add column gateway_bridge_version varchar(100) not null default '';
+
+create index idx_gateway_model on gateway (model);
+create index idx_gateway_hal_version on gateway (hal_version);
+create index idx_gateway_concentratord_version on gateway (concentratord_version);
+create index idx_gateway_gateway_bridge_version on gateway (gateway_bridge_version);
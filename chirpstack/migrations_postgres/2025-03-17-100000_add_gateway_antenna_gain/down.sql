@@ -0,0 +1,3 @@
+alter table gateway
+  drop column cable_loss,
+  drop column antenna_gain;
@@ -0,0 +1,3 @@
+alter table gateway
+  add column antenna_gain real not null default 0,
+  add column cable_loss real not null default 0;
@@ -0,0 +1,11 @@
+create table codec_library (
+  id uuid primary key,
+  tenant_id uuid not null references tenant on delete cascade,
+  created_at timestamp with time zone not null,
+  updated_at timestamp with time zone not null,
+  name varchar(100) not null,
+  description text not null,
+  script text not null
+);
+
+create unique index idx_codec_library_tenant_id_name on codec_library (tenant_id, name);
@@ -0,0 +1,15 @@
+create table roaming_usage (
+  day date not null,
+  net_id varchar(6) not null,
+  role varchar(3) not null,
+  dev_eui bytea not null,
+  dev_addr bytea not null,
+  uplink_count bigint not null,
+  downlink_count bigint not null,
+  uplink_airtime_ms bigint not null,
+  downlink_airtime_ms bigint not null,
+  updated_at timestamp with time zone not null,
+  primary key (day, net_id, role, dev_eui, dev_addr)
+);
+
+create index idx_roaming_usage_net_id on roaming_usage (net_id);
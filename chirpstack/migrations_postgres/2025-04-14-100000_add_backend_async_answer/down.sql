@@ -0,0 +1 @@
+drop table backend_async_answer;
@@ -0,0 +1,22 @@
+create table roaming_agreement (
+  id uuid primary key,
+  created_at timestamp with time zone not null,
+  updated_at timestamp with time zone not null,
+  name varchar(100) not null,
+  net_id varchar(6) not null,
+  server varchar(500) not null,
+  use_target_role_suffix boolean not null,
+  async_timeout integer not null,
+  passive_roaming_lifetime integer not null,
+  passive_roaming_kek_label varchar(100) not null,
+  passive_roaming_validate_mic boolean not null,
+  handover_roaming_lifetime integer not null,
+  handover_roaming_kek_label varchar(100) not null,
+  ca_cert text not null,
+  tls_cert text not null,
+  tls_key text not null,
+  authorization_header text not null,
+  max_requests_per_second integer not null
+);
+
+create unique index idx_roaming_agreement_net_id on roaming_agreement (net_id);
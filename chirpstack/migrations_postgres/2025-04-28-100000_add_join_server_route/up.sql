@@ -0,0 +1,15 @@
+create table join_server_route (
+  id uuid primary key,
+  created_at timestamp with time zone not null,
+  updated_at timestamp with time zone not null,
+  name varchar(100) not null,
+  join_eui_prefix varchar(20) not null,
+  server varchar(500) not null,
+  async_timeout integer not null,
+  ca_cert text not null,
+  tls_cert text not null,
+  tls_key text not null,
+  authorization_header text not null
+);
+
+create unique index idx_join_server_route_join_eui_prefix on join_server_route (join_eui_prefix);
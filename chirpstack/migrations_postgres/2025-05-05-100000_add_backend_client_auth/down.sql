@@ -0,0 +1,15 @@
+alter table join_server_route
+  drop column api_key,
+  drop column api_key_header,
+  drop column oauth2_scope,
+  drop column oauth2_client_secret,
+  drop column oauth2_client_id,
+  drop column oauth2_token_url;
+
+alter table roaming_agreement
+  drop column api_key,
+  drop column api_key_header,
+  drop column oauth2_scope,
+  drop column oauth2_client_secret,
+  drop column oauth2_client_id,
+  drop column oauth2_token_url;
@@ -0,0 +1,15 @@
+alter table roaming_agreement
+  add column oauth2_token_url text not null default '',
+  add column oauth2_client_id text not null default '',
+  add column oauth2_client_secret text not null default '',
+  add column oauth2_scope text not null default '',
+  add column api_key_header text not null default '',
+  add column api_key text not null default '';
+
+alter table join_server_route
+  add column oauth2_token_url text not null default '',
+  add column oauth2_client_id text not null default '',
+  add column oauth2_client_secret text not null default '',
+  add column oauth2_scope text not null default '',
+  add column api_key_header text not null default '',
+  add column api_key text not null default '';
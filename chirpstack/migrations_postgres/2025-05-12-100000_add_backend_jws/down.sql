@@ -0,0 +1,9 @@
+alter table join_server_route
+  drop column jws_verification_key,
+  drop column jws_signing_key,
+  drop column jws_algorithm;
+
+alter table roaming_agreement
+  drop column jws_verification_key,
+  drop column jws_signing_key,
+  drop column jws_algorithm;
@@ -0,0 +1,12 @@
+create table tenant_sla_report (
+  tenant_id uuid not null references tenant on delete cascade,
+  month date not null,
+  uplink_count bigint not null,
+  uplink_success_count bigint not null,
+  downlink_count bigint not null,
+  downlink_success_count bigint not null,
+  api_request_count bigint not null,
+  api_success_count bigint not null,
+  updated_at timestamp with time zone not null,
+  primary key (tenant_id, month)
+);
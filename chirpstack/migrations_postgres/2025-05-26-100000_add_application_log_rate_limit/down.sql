@@ -0,0 +1,2 @@
+alter table application
+  drop column log_rate_limit;
@@ -0,0 +1,5 @@
+alter table application
+  add column log_rate_limit integer not null default 0;
+
+alter table application
+  alter column log_rate_limit drop default;
@@ -0,0 +1,5 @@
+alter table tenant
+  add column use_hsm boolean not null default false;
+
+alter table tenant
+  alter column use_hsm drop default;
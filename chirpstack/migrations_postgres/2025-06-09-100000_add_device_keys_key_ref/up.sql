@@ -0,0 +1,5 @@
+alter table device_keys
+  add column key_ref varchar(100) not null default '';
+
+alter table device_keys
+  alter column key_ref drop default;
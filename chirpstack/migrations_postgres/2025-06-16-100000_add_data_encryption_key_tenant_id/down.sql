@@ -0,0 +1,4 @@
+drop index idx_data_encryption_key_tenant_id;
+
+alter table data_encryption_key
+  drop column tenant_id;
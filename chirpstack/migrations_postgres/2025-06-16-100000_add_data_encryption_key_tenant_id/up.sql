@@ -0,0 +1,4 @@
+alter table data_encryption_key
+  add column tenant_id uuid null references tenant on delete cascade;
+
+create index idx_data_encryption_key_tenant_id on data_encryption_key (tenant_id);
@@ -0,0 +1,3 @@
+drop index idx_user_session_expires_at;
+drop index idx_user_session_user_id;
+drop table user_session;
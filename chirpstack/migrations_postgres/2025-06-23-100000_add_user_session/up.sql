@@ -0,0 +1,10 @@
+create table user_session (
+  id uuid primary key,
+  user_id uuid not null references "user" on delete cascade,
+  created_at timestamp with time zone not null,
+  expires_at timestamp with time zone not null,
+  revoked_at timestamp with time zone null
+);
+
+create index idx_user_session_user_id on user_session (user_id);
+create index idx_user_session_expires_at on user_session (expires_at);
@@ -0,0 +1,21 @@
+create table tenant_role (
+  id uuid primary key,
+  tenant_id uuid not null references tenant on delete cascade,
+  created_at timestamp with time zone not null,
+  updated_at timestamp with time zone not null,
+  name varchar(100) not null,
+  description text not null
+);
+
+create index idx_tenant_role_tenant_id on tenant_role (tenant_id);
+
+create table tenant_role_permission (
+  role_id uuid not null references tenant_role on delete cascade,
+  permission varchar(50) not null,
+  primary key (role_id, permission)
+);
+
+alter table tenant_user
+  add column role_id uuid null references tenant_role on delete set null;
+
+create index idx_tenant_user_role_id on tenant_user (role_id);
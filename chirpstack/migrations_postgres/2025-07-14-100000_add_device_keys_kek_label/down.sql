@@ -0,0 +1,4 @@
+alter table device_keys
+  drop column wrapped_app_key,
+  drop column wrapped_nwk_key,
+  drop column kek_label;
@@ -0,0 +1,9 @@
+alter table device_keys
+  add column kek_label varchar(100) not null default '',
+  add column wrapped_nwk_key bytea not null default '',
+  add column wrapped_app_key bytea not null default '';
+
+alter table device_keys
+  alter column kek_label drop default,
+  alter column wrapped_nwk_key drop default,
+  alter column wrapped_app_key drop default;
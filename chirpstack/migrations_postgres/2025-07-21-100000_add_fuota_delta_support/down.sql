@@ -0,0 +1,9 @@
+drop table fuota_deployment_delta;
+
+alter table fuota_deployment_device
+  drop column full_image_required,
+  drop column target_version,
+  drop column base_version;
+
+alter table fuota_deployment
+  drop column payload_version;
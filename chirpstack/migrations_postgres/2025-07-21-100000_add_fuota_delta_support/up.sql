@@ -0,0 +1,24 @@
+alter table fuota_deployment
+  add column payload_version varchar(100) not null default '';
+
+alter table fuota_deployment
+  alter column payload_version drop default;
+
+alter table fuota_deployment_device
+  add column base_version varchar(100) not null default '',
+  add column target_version varchar(100) not null default '',
+  add column full_image_required boolean not null default false;
+
+alter table fuota_deployment_device
+  alter column base_version drop default,
+  alter column target_version drop default,
+  alter column full_image_required drop default;
+
+create table fuota_deployment_delta (
+  fuota_deployment_id uuid not null references fuota_deployment on delete cascade,
+  base_version varchar(100) not null,
+  created_at timestamp with time zone not null,
+  payload bytea not null,
+
+  primary key (fuota_deployment_id, base_version)
+);
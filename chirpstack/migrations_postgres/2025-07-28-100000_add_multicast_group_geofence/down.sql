@@ -0,0 +1,5 @@
+alter table multicast_group
+  drop column gateway_tags,
+  drop column geofence_radius,
+  drop column geofence_longitude,
+  drop column geofence_latitude;
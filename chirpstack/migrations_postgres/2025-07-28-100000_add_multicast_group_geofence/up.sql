@@ -0,0 +1,11 @@
+alter table multicast_group
+  add column geofence_latitude double precision not null default 0,
+  add column geofence_longitude double precision not null default 0,
+  add column geofence_radius integer not null default 0,
+  add column gateway_tags jsonb not null default '{}';
+
+alter table multicast_group
+  alter column geofence_latitude drop default,
+  alter column geofence_longitude drop default,
+  alter column geofence_radius drop default,
+  alter column gateway_tags drop default;
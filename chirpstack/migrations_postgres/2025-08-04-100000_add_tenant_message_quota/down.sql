@@ -0,0 +1,5 @@
+drop table tenant_usage;
+
+alter table tenant
+  drop column max_downlink_count_per_day,
+  drop column max_uplink_count_per_day;
@@ -0,0 +1,16 @@
+alter table tenant
+  add column max_uplink_count_per_day integer not null default 0,
+  add column max_downlink_count_per_day integer not null default 0;
+
+alter table tenant
+  alter column max_uplink_count_per_day drop default,
+  alter column max_downlink_count_per_day drop default;
+
+create table tenant_usage (
+  tenant_id uuid not null references tenant on delete cascade,
+  day date not null,
+  uplink_count bigint not null,
+  downlink_count bigint not null,
+  updated_at timestamp with time zone not null,
+  primary key (tenant_id, day)
+);
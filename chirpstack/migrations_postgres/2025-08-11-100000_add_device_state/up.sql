@@ -0,0 +1,9 @@
+alter table device
+  add column state varchar(20) not null default 'PROVISIONED';
+
+update device
+  set state = 'ACTIVE'
+  where device_session is not null;
+
+alter table device
+  alter column state drop default;
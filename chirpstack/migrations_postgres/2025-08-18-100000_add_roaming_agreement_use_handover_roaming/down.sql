@@ -0,0 +1,2 @@
+alter table roaming_agreement
+  drop column use_handover_roaming;
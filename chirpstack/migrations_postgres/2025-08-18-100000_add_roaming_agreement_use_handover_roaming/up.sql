@@ -0,0 +1,2 @@
+alter table roaming_agreement
+  add column use_handover_roaming boolean not null default false;
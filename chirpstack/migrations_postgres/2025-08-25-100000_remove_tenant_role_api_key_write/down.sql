@@ -0,0 +1 @@
+-- The removed api_key:write permissions can not be restored.
@@ -0,0 +1 @@
+delete from tenant_role_permission where permission = 'api_key:write';
@@ -0,0 +1,2 @@
+alter table data_encryption_key
+  alter column id drop identity;
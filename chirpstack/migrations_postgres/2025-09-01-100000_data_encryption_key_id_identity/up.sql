@@ -0,0 +1,8 @@
+alter table data_encryption_key
+  alter column id add generated by default as identity;
+
+select setval(
+  pg_get_serial_sequence('data_encryption_key', 'id'),
+  coalesce(max(id), 0) + 1,
+  false
+) from data_encryption_key;
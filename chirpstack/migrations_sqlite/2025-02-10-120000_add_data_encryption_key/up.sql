@@ -0,0 +1,6 @@
+create table data_encryption_key (
+  id integer primary key not null,
+  created_at datetime not null,
+  backend varchar(20) not null,
+  encrypted_key blob not null
+);
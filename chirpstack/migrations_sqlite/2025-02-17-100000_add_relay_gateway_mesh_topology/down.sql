@@ -0,0 +1,7 @@
+drop index idx_relay_gateway_border_gateway_id;
+
+alter table relay_gateway
+  drop column relay_path;
+
+alter table relay_gateway
+  drop column border_gateway_id;
@@ -0,0 +1,7 @@
+alter table relay_gateway
+  add column border_gateway_id blob null;
+
+alter table relay_gateway
+  add column relay_path text not null default '[]';
+
+create index idx_relay_gateway_border_gateway_id on relay_gateway (border_gateway_id);
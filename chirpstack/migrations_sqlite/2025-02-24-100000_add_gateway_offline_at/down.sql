@@ -0,0 +1,2 @@
+alter table gateway
+  drop column offline_at;
@@ -0,0 +1,2 @@
+alter table gateway
+  add column offline_at datetime null;
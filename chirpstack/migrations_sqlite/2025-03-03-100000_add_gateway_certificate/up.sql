@@ -0,0 +1,10 @@
+create table gateway_certificate (
+  serial_number blob primary key,
+  gateway_id blob not null,
+  created_at datetime not null,
+  expires_at datetime not null,
+  revoked_at datetime null
+);
+
+create index idx_gateway_certificate_gateway_id on gateway_certificate (gateway_id);
+create index idx_gateway_certificate_expires_at on gateway_certificate (expires_at);
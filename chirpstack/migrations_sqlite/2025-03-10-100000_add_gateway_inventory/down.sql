@@ -0,0 +1,9 @@
+drop index idx_gateway_gateway_bridge_version;
+drop index idx_gateway_concentratord_version;
+drop index idx_gateway_hal_version;
+drop index idx_gateway_model;
+
+alter table gateway drop column gateway_bridge_version;
+alter table gateway drop column concentratord_version;
+alter table gateway drop column hal_version;
+alter table gateway drop column model;
@@ -0,0 +1,9 @@
+alter table gateway add column model text not null default '';
+alter table gateway add column hal_version text not null default '';
+alter table gateway add column concentratord_version text not null default '';
+alter table gateway add column gateway_bridge_version text not null default '';
+
+create index idx_gateway_model on gateway (model);
+create index idx_gateway_hal_version on gateway (hal_version);
+create index idx_gateway_concentratord_version on gateway (concentratord_version);
+create index idx_gateway_gateway_bridge_version on gateway (gateway_bridge_version);
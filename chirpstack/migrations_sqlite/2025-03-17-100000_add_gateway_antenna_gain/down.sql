@@ -0,0 +1,2 @@
+alter table gateway drop column cable_loss;
+alter table gateway drop column antenna_gain;
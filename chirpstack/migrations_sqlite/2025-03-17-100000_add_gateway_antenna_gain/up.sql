@@ -0,0 +1,2 @@
+alter table gateway add column antenna_gain real not null default 0;
+alter table gateway add column cable_loss real not null default 0;
@@ -0,0 +1,10 @@
+create table device_repository_codec (
+  id text not null primary key,
+  created_at datetime not null,
+  updated_at datetime not null,
+  vendor_id varchar(100) not null,
+  device_id varchar(100) not null,
+  firmware_version varchar(100) not null,
+  codec_id varchar(100) not null,
+  script text not null
+);
@@ -0,0 +1 @@
+drop table codec_library;
@@ -0,0 +1,11 @@
+create table codec_library (
+  id text not null primary key,
+  tenant_id text not null references tenant on delete cascade,
+  created_at datetime not null,
+  updated_at datetime not null,
+  name varchar(100) not null,
+  description text not null,
+  script text not null
+);
+
+create unique index idx_codec_library_tenant_id_name on codec_library (tenant_id, name);
@@ -0,0 +1,15 @@
+create table backend_async_answer (
+  id text not null primary key,
+  created_at datetime not null,
+  receiver_id blob not null,
+  target_role varchar(3) not null,
+  transaction_id bigint not null,
+  message_type varchar(20) not null,
+  payload text not null,
+  attempt_count smallint not null,
+  scheduler_run_after datetime not null,
+  error_msg text not null
+);
+
+create unique index idx_backend_async_answer_receiver_id_transaction_id on backend_async_answer (receiver_id, transaction_id, message_type);
+create index idx_backend_async_answer_scheduler_run_after on backend_async_answer (scheduler_run_after);
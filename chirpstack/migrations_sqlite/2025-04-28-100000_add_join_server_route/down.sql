@@ -0,0 +1 @@
+drop table join_server_route;
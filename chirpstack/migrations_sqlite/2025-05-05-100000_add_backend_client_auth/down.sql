@@ -0,0 +1,13 @@
+alter table roaming_agreement drop column api_key;
+alter table roaming_agreement drop column api_key_header;
+alter table roaming_agreement drop column oauth2_scope;
+alter table roaming_agreement drop column oauth2_client_secret;
+alter table roaming_agreement drop column oauth2_client_id;
+alter table roaming_agreement drop column oauth2_token_url;
+
+alter table join_server_route drop column api_key;
+alter table join_server_route drop column api_key_header;
+alter table join_server_route drop column oauth2_scope;
+alter table join_server_route drop column oauth2_client_secret;
+alter table join_server_route drop column oauth2_client_id;
+alter table join_server_route drop column oauth2_token_url;
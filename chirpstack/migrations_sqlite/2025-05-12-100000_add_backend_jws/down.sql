@@ -0,0 +1,7 @@
+alter table join_server_route drop column jws_verification_key;
+alter table join_server_route drop column jws_signing_key;
+alter table join_server_route drop column jws_algorithm;
+
+alter table roaming_agreement drop column jws_verification_key;
+alter table roaming_agreement drop column jws_signing_key;
+alter table roaming_agreement drop column jws_algorithm;
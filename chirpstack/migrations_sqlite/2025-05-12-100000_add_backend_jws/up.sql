@@ -0,0 +1,7 @@
+alter table roaming_agreement add column jws_algorithm text not null default '';
+alter table roaming_agreement add column jws_signing_key text not null default '';
+alter table roaming_agreement add column jws_verification_key text not null default '';
+
+alter table join_server_route add column jws_algorithm text not null default '';
+alter table join_server_route add column jws_signing_key text not null default '';
+alter table join_server_route add column jws_verification_key text not null default '';
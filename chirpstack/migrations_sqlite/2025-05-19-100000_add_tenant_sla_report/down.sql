@@ -0,0 +1 @@
+drop table tenant_sla_report;
@@ -0,0 +1,2 @@
+alter table application
+  add column log_rate_limit integer not null default 0;
@@ -0,0 +1,2 @@
+alter table tenant
+  drop column use_hsm;
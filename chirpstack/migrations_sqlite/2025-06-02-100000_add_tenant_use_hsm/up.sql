@@ -0,0 +1,2 @@
+alter table tenant
+  add column use_hsm boolean not null default false;
@@ -0,0 +1,2 @@
+alter table device_keys
+  drop column key_ref;
@@ -0,0 +1,2 @@
+alter table device_keys
+  add column key_ref varchar(100) not null default '';
@@ -0,0 +1,10 @@
+create table user_session (
+  id text not null primary key,
+  user_id text not null references "user" on delete cascade,
+  created_at datetime not null,
+  expires_at datetime not null,
+  revoked_at datetime null
+);
+
+create index idx_user_session_user_id on user_session (user_id);
+create index idx_user_session_expires_at on user_session (expires_at);
@@ -0,0 +1,8 @@
+drop index idx_tenant_user_role_id;
+
+alter table tenant_user
+  drop column role_id;
+
+drop table tenant_role_permission;
+drop index idx_tenant_role_tenant_id;
+drop table tenant_role;
@@ -0,0 +1,3 @@
+drop index idx_key_access_log_created_at;
+drop index idx_key_access_log_dev_eui;
+drop table key_access_log;
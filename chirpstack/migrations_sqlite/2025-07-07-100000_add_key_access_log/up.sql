@@ -0,0 +1,11 @@
+create table key_access_log (
+  id text not null primary key,
+  created_at datetime not null,
+  dev_eui blob not null,
+  key_type varchar(20) not null,
+  actor varchar(100) not null,
+  purpose varchar(50) not null
+);
+
+create index idx_key_access_log_dev_eui on key_access_log (dev_eui);
+create index idx_key_access_log_created_at on key_access_log (created_at);
@@ -0,0 +1,6 @@
+alter table device_keys
+  drop column wrapped_app_key;
+alter table device_keys
+  drop column wrapped_nwk_key;
+alter table device_keys
+  drop column kek_label;
@@ -0,0 +1,6 @@
+alter table device_keys
+  add column kek_label varchar(100) not null default '';
+alter table device_keys
+  add column wrapped_nwk_key blob not null default x'';
+alter table device_keys
+  add column wrapped_app_key blob not null default x'';
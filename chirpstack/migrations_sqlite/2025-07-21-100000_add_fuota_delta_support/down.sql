@@ -0,0 +1,11 @@
+drop table fuota_deployment_delta;
+
+alter table fuota_deployment_device
+  drop column full_image_required;
+alter table fuota_deployment_device
+  drop column target_version;
+alter table fuota_deployment_device
+  drop column base_version;
+
+alter table fuota_deployment
+  drop column payload_version;
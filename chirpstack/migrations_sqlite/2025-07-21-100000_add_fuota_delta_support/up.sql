@@ -0,0 +1,18 @@
+alter table fuota_deployment
+  add column payload_version varchar(100) not null default '';
+
+alter table fuota_deployment_device
+  add column base_version varchar(100) not null default '';
+alter table fuota_deployment_device
+  add column target_version varchar(100) not null default '';
+alter table fuota_deployment_device
+  add column full_image_required boolean not null default false;
+
+create table fuota_deployment_delta (
+  fuota_deployment_id text not null references fuota_deployment on delete cascade,
+  base_version varchar(100) not null,
+  created_at datetime not null,
+  payload blob not null,
+
+  primary key (fuota_deployment_id, base_version)
+);
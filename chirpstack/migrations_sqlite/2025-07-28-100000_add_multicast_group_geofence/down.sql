@@ -0,0 +1,8 @@
+alter table multicast_group
+  drop column gateway_tags;
+alter table multicast_group
+  drop column geofence_radius;
+alter table multicast_group
+  drop column geofence_longitude;
+alter table multicast_group
+  drop column geofence_latitude;
@@ -0,0 +1,8 @@
+alter table multicast_group
+  add column geofence_latitude double not null default 0;
+alter table multicast_group
+  add column geofence_longitude double not null default 0;
+alter table multicast_group
+  add column geofence_radius integer not null default 0;
+alter table multicast_group
+  add column gateway_tags text not null default '{}';
@@ -0,0 +1,13 @@
+alter table tenant
+  add column max_uplink_count_per_day integer not null default 0;
+alter table tenant
+  add column max_downlink_count_per_day integer not null default 0;
+
+create table tenant_usage (
+  tenant_id text not null references tenant on delete cascade,
+  day date not null,
+  uplink_count bigint not null,
+  downlink_count bigint not null,
+  updated_at datetime not null,
+  primary key (tenant_id, day)
+);
@@ -0,0 +1,2 @@
+alter table device
+  drop column state;
@@ -0,0 +1,6 @@
+alter table device
+  add column state text not null default 'PROVISIONED';
+
+update device
+  set state = 'ACTIVE'
+  where device_session is not null;
@@ -0,0 +1 @@
+alter table roaming_agreement drop column use_handover_roaming;
@@ -4,6 +4,7 @@ use async_trait::async_trait;
 use super::{Handler, Request, Response};
 use crate::region;
 
+#[derive(Default)]
 pub struct Algorithm {}
 
 impl Algorithm {
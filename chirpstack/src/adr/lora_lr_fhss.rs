@@ -5,6 +5,7 @@ use super::{default, lr_fhss};
 use super::{Handler, Request, Response};
 use crate::region;
 
+#[derive(Default)]
 pub struct Algorithm {}
 
 impl Algorithm {
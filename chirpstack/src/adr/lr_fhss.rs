@@ -6,6 +6,7 @@ use super::{Handler, Request, Response};
 use crate::region;
 use chirpstack_api::internal;
 
+#[derive(Default)]
 pub struct Algorithm {}
 
 impl Algorithm {
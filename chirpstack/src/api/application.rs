@@ -48,6 +48,7 @@ impl ApplicationService for Application {
             name: req_app.name.clone(),
             description: req_app.description.clone(),
             tags: fields::KeyValue::new(req_app.tags.clone()),
+            log_rate_limit: req_app.log_rate_limit as i32,
             ..Default::default()
         };
 
@@ -88,6 +89,7 @@ impl ApplicationService for Application {
                 name: a.name,
                 description: a.description,
                 tags: a.tags.into_hashmap(),
+                log_rate_limit: a.log_rate_limit as u32,
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&a.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&a.updated_at)),
@@ -123,6 +125,7 @@ impl ApplicationService for Application {
             name: req_app.name.to_string(),
             description: req_app.description.to_string(),
             tags: fields::KeyValue::new(req_app.tags.clone()),
+            log_rate_limit: req_app.log_rate_limit as i32,
             ..Default::default()
         })
         .await
@@ -241,6 +244,7 @@ impl ApplicationService for Application {
                     }
                     application::IntegrationKind::PilotThings => api::IntegrationKind::PilotThings,
                     application::IntegrationKind::Ifttt => api::IntegrationKind::Ifttt,
+                    application::IntegrationKind::Geolocation => api::IntegrationKind::Geolocation,
                 }
                 .into(),
             })
@@ -1866,6 +1870,155 @@ impl ApplicationService for Application {
         Ok(resp)
     }
 
+    async fn create_geolocation_integration(
+        &self,
+        request: Request<api::CreateGeolocationIntegrationRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_int = match &request.get_ref().integration {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("integration is missing"));
+            }
+        };
+        let app_id = Uuid::from_str(&req_int.application_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateApplicationAccess::new(validator::Flag::Update, app_id),
+            )
+            .await?;
+
+        let _ = application::create_integration(application::Integration {
+            application_id: app_id.into(),
+            kind: application::IntegrationKind::Geolocation,
+            configuration: application::IntegrationConfiguration::Geolocation(
+                application::GeolocationConfiguration {
+                    tdoa: req_int.tdoa,
+                    rssi: req_int.rssi,
+                    min_gateways: req_int.min_gateways,
+                },
+            ),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut().insert(
+            "x-log-application_id",
+            req_int.application_id.parse().unwrap(),
+        );
+
+        Ok(resp)
+    }
+
+    async fn get_geolocation_integration(
+        &self,
+        request: Request<api::GetGeolocationIntegrationRequest>,
+    ) -> Result<Response<api::GetGeolocationIntegrationResponse>, Status> {
+        let req = request.get_ref();
+        let app_id = Uuid::from_str(&req.application_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateApplicationAccess::new(validator::Flag::Read, app_id),
+            )
+            .await?;
+
+        let i = application::get_integration(&app_id, application::IntegrationKind::Geolocation)
+            .await
+            .map_err(|e| e.status())?;
+
+        if let application::IntegrationConfiguration::Geolocation(conf) = &i.configuration {
+            let mut resp = Response::new(api::GetGeolocationIntegrationResponse {
+                integration: Some(api::GeolocationIntegration {
+                    application_id: app_id.to_string(),
+                    tdoa: conf.tdoa,
+                    rssi: conf.rssi,
+                    min_gateways: conf.min_gateways,
+                }),
+            });
+            resp.metadata_mut()
+                .insert("x-log-application_id", req.application_id.parse().unwrap());
+
+            Ok(resp)
+        } else {
+            Err(Status::internal(
+                "Integration has no Geolocation configuration",
+            ))
+        }
+    }
+
+    async fn update_geolocation_integration(
+        &self,
+        request: Request<api::UpdateGeolocationIntegrationRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_int = match &request.get_ref().integration {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("integration is missing"));
+            }
+        };
+        let app_id = Uuid::from_str(&req_int.application_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateApplicationAccess::new(validator::Flag::Update, app_id),
+            )
+            .await?;
+
+        let _ = application::update_integration(application::Integration {
+            application_id: app_id.into(),
+            kind: application::IntegrationKind::Geolocation,
+            configuration: application::IntegrationConfiguration::Geolocation(
+                application::GeolocationConfiguration {
+                    tdoa: req_int.tdoa,
+                    rssi: req_int.rssi,
+                    min_gateways: req_int.min_gateways,
+                },
+            ),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut().insert(
+            "x-log-application_id",
+            req_int.application_id.parse().unwrap(),
+        );
+
+        Ok(resp)
+    }
+
+    async fn delete_geolocation_integration(
+        &self,
+        request: Request<api::DeleteGeolocationIntegrationRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let app_id = Uuid::from_str(&req.application_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateApplicationAccess::new(validator::Flag::Update, app_id),
+            )
+            .await?;
+
+        application::delete_integration(&app_id, application::IntegrationKind::Geolocation)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-application_id", req.application_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
     async fn generate_mqtt_integration_client_certificate(
         &self,
         request: Request<api::GenerateMqttIntegrationClientCertificateRequest>,
@@ -2039,6 +2192,7 @@ pub mod test {
                 id: create_resp.id.clone(),
                 tenant_id: t.id.to_string(),
                 name: "updated-app".into(),
+                log_rate_limit: 10,
                 ..Default::default()
             }),
         };
@@ -2062,6 +2216,7 @@ pub mod test {
                 id: create_resp.id.clone(),
                 tenant_id: t.id.to_string(),
                 name: "updated-app".into(),
+                log_rate_limit: 10,
                 ..Default::default()
             }),
             get_resp.get_ref().application
@@ -3561,4 +3716,139 @@ pub mod test {
             list_resp
         );
     }
+
+    #[tokio::test]
+    async fn test_geolocation_integration() {
+        let _guard = test::prepare().await;
+        let app = get_application().await;
+        let u = get_user().await;
+        let service = Application::new(RequestValidator::new());
+
+        // create
+        let create_req = get_request(
+            &u.id,
+            api::CreateGeolocationIntegrationRequest {
+                integration: Some(api::GeolocationIntegration {
+                    application_id: app.id.to_string(),
+                    tdoa: true,
+                    rssi: false,
+                    min_gateways: 3,
+                }),
+            },
+        );
+        let _ = service
+            .create_geolocation_integration(create_req)
+            .await
+            .unwrap();
+
+        // get
+        let get_req = get_request(
+            &u.id,
+            api::GetGeolocationIntegrationRequest {
+                application_id: app.id.to_string(),
+            },
+        );
+        let get_resp = service.get_geolocation_integration(get_req).await.unwrap();
+        let get_resp = get_resp.get_ref();
+        assert_eq!(
+            Some(api::GeolocationIntegration {
+                application_id: app.id.to_string(),
+                tdoa: true,
+                rssi: false,
+                min_gateways: 3,
+            }),
+            get_resp.integration
+        );
+
+        // update
+        let update_req = get_request(
+            &u.id,
+            api::UpdateGeolocationIntegrationRequest {
+                integration: Some(api::GeolocationIntegration {
+                    application_id: app.id.to_string(),
+                    tdoa: true,
+                    rssi: true,
+                    min_gateways: 4,
+                }),
+            },
+        );
+        let _ = service
+            .update_geolocation_integration(update_req)
+            .await
+            .unwrap();
+
+        // get
+        let get_req = get_request(
+            &u.id,
+            api::GetGeolocationIntegrationRequest {
+                application_id: app.id.to_string(),
+            },
+        );
+        let get_resp = service.get_geolocation_integration(get_req).await.unwrap();
+        let get_resp = get_resp.get_ref();
+        assert_eq!(
+            Some(api::GeolocationIntegration {
+                application_id: app.id.to_string(),
+                tdoa: true,
+                rssi: true,
+                min_gateways: 4,
+            }),
+            get_resp.integration
+        );
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListIntegrationsRequest {
+                application_id: app.id.to_string(),
+            },
+        );
+        let list_resp = service.list_integrations(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(
+            &api::ListIntegrationsResponse {
+                total_count: 2,
+                result: vec![
+                    api::IntegrationListItem {
+                        kind: api::IntegrationKind::Geolocation.into(),
+                    },
+                    api::IntegrationListItem {
+                        kind: api::IntegrationKind::MqttGlobal.into(),
+                    }
+                ],
+            },
+            list_resp
+        );
+
+        // delete
+        let del_req = get_request(
+            &u.id,
+            api::DeleteGeolocationIntegrationRequest {
+                application_id: app.id.to_string(),
+            },
+        );
+        let _ = service
+            .delete_geolocation_integration(del_req)
+            .await
+            .unwrap();
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListIntegrationsRequest {
+                application_id: app.id.to_string(),
+            },
+        );
+        let list_resp = service.list_integrations(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(
+            &api::ListIntegrationsResponse {
+                total_count: 1,
+                result: vec![api::IntegrationListItem {
+                    kind: api::IntegrationKind::MqttGlobal.into(),
+                },],
+            },
+            list_resp
+        );
+    }
 }
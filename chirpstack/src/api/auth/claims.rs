@@ -15,6 +15,8 @@ pub struct AuthClaim {
     pub iss: String,
     pub sub: String,
     pub typ: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub jti: String,
 }
 
 fn is_default<T: Default + PartialEq>(t: &T) -> bool {
@@ -32,6 +34,7 @@ impl AuthClaim {
             iss: "chirpstack".to_string(),
             sub: id.to_string(),
             typ: "user".to_string(),
+            jti: "".to_string(),
         }
     }
 
@@ -42,6 +45,7 @@ impl AuthClaim {
             sub: id.to_string(),
             typ: "key".to_string(),
             exp: None,
+            jti: "".to_string(),
         }
     }
 
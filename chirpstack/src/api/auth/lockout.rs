@@ -0,0 +1,627 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use tracing::{error, warn};
+
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+use crate::monitoring::prometheus;
+use crate::storage::{get_async_redis_conn, redis_key};
+
+// Interval after which the token lockout of a client IP is re-checked in Redis.
+const TOKEN_LOCKOUT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Approximate max. number of entries kept in the lockout stream.
+const LOCKOUT_STREAM_MAX_LEN: usize = 10_000;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct KindLabels {
+    kind: String,
+}
+
+lazy_static! {
+    static ref FAILURE_COUNTER: Family<KindLabels, Counter> = {
+        let counter = Family::<KindLabels, Counter>::default();
+        prometheus::register(
+            "api_auth_failure_count",
+            "Number of failed authentications by kind",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref LOCKOUT_COUNTER: Family<KindLabels, Counter> = {
+        let counter = Family::<KindLabels, Counter>::default();
+        prometheus::register(
+            "api_auth_lockout_count",
+            "Number of authentication lockouts by kind",
+            counter.clone(),
+        );
+        counter
+    };
+
+    // Local cache of the token lockouts by client IP. As the auth interceptor can not perform
+    // async operations, the lockouts stored in Redis are checked in the background, at most
+    // every TOKEN_LOCKOUT_CHECK_INTERVAL per client IP. Until the first check has completed,
+    // a client IP is not locked out by this instance.
+    static ref TOKEN_LOCKOUTS: RwLock<HashMap<IpAddr, TokenLockout>> = RwLock::new(HashMap::new());
+}
+
+struct TokenLockout {
+    checked_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // Failed password login, counted per email and client IP.
+    Login,
+    // Failed token validation, counted per client IP.
+    Token,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Kind::Login => "login",
+                Kind::Token => "token",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Failure {
+    // Number of failures within the current interval.
+    pub count: u32,
+    // Delay to apply before responding.
+    pub delay: Duration,
+    // The subject is locked out.
+    pub locked: bool,
+}
+
+// Returns true when the given subject is locked out.
+pub async fn is_locked(kind: Kind, subject: &str) -> Result<bool> {
+    let conf = config::get();
+    if !conf.api.auth_lockout.enabled {
+        return Ok(false);
+    }
+
+    let locked: bool = redis::cmd("EXISTS")
+        .arg(get_lockout_key(kind, subject))
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Get authentication lockout")?;
+
+    Ok(locked)
+}
+
+// Registers an authentication failure for the given subject. Once the max. number of failures
+// has been reached within the interval, the subject is locked out for the lockout duration.
+pub async fn failure(kind: Kind, subject: &str) -> Result<Failure> {
+    let conf = config::get();
+    let conf = &conf.api.auth_lockout;
+
+    FAILURE_COUNTER
+        .get_or_create(&KindLabels {
+            kind: kind.to_string(),
+        })
+        .inc();
+
+    if !conf.enabled {
+        return Ok(Failure {
+            count: 0,
+            delay: Duration::ZERO,
+            locked: false,
+        });
+    }
+
+    // The key is only created with a TTL when it does not yet exist, such that the count
+    // expires at the end of the interval.
+    let key = get_failures_key(kind, subject);
+    let (count,): (u32,) = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(&key)
+        .arg(0)
+        .arg("PX")
+        .arg(conf.interval.as_millis() as usize)
+        .arg("NX")
+        .ignore()
+        .cmd("INCR")
+        .arg(&key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Increment authentication failure count")?;
+
+    let locked = conf.max_failures > 0 && count >= conf.max_failures;
+    if locked {
+        () = redis::cmd("SET")
+            .arg(get_lockout_key(kind, subject))
+            .arg(1)
+            .arg("PX")
+            .arg(conf.lockout_duration.as_millis() as usize)
+            .query_async(&mut get_async_redis_conn().await?)
+            .await
+            .context("Set authentication lockout")?;
+
+        // Only alert once per interval.
+        if count == conf.max_failures {
+            LOCKOUT_COUNTER
+                .get_or_create(&KindLabels {
+                    kind: kind.to_string(),
+                })
+                .inc();
+            warn!(
+                kind = %kind,
+                subject = %subject,
+                failures = count,
+                lockout_duration = ?conf.lockout_duration,
+                "Too many authentication failures, subject has been locked out"
+            );
+
+            if let Err(e) = publish_lockout(kind, subject, count, conf.lockout_duration).await {
+                error!(
+                    kind = %kind,
+                    subject = %subject,
+                    error = %e.full(),
+                    "Publish authentication lockout error"
+                );
+            }
+        }
+    }
+
+    Ok(Failure {
+        count,
+        delay: get_delay(count),
+        locked,
+    })
+}
+
+// Resets the failure count of the given subject, e.g. after a successful login.
+pub async fn reset(kind: Kind, subject: &str) -> Result<()> {
+    let conf = config::get();
+    if !conf.api.auth_lockout.enabled {
+        return Ok(());
+    }
+
+    () = redis::cmd("DEL")
+        .arg(get_failures_key(kind, subject))
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Reset authentication failure count")?;
+
+    Ok(())
+}
+
+// Publishes the lockout to the lockout stream, such that lockouts can be consumed for alerting,
+// independent of the instance on which the lockout occurred.
+async fn publish_lockout(
+    kind: Kind,
+    subject: &str,
+    failures: u32,
+    lockout_duration: Duration,
+) -> Result<()> {
+    () = redis::cmd("XADD")
+        .arg(get_lockout_stream_key())
+        .arg("MAXLEN")
+        .arg("~")
+        .arg(LOCKOUT_STREAM_MAX_LEN)
+        .arg("*")
+        .arg("kind")
+        .arg(kind.to_string())
+        .arg("subject")
+        .arg(subject)
+        .arg("failures")
+        .arg(failures)
+        .arg("lockout_duration_ms")
+        .arg(lockout_duration.as_millis() as u64)
+        .arg("time")
+        .arg(Utc::now().to_rfc3339())
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Publish authentication lockout")?;
+
+    Ok(())
+}
+
+// Returns the remaining lockout duration of the given subject, or None in case the subject is
+// not locked out.
+async fn get_lockout_ttl(kind: Kind, subject: &str) -> Result<Option<Duration>> {
+    let ttl: i64 = redis::cmd("PTTL")
+        .arg(get_lockout_key(kind, subject))
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Get authentication lockout TTL")?;
+
+    // -2 is returned when the key does not exist. A lockout is always set with a TTL.
+    Ok(if ttl > 0 {
+        Some(Duration::from_millis(ttl as u64))
+    } else {
+        None
+    })
+}
+
+// Returns true when the given client IP is locked out because of token validation failures,
+// on this or an other instance. The lockout is read from the local cache, which is refreshed
+// in the background in case it was not checked within the TOKEN_LOCKOUT_CHECK_INTERVAL.
+pub fn is_token_locked(ip: &IpAddr) -> bool {
+    let conf = config::get();
+    if !conf.api.auth_lockout.enabled {
+        return false;
+    }
+
+    let now = Instant::now();
+    let (locked, check) = match TOKEN_LOCKOUTS.read().unwrap().get(ip) {
+        Some(v) => (
+            matches!(v.locked_until, Some(until) if until > now),
+            now.duration_since(v.checked_at) >= TOKEN_LOCKOUT_CHECK_INTERVAL,
+        ),
+        None => (false, true),
+    };
+
+    if check {
+        check_token_lockout(*ip);
+    }
+
+    locked
+}
+
+// Checks the token lockout of the given client IP in Redis and updates the local cache.
+fn check_token_lockout(ip: IpAddr) {
+    // The entry is marked as checked before the check has completed, such that concurrent
+    // requests from the same client IP do not trigger additional checks.
+    {
+        let now = Instant::now();
+        let mut lockouts = TOKEN_LOCKOUTS.write().unwrap();
+        lockouts.retain(|_, v| {
+            now.duration_since(v.checked_at) < TOKEN_LOCKOUT_CHECK_INTERVAL
+                || matches!(v.locked_until, Some(until) if until > now)
+        });
+        lockouts
+            .entry(ip)
+            .or_insert(TokenLockout {
+                checked_at: now,
+                locked_until: None,
+            })
+            .checked_at = now;
+    }
+
+    tokio::spawn(async move {
+        match get_lockout_ttl(Kind::Token, &ip.to_string()).await {
+            Ok(ttl) => set_token_lockout(ip, ttl),
+            Err(e) => {
+                error!(ip = %ip, error = %e.full(), "Check token lockout error");
+            }
+        }
+    });
+}
+
+fn set_token_lockout(ip: IpAddr, ttl: Option<Duration>) {
+    let now = Instant::now();
+    TOKEN_LOCKOUTS.write().unwrap().insert(
+        ip,
+        TokenLockout {
+            checked_at: now,
+            locked_until: ttl.map(|ttl| now + ttl),
+        },
+    );
+}
+
+// Registers a token validation failure for the given client IP. As this is called from the
+// auth interceptor, the failure is registered in the background.
+pub fn token_failure(ip: IpAddr) {
+    let conf = config::get();
+    if !conf.api.auth_lockout.enabled {
+        FAILURE_COUNTER
+            .get_or_create(&KindLabels {
+                kind: Kind::Token.to_string(),
+            })
+            .inc();
+        return;
+    }
+
+    tokio::spawn(async move {
+        match failure(Kind::Token, &ip.to_string()).await {
+            Ok(f) => {
+                if f.locked {
+                    let conf = config::get();
+                    set_token_lockout(ip, Some(conf.api.auth_lockout.lockout_duration));
+                }
+            }
+            Err(e) => {
+                error!(ip = %ip, error = %e.full(), "Register token failure error");
+            }
+        }
+    });
+}
+
+// Returns the client IP of the given request. The X-Forwarded-For header is only taken into
+// account when the request originates from a trusted proxy, in which case the right-most address
+// which is not a trusted proxy is returned.
+pub fn client_ip<T>(req: &tonic::Request<T>) -> Option<IpAddr> {
+    let conf = config::get();
+    get_client_ip(req, &conf.api.auth_lockout.trusted_proxies)
+}
+
+fn get_client_ip<T>(req: &tonic::Request<T>, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let remote_ip = req.remote_addr()?.ip();
+    if !trusted_proxies.contains(&remote_ip) {
+        return Some(remote_ip);
+    }
+
+    let mut ip = remote_ip;
+    for v in req.metadata().get_all("x-forwarded-for").iter().rev() {
+        let Ok(v) = v.to_str() else {
+            return Some(ip);
+        };
+
+        for addr in v.rsplit(',') {
+            match addr.trim().parse::<IpAddr>() {
+                Ok(addr) => {
+                    ip = addr;
+                    if !trusted_proxies.contains(&addr) {
+                        return Some(ip);
+                    }
+                }
+                Err(_) => return Some(ip),
+            }
+        }
+    }
+
+    Some(ip)
+}
+
+// Returns the subject for login failures, which are counted per email and client IP such that
+// failures from other sources do not lock out the user.
+pub fn login_subject(email: &str, ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(ip) => format!("{}/{}", email, ip),
+        None => email.to_string(),
+    }
+}
+
+// Returns the exponential delay for the given failure count, capped at the max. delay.
+fn get_delay(count: u32) -> Duration {
+    let conf = config::get();
+    let conf = &conf.api.auth_lockout;
+
+    if count == 0 {
+        return Duration::ZERO;
+    }
+
+    let factor = 2u32.saturating_pow(count - 1);
+    conf.base_delay.saturating_mul(factor).min(conf.max_delay)
+}
+
+fn get_failures_key(kind: Kind, subject: &str) -> String {
+    redis_key(format!("api:auth:{}:{{{}}}:failures", kind, subject))
+}
+
+fn get_lockout_key(kind: Kind, subject: &str) -> String {
+    redis_key(format!("api:auth:{}:{{{}}}:lockout", kind, subject))
+}
+
+fn get_lockout_stream_key() -> String {
+    redis_key("api:stream:auth_lockout".to_string())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+    use redis::streams::StreamReadReply;
+
+    #[tokio::test]
+    async fn test_lockout() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.api.auth_lockout.enabled = true;
+        conf.api.auth_lockout.max_failures = 3;
+        conf.api.auth_lockout.base_delay = Duration::from_millis(100);
+        conf.api.auth_lockout.max_delay = Duration::from_millis(300);
+        config::set(conf);
+
+        let subject = "user@example.com";
+        assert!(!is_locked(Kind::Login, subject).await.unwrap());
+
+        assert_eq!(
+            Failure {
+                count: 1,
+                delay: Duration::from_millis(100),
+                locked: false,
+            },
+            failure(Kind::Login, subject).await.unwrap()
+        );
+
+        // the count is reset after a successful login
+        reset(Kind::Login, subject).await.unwrap();
+        assert_eq!(1, failure(Kind::Login, subject).await.unwrap().count);
+        assert_eq!(
+            Failure {
+                count: 2,
+                delay: Duration::from_millis(200),
+                locked: false,
+            },
+            failure(Kind::Login, subject).await.unwrap()
+        );
+        assert!(!is_locked(Kind::Login, subject).await.unwrap());
+
+        // the delay is capped at the max. delay
+        assert_eq!(
+            Failure {
+                count: 3,
+                delay: Duration::from_millis(300),
+                locked: true,
+            },
+            failure(Kind::Login, subject).await.unwrap()
+        );
+        assert!(is_locked(Kind::Login, subject).await.unwrap());
+
+        // other subjects and kinds are not affected
+        assert!(!is_locked(Kind::Login, "other@example.com").await.unwrap());
+        assert!(!is_locked(Kind::Token, subject).await.unwrap());
+
+        // the lockout is published to the lockout stream
+        let srr: StreamReadReply = redis::cmd("XREAD")
+            .arg("STREAMS")
+            .arg(get_lockout_stream_key())
+            .arg("0")
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(1, srr.keys.len());
+        assert_eq!(1, srr.keys[0].ids.len());
+        let m = &srr.keys[0].ids[0].map;
+        assert_eq!(
+            Some(redis::Value::BulkString(b"login".to_vec())),
+            m.get("kind").cloned()
+        );
+        assert_eq!(
+            Some(redis::Value::BulkString(subject.as_bytes().to_vec())),
+            m.get("subject").cloned()
+        );
+
+        // only alerted once per interval
+        failure(Kind::Login, subject).await.unwrap();
+        let len: usize = redis::cmd("XLEN")
+            .arg(get_lockout_stream_key())
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(1, len);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let trusted_proxies: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap()];
+
+        let req = |remote: &str, forwarded: Option<&str>| {
+            let mut req = tonic::Request::new(());
+            req.extensions_mut()
+                .insert(tonic::transport::server::TcpConnectInfo {
+                    local_addr: None,
+                    remote_addr: Some(remote.parse().unwrap()),
+                });
+            if let Some(v) = forwarded {
+                req.metadata_mut()
+                    .insert("x-forwarded-for", v.parse().unwrap());
+            }
+            req
+        };
+
+        // untrusted source, the header is ignored
+        assert_eq!(
+            Some("192.168.1.1".parse().unwrap()),
+            get_client_ip(
+                &req("192.168.1.1:1234", Some("172.16.0.1")),
+                &trusted_proxies
+            )
+        );
+
+        // trusted proxy
+        assert_eq!(
+            Some("172.16.0.1".parse().unwrap()),
+            get_client_ip(&req("10.0.0.1:1234", Some("172.16.0.1")), &trusted_proxies)
+        );
+
+        // spoofed addresses added by the client are ignored
+        assert_eq!(
+            Some("172.16.0.1".parse().unwrap()),
+            get_client_ip(
+                &req("10.0.0.1:1234", Some("1.2.3.4, 172.16.0.1, 10.0.0.1")),
+                &trusted_proxies
+            )
+        );
+
+        // trusted proxy, without header
+        assert_eq!(
+            Some("10.0.0.1".parse().unwrap()),
+            get_client_ip(&req("10.0.0.1:1234", None), &trusted_proxies)
+        );
+
+        // invalid header
+        assert_eq!(
+            Some("10.0.0.1".parse().unwrap()),
+            get_client_ip(&req("10.0.0.1:1234", Some("invalid")), &trusted_proxies)
+        );
+    }
+
+    #[test]
+    fn test_login_subject() {
+        assert_eq!(
+            "user@example.com/192.168.1.1",
+            login_subject("user@example.com", Some("192.168.1.1".parse().unwrap()))
+        );
+        assert_eq!("user@example.com", login_subject("user@example.com", None));
+    }
+
+    #[tokio::test]
+    async fn test_is_token_locked() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.api.auth_lockout.enabled = true;
+        config::set(conf);
+
+        let ip: IpAddr = "192.168.1.1".parse().unwrap();
+        TOKEN_LOCKOUTS.write().unwrap().clear();
+        assert!(!is_token_locked(&ip));
+
+        // locked out on this instance
+        set_token_lockout(ip, Some(Duration::from_secs(60)));
+        assert!(is_token_locked(&ip));
+
+        set_token_lockout(ip, Some(Duration::ZERO));
+        assert!(!is_token_locked(&ip));
+
+        // locked out by an other instance, the lockout is read from Redis in the background
+        let ip: IpAddr = "192.168.1.2".parse().unwrap();
+        () = redis::cmd("SET")
+            .arg(get_lockout_key(Kind::Token, &ip.to_string()))
+            .arg(1)
+            .arg("PX")
+            .arg(60_000)
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert!(!is_token_locked(&ip));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(is_token_locked(&ip));
+
+        // the cached lockout is used until the next check
+        () = redis::cmd("DEL")
+            .arg(get_lockout_key(Kind::Token, &ip.to_string()))
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert!(is_token_locked(&ip));
+        TOKEN_LOCKOUTS
+            .write()
+            .unwrap()
+            .get_mut(&ip)
+            .unwrap()
+            .checked_at = Instant::now() - TOKEN_LOCKOUT_CHECK_INTERVAL;
+        assert!(is_token_locked(&ip));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!is_token_locked(&ip));
+
+        // disabled
+        let mut conf = (*config::get()).clone();
+        conf.api.auth_lockout.enabled = false;
+        config::set(conf);
+        set_token_lockout(ip, Some(Duration::from_secs(60)));
+        assert!(!is_token_locked(&ip));
+    }
+}
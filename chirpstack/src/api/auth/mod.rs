@@ -1,9 +1,13 @@
-use crate::config;
+use anyhow::Result;
 use tonic::{Request, Status};
 use uuid::Uuid;
 
+use crate::config;
+
 pub mod claims;
 pub mod error;
+pub mod lockout;
+pub mod session;
 pub mod validator;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -13,7 +17,25 @@ pub enum AuthID {
     Key(Uuid),
 }
 
-pub fn auth_interceptor(mut req: Request<()>) -> Result<Request<()>, Status> {
+pub fn auth_interceptor(req: Request<()>) -> Result<Request<()>, Status> {
+    let ip = lockout::client_ip(&req);
+
+    if let Some(ip) = &ip {
+        if lockout::is_token_locked(ip) {
+            return Err(Status::resource_exhausted(
+                "too many authentication failures, try again later",
+            ));
+        }
+    }
+
+    authenticate(req).inspect_err(|e| {
+        if let (tonic::Code::Unauthenticated, Some(ip)) = (e.code(), ip) {
+            lockout::token_failure(ip);
+        }
+    })
+}
+
+fn authenticate(mut req: Request<()>) -> Result<Request<()>, Status> {
     let conf = config::get();
 
     let auth_str = match req.metadata().get("authorization") {
@@ -24,6 +46,13 @@ pub fn auth_interceptor(mut req: Request<()>) -> Result<Request<()>, Status> {
             }
         },
         _ => {
+            // In case the client authenticated using a client-certificate, the client is
+            // authorized as the API key to which the certificate is mapped.
+            if let Some(id) = get_client_certificate_api_key_id(&req)? {
+                req.extensions_mut().insert(AuthID::Key(id));
+                return Ok(req);
+            }
+
             // some API methods do not require the authorization metadata. When it is not available
             // we do not error. Each will perform its own authorization.
             req.extensions_mut().insert(AuthID::None);
@@ -56,6 +85,20 @@ pub fn auth_interceptor(mut req: Request<()>) -> Result<Request<()>, Status> {
 
     match token.typ.as_ref() {
         "user" => {
+            // Tokens issued before session tracking was added do not have a JWT ID. As these
+            // can not be revoked, these are rejected.
+            if token.jti.is_empty() {
+                return Err(Status::unauthenticated(
+                    "token has no session ID, please log in again",
+                ));
+            }
+
+            let session_id = Uuid::parse_str(&token.jti)
+                .map_err(|e| Status::unauthenticated(format!("{}", e)))?;
+            if session::is_revoked(&session_id) {
+                return Err(Status::unauthenticated("token has been revoked"));
+            }
+
             req.extensions_mut().insert(AuthID::User(id));
         }
         "key" => {
@@ -71,3 +114,127 @@ pub fn auth_interceptor(mut req: Request<()>) -> Result<Request<()>, Status> {
 
     Ok(req)
 }
+
+// Returns the API key ID to which the client-certificate is mapped, or None in case the client
+// did not present a client-certificate.
+fn get_client_certificate_api_key_id(req: &Request<()>) -> Result<Option<Uuid>, Status> {
+    let certs = match req.peer_certs() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let cert = match certs.first() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let common_name =
+        get_common_name(cert.as_ref()).map_err(|e| Status::unauthenticated(format!("{}", e)))?;
+
+    match get_api_key_id_for_common_name(&common_name) {
+        Some(v) => Ok(Some(v)),
+        None => Err(Status::unauthenticated(format!(
+            "client-certificate is not mapped to an API key: {}",
+            common_name
+        ))),
+    }
+}
+
+fn get_common_name(der: &[u8]) -> Result<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|v| v.as_str().ok())
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("Client-certificate does not contain a Common Name"));
+    common_name
+}
+
+fn get_api_key_id_for_common_name(common_name: &str) -> Option<Uuid> {
+    let conf = config::get();
+    conf.api
+        .client_certificates
+        .iter()
+        .find(|v| v.common_name == common_name)
+        .map(|v| v.api_key_id)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::user;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_client_certificate_mapping() {
+        let _guard = test::prepare().await;
+
+        let api_key_id = Uuid::new_v4();
+        let mut conf = (*config::get()).clone();
+        conf.api.client_certificates = vec![config::ApiClientCertificate {
+            common_name: "m2m-client".into(),
+            api_key_id,
+        }];
+        config::set(conf);
+
+        let mut params = rcgen::CertificateParams::new(vec![]).unwrap();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "m2m-client");
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        let common_name = get_common_name(cert.der()).unwrap();
+        assert_eq!("m2m-client", common_name);
+        assert_eq!(
+            Some(api_key_id),
+            get_api_key_id_for_common_name(&common_name)
+        );
+        assert_eq!(None, get_api_key_id_for_common_name("other-client"));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_user_token() {
+        let _guard = test::prepare().await;
+        session::reload().await.unwrap();
+
+        let mut conf = (*config::get()).clone();
+        conf.api.secret = "verysecret".into();
+        config::set(conf);
+
+        let u = user::test::create_user().await;
+
+        let request = |token: &str| {
+            let mut req = Request::new(());
+            req.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            req
+        };
+
+        // Token with session.
+        let token = session::create_token(&u.id, b"verysecret").await.unwrap();
+        let req = authenticate(request(&token)).unwrap();
+        assert_eq!(
+            Some(&AuthID::User(u.id.into())),
+            req.extensions().get::<AuthID>()
+        );
+
+        // Revoked token.
+        let claim = claims::AuthClaim::decode(&token, b"verysecret").unwrap();
+        session::revoke(&u.id, &Uuid::parse_str(&claim.jti).unwrap())
+            .await
+            .unwrap();
+        let err = authenticate(request(&token)).unwrap_err();
+        assert_eq!(tonic::Code::Unauthenticated, err.code());
+
+        // Token issued before session tracking was added (no JWT ID), this can not be revoked.
+        let token = claims::AuthClaim::new_for_user(&u.id)
+            .encode(b"verysecret")
+            .unwrap();
+        let err = authenticate(request(&token)).unwrap_err();
+        assert_eq!(tonic::Code::Unauthenticated, err.code());
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::sleep;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::claims;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{cache, error::Error, user_session};
+
+// Interval at which the expired sessions are removed and the revocation list is reloaded.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    // IDs of the revoked (but not yet expired) sessions. The auth interceptor rejects user
+    // tokens of which the JWT ID is in this list.
+    static ref REVOKED: RwLock<HashSet<Uuid>> = RwLock::new(HashSet::new());
+}
+
+pub async fn setup() -> Result<()> {
+    info!("Setting up user session revocation list");
+    reload().await?;
+
+    tokio::spawn(async {
+        loop {
+            sleep(CLEANUP_INTERVAL).await;
+
+            if let Err(e) = cleanup().await {
+                error!(error = %e.full(), "User session cleanup error");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// Reloads the revocation list. This is called on revocation by other instances.
+pub async fn reload() -> Result<()> {
+    let ids = user_session::get_revoked_ids().await?;
+    *REVOKED.write().unwrap() = ids.into_iter().collect();
+    Ok(())
+}
+
+// Returns true when the session with the given ID has been revoked.
+pub fn is_revoked(id: &Uuid) -> bool {
+    REVOKED.read().unwrap().contains(id)
+}
+
+// Creates a new session for the given user and returns the token for this session.
+pub async fn create_token(user_id: &Uuid, secret: &[u8]) -> Result<String, Error> {
+    let s = user_session::create(user_session::UserSession::new(
+        user_id,
+        chrono::Duration::try_days(1).unwrap(),
+    ))
+    .await?;
+
+    let mut claim = claims::AuthClaim::new_for_user(user_id);
+    claim.jti = s.id.to_string();
+    claim.exp = Some(s.expires_at.timestamp() as usize);
+
+    Ok(claim.encode(secret)?)
+}
+
+// Revokes the given session of the given user.
+pub async fn revoke(user_id: &Uuid, id: &Uuid) -> Result<(), Error> {
+    user_session::revoke(user_id, id).await?;
+    REVOKED.write().unwrap().insert(*id);
+    cache::invalidate(cache::Kind::UserSessions, user_id).await;
+    Ok(())
+}
+
+// Revokes all the sessions of the given user. It returns the number of revoked sessions.
+pub async fn revoke_all(user_id: &Uuid) -> Result<usize, Error> {
+    let count = user_session::revoke_all(user_id).await?;
+    reload().await?;
+    cache::invalidate(cache::Kind::UserSessions, user_id).await;
+    Ok(count)
+}
+
+async fn cleanup() -> Result<()> {
+    let count = user_session::delete_expired().await?;
+    info!(count = count, "Expired user sessions deleted");
+    reload().await
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::user;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_session() {
+        let _guard = test::prepare().await;
+        reload().await.unwrap();
+
+        let u = user::test::create_user().await;
+        let secret = b"verysecret";
+
+        let token = create_token(&u.id, secret).await.unwrap();
+        let claim = claims::AuthClaim::decode(&token, secret).unwrap();
+        assert_eq!(u.id.to_string(), claim.sub);
+        let id = Uuid::parse_str(&claim.jti).unwrap();
+        assert!(!is_revoked(&id));
+
+        let sessions = user_session::list(&u.id, 10, 0).await.unwrap();
+        assert_eq!(1, sessions.len());
+        assert_eq!(id, *sessions[0].id);
+
+        // revoke
+        revoke(&u.id, &id).await.unwrap();
+        assert!(is_revoked(&id));
+
+        // revoke all
+        let token = create_token(&u.id, secret).await.unwrap();
+        let id2 = Uuid::parse_str(&claims::AuthClaim::decode(&token, secret).unwrap().jti).unwrap();
+        assert_eq!(1, revoke_all(&u.id).await.unwrap());
+        assert!(is_revoked(&id));
+        assert!(is_revoked(&id2));
+    }
+}
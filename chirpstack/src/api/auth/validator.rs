@@ -11,12 +11,30 @@ use lrwn::EUI64;
 use super::error::Error;
 use crate::api::auth::AuthID;
 use crate::helpers::errors::PrintFullError;
+use crate::storage::fields::Permission;
 use crate::storage::schema::{
-    api_key, application, device, device_profile, fuota_deployment, gateway, multicast_group,
-    tenant_user, user,
+    api_key, application, codec_library, device, device_profile, fuota_deployment, gateway,
+    multicast_group, tenant_role, tenant_role_permission, tenant_user, user,
 };
 use crate::storage::{fields, get_async_db_conn};
 
+// Filters on the tenant users of which the role grants the given permission. This must be used
+// within a query joining the tenant_user table. Note that tenant users without role
+// (role_id is null) never match, for these the is_admin, is_device_admin and is_gateway_admin
+// flags must be used instead.
+macro_rules! tenant_user_has_permission {
+    ($permission:expr) => {
+        dsl::exists(
+            tenant_role_permission::dsl::tenant_role_permission.filter(
+                tenant_role_permission::dsl::role_id
+                    .nullable()
+                    .eq(tenant_user::dsl::role_id)
+                    .and(tenant_role_permission::dsl::permission.eq($permission)),
+            ),
+        )
+    };
+}
+
 #[derive(Copy, Clone)]
 pub enum Flag {
     Create,
@@ -291,7 +309,7 @@ impl Validator for ValidateApiKeysAccess {
 
         match self.flag {
             // admin user
-            // tenant admin
+            // tenant admin (without role, as tenant API keys are not restricted by a role)
             Flag::Create => {
                 q = q.filter(
                     user::dsl::is_admin.eq(true).or(dsl::exists(
@@ -299,6 +317,7 @@ impl Validator for ValidateApiKeysAccess {
                             tenant_user::dsl::tenant_id
                                 .eq(fields::Uuid::from(self.tenant_id))
                                 .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                .and(tenant_user::dsl::role_id.is_null())
                                 .and(tenant_user::dsl::is_admin.eq(true)),
                         ),
                     )),
@@ -312,7 +331,12 @@ impl Validator for ValidateApiKeysAccess {
                         tenant_user::dsl::tenant_user.filter(
                             tenant_user::dsl::tenant_id
                                 .eq(fields::Uuid::from(self.tenant_id))
-                                .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .or(tenant_user_has_permission!(Permission::ApiKeyRead)),
+                                ),
                         ),
                     )),
                 );
@@ -355,7 +379,7 @@ impl Validator for ValidateApiKeyAccess {
 
         match self.flag {
             // admin user
-            // tenant admin
+            // tenant admin (without role, as tenant API keys are not restricted by a role)
             Flag::Delete => {
                 q = q.filter(
                     user::dsl::is_admin.eq(true).or(dsl::exists(
@@ -366,6 +390,7 @@ impl Validator for ValidateApiKeyAccess {
                             .filter(
                                 tenant_user::dsl::user_id
                                     .eq(user::dsl::id)
+                                    .and(tenant_user::dsl::role_id.is_null())
                                     .and(tenant_user::dsl::is_admin.eq(true))
                                     .and(api_key::dsl::id.eq(fields::Uuid::from(self.id))),
                             ),
@@ -544,7 +569,14 @@ impl Validator for ValidateTenantUsersAccess {
                                     tenant_user::dsl::tenant_id
                                         .eq(fields::Uuid::from(self.tenant_id)),
                                 )
-                                .and(tenant_user::dsl::is_admin.eq(true)),
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(tenant_user::dsl::is_admin.eq(true))
+                                        .or(tenant_user_has_permission!(
+                                            Permission::TenantUserWrite
+                                        )),
+                                ),
                         ),
                     )),
                 );
@@ -552,13 +584,22 @@ impl Validator for ValidateTenantUsersAccess {
             // global admin
             // tenant user
             Flag::List => {
-                q = q.filter(user::dsl::is_admin.eq(true).or(dsl::exists(
-                    tenant_user::dsl::tenant_user.filter(
-                        tenant_user::dsl::user_id.eq(user::dsl::id).and(
-                            tenant_user::dsl::tenant_id.eq(fields::Uuid::from(self.tenant_id)),
-                        ),
-                    ),
-                )));
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_user::dsl::tenant_user.filter(
+                                tenant_user::dsl::user_id
+                                    .eq(user::dsl::id)
+                                    .and(
+                                        tenant_user::dsl::tenant_id
+                                            .eq(fields::Uuid::from(self.tenant_id)),
+                                    )
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::TenantUserRead),
+                                    )),
+                            ),
+                        )),
+                    );
             }
             _ => {
                 return Ok(0);
@@ -635,9 +676,14 @@ impl Validator for ValidateTenantUserAccess {
                                     tenant_user::dsl::tenant_id
                                         .eq(fields::Uuid::from(self.tenant_id)),
                                 )
-                                .and(tenant_user::dsl::is_admin.eq(true).or(
-                                    tenant_user::dsl::user_id.eq(fields::Uuid::from(self.user_id)),
-                                )),
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(tenant_user::dsl::is_admin.eq(true))
+                                        .or(tenant_user_has_permission!(Permission::TenantUserRead))
+                                        .or(tenant_user::dsl::user_id
+                                            .eq(fields::Uuid::from(self.user_id))),
+                                ),
                         ),
                     )),
                 );
@@ -654,7 +700,14 @@ impl Validator for ValidateTenantUserAccess {
                                     tenant_user::dsl::tenant_id
                                         .eq(fields::Uuid::from(self.tenant_id)),
                                 )
-                                .and(tenant_user::dsl::is_admin.eq(true)),
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(tenant_user::dsl::is_admin.eq(true))
+                                        .or(tenant_user_has_permission!(
+                                            Permission::TenantUserWrite
+                                        )),
+                                ),
                         ),
                     )),
                 );
@@ -692,6 +745,218 @@ impl Validator for ValidateTenantUserAccess {
     }
 }
 
+pub struct ValidateTenantRolesAccess {
+    flag: Flag,
+    tenant_id: Uuid,
+}
+
+impl ValidateTenantRolesAccess {
+    pub fn new(flag: Flag, tenant_id: Uuid) -> Self {
+        ValidateTenantRolesAccess { flag, tenant_id }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateTenantRolesAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            // tenant admin
+            Flag::Create => {
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        tenant_user::dsl::tenant_user.filter(
+                            tenant_user::dsl::user_id
+                                .eq(user::dsl::id)
+                                .and(
+                                    tenant_user::dsl::tenant_id
+                                        .eq(fields::Uuid::from(self.tenant_id)),
+                                )
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(tenant_user::dsl::is_admin.eq(true))
+                                        .or(tenant_user_has_permission!(
+                                            Permission::TenantUserWrite
+                                        )),
+                                ),
+                        ),
+                    )),
+                );
+            }
+            // global admin
+            // tenant user
+            Flag::List => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_user::dsl::tenant_user.filter(
+                                tenant_user::dsl::user_id
+                                    .eq(user::dsl::id)
+                                    .and(
+                                        tenant_user::dsl::tenant_id
+                                            .eq(fields::Uuid::from(self.tenant_id)),
+                                    )
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::TenantUserRead),
+                                    )),
+                            ),
+                        )),
+                    );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            // tenant api key
+            Flag::Create | Flag::List => {
+                q = q.filter(
+                    api_key::dsl::is_admin
+                        .eq(true)
+                        .or(api_key::dsl::tenant_id.eq(fields::Uuid::from(self.tenant_id))),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateTenantRoleAccess {
+    flag: Flag,
+    role_id: Uuid,
+}
+
+impl ValidateTenantRoleAccess {
+    pub fn new(flag: Flag, role_id: Uuid) -> Self {
+        ValidateTenantRoleAccess { flag, role_id }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateTenantRoleAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            // tenant user
+            Flag::Read => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_role::dsl::tenant_role
+                                .inner_join(tenant_user::table.on(
+                                    tenant_user::dsl::tenant_id.eq(tenant_role::dsl::tenant_id),
+                                ))
+                                .filter(
+                                    tenant_role::dsl::id
+                                        .eq(fields::Uuid::from(self.role_id))
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(Permission::TenantUserRead),
+                                        )),
+                                ),
+                        )),
+                    );
+            }
+            // global admin
+            // tenant admin
+            Flag::Update | Flag::Delete => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_role::dsl::tenant_role
+                                .inner_join(tenant_user::table.on(
+                                    tenant_user::dsl::tenant_id.eq(tenant_role::dsl::tenant_id),
+                                ))
+                                .filter(
+                                    tenant_role::dsl::id
+                                        .eq(fields::Uuid::from(self.role_id))
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(tenant_user::dsl::is_admin.eq(true))
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::TenantUserWrite
+                                                )),
+                                        ),
+                                ),
+                        )),
+                    );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            // tenant api key
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(
+                    api_key::dsl::is_admin.eq(true).or(dsl::exists(
+                        tenant_role::dsl::tenant_role.filter(
+                            tenant_role::dsl::id
+                                .eq(fields::Uuid::from(self.role_id))
+                                .and(
+                                    api_key::dsl::tenant_id
+                                        .eq(tenant_role::dsl::tenant_id.nullable()),
+                                ),
+                        ),
+                    )),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
 pub struct ValidateApplicationsAccess {
     flag: Flag,
     tenant_id: Uuid,
@@ -730,9 +995,16 @@ impl Validator for ValidateApplicationsAccess {
                                         .eq(fields::Uuid::from(self.tenant_id)),
                                 )
                                 .and(
-                                    tenant_user::dsl::is_admin
-                                        .eq(true)
-                                        .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(
+                                            tenant_user::dsl::is_admin
+                                                .eq(true)
+                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                        )
+                                        .or(tenant_user_has_permission!(
+                                            Permission::ApplicationWrite
+                                        )),
                                 ),
                         ),
                     )),
@@ -741,13 +1013,22 @@ impl Validator for ValidateApplicationsAccess {
             // global admin
             // tenant user
             Flag::List => {
-                q = q.filter(user::dsl::is_admin.eq(true).or(dsl::exists(
-                    tenant_user::dsl::tenant_user.filter(
-                        tenant_user::dsl::user_id.eq(user::dsl::id).and(
-                            tenant_user::dsl::tenant_id.eq(fields::Uuid::from(self.tenant_id)),
-                        ),
-                    ),
-                )));
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_user::dsl::tenant_user.filter(
+                                tenant_user::dsl::user_id
+                                    .eq(user::dsl::id)
+                                    .and(
+                                        tenant_user::dsl::tenant_id
+                                            .eq(fields::Uuid::from(self.tenant_id)),
+                                    )
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::ApplicationRead),
+                                    )),
+                            ),
+                        )),
+                    );
             }
             _ => {
                 return Ok(0);
@@ -831,7 +1112,12 @@ impl Validator for ValidateApplicationAccess {
                                 .filter(
                                     application::dsl::id
                                         .eq(fields::Uuid::from(self.application_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::ApplicationRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -852,9 +1138,17 @@ impl Validator for ValidateApplicationAccess {
                                         .eq(fields::Uuid::from(self.application_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::ApplicationWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1056,9 +1350,16 @@ impl Validator for ValidateDeviceProfilesAccess {
                                         .eq(fields::Uuid::from(self.tenant_id)),
                                 )
                                 .and(
-                                    tenant_user::dsl::is_admin
-                                        .eq(true)
-                                        .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(
+                                            tenant_user::dsl::is_admin
+                                                .eq(true)
+                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                        )
+                                        .or(tenant_user_has_permission!(
+                                            Permission::DeviceProfileWrite
+                                        )),
                                 ),
                         ),
                     )),
@@ -1067,13 +1368,21 @@ impl Validator for ValidateDeviceProfilesAccess {
             // global admin
             // tenant user
             Flag::List => {
-                q = q.filter(user::dsl::is_admin.eq(true).or(dsl::exists(
-                    tenant_user::dsl::tenant_user.filter(
-                        tenant_user::dsl::user_id.eq(user::dsl::id).and(
-                            tenant_user::dsl::tenant_id.eq(fields::Uuid::from(self.tenant_id)),
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        tenant_user::dsl::tenant_user.filter(
+                            tenant_user::dsl::user_id
+                                .eq(user::dsl::id)
+                                .and(
+                                    tenant_user::dsl::tenant_id
+                                        .eq(fields::Uuid::from(self.tenant_id)),
+                                )
+                                .and(tenant_user::dsl::role_id.is_null().or(
+                                    tenant_user_has_permission!(Permission::DeviceProfileRead),
+                                )),
                         ),
-                    ),
-                )));
+                    )),
+                );
             }
             _ => {
                 return Ok(0);
@@ -1138,42 +1447,167 @@ impl Validator for ValidateDeviceProfileAccess {
             // global admin
             // tenant user
             Flag::Read => {
-                q =
-                    q.filter(
-                        user::dsl::is_admin.eq(true).or(dsl::exists(
-                            device_profile::dsl::device_profile
-                                .inner_join(tenant_user::table.on(
-                                    tenant_user::dsl::tenant_id.eq(device_profile::dsl::tenant_id),
-                                ))
-                                .filter(
-                                    device_profile::dsl::id
-                                        .eq(fields::Uuid::from(self.device_profile_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
-                                ),
-                        )),
-                    );
-            }
-            // global admin
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        device_profile::dsl::device_profile
+                            .inner_join(
+                                tenant_user::table
+                                    .on(tenant_user::dsl::tenant_id
+                                        .eq(device_profile::dsl::tenant_id)),
+                            )
+                            .filter(
+                                device_profile::dsl::id
+                                    .eq(fields::Uuid::from(self.device_profile_id))
+                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::DeviceProfileRead),
+                                    )),
+                            ),
+                    )),
+                );
+            }
+            // global admin
             // tenant admin user
             // tenant device admin
             Flag::Update | Flag::Delete => {
-                q =
-                    q.filter(
-                        user::dsl::is_admin.eq(true).or(dsl::exists(
-                            device_profile::dsl::device_profile
-                                .inner_join(tenant_user::table.on(
-                                    tenant_user::dsl::tenant_id.eq(device_profile::dsl::tenant_id),
-                                ))
-                                .filter(
-                                    device_profile::dsl::id
-                                        .eq(fields::Uuid::from(self.device_profile_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        device_profile::dsl::device_profile
+                            .inner_join(
+                                tenant_user::table
+                                    .on(tenant_user::dsl::tenant_id
+                                        .eq(device_profile::dsl::tenant_id)),
+                            )
+                            .filter(
+                                device_profile::dsl::id
+                                    .eq(fields::Uuid::from(self.device_profile_id))
+                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                    .and(
+                                        tenant_user::dsl::role_id
+                                            .is_null()
+                                            .and(
+                                                tenant_user::dsl::is_admin
+                                                    .eq(true)
+                                                    .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            )
+                                            .or(tenant_user_has_permission!(
+                                                Permission::DeviceProfileWrite
+                                            )),
+                                    ),
+                            ),
+                    )),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .filter(api_key::dsl::id.eq(fields::Uuid::from(id)))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            // tenant api key
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(
+                    api_key::dsl::is_admin.eq(true).or(dsl::exists(
+                        device_profile::dsl::device_profile.filter(
+                            device_profile::dsl::id
+                                .eq(fields::Uuid::from(self.device_profile_id))
+                                .and(
+                                    api_key::dsl::tenant_id
+                                        .eq(device_profile::dsl::tenant_id.nullable()),
+                                ),
+                        ),
+                    )),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateCodecLibrariesAccess {
+    flag: Flag,
+    tenant_id: Uuid,
+}
+
+impl ValidateCodecLibrariesAccess {
+    pub fn new(flag: Flag, tenant_id: Uuid) -> Self {
+        ValidateCodecLibrariesAccess { flag, tenant_id }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateCodecLibrariesAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            // tenant admin
+            // tenant device admin
+            Flag::Create => {
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        tenant_user::dsl::tenant_user.filter(
+                            tenant_user::dsl::user_id
+                                .eq(user::dsl::id)
+                                .and(
+                                    tenant_user::dsl::tenant_id
+                                        .eq(fields::Uuid::from(self.tenant_id)),
+                                )
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
                                         .and(
                                             tenant_user::dsl::is_admin
                                                 .eq(true)
                                                 .or(tenant_user::dsl::is_device_admin.eq(true)),
-                                        ),
+                                        )
+                                        .or(tenant_user_has_permission!(
+                                            Permission::CodecLibraryWrite
+                                        )),
                                 ),
+                        ),
+                    )),
+                );
+            }
+            // global admin
+            // tenant user
+            Flag::List => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            tenant_user::dsl::tenant_user.filter(
+                                tenant_user::dsl::user_id
+                                    .eq(user::dsl::id)
+                                    .and(
+                                        tenant_user::dsl::tenant_id
+                                            .eq(fields::Uuid::from(self.tenant_id)),
+                                    )
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::CodecLibraryRead),
+                                    )),
+                            ),
                         )),
                     );
             }
@@ -1185,6 +1619,120 @@ impl Validator for ValidateDeviceProfileAccess {
         Ok(q.first(&mut get_async_db_conn().await?).await?)
     }
 
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            // tenant api key
+            Flag::Create | Flag::List => {
+                q = q.filter(
+                    api_key::dsl::is_admin
+                        .eq(true)
+                        .or(api_key::dsl::tenant_id.eq(fields::Uuid::from(self.tenant_id))),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateCodecLibraryAccess {
+    flag: Flag,
+    codec_library_id: Uuid,
+}
+
+impl ValidateCodecLibraryAccess {
+    pub fn new(flag: Flag, id: Uuid) -> Self {
+        ValidateCodecLibraryAccess {
+            flag,
+            codec_library_id: id,
+        }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateCodecLibraryAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            // tenant user
+            Flag::Read => {
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        codec_library::dsl::codec_library
+                            .inner_join(
+                                tenant_user::table
+                                    .on(tenant_user::dsl::tenant_id
+                                        .eq(codec_library::dsl::tenant_id)),
+                            )
+                            .filter(
+                                codec_library::dsl::id
+                                    .eq(fields::Uuid::from(self.codec_library_id))
+                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                    .and(tenant_user::dsl::role_id.is_null().or(
+                                        tenant_user_has_permission!(Permission::CodecLibraryRead),
+                                    )),
+                            ),
+                    )),
+                );
+            }
+            // global admin
+            // tenant admin user
+            // tenant device admin
+            Flag::Update | Flag::Delete => {
+                q = q.filter(
+                    user::dsl::is_admin.eq(true).or(dsl::exists(
+                        codec_library::dsl::codec_library
+                            .inner_join(
+                                tenant_user::table
+                                    .on(tenant_user::dsl::tenant_id
+                                        .eq(codec_library::dsl::tenant_id)),
+                            )
+                            .filter(
+                                codec_library::dsl::id
+                                    .eq(fields::Uuid::from(self.codec_library_id))
+                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                    .and(
+                                        tenant_user::dsl::role_id
+                                            .is_null()
+                                            .and(
+                                                tenant_user::dsl::is_admin
+                                                    .eq(true)
+                                                    .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            )
+                                            .or(tenant_user_has_permission!(
+                                                Permission::CodecLibraryWrite
+                                            )),
+                                    ),
+                            ),
+                    )),
+                );
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
     async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
         let mut q = api_key::dsl::api_key
             .select(dsl::count_star())
@@ -1197,12 +1745,12 @@ impl Validator for ValidateDeviceProfileAccess {
             Flag::Read | Flag::Update | Flag::Delete => {
                 q = q.filter(
                     api_key::dsl::is_admin.eq(true).or(dsl::exists(
-                        device_profile::dsl::device_profile.filter(
-                            device_profile::dsl::id
-                                .eq(fields::Uuid::from(self.device_profile_id))
+                        codec_library::dsl::codec_library.filter(
+                            codec_library::dsl::id
+                                .eq(fields::Uuid::from(self.codec_library_id))
                                 .and(
                                     api_key::dsl::tenant_id
-                                        .eq(device_profile::dsl::tenant_id.nullable()),
+                                        .eq(codec_library::dsl::tenant_id.nullable()),
                                 ),
                         ),
                     )),
@@ -1260,9 +1808,17 @@ impl Validator for ValidateDevicesAccess {
                                         .eq(fields::Uuid::from(self.application_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::DeviceWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1281,7 +1837,10 @@ impl Validator for ValidateDevicesAccess {
                                 .filter(
                                     application::dsl::id
                                         .eq(fields::Uuid::from(self.application_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(Permission::DeviceRead),
+                                        )),
                                 ),
                         )),
                     );
@@ -1364,7 +1923,10 @@ impl Validator for ValidateDeviceAccess {
                                 .filter(
                                     device::dsl::dev_eui
                                         .eq(&self.dev_eui)
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(Permission::DeviceRead),
+                                        )),
                                 ),
                         )),
                     );
@@ -1386,9 +1948,17 @@ impl Validator for ValidateDeviceAccess {
                                         .eq(&self.dev_eui)
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::DeviceWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1429,19 +1999,21 @@ impl Validator for ValidateDeviceAccess {
     }
 }
 
-pub struct ValidateDeviceQueueAccess {
+// Validates the access to the device root-keys and activation (session-keys). Tenant users
+// without role have the same access as for the device itself.
+pub struct ValidateDeviceKeysAccess {
     flag: Flag,
     dev_eui: EUI64,
 }
 
-impl ValidateDeviceQueueAccess {
+impl ValidateDeviceKeysAccess {
     pub fn new(flag: Flag, dev_eui: EUI64) -> Self {
-        ValidateDeviceQueueAccess { flag, dev_eui }
+        ValidateDeviceKeysAccess { flag, dev_eui }
     }
 }
 
 #[async_trait]
-impl Validator for ValidateDeviceQueueAccess {
+impl Validator for ValidateDeviceKeysAccess {
     async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
         let mut q = user::dsl::user
             .select(dsl::count_star())
@@ -1455,7 +2027,7 @@ impl Validator for ValidateDeviceQueueAccess {
         match self.flag {
             // admin user
             // tenant user
-            Flag::Create | Flag::List | Flag::Delete => {
+            Flag::Read => {
                 q =
                     q.filter(
                         user::dsl::is_admin.eq(true).or(dsl::exists(
@@ -1467,7 +2039,43 @@ impl Validator for ValidateDeviceQueueAccess {
                                 .filter(
                                     device::dsl::dev_eui
                                         .eq(&self.dev_eui)
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(Permission::DeviceKeysRead),
+                                        )),
+                                ),
+                        )),
+                    );
+            }
+            // admin user
+            // tenant admin
+            // tenant device admin
+            Flag::Update => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            device::dsl::device
+                                .inner_join(application::table)
+                                .inner_join(tenant_user::table.on(
+                                    tenant_user::dsl::tenant_id.eq(application::dsl::tenant_id),
+                                ))
+                                .filter(
+                                    device::dsl::dev_eui
+                                        .eq(&self.dev_eui)
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::DeviceKeysWrite
+                                                )),
+                                        ),
                                 ),
                         )),
                     );
@@ -1489,14 +2097,14 @@ impl Validator for ValidateDeviceQueueAccess {
         match self.flag {
             // admin api key
             // tenant api key
-            Flag::Create | Flag::List | Flag::Delete => {
+            Flag::Read | Flag::Update => {
                 q = q.filter(api_key::dsl::is_admin.eq(true).or(dsl::exists(
                     device::dsl::device.inner_join(application::table).filter(
-                        device::dsl::dev_eui.eq(&self.dev_eui).and(
+                        device::dsl::dev_eui.eq(self.dev_eui).and(
                             api_key::dsl::tenant_id.eq(application::dsl::tenant_id.nullable()),
                         ),
                     ),
-                )));
+                )))
             }
             _ => {
                 return Ok(0);
@@ -1507,12 +2115,119 @@ impl Validator for ValidateDeviceQueueAccess {
     }
 }
 
-pub struct ValidateGatewaysAccess {
+pub struct ValidateDeviceQueueAccess {
     flag: Flag,
-    tenant_id: Uuid,
+    dev_eui: EUI64,
 }
 
-impl ValidateGatewaysAccess {
+impl ValidateDeviceQueueAccess {
+    pub fn new(flag: Flag, dev_eui: EUI64) -> Self {
+        ValidateDeviceQueueAccess { flag, dev_eui }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateDeviceQueueAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // admin user
+            // tenant user
+            Flag::Create | Flag::Delete => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            device::dsl::device
+                                .inner_join(application::table)
+                                .inner_join(tenant_user::table.on(
+                                    tenant_user::dsl::tenant_id.eq(application::dsl::tenant_id),
+                                ))
+                                .filter(
+                                    device::dsl::dev_eui
+                                        .eq(&self.dev_eui)
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::DeviceQueueWrite
+                                            ),
+                                        )),
+                                ),
+                        )),
+                    );
+            }
+            // admin user
+            // tenant user
+            Flag::List => {
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(dsl::exists(
+                            device::dsl::device
+                                .inner_join(application::table)
+                                .inner_join(tenant_user::table.on(
+                                    tenant_user::dsl::tenant_id.eq(application::dsl::tenant_id),
+                                ))
+                                .filter(
+                                    device::dsl::dev_eui
+                                        .eq(&self.dev_eui)
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::DeviceQueueRead
+                                            ),
+                                        )),
+                                ),
+                        )),
+                    );
+            }
+            _ => {
+                return Ok(0);
+            }
+        }
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .filter(api_key::dsl::id.eq(fields::Uuid::from(id)))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            // tenant api key
+            Flag::Create | Flag::List | Flag::Delete => {
+                q = q.filter(api_key::dsl::is_admin.eq(true).or(dsl::exists(
+                    device::dsl::device.inner_join(application::table).filter(
+                        device::dsl::dev_eui.eq(&self.dev_eui).and(
+                            api_key::dsl::tenant_id.eq(application::dsl::tenant_id.nullable()),
+                        ),
+                    ),
+                )));
+            }
+            _ => {
+                return Ok(0);
+            }
+        }
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateGatewaysAccess {
+    flag: Flag,
+    tenant_id: Uuid,
+}
+
+impl ValidateGatewaysAccess {
     pub fn new(flag: Flag, tenant_id: Uuid) -> Self {
         ValidateGatewaysAccess { flag, tenant_id }
     }
@@ -1542,9 +2257,14 @@ impl Validator for ValidateGatewaysAccess {
                                 .eq(fields::Uuid::from(self.tenant_id))
                                 .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                 .and(
-                                    tenant_user::dsl::is_admin
-                                        .eq(true)
-                                        .or(tenant_user::dsl::is_gateway_admin.eq(true)),
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .and(
+                                            tenant_user::dsl::is_admin
+                                                .eq(true)
+                                                .or(tenant_user::dsl::is_gateway_admin.eq(true)),
+                                        )
+                                        .or(tenant_user_has_permission!(Permission::GatewayWrite)),
                                 ),
                         ),
                     )),
@@ -1558,7 +2278,12 @@ impl Validator for ValidateGatewaysAccess {
                         tenant_user::dsl::tenant_user.filter(
                             tenant_user::dsl::tenant_id
                                 .eq(fields::Uuid::from(self.tenant_id))
-                                .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                .and(
+                                    tenant_user::dsl::role_id
+                                        .is_null()
+                                        .or(tenant_user_has_permission!(Permission::GatewayRead)),
+                                ),
                         ),
                     )),
                 );
@@ -1633,7 +2358,12 @@ impl Validator for ValidateGatewayAccess {
                             .filter(
                                 gateway::dsl::gateway_id
                                     .eq(&self.gateway_id)
-                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                    .and(
+                                        tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(Permission::GatewayRead),
+                                        ),
+                                    ),
                             ),
                     )),
                 );
@@ -1642,25 +2372,32 @@ impl Validator for ValidateGatewayAccess {
             // tenant admin
             // gateway admin
             Flag::Update | Flag::Delete => {
-                q = q.filter(
-                    user::dsl::is_admin.eq(true).or(dsl::exists(
-                        gateway::dsl::gateway
-                            .inner_join(
-                                tenant_user::table
-                                    .on(tenant_user::dsl::tenant_id.eq(gateway::dsl::tenant_id)),
-                            )
-                            .filter(
-                                gateway::dsl::gateway_id
-                                    .eq(&self.gateway_id)
-                                    .and(tenant_user::dsl::user_id.eq(user::dsl::id))
-                                    .and(
-                                        tenant_user::dsl::is_admin
-                                            .eq(true)
-                                            .or(tenant_user::dsl::is_gateway_admin.eq(true)),
+                q =
+                    q.filter(
+                        user::dsl::is_admin.eq(true).or(
+                            dsl::exists(
+                                gateway::dsl::gateway
+                                    .inner_join(tenant_user::table.on(
+                                        tenant_user::dsl::tenant_id.eq(gateway::dsl::tenant_id),
+                                    ))
+                                    .filter(
+                                        gateway::dsl::gateway_id
+                                            .eq(&self.gateway_id)
+                                            .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                            .and(
+                                                tenant_user::dsl::role_id
+                                                    .is_null()
+                                                    .and(tenant_user::dsl::is_admin.eq(true).or(
+                                                        tenant_user::dsl::is_gateway_admin.eq(true),
+                                                    ))
+                                                    .or(tenant_user_has_permission!(
+                                                        Permission::GatewayWrite
+                                                    )),
+                                            ),
                                     ),
                             ),
-                    )),
-                );
+                        ),
+                    );
             }
             _ => {
                 return Ok(0);
@@ -1741,9 +2478,17 @@ impl Validator for ValidateMulticastGroupsAccess {
                                         .eq(fields::Uuid::from(self.application_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::MulticastGroupWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1762,7 +2507,12 @@ impl Validator for ValidateMulticastGroupsAccess {
                                 .filter(
                                     application::dsl::id
                                         .eq(fields::Uuid::from(self.application_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::MulticastGroupRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -1848,7 +2598,12 @@ impl Validator for ValidateMulticastGroupAccess {
                                 .filter(
                                     multicast_group::dsl::id
                                         .eq(fields::Uuid::from(self.multicast_group_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::MulticastGroupRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -1870,9 +2625,17 @@ impl Validator for ValidateMulticastGroupAccess {
                                         .eq(fields::Uuid::from(self.multicast_group_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::MulticastGroupWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1964,9 +2727,17 @@ impl Validator for ValidateMulticastGroupQueueAccess {
                                         .eq(fields::Uuid::from(self.multicast_group_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::MulticastGroupWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -1986,7 +2757,12 @@ impl Validator for ValidateMulticastGroupQueueAccess {
                                 .filter(
                                     multicast_group::dsl::id
                                         .eq(fields::Uuid::from(self.multicast_group_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::MulticastGroupRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -2076,9 +2852,17 @@ impl Validator for ValidateFuotaDeploymentsAccess {
                                         .eq(fields::Uuid::from(self.application_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::FuotaDeploymentWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -2097,7 +2881,12 @@ impl Validator for ValidateFuotaDeploymentsAccess {
                                 .filter(
                                     application::dsl::id
                                         .eq(fields::Uuid::from(self.application_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::FuotaDeploymentRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -2181,7 +2970,12 @@ impl Validator for ValidateFuotaDeploymentAccess {
                                 .filter(
                                     fuota_deployment::dsl::id
                                         .eq(fields::Uuid::from(self.fuota_deployment_id))
-                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id)),
+                                        .and(tenant_user::dsl::user_id.eq(user::dsl::id))
+                                        .and(tenant_user::dsl::role_id.is_null().or(
+                                            tenant_user_has_permission!(
+                                                Permission::FuotaDeploymentRead
+                                            ),
+                                        )),
                                 ),
                         )),
                     );
@@ -2203,9 +2997,17 @@ impl Validator for ValidateFuotaDeploymentAccess {
                                         .eq(fields::Uuid::from(self.fuota_deployment_id))
                                         .and(tenant_user::dsl::user_id.eq(user::dsl::id))
                                         .and(
-                                            tenant_user::dsl::is_admin
-                                                .eq(true)
-                                                .or(tenant_user::dsl::is_device_admin.eq(true)),
+                                            tenant_user::dsl::role_id
+                                                .is_null()
+                                                .and(
+                                                    tenant_user::dsl::is_admin
+                                                        .eq(true)
+                                                        .or(tenant_user::dsl::is_device_admin
+                                                            .eq(true)),
+                                                )
+                                                .or(tenant_user_has_permission!(
+                                                    Permission::FuotaDeploymentWrite
+                                                )),
                                         ),
                                 ),
                         )),
@@ -2249,205 +3051,1570 @@ impl Validator for ValidateFuotaDeploymentAccess {
     }
 }
 
-#[cfg(test)]
-pub mod test {
-    use super::*;
-    use crate::storage::{
-        api_key, application, device, device_profile, fuota, gateway, multicast, tenant, user,
-    };
-    use crate::test;
-    use std::str::FromStr;
-
-    struct ValidatorTest<V>
-    where
-        V: Validator + Sync,
-    {
-        validators: Vec<V>,
-        id: AuthID,
-        ok: bool,
-    }
+pub struct ValidateRoamingAgreementsAccess {
+    flag: Flag,
+}
 
-    async fn run_tests<V>(tests: Vec<ValidatorTest<V>>)
-    where
-        V: Validator + Sync,
-    {
-        println!("Running tests");
-        for (i, tst) in tests.iter().enumerate() {
-            for (j, v) in tst.validators.iter().enumerate() {
-                assert_eq!(
-                    tst.ok,
-                    v.validate(&tst.id).await.is_ok(),
-                    "Test {}, assertion {}",
-                    i,
-                    j
-                );
-            }
-        }
+impl ValidateRoamingAgreementsAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidateRoamingAgreementsAccess { flag }
     }
+}
 
-    #[tokio::test]
-    async fn validate_is_admin() {
-        let _guard = test::prepare().await;
-        let users = vec![
-            user::User {
-                email: "admin@user".into(),
-                is_active: true,
-                is_admin: true,
-                ..Default::default()
-            },
-            user::User {
-                email: "inactive@user".into(),
-                is_active: false,
-                is_admin: false,
-                ..Default::default()
-            },
-            user::User {
-                email: "normal@user".into(),
-                is_active: true,
-                is_admin: false,
-                ..Default::default()
-            },
-        ];
-        for u in &users {
-            user::create(u.clone()).await.unwrap();
-        }
+#[async_trait]
+impl Validator for ValidateRoamingAgreementsAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
 
-        let tests = vec![
-            // admin user
-            ValidatorTest {
-                validators: vec![ValidateIsAdmin::new()],
-                id: AuthID::User(users[0].id.into()),
-                ok: true,
-            },
-            // inactive
-            ValidatorTest {
-                validators: vec![ValidateIsAdmin::new()],
-                id: AuthID::User(users[1].id.into()),
-                ok: false,
-            },
-            // active regular user
-            ValidatorTest {
-                validators: vec![ValidateIsAdmin::new()],
-                id: AuthID::User(users[2].id.into()),
-                ok: false,
-            },
-        ];
+        match self.flag {
+            // global admin
+            Flag::Create | Flag::List => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
 
-        run_tests(tests).await;
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
     }
 
-    #[tokio::test]
-    async fn validate_active_user() {
-        let _guard = test::prepare().await;
-        let users = vec![
-            user::User {
-                email: "active@user".into(),
-                is_active: true,
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::Create | Flag::List => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateRoamingAgreementAccess {
+    flag: Flag,
+}
+
+impl ValidateRoamingAgreementAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidateRoamingAgreementAccess { flag }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateRoamingAgreementAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateJoinServerRoutesAccess {
+    flag: Flag,
+}
+
+impl ValidateJoinServerRoutesAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidateJoinServerRoutesAccess { flag }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateJoinServerRoutesAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            Flag::Create | Flag::List => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::Create | Flag::List => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateJoinServerRouteAccess {
+    flag: Flag,
+}
+
+impl ValidateJoinServerRouteAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidateJoinServerRouteAccess { flag }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateJoinServerRouteAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::Read | Flag::Update | Flag::Delete => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidateRoamingUsageAccess {
+    flag: Flag,
+}
+
+impl ValidateRoamingUsageAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidateRoamingUsageAccess { flag }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidateRoamingUsageAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            Flag::List => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::List => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+pub struct ValidatePassiveRoamingSessionsAccess {
+    flag: Flag,
+}
+
+impl ValidatePassiveRoamingSessionsAccess {
+    pub fn new(flag: Flag) -> Self {
+        ValidatePassiveRoamingSessionsAccess { flag }
+    }
+}
+
+#[async_trait]
+impl Validator for ValidatePassiveRoamingSessionsAccess {
+    async fn validate_user(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = user::dsl::user
+            .select(dsl::count_star())
+            .filter(
+                user::dsl::id
+                    .eq(fields::Uuid::from(id))
+                    .and(user::dsl::is_active.eq(true)),
+            )
+            .into_boxed();
+
+        match self.flag {
+            // global admin
+            Flag::List | Flag::Delete => {
+                q = q.filter(user::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+
+    async fn validate_key(&self, id: &Uuid) -> Result<i64, Error> {
+        let mut q = api_key::dsl::api_key
+            .select(dsl::count_star())
+            .find(fields::Uuid::from(id))
+            .into_boxed();
+
+        match self.flag {
+            // admin api key
+            Flag::List | Flag::Delete => {
+                q = q.filter(api_key::dsl::is_admin.eq(true));
+            }
+            _ => {
+                return Ok(0);
+            }
+        };
+
+        Ok(q.first(&mut get_async_db_conn().await?).await?)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::{
+        api_key, application, codec_library, device, device_profile, fuota, gateway, multicast,
+        tenant, tenant_role, user,
+    };
+    use crate::test;
+    use std::str::FromStr;
+
+    struct ValidatorTest<V>
+    where
+        V: Validator + Sync,
+    {
+        validators: Vec<V>,
+        id: AuthID,
+        ok: bool,
+    }
+
+    async fn run_tests<V>(tests: Vec<ValidatorTest<V>>)
+    where
+        V: Validator + Sync,
+    {
+        println!("Running tests");
+        for (i, tst) in tests.iter().enumerate() {
+            for (j, v) in tst.validators.iter().enumerate() {
+                assert_eq!(
+                    tst.ok,
+                    v.validate(&tst.id).await.is_ok(),
+                    "Test {}, assertion {}",
+                    i,
+                    j
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn validate_is_admin() {
+        let _guard = test::prepare().await;
+        let users = vec![
+            user::User {
+                email: "admin@user".into(),
+                is_active: true,
+                is_admin: true,
+                ..Default::default()
+            },
+            user::User {
+                email: "inactive@user".into(),
+                is_active: false,
+                is_admin: false,
+                ..Default::default()
+            },
+            user::User {
+                email: "normal@user".into(),
+                is_active: true,
+                is_admin: false,
+                ..Default::default()
+            },
+        ];
+        for u in &users {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let tests = vec![
+            // admin user
+            ValidatorTest {
+                validators: vec![ValidateIsAdmin::new()],
+                id: AuthID::User(users[0].id.into()),
+                ok: true,
+            },
+            // inactive
+            ValidatorTest {
+                validators: vec![ValidateIsAdmin::new()],
+                id: AuthID::User(users[1].id.into()),
+                ok: false,
+            },
+            // active regular user
+            ValidatorTest {
+                validators: vec![ValidateIsAdmin::new()],
+                id: AuthID::User(users[2].id.into()),
+                ok: false,
+            },
+        ];
+
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn validate_active_user() {
+        let _guard = test::prepare().await;
+        let users = vec![
+            user::User {
+                email: "active@user".into(),
+                is_active: true,
+                is_admin: false,
+                ..Default::default()
+            },
+            user::User {
+                email: "inactive@user".into(),
+                is_active: false,
+                is_admin: false,
+                ..Default::default()
+            },
+        ];
+        for u in &users {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key = api_key::test::create_api_key(true, false).await;
+
+        let tests = vec![
+            // active user
+            ValidatorTest {
+                validators: vec![ValidateActiveUser::new()],
+                id: AuthID::User(users[0].id.into()),
+                ok: true,
+            },
+            // inactive user
+            ValidatorTest {
+                validators: vec![ValidateActiveUser::new()],
+                id: AuthID::User(users[1].id.into()),
+                ok: false,
+            },
+            // api key
+            ValidatorTest {
+                validators: vec![ValidateActiveUser::new()],
+                id: AuthID::Key(api_key.id.into()),
+                ok: false,
+            },
+        ];
+
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn validate_active_user_or_key() {
+        let _guard = test::prepare().await;
+
+        let users = vec![
+            user::User {
+                email: "active@user".into(),
+                is_active: true,
+                is_admin: false,
+                ..Default::default()
+            },
+            user::User {
+                email: "inactive@user".into(),
+                is_active: false,
                 is_admin: false,
                 ..Default::default()
             },
-            user::User {
-                email: "inactive@user".into(),
-                is_active: false,
-                is_admin: false,
-                ..Default::default()
+        ];
+        for u in &users {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key = api_key::test::create_api_key(false, true).await;
+
+        let tests = vec![
+            // active user
+            ValidatorTest {
+                validators: vec![ValidateActiveUserOrKey::new()],
+                id: AuthID::User(users[0].id.into()),
+                ok: true,
+            },
+            // inactive user
+            ValidatorTest {
+                validators: vec![ValidateActiveUserOrKey::new()],
+                id: AuthID::User(users[1].id.into()),
+                ok: false,
+            },
+            // api key
+            ValidatorTest {
+                validators: vec![ValidateActiveUserOrKey::new()],
+                id: AuthID::Key(api_key.id.into()),
+                ok: true,
+            },
+            // non-existing key
+            ValidatorTest {
+                validators: vec![ValidateActiveUserOrKey::new()],
+                id: AuthID::Key(Uuid::new_v4()),
+                ok: false,
+            },
+        ];
+
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn validate_tenant() {
+        let _guard = test::prepare().await;
+        let user = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_user = user::User {
+            email: "tenant@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_admin = user::User {
+            email: "tenant-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let user_inactive = user::User {
+            email: "inactive@user".into(),
+            ..Default::default()
+        };
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+
+        for u in [
+            &user,
+            &tenant_user,
+            &tenant_admin,
+            &user_inactive,
+            &user_admin,
+        ] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let tenant_a = tenant::test::create_tenant().await;
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_admin.id,
+            is_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // tenants with user id
+        let tests = vec![
+            // global admin user can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantsAccess::new(Flag::Create),
+                    ValidateTenantsAccess::new(Flag::List),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can list
+            ValidatorTest {
+                validators: vec![ValidateTenantsAccess::new(Flag::List)],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // normal user can list
+            ValidatorTest {
+                validators: vec![ValidateTenantsAccess::new(Flag::List)],
+                id: AuthID::User(user.id.into()),
+                ok: true,
+            },
+            // tenant user can not create
+            ValidatorTest {
+                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // normal user can not create
+            ValidatorTest {
+                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+            // inactive user can not list
+            ValidatorTest {
+                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
+                id: AuthID::User(user_inactive.id.into()),
+                ok: false,
+            },
+        ];
+
+        run_tests(tests).await;
+
+        // tenants with api key
+        let tests = vec![
+            // admin api key can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantsAccess::new(Flag::Create),
+                    ValidateTenantsAccess::new(Flag::List),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api can not create or list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantsAccess::new(Flag::Create),
+                    ValidateTenantsAccess::new(Flag::List),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+
+        run_tests(tests).await;
+
+        // tenant with user
+        let tests = vec![
+            // global admin can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantAccess::new(Flag::Read, tenant_a.id.into()),
+                    ValidateTenantAccess::new(Flag::Update, tenant_a.id.into()),
+                    ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // tenant admin can read
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can read
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // tenant admin can not update
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: false,
+            },
+            // tenant admin can not delete
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: false,
+            },
+            // tenant user can not update
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // tenant user can not delete
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // normal user can not read
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+            // normal user can not update
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+            // normal user can not delete
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // tenant with api key
+        let tests = vec![
+            // admin api key can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantAccess::new(Flag::Read, tenant_a.id.into()),
+                    ValidateTenantAccess::new(Flag::Update, tenant_a.id.into()),
+                    ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into()),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can read
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(
+                    Flag::Read,
+                    api_key_tenant.tenant_id.unwrap().into(),
+                )],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key can not update
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(
+                    Flag::Update,
+                    api_key_tenant.tenant_id.unwrap().into(),
+                )],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+            // tenant api key can not delete
+            ValidatorTest {
+                validators: vec![ValidateTenantAccess::new(
+                    Flag::Delete,
+                    api_key_tenant.tenant_id.unwrap().into(),
+                )],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn tenant_user() {
+        let _guard = test::prepare().await;
+
+        let user = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+        let tenant_admin = user::User {
+            email: "tenant-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_user = user::User {
+            email: "tenant-user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_user_other = user::User {
+            email: "tenant-user-other@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        for u in [
+            &user,
+            &user_admin,
+            &tenant_admin,
+            &tenant_user,
+            &tenant_user_other,
+        ] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let tenant_a = tenant::test::create_tenant().await;
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_admin.id,
+            is_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_user_other.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // tenant users with user id
+        let tests = vec![
+            // admin user can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // tenant admin can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can list
+            ValidatorTest {
+                validators: vec![ValidateTenantUsersAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // tenant user can not create
+            ValidatorTest {
+                validators: vec![ValidateTenantUsersAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // normal user can not create
+            ValidatorTest {
+                validators: vec![ValidateTenantUsersAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+            // normal user can not list
+            ValidatorTest {
+                validators: vec![ValidateTenantUsersAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // tenant users with api key
+        let tests = vec![
+            // admin api key can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUsersAccess::new(
+                        Flag::Create,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                    ValidateTenantUsersAccess::new(
+                        Flag::List,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key for different tenant can not create or list
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // tenant user with user
+        let tests = vec![
+            // admin user can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // tenant admin can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can read own user
+            ValidatorTest {
+                validators: vec![ValidateTenantUserAccess::new(
+                    Flag::Read,
+                    tenant_a.id.into(),
+                    tenant_user.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // tenant user can not read other user
+            ValidatorTest {
+                validators: vec![ValidateTenantUserAccess::new(
+                    Flag::Read,
+                    tenant_a.id.into(),
+                    tenant_user_other.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // tenant user can not update or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // normal user can not read, update or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::User(user.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // tenant user with api key
+        let tests = vec![
+            // admin api key can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key can not read, update or delete for other tenant
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantUserAccess::new(
+                        Flag::Read,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Update,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                    ValidateTenantUserAccess::new(
+                        Flag::Delete,
+                        tenant_a.id.into(),
+                        tenant_user.id.into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn application() {
+        let _guard = test::prepare().await;
+
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+        let tenant_admin = user::User {
+            email: "tenant-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_device_admin = user::User {
+            email: "tenant-device-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_gateway_admin = user::User {
+            email: "tenant-gateway-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_user = user::User {
+            email: "tenant-user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        for u in [
+            &user_active,
+            &user_admin,
+            &tenant_admin,
+            &tenant_device_admin,
+            &tenant_gateway_admin,
+            &tenant_user,
+        ] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let tenant_a = tenant::test::create_tenant().await;
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        let app = application::test::create_application(Some(tenant_a.id.into())).await;
+        let app_api_key_tenant =
+            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
+                .await;
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_admin.id,
+            is_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_device_admin.id,
+            is_device_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_gateway_admin.id,
+            is_gateway_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // applications with user
+        let tests = vec![
+            // admin user can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // tenant admin can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant device admin can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(tenant_device_admin.id.into()),
+                ok: true,
+            },
+            // tenant gateway admin can list
+            ValidatorTest {
+                validators: vec![ValidateApplicationsAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can list
+            ValidatorTest {
+                validators: vec![ValidateApplicationsAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // tenant gateway admin can not create
+            ValidatorTest {
+                validators: vec![ValidateApplicationsAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
+                ok: false,
+            },
+            // tenant user can not create
+            ValidatorTest {
+                validators: vec![ValidateApplicationsAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // normal user can not create or list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
             },
         ];
-        for u in &users {
-            user::create(u.clone()).await.unwrap();
-        }
+        run_tests(tests).await;
 
-        let api_key = api_key::test::create_api_key(true, false).await;
+        // applications with api key
+        let tests = vec![
+            // admin api key can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(
+                        Flag::Create,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                    ValidateApplicationsAccess::new(
+                        Flag::List,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key can not create or list for other tenant
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
 
+        // application with user
         let tests = vec![
-            // active user
+            // admin user can read, update and delete
             ValidatorTest {
-                validators: vec![ValidateActiveUser::new()],
-                id: AuthID::User(users[0].id.into()),
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // inactive user
+            // tenant admin user can read, update and delete
             ValidatorTest {
-                validators: vec![ValidateActiveUser::new()],
-                id: AuthID::User(users[1].id.into()),
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant device admin can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::User(tenant_device_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can read
+            ValidatorTest {
+                validators: vec![ValidateApplicationAccess::new(Flag::Read, app.id.into())],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // user can not read, update or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
-            // api key
+            // tenant user can not update or delete
             ValidatorTest {
-                validators: vec![ValidateActiveUser::new()],
-                id: AuthID::Key(api_key.id.into()),
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
         ];
+        run_tests(tests).await;
 
+        // application with api key
+        let tests = vec![
+            // admin api key can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can read update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app_api_key_tenant.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app_api_key_tenant.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app_api_key_tenant.id.into()),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key can not read, update or delete app from other tentant
+            ValidatorTest {
+                validators: vec![
+                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
+                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
         run_tests(tests).await;
     }
 
     #[tokio::test]
-    async fn validate_active_user_or_key() {
+    async fn device_profile_test() {
         let _guard = test::prepare().await;
 
-        let users = vec![
-            user::User {
-                email: "active@user".into(),
-                is_active: true,
-                is_admin: false,
-                ..Default::default()
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+
+        for u in [&user_active, &user_admin] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        // device-profile templates with user
+        let tests = vec![
+            // admin user can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateDeviceProfileTemplatesAccess::new(Flag::Create),
+                    ValidateDeviceProfileTemplatesAccess::new(Flag::List),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
             },
-            user::User {
-                email: "inactive@user".into(),
-                is_active: false,
-                is_admin: false,
-                ..Default::default()
+            // user can list
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::List)],
+                id: AuthID::User(user_active.id.into()),
+                ok: true,
+            },
+            // user can not create
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::Create)],
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // device-profile templates with api key
+        let tests = vec![
+            // admin api can create and list
+            ValidatorTest {
+                validators: vec![
+                    ValidateDeviceProfileTemplatesAccess::new(Flag::Create),
+                    ValidateDeviceProfileTemplatesAccess::new(Flag::List),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
+                ok: true,
+            },
+            // tenant api key can list
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::List)],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api can not create
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::Create)],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // device-profile template with user
+        let tests = vec![
+            // admin user can read, update and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Read),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // user can read
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfileTemplateAccess::new(Flag::Read)],
+                id: AuthID::User(user_active.id.into()),
+                ok: true,
+            },
+            // user can not update or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                ],
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
             },
         ];
-        for u in &users {
-            user::create(u.clone()).await.unwrap();
-        }
-
-        let api_key = api_key::test::create_api_key(false, true).await;
+        run_tests(tests).await;
 
+        // device-profile template with api key
         let tests = vec![
-            // active user
+            // admin api key can read, update and delete
             ValidatorTest {
-                validators: vec![ValidateActiveUserOrKey::new()],
-                id: AuthID::User(users[0].id.into()),
+                validators: vec![
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Read),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                ],
+                id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // inactive user
-            ValidatorTest {
-                validators: vec![ValidateActiveUserOrKey::new()],
-                id: AuthID::User(users[1].id.into()),
-                ok: false,
-            },
-            // api key
+            // tenant api key can read
             ValidatorTest {
-                validators: vec![ValidateActiveUserOrKey::new()],
-                id: AuthID::Key(api_key.id.into()),
+                validators: vec![ValidateDeviceProfileTemplateAccess::new(Flag::Read)],
+                id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // non-existing key
+            // tenant api key can not update or delete
             ValidatorTest {
-                validators: vec![ValidateActiveUserOrKey::new()],
-                id: AuthID::Key(Uuid::new_v4()),
+                validators: vec![
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
+                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
             },
         ];
-
         run_tests(tests).await;
     }
 
     #[tokio::test]
-    async fn validate_tenant() {
+    async fn device_profile() {
         let _guard = test::prepare().await;
-        let user = user::User {
+
+        let user_active = user::User {
             email: "user@user".into(),
             is_active: true,
             ..Default::default()
         };
-        let tenant_user = user::User {
-            email: "tenant@user".into(),
+        let user_admin = user::User {
+            email: "admin@user".into(),
             is_active: true,
+            is_admin: true,
             ..Default::default()
         };
         let tenant_admin = user::User {
@@ -2455,23 +4622,29 @@ pub mod test {
             is_active: true,
             ..Default::default()
         };
-        let user_inactive = user::User {
-            email: "inactive@user".into(),
+        let tenant_device_admin = user::User {
+            email: "tenant-device-admin@user".into(),
+            is_active: true,
             ..Default::default()
         };
-        let user_admin = user::User {
-            email: "admin@user".into(),
+        let tenant_gateway_admin = user::User {
+            email: "tenant-gateway-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let tenant_user = user::User {
+            email: "tenant-user@user".into(),
             is_active: true,
-            is_admin: true,
             ..Default::default()
         };
 
         for u in [
-            &user,
-            &tenant_user,
-            &tenant_admin,
-            &user_inactive,
+            &user_active,
             &user_admin,
+            &tenant_admin,
+            &tenant_gateway_admin,
+            &tenant_device_admin,
+            &tenant_user,
         ] {
             user::create(u.clone()).await.unwrap();
         }
@@ -2481,9 +4654,16 @@ pub mod test {
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
 
-        tenant::add_user(tenant::TenantUser {
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            name: "test-dp".into(),
             tenant_id: tenant_a.id,
-            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let dp_api_key_tenant = device_profile::create(device_profile::DeviceProfile {
+            name: "test-dp-tenant".into(),
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             ..Default::default()
         })
         .await
@@ -2497,181 +4677,239 @@ pub mod test {
         })
         .await
         .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_device_admin.id,
+            is_device_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_gateway_admin.id,
+            is_gateway_admin: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: tenant_a.id,
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
 
-        // tenants with user id
+        // device profiles with user
         let tests = vec![
-            // global admin user can create and list
+            // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantsAccess::new(Flag::Create),
-                    ValidateTenantsAccess::new(Flag::List),
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant user can list
+            // tenant admin user can create and list
             ValidatorTest {
-                validators: vec![ValidateTenantsAccess::new(Flag::List)],
-                id: AuthID::User(tenant_user.id.into()),
+                validators: vec![
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // normal user can list
+            // tenant device admin can create and list
             ValidatorTest {
-                validators: vec![ValidateTenantsAccess::new(Flag::List)],
-                id: AuthID::User(user.id.into()),
+                validators: vec![
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
-            // tenant user can not create
+            // tenant gateway admin can list
             ValidatorTest {
-                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
+                validators: vec![ValidateDeviceProfilesAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
+                ok: true,
+            },
+            // tenant users can list
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfilesAccess::new(
+                    Flag::List,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: true,
+            },
+            // tenant users can not create
+            ValidatorTest {
+                validators: vec![ValidateDeviceProfilesAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // normal user can not create
+            // tenant gateway admin can not create
             ValidatorTest {
-                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
-                id: AuthID::User(user.id.into()),
+                validators: vec![ValidateDeviceProfilesAccess::new(
+                    Flag::Create,
+                    tenant_a.id.into(),
+                )],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
                 ok: false,
             },
-            // inactive user can not list
+            // non-tenant users can not list or create
             ValidatorTest {
-                validators: vec![ValidateTenantsAccess::new(Flag::Create)],
-                id: AuthID::User(user_inactive.id.into()),
+                validators: vec![
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
         ];
-
         run_tests(tests).await;
 
-        // tenants with api key
+        // device profiles with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantsAccess::new(Flag::Create),
-                    ValidateTenantsAccess::new(Flag::List),
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api can not create or list
+            // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantsAccess::new(Flag::Create),
-                    ValidateTenantsAccess::new(Flag::List),
+                    ValidateDeviceProfilesAccess::new(
+                        Flag::Create,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                    ValidateDeviceProfilesAccess::new(
+                        Flag::List,
+                        api_key_tenant.tenant_id.unwrap().into(),
+                    ),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
+            },
+            // tenant api key can not create or list for other tenant
+            ValidatorTest {
+                validators: vec![
+                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
             },
         ];
-
         run_tests(tests).await;
 
-        // tenant with user
+        // device profile with user
         let tests = vec![
-            // global admin can read, update and delete
+            // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantAccess::new(Flag::Read, tenant_a.id.into()),
-                    ValidateTenantAccess::new(Flag::Update, tenant_a.id.into()),
-                    ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can read
+            // tenant admin can read, update and delete
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant user can read
+            // tenant device admin can read, update and delete
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
-                id: AuthID::User(tenant_user.id.into()),
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                ],
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can not update
-            ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
-                id: AuthID::User(tenant_admin.id.into()),
-                ok: false,
-            },
-            // tenant admin can not delete
-            ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
-                id: AuthID::User(tenant_admin.id.into()),
-                ok: false,
-            },
-            // tenant user can not update
+            // tenant gateway admin can read
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
+                validators: vec![ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into())],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
+                ok: true,
             },
-            // tenant user can not delete
+            // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
+                validators: vec![ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
-                ok: false,
-            },
-            // normal user can not read
-            ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Read, tenant_a.id.into())],
-                id: AuthID::User(user.id.into()),
-                ok: false,
+                ok: true,
             },
-            // normal user can not update
+            // tenant gateway admin can not update or delete
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Update, tenant_a.id.into())],
-                id: AuthID::User(user.id.into()),
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                ],
+                id: AuthID::User(tenant_gateway_admin.id.into()),
                 ok: false,
             },
-            // normal user can not delete
+            // tenant user can not update or delete
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into())],
-                id: AuthID::User(user.id.into()),
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                ],
+                id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // tenant with api key
+        // device profile with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantAccess::new(Flag::Read, tenant_a.id.into()),
-                    ValidateTenantAccess::new(Flag::Update, tenant_a.id.into()),
-                    ValidateTenantAccess::new(Flag::Delete, tenant_a.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read
+            // tenant api key can read update and delete
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(
-                    Flag::Read,
-                    api_key_tenant.tenant_id.unwrap().into(),
-                )],
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp_api_key_tenant.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp_api_key_tenant.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp_api_key_tenant.id.into()),
+                ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // tenant api key can not update
-            ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(
-                    Flag::Update,
-                    api_key_tenant.tenant_id.unwrap().into(),
-                )],
-                id: AuthID::Key(api_key_tenant.id.into()),
-                ok: false,
-            },
-            // tenant api key can not delete
+            // tenant api key can not read, update or delete for other tenant
             ValidatorTest {
-                validators: vec![ValidateTenantAccess::new(
-                    Flag::Delete,
-                    api_key_tenant.tenant_id.unwrap().into(),
-                )],
+                validators: vec![
+                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
+                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
             },
@@ -2680,10 +4918,10 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn tenant_user() {
+    async fn codec_library() {
         let _guard = test::prepare().await;
 
-        let user = user::User {
+        let user_active = user::User {
             email: "user@user".into(),
             is_active: true,
             ..Default::default()
@@ -2694,8 +4932,8 @@ pub mod test {
             is_admin: true,
             ..Default::default()
         };
-        let tenant_admin = user::User {
-            email: "tenant-admin@user".into(),
+        let tenant_device_admin = user::User {
+            email: "tenant-device-admin@user".into(),
             is_active: true,
             ..Default::default()
         };
@@ -2704,18 +4942,12 @@ pub mod test {
             is_active: true,
             ..Default::default()
         };
-        let tenant_user_other = user::User {
-            email: "tenant-user-other@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
 
         for u in [
-            &user,
+            &user_active,
             &user_admin,
-            &tenant_admin,
+            &tenant_device_admin,
             &tenant_user,
-            &tenant_user_other,
         ] {
             user::create(u.clone()).await.unwrap();
         }
@@ -2725,102 +4957,94 @@ pub mod test {
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
 
-        tenant::add_user(tenant::TenantUser {
+        let cl = codec_library::create(codec_library::CodecLibrary {
+            name: "lib".into(),
             tenant_id: tenant_a.id,
-            user_id: tenant_admin.id,
-            is_admin: true,
             ..Default::default()
         })
         .await
         .unwrap();
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
-            user_id: tenant_user.id,
+        let cl_api_key_tenant = codec_library::create(codec_library::CodecLibrary {
+            name: "lib".into(),
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             ..Default::default()
         })
         .await
         .unwrap();
+
         tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_user.id,
+            tenant_id: tenant_a.id,
+            user_id: tenant_device_admin.id,
+            is_device_admin: true,
             ..Default::default()
         })
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
             tenant_id: tenant_a.id,
-            user_id: tenant_user_other.id,
+            user_id: tenant_user.id,
             ..Default::default()
         })
         .await
         .unwrap();
 
-        // tenant users with user id
+        // codec libraries with user
         let tests = vec![
             // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can create and list
+            // tenant device admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
-                id: AuthID::User(tenant_admin.id.into()),
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
-            // tenant user can list
+            // tenant users can list
             ValidatorTest {
-                validators: vec![ValidateTenantUsersAccess::new(
+                validators: vec![ValidateCodecLibrariesAccess::new(
                     Flag::List,
                     tenant_a.id.into(),
                 )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // tenant user can not create
+            // tenant users can not create
             ValidatorTest {
-                validators: vec![ValidateTenantUsersAccess::new(
+                validators: vec![ValidateCodecLibrariesAccess::new(
                     Flag::Create,
                     tenant_a.id.into(),
                 )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // normal user can not create
-            ValidatorTest {
-                validators: vec![ValidateTenantUsersAccess::new(
-                    Flag::Create,
-                    tenant_a.id.into(),
-                )],
-                id: AuthID::User(user.id.into()),
-                ok: false,
-            },
-            // normal user can not list
+            // non-tenant users can not list or create
             ValidatorTest {
-                validators: vec![ValidateTenantUsersAccess::new(
-                    Flag::List,
-                    tenant_a.id.into(),
-                )],
-                id: AuthID::User(user.id.into()),
+                validators: vec![
+                    ValidateCodecLibrariesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::List, tenant_a.id.into()),
+                ],
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // tenant users with api key
+        // codec libraries with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -2828,11 +5052,11 @@ pub mod test {
             // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUsersAccess::new(
+                    ValidateCodecLibrariesAccess::new(
                         Flag::Create,
                         api_key_tenant.tenant_id.unwrap().into(),
                     ),
-                    ValidateTenantUsersAccess::new(
+                    ValidateCodecLibrariesAccess::new(
                         Flag::List,
                         api_key_tenant.tenant_id.unwrap().into(),
                     ),
@@ -2840,11 +5064,11 @@ pub mod test {
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // tenant api key for different tenant can not create or list
+            // tenant api key can not create or list for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUsersAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateTenantUsersAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateCodecLibrariesAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
@@ -2852,134 +5076,64 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // tenant user with user
+        // codec library with user
         let tests = vec![
             // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can read, update and delete
+            // tenant device admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
-                id: AuthID::User(tenant_admin.id.into()),
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
-            // tenant user can read own user
+            // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateTenantUserAccess::new(
-                    Flag::Read,
-                    tenant_a.id.into(),
-                    tenant_user.id.into(),
-                )],
+                validators: vec![ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // tenant user can not read other user
-            ValidatorTest {
-                validators: vec![ValidateTenantUserAccess::new(
-                    Flag::Read,
-                    tenant_a.id.into(),
-                    tenant_user_other.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
-            },
             // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // normal user can not read, update or delete
+            // non-tenant user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
-                id: AuthID::User(user.id.into()),
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // tenant user with api key
+        // codec library with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -2987,21 +5141,9 @@ pub mod test {
             // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl_api_key_tenant.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl_api_key_tenant.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl_api_key_tenant.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
@@ -3009,21 +5151,9 @@ pub mod test {
             // tenant api key can not read, update or delete for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateTenantUserAccess::new(
-                        Flag::Read,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Update,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
-                    ValidateTenantUserAccess::new(
-                        Flag::Delete,
-                        tenant_a.id.into(),
-                        tenant_user.id.into(),
-                    ),
+                    ValidateCodecLibraryAccess::new(Flag::Read, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Update, cl.id.into()),
+                    ValidateCodecLibraryAccess::new(Flag::Delete, cl.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
@@ -3033,7 +5163,7 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn application() {
+    async fn device() {
         let _guard = test::prepare().await;
 
         let user_active = user::User {
@@ -3072,25 +5202,23 @@ pub mod test {
             &user_active,
             &user_admin,
             &tenant_admin,
-            &tenant_device_admin,
             &tenant_gateway_admin,
+            &tenant_device_admin,
             &tenant_user,
         ] {
             user::create(u.clone()).await.unwrap();
         }
 
-        let tenant_a = tenant::test::create_tenant().await;
-
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
+        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
 
-        let app = application::test::create_application(Some(tenant_a.id.into())).await;
-        let app_api_key_tenant =
+        let app =
             application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
                 .await;
 
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_admin.id,
             is_admin: true,
             ..Default::default()
@@ -3098,7 +5226,7 @@ pub mod test {
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_device_admin.id,
             is_device_admin: true,
             ..Default::default()
@@ -3106,7 +5234,7 @@ pub mod test {
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_gateway_admin.id,
             is_gateway_admin: true,
             ..Default::default()
@@ -3114,29 +5242,28 @@ pub mod test {
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_user.id,
             ..Default::default()
         })
         .await
         .unwrap();
 
-        // applications with user
         let tests = vec![
             // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can create and list
+            // tenant admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
@@ -3144,53 +5271,29 @@ pub mod test {
             // tenant device admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
-            // tenant gateway admin can list
-            ValidatorTest {
-                validators: vec![ValidateApplicationsAccess::new(
-                    Flag::List,
-                    tenant_a.id.into(),
-                )],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
-                ok: true,
-            },
             // tenant user can list
             ValidatorTest {
-                validators: vec![ValidateApplicationsAccess::new(
-                    Flag::List,
-                    tenant_a.id.into(),
-                )],
+                validators: vec![ValidateDevicesAccess::new(Flag::List, app.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // tenant gateway admin can not create
-            ValidatorTest {
-                validators: vec![ValidateApplicationsAccess::new(
-                    Flag::Create,
-                    tenant_a.id.into(),
-                )],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
-                ok: false,
-            },
             // tenant user can not create
             ValidatorTest {
-                validators: vec![ValidateApplicationsAccess::new(
-                    Flag::Create,
-                    tenant_a.id.into(),
-                )],
+                validators: vec![ValidateDevicesAccess::new(Flag::Create, app.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // normal user can not create or list
+            // other users can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -3198,13 +5301,12 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // applications with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -3212,14 +5314,8 @@ pub mod test {
             // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(
-                        Flag::Create,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                    ),
-                    ValidateApplicationsAccess::new(
-                        Flag::List,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                    ),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
@@ -3227,33 +5323,43 @@ pub mod test {
             // tenant api key can not create or list for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationsAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateApplicationsAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
+                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // application with user
+        let dp = device_profile::test::create_device_profile(Some(
+            api_key_tenant.tenant_id.unwrap().into(),
+        ))
+        .await;
+        let dev = device::test::create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            dp.id.into(),
+            Some(app.id.into()),
+        )
+        .await;
+
         let tests = vec![
             // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin user can read, update and delete
+            // tenant admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
@@ -3261,176 +5367,155 @@ pub mod test {
             // tenant device admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
             // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateApplicationAccess::new(Flag::Read, app.id.into())],
+                validators: vec![ValidateDeviceAccess::new(Flag::Read, dev.dev_eui)],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // user can not read, update or delete
+            // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::User(user_active.id.into()),
+                id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // tenant user can not update or delete
+            // other user can not read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::User(tenant_user.id.into()),
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // application with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read update and delete
-            ValidatorTest {
-                validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app_api_key_tenant.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app_api_key_tenant.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app_api_key_tenant.id.into()),
-                ],
-                id: AuthID::Key(api_key_tenant.id.into()),
-                ok: true,
-            },
-            // tenant api key can not read, update or delete app from other tentant
+            // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateApplicationAccess::new(Flag::Read, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Update, app.id.into()),
-                    ValidateApplicationAccess::new(Flag::Delete, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
-                ok: false,
-            },
-        ];
-        run_tests(tests).await;
-    }
-
-    #[tokio::test]
-    async fn device_profile_test() {
-        let _guard = test::prepare().await;
-
-        let user_active = user::User {
-            email: "user@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-
-        let user_admin = user::User {
-            email: "admin@user".into(),
-            is_active: true,
-            is_admin: true,
-            ..Default::default()
-        };
-
-        for u in [&user_active, &user_admin] {
-            user::create(u.clone()).await.unwrap();
-        }
-
-        let api_key_admin = api_key::test::create_api_key(true, false).await;
-        let api_key_tenant = api_key::test::create_api_key(false, true).await;
-
-        // device-profile templates with user
-        let tests = vec![
-            // admin user can create and list
-            ValidatorTest {
-                validators: vec![
-                    ValidateDeviceProfileTemplatesAccess::new(Flag::Create),
-                    ValidateDeviceProfileTemplatesAccess::new(Flag::List),
-                ],
-                id: AuthID::User(user_admin.id.into()),
-                ok: true,
-            },
-            // user can list
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::List)],
-                id: AuthID::User(user_active.id.into()),
                 ok: true,
             },
-            // user can not create
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::Create)],
-                id: AuthID::User(user_active.id.into()),
-                ok: false,
-            },
-        ];
-        run_tests(tests).await;
-
-        // device-profile templates with api key
-        let tests = vec![
-            // admin api can create and list
+            // other api key can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileTemplatesAccess::new(Flag::Create),
-                    ValidateDeviceProfileTemplatesAccess::new(Flag::List),
+                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::Key(api_key_admin.id.into()),
-                ok: true,
-            },
-            // tenant api key can list
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::List)],
-                id: AuthID::Key(api_key_tenant.id.into()),
-                ok: true,
-            },
-            // tenant api can not create
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplatesAccess::new(Flag::Create)],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn device_queue() {
+        let _guard = test::prepare().await;
+
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+        let tenant_user = user::User {
+            email: "tenant-user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        for u in [&user_active, &user_admin, &tenant_user] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
+
+        let app =
+            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
+                .await;
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
+            user_id: tenant_user.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp = device_profile::test::create_device_profile(Some(
+            api_key_tenant.tenant_id.unwrap().into(),
+        ))
+        .await;
+        let dev = device::test::create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            dp.id.into(),
+            Some(app.id.into()),
+        )
+        .await;
 
-        // device-profile template with user
         let tests = vec![
-            // admin user can read, update and delete
+            // admin user can create list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Read),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // user can read
+            // tenant user can create list and delete
             ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplateAccess::new(Flag::Read)],
-                id: AuthID::User(user_active.id.into()),
+                validators: vec![
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                ],
+                id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // user can not update or delete
+            // other user can not create, list or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -3438,31 +5523,35 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // device-profile template with api key
         let tests = vec![
-            // admin api key can read, update and delete
+            // admin api key can create, list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Read),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read
+            // tenant api key can create, list and delete
             ValidatorTest {
-                validators: vec![ValidateDeviceProfileTemplateAccess::new(Flag::Read)],
+                validators: vec![
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // tenant api key can not update or delete
+            // api key for other tenant cna not create, list or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Update),
-                    ValidateDeviceProfileTemplateAccess::new(Flag::Delete),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
@@ -3470,7 +5559,7 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn device_profile() {
+    async fn gateway() {
         let _guard = test::prepare().await;
 
         let user_active = user::User {
@@ -3489,11 +5578,6 @@ pub mod test {
             is_active: true,
             ..Default::default()
         };
-        let tenant_device_admin = user::User {
-            email: "tenant-device-admin@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
         let tenant_gateway_admin = user::User {
             email: "tenant-gateway-admin@user".into(),
             is_active: true,
@@ -3510,7 +5594,6 @@ pub mod test {
             &user_admin,
             &tenant_admin,
             &tenant_gateway_admin,
-            &tenant_device_admin,
             &tenant_user,
         ] {
             user::create(u.clone()).await.unwrap();
@@ -3521,15 +5604,17 @@ pub mod test {
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
 
-        let dp = device_profile::create(device_profile::DeviceProfile {
-            name: "test-dp".into(),
+        let gw = gateway::create(gateway::Gateway {
+            name: "test-gw".into(),
+            gateway_id: EUI64::from_str("0102030405060708").unwrap(),
             tenant_id: tenant_a.id,
             ..Default::default()
         })
         .await
         .unwrap();
-        let dp_api_key_tenant = device_profile::create(device_profile::DeviceProfile {
-            name: "test-dp-tenant".into(),
+        let gw_api_key_tenant = gateway::create(gateway::Gateway {
+            name: "test-gw-tenant".into(),
+            gateway_id: EUI64::from_str("0202030405060708").unwrap(),
             tenant_id: api_key_tenant.tenant_id.unwrap(),
             ..Default::default()
         })
@@ -3544,14 +5629,6 @@ pub mod test {
         })
         .await
         .unwrap();
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
-            user_id: tenant_device_admin.id,
-            is_device_admin: true,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
         tenant::add_user(tenant::TenantUser {
             tenant_id: tenant_a.id,
             user_id: tenant_gateway_admin.id,
@@ -3568,76 +5645,55 @@ pub mod test {
         .await
         .unwrap();
 
-        // device profiles with user
+        // gateways with user
         let tests = vec![
             // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin user can create and list
+            // tenant admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant device admin can create and list
+            // tenant gateway admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
-                id: AuthID::User(tenant_device_admin.id.into()),
-                ok: true,
-            },
-            // tenant gateway admin can list
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfilesAccess::new(
-                    Flag::List,
-                    tenant_a.id.into(),
-                )],
                 id: AuthID::User(tenant_gateway_admin.id.into()),
                 ok: true,
             },
-            // tenant users can list
+            // tenant user can list
             ValidatorTest {
-                validators: vec![ValidateDeviceProfilesAccess::new(
-                    Flag::List,
-                    tenant_a.id.into(),
-                )],
+                validators: vec![ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // tenant users can not create
+            // tenant user can not create
             ValidatorTest {
-                validators: vec![ValidateDeviceProfilesAccess::new(
+                validators: vec![ValidateGatewaysAccess::new(
                     Flag::Create,
                     tenant_a.id.into(),
                 )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // tenant gateway admin can not create
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfilesAccess::new(
-                    Flag::Create,
-                    tenant_a.id.into(),
-                )],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
-                ok: false,
-            },
-            // non-tenant users can not list or create
+            // other users can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -3645,13 +5701,13 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // device profiles with api key
+        // gateways with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -3659,11 +5715,11 @@ pub mod test {
             // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(
+                    ValidateGatewaysAccess::new(
                         Flag::Create,
                         api_key_tenant.tenant_id.unwrap().into(),
                     ),
-                    ValidateDeviceProfilesAccess::new(
+                    ValidateGatewaysAccess::new(
                         Flag::List,
                         api_key_tenant.tenant_id.unwrap().into(),
                     ),
@@ -3674,8 +5730,8 @@ pub mod test {
             // tenant api key can not create or list for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfilesAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateDeviceProfilesAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
+                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
@@ -3683,14 +5739,14 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // device profile with user
+        // gateway with user
         let tests = vec![
             // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
@@ -3698,84 +5754,79 @@ pub mod test {
             // tenant admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant device admin can read, update and delete
+            // tenant gateway admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
-                id: AuthID::User(tenant_device_admin.id.into()),
-                ok: true,
-            },
-            // tenant gateway admin can read
-            ValidatorTest {
-                validators: vec![ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into())],
                 id: AuthID::User(tenant_gateway_admin.id.into()),
                 ok: true,
             },
             // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into())],
+                validators: vec![ValidateGatewayAccess::new(Flag::Read, gw.gateway_id)],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // tenant gateway admin can not update or delete
+            // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
+                id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // tenant user can not update or delete
+            // other user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
-                id: AuthID::User(tenant_user.id.into()),
+                id: AuthID::User(user_active.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // device profile with api key
+        // gateway with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read update and delete
+            // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp_api_key_tenant.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp_api_key_tenant.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp_api_key_tenant.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw_api_key_tenant.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw_api_key_tenant.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw_api_key_tenant.gateway_id),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // tenant api key can not read, update or delete for other tenant
+            // tenant api key can not read, update or delete gw from other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceProfileAccess::new(Flag::Read, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Update, dp.id.into()),
-                    ValidateDeviceProfileAccess::new(Flag::Delete, dp.id.into()),
+                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
@@ -3785,7 +5836,7 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn device() {
+    async fn multicast_group() {
         let _guard = test::prepare().await;
 
         let user_active = user::User {
@@ -3871,21 +5922,22 @@ pub mod test {
         .await
         .unwrap();
 
+        // multicast-groups with user
         let tests = vec![
             // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin user can create and list
+            // tenant admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
@@ -3893,29 +5945,35 @@ pub mod test {
             // tenant device admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
             // tenant user can list
             ValidatorTest {
-                validators: vec![ValidateDevicesAccess::new(Flag::List, app.id.into())],
+                validators: vec![ValidateMulticastGroupsAccess::new(
+                    Flag::List,
+                    app.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
             // tenant user can not create
             ValidatorTest {
-                validators: vec![ValidateDevicesAccess::new(Flag::Create, app.id.into())],
+                validators: vec![ValidateMulticastGroupsAccess::new(
+                    Flag::Create,
+                    app.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // other users can not create or list
+            // other user can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -3923,12 +5981,13 @@ pub mod test {
         ];
         run_tests(tests).await;
 
+        // multicast-groups with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -3936,8 +5995,8 @@ pub mod test {
             // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
@@ -3945,8 +6004,8 @@ pub mod test {
             // tenant api key can not create or list for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateDevicesAccess::new(Flag::Create, app.id.into()),
-                    ValidateDevicesAccess::new(Flag::List, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
+                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
@@ -3954,24 +6013,22 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        let dp = device_profile::test::create_device_profile(Some(
-            api_key_tenant.tenant_id.unwrap().into(),
-        ))
-        .await;
-        let dev = device::test::create_device(
-            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
-            dp.id.into(),
-            Some(app.id.into()),
-        )
-        .await;
+        let mg = multicast::create(multicast::MulticastGroup {
+            name: "test-mg".into(),
+            application_id: app.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
 
+        // multicast-group with user
         let tests = vec![
             // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
@@ -3979,9 +6036,9 @@ pub mod test {
             // tenant admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
@@ -3989,34 +6046,34 @@ pub mod test {
             // tenant device admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
             // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateDeviceAccess::new(Flag::Read, dev.dev_eui)],
+                validators: vec![ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into())],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
             // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // other user can not read, update and delete
+            // other user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4024,13 +6081,14 @@ pub mod test {
         ];
         run_tests(tests).await;
 
+        // multicast-group with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -4038,9 +6096,9 @@ pub mod test {
             // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
@@ -4048,96 +6106,72 @@ pub mod test {
             // other api key can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceAccess::new(Flag::Read, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
-                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
+                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
-    }
-
-    #[tokio::test]
-    async fn device_queue() {
-        let _guard = test::prepare().await;
-
-        let user_active = user::User {
-            email: "user@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-        let user_admin = user::User {
-            email: "admin@user".into(),
-            is_active: true,
-            is_admin: true,
-            ..Default::default()
-        };
-        let tenant_user = user::User {
-            email: "tenant-user@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-
-        for u in [&user_active, &user_admin, &tenant_user] {
-            user::create(u.clone()).await.unwrap();
-        }
-
-        let api_key_admin = api_key::test::create_api_key(true, false).await;
-        let api_key_tenant = api_key::test::create_api_key(false, true).await;
-        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
-
-        let app =
-            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
-                .await;
-
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_user.id,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-
-        let dp = device_profile::test::create_device_profile(Some(
-            api_key_tenant.tenant_id.unwrap().into(),
-        ))
-        .await;
-        let dev = device::test::create_device(
-            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
-            dp.id.into(),
-            Some(app.id.into()),
-        )
-        .await;
 
+        // multicast-group queue with user
         let tests = vec![
-            // admin user can create list and delete
+            // admin user can create, list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant user can create list and delete
+            // tenant admin can create, list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
+                ok: true,
+            },
+            // tenant device admin can create, list and delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
+                id: AuthID::User(tenant_device_admin.id.into()),
+                ok: true,
+            },
+            // tenant user can list
+            ValidatorTest {
+                validators: vec![ValidateMulticastGroupQueueAccess::new(
+                    Flag::List,
+                    mg.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
-            // other user can not create, list or delete
+            // tenant user can not create or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                ],
+                id: AuthID::User(tenant_user.id.into()),
+                ok: false,
+            },
+            // uther user can not create, list or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4145,13 +6179,14 @@ pub mod test {
         ];
         run_tests(tests).await;
 
+        // multicast-group queue with api key
         let tests = vec![
             // admin api key can create, list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -4159,19 +6194,19 @@ pub mod test {
             // tenant api key can create, list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // api key for other tenant cna not create, list or delete
+            // other api key can not create, list or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
-                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
+                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
                 ],
                 id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
@@ -4181,7 +6216,7 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn gateway() {
+    async fn fuota_deployment() {
         let _guard = test::prepare().await;
 
         let user_active = user::User {
@@ -4200,6 +6235,11 @@ pub mod test {
             is_active: true,
             ..Default::default()
         };
+        let tenant_device_admin = user::User {
+            email: "tenant-device-admin@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
         let tenant_gateway_admin = user::User {
             email: "tenant-gateway-admin@user".into(),
             is_active: true,
@@ -4216,43 +6256,38 @@ pub mod test {
             &user_admin,
             &tenant_admin,
             &tenant_gateway_admin,
+            &tenant_device_admin,
             &tenant_user,
         ] {
             user::create(u.clone()).await.unwrap();
         }
 
-        let tenant_a = tenant::test::create_tenant().await;
-
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
+        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
 
-        let gw = gateway::create(gateway::Gateway {
-            name: "test-gw".into(),
-            gateway_id: EUI64::from_str("0102030405060708").unwrap(),
-            tenant_id: tenant_a.id,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-        let gw_api_key_tenant = gateway::create(gateway::Gateway {
-            name: "test-gw-tenant".into(),
-            gateway_id: EUI64::from_str("0202030405060708").unwrap(),
+        let app =
+            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
+                .await;
+
+        tenant::add_user(tenant::TenantUser {
             tenant_id: api_key_tenant.tenant_id.unwrap(),
+            user_id: tenant_admin.id,
+            is_admin: true,
             ..Default::default()
         })
         .await
         .unwrap();
-
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
-            user_id: tenant_admin.id,
-            is_admin: true,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
+            user_id: tenant_device_admin.id,
+            is_device_admin: true,
             ..Default::default()
         })
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_gateway_admin.id,
             is_gateway_admin: true,
             ..Default::default()
@@ -4260,20 +6295,20 @@ pub mod test {
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: tenant_a.id,
+            tenant_id: api_key_tenant.tenant_id.unwrap(),
             user_id: tenant_user.id,
             ..Default::default()
         })
         .await
         .unwrap();
 
-        // gateways with user
+        // fuota deployments with user
         let tests = vec![
             // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
@@ -4281,41 +6316,44 @@ pub mod test {
             // tenant admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant gateway admin can create and list
+            // tenant device admin can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
             // tenant user can list
             ValidatorTest {
-                validators: vec![ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into())],
+                validators: vec![ValidateFuotaDeploymentsAccess::new(
+                    Flag::List,
+                    app.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
             // tenant user can not create
             ValidatorTest {
-                validators: vec![ValidateGatewaysAccess::new(
+                validators: vec![ValidateFuotaDeploymentsAccess::new(
                     Flag::Create,
-                    tenant_a.id.into(),
+                    app.id.into(),
                 )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
             },
-            // other users can not create or list
+            // other user can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4323,13 +6361,13 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // gateways with api key
+        // fuota deployments with api key
         let tests = vec![
             // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -4337,14 +6375,8 @@ pub mod test {
             // tenant api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(
-                        Flag::Create,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                    ),
-                    ValidateGatewaysAccess::new(
-                        Flag::List,
-                        api_key_tenant.tenant_id.unwrap().into(),
-                    ),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
@@ -4352,23 +6384,40 @@ pub mod test {
             // tenant api key can not create or list for other tenant
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewaysAccess::new(Flag::Create, tenant_a.id.into()),
-                    ValidateGatewaysAccess::new(Flag::List, tenant_a.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
+                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // gateway with user
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            tenant_id: app.tenant_id,
+            name: "test-dp".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let fuota = fuota::create_deployment(fuota::FuotaDeployment {
+            name: "test-fuota".into(),
+            application_id: app.id,
+            device_profile_id: dp.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // fuota deployment with user
         let tests = vec![
             // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
@@ -4376,34 +6425,37 @@ pub mod test {
             // tenant admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant gateway admin can read, update and delete
+            // tenant device admin can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
-                id: AuthID::User(tenant_gateway_admin.id.into()),
+                id: AuthID::User(tenant_device_admin.id.into()),
                 ok: true,
             },
             // tenant user can read
             ValidatorTest {
-                validators: vec![ValidateGatewayAccess::new(Flag::Read, gw.gateway_id)],
+                validators: vec![ValidateFuotaDeploymentAccess::new(
+                    Flag::Read,
+                    fuota.id.into(),
+                )],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: true,
             },
             // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
                 id: AuthID::User(tenant_user.id.into()),
                 ok: false,
@@ -4411,9 +6463,9 @@ pub mod test {
             // other user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4421,14 +6473,14 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // gateway with api key
+        // fuota deployment with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
@@ -4436,21 +6488,21 @@ pub mod test {
             // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw_api_key_tenant.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw_api_key_tenant.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw_api_key_tenant.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can not read, update or delete gw from other tenant
+            // other api key can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateGatewayAccess::new(Flag::Read, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
-                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
+                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
+                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
@@ -4458,7 +6510,7 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn multicast_group() {
+    async fn roaming_usage() {
         let _guard = test::prepare().await;
 
         let user_active = user::User {
@@ -4466,136 +6518,96 @@ pub mod test {
             is_active: true,
             ..Default::default()
         };
+
         let user_admin = user::User {
             email: "admin@user".into(),
             is_active: true,
             is_admin: true,
             ..Default::default()
         };
-        let tenant_admin = user::User {
-            email: "tenant-admin@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-        let tenant_device_admin = user::User {
-            email: "tenant-device-admin@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-        let tenant_gateway_admin = user::User {
-            email: "tenant-gateway-admin@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-        let tenant_user = user::User {
-            email: "tenant-user@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
 
-        for u in [
-            &user_active,
-            &user_admin,
-            &tenant_admin,
-            &tenant_gateway_admin,
-            &tenant_device_admin,
-            &tenant_user,
-        ] {
+        for u in [&user_active, &user_admin] {
             user::create(u.clone()).await.unwrap();
         }
 
         let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
-        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
-
-        let app =
-            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
-                .await;
-
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_admin.id,
-            is_admin: true,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_device_admin.id,
-            is_device_admin: true,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_gateway_admin.id,
-            is_gateway_admin: true,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-        tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_user.id,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
 
-        // multicast-groups with user
+        // roaming usage with user
         let tests = vec![
-            // admin user can create and list
-            ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::User(user_admin.id.into()),
-                ok: true,
-            },
-            // tenant admin can create and list
+            // admin user can list
             ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::User(tenant_admin.id.into()),
+                validators: vec![ValidateRoamingUsageAccess::new(Flag::List)],
+                id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant device admin can create and list
+            // user can not list
             ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::User(tenant_device_admin.id.into()),
-                ok: true,
+                validators: vec![ValidateRoamingUsageAccess::new(Flag::List)],
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
             },
-            // tenant user can list
+        ];
+        run_tests(tests).await;
+
+        // roaming usage with api key
+        let tests = vec![
+            // admin api key can list
             ValidatorTest {
-                validators: vec![ValidateMulticastGroupsAccess::new(
-                    Flag::List,
-                    app.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
+                validators: vec![ValidateRoamingUsageAccess::new(Flag::List)],
+                id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant user can not create
+            // tenant api key can not list
             ValidatorTest {
-                validators: vec![ValidateMulticastGroupsAccess::new(
-                    Flag::Create,
-                    app.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
+                validators: vec![ValidateRoamingUsageAccess::new(Flag::List)],
+                id: AuthID::Key(api_key_tenant.id.into()),
                 ok: false,
             },
-            // other user can not create or list
+        ];
+        run_tests(tests).await;
+    }
+
+    #[tokio::test]
+    async fn passive_roaming_session() {
+        let _guard = test::prepare().await;
+
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+
+        for u in [&user_active, &user_admin] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        // passive-roaming sessions with user
+        let tests = vec![
+            // admin user can list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::List),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::Delete),
+                ],
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
+            },
+            // user can not list or delete
+            ValidatorTest {
+                validators: vec![
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::List),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::Delete),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4603,99 +6615,118 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // multicast-groups with api key
+        // passive-roaming sessions with api key
         let tests = vec![
-            // admin api key can create and list
+            // admin api key can list and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::List),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can create and list
+            // tenant api key can not list or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::List),
+                    ValidatePassiveRoamingSessionsAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
-                ok: true,
-            },
-            // tenant api key can not create or list for other tenant
-            ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupsAccess::new(Flag::Create, app.id.into()),
-                    ValidateMulticastGroupsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
+    }
 
-        let mg = multicast::create(multicast::MulticastGroup {
-            name: "test-mg".into(),
-            application_id: app.id,
+    #[tokio::test]
+    async fn roaming_agreement() {
+        let _guard = test::prepare().await;
+
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
             ..Default::default()
-        })
-        .await
-        .unwrap();
+        };
 
-        // multicast-group with user
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+
+        for u in [&user_active, &user_admin] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        // roaming agreements with user
         let tests = vec![
-            // admin user can read, update and delete
+            // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementsAccess::new(Flag::Create),
+                    ValidateRoamingAgreementsAccess::new(Flag::List),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can read, update and delete
+            // user can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementsAccess::new(Flag::Create),
+                    ValidateRoamingAgreementsAccess::new(Flag::List),
                 ],
-                id: AuthID::User(tenant_admin.id.into()),
-                ok: true,
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
             },
-            // tenant device admin can read, update and delete
+        ];
+        run_tests(tests).await;
+
+        // roaming agreements with api key
+        let tests = vec![
+            // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementsAccess::new(Flag::Create),
+                    ValidateRoamingAgreementsAccess::new(Flag::List),
                 ],
-                id: AuthID::User(tenant_device_admin.id.into()),
+                id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant user can read
+            // tenant api key can not create or list
             ValidatorTest {
-                validators: vec![ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into())],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: true,
+                validators: vec![
+                    ValidateRoamingAgreementsAccess::new(Flag::Create),
+                    ValidateRoamingAgreementsAccess::new(Flag::List),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
             },
-            // tenant user can not update or delete
+        ];
+        run_tests(tests).await;
+
+        // roaming agreement with user
+        let tests = vec![
+            // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementAccess::new(Flag::Read),
+                    ValidateRoamingAgreementAccess::new(Flag::Update),
+                    ValidateRoamingAgreementAccess::new(Flag::Delete),
                 ],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
             },
-            // other user can not read, update or delete
+            // user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementAccess::new(Flag::Read),
+                    ValidateRoamingAgreementAccess::new(Flag::Update),
+                    ValidateRoamingAgreementAccess::new(Flag::Delete),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4703,97 +6734,120 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // multicast-group with api key
+        // roaming agreement with api key
         let tests = vec![
             // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementAccess::new(Flag::Read),
+                    ValidateRoamingAgreementAccess::new(Flag::Update),
+                    ValidateRoamingAgreementAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read, update and delete
+            // tenant api key can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateRoamingAgreementAccess::new(Flag::Read),
+                    ValidateRoamingAgreementAccess::new(Flag::Update),
+                    ValidateRoamingAgreementAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
-                ok: true,
-            },
-            // other api key can not read, update or delete
-            ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupAccess::new(Flag::Read, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Update, mg.id.into()),
-                    ValidateMulticastGroupAccess::new(Flag::Delete, mg.id.into()),
-                ],
-                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
+    }
 
-        // multicast-group queue with user
+    #[tokio::test]
+    async fn join_server_route() {
+        let _guard = test::prepare().await;
+
+        let user_active = user::User {
+            email: "user@user".into(),
+            is_active: true,
+            ..Default::default()
+        };
+
+        let user_admin = user::User {
+            email: "admin@user".into(),
+            is_active: true,
+            is_admin: true,
+            ..Default::default()
+        };
+
+        for u in [&user_active, &user_admin] {
+            user::create(u.clone()).await.unwrap();
+        }
+
+        let api_key_admin = api_key::test::create_api_key(true, false).await;
+        let api_key_tenant = api_key::test::create_api_key(false, true).await;
+
+        // join server routes with user
         let tests = vec![
-            // admin user can create, list and delete
+            // admin user can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRoutesAccess::new(Flag::Create),
+                    ValidateJoinServerRoutesAccess::new(Flag::List),
                 ],
                 id: AuthID::User(user_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can create, list and delete
+            // user can not create or list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRoutesAccess::new(Flag::Create),
+                    ValidateJoinServerRoutesAccess::new(Flag::List),
                 ],
-                id: AuthID::User(tenant_admin.id.into()),
-                ok: true,
+                id: AuthID::User(user_active.id.into()),
+                ok: false,
             },
-            // tenant device admin can create, list and delete
+        ];
+        run_tests(tests).await;
+
+        // join server routes with api key
+        let tests = vec![
+            // admin api key can create and list
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRoutesAccess::new(Flag::Create),
+                    ValidateJoinServerRoutesAccess::new(Flag::List),
                 ],
-                id: AuthID::User(tenant_device_admin.id.into()),
+                id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant user can list
+            // tenant api key can not create or list
             ValidatorTest {
-                validators: vec![ValidateMulticastGroupQueueAccess::new(
-                    Flag::List,
-                    mg.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: true,
+                validators: vec![
+                    ValidateJoinServerRoutesAccess::new(Flag::Create),
+                    ValidateJoinServerRoutesAccess::new(Flag::List),
+                ],
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: false,
             },
-            // tenant user can not create or delete
+        ];
+        run_tests(tests).await;
+
+        // join server route with user
+        let tests = vec![
+            // admin user can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRouteAccess::new(Flag::Read),
+                    ValidateJoinServerRouteAccess::new(Flag::Update),
+                    ValidateJoinServerRouteAccess::new(Flag::Delete),
                 ],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
+                id: AuthID::User(user_admin.id.into()),
+                ok: true,
             },
-            // uther user can not create, list or delete
+            // user can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRouteAccess::new(Flag::Read),
+                    ValidateJoinServerRouteAccess::new(Flag::Update),
+                    ValidateJoinServerRouteAccess::new(Flag::Delete),
                 ],
                 id: AuthID::User(user_active.id.into()),
                 ok: false,
@@ -4801,36 +6855,26 @@ pub mod test {
         ];
         run_tests(tests).await;
 
-        // multicast-group queue with api key
+        // join server route with api key
         let tests = vec![
-            // admin api key can create, list and delete
+            // admin api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRouteAccess::new(Flag::Read),
+                    ValidateJoinServerRouteAccess::new(Flag::Update),
+                    ValidateJoinServerRouteAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can create, list and delete
+            // tenant api key can not read, update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
+                    ValidateJoinServerRouteAccess::new(Flag::Read),
+                    ValidateJoinServerRouteAccess::new(Flag::Update),
+                    ValidateJoinServerRouteAccess::new(Flag::Delete),
                 ],
                 id: AuthID::Key(api_key_tenant.id.into()),
-                ok: true,
-            },
-            // other api key can not create, list or delete
-            ValidatorTest {
-                validators: vec![
-                    ValidateMulticastGroupQueueAccess::new(Flag::Create, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::List, mg.id.into()),
-                    ValidateMulticastGroupQueueAccess::new(Flag::Delete, mg.id.into()),
-                ],
-                id: AuthID::Key(api_key_other_tenant.id.into()),
                 ok: false,
             },
         ];
@@ -4838,293 +6882,308 @@ pub mod test {
     }
 
     #[tokio::test]
-    async fn fuota_deployment() {
+    async fn tenant_role() {
         let _guard = test::prepare().await;
 
-        let user_active = user::User {
-            email: "user@user".into(),
-            is_active: true,
-            ..Default::default()
-        };
-        let user_admin = user::User {
-            email: "admin@user".into(),
-            is_active: true,
-            is_admin: true,
-            ..Default::default()
-        };
         let tenant_admin = user::User {
             email: "tenant-admin@user".into(),
             is_active: true,
             ..Default::default()
         };
-        let tenant_device_admin = user::User {
-            email: "tenant-device-admin@user".into(),
+        let queue_operator = user::User {
+            email: "queue-operator@user".into(),
             is_active: true,
             ..Default::default()
         };
-        let tenant_gateway_admin = user::User {
-            email: "tenant-gateway-admin@user".into(),
+        let gateway_viewer = user::User {
+            email: "gateway-viewer@user".into(),
             is_active: true,
             ..Default::default()
         };
-        let tenant_user = user::User {
-            email: "tenant-user@user".into(),
+        let key_viewer = user::User {
+            email: "key-viewer@user".into(),
             is_active: true,
             ..Default::default()
         };
 
-        for u in [
-            &user_active,
-            &user_admin,
-            &tenant_admin,
-            &tenant_gateway_admin,
-            &tenant_device_admin,
-            &tenant_user,
-        ] {
+        for u in [&tenant_admin, &queue_operator, &gateway_viewer, &key_viewer] {
             user::create(u.clone()).await.unwrap();
         }
 
-        let api_key_admin = api_key::test::create_api_key(true, false).await;
         let api_key_tenant = api_key::test::create_api_key(false, true).await;
-        let api_key_other_tenant = api_key::test::create_api_key(false, true).await;
+        let tenant_id: Uuid = api_key_tenant.tenant_id.unwrap().into();
 
-        let app =
-            application::test::create_application(Some(api_key_tenant.tenant_id.unwrap().into()))
-                .await;
+        let app = application::test::create_application(Some(tenant_id)).await;
+        let dp = device_profile::test::create_device_profile(Some(tenant_id)).await;
+        let dev = device::test::create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            dp.id.into(),
+            Some(app.id.into()),
+        )
+        .await;
+        let gw = gateway::create(gateway::Gateway {
+            name: "test-gw".into(),
+            gateway_id: EUI64::from_str("0102030405060708").unwrap(),
+            tenant_id: tenant_id.into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let role_queue_operator = tenant_role::test::create_tenant_role(
+            &tenant_id,
+            &[
+                Permission::DeviceRead,
+                Permission::DeviceQueueRead,
+                Permission::DeviceQueueWrite,
+            ],
+        )
+        .await;
+        let role_gateway_viewer =
+            tenant_role::test::create_tenant_role(&tenant_id, &[Permission::GatewayRead]).await;
+        let role_key_viewer = tenant_role::test::create_tenant_role(
+            &tenant_id,
+            &[
+                Permission::ApiKeyRead,
+                Permission::TenantUserRead,
+                Permission::TenantUserWrite,
+            ],
+        )
+        .await;
 
         tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
+            tenant_id: tenant_id.into(),
             user_id: tenant_admin.id,
             is_admin: true,
             ..Default::default()
         })
         .await
         .unwrap();
+        // The flags are ignored when a role has been assigned.
         tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_device_admin.id,
-            is_device_admin: true,
+            tenant_id: tenant_id.into(),
+            user_id: queue_operator.id,
+            is_admin: true,
+            role_id: Some(role_queue_operator.id),
             ..Default::default()
         })
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_gateway_admin.id,
-            is_gateway_admin: true,
+            tenant_id: tenant_id.into(),
+            user_id: gateway_viewer.id,
+            role_id: Some(role_gateway_viewer.id),
             ..Default::default()
         })
         .await
         .unwrap();
         tenant::add_user(tenant::TenantUser {
-            tenant_id: api_key_tenant.tenant_id.unwrap(),
-            user_id: tenant_user.id,
+            tenant_id: tenant_id.into(),
+            user_id: key_viewer.id,
+            is_admin: true,
+            role_id: Some(role_key_viewer.id),
             ..Default::default()
         })
         .await
         .unwrap();
 
-        // fuota deployments with user
+        // device
         let tests = vec![
-            // admin user can create and list
+            // queue operator can read the device
             ValidatorTest {
-                validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::User(user_admin.id.into()),
+                validators: vec![ValidateDeviceAccess::new(Flag::Read, dev.dev_eui)],
+                id: AuthID::User(queue_operator.id.into()),
                 ok: true,
             },
-            // tenant admin can create and list
+            // queue operator can not update or delete the device
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
+                    ValidateDeviceAccess::new(Flag::Update, dev.dev_eui),
+                    ValidateDeviceAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::User(tenant_admin.id.into()),
-                ok: true,
+                id: AuthID::User(queue_operator.id.into()),
+                ok: false,
             },
-            // tenant device admin can create and list
+            // gateway viewer can not read the device
             ValidatorTest {
-                validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
-                ],
-                id: AuthID::User(tenant_device_admin.id.into()),
-                ok: true,
+                validators: vec![ValidateDeviceAccess::new(Flag::Read, dev.dev_eui)],
+                id: AuthID::User(gateway_viewer.id.into()),
+                ok: false,
             },
-            // tenant user can list
+        ];
+        run_tests(tests).await;
+
+        // device keys
+        let tests = vec![
+            // tenant admin without role can read and update the keys
             ValidatorTest {
-                validators: vec![ValidateFuotaDeploymentsAccess::new(
-                    Flag::List,
-                    app.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
+                validators: vec![
+                    ValidateDeviceKeysAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceKeysAccess::new(Flag::Update, dev.dev_eui),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant user can not create
-            ValidatorTest {
-                validators: vec![ValidateFuotaDeploymentsAccess::new(
-                    Flag::Create,
-                    app.id.into(),
-                )],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
-            },
-            // other user can not create or list
+            // queue operator can not read or update the keys
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
+                    ValidateDeviceKeysAccess::new(Flag::Read, dev.dev_eui),
+                    ValidateDeviceKeysAccess::new(Flag::Update, dev.dev_eui),
                 ],
-                id: AuthID::User(user_active.id.into()),
+                id: AuthID::User(queue_operator.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // fuota deployments with api key
+        // device queue
         let tests = vec![
-            // admin api key can create and list
+            // queue operator can enqueue, list and flush the queue
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::Delete, dev.dev_eui),
                 ],
-                id: AuthID::Key(api_key_admin.id.into()),
+                id: AuthID::User(queue_operator.id.into()),
                 ok: true,
             },
-            // tenant api key can create and list
+            // gateway viewer can not access the queue
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
+                    ValidateDeviceQueueAccess::new(Flag::Create, dev.dev_eui),
+                    ValidateDeviceQueueAccess::new(Flag::List, dev.dev_eui),
                 ],
-                id: AuthID::Key(api_key_tenant.id.into()),
+                id: AuthID::User(gateway_viewer.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // gateway
+        let tests = vec![
+            // gateway viewer can read the gateway
+            ValidatorTest {
+                validators: vec![ValidateGatewayAccess::new(Flag::Read, gw.gateway_id)],
+                id: AuthID::User(gateway_viewer.id.into()),
                 ok: true,
             },
-            // tenant api key can not create or list for other tenant
+            // gateway viewer can not update the gateway
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentsAccess::new(Flag::Create, app.id.into()),
-                    ValidateFuotaDeploymentsAccess::new(Flag::List, app.id.into()),
+                    ValidateGatewayAccess::new(Flag::Update, gw.gateway_id),
+                    ValidateGatewayAccess::new(Flag::Delete, gw.gateway_id),
                 ],
-                id: AuthID::Key(api_key_other_tenant.id.into()),
+                id: AuthID::User(gateway_viewer.id.into()),
+                ok: false,
+            },
+            // queue operator can not read the gateway
+            ValidatorTest {
+                validators: vec![ValidateGatewayAccess::new(Flag::Read, gw.gateway_id)],
+                id: AuthID::User(queue_operator.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        let dp = device_profile::create(device_profile::DeviceProfile {
-            tenant_id: app.tenant_id,
-            name: "test-dp".into(),
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-
-        let fuota = fuota::create_deployment(fuota::FuotaDeployment {
-            name: "test-fuota".into(),
-            application_id: app.id,
-            device_profile_id: dp.id,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-
-        // fuota deployment with user
+        // api keys
         let tests = vec![
-            // admin user can read, update and delete
+            // tenant admin without role can create and delete api keys
             ValidatorTest {
-                validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
-                ],
-                id: AuthID::User(user_admin.id.into()),
+                validators: vec![ValidateApiKeysAccess::new(Flag::Create, Some(tenant_id))],
+                id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant admin can read, update and delete
             ValidatorTest {
-                validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
-                ],
+                validators: vec![ValidateApiKeyAccess::new(
+                    Flag::Delete,
+                    api_key_tenant.id.into(),
+                )],
                 id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant device admin can read, update and delete
+            // key viewer can list the api keys
             ValidatorTest {
-                validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
-                ],
-                id: AuthID::User(tenant_device_admin.id.into()),
+                validators: vec![ValidateApiKeysAccess::new(Flag::List, Some(tenant_id))],
+                id: AuthID::User(key_viewer.id.into()),
                 ok: true,
             },
-            // tenant user can read
+            // no role grants the creation of (unrestricted) tenant api keys
             ValidatorTest {
-                validators: vec![ValidateFuotaDeploymentAccess::new(
-                    Flag::Read,
-                    fuota.id.into(),
+                validators: vec![ValidateApiKeysAccess::new(Flag::Create, Some(tenant_id))],
+                id: AuthID::User(key_viewer.id.into()),
+                ok: false,
+            },
+            ValidatorTest {
+                validators: vec![ValidateApiKeyAccess::new(
+                    Flag::Delete,
+                    api_key_tenant.id.into(),
                 )],
-                id: AuthID::User(tenant_user.id.into()),
+                id: AuthID::User(key_viewer.id.into()),
+                ok: false,
+            },
+        ];
+        run_tests(tests).await;
+
+        // tenant roles
+        let tests = vec![
+            // tenant admin and tenant api key can create and list roles
+            ValidatorTest {
+                validators: vec![
+                    ValidateTenantRolesAccess::new(Flag::Create, tenant_id),
+                    ValidateTenantRolesAccess::new(Flag::List, tenant_id),
+                ],
+                id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant user can not update or delete
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
+                    ValidateTenantRolesAccess::new(Flag::Create, tenant_id),
+                    ValidateTenantRolesAccess::new(Flag::List, tenant_id),
                 ],
-                id: AuthID::User(tenant_user.id.into()),
-                ok: false,
+                id: AuthID::Key(api_key_tenant.id.into()),
+                ok: true,
             },
-            // other user can not read, update or delete
+            // queue operator can not create or list roles
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
+                    ValidateTenantRolesAccess::new(Flag::Create, tenant_id),
+                    ValidateTenantRolesAccess::new(Flag::List, tenant_id),
                 ],
-                id: AuthID::User(user_active.id.into()),
+                id: AuthID::User(queue_operator.id.into()),
                 ok: false,
             },
         ];
         run_tests(tests).await;
 
-        // fuota deployment with api key
+        // tenant role
         let tests = vec![
-            // admin api key can read, update and delete
+            // tenant admin and tenant api key can read, update and delete the role
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Read, role_gateway_viewer.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Update, role_gateway_viewer.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Delete, role_gateway_viewer.id.into()),
                 ],
-                id: AuthID::Key(api_key_admin.id.into()),
+                id: AuthID::User(tenant_admin.id.into()),
                 ok: true,
             },
-            // tenant api key can read, update and delete
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Read, role_gateway_viewer.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Update, role_gateway_viewer.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Delete, role_gateway_viewer.id.into()),
                 ],
-                id: AuthID::Key(api_key_admin.id.into()),
+                id: AuthID::Key(api_key_tenant.id.into()),
                 ok: true,
             },
-            // other api key can not read, update or delete
+            // gateway viewer can not read or update the role
             ValidatorTest {
                 validators: vec![
-                    ValidateFuotaDeploymentAccess::new(Flag::Read, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Update, fuota.id.into()),
-                    ValidateFuotaDeploymentAccess::new(Flag::Delete, fuota.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Read, role_gateway_viewer.id.into()),
+                    ValidateTenantRoleAccess::new(Flag::Update, role_gateway_viewer.id.into()),
                 ],
-                id: AuthID::Key(api_key_other_tenant.id.into()),
+                id: AuthID::User(gateway_viewer.id.into()),
                 ok: false,
             },
         ];
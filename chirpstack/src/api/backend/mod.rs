@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use axum::{
@@ -11,7 +11,7 @@ use axum::{
     Router,
 };
 use chrono::Utc;
-use http::StatusCode;
+use http::{header, HeaderMap, HeaderValue, StatusCode};
 use redis::streams::StreamReadReply;
 use rustls::{
     server::{NoClientAuth, WebPkiClientVerifier},
@@ -21,17 +21,21 @@ use serde::Serialize;
 use tokio::sync::oneshot;
 use tokio::task;
 use tracing::{error, info, span, warn, Instrument, Level};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use uuid::Uuid;
 
-use crate::backend::{joinserver, keywrap, roaming};
-use crate::downlink::data_fns;
+use crate::backend::{async_answer, joinserver, keywrap, roaming};
+use crate::downlink::{data_fns, data_sns_hr};
 use crate::helpers::errors::PrintFullError;
 use crate::helpers::tls::{get_root_certs, load_cert, load_key};
+use crate::monitoring;
 use crate::storage::{
-    device, error::Error as StorageError, get_async_redis_conn, passive_roaming, redis_key,
+    device, error::Error as StorageError, get_async_redis_conn, handover_roaming, passive_roaming,
+    redis_key,
 };
 use crate::uplink::{
-    data_sns, error::Error as UplinkError, helpers, join_sns, RoamingMetaData, UplinkFrameSet,
+    data_hns, data_sns, error::Error as UplinkError, helpers, join_sns, RoamingMetaData,
+    UplinkFrameSet,
 };
 use crate::{config, region, stream};
 use backend::{BasePayload, BasePayloadResultProvider, MessageType};
@@ -83,8 +87,12 @@ pub async fn setup() -> Result<()> {
     Ok(())
 }
 
-pub async fn handle_request(b: Bytes) -> Response {
+pub async fn handle_request(headers: HeaderMap, b: Bytes) -> Response {
     let b: Vec<u8> = b.into();
+    let signature = headers
+        .get(backend::JWS_SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
 
     let bp: BasePayload = match serde_json::from_slice(&b) {
         Ok(v) => v,
@@ -94,10 +102,30 @@ pub async fn handle_request(b: Bytes) -> Response {
     };
 
     let span = span!(Level::INFO, "request", sender_id = %hex::encode(&bp.sender_id), receiver_id = %hex::encode(&bp.receiver_id), message_type = ?bp.message_type, transaction_id = bp.transaction_id);
-    _handle_request(bp, b).instrument(span).await
+    span.set_parent(monitoring::otel::extract_context(&headers));
+
+    let start = Instant::now();
+    let sender_id = NetID::from_slice(&bp.sender_id).ok();
+    let message_type = bp.message_type;
+    let is_answer = bp.is_answer();
+
+    let resp = _handle_request(bp, b, signature).instrument(span).await;
+
+    if let Some(sender_id) = sender_id {
+        if !is_answer {
+            roaming::log_request_duration(
+                sender_id,
+                roaming::Direction::Inbound,
+                message_type,
+                start.elapsed(),
+            );
+        }
+    }
+
+    resp
 }
 
-pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
+pub async fn _handle_request(bp: BasePayload, b: Vec<u8>, signature: Option<String>) -> Response {
     info!("Request received");
 
     let sender_client = {
@@ -137,7 +165,7 @@ pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
                 }
             };
 
-            match roaming::get(&sender_id).await {
+            let client = match roaming::get(&sender_id).await {
                 Ok(v) => v,
                 Err(_) => {
                     warn!("Unknown SenderID");
@@ -146,7 +174,20 @@ pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
                     log_request_response(&bp, &b, &pl).await;
                     return Json(&pl).into_response();
                 }
+            };
+
+            // Async answers are not rate limited, as these are answers to our own requests.
+            if !bp.is_answer() && !roaming::inbound_rate_limit_allow(sender_id).await {
+                warn!("Rate limit exceeded");
+                let pl = bp.to_base_payload_result(
+                    backend::ResultCode::Other,
+                    backend::RATE_LIMIT_EXCEEDED,
+                );
+                log_request_response(&bp, &b, &pl).await;
+                return (StatusCode::TOO_MANY_REQUESTS, Json(&pl)).into_response();
             }
+
+            client
         } else {
             // Unknown size
             warn!("Invalid SenderID length");
@@ -159,6 +200,14 @@ pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
         }
     };
 
+    if let Err(e) = sender_client.verify_signature(signature.as_deref(), &b) {
+        warn!(error = %e.full(), "Verify signature error");
+        let msg = format!("Verify signature error: {}", e);
+        let pl = bp.to_base_payload_result(backend::ResultCode::MalformedRequest, &msg);
+        log_request_response(&bp, &b, &pl).await;
+        return answer_response(&sender_client, &pl);
+    }
+
     // Request is an async answer.
     if bp.is_answer() {
         tokio::spawn(async move {
@@ -172,6 +221,8 @@ pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
     match bp.message_type {
         MessageType::PRStartReq => handle_pr_start_req(sender_client, bp, &b).await,
         MessageType::PRStopReq => handle_pr_stop_req(sender_client, bp, &b).await,
+        MessageType::HRStartReq => handle_hr_start_req(sender_client, bp, &b).await,
+        MessageType::HRStopReq => handle_hr_stop_req(sender_client, bp, &b).await,
         MessageType::XmitDataReq => handle_xmit_data_req(sender_client, bp, &b).await,
         MessageType::HomeNSReq => handle_home_ns_req(sender_client, bp, &b).await,
         // Unknown message
@@ -183,6 +234,33 @@ pub async fn _handle_request(bp: BasePayload, b: Vec<u8>) -> Response {
     }
 }
 
+// Returns the (sync) answer response. In case JWS signing is configured for the sender, the
+// answer is signed, such that the sender can verify the answer before applying it.
+fn answer_response<T: Serialize>(sender_client: &backend::Client, ans: &T) -> Response {
+    let resp = || -> Result<Response> {
+        let body = serde_json::to_string(ans)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        if let Some(signature) = sender_client.sign(&body)? {
+            headers.insert(
+                backend::JWS_SIGNATURE_HEADER,
+                HeaderValue::from_str(&signature)?,
+            );
+        }
+
+        Ok((headers, body).into_response())
+    };
+
+    resp().unwrap_or_else(|e| {
+        error!(error = %e.full(), "Encode answer error");
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+    })
+}
+
 fn err_to_response(e: anyhow::Error, bp: &backend::BasePayload) -> backend::BasePayloadResult {
     let msg = format!("{}", e);
     bp.to_base_payload_result(err_to_result_code(e), &msg)
@@ -228,7 +306,7 @@ async fn handle_pr_start_req(
 
             log_request_response(&bp, &b, &ans).await;
 
-            if let Err(e) = sender_client.pr_start_ans(backend::Role::FNS, &ans).await {
+            if let Err(e) = async_answer::send(&sender_client, backend::Role::FNS, &ans).await {
                 error!(error = %e.full(), transaction_id = bp.transaction_id, "Send async PRStartAns error");
             }
         });
@@ -237,12 +315,12 @@ async fn handle_pr_start_req(
         match _handle_pr_start_req(b).await {
             Ok(ans) => {
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
             Err(e) => {
                 let ans = err_to_response(e, &bp);
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
         }
     }
@@ -390,7 +468,7 @@ async fn handle_pr_stop_req(
 
             log_request_response(&bp, &b, &ans).await;
 
-            if let Err(e) = sender_client.pr_stop_ans(backend::Role::SNS, &ans).await {
+            if let Err(e) = async_answer::send(&sender_client, backend::Role::SNS, &ans).await {
                 error!(error = %e.full(), "Send async PRStopAns error");
             }
         });
@@ -399,12 +477,12 @@ async fn handle_pr_stop_req(
         match _handle_pr_stop_req(b).await {
             Ok(ans) => {
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
             Err(e) => {
                 let ans = err_to_response(e, &bp);
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
         }
     }
@@ -436,6 +514,159 @@ async fn _handle_pr_stop_req(b: &[u8]) -> Result<backend::PRStopAnsPayload> {
     })
 }
 
+async fn handle_hr_start_req(
+    sender_client: Arc<backend::Client>,
+    bp: backend::BasePayload,
+    b: &[u8],
+) -> Response {
+    if sender_client.is_async() {
+        let b = b.to_vec();
+        task::spawn(async move {
+            let ans = match _handle_hr_start_req(&b).await {
+                Ok(v) => v,
+                Err(e) => {
+                    let msg = e.to_string();
+                    backend::HRStartAnsPayload {
+                        base: bp.to_base_payload_result(err_to_result_code(e), &msg),
+                        ..Default::default()
+                    }
+                }
+            };
+
+            log_request_response(&bp, &b, &ans).await;
+
+            if let Err(e) = async_answer::send(&sender_client, backend::Role::SNS, &ans).await {
+                error!(error = %e.full(), transaction_id = bp.transaction_id, "Send async HRStartAns error");
+            }
+        });
+        (StatusCode::OK, "").into_response()
+    } else {
+        match _handle_hr_start_req(b).await {
+            Ok(ans) => {
+                log_request_response(&bp, b, &ans).await;
+                answer_response(&sender_client, &ans)
+            }
+            Err(e) => {
+                let ans = err_to_response(e, &bp);
+                log_request_response(&bp, b, &ans).await;
+                answer_response(&sender_client, &ans)
+            }
+        }
+    }
+}
+
+async fn _handle_hr_start_req(b: &[u8]) -> Result<backend::HRStartAnsPayload> {
+    let pl: backend::HRStartReqPayload = serde_json::from_slice(b)?;
+    let phy = lrwn::PhyPayload::from_slice(&pl.phy_payload)?;
+
+    if phy.mhdr.m_type != lrwn::MType::JoinRequest {
+        return Err(anyhow!("PHYPayload must contain a JoinRequest"));
+    }
+
+    let rx_info = roaming::ul_meta_data_to_rx_info(&pl.ul_meta_data)?;
+    let tx_info = roaming::ul_meta_data_to_tx_info(&pl.ul_meta_data)?;
+    let region_common_name = CommonName::from_str(&pl.ul_meta_data.rf_region)?;
+    let region_config_id = region::get_region_config_id(region_common_name)?;
+    let dr = pl.ul_meta_data.data_rate.unwrap_or_default();
+
+    let ufs = UplinkFrameSet {
+        uplink_set_id: Uuid::new_v4(),
+        dr,
+        ch: helpers::get_uplink_ch(&region_config_id, tx_info.frequency, dr)?,
+        phy_payload: phy,
+        tx_info,
+        rx_info_set: rx_info,
+        gateway_private_up_map: HashMap::new(),
+        gateway_private_down_map: HashMap::new(),
+        gateway_tenant_id_map: HashMap::new(),
+        region_common_name,
+        region_config_id,
+        roaming_meta_data: Some(RoamingMetaData {
+            base_payload: pl.base.clone(),
+            ul_meta_data: pl.ul_meta_data.clone(),
+        }),
+    };
+
+    // This flow will return RoamingIsNotAllowed in case allow_roaming
+    // is not enabled in the device-profile.
+    join_sns::JoinRequest::start_hr(ufs, pl).await
+}
+
+async fn handle_hr_stop_req(
+    sender_client: Arc<backend::Client>,
+    bp: backend::BasePayload,
+    b: &[u8],
+) -> Response {
+    if sender_client.is_async() {
+        let b = b.to_vec();
+        task::spawn(async move {
+            let ans = match _handle_hr_stop_req(&b).await {
+                Ok(v) => v,
+                Err(e) => {
+                    let msg = e.to_string();
+                    backend::HRStopAnsPayload {
+                        base: bp.to_base_payload_result(err_to_result_code(e), &msg),
+                    }
+                }
+            };
+
+            log_request_response(&bp, &b, &ans).await;
+
+            if let Err(e) = async_answer::send(&sender_client, backend::Role::SNS, &ans).await {
+                error!(error = %e.full(), "Send async HRStopAns error");
+            }
+        });
+        (StatusCode::OK, "").into_response()
+    } else {
+        match _handle_hr_stop_req(b).await {
+            Ok(ans) => {
+                log_request_response(&bp, b, &ans).await;
+                answer_response(&sender_client, &ans)
+            }
+            Err(e) => {
+                let ans = err_to_response(e, &bp);
+                log_request_response(&bp, b, &ans).await;
+                answer_response(&sender_client, &ans)
+            }
+        }
+    }
+}
+
+async fn _handle_hr_stop_req(b: &[u8]) -> Result<backend::HRStopAnsPayload> {
+    let pl: backend::HRStopReqPayload = serde_json::from_slice(b)?;
+    let dev_eui = EUI64::from_slice(&pl.dev_eui)?;
+    let sender_id = NetID::from_slice(&pl.base.sender_id)?;
+
+    // The HRStopReq can be sent by the sNS (we are the hNS) or by the hNS (we are the sNS).
+    match handover_roaming::get(&dev_eui).await {
+        Ok(sess) if sess.net_id == sender_id.to_vec() => {
+            handover_roaming::delete(&dev_eui).await?;
+        }
+        Ok(_) | Err(StorageError::NotFound(_)) => {
+            match handover_roaming::get_serving(&dev_eui).await {
+                Ok(sess) if sess.net_id == sender_id.to_vec() => {
+                    handover_roaming::delete_serving(&dev_eui).await?;
+                }
+                Ok(_) | Err(StorageError::NotFound(_)) => {
+                    return Ok(backend::HRStopAnsPayload {
+                        base: pl
+                            .base
+                            .to_base_payload_result(backend::ResultCode::UnknownDevEUI, ""),
+                    });
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(backend::HRStopAnsPayload {
+        base: pl
+            .base
+            .to_base_payload_result(backend::ResultCode::Success, ""),
+    })
+}
+
 async fn handle_xmit_data_req(
     sender_client: Arc<backend::Client>,
     bp: backend::BasePayload,
@@ -446,15 +677,19 @@ async fn handle_xmit_data_req(
         Err(e) => {
             let ans = err_to_response(anyhow::Error::new(e), &bp);
             log_request_response(&bp, b, &ans).await;
-            return Json(&ans).into_response();
+            return answer_response(&sender_client, &ans);
         }
     };
 
     if sender_client.is_async() {
         let b = b.to_vec();
         task::spawn(async move {
-            let sender_role = if pl.ul_meta_data.is_some() {
+            // In case of handover-roaming, the sNS sends the FRMPayload with ULMetaData and
+            // the hNS sends the FRMPayload with DLMetaData.
+            let sender_role = if pl.ul_meta_data.is_some() && !pl.phy_payload.is_empty() {
                 backend::Role::FNS
+            } else if pl.dl_meta_data.is_some() && pl.phy_payload.is_empty() {
+                backend::Role::HNS
             } else {
                 backend::Role::SNS
             };
@@ -471,7 +706,7 @@ async fn handle_xmit_data_req(
 
             log_request_response(&bp, &b, &ans).await;
 
-            if let Err(e) = sender_client.xmit_data_ans(sender_role, &ans).await {
+            if let Err(e) = async_answer::send(&sender_client, sender_role, &ans).await {
                 error!(error = %e.full(), "Send async XmitDataAns error");
             }
         });
@@ -480,12 +715,12 @@ async fn handle_xmit_data_req(
         match _handle_xmit_data_req(pl).await {
             Ok(ans) => {
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
             Err(e) => {
                 let ans = err_to_response(e, &bp);
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
         }
     }
@@ -495,6 +730,17 @@ async fn _handle_xmit_data_req(
     pl: backend::XmitDataReqPayload,
 ) -> Result<backend::XmitDataAnsPayload> {
     if let Some(ul_meta_data) = &pl.ul_meta_data {
+        // Handover-roaming, the sNS has handled the MAC layer.
+        if pl.phy_payload.is_empty() {
+            data_hns::Data::handle(pl.clone(), ul_meta_data.clone()).await?;
+
+            return Ok(backend::XmitDataAnsPayload {
+                base: pl
+                    .base
+                    .to_base_payload_result(backend::ResultCode::Success, ""),
+            });
+        }
+
         let rx_info = roaming::ul_meta_data_to_rx_info(ul_meta_data)?;
         let tx_info = roaming::ul_meta_data_to_tx_info(ul_meta_data)?;
         let region_common_name = CommonName::from_str(&ul_meta_data.rf_region)?;
@@ -524,7 +770,12 @@ async fn _handle_xmit_data_req(
     }
 
     if let Some(dl_meta_data) = &pl.dl_meta_data {
-        data_fns::Data::handle(pl.clone(), dl_meta_data.clone()).await?;
+        // Handover-roaming, the sNS must construct the PHYPayload.
+        if pl.phy_payload.is_empty() {
+            data_sns_hr::Data::handle(pl.clone(), dl_meta_data.clone()).await?;
+        } else {
+            data_fns::Data::handle(pl.clone(), dl_meta_data.clone()).await?;
+        }
     }
 
     Ok(backend::XmitDataAnsPayload {
@@ -544,7 +795,7 @@ async fn handle_home_ns_req(
         Err(e) => {
             let ans = err_to_response(anyhow::Error::new(e), &bp);
             log_request_response(&bp, b, &ans).await;
-            return Json(&ans).into_response();
+            return answer_response(&sender_client, &ans);
         }
     };
 
@@ -564,7 +815,7 @@ async fn handle_home_ns_req(
 
             log_request_response(&bp, &b, &ans).await;
 
-            if let Err(e) = sender_client.home_ns_ans(backend::Role::FNS, &ans).await {
+            if let Err(e) = async_answer::send(&sender_client, backend::Role::FNS, &ans).await {
                 error!(error = %e.full(), "Send async HomeNSAns error");
             }
         });
@@ -574,12 +825,12 @@ async fn handle_home_ns_req(
         match _handle_home_ns_req(pl).await {
             Ok(ans) => {
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
             Err(e) => {
                 let ans = err_to_response(e, &bp);
                 log_request_response(&bp, b, &ans).await;
-                Json(&ans).into_response()
+                answer_response(&sender_client, &ans)
             }
         }
     }
@@ -599,7 +850,9 @@ async fn _handle_home_ns_req(pl: backend::HomeNSReqPayload) -> Result<backend::H
 async fn handle_async_ans(bp: &BasePayload, b: &[u8]) -> Result<Response> {
     let transaction_id = bp.transaction_id;
 
+    let conf = config::get();
     let key = redis_key(format!("backend:async:{}", transaction_id));
+    let ttl = conf.redis.ttl.backend_async_answer.as_millis() as usize;
 
     () = redis::pipe()
         .atomic()
@@ -611,9 +864,9 @@ async fn handle_async_ans(bp: &BasePayload, b: &[u8]) -> Result<Response> {
         .arg("pl")
         .arg(b)
         .ignore()
-        .cmd("EXPIRE")
+        .cmd("PEXPIRE")
         .arg(&key)
-        .arg(30_i64)
+        .arg(ttl)
         .ignore()
         .query_async(&mut get_async_redis_conn().await?)
         .await?;
@@ -691,6 +944,15 @@ where
         return;
     }
 
+    if let Ok(sender_id) = NetID::from_slice(&bp.sender_id) {
+        roaming::log_request_result(
+            sender_id,
+            roaming::Direction::Inbound,
+            bp.message_type,
+            &format!("{:?}", resp.base_payload().result.result_code),
+        );
+    }
+
     let be_req_log = stream_pb::BackendInterfacesRequest {
         sender_id: hex::encode(&bp.sender_id),
         receiver_id: hex::encode(&bp.receiver_id),
@@ -0,0 +1,341 @@
+use std::str::FromStr;
+
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use chirpstack_api::api;
+use chirpstack_api::api::codec_library_service_server::CodecLibraryService;
+
+use super::auth::validator;
+use super::error::ToStatus;
+use super::helpers;
+use crate::storage::codec_library;
+
+pub struct CodecLibrary {
+    validator: validator::RequestValidator,
+}
+
+impl CodecLibrary {
+    pub fn new(validator: validator::RequestValidator) -> Self {
+        CodecLibrary { validator }
+    }
+}
+
+#[tonic::async_trait]
+impl CodecLibraryService for CodecLibrary {
+    async fn create(
+        &self,
+        request: Request<api::CreateCodecLibraryRequest>,
+    ) -> Result<Response<api::CreateCodecLibraryResponse>, Status> {
+        let req_cl = match &request.get_ref().codec_library {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("codec_library is missing"));
+            }
+        };
+        let tenant_id = Uuid::from_str(&req_cl.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateCodecLibrariesAccess::new(validator::Flag::Create, tenant_id),
+            )
+            .await?;
+
+        let cl = codec_library::create(codec_library::CodecLibrary {
+            tenant_id: tenant_id.into(),
+            name: req_cl.name.clone(),
+            description: req_cl.description.clone(),
+            script: req_cl.script.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::CreateCodecLibraryResponse {
+            id: cl.id.to_string(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-codec_library_id", cl.id.to_string().parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn get(
+        &self,
+        request: Request<api::GetCodecLibraryRequest>,
+    ) -> Result<Response<api::GetCodecLibraryResponse>, Status> {
+        let req = request.get_ref();
+        let cl_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateCodecLibraryAccess::new(validator::Flag::Read, cl_id),
+            )
+            .await?;
+
+        let cl = codec_library::get(&cl_id).await.map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetCodecLibraryResponse {
+            codec_library: Some(api::CodecLibrary {
+                id: cl.id.to_string(),
+                tenant_id: cl.tenant_id.to_string(),
+                name: cl.name,
+                description: cl.description,
+                script: cl.script,
+            }),
+            created_at: Some(helpers::datetime_to_prost_timestamp(&cl.created_at)),
+            updated_at: Some(helpers::datetime_to_prost_timestamp(&cl.updated_at)),
+        });
+        resp.metadata_mut()
+            .insert("x-log-codec_library_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn update(
+        &self,
+        request: Request<api::UpdateCodecLibraryRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_cl = match &request.get_ref().codec_library {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("codec_library is missing"));
+            }
+        };
+        let cl_id = Uuid::from_str(&req_cl.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateCodecLibraryAccess::new(validator::Flag::Update, cl_id),
+            )
+            .await?;
+
+        let _ = codec_library::update(codec_library::CodecLibrary {
+            id: cl_id.into(),
+            name: req_cl.name.clone(),
+            description: req_cl.description.clone(),
+            script: req_cl.script.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-codec_library_id", req_cl.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn delete(
+        &self,
+        request: Request<api::DeleteCodecLibraryRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let cl_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateCodecLibraryAccess::new(validator::Flag::Delete, cl_id),
+            )
+            .await?;
+
+        codec_library::delete(&cl_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-codec_library_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn list(
+        &self,
+        request: Request<api::ListCodecLibrariesRequest>,
+    ) -> Result<Response<api::ListCodecLibrariesResponse>, Status> {
+        let req = request.get_ref();
+        let tenant_id = Uuid::from_str(&req.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateCodecLibrariesAccess::new(validator::Flag::List, tenant_id),
+            )
+            .await?;
+
+        let filters = codec_library::Filters {
+            tenant_id: Some(tenant_id),
+            search: if req.search.is_empty() {
+                None
+            } else {
+                Some(req.search.to_string())
+            },
+        };
+
+        let count = codec_library::get_count(&filters)
+            .await
+            .map_err(|e| e.status())?;
+        let items = codec_library::list(req.limit as i64, req.offset as i64, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::ListCodecLibrariesResponse {
+            total_count: count as u32,
+            result: items
+                .iter()
+                .map(|cl| api::CodecLibraryListItem {
+                    id: cl.id.to_string(),
+                    created_at: Some(helpers::datetime_to_prost_timestamp(&cl.created_at)),
+                    updated_at: Some(helpers::datetime_to_prost_timestamp(&cl.updated_at)),
+                    name: cl.name.clone(),
+                    description: cl.description.clone(),
+                })
+                .collect(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_id", req.tenant_id.parse().unwrap());
+
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::api::auth::validator::RequestValidator;
+    use crate::api::auth::AuthID;
+    use crate::storage::{tenant, user};
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_codec_library() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let t = tenant::test::create_tenant().await;
+
+        // setup the api
+        let service = CodecLibrary::new(RequestValidator::new());
+
+        // create
+        let create_req = get_request(
+            &u.id,
+            api::CreateCodecLibraryRequest {
+                codec_library: Some(api::CodecLibrary {
+                    tenant_id: t.id.to_string(),
+                    name: "crc".into(),
+                    script: "export function crc8(b) { return 0; }".into(),
+                    ..Default::default()
+                }),
+            },
+        );
+        let create_resp = service.create(create_req).await.unwrap();
+        let cl_id = Uuid::from_str(&create_resp.get_ref().id).unwrap();
+
+        // get
+        let get_req = get_request(
+            &u.id,
+            api::GetCodecLibraryRequest {
+                id: cl_id.to_string(),
+            },
+        );
+        let get_resp = service.get(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::CodecLibrary {
+                id: cl_id.to_string(),
+                tenant_id: t.id.to_string(),
+                name: "crc".into(),
+                script: "export function crc8(b) { return 0; }".into(),
+                ..Default::default()
+            }),
+            get_resp.get_ref().codec_library
+        );
+
+        // update
+        let update_req = get_request(
+            &u.id,
+            api::UpdateCodecLibraryRequest {
+                codec_library: Some(api::CodecLibrary {
+                    id: cl_id.to_string(),
+                    tenant_id: t.id.to_string(),
+                    name: "crc".into(),
+                    description: "CRC functions".into(),
+                    script: "export function crc8(b) { return 1; }".into(),
+                }),
+            },
+        );
+        let _ = service.update(update_req).await.unwrap();
+
+        let get_req = get_request(
+            &u.id,
+            api::GetCodecLibraryRequest {
+                id: cl_id.to_string(),
+            },
+        );
+        let get_resp = service.get(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::CodecLibrary {
+                id: cl_id.to_string(),
+                tenant_id: t.id.to_string(),
+                name: "crc".into(),
+                description: "CRC functions".into(),
+                script: "export function crc8(b) { return 1; }".into(),
+            }),
+            get_resp.get_ref().codec_library
+        );
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListCodecLibrariesRequest {
+                tenant_id: t.id.to_string(),
+                limit: 10,
+                ..Default::default()
+            },
+        );
+        let list_resp = service.list(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(1, list_resp.total_count);
+        assert_eq!(1, list_resp.result.len());
+        assert_eq!(cl_id.to_string(), list_resp.result[0].id);
+
+        // delete
+        let del_req = get_request(
+            &u.id,
+            api::DeleteCodecLibraryRequest {
+                id: cl_id.to_string(),
+            },
+        );
+        let _ = service.delete(del_req).await.unwrap();
+        let del_req = get_request(
+            &u.id,
+            api::DeleteCodecLibraryRequest {
+                id: cl_id.to_string(),
+            },
+        );
+        let del_resp = service.delete(del_req).await;
+        assert!(del_resp.is_err());
+    }
+
+    fn get_request<T>(user_id: &Uuid, req: T) -> Request<T> {
+        let mut req = Request::new(req);
+        req.extensions_mut().insert(AuthID::User(*user_id));
+        req
+    }
+}
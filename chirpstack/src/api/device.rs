@@ -1,7 +1,7 @@
 use std::cmp;
 use std::collections::HashSet;
 use std::str::FromStr;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use bigdecimal::ToPrimitive;
 use chrono::{DateTime, Local, Utc};
@@ -12,16 +12,18 @@ use chirpstack_api::api::device_service_server::DeviceService;
 use chirpstack_api::{api, common, internal};
 use lrwn::{AES128Key, DevAddr, EUI64};
 
-use super::auth::validator;
+use super::auth::{validator, AuthID};
 use super::error::ToStatus;
 use super::helpers::{self, FromProto, ToProto};
+use crate::monitoring::device_trace;
 use crate::storage::{
+    application,
     device::{self, DeviceClass},
     device_keys, device_profile, device_queue,
     error::Error as StorageError,
-    fields, metrics,
+    fields, key_access_log, metrics,
 };
-use crate::{codec, devaddr::get_random_dev_addr};
+use crate::{codec, config, devaddr::get_random_dev_addr, quota, ttn};
 
 pub struct Device {
     validator: validator::RequestValidator,
@@ -136,6 +138,7 @@ impl DeviceService for Device {
                 false => None,
             },
             class_enabled: d.enabled_class.to_proto().into(),
+            state: d.state.to_proto().into(),
         });
         resp.metadata_mut()
             .insert("x-log-dev_eui", req.dev_eui.parse().unwrap());
@@ -323,6 +326,7 @@ impl DeviceService for Device {
                         false => None,
                     },
                     tags: d.tags.into_hashmap(),
+                    state: d.state.to_proto().into(),
                 })
                 .collect(),
         });
@@ -347,18 +351,11 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
-        let dk = device_keys::DeviceKeys {
-            dev_eui,
-            nwk_key: AES128Key::from_str(&req_dk.nwk_key).map_err(|e| e.status())?,
-            app_key: AES128Key::from_str(&req_dk.app_key).map_err(|e| e.status())?,
-            gen_app_key: AES128Key::from_str(&req_dk.gen_app_key).map_err(|e| e.status())?,
-            ..Default::default()
-        };
-
+        let dk = device_keys_from_proto(dev_eui, req_dk)?;
         let _ = device_keys::create(dk).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(());
@@ -378,18 +375,37 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Read, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Read, dev_eui),
             )
             .await?;
 
         let dk = device_keys::get(&dev_eui).await.map_err(|e| e.status())?;
+        key_access_log::log(
+            &dev_eui,
+            key_access_log::KeyType::RootKeys,
+            get_key_access_actor(&request),
+            key_access_log::Purpose::ApiExport,
+        )
+        .await
+        .map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::GetDeviceKeysResponse {
-            device_keys: Some(api::DeviceKeys {
-                dev_eui: dk.dev_eui.to_string(),
-                nwk_key: dk.nwk_key.to_string(),
-                app_key: dk.app_key.to_string(),
-                gen_app_key: dk.gen_app_key.to_string(),
+            device_keys: Some(if dk.kek_label.is_empty() {
+                api::DeviceKeys {
+                    dev_eui: dk.dev_eui.to_string(),
+                    nwk_key: dk.nwk_key.to_string(),
+                    app_key: dk.app_key.to_string(),
+                    gen_app_key: dk.gen_app_key.to_string(),
+                    kek_label: "".into(),
+                }
+            } else {
+                api::DeviceKeys {
+                    dev_eui: dk.dev_eui.to_string(),
+                    nwk_key: hex::encode(&dk.wrapped_nwk_key),
+                    app_key: hex::encode(&dk.wrapped_app_key),
+                    gen_app_key: dk.gen_app_key.to_string(),
+                    kek_label: dk.kek_label.clone(),
+                }
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&dk.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&dk.updated_at)),
@@ -415,20 +431,16 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
         let dk = device_keys::get(&dev_eui).await.map_err(|e| e.status())?;
         let dk = device_keys::DeviceKeys {
-            dev_eui: dk.dev_eui,
             created_at: dk.created_at,
             dev_nonces: dk.dev_nonces,
             join_nonce: dk.join_nonce,
-            nwk_key: AES128Key::from_str(&req_dk.nwk_key).map_err(|e| e.status())?,
-            app_key: AES128Key::from_str(&req_dk.app_key).map_err(|e| e.status())?,
-            gen_app_key: AES128Key::from_str(&req_dk.gen_app_key).map_err(|e| e.status())?,
-            ..Default::default()
+            ..device_keys_from_proto(dk.dev_eui, req_dk)?
         };
         let _ = device_keys::update(dk).await.map_err(|e| e.status())?;
 
@@ -449,7 +461,7 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
@@ -474,7 +486,7 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
@@ -505,7 +517,7 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
@@ -545,6 +557,11 @@ impl DeviceService for Device {
             device_session: Some(Some(ds.into())),
             dev_addr: Some(Some(dev_addr)),
             secondary_dev_addr: Some(None),
+            state: if d.state == device::DeviceState::Provisioned {
+                Some(device::DeviceState::Active)
+            } else {
+                None
+            },
             ..Default::default()
         };
 
@@ -583,7 +600,7 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Update, dev_eui),
             )
             .await?;
 
@@ -620,7 +637,7 @@ impl DeviceService for Device {
         self.validator
             .validate(
                 request.extensions(),
-                validator::ValidateDeviceAccess::new(validator::Flag::Read, dev_eui),
+                validator::ValidateDeviceKeysAccess::new(validator::Flag::Read, dev_eui),
             )
             .await?;
 
@@ -637,6 +654,14 @@ impl DeviceService for Device {
                 return Err(e.status());
             }
         };
+        key_access_log::log(
+            &dev_eui,
+            key_access_log::KeyType::SessionKeys,
+            get_key_access_actor(&request),
+            key_access_log::Purpose::ApiExport,
+        )
+        .await
+        .map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::GetDeviceActivationResponse {
             device_activation: Some(api::DeviceActivation {
@@ -1084,6 +1109,7 @@ impl DeviceService for Device {
 
             data = codec::struct_to_binary(
                 dp.payload_codec_runtime,
+                &dp.tenant_id,
                 req_qi.f_port as u8,
                 &dev.variables,
                 &dp.payload_codec_script,
@@ -1116,9 +1142,7 @@ impl DeviceService for Device {
             ..Default::default()
         };
 
-        let qi = device_queue::enqueue_item(qi)
-            .await
-            .map_err(|e| e.status())?;
+        let qi = quota::enqueue_item(qi).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::EnqueueDeviceQueueItemResponse {
             id: qi.id.to_string(),
@@ -1235,6 +1259,203 @@ impl DeviceService for Device {
 
         Ok(resp)
     }
+
+    async fn enable_debug_trace(
+        &self,
+        request: Request<api::EnableDeviceDebugTraceRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let dev_eui = EUI64::from_str(&req.dev_eui).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+            )
+            .await?;
+
+        let conf = config::get();
+        let duration = Duration::from_secs(req.duration_minutes as u64 * 60);
+        if duration.is_zero() || duration > conf.monitoring.device_debug_trace_max_duration {
+            return Err(Status::invalid_argument(format!(
+                "duration_minutes must be between 1 and {}",
+                conf.monitoring.device_debug_trace_max_duration.as_secs() / 60
+            )));
+        }
+
+        // Make sure that the device exists.
+        let _ = device::get(&dev_eui).await.map_err(|e| e.status())?;
+
+        device_trace::enable(dev_eui, duration)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-dev_eui", req.dev_eui.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn disable_debug_trace(
+        &self,
+        request: Request<api::DisableDeviceDebugTraceRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let dev_eui = EUI64::from_str(&req.dev_eui).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+            )
+            .await?;
+
+        device_trace::disable(dev_eui)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-dev_eui", req.dev_eui.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn import_ttn(
+        &self,
+        request: Request<api::ImportTtnDevicesRequest>,
+    ) -> Result<Response<api::ImportTtnDevicesResponse>, Status> {
+        let req = request.get_ref();
+        let app_id = Uuid::from_str(&req.application_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDevicesAccess::new(validator::Flag::Create, app_id),
+            )
+            .await?;
+
+        // The import might create device-profiles within the tenant of the application.
+        let app = application::get(&app_id).await.map_err(|e| e.status())?;
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceProfilesAccess::new(
+                    validator::Flag::Create,
+                    app.tenant_id.into(),
+                ),
+            )
+            .await?;
+
+        let devices = ttn::parse(&req.data).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let res = ttn::import(app_id, &devices)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::ImportTtnDevicesResponse {
+            device_count: res.device_count as u32,
+            device_profile_count: res.device_profile_count as u32,
+            errors: res
+                .errors
+                .into_iter()
+                .map(|(device_id, error)| api::ImportTtnDeviceError { device_id, error })
+                .collect(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-application_id", req.application_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn set_state(
+        &self,
+        request: Request<api::SetDeviceStateRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let dev_eui = EUI64::from_str(&req.dev_eui).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceAccess::new(validator::Flag::Update, dev_eui),
+            )
+            .await?;
+
+        let state = api::DeviceLifecycleState::try_from(req.state)
+            .map_err(|_| Status::invalid_argument("Invalid state"))?
+            .from_proto();
+
+        device::partial_update(
+            dev_eui,
+            &device::DeviceChangeset {
+                state: Some(state),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-dev_eui", req.dev_eui.parse().unwrap());
+        resp.metadata_mut()
+            .insert("x-log-state", state.to_string().parse().unwrap());
+
+        Ok(resp)
+    }
+}
+
+// Returns the device-keys for the given API device-keys. When the KEK label is set, the NwkKey and
+// AppKey are wrapped and are stored as-is.
+fn device_keys_from_proto(
+    dev_eui: EUI64,
+    dk: &api::DeviceKeys,
+) -> Result<device_keys::DeviceKeys, Status> {
+    let gen_app_key = AES128Key::from_str(&dk.gen_app_key).map_err(|e| e.status())?;
+
+    if dk.kek_label.is_empty() {
+        return Ok(device_keys::DeviceKeys {
+            dev_eui,
+            nwk_key: AES128Key::from_str(&dk.nwk_key).map_err(|e| e.status())?,
+            app_key: AES128Key::from_str(&dk.app_key).map_err(|e| e.status())?,
+            gen_app_key,
+            ..Default::default()
+        });
+    }
+
+    let decode_wrapped_key = |s: &str| -> Result<Vec<u8>, Status> {
+        let b = hex::decode(s).map_err(|e| e.status())?;
+        // RFC 3394 wrapped 128 bit key.
+        if !b.is_empty() && b.len() != 24 {
+            return Err(Status::invalid_argument(
+                "wrapped key must be exactly 24 bytes",
+            ));
+        }
+        Ok(b)
+    };
+
+    let wrapped_nwk_key = decode_wrapped_key(&dk.nwk_key)?;
+    if wrapped_nwk_key.is_empty() {
+        return Err(Status::invalid_argument("nwk_key is missing"));
+    }
+
+    Ok(device_keys::DeviceKeys {
+        dev_eui,
+        gen_app_key,
+        kek_label: dk.kek_label.clone(),
+        wrapped_nwk_key,
+        wrapped_app_key: decode_wrapped_key(&dk.app_key)?,
+        ..Default::default()
+    })
+}
+
+fn get_key_access_actor<T>(request: &Request<T>) -> key_access_log::Actor {
+    match request.extensions().get::<AuthID>() {
+        Some(AuthID::User(id)) => key_access_log::Actor::User(*id),
+        Some(AuthID::Key(id)) => key_access_log::Actor::ApiKey(*id),
+        _ => key_access_log::Actor::System,
+    }
 }
 
 #[cfg(test)]
@@ -1389,6 +1610,7 @@ pub mod test {
                     nwk_key: "01020304050607080102030405060708".into(),
                     app_key: "02020304050607080202030405060708".into(),
                     gen_app_key: "03020304050607080202030405060708".into(),
+                    ..Default::default()
                 }),
             },
         );
@@ -1408,6 +1630,7 @@ pub mod test {
                 nwk_key: "01020304050607080102030405060708".into(),
                 app_key: "02020304050607080202030405060708".into(),
                 gen_app_key: "03020304050607080202030405060708".into(),
+                ..Default::default()
             }),
             get_keys_resp.get_ref().device_keys
         );
@@ -1421,6 +1644,7 @@ pub mod test {
                     nwk_key: "01020304050607080102030405060708".into(),
                     app_key: "03020304050607080302030405060708".into(),
                     gen_app_key: "03020304050607080202030405060708".into(),
+                    ..Default::default()
                 }),
             },
         );
@@ -1440,10 +1664,19 @@ pub mod test {
                 nwk_key: "01020304050607080102030405060708".into(),
                 app_key: "03020304050607080302030405060708".into(),
                 gen_app_key: "03020304050607080202030405060708".into(),
+                ..Default::default()
             }),
             get_keys_resp.get_ref().device_keys
         );
 
+        // key access log
+        let key_access =
+            key_access_log::test::list(&EUI64::from_str("0102030405060708").unwrap()).await;
+        assert_eq!(2, key_access.len());
+        assert!(key_access
+            .iter()
+            .all(|l| l.actor == format!("user:{}", u.id) && l.purpose == "api_export"));
+
         // flush dev nonces
         let _ = device_keys::set_dev_nonces(EUI64::from_str("0102030405060708").unwrap(), &{
             let mut dev_nonces = fields::DevNonces::default();
@@ -1511,6 +1744,7 @@ pub mod test {
             DevAddr::from_str("04030201").unwrap(),
             dev.dev_addr.unwrap()
         );
+        assert_eq!(device::DeviceState::Active, dev.state);
 
         // get activation
         let get_activation_req = get_request(
@@ -1548,6 +1782,100 @@ pub mod test {
             .unwrap();
         assert_eq!(1, get_next_f_cnt_resp.get_ref().f_cnt_down);
 
+        // enable debug-trace with invalid duration
+        let enable_debug_trace_req = get_request(
+            &u.id,
+            api::EnableDeviceDebugTraceRequest {
+                dev_eui: "0102030405060708".into(),
+                duration_minutes: 0,
+            },
+        );
+        assert!(service
+            .enable_debug_trace(enable_debug_trace_req)
+            .await
+            .is_err());
+
+        // enable debug-trace
+        let enable_debug_trace_req = get_request(
+            &u.id,
+            api::EnableDeviceDebugTraceRequest {
+                dev_eui: "0102030405060708".into(),
+                duration_minutes: 10,
+            },
+        );
+        let _ = service
+            .enable_debug_trace(enable_debug_trace_req)
+            .await
+            .unwrap();
+
+        // disable debug-trace
+        let disable_debug_trace_req = get_request(
+            &u.id,
+            api::DisableDeviceDebugTraceRequest {
+                dev_eui: "0102030405060708".into(),
+            },
+        );
+        let _ = service
+            .disable_debug_trace(disable_debug_trace_req)
+            .await
+            .unwrap();
+
+        // set state
+        let set_state_req = get_request(
+            &u.id,
+            api::SetDeviceStateRequest {
+                dev_eui: "0102030405060708".into(),
+                state: api::DeviceLifecycleState::Suspended.into(),
+            },
+        );
+        let _ = service.set_state(set_state_req).await.unwrap();
+        let get_req = get_request(
+            &u.id,
+            api::GetDeviceRequest {
+                dev_eui: "0102030405060708".into(),
+            },
+        );
+        let get_resp = service.get(get_req).await.unwrap();
+        assert_eq!(
+            api::DeviceLifecycleState::Suspended,
+            get_resp.get_ref().state()
+        );
+
+        // enqueue is rejected when suspended
+        let enqueue_req = get_request(
+            &u.id,
+            api::EnqueueDeviceQueueItemRequest {
+                queue_item: Some(api::DeviceQueueItem {
+                    dev_eui: "0102030405060708".into(),
+                    f_port: 10,
+                    ..Default::default()
+                }),
+            },
+        );
+        assert!(service.enqueue(enqueue_req).await.is_err());
+
+        let set_state_req = get_request(
+            &u.id,
+            api::SetDeviceStateRequest {
+                dev_eui: "0102030405060708".into(),
+                state: api::DeviceLifecycleState::Active.into(),
+            },
+        );
+        let _ = service.set_state(set_state_req).await.unwrap();
+
+        // unknown state is rejected
+        let set_state_req = get_request(
+            &u.id,
+            api::SetDeviceStateRequest {
+                dev_eui: "0102030405060708".into(),
+                state: 99,
+            },
+        );
+        assert_eq!(
+            tonic::Code::InvalidArgument,
+            service.set_state(set_state_req).await.unwrap_err().code()
+        );
+
         // deactivate
         let deactivate_req = get_request(
             &u.id,
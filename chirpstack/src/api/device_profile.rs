@@ -1,5 +1,7 @@
 use std::str::FromStr;
+use std::time::Instant;
 
+use chrono::Utc;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -11,6 +13,7 @@ use super::error::ToStatus;
 use super::helpers;
 use super::helpers::{FromProto, ToProto};
 use crate::adr;
+use crate::codec::{self, Codec};
 use crate::storage::{device_profile, fields};
 
 pub struct DeviceProfile {
@@ -153,6 +156,8 @@ impl DeviceProfileService for DeviceProfile {
             ..Default::default()
         };
 
+        sync_device_repository_codec(dp.payload_codec_runtime, &dp.payload_codec_script).await?;
+
         dp = device_profile::create(dp).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::CreateDeviceProfileResponse {
@@ -299,6 +304,12 @@ impl DeviceProfileService for DeviceProfile {
             )
             .await?;
 
+        sync_device_repository_codec(
+            req_dp.payload_codec_runtime().from_proto(),
+            &req_dp.payload_codec_script,
+        )
+        .await?;
+
         // update
         let _ = device_profile::update(device_profile::DeviceProfile {
             id: dp_id.into(),
@@ -525,6 +536,117 @@ impl DeviceProfileService for DeviceProfile {
             result,
         }))
     }
+
+    async fn test_decode(
+        &self,
+        request: Request<api::TestDeviceProfileDecodeRequest>,
+    ) -> Result<Response<api::TestDeviceProfileDecodeResponse>, Status> {
+        let req = request.get_ref();
+        let dp_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+        let f_port = u8::try_from(req.f_port)
+            .map_err(|_| Status::invalid_argument("f_port must be <= 255"))?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceProfileAccess::new(validator::Flag::Read, dp_id),
+            )
+            .await?;
+
+        let dp = device_profile::get(&dp_id).await.map_err(|e| e.status())?;
+
+        let start = Instant::now();
+        let res = codec::binary_to_struct(
+            dp.payload_codec_runtime,
+            &dp.tenant_id,
+            Utc::now(),
+            f_port,
+            &req.variables,
+            &dp.payload_codec_script,
+            &req.data,
+        )
+        .await;
+        let execution_time = start.elapsed();
+
+        let mut resp = api::TestDeviceProfileDecodeResponse {
+            execution_time: execution_time.try_into().ok(),
+            ..Default::default()
+        };
+        match res {
+            Ok(v) => resp.object = v.as_ref().map(codec::convert::pb_json_to_prost),
+            Err(e) => resp.error = format!("{:#}", e),
+        }
+
+        let mut resp = Response::new(resp);
+        resp.metadata_mut()
+            .insert("x-log-device_profile_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn test_encode(
+        &self,
+        request: Request<api::TestDeviceProfileEncodeRequest>,
+    ) -> Result<Response<api::TestDeviceProfileEncodeResponse>, Status> {
+        let req = request.get_ref();
+        let dp_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+        let f_port = u8::try_from(req.f_port)
+            .map_err(|_| Status::invalid_argument("f_port must be <= 255"))?;
+        let obj = req
+            .object
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("object is missing"))?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceProfileAccess::new(validator::Flag::Read, dp_id),
+            )
+            .await?;
+
+        let dp = device_profile::get(&dp_id).await.map_err(|e| e.status())?;
+
+        let start = Instant::now();
+        let res = codec::struct_to_binary(
+            dp.payload_codec_runtime,
+            &dp.tenant_id,
+            f_port,
+            &req.variables,
+            &dp.payload_codec_script,
+            obj,
+        )
+        .await;
+        let execution_time = start.elapsed();
+
+        let mut resp = api::TestDeviceProfileEncodeResponse {
+            execution_time: execution_time.try_into().ok(),
+            ..Default::default()
+        };
+        match res {
+            Ok(v) => resp.data = v,
+            Err(e) => resp.error = format!("{:#}", e),
+        }
+
+        let mut resp = Response::new(resp);
+        resp.metadata_mut()
+            .insert("x-log-device_profile_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+}
+
+// Fetches the referenced Device Repository codec, such that an invalid reference is rejected and
+// the codec is available before the first uplink is received.
+async fn sync_device_repository_codec(runtime: Codec, script: &str) -> Result<(), Status> {
+    if runtime != Codec::DEVICE_REPOSITORY {
+        return Ok(());
+    }
+
+    codec::device_repository::sync(script)
+        .await
+        .map_err(|e| Status::invalid_argument(format!("Sync device repository codec: {:#}", e)))?;
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -699,6 +821,126 @@ pub mod test {
         assert_eq!("lora_lr_fhss", list_adr_algs_resp.result[2].id);
     }
 
+    #[tokio::test]
+    async fn test_device_profile_test_codec() {
+        let _guard = test::prepare().await;
+
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let t = tenant::test::create_tenant().await;
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            tenant_id: t.id,
+            name: "test-dp".into(),
+            payload_codec_runtime: Codec::JS,
+            payload_codec_script: r#"
+                function decodeUplink(input) {
+                    if (input.bytes.length == 0) {
+                        throw new Error("empty payload");
+                    }
+
+                    return {
+                        data: {
+                            temperature: input.bytes[0],
+                            unit: input.variables.unit,
+                        }
+                    };
+                }
+
+                function encodeDownlink(input) {
+                    return {
+                        bytes: [input.data.temperature, input.fPort]
+                    };
+                }
+            "#
+            .into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let service = DeviceProfile::new(RequestValidator::new());
+
+        // decode
+        let decode_req = get_request(
+            &u.id,
+            api::TestDeviceProfileDecodeRequest {
+                id: dp.id.to_string(),
+                f_port: 10,
+                data: vec![21],
+                variables: [("unit".to_string(), "C".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            },
+        );
+        let decode_resp = service.test_decode(decode_req).await.unwrap();
+        let decode_resp = decode_resp.get_ref();
+        assert_eq!("", decode_resp.error);
+        assert!(decode_resp.execution_time.is_some());
+        assert_eq!(
+            Some(codec::convert::pb_json_to_prost(
+                &codec::convert::json_to_struct(&serde_json::json!({
+                    "temperature": 21,
+                    "unit": "C",
+                }))
+            )),
+            decode_resp.object
+        );
+
+        // decode error
+        let decode_req = get_request(
+            &u.id,
+            api::TestDeviceProfileDecodeRequest {
+                id: dp.id.to_string(),
+                f_port: 10,
+                ..Default::default()
+            },
+        );
+        let decode_resp = service.test_decode(decode_req).await.unwrap();
+        let decode_resp = decode_resp.get_ref();
+        assert!(decode_resp.error.contains("empty payload"));
+        assert_eq!(None, decode_resp.object);
+
+        // decode invalid f_port
+        let decode_req = get_request(
+            &u.id,
+            api::TestDeviceProfileDecodeRequest {
+                id: dp.id.to_string(),
+                f_port: 256,
+                ..Default::default()
+            },
+        );
+        assert!(service.test_decode(decode_req).await.is_err());
+
+        // encode
+        let encode_req = get_request(
+            &u.id,
+            api::TestDeviceProfileEncodeRequest {
+                id: dp.id.to_string(),
+                f_port: 10,
+                object: Some(codec::convert::pb_json_to_prost(
+                    &codec::convert::json_to_struct(&serde_json::json!({
+                        "temperature": 21,
+                    })),
+                )),
+                ..Default::default()
+            },
+        );
+        let encode_resp = service.test_encode(encode_req).await.unwrap();
+        let encode_resp = encode_resp.get_ref();
+        assert_eq!("", encode_resp.error);
+        assert_eq!(vec![21, 10], encode_resp.data);
+    }
+
     fn get_request<T>(user_id: &Uuid, req: T) -> Request<T> {
         let mut req = Request::new(req);
         req.extensions_mut().insert(AuthID::User(*user_id));
@@ -103,3 +103,9 @@ impl ToStatus for std::num::ParseIntError {
         Status::new(Code::Internal, format!("{:#}", self))
     }
 }
+
+impl ToStatus for hex::FromHexError {
+    fn status(&self) -> Status {
+        Status::new(Code::InvalidArgument, format!("{:#}", self))
+    }
+}
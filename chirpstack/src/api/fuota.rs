@@ -77,22 +77,56 @@ impl FuotaService for Fuota {
                 .request_fragmentation_session_status()
                 .from_proto(),
             payload: req_dp.payload.clone(),
+            payload_version: req_dp.payload_version.clone(),
             on_complete_set_device_tags: fields::KeyValue::new(
                 req_dp.on_complete_set_device_tags.clone(),
             ),
             ..Default::default()
         };
+        let deltas: Vec<fuota::FuotaDeploymentDelta> = req_dp
+            .deltas
+            .iter()
+            .map(|d| fuota::FuotaDeploymentDelta {
+                base_version: d.base_version.clone(),
+                payload: d.payload.clone(),
+                ..Default::default()
+            })
+            .collect();
+        fuota::validate_deltas(&deltas).map_err(|e| e.status())?;
+
         if req_dp.calculate_fragmentation_fragment_size {
-            dp.fragmentation_fragment_size = fuota::get_max_fragment_size(&dp)
+            dp.fragmentation_fragment_size = fuota::get_max_fragment_size(&dp, &deltas)
                 .await
                 .map_err(|e| e.status())? as i16;
         }
+        if req_dp.calculate_fragmentation_redundancy {
+            if req_dp.fragmentation_expected_loss_rate > 99 {
+                return Err(Status::invalid_argument(
+                    "fragmentation_expected_loss_rate must be less than 100",
+                ));
+            }
+
+            let params = fuota::calculate_fragmentation_parameters(
+                fuota::get_artifacts(&dp, &deltas)
+                    .iter()
+                    .map(|a| a.payload.len())
+                    .max()
+                    .unwrap_or_default(),
+                dp.fragmentation_fragment_size as usize,
+                req_dp.fragmentation_expected_loss_rate as u8,
+            )
+            .map_err(|e| e.status())?;
+            dp.fragmentation_redundancy_percentage = params.redundancy_percentage as i16;
+        }
         if req_dp.calculate_multicast_timeout {
             dp.multicast_timeout =
-                fuota::get_multicast_timeout(&dp).map_err(|e| e.status())? as i16;
+                fuota::get_multicast_timeout(&dp, &deltas).map_err(|e| e.status())? as i16;
         }
 
         let dp = fuota::create_deployment(dp).await.map_err(|e| e.status())?;
+        fuota::set_deltas(dp.id.into(), deltas)
+            .await
+            .map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::CreateFuotaDeploymentResponse {
             id: dp.id.to_string(),
@@ -120,6 +154,10 @@ impl FuotaService for Fuota {
             .await?;
 
         let dp = fuota::get_deployment(dp_id).await.map_err(|e| e.status())?;
+        let device_counts = fuota::get_device_counts(dp_id)
+            .await
+            .map_err(|e| e.status())?;
+        let deltas = fuota::get_deltas(dp_id).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::GetFuotaDeploymentResponse {
             deployment: Some(api::FuotaDeployment {
@@ -153,8 +191,18 @@ impl FuotaService for Fuota {
                     .to_proto()
                     .into(),
                 payload: dp.payload.clone(),
+                payload_version: dp.payload_version.clone(),
+                deltas: deltas
+                    .iter()
+                    .map(|d| api::FuotaDeploymentDelta {
+                        base_version: d.base_version.clone(),
+                        payload: d.payload.clone(),
+                    })
+                    .collect(),
                 calculate_multicast_timeout: false,
                 calculate_fragmentation_fragment_size: false,
+                calculate_fragmentation_redundancy: false,
+                fragmentation_expected_loss_rate: 0,
                 on_complete_set_device_tags: dp.on_complete_set_device_tags.into_hashmap(),
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&dp.created_at)),
@@ -167,6 +215,10 @@ impl FuotaService for Fuota {
                 .completed_at
                 .as_ref()
                 .map(helpers::datetime_to_prost_timestamp),
+            device_count: device_counts.total as u32,
+            device_completed_count: device_counts.completed as u32,
+            device_failed_count: device_counts.failed as u32,
+            device_pending_count: device_counts.pending as u32,
         });
         resp.metadata_mut()
             .insert("x-log-fuota_deployment_id", req.id.parse().unwrap());
@@ -231,22 +283,56 @@ impl FuotaService for Fuota {
                 .request_fragmentation_session_status()
                 .from_proto(),
             payload: req_dp.payload.clone(),
+            payload_version: req_dp.payload_version.clone(),
             on_complete_set_device_tags: fields::KeyValue::new(
                 req_dp.on_complete_set_device_tags.clone(),
             ),
             ..Default::default()
         };
+        let deltas: Vec<fuota::FuotaDeploymentDelta> = req_dp
+            .deltas
+            .iter()
+            .map(|d| fuota::FuotaDeploymentDelta {
+                base_version: d.base_version.clone(),
+                payload: d.payload.clone(),
+                ..Default::default()
+            })
+            .collect();
+        fuota::validate_deltas(&deltas).map_err(|e| e.status())?;
+
         if req_dp.calculate_fragmentation_fragment_size {
-            dp.fragmentation_fragment_size = fuota::get_max_fragment_size(&dp)
+            dp.fragmentation_fragment_size = fuota::get_max_fragment_size(&dp, &deltas)
                 .await
                 .map_err(|e| e.status())? as i16;
         }
+        if req_dp.calculate_fragmentation_redundancy {
+            if req_dp.fragmentation_expected_loss_rate > 99 {
+                return Err(Status::invalid_argument(
+                    "fragmentation_expected_loss_rate must be less than 100",
+                ));
+            }
+
+            let params = fuota::calculate_fragmentation_parameters(
+                fuota::get_artifacts(&dp, &deltas)
+                    .iter()
+                    .map(|a| a.payload.len())
+                    .max()
+                    .unwrap_or_default(),
+                dp.fragmentation_fragment_size as usize,
+                req_dp.fragmentation_expected_loss_rate as u8,
+            )
+            .map_err(|e| e.status())?;
+            dp.fragmentation_redundancy_percentage = params.redundancy_percentage as i16;
+        }
         if req_dp.calculate_multicast_timeout {
             dp.multicast_timeout =
-                fuota::get_multicast_timeout(&dp).map_err(|e| e.status())? as i16;
+                fuota::get_multicast_timeout(&dp, &deltas).map_err(|e| e.status())? as i16;
         }
 
-        let _ = fuota::update_deployment(dp).await.map_err(|e| e.status())?;
+        let dp = fuota::update_deployment(dp).await.map_err(|e| e.status())?;
+        fuota::set_deltas(dp.id.into(), deltas)
+            .await
+            .map_err(|e| e.status())?;
 
         let mut resp = Response::new(());
         resp.metadata_mut()
@@ -387,7 +473,7 @@ impl FuotaService for Fuota {
             dev_euis.push(EUI64::from_str(dev_eui).map_err(|e| e.status())?);
         }
 
-        fuota::add_devices(dp_id, dev_euis)
+        fuota::add_devices(dp_id, dev_euis, &req.base_version)
             .await
             .map_err(|e| e.status())?;
 
@@ -480,6 +566,9 @@ impl FuotaService for Fuota {
                         .as_ref()
                         .map(helpers::datetime_to_prost_timestamp),
                     error_msg: d.error_msg.clone(),
+                    base_version: d.base_version.clone(),
+                    target_version: d.target_version.clone(),
+                    full_image_required: d.full_image_required,
                 })
                 .collect(),
         });
@@ -641,6 +730,45 @@ impl FuotaService for Fuota {
         );
         Ok(resp)
     }
+
+    async fn calculate_fragmentation_parameters(
+        &self,
+        request: Request<api::CalculateFuotaFragmentationParametersRequest>,
+    ) -> Result<Response<api::CalculateFuotaFragmentationParametersResponse>, Status> {
+        let req = request.get_ref();
+        let dp_id = Uuid::from_str(&req.device_profile_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceProfileAccess::new(validator::Flag::Read, dp_id),
+            )
+            .await?;
+
+        if req.expected_loss_rate > 99 {
+            return Err(Status::invalid_argument(
+                "expected_loss_rate must be less than 100",
+            ));
+        }
+
+        let params = fuota::get_fragmentation_parameters(
+            &dp_id,
+            req.multicast_dr as u8,
+            req.payload_size as usize,
+            req.expected_loss_rate as u8,
+        )
+        .await
+        .map_err(|e| e.status())?;
+
+        Ok(Response::new(
+            api::CalculateFuotaFragmentationParametersResponse {
+                fragment_size: params.fragment_size as u32,
+                fragment_count: params.fragment_count as u32,
+                redundancy_count: params.redundancy_count as u32,
+                redundancy_percentage: params.redundancy_percentage as u32,
+            },
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -730,6 +858,23 @@ mod test {
         let create_resp = service.create_deployment(create_req).await.unwrap();
         let create_resp = create_resp.get_ref();
 
+        // create deployment with invalid expected loss-rate
+        let create_req = get_request(
+            &u.id,
+            api::CreateFuotaDeploymentRequest {
+                deployment: Some(api::FuotaDeployment {
+                    application_id: app.id.to_string(),
+                    device_profile_id: dp.id.to_string(),
+                    name: "test-fuota-invalid".into(),
+                    calculate_fragmentation_redundancy: true,
+                    fragmentation_expected_loss_rate: 300,
+                    ..Default::default()
+                }),
+            },
+        );
+        let create_err = service.create_deployment(create_req).await.unwrap_err();
+        assert_eq!(tonic::Code::InvalidArgument, create_err.code());
+
         // get deployment
         let get_req = get_request(
             &u.id,
@@ -804,6 +949,7 @@ mod test {
             api::AddDevicesToFuotaDeploymentRequest {
                 fuota_deployment_id: create_resp.id.clone(),
                 dev_euis: vec![dev.dev_eui.to_string()],
+                base_version: "1.0.0".into(),
             },
         );
         service.add_devices(add_dev_req).await.unwrap();
@@ -822,6 +968,21 @@ mod test {
         assert_eq!(1, list_devs_resp.total_count);
         assert_eq!(1, list_devs_resp.result.len());
         assert_eq!(dev.dev_eui.to_string(), list_devs_resp.result[0].dev_eui);
+        assert_eq!("1.0.0", list_devs_resp.result[0].base_version);
+
+        // get deployment device counts
+        let get_req = get_request(
+            &u.id,
+            api::GetFuotaDeploymentRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let get_resp = service.get_deployment(get_req).await.unwrap();
+        let get_resp = get_resp.get_ref();
+        assert_eq!(1, get_resp.device_count);
+        assert_eq!(1, get_resp.device_pending_count);
+        assert_eq!(0, get_resp.device_completed_count);
+        assert_eq!(0, get_resp.device_failed_count);
 
         // remove devices
         let remove_devs_req = get_request(
@@ -910,6 +1071,30 @@ mod test {
         assert_eq!(create_resp.id, jobs[0].fuota_deployment_id.to_string());
         assert_eq!(fields::FuotaJob::CreateMcGroup, jobs[0].job);
 
+        // calculate fragmentation parameters
+        let calc_req = get_request(
+            &u.id,
+            api::CalculateFuotaFragmentationParametersRequest {
+                device_profile_id: dp.id.to_string(),
+                multicast_dr: 5,
+                payload_size: 1000,
+                expected_loss_rate: 20,
+            },
+        );
+        let calc_resp = service
+            .calculate_fragmentation_parameters(calc_req)
+            .await
+            .unwrap();
+        assert_eq!(
+            &api::CalculateFuotaFragmentationParametersResponse {
+                fragment_size: 200,
+                fragment_count: 5,
+                redundancy_count: 3,
+                redundancy_percentage: 60,
+            },
+            calc_resp.get_ref()
+        );
+
         // delete deployment
         let delete_req = get_request(
             &u.id,
@@ -14,10 +14,13 @@ use super::auth::validator;
 use super::error::ToStatus;
 use super::helpers::{self, FromProto};
 use crate::certificate;
+use crate::config;
 use crate::storage::{
+    downlink_frame,
+    error::Error as StorageError,
     fields,
     gateway::{self, RelayId},
-    metrics,
+    gateway_certificate, metrics,
 };
 
 pub struct Gateway {
@@ -66,6 +69,8 @@ impl GatewayService for Gateway {
             altitude: alt,
             tags: fields::KeyValue::new(req_gw.tags.clone()),
             stats_interval_secs: req_gw.stats_interval as i32,
+            antenna_gain: req_gw.antenna_gain,
+            cable_loss: req_gw.cable_loss,
             ..Default::default()
         };
 
@@ -109,6 +114,8 @@ impl GatewayService for Gateway {
                 tags: gw.tags.into_hashmap(),
                 metadata: gw.properties.into_hashmap(),
                 stats_interval: gw.stats_interval_secs as u32,
+                antenna_gain: gw.antenna_gain,
+                cable_loss: gw.cable_loss,
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&gw.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&gw.updated_at)),
@@ -116,6 +123,33 @@ impl GatewayService for Gateway {
                 .last_seen_at
                 .as_ref()
                 .map(helpers::datetime_to_prost_timestamp),
+            state: {
+                if gw.offline_at.is_some() {
+                    api::GatewayState::Offline
+                } else if let Some(ts) = gw.last_seen_at {
+                    if (Utc::now() - ts)
+                        > Duration::try_seconds((gw.stats_interval_secs * 2).into())
+                            .unwrap_or_default()
+                    {
+                        api::GatewayState::Offline
+                    } else {
+                        api::GatewayState::Online
+                    }
+                } else {
+                    api::GatewayState::NeverSeen
+                }
+            }
+            .into(),
+            offline_at: gw
+                .offline_at
+                .as_ref()
+                .map(helpers::datetime_to_prost_timestamp),
+            inventory: Some(api::GatewayInventory {
+                model: gw.model,
+                hal_version: gw.hal_version,
+                concentratord_version: gw.concentratord_version,
+                gateway_bridge_version: gw.gateway_bridge_version,
+            }),
         });
         resp.metadata_mut()
             .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
@@ -157,6 +191,8 @@ impl GatewayService for Gateway {
             altitude: alt,
             tags: fields::KeyValue::new(req_gw.tags.clone()),
             stats_interval_secs: req_gw.stats_interval as i32,
+            antenna_gain: req_gw.antenna_gain,
+            cable_loss: req_gw.cable_loss,
             ..Default::default()
         })
         .await
@@ -185,6 +221,17 @@ impl GatewayService for Gateway {
 
         gateway::delete(&gw_id).await.map_err(|e| e.status())?;
 
+        // Certificates issued to the deleted gateway must no longer be accepted.
+        if !gateway_certificate::revoke_for_gateway(&gw_id)
+            .await
+            .map_err(|e| e.status())?
+            .is_empty()
+        {
+            certificate::publish_gateway_crl()
+                .await
+                .map_err(|e| e.status())?;
+        }
+
         let mut resp = Response::new(());
         resp.metadata_mut()
             .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
@@ -235,6 +282,26 @@ impl GatewayService for Gateway {
             } else {
                 Some(req.search.to_string())
             },
+            model: if req.model.is_empty() {
+                None
+            } else {
+                Some(req.model.clone())
+            },
+            hal_version: if req.hal_version.is_empty() {
+                None
+            } else {
+                Some(req.hal_version.clone())
+            },
+            concentratord_version: if req.concentratord_version.is_empty() {
+                None
+            } else {
+                Some(req.concentratord_version.clone())
+            },
+            gateway_bridge_version: if req.gateway_bridge_version.is_empty() {
+                None
+            } else {
+                Some(req.gateway_bridge_version.clone())
+            },
         };
 
         let count = gateway::get_count(&filters).await.map_err(|e| e.status())?;
@@ -285,6 +352,12 @@ impl GatewayService for Gateway {
                         }
                     }
                     .into(),
+                    inventory: Some(api::GatewayInventory {
+                        model: gw.model.clone(),
+                        hal_version: gw.hal_version.clone(),
+                        concentratord_version: gw.concentratord_version.clone(),
+                        gateway_bridge_version: gw.gateway_bridge_version.clone(),
+                    }),
                 })
                 .collect(),
         });
@@ -310,26 +383,87 @@ impl GatewayService for Gateway {
             )
             .await?;
 
-        let (ttl, ca_cert, cert, key) = certificate::client_cert_for_gateway_id(&gw_id)
+        let mut resp = Response::new(issue_client_certificate(gw_id).await?);
+        resp.metadata_mut()
+            .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn renew_client_certificate(
+        &self,
+        request: Request<api::RenewGatewayClientCertificateRequest>,
+    ) -> Result<Response<api::GenerateGatewayClientCertificateResponse>, Status> {
+        let req = request.get_ref();
+        let gw_id = EUI64::from_str(&req.gateway_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateGatewayAccess::new(validator::Flag::Update, gw_id),
+            )
+            .await?;
+
+        // Certificates that were issued before their serial numbers were stored can always be
+        // renewed.
+        let conf = config::get();
+        match gateway_certificate::get_latest_for_gateway(&gw_id).await {
+            Ok(v) => {
+                let renew_before: DateTime<Utc> =
+                    (SystemTime::now() + conf.gateway.client_cert_renew_before).into();
+                if v.expires_at > renew_before {
+                    return Err(Status::failed_precondition(format!(
+                        "Certificate is not yet due for renewal, it expires at {}",
+                        v.expires_at
+                    )));
+                }
+            }
+            Err(StorageError::NotFound(_)) => {}
+            Err(e) => {
+                return Err(e.status());
+            }
+        }
+
+        let mut resp = Response::new(issue_client_certificate(gw_id).await?);
+        resp.metadata_mut()
+            .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn revoke_client_certificates(
+        &self,
+        request: Request<api::RevokeGatewayClientCertificatesRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let gw_id = EUI64::from_str(&req.gateway_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateGatewayAccess::new(validator::Flag::Update, gw_id),
+            )
+            .await?;
+
+        gateway_certificate::revoke_for_gateway(&gw_id)
             .await
             .map_err(|e| e.status())?;
 
         gateway::partial_update(
             gw_id,
             &gateway::GatewayChangeset {
-                tls_certificate: Some(Some(cert.as_bytes().to_vec())),
+                tls_certificate: Some(None),
                 ..Default::default()
             },
         )
         .await
         .map_err(|e| e.status())?;
 
-        let mut resp = Response::new(api::GenerateGatewayClientCertificateResponse {
-            ca_cert,
-            tls_cert: cert,
-            tls_key: key,
-            expires_at: Some(ttl.into()),
-        });
+        certificate::publish_gateway_crl()
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
         resp.metadata_mut()
             .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
 
@@ -614,6 +748,135 @@ impl GatewayService for Gateway {
                     kind: common::MetricKind::Absolute.into(),
                 }
             }),
+            rx_airtime_per_sub_band: Some({
+                // discover all data-sets
+                let mut datasets: HashSet<String> = HashSet::new();
+                for m in &gw_metrics {
+                    for k in m.metrics.keys() {
+                        if k.starts_with("rx_airtime_") {
+                            datasets.insert(k.trim_start_matches("rx_airtime_").to_string());
+                        }
+                    }
+                }
+
+                common::Metric {
+                    name: "RX airtime (ms) / sub-band".to_string(),
+                    timestamps: gw_metrics
+                        .iter()
+                        .map(|row| {
+                            let ts: DateTime<Utc> = row.time.into();
+                            let ts: pbjson_types::Timestamp = ts.into();
+                            ts
+                        })
+                        .collect(),
+                    datasets: datasets
+                        .iter()
+                        .map(|label| common::MetricDataset {
+                            label: label.to_string(),
+                            data: gw_metrics
+                                .iter()
+                                .map(|row| {
+                                    row.metrics
+                                        .get(&format!("rx_airtime_{}", label))
+                                        .cloned()
+                                        .unwrap_or(0.0) as f32
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    kind: common::MetricKind::Absolute.into(),
+                }
+            }),
+            tx_airtime_per_sub_band: Some({
+                // discover all data-sets
+                let mut datasets: HashSet<String> = HashSet::new();
+                for m in &gw_metrics {
+                    for k in m.metrics.keys() {
+                        if k.starts_with("tx_airtime_") {
+                            datasets.insert(k.trim_start_matches("tx_airtime_").to_string());
+                        }
+                    }
+                }
+
+                common::Metric {
+                    name: "TX airtime (ms) / sub-band".to_string(),
+                    timestamps: gw_metrics
+                        .iter()
+                        .map(|row| {
+                            let ts: DateTime<Utc> = row.time.into();
+                            let ts: pbjson_types::Timestamp = ts.into();
+                            ts
+                        })
+                        .collect(),
+                    datasets: datasets
+                        .iter()
+                        .map(|label| common::MetricDataset {
+                            label: label.to_string(),
+                            data: gw_metrics
+                                .iter()
+                                .map(|row| {
+                                    row.metrics
+                                        .get(&format!("tx_airtime_{}", label))
+                                        .cloned()
+                                        .unwrap_or(0.0) as f32
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    kind: common::MetricKind::Absolute.into(),
+                }
+            }),
+            noise_floor_per_freq: Some({
+                // discover all data-sets
+                let mut datasets: HashSet<String> = HashSet::new();
+                for m in &gw_metrics {
+                    for k in m.metrics.keys() {
+                        if k.starts_with("noise_floor_count_") {
+                            datasets.insert(k.trim_start_matches("noise_floor_count_").to_string());
+                        }
+                    }
+                }
+
+                common::Metric {
+                    name: "Noise floor (dBm) / frequency".to_string(),
+                    timestamps: gw_metrics
+                        .iter()
+                        .map(|row| {
+                            let ts: DateTime<Utc> = row.time.into();
+                            let ts: pbjson_types::Timestamp = ts.into();
+                            ts
+                        })
+                        .collect(),
+                    datasets: datasets
+                        .iter()
+                        .map(|label| common::MetricDataset {
+                            label: label.to_string(),
+                            data: gw_metrics
+                                .iter()
+                                .map(|row| {
+                                    let sum = row
+                                        .metrics
+                                        .get(&format!("noise_floor_sum_{}", label))
+                                        .cloned()
+                                        .unwrap_or(0.0);
+                                    let count = row
+                                        .metrics
+                                        .get(&format!("noise_floor_count_{}", label))
+                                        .cloned()
+                                        .unwrap_or(0.0);
+
+                                    if count > 0.0 {
+                                        (sum / count) as f32
+                                    } else {
+                                        0.0
+                                    }
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    kind: common::MetricKind::Absolute.into(),
+                }
+            }),
         };
 
         let mut resp = Response::new(out);
@@ -790,6 +1053,61 @@ impl GatewayService for Gateway {
         Ok(resp)
     }
 
+    async fn list_pending_downlinks(
+        &self,
+        request: Request<api::ListGatewayPendingDownlinksRequest>,
+    ) -> Result<Response<api::ListGatewayPendingDownlinksResponse>, Status> {
+        let req = request.get_ref();
+        let gateway_id = EUI64::from_str(&req.gateway_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateGatewayAccess::new(validator::Flag::Read, gateway_id),
+            )
+            .await?;
+
+        let items = downlink_frame::get_pending_for_gateway(&gateway_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::ListGatewayPendingDownlinksResponse {
+            result: items
+                .iter()
+                .map(|item| {
+                    let df = &item.downlink_frame;
+                    let first_item = df.downlink_frame.as_ref().and_then(|v| v.items.first());
+
+                    api::GatewayPendingDownlink {
+                        downlink_id: df.downlink_id,
+                        created_at: Some(helpers::datetime_to_prost_timestamp(&item.created_at)),
+                        dev_eui: EUI64::from_slice(&df.dev_eui)
+                            .map(|v| v.to_string())
+                            .unwrap_or_default(),
+                        multicast_group_id: Uuid::from_slice(&df.multicast_group_id)
+                            .map(|v| v.to_string())
+                            .unwrap_or_default(),
+                        queue_item_id: Uuid::from_slice(&df.device_queue_item_id)
+                            .or_else(|_| Uuid::from_slice(&df.multicast_group_queue_item_id))
+                            .map(|v| v.to_string())
+                            .unwrap_or_default(),
+                        frequency: first_item
+                            .and_then(|v| v.tx_info.as_ref())
+                            .map(|v| v.frequency)
+                            .unwrap_or_default(),
+                        phy_payload_size: first_item
+                            .map(|v| v.phy_payload.len() as u32)
+                            .unwrap_or_default(),
+                    }
+                })
+                .collect(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-gateway_id", req.gateway_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
     async fn get_relay_gateway(
         &self,
         request: Request<api::GetRelayGatewayRequest>,
@@ -825,6 +1143,11 @@ impl GatewayService for Gateway {
                 .last_seen_at
                 .as_ref()
                 .map(helpers::datetime_to_prost_timestamp),
+            border_gateway_id: relay
+                .border_gateway_id
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            relay_path: relay_path_to_proto(&relay.relay_path),
         });
 
         resp.metadata_mut()
@@ -856,14 +1179,19 @@ impl GatewayService for Gateway {
             )
             .await?;
 
+        // The last-seen timestamp and mesh topology are retained, as these are set by the
+        // mesh heartbeats.
+        let relay = gateway::get_relay_gateway(tenant_id, relay_id)
+            .await
+            .map_err(|e| e.status())?;
+
         let _ = gateway::update_relay_gateway(gateway::RelayGateway {
-            relay_id,
-            tenant_id: tenant_id.into(),
             name: req_relay.name.clone(),
             description: req_relay.description.clone(),
             stats_interval_secs: req_relay.stats_interval as i32,
             region_config_id: req_relay.region_config_id.clone(),
-            ..Default::default()
+            updated_at: Utc::now(),
+            ..relay
         })
         .await
         .map_err(|e| e.status())?;
@@ -927,7 +1255,16 @@ impl GatewayService for Gateway {
             )
             .await?;
 
-        let filters = gateway::RelayGatewayFilters { tenant_id };
+        let border_gateway_id = if req.border_gateway_id.is_empty() {
+            None
+        } else {
+            Some(EUI64::from_str(&req.border_gateway_id).map_err(|e| e.status())?)
+        };
+
+        let filters = gateway::RelayGatewayFilters {
+            tenant_id,
+            border_gateway_id,
+        };
 
         let count = gateway::get_relay_gateway_count(&filters)
             .await
@@ -967,6 +1304,11 @@ impl GatewayService for Gateway {
                     }
                     .into(),
                     region_config_id: r.region_config_id.to_string(),
+                    border_gateway_id: r
+                        .border_gateway_id
+                        .map(|v| v.to_string())
+                        .unwrap_or_default(),
+                    relay_path: relay_path_to_proto(&r.relay_path),
                 })
                 .collect(),
         });
@@ -979,6 +1321,42 @@ impl GatewayService for Gateway {
     }
 }
 
+fn relay_path_to_proto(relay_path: &fields::RelayPath) -> Vec<api::RelayGatewayPathItem> {
+    relay_path
+        .iter()
+        .map(|p| api::RelayGatewayPathItem {
+            relay_id: p.relay_id.clone(),
+            rssi: p.rssi,
+            snr: p.snr,
+        })
+        .collect()
+}
+
+async fn issue_client_certificate(
+    gw_id: EUI64,
+) -> Result<api::GenerateGatewayClientCertificateResponse, Status> {
+    let (ttl, ca_cert, cert, key) = certificate::client_cert_for_gateway_id(&gw_id)
+        .await
+        .map_err(|e| e.status())?;
+
+    gateway::partial_update(
+        gw_id,
+        &gateway::GatewayChangeset {
+            tls_certificate: Some(Some(cert.as_bytes().to_vec())),
+            ..Default::default()
+        },
+    )
+    .await
+    .map_err(|e| e.status())?;
+
+    Ok(api::GenerateGatewayClientCertificateResponse {
+        ca_cert,
+        tls_cert: cert,
+        tls_key: key,
+        expires_at: Some(ttl.into()),
+    })
+}
+
 #[cfg(test)]
 pub mod test {
     use chrono::{Datelike, Local, TimeZone, Timelike};
@@ -1063,6 +1441,55 @@ pub mod test {
             }),
             get_resp.get_ref().gateway
         );
+        assert_eq!(
+            api::GatewayState::NeverSeen as i32,
+            get_resp.get_ref().state
+        );
+        assert_eq!(None, get_resp.get_ref().offline_at);
+
+        // pending downlinks
+        downlink_frame::save(&chirpstack_api::internal::DownlinkFrame {
+            downlink_id: 123,
+            dev_eui: vec![2, 2, 3, 4, 5, 6, 7, 8],
+            downlink_frame: Some(chirpstack_api::gw::DownlinkFrame {
+                downlink_id: 123,
+                gateway_id: "0102030405060708".into(),
+                items: vec![chirpstack_api::gw::DownlinkFrameItem {
+                    phy_payload: vec![1, 2, 3],
+                    tx_info: Some(chirpstack_api::gw::DownlinkTxInfo {
+                        frequency: 868100000,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let pending_req = api::ListGatewayPendingDownlinksRequest {
+            gateway_id: "0102030405060708".into(),
+        };
+        let mut pending_req = Request::new(pending_req);
+        pending_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let pending_resp = service.list_pending_downlinks(pending_req).await.unwrap();
+        let pending_resp = pending_resp.get_ref();
+        assert_eq!(1, pending_resp.result.len());
+        assert_eq!(
+            api::GatewayPendingDownlink {
+                downlink_id: 123,
+                created_at: pending_resp.result[0].created_at,
+                dev_eui: "0202030405060708".into(),
+                frequency: 868100000,
+                phy_payload_size: 3,
+                ..Default::default()
+            },
+            pending_resp.result[0]
+        );
 
         // update
         let up_req = api::UpdateGatewayRequest {
@@ -1076,6 +1503,8 @@ pub mod test {
                     altitude: 2.0,
                     ..Default::default()
                 }),
+                antenna_gain: 6.0,
+                cable_loss: 1.5,
                 ..Default::default()
             }),
         };
@@ -1105,6 +1534,8 @@ pub mod test {
                     altitude: 2.0,
                     ..Default::default()
                 }),
+                antenna_gain: 6.0,
+                cable_loss: 1.5,
                 ..Default::default()
             }),
             get_resp.get_ref().gateway
@@ -1125,6 +1556,24 @@ pub mod test {
         let list_resp = service.list(list_req).await.unwrap();
         assert_eq!(1, list_resp.get_ref().total_count);
         assert_eq!(1, list_resp.get_ref().result.len());
+        assert_eq!(
+            Some(api::GatewayInventory::default()),
+            list_resp.get_ref().result[0].inventory
+        );
+
+        // list by inventory
+        let list_req = api::ListGatewaysRequest {
+            tenant_id: t.id.to_string(),
+            model: "rak7268".into(),
+            limit: 10,
+            ..Default::default()
+        };
+        let mut list_req = Request::new(list_req);
+        list_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let list_resp = service.list(list_req).await.unwrap();
+        assert_eq!(0, list_resp.get_ref().total_count);
 
         // delete
         let del_req = api::DeleteGatewayRequest {
@@ -1197,6 +1646,9 @@ pub mod test {
         m.metrics.insert("tx_count".into(), 5.0);
         m.metrics.insert("tx_freq_868200000".into(), 5.0);
         m.metrics.insert("tx_dr_4".into(), 5.0);
+        m.metrics.insert("tx_airtime_g3".into(), 41.216);
+        m.metrics.insert("noise_floor_sum_868100000".into(), -210.0);
+        m.metrics.insert("noise_floor_count_868100000".into(), 2.0);
 
         metrics::save(
             "gw:0102030405060708",
@@ -1242,6 +1694,42 @@ pub mod test {
             }),
             stats_resp.rx_packets
         );
+        assert_eq!(
+            Some(common::Metric {
+                name: "TX airtime (ms) / sub-band".to_string(),
+                timestamps: vec![{
+                    let ts = Local
+                        .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+                        .unwrap();
+                    let ts: DateTime<Utc> = ts.into();
+                    ts.into()
+                }],
+                datasets: vec![common::MetricDataset {
+                    label: "g3".to_string(),
+                    data: vec![41.216],
+                }],
+                kind: common::MetricKind::Absolute.into(),
+            }),
+            stats_resp.tx_airtime_per_sub_band
+        );
+        assert_eq!(
+            Some(common::Metric {
+                name: "Noise floor (dBm) / frequency".to_string(),
+                timestamps: vec![{
+                    let ts = Local
+                        .with_ymd_and_hms(now.year(), now.month(), now.day(), 0, 0, 0)
+                        .unwrap();
+                    let ts: DateTime<Utc> = ts.into();
+                    ts.into()
+                }],
+                datasets: vec![common::MetricDataset {
+                    label: "868100000".to_string(),
+                    data: vec![-105.0],
+                }],
+                kind: common::MetricKind::Absolute.into(),
+            }),
+            stats_resp.noise_floor_per_freq
+        );
     }
 
     #[tokio::test]
@@ -1374,6 +1862,12 @@ pub mod test {
             name: "test-relay".into(),
             description: "test relay".into(),
             region_config_id: "eu868".into(),
+            border_gateway_id: Some(EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8])),
+            relay_path: fields::RelayPath::new(vec![fields::RelayPathItem {
+                relay_id: "01020304".into(),
+                rssi: -60,
+                snr: 6,
+            }]),
             ..Default::default()
         })
         .await
@@ -1400,6 +1894,18 @@ pub mod test {
             }),
             get_relay_resp.get_ref().relay_gateway
         );
+        assert_eq!(
+            "0102030405060708",
+            get_relay_resp.get_ref().border_gateway_id
+        );
+        assert_eq!(
+            vec![api::RelayGatewayPathItem {
+                relay_id: "01020304".into(),
+                rssi: -60,
+                snr: 6,
+            }],
+            get_relay_resp.get_ref().relay_path
+        );
 
         // update
         let up_relay_req = api::UpdateRelayGatewayRequest {
@@ -1439,10 +1945,15 @@ pub mod test {
             }),
             get_relay_resp.get_ref().relay_gateway
         );
+        assert_eq!(
+            "0102030405060708",
+            get_relay_resp.get_ref().border_gateway_id
+        );
 
         // list
         let list_relay_req = api::ListRelayGatewaysRequest {
             tenant_id: t.id.to_string(),
+            border_gateway_id: "0102030405060708".into(),
             limit: 10,
             offset: 0,
         };
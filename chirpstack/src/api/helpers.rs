@@ -7,7 +7,12 @@ use crate::codec::Codec;
 use crate::storage::fields::{
     self, MeasurementKind, MulticastGroupSchedulingType, RequestFragmentationSessionStatus,
 };
-use crate::storage::{device, device::DeviceClass, gateway, metrics::Aggregation};
+use crate::storage::{
+    device,
+    device::{DeviceClass, DeviceState},
+    gateway,
+    metrics::Aggregation,
+};
 
 pub trait FromProto<T> {
     #[allow(clippy::wrong_self_convention)]
@@ -133,6 +138,10 @@ impl ToProto<api::CodecRuntime> for Codec {
             Codec::NONE => api::CodecRuntime::None,
             Codec::CAYENNE_LPP => api::CodecRuntime::CayenneLpp,
             Codec::JS => api::CodecRuntime::Js,
+            Codec::WASM => api::CodecRuntime::Wasm,
+            Codec::DEVICE_REPOSITORY => api::CodecRuntime::DeviceRepository,
+            Codec::PYTHON => api::CodecRuntime::Python,
+            Codec::BINARY => api::CodecRuntime::Binary,
         }
     }
 }
@@ -143,6 +152,10 @@ impl FromProto<Codec> for api::CodecRuntime {
             api::CodecRuntime::None => Codec::NONE,
             api::CodecRuntime::CayenneLpp => Codec::CAYENNE_LPP,
             api::CodecRuntime::Js => Codec::JS,
+            api::CodecRuntime::Wasm => Codec::WASM,
+            api::CodecRuntime::DeviceRepository => Codec::DEVICE_REPOSITORY,
+            api::CodecRuntime::Python => Codec::PYTHON,
+            api::CodecRuntime::Binary => Codec::BINARY,
         }
     }
 }
@@ -226,6 +239,16 @@ impl FromProto<MulticastGroupSchedulingType> for api::MulticastGroupSchedulingTy
     }
 }
 
+impl ToProto<api::RoamingRole> for fields::RoamingRole {
+    fn to_proto(self) -> api::RoamingRole {
+        match self {
+            fields::RoamingRole::FNS => api::RoamingRole::Fns,
+            fields::RoamingRole::SNS => api::RoamingRole::Sns,
+            fields::RoamingRole::HNS => api::RoamingRole::Hns,
+        }
+    }
+}
+
 impl ToProto<api::RelayModeActivation> for lrwn::RelayModeActivation {
     fn to_proto(self) -> api::RelayModeActivation {
         match self {
@@ -266,6 +289,28 @@ impl ToProto<common::DeviceClass> for DeviceClass {
     }
 }
 
+impl ToProto<api::DeviceLifecycleState> for DeviceState {
+    fn to_proto(self) -> api::DeviceLifecycleState {
+        match self {
+            DeviceState::Provisioned => api::DeviceLifecycleState::Provisioned,
+            DeviceState::Active => api::DeviceLifecycleState::Active,
+            DeviceState::Suspended => api::DeviceLifecycleState::Suspended,
+            DeviceState::Decommissioned => api::DeviceLifecycleState::Decommissioned,
+        }
+    }
+}
+
+impl FromProto<DeviceState> for api::DeviceLifecycleState {
+    fn from_proto(self) -> DeviceState {
+        match self {
+            api::DeviceLifecycleState::Provisioned => DeviceState::Provisioned,
+            api::DeviceLifecycleState::Active => DeviceState::Active,
+            api::DeviceLifecycleState::Suspended => DeviceState::Suspended,
+            api::DeviceLifecycleState::Decommissioned => DeviceState::Decommissioned,
+        }
+    }
+}
+
 impl FromProto<device::OrderBy> for api::list_devices_request::OrderBy {
     fn from_proto(self) -> device::OrderBy {
         match self {
@@ -367,6 +412,62 @@ impl FromProto<RequestFragmentationSessionStatus> for api::RequestFragmentationS
     }
 }
 
+impl ToProto<api::TenantPermission> for fields::Permission {
+    fn to_proto(self) -> api::TenantPermission {
+        match self {
+            Self::ApplicationRead => api::TenantPermission::ApplicationRead,
+            Self::ApplicationWrite => api::TenantPermission::ApplicationWrite,
+            Self::DeviceRead => api::TenantPermission::DeviceRead,
+            Self::DeviceWrite => api::TenantPermission::DeviceWrite,
+            Self::DeviceKeysRead => api::TenantPermission::DeviceKeysRead,
+            Self::DeviceKeysWrite => api::TenantPermission::DeviceKeysWrite,
+            Self::DeviceQueueRead => api::TenantPermission::DeviceQueueRead,
+            Self::DeviceQueueWrite => api::TenantPermission::DeviceQueueWrite,
+            Self::DeviceProfileRead => api::TenantPermission::DeviceProfileRead,
+            Self::DeviceProfileWrite => api::TenantPermission::DeviceProfileWrite,
+            Self::CodecLibraryRead => api::TenantPermission::CodecLibraryRead,
+            Self::CodecLibraryWrite => api::TenantPermission::CodecLibraryWrite,
+            Self::GatewayRead => api::TenantPermission::GatewayRead,
+            Self::GatewayWrite => api::TenantPermission::GatewayWrite,
+            Self::MulticastGroupRead => api::TenantPermission::MulticastGroupRead,
+            Self::MulticastGroupWrite => api::TenantPermission::MulticastGroupWrite,
+            Self::FuotaDeploymentRead => api::TenantPermission::FuotaDeploymentRead,
+            Self::FuotaDeploymentWrite => api::TenantPermission::FuotaDeploymentWrite,
+            Self::ApiKeyRead => api::TenantPermission::ApiKeyRead,
+            Self::TenantUserRead => api::TenantPermission::TenantUserRead,
+            Self::TenantUserWrite => api::TenantPermission::TenantUserWrite,
+        }
+    }
+}
+
+impl FromProto<fields::Permission> for api::TenantPermission {
+    fn from_proto(self) -> fields::Permission {
+        match self {
+            Self::ApplicationRead => fields::Permission::ApplicationRead,
+            Self::ApplicationWrite => fields::Permission::ApplicationWrite,
+            Self::DeviceRead => fields::Permission::DeviceRead,
+            Self::DeviceWrite => fields::Permission::DeviceWrite,
+            Self::DeviceKeysRead => fields::Permission::DeviceKeysRead,
+            Self::DeviceKeysWrite => fields::Permission::DeviceKeysWrite,
+            Self::DeviceQueueRead => fields::Permission::DeviceQueueRead,
+            Self::DeviceQueueWrite => fields::Permission::DeviceQueueWrite,
+            Self::DeviceProfileRead => fields::Permission::DeviceProfileRead,
+            Self::DeviceProfileWrite => fields::Permission::DeviceProfileWrite,
+            Self::CodecLibraryRead => fields::Permission::CodecLibraryRead,
+            Self::CodecLibraryWrite => fields::Permission::CodecLibraryWrite,
+            Self::GatewayRead => fields::Permission::GatewayRead,
+            Self::GatewayWrite => fields::Permission::GatewayWrite,
+            Self::MulticastGroupRead => fields::Permission::MulticastGroupRead,
+            Self::MulticastGroupWrite => fields::Permission::MulticastGroupWrite,
+            Self::FuotaDeploymentRead => fields::Permission::FuotaDeploymentRead,
+            Self::FuotaDeploymentWrite => fields::Permission::FuotaDeploymentWrite,
+            Self::ApiKeyRead => fields::Permission::ApiKeyRead,
+            Self::TenantUserRead => fields::Permission::TenantUserRead,
+            Self::TenantUserWrite => fields::Permission::TenantUserWrite,
+        }
+    }
+}
+
 pub fn datetime_to_prost_timestamp(dt: &DateTime<Utc>) -> prost_types::Timestamp {
     let ts = dt.timestamp_nanos_opt().unwrap_or_default();
 
@@ -17,11 +17,12 @@ use uuid::Uuid;
 use chirpstack_api::api;
 use chirpstack_api::api::internal_service_server::InternalService;
 
-use super::auth::claims;
+use super::auth::{claims, lockout, session};
 use super::auth::{validator, AuthID};
 use super::error::ToStatus;
 use super::helpers::ToProto;
 use super::{helpers, oauth2, oidc};
+use crate::helpers::errors::PrintFullError;
 use crate::storage::{api_key, device, error::Error, gateway, redis_key, search, tenant, user};
 use crate::{config, region, stream};
 use lrwn::EUI64;
@@ -147,12 +148,41 @@ impl InternalService for Internal {
         request: Request<api::LoginRequest>,
     ) -> Result<Response<api::LoginResponse>, Status> {
         let req = request.get_ref();
-        let u = user::get_by_email_and_pw(&req.email, &req.password)
-            .await
-            .map_err(|e| e.status())?;
+        let subject = lockout::login_subject(&req.email, lockout::client_ip(&request));
+
+        // In case the lockout state can not be retrieved or stored, the login is not blocked.
+        match lockout::is_locked(lockout::Kind::Login, &subject).await {
+            Ok(true) => {
+                return Err(Status::resource_exhausted(
+                    "too many authentication failures, try again later",
+                ));
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!(error = %e.full(), "Get authentication lockout error");
+            }
+        }
 
-        let token = claims::AuthClaim::new_for_user(&u.id)
-            .encode(self.jwt_secret.as_ref())
+        let u = match user::get_by_email_and_pw(&req.email, &req.password).await {
+            Ok(v) => v,
+            Err(Error::InvalidUsernameOrPassword) => {
+                match lockout::failure(lockout::Kind::Login, &subject).await {
+                    Ok(f) => tokio::time::sleep(f.delay).await,
+                    Err(e) => {
+                        error!(error = %e.full(), "Register authentication failure error");
+                    }
+                }
+                return Err(Error::InvalidUsernameOrPassword.status());
+            }
+            Err(e) => return Err(e.status()),
+        };
+
+        if let Err(e) = lockout::reset(lockout::Kind::Login, &subject).await {
+            error!(error = %e.full(), "Reset authentication failure count error");
+        }
+
+        let token = session::create_token(&u.id, self.jwt_secret.as_ref())
+            .await
             .map_err(|e| e.status())?;
 
         Ok(Response::new(api::LoginResponse { jwt: token }))
@@ -508,8 +538,8 @@ impl InternalService for Internal {
         u.email_verified = email_verified;
         let u = user::update(u).await.map_err(|e| e.status())?;
 
-        let token = claims::AuthClaim::new_for_user(&u.id)
-            .encode(self.jwt_secret.as_ref())
+        let token = session::create_token(&u.id, self.jwt_secret.as_ref())
+            .await
             .map_err(|e| e.status())?;
         Ok(Response::new(api::OpenIdConnectLoginResponse { token }))
     }
@@ -589,8 +619,8 @@ impl InternalService for Internal {
         u.email_verified = email_verified;
         let u = user::update(u).await.map_err(|e| e.status())?;
 
-        let token = claims::AuthClaim::new_for_user(&u.id)
-            .encode(self.jwt_secret.as_ref())
+        let token = session::create_token(&u.id, self.jwt_secret.as_ref())
+            .await
             .map_err(|e| e.status())?;
         Ok(Response::new(api::OAuth2LoginResponse { token }))
     }
@@ -884,6 +914,74 @@ impl InternalService for Internal {
         Ok(Response::new(drop_receiver))
     }
 
+    type StreamDeviceDebugTraceStream = DropReceiver<Result<api::LogItem, Status>>;
+
+    async fn stream_device_debug_trace(
+        &self,
+        request: Request<api::StreamDeviceDebugTraceRequest>,
+    ) -> Result<Response<Self::StreamDeviceDebugTraceStream>, Status> {
+        let req = request.get_ref();
+        let dev_eui = EUI64::from_str(&req.dev_eui).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateDeviceAccess::new(validator::Flag::Read, dev_eui),
+            )
+            .await?;
+
+        let key = redis_key(format!("device:{{{}}}:stream:trace", req.dev_eui));
+        let (redis_tx, mut redis_rx) = mpsc::channel(1);
+        let (stream_tx, stream_rx) = mpsc::channel(1);
+
+        let mut tracelog_future = Box::pin(stream::device_trace::get_trace_logs(key, 10, redis_tx));
+        let (drop_receiver, mut close_rx) = DropReceiver::new(ReceiverStream::new(stream_rx));
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    // detect client disconnect
+                    _ = close_rx.recv() => {
+                        debug!("Client disconnected");
+                        redis_rx.close();
+                        break;
+                    },
+                    // detect get_trace_logs function return
+                    res = &mut tracelog_future => {
+                        match res {
+                            Ok(_) => {
+                                trace!("get_trace_logs returned");
+                            },
+                            Err(e) => {
+                                error!("Reading trace-log returned error: {}", e);
+                                stream_tx.send(Err(e.status())).await.unwrap();
+                            },
+                        }
+                        break;
+                    }
+                    // detect stream message
+                    msg = redis_rx.recv() => {
+                        match msg {
+                            None => {
+                                trace!("Redis Stream channel has been closed");
+                                break;
+                            },
+                            Some(msg) => {
+                                trace!("Message received from Redis Stream channel");
+                                if stream_tx.send(Ok(msg)).await.is_err() {
+                                    error!("Sending message to gRPC channel error");
+                                    break;
+                                };
+                            },
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(drop_receiver))
+    }
+
     async fn list_regions(
         &self,
         request: Request<()>,
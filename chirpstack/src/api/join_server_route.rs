@@ -0,0 +1,398 @@
+use std::str::FromStr;
+
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use chirpstack_api::api;
+use chirpstack_api::api::join_server_route_service_server::JoinServerRouteService;
+
+use super::auth::validator;
+use super::error::ToStatus;
+use super::helpers;
+use crate::storage::join_server_route;
+
+pub struct JoinServerRoute {
+    validator: validator::RequestValidator,
+}
+
+impl JoinServerRoute {
+    pub fn new(validator: validator::RequestValidator) -> Self {
+        JoinServerRoute { validator }
+    }
+}
+
+#[tonic::async_trait]
+impl JoinServerRouteService for JoinServerRoute {
+    async fn create(
+        &self,
+        request: Request<api::CreateJoinServerRouteRequest>,
+    ) -> Result<Response<api::CreateJoinServerRouteResponse>, Status> {
+        let req_r = match &request.get_ref().join_server_route {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("join_server_route is missing"));
+            }
+        };
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateJoinServerRoutesAccess::new(validator::Flag::Create),
+            )
+            .await?;
+
+        let r = join_server_route::create(route_from_proto(req_r))
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::CreateJoinServerRouteResponse {
+            id: r.id.to_string(),
+        });
+        resp.metadata_mut().insert(
+            "x-log-join_server_route_id",
+            r.id.to_string().parse().unwrap(),
+        );
+
+        Ok(resp)
+    }
+
+    async fn get(
+        &self,
+        request: Request<api::GetJoinServerRouteRequest>,
+    ) -> Result<Response<api::GetJoinServerRouteResponse>, Status> {
+        let req = request.get_ref();
+        let r_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateJoinServerRouteAccess::new(validator::Flag::Read),
+            )
+            .await?;
+
+        let r = join_server_route::get(&r_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetJoinServerRouteResponse {
+            created_at: Some(helpers::datetime_to_prost_timestamp(&r.created_at)),
+            updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+            join_server_route: Some(route_to_proto(r)),
+        });
+        resp.metadata_mut()
+            .insert("x-log-join_server_route_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn update(
+        &self,
+        request: Request<api::UpdateJoinServerRouteRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_r = match &request.get_ref().join_server_route {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("join_server_route is missing"));
+            }
+        };
+        let r_id = Uuid::from_str(&req_r.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateJoinServerRouteAccess::new(validator::Flag::Update),
+            )
+            .await?;
+
+        // The secrets are not returned by the get method. When left empty, the stored values
+        // are kept.
+        let r = join_server_route::get(&r_id)
+            .await
+            .map_err(|e| e.status())?;
+        let mut r_new = route_from_proto(req_r);
+        if r_new.authorization_header.is_empty() {
+            r_new.authorization_header = r.authorization_header;
+        }
+        if r_new.oauth2_client_secret.is_empty() {
+            r_new.oauth2_client_secret = r.oauth2_client_secret;
+        }
+        if r_new.api_key.is_empty() {
+            r_new.api_key = r.api_key;
+        }
+
+        let _ = join_server_route::update(join_server_route::JoinServerRoute {
+            id: r_id.into(),
+            ..r_new
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-join_server_route_id", req_r.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn delete(
+        &self,
+        request: Request<api::DeleteJoinServerRouteRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let r_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateJoinServerRouteAccess::new(validator::Flag::Delete),
+            )
+            .await?;
+
+        join_server_route::delete(&r_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-join_server_route_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn list(
+        &self,
+        request: Request<api::ListJoinServerRoutesRequest>,
+    ) -> Result<Response<api::ListJoinServerRoutesResponse>, Status> {
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateJoinServerRoutesAccess::new(validator::Flag::List),
+            )
+            .await?;
+
+        let req = request.get_ref();
+        let filters = join_server_route::Filters {
+            search: if req.search.is_empty() {
+                None
+            } else {
+                Some(req.search.to_string())
+            },
+        };
+
+        let count = join_server_route::get_count(&filters)
+            .await
+            .map_err(|e| e.status())?;
+        let items = join_server_route::list(req.limit as i64, req.offset as i64, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        Ok(Response::new(api::ListJoinServerRoutesResponse {
+            total_count: count as u32,
+            result: items
+                .iter()
+                .map(|r| api::JoinServerRouteListItem {
+                    id: r.id.to_string(),
+                    created_at: Some(helpers::datetime_to_prost_timestamp(&r.created_at)),
+                    updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+                    name: r.name.clone(),
+                    join_eui_prefix: r.join_eui_prefix.clone(),
+                    server: r.server.clone(),
+                })
+                .collect(),
+        }))
+    }
+}
+
+fn route_from_proto(r: &api::JoinServerRoute) -> join_server_route::JoinServerRoute {
+    join_server_route::JoinServerRoute {
+        name: r.name.clone(),
+        join_eui_prefix: r.join_eui_prefix.clone(),
+        server: r.server.clone(),
+        async_timeout: r.async_timeout as i32,
+        ca_cert: r.ca_cert.clone(),
+        tls_cert: r.tls_cert.clone(),
+        tls_key: r.tls_key.clone(),
+        authorization_header: r.authorization_header.clone(),
+        oauth2_token_url: r.oauth2_token_url.clone(),
+        oauth2_client_id: r.oauth2_client_id.clone(),
+        oauth2_client_secret: r.oauth2_client_secret.clone(),
+        oauth2_scope: r.oauth2_scope.clone(),
+        api_key_header: r.api_key_header.clone(),
+        api_key: r.api_key.clone(),
+        jws_algorithm: r.jws_algorithm.clone(),
+        jws_signing_key: r.jws_signing_key.clone(),
+        jws_verification_key: r.jws_verification_key.clone(),
+        ..Default::default()
+    }
+}
+
+fn route_to_proto(r: join_server_route::JoinServerRoute) -> api::JoinServerRoute {
+    api::JoinServerRoute {
+        id: r.id.to_string(),
+        name: r.name,
+        join_eui_prefix: r.join_eui_prefix,
+        server: r.server,
+        async_timeout: r.async_timeout as u32,
+        ca_cert: r.ca_cert,
+        tls_cert: r.tls_cert,
+        tls_key: r.tls_key,
+        // Secrets are never returned.
+        authorization_header: "".into(),
+        oauth2_token_url: r.oauth2_token_url,
+        oauth2_client_id: r.oauth2_client_id,
+        oauth2_client_secret: "".into(),
+        oauth2_scope: r.oauth2_scope,
+        api_key_header: r.api_key_header,
+        api_key: "".into(),
+        jws_algorithm: r.jws_algorithm,
+        jws_signing_key: r.jws_signing_key,
+        jws_verification_key: r.jws_verification_key,
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::api::auth::validator::RequestValidator;
+    use crate::api::auth::AuthID;
+    use crate::backend::joinserver;
+    use crate::storage::user;
+    use crate::test;
+    use lrwn::EUI64;
+
+    #[tokio::test]
+    async fn test_join_server_route() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // setup the api
+        let service = JoinServerRoute::new(RequestValidator::new());
+
+        let join_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // create
+        let create_req = get_request(
+            &u.id,
+            api::CreateJoinServerRouteRequest {
+                join_server_route: Some(api::JoinServerRoute {
+                    name: "js".into(),
+                    join_eui_prefix: "0102030405060700/56".into(),
+                    server: "https://js.example.com".into(),
+                    async_timeout: 1,
+                    authorization_header: "secret".into(),
+                    oauth2_client_secret: "client-secret".into(),
+                    api_key: "api-key".into(),
+                    ..Default::default()
+                }),
+            },
+        );
+        let create_resp = service.create(create_req).await.unwrap();
+        let create_resp = create_resp.get_ref();
+
+        // the join server client has been configured
+        joinserver::reload().await.unwrap();
+        let client = joinserver::get(join_eui).await.unwrap();
+        assert!(client.is_async());
+
+        // get
+        let get_req = get_request(
+            &u.id,
+            api::GetJoinServerRouteRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let get_resp = service.get(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::JoinServerRoute {
+                id: create_resp.id.clone(),
+                name: "js".into(),
+                join_eui_prefix: "0102030405060700/56".into(),
+                server: "https://js.example.com".into(),
+                async_timeout: 1,
+                ..Default::default()
+            }),
+            get_resp.get_ref().join_server_route
+        );
+
+        // update
+        let up_req = get_request(
+            &u.id,
+            api::UpdateJoinServerRouteRequest {
+                join_server_route: Some(api::JoinServerRoute {
+                    id: create_resp.id.clone(),
+                    name: "js-updated".into(),
+                    join_eui_prefix: "0102030405060700/56".into(),
+                    server: "https://js.example.com".into(),
+                    ..Default::default()
+                }),
+            },
+        );
+        let _ = service.update(up_req).await.unwrap();
+
+        // the secrets have been kept
+        let r = join_server_route::get(&Uuid::from_str(&create_resp.id).unwrap())
+            .await
+            .unwrap();
+        assert_eq!("secret", r.authorization_header);
+        assert_eq!("client-secret", r.oauth2_client_secret);
+        assert_eq!("api-key", r.api_key);
+
+        // the join server client has been re-configured
+        joinserver::reload().await.unwrap();
+        let client = joinserver::get(join_eui).await.unwrap();
+        assert!(!client.is_async());
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListJoinServerRoutesRequest {
+                limit: 10,
+                ..Default::default()
+            },
+        );
+        let list_resp = service.list(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(1, list_resp.total_count);
+        assert_eq!(1, list_resp.result.len());
+        assert_eq!("js-updated", list_resp.result[0].name);
+        assert_eq!("0102030405060700/56", list_resp.result[0].join_eui_prefix);
+
+        // delete
+        let del_req = get_request(
+            &u.id,
+            api::DeleteJoinServerRouteRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let _ = service.delete(del_req).await.unwrap();
+
+        let del_req = get_request(
+            &u.id,
+            api::DeleteJoinServerRouteRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let del_resp = service.delete(del_req).await;
+        assert!(del_resp.is_err());
+
+        // the join server client has been removed
+        joinserver::reload().await.unwrap();
+        assert!(joinserver::get(join_eui).await.is_err());
+    }
+
+    fn get_request<T>(user_id: &Uuid, req: T) -> Request<T> {
+        let mut req = Request::new(req);
+        req.extensions_mut().insert(AuthID::User(*user_id));
+        req
+    }
+}
@@ -1,11 +1,12 @@
 use std::time::{Duration, Instant};
 use std::{
+    fs,
     future::Future,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use axum::{response::IntoResponse, routing::get, Router};
 use http::{
     header::{self, HeaderMap, HeaderValue},
@@ -19,7 +20,10 @@ use prometheus_client::metrics::histogram::Histogram;
 use rust_embed::RustEmbed;
 use tokio::task;
 use tokio::try_join;
-use tonic::transport::Server as TonicServer;
+use tonic::transport::{
+    Certificate as TonicCertificate, Identity as TonicIdentity, Server as TonicServer,
+    ServerTlsConfig,
+};
 use tonic::Code;
 use tonic_reflection::server::Builder as TonicReflectionBuilder;
 use tonic_web::GrpcWebLayer;
@@ -27,16 +31,20 @@ use tower::util::ServiceExt;
 use tower::Service;
 use tower_http::trace::TraceLayer;
 use tracing::{error, info};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use chirpstack_api::api::application_service_server::ApplicationServiceServer;
+use chirpstack_api::api::codec_library_service_server::CodecLibraryServiceServer;
 use chirpstack_api::api::device_profile_service_server::DeviceProfileServiceServer;
 use chirpstack_api::api::device_profile_template_service_server::DeviceProfileTemplateServiceServer;
 use chirpstack_api::api::device_service_server::DeviceServiceServer;
 use chirpstack_api::api::fuota_service_server::FuotaServiceServer;
 use chirpstack_api::api::gateway_service_server::GatewayServiceServer;
 use chirpstack_api::api::internal_service_server::InternalServiceServer;
+use chirpstack_api::api::join_server_route_service_server::JoinServerRouteServiceServer;
 use chirpstack_api::api::multicast_group_service_server::MulticastGroupServiceServer;
 use chirpstack_api::api::relay_service_server::RelayServiceServer;
+use chirpstack_api::api::roaming_service_server::RoamingServiceServer;
 use chirpstack_api::api::tenant_service_server::TenantServiceServer;
 use chirpstack_api::api::user_service_server::UserServiceServer;
 use chirpstack_api::stream as stream_pb;
@@ -44,12 +52,13 @@ use chirpstack_api::stream as stream_pb;
 use super::config;
 use crate::api::auth::validator;
 use crate::helpers::errors::PrintFullError;
-use crate::monitoring::prometheus;
+use crate::monitoring::{otel, prometheus, sla};
 use crate::stream;
 
 pub mod application;
 pub mod auth;
 pub mod backend;
+pub mod codec_library;
 pub mod device;
 pub mod device_profile;
 pub mod device_profile_template;
@@ -59,11 +68,14 @@ pub mod gateway;
 mod grpc_multiplex;
 pub mod helpers;
 pub mod internal;
+pub mod join_server_route;
 pub mod monitoring;
 pub mod multicast;
 pub mod oauth2;
 pub mod oidc;
 pub mod relay;
+pub mod roaming;
+pub mod scim;
 pub mod tenant;
 pub mod user;
 
@@ -107,24 +119,36 @@ pub async fn setup() -> Result<()> {
 
     info!(bind = %bind, "Setting up API interface");
 
-    let web = Router::new()
+    let mut web = Router::new()
         .route("/auth/oidc/login", get(oidc::login_handler))
         .route("/auth/oidc/callback", get(oidc::callback_handler))
         .route("/auth/oauth2/login", get(oauth2::login_handler))
-        .route("/auth/oauth2/callback", get(oauth2::callback_handler))
+        .route("/auth/oauth2/callback", get(oauth2::callback_handler));
+    if conf.user_authentication.scim.enabled {
+        web = web.nest("/scim/v2", scim::router());
+    }
+
+    let web = web
         .fallback(service_static_handler)
         .into_service()
         .map_response(|r| r.map(tonic::body::boxed));
 
-    let grpc = TonicServer::builder()
+    let mut grpc = TonicServer::builder();
+    if !conf.api.tls_cert.is_empty() || !conf.api.tls_key.is_empty() {
+        grpc = grpc.tls_config(get_tls_config()?)?;
+    }
+
+    let grpc = grpc
         .accept_http1(true)
         .layer(
             TraceLayer::new_for_grpc()
                 .make_span_with(|req: &Request<_>| {
-                    tracing::info_span!(
+                    let span = tracing::info_span!(
                     "gRPC",
                     uri = %req.uri().path(),
-                    )
+                    );
+                    span.set_parent(otel::extract_context(req.headers()));
+                    span
                 })
                 .on_request(OnRequest {})
                 .on_response(OnResponse {}),
@@ -150,6 +174,10 @@ pub async fn setup() -> Result<()> {
             device_profile::DeviceProfile::new(validator::RequestValidator::new()),
             auth::auth_interceptor,
         ))
+        .add_service(CodecLibraryServiceServer::with_interceptor(
+            codec_library::CodecLibrary::new(validator::RequestValidator::new()),
+            auth::auth_interceptor,
+        ))
         .add_service(DeviceProfileTemplateServiceServer::with_interceptor(
             device_profile_template::DeviceProfileTemplate::new(validator::RequestValidator::new()),
             auth::auth_interceptor,
@@ -178,6 +206,14 @@ pub async fn setup() -> Result<()> {
             relay::Relay::new(validator::RequestValidator::new()),
             auth::auth_interceptor,
         ))
+        .add_service(RoamingServiceServer::with_interceptor(
+            roaming::Roaming::new(validator::RequestValidator::new()),
+            auth::auth_interceptor,
+        ))
+        .add_service(JoinServerRouteServiceServer::with_interceptor(
+            join_server_route::JoinServerRoute::new(validator::RequestValidator::new()),
+            auth::auth_interceptor,
+        ))
         .add_service(FuotaServiceServer::with_interceptor(
             fuota::Fuota::new(validator::RequestValidator::new()),
             auth::auth_interceptor,
@@ -197,6 +233,25 @@ pub async fn setup() -> Result<()> {
     Ok(())
 }
 
+fn get_tls_config() -> Result<ServerTlsConfig> {
+    let conf = config::get();
+
+    let cert = fs::read(&conf.api.tls_cert).context("Read API TLS certificate")?;
+    let key = fs::read(&conf.api.tls_key).context("Read API TLS key")?;
+    let mut tls_config = ServerTlsConfig::new().identity(TonicIdentity::from_pem(cert, key));
+
+    // Client-certificates are optional, such that clients can still authenticate using a
+    // bearer token.
+    if !conf.api.ca_cert.is_empty() {
+        let ca_cert = fs::read(&conf.api.ca_cert).context("Read API CA certificate")?;
+        tls_config = tls_config
+            .client_ca_root(TonicCertificate::from_pem(ca_cert))
+            .client_auth_optional(true);
+    }
+
+    Ok(tls_config)
+}
+
 async fn service_static_handler(uri: Uri) -> impl IntoResponse {
     let mut path = {
         let mut chars = uri.path().chars();
@@ -352,11 +407,24 @@ where
                             .collect(),
                     };
 
+                    // Requests resulting in a server error count against the API availability.
+                    let api_success = !response.status().is_server_error()
+                        && !matches!(
+                            status_code,
+                            Code::Unknown
+                                | Code::DeadlineExceeded
+                                | Code::Internal
+                                | Code::Unavailable
+                                | Code::DataLoss
+                        );
+
                     task::spawn(async move {
                         if let Err(e) = stream::api_request::log_request(&req_log).await {
                             error!(error = %e.full(), "Log request error");
                         }
                     });
+
+                    sla::record_api_request(api_success);
                 }
                 Poll::Ready(result)
             }
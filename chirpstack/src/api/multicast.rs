@@ -12,7 +12,7 @@ use super::auth::validator;
 use super::error::ToStatus;
 use super::helpers::{self, FromProto, ToProto};
 use crate::downlink;
-use crate::storage::multicast;
+use crate::storage::{fields, multicast};
 
 pub struct MulticastGroup {
     validator: validator::RequestValidator,
@@ -46,7 +46,7 @@ impl MulticastGroupService for MulticastGroup {
             )
             .await?;
 
-        let mg = multicast::MulticastGroup {
+        let mut mg = multicast::MulticastGroup {
             application_id: app_id.into(),
             name: req_mg.name.clone(),
             region: req_mg.region().from_proto(),
@@ -70,8 +70,17 @@ impl MulticastGroupService for MulticastGroup {
                 req_mg.class_b_ping_slot_nb_k
             } as i16,
             class_c_scheduling_type: req_mg.class_c_scheduling_type().from_proto(),
+            geofence_latitude: req_mg.geofence_latitude,
+            geofence_longitude: req_mg.geofence_longitude,
+            geofence_radius: req_mg.geofence_radius as i32,
+            gateway_tags: fields::KeyValue::new(req_mg.gateway_tags.clone()),
             ..Default::default()
         };
+        if req_mg.calculate_parameters {
+            multicast::set_parameters(&mut mg)
+                .await
+                .map_err(|e| e.status())?;
+        }
         let mg = multicast::create(mg).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(api::CreateMulticastGroupResponse {
@@ -124,6 +133,11 @@ impl MulticastGroupService for MulticastGroup {
                 class_b_ping_slot_period: (1 << (mg.class_b_ping_slot_nb_k as u32)) * 32,
                 class_b_ping_slot_nb_k: mg.class_b_ping_slot_nb_k as u32,
                 class_c_scheduling_type: mg.class_c_scheduling_type.to_proto().into(),
+                calculate_parameters: false,
+                geofence_latitude: mg.geofence_latitude,
+                geofence_longitude: mg.geofence_longitude,
+                geofence_radius: mg.geofence_radius as u32,
+                gateway_tags: mg.gateway_tags.into_hashmap(),
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&mg.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&mg.updated_at)),
@@ -153,7 +167,7 @@ impl MulticastGroupService for MulticastGroup {
             )
             .await?;
 
-        let _ = multicast::update(multicast::MulticastGroup {
+        let mut mg = multicast::MulticastGroup {
             id: mg_id.into(),
             name: req_mg.name.clone(),
             region: req_mg.region().from_proto(),
@@ -177,10 +191,18 @@ impl MulticastGroupService for MulticastGroup {
                 req_mg.class_b_ping_slot_nb_k
             } as i16,
             class_c_scheduling_type: req_mg.class_c_scheduling_type().from_proto(),
+            geofence_latitude: req_mg.geofence_latitude,
+            geofence_longitude: req_mg.geofence_longitude,
+            geofence_radius: req_mg.geofence_radius as i32,
+            gateway_tags: fields::KeyValue::new(req_mg.gateway_tags.clone()),
             ..Default::default()
-        })
-        .await
-        .map_err(|e| e.status())?;
+        };
+        if req_mg.calculate_parameters {
+            multicast::set_parameters(&mut mg)
+                .await
+                .map_err(|e| e.status())?;
+        }
+        let _ = multicast::update(mg).await.map_err(|e| e.status())?;
 
         let mut resp = Response::new(());
         resp.metadata_mut()
@@ -508,6 +530,8 @@ impl MulticastGroupService for MulticastGroup {
 
 #[cfg(test)]
 pub mod test {
+    use std::collections::HashMap;
+
     use super::*;
     use crate::api::auth::validator::RequestValidator;
     use crate::api::auth::AuthID;
@@ -639,6 +663,11 @@ pub mod test {
                 class_b_ping_slot_nb_k: 1,
                 class_b_ping_slot_period: 64,
                 class_c_scheduling_type: api::MulticastGroupSchedulingType::GpsTime.into(),
+                calculate_parameters: false,
+                geofence_latitude: 0.0,
+                geofence_longitude: 0.0,
+                geofence_radius: 0,
+                gateway_tags: HashMap::new(),
             }),
             get_resp.get_ref().multicast_group
         );
@@ -662,6 +691,13 @@ pub mod test {
                     class_b_ping_slot_nb_k: 2,
                     class_b_ping_slot_period: 0,
                     class_c_scheduling_type: api::MulticastGroupSchedulingType::Delay.into(),
+                    calculate_parameters: false,
+                    geofence_latitude: 52.3676,
+                    geofence_longitude: 4.9041,
+                    geofence_radius: 50000,
+                    gateway_tags: [("region".to_string(), "north".to_string())]
+                        .into_iter()
+                        .collect(),
                 }),
             },
         );
@@ -691,6 +727,13 @@ pub mod test {
                 class_b_ping_slot_nb_k: 2,
                 class_b_ping_slot_period: 128,
                 class_c_scheduling_type: api::MulticastGroupSchedulingType::Delay.into(),
+                calculate_parameters: false,
+                geofence_latitude: 52.3676,
+                geofence_longitude: 4.9041,
+                geofence_radius: 50000,
+                gateway_tags: [("region".to_string(), "north".to_string())]
+                    .into_iter()
+                    .collect(),
             }),
             get_resp.get_ref().multicast_group
         );
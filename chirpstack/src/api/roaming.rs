@@ -0,0 +1,882 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use chirpstack_api::api::roaming_service_server::RoamingService;
+use chirpstack_api::{api, internal};
+use lrwn::{DevAddr, NetID, EUI64};
+
+use super::auth::validator;
+use super::error::ToStatus;
+use super::helpers::{self, ToProto};
+use crate::storage::{passive_roaming, roaming_agreement, roaming_usage};
+
+// Number of records to fetch per query when exporting the usage records.
+const EXPORT_PAGE_SIZE: i64 = 1000;
+
+pub struct Roaming {
+    validator: validator::RequestValidator,
+}
+
+impl Roaming {
+    pub fn new(validator: validator::RequestValidator) -> Self {
+        Roaming { validator }
+    }
+}
+
+#[tonic::async_trait]
+impl RoamingService for Roaming {
+    async fn list_usage(
+        &self,
+        request: Request<api::ListRoamingUsageRequest>,
+    ) -> Result<Response<api::ListRoamingUsageResponse>, Status> {
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingUsageAccess::new(validator::Flag::List),
+            )
+            .await?;
+
+        let req = request.get_ref();
+        let filters = get_filters(&req.net_id, &req.dev_eui, &req.start, &req.end)?;
+
+        let count = roaming_usage::get_count(&filters)
+            .await
+            .map_err(|e| e.status())?;
+        let items = roaming_usage::list(req.limit as i64, req.offset as i64, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        Ok(Response::new(api::ListRoamingUsageResponse {
+            total_count: count as u32,
+            result: items.into_iter().map(usage_to_proto).collect(),
+        }))
+    }
+
+    async fn export_usage(
+        &self,
+        request: Request<api::ExportRoamingUsageRequest>,
+    ) -> Result<Response<api::ExportRoamingUsageResponse>, Status> {
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingUsageAccess::new(validator::Flag::List),
+            )
+            .await?;
+
+        let req = request.get_ref();
+        let filters = get_filters(&req.net_id, &req.dev_eui, &req.start, &req.end)?;
+
+        let mut csv = String::from("day,net_id,role,dev_eui,dev_addr,uplink_count,downlink_count,uplink_airtime_ms,downlink_airtime_ms\n");
+        let mut offset = 0;
+
+        loop {
+            let items = roaming_usage::list(EXPORT_PAGE_SIZE, offset, &filters)
+                .await
+                .map_err(|e| e.status())?;
+
+            for item in &items {
+                writeln!(
+                    csv,
+                    "{},{},{},{},{},{},{},{},{}",
+                    item.day,
+                    item.net_id,
+                    item.role,
+                    item.dev_eui,
+                    item.dev_addr,
+                    item.uplink_count,
+                    item.downlink_count,
+                    item.uplink_airtime_ms,
+                    item.downlink_airtime_ms
+                )
+                .map_err(|e| Status::internal(e.to_string()))?;
+            }
+
+            if (items.len() as i64) < EXPORT_PAGE_SIZE {
+                break;
+            }
+            offset += EXPORT_PAGE_SIZE;
+        }
+
+        Ok(Response::new(api::ExportRoamingUsageResponse {
+            csv: csv.into_bytes(),
+        }))
+    }
+
+    async fn create_agreement(
+        &self,
+        request: Request<api::CreateRoamingAgreementRequest>,
+    ) -> Result<Response<api::CreateRoamingAgreementResponse>, Status> {
+        let req_ra = match &request.get_ref().roaming_agreement {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("roaming_agreement is missing"));
+            }
+        };
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingAgreementsAccess::new(validator::Flag::Create),
+            )
+            .await?;
+
+        let ra = roaming_agreement::create(agreement_from_proto(req_ra))
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::CreateRoamingAgreementResponse {
+            id: ra.id.to_string(),
+        });
+        resp.metadata_mut().insert(
+            "x-log-roaming_agreement_id",
+            ra.id.to_string().parse().unwrap(),
+        );
+
+        Ok(resp)
+    }
+
+    async fn get_agreement(
+        &self,
+        request: Request<api::GetRoamingAgreementRequest>,
+    ) -> Result<Response<api::GetRoamingAgreementResponse>, Status> {
+        let req = request.get_ref();
+        let ra_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingAgreementAccess::new(validator::Flag::Read),
+            )
+            .await?;
+
+        let ra = roaming_agreement::get(&ra_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetRoamingAgreementResponse {
+            created_at: Some(helpers::datetime_to_prost_timestamp(&ra.created_at)),
+            updated_at: Some(helpers::datetime_to_prost_timestamp(&ra.updated_at)),
+            roaming_agreement: Some(agreement_to_proto(ra)),
+        });
+        resp.metadata_mut()
+            .insert("x-log-roaming_agreement_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn update_agreement(
+        &self,
+        request: Request<api::UpdateRoamingAgreementRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_ra = match &request.get_ref().roaming_agreement {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("roaming_agreement is missing"));
+            }
+        };
+        let ra_id = Uuid::from_str(&req_ra.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingAgreementAccess::new(validator::Flag::Update),
+            )
+            .await?;
+
+        // The secrets are not returned by the get method. When left empty, the stored values
+        // are kept.
+        let ra = roaming_agreement::get(&ra_id)
+            .await
+            .map_err(|e| e.status())?;
+        let mut ra_new = agreement_from_proto(req_ra);
+        if ra_new.authorization_header.is_empty() {
+            ra_new.authorization_header = ra.authorization_header;
+        }
+        if ra_new.oauth2_client_secret.is_empty() {
+            ra_new.oauth2_client_secret = ra.oauth2_client_secret;
+        }
+        if ra_new.api_key.is_empty() {
+            ra_new.api_key = ra.api_key;
+        }
+
+        let _ = roaming_agreement::update(roaming_agreement::RoamingAgreement {
+            id: ra_id.into(),
+            ..ra_new
+        })
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-roaming_agreement_id", req_ra.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn delete_agreement(
+        &self,
+        request: Request<api::DeleteRoamingAgreementRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let ra_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingAgreementAccess::new(validator::Flag::Delete),
+            )
+            .await?;
+
+        roaming_agreement::delete(&ra_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-roaming_agreement_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn list_agreements(
+        &self,
+        request: Request<api::ListRoamingAgreementsRequest>,
+    ) -> Result<Response<api::ListRoamingAgreementsResponse>, Status> {
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateRoamingAgreementsAccess::new(validator::Flag::List),
+            )
+            .await?;
+
+        let req = request.get_ref();
+        let filters = roaming_agreement::Filters {
+            search: if req.search.is_empty() {
+                None
+            } else {
+                Some(req.search.to_string())
+            },
+        };
+
+        let count = roaming_agreement::get_count(&filters)
+            .await
+            .map_err(|e| e.status())?;
+        let items = roaming_agreement::list(req.limit as i64, req.offset as i64, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        Ok(Response::new(api::ListRoamingAgreementsResponse {
+            total_count: count as u32,
+            result: items
+                .iter()
+                .map(|ra| api::RoamingAgreementListItem {
+                    id: ra.id.to_string(),
+                    created_at: Some(helpers::datetime_to_prost_timestamp(&ra.created_at)),
+                    updated_at: Some(helpers::datetime_to_prost_timestamp(&ra.updated_at)),
+                    name: ra.name.clone(),
+                    net_id: ra.net_id.clone(),
+                    server: ra.server.clone(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn list_passive_roaming_sessions(
+        &self,
+        request: Request<api::ListPassiveRoamingSessionsRequest>,
+    ) -> Result<Response<api::ListPassiveRoamingSessionsResponse>, Status> {
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidatePassiveRoamingSessionsAccess::new(validator::Flag::List),
+            )
+            .await?;
+
+        let req = request.get_ref();
+        let filters = passive_roaming::Filters {
+            net_id: if req.net_id.is_empty() {
+                None
+            } else {
+                Some(NetID::from_str(&req.net_id).map_err(|e| e.status())?)
+            },
+            dev_eui: if req.dev_eui.is_empty() {
+                None
+            } else {
+                Some(EUI64::from_str(&req.dev_eui).map_err(|e| e.status())?)
+            },
+        };
+
+        let count = passive_roaming::get_count(&filters)
+            .await
+            .map_err(|e| e.status())?;
+        let items = passive_roaming::list(req.limit as usize, req.offset as usize, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        Ok(Response::new(api::ListPassiveRoamingSessionsResponse {
+            total_count: count as u32,
+            result: items
+                .into_iter()
+                .map(session_to_proto)
+                .collect::<Result<_, _>>()?,
+        }))
+    }
+
+    async fn delete_passive_roaming_session(
+        &self,
+        request: Request<api::DeletePassiveRoamingSessionRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let sess_id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidatePassiveRoamingSessionsAccess::new(validator::Flag::Delete),
+            )
+            .await?;
+
+        // Make sure the session exists.
+        let _ = passive_roaming::get(sess_id)
+            .await
+            .map_err(|e| e.status())?;
+        passive_roaming::delete(sess_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-passive_roaming_session_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+}
+
+fn agreement_from_proto(ra: &api::RoamingAgreement) -> roaming_agreement::RoamingAgreement {
+    roaming_agreement::RoamingAgreement {
+        name: ra.name.clone(),
+        net_id: ra.net_id.clone(),
+        server: ra.server.clone(),
+        use_target_role_suffix: ra.use_target_role_suffix,
+        async_timeout: ra.async_timeout as i32,
+        passive_roaming_lifetime: ra.passive_roaming_lifetime as i32,
+        passive_roaming_kek_label: ra.passive_roaming_kek_label.clone(),
+        passive_roaming_validate_mic: ra.passive_roaming_validate_mic,
+        handover_roaming_lifetime: ra.handover_roaming_lifetime as i32,
+        handover_roaming_kek_label: ra.handover_roaming_kek_label.clone(),
+        ca_cert: ra.ca_cert.clone(),
+        tls_cert: ra.tls_cert.clone(),
+        tls_key: ra.tls_key.clone(),
+        authorization_header: ra.authorization_header.clone(),
+        max_requests_per_second: ra.max_requests_per_second as i32,
+        oauth2_token_url: ra.oauth2_token_url.clone(),
+        oauth2_client_id: ra.oauth2_client_id.clone(),
+        oauth2_client_secret: ra.oauth2_client_secret.clone(),
+        oauth2_scope: ra.oauth2_scope.clone(),
+        api_key_header: ra.api_key_header.clone(),
+        api_key: ra.api_key.clone(),
+        jws_algorithm: ra.jws_algorithm.clone(),
+        jws_signing_key: ra.jws_signing_key.clone(),
+        jws_verification_key: ra.jws_verification_key.clone(),
+        use_handover_roaming: ra.use_handover_roaming,
+        ..Default::default()
+    }
+}
+
+fn agreement_to_proto(ra: roaming_agreement::RoamingAgreement) -> api::RoamingAgreement {
+    api::RoamingAgreement {
+        id: ra.id.to_string(),
+        name: ra.name,
+        net_id: ra.net_id,
+        server: ra.server,
+        use_target_role_suffix: ra.use_target_role_suffix,
+        async_timeout: ra.async_timeout as u32,
+        passive_roaming_lifetime: ra.passive_roaming_lifetime as u32,
+        passive_roaming_kek_label: ra.passive_roaming_kek_label,
+        passive_roaming_validate_mic: ra.passive_roaming_validate_mic,
+        handover_roaming_lifetime: ra.handover_roaming_lifetime as u32,
+        handover_roaming_kek_label: ra.handover_roaming_kek_label,
+        ca_cert: ra.ca_cert,
+        tls_cert: ra.tls_cert,
+        tls_key: ra.tls_key,
+        // Secrets are never returned.
+        authorization_header: "".into(),
+        max_requests_per_second: ra.max_requests_per_second as u32,
+        oauth2_token_url: ra.oauth2_token_url,
+        oauth2_client_id: ra.oauth2_client_id,
+        oauth2_client_secret: "".into(),
+        oauth2_scope: ra.oauth2_scope,
+        api_key_header: ra.api_key_header,
+        api_key: "".into(),
+        jws_algorithm: ra.jws_algorithm,
+        jws_signing_key: ra.jws_signing_key,
+        jws_verification_key: ra.jws_verification_key,
+        use_handover_roaming: ra.use_handover_roaming,
+    }
+}
+
+fn get_filters(
+    net_id: &str,
+    dev_eui: &str,
+    start: &Option<prost_types::Timestamp>,
+    end: &Option<prost_types::Timestamp>,
+) -> Result<roaming_usage::Filters, Status> {
+    Ok(roaming_usage::Filters {
+        net_id: if net_id.is_empty() {
+            None
+        } else {
+            Some(NetID::from_str(net_id).map_err(|e| e.status())?)
+        },
+        dev_eui: if dev_eui.is_empty() {
+            None
+        } else {
+            Some(EUI64::from_str(dev_eui).map_err(|e| e.status())?)
+        },
+        start: match start {
+            Some(v) => Some(timestamp_to_date(v)?),
+            None => None,
+        },
+        end: match end {
+            Some(v) => Some(timestamp_to_date(v)?),
+            None => None,
+        },
+    })
+}
+
+fn timestamp_to_date(ts: &prost_types::Timestamp) -> Result<NaiveDate, Status> {
+    let ts: DateTime<Utc> = SystemTime::try_from(*ts).map_err(|e| e.status())?.into();
+    Ok(ts.date_naive())
+}
+
+fn usage_to_proto(ru: roaming_usage::RoamingUsage) -> api::RoamingUsage {
+    api::RoamingUsage {
+        day: ru.day.to_string(),
+        net_id: ru.net_id,
+        role: ru.role.to_proto().into(),
+        dev_eui: ru.dev_eui.to_string(),
+        dev_addr: ru.dev_addr.to_string(),
+        uplink_count: ru.uplink_count as u64,
+        downlink_count: ru.downlink_count as u64,
+        uplink_airtime_ms: ru.uplink_airtime_ms as u64,
+        downlink_airtime_ms: ru.downlink_airtime_ms as u64,
+        updated_at: Some(helpers::datetime_to_prost_timestamp(&ru.updated_at)),
+    }
+}
+
+fn session_to_proto(
+    ds: internal::PassiveRoamingDeviceSession,
+) -> Result<api::PassiveRoamingSession, Status> {
+    Ok(api::PassiveRoamingSession {
+        id: Uuid::from_slice(&ds.session_id)
+            .map_err(|e| e.status())?
+            .to_string(),
+        net_id: NetID::from_slice(&ds.net_id)
+            .map_err(|e| e.status())?
+            .to_string(),
+        dev_addr: DevAddr::from_slice(&ds.dev_addr)
+            .map_err(|e| e.status())?
+            .to_string(),
+        dev_eui: if ds.dev_eui.is_empty() {
+            EUI64::default().to_string()
+        } else {
+            EUI64::from_slice(&ds.dev_eui)
+                .map_err(|e| e.status())?
+                .to_string()
+        },
+        lorawan_1_1: ds.lorawan_1_1,
+        validate_mic: ds.validate_mic,
+        created_at: ds.created_at.as_ref().map(|v| prost_types::Timestamp {
+            seconds: v.seconds,
+            nanos: v.nanos,
+        }),
+        lifetime: ds.lifetime.as_ref().map(|v| prost_types::Timestamp {
+            seconds: v.seconds,
+            nanos: v.nanos,
+        }),
+        f_cnt_up: ds.f_cnt_up,
+        uplink_count: ds.uplink_count,
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::api::auth::validator::RequestValidator;
+    use crate::api::auth::AuthID;
+    use crate::backend::roaming;
+    use crate::storage::{fields, user};
+    use crate::test;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_roaming() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2025, 4, 7).unwrap();
+        for net_id in ["010203", "030201"] {
+            roaming_usage::increment(roaming_usage::RoamingUsage {
+                day,
+                net_id: net_id.into(),
+                role: fields::RoamingRole::SNS,
+                dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+                dev_addr: DevAddr::from_be_bytes([1, 2, 3, 4]),
+                uplink_count: 2,
+                uplink_airtime_ms: 100,
+                downlink_count: 1,
+                downlink_airtime_ms: 60,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        // setup the api
+        let service = Roaming::new(RequestValidator::new());
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListRoamingUsageRequest {
+                limit: 10,
+                net_id: "010203".into(),
+                ..Default::default()
+            },
+        );
+        let list_resp = service.list_usage(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(1, list_resp.total_count);
+        assert_eq!(1, list_resp.result.len());
+        assert_eq!(
+            api::RoamingUsage {
+                day: "2025-04-07".into(),
+                net_id: "010203".into(),
+                role: api::RoamingRole::Sns.into(),
+                dev_eui: "0102030405060708".into(),
+                dev_addr: "01020304".into(),
+                uplink_count: 2,
+                downlink_count: 1,
+                uplink_airtime_ms: 100,
+                downlink_airtime_ms: 60,
+                updated_at: list_resp.result[0].updated_at,
+            },
+            list_resp.result[0]
+        );
+
+        // export
+        let export_req = get_request(&u.id, api::ExportRoamingUsageRequest::default());
+        let export_resp = service.export_usage(export_req).await.unwrap();
+        assert_eq!(
+            "day,net_id,role,dev_eui,dev_addr,uplink_count,downlink_count,uplink_airtime_ms,downlink_airtime_ms\n2025-04-07,010203,SNS,0102030405060708,01020304,2,1,100,60\n2025-04-07,030201,SNS,0102030405060708,01020304,2,1,100,60\n",
+            String::from_utf8(export_resp.get_ref().csv.clone()).unwrap()
+        );
+
+        // invalid NetID
+        let list_req = get_request(
+            &u.id,
+            api::ListRoamingUsageRequest {
+                net_id: "foo".into(),
+                ..Default::default()
+            },
+        );
+        assert!(service.list_usage(list_req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_roaming_agreement() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // setup the api
+        let service = Roaming::new(RequestValidator::new());
+
+        // create
+        let create_req = get_request(
+            &u.id,
+            api::CreateRoamingAgreementRequest {
+                roaming_agreement: Some(api::RoamingAgreement {
+                    name: "partner".into(),
+                    net_id: "010203".into(),
+                    server: "https://partner.example.com".into(),
+                    async_timeout: 1,
+                    passive_roaming_lifetime: 60,
+                    authorization_header: "secret".into(),
+                    oauth2_client_secret: "client-secret".into(),
+                    api_key: "api-key".into(),
+                    ..Default::default()
+                }),
+            },
+        );
+        let create_resp = service.create_agreement(create_req).await.unwrap();
+        let create_resp = create_resp.get_ref();
+
+        // the roaming client has been configured
+        roaming::reload().await.unwrap();
+        let client = roaming::get(&NetID::from_be_bytes([1, 2, 3]))
+            .await
+            .unwrap();
+        assert!(client.is_async());
+        assert_eq!(
+            std::time::Duration::from_secs(60),
+            roaming::get_passive_roaming_lifetime(NetID::from_be_bytes([1, 2, 3])).unwrap()
+        );
+
+        // get
+        let get_req = get_request(
+            &u.id,
+            api::GetRoamingAgreementRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let get_resp = service.get_agreement(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::RoamingAgreement {
+                id: create_resp.id.clone(),
+                name: "partner".into(),
+                net_id: "010203".into(),
+                server: "https://partner.example.com".into(),
+                async_timeout: 1,
+                passive_roaming_lifetime: 60,
+                ..Default::default()
+            }),
+            get_resp.get_ref().roaming_agreement
+        );
+
+        // update
+        let up_req = get_request(
+            &u.id,
+            api::UpdateRoamingAgreementRequest {
+                roaming_agreement: Some(api::RoamingAgreement {
+                    id: create_resp.id.clone(),
+                    name: "partner-updated".into(),
+                    net_id: "010203".into(),
+                    server: "https://partner.example.com".into(),
+                    passive_roaming_lifetime: 120,
+                    ..Default::default()
+                }),
+            },
+        );
+        let _ = service.update_agreement(up_req).await.unwrap();
+
+        // the secrets have been kept
+        let ra = roaming_agreement::get(&Uuid::from_str(&create_resp.id).unwrap())
+            .await
+            .unwrap();
+        assert_eq!("secret", ra.authorization_header);
+        assert_eq!("client-secret", ra.oauth2_client_secret);
+        assert_eq!("api-key", ra.api_key);
+
+        // the roaming client has been re-configured
+        roaming::reload().await.unwrap();
+        let client = roaming::get(&NetID::from_be_bytes([1, 2, 3]))
+            .await
+            .unwrap();
+        assert!(!client.is_async());
+        assert_eq!(
+            std::time::Duration::from_secs(120),
+            roaming::get_passive_roaming_lifetime(NetID::from_be_bytes([1, 2, 3])).unwrap()
+        );
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListRoamingAgreementsRequest {
+                limit: 10,
+                ..Default::default()
+            },
+        );
+        let list_resp = service.list_agreements(list_req).await.unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(1, list_resp.total_count);
+        assert_eq!(1, list_resp.result.len());
+        assert_eq!("partner-updated", list_resp.result[0].name);
+        assert_eq!("010203", list_resp.result[0].net_id);
+
+        // delete
+        let del_req = get_request(
+            &u.id,
+            api::DeleteRoamingAgreementRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let _ = service.delete_agreement(del_req).await.unwrap();
+
+        let del_req = get_request(
+            &u.id,
+            api::DeleteRoamingAgreementRequest {
+                id: create_resp.id.clone(),
+            },
+        );
+        let del_resp = service.delete_agreement(del_req).await;
+        assert!(del_resp.is_err());
+
+        // the roaming client has been removed
+        roaming::reload().await.unwrap();
+        assert!(roaming::get(&NetID::from_be_bytes([1, 2, 3]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_passive_roaming_session() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::create(user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let sess_id = Uuid::new_v4();
+        let lifetime = Utc::now() + Duration::try_minutes(5).unwrap();
+        let ds = internal::PassiveRoamingDeviceSession {
+            session_id: sess_id.as_bytes().to_vec(),
+            net_id: vec![1, 2, 3],
+            dev_addr: vec![1, 2, 3, 4],
+            dev_eui: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            lifetime: Some(lifetime.into()),
+            f_cnt_up: 10,
+            uplink_count: 3,
+            ..Default::default()
+        };
+        passive_roaming::save(&ds).await.unwrap();
+
+        // setup the api
+        let service = Roaming::new(RequestValidator::new());
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListPassiveRoamingSessionsRequest {
+                limit: 10,
+                net_id: "010203".into(),
+                ..Default::default()
+            },
+        );
+        let list_resp = service
+            .list_passive_roaming_sessions(list_req)
+            .await
+            .unwrap();
+        let list_resp = list_resp.get_ref();
+        assert_eq!(1, list_resp.total_count);
+        assert_eq!(
+            vec![api::PassiveRoamingSession {
+                id: sess_id.to_string(),
+                net_id: "010203".into(),
+                dev_addr: "01020304".into(),
+                dev_eui: "0102030405060708".into(),
+                lifetime: Some(prost_types::Timestamp {
+                    seconds: lifetime.timestamp(),
+                    nanos: lifetime.timestamp_subsec_nanos() as i32,
+                }),
+                f_cnt_up: 10,
+                uplink_count: 3,
+                ..Default::default()
+            }],
+            list_resp.result
+        );
+
+        // list with other NetID
+        let list_req = get_request(
+            &u.id,
+            api::ListPassiveRoamingSessionsRequest {
+                limit: 10,
+                net_id: "030201".into(),
+                ..Default::default()
+            },
+        );
+        let list_resp = service
+            .list_passive_roaming_sessions(list_req)
+            .await
+            .unwrap();
+        assert_eq!(0, list_resp.get_ref().total_count);
+
+        // delete
+        let del_req = get_request(
+            &u.id,
+            api::DeletePassiveRoamingSessionRequest {
+                id: sess_id.to_string(),
+            },
+        );
+        let _ = service
+            .delete_passive_roaming_session(del_req)
+            .await
+            .unwrap();
+
+        let del_req = get_request(
+            &u.id,
+            api::DeletePassiveRoamingSessionRequest {
+                id: sess_id.to_string(),
+            },
+        );
+        assert!(service
+            .delete_passive_roaming_session(del_req)
+            .await
+            .is_err());
+
+        // the DevEUI pointer has been removed
+        assert!(
+            passive_roaming::get_session_ids_for_dev_eui(EUI64::from_be_bytes([
+                1, 2, 3, 4, 5, 6, 7, 8
+            ]))
+            .await
+            .unwrap()
+            .is_empty()
+        );
+
+        // updating a terminated session does not re-create it (e.g. in case of an uplink
+        // which was being handled while the session was deleted)
+        passive_roaming::update(&ds).await.unwrap();
+        assert!(passive_roaming::get(sess_id).await.is_err());
+
+        // list
+        let list_req = get_request(
+            &u.id,
+            api::ListPassiveRoamingSessionsRequest {
+                limit: 10,
+                ..Default::default()
+            },
+        );
+        let list_resp = service
+            .list_passive_roaming_sessions(list_req)
+            .await
+            .unwrap();
+        assert_eq!(0, list_resp.get_ref().total_count);
+    }
+
+    fn get_request<T>(user_id: &Uuid, req: T) -> Request<T> {
+        let mut req = Request::new(req);
+        req.extensions_mut().insert(AuthID::User(*user_id));
+        req
+    }
+}
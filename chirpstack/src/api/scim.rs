@@ -0,0 +1,887 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use axum::{
+    extract::{Path, Query},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use http::{header, HeaderMap, StatusCode};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::error;
+use uuid::Uuid;
+
+use super::auth::{claims, session};
+use crate::config;
+use crate::storage::{api_key, error::Error, tenant, user};
+
+const SCHEMA_USER: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const SCHEMA_GROUP: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const SCHEMA_LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const SCHEMA_ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SCHEMA_SERVICE_PROVIDER_CONFIG: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+const CONTENT_TYPE: &str = "application/scim+json";
+const BASE_PATH: &str = "/scim/v2";
+const MAX_COUNT: i64 = 1000;
+
+lazy_static! {
+    static ref FILTER_REGEX: Regex = Regex::new(r#"^\s*(\w+)\s+(?i:eq)\s+"([^"]*)"\s*$"#).unwrap();
+    static ref MEMBER_PATH_REGEX: Regex =
+        Regex::new(r#"^(?i:members)\[\s*(?i:value)\s+(?i:eq)\s+"([^"]*)"\s*\]$"#).unwrap();
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/ServiceProviderConfig",
+            get(service_provider_config_handler),
+        )
+        .route("/Users", get(list_users_handler).post(create_user_handler))
+        .route(
+            "/Users/{id}",
+            get(get_user_handler)
+                .put(replace_user_handler)
+                .patch(patch_user_handler)
+                .delete(delete_user_handler),
+        )
+        .route("/Groups", get(list_groups_handler))
+        .route(
+            "/Groups/{id}",
+            get(get_group_handler).patch(patch_group_handler),
+        )
+}
+
+#[derive(Debug)]
+pub struct ScimError {
+    status: StatusCode,
+    scim_type: Option<&'static str>,
+    detail: String,
+}
+
+impl ScimError {
+    fn new(status: StatusCode, detail: &str) -> Self {
+        ScimError {
+            status,
+            scim_type: None,
+            detail: detail.to_string(),
+        }
+    }
+
+    fn bad_request(scim_type: &'static str, detail: &str) -> Self {
+        ScimError {
+            status: StatusCode::BAD_REQUEST,
+            scim_type: Some(scim_type),
+            detail: detail.to_string(),
+        }
+    }
+}
+
+impl From<Error> for ScimError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::NotFound(_) => ScimError::new(StatusCode::NOT_FOUND, "Resource not found"),
+            Error::AlreadyExists(_) => ScimError {
+                status: StatusCode::CONFLICT,
+                scim_type: Some("uniqueness"),
+                detail: e.to_string(),
+            },
+            Error::InvalidEmail | Error::Validation(_) => {
+                ScimError::bad_request("invalidValue", &e.to_string())
+            }
+            _ => {
+                error!(error = %e, "SCIM request error");
+                ScimError::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal error")
+            }
+        }
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({
+            "schemas": [SCHEMA_ERROR],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = scim_type.into();
+        }
+
+        scim_response(self.status, &body)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub user_name: String,
+    #[serde(default = "default_true")]
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<ScimEmail>,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+impl From<&user::User> for ScimUser {
+    fn from(u: &user::User) -> Self {
+        ScimUser {
+            schemas: vec![SCHEMA_USER.into()],
+            id: u.id.to_string(),
+            user_name: u.email.clone(),
+            active: u.is_active,
+            emails: vec![ScimEmail {
+                value: u.email.clone(),
+                primary: true,
+            }],
+            meta: Some(ScimMeta {
+                resource_type: "User".into(),
+                created: u.created_at,
+                last_modified: u.updated_at,
+                location: format!("{}/Users/{}", BASE_PATH, u.id),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    pub schemas: Vec<String>,
+    pub id: String,
+    pub display_name: String,
+    pub members: Vec<ScimMember>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ScimMember {
+    pub value: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub display: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: String,
+    pub created: DateTime<Utc>,
+    pub last_modified: DateTime<Utc>,
+    pub location: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T> {
+    pub schemas: Vec<String>,
+    pub total_results: i64,
+    pub start_index: i64,
+    pub items_per_page: i64,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ListResponse<T> {
+    fn new(total_results: i64, start_index: i64, resources: Vec<T>) -> Self {
+        ListResponse {
+            schemas: vec![SCHEMA_LIST_RESPONSE.into()],
+            total_results,
+            start_index,
+            items_per_page: resources.len() as i64,
+            resources,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PatchRequest {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PatchOperation {
+    pub op: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListArgs {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl ListArgs {
+    // Returns the (1-based) start index and the number of items to return.
+    fn pagination(&self) -> (i64, i64) {
+        (
+            self.start_index.unwrap_or(1).max(1),
+            self.count.unwrap_or(100).clamp(0, MAX_COUNT),
+        )
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn scim_response<T: Serialize>(status: StatusCode, body: &T) -> Response {
+    (status, [(header::CONTENT_TYPE, CONTENT_TYPE)], Json(body)).into_response()
+}
+
+// The identity provider must authenticate using an admin API key.
+async fn authenticate(headers: &HeaderMap) -> Result<(), ScimError> {
+    let conf = config::get();
+    let unauthenticated = || ScimError::new(StatusCode::UNAUTHORIZED, "Invalid bearer token");
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(unauthenticated)?;
+    let claim = claims::AuthClaim::decode(token, conf.api.secret.as_ref())
+        .map_err(|_| unauthenticated())?;
+    if claim.typ != "key" {
+        return Err(unauthenticated());
+    }
+    let id = Uuid::from_str(&claim.sub).map_err(|_| unauthenticated())?;
+
+    let key = match api_key::get(&id).await {
+        Ok(v) => v,
+        Err(Error::NotFound(_)) => return Err(unauthenticated()),
+        Err(e) => return Err(e.into()),
+    };
+    if !key.is_admin {
+        return Err(ScimError::new(
+            StatusCode::FORBIDDEN,
+            "SCIM requires an admin API key",
+        ));
+    }
+
+    Ok(())
+}
+
+fn parse_id(id: &str) -> Result<Uuid, ScimError> {
+    Uuid::from_str(id).map_err(|_| ScimError::new(StatusCode::NOT_FOUND, "Resource not found"))
+}
+
+// Only the 'eq' operator is supported, which covers the filters used by identity providers
+// for matching existing users and groups.
+fn parse_filter(filter: &str) -> Result<(String, String), ScimError> {
+    let caps = FILTER_REGEX.captures(filter).ok_or_else(|| {
+        ScimError::bad_request(
+            "invalidFilter",
+            "Only 'attribute eq \"value\"' is supported",
+        )
+    })?;
+    Ok((caps[1].to_lowercase(), caps[2].to_string()))
+}
+
+fn parse_bool(v: &Value) -> Result<bool, ScimError> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::bad_request(
+            "invalidValue",
+            "active must be a boolean",
+        )),
+    }
+}
+
+fn parse_string(v: &Value) -> Result<String, ScimError> {
+    v.as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| ScimError::bad_request("invalidValue", "Value must be a string"))
+}
+
+fn parse_member_ids(v: &Option<Value>) -> Result<Vec<Uuid>, ScimError> {
+    let members: Vec<ScimMember> = match v {
+        Some(v) => serde_json::from_value(v.clone())
+            .map_err(|_| ScimError::bad_request("invalidValue", "Invalid members value"))?,
+        None => vec![],
+    };
+
+    members
+        .iter()
+        .map(|m| {
+            Uuid::from_str(&m.value)
+                .map_err(|_| ScimError::bad_request("invalidValue", "Invalid member value"))
+        })
+        .collect()
+}
+
+pub async fn service_provider_config_handler(headers: HeaderMap) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    Ok(scim_response(
+        StatusCode::OK,
+        &serde_json::json!({
+            "schemas": [SCHEMA_SERVICE_PROVIDER_CONFIG],
+            "patch": {"supported": true},
+            "bulk": {"supported": false, "maxOperations": 0, "maxPayloadSize": 0},
+            "filter": {"supported": true, "maxResults": MAX_COUNT},
+            "changePassword": {"supported": false},
+            "sort": {"supported": false},
+            "etag": {"supported": false},
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "Authentication using a ChirpStack admin API key",
+                "primary": true,
+            }],
+        }),
+    ))
+}
+
+pub async fn list_users_handler(
+    headers: HeaderMap,
+    Query(args): Query<ListArgs>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+    let (start_index, count) = args.pagination();
+
+    let resp = match &args.filter {
+        Some(filter) => {
+            let (attr, value) = parse_filter(filter)?;
+            if attr != "username" {
+                return Err(ScimError::bad_request(
+                    "invalidFilter",
+                    "Only filtering on userName is supported",
+                ));
+            }
+
+            let users: Vec<ScimUser> = match user::get_by_email(&value).await {
+                Ok(v) => vec![(&v).into()],
+                Err(Error::NotFound(_)) => vec![],
+                Err(e) => return Err(e.into()),
+            };
+            ListResponse::new(users.len() as i64, 1, users)
+        }
+        None => {
+            let total = user::get_count().await?;
+            let users = user::list(count, start_index - 1).await?;
+            ListResponse::new(total, start_index, users.iter().map(|u| u.into()).collect())
+        }
+    };
+
+    Ok(scim_response(StatusCode::OK, &resp))
+}
+
+pub async fn create_user_handler(
+    headers: HeaderMap,
+    Json(req): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    match user::get_by_email(&req.user_name).await {
+        Ok(_) => {
+            return Err(Error::AlreadyExists(req.user_name).into());
+        }
+        Err(Error::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    // The user has been verified by the identity provider and must login through OpenID
+    // Connect or OAuth2, therefore no password is set.
+    let u = user::create(user::User {
+        is_active: req.active,
+        email: req.user_name,
+        email_verified: true,
+        ..Default::default()
+    })
+    .await?;
+
+    let mut resp = scim_response(StatusCode::CREATED, &ScimUser::from(&u));
+    resp.headers_mut().insert(
+        header::LOCATION,
+        format!("{}/Users/{}", BASE_PATH, u.id).parse().unwrap(),
+    );
+    Ok(resp)
+}
+
+pub async fn get_user_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let u = user::get(&parse_id(&id)?).await?;
+    Ok(scim_response(StatusCode::OK, &ScimUser::from(&u)))
+}
+
+pub async fn replace_user_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(req): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let mut u = user::get(&parse_id(&id)?).await?;
+    u.email = req.user_name;
+    u.is_active = req.active;
+
+    let u = update_user(u).await?;
+    Ok(scim_response(StatusCode::OK, &ScimUser::from(&u)))
+}
+
+pub async fn patch_user_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(req): Json<PatchRequest>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let mut u = user::get(&parse_id(&id)?).await?;
+
+    // Attributes which are not stored by ChirpStack (e.g. name) are ignored.
+    for op in &req.operations {
+        let op_name = op.op.to_lowercase();
+        if op_name != "add" && op_name != "replace" {
+            continue;
+        }
+
+        let value = op
+            .value
+            .as_ref()
+            .ok_or_else(|| ScimError::bad_request("invalidValue", "Value is not set"))?;
+
+        match op.path.as_ref().map(|p| p.to_lowercase()).as_deref() {
+            Some("active") => u.is_active = parse_bool(value)?,
+            Some("username") => u.email = parse_string(value)?,
+            Some(_) => {}
+            None => {
+                let obj = value.as_object().ok_or_else(|| {
+                    ScimError::bad_request("invalidValue", "Value must be an object")
+                })?;
+                for (k, v) in obj {
+                    match k.to_lowercase().as_str() {
+                        "active" => u.is_active = parse_bool(v)?,
+                        "username" => u.email = parse_string(v)?,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let u = update_user(u).await?;
+    Ok(scim_response(StatusCode::OK, &ScimUser::from(&u)))
+}
+
+pub async fn delete_user_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let id = parse_id(&id)?;
+    let _ = user::get(&id).await?;
+    session::revoke_all(&id).await?;
+    user::delete(&id).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+// Updates the user and revokes all its sessions in case the user has been deactivated.
+async fn update_user(u: user::User) -> Result<user::User, Error> {
+    let user_id: Uuid = u.id.into();
+    let current = user::get(&user_id).await?;
+    let u = user::update(u).await?;
+
+    if current.is_active && !u.is_active {
+        session::revoke_all(&user_id).await?;
+    }
+
+    Ok(u)
+}
+
+pub async fn list_groups_handler(
+    headers: HeaderMap,
+    Query(args): Query<ListArgs>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+    let (start_index, count) = args.pagination();
+
+    let resp = match &args.filter {
+        Some(filter) => {
+            let (attr, value) = parse_filter(filter)?;
+            if attr != "displayname" {
+                return Err(ScimError::bad_request(
+                    "invalidFilter",
+                    "Only filtering on displayName is supported",
+                ));
+            }
+
+            let filters = tenant::Filters {
+                search: Some(value.clone()),
+                ..Default::default()
+            };
+            let mut groups = vec![];
+            for t in tenant::list(MAX_COUNT, 0, &filters).await? {
+                if t.name == value {
+                    groups.push(get_group(t).await?);
+                }
+            }
+            ListResponse::new(groups.len() as i64, 1, groups)
+        }
+        None => {
+            let filters = tenant::Filters::default();
+            let total = tenant::get_count(&filters).await?;
+            let mut groups = vec![];
+            for t in tenant::list(count, start_index - 1, &filters).await? {
+                groups.push(get_group(t).await?);
+            }
+            ListResponse::new(total, start_index, groups)
+        }
+    };
+
+    Ok(scim_response(StatusCode::OK, &resp))
+}
+
+pub async fn get_group_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let t = tenant::get(&parse_id(&id)?).await?;
+    Ok(scim_response(StatusCode::OK, &get_group(t).await?))
+}
+
+// Adds and removes tenant users. Other attributes are ignored, as tenants are managed
+// through ChirpStack.
+pub async fn patch_group_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(req): Json<PatchRequest>,
+) -> Result<Response, ScimError> {
+    authenticate(&headers).await?;
+
+    let t = tenant::get(&parse_id(&id)?).await?;
+    let tenant_id: Uuid = t.id.into();
+
+    for op in &req.operations {
+        let path = op.path.clone().unwrap_or_default();
+
+        match op.op.to_lowercase().as_str() {
+            "add" if path.eq_ignore_ascii_case("members") => {
+                for user_id in parse_member_ids(&op.value)? {
+                    add_member(&tenant_id, &user_id).await?;
+                }
+            }
+            "remove" if path.eq_ignore_ascii_case("members") => {
+                let user_ids = match op.value {
+                    Some(_) => parse_member_ids(&op.value)?,
+                    None => get_member_ids(&tenant_id).await?,
+                };
+                for user_id in user_ids {
+                    remove_member(&tenant_id, &user_id).await?;
+                }
+            }
+            "remove" if MEMBER_PATH_REGEX.is_match(&path) => {
+                let caps = MEMBER_PATH_REGEX.captures(&path).unwrap();
+                let user_id = Uuid::from_str(&caps[1])
+                    .map_err(|_| ScimError::bad_request("invalidPath", "Invalid member value"))?;
+                remove_member(&tenant_id, &user_id).await?;
+            }
+            "replace" if path.eq_ignore_ascii_case("members") => {
+                let user_ids: HashSet<Uuid> = parse_member_ids(&op.value)?.into_iter().collect();
+                for user_id in get_member_ids(&tenant_id).await? {
+                    if !user_ids.contains(&user_id) {
+                        remove_member(&tenant_id, &user_id).await?;
+                    }
+                }
+                for user_id in &user_ids {
+                    add_member(&tenant_id, user_id).await?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn get_group(t: tenant::Tenant) -> Result<ScimGroup, Error> {
+    let tenant_id: Uuid = t.id.into();
+    let count = tenant::get_user_count(&tenant_id).await?;
+    let members = tenant::get_users(&tenant_id, count, 0)
+        .await?
+        .into_iter()
+        .map(|tu| ScimMember {
+            value: tu.user_id.to_string(),
+            display: tu.email,
+        })
+        .collect();
+
+    Ok(ScimGroup {
+        schemas: vec![SCHEMA_GROUP.into()],
+        id: t.id.to_string(),
+        display_name: t.name,
+        members,
+        meta: ScimMeta {
+            resource_type: "Group".into(),
+            created: t.created_at,
+            last_modified: t.updated_at,
+            location: format!("{}/Groups/{}", BASE_PATH, t.id),
+        },
+    })
+}
+
+async fn get_member_ids(tenant_id: &Uuid) -> Result<Vec<Uuid>, Error> {
+    let count = tenant::get_user_count(tenant_id).await?;
+    Ok(tenant::get_users(tenant_id, count, 0)
+        .await?
+        .into_iter()
+        .map(|tu| tu.user_id.into())
+        .collect())
+}
+
+// Adds the user to the tenant without any additional permissions. These can be granted
+// through ChirpStack.
+async fn add_member(tenant_id: &Uuid, user_id: &Uuid) -> Result<(), ScimError> {
+    match user::get(user_id).await {
+        Ok(_) => {}
+        Err(Error::NotFound(_)) => {
+            return Err(ScimError::bad_request(
+                "invalidValue",
+                &format!("User {} does not exist", user_id),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    match tenant::get_user(tenant_id, user_id).await {
+        Ok(_) => return Ok(()),
+        Err(Error::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    tenant::add_user(tenant::TenantUser {
+        tenant_id: (*tenant_id).into(),
+        user_id: (*user_id).into(),
+        ..Default::default()
+    })
+    .await?;
+
+    Ok(())
+}
+
+async fn remove_member(tenant_id: &Uuid, user_id: &Uuid) -> Result<(), ScimError> {
+    match tenant::delete_user(tenant_id, user_id).await {
+        Ok(_) | Err(Error::NotFound(_)) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    async fn get_headers(is_admin: bool) -> HeaderMap {
+        let conf = config::get();
+        let ak = api_key::test::create_api_key(is_admin, !is_admin).await;
+        let token = claims::AuthClaim::new_for_api_key(&ak.id.into())
+            .encode(conf.api.secret.as_ref())
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn get_body<T: serde::de::DeserializeOwned>(resp: Response) -> T {
+        let b = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&b).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_scim() {
+        let _guard = test::prepare().await;
+        let headers = get_headers(true).await;
+
+        // authentication
+        let resp = service_provider_config_handler(HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status);
+        let resp = service_provider_config_handler(get_headers(false).await)
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::FORBIDDEN, resp.status);
+
+        // create user
+        let resp = create_user_handler(
+            headers.clone(),
+            Json(ScimUser {
+                schemas: vec![SCHEMA_USER.into()],
+                user_name: "scim@example.com".into(),
+                active: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::CREATED, resp.status());
+        let u: ScimUser = get_body(resp).await;
+        assert_eq!("scim@example.com", u.user_name);
+        assert!(u.active);
+
+        let user_get = user::get(&Uuid::from_str(&u.id).unwrap()).await.unwrap();
+        assert!(user_get.is_active);
+        assert!(!user_get.is_admin);
+        assert!(user_get.email_verified);
+
+        // create user again
+        let resp = create_user_handler(
+            headers.clone(),
+            Json(ScimUser {
+                user_name: "scim@example.com".into(),
+                active: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(StatusCode::CONFLICT, resp.status);
+
+        // list users with filter
+        let resp = list_users_handler(
+            headers.clone(),
+            Query(ListArgs {
+                filter: Some(r#"userName eq "scim@example.com""#.into()),
+                start_index: None,
+                count: None,
+            }),
+        )
+        .await
+        .unwrap();
+        let list: ListResponse<ScimUser> = get_body(resp).await;
+        assert_eq!(1, list.total_results);
+        assert_eq!(u.id, list.resources[0].id);
+
+        // list users with unsupported filter
+        let resp = list_users_handler(
+            headers.clone(),
+            Query(ListArgs {
+                filter: Some(r#"userName co "scim""#.into()),
+                start_index: None,
+                count: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status);
+
+        // add to tenant
+        let t = tenant::test::create_tenant().await;
+        let resp = patch_group_handler(
+            headers.clone(),
+            Path(t.id.to_string()),
+            Json(
+                serde_json::from_value(serde_json::json!({
+                    "Operations": [{
+                        "op": "Add",
+                        "path": "members",
+                        "value": [{"value": u.id}],
+                    }],
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+
+        let resp = get_group_handler(headers.clone(), Path(t.id.to_string()))
+            .await
+            .unwrap();
+        let g: ScimGroup = get_body(resp).await;
+        assert_eq!(t.name, g.display_name);
+        assert_eq!(1, g.members.len());
+        assert_eq!(u.id, g.members[0].value);
+
+        // deactivate user
+        let resp = patch_user_handler(
+            headers.clone(),
+            Path(u.id.clone()),
+            Json(
+                serde_json::from_value(serde_json::json!({
+                    "Operations": [{
+                        "op": "Replace",
+                        "value": {"active": "False"},
+                    }],
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        let u: ScimUser = get_body(resp).await;
+        assert!(!u.active);
+
+        let user_get = user::get(&Uuid::from_str(&u.id).unwrap()).await.unwrap();
+        assert!(!user_get.is_active);
+
+        // remove from tenant
+        let resp = patch_group_handler(
+            headers.clone(),
+            Path(t.id.to_string()),
+            Json(
+                serde_json::from_value(serde_json::json!({
+                    "Operations": [{
+                        "op": "remove",
+                        "path": format!("members[value eq \"{}\"]", u.id),
+                    }],
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert!(
+            tenant::get_user(&t.id.into(), &Uuid::from_str(&u.id).unwrap())
+                .await
+                .is_err()
+        );
+
+        // delete user
+        let resp = delete_user_handler(headers.clone(), Path(u.id.clone()))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        let resp = get_user_handler(headers.clone(), Path(u.id.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, resp.status);
+    }
+}
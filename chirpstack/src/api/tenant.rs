@@ -1,5 +1,7 @@
 use std::str::FromStr;
+use std::time::SystemTime;
 
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
@@ -8,8 +10,10 @@ use chirpstack_api::api::tenant_service_server::TenantService;
 
 use super::auth::{validator, AuthID};
 use super::error::ToStatus;
-use super::helpers;
-use crate::storage::{fields, tenant, user};
+use super::helpers::{self, FromProto, ToProto};
+use crate::monitoring::sla;
+use crate::quota;
+use crate::storage::{api_key, fields, tenant, tenant_role, tenant_sla_report, tenant_usage, user};
 
 pub struct Tenant {
     validator: validator::RequestValidator,
@@ -50,6 +54,9 @@ impl TenantService for Tenant {
             private_gateways_up: req_tenant.private_gateways_up,
             private_gateways_down: req_tenant.private_gateways_down,
             tags: fields::KeyValue::new(req_tenant.tags.clone()),
+            use_hsm: req_tenant.use_hsm,
+            max_uplink_count_per_day: req_tenant.max_uplink_count_per_day as i32,
+            max_downlink_count_per_day: req_tenant.max_downlink_count_per_day as i32,
             ..Default::default()
         };
 
@@ -91,6 +98,9 @@ impl TenantService for Tenant {
                 private_gateways_up: t.private_gateways_up,
                 private_gateways_down: t.private_gateways_down,
                 tags: t.tags.into_hashmap(),
+                use_hsm: t.use_hsm,
+                max_uplink_count_per_day: t.max_uplink_count_per_day as u32,
+                max_downlink_count_per_day: t.max_downlink_count_per_day as u32,
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&t.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&t.updated_at)),
@@ -131,6 +141,9 @@ impl TenantService for Tenant {
             private_gateways_up: req_tenant.private_gateways_up,
             private_gateways_down: req_tenant.private_gateways_down,
             tags: fields::KeyValue::new(req_tenant.tags.clone()),
+            use_hsm: req_tenant.use_hsm,
+            max_uplink_count_per_day: req_tenant.max_uplink_count_per_day as i32,
+            max_downlink_count_per_day: req_tenant.max_downlink_count_per_day as i32,
             ..Default::default()
         })
         .await
@@ -257,12 +270,16 @@ impl TenantService for Tenant {
             )
             .await?;
 
+        let role_id = get_role_id(&tenant_id, &req_user.role_id).await?;
+        validate_tenant_user_grants(request.extensions(), &tenant_id, req_user, role_id).await?;
+
         let _ = tenant::add_user(tenant::TenantUser {
             user_id,
             tenant_id: tenant_id.into(),
             is_admin: req_user.is_admin,
             is_device_admin: req_user.is_device_admin,
             is_gateway_admin: req_user.is_gateway_admin,
+            role_id,
             ..Default::default()
         })
         .await
@@ -305,6 +322,7 @@ impl TenantService for Tenant {
                 is_admin: tu.is_admin,
                 is_device_admin: tu.is_device_admin,
                 is_gateway_admin: tu.is_gateway_admin,
+                role_id: tu.role_id.map(|v| v.to_string()).unwrap_or_default(),
             }),
             created_at: Some(helpers::datetime_to_prost_timestamp(&tu.created_at)),
             updated_at: Some(helpers::datetime_to_prost_timestamp(&tu.updated_at)),
@@ -341,12 +359,16 @@ impl TenantService for Tenant {
             )
             .await?;
 
+        let role_id = get_role_id(&tenant_id, &req_user.role_id).await?;
+        validate_tenant_user_grants(request.extensions(), &tenant_id, req_user, role_id).await?;
+
         tenant::update_user(tenant::TenantUser {
             tenant_id: tenant_id.into(),
             user_id: user_id.into(),
             is_admin: req_user.is_admin,
             is_device_admin: req_user.is_device_admin,
             is_gateway_admin: req_user.is_gateway_admin,
+            role_id,
             ..Default::default()
         })
         .await
@@ -436,6 +458,7 @@ impl TenantService for Tenant {
                     is_admin: tu.is_admin,
                     is_device_admin: tu.is_device_admin,
                     is_gateway_admin: tu.is_gateway_admin,
+                    role_id: tu.role_id.map(|v| v.to_string()).unwrap_or_default(),
                 })
                 .collect(),
         });
@@ -444,6 +467,408 @@ impl TenantService for Tenant {
 
         Ok(resp)
     }
+
+    async fn create_role(
+        &self,
+        request: Request<api::CreateTenantRoleRequest>,
+    ) -> Result<Response<api::CreateTenantRoleResponse>, Status> {
+        let req_role = match &request.get_ref().tenant_role {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("tenant_role is missing"));
+            }
+        };
+        let tenant_id = Uuid::from_str(&req_role.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantRolesAccess::new(validator::Flag::Create, tenant_id),
+            )
+            .await?;
+
+        let permissions = req_role
+            .permissions()
+            .map(|p| p.from_proto())
+            .collect::<Vec<_>>();
+        validate_permissions_granted(request.extensions(), &tenant_id, &permissions).await?;
+
+        let r = tenant_role::create(
+            tenant_role::TenantRole {
+                tenant_id: tenant_id.into(),
+                name: req_role.name.clone(),
+                description: req_role.description.clone(),
+                ..Default::default()
+            },
+            &permissions,
+        )
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::CreateTenantRoleResponse {
+            id: r.id.to_string(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_id", req_role.tenant_id.parse().unwrap());
+        resp.metadata_mut()
+            .insert("x-log-tenant_role_id", r.id.to_string().parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn get_role(
+        &self,
+        request: Request<api::GetTenantRoleRequest>,
+    ) -> Result<Response<api::GetTenantRoleResponse>, Status> {
+        let req = request.get_ref();
+        let id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantRoleAccess::new(validator::Flag::Read, id),
+            )
+            .await?;
+
+        let r = tenant_role::get(&id).await.map_err(|e| e.status())?;
+        let permissions = tenant_role::get_permissions(&id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetTenantRoleResponse {
+            tenant_role: Some(api::TenantRole {
+                id: r.id.to_string(),
+                tenant_id: r.tenant_id.to_string(),
+                name: r.name.clone(),
+                description: r.description.clone(),
+                permissions: permissions
+                    .into_iter()
+                    .map(|p| p.to_proto().into())
+                    .collect(),
+            }),
+            created_at: Some(helpers::datetime_to_prost_timestamp(&r.created_at)),
+            updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_role_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn update_role(
+        &self,
+        request: Request<api::UpdateTenantRoleRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req_role = match &request.get_ref().tenant_role {
+            Some(v) => v,
+            None => {
+                return Err(Status::invalid_argument("tenant_role is missing"));
+            }
+        };
+        let id = Uuid::from_str(&req_role.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantRoleAccess::new(validator::Flag::Update, id),
+            )
+            .await?;
+
+        // The tenant of a role can not be changed.
+        let r = tenant_role::get(&id).await.map_err(|e| e.status())?;
+
+        let permissions = req_role
+            .permissions()
+            .map(|p| p.from_proto())
+            .collect::<Vec<_>>();
+        validate_permissions_granted(request.extensions(), &r.tenant_id.into(), &permissions)
+            .await?;
+
+        let _ = tenant_role::update(
+            tenant_role::TenantRole {
+                name: req_role.name.clone(),
+                description: req_role.description.clone(),
+                ..r
+            },
+            &permissions,
+        )
+        .await
+        .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-tenant_role_id", req_role.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn delete_role(
+        &self,
+        request: Request<api::DeleteTenantRoleRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantRoleAccess::new(validator::Flag::Delete, id),
+            )
+            .await?;
+
+        tenant_role::delete(&id).await.map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-tenant_role_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn list_roles(
+        &self,
+        request: Request<api::ListTenantRolesRequest>,
+    ) -> Result<Response<api::ListTenantRolesResponse>, Status> {
+        let req = request.get_ref();
+        let tenant_id = Uuid::from_str(&req.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantRolesAccess::new(validator::Flag::List, tenant_id),
+            )
+            .await?;
+
+        let count = tenant_role::get_count(&tenant_id)
+            .await
+            .map_err(|e| e.status())?;
+        let result = tenant_role::list(&tenant_id, req.limit as i64, req.offset as i64)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::ListTenantRolesResponse {
+            total_count: count as u32,
+            result: result
+                .iter()
+                .map(|r| api::TenantRoleListItem {
+                    id: r.id.to_string(),
+                    created_at: Some(helpers::datetime_to_prost_timestamp(&r.created_at)),
+                    updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+                    name: r.name.clone(),
+                    description: r.description.clone(),
+                })
+                .collect(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_id", req.tenant_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn get_sla_report(
+        &self,
+        request: Request<api::GetTenantSlaReportRequest>,
+    ) -> Result<Response<api::GetTenantSlaReportResponse>, Status> {
+        let req = request.get_ref();
+        let tenant_id = Uuid::from_str(&req.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantAccess::new(validator::Flag::Read, tenant_id),
+            )
+            .await?;
+
+        let filters = tenant_sla_report::Filters {
+            start: match &req.start {
+                Some(v) => Some(timestamp_to_month(v)?),
+                None => None,
+            },
+            end: match &req.end {
+                Some(v) => Some(timestamp_to_month(v)?),
+                None => None,
+            },
+        };
+
+        let items = tenant_sla_report::list(&tenant_id, &filters)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetTenantSlaReportResponse {
+            result: items
+                .iter()
+                .map(|r| api::TenantSlaReport {
+                    month: r.month.format("%Y-%m").to_string(),
+                    uplink_count: r.uplink_count as u64,
+                    uplink_success_count: r.uplink_success_count as u64,
+                    uplink_availability: sla::availability(r.uplink_count, r.uplink_success_count),
+                    downlink_count: r.downlink_count as u64,
+                    downlink_success_count: r.downlink_success_count as u64,
+                    downlink_availability: sla::availability(
+                        r.downlink_count,
+                        r.downlink_success_count,
+                    ),
+                    api_request_count: r.api_request_count as u64,
+                    api_success_count: r.api_success_count as u64,
+                    api_availability: sla::availability(r.api_request_count, r.api_success_count),
+                    updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+                })
+                .collect(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_id", req.tenant_id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn get_usage(
+        &self,
+        request: Request<api::GetTenantUsageRequest>,
+    ) -> Result<Response<api::GetTenantUsageResponse>, Status> {
+        let req = request.get_ref();
+        let tenant_id = Uuid::from_str(&req.tenant_id).map_err(|e| e.status())?;
+
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateTenantAccess::new(validator::Flag::Read, tenant_id),
+            )
+            .await?;
+
+        let t = tenant::get(&tenant_id).await.map_err(|e| e.status())?;
+        let u = tenant_usage::get(&tenant_id, Utc::now().date_naive())
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(api::GetTenantUsageResponse {
+            uplink_count: u.uplink_count as u64,
+            downlink_count: u.downlink_count as u64,
+            max_uplink_count_per_day: t.max_uplink_count_per_day as u32,
+            max_downlink_count_per_day: t.max_downlink_count_per_day as u32,
+            uplink_quota_exceeded: quota::uplink_quota_exceeded(&tenant_id).is_some(),
+            downlink_quota_exceeded: quota::downlink_quota_exceeded(&tenant_id).is_some(),
+        });
+        resp.metadata_mut()
+            .insert("x-log-tenant_id", req.tenant_id.parse().unwrap());
+
+        Ok(resp)
+    }
+}
+
+// Returns the first day of the month of the given timestamp (UTC).
+fn timestamp_to_month(ts: &prost_types::Timestamp) -> Result<NaiveDate, Status> {
+    let ts: DateTime<Utc> = SystemTime::try_from(*ts).map_err(|e| e.status())?.into();
+    NaiveDate::from_ymd_opt(ts.year(), ts.month(), 1)
+        .ok_or_else(|| Status::invalid_argument("invalid month"))
+}
+
+// Returns the tenant role ID, after validating that the role belongs to the given tenant.
+// An empty string means that no role is assigned.
+async fn get_role_id(tenant_id: &Uuid, role_id: &str) -> Result<Option<fields::Uuid>, Status> {
+    if role_id.is_empty() {
+        return Ok(None);
+    }
+
+    let role_id = Uuid::from_str(role_id).map_err(|e| e.status())?;
+    let r = tenant_role::get(&role_id).await.map_err(|e| e.status())?;
+    if Uuid::from(r.tenant_id) != *tenant_id {
+        return Err(Status::invalid_argument(
+            "role_id does not belong to the tenant",
+        ));
+    }
+
+    Ok(Some(r.id))
+}
+
+// Returns the permissions of the caller within the given tenant. None means that the caller is
+// not restricted (global admin, tenant admin or admin / tenant API key).
+async fn get_caller_permissions(
+    auth_id: &AuthID,
+    tenant_id: &Uuid,
+) -> Result<Option<Vec<fields::Permission>>, Status> {
+    match auth_id {
+        AuthID::User(id) => {
+            let u = user::get(id).await.map_err(|e| e.status())?;
+            if u.is_admin {
+                return Ok(None);
+            }
+
+            let tu = tenant::get_user(tenant_id, id)
+                .await
+                .map_err(|e| e.status())?;
+            match tu.role_id {
+                Some(role_id) => Ok(Some(
+                    tenant_role::get_permissions(&role_id.into())
+                        .await
+                        .map_err(|e| e.status())?,
+                )),
+                None if tu.is_admin => Ok(None),
+                None => Ok(Some(Vec::new())),
+            }
+        }
+        AuthID::Key(id) => {
+            let k = api_key::get(id).await.map_err(|e| e.status())?;
+            if k.is_admin || k.tenant_id.map(Uuid::from) == Some(*tenant_id) {
+                Ok(None)
+            } else {
+                Ok(Some(Vec::new()))
+            }
+        }
+        AuthID::None => Ok(Some(Vec::new())),
+    }
+}
+
+// Validates that the caller holds all the given permissions, such that a caller can not grant
+// more permissions than it has itself.
+async fn validate_permissions_granted(
+    ext: &tonic::Extensions,
+    tenant_id: &Uuid,
+    permissions: &[fields::Permission],
+) -> Result<(), Status> {
+    let auth_id = ext
+        .get::<AuthID>()
+        .ok_or_else(|| Status::unauthenticated("no authorization provided"))?;
+
+    if let Some(caller_permissions) = get_caller_permissions(auth_id, tenant_id).await? {
+        if let Some(p) = permissions.iter().find(|p| !caller_permissions.contains(p)) {
+            return Err(Status::permission_denied(format!(
+                "you can not grant the {:?} permission",
+                p
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// Validates that the caller does not grant the tenant user more than it holds itself. Callers
+// restricted by a role can only assign roles within their own permissions and can not grant
+// the admin flags.
+async fn validate_tenant_user_grants(
+    ext: &tonic::Extensions,
+    tenant_id: &Uuid,
+    req_user: &api::TenantUser,
+    role_id: Option<fields::Uuid>,
+) -> Result<(), Status> {
+    if let Some(role_id) = role_id {
+        let permissions = tenant_role::get_permissions(&role_id.into())
+            .await
+            .map_err(|e| e.status())?;
+        validate_permissions_granted(ext, tenant_id, &permissions).await?;
+    }
+
+    if req_user.is_admin || req_user.is_device_admin || req_user.is_gateway_admin {
+        let auth_id = ext
+            .get::<AuthID>()
+            .ok_or_else(|| Status::unauthenticated("no authorization provided"))?;
+        if get_caller_permissions(auth_id, tenant_id).await?.is_some() {
+            return Err(Status::permission_denied(
+                "you can not grant the admin flags",
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -452,6 +877,7 @@ pub mod test {
     use crate::api::auth::validator::RequestValidator;
     use crate::api::auth::AuthID;
     use crate::test;
+    use chrono::TimeZone;
 
     #[tokio::test]
     async fn test_tenant() {
@@ -518,6 +944,9 @@ pub mod test {
                 can_have_gateways: true,
                 max_device_count: 10,
                 max_gateway_count: 3,
+                use_hsm: true,
+                max_uplink_count_per_day: 1000,
+                max_downlink_count_per_day: 100,
                 ..Default::default()
             }),
         };
@@ -544,6 +973,9 @@ pub mod test {
                 can_have_gateways: true,
                 max_device_count: 10,
                 max_gateway_count: 3,
+                use_hsm: true,
+                max_uplink_count_per_day: 1000,
+                max_downlink_count_per_day: 100,
                 ..Default::default()
             }),
             get_resp.get_ref().tenant
@@ -584,4 +1016,392 @@ pub mod test {
         let del_resp = service.delete(del_req).await;
         assert!(del_resp.is_err());
     }
+
+    #[tokio::test]
+    async fn test_tenant_role() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        };
+        let u = user::create(u).await.unwrap();
+
+        // setup tenant user
+        let tu = user::User {
+            is_active: true,
+            email: "user@user".into(),
+            email_verified: true,
+            ..Default::default()
+        };
+        let tu = user::create(tu).await.unwrap();
+
+        let t = tenant::test::create_tenant().await;
+        let t_other = tenant::test::create_tenant().await;
+
+        // setup api
+        let service = Tenant::new(RequestValidator::new());
+
+        // create
+        let create_req = api::CreateTenantRoleRequest {
+            tenant_role: Some(api::TenantRole {
+                tenant_id: t.id.to_string(),
+                name: "Operator".into(),
+                description: "Can enqueue downlinks".into(),
+                permissions: vec![
+                    api::TenantPermission::DeviceRead.into(),
+                    api::TenantPermission::DeviceQueueWrite.into(),
+                ],
+                ..Default::default()
+            }),
+        };
+        let mut create_req = Request::new(create_req);
+        create_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let create_resp = service.create_role(create_req).await.unwrap();
+
+        // get
+        let get_req = api::GetTenantRoleRequest {
+            id: create_resp.get_ref().id.clone(),
+        };
+        let mut get_req = Request::new(get_req);
+        get_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let get_resp = service.get_role(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::TenantRole {
+                id: create_resp.get_ref().id.clone(),
+                tenant_id: t.id.to_string(),
+                name: "Operator".into(),
+                description: "Can enqueue downlinks".into(),
+                permissions: vec![
+                    api::TenantPermission::DeviceRead.into(),
+                    api::TenantPermission::DeviceQueueWrite.into(),
+                ],
+            }),
+            get_resp.get_ref().tenant_role
+        );
+
+        // update
+        let up_req = api::UpdateTenantRoleRequest {
+            tenant_role: Some(api::TenantRole {
+                id: create_resp.get_ref().id.clone(),
+                name: "Gateway viewer".into(),
+                permissions: vec![api::TenantPermission::GatewayRead.into()],
+                ..Default::default()
+            }),
+        };
+        let mut up_req = Request::new(up_req);
+        up_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let _ = service.update_role(up_req).await.unwrap();
+
+        let get_req = api::GetTenantRoleRequest {
+            id: create_resp.get_ref().id.clone(),
+        };
+        let mut get_req = Request::new(get_req);
+        get_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let get_resp = service.get_role(get_req).await.unwrap();
+        assert_eq!(
+            Some(api::TenantRole {
+                id: create_resp.get_ref().id.clone(),
+                tenant_id: t.id.to_string(),
+                name: "Gateway viewer".into(),
+                description: "".into(),
+                permissions: vec![api::TenantPermission::GatewayRead.into()],
+            }),
+            get_resp.get_ref().tenant_role
+        );
+
+        // list
+        let list_req = api::ListTenantRolesRequest {
+            tenant_id: t.id.to_string(),
+            limit: 10,
+            offset: 0,
+        };
+        let mut list_req = Request::new(list_req);
+        list_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let list_resp = service.list_roles(list_req).await.unwrap();
+        assert_eq!(1, list_resp.get_ref().total_count);
+        assert_eq!(1, list_resp.get_ref().result.len());
+        assert_eq!(create_resp.get_ref().id, list_resp.get_ref().result[0].id);
+
+        // assign role of other tenant
+        let add_req = api::AddTenantUserRequest {
+            tenant_user: Some(api::TenantUser {
+                tenant_id: t_other.id.to_string(),
+                email: "user@user".into(),
+                role_id: create_resp.get_ref().id.clone(),
+                ..Default::default()
+            }),
+        };
+        let mut add_req = Request::new(add_req);
+        add_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        assert!(service.add_user(add_req).await.is_err());
+
+        // assign role
+        let add_req = api::AddTenantUserRequest {
+            tenant_user: Some(api::TenantUser {
+                tenant_id: t.id.to_string(),
+                email: "user@user".into(),
+                role_id: create_resp.get_ref().id.clone(),
+                ..Default::default()
+            }),
+        };
+        let mut add_req = Request::new(add_req);
+        add_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let _ = service.add_user(add_req).await.unwrap();
+
+        let get_req = api::GetTenantUserRequest {
+            tenant_id: t.id.to_string(),
+            user_id: tu.id.to_string(),
+        };
+        let mut get_req = Request::new(get_req);
+        get_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let get_resp = service.get_user(get_req).await.unwrap();
+        assert_eq!(
+            create_resp.get_ref().id,
+            get_resp.get_ref().tenant_user.as_ref().unwrap().role_id
+        );
+
+        // delete
+        let del_req = api::DeleteTenantRoleRequest {
+            id: create_resp.get_ref().id.clone(),
+        };
+        let mut del_req = Request::new(del_req);
+        del_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let _ = service.delete_role(del_req).await.unwrap();
+
+        // the tenant user falls back on the flags
+        let tu = tenant::get_user(&t.id, &tu.id).await.unwrap();
+        assert_eq!(None, tu.role_id);
+
+        let del_req = api::DeleteTenantRoleRequest {
+            id: create_resp.get_ref().id.clone(),
+        };
+        let mut del_req = Request::new(del_req);
+        del_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        assert!(service.delete_role(del_req).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_role_escalation() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::test::create_tenant().await;
+
+        // setup tenant user with a role granting user management
+        let u = user::User {
+            is_active: true,
+            email: "user@user".into(),
+            email_verified: true,
+            ..Default::default()
+        };
+        let u = user::create(u).await.unwrap();
+
+        let r = tenant_role::create(
+            tenant_role::TenantRole {
+                tenant_id: t.id,
+                name: "User manager".into(),
+                ..Default::default()
+            },
+            &[
+                fields::Permission::TenantUserRead,
+                fields::Permission::TenantUserWrite,
+                fields::Permission::GatewayRead,
+            ],
+        )
+        .await
+        .unwrap();
+
+        tenant::add_user(tenant::TenantUser {
+            tenant_id: t.id,
+            user_id: u.id,
+            role_id: Some(r.id),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let service = Tenant::new(RequestValidator::new());
+
+        let create_role = |permissions: Vec<api::TenantPermission>| {
+            let mut req = Request::new(api::CreateTenantRoleRequest {
+                tenant_role: Some(api::TenantRole {
+                    tenant_id: t.id.to_string(),
+                    name: "Role".into(),
+                    permissions: permissions.into_iter().map(|p| p.into()).collect(),
+                    ..Default::default()
+                }),
+            });
+            req.extensions_mut()
+                .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+            req
+        };
+
+        // within the permissions of the caller
+        let create_resp = service
+            .create_role(create_role(vec![api::TenantPermission::GatewayRead]))
+            .await
+            .unwrap();
+
+        // exceeding the permissions of the caller
+        let resp = service
+            .create_role(create_role(vec![
+                api::TenantPermission::GatewayRead,
+                api::TenantPermission::GatewayWrite,
+            ]))
+            .await;
+        assert_eq!(tonic::Code::PermissionDenied, resp.unwrap_err().code());
+
+        // update exceeding the permissions of the caller
+        let mut up_req = Request::new(api::UpdateTenantRoleRequest {
+            tenant_role: Some(api::TenantRole {
+                id: create_resp.get_ref().id.clone(),
+                name: "Role".into(),
+                permissions: vec![api::TenantPermission::DeviceKeysWrite.into()],
+                ..Default::default()
+            }),
+        });
+        up_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let resp = service.update_role(up_req).await;
+        assert_eq!(tonic::Code::PermissionDenied, resp.unwrap_err().code());
+
+        // grant the admin flag to a user
+        let other = user::create(user::User {
+            is_active: true,
+            email: "other@user".into(),
+            email_verified: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut add_req = Request::new(api::AddTenantUserRequest {
+            tenant_user: Some(api::TenantUser {
+                tenant_id: t.id.to_string(),
+                email: "other@user".into(),
+                is_admin: true,
+                ..Default::default()
+            }),
+        });
+        add_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let resp = service.add_user(add_req).await;
+        assert_eq!(tonic::Code::PermissionDenied, resp.unwrap_err().code());
+
+        // assign a role within the permissions of the caller
+        let mut add_req = Request::new(api::AddTenantUserRequest {
+            tenant_user: Some(api::TenantUser {
+                tenant_id: t.id.to_string(),
+                email: "other@user".into(),
+                role_id: create_resp.get_ref().id.clone(),
+                ..Default::default()
+            }),
+        });
+        add_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        service.add_user(add_req).await.unwrap();
+
+        let tu = tenant::get_user(&t.id, &other.id).await.unwrap();
+        assert_eq!(create_resp.get_ref().id, tu.role_id.unwrap().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_get_sla_report() {
+        let _guard = test::prepare().await;
+
+        // setup admin user
+        let u = user::User {
+            is_admin: true,
+            is_active: true,
+            email: "admin@admin".into(),
+            email_verified: true,
+            ..Default::default()
+        };
+        let u = user::create(u).await.unwrap();
+
+        let t = tenant::test::create_tenant().await;
+        let month = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        let r = tenant_sla_report::upsert(tenant_sla_report::TenantSlaReport {
+            tenant_id: t.id,
+            month,
+            uplink_count: 10,
+            uplink_success_count: 9,
+            downlink_count: 4,
+            downlink_success_count: 3,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let service = Tenant::new(RequestValidator::new());
+
+        // get
+        let get_req = api::GetTenantSlaReportRequest {
+            tenant_id: t.id.to_string(),
+            start: None,
+            end: None,
+        };
+        let mut get_req = Request::new(get_req);
+        get_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let get_resp = service.get_sla_report(get_req).await.unwrap();
+        assert_eq!(
+            vec![api::TenantSlaReport {
+                month: "2025-05".into(),
+                uplink_count: 10,
+                uplink_success_count: 9,
+                uplink_availability: 90.0,
+                downlink_count: 4,
+                downlink_success_count: 3,
+                downlink_availability: 75.0,
+                api_request_count: 0,
+                api_success_count: 0,
+                api_availability: 100.0,
+                updated_at: Some(helpers::datetime_to_prost_timestamp(&r.updated_at)),
+            }],
+            get_resp.get_ref().result
+        );
+
+        // get with start after the report month
+        let start: SystemTime = Utc.with_ymd_and_hms(2025, 6, 15, 0, 0, 0).unwrap().into();
+        let get_req = api::GetTenantSlaReportRequest {
+            tenant_id: t.id.to_string(),
+            start: Some(start.into()),
+            end: None,
+        };
+        let mut get_req = Request::new(get_req);
+        get_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let get_resp = service.get_sla_report(get_req).await.unwrap();
+        assert!(get_resp.get_ref().result.is_empty());
+    }
 }
@@ -7,10 +7,10 @@ use uuid::Uuid;
 use chirpstack_api::api;
 use chirpstack_api::api::user_service_server::UserService;
 
-use super::auth::{validator, AuthID};
+use super::auth::{session, validator, AuthID};
 use super::error::ToStatus;
 use super::helpers;
-use crate::storage::{tenant, user};
+use crate::storage::{tenant, user, user_session};
 
 pub struct User {
     validator: validator::RequestValidator,
@@ -253,6 +253,91 @@ impl UserService for User {
 
         Ok(resp)
     }
+
+    async fn list_sessions(
+        &self,
+        request: Request<api::ListUserSessionsRequest>,
+    ) -> Result<Response<api::ListUserSessionsResponse>, Status> {
+        let req = request.get_ref();
+        let user_id = Uuid::from_str(&req.user_id).map_err(|e| e.status())?;
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateUserAccess::new(validator::Flag::Read, user_id),
+            )
+            .await?;
+
+        let count = user_session::get_count(&user_id)
+            .await
+            .map_err(|e| e.status())?;
+        let items = user_session::list(&user_id, req.limit as i64, req.offset as i64)
+            .await
+            .map_err(|e| e.status())?;
+
+        Ok(Response::new(api::ListUserSessionsResponse {
+            total_count: count as u32,
+            result: items
+                .iter()
+                .map(|s| api::UserSession {
+                    id: s.id.to_string(),
+                    user_id: s.user_id.to_string(),
+                    created_at: Some(helpers::datetime_to_prost_timestamp(&s.created_at)),
+                    expires_at: Some(helpers::datetime_to_prost_timestamp(&s.expires_at)),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn revoke_session(
+        &self,
+        request: Request<api::RevokeUserSessionRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let user_id = Uuid::from_str(&req.user_id).map_err(|e| e.status())?;
+        let id = Uuid::from_str(&req.id).map_err(|e| e.status())?;
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateUserAccess::new(validator::Flag::UpdateProfile, user_id),
+            )
+            .await?;
+
+        session::revoke(&user_id, &id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-user_id", req.user_id.parse().unwrap());
+        resp.metadata_mut()
+            .insert("x-log-session_id", req.id.parse().unwrap());
+
+        Ok(resp)
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        request: Request<api::RevokeAllUserSessionsRequest>,
+    ) -> Result<Response<()>, Status> {
+        let req = request.get_ref();
+        let user_id = Uuid::from_str(&req.user_id).map_err(|e| e.status())?;
+        self.validator
+            .validate(
+                request.extensions(),
+                validator::ValidateUserAccess::new(validator::Flag::UpdateProfile, user_id),
+            )
+            .await?;
+
+        session::revoke_all(&user_id)
+            .await
+            .map_err(|e| e.status())?;
+
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("x-log-user_id", req.user_id.parse().unwrap());
+
+        Ok(resp)
+    }
 }
 
 #[cfg(test)]
@@ -383,6 +468,47 @@ pub mod test {
         assert_eq!(3, list_resp.get_ref().total_count);
         assert_eq!(3, list_resp.get_ref().result.len());
 
+        // sessions
+        let token = session::create_token(&u.id, b"secret").await.unwrap();
+        let claim = crate::api::auth::claims::AuthClaim::decode(&token, b"secret").unwrap();
+
+        let list_req = api::ListUserSessionsRequest {
+            user_id: u.id.to_string(),
+            limit: 10,
+            offset: 0,
+        };
+        let mut list_req = Request::new(list_req);
+        list_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let list_resp = service.list_sessions(list_req).await.unwrap();
+        assert_eq!(1, list_resp.get_ref().total_count);
+        assert_eq!(claim.jti, list_resp.get_ref().result[0].id);
+
+        // revoke session
+        let revoke_req = api::RevokeUserSessionRequest {
+            user_id: u.id.to_string(),
+            id: claim.jti.clone(),
+        };
+        let mut revoke_req = Request::new(revoke_req);
+        revoke_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let _ = service.revoke_session(revoke_req).await.unwrap();
+        assert!(session::is_revoked(
+            &uuid::Uuid::from_str(&claim.jti).unwrap()
+        ));
+
+        // revoke all sessions
+        let revoke_req = api::RevokeAllUserSessionsRequest {
+            user_id: u.id.to_string(),
+        };
+        let mut revoke_req = Request::new(revoke_req);
+        revoke_req
+            .extensions_mut()
+            .insert(AuthID::User(Into::<uuid::Uuid>::into(u.id)));
+        let _ = service.revoke_all_sessions(revoke_req).await.unwrap();
+
         // delete
         let del_req = api::DeleteUserRequest {
             id: create_resp.get_ref().id.clone(),
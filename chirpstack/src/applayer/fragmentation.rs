@@ -2,8 +2,10 @@ use anyhow::Result;
 use chrono::Utc;
 use tracing::{info, warn};
 
+use crate::applayer::fuota::events;
 use crate::storage::fields::device_profile::Ts004Version;
 use crate::storage::{device, device_profile, fuota};
+use chirpstack_api::integration::FuotaEventType;
 use lrwn::applayer::fragmentation;
 
 pub async fn handle_uplink(
@@ -80,7 +82,10 @@ async fn handle_v1_frag_session_setup_ans(
         fuota_dev.frag_session_setup_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    if !fuota_dev.error_msg.is_empty() {
+        events::device_event(&fuota_dev, FuotaEventType::DeviceFailed).await;
+    }
 
     Ok(())
 }
@@ -113,7 +118,10 @@ async fn handle_v2_frag_session_setup_ans(
         fuota_dev.frag_session_setup_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    if !fuota_dev.error_msg.is_empty() {
+        events::device_event(&fuota_dev, FuotaEventType::DeviceFailed).await;
+    }
 
     Ok(())
 }
@@ -140,7 +148,16 @@ async fn handle_v1_frag_session_status_ans(
         fuota_dev.frag_status_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
@@ -173,7 +190,16 @@ async fn handle_v2_frag_session_status_ans(
         fuota_dev.frag_status_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::Utc;
+use tracing::warn;
+
+use crate::api::helpers::ToProto;
+use crate::helpers::errors::PrintFullError;
+use crate::integration;
+use crate::storage::{application, fuota, helpers::get_all_device_data};
+use chirpstack_api::integration as integration_pb;
+
+// Sends the FUOTA event for the given deployment device to the integrations.
+pub async fn device_event(
+    fuota_dev: &fuota::FuotaDeploymentDevice,
+    event_type: integration_pb::FuotaEventType,
+) {
+    if let Err(e) = _device_event(fuota_dev, event_type).await {
+        warn!(dev_eui = %fuota_dev.dev_eui, error = %e.full(), "Sending FUOTA device event failed");
+    }
+}
+
+// Sends the FUOTA event for the given deployment to the integrations.
+pub async fn deployment_event(
+    fuota_deployment: &fuota::FuotaDeployment,
+    event_type: integration_pb::FuotaEventType,
+) {
+    if let Err(e) = _deployment_event(fuota_deployment, event_type).await {
+        warn!(deployment_id = %fuota_deployment.id, error = %e.full(), "Sending FUOTA deployment event failed");
+    }
+}
+
+async fn _device_event(
+    fuota_dev: &fuota::FuotaDeploymentDevice,
+    event_type: integration_pb::FuotaEventType,
+) -> Result<()> {
+    let fuota_deployment = fuota::get_deployment(fuota_dev.fuota_deployment_id.into()).await?;
+    let (dev, app, t, dp) = get_all_device_data(fuota_dev.dev_eui).await?;
+
+    let pl = integration_pb::FuotaEvent {
+        time: Some(Utc::now().into()),
+        tenant_id: t.id.to_string(),
+        application_id: app.id.to_string(),
+        deployment_id: fuota_deployment.id.to_string(),
+        deployment_name: fuota_deployment.name.clone(),
+        event_type: event_type.into(),
+        device_info: Some(integration_pb::DeviceInfo {
+            tenant_id: t.id.to_string(),
+            tenant_name: t.name.clone(),
+            application_id: app.id.to_string(),
+            application_name: app.name.to_string(),
+            device_profile_id: dp.id.to_string(),
+            device_profile_name: dp.name.clone(),
+            device_name: dev.name.clone(),
+            device_class_enabled: dev.enabled_class.to_proto().into(),
+            dev_eui: dev.dev_eui.to_string(),
+            tags: {
+                let mut tags = (*app.tags).clone();
+                tags.extend((*dp.tags).clone());
+                tags.extend((*dev.tags).clone());
+                tags
+            },
+        }),
+        error: fuota_dev.error_msg.clone(),
+        ..Default::default()
+    };
+
+    integration::fuota_event(app.id.into(), &dev.variables, &pl).await;
+
+    Ok(())
+}
+
+async fn _deployment_event(
+    fuota_deployment: &fuota::FuotaDeployment,
+    event_type: integration_pb::FuotaEventType,
+) -> Result<()> {
+    let app = application::get(&fuota_deployment.application_id.into()).await?;
+
+    let mut pl = integration_pb::FuotaEvent {
+        time: Some(Utc::now().into()),
+        tenant_id: app.tenant_id.to_string(),
+        application_id: app.id.to_string(),
+        deployment_id: fuota_deployment.id.to_string(),
+        deployment_name: fuota_deployment.name.clone(),
+        event_type: event_type.into(),
+        ..Default::default()
+    };
+
+    if event_type == integration_pb::FuotaEventType::DeploymentCompleted {
+        let device_counts = fuota::get_device_counts(fuota_deployment.id.into()).await?;
+        pl.device_count = device_counts.total as u32;
+        pl.device_completed_count = device_counts.completed as u32;
+        pl.device_failed_count = device_counts.failed as u32;
+    }
+
+    integration::fuota_event(app.id.into(), &HashMap::new(), &pl).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::integration::mock;
+    use crate::storage::{device, device_profile, tenant};
+    use crate::test;
+    use lrwn::EUI64;
+
+    #[tokio::test]
+    async fn test_events() {
+        let _guard = test::prepare().await;
+        integration::set_mock().await;
+        mock::reset().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            name: "test-dp".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dev = device::create(device::Device {
+            name: "test-device".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            application_id: app.id,
+            device_profile_id: dp.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let d = fuota::create_deployment(fuota::FuotaDeployment {
+            name: "test-fuota".into(),
+            application_id: app.id,
+            device_profile_id: dp.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        fuota::add_devices(d.id.into(), vec![dev.dev_eui], "")
+            .await
+            .unwrap();
+        let mut fuota_dev = fuota::get_latest_device_by_dev_eui(dev.dev_eui)
+            .await
+            .unwrap();
+        fuota_dev.frag_status_completed_at = Some(Utc::now());
+        fuota_dev.completed_at = Some(Utc::now());
+        let fuota_dev = fuota::update_device(fuota_dev).await.unwrap();
+
+        device_event(&fuota_dev, integration_pb::FuotaEventType::DeviceCompleted).await;
+        deployment_event(&d, integration_pb::FuotaEventType::DeploymentCompleted).await;
+
+        // The events are published asynchronously.
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let events = mock::get_fuota_events().await;
+        assert_eq!(2, events.len());
+
+        let dev_event = events
+            .iter()
+            .find(|e| e.event_type() == integration_pb::FuotaEventType::DeviceCompleted)
+            .unwrap();
+        assert_eq!(d.id.to_string(), dev_event.deployment_id);
+        assert_eq!(
+            dev.dev_eui.to_string(),
+            dev_event.device_info.as_ref().unwrap().dev_eui
+        );
+
+        let dep_event = events
+            .iter()
+            .find(|e| e.event_type() == integration_pb::FuotaEventType::DeploymentCompleted)
+            .unwrap();
+        assert_eq!(t.id.to_string(), dep_event.tenant_id);
+        assert!(dep_event.device_info.is_none());
+        assert_eq!(1, dep_event.device_count);
+        assert_eq!(1, dep_event.device_completed_count);
+        assert_eq!(0, dep_event.device_failed_count);
+    }
+}
@@ -5,9 +5,11 @@ use anyhow::Result;
 use chrono::{DateTime, TimeDelta, Utc};
 use tracing::info;
 
+use chirpstack_api::integration::FuotaEventType;
 use lrwn::applayer::{fragmentation, multicastsetup};
 use lrwn::region::MacVersion;
 
+use super::events;
 use crate::config;
 use crate::downlink;
 use crate::gpstime::ToGpsTime;
@@ -15,13 +17,16 @@ use crate::storage::fields::{
     device_profile::Ts004Version, device_profile::Ts005Version, FuotaJob,
     RequestFragmentationSessionStatus,
 };
-use crate::storage::{device, device_keys, device_profile, device_queue, fuota, multicast};
+use crate::storage::{
+    device, device_keys, device_profile, device_queue, fuota, key_access_log, multicast,
+};
 
 pub struct Flow {
     scheduler_interval: Duration,
     job: fuota::FuotaDeploymentJob,
     fuota_deployment: fuota::FuotaDeployment,
     device_profile: device_profile::DeviceProfile,
+    artifacts: Vec<fuota::FuotaArtifact>,
 }
 
 impl Flow {
@@ -31,11 +36,14 @@ impl Flow {
         let fuota_deployment = fuota::get_deployment(job.fuota_deployment_id.into()).await?;
         let device_profile =
             device_profile::get(&fuota_deployment.device_profile_id.into()).await?;
+        let deltas = fuota::get_deltas(fuota_deployment.id.into()).await?;
+        let artifacts = fuota::get_artifacts(&fuota_deployment, &deltas);
 
         let mut flow = Flow {
             job,
             fuota_deployment,
             device_profile,
+            artifacts,
             scheduler_interval: conf.network.scheduler.interval,
         };
         flow.dispatch().await
@@ -169,8 +177,24 @@ impl Flow {
         info!("Adding devices to multicast-group");
         self.job.attempt_count += 1;
 
+        let has_deltas = self.artifacts.iter().any(|a| !a.base_version.is_empty());
+
         let fuota_devices = fuota::get_devices(self.job.fuota_deployment_id.into(), -1, 0).await?;
-        for fuota_d in fuota_devices {
+        for mut fuota_d in fuota_devices {
+            // Select the image for the device, based on its base version.
+            let artifact = fuota::select_artifact(&self.artifacts, &fuota_d.base_version);
+            fuota_d.target_version = self.fuota_deployment.payload_version.clone();
+            fuota_d.full_image_required =
+                has_deltas && artifact.map(|a| a.base_version.is_empty()).unwrap_or(true);
+
+            if artifact.is_none() {
+                fuota_d.error_msg = "Full image required.".into();
+                let fuota_d = fuota::update_device(fuota_d).await?;
+                events::device_event(&fuota_d, FuotaEventType::DeviceFailed).await;
+                continue;
+            }
+
+            let fuota_d = fuota::update_device(fuota_d).await?;
             multicast::add_device(&fuota_d.fuota_deployment_id, &fuota_d.dev_eui).await?;
         }
 
@@ -198,16 +222,17 @@ impl Flow {
 
     async fn multicast_group_setup(&mut self) -> Result<Option<(FuotaJob, DateTime<Utc>)>> {
         let fuota_devices = fuota::get_devices(self.job.fuota_deployment_id.into(), -1, 0).await?;
-        // Filter on devices that have not completed the McGroupSetup.
+        // Filter on devices that have not completed the McGroupSetup. Devices for which no image
+        // is available have already been marked as failed.
         let fuota_devices: Vec<fuota::FuotaDeploymentDevice> = fuota_devices
             .into_iter()
-            .filter(|d| d.mc_group_setup_completed_at.is_none())
+            .filter(|d| d.mc_group_setup_completed_at.is_none() && d.error_msg.is_empty())
             .collect();
 
         // Proceed with next step after reaching the max attempts.
         if self.job.attempt_count > self.job.max_retry_count {
             info!("Set timeout error to devices that did not respond to McGroupSetupReq");
-            fuota::set_device_timeout_error(
+            let timeout_devices = fuota::set_device_timeout_error(
                 self.fuota_deployment.id.into(),
                 true,
                 false,
@@ -215,6 +240,9 @@ impl Flow {
                 false,
             )
             .await?;
+            for fuota_dev in &timeout_devices {
+                events::device_event(fuota_dev, FuotaEventType::DeviceFailed).await;
+            }
 
             if !fuota_devices.is_empty() {
                 self.job.warning_msg = format!(
@@ -230,7 +258,15 @@ impl Flow {
         self.job.attempt_count += 1;
 
         for fuota_dev in &fuota_devices {
-            let dev_keys = device_keys::get(&fuota_dev.dev_eui).await?;
+            let mut dev_keys = device_keys::get(&fuota_dev.dev_eui).await?;
+            key_access_log::log(
+                &fuota_dev.dev_eui,
+                key_access_log::KeyType::RootKeys,
+                key_access_log::Actor::System,
+                key_access_log::Purpose::Fuota,
+            )
+            .await?;
+            dev_keys.unwrap_root_keys()?;
 
             let pl = match self.device_profile.app_layer_params.ts005_version {
                 Some(Ts005Version::V100) => {
@@ -345,7 +381,7 @@ impl Flow {
         // Proceed with next step after reaching the max attempts.
         if self.job.attempt_count > self.job.max_retry_count {
             info!("Set timeout error to devices that did not respond to FragSessionSetupReq");
-            fuota::set_device_timeout_error(
+            let timeout_devices = fuota::set_device_timeout_error(
                 self.fuota_deployment.id.into(),
                 false,
                 false,
@@ -353,6 +389,9 @@ impl Flow {
                 false,
             )
             .await?;
+            for fuota_dev in &timeout_devices {
+                events::device_event(fuota_dev, FuotaEventType::DeviceFailed).await;
+            }
 
             if !fuota_devices.is_empty() {
                 self.job.warning_msg = format!(
@@ -372,18 +411,21 @@ impl Flow {
         }
 
         let fragment_size = self.fuota_deployment.fragmentation_fragment_size as usize;
-        let fragments =
-            (self.fuota_deployment.payload.len() as f32 / fragment_size as f32).ceil() as usize;
-        let padding =
-            (fragment_size - (self.fuota_deployment.payload.len() % fragment_size)) % fragment_size;
 
         for fuota_dev in &fuota_devices {
+            let artifact = fuota::select_artifact(&self.artifacts, &fuota_dev.base_version)
+                .ok_or_else(|| anyhow!("No image available for device {}", fuota_dev.dev_eui))?;
+            let frag_index = artifact.frag_index;
+            let fragments = (artifact.payload.len() as f32 / fragment_size as f32).ceil() as usize;
+            let padding =
+                (fragment_size - (artifact.payload.len() % fragment_size)) % fragment_size;
+
             let pl = match self.device_profile.app_layer_params.ts004_version {
                 Some(Ts004Version::V100) => fragmentation::v1::Payload::FragSessionSetupReq(
                     fragmentation::v1::FragSessionSetupReqPayload {
                         frag_session: fragmentation::v1::FragSessionSetuReqPayloadFragSession {
                             mc_group_bit_mask: [true, false, false, false],
-                            frag_index: 0,
+                            frag_index,
                         },
                         nb_frag: fragments as u16,
                         frag_size: fragment_size as u8,
@@ -398,9 +440,9 @@ impl Flow {
                 .to_vec()?,
                 Some(Ts004Version::V200) => {
                     let dev = device::get(&fuota_dev.dev_eui).await?;
-                    let session_cnt = dev.app_layer_params.ts004_session_cnt[0];
+                    let session_cnt = dev.app_layer_params.ts004_session_cnt[frag_index as usize];
                     let mut app_layer_params = dev.app_layer_params.clone();
-                    app_layer_params.ts004_session_cnt[0] += 1;
+                    app_layer_params.ts004_session_cnt[frag_index as usize] += 1;
 
                     device::partial_update(
                         fuota_dev.dev_eui,
@@ -411,7 +453,15 @@ impl Flow {
                     )
                     .await?;
 
-                    let dev_keys = device_keys::get(&fuota_dev.dev_eui).await?;
+                    let mut dev_keys = device_keys::get(&fuota_dev.dev_eui).await?;
+                    key_access_log::log(
+                        &fuota_dev.dev_eui,
+                        key_access_log::KeyType::RootKeys,
+                        key_access_log::Actor::System,
+                        key_access_log::Purpose::Fuota,
+                    )
+                    .await?;
+                    dev_keys.unwrap_root_keys()?;
                     let data_block_int_key = match self.device_profile.mac_version {
                         MacVersion::LORAWAN_1_0_0
                         | MacVersion::LORAWAN_1_0_1
@@ -427,16 +477,16 @@ impl Flow {
                     let mic = fragmentation::v2::calculate_mic(
                         data_block_int_key,
                         session_cnt,
-                        0,
+                        frag_index,
                         [0, 0, 0, 0],
-                        &self.fuota_deployment.payload,
+                        &artifact.payload,
                     )?;
 
                     fragmentation::v2::Payload::FragSessionSetupReq(
                         fragmentation::v2::FragSessionSetupReqPayload {
                             frag_session: fragmentation::v2::FragSessionSetuReqPayloadFragSession {
                                 mc_group_bit_mask: [true, false, false, false],
-                                frag_index: 0,
+                                frag_index,
                             },
                             nb_frag: fragments as u16,
                             frag_size: fragment_size as u8,
@@ -494,7 +544,7 @@ impl Flow {
         // Proceed with next step after reaching the max attempts.
         if self.job.attempt_count > self.job.max_retry_count {
             info!("Set timeout error to devices that did not respond to McSessionReq");
-            fuota::set_device_timeout_error(
+            let timeout_devices = fuota::set_device_timeout_error(
                 self.fuota_deployment.id.into(),
                 false,
                 true,
@@ -502,6 +552,9 @@ impl Flow {
                 false,
             )
             .await?;
+            for fuota_dev in &timeout_devices {
+                events::device_event(fuota_dev, FuotaEventType::DeviceFailed).await;
+            }
 
             if !fuota_devices.is_empty() {
                 self.job.warning_msg = format!(
@@ -698,62 +751,72 @@ impl Flow {
             return Ok(Some((FuotaJob::DeleteMcGroup, Utc::now())));
         }
 
-        let payload_length = self.fuota_deployment.payload.len();
+        // Only the images used by the devices that completed the previous step are sent, each
+        // within its own fragmentation-session.
         let fragment_size = self.fuota_deployment.fragmentation_fragment_size as usize;
-        let padding = (fragment_size - (payload_length % fragment_size)) % fragment_size;
-
-        let fragments = (payload_length as f32 / fragment_size as f32).ceil() as usize;
-        let redundancy = (fragments as f32
-            * self.fuota_deployment.fragmentation_redundancy_percentage as f32
-            / 100.0)
-            .ceil() as usize;
-
-        let mut payload = self.fuota_deployment.payload.clone();
-        payload.extend_from_slice(&vec![0; padding]);
-
-        let payloads = match self.device_profile.app_layer_params.ts004_version {
-            Some(Ts004Version::V100) => {
-                let mut payloads = Vec::new();
-                let encoded_fragments =
-                    fragmentation::v1::encode(&payload, fragment_size, redundancy)?;
-                for (i, frag) in encoded_fragments.iter().enumerate() {
-                    payloads.push(
-                        fragmentation::v1::Payload::DataFragment(
-                            fragmentation::v1::DataFragmentPayload {
-                                index_and_n: fragmentation::v1::DataFragmentPayloadIndexAndN {
-                                    frag_index: 0,
-                                    n: (i + 1) as u16,
+        let mut payloads = Vec::new();
+
+        for artifact in &self.artifacts {
+            if !fuota_devices.iter().any(|d| {
+                fuota::select_artifact(&self.artifacts, &d.base_version)
+                    .map(|a| a.frag_index == artifact.frag_index)
+                    .unwrap_or_default()
+            }) {
+                continue;
+            }
+
+            let payload_length = artifact.payload.len();
+            let padding = (fragment_size - (payload_length % fragment_size)) % fragment_size;
+
+            let fragments = (payload_length as f32 / fragment_size as f32).ceil() as usize;
+            let redundancy = (fragments as f32
+                * self.fuota_deployment.fragmentation_redundancy_percentage as f32
+                / 100.0)
+                .ceil() as usize;
+
+            let mut payload = artifact.payload.clone();
+            payload.extend_from_slice(&vec![0; padding]);
+
+            match self.device_profile.app_layer_params.ts004_version {
+                Some(Ts004Version::V100) => {
+                    let encoded_fragments =
+                        fragmentation::v1::encode(&payload, fragment_size, redundancy)?;
+                    for (i, frag) in encoded_fragments.iter().enumerate() {
+                        payloads.push(
+                            fragmentation::v1::Payload::DataFragment(
+                                fragmentation::v1::DataFragmentPayload {
+                                    index_and_n: fragmentation::v1::DataFragmentPayloadIndexAndN {
+                                        frag_index: artifact.frag_index,
+                                        n: (i + 1) as u16,
+                                    },
+                                    data: frag.clone(),
                                 },
-                                data: frag.clone(),
-                            },
-                        )
-                        .to_vec()?,
-                    );
+                            )
+                            .to_vec()?,
+                        );
+                    }
                 }
-                payloads
-            }
-            Some(Ts004Version::V200) => {
-                let mut payloads = Vec::new();
-                let encoded_fragments =
-                    fragmentation::v2::encode(&payload, fragment_size, redundancy)?;
-                for (i, frag) in encoded_fragments.iter().enumerate() {
-                    payloads.push(
-                        fragmentation::v2::Payload::DataFragment(
-                            fragmentation::v2::DataFragmentPayload {
-                                index_and_n: fragmentation::v2::DataFragmentPayloadIndexAndN {
-                                    frag_index: 0,
-                                    n: (i + 1) as u16,
+                Some(Ts004Version::V200) => {
+                    let encoded_fragments =
+                        fragmentation::v2::encode(&payload, fragment_size, redundancy)?;
+                    for (i, frag) in encoded_fragments.iter().enumerate() {
+                        payloads.push(
+                            fragmentation::v2::Payload::DataFragment(
+                                fragmentation::v2::DataFragmentPayload {
+                                    index_and_n: fragmentation::v2::DataFragmentPayloadIndexAndN {
+                                        frag_index: artifact.frag_index,
+                                        n: (i + 1) as u16,
+                                    },
+                                    data: frag.clone(),
                                 },
-                                data: frag.clone(),
-                            },
-                        )
-                        .to_vec()?,
-                    );
+                            )
+                            .to_vec()?,
+                        );
+                    }
                 }
-                payloads
+                None => return Err(anyhow!("Device-profile does not support TS004")),
             }
-            None => return Err(anyhow!("Device-profile does not support TS004")),
-        };
+        }
 
         for pl in payloads {
             let _ = downlink::multicast::enqueue(multicast::MulticastGroupQueueItem {
@@ -765,6 +828,8 @@ impl Flow {
             .await?;
         }
 
+        events::deployment_event(&self.fuota_deployment, FuotaEventType::FragmentsSent).await;
+
         match self.fuota_deployment.request_fragmentation_session_status {
             RequestFragmentationSessionStatus::NoRequest => Ok(Some((
                 FuotaJob::DeleteMcGroup,
@@ -801,7 +866,7 @@ impl Flow {
         // Proceed with next step after reaching the max attempts.
         if self.job.attempt_count > self.job.max_retry_count {
             info!("Set timeout error to devices that did not respond to FragSessionStatusReq");
-            fuota::set_device_timeout_error(
+            let timeout_devices = fuota::set_device_timeout_error(
                 self.fuota_deployment.id.into(),
                 false,
                 false,
@@ -809,6 +874,9 @@ impl Flow {
                 true,
             )
             .await?;
+            for fuota_dev in &timeout_devices {
+                events::device_event(fuota_dev, FuotaEventType::DeviceFailed).await;
+            }
 
             if !fuota_devices.is_empty() {
                 self.job.warning_msg = format!(
@@ -829,18 +897,22 @@ impl Flow {
         }
 
         for fuota_dev in &fuota_devices {
+            let frag_index = fuota::select_artifact(&self.artifacts, &fuota_dev.base_version)
+                .map(|a| a.frag_index)
+                .unwrap_or_default();
+
             let pl = match self.device_profile.app_layer_params.ts004_version {
                 Some(Ts004Version::V100) => fragmentation::v1::Payload::FragSessionStatusReq(
                     fragmentation::v1::FragSessionStatusReqPayload {
                         participants: true,
-                        frag_index: 0,
+                        frag_index,
                     },
                 )
                 .to_vec()?,
                 Some(Ts004Version::V200) => fragmentation::v2::Payload::FragSessionStatusReq(
                     fragmentation::v2::FragSessionStatusReqPayload {
                         participants: true,
-                        frag_index: 0,
+                        frag_index,
                     },
                 )
                 .to_vec()?,
@@ -918,6 +990,14 @@ impl Flow {
                 d.tags.deref_mut().insert(k.to_string(), v.to_string());
             }
             let _ = device::update(d).await?;
+
+            // Without the fragmentation-session status, the devices are only marked as completed
+            // at this point. Otherwise the event was already sent on the FragSessionStatusAns.
+            if self.fuota_deployment.request_fragmentation_session_status
+                == RequestFragmentationSessionStatus::NoRequest
+            {
+                events::device_event(fuota_device, FuotaEventType::DeviceCompleted).await;
+            }
         }
 
         if fuota_devices_count != fuota_devices_completed_count {
@@ -930,6 +1010,16 @@ impl Flow {
         self.fuota_deployment.completed_at = Some(Utc::now());
         self.fuota_deployment = fuota::update_deployment(self.fuota_deployment.clone()).await?;
 
+        let device_counts = fuota::get_device_counts(self.fuota_deployment.id.into()).await?;
+        info!(
+            device_count = device_counts.total,
+            completed_count = device_counts.completed,
+            failed_count = device_counts.failed,
+            pending_count = device_counts.pending,
+            "FUOTA deployment completed"
+        );
+        events::deployment_event(&self.fuota_deployment, FuotaEventType::DeploymentCompleted).await;
+
         Ok(None)
     }
 }
@@ -1,5 +1,6 @@
 use tracing::info;
 
+pub mod events;
 pub mod flow;
 pub mod scheduler;
 
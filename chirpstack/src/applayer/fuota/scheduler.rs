@@ -3,8 +3,9 @@ use tokio::time::sleep;
 use tracing::{error, span, trace, Instrument, Level};
 
 use crate::applayer::fuota::flow;
-use crate::config;
+use crate::helpers::errors::PrintFullError;
 use crate::storage::fuota;
+use crate::{config, coordination};
 
 pub async fn scheduler_loop() {
     let conf = config::get();
@@ -25,15 +26,31 @@ async fn schedule_batch(size: usize) -> Result<()> {
     let jobs = fuota::get_schedulable_jobs(size).await?;
     trace!(job_count = jobs.len(), "Got this number of fuota jobs");
 
+    // The scheduler_run_after claim distributes the jobs over the ChirpStack instances. The
+    // deployment lock is held while the job is being handled, such that an other instance
+    // skips the deployment, also when handling the job takes longer than the claim.
     let mut handles = vec![];
 
     for job in jobs {
         // Spawn the batch as async tasks.
         let handle = tokio::spawn(async move {
             let span = span!(Level::INFO, "job", fuota_deployment_id = %job.fuota_deployment_id, job = %job.job);
-
-            if let Err(e) = flow::Flow::handle_job(job).instrument(span).await {
-                error!(error = %e, "Handle FUOTA job error");
+            let lock_name = format!("fuota:deployment:{}", job.fuota_deployment_id);
+
+            match coordination::with_lock(&lock_name, flow::Flow::handle_job(job))
+                .instrument(span)
+                .await
+            {
+                Ok(Some(Err(e))) => {
+                    error!(error = %e, "Handle FUOTA job error");
+                }
+                Ok(Some(Ok(_))) => {}
+                Ok(None) => {
+                    trace!(lock = %lock_name, "FUOTA deployment is locked by an other scheduler, skipping");
+                }
+                Err(e) => {
+                    error!(error = %e.full(), "Acquire FUOTA deployment lock failed");
+                }
             }
         });
         handles.push(handle);
@@ -2,8 +2,10 @@ use anyhow::Result;
 use chrono::Utc;
 use tracing::{info, warn};
 
+use crate::applayer::fuota::events;
 use crate::storage::fields::device_profile::Ts005Version;
 use crate::storage::{device, device_profile, fuota};
+use chirpstack_api::integration::FuotaEventType;
 use lrwn::applayer::multicastsetup;
 
 pub async fn handle_uplink(
@@ -79,7 +81,10 @@ async fn handle_v1_mc_group_setup_ans(
         fuota_dev.mc_group_setup_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    if !fuota_dev.error_msg.is_empty() {
+        events::device_event(&fuota_dev, FuotaEventType::DeviceFailed).await;
+    }
 
     Ok(())
 }
@@ -103,7 +108,10 @@ async fn handle_v2_mc_group_setup_ans(
         fuota_dev.mc_group_setup_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    if !fuota_dev.error_msg.is_empty() {
+        events::device_event(&fuota_dev, FuotaEventType::DeviceFailed).await;
+    }
 
     Ok(())
 }
@@ -140,7 +148,16 @@ async fn handle_v1_mc_class_b_session_ans(
         fuota_dev.mc_session_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceSetupCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
@@ -180,7 +197,16 @@ async fn handle_v2_mc_class_b_session_ans(
         fuota_dev.mc_session_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceSetupCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
@@ -217,7 +243,16 @@ async fn handle_v1_mc_class_c_session_ans(
         fuota_dev.mc_session_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceSetupCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
@@ -257,7 +292,16 @@ async fn handle_v2_mc_class_c_session_ans(
         fuota_dev.mc_session_completed_at = Some(Utc::now());
     }
 
-    let _ = fuota::update_device(fuota_dev).await?;
+    let fuota_dev = fuota::update_device(fuota_dev).await?;
+    events::device_event(
+        &fuota_dev,
+        if fuota_dev.error_msg.is_empty() {
+            FuotaEventType::DeviceSetupCompleted
+        } else {
+            FuotaEventType::DeviceFailed
+        },
+    )
+    .await;
 
     Ok(())
 }
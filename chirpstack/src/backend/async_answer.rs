@@ -0,0 +1,275 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use tokio::time::sleep;
+use tracing::{error, info, span, trace, warn, Instrument, Level};
+
+use crate::backend::{joinserver, roaming};
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::backend_async_answer;
+use backend::{BasePayloadResultProvider, Client, Role};
+use lrwn::{NetID, EUI64};
+
+pub async fn setup() {
+    info!("Setting up backend async answer scheduler loop");
+    tokio::spawn(scheduler_loop());
+}
+
+// Send sends the given async answer to the receiver. In case this fails, the answer is stored
+// so that it will be re-sent by the scheduler loop, using an exponential backoff.
+pub async fn send<S>(client: &Client, target_role: Role, pl: &S) -> Result<()>
+where
+    S: Serialize + BasePayloadResultProvider,
+{
+    let conf = config::get();
+    let bp = &pl.base_payload().base;
+    let body = serde_json::to_string(pl)?;
+
+    info!(receiver_id = %hex::encode(&bp.receiver_id), transaction_id = bp.transaction_id, message_type = ?bp.message_type, "Sending async answer");
+
+    if let Err(e) = client.send_answer(Some(target_role), body.clone()).await {
+        if conf.roaming.async_answer.max_retry_count == 0 {
+            return Err(e);
+        }
+
+        warn!(error = %e.full(), transaction_id = bp.transaction_id, "Sending async answer failed, scheduling retry");
+        backend_async_answer::create(backend_async_answer::BackendAsyncAnswer {
+            receiver_id: bp.receiver_id.clone(),
+            target_role: target_role.into(),
+            transaction_id: bp.transaction_id.into(),
+            message_type: format!("{:?}", bp.message_type),
+            payload: body,
+            scheduler_run_after: Utc::now()
+                + chrono::Duration::from_std(get_retry_interval(&conf.roaming.async_answer, 0))?,
+            error_msg: e.full(),
+            ..Default::default()
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn scheduler_loop() {
+    let conf = config::get();
+
+    loop {
+        trace!("Starting backend async answer scheduler_loop run");
+        if let Err(err) = schedule_batch(conf.network.scheduler.batch_size).await {
+            error!(error = %err.full(), "Scheduling backend async answer batch error");
+        } else {
+            trace!("schedule_batch completed without error");
+        }
+        sleep(conf.network.scheduler.interval).await;
+    }
+}
+
+async fn schedule_batch(size: usize) -> Result<()> {
+    trace!("Get schedulable backend async answers");
+    let items = backend_async_answer::get_schedulable(size).await?;
+    trace!(
+        answer_count = items.len(),
+        "Got this number of backend async answers"
+    );
+
+    let mut handles = vec![];
+
+    for item in items {
+        // Spawn the batch as async tasks.
+        let handle = tokio::spawn(async move {
+            let span = span!(Level::INFO, "async_answer", receiver_id = %hex::encode(&item.receiver_id), transaction_id = item.transaction_id, message_type = %item.message_type);
+
+            if let Err(e) = retry(item).instrument(span).await {
+                error!(error = %e.full(), "Retry backend async answer error");
+            }
+        });
+        handles.push(handle);
+    }
+
+    futures::future::join_all(handles).await;
+
+    Ok(())
+}
+
+async fn retry(mut a: backend_async_answer::BackendAsyncAnswer) -> Result<()> {
+    let conf = config::get();
+
+    let res = match get_client(&a.receiver_id).await {
+        Ok(client) => {
+            client
+                .send_answer(Some(a.target_role.into()), a.payload.clone())
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    a.attempt_count += 1;
+
+    match res {
+        Ok(_) => {
+            info!(attempt_count = a.attempt_count, "Async answer delivered");
+            backend_async_answer::delete(&a.id.into()).await?;
+        }
+        Err(e) => {
+            if a.attempt_count as u32 >= conf.roaming.async_answer.max_retry_count {
+                error!(error = %e.full(), attempt_count = a.attempt_count, "Sending async answer failed, max. retry count reached");
+                backend_async_answer::delete(&a.id.into()).await?;
+            } else {
+                warn!(error = %e.full(), attempt_count = a.attempt_count, "Sending async answer failed, scheduling retry");
+                a.scheduler_run_after = Utc::now()
+                    + chrono::Duration::from_std(get_retry_interval(
+                        &conf.roaming.async_answer,
+                        a.attempt_count as u32,
+                    ))?;
+                a.error_msg = e.full();
+                backend_async_answer::update(a).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The ReceiverID of the answer is the SenderID of the request, which is either a JoinEUI
+// (Join Server) or a NetID (roaming partner).
+async fn get_client(receiver_id: &[u8]) -> Result<Arc<Client>> {
+    match receiver_id.len() {
+        8 => joinserver::get(EUI64::from_slice(receiver_id)?).await,
+        3 => roaming::get(&NetID::from_slice(receiver_id)?).await,
+        _ => Err(anyhow!("Invalid ReceiverID length")),
+    }
+}
+
+// This returns the interval before the next retry, given the number of failed retries.
+fn get_retry_interval(conf: &config::RoamingAsyncAnswer, attempt_count: u32) -> Duration {
+    conf.retry_interval
+        .saturating_mul(2_u32.saturating_pow(attempt_count))
+        .min(conf.max_retry_interval)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+    use httpmock::prelude::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_get_retry_interval() {
+        let conf = config::RoamingAsyncAnswer {
+            max_retry_count: 5,
+            retry_interval: Duration::from_secs(1),
+            max_retry_interval: Duration::from_secs(60),
+        };
+
+        assert_eq!(Duration::from_secs(1), get_retry_interval(&conf, 0));
+        assert_eq!(Duration::from_secs(2), get_retry_interval(&conf, 1));
+        assert_eq!(Duration::from_secs(32), get_retry_interval(&conf, 5));
+        assert_eq!(Duration::from_secs(60), get_retry_interval(&conf, 6));
+        assert_eq!(Duration::from_secs(60), get_retry_interval(&conf, 100));
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let _guard = test::prepare().await;
+
+        let server = MockServer::start();
+
+        let mut conf = (*config::get()).clone();
+        conf.roaming.servers.push(config::RoamingServer {
+            net_id: NetID::from_str("010203").unwrap(),
+            server: server.url("/"),
+            ..Default::default()
+        });
+        conf.roaming.async_answer = config::RoamingAsyncAnswer {
+            max_retry_count: 2,
+            retry_interval: Duration::from_secs(0),
+            max_retry_interval: Duration::from_secs(0),
+        };
+        config::set(conf);
+        roaming::setup().await.unwrap();
+
+        let client = roaming::get(&NetID::from_str("010203").unwrap())
+            .await
+            .unwrap();
+
+        let ans = backend::PRStartAnsPayload {
+            base: backend::BasePayloadResult {
+                base: backend::BasePayload {
+                    sender_id: vec![4, 5, 6],
+                    receiver_id: vec![1, 2, 3],
+                    message_type: backend::MessageType::PRStartAns,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                result: backend::ResultPayload {
+                    result_code: backend::ResultCode::Success,
+                    description: "".into(),
+                },
+            },
+            ..Default::default()
+        };
+
+        // Delivered on first attempt.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(serde_json::to_string(&ans).unwrap());
+            then.status(200);
+        });
+        send(&client, Role::FNS, &ans).await.unwrap();
+        mock.assert();
+        mock.delete();
+        assert!(backend_async_answer::get_schedulable(10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // First attempt fails, answer is stored.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(500);
+        });
+        send(&client, Role::FNS, &ans).await.unwrap();
+        mock.assert();
+        mock.delete();
+
+        // Retry succeeds, answer is removed.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .body(serde_json::to_string(&ans).unwrap());
+            then.status(200);
+        });
+        let items = backend_async_answer::get_schedulable(10).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(1234, items[0].transaction_id);
+        assert_eq!("PRStartAns", items[0].message_type);
+        let id = items[0].id;
+        retry(items[0].clone()).await.unwrap();
+        mock.assert();
+        mock.delete();
+        assert!(backend_async_answer::test::get(&id.into()).await.is_err());
+
+        // All attempts fail, answer is removed after max. retry count.
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(500);
+        });
+        send(&client, Role::FNS, &ans).await.unwrap();
+        let items = backend_async_answer::get_schedulable(10).await.unwrap();
+        let id = items[0].id;
+        retry(items[0].clone()).await.unwrap();
+        let a = backend_async_answer::test::get(&id.into()).await.unwrap();
+        assert_eq!(1, a.attempt_count);
+        assert!(!a.error_msg.is_empty());
+        retry(a).await.unwrap();
+        mock.assert_hits(3);
+        mock.delete();
+        assert!(backend_async_answer::test::get(&id.into()).await.is_err());
+    }
+}
@@ -1,25 +1,51 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use tokio::sync::RwLock;
-use tracing::info;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info};
 
+use crate::helpers::errors::PrintFullError;
+use crate::storage::join_server_route;
 use crate::{config, stream};
 use backend::{Client, ClientConfig};
 use lrwn::{EUI64Prefix, EUI64};
 
 lazy_static! {
     static ref CLIENTS: RwLock<Vec<(EUI64Prefix, Arc<Client>)>> = RwLock::new(vec![]);
+    // Serializes the reloads, such that a reload reading older routes can not overwrite the
+    // clients of a reload reading newer routes.
+    static ref RELOAD_LOCK: Mutex<()> = Mutex::new(());
 }
 
 pub async fn setup() -> Result<()> {
     info!("Setting up Join Server clients");
+    reload().await
+}
+
+// Reload (re-)configures the Join Server clients using the Join Server routes stored in the
+// database and the Join Servers of the configuration file. The routes are matched first, the
+// most specific JoinEUI prefix first. This is called on setup and each time the Join Server
+// routes are modified.
+pub async fn reload() -> Result<()> {
+    let _lock = RELOAD_LOCK.lock().await;
+
     let conf = config::get();
+    let mut servers: Vec<config::JoinServerServer> = Vec::new();
 
-    let mut clients_w = CLIENTS.write().await;
-    *clients_w = vec![];
+    for r in join_server_route::get_all().await? {
+        match config::JoinServerServer::try_from(&r) {
+            Ok(v) => servers.push(v),
+            Err(e) => {
+                error!(id = %r.id, error = %e.full(), "Invalid Join Server route");
+            }
+        }
+    }
+    servers.sort_by_key(|v| std::cmp::Reverse(v.join_eui_prefix.size()));
+    servers.extend(conf.join_server.servers.iter().cloned());
+
+    let mut clients: Vec<(EUI64Prefix, Arc<Client>)> = Vec::new();
 
-    for js in &conf.join_server.servers {
+    for js in &servers {
         info!(join_eui_prefix = %js.join_eui_prefix, "Configuring Join Server");
 
         let c = Client::new(ClientConfig {
@@ -28,14 +54,34 @@ pub async fn setup() -> Result<()> {
             ca_cert: js.ca_cert.clone(),
             tls_cert: js.tls_cert.clone(),
             tls_key: js.tls_key.clone(),
+            authorization: if js.authorization_header.is_empty() {
+                None
+            } else {
+                Some(js.authorization_header.clone())
+            },
+            oauth2: super::get_oauth2_config(
+                &js.oauth2_token_url,
+                &js.oauth2_client_id,
+                &js.oauth2_client_secret,
+                &js.oauth2_scope,
+            ),
+            api_key: super::get_api_key_config(&js.api_key_header, &js.api_key),
+            jws: super::get_jws_config(
+                &js.jws_algorithm,
+                &js.jws_signing_key,
+                &js.jws_verification_key,
+            ),
             async_timeout: js.async_timeout,
             request_log_sender: stream::backend_interfaces::get_log_sender().await,
             ..Default::default()
         })?;
 
-        clients_w.push((js.join_eui_prefix, Arc::new(c)));
+        clients.push((js.join_eui_prefix, Arc::new(c)));
     }
 
+    let mut clients_w = CLIENTS.write().await;
+    *clients_w = clients;
+
     Ok(())
 }
 
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use tracing::trace;
 
-use crate::config;
+use crate::{config, hsm};
 use backend::KeyEnvelope;
 use lrwn::AES128Key;
 
@@ -38,3 +38,52 @@ pub fn wrap(label: &str, key: AES128Key) -> Result<KeyEnvelope> {
 
     Err(anyhow!("KEK label {} does not exist", label))
 }
+
+// Unwraps the given (RFC 3394) wrapped device root-key. The KEK is looked up in the KEK
+// configuration first. If the KEK label is not configured, the KEK must be provisioned in the HSM.
+pub fn unwrap_root_key(label: &str, wrapped: &[u8]) -> Result<AES128Key> {
+    trace!(kek_label = %label, "Unwrapping root-key");
+    let conf = config::get();
+
+    for kek in &conf.keks {
+        if kek.label == *label {
+            let ke = KeyEnvelope {
+                kek_label: label.to_string(),
+                aes_key: wrapped.to_vec(),
+            };
+            let key = ke.unwrap(&kek.kek.to_bytes())?;
+            return Ok(AES128Key::from_bytes(key));
+        }
+    }
+
+    if hsm::enabled() {
+        return hsm::unwrap_key(label, wrapped);
+    }
+
+    Err(anyhow!("KEK label {} does not exist", label))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_unwrap_root_key() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.keks = vec![config::Kek {
+            label: "se-kek".into(),
+            kek: AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]),
+        }];
+        config::set(conf);
+
+        let key = AES128Key::from_bytes([8, 7, 6, 5, 4, 3, 2, 1, 8, 7, 6, 5, 4, 3, 2, 1]);
+        let ke = wrap("se-kek", key).unwrap();
+        assert_eq!(24, ke.aes_key.len());
+
+        assert_eq!(key, unwrap_root_key("se-kek", &ke.aes_key).unwrap());
+        assert!(unwrap_root_key("unknown-kek", &ke.aes_key).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 
+pub mod async_answer;
 pub mod joinserver;
 pub mod keywrap;
 pub mod roaming;
@@ -10,3 +11,52 @@ pub async fn setup() -> Result<()> {
 
     Ok(())
 }
+
+// Returns the OAuth2 client-credentials configuration, or None in case no token URL is
+// configured.
+pub fn get_oauth2_config(
+    token_url: &str,
+    client_id: &str,
+    client_secret: &str,
+    scope: &str,
+) -> Option<backend::OAuth2Config> {
+    if token_url.is_empty() {
+        return None;
+    }
+
+    Some(backend::OAuth2Config {
+        token_url: token_url.to_string(),
+        client_id: client_id.to_string(),
+        client_secret: client_secret.to_string(),
+        scope: scope.to_string(),
+    })
+}
+
+// Returns the API-key configuration, or None in case no API-key header is configured.
+pub fn get_api_key_config(header: &str, key: &str) -> Option<backend::ApiKeyConfig> {
+    if header.is_empty() {
+        return None;
+    }
+
+    Some(backend::ApiKeyConfig {
+        header: header.to_string(),
+        key: key.to_string(),
+    })
+}
+
+// Returns the JWS configuration, or None in case no JWS algorithm is configured.
+pub fn get_jws_config(
+    algorithm: &str,
+    signing_key: &str,
+    verification_key: &str,
+) -> Option<backend::JwsConfig> {
+    if algorithm.is_empty() {
+        return None;
+    }
+
+    Some(backend::JwsConfig {
+        algorithm: algorithm.to_string(),
+        signing_key: signing_key.to_string(),
+        verification_key: verification_key.to_string(),
+    })
+}
@@ -5,25 +5,139 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use chrono::{Duration, DurationRound};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::Histogram;
 use prost::Message;
-use tokio::sync::RwLock;
-use tracing::{debug, info, span, Level};
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, span, Level};
 
+use crate::gateway::airtime;
 use crate::gpstime::ToGpsTime;
+use crate::helpers::errors::PrintFullError;
+use crate::monitoring::prometheus;
+use crate::storage::{fields, roaming_agreement, roaming_usage};
 use crate::{config, stream};
-use backend::{Client, ClientConfig, GWInfoElement, ULMetaData};
-use chirpstack_api::{common, gw};
+use backend::{Client, ClientConfig, GWInfoElement, RateLimiter, ULMetaData};
+use chirpstack_api::{common, gw, stream as stream_pb};
 use lrwn::{region, DevAddr, NetID, EUI64};
 
+// Minimum MAC-layer overhead (MHDR, FHDR without FOpts, FPort and MIC) of a data frame. This
+// is used to estimate the airtime in case only the FRMPayload is known (handover-roaming).
+pub const DATA_FRAME_OVERHEAD: usize = 13;
+
 lazy_static! {
     static ref CLIENTS: RwLock<HashMap<NetID, Arc<Client>>> = RwLock::new(HashMap::new());
+    static ref SERVERS: std::sync::RwLock<Vec<config::RoamingServer>> =
+        std::sync::RwLock::new(Vec::new());
+    static ref INBOUND_RATE_LIMITERS: RwLock<HashMap<NetID, Arc<RateLimiter>>> =
+        RwLock::new(HashMap::new());
+    // Serializes the reloads, such that a reload reading older roaming agreements can not
+    // overwrite the clients of a reload reading newer roaming agreements.
+    static ref RELOAD_LOCK: Mutex<()> = Mutex::new(());
+    static ref REQUEST_COUNTER: Family<RequestLabels, Counter> = {
+        let counter = Family::<RequestLabels, Counter>::default();
+        prometheus::register(
+            "roaming_requests",
+            "Number of roaming requests by NetID, direction, message-type and result-code",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref REQUEST_HISTOGRAM: Family<RequestDurationLabels, Histogram> = {
+        let histogram = Family::<RequestDurationLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(
+                [
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ]
+                .into_iter(),
+            )
+        });
+        prometheus::register(
+            "roaming_requests_seconds",
+            "Duration of roaming requests by NetID, direction and message-type",
+            histogram.clone(),
+        );
+        histogram
+    };
+    static ref RATE_LIMITED_COUNTER: Family<RateLimitedLabels, Counter> = {
+        let counter = Family::<RateLimitedLabels, Counter>::default();
+        prometheus::register(
+            "roaming_requests_rate_limited",
+            "Number of roaming requests rejected by the rate limiter by NetID and direction",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Direction::Inbound => write!(f, "inbound"),
+            Direction::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct RequestLabels {
+    net_id: String,
+    direction: String,
+    message_type: String,
+    result_code: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct RequestDurationLabels {
+    net_id: String,
+    direction: String,
+    message_type: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct RateLimitedLabels {
+    net_id: String,
+    direction: String,
 }
 
 pub async fn setup() -> Result<()> {
     info!("Setting up roaming clients");
+    reload().await
+}
+
+// Reload (re-)configures the roaming clients using the roaming servers of the configuration
+// file and the roaming agreements stored in the database. This is called on setup and each time
+// the roaming agreements are modified.
+pub async fn reload() -> Result<()> {
+    let _lock = RELOAD_LOCK.lock().await;
+
     let conf = config::get();
+    let mut servers = conf.roaming.servers.clone();
+    let mut clients: HashMap<NetID, Arc<Client>> = HashMap::new();
+
+    for ra in roaming_agreement::get_all().await? {
+        let s = match config::RoamingServer::try_from(&ra) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(id = %ra.id, error = %e.full(), "Invalid roaming agreement");
+                continue;
+            }
+        };
 
-    for s in &conf.roaming.servers {
+        servers.retain(|v| v.net_id != s.net_id);
+        servers.push(s);
+    }
+
+    for s in &servers {
         let span = span!(Level::INFO, "setup", net_id  = %s.net_id);
         let _guard = span.enter();
 
@@ -56,16 +170,44 @@ pub async fn setup() -> Result<()> {
             } else {
                 Some(s.authorization_header.clone())
             },
+            oauth2: super::get_oauth2_config(
+                &s.oauth2_token_url,
+                &s.oauth2_client_id,
+                &s.oauth2_client_secret,
+                &s.oauth2_scope,
+            ),
+            api_key: super::get_api_key_config(&s.api_key_header, &s.api_key),
+            jws: super::get_jws_config(
+                &s.jws_algorithm,
+                &s.jws_signing_key,
+                &s.jws_verification_key,
+            ),
             async_timeout: s.async_timeout,
-            request_log_sender: stream::backend_interfaces::get_log_sender().await,
+            request_log_sender: get_request_log_sender(),
+            max_requests_per_second: s.max_requests_per_second,
         })?;
 
-        set(&s.net_id, c).await;
+        clients.insert(s.net_id, Arc::new(c));
     }
 
+    *CLIENTS.write().await = clients;
+    INBOUND_RATE_LIMITERS.write().await.clear();
+    *SERVERS.write().unwrap() = servers;
+
     Ok(())
 }
 
+// Returns the roaming server (either from the configuration file or a roaming agreement) for
+// the given NetID.
+fn get_server(net_id: NetID) -> Option<config::RoamingServer> {
+    SERVERS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|s| s.net_id == net_id)
+        .cloned()
+}
+
 pub async fn set(net_id: &NetID, c: Client) {
     let mut clients_w = CLIENTS.write().await;
     clients_w.insert(*net_id, Arc::new(c));
@@ -104,8 +246,24 @@ pub async fn get(net_id: &NetID) -> Result<Arc<Client>> {
             } else {
                 Some(conf.roaming.default.authorization_header.clone())
             },
+            oauth2: super::get_oauth2_config(
+                &conf.roaming.default.oauth2_token_url,
+                &conf.roaming.default.oauth2_client_id,
+                &conf.roaming.default.oauth2_client_secret,
+                &conf.roaming.default.oauth2_scope,
+            ),
+            api_key: super::get_api_key_config(
+                &conf.roaming.default.api_key_header,
+                &conf.roaming.default.api_key,
+            ),
+            jws: super::get_jws_config(
+                &conf.roaming.default.jws_algorithm,
+                &conf.roaming.default.jws_signing_key,
+                &conf.roaming.default.jws_verification_key,
+            ),
             async_timeout: conf.roaming.default.async_timeout,
-            request_log_sender: stream::backend_interfaces::get_log_sender().await,
+            request_log_sender: get_request_log_sender(),
+            max_requests_per_second: conf.roaming.default.max_requests_per_second,
         })?;
 
         let c = Arc::new(c);
@@ -122,14 +280,11 @@ pub async fn get(net_id: &NetID) -> Result<Arc<Client>> {
 }
 
 pub fn get_passive_roaming_lifetime(net_id: NetID) -> Result<std::time::Duration> {
-    let conf = config::get();
-
-    for s in &conf.roaming.servers {
-        if s.net_id == net_id {
-            return Ok(s.passive_roaming_lifetime);
-        }
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.passive_roaming_lifetime);
     }
 
+    let conf = config::get();
     if conf.roaming.default.enabled {
         return Ok(conf.roaming.default.passive_roaming_lifetime);
     }
@@ -141,12 +296,8 @@ pub fn get_passive_roaming_lifetime(net_id: NetID) -> Result<std::time::Duration
 }
 
 pub fn get_passive_roaming_kek_label(net_id: NetID) -> Result<String> {
-    let conf = config::get();
-
-    for s in &conf.roaming.servers {
-        if s.net_id == net_id {
-            return Ok(s.passive_roaming_kek_label.clone());
-        }
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.passive_roaming_kek_label);
     }
 
     Err(anyhow!(
@@ -156,14 +307,11 @@ pub fn get_passive_roaming_kek_label(net_id: NetID) -> Result<String> {
 }
 
 pub fn get_passive_roaming_validate_mic(net_id: NetID) -> Result<bool> {
-    let conf = config::get();
-
-    for s in &conf.roaming.servers {
-        if s.net_id == net_id {
-            return Ok(s.passive_roaming_validate_mic);
-        }
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.passive_roaming_validate_mic);
     }
 
+    let conf = config::get();
     if conf.roaming.default.enabled {
         return Ok(conf.roaming.default.passive_roaming_validate_mic);
     }
@@ -174,9 +322,265 @@ pub fn get_passive_roaming_validate_mic(net_id: NetID) -> Result<bool> {
     ))
 }
 
+pub fn get_handover_roaming_lifetime(net_id: NetID) -> Result<std::time::Duration> {
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.handover_roaming_lifetime);
+    }
+
+    let conf = config::get();
+    if conf.roaming.default.enabled {
+        return Ok(conf.roaming.default.handover_roaming_lifetime);
+    }
+
+    Err(anyhow!(
+        "Handover-roaming lifetime for net_id {} does not exist",
+        net_id
+    ))
+}
+
+pub fn get_handover_roaming_kek_label(net_id: NetID) -> Result<String> {
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.handover_roaming_kek_label);
+    }
+
+    let conf = config::get();
+    if conf.roaming.default.enabled {
+        return Ok(conf.roaming.default.handover_roaming_kek_label.clone());
+    }
+
+    Err(anyhow!(
+        "Handover-roaming kek-label for net_id {} does not exist",
+        net_id
+    ))
+}
+
+pub fn get_use_handover_roaming(net_id: NetID) -> Result<bool> {
+    if let Some(s) = get_server(net_id) {
+        return Ok(s.use_handover_roaming);
+    }
+
+    let conf = config::get();
+    if conf.roaming.default.enabled {
+        return Ok(conf.roaming.default.use_handover_roaming);
+    }
+
+    Err(anyhow!(
+        "Use handover-roaming for net_id {} does not exist",
+        net_id
+    ))
+}
+
+pub fn get_max_requests_per_second(net_id: NetID) -> u32 {
+    if let Some(s) = get_server(net_id) {
+        return s.max_requests_per_second;
+    }
+
+    config::get().roaming.default.max_requests_per_second
+}
+
+// Returns the NetID label for the roaming metrics. To bound the cardinality of the metrics, only
+// the NetIDs of the configured roaming servers are used as label, all other NetIDs are aggregated
+// under the "default" label.
+fn net_id_label(net_id: NetID) -> String {
+    if SERVERS.read().unwrap().iter().any(|s| s.net_id == net_id) {
+        net_id.to_string()
+    } else {
+        "default".to_string()
+    }
+}
+
+// Returns true if the inbound request from the given NetID is allowed by the rate limiter.
+pub async fn inbound_rate_limit_allow(net_id: NetID) -> bool {
+    let rate_limiter = {
+        let limiters_r = INBOUND_RATE_LIMITERS.read().await;
+        limiters_r.get(&net_id).cloned()
+    };
+
+    let rate_limiter = match rate_limiter {
+        Some(v) => v,
+        None => {
+            let mut limiters_w = INBOUND_RATE_LIMITERS.write().await;
+            limiters_w
+                .entry(net_id)
+                .or_insert_with(|| Arc::new(RateLimiter::new(get_max_requests_per_second(net_id))))
+                .clone()
+        }
+    };
+
+    let allow = rate_limiter.allow();
+    if !allow {
+        RATE_LIMITED_COUNTER
+            .get_or_create(&RateLimitedLabels {
+                net_id: net_id_label(net_id),
+                direction: Direction::Inbound.to_string(),
+            })
+            .inc();
+    }
+    allow
+}
+
+pub fn log_request_result(
+    net_id: NetID,
+    direction: Direction,
+    message_type: backend::MessageType,
+    result_code: &str,
+) {
+    REQUEST_COUNTER
+        .get_or_create(&RequestLabels {
+            net_id: net_id_label(net_id),
+            direction: direction.to_string(),
+            message_type: format!("{:?}", message_type),
+            result_code: result_code.to_string(),
+        })
+        .inc();
+}
+
+pub fn log_request_duration(
+    net_id: NetID,
+    direction: Direction,
+    message_type: backend::MessageType,
+    duration: std::time::Duration,
+) {
+    REQUEST_HISTOGRAM
+        .get_or_create(&RequestDurationLabels {
+            net_id: net_id_label(net_id),
+            direction: direction.to_string(),
+            message_type: format!("{:?}", message_type),
+        })
+        .observe(duration.as_secs_f64());
+}
+
+// Returns the request-log sender for the roaming clients. Besides (optionally) logging the
+// requests to the Redis stream, this records the metrics of the outbound requests.
+fn get_request_log_sender() -> Option<Sender<stream_pb::BackendInterfacesRequest>> {
+    let (tx, mut rx) = mpsc::channel(100);
+
+    tokio::spawn(async move {
+        while let Some(pl) = rx.recv().await {
+            log_outbound_request(&pl);
+
+            let conf = config::get();
+            if conf.monitoring.backend_interfaces_log_max_history == 0 {
+                continue;
+            }
+
+            tokio::spawn(async move {
+                if let Err(e) = stream::backend_interfaces::log_request(pl).await {
+                    error!(error = %e, "Log request error");
+                }
+            });
+        }
+    });
+
+    Some(tx)
+}
+
+fn log_outbound_request(pl: &stream_pb::BackendInterfacesRequest) {
+    let net_id = match NetID::from_str(&pl.receiver_id) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let labels = RequestDurationLabels {
+        net_id: net_id_label(net_id),
+        direction: Direction::Outbound.to_string(),
+        message_type: pl.message_type.clone(),
+    };
+
+    // Requests rejected by the rate limiter are never sent.
+    if pl.request_error == backend::RATE_LIMIT_EXCEEDED {
+        RATE_LIMITED_COUNTER
+            .get_or_create(&RateLimitedLabels {
+                net_id: labels.net_id,
+                direction: labels.direction,
+            })
+            .inc();
+        return;
+    }
+
+    REQUEST_COUNTER
+        .get_or_create(&RequestLabels {
+            net_id: labels.net_id.clone(),
+            direction: labels.direction.clone(),
+            message_type: labels.message_type.clone(),
+            result_code: if pl.result_code.is_empty() {
+                "Error".to_string()
+            } else {
+                pl.result_code.clone()
+            },
+        })
+        .inc();
+
+    if let Some(duration) = &pl.duration {
+        let duration = std::time::Duration::new(duration.seconds as u64, duration.nanos as u32);
+        REQUEST_HISTOGRAM
+            .get_or_create(&labels)
+            .observe(duration.as_secs_f64());
+    }
+}
+
+// Logs the uplink as roaming usage for the given roaming partner. The airtime is calculated
+// using the given modulation and PHYPayload size.
+pub async fn log_uplink_usage(
+    net_id: NetID,
+    role: fields::RoamingRole,
+    dev_eui: Option<EUI64>,
+    dev_addr: DevAddr,
+    modulation: Option<&gw::Modulation>,
+    size: usize,
+) {
+    let airtime = modulation
+        .and_then(|v| airtime::get_airtime(v, size))
+        .unwrap_or_default();
+
+    log_usage(roaming_usage::RoamingUsage {
+        net_id: net_id.to_string(),
+        role,
+        dev_eui: dev_eui.unwrap_or_default(),
+        dev_addr,
+        uplink_count: 1,
+        uplink_airtime_ms: airtime.as_millis() as i64,
+        ..Default::default()
+    })
+    .await;
+}
+
+// Logs the downlink as roaming usage for the given roaming partner. The airtime is calculated
+// using the given modulation and PHYPayload size.
+pub async fn log_downlink_usage(
+    net_id: NetID,
+    role: fields::RoamingRole,
+    dev_eui: Option<EUI64>,
+    dev_addr: DevAddr,
+    modulation: Option<&gw::Modulation>,
+    size: usize,
+) {
+    let airtime = modulation
+        .and_then(|v| airtime::get_airtime(v, size))
+        .unwrap_or_default();
+
+    log_usage(roaming_usage::RoamingUsage {
+        net_id: net_id.to_string(),
+        role,
+        dev_eui: dev_eui.unwrap_or_default(),
+        dev_addr,
+        downlink_count: 1,
+        downlink_airtime_ms: airtime.as_millis() as i64,
+        ..Default::default()
+    })
+    .await;
+}
+
+// Failing to store the usage must not fail the handling of the uplink or downlink, therefore
+// errors are only logged.
+async fn log_usage(ru: roaming_usage::RoamingUsage) {
+    if let Err(e) = roaming_usage::increment(ru).await {
+        error!(error = %e.full(), "Log roaming usage error");
+    }
+}
+
 pub fn is_enabled() -> bool {
     let conf = config::get();
-    conf.roaming.default.enabled || !conf.roaming.servers.is_empty()
+    conf.roaming.default.enabled || !SERVERS.read().unwrap().is_empty()
 }
 
 pub fn is_roaming_dev_addr(dev_addr: DevAddr) -> bool {
@@ -212,9 +616,8 @@ pub fn is_roaming_dev_addr(dev_addr: DevAddr) -> bool {
 
 pub fn get_net_ids_for_dev_addr(dev_addr: DevAddr) -> Vec<NetID> {
     let mut out: Vec<NetID> = Vec::new();
-    let conf = config::get();
 
-    for agreement in &conf.roaming.servers {
+    for agreement in SERVERS.read().unwrap().iter() {
         if dev_addr.is_net_id(agreement.net_id) {
             out.push(agreement.net_id);
         }
@@ -353,11 +756,49 @@ pub fn dl_meta_data_to_uplink_rx_info(
 pub async fn reset() {
     let mut clients_w = CLIENTS.write().await;
     *clients_w = HashMap::new();
+
+    let mut limiters_w = INBOUND_RATE_LIMITERS.write().await;
+    *limiters_w = HashMap::new();
+
+    *SERVERS.write().unwrap() = Vec::new();
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_inbound_rate_limit() {
+        let _guard = test::prepare().await;
+        reset().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.roaming.servers.push(config::RoamingServer {
+            net_id: NetID::from_be_bytes([1, 2, 3]),
+            max_requests_per_second: 2,
+            ..Default::default()
+        });
+        config::set(conf);
+        setup().await.unwrap();
+
+        let net_id = NetID::from_be_bytes([1, 2, 3]);
+        assert!(inbound_rate_limit_allow(net_id).await);
+        assert!(inbound_rate_limit_allow(net_id).await);
+        assert!(!inbound_rate_limit_allow(net_id).await);
+
+        // Not rate limited.
+        let net_id = NetID::from_be_bytes([3, 2, 1]);
+        for _ in 0..10 {
+            assert!(inbound_rate_limit_allow(net_id).await);
+        }
+
+        // Only configured NetIDs are used as metric label.
+        assert_eq!("010203", net_id_label(NetID::from_be_bytes([1, 2, 3])));
+        assert_eq!("default", net_id_label(NetID::from_be_bytes([3, 2, 1])));
+
+        reset().await;
+    }
 
     #[test]
     fn test_is_roaming_dev_addr() {
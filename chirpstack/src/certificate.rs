@@ -1,19 +1,49 @@
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use rcgen::{
-    Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, KeyPair, KeyUsagePurpose,
-    SignatureAlgorithm,
+    Certificate, CertificateParams, CertificateRevocationListParams, DnType,
+    ExtendedKeyUsagePurpose, KeyIdMethod, KeyPair, KeyUsagePurpose, RevokedCertParams,
+    SerialNumber, SignatureAlgorithm,
 };
 use tokio::fs;
+use tokio::time::sleep;
+use tracing::{error, info};
 use uuid::Uuid;
 
 use crate::config;
+use crate::helpers::errors::PrintFullError;
 use crate::helpers::tls::private_key_to_pkcs8;
+use crate::storage::gateway_certificate;
 use lrwn::EUI64;
 
+pub async fn setup() {
+    let conf = config::get();
+    if conf.gateway.crl_file.is_empty() {
+        return;
+    }
+
+    info!(crl_file = %conf.gateway.crl_file, "Setting up gateway certificate revocation list loop");
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = gateway_certificate::delete_expired().await {
+                error!(error = %e.full(), "Delete expired gateway certificates error");
+            }
+
+            if let Err(e) = publish_gateway_crl().await {
+                error!(error = %e.full(), "Publish gateway certificate revocation list error");
+            }
+
+            sleep(conf.gateway.crl_lifetime / 2).await;
+        }
+    });
+}
+
 fn gen_client_cert(
     id: &str,
+    serial_number: &[u8],
     not_before: SystemTime,
     not_after: SystemTime,
     issuer: &Certificate,
@@ -24,6 +54,7 @@ fn gen_client_cert(
         .distinguished_name
         .push(DnType::CommonName, id.to_string());
     params.use_authority_key_identifier_extension = true;
+    params.serial_number = Some(SerialNumber::from(serial_number.to_vec()));
     params.not_before = not_before.into();
     params.not_after = not_after.into();
     params.key_usages.push(KeyUsagePurpose::DigitalSignature);
@@ -35,6 +66,14 @@ fn gen_client_cert(
     Ok((params.signed_by(&kp, issuer, issuer_key)?, kp))
 }
 
+// Returns a random serial number. The first bit is cleared (the serial number must be positive)
+// and the second bit is set (the encoded length is always 16 bytes).
+fn gen_serial_number() -> Vec<u8> {
+    let mut serial_number: [u8; 16] = rand::random();
+    serial_number[0] = (serial_number[0] & 0x7f) | 0x40;
+    serial_number.to_vec()
+}
+
 async fn get_ca_cert(ca_cert_file: &str, ca_key_file: &str) -> Result<(Certificate, KeyPair)> {
     let ca_cert_s = fs::read_to_string(ca_cert_file)
         .await
@@ -53,7 +92,28 @@ async fn get_ca_cert(ca_cert_file: &str, ca_key_file: &str) -> Result<(Certifica
     Ok((params.self_signed(&ca_key)?, ca_key))
 }
 
-// This returns the CA, certificate and private-key as PEM encoded strings.
+// This returns the (PEM encoded) CA certificate, followed by the certificates of the CA chain.
+async fn get_ca_bundle(ca_cert_file: &str, ca_chain: &[String]) -> Result<String> {
+    let mut out = fs::read_to_string(ca_cert_file)
+        .await
+        .context("Read gateway ca_cert")?;
+
+    for chain_file in ca_chain {
+        let chain_s = fs::read_to_string(chain_file)
+            .await
+            .with_context(|| format!("Read gateway ca_chain file: {}", chain_file))?;
+
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&chain_s);
+    }
+
+    Ok(out)
+}
+
+// This returns the CA, certificate and private-key as PEM encoded strings. The serial number of
+// the issued certificate is stored, so that the certificate can be revoked later.
 pub async fn client_cert_for_gateway_id(
     gateway_id: &EUI64,
 ) -> Result<(SystemTime, String, String, String)> {
@@ -61,10 +121,15 @@ pub async fn client_cert_for_gateway_id(
     let (ca_cert, ca_key) = get_ca_cert(&conf.gateway.ca_cert, &conf.gateway.ca_key)
         .await
         .context("Get CA cert")?;
+    let ca_bundle = get_ca_bundle(&conf.gateway.ca_cert, &conf.gateway.ca_chain)
+        .await
+        .context("Get CA bundle")?;
+    let serial_number = gen_serial_number();
     let not_before = SystemTime::now();
     let not_after = SystemTime::now() + conf.gateway.client_cert_lifetime;
     let (gw_cert, gw_key) = gen_client_cert(
         &gateway_id.to_string(),
+        &serial_number,
         not_before,
         not_after,
         &ca_cert,
@@ -72,12 +137,62 @@ pub async fn client_cert_for_gateway_id(
     )
     .context("Generate client certificate")?;
 
-    Ok((
-        not_after,
-        ca_cert.pem(),
-        gw_cert.pem(),
-        gw_key.serialize_pem(),
-    ))
+    gateway_certificate::create(gateway_certificate::GatewayCertificate {
+        serial_number,
+        gateway_id: *gateway_id,
+        created_at: DateTime::<Utc>::from(not_before),
+        expires_at: DateTime::<Utc>::from(not_after),
+        revoked_at: None,
+    })
+    .await
+    .context("Store gateway certificate")?;
+
+    Ok((not_after, ca_bundle, gw_cert.pem(), gw_key.serialize_pem()))
+}
+
+// This writes the (PEM encoded) revocation list, containing the revoked gateway client
+// certificates that have not yet expired, to the configured crl_file.
+pub async fn publish_gateway_crl() -> Result<()> {
+    let conf = config::get();
+    if conf.gateway.crl_file.is_empty() {
+        return Ok(());
+    }
+
+    let (ca_cert, ca_key) = get_ca_cert(&conf.gateway.ca_cert, &conf.gateway.ca_key)
+        .await
+        .context("Get CA cert")?;
+    let revoked = gateway_certificate::get_revoked()
+        .await
+        .context("Get revoked gateway certificates")?;
+    let revoked_count = revoked.len();
+
+    let now = SystemTime::now();
+    let crl = CertificateRevocationListParams {
+        this_update: now.into(),
+        next_update: (now + conf.gateway.crl_lifetime).into(),
+        crl_number: SerialNumber::from(now.duration_since(UNIX_EPOCH)?.as_secs()),
+        issuing_distribution_point: None,
+        revoked_certs: revoked
+            .into_iter()
+            .map(|c| RevokedCertParams {
+                serial_number: SerialNumber::from(c.serial_number),
+                revocation_time: SystemTime::from(c.revoked_at.unwrap_or(c.created_at)).into(),
+                reason_code: None,
+                invalidity_date: None,
+            })
+            .collect(),
+        key_identifier_method: KeyIdMethod::Sha256,
+    }
+    .signed_by(&ca_cert, &ca_key)
+    .context("Sign certificate revocation list")?;
+
+    fs::write(&conf.gateway.crl_file, crl.pem()?)
+        .await
+        .context("Write certificate revocation list")?;
+
+    info!(crl_file = %conf.gateway.crl_file, revoked_count = revoked_count, "Gateway certificate revocation list published");
+
+    Ok(())
 }
 
 pub async fn client_cert_for_application_id(
@@ -93,6 +208,7 @@ pub async fn client_cert_for_application_id(
     let not_after = SystemTime::now() + conf.integration.mqtt.client.client_cert_lifetime;
     let (app_cert, app_key) = gen_client_cert(
         &application_id.to_string(),
+        &gen_serial_number(),
         not_before,
         not_after,
         &ca_cert,
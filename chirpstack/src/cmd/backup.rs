@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::storage::{self, backup as storage_backup};
+
+pub async fn backup(file: &Path) -> Result<()> {
+    storage::setup().await?;
+
+    let summary = storage_backup::backup(file).await?;
+    println!(
+        "Backup written to {} ({} rows, {} Redis keys)",
+        file.display(),
+        summary.row_count,
+        summary.redis_key_count
+    );
+
+    Ok(())
+}
+
+pub async fn restore(file: &Path) -> Result<()> {
+    storage::setup().await?;
+
+    let summary = storage_backup::restore(file).await?;
+    println!(
+        "Backup restored from {} ({} rows, {} Redis keys)",
+        file.display(),
+        summary.row_count,
+        summary.redis_key_count
+    );
+
+    Ok(())
+}
@@ -4,6 +4,26 @@ use super::super::config;
 
 pub fn run() {
     let template = r#"
+# Secrets.
+#
+# Instead of storing secrets (e.g. passwords) as plaintext in the configuration
+# file, configuration values can reference a secret which is resolved when
+# loading the configuration:
+#
+#   * env://NAME: read from the environment variable NAME
+#   * file:///path/to/file: read from the given file (trailing newlines are removed)
+#   * vault://MOUNT/PATH#FIELD: read the field of the HashiCorp Vault KV (v2)
+#     secret. The Vault address and token are read from the VAULT_ADDR and
+#     VAULT_TOKEN environment variables.
+#   * enc://...: encrypted value, created using the 'encrypt-secret' command.
+#     The (hex encoded) AES-256 key must be set using the CHIRPSTACK_SECRETS_KEY
+#     environment variable.
+#
+# Example:
+#   password="env://MQTT_PASSWORD"
+#
+# Note that the complete value must be a reference.
+
 # Logging configuration
 [logging]
 
@@ -20,6 +40,24 @@ pub fn run() {
   # Log as JSON.
   json={{ logging.json }}
 
+  # Per-device log rate limit.
+  #
+  # This limits the number of log lines and integration log events per device,
+  # such that a single device (e.g. stuck in an error loop) does not flood the
+  # log output and the integrations. Log lines and log events exceeding the
+  # limit within the interval are dropped. The limit is applied per ChirpStack
+  # instance. The max. number of integration log events can be overridden per
+  # application.
+  [logging.device_rate_limit]
+
+    # Max. number of log lines and log events per device within the interval.
+    #
+    # Set this to 0 to disable the rate limit.
+    max_events={{ logging.device_rate_limit.max_events }}
+
+    # Interval.
+    interval="{{ logging.device_rate_limit.interval }}"
+
 
 # PostgreSQL configuration.
 #
@@ -82,6 +120,22 @@ pub fn run() {
   ]
 
 
+# Cache configuration.
+#
+# Device-profiles, applications and application integrations are cached
+# in-memory. When one of these is updated or deleted, all ChirpStack instances
+# sharing the same PostgreSQL database are notified (using LISTEN / NOTIFY) so
+# that they invalidate their cached copy immediately.
+[cache]
+
+  # Cache TTL.
+  #
+  # This defines the max. time that an item is cached. This acts as safeguard
+  # in case an invalidation notification was not received. Set this to 0s to
+  # disable caching.
+  ttl="{{ cache.ttl }}"
+
+
 # Redis configuration.
 [redis]
 
@@ -129,6 +183,57 @@ pub fn run() {
   min_idle_connections={{ redis.min_idle_connections }}
 
 
+  # Redis key TTLs (per object class).
+  [redis.ttl]
+
+    # Device gateway rx-info.
+    #
+    # The TTL of the gateway rx-info (gateway meta-data) of the last uplink
+    # of each device, used for scheduling downlinks. If set to 0, the
+    # device_session_ttl of the network configuration is used.
+    device_gateway_rx_info="{{ redis.ttl.device_gateway_rx_info }}"
+
+    # Device pending mac-commands.
+    #
+    # The TTL of mac-commands that are pending an answer from the device.
+    # If set to 0, the device_session_ttl of the network configuration is used.
+    device_mac_command_pending="{{ redis.ttl.device_mac_command_pending }}"
+
+    # Passive-roaming sessions.
+    #
+    # The TTL of the DevAddr and DevEUI to passive-roaming session indices.
+    # If set to 0, the device_session_ttl of the network configuration is used.
+    passive_roaming_session="{{ redis.ttl.passive_roaming_session }}"
+
+    # Handover-roaming sessions.
+    #
+    # The TTL of handover-roaming sessions without lifetime. If set to 0,
+    # the device_session_ttl of the network configuration is used.
+    handover_roaming_session="{{ redis.ttl.handover_roaming_session }}"
+
+    # Uplink deduplication.
+    #
+    # The TTL of the uplink deduplication sets and locks. If set to 0, this
+    # is set to two times the deduplication_delay of the network
+    # configuration, with a minimum of 200ms.
+    deduplication="{{ redis.ttl.deduplication }}"
+
+    # Backend Interfaces async answer.
+    #
+    # The TTL of the async answers received through the Backend Interfaces
+    # API. This must be greater than 0.
+    backend_async_answer="{{ redis.ttl.backend_async_answer }}"
+
+    # Metrics.
+    #
+    # The TTLs of the aggregated metrics (device, gateway, ...) per
+    # aggregation interval. These must be greater than 0.
+    metrics_minute="{{ redis.ttl.metrics_minute }}"
+    metrics_hour="{{ redis.ttl.metrics_hour }}"
+    metrics_day="{{ redis.ttl.metrics_day }}"
+    metrics_month="{{ redis.ttl.metrics_month }}"
+
+
 # API interface configuration.
 [api]
 
@@ -143,6 +248,108 @@ pub fn run() {
   #   openssl rand -base64 32
   secret="{{ api.secret }}"
 
+  # TLS certificate and key files.
+  #
+  # When set, the API interface (gRPC, gRPC-web, REST and UI) is served over
+  # TLS. Note that this requires HTTP/2 capable clients.
+  tls_cert="{{ api.tls_cert }}"
+  tls_key="{{ api.tls_key }}"
+
+  # CA certificate file for client-certificate authentication (optional).
+  #
+  # When set, API clients can authenticate using a client-certificate signed
+  # by this CA, instead of using a bearer token. Clients not presenting a
+  # client-certificate must still provide a bearer token. This requires the
+  # TLS certificate and key to be set.
+  ca_cert="{{ api.ca_cert }}"
+
+  # Client-certificate mapping.
+  #
+  # This maps the Common Name of the client-certificate to an API key. The
+  # client is authorized as the API key, thus the API key defines the tenant
+  # (or admin role) of the client. Client-certificates of which the Common
+  # Name is not mapped are rejected.
+  #
+  # Example (can be repeated):
+  # [[api.client_certificates]]
+  #
+  #   # Common Name of the client-certificate.
+  #   common_name="m2m-client"
+  #
+  #   # API key ID.
+  #   api_key_id="9bd7d6a6-c7b5-4e4f-8d4b-1b9b1a3b4c5d"
+{{#each api.client_certificates}}
+
+  [[api.client_certificates]]
+    common_name="{{ this.common_name }}"
+    api_key_id="{{ this.api_key_id }}"
+{{/each}}
+
+  # Authentication lockout.
+  #
+  # When enabled, failed password logins are counted per user (email) and
+  # client IP address and failed token validations are counted per client IP
+  # address, such that failures from other sources can not lock out a user. When
+  # the failures can not be registered (e.g. Redis is unavailable), the
+  # authentication is not blocked. Each failed
+  # login is delayed exponentially (base_delay, 2 x base_delay, 4 x base_delay,
+  # ... up to max_delay). Once max_failures is reached within the interval,
+  # the user or client IP is locked out for the lockout duration. The
+  # lockouts are stored in Redis, thus these apply to all ChirpStack instances.
+  # For token validations, each instance re-checks the lockout of a client IP
+  # at most every 5 seconds.
+  #
+  # Alerting:
+  #
+  # Each lockout is published to the "api:stream:auth_lockout" Redis stream
+  # (prefixed by the Redis key_prefix), with the fields kind (login or token),
+  # subject (email/client IP or client IP), failures, lockout_duration_ms and
+  # time. This stream can be consumed to alert on lockouts. Lockouts are also
+  # logged as warning and are counted by the api_auth_lockout_count metric
+  # (labeled by kind), e.g. alert on increase(api_auth_lockout_count[5m]) > 0.
+  [api.auth_lockout]
+
+    # Enable authentication lockout.
+    enabled={{ api.auth_lockout.enabled }}
+
+    # Interval.
+    #
+    # The interval over which the failures are counted.
+    interval="{{ api.auth_lockout.interval }}"
+
+    # Max. failures.
+    #
+    # The number of failures within the interval after which the user or
+    # client IP is locked out.
+    max_failures={{ api.auth_lockout.max_failures }}
+
+    # Lockout duration.
+    lockout_duration="{{ api.auth_lockout.lockout_duration }}"
+
+    # Base delay.
+    #
+    # The delay applied to the first failed login.
+    base_delay="{{ api.auth_lockout.base_delay }}"
+
+    # Max. delay.
+    max_delay="{{ api.auth_lockout.max_delay }}"
+
+    # Trusted proxies.
+    #
+    # Failures are counted per client IP (and per user and client IP for
+    # logins). When ChirpStack is running behind a reverse-proxy, configure
+    # the IP addresses of the proxies such that the client IP is obtained from
+    # the X-Forwarded-For header. This header is ignored for requests not
+    # originating from a trusted proxy.
+    #
+    # Example:
+    # trusted_proxies=["127.0.0.1", "::1"]
+    trusted_proxies=[
+      {{#each api.auth_lockout.trusted_proxies}}
+      "{{this}}",
+      {{/each}}
+    ]
+
 
 # Global gateway configuration.
 # Please note that backend configuration can be found in the per-region
@@ -166,12 +373,76 @@ pub fn run() {
   # This defines how long (after generating) the certificate remains valid.
   client_cert_lifetime="{{ gateway.client_cert_lifetime }}"
 
+  # CA chain (optional).
+  #
+  # In case the above CA certificate is an intermediate CA, this can be used
+  # to configure the (PEM encoded) certificate files of the remaining chain,
+  # e.g. the root CA. These certificates are appended to the CA certificate
+  # that is returned when generating a client certificate.
+  ca_chain=[
+    {{#each gateway.ca_chain}}
+    "{{this}}",
+    {{/each}}
+  ]
+
+  # Certificate renewal window.
+  #
+  # A client certificate can be renewed through the API when it expires
+  # within this duration.
+  client_cert_renew_before="{{ gateway.client_cert_renew_before }}"
+
+  # Certificate revocation list file (optional).
+  #
+  # If set, ChirpStack writes the (PEM encoded) revocation list of the
+  # revoked gateway client certificates to this file. The revocation list
+  # is updated each time certificates are revoked and periodically before
+  # it expires. This file can be used by the MQTT broker to reject revoked
+  # certificates (e.g. the Mosquitto crlfile option).
+  crl_file="{{ gateway.crl_file }}"
+
+  # Certificate revocation list lifetime.
+  #
+  # This defines the next-update value of the revocation list. The list is
+  # re-published at half of this interval.
+  crl_lifetime="{{ gateway.crl_lifetime }}"
+
   # Allow unknown gateways.
   #
   # If set to true, then uplinks received from gateways not configured in
   # ChirpStack will be allowed.
   allow_unknown_gateways={{ gateway.allow_unknown_gateways }}
 
+  # Stats batch interval.
+  #
+  # Gateway stats are collected and processed in batches using this interval.
+  # For each batch, the gateway state (last-seen, location and meta-data) is
+  # updated within a single database transaction and the gateway metrics are
+  # written to Redis using a single pipeline. In case the batched database
+  # update fails, the gateways are updated individually. The default (0)
+  # disables batching and processes each gateway stats message individually.
+  stats_batch_interval="{{ gateway.stats_batch_interval }}"
+
+
+  # Gateway offline detection.
+  #
+  # When enabled, gateways for which no stats have been received within the
+  # configured silence period are marked offline and a gateway offline event
+  # is sent to the global integrations. Once stats are received again, the
+  # gateway is marked online and a gateway online event is sent.
+  [gateway.offline_detection]
+
+    # Enable offline detection.
+    enabled={{ gateway.offline_detection.enabled }}
+
+    # Interval in which the offline detection runs.
+    interval="{{ gateway.offline_detection.interval }}"
+
+    # Missed stats intervals.
+    #
+    # The silence period of a gateway is defined as its stats interval
+    # multiplied by this value.
+    missed_stats_intervals={{ gateway.offline_detection.missed_stats_intervals }}
+
 
 # Network related configuration.
 [network]
@@ -293,6 +564,81 @@ pub fn run() {
     # scheduler interval.
     multicast_class_b_margin="{{ network.scheduler.multicast_class_b_margin }}"
 
+  # Frame-counter anomaly detection.
+  #
+  # When enabled, devices with an abnormal frame-counter behavior are flagged
+  # by sending an UPLINK_F_CNT_ANOMALY log event to the integrations. This can
+  # be an early signal for cloned or malfunctioning devices. The detected
+  # anomalies are also exposed by the uplink_f_cnt_anomaly_count metric.
+  #
+  # Anomalies are counted per device within the configured interval. An
+  # alert is sent once the threshold has been reached, after which no further
+  # alerts are sent for the same device and anomaly within the interval.
+  #
+  # Note: devices for which the frame-counter validation is disabled are
+  # excluded from the anomaly detection.
+  [network.f_cnt_anomaly]
+
+    # Enable frame-counter anomaly detection.
+    enabled={{ network.f_cnt_anomaly.enabled }}
+
+    # Interval.
+    #
+    # The interval over which the anomalies are counted.
+    interval="{{ network.f_cnt_anomaly.interval }}"
+
+    # Reset threshold.
+    #
+    # The number of frame-counter resets within the interval after which an
+    # alert is sent.
+    reset_threshold={{ network.f_cnt_anomaly.reset_threshold }}
+
+    # Replay threshold.
+    #
+    # The number of replays within the interval after which an alert is sent.
+    # An uplink is flagged as replay when it was received with an already
+    # used frame-counter, by none of the gateways that received the original
+    # uplink.
+    replay_threshold={{ network.f_cnt_anomaly.replay_threshold }}
+
+    # Max. frame-counter gap.
+    #
+    # An alert is sent when the frame-counter of an uplink exceeds the
+    # expected frame-counter by more than this number.
+    max_f_cnt_gap={{ network.f_cnt_anomaly.max_f_cnt_gap }}
+
+  # Tenant quota configuration.
+  #
+  # The uplinks and downlinks per day quotas are configured per tenant. The
+  # usage is counted per tenant and day (UTC) and is periodically stored by
+  # the usage accounting task. Because of this, a quota could be exceeded by
+  # the number of messages received within one accounting interval. When a
+  # quota has been exceeded, a QUOTA_EXCEEDED log event is sent to the
+  # integrations of the affected device.
+  #
+  # Note: the downlinks quota applies to the downlinks enqueued through the
+  # API and integrations.
+  [network.quota]
+
+    # Accounting interval.
+    #
+    # The interval in which the usage is stored and the quotas are evaluated.
+    accounting_interval="{{ network.quota.accounting_interval }}"
+
+    # Uplink action.
+    #
+    # The action when the uplinks per day quota has been exceeded. Options are:
+    #   warn   - Send a warning log event, but continue processing the uplink.
+    #   reject - Send a warning log event and drop the uplink.
+    uplink_action="{{ network.quota.uplink_action }}"
+
+    # Downlink action.
+    #
+    # The action when the downlinks per day quota has been exceeded. Options are:
+    #   warn   - Send a warning log event, but enqueue the downlink.
+    #   reject - Send a warning log event and reject the downlink enqueue.
+    downlink_action="{{ network.quota.downlink_action }}"
+
 
 # Monitoring related configuration.
 [monitoring]
@@ -317,6 +663,14 @@ pub fn run() {
   # Setting this value to 0 disables this feature.
   meta_log_max_history={{ monitoring.meta_log_max_history }}
 
+  # Meta-log dropped uplinks.
+  #
+  # If enabled, each dropped uplink (e.g. unknown DevAddr, MIC failure,
+  # frame-counter reset, ...) is logged to the meta-log stream, including
+  # the reason code. The dropped uplinks are always counted in the
+  # uplink_dropped_count metric.
+  meta_log_dropped_uplinks={{ monitoring.meta_log_dropped_uplinks }}
+
   # Gateway frame-log max history.
   #
   # This defines the max number of frame-log records that will be persisted in Redis Streams.
@@ -374,6 +728,151 @@ pub fn run() {
   # This defines the TTL of the Redis Stream key.
   per_device_event_log_ttl="{{ monitoring.per_device_event_log_ttl }}"
 
+  # Per device debug-trace max history.
+  #
+  # This defines the max number of debug-trace records that will be persisted
+  # in Redis Streams for each device for which the debug tracing is enabled
+  # (through the API).
+  per_device_debug_trace_max_history={{ monitoring.per_device_debug_trace_max_history }}
+
+  # Per device debug-trace TTL.
+  #
+  # This defines the TTL of the Redis Stream key.
+  per_device_debug_trace_ttl="{{ monitoring.per_device_debug_trace_ttl }}"
+
+  # Device debug-trace max duration.
+  #
+  # This defines the max duration for which the debug tracing can be enabled
+  # for a single device.
+  device_debug_trace_max_duration="{{ monitoring.device_debug_trace_max_duration }}"
+
+  # Storage slow-operation log threshold.
+  #
+  # Database queries and Redis commands taking longer than this threshold
+  # are logged as warning, including the query class (or Redis command) and
+  # the context (e.g. the DevEUI) of the operation. The durations are always
+  # exposed by the storage_db_query_duration_seconds and
+  # storage_redis_command_duration_seconds metrics.
+  # Setting this value to 0s disables this feature.
+  storage_slow_log_threshold="{{ monitoring.storage_slow_log_threshold }}"
+
+  # Key access log retention.
+  #
+  # Entries of the key access log (the audit log of the accessed device
+  # root-keys and session-keys) older than this duration are periodically
+  # removed from the database.
+  # Setting this value to 0s disables the removal.
+  key_access_log_retention="{{ monitoring.key_access_log_retention }}"
+
+  # OpenTelemetry distributed tracing.
+  #
+  # When enabled, the uplink, join, downlink and API spans are exported
+  # using the OTLP (gRPC) protocol. The trace context (W3C Trace Context) is
+  # propagated to roaming partners (Backend Interfaces) and HTTP integrations,
+  # and extracted from incoming API and Backend Interfaces requests.
+  [monitoring.opentelemetry]
+
+    # Enable OpenTelemetry tracing.
+    enabled={{ monitoring.opentelemetry.enabled }}
+
+    # OTLP (gRPC) endpoint of the collector.
+    otlp_endpoint="{{ monitoring.opentelemetry.otlp_endpoint }}"
+
+    # Service name.
+    service_name="{{ monitoring.opentelemetry.service_name }}"
+
+    # Sample ratio.
+    #
+    # The ratio of traces that will be sampled (0.0 - 1.0). Traces of which
+    # the parent was sampled (e.g. an incoming API request) are always sampled.
+    sample_ratio={{ monitoring.opentelemetry.sample_ratio }}
+
+  # Per-tenant metrics.
+  #
+  # When enabled, the tenant_uplink_count, tenant_downlink_count,
+  # tenant_integration_errors and tenant_airtime_seconds metrics are exposed,
+  # labeled by tenant ID. This makes it possible to build per-tenant
+  # dashboards from the /metrics endpoint.
+  [monitoring.tenant_metrics]
+
+    # Enable per-tenant metrics.
+    enabled={{ monitoring.tenant_metrics.enabled }}
+
+    # Max. number of tenants.
+    #
+    # To limit the cardinality of the metrics, at most this number of tenants
+    # will be labeled by their tenant ID. Metrics of all other tenants are
+    # aggregated under the "other" tenant_id label.
+    max_tenants={{ monitoring.tenant_metrics.max_tenants }}
+
+  # Error reporting.
+  #
+  # When configured, error log events and panics are reported to a
+  # Sentry-compatible endpoint. The reported errors include the context
+  # in which they occurred (e.g. the DevEUI, Gateway ID and region) as tags.
+  [monitoring.error_reporting]
+
+    # DSN.
+    #
+    # The DSN of the Sentry (compatible) project, in the format:
+    # https://PUBLIC_KEY@HOST/PROJECT_ID. Leave this empty to disable
+    # error reporting.
+    dsn="{{ monitoring.error_reporting.dsn }}"
+
+    # Environment.
+    #
+    # Optional environment name (e.g. production or staging) included in the
+    # reported errors.
+    environment="{{ monitoring.error_reporting.environment }}"
+
+
+# Device and gateway metrics configuration.
+[metrics]
+
+  # Metrics rollup.
+  #
+  # When enabled, the daily and monthly metrics aggregates are no longer
+  # updated on every write. Instead, metrics are written to hourly buckets
+  # and a background task rolls up the completed hours into the daily and
+  # monthly aggregates. When reading the daily or monthly aggregates, the
+  # hourly buckets that have not yet been rolled up are included.
+  [metrics.rollup]
+
+    # Enable the metrics rollup.
+    enabled={{ metrics.rollup.enabled }}
+
+    # Rollup interval.
+    #
+    # This defines how often the rollup task runs. Only one ChirpStack
+    # instance will run the rollup at a time.
+    interval="{{ metrics.rollup.interval }}"
+
+    # Hourly bucket retention.
+    #
+    # After an hourly bucket has been rolled up, it will be deleted after
+    # this window.
+    hour_retention="{{ metrics.rollup.hour_retention }}"
+
+  # SLA reporting configuration.
+  #
+  # When enabled, ChirpStack counts per tenant and month the uplinks delivered
+  # to the integrations, the downlinks acknowledged by the gateways and the
+  # API requests handled without server error. A background task stores
+  # these monthly figures in the database, such that they can be retrieved
+  # using the tenant SLA report API. Note that the API figures are not tenant
+  # specific, these cover all API requests.
+  [metrics.sla]
+
+    # Enable SLA reporting.
+    enabled={{ metrics.sla.enabled }}
+
+    # Report interval.
+    #
+    # This defines how often the SLA reports of the current and previous
+    # month are updated. Only one ChirpStack instance will update the reports
+    # at a time.
+    interval="{{ metrics.sla.interval }}"
+
 
 # Global integration related configuration.
 [integration]
@@ -385,17 +884,49 @@ pub fn run() {
     {{/each}}
   ]
 
+  # Max. number of retries.
+  #
+  # This defines how many times publishing an event is retried when an
+  # integration returns an error, before the event is dropped. Note that
+  # retrying might result in duplicate events (e.g. when only one of the
+  # configured HTTP endpoints failed). Set to 0 to disable retries.
+  max_retries={{ integration.max_retries }}
+
+  # Retry interval.
+  #
+  # The interval between the retries.
+  retry_interval="{{ integration.retry_interval }}"
+
   # MQTT integration configuration.
   [integration.mqtt]
 
     # Event topic template.
     event_topic="{{ integration.mqtt.event_topic }}"
 
+    # Gateway event topic template.
+    #
+    # This is the topic template used when publishing gateway events (e.g.
+    # the gateway online and offline events).
+    gateway_event_topic="{{ integration.mqtt.gateway_event_topic }}"
+
+    # FUOTA event topic template.
+    #
+    # This is the topic template used when publishing FUOTA deployment events
+    # (e.g. the device completed and deployment completed events).
+    fuota_event_topic="{{ integration.mqtt.fuota_event_topic }}"
+
     # Command topic.
     #
     # This is the topic on which the MQTT subscribes for receiving (enqueue) commands.
     command_topic="{{ integration.mqtt.command_topic }}"
 
+    # Multicast command topic.
+    #
+    # This is the topic on which the MQTT subscribes for receiving multicast-group
+    # (enqueue) commands. The payload contains the multicast_group_id, f_port,
+    # data and (optional) expires_at fields.
+    multicast_command_topic="{{ integration.mqtt.multicast_command_topic }}"
+
     # Use JSON encoding instead of Protobuf (binary).
     json={{ integration.mqtt.json }}
 
@@ -516,6 +1047,19 @@ pub fn run() {
     # events. Messages will be published to the "amq.topic" exchange.
     event_routing_key="{{ integration.amqp.event_routing_key }}"
 
+    # Gateway event routing key.
+    #
+    # This is the event routing-key template used when publishing gateway
+    # events (e.g. the gateway online and offline events).
+    gateway_event_routing_key="{{ integration.amqp.gateway_event_routing_key }}"
+
+    # FUOTA event routing key.
+    #
+    # This is the event routing-key template used when publishing FUOTA
+    # deployment events (e.g. the device completed and deployment completed
+    # events).
+    fuota_event_routing_key="{{ integration.amqp.fuota_event_routing_key }}"
+
     # Use JSON encoding instead of Protobuf (binary).
     json={{ integration.amqp.json }}
 
@@ -546,6 +1090,33 @@ pub fn run() {
     # message. There is no need to parse it from the key.
     event_key="{{ integration.kafka.event_key }}"
 
+    # Template for keys included in Kafka messages for gateway events (e.g.
+    # the gateway online and offline events).
+    gateway_event_key="{{ integration.kafka.gateway_event_key }}"
+
+    # Template for keys included in Kafka messages for FUOTA deployment events
+    # (e.g. the device completed and deployment completed events).
+    fuota_event_key="{{ integration.kafka.fuota_event_key }}"
+
+    # Topic for commands (optional).
+    #
+    # When set, ChirpStack will consume (enqueue) commands from this topic.
+    # The key of each message is used to determine the command type and the
+    # device or multicast-group to which the command applies.
+    command_topic="{{ integration.kafka.command_topic }}"
+
+    # Template for keys of device commands.
+    command_key="{{ integration.kafka.command_key }}"
+
+    # Template for keys of multicast-group commands.
+    #
+    # The payload contains the multicast_group_id, f_port, data and (optional)
+    # expires_at fields.
+    multicast_command_key="{{ integration.kafka.multicast_command_key }}"
+
+    # Consumer group used for consuming the commands.
+    consumer_group="{{ integration.kafka.consumer_group }}"
+
     # Username (optional).
     username="{{ integration.kafka.username }}"
 
@@ -573,6 +1144,100 @@ pub fn run() {
     # Maximum execution time.
     max_execution_time="{{ codec.js.max_execution_time }}"
 
+    # Maximum memory size (bytes).
+    #
+    # The maximum memory a single codec execution is allowed to allocate.
+    # Set this to 0 to disable the limit.
+    max_memory_size={{ codec.js.max_memory_size }}
+
+    # Runtime pool size.
+    #
+    # Initialized JS runtimes are kept in a pool and re-used for the next
+    # codec executions. Each runtime holds a pre-initialized context (with
+    # the Buffer class already set up), which is used by a single execution
+    # only, such that executions are isolated. After each execution, a new
+    # context is set up in the background. This defines the max. number of
+    # runtimes kept in the pool.
+    pool_size={{ codec.js.pool_size }}
+
+  # WASM codec configuration.
+  [codec.wasm]
+
+    # Maximum fuel.
+    #
+    # Each executed WASM instruction consumes fuel. The execution of the codec
+    # function is aborted when all fuel has been consumed.
+    max_fuel={{ codec.wasm.max_fuel }}
+
+    # Maximum memory size (bytes).
+    #
+    # The maximum size of the linear memory of the WASM module.
+    max_memory_size={{ codec.wasm.max_memory_size }}
+
+  # LoRaWAN Device Repository codec configuration.
+  #
+  # Device-profiles using the Device Repository codec runtime reference a
+  # codec by vendor / device (and optionally firmware version). These codecs
+  # are fetched from the repository and stored in the database.
+  [codec.device_repository]
+
+    # Repository URL.
+    #
+    # This must point to the raw content of the repository. By setting this
+    # to a tag or commit instead of a branch, all codecs can be pinned to a
+    # specific repository version. Set this to an empty string to disable
+    # the Device Repository codec runtime.
+    url="{{ codec.device_repository.url }}"
+
+    # Refresh interval.
+    #
+    # The interval in which the referenced codecs are refreshed.
+    refresh_interval="{{ codec.device_repository.refresh_interval }}"
+
+  # Python codec configuration.
+  #
+  # Python codecs are executed by a pool of Python worker processes. Each
+  # execution uses its own (isolated) namespace, but the imported modules
+  # are shared by the executions handled by the same worker process.
+  [codec.python]
+
+    # Python interpreter.
+    #
+    # The Python (3) interpreter used for the worker processes, e.g. python3.
+    # Set this to an empty string to disable the Python codec runtime.
+    interpreter="{{ codec.python.interpreter }}"
+
+    # Maximum execution time.
+    #
+    # The worker process is killed when the execution of the codec function
+    # exceeds this duration.
+    max_execution_time="{{ codec.python.max_execution_time }}"
+
+    # Maximum memory size (bytes).
+    #
+    # The maximum (virtual) memory size of a worker process.
+    # Set this to 0 to disable the limit.
+    max_memory_size={{ codec.python.max_memory_size }}
+
+    # Worker pool size.
+    #
+    # This defines the max. number of idle worker processes kept in the pool
+    # of each tenant for re-use by the next codec executions of that tenant.
+    pool_size={{ codec.python.pool_size }}
+
+    # User ID.
+    #
+    # The (unprivileged) user ID used for the worker processes. When set to 0,
+    # the worker processes run as the ChirpStack user. Running the worker
+    # processes as root is refused.
+    user_id={{ codec.python.user_id }}
+
+    # Group ID.
+    #
+    # The group ID used for the worker processes. When set to 0, the worker
+    # processes use the group of the ChirpStack user.
+    group_id={{ codec.python.group_id }}
+
 
 # User authentication configuration.
 [user_authentication]
@@ -749,6 +1414,22 @@ pub fn run() {
       {{/each}}
     ]
 
+  # SCIM 2.0 provisioning.
+  #
+  # When enabled, identity providers can create, update, deactivate and delete
+  # users and manage tenant memberships using the SCIM 2.0 API (RFC 7644), which
+  # is exposed under the '/scim/v2' path of the API interface. Tenants are
+  # exposed as SCIM groups, the group members are the tenant users. Groups can
+  # not be created or deleted through SCIM.
+  #
+  # The identity provider must authenticate using an admin API key as bearer
+  # token. SCIM users are matched by their e-mail address (userName), such that
+  # they can login using OpenID Connect or OAuth2.
+  [user_authentication.scim]
+
+    # Enable SCIM 2.0 provisioning.
+    enabled={{ user_authentication.scim.enabled }}
+
 
 # Join Server configuration.
 [join_server]
@@ -761,6 +1442,10 @@ pub fn run() {
     # If you configure a 'catch-all' Join Server, then this entry must appear
     # as the last item in the list.
     #
+    # Join Server routes can also be managed using the API, in which case
+    # changes are applied without a restart. These routes are matched (most
+    # specific JoinEUI prefix first) before the servers below.
+    #
     # Example:
     # [[join_server.servers]]
     #
@@ -797,6 +1482,55 @@ pub fn run() {
     #   #
     #   # Set this to enable client-certificate authentication with the join-server.
     #   tls_key="/path/to/tls_key.pem"
+
+    #   # Authorization header (optional).
+    #   #
+    #   # Optional value of the Authorization header, e.g. token or password.
+    #   authorization_header="Bearer some-token"
+    #
+    #   # OAuth2 token URL (optional).
+    #   #
+    #   # If set, an access-token is requested from this endpoint using the
+    #   # client-credentials grant, and used as Bearer token in the
+    #   # Authorization header. The token is cached until it expires.
+    #   oauth2_token_url="https://auth.example.com/oauth2/token"
+    #
+    #   # OAuth2 client ID.
+    #   oauth2_client_id=""
+    #
+    #   # OAuth2 client secret.
+    #   oauth2_client_secret=""
+    #
+    #   # OAuth2 scope (optional, space separated).
+    #   oauth2_scope=""
+    #
+    #   # API-key header (optional).
+    #   #
+    #   # If set, the api_key will be added to each request using this header.
+    #   api_key_header="X-API-Key"
+    #
+    #   # API-key.
+    #   api_key=""
+    #
+    #   # JWS algorithm (optional).
+    #   #
+    #   # If set, messages are signed and / or verified using a detached JWS
+    #   # (X-JWS-Signature header). Valid options are: HS256, HS384, HS512,
+    #   # RS256, RS384, RS512, PS256, PS384, PS512, ES256, ES384 and EdDSA.
+    #   jws_algorithm="ES256"
+    #
+    #   # JWS signing key (path).
+    #   #
+    #   # PEM encoded private key (or the shared secret in case of a HMAC
+    #   # algorithm) for signing the messages sent to the Join Server.
+    #   jws_signing_key="/path/to/jws_signing_key.pem"
+    #
+    #   # JWS verification key (path).
+    #   #
+    #   # PEM encoded public key (or the shared secret in case of a HMAC
+    #   # algorithm) for verifying the messages received from the Join Server.
+    #   # If set, messages without valid signature are rejected.
+    #   jws_verification_key="/path/to/jws_verification_key.pem"
     {{#each join_server.servers}}
 
     [[join_server.servers]]
@@ -807,6 +1541,16 @@ pub fn run() {
       ca_cert="{{ this.ca_cert }}"
       tls_cert="{{ this.tls_cert }}"
       tls_key="{{ this.tls_key }}"
+      authorization_header="{{ this.authorization_header }}"
+      oauth2_token_url="{{ this.oauth2_token_url }}"
+      oauth2_client_id="{{ this.oauth2_client_id }}"
+      oauth2_client_secret="{{ this.oauth2_client_secret }}"
+      oauth2_scope="{{ this.oauth2_scope }}"
+      api_key_header="{{ this.api_key_header }}"
+      api_key="{{ this.api_key }}"
+      jws_algorithm="{{ this.jws_algorithm }}"
+      jws_signing_key="{{ this.jws_signing_key }}"
+      jws_verification_key="{{ this.jws_verification_key }}"
     {{/each}}
 
 
@@ -838,6 +1582,42 @@ pub fn run() {
   resolve_net_id_domain_suffix="{{ backend_interfaces.resolve_net_id_domain_suffix }}"
 
 
+  # Async answer delivery.
+  #
+  # When a roaming partner (or Join Server) uses the async interface, answers
+  # are sent in a separate request. In case this request fails, the answer is
+  # stored and re-sent using an exponential backoff, until it has been
+  # delivered or the max. number of retries has been reached.
+  [roaming.async_answer]
+
+    # Max. number of retries.
+    max_retry_count={{ roaming.async_answer.max_retry_count }}
+
+    # Initial retry interval.
+    #
+    # This interval is doubled after each failed retry.
+    retry_interval="{{ roaming.async_answer.retry_interval }}"
+
+    # Max. retry interval.
+    max_retry_interval="{{ roaming.async_answer.max_retry_interval }}"
+
+
+  # Forwarding Network Server only (functional split).
+  #
+  # If enabled, ChirpStack acts as fNS only. All uplinks (join-requests and
+  # data uplinks) received by the (public) gateways are forwarded to the
+  # configured sNS using XmitDataReq, including the gateway meta-data. The
+  # sNS sends the downlinks using XmitDataReq. Note that the sNS must be
+  # configured as roaming server (or roaming agreement) using the same NetID.
+  [roaming.fns_only]
+
+    # Enable fNS-only mode.
+    enabled={{ roaming.fns_only.enabled }}
+
+    # NetID of the sNS.
+    net_id="{{ roaming.fns_only.net_id }}"
+
+
   # Default roaming server.
   [roaming.default]
 
@@ -861,6 +1641,25 @@ pub fn run() {
     # agreements). As well it means it will expose the NwkSKey / FNwkSIntKey
     # on PRStartAns.
     passive_roaming_validate_mic={{roaming.default.passive_roaming_validate_mic}}
+
+    # Handover-roaming session lifetime.
+    #
+    # The lifetime of the handover-roaming sessions started by the sNS. If set
+    # to 0, the session remains active until it is stopped by the sNS.
+    handover_roaming_lifetime="{{roaming.default.handover_roaming_lifetime}}"
+
+    # Handover-roaming KEK label (optional).
+    #
+    # If set, the network session-keys sent to the sNS will be encrypted
+    # using the given KEK.
+    handover_roaming_kek_label="{{roaming.default.handover_roaming_kek_label}}"
+
+    # Use handover-roaming.
+    #
+    # If set, ChirpStack will activate devices of the hNS using handover-
+    # roaming (HRStartReq) instead of passive-roaming (PRStartReq). In this
+    # case ChirpStack (as sNS) handles the MAC layer of the device.
+    use_handover_roaming={{roaming.default.use_handover_roaming}}
    
     # Server.
     #
@@ -887,8 +1686,66 @@ pub fn run() {
     # Optional value of the Authorization header, e.g. token or password.
     authorization_header="{{roaming.default.authorization_header}}"
 
+    # OAuth2 token URL.
+    #
+    # If set, an access-token is requested from this endpoint using the
+    # client-credentials grant, and used as Bearer token in the
+    # Authorization header. The token is cached until it expires.
+    oauth2_token_url="{{roaming.default.oauth2_token_url}}"
+
+    # OAuth2 client ID.
+    oauth2_client_id="{{roaming.default.oauth2_client_id}}"
+
+    # OAuth2 client secret.
+    oauth2_client_secret="{{roaming.default.oauth2_client_secret}}"
+
+    # OAuth2 scope (space separated).
+    oauth2_scope="{{roaming.default.oauth2_scope}}"
+
+    # API-key header.
+    #
+    # If set, the api_key will be added to each request using this header,
+    # e.g. X-API-Key.
+    api_key_header="{{roaming.default.api_key_header}}"
+
+    # API-key.
+    api_key="{{roaming.default.api_key}}"
+
+    # JWS algorithm.
+    #
+    # If set, messages are signed and / or verified using a detached JWS
+    # (X-JWS-Signature header). Valid options are: HS256, HS384, HS512,
+    # RS256, RS384, RS512, PS256, PS384, PS512, ES256, ES384 and EdDSA.
+    jws_algorithm="{{roaming.default.jws_algorithm}}"
+
+    # JWS signing key (path).
+    #
+    # PEM encoded private key (or the shared secret in case of a HMAC
+    # algorithm) for signing the messages sent to the roaming partner.
+    jws_signing_key="{{roaming.default.jws_signing_key}}"
+
+    # JWS verification key (path).
+    #
+    # PEM encoded public key (or the shared secret in case of a HMAC
+    # algorithm) for verifying the messages received from the roaming
+    # partner. If set, messages without valid signature are rejected.
+    jws_verification_key="{{roaming.default.jws_verification_key}}"
+
+    # Max. requests per second.
+    #
+    # This limits both the number of requests received from and the number
+    # of requests sent to each roaming partner. Set to 0 to disable rate
+    # limiting.
+    max_requests_per_second={{roaming.default.max_requests_per_second}}
+
 
   # Per server roaming configuration (this can be repeated).
+  #
+  # Roaming agreements can also be managed using the API, in which case
+  # changes are applied without a restart. In case a roaming agreement and
+  # a server below share the same NetID, the roaming agreement takes
+  # precedence.
+  #
   # Example:
   # [[roaming.servers]]
   #
@@ -913,6 +1770,25 @@ pub fn run() {
   #  # on PRStartAns.
   #  passive_roaming_validate_mic=false
   #
+  #  # Handover-roaming session lifetime.
+  #  #
+  #  # The lifetime of the handover-roaming sessions started by the sNS. If
+  #  # set to 0, the session remains active until it is stopped by the sNS.
+  #  handover_roaming_lifetime="0s"
+  #
+  #  # Handover-roaming KEK label (optional).
+  #  #
+  #  # If set, the network session-keys sent to the sNS will be encrypted
+  #  # using the given KEK.
+  #  handover_roaming_kek_label=""
+  #
+  #  # Use handover-roaming.
+  #  #
+  #  # If set, ChirpStack will activate devices of the hNS using handover-
+  #  # roaming (HRStartReq) instead of passive-roaming (PRStartReq). In this
+  #  # case ChirpStack (as sNS) handles the MAC layer of the device.
+  #  use_handover_roaming=false
+  #
   #  # Server.
   #  #
   #  # If set, this will bypass the DNS resolving of the server.
@@ -937,6 +1813,52 @@ pub fn run() {
   #  #
   #  # Optional value of the Authorization header, e.g. token or password.
   #  authorization_header=""
+  #
+  #  # OAuth2 token URL.
+  #  #
+  #  # If set, an access-token is requested from this endpoint using the
+  #  # client-credentials grant, and used as Bearer token in the
+  #  # Authorization header. The token is cached until it expires.
+  #  oauth2_token_url=""
+  #
+  #  # OAuth2 client ID.
+  #  oauth2_client_id=""
+  #
+  #  # OAuth2 client secret.
+  #  oauth2_client_secret=""
+  #
+  #  # OAuth2 scope (space separated).
+  #  oauth2_scope=""
+  #
+  #  # API-key header.
+  #  #
+  #  # If set, the api_key will be added to each request using this header,
+  #  # e.g. X-API-Key.
+  #  api_key_header=""
+  #
+  #  # API-key.
+  #  api_key=""
+  #
+  #  # JWS algorithm.
+  #  #
+  #  # If set, messages are signed and / or verified using a detached JWS
+  #  # (X-JWS-Signature header).
+  #  jws_algorithm=""
+  #
+  #  # JWS signing key (path).
+  #  jws_signing_key=""
+  #
+  #  # JWS verification key (path).
+  #  #
+  #  # If set, messages without valid signature are rejected.
+  #  jws_verification_key=""
+  #
+  #  # Max. requests per second.
+  #  #
+  #  # This limits both the number of requests received from and the number
+  #  # of requests sent to this roaming partner. Set to 0 to disable rate
+  #  # limiting.
+  #  max_requests_per_second=0
   {{#each roaming.servers}}
 
   [[roaming.servers]]
@@ -945,12 +1867,25 @@ pub fn run() {
     passive_roaming_lifetime="{{ this.passive_roaming_lifetime }}"
     passive_roaming_kek_label="{{ this.passive_roaming_kek_label }}"
     passive_roaming_validate_mic={{ this.passive_roaming_validate_mic }}
+    handover_roaming_lifetime="{{ this.handover_roaming_lifetime }}"
+    handover_roaming_kek_label="{{ this.handover_roaming_kek_label }}"
+    use_handover_roaming={{ this.use_handover_roaming }}
     server="{{ this.server }}"
     use_target_role_suffix="{{ this.use_target_role_suffix }}"
     ca_cert="{{ this.ca_cert }}"
     tls_cert="{{ this.tls_cert }}"
     tls_key="{{ this.tls_key }}"
     authorization_header="{{ this.authorization_header }}"
+    oauth2_token_url="{{ this.oauth2_token_url }}"
+    oauth2_client_id="{{ this.oauth2_client_id }}"
+    oauth2_client_secret="{{ this.oauth2_client_secret }}"
+    oauth2_scope="{{ this.oauth2_scope }}"
+    api_key_header="{{ this.api_key_header }}"
+    api_key="{{ this.api_key }}"
+    jws_algorithm="{{ this.jws_algorithm }}"
+    jws_signing_key="{{ this.jws_signing_key }}"
+    jws_verification_key="{{ this.jws_verification_key }}"
+    max_requests_per_second={{ this.max_requests_per_second }}
   {{/each}}
 
 
@@ -963,6 +1898,10 @@ pub fn run() {
 # to the receiver the label of the KEK that was used for encryption,
 # such that the receiver is able to decrypt the session-key.
 #
+# KEKs are also used for unwrapping device root-keys which have been imported
+# wrapped (see the kek_label field of the device-keys API). If the KEK label
+# is not configured here, the KEK is looked up in the HSM (see below).
+#
 # Example (can be repeated):
 # [[keks]]
 #
@@ -979,6 +1918,155 @@ pub fn run() {
 {{/each}}
 
 
+# Encryption at rest of stored key material.
+#
+# When enabled, the device root-keys (device-keys) and the session-keys of
+# the device-sessions are encrypted before they are stored in the database.
+# A randomly generated data encryption key (DEK) is used for encrypting the
+# key material. This DEK is stored in the database, encrypted by the
+# configured backend (envelope encryption).
+#
+# Note: after enabling encryption, use the reencrypt-keys sub-command to
+# encrypt the already stored key material. The backend must not be changed
+# once data encryption keys have been created.
+[encryption]
+
+  # Backend.
+  #
+  # Valid options are:
+  #   * ""              Disabled (key material is stored in plaintext)
+  #   * local           Local master key
+  #   * vault_transit   HashiCorp Vault transit secrets engine
+  #   * aws_kms         AWS KMS
+  backend="{{ encryption.backend }}"
+
+  # Per-tenant data encryption keys.
+  #
+  # When enabled, each tenant gets its own DEK which is used for encrypting
+  # the key material of the devices of the tenant. This provides cryptographic
+  # isolation between tenants. The DEK of a tenant is removed when the tenant is
+  # deleted. The DEK of a tenant can be rotated using the reencrypt-keys
+  # sub-command (--rotate-data-key --tenant-id TENANT_ID).
+  #
+  # Note: after enabling this option, use the reencrypt-keys sub-command to
+  # re-encrypt the already stored key material using the tenant DEKs.
+  per_tenant_keys={{ encryption.per_tenant_keys }}
+
+  # Local master key configuration.
+  [encryption.local]
+
+    # Master key (HEX encoded AES256 key).
+    master_key="{{ encryption.local.master_key }}"
+
+  # HashiCorp Vault transit configuration.
+  [encryption.vault_transit]
+
+    # Vault address.
+    address="{{ encryption.vault_transit.address }}"
+
+    # Vault token.
+    token="{{ encryption.vault_transit.token }}"
+
+    # Mount path of the transit secrets engine.
+    mount="{{ encryption.vault_transit.mount }}"
+
+    # Name of the transit encryption key.
+    key_name="{{ encryption.vault_transit.key_name }}"
+
+  # AWS KMS configuration.
+  [encryption.aws_kms]
+
+    # AWS region.
+    region="{{ encryption.aws_kms.region }}"
+
+    # AWS Access Key ID.
+    access_key_id="{{ encryption.aws_kms.access_key_id }}"
+
+    # AWS Secret Access Key.
+    secret_access_key="{{ encryption.aws_kms.secret_access_key }}"
+
+    # KMS key ID (or ARN / alias).
+    key_id="{{ encryption.aws_kms.key_id }}"
+
+
+# Keystore for device root-keys.
+#
+# When configured, the device root-keys (NwkKey, AppKey and GenAppKey) are
+# stored in the configured keystore instead of the database. In this case only
+# a reference to the keys is stored in the database. Retrieved keys are cached
+# in memory.
+#
+# Note: after configuring the keystore, use the migrate-device-keys-to-keystore
+# sub-command to move the root-keys that are already stored in the database to
+# the keystore. Device-keys removed by deleting an application or tenant are
+# not removed from the keystore.
+[keystore]
+
+  # Backend.
+  #
+  # Valid options are:
+  #   * ""              Disabled (root-keys are stored in the database)
+  #   * vault_kv        HashiCorp Vault KV (version 2) secrets engine
+  backend="{{ keystore.backend }}"
+
+  # Cache TTL.
+  #
+  # This defines the max. time that the root-keys retrieved from the keystore
+  # are cached. Note that updates made through other instances are only seen
+  # after the cached keys have expired. Set this to 0s to disable caching.
+  cache_ttl="{{ keystore.cache_ttl }}"
+
+  # HashiCorp Vault KV configuration.
+  [keystore.vault_kv]
+
+    # Vault address.
+    address="{{ keystore.vault_kv.address }}"
+
+    # Vault token.
+    token="{{ keystore.vault_kv.token }}"
+
+    # Mount path of the KV (version 2) secrets engine.
+    mount="{{ keystore.vault_kv.mount }}"
+
+    # Path prefix.
+    #
+    # The root-keys of each device are stored under {path_prefix}/{dev_eui}.
+    path_prefix="{{ keystore.vault_kv.path_prefix }}"
+
+
+# PKCS#11 HSM configuration.
+#
+# When configured, the OTAA session-key derivation and the join-request and
+# join-accept MIC / encryption operations are performed by the HSM for the
+# tenants for which the HSM option is enabled. For these tenants, the root-keys
+# (NwkKey and AppKey) of the devices never exist in plaintext in the server
+# memory. The root-keys must be provisioned in the HSM as AES secret-key objects,
+# labeled nwk_key:DEV_EUI and app_key:DEV_EUI (e.g. nwk_key:0102030405060708).
+# The root-keys stored in the database are not used for these devices.
+#
+# Note: for LoRaWAN 1.0.x devices, only the nwk_key is used. The derived
+# session-keys are stored as usual (see the encryption configuration to encrypt
+# these at rest). FUOTA is not supported for devices of which the root-keys are
+# stored in the HSM.
+#
+# The HSM can also hold the KEKs for unwrapping device root-keys which have
+# been imported wrapped. These must be provisioned as AES secret-key objects,
+# labeled kek:LABEL and must allow the CKM_AES_KEY_WRAP decrypt operation.
+[hsm]
+
+  # PKCS#11 library path.
+  #
+  # Path to the PKCS#11 module (shared library) of the HSM vendor. Leave this
+  # empty to disable the HSM.
+  library_path="{{ hsm.library_path }}"
+
+  # Slot ID.
+  slot={{ hsm.slot }}
+
+  # User PIN.
+  pin="{{ hsm.pin }}"
+
+
 # UI configuration.
 [ui]
   # Tileserver URL.
@@ -0,0 +1,18 @@
+use std::io::{self, BufRead};
+
+use anyhow::Result;
+
+use crate::config::secrets;
+
+pub fn run() -> Result<()> {
+    // The secret is read from stdin, such that it does not end up in the shell history.
+    let mut secret = String::new();
+    io::stdin().lock().read_line(&mut secret)?;
+
+    println!(
+        "{}",
+        secrets::encrypt(secret.trim_end_matches(['\r', '\n']))?
+    );
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{region, storage, ttn};
+
+pub async fn run(application_id: &str, file: &Path) -> Result<()> {
+    storage::setup().await?;
+    region::setup()?;
+
+    let application_id = Uuid::from_str(application_id).context("Parse application ID")?;
+    let data = fs::read_to_string(file).context(format!("Read file: {}", file.display()))?;
+    let devices = ttn::parse(&data)?;
+
+    info!(
+        "Importing {} end-devices from The Things Stack export",
+        devices.len()
+    );
+
+    let res = ttn::import(application_id, &devices).await?;
+    for (device_id, error) in &res.errors {
+        warn!(device_id = %device_id, error = %error, "End-device not imported");
+    }
+
+    info!(
+        "Imported {} devices, created {} device-profiles, {} devices failed",
+        res.device_count,
+        res.device_profile_count,
+        res.errors.len()
+    );
+
+    if !res.errors.is_empty() {
+        return Err(anyhow!("{} devices failed to import", res.errors.len()));
+    }
+
+    Ok(())
+}
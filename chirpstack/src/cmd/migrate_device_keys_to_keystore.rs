@@ -0,0 +1,48 @@
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::{debug, info};
+
+use crate::keystore;
+use crate::storage::{self, device_keys, get_async_db_conn, key_access_log, schema};
+use lrwn::EUI64;
+
+// Moves the root-keys which are stored in the database to the configured keystore. After this,
+// only the keystore references are stored in the database.
+pub async fn run() -> Result<()> {
+    storage::setup().await?;
+
+    if !keystore::enabled() {
+        return Err(anyhow!("No keystore backend is configured"));
+    }
+
+    info!("Migrating device root-keys to keystore");
+
+    let dev_euis: Vec<EUI64> = schema::device_keys::dsl::device_keys
+        .select(schema::device_keys::dsl::dev_eui)
+        .filter(schema::device_keys::dsl::key_ref.eq(""))
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+
+    for dev_eui in &dev_euis {
+        debug!(dev_eui = %dev_eui, "Migrating device root-keys");
+
+        // The update stores the root-keys in the keystore.
+        let dk = device_keys::get(dev_eui).await?;
+        key_access_log::log(
+            dev_eui,
+            key_access_log::KeyType::RootKeys,
+            key_access_log::Actor::System,
+            key_access_log::Purpose::KeystoreMigration,
+        )
+        .await?;
+        device_keys::update(dk).await?;
+    }
+
+    info!(
+        count = dev_euis.len(),
+        "Device root-keys migrated to keystore"
+    );
+
+    Ok(())
+}
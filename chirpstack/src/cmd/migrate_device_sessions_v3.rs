@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use prost::Message;
+use tracing::{debug, info, warn};
+
+use crate::region;
+use crate::storage::{self, get_async_db_conn, mac_command, schema::device};
+use chirpstack_api::{common, internal};
+use lrwn::{DevAddr, EUI64};
+
+// The structs below are the (partial) Protobuf definitions of the device-session as it was
+// stored in Redis by the ChirpStack Network Server v3 (DeviceSessionPB). Fields which do not
+// have an equivalent in the current device-session are omitted.
+
+#[derive(Clone, PartialEq, Message)]
+struct DeviceSessionV3 {
+    #[prost(bytes = "vec", tag = "4")]
+    dev_addr: Vec<u8>,
+    #[prost(bytes = "vec", tag = "5")]
+    dev_eui: Vec<u8>,
+    #[prost(bytes = "vec", tag = "7")]
+    f_nwk_s_int_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "8")]
+    s_nwk_s_int_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "9")]
+    nwk_s_enc_key: Vec<u8>,
+    #[prost(message, optional, tag = "45")]
+    app_s_key_envelope: Option<common::KeyEnvelope>,
+    #[prost(uint32, tag = "10")]
+    f_cnt_up: u32,
+    #[prost(uint32, tag = "11")]
+    n_f_cnt_down: u32,
+    #[prost(uint32, tag = "12")]
+    a_f_cnt_down: u32,
+    #[prost(uint32, tag = "39")]
+    conf_f_cnt: u32,
+    #[prost(bool, tag = "13")]
+    skip_f_cnt_check: bool,
+    #[prost(uint32, tag = "14")]
+    rx_delay: u32,
+    #[prost(uint32, tag = "15")]
+    rx1_dr_offset: u32,
+    #[prost(uint32, tag = "16")]
+    rx2_dr: u32,
+    #[prost(uint32, tag = "17")]
+    rx2_frequency: u32,
+    #[prost(uint32, tag = "18")]
+    tx_power_index: u32,
+    #[prost(uint32, tag = "19")]
+    dr: u32,
+    #[prost(bool, tag = "20")]
+    adr: bool,
+    #[prost(uint32, tag = "21")]
+    max_supported_tx_power_index: u32,
+    #[prost(uint32, tag = "23")]
+    nb_trans: u32,
+    #[prost(uint32, repeated, tag = "24")]
+    enabled_uplink_channels: Vec<u32>,
+    #[prost(map = "uint32, message", tag = "25")]
+    extra_uplink_channels: HashMap<u32, DeviceSessionV3Channel>,
+    #[prost(message, repeated, tag = "27")]
+    uplink_adr_history: Vec<DeviceSessionV3UplinkAdrHistory>,
+    #[prost(int64, tag = "29")]
+    last_device_status_request_time_unix_ns: i64,
+    #[prost(uint32, tag = "34")]
+    ping_slot_nb: u32,
+    #[prost(uint32, tag = "35")]
+    ping_slot_dr: u32,
+    #[prost(uint32, tag = "36")]
+    ping_slot_frequency: u32,
+    #[prost(string, tag = "37")]
+    mac_version: String,
+    #[prost(uint32, tag = "38")]
+    min_supported_tx_power_index: u32,
+    #[prost(uint32, tag = "40")]
+    rejoin_request_max_count_n: u32,
+    #[prost(uint32, tag = "41")]
+    rejoin_request_max_time_n: u32,
+    #[prost(uint32, tag = "42")]
+    rejoin_count_0: u32,
+    #[prost(bytes = "vec", tag = "43")]
+    pending_rejoin_device_session: Vec<u8>,
+    #[prost(bool, tag = "44")]
+    rejoin_request_enabled: bool,
+    #[prost(bool, tag = "47")]
+    uplink_dwell_time_400ms: bool,
+    #[prost(bool, tag = "48")]
+    downlink_dwell_time_400ms: bool,
+    #[prost(uint32, tag = "49")]
+    uplink_max_eirp_index: u32,
+    #[prost(map = "uint32, uint32", tag = "50")]
+    mac_command_error_count: HashMap<u32, u32>,
+}
+
+// Pending mac-command block (MACCommandBlockPB), stored per CID. Each item contains the encoded
+// mac-command, including its CID.
+#[derive(Clone, PartialEq, Message)]
+struct MACCommandBlockV3 {
+    #[prost(uint32, tag = "1")]
+    cid: u32,
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    mac_commands: Vec<Vec<u8>>,
+}
+
+// The CIDs for which v3 stored the pending mac-commands, while awaiting the answer of the device.
+const PENDING_MAC_COMMAND_CIDS: [lrwn::CID; 8] = [
+    lrwn::CID::LinkADRReq,
+    lrwn::CID::DevStatusReq,
+    lrwn::CID::NewChannelReq,
+    lrwn::CID::RxParamSetupReq,
+    lrwn::CID::RxTimingSetupReq,
+    lrwn::CID::TxParamSetupReq,
+    lrwn::CID::PingSlotChannelReq,
+    lrwn::CID::RejoinParamSetupReq,
+];
+
+#[derive(Clone, PartialEq, Message)]
+struct DeviceSessionV3Channel {
+    #[prost(uint32, tag = "1")]
+    frequency: u32,
+    #[prost(uint32, tag = "2")]
+    min_dr: u32,
+    #[prost(uint32, tag = "3")]
+    max_dr: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DeviceSessionV3UplinkAdrHistory {
+    #[prost(uint32, tag = "1")]
+    f_cnt: u32,
+    #[prost(float, tag = "2")]
+    max_snr: f32,
+    #[prost(uint32, tag = "3")]
+    tx_power_index: u32,
+    #[prost(uint32, tag = "4")]
+    gateway_count: u32,
+}
+
+pub async fn run(redis_url: &str, region_config_id: &str) -> Result<()> {
+    storage::setup().await?;
+    region::setup()?;
+
+    // Validate that the region configuration exists.
+    region::get(region_config_id)?;
+
+    info!("Migrating ChirpStack v3 device-sessions");
+    info!("Getting DevEUIs from database without device-session");
+
+    let dev_euis: Vec<EUI64> = device::dsl::device
+        .select(device::dsl::dev_eui)
+        .filter(device::dsl::device_session.is_null())
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+
+    info!(
+        "There are {} devices in the database without device-session set",
+        dev_euis.len()
+    );
+
+    let client = redis::Client::open(redis_url).context("Open v3 Redis client")?;
+    let mut c = client
+        .get_multiplexed_async_connection()
+        .await
+        .context("Connect v3 Redis")?;
+
+    let mut migrated: usize = 0;
+    let mut migrated_mac: usize = 0;
+
+    for dev_eui in &dev_euis {
+        debug!(dev_eui = %dev_eui, "Migrating v3 device-session");
+
+        let b: Vec<u8> = redis::cmd("GET")
+            .arg(format!("lora:ns:device:{}", dev_eui))
+            .query_async(&mut c)
+            .await
+            .context("Get v3 device-session")?;
+        if b.is_empty() {
+            debug!(dev_eui = %dev_eui, "Device does not have a v3 device-session");
+            continue;
+        }
+
+        let ds_v3 =
+            DeviceSessionV3::decode(&mut Cursor::new(b)).context("Decode v3 device-session")?;
+        if ds_v3.dev_eui != dev_eui.to_vec() {
+            warn!(dev_eui = %dev_eui, "DevEUI of v3 device-session does not match, skipping");
+            continue;
+        }
+
+        let ds = convert(ds_v3, region_config_id)
+            .with_context(|| format!("Convert v3 device-session, dev_eui: {}", dev_eui))?;
+
+        storage::device::partial_update(
+            *dev_eui,
+            &storage::device::DeviceChangeset {
+                dev_addr: Some(Some(DevAddr::from_slice(&ds.dev_addr)?)),
+                device_session: Some(Some(ds.into())),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        migrated_mac += migrate_pending_mac_commands(&mut c, dev_eui).await?;
+        migrated += 1;
+        debug!(dev_eui = %dev_eui, "v3 device-session migrated");
+    }
+
+    info!(
+        "Migrated {} v3 device-sessions and {} pending mac-command blocks",
+        migrated, migrated_mac
+    );
+
+    Ok(())
+}
+
+// Migrates the pending mac-commands, such that the answers of the device to mac-commands sent by
+// v3 are handled. It returns the number of migrated mac-command blocks.
+async fn migrate_pending_mac_commands(
+    c: &mut redis::aio::MultiplexedConnection,
+    dev_eui: &EUI64,
+) -> Result<usize> {
+    let mut migrated: usize = 0;
+
+    for cid in PENDING_MAC_COMMAND_CIDS {
+        let b: Vec<u8> = redis::cmd("GET")
+            .arg(format!(
+                "lora:ns:device:{}:mac:pending:{}",
+                dev_eui,
+                cid.to_u8()
+            ))
+            .query_async(c)
+            .await
+            .context("Get v3 pending mac-command block")?;
+        if b.is_empty() {
+            continue;
+        }
+
+        // A pending mac-command block which can't be decoded is not fatal, in the worst case the
+        // answer of the device is not handled and the mac-command will be sent again.
+        let set = match convert_mac_command_block(cid, &b) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(dev_eui = %dev_eui, cid = %cid, error = %e, "Skipping v3 pending mac-command block");
+                continue;
+            }
+        };
+
+        mac_command::set_pending(dev_eui, cid, &set).await?;
+        migrated += 1;
+    }
+
+    Ok(migrated)
+}
+
+fn convert_mac_command_block(cid: lrwn::CID, b: &[u8]) -> Result<lrwn::MACCommandSet> {
+    let block =
+        MACCommandBlockV3::decode(&mut Cursor::new(b)).context("Decode v3 mac-command block")?;
+    if block.cid != cid.to_u8() as u32 {
+        return Err(anyhow!(
+            "Unexpected CID, expected: {}, got: {}",
+            cid.to_u8(),
+            block.cid
+        ));
+    }
+
+    // The mac-commands are encoded including their CID, thus they can be decoded as a set of
+    // downlink mac-commands.
+    let mut set = lrwn::MACCommandSet::from_slice(&block.mac_commands.concat());
+    set.decode_from_raw(false)?;
+
+    Ok(set)
+}
+
+fn convert(ds: DeviceSessionV3, region_config_id: &str) -> Result<internal::DeviceSession> {
+    Ok(internal::DeviceSession {
+        dev_addr: ds.dev_addr,
+        mac_version: match ds.mac_version.as_str() {
+            "1.0.0" => common::MacVersion::Lorawan100,
+            "1.0.1" => common::MacVersion::Lorawan101,
+            "1.0.2" => common::MacVersion::Lorawan102,
+            "1.0.3" => common::MacVersion::Lorawan103,
+            "1.0.4" => common::MacVersion::Lorawan104,
+            "1.1.0" => common::MacVersion::Lorawan110,
+            _ => return Err(anyhow!("Unexpected mac_version: {}", ds.mac_version)),
+        }
+        .into(),
+        f_nwk_s_int_key: ds.f_nwk_s_int_key,
+        s_nwk_s_int_key: ds.s_nwk_s_int_key,
+        nwk_s_enc_key: ds.nwk_s_enc_key,
+        app_s_key: ds.app_s_key_envelope,
+        f_cnt_up: ds.f_cnt_up,
+        n_f_cnt_down: ds.n_f_cnt_down,
+        a_f_cnt_down: ds.a_f_cnt_down,
+        conf_f_cnt: ds.conf_f_cnt,
+        skip_f_cnt_check: ds.skip_f_cnt_check,
+        rx1_delay: ds.rx_delay,
+        rx1_dr_offset: ds.rx1_dr_offset,
+        rx2_dr: ds.rx2_dr,
+        rx2_frequency: ds.rx2_frequency,
+        enabled_uplink_channel_indices: ds.enabled_uplink_channels,
+        extra_uplink_channels: ds
+            .extra_uplink_channels
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    k,
+                    internal::DeviceSessionChannel {
+                        frequency: v.frequency,
+                        min_dr: v.min_dr,
+                        max_dr: v.max_dr,
+                    },
+                )
+            })
+            .collect(),
+        class_b_ping_slot_dr: ds.ping_slot_dr,
+        class_b_ping_slot_freq: ds.ping_slot_frequency,
+        class_b_ping_slot_nb: ds.ping_slot_nb,
+        nb_trans: ds.nb_trans,
+        tx_power_index: ds.tx_power_index,
+        dr: ds.dr,
+        adr: ds.adr,
+        max_supported_tx_power_index: ds.max_supported_tx_power_index,
+        min_supported_tx_power_index: ds.min_supported_tx_power_index,
+        pending_rejoin_device_session: if ds.pending_rejoin_device_session.is_empty() {
+            None
+        } else {
+            let pending =
+                DeviceSessionV3::decode(&mut Cursor::new(ds.pending_rejoin_device_session))
+                    .context("Decode pending rejoin device-session")?;
+            Some(Box::new(convert(pending, region_config_id)?))
+        },
+        uplink_adr_history: ds
+            .uplink_adr_history
+            .into_iter()
+            .map(|v| internal::UplinkAdrHistory {
+                f_cnt: v.f_cnt,
+                max_snr: v.max_snr,
+                tx_power_index: v.tx_power_index,
+                gateway_count: v.gateway_count,
+                ..Default::default()
+            })
+            .collect(),
+        mac_command_error_count: ds.mac_command_error_count,
+        last_device_status_request: if ds.last_device_status_request_time_unix_ns != 0 {
+            let ts: DateTime<Utc> =
+                DateTime::from_timestamp_nanos(ds.last_device_status_request_time_unix_ns);
+            Some(ts.into())
+        } else {
+            None
+        },
+        rejoin_request_enabled: ds.rejoin_request_enabled,
+        rejoin_request_max_count_n: ds.rejoin_request_max_count_n,
+        rejoin_request_max_time_n: ds.rejoin_request_max_time_n,
+        rejoin_count_0: ds.rejoin_count_0,
+        uplink_dwell_time_400ms: ds.uplink_dwell_time_400ms,
+        downlink_dwell_time_400ms: ds.downlink_dwell_time_400ms,
+        uplink_max_eirp_index: ds.uplink_max_eirp_index,
+        region_config_id: region_config_id.to_string(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let pending = DeviceSessionV3 {
+            dev_addr: vec![4, 3, 2, 1],
+            mac_version: "1.1.0".into(),
+            f_cnt_up: 1,
+            ..Default::default()
+        };
+
+        let ds = DeviceSessionV3 {
+            dev_addr: vec![1, 2, 3, 4],
+            dev_eui: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            f_nwk_s_int_key: vec![1; 16],
+            s_nwk_s_int_key: vec![2; 16],
+            nwk_s_enc_key: vec![3; 16],
+            app_s_key_envelope: Some(common::KeyEnvelope {
+                kek_label: "".into(),
+                aes_key: vec![4; 16],
+            }),
+            f_cnt_up: 10,
+            n_f_cnt_down: 5,
+            a_f_cnt_down: 3,
+            rx_delay: 1,
+            rx2_frequency: 869525000,
+            enabled_uplink_channels: vec![0, 1, 2],
+            extra_uplink_channels: [(
+                3,
+                DeviceSessionV3Channel {
+                    frequency: 867100000,
+                    min_dr: 0,
+                    max_dr: 5,
+                },
+            )]
+            .into_iter()
+            .collect(),
+            uplink_adr_history: vec![DeviceSessionV3UplinkAdrHistory {
+                f_cnt: 9,
+                max_snr: 7.5,
+                tx_power_index: 0,
+                gateway_count: 2,
+            }],
+            mac_version: "1.0.3".into(),
+            pending_rejoin_device_session: pending.encode_to_vec(),
+            ..Default::default()
+        };
+
+        let out = convert(ds, "eu868").unwrap();
+        assert_eq!(
+            internal::DeviceSession {
+                dev_addr: vec![1, 2, 3, 4],
+                mac_version: common::MacVersion::Lorawan103.into(),
+                f_nwk_s_int_key: vec![1; 16],
+                s_nwk_s_int_key: vec![2; 16],
+                nwk_s_enc_key: vec![3; 16],
+                app_s_key: Some(common::KeyEnvelope {
+                    kek_label: "".into(),
+                    aes_key: vec![4; 16],
+                }),
+                f_cnt_up: 10,
+                n_f_cnt_down: 5,
+                a_f_cnt_down: 3,
+                rx1_delay: 1,
+                rx2_frequency: 869525000,
+                enabled_uplink_channel_indices: vec![0, 1, 2],
+                extra_uplink_channels: [(
+                    3,
+                    internal::DeviceSessionChannel {
+                        frequency: 867100000,
+                        min_dr: 0,
+                        max_dr: 5,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                uplink_adr_history: vec![internal::UplinkAdrHistory {
+                    f_cnt: 9,
+                    max_snr: 7.5,
+                    tx_power_index: 0,
+                    gateway_count: 2,
+                    ..Default::default()
+                }],
+                pending_rejoin_device_session: Some(Box::new(internal::DeviceSession {
+                    dev_addr: vec![4, 3, 2, 1],
+                    mac_version: common::MacVersion::Lorawan110.into(),
+                    f_cnt_up: 1,
+                    region_config_id: "eu868".into(),
+                    ..Default::default()
+                })),
+                region_config_id: "eu868".into(),
+                ..Default::default()
+            },
+            out
+        );
+
+        // Invalid mac_version.
+        let ds = DeviceSessionV3 {
+            mac_version: "1.2.0".into(),
+            ..Default::default()
+        };
+        assert!(convert(ds, "eu868").is_err());
+    }
+
+    #[test]
+    fn test_convert_mac_command_block() {
+        let block = MACCommandBlockV3 {
+            cid: 0x06,
+            mac_commands: vec![vec![0x06]],
+        };
+
+        let set =
+            convert_mac_command_block(lrwn::CID::DevStatusReq, &block.encode_to_vec()).unwrap();
+        assert_eq!(
+            lrwn::MACCommandSet::new(vec![lrwn::MACCommand::DevStatusReq]),
+            set
+        );
+
+        // CID mismatch.
+        assert!(convert_mac_command_block(lrwn::CID::LinkADRReq, &block.encode_to_vec()).is_err());
+    }
+}
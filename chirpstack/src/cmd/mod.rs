@@ -1,7 +1,17 @@
+#[cfg(feature = "postgres")]
+pub mod backup;
+pub mod config;
 pub mod configfile;
 pub mod create_api_key;
+pub mod encrypt_secret;
 pub mod import_legacy_lorawan_devices_repository;
 pub mod import_lorawan_device_profiles;
+pub mod import_ttn;
+pub mod migrate_device_keys_to_keystore;
+pub mod migrate_device_sessions_v3;
 pub mod migrate_ds_to_pg;
 pub mod print_ds;
+pub mod reencrypt_keys;
 pub mod root;
+#[cfg(feature = "simulator")]
+pub mod simulate;
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::encryption;
+use crate::storage::{self, device_keys, fields, get_async_db_conn, key_access_log, schema};
+use lrwn::EUI64;
+
+// Re-encrypts the stored device-keys and device-sessions using the active data encryption
+// key. This can be used to encrypt key material which was stored before encryption was enabled,
+// or after rotating the data encryption key. When a tenant ID is given, only the key material
+// of the given tenant is re-encrypted and the data encryption key of the tenant is rotated.
+pub async fn run(rotate_data_key: bool, tenant_id: Option<Uuid>) -> Result<()> {
+    storage::setup().await?;
+
+    if rotate_data_key {
+        let id = encryption::rotate_data_key(tenant_id).await?;
+        info!(data_encryption_key_id = id, tenant_id = ?tenant_id, "Data encryption key rotated");
+    }
+
+    info!("Re-encrypting device-keys");
+
+    let mut q = schema::device_keys::dsl::device_keys
+        .select(schema::device_keys::dsl::dev_eui)
+        .inner_join(schema::device::table.inner_join(schema::application::table))
+        .into_boxed();
+    if let Some(tenant_id) = &tenant_id {
+        q = q.filter(schema::application::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
+    }
+    let dev_euis: Vec<EUI64> = q.load(&mut get_async_db_conn().await?).await?;
+
+    for dev_eui in &dev_euis {
+        debug!(dev_eui = %dev_eui, "Re-encrypting device-keys");
+        let dk = device_keys::get(dev_eui).await?;
+        key_access_log::log(
+            dev_eui,
+            key_access_log::KeyType::RootKeys,
+            key_access_log::Actor::System,
+            key_access_log::Purpose::Reencryption,
+        )
+        .await?;
+        device_keys::update(dk).await?;
+    }
+
+    info!(count = dev_euis.len(), "Device-keys re-encrypted");
+    info!("Re-encrypting device-sessions");
+
+    let mut q = schema::device::dsl::device
+        .select(schema::device::dsl::dev_eui)
+        .inner_join(schema::application::table)
+        .filter(schema::device::dsl::device_session.is_not_null())
+        .into_boxed();
+    if let Some(tenant_id) = &tenant_id {
+        q = q.filter(schema::application::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
+    }
+    let dev_euis: Vec<EUI64> = q.load(&mut get_async_db_conn().await?).await?;
+
+    for dev_eui in &dev_euis {
+        debug!(dev_eui = %dev_eui, "Re-encrypting device-session");
+        let d = storage::device::get(dev_eui).await?;
+        key_access_log::log(
+            dev_eui,
+            key_access_log::KeyType::SessionKeys,
+            key_access_log::Actor::System,
+            key_access_log::Purpose::Reencryption,
+        )
+        .await?;
+
+        storage::device::partial_update(
+            *dev_eui,
+            &storage::device::DeviceChangeset {
+                device_session: Some(d.device_session),
+                ..Default::default()
+            },
+        )
+        .await?;
+    }
+
+    info!(count = dev_euis.len(), "Device-sessions re-encrypted");
+
+    Ok(())
+}
@@ -1,33 +1,99 @@
+use std::path::Path;
+use std::time::Instant;
+
 use anyhow::Result;
 use futures::stream::StreamExt;
-use signal_hook::consts::signal::{SIGINT, SIGTERM};
+use signal_hook::consts::signal::{SIGHUP, SIGINT, SIGTERM};
 use signal_hook_tokio::Signals;
-use tracing::{info, warn};
+use tokio::try_join;
+use tracing::{error, info, warn};
 
 use crate::gateway;
-use crate::{adr, api, applayer::fuota, backend, downlink, integration, region, storage};
+use crate::helpers::errors::PrintFullError;
+use crate::{
+    adr, api, applayer::fuota, backend, certificate, codec, config, downlink, hsm, integration,
+    monitoring, quota, region, storage, uplink,
+};
 
-pub async fn run() -> Result<()> {
+pub async fn run(config_dir: &Path) -> Result<()> {
     info!(
         version = env!("CARGO_PKG_VERSION"),
         docs = "https://www.chirpstack.io/",
         "Starting ChirpStack LoRaWAN Network Server"
     );
 
+    let start = Instant::now();
+
     storage::setup().await?;
+    storage::cache::setup().await?;
+    api::auth::session::setup().await?;
     region::setup()?;
-    backend::setup().await?;
-    adr::setup().await?;
-    integration::setup().await?;
-    gateway::backend::setup().await?;
+    hsm::setup()?;
+
+    // These do not depend on each other and might need to connect to external services,
+    // therefore they are set up concurrently.
+    try_join!(backend::setup(), adr::setup(), integration::setup())?;
+
+    // The gateway backends must be set up after the above, as from this point uplinks are
+    // received. The schedulers are started after the gateway backends, as they send downlinks.
+    uplink::stats::setup().await;
+    try_join!(gateway::backend::setup(), api::setup())?;
     downlink::setup().await;
     fuota::setup().await;
-    api::setup().await?;
+    backend::async_answer::setup().await;
+    storage::metrics::setup().await;
+    gateway::state::setup().await;
+    gateway::noise_floor::setup().await;
+    certificate::setup().await;
+    codec::device_repository::setup().await;
+    monitoring::device_trace::setup().await;
+    monitoring::sla::setup().await;
+    monitoring::runtime::setup().await;
+    quota::setup().await;
+    storage::key_access_log::setup().await;
+
+    info!(duration = ?start.elapsed(), "ChirpStack started");
+
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP]).unwrap();
+    while let Some(signal) = signals.next().await {
+        if signal == SIGHUP {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = reload(config_dir).await {
+                error!(error = %e.full(), "Reloading configuration failed");
+            }
+            continue;
+        }
 
-    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
-    if let Some(signal) = signals.next().await {
         warn!(signal = ?signal, "Signal received, terminating process");
+        break;
+    }
+
+    Ok(())
+}
+
+// Re-reads the configuration and re-configures the global integrations, regions and gateway
+// backends of which the configuration has changed. Other settings which are read at runtime take
+// effect immediately, settings which are only used on start (e.g. the database configuration)
+// require a restart.
+async fn reload(config_dir: &Path) -> Result<()> {
+    let old = config::get();
+    let new = config::read(config_dir).await?;
+
+    // The configuration is only replaced once all the reload steps have succeeded, such that a
+    // failed reload does not leave a configuration which does not match the running state. The
+    // regions and integrations are prepared first, the gateway backends are reloaded last as
+    // these can only be replaced in place. The prepared changes are applied after this, which
+    // can not fail.
+    let regions = region::prepare_reload(&old, &new).await?;
+    let integrations = integration::prepare_reload(&old, &new).await?;
+    if let Err(e) = gateway::backend::reload(&old, &new).await {
+        integrations.abort().await;
+        return Err(e);
     }
+    integrations.apply().await;
+    regions.apply();
+    config::set(new);
 
+    info!("Configuration reloaded");
     Ok(())
 }
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::stream::StreamExt;
+use signal_hook::consts::signal::{SIGINT, SIGTERM};
+use signal_hook_tokio::Signals;
+use tokio::time::sleep;
+use tokio::try_join;
+use tracing::{info, warn};
+
+use crate::simulator::Simulator;
+use crate::{adr, applayer::fuota, backend, downlink, integration, region, storage, uplink};
+
+pub use crate::simulator::Options;
+
+// Runs the simulator against the uplink and downlink pipeline. Unlike the root command, the
+// API and the gateway backends are not started, the simulated gateways replace the latter.
+// When the duration is zero, the simulator runs until it is terminated.
+pub async fn run(opts: &Options, duration: Duration) -> Result<()> {
+    storage::setup().await?;
+    storage::cache::setup().await?;
+    region::setup()?;
+
+    try_join!(backend::setup(), adr::setup(), integration::setup())?;
+    uplink::stats::setup().await;
+
+    let sim = Simulator::start(opts).await?;
+    downlink::setup().await;
+    fuota::setup().await;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM]).unwrap();
+
+    if duration.is_zero() {
+        if let Some(signal) = signals.next().await {
+            warn!(signal = ?signal, "Signal received, stopping simulator");
+        }
+    } else {
+        tokio::select! {
+            Some(signal) = signals.next() => {
+                warn!(signal = ?signal, "Signal received, stopping simulator");
+            }
+            _ = sleep(duration) => {
+                info!(duration = ?duration, "Simulation duration elapsed");
+            }
+        }
+    }
+
+    sim.stop().await;
+
+    Ok(())
+}
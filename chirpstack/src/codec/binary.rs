@@ -0,0 +1,454 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// The codec script contains the JSON encoded payload definition, e.g.:
+//
+// {
+//   "uplink": [
+//     {
+//       "fPort": 1,
+//       "fields": [
+//         {"name": "temperature", "offset": 0, "length": 16, "type": "int", "scale": 0.1},
+//         {"name": "humidity", "offset": 16, "length": 7, "type": "uint"},
+//         {"name": "alarm", "offset": 23, "length": 1, "type": "bool"}
+//       ]
+//     }
+//   ],
+//   "downlink": []
+// }
+//
+// The offset and length of each field are in bits. Bits are numbered from the most significant
+// bit of the first byte. The first message matching the fPort (or without fPort) is used.
+
+// Max. size of a LoRaWAN FRMPayload.
+const MAX_PAYLOAD_SIZE: usize = 242;
+
+#[derive(Deserialize)]
+struct Definition {
+    #[serde(default)]
+    uplink: Vec<Message>,
+    #[serde(default)]
+    downlink: Vec<Message>,
+}
+
+impl Definition {
+    fn parse(s: &str) -> Result<Self> {
+        let d: Definition = serde_json::from_str(s).context("Parse binary codec definition")?;
+        for m in d.uplink.iter().chain(d.downlink.iter()) {
+            for f in &m.fields {
+                f.validate()?;
+            }
+        }
+        Ok(d)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Message {
+    #[serde(default)]
+    f_port: Option<u8>,
+    fields: Vec<Field>,
+}
+
+impl Message {
+    fn find(messages: &[Message], f_port: u8) -> Option<&Message> {
+        messages
+            .iter()
+            .find(|m| m.f_port.is_none() || m.f_port == Some(f_port))
+    }
+}
+
+#[derive(Deserialize)]
+struct Field {
+    name: String,
+    offset: usize,
+    length: usize,
+    #[serde(rename = "type")]
+    typ: FieldType,
+    #[serde(default)]
+    endianness: Endianness,
+    #[serde(default = "default_scale")]
+    scale: f64,
+}
+
+impl Field {
+    fn validate(&self) -> Result<()> {
+        if self.length == 0 || self.length > 64 {
+            return Err(anyhow!(
+                "Field {}: length must be between 1 and 64 bits",
+                self.name
+            ));
+        }
+
+        if self.typ == FieldType::Float && self.length != 32 && self.length != 64 {
+            return Err(anyhow!(
+                "Field {}: float length must be 32 or 64 bits",
+                self.name
+            ));
+        }
+
+        if self.endianness == Endianness::Little && (self.offset % 8 != 0 || self.length % 8 != 0) {
+            return Err(anyhow!(
+                "Field {}: little-endian fields must be byte aligned",
+                self.name
+            ));
+        }
+
+        match self.offset.checked_add(self.length) {
+            Some(end) if end <= MAX_PAYLOAD_SIZE * 8 => {}
+            _ => {
+                return Err(anyhow!(
+                    "Field {}: field exceeds the max. payload size of {} bytes",
+                    self.name,
+                    MAX_PAYLOAD_SIZE
+                ));
+            }
+        }
+
+        if self.scale == 0.0 {
+            return Err(anyhow!("Field {}: scale must not be 0", self.name));
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, b: &[u8]) -> Result<u64> {
+        if self.offset + self.length > b.len() * 8 {
+            return Err(anyhow!("Payload too short for field {}", self.name));
+        }
+
+        Ok(match self.endianness {
+            Endianness::Big => read_bits(b, self.offset, self.length),
+            Endianness::Little => {
+                let mut b = b[self.offset / 8..(self.offset + self.length) / 8].to_vec();
+                b.reverse();
+                read_bits(&b, 0, self.length)
+            }
+        })
+    }
+
+    fn write(&self, b: &mut [u8], v: u64) {
+        match self.endianness {
+            Endianness::Big => write_bits(b, self.offset, self.length, v),
+            Endianness::Little => {
+                let mut tmp = vec![0; self.length / 8];
+                write_bits(&mut tmp, 0, self.length, v);
+                tmp.reverse();
+                b[self.offset / 8..(self.offset + self.length) / 8].copy_from_slice(&tmp);
+            }
+        }
+    }
+
+    fn decode(&self, b: &[u8]) -> Result<pbjson_types::value::Kind> {
+        let v = self.read(b)?;
+
+        Ok(match self.typ {
+            FieldType::Uint => pbjson_types::value::Kind::NumberValue(v as f64 * self.scale),
+            FieldType::Int => {
+                // Sign-extend the value to 64 bits.
+                let shift = 64 - self.length;
+                let v = ((v << shift) as i64) >> shift;
+                pbjson_types::value::Kind::NumberValue(v as f64 * self.scale)
+            }
+            FieldType::Float => {
+                let v = if self.length == 32 {
+                    f32::from_bits(v as u32) as f64
+                } else {
+                    f64::from_bits(v)
+                };
+                pbjson_types::value::Kind::NumberValue(v * self.scale)
+            }
+            FieldType::Bool => pbjson_types::value::Kind::BoolValue(v != 0),
+        })
+    }
+
+    fn encode(&self, val: &prost_types::value::Kind) -> Result<u64> {
+        let v = match val {
+            prost_types::value::Kind::NumberValue(v) => *v,
+            prost_types::value::Kind::BoolValue(v) => {
+                if *v {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Field {}: value must be a number or bool",
+                    self.name
+                ));
+            }
+        };
+
+        Ok(match self.typ {
+            FieldType::Uint => {
+                let v = (v / self.scale).round();
+                let max = u64::MAX >> (64 - self.length);
+                if v < 0.0 || v > max as f64 {
+                    return Err(anyhow!("Field {}: value out of range", self.name));
+                }
+                v as u64
+            }
+            FieldType::Int => {
+                let v = (v / self.scale).round();
+                let min = -(1_i128 << (self.length - 1));
+                let max = (1_i128 << (self.length - 1)) - 1;
+                if v < min as f64 || v > max as f64 {
+                    return Err(anyhow!("Field {}: value out of range", self.name));
+                }
+                (v as i64 as u64) & (u64::MAX >> (64 - self.length))
+            }
+            FieldType::Float => {
+                if self.length == 32 {
+                    ((v / self.scale) as f32).to_bits() as u64
+                } else {
+                    (v / self.scale).to_bits()
+                }
+            }
+            FieldType::Bool => (v != 0.0) as u64,
+        })
+    }
+}
+
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum FieldType {
+    Uint,
+    Int,
+    Float,
+    Bool,
+}
+
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+pub fn decode(f_port: u8, decoder_config: &str, b: &[u8]) -> Result<pbjson_types::Struct> {
+    let d = Definition::parse(decoder_config)?;
+    let m = Message::find(&d.uplink, f_port)
+        .ok_or_else(|| anyhow!("No uplink definition for fPort {}", f_port))?;
+
+    let mut out = pbjson_types::Struct::default();
+    for f in &m.fields {
+        out.fields.insert(
+            f.name.clone(),
+            pbjson_types::Value {
+                kind: Some(f.decode(b)?),
+            },
+        );
+    }
+
+    Ok(out)
+}
+
+pub fn encode(f_port: u8, encoder_config: &str, obj: &prost_types::Struct) -> Result<Vec<u8>> {
+    let d = Definition::parse(encoder_config)?;
+    let m = Message::find(&d.downlink, f_port)
+        .ok_or_else(|| anyhow!("No downlink definition for fPort {}", f_port))?;
+
+    let bits = m
+        .fields
+        .iter()
+        .map(|f| f.offset + f.length)
+        .max()
+        .unwrap_or_default();
+    let mut b = vec![0; bits.div_ceil(8)];
+
+    for f in &m.fields {
+        let val = obj
+            .fields
+            .get(&f.name)
+            .and_then(|v| v.kind.as_ref())
+            .ok_or_else(|| anyhow!("Field {} is missing", f.name))?;
+        let v = f.encode(val)?;
+        f.write(&mut b, v);
+    }
+
+    Ok(b)
+}
+
+fn read_bits(b: &[u8], offset: usize, length: usize) -> u64 {
+    let mut v: u64 = 0;
+    for p in offset..offset + length {
+        let bit = (b[p / 8] >> (7 - p % 8)) & 0x01;
+        v = (v << 1) | bit as u64;
+    }
+    v
+}
+
+fn write_bits(b: &mut [u8], offset: usize, length: usize, v: u64) {
+    for (i, p) in (offset..offset + length).enumerate() {
+        let bit = (v >> (length - 1 - i)) & 0x01;
+        if bit == 1 {
+            b[p / 8] |= 0x80 >> (p % 8);
+        } else {
+            b[p / 8] &= !(0x80 >> (p % 8));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::codec::convert;
+
+    const DEFINITION: &str = r#"
+    {
+        "uplink": [
+            {
+                "fPort": 1,
+                "fields": [
+                    {"name": "temperature", "offset": 0, "length": 16, "type": "int", "scale": 0.1},
+                    {"name": "humidity", "offset": 16, "length": 7, "type": "uint"},
+                    {"name": "alarm", "offset": 23, "length": 1, "type": "bool"},
+                    {"name": "counter", "offset": 24, "length": 16, "type": "uint", "endianness": "little"},
+                    {"name": "pressure", "offset": 40, "length": 32, "type": "float"}
+                ]
+            }
+        ],
+        "downlink": [
+            {
+                "fields": [
+                    {"name": "interval", "offset": 0, "length": 12, "type": "uint", "scale": 10},
+                    {"name": "enabled", "offset": 12, "length": 1, "type": "bool"},
+                    {"name": "offset", "offset": 16, "length": 8, "type": "int"}
+                ]
+            }
+        ]
+    }
+    "#;
+
+    #[test]
+    fn test_decode() {
+        let mut b = vec![0xff, 0x38, 0x65, 0x01, 0x02];
+        b.extend_from_slice(&1013.25_f32.to_be_bytes());
+
+        let out = decode(1, DEFINITION, &b).unwrap();
+        assert_eq!(
+            convert::json_to_struct(&serde_json::json!({
+                "temperature": -20.0,
+                "humidity": 50,
+                "alarm": true,
+                "counter": 513,
+                "pressure": 1013.25,
+            })),
+            out
+        );
+
+        // payload too short
+        assert_eq!(
+            "Payload too short for field pressure",
+            decode(1, DEFINITION, &b[..5]).err().unwrap().to_string()
+        );
+
+        // no definition for fPort
+        assert_eq!(
+            "No uplink definition for fPort 2",
+            decode(2, DEFINITION, &b).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_encode() {
+        let obj = convert::pb_json_to_prost(&convert::json_to_struct(&serde_json::json!({
+            "interval": 3600,
+            "enabled": true,
+            "offset": -2,
+        })));
+
+        let out = encode(10, DEFINITION, &obj).unwrap();
+        assert_eq!(vec![0x16, 0x88, 0xfe], out);
+
+        // out of range
+        let obj = convert::pb_json_to_prost(&convert::json_to_struct(&serde_json::json!({
+            "interval": 100000,
+            "enabled": true,
+            "offset": 0,
+        })));
+        assert_eq!(
+            "Field interval: value out of range",
+            encode(10, DEFINITION, &obj).err().unwrap().to_string()
+        );
+
+        // missing field
+        let obj = convert::pb_json_to_prost(&convert::json_to_struct(&serde_json::json!({
+            "interval": 3600,
+        })));
+        assert_eq!(
+            "Field enabled is missing",
+            encode(10, DEFINITION, &obj).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_invalid_definition() {
+        let definition = r#"
+        {
+            "uplink": [
+                {
+                    "fields": [
+                        {"name": "value", "offset": 4, "length": 16, "type": "uint", "endianness": "little"}
+                    ]
+                }
+            ]
+        }
+        "#;
+
+        assert_eq!(
+            "Field value: little-endian fields must be byte aligned",
+            decode(1, definition, &[0x00, 0x00, 0x00])
+                .err()
+                .unwrap()
+                .to_string()
+        );
+
+        let definition = r#"
+        {
+            "downlink": [
+                {
+                    "fields": [
+                        {"name": "value", "offset": 18446744073709551615, "length": 8, "type": "uint"}
+                    ]
+                }
+            ]
+        }
+        "#;
+
+        assert_eq!(
+            "Field value: field exceeds the max. payload size of 242 bytes",
+            encode(1, definition, &Default::default())
+                .err()
+                .unwrap()
+                .to_string()
+        );
+
+        let definition = r#"
+        {
+            "downlink": [
+                {
+                    "fields": [
+                        {"name": "value", "offset": 1936, "length": 8, "type": "uint"}
+                    ]
+                }
+            ]
+        }
+        "#;
+
+        assert_eq!(
+            "Field value: field exceeds the max. payload size of 242 bytes",
+            encode(1, definition, &Default::default())
+                .err()
+                .unwrap()
+                .to_string()
+        );
+    }
+}
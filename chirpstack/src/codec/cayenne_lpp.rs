@@ -16,6 +16,11 @@ const LPP_BAROMETER: u8 = 115;
 const LPP_GYROMETER: u8 = 134;
 const LPP_GPS_LOCATION: u8 = 136;
 
+// Extended types.
+const LPP_UNIX_TIME: u8 = 133;
+const LPP_GPS_LOCATION_PRECISE: u8 = 137;
+const LPP_SWITCH: u8 = 142;
+
 pub fn decode(b: &[u8]) -> Result<pbjson_types::Struct> {
     let lpp = CayenneLpp::from_slice(b).context("Decode Cayenne LPP payload")?;
     Ok(lpp.to_struct())
@@ -58,6 +63,9 @@ struct CayenneLpp {
     barometer: BTreeMap<u8, f64>,
     gyrometer: BTreeMap<u8, Gyrometer>,
     gps_location: BTreeMap<u8, GpsLocation>,
+    unix_time: BTreeMap<u8, u32>,
+    gps_location_precise: BTreeMap<u8, GpsLocation>,
+    switch: BTreeMap<u8, u8>,
 }
 
 impl CayenneLpp {
@@ -84,6 +92,9 @@ impl CayenneLpp {
                 LPP_BAROMETER => lpp.set_barometer(buf[0], &mut cur)?,
                 LPP_GYROMETER => lpp.set_gyrometer(buf[0], &mut cur)?,
                 LPP_GPS_LOCATION => lpp.set_gps_location(buf[0], &mut cur)?,
+                LPP_UNIX_TIME => lpp.set_unix_time(buf[0], &mut cur)?,
+                LPP_GPS_LOCATION_PRECISE => lpp.set_gps_location_precise(buf[0], &mut cur)?,
+                LPP_SWITCH => lpp.set_switch(buf[0], &mut cur)?,
                 _ => {
                     return Err(anyhow!("Invalid data type: {}", buf[1]));
                 }
@@ -126,6 +137,11 @@ impl CayenneLpp {
                 "barometer" => lpp.set_barometer_from_value(v).context("barometer")?,
                 "gyrometer" => lpp.set_gyrometer_from_value(v).context("gyrometer")?,
                 "gpsLocation" => lpp.set_gps_location_from_value(v).context("gpsLocation")?,
+                "unixTime" => lpp.set_unix_time_from_value(v).context("unixTime")?,
+                "gpsLocationPrecise" => lpp
+                    .set_gps_location_precise_from_value(v)
+                    .context("gpsLocationPrecise")?,
+                "switch" => lpp.set_switch_from_value(v).context("switch")?,
                 _ => {
                     return Err(anyhow!("Unexpected key '{}' in payload", k));
                 }
@@ -239,6 +255,31 @@ impl CayenneLpp {
             out.extend(&alt.to_be_bytes()[1..]);
         }
 
+        // unix time
+        for (k, v) in &self.unix_time {
+            out.extend([*k, LPP_UNIX_TIME]);
+            out.extend(v.to_be_bytes());
+        }
+
+        // gps location (precise)
+        for (k, v) in &self.gps_location_precise {
+            out.extend([*k, LPP_GPS_LOCATION_PRECISE]);
+
+            let lat = (v.latitude * 1000000.0).round() as i32;
+            let lon = (v.longitude * 1000000.0).round() as i32;
+            let alt = (v.altitude * 100.0).round() as i32;
+
+            out.extend(lat.to_be_bytes());
+            out.extend(lon.to_be_bytes());
+            out.extend(&alt.to_be_bytes()[1..]);
+        }
+
+        // switch
+        for (k, v) in &self.switch {
+            out.extend([*k, LPP_SWITCH]);
+            out.push(*v);
+        }
+
         out
     }
 
@@ -521,6 +562,80 @@ impl CayenneLpp {
             );
         }
 
+        if !self.unix_time.is_empty() {
+            let mut val: pbjson_types::Struct = Default::default();
+            for (k, v) in &self.unix_time {
+                val.fields.insert(
+                    format!("{}", k),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(*v as f64)),
+                    },
+                );
+            }
+            out.fields.insert(
+                "unixTime".to_string(),
+                pbjson_types::Value {
+                    kind: Some(pbjson_types::value::Kind::StructValue(val)),
+                },
+            );
+        }
+
+        if !self.gps_location_precise.is_empty() {
+            let mut val: pbjson_types::Struct = Default::default();
+            for (k, v) in &self.gps_location_precise {
+                let mut item: pbjson_types::Struct = Default::default();
+                item.fields.insert(
+                    "latitude".to_string(),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(v.latitude)),
+                    },
+                );
+                item.fields.insert(
+                    "longitude".to_string(),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(v.longitude)),
+                    },
+                );
+                item.fields.insert(
+                    "altitude".to_string(),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(v.altitude)),
+                    },
+                );
+
+                val.fields.insert(
+                    format!("{}", k),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::StructValue(item)),
+                    },
+                );
+            }
+            out.fields.insert(
+                "gpsLocationPrecise".to_string(),
+                pbjson_types::Value {
+                    kind: Some(pbjson_types::value::Kind::StructValue(val)),
+                },
+            );
+        }
+
+        if !self.switch.is_empty() {
+            let mut val: pbjson_types::Struct = Default::default();
+            for (k, v) in &self.switch {
+                val.fields.insert(
+                    format!("{}", k),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(*v as f64)),
+                    },
+                );
+            }
+            out.fields.insert(
+                "switch".to_string(),
+                pbjson_types::Value {
+                    kind: Some(pbjson_types::value::Kind::StructValue(val)),
+                },
+            );
+        }
+
         out
     }
 
@@ -555,8 +670,14 @@ impl CayenneLpp {
         if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
             for (k, v) in &s.fields {
                 let c: u8 = k.parse()?;
-                if let Some(prost_types::value::Kind::NumberValue(v)) = &v.kind {
-                    self.digital_output.insert(c, *v as u8);
+                match &v.kind {
+                    Some(prost_types::value::Kind::NumberValue(v)) => {
+                        self.digital_output.insert(c, *v as u8);
+                    }
+                    Some(prost_types::value::Kind::BoolValue(v)) => {
+                        self.digital_output.insert(c, *v as u8);
+                    }
+                    _ => {}
                 }
             }
         }
@@ -854,40 +975,75 @@ impl CayenneLpp {
     }
 
     fn set_gps_location_from_value(&mut self, v: &prost_types::Value) -> Result<()> {
+        self.gps_location.extend(gps_locations_from_value(v)?);
+        Ok(())
+    }
+
+    fn set_unix_time(&mut self, channel: u8, cur: &mut Cursor<&[u8]>) -> Result<()> {
+        let mut buf: [u8; 4] = [0; 4];
+        cur.read_exact(&mut buf)?;
+        self.unix_time.insert(channel, u32::from_be_bytes(buf));
+        Ok(())
+    }
+
+    fn set_unix_time_from_value(&mut self, v: &prost_types::Value) -> Result<()> {
         if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
             for (k, v) in &s.fields {
                 let c: u8 = k.parse()?;
-                let mut item = GpsLocation {
-                    latitude: 0.0,
-                    longitude: 0.0,
-                    altitude: 0.0,
-                };
+                if let Some(prost_types::value::Kind::NumberValue(v)) = &v.kind {
+                    self.unix_time.insert(c, *v as u32);
+                }
+            }
+        }
 
-                if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
-                    let lat = s
-                        .fields
-                        .get("latitude")
-                        .ok_or_else(|| anyhow!("latitude field is missing"))?;
-                    let lon = s
-                        .fields
-                        .get("longitude")
-                        .ok_or_else(|| anyhow!("longitude field is missing"))?;
-                    let alt = s
-                        .fields
-                        .get("altitude")
-                        .ok_or_else(|| anyhow!("altitude field is missing"))?;
+        Ok(())
+    }
 
-                    if let Some(prost_types::value::Kind::NumberValue(v)) = &lat.kind {
-                        item.latitude = *v;
-                    }
-                    if let Some(prost_types::value::Kind::NumberValue(v)) = &lon.kind {
-                        item.longitude = *v;
+    fn set_gps_location_precise(&mut self, channel: u8, cur: &mut Cursor<&[u8]>) -> Result<()> {
+        let mut buf_lat: [u8; 4] = [0; 4];
+        let mut buf_lon: [u8; 4] = [0; 4];
+        let mut buf_alt: [u8; 3] = [0; 3];
+        cur.read_exact(&mut buf_lat)?;
+        cur.read_exact(&mut buf_lon)?;
+        cur.read_exact(&mut buf_alt)?;
+        self.gps_location_precise.insert(
+            channel,
+            GpsLocation {
+                latitude: (i32::from_be_bytes(buf_lat) as f64) / 1000000.0,
+                longitude: (i32::from_be_bytes(buf_lon) as f64) / 1000000.0,
+                altitude: ((i32::from_be_bytes([buf_alt[0], buf_alt[1], buf_alt[2], 0]) >> 8)
+                    as f64)
+                    / 100.0,
+            },
+        );
+        Ok(())
+    }
+
+    fn set_gps_location_precise_from_value(&mut self, v: &prost_types::Value) -> Result<()> {
+        self.gps_location_precise
+            .extend(gps_locations_from_value(v)?);
+        Ok(())
+    }
+
+    fn set_switch(&mut self, channel: u8, cur: &mut Cursor<&[u8]>) -> Result<()> {
+        let mut buf: [u8; 1] = [0; 1];
+        cur.read_exact(&mut buf)?;
+        self.switch.insert(channel, buf[0]);
+        Ok(())
+    }
+
+    fn set_switch_from_value(&mut self, v: &prost_types::Value) -> Result<()> {
+        if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
+            for (k, v) in &s.fields {
+                let c: u8 = k.parse()?;
+                match &v.kind {
+                    Some(prost_types::value::Kind::NumberValue(v)) => {
+                        self.switch.insert(c, if *v == 0.0 { 0 } else { 1 });
                     }
-                    if let Some(prost_types::value::Kind::NumberValue(v)) = &alt.kind {
-                        item.altitude = *v;
+                    Some(prost_types::value::Kind::BoolValue(v)) => {
+                        self.switch.insert(c, *v as u8);
                     }
-
-                    self.gps_location.insert(c, item);
+                    _ => {}
                 }
             }
         }
@@ -896,9 +1052,54 @@ impl CayenneLpp {
     }
 }
 
+fn gps_locations_from_value(v: &prost_types::Value) -> Result<Vec<(u8, GpsLocation)>> {
+    let mut out = Vec::new();
+
+    if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
+        for (k, v) in &s.fields {
+            let c: u8 = k.parse()?;
+            let mut item = GpsLocation {
+                latitude: 0.0,
+                longitude: 0.0,
+                altitude: 0.0,
+            };
+
+            if let Some(prost_types::value::Kind::StructValue(s)) = &v.kind {
+                let lat = s
+                    .fields
+                    .get("latitude")
+                    .ok_or_else(|| anyhow!("latitude field is missing"))?;
+                let lon = s
+                    .fields
+                    .get("longitude")
+                    .ok_or_else(|| anyhow!("longitude field is missing"))?;
+                let alt = s
+                    .fields
+                    .get("altitude")
+                    .ok_or_else(|| anyhow!("altitude field is missing"))?;
+
+                if let Some(prost_types::value::Kind::NumberValue(v)) = &lat.kind {
+                    item.latitude = *v;
+                }
+                if let Some(prost_types::value::Kind::NumberValue(v)) = &lon.kind {
+                    item.longitude = *v;
+                }
+                if let Some(prost_types::value::Kind::NumberValue(v)) = &alt.kind {
+                    item.altitude = *v;
+                }
+
+                out.push((c, item));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::codec::convert;
 
     #[test]
     fn test_lpp() {
@@ -1842,4 +2043,51 @@ pub mod test {
         let b_encode = encode(&prost_struct).unwrap();
         assert_eq!(b, b_encode);
     }
+
+    #[test]
+    fn test_lpp_extended() {
+        let b: Vec<u8> = vec![
+            1, 133, 103, 225, 50, 128, // unix time
+            2, 137, 2, 133, 59, 72, 250, 195, 187, 64, 0, 3, 232, // gps location (precise)
+            4, 142, 1, // switch
+        ];
+
+        let obj = serde_json::json!({
+            "gpsLocationPrecise": {
+                "2": {
+                    "latitude": 42.285896,
+                    "longitude": -87.83584,
+                    "altitude": 10.0,
+                },
+            },
+            "unixTime": {
+                "1": 1742811776.0,
+            },
+            "switch": {
+                "4": 1.0,
+            },
+        });
+
+        let lpp_decode = decode(&b).unwrap();
+        assert_eq!(convert::json_to_struct(&obj), lpp_decode);
+
+        let b_encode = encode(&convert::pb_json_to_prost(&convert::json_to_struct(&obj))).unwrap();
+        assert_eq!(b, b_encode);
+    }
+
+    #[test]
+    fn test_lpp_encode_bool() {
+        let obj = serde_json::json!({
+            "digitalOutput": {
+                "1": true,
+            },
+            "switch": {
+                "2": false,
+                "3": true,
+            },
+        });
+
+        let b = encode(&convert::pb_json_to_prost(&convert::json_to_struct(&obj))).unwrap();
+        assert_eq!(vec![1, 1, 1, 2, 142, 0, 3, 142, 1], b);
+    }
 }
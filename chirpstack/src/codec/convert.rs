@@ -23,6 +23,16 @@ fn _rquickjs_to_struct_val(val: &rquickjs::Value) -> Option<pbjson_types::value:
                 Some(pbjson_types::value::Kind::NumberValue(v))
             }
         }
+        rquickjs::Type::BigInt => {
+            // BigInt values that can not be represented exactly as number are returned as string.
+            let s = val.get::<rquickjs::convert::Coerced<String>>().ok()?.0;
+            match s.parse::<i64>() {
+                Ok(v) if v.unsigned_abs() <= 1 << 53 => {
+                    Some(pbjson_types::value::Kind::NumberValue(v as f64))
+                }
+                _ => Some(pbjson_types::value::Kind::StringValue(s)),
+            }
+        }
         rquickjs::Type::String => Some(pbjson_types::value::Kind::StringValue(
             val.as_string().unwrap().to_string().unwrap(),
         )),
@@ -140,3 +150,69 @@ fn _pb_json_to_prost(v: &pbjson_types::Value) -> prost_types::Value {
         }),
     }
 }
+
+pub fn struct_to_json(obj: &prost_types::Struct) -> serde_json::Value {
+    serde_json::Value::Object(
+        obj.fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), _struct_to_json(v)))
+            .collect(),
+    )
+}
+
+fn _struct_to_json(val: &prost_types::Value) -> serde_json::Value {
+    match &val.kind {
+        None | Some(prost_types::value::Kind::NullValue(_)) => serde_json::Value::Null,
+        Some(prost_types::value::Kind::NumberValue(v)) => serde_json::Number::from_f64(*v)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Some(prost_types::value::Kind::StringValue(v)) => serde_json::Value::String(v.to_string()),
+        Some(prost_types::value::Kind::BoolValue(v)) => serde_json::Value::Bool(*v),
+        Some(prost_types::value::Kind::StructValue(v)) => struct_to_json(v),
+        Some(prost_types::value::Kind::ListValue(v)) => {
+            serde_json::Value::Array(v.values.iter().map(_struct_to_json).collect())
+        }
+    }
+}
+
+pub fn json_to_struct(val: &serde_json::Value) -> pbjson_types::Struct {
+    if let Some(pbjson_types::value::Kind::StructValue(v)) = _json_to_struct_val(val) {
+        return v;
+    }
+
+    Default::default()
+}
+
+fn _json_to_struct_val(val: &serde_json::Value) -> Option<pbjson_types::value::Kind> {
+    match val {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(v) => Some(pbjson_types::value::Kind::BoolValue(*v)),
+        serde_json::Value::Number(v) => v.as_f64().map(pbjson_types::value::Kind::NumberValue),
+        serde_json::Value::String(v) => Some(pbjson_types::value::Kind::StringValue(v.clone())),
+        serde_json::Value::Array(v) => Some(pbjson_types::value::Kind::ListValue(
+            pbjson_types::ListValue {
+                values: v
+                    .iter()
+                    .map(|v| pbjson_types::Value {
+                        kind: _json_to_struct_val(v),
+                    })
+                    .collect(),
+            },
+        )),
+        serde_json::Value::Object(v) => Some(pbjson_types::value::Kind::StructValue(
+            pbjson_types::Struct {
+                fields: v
+                    .iter()
+                    .map(|(k, v)| {
+                        (
+                            k.to_string(),
+                            pbjson_types::Value {
+                                kind: _json_to_struct_val(v),
+                            },
+                        )
+                    })
+                    .collect(),
+            },
+        )),
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::time::sleep;
+use tracing::{error, info, span, trace, warn, Instrument, Level};
+
+use super::js;
+use crate::config;
+use crate::coordination;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::device_repository_codec;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Device {
+    #[serde(rename = "firmwareVersions")]
+    pub firmware_versions: Vec<FirmwareVersion>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FirmwareVersion {
+    pub version: String,
+    pub profiles: BTreeMap<String, ProfileMeta>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ProfileMeta {
+    pub codec: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct JsCodec {
+    #[serde(rename = "uplinkDecoder")]
+    pub uplink_decoder: Option<CodecFunction>,
+    #[serde(rename = "downlinkEncoder")]
+    pub downlink_encoder: Option<CodecFunction>,
+    #[serde(rename = "downlinkDecoder")]
+    pub downlink_decoder: Option<CodecFunction>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CodecFunction {
+    #[serde(rename = "fileName")]
+    pub filename: String,
+}
+
+// Reference to a codec in the LoRaWAN Device Repository. The format is vendor_id/device_id,
+// in which case the codec of the latest firmware version is used. The codec can be pinned to a
+// firmware version using vendor_id/device_id@firmware_version.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Reference {
+    pub vendor_id: String,
+    pub device_id: String,
+    pub firmware_version: Option<String>,
+}
+
+impl FromStr for Reference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, firmware_version) = match s.split_once('@') {
+            Some((path, fw)) => (path, Some(fw.to_string())),
+            None => (s, None),
+        };
+
+        let (vendor_id, device_id) = path
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Expected vendor_id/device_id[@firmware_version]"))?;
+
+        let id_regex = regex::Regex::new(r"^[a-zA-Z0-9_\-]+$").unwrap();
+        if !id_regex.is_match(vendor_id) || !id_regex.is_match(device_id) {
+            return Err(anyhow!("Invalid vendor_id or device_id"));
+        }
+
+        if let Some(fw) = &firmware_version {
+            if fw.is_empty() {
+                return Err(anyhow!("Firmware version must not be empty"));
+            }
+        }
+
+        Ok(Reference {
+            vendor_id: vendor_id.to_string(),
+            device_id: device_id.to_string(),
+            firmware_version,
+        })
+    }
+}
+
+pub async fn setup() {
+    let conf = config::get();
+    if conf.codec.device_repository.url.is_empty() {
+        return;
+    }
+
+    info!(url = %conf.codec.device_repository.url, refresh_interval = ?conf.codec.device_repository.refresh_interval, "Setting up device repository codec sync loop");
+
+    tokio::spawn(async move {
+        loop {
+            let span = span!(Level::INFO, "device_repository_sync");
+
+            match coordination::with_lock("codec:device_repository", sync_all().instrument(span))
+                .await
+            {
+                Ok(Some(Err(e))) => {
+                    error!(error = %e.full(), "Device repository codec sync failed");
+                }
+                Ok(Some(Ok(_))) => {}
+                Ok(None) => {
+                    trace!("Device repository codec sync is locked by an other instance, skipping");
+                }
+                Err(e) => {
+                    error!(error = %e.full(), "Acquire device repository codec sync lock failed");
+                }
+            }
+
+            sleep(conf.codec.device_repository.refresh_interval).await;
+        }
+    });
+}
+
+// Syncs all the codecs referenced by the device-profiles and deletes the codecs that are no
+// longer referenced.
+async fn sync_all() -> Result<()> {
+    let ids = device_repository_codec::get_referenced_ids().await?;
+    info!(count = ids.len(), "Syncing device repository codecs");
+
+    for id in &ids {
+        // A failing codec must not block the sync of the others. The previously synced
+        // version of the codec is kept.
+        if let Err(e) = sync(id).await {
+            warn!(id = %id, error = %e.full(), "Syncing device repository codec failed");
+        }
+    }
+
+    device_repository_codec::delete_unreferenced().await?;
+
+    Ok(())
+}
+
+// Fetches the codec for the given reference from the Device Repository and stores it.
+pub async fn sync(reference: &str) -> Result<device_repository_codec::DeviceRepositoryCodec> {
+    let r = Reference::from_str(reference)?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let device: Device = serde_yaml::from_str(
+        &get_file(
+            &client,
+            &format!("vendor/{}/{}.yaml", r.vendor_id, r.device_id),
+        )
+        .await?,
+    )
+    .context("Parse device")?;
+
+    let fw = match &r.firmware_version {
+        Some(version) => device
+            .firmware_versions
+            .iter()
+            .find(|fw| &fw.version == version)
+            .ok_or_else(|| anyhow!("Firmware version {} does not exist", version))?,
+        None => device
+            .firmware_versions
+            .last()
+            .ok_or_else(|| anyhow!("Device does not have any firmware versions"))?,
+    };
+
+    let codec_id = fw
+        .profiles
+        .values()
+        .map(|p| p.codec.clone())
+        .find(|c| !c.is_empty())
+        .ok_or_else(|| anyhow!("Firmware version {} does not have a codec", fw.version))?;
+
+    let codec: JsCodec = serde_yaml::from_str(
+        &get_file(
+            &client,
+            &format!("vendor/{}/{}.yaml", r.vendor_id, codec_id),
+        )
+        .await?,
+    )
+    .context("Parse codec")?;
+
+    let mut filenames: Vec<String> = Vec::new();
+    for f in [
+        &codec.uplink_decoder,
+        &codec.downlink_encoder,
+        &codec.downlink_decoder,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if !filenames.contains(&f.filename) {
+            filenames.push(f.filename.clone());
+        }
+    }
+
+    let mut script = String::new();
+    for f in &filenames {
+        script.push_str(&get_file(&client, &format!("vendor/{}/{}", r.vendor_id, f)).await?);
+        script.push('\n');
+    }
+
+    Ok(
+        device_repository_codec::upsert(device_repository_codec::DeviceRepositoryCodec {
+            id: reference.to_string(),
+            vendor_id: r.vendor_id,
+            device_id: r.device_id,
+            firmware_version: fw.version.clone(),
+            codec_id,
+            script,
+            ..Default::default()
+        })
+        .await?,
+    )
+}
+
+async fn get_file(client: &reqwest::Client, path: &str) -> Result<String> {
+    let conf = config::get();
+    if conf.codec.device_repository.url.is_empty() {
+        return Err(anyhow!("Device repository url is not configured"));
+    }
+
+    let url = format!(
+        "{}/{}",
+        conf.codec.device_repository.url.trim_end_matches('/'),
+        path
+    );
+
+    trace!(url = %url, "Fetching file from device repository");
+    let resp = client
+        .get(&url)
+        .send()
+        .await
+        .with_context(|| format!("Fetch {}", url))?
+        .error_for_status()
+        .with_context(|| format!("Fetch {}", url))?;
+
+    Ok(resp.text().await?)
+}
+
+pub async fn decode(
+    recv_time: DateTime<Utc>,
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    reference: &str,
+    b: &[u8],
+) -> Result<pbjson_types::Struct> {
+    let c = device_repository_codec::get(reference)
+        .await
+        .context("Get device repository codec")?;
+    js::decode(recv_time, f_port, variables, &HashMap::new(), &c.script, b).await
+}
+
+pub async fn encode(
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    reference: &str,
+    s: &prost_types::Struct,
+) -> Result<Vec<u8>> {
+    let c = device_repository_codec::get(reference)
+        .await
+        .context("Get device repository codec")?;
+    js::encode(f_port, variables, &HashMap::new(), &c.script, s).await
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn test_reference_from_str() {
+        assert_eq!(
+            Reference {
+                vendor_id: "acme".into(),
+                device_id: "sensor-1".into(),
+                firmware_version: None,
+            },
+            Reference::from_str("acme/sensor-1").unwrap()
+        );
+        assert_eq!(
+            Reference {
+                vendor_id: "acme".into(),
+                device_id: "sensor-1".into(),
+                firmware_version: Some("1.2".into()),
+            },
+            Reference::from_str("acme/sensor-1@1.2").unwrap()
+        );
+        assert!(Reference::from_str("acme").is_err());
+        assert!(Reference::from_str("acme/../sensor-1").is_err());
+        assert!(Reference::from_str("acme/sensor-1@").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let _guard = test::prepare().await;
+        let server = MockServer::start();
+
+        let mut conf = (*config::get()).clone();
+        conf.codec.device_repository.url = server.url("/");
+        config::set(conf);
+
+        server.mock(|when, then| {
+            when.method(GET).path("/vendor/acme/sensor-1.yaml");
+            then.status(200).body(
+                r#"
+firmwareVersions:
+  - version: '1.0'
+    profiles:
+      EU863-870:
+        id: sensor-profile
+        codec: sensor-1-codec-v1
+  - version: '2.0'
+    profiles:
+      EU863-870:
+        id: sensor-profile
+        codec: sensor-1-codec-v2
+"#,
+            );
+        });
+
+        for v in ["v1", "v2"] {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(format!("/vendor/acme/sensor-1-codec-{}.yaml", v));
+                then.status(200).body(format!(
+                    r#"
+uplinkDecoder:
+  fileName: sensor-1-{}.js
+downlinkEncoder:
+  fileName: sensor-1-{}.js
+"#,
+                    v, v
+                ));
+            });
+
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(format!("/vendor/acme/sensor-1-{}.js", v));
+                then.status(200).body(format!(
+                    "function decodeUplink(input) {{ return {{ data: {{ version: '{}' }} }}; }}",
+                    v
+                ));
+            });
+        }
+
+        // latest firmware version
+        let c = sync("acme/sensor-1").await.unwrap();
+        assert_eq!("2.0", c.firmware_version);
+        assert_eq!("sensor-1-codec-v2", c.codec_id);
+        assert_eq!(
+            "function decodeUplink(input) { return { data: { version: 'v2' } }; }\n",
+            c.script
+        );
+
+        // pinned firmware version
+        let c = sync("acme/sensor-1@1.0").await.unwrap();
+        assert_eq!("1.0", c.firmware_version);
+        assert_eq!("sensor-1-codec-v1", c.codec_id);
+
+        let out = decode(Utc::now(), 1, &HashMap::new(), "acme/sensor-1@1.0", &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(pbjson_types::value::Kind::StringValue("v1".into())),
+            out.fields.get("version").cloned().unwrap().kind
+        );
+
+        // unknown firmware version
+        assert!(sync("acme/sensor-1@3.0").await.is_err());
+
+        // unknown device
+        assert!(sync("acme/sensor-2").await.is_err());
+    }
+}
@@ -1,70 +1,36 @@
 use std::collections::HashMap;
-use std::time::SystemTime;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use rquickjs::{CatchResultExt, IntoJs};
 
 use super::convert;
-use crate::config;
 
+mod pool;
 mod vendor_base64_js;
 mod vendor_buffer;
 mod vendor_ieee754;
 
+lazy_static! {
+    // Codec scripts using import or export statements are evaluated as ES module.
+    static ref MODULE_REGEX: Regex = Regex::new(r"(?m)^\s*(import|export)\b").unwrap();
+}
+
 pub async fn decode(
     recv_time: DateTime<Utc>,
     f_port: u8,
     variables: &HashMap<String, String>,
+    libraries: &HashMap<String, String>,
     decode_config: &str,
     b: &[u8],
 ) -> Result<pbjson_types::Struct> {
-    let conf = config::get();
-    let max_run_ts = SystemTime::now() + conf.codec.js.max_execution_time;
-
-    let resolver = rquickjs::loader::BuiltinResolver::default()
-        .with_module("base64-js")
-        .with_module("ieee754")
-        .with_module("buffer");
-    let loader = rquickjs::loader::BuiltinLoader::default()
-        .with_module("base64-js", vendor_base64_js::SCRIPT)
-        .with_module("ieee754", vendor_ieee754::SCRIPT)
-        .with_module("buffer", vendor_buffer::SCRIPT);
-
-    let rt = rquickjs::Runtime::new()?;
-    rt.set_interrupt_handler(Some(Box::new(move || SystemTime::now() > max_run_ts)));
-    rt.set_loader(resolver, loader);
-
-    let ctx = rquickjs::Context::full(&rt)?;
-
-    let script = format!(
-        r#"
-        {}
-
-        decodeUplink(chirpstack_input)
-        "#,
-        decode_config
-    );
+    let instance = pool::get()?;
     let b = b.to_vec();
 
-    let out = ctx.with(|ctx| -> Result<pbjson_types::Struct> {
-        // We need to export the Buffer class, as eval / eval_with_options
-        // does not allow using import statement.
-        let buff = rquickjs::Module::declare(
-            ctx.clone(),
-            "b",
-            r#"
-            import { Buffer } from "buffer";
-            export { Buffer }
-            "#,
-        )
-        .context("Declare script")?;
-        let (buff, buff_promise) = buff
-            .eval()
-            .catch(&ctx)
-            .map_err(|e| anyhow!("JS error: {}", e))?;
-        () = buff_promise.finish()?;
-        let buff: rquickjs::Function = buff.get("Buffer")?;
+    let out = instance.ctx.with(|ctx| -> Result<pbjson_types::Struct> {
+        // The Buffer class has already been set up by the pool.
+        set_libraries(&ctx, libraries)?;
 
         let input = rquickjs::Object::new(ctx.clone())?;
         input.set("bytes", b.into_js(&ctx)?)?;
@@ -72,17 +38,7 @@ pub async fn decode(
         input.set("recvTime", recv_time.into_js(&ctx)?)?;
         input.set("variables", variables.into_js(&ctx)?)?;
 
-        let globals = ctx.globals();
-        globals.set("chirpstack_input", input)?;
-        globals.set("Buffer", buff)?;
-
-        let mut eval_options = rquickjs::context::EvalOptions::default();
-        eval_options.strict = false;
-
-        let res: rquickjs::Object = ctx
-            .eval_with_options(script, eval_options)
-            .catch(&ctx)
-            .map_err(|e| anyhow!("JS error: {}", e))?;
+        let res = execute(&ctx, decode_config, "decodeUplink", input)?;
 
         let errors: Result<Vec<String>, rquickjs::Error> = res.get("errors");
         if let Ok(errors) = errors {
@@ -95,7 +51,12 @@ pub async fn decode(
         }
 
         Ok(convert::rquickjs_to_struct(&res))
-    })?;
+    });
+
+    if out.is_ok() {
+        pool::put(instance);
+    }
+    let out = out?;
 
     let data = out.fields.get("data").cloned().unwrap_or_default();
     if let Some(pbjson_types::value::Kind::StructValue(v)) = data.kind {
@@ -108,71 +69,22 @@ pub async fn decode(
 pub async fn encode(
     f_port: u8,
     variables: &HashMap<String, String>,
+    libraries: &HashMap<String, String>,
     encode_config: &str,
     s: &prost_types::Struct,
 ) -> Result<Vec<u8>> {
-    let conf = config::get();
-    let max_run_ts = SystemTime::now() + conf.codec.js.max_execution_time;
-
-    let resolver = rquickjs::loader::BuiltinResolver::default()
-        .with_module("base64-js")
-        .with_module("ieee754")
-        .with_module("buffer");
-    let loader = rquickjs::loader::BuiltinLoader::default()
-        .with_module("base64-js", vendor_base64_js::SCRIPT)
-        .with_module("ieee754", vendor_ieee754::SCRIPT)
-        .with_module("buffer", vendor_buffer::SCRIPT);
+    let instance = pool::get()?;
 
-    let rt = rquickjs::Runtime::new()?;
-    rt.set_interrupt_handler(Some(Box::new(move || SystemTime::now() > max_run_ts)));
-    rt.set_loader(resolver, loader);
-
-    let ctx = rquickjs::Context::full(&rt)?;
-
-    let script = format!(
-        r#"
-        {}
-
-        encodeDownlink(chirpstack_input)
-        "#,
-        encode_config,
-    );
-
-    ctx.with(|ctx| {
-        // We need to export the Buffer class, as eval / eval_with_options
-        // does not allow using import statement.
-        let buff = rquickjs::Module::declare(
-            ctx.clone(),
-            "b",
-            r#"
-            import { Buffer } from "buffer";
-            export { Buffer }
-            "#,
-        )
-        .context("Declare script")?;
-        let (buff, buff_promise) = buff
-            .eval()
-            .catch(&ctx)
-            .map_err(|e| anyhow!("JS error: {}", e))?;
-        () = buff_promise.finish()?;
-        let buff: rquickjs::Function = buff.get("Buffer")?;
+    let out = instance.ctx.with(|ctx| -> Result<Vec<u8>> {
+        // The Buffer class has already been set up by the pool.
+        set_libraries(&ctx, libraries)?;
 
         let input = rquickjs::Object::new(ctx.clone())?;
         input.set("fPort", f_port.into_js(&ctx)?)?;
         input.set("variables", variables.into_js(&ctx)?)?;
         input.set("data", convert::struct_to_rquickjs(&ctx, s))?;
 
-        let globals = ctx.globals();
-        globals.set("chirpstack_input", input)?;
-        globals.set("Buffer", buff)?;
-
-        let mut eval_options = rquickjs::context::EvalOptions::default();
-        eval_options.strict = false;
-
-        let res: rquickjs::Object = ctx
-            .eval_with_options(script, eval_options)
-            .catch(&ctx)
-            .map_err(|e| anyhow!("JS error: {}", e))?;
+        let res = execute(&ctx, encode_config, "encodeDownlink", input)?;
 
         let errors: Result<Vec<String>, rquickjs::Error> = res.get("errors");
         if let Ok(errors) = errors {
@@ -190,12 +102,98 @@ pub async fn encode(
         let v: Vec<u8> = v.iter().map(|v| *v as u8).collect();
 
         Ok(v)
-    })
+    });
+
+    if out.is_ok() {
+        pool::put(instance);
+    }
+    out
+}
+
+// Executes the given codec function with the given input. In case the codec script is an ES
+// module, the function must be exported by the module. Otherwise the script is evaluated as
+// (non-strict) script and the function must be declared in the global scope.
+fn execute<'js>(
+    ctx: &rquickjs::Ctx<'js>,
+    script: &str,
+    func: &str,
+    input: rquickjs::Object<'js>,
+) -> Result<rquickjs::Object<'js>> {
+    if MODULE_REGEX.is_match(script) {
+        let m = rquickjs::Module::declare(ctx.clone(), "codec", script)
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error: {}", e))?;
+        let (m, m_promise) = m
+            .eval()
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error: {}", e))?;
+        () = m_promise
+            .finish()
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error: {}", e))?;
+
+        let f: rquickjs::Function = m
+            .get(func)
+            .map_err(|_| anyhow!("{} is not exported by the codec module", func))?;
+
+        return f
+            .call((input,))
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error: {}", e));
+    }
+
+    ctx.globals().set("chirpstack_input", input)?;
+
+    let script = format!(
+        r#"
+        {}
+
+        {}(chirpstack_input)
+        "#,
+        script, func
+    );
+
+    let mut eval_options = rquickjs::context::EvalOptions::default();
+    eval_options.strict = false;
+
+    ctx.eval_with_options(script, eval_options)
+        .catch(ctx)
+        .map_err(|e| anyhow!("JS error: {}", e))
+}
+
+// Declares the codec libraries as modules and exposes the exports of each library as a global
+// object, named after the library. Codec modules and libraries can also import a library as
+// "lib/<name>". All libraries are declared before evaluation, such that the import order
+// between libraries does not matter.
+fn set_libraries(ctx: &rquickjs::Ctx<'_>, libraries: &HashMap<String, String>) -> Result<()> {
+    let globals = ctx.globals();
+
+    let mut declared = Vec::with_capacity(libraries.len());
+    for (name, script) in libraries {
+        let m = rquickjs::Module::declare(ctx.clone(), format!("lib/{}", name), script.as_str())
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error in library {}: {}", name, e))?;
+        declared.push((name, m));
+    }
+
+    for (name, m) in declared {
+        let (m, m_promise) = m
+            .eval()
+            .catch(ctx)
+            .map_err(|e| anyhow!("JS error in library {}: {}", name, e))?;
+        () = m_promise.finish()?;
+
+        let exports: rquickjs::Object = m.namespace()?;
+        globals.set(name.as_str(), exports)?;
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::{config, test};
     use chrono::TimeZone;
 
     #[tokio::test]
@@ -210,10 +208,88 @@ pub mod test {
         .to_string();
 
         let vars: HashMap<String, String> = HashMap::new();
-        let out = decode(Utc::now(), 10, &vars, &decoder, &[0x01, 0x02, 0x03]).await;
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &HashMap::new(),
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await;
         assert!(out.is_err());
     }
 
+    #[tokio::test]
+    pub async fn test_decode_memory_limit() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.codec.js.max_memory_size = 32 * 1024 * 1024;
+        config::set(conf);
+
+        let decoder = r#"
+            function decodeUplink(input) {
+                return {
+                    data: {
+                        items: new Array(100000000).fill(0)
+                    }
+                };
+            }
+        "#
+        .to_string();
+
+        let vars: HashMap<String, String> = HashMap::new();
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &HashMap::new(),
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await;
+        assert!(out.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_decode_isolation() {
+        let decoder = r#"
+            function decodeUplink(input) {
+                var seen = typeof leaked !== "undefined";
+                leaked = true;
+
+                return {
+                    data: {
+                        seen: seen
+                    }
+                };
+            }
+        "#
+        .to_string();
+
+        let vars: HashMap<String, String> = HashMap::new();
+
+        // Globals set by a previous execution must not be visible, even when the same runtime
+        // is re-used from the pool.
+        for _ in 0..2 {
+            let out = decode(
+                Utc::now(),
+                10,
+                &vars,
+                &HashMap::new(),
+                &decoder,
+                &[0x01, 0x02, 0x03],
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                Some(pbjson_types::value::Kind::BoolValue(false)),
+                out.fields.get("seen").cloned().unwrap().kind
+            );
+        }
+    }
+
     #[tokio::test]
     pub async fn test_decode_error() {
         let decoder = r#"
@@ -224,7 +300,15 @@ pub mod test {
         .to_string();
 
         let vars: HashMap<String, String> = HashMap::new();
-        let out = decode(Utc::now(), 10, &vars, &decoder, &[0x01, 0x02, 0x03]).await;
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &HashMap::new(),
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await;
 
         assert_eq!(
             "JS error: Error: foo is not defined\n    at decodeUplink (eval_script:3:1)\n    at <eval> (eval_script:8:22)\n",
@@ -256,9 +340,16 @@ pub mod test {
         let mut vars: HashMap<String, String> = HashMap::new();
         vars.insert("foo".into(), "bar".into());
 
-        let out = decode(recv_time, 10, &vars, &decoder, &[0x01, 0x02, 0x03])
-            .await
-            .unwrap();
+        let out = decode(
+            recv_time,
+            10,
+            &vars,
+            &HashMap::new(),
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await
+        .unwrap();
 
         let expected = pbjson_types::Struct {
             fields: [
@@ -348,7 +439,7 @@ pub mod test {
             ..Default::default()
         };
 
-        let out = encode(10, &vars, &encoder, &input).await;
+        let out = encode(10, &vars, &HashMap::new(), &encoder, &input).await;
         assert!(out.is_err());
     }
 
@@ -367,7 +458,7 @@ pub mod test {
             ..Default::default()
         };
 
-        let out = encode(10, &vars, &encoder, &input).await;
+        let out = encode(10, &vars, &HashMap::new(), &encoder, &input).await;
         assert_eq!("JS error: Error: foo is not defined\n    at encodeDownlink (eval_script:3:1)\n    at <eval> (eval_script:8:24)\n", out.err().unwrap().to_string());
     }
 
@@ -399,7 +490,188 @@ pub mod test {
             },
         );
 
-        let out = encode(10, &vars, &encoder, &input).await.unwrap();
+        let out = encode(10, &vars, &HashMap::new(), &encoder, &input)
+            .await
+            .unwrap();
         assert_eq!(vec![1], out);
     }
+
+    #[tokio::test]
+    pub async fn test_decode_library() {
+        let decoder = r#"
+            function decodeUplink(input) {
+                return {
+                    data: {
+                        sum: checksum.sum(input.bytes)
+                    }
+                };
+            }
+        "#
+        .to_string();
+
+        let mut libraries: HashMap<String, String> = HashMap::new();
+        libraries.insert(
+            "checksum".into(),
+            r#"
+            export function sum(b) {
+                return b.reduce((a, v) => a + v, 0);
+            }
+            "#
+            .into(),
+        );
+
+        let vars: HashMap<String, String> = HashMap::new();
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &libraries,
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            Some(pbjson_types::value::Kind::NumberValue(6.0)),
+            out.fields.get("sum").cloned().unwrap().kind
+        );
+
+        // invalid library
+        libraries.insert("invalid".into(), "export function (".into());
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &libraries,
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await;
+        assert!(out
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("JS error in library invalid"));
+    }
+
+    #[tokio::test]
+    pub async fn test_decode_module() {
+        let decoder = r#"
+            import { sum } from "lib/checksum";
+
+            export function decodeUplink(input) {
+                return {
+                    data: {
+                        sum: sum(input.bytes),
+                        unit: input.variables?.unit ?? "C",
+                        counter: BigInt(input.bytes[2]) << 8n,
+                        big: 1n << 60n
+                    }
+                };
+            }
+        "#
+        .to_string();
+
+        let mut libraries: HashMap<String, String> = HashMap::new();
+        libraries.insert(
+            "checksum".into(),
+            r#"
+            export function sum(b) {
+                return b.reduce((a, v) => a + v, 0);
+            }
+            "#
+            .into(),
+        );
+
+        let vars: HashMap<String, String> = HashMap::new();
+        let out = decode(
+            Utc::now(),
+            10,
+            &vars,
+            &libraries,
+            &decoder,
+            &[0x01, 0x02, 0x03],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            Some(pbjson_types::value::Kind::NumberValue(6.0)),
+            out.fields.get("sum").cloned().unwrap().kind
+        );
+        assert_eq!(
+            Some(pbjson_types::value::Kind::StringValue("C".into())),
+            out.fields.get("unit").cloned().unwrap().kind
+        );
+        assert_eq!(
+            Some(pbjson_types::value::Kind::NumberValue(768.0)),
+            out.fields.get("counter").cloned().unwrap().kind
+        );
+        assert_eq!(
+            Some(pbjson_types::value::Kind::StringValue(
+                "1152921504606846976".into()
+            )),
+            out.fields.get("big").cloned().unwrap().kind
+        );
+
+        // unknown library
+        let decoder = r#"
+            import { sum } from "lib/unknown";
+
+            export function decodeUplink(input) {
+                return { data: { sum: sum(input.bytes) } };
+            }
+        "#
+        .to_string();
+        let out = decode(Utc::now(), 10, &vars, &libraries, &decoder, &[]).await;
+        assert!(out.err().unwrap().to_string().starts_with("JS error:"));
+
+        // function not exported
+        let decoder = r#"
+            import { sum } from "lib/checksum";
+
+            function decodeUplink(input) {
+                return { data: { sum: sum(input.bytes) } };
+            }
+        "#
+        .to_string();
+        let out = decode(Utc::now(), 10, &vars, &libraries, &decoder, &[]).await;
+        assert_eq!(
+            "decodeUplink is not exported by the codec module",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_encode_module() {
+        let encoder = r#"
+            import { Buffer } from "buffer";
+
+            export function encodeDownlink(input) {
+                const b = Buffer.alloc(2);
+                b.writeUInt16BE(input.data?.value ?? 0);
+
+                return {
+                    bytes: Array.from(b)
+                };
+            }
+        "#
+        .to_string();
+
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let mut input = prost_types::Struct::default();
+        input.fields.insert(
+            "value".to_string(),
+            prost_types::Value {
+                kind: Some(prost_types::value::Kind::NumberValue(258.0)),
+            },
+        );
+
+        let out = encode(10, &vars, &HashMap::new(), &encoder, &input)
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 2], out);
+    }
 }
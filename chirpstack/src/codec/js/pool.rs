@@ -0,0 +1,170 @@
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use rquickjs::CatchResultExt;
+use tracing::warn;
+
+use super::{vendor_base64_js, vendor_buffer, vendor_ieee754};
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+
+lazy_static! {
+    static ref INSTANCES: Mutex<Vec<Instance>> = Mutex::new(Vec::new());
+}
+
+// Instance is a runtime together with a context in which the Buffer class has already been set
+// up. Evaluating the vendored Buffer module is the most expensive part of setting up a context,
+// therefore this is done before the instance is returned to the pool, instead of on execution.
+// A context is only used for a single execution, such that globals set by an execution are
+// never visible to the next execution.
+pub struct Instance {
+    pub ctx: rquickjs::Context,
+    rt: rquickjs::Runtime,
+}
+
+// Returns an instance from the pool, or a new instance in case the pool is empty. The execution
+// is interrupted once the max. execution time has been exceeded. The memory limit is set on each
+// get, such that configuration changes also apply to the pooled runtimes.
+pub fn get() -> Result<Instance> {
+    let conf = config::get();
+
+    let instance = INSTANCES.lock().unwrap().pop();
+    let instance = match instance {
+        Some(v) => v,
+        None => {
+            let rt = new_runtime()?;
+            let ctx = new_context(&rt)?;
+            Instance { ctx, rt }
+        }
+    };
+
+    // A limit of 0 disables the limit.
+    instance.rt.set_memory_limit(conf.codec.js.max_memory_size);
+
+    let max_run_ts = SystemTime::now() + conf.codec.js.max_execution_time;
+    instance
+        .rt
+        .set_interrupt_handler(Some(Box::new(move || SystemTime::now() > max_run_ts)));
+
+    Ok(instance)
+}
+
+// Returns the runtime of the instance to the pool. This must only be called after a successful
+// execution, as the state of the runtime is undefined after it has been interrupted or it ran
+// out of memory. The used context is dropped and a new context is set up in the background,
+// such that this does not add to the latency of the execution.
+pub fn put(instance: Instance) {
+    let conf = config::get();
+    if INSTANCES.lock().unwrap().len() >= conf.codec.js.pool_size {
+        return;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let Instance { ctx, rt } = instance;
+        drop(ctx);
+
+        rt.set_interrupt_handler(None);
+        rt.set_memory_limit(0);
+        rt.run_gc();
+
+        let ctx = match new_context(&rt) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(error = %e.full(), "Setting up JS context failed");
+                return;
+            }
+        };
+
+        let mut instances = INSTANCES.lock().unwrap();
+        if instances.len() < conf.codec.js.pool_size {
+            instances.push(Instance { ctx, rt });
+        }
+    });
+}
+
+// Returns a new context in which the Buffer class is exposed as global. We need to export the
+// Buffer class through a module, as eval / eval_with_options does not allow using import
+// statements in (non-module) scripts.
+fn new_context(rt: &rquickjs::Runtime) -> Result<rquickjs::Context> {
+    let ctx = rquickjs::Context::full(rt)?;
+
+    ctx.with(|ctx| -> Result<()> {
+        let buff = rquickjs::Module::declare(
+            ctx.clone(),
+            "b",
+            r#"
+            import { Buffer } from "buffer";
+            export { Buffer }
+            "#,
+        )
+        .context("Declare script")?;
+        let (buff, buff_promise) = buff
+            .eval()
+            .catch(&ctx)
+            .map_err(|e| anyhow!("JS error: {}", e))?;
+        () = buff_promise.finish()?;
+        let buff: rquickjs::Function = buff.get("Buffer")?;
+
+        ctx.globals().set("Buffer", buff)?;
+        Ok(())
+    })?;
+
+    Ok(ctx)
+}
+
+// Resolves the "lib/<name>" imports of codec libraries. The libraries are declared as modules
+// within the context of each execution, such that these are not handled by the loader.
+struct LibraryResolver;
+
+impl rquickjs::loader::Resolver for LibraryResolver {
+    fn resolve(
+        &mut self,
+        _ctx: &rquickjs::Ctx<'_>,
+        base: &str,
+        name: &str,
+    ) -> rquickjs::Result<String> {
+        if name.starts_with("lib/") {
+            Ok(name.to_string())
+        } else {
+            Err(rquickjs::Error::new_resolving(base, name))
+        }
+    }
+}
+
+// Loads the vendored modules. Unlike the rquickjs BuiltinLoader, which can load each module only
+// once, this loader can be used by multiple contexts of the same (pooled) runtime.
+struct VendorLoader;
+
+impl rquickjs::loader::Loader for VendorLoader {
+    fn load<'js>(
+        &mut self,
+        ctx: &rquickjs::Ctx<'js>,
+        name: &str,
+    ) -> rquickjs::Result<rquickjs::Module<'js, rquickjs::module::Declared>> {
+        let script = match name {
+            "base64-js" => vendor_base64_js::SCRIPT,
+            "ieee754" => vendor_ieee754::SCRIPT,
+            "buffer" => vendor_buffer::SCRIPT,
+            _ => return Err(rquickjs::Error::new_loading(name)),
+        };
+
+        rquickjs::Module::declare(ctx.clone(), name, script)
+    }
+}
+
+fn new_runtime() -> Result<rquickjs::Runtime> {
+    let resolver = (
+        rquickjs::loader::BuiltinResolver::default()
+            .with_module("base64-js")
+            .with_module("ieee754")
+            .with_module("buffer"),
+        LibraryResolver,
+    );
+    let loader = VendorLoader;
+
+    let rt = rquickjs::Runtime::new()?;
+    rt.set_loader(resolver, loader);
+
+    Ok(rt)
+}
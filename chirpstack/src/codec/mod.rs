@@ -12,10 +12,17 @@ use diesel::sql_types::Text;
 use diesel::sqlite::Sqlite;
 use diesel::{deserialize, serialize};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use crate::storage::codec_library;
+
+mod binary;
 mod cayenne_lpp;
 pub mod convert;
-mod js;
+pub mod device_repository;
+pub(crate) mod js;
+mod python;
+mod wasm;
 
 #[derive(Deserialize, Serialize, Copy, Clone, Debug, Eq, PartialEq, AsExpression, FromSqlRow)]
 #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
@@ -24,6 +31,10 @@ pub enum Codec {
     NONE,
     CAYENNE_LPP,
     JS,
+    WASM,
+    DEVICE_REPOSITORY,
+    PYTHON,
+    BINARY,
 }
 
 impl fmt::Display for Codec {
@@ -69,6 +80,10 @@ impl FromStr for Codec {
             "" | "NONE" => Codec::NONE,
             "CAYENNE_LPP" => Codec::CAYENNE_LPP,
             "JS" => Codec::JS,
+            "WASM" => Codec::WASM,
+            "DEVICE_REPOSITORY" => Codec::DEVICE_REPOSITORY,
+            "PYTHON" => Codec::PYTHON,
+            "BINARY" => Codec::BINARY,
             _ => {
                 return Err(anyhow!("Unexpected codec: {}", s));
             }
@@ -78,6 +93,7 @@ impl FromStr for Codec {
 
 pub async fn binary_to_struct(
     codec: Codec,
+    tenant_id: &Uuid,
     recv_time: DateTime<Utc>,
     f_port: u8,
     variables: &HashMap<String, String>,
@@ -87,12 +103,24 @@ pub async fn binary_to_struct(
     Ok(match codec {
         Codec::NONE => None,
         Codec::CAYENNE_LPP => Some(cayenne_lpp::decode(b).context("CayenneLpp decode")?),
-        Codec::JS => Some(js::decode(recv_time, f_port, variables, decoder_config, b).await?),
+        Codec::JS => {
+            let libraries = get_libraries(tenant_id).await?;
+            Some(js::decode(recv_time, f_port, variables, &libraries, decoder_config, b).await?)
+        }
+        Codec::WASM => Some(wasm::decode(recv_time, f_port, variables, decoder_config, b).await?),
+        Codec::DEVICE_REPOSITORY => {
+            Some(device_repository::decode(recv_time, f_port, variables, decoder_config, b).await?)
+        }
+        Codec::PYTHON => {
+            Some(python::decode(tenant_id, recv_time, f_port, variables, decoder_config, b).await?)
+        }
+        Codec::BINARY => Some(binary::decode(f_port, decoder_config, b).context("Binary decode")?),
     })
 }
 
 pub async fn struct_to_binary(
     codec: Codec,
+    tenant_id: &Uuid,
     f_port: u8,
     variables: &HashMap<String, String>,
     encoder_config: &str,
@@ -101,10 +129,29 @@ pub async fn struct_to_binary(
     Ok(match codec {
         Codec::NONE => Vec::new(),
         Codec::CAYENNE_LPP => cayenne_lpp::encode(obj).context("CayenneLpp encode")?,
-        Codec::JS => js::encode(f_port, variables, encoder_config, obj).await?,
+        Codec::JS => {
+            let libraries = get_libraries(tenant_id).await?;
+            js::encode(f_port, variables, &libraries, encoder_config, obj).await?
+        }
+        Codec::WASM => wasm::encode(f_port, variables, encoder_config, obj).await?,
+        Codec::DEVICE_REPOSITORY => {
+            device_repository::encode(f_port, variables, encoder_config, obj).await?
+        }
+        Codec::PYTHON => python::encode(tenant_id, f_port, variables, encoder_config, obj).await?,
+        Codec::BINARY => binary::encode(f_port, encoder_config, obj).context("Binary encode")?,
     })
 }
 
+// Returns the codec libraries of the tenant, by library name.
+async fn get_libraries(tenant_id: &Uuid) -> Result<HashMap<String, String>> {
+    Ok(codec_library::get_for_tenant(tenant_id)
+        .await
+        .context("Get codec libraries")?
+        .into_iter()
+        .map(|l| (l.name, l.script))
+        .collect())
+}
+
 pub fn get_measurements(s: &pbjson_types::Struct) -> HashMap<String, pbjson_types::value::Kind> {
     let mut out: HashMap<String, pbjson_types::value::Kind> = HashMap::new();
 
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+use super::convert;
+use crate::config;
+
+mod pool;
+mod sandbox;
+
+// The codec script must define the following functions:
+//
+//   * decode_uplink(input): called with a dict containing bytes, fPort, recvTime and variables
+//   * encode_downlink(input): called with a dict containing data, fPort and variables
+//
+// The returned dicts use the same structure as the JS codec functions. The functions are
+// executed by sandboxed Python worker processes, pooled per tenant.
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DecodeOutput {
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EncodeOutput {
+    #[serde(default)]
+    bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+pub async fn decode(
+    tenant_id: &Uuid,
+    recv_time: DateTime<Utc>,
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    decode_config: &str,
+    b: &[u8],
+) -> Result<pbjson_types::Struct> {
+    let input = serde_json::json!({
+        "bytes": b,
+        "fPort": f_port,
+        "recvTime": recv_time.to_rfc3339(),
+        "variables": variables,
+    });
+
+    let out = execute(tenant_id, decode_config, "decode_uplink", &input).await?;
+    let out: DecodeOutput = serde_json::from_value(out).context("Parse decode_uplink output")?;
+
+    if !out.errors.is_empty() {
+        return Err(anyhow!(
+            "decode_uplink returned errors: {}",
+            out.errors.join(", ")
+        ));
+    }
+
+    match out.data {
+        Some(v @ serde_json::Value::Object(_)) => Ok(convert::json_to_struct(&v)),
+        _ => Err(anyhow!("decode_uplink did not return 'data'")),
+    }
+}
+
+pub async fn encode(
+    tenant_id: &Uuid,
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    encode_config: &str,
+    s: &prost_types::Struct,
+) -> Result<Vec<u8>> {
+    let input = serde_json::json!({
+        "fPort": f_port,
+        "variables": variables,
+        "data": convert::struct_to_json(s),
+    });
+
+    let out = execute(tenant_id, encode_config, "encode_downlink", &input).await?;
+    let out: EncodeOutput = serde_json::from_value(out).context("Parse encode_downlink output")?;
+
+    if !out.errors.is_empty() {
+        return Err(anyhow!(
+            "encode_downlink returned errors: {}",
+            out.errors.join(", ")
+        ));
+    }
+
+    out.bytes
+        .ok_or_else(|| anyhow!("encode_downlink did not return 'bytes'"))
+}
+
+async fn execute(
+    tenant_id: &Uuid,
+    script: &str,
+    func: &str,
+    input: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let conf = config::get();
+
+    let req = serde_json::to_string(&serde_json::json!({
+        "script": script,
+        "function": func,
+        "input": input,
+    }))?;
+
+    let mut w = pool::get(tenant_id)?;
+
+    // In case of a timeout or error, the worker is dropped (and its process killed).
+    let resp = timeout(conf.codec.python.max_execution_time, w.call(&req))
+        .await
+        .map_err(|_| anyhow!("Max execution time exceeded"))??;
+    pool::put(tenant_id, w);
+
+    let resp: Response = serde_json::from_str(&resp).context("Parse Python worker response")?;
+    if let Some(e) = resp.error {
+        return Err(anyhow!("{} failed: {}", func, e));
+    }
+
+    Ok(resp.result.unwrap_or_default())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    async fn setup() -> std::sync::MutexGuard<'static, ()> {
+        let guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.codec.python.interpreter = "python3".into();
+        if unsafe { libc::geteuid() } == 0 {
+            conf.codec.python.user_id = 65534;
+            conf.codec.python.group_id = 65534;
+        }
+        config::set(conf);
+
+        guard
+    }
+
+    #[tokio::test]
+    async fn test_decode() {
+        let _guard = setup().await;
+
+        let decoder = r#"
+import struct
+
+def decode_uplink(input):
+    print("output must be ignored")
+    temp, = struct.unpack(">h", bytes(input["bytes"]))
+    return {
+        "data": {
+            "temperature": temp / 10,
+            "fPort": input["fPort"],
+            "unit": input["variables"]["unit"],
+        }
+    }
+"#;
+        let mut vars: HashMap<String, String> = HashMap::new();
+        vars.insert("unit".into(), "C".into());
+
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[0x00, 0xd7])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            convert::json_to_struct(&serde_json::json!({
+                "temperature": 21.5,
+                "fPort": 10,
+                "unit": "C",
+            })),
+            out
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_errors() {
+        let _guard = setup().await;
+
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let decoder = r#"
+def decode_uplink(input):
+    return {"errors": ["invalid payload"]}
+"#;
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[]).await;
+        assert_eq!(
+            "decode_uplink returned errors: invalid payload",
+            out.err().unwrap().to_string()
+        );
+
+        let decoder = r#"
+def decode_uplink(input):
+    raise ValueError("boom")
+"#;
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[]).await;
+        assert_eq!(
+            "decode_uplink failed: ValueError: boom",
+            out.err().unwrap().to_string()
+        );
+
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, "", &[]).await;
+        assert_eq!(
+            "decode_uplink failed: NameError: function 'decode_uplink' is not defined",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_restricted() {
+        let _guard = setup().await;
+
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let decoder = r#"
+import os
+
+def decode_uplink(input):
+    return {"data": {"cwd": os.getcwd()}}
+"#;
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[]).await;
+        assert_eq!(
+            "decode_uplink failed: ImportError: import of 'os' is not allowed",
+            out.err().unwrap().to_string()
+        );
+
+        let decoder = r#"
+def decode_uplink(input):
+    return {"data": {"passwd": open("/etc/passwd").read()}}
+"#;
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[]).await;
+        assert_eq!(
+            "decode_uplink failed: NameError: name 'open' is not defined",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_timeout() {
+        let _guard = setup().await;
+
+        let decoder = r#"
+def decode_uplink(input):
+    while True:
+        pass
+"#;
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, decoder, &[]).await;
+        assert_eq!(
+            "Max execution time exceeded",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode() {
+        let _guard = setup().await;
+
+        let encoder = r#"
+def encode_downlink(input):
+    return {"bytes": [1 if input["data"]["enabled"] else 0, input["fPort"]]}
+"#;
+        let vars: HashMap<String, String> = HashMap::new();
+        let data = convert::pb_json_to_prost(&convert::json_to_struct(&serde_json::json!({
+            "enabled": true,
+        })));
+
+        let out = encode(&Uuid::nil(), 10, &vars, encoder, &data)
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 10], out);
+    }
+
+    #[tokio::test]
+    async fn test_not_configured() {
+        let _guard = test::prepare().await;
+
+        let vars: HashMap<String, String> = HashMap::new();
+        let out = decode(&Uuid::nil(), Utc::now(), 10, &vars, "", &[]).await;
+        assert_eq!(
+            "Python codec runtime is not configured",
+            out.err().unwrap().to_string()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use uuid::Uuid;
+
+use super::sandbox;
+use crate::config;
+
+const RUNNER: &str = include_str!("runner.py");
+
+// Workers are pooled per tenant, such that a worker never executes the codec scripts of more
+// than one tenant.
+lazy_static! {
+    static ref WORKERS: Mutex<HashMap<Uuid, Vec<Worker>>> = Mutex::new(HashMap::new());
+}
+
+// Python worker process. The process is killed when the worker is dropped.
+pub struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    fn new() -> Result<Self> {
+        let conf = config::get();
+
+        // The CPU time limit (seconds) is a fallback for the max. execution time, in case the
+        // process can't be killed in time.
+        let max_cpu_time = conf
+            .codec
+            .python
+            .max_execution_time
+            .as_secs_f64()
+            .ceil()
+            .max(1.0) as u64;
+
+        let mut cmd = Command::new(&conf.codec.python.interpreter);
+        cmd.args(["-I", "-S", "-B", "-c", RUNNER])
+            .arg(conf.codec.python.max_memory_size.to_string())
+            .arg(max_cpu_time.to_string())
+            .env_clear()
+            .current_dir("/")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        sandbox::apply(&mut cmd)?;
+
+        let mut child = cmd.spawn().context("Start Python worker process")?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Python worker stdin is not available"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Python worker stdout is not available"))?;
+
+        Ok(Worker {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    // Sends the request to the worker and returns the response. Both the request and the
+    // response are a single line of JSON.
+    pub async fn call(&mut self, req: &str) -> Result<String> {
+        self.stdin.write_all(req.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+
+        let mut resp = String::new();
+        if self.stdout.read_line(&mut resp).await? == 0 {
+            return Err(anyhow!("Python worker process exited unexpectedly"));
+        }
+
+        Ok(resp)
+    }
+}
+
+// Returns a worker from the pool of the tenant, or a new worker in case the pool is empty.
+// Workers of which the process has exited are discarded.
+pub fn get(tenant_id: &Uuid) -> Result<Worker> {
+    let conf = config::get();
+    if conf.codec.python.interpreter.is_empty() {
+        return Err(anyhow!("Python codec runtime is not configured"));
+    }
+
+    loop {
+        let w = WORKERS
+            .lock()
+            .unwrap()
+            .get_mut(tenant_id)
+            .and_then(|v| v.pop());
+        match w {
+            Some(mut w) => {
+                if let Ok(None) = w.child.try_wait() {
+                    return Ok(w);
+                }
+            }
+            None => return Worker::new(),
+        }
+    }
+}
+
+// Returns the worker to the pool of the tenant. This must only be called after the worker
+// returned a response, as the state of the worker is undefined after a failed or interrupted
+// call.
+pub fn put(tenant_id: &Uuid, w: Worker) {
+    let conf = config::get();
+
+    let mut workers = WORKERS.lock().unwrap();
+    let workers = workers.entry(*tenant_id).or_default();
+    if workers.len() < conf.codec.python.pool_size {
+        workers.push(w);
+    }
+}
@@ -0,0 +1,139 @@
+# Worker process executing the Python codec functions.
+#
+# Each request is a single JSON encoded line (read from stdin) containing the
+# codec script, the name of the function to call and its input. The response
+# is written as a single JSON encoded line to stdout, containing either the
+# result or the error.
+#
+# The worker process is sandboxed by ChirpStack (unprivileged user, resource
+# limits and a seccomp filter blocking network access). As an additional layer,
+# the codec script is executed with a restricted set of builtins and can only
+# import the modules listed below.
+import builtins
+import json
+import resource
+import sys
+
+ALLOWED_MODULES = {
+    "base64",
+    "binascii",
+    "collections",
+    "datetime",
+    "functools",
+    "itertools",
+    "json",
+    "math",
+    "re",
+    "string",
+    "struct",
+}
+
+ALLOWED_BUILTINS = [
+    "ArithmeticError",
+    "AssertionError",
+    "Exception",
+    "IndexError",
+    "KeyError",
+    "LookupError",
+    "NameError",
+    "NotImplementedError",
+    "OverflowError",
+    "RuntimeError",
+    "StopIteration",
+    "TypeError",
+    "ValueError",
+    "ZeroDivisionError",
+    "__build_class__",
+    "abs",
+    "all",
+    "any",
+    "bin",
+    "bool",
+    "bytearray",
+    "bytes",
+    "callable",
+    "chr",
+    "dict",
+    "divmod",
+    "enumerate",
+    "filter",
+    "float",
+    "format",
+    "frozenset",
+    "hasattr",
+    "hex",
+    "int",
+    "isinstance",
+    "issubclass",
+    "iter",
+    "len",
+    "list",
+    "map",
+    "max",
+    "min",
+    "next",
+    "object",
+    "oct",
+    "ord",
+    "pow",
+    "print",
+    "range",
+    "repr",
+    "reversed",
+    "round",
+    "set",
+    "slice",
+    "sorted",
+    "str",
+    "sum",
+    "tuple",
+    "zip",
+]
+
+
+def restricted_import(name, globals=None, locals=None, fromlist=(), level=0):
+    if level != 0 or name.split(".")[0] not in ALLOWED_MODULES:
+        raise ImportError("import of '%s' is not allowed" % name)
+    return __import__(name, globals, locals, fromlist, level)
+
+
+restricted_builtins = {name: getattr(builtins, name) for name in ALLOWED_BUILTINS}
+restricted_builtins["__import__"] = restricted_import
+
+max_memory_size = int(sys.argv[1])
+if max_memory_size > 0:
+    resource.setrlimit(resource.RLIMIT_AS, (max_memory_size, max_memory_size))
+
+max_cpu_time = int(sys.argv[2])
+
+# Output written by the codec (e.g. using print) must not end up in the
+# responses.
+out = sys.stdout
+sys.stdout = sys.stderr
+
+for line in sys.stdin:
+    # The CPU time limit applies per execution. When exceeded, the worker
+    # process is terminated by the kernel.
+    usage = resource.getrusage(resource.RUSAGE_SELF)
+    cpu_time = int(usage.ru_utime + usage.ru_stime)
+    soft = cpu_time + max_cpu_time
+    _, hard = resource.getrlimit(resource.RLIMIT_CPU)
+    if hard != resource.RLIM_INFINITY:
+        soft = min(soft, hard)
+    resource.setrlimit(resource.RLIMIT_CPU, (soft, hard))
+
+    try:
+        req = json.loads(line)
+        scope = {"__name__": "codec", "__builtins__": restricted_builtins}
+        exec(compile(req["script"], "codec", "exec"), scope)
+
+        fn = scope.get(req["function"])
+        if not callable(fn):
+            raise NameError("function '%s' is not defined" % req["function"])
+
+        resp = json.dumps({"result": fn(req["input"])})
+    except BaseException as e:
+        resp = json.dumps({"error": "%s: %s" % (type(e).__name__, e)})
+
+    out.write(resp + "\n")
+    out.flush()
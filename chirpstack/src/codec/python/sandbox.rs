@@ -0,0 +1,158 @@
+use anyhow::Result;
+use tokio::process::Command;
+
+use crate::config;
+
+// Sandboxes the Python worker process:
+//
+//   * The process runs as the configured (unprivileged) user and group.
+//   * The process can not create new processes, write files or dump core.
+//   * A seccomp filter blocks network access and process inspection syscalls.
+//
+// The restricted builtins of the runner are not a security boundary on their own, as these
+// can be bypassed from within Python.
+#[cfg(target_os = "linux")]
+pub fn apply(cmd: &mut Command) -> Result<()> {
+    let conf = config::get();
+
+    if conf.codec.python.user_id != 0 {
+        cmd.uid(conf.codec.python.user_id);
+    }
+    if conf.codec.python.group_id != 0 {
+        cmd.gid(conf.codec.python.group_id);
+    }
+    if conf.codec.python.user_id == 0 && unsafe { libc::geteuid() } == 0 {
+        return Err(anyhow!(
+            "Python worker processes must not run as root, please configure codec.python.user_id"
+        ));
+    }
+
+    let filter = seccomp_filter()?;
+
+    // Only async-signal-safe functions may be called between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            for resource in [libc::RLIMIT_NPROC, libc::RLIMIT_FSIZE, libc::RLIMIT_CORE] {
+                let limit = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::setrlimit(resource, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            let prog = libc::sock_fprog {
+                len: filter.len() as u16,
+                filter: filter.as_ptr() as *mut libc::sock_filter,
+            };
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_cmd: &mut Command) -> Result<()> {
+    Err(anyhow!(
+        "Python codec runtime is only supported on Linux (sandboxing is not available)"
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn seccomp_filter() -> Result<Vec<libc::sock_filter>> {
+    // Offsets of the fields of struct seccomp_data.
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    let audit_arch: u32 = if cfg!(target_arch = "x86_64") {
+        0xc000003e
+    } else if cfg!(target_arch = "aarch64") {
+        0xc00000b7
+    } else {
+        return Err(anyhow!(
+            "Python codec runtime is not supported on this architecture (sandboxing is not available)"
+        ));
+    };
+
+    let deny: Vec<libc::c_long> = vec![
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_fork,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_vfork,
+    ];
+
+    let load = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+    let jeq = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+    let ret = (libc::BPF_RET | libc::BPF_K) as u16;
+    let eperm = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+    // Any other architecture (e.g. 32-bit syscalls) kills the process.
+    let mut filter = vec![
+        stmt(load, ARCH_OFFSET),
+        jump(jeq, audit_arch, 1, 0),
+        stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(load, NR_OFFSET),
+    ];
+
+    // The x32 ABI uses the x86_64 arch, with the __X32_SYSCALL_BIT set.
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(
+            (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+            0x40000000,
+            0,
+            1,
+        ),
+        stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+
+    for nr in deny {
+        filter.extend([jump(jeq, nr as u32, 0, 1), stmt(ret, eperm)]);
+    }
+    filter.push(stmt(ret, libc::SECCOMP_RET_ALLOW));
+
+    Ok(filter)
+}
+
+#[cfg(target_os = "linux")]
+fn stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::convert;
+use crate::config;
+
+// The WASM module is stored base64 encoded as codec script. The module must export:
+//
+//   * memory: the linear memory
+//   * alloc(len: i32) -> i32: allocates len bytes and returns the pointer
+//   * decode_uplink(ptr: i32, len: i32) -> i64
+//   * encode_downlink(ptr: i32, len: i32) -> i64
+//
+// The input (written to the memory allocated using alloc) and the output are JSON documents,
+// using the same structure as the JS codec functions. The returned i64 contains the pointer of
+// the output in the upper 32 bits and the length in the lower 32 bits. The module is not
+// provided with any imports.
+
+// Max. number of compiled modules to cache. When exceeded, the least recently used module is
+// removed from the cache.
+const MAX_CACHED_MODULES: usize = 128;
+
+lazy_static! {
+    // All modules are compiled and instantiated using the same engine, such that the compiled
+    // modules can be cached.
+    static ref ENGINE: wasmi::Engine = {
+        let mut wasm_conf = wasmi::Config::default();
+        wasm_conf.consume_fuel(true);
+        wasmi::Engine::new(&wasm_conf)
+    };
+
+    // Compiled modules by (base64 encoded) codec configuration, with the last time these were
+    // used.
+    static ref MODULES: Mutex<HashMap<String, (Instant, wasmi::Module)>> =
+        Mutex::new(HashMap::new());
+}
+
+struct State {
+    limits: wasmi::StoreLimits,
+}
+
+#[derive(Deserialize)]
+struct DecodeOutput {
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct EncodeOutput {
+    #[serde(default)]
+    bytes: Option<Vec<u8>>,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+pub async fn decode(
+    recv_time: DateTime<Utc>,
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    decode_config: &str,
+    b: &[u8],
+) -> Result<pbjson_types::Struct> {
+    let input = serde_json::json!({
+        "bytes": b,
+        "fPort": f_port,
+        "recvTime": recv_time.to_rfc3339(),
+        "variables": variables,
+    });
+
+    let out = execute(decode_config, "decode_uplink", &serde_json::to_vec(&input)?)?;
+    let out: DecodeOutput = serde_json::from_slice(&out).context("Parse decode_uplink output")?;
+
+    if !out.errors.is_empty() {
+        return Err(anyhow!(
+            "decode_uplink returned errors: {}",
+            out.errors.join(", ")
+        ));
+    }
+
+    match out.data {
+        Some(v @ serde_json::Value::Object(_)) => Ok(convert::json_to_struct(&v)),
+        _ => Err(anyhow!("decode_uplink did not return 'data'")),
+    }
+}
+
+pub async fn encode(
+    f_port: u8,
+    variables: &HashMap<String, String>,
+    encode_config: &str,
+    s: &prost_types::Struct,
+) -> Result<Vec<u8>> {
+    let input = serde_json::json!({
+        "fPort": f_port,
+        "variables": variables,
+        "data": convert::struct_to_json(s),
+    });
+
+    let out = execute(
+        encode_config,
+        "encode_downlink",
+        &serde_json::to_vec(&input)?,
+    )?;
+    let out: EncodeOutput = serde_json::from_slice(&out).context("Parse encode_downlink output")?;
+
+    if !out.errors.is_empty() {
+        return Err(anyhow!(
+            "encode_downlink returned errors: {}",
+            out.errors.join(", ")
+        ));
+    }
+
+    out.bytes
+        .ok_or_else(|| anyhow!("encode_downlink did not return 'bytes'"))
+}
+
+fn execute(codec_config: &str, func: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let conf = config::get();
+    let module = get_module(codec_config)?;
+
+    let mut store = wasmi::Store::new(
+        &ENGINE,
+        State {
+            limits: wasmi::StoreLimitsBuilder::new()
+                .memory_size(conf.codec.wasm.max_memory_size)
+                .instances(1)
+                .build(),
+        },
+    );
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(conf.codec.wasm.max_fuel)?;
+
+    let linker = wasmi::Linker::<State>::new(&ENGINE);
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .context("Instantiate WASM module")?
+        .start(&mut store)
+        .context("Start WASM module")?;
+
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or_else(|| anyhow!("WASM module does not export 'memory'"))?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&store, "alloc")
+        .context("Get 'alloc' function")?;
+    let f = instance
+        .get_typed_func::<(i32, i32), i64>(&store, func)
+        .with_context(|| format!("Get '{}' function", func))?;
+
+    let len = i32::try_from(input.len()).context("Input too large")?;
+    let ptr = alloc
+        .call(&mut store, len)
+        .map_err(|e| anyhow!("WASM error: {}", e))?;
+    memory
+        .write(&mut store, ptr as u32 as usize, input)
+        .context("Write input")?;
+
+    let res = f
+        .call(&mut store, (ptr, len))
+        .map_err(|e| anyhow!("WASM error: {}", e))? as u64;
+
+    // The pointer and length are returned by the module, these must be validated against the
+    // memory size before allocating the output buffer.
+    let out_ptr = (res >> 32) as usize;
+    let out_len = (res & 0xffffffff) as usize;
+    match out_ptr.checked_add(out_len) {
+        Some(end) if end <= memory.data_size(&store) => {}
+        _ => {
+            return Err(anyhow!(
+                "WASM output out of bounds (ptr: {}, len: {})",
+                out_ptr,
+                out_len
+            ))
+        }
+    }
+
+    let mut out = vec![0; out_len];
+    memory
+        .read(&store, out_ptr, &mut out)
+        .context("Read output")?;
+
+    Ok(out)
+}
+
+// Returns the compiled module for the given codec configuration. As compiling the module is
+// expensive, the compiled modules are cached.
+fn get_module(codec_config: &str) -> Result<wasmi::Module> {
+    let codec_config = codec_config.trim();
+
+    if let Some((last_used, module)) = MODULES.lock().unwrap().get_mut(codec_config) {
+        *last_used = Instant::now();
+        return Ok(module.clone());
+    }
+
+    let b = general_purpose::STANDARD
+        .decode(codec_config)
+        .context("Decode base64 encoded WASM module")?;
+    let module = wasmi::Module::new(&ENGINE, &b[..]).context("Load WASM module")?;
+
+    let mut modules = MODULES.lock().unwrap();
+    if modules.len() >= MAX_CACHED_MODULES {
+        if let Some(k) = modules
+            .iter()
+            .min_by_key(|(_, (last_used, _))| *last_used)
+            .map(|(k, _)| k.clone())
+        {
+            modules.remove(&k);
+        }
+    }
+    modules.insert(codec_config.to_string(), (Instant::now(), module.clone()));
+
+    Ok(module)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn get_module(wat: &str) -> String {
+        general_purpose::STANDARD.encode(wat::parse_str(wat).unwrap())
+    }
+
+    // Returns a module with decode_uplink and encode_downlink functions returning the
+    // given output, which is stored at offset 0.
+    fn get_static_module(output: &str) -> String {
+        get_module(&format!(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{}")
+                (func (export "alloc") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "decode_uplink") (param i32 i32) (result i64)
+                    (i64.const {}))
+                (func (export "encode_downlink") (param i32 i32) (result i64)
+                    (i64.const {})))
+            "#,
+            output.replace('"', "\\\""),
+            output.len(),
+            output.len(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_decode() {
+        let module = get_static_module(r#"{"data":{"temperature":21.5}}"#);
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pbjson_types::Struct {
+                fields: [(
+                    "temperature".to_string(),
+                    pbjson_types::Value {
+                        kind: Some(pbjson_types::value::Kind::NumberValue(21.5)),
+                    },
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+            },
+            out
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_errors() {
+        let module = get_static_module(r#"{"errors":["invalid payload"]}"#);
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03]).await;
+        assert_eq!(
+            "decode_uplink returned errors: invalid payload",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_fuel_exhausted() {
+        let module = get_module(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "decode_uplink") (param i32 i32) (result i64)
+                    (loop $l (br $l))
+                    (i64.const 0)))
+            "#,
+        );
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03]).await;
+        assert!(out.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_output_out_of_bounds() {
+        // The returned length exceeds the memory size (1 page = 64KiB).
+        let module = get_module(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "decode_uplink") (param i32 i32) (result i64)
+                    (i64.const 0xffffffff)))
+            "#,
+        );
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03]).await;
+        assert_eq!(
+            "WASM output out of bounds (ptr: 0, len: 4294967295)",
+            out.err().unwrap().to_string()
+        );
+
+        // The pointer is within the memory, but the output exceeds it.
+        let module = get_module(
+            r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32)
+                    (i32.const 1024))
+                (func (export "decode_uplink") (param i32 i32) (result i64)
+                    (i64.const 0x0000fff000000100)))
+            "#,
+        );
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03]).await;
+        assert_eq!(
+            "WASM output out of bounds (ptr: 65520, len: 256)",
+            out.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_imports() {
+        let module = get_module(
+            r#"
+            (module
+                (import "env" "foo" (func $foo))
+                (memory (export "memory") 1))
+            "#,
+        );
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = decode(Utc::now(), 10, &vars, &module, &[0x01, 0x02, 0x03]).await;
+        assert_eq!("Instantiate WASM module", out.err().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_get_module() {
+        let module = get_static_module(r#"{"bytes":[4,5,6]}"#);
+
+        super::get_module(&module).unwrap();
+        assert!(MODULES.lock().unwrap().contains_key(&module));
+
+        // Invalid modules are not cached.
+        assert!(super::get_module("AAAA").is_err());
+        assert!(!MODULES.lock().unwrap().contains_key("AAAA"));
+    }
+
+    #[tokio::test]
+    async fn test_encode() {
+        let module = get_static_module(r#"{"bytes":[1,2,3]}"#);
+        let vars: HashMap<String, String> = HashMap::new();
+
+        let out = encode(10, &vars, &module, &prost_types::Struct::default())
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], out);
+    }
+}
@@ -1,14 +1,18 @@
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{env, fs};
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use lrwn::region::CommonName;
 use lrwn::{AES128Key, DevAddrPrefix, EUI64Prefix, NetID};
 
+pub mod secrets;
+
 lazy_static! {
     static ref CONFIG: Mutex<Arc<Configuration>> = Mutex::new(Arc::new(Default::default()));
 }
@@ -20,10 +24,12 @@ pub struct Configuration {
     pub postgresql: Postgresql,
     pub redis: Redis,
     pub sqlite: Sqlite,
+    pub cache: Cache,
     pub api: Api,
     pub gateway: Gateway,
     pub network: Network,
     pub monitoring: Monitoring,
+    pub metrics: Metrics,
     pub integration: Integration,
     pub codec: Codec,
     pub user_authentication: UserAuthentication,
@@ -31,6 +37,9 @@ pub struct Configuration {
     pub backend_interfaces: BackendInterfaces,
     pub roaming: Roaming,
     pub keks: Vec<Kek>,
+    pub encryption: Encryption,
+    pub keystore: Keystore,
+    pub hsm: Hsm,
     pub regions: Vec<Region>,
     pub ui: UI,
 }
@@ -40,6 +49,7 @@ pub struct Configuration {
 pub struct Logging {
     pub level: String,
     pub json: bool,
+    pub device_rate_limit: DeviceLogRateLimit,
 }
 
 impl Default for Logging {
@@ -47,6 +57,24 @@ impl Default for Logging {
         Logging {
             level: "info".into(),
             json: false,
+            device_rate_limit: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DeviceLogRateLimit {
+    pub max_events: u32,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+impl Default for DeviceLogRateLimit {
+    fn default() -> Self {
+        DeviceLogRateLimit {
+            max_events: 0,
+            interval: Duration::from_secs(60),
         }
     }
 }
@@ -77,6 +105,7 @@ pub struct Redis {
     pub key_prefix: String,
     pub max_open_connections: u32,
     pub min_idle_connections: u32,
+    pub ttl: RedisTtl,
 }
 
 impl Default for Redis {
@@ -87,6 +116,49 @@ impl Default for Redis {
             key_prefix: "".into(),
             max_open_connections: 100,
             min_idle_connections: 0,
+            ttl: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RedisTtl {
+    #[serde(with = "humantime_serde")]
+    pub device_gateway_rx_info: Duration,
+    #[serde(with = "humantime_serde")]
+    pub device_mac_command_pending: Duration,
+    #[serde(with = "humantime_serde")]
+    pub passive_roaming_session: Duration,
+    #[serde(with = "humantime_serde")]
+    pub handover_roaming_session: Duration,
+    #[serde(with = "humantime_serde")]
+    pub deduplication: Duration,
+    #[serde(with = "humantime_serde")]
+    pub backend_async_answer: Duration,
+    #[serde(with = "humantime_serde")]
+    pub metrics_minute: Duration,
+    #[serde(with = "humantime_serde")]
+    pub metrics_hour: Duration,
+    #[serde(with = "humantime_serde")]
+    pub metrics_day: Duration,
+    #[serde(with = "humantime_serde")]
+    pub metrics_month: Duration,
+}
+
+impl Default for RedisTtl {
+    fn default() -> Self {
+        RedisTtl {
+            device_gateway_rx_info: Duration::ZERO,
+            device_mac_command_pending: Duration::ZERO,
+            passive_roaming_session: Duration::ZERO,
+            handover_roaming_session: Duration::ZERO,
+            deduplication: Duration::ZERO,
+            backend_async_answer: Duration::from_secs(30),
+            metrics_minute: Duration::from_secs(60 * 60 * 2),
+            metrics_hour: Duration::from_secs(60 * 60 * 24 * 2),
+            metrics_day: Duration::from_secs(60 * 60 * 24 * 31 * 2),
+            metrics_month: Duration::from_secs(60 * 60 * 24 * 365 * 2),
         }
     }
 }
@@ -114,11 +186,31 @@ impl Default for Sqlite {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Cache {
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Api {
     pub bind: String,
     pub secret: String,
+    pub tls_cert: String,
+    pub tls_key: String,
+    pub ca_cert: String,
+    pub client_certificates: Vec<ApiClientCertificate>,
+    pub auth_lockout: AuthLockout,
 }
 
 impl Default for Api {
@@ -126,27 +218,103 @@ impl Default for Api {
         Api {
             bind: "0.0.0.0:8080".into(),
             secret: "".into(),
+            tls_cert: "".into(),
+            tls_key: "".into(),
+            ca_cert: "".into(),
+            client_certificates: vec![],
+            auth_lockout: Default::default(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AuthLockout {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    pub max_failures: u32,
+    #[serde(with = "humantime_serde")]
+    pub lockout_duration: Duration,
+    #[serde(with = "humantime_serde")]
+    pub base_delay: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for AuthLockout {
+    fn default() -> Self {
+        AuthLockout {
+            enabled: false,
+            interval: Duration::from_secs(15 * 60),
+            max_failures: 5,
+            lockout_duration: Duration::from_secs(15 * 60),
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ApiClientCertificate {
+    pub common_name: String,
+    pub api_key_id: Uuid,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Gateway {
     #[serde(with = "humantime_serde")]
     pub client_cert_lifetime: Duration,
+    #[serde(with = "humantime_serde")]
+    pub client_cert_renew_before: Duration,
     pub ca_cert: String,
     pub ca_key: String,
+    pub ca_chain: Vec<String>,
+    pub crl_file: String,
+    #[serde(with = "humantime_serde")]
+    pub crl_lifetime: Duration,
     pub allow_unknown_gateways: bool,
+    #[serde(with = "humantime_serde")]
+    pub stats_batch_interval: Duration,
+    pub offline_detection: GatewayOfflineDetection,
 }
 
 impl Default for Gateway {
     fn default() -> Self {
         Gateway {
             client_cert_lifetime: Duration::from_secs(60 * 60 * 24 * 365),
+            client_cert_renew_before: Duration::from_secs(60 * 60 * 24 * 30),
             ca_cert: "".to_string(),
             ca_key: "".to_string(),
+            ca_chain: Vec::new(),
+            crl_file: "".to_string(),
+            crl_lifetime: Duration::from_secs(60 * 60 * 24 * 7),
             allow_unknown_gateways: false,
+            stats_batch_interval: Duration::ZERO,
+            offline_detection: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewayOfflineDetection {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    pub missed_stats_intervals: u32,
+}
+
+impl Default for GatewayOfflineDetection {
+    fn default() -> Self {
+        GatewayOfflineDetection {
+            enabled: false,
+            interval: Duration::from_secs(60),
+            missed_stats_intervals: 2,
         }
     }
 }
@@ -167,6 +335,8 @@ pub struct Network {
     pub mac_commands_disabled: bool,
     pub adr_plugins: Vec<String>,
     pub scheduler: Scheduler,
+    pub f_cnt_anomaly: FCntAnomaly,
+    pub quota: Quota,
 }
 
 impl Default for Network {
@@ -182,6 +352,8 @@ impl Default for Network {
             mac_commands_disabled: false,
             adr_plugins: vec![],
             scheduler: Default::default(),
+            f_cnt_anomaly: Default::default(),
+            quota: Default::default(),
         }
     }
 }
@@ -215,6 +387,59 @@ impl Default for Scheduler {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct FCntAnomaly {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    pub reset_threshold: u32,
+    pub replay_threshold: u32,
+    pub max_f_cnt_gap: u32,
+}
+
+impl Default for FCntAnomaly {
+    fn default() -> Self {
+        FCntAnomaly {
+            enabled: false,
+            interval: Duration::from_secs(60 * 60),
+            reset_threshold: 3,
+            replay_threshold: 1,
+            max_f_cnt_gap: 16384,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Quota {
+    #[serde(with = "humantime_serde")]
+    pub accounting_interval: Duration,
+    pub uplink_action: QuotaAction,
+    pub downlink_action: QuotaAction,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota {
+            accounting_interval: Duration::from_secs(60),
+            uplink_action: QuotaAction::Warn,
+            downlink_action: QuotaAction::Reject,
+        }
+    }
+}
+
+// Defines the behavior when a tenant exceeded its uplinks or downlinks per day quota.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaAction {
+    // Send a warning log event, but continue processing.
+    #[default]
+    Warn,
+    // Send a warning log event and drop the uplink or reject the downlink enqueue.
+    Reject,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Monitoring {
@@ -222,6 +447,7 @@ pub struct Monitoring {
     pub api_request_log_max_history: usize,
     pub backend_interfaces_log_max_history: usize,
     pub meta_log_max_history: usize,
+    pub meta_log_dropped_uplinks: bool,
     pub gateway_frame_log_max_history: usize,
     pub device_frame_log_max_history: usize,
     pub device_event_log_max_history: usize,
@@ -234,6 +460,18 @@ pub struct Monitoring {
     pub per_device_event_log_max_history: usize,
     #[serde(with = "humantime_serde")]
     pub per_device_event_log_ttl: Duration,
+    pub per_device_debug_trace_max_history: usize,
+    #[serde(with = "humantime_serde")]
+    pub per_device_debug_trace_ttl: Duration,
+    #[serde(with = "humantime_serde")]
+    pub device_debug_trace_max_duration: Duration,
+    #[serde(with = "humantime_serde")]
+    pub storage_slow_log_threshold: Duration,
+    #[serde(with = "humantime_serde")]
+    pub key_access_log_retention: Duration,
+    pub opentelemetry: OpenTelemetry,
+    pub tenant_metrics: TenantMetrics,
+    pub error_reporting: ErrorReporting,
 }
 
 impl Default for Monitoring {
@@ -243,6 +481,7 @@ impl Default for Monitoring {
             api_request_log_max_history: 10,
             backend_interfaces_log_max_history: 10,
             meta_log_max_history: 10,
+            meta_log_dropped_uplinks: false,
             gateway_frame_log_max_history: 10,
             device_frame_log_max_history: 10,
             device_event_log_max_history: 10,
@@ -252,26 +491,141 @@ impl Default for Monitoring {
             per_gateway_frame_log_ttl: Duration::from_secs(60 * 60 * 24 * 31), // 31 days
             per_device_frame_log_ttl: Duration::from_secs(60 * 60 * 24 * 31),
             per_device_event_log_ttl: Duration::from_secs(60 * 60 * 24 * 31),
+            per_device_debug_trace_max_history: 1000,
+            per_device_debug_trace_ttl: Duration::from_secs(60 * 60 * 24),
+            device_debug_trace_max_duration: Duration::from_secs(60 * 60),
+            storage_slow_log_threshold: Duration::ZERO,
+            key_access_log_retention: Duration::from_secs(60 * 60 * 24 * 365),
+            opentelemetry: Default::default(),
+            tenant_metrics: Default::default(),
+            error_reporting: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OpenTelemetry {
+    pub enabled: bool,
+    pub otlp_endpoint: String,
+    pub service_name: String,
+    pub sample_ratio: f64,
+}
+
+impl Default for OpenTelemetry {
+    fn default() -> Self {
+        OpenTelemetry {
+            enabled: false,
+            otlp_endpoint: "http://localhost:4317".into(),
+            service_name: "chirpstack".into(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TenantMetrics {
+    pub enabled: bool,
+    pub max_tenants: usize,
+}
+
+impl Default for TenantMetrics {
+    fn default() -> Self {
+        TenantMetrics {
+            enabled: false,
+            max_tenants: 100,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Default)]
 #[serde(default)]
+pub struct ErrorReporting {
+    pub dsn: String,
+    pub environment: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Metrics {
+    pub rollup: MetricsRollup,
+    pub sla: MetricsSla,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MetricsRollup {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub hour_retention: Duration,
+}
+
+impl Default for MetricsRollup {
+    fn default() -> Self {
+        MetricsRollup {
+            enabled: false,
+            interval: Duration::from_secs(60 * 5),
+            hour_retention: Duration::from_secs(60 * 60 * 24 * 2),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MetricsSla {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+}
+
+impl Default for MetricsSla {
+    fn default() -> Self {
+        MetricsSla {
+            enabled: false,
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Integration {
     pub enabled: Vec<String>,
+    pub max_retries: usize,
+    #[serde(with = "humantime_serde")]
+    pub retry_interval: Duration,
     pub mqtt: MqttIntegration,
     pub postgresql: PostgresqlIntegration,
     pub amqp: AmqpIntegration,
     pub kafka: KafkaIntegration,
 }
 
+impl Default for Integration {
+    fn default() -> Self {
+        Integration {
+            enabled: Vec::new(),
+            max_retries: 0,
+            retry_interval: Duration::from_secs(1),
+            mqtt: Default::default(),
+            postgresql: Default::default(),
+            amqp: Default::default(),
+            kafka: Default::default(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct MqttIntegration {
     pub client: MqttIntegrationClient,
     pub event_topic: String,
+    pub gateway_event_topic: String,
+    pub fuota_event_topic: String,
     pub command_topic: String,
+    pub multicast_command_topic: String,
     pub json: bool,
     pub server: String,
     pub username: String,
@@ -292,8 +646,13 @@ impl Default for MqttIntegration {
         MqttIntegration {
             client: Default::default(),
             event_topic: "application/{{application_id}}/device/{{dev_eui}}/event/{{event}}".into(),
+            gateway_event_topic: "tenant/{{tenant_id}}/gateway/{{gateway_id}}/event/{{event}}"
+                .into(),
+            fuota_event_topic:
+                "application/{{application_id}}/fuota/{{deployment_id}}/event/{{event}}".into(),
             command_topic: "application/{{application_id}}/device/{{dev_eui}}/command/{{command}}"
                 .into(),
+            multicast_command_topic: "application/{{application_id}}/multicast-group/{{multicast_group_id}}/command/{{command}}".into(),
             json: true,
             server: "tcp://127.0.0.1:1883/".into(),
             username: "".into(),
@@ -353,6 +712,8 @@ pub struct AmqpIntegration {
     pub url: String,
     pub json: bool,
     pub event_routing_key: String,
+    pub gateway_event_routing_key: String,
+    pub fuota_event_routing_key: String,
 }
 
 impl Default for AmqpIntegration {
@@ -362,6 +723,10 @@ impl Default for AmqpIntegration {
             json: true,
             event_routing_key: "application.{{application_id}}.device.{{dev_eui}}.event.{{event}}"
                 .to_string(),
+            gateway_event_routing_key:
+                "tenant.{{tenant_id}}.gateway.{{gateway_id}}.event.{{event}}".to_string(),
+            fuota_event_routing_key:
+                "application.{{application_id}}.fuota.{{deployment_id}}.event.{{event}}".to_string(),
         }
     }
 }
@@ -373,6 +738,12 @@ pub struct KafkaIntegration {
     pub tls: bool,
     pub topic: String,
     pub event_key: String,
+    pub gateway_event_key: String,
+    pub fuota_event_key: String,
+    pub command_topic: String,
+    pub command_key: String,
+    pub multicast_command_key: String,
+    pub consumer_group: String,
     pub username: String,
     pub password: String,
     pub mechanism: String,
@@ -387,6 +758,15 @@ impl Default for KafkaIntegration {
             topic: "chirpstack".to_string(),
             event_key: "application.{{application_id}}.device.{{dev_eui}}.event.{{event}}"
                 .to_string(),
+            gateway_event_key: "tenant.{{tenant_id}}.gateway.{{gateway_id}}.event.{{event}}"
+                .to_string(),
+            fuota_event_key:
+                "application.{{application_id}}.fuota.{{deployment_id}}.event.{{event}}".to_string(),
+            command_topic: "".to_string(),
+            command_key: "application.{{application_id}}.device.{{dev_eui}}.command.{{command}}"
+                .to_string(),
+            multicast_command_key: "application.{{application_id}}.multicast-group.{{multicast_group_id}}.command.{{command}}".to_string(),
+            consumer_group: "chirpstack".to_string(),
             username: "".to_string(),
             password: "".to_string(),
             mechanism: "PLAIN".to_string(),
@@ -399,6 +779,9 @@ impl Default for KafkaIntegration {
 #[serde(default)]
 pub struct Codec {
     pub js: CodecJs,
+    pub wasm: CodecWasm,
+    pub device_repository: CodecDeviceRepository,
+    pub python: CodecPython,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -406,12 +789,74 @@ pub struct Codec {
 pub struct CodecJs {
     #[serde(with = "humantime_serde")]
     pub max_execution_time: Duration,
+    pub max_memory_size: usize,
+    pub pool_size: usize,
 }
 
 impl Default for CodecJs {
     fn default() -> Self {
         CodecJs {
             max_execution_time: Duration::from_millis(100),
+            max_memory_size: 0,
+            pool_size: 16,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CodecWasm {
+    pub max_fuel: u64,
+    pub max_memory_size: usize,
+}
+
+impl Default for CodecWasm {
+    fn default() -> Self {
+        CodecWasm {
+            max_fuel: 10_000_000,
+            max_memory_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CodecDeviceRepository {
+    pub url: String,
+    #[serde(with = "humantime_serde")]
+    pub refresh_interval: Duration,
+}
+
+impl Default for CodecDeviceRepository {
+    fn default() -> Self {
+        CodecDeviceRepository {
+            url: "https://raw.githubusercontent.com/TheThingsNetwork/lorawan-devices/master".into(),
+            refresh_interval: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CodecPython {
+    pub interpreter: String,
+    #[serde(with = "humantime_serde")]
+    pub max_execution_time: Duration,
+    pub max_memory_size: usize,
+    pub pool_size: usize,
+    pub user_id: u32,
+    pub group_id: u32,
+}
+
+impl Default for CodecPython {
+    fn default() -> Self {
+        CodecPython {
+            interpreter: "".into(),
+            max_execution_time: Duration::from_millis(500),
+            max_memory_size: 128 * 1024 * 1024,
+            pool_size: 4,
+            user_id: 0,
+            group_id: 0,
         }
     }
 }
@@ -422,6 +867,7 @@ pub struct UserAuthentication {
     pub enabled: String,
     pub openid_connect: OpenIdConnect,
     pub oauth2: OAuth2,
+    pub scim: Scim,
 }
 
 impl Default for UserAuthentication {
@@ -430,6 +876,7 @@ impl Default for UserAuthentication {
             enabled: "internal".into(),
             openid_connect: Default::default(),
             oauth2: Default::default(),
+            scim: Default::default(),
         }
     }
 }
@@ -508,6 +955,12 @@ impl Default for OAuth2 {
     }
 }
 
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Scim {
+    pub enabled: bool,
+}
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 #[serde(default)]
 pub struct JoinServer {
@@ -525,6 +978,16 @@ pub struct JoinServerServer {
     pub ca_cert: String,
     pub tls_cert: String,
     pub tls_key: String,
+    pub authorization_header: String,
+    pub oauth2_token_url: String,
+    pub oauth2_client_id: String,
+    pub oauth2_client_secret: String,
+    pub oauth2_scope: String,
+    pub api_key_header: String,
+    pub api_key: String,
+    pub jws_algorithm: String,
+    pub jws_signing_key: String,
+    pub jws_verification_key: String,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -533,6 +996,35 @@ pub struct Roaming {
     pub resolve_net_id_domain_suffix: String,
     pub servers: Vec<RoamingServer>,
     pub default: RoamingServerDefault,
+    pub async_answer: RoamingAsyncAnswer,
+    pub fns_only: RoamingFnsOnly,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct RoamingFnsOnly {
+    pub enabled: bool,
+    pub net_id: NetID,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RoamingAsyncAnswer {
+    pub max_retry_count: u32,
+    #[serde(with = "humantime_serde")]
+    pub retry_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_retry_interval: Duration,
+}
+
+impl Default for RoamingAsyncAnswer {
+    fn default() -> Self {
+        RoamingAsyncAnswer {
+            max_retry_count: 5,
+            retry_interval: Duration::from_secs(1),
+            max_retry_interval: Duration::from_secs(60),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -554,12 +1046,26 @@ pub struct RoamingServer {
     pub passive_roaming_lifetime: Duration,
     pub passive_roaming_kek_label: String,
     pub passive_roaming_validate_mic: bool,
+    #[serde(with = "humantime_serde")]
+    pub handover_roaming_lifetime: Duration,
+    pub handover_roaming_kek_label: String,
+    pub use_handover_roaming: bool,
     pub server: String,
     pub use_target_role_suffix: bool,
     pub ca_cert: String,
     pub tls_cert: String,
     pub tls_key: String,
     pub authorization_header: String,
+    pub oauth2_token_url: String,
+    pub oauth2_client_id: String,
+    pub oauth2_client_secret: String,
+    pub oauth2_scope: String,
+    pub api_key_header: String,
+    pub api_key: String,
+    pub jws_algorithm: String,
+    pub jws_signing_key: String,
+    pub jws_verification_key: String,
+    pub max_requests_per_second: u32,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -572,12 +1078,26 @@ pub struct RoamingServerDefault {
     pub passive_roaming_lifetime: Duration,
     pub passive_roaming_kek_label: String,
     pub passive_roaming_validate_mic: bool,
+    #[serde(with = "humantime_serde")]
+    pub handover_roaming_lifetime: Duration,
+    pub handover_roaming_kek_label: String,
+    pub use_handover_roaming: bool,
     pub server: String,
     pub use_target_role_suffix: bool,
     pub ca_cert: String,
     pub tls_cert: String,
     pub tls_key: String,
     pub authorization_header: String,
+    pub oauth2_token_url: String,
+    pub oauth2_client_id: String,
+    pub oauth2_client_secret: String,
+    pub oauth2_scope: String,
+    pub api_key_header: String,
+    pub api_key: String,
+    pub jws_algorithm: String,
+    pub jws_signing_key: String,
+    pub jws_verification_key: String,
+    pub max_requests_per_second: u32,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -587,6 +1107,98 @@ pub struct Kek {
     pub kek: AES128Key,
 }
 
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Encryption {
+    pub backend: String,
+    pub per_tenant_keys: bool,
+    pub local: EncryptionLocal,
+    pub vault_transit: EncryptionVaultTransit,
+    pub aws_kms: EncryptionAwsKms,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct EncryptionLocal {
+    pub master_key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct EncryptionVaultTransit {
+    pub address: String,
+    pub token: String,
+    pub mount: String,
+    pub key_name: String,
+}
+
+impl Default for EncryptionVaultTransit {
+    fn default() -> Self {
+        EncryptionVaultTransit {
+            address: "http://127.0.0.1:8200".into(),
+            token: "".into(),
+            mount: "transit".into(),
+            key_name: "chirpstack".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct EncryptionAwsKms {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub key_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Keystore {
+    pub backend: String,
+    #[serde(with = "humantime_serde")]
+    pub cache_ttl: Duration,
+    pub vault_kv: KeystoreVaultKv,
+}
+
+impl Default for Keystore {
+    fn default() -> Self {
+        Keystore {
+            backend: "".into(),
+            cache_ttl: Duration::from_secs(300),
+            vault_kv: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct KeystoreVaultKv {
+    pub address: String,
+    pub token: String,
+    pub mount: String,
+    pub path_prefix: String,
+}
+
+impl Default for KeystoreVaultKv {
+    fn default() -> Self {
+        KeystoreVaultKv {
+            address: "http://127.0.0.1:8200".into(),
+            token: "".into(),
+            mount: "secret".into(),
+            path_prefix: "chirpstack/device-keys".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Hsm {
+    pub library_path: String,
+    pub slot: u64,
+    pub pin: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Region {
@@ -697,6 +1309,7 @@ pub struct RegionGateway {
     pub force_gws_private: bool,
     pub backend: GatewayBackend,
     pub channels: Vec<GatewayChannel>,
+    pub sub_bands: Vec<GatewaySubBand>,
 }
 
 #[derive(Default, Serialize, Deserialize, Clone)]
@@ -704,6 +1317,9 @@ pub struct RegionGateway {
 pub struct GatewayBackend {
     pub enabled: String,
     pub mqtt: GatewayBackendMqtt,
+    pub kafka: GatewayBackendKafka,
+    pub nats: GatewayBackendNats,
+    pub basic_station: GatewayBackendBasicStation,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -725,6 +1341,7 @@ pub struct GatewayBackendMqtt {
     pub keep_alive_interval: Duration,
     pub v4_migrate: bool,
     pub share_name: String,
+    pub shared_subscription_fallback: SharedSubscriptionFallback,
 }
 
 impl Default for GatewayBackendMqtt {
@@ -745,10 +1362,145 @@ impl Default for GatewayBackendMqtt {
             keep_alive_interval: Duration::from_secs(30),
             v4_migrate: false,
             share_name: "chirpstack".into(),
+            shared_subscription_fallback: SharedSubscriptionFallback::Subscribe,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewayBackendKafka {
+    pub brokers: Vec<String>,
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub mechanism: String,
+    pub consumer_group: String,
+    pub event_topics: Vec<String>,
+    pub command_topic: String,
+}
+
+impl Default for GatewayBackendKafka {
+    fn default() -> Self {
+        GatewayBackendKafka {
+            brokers: vec!["localhost:9092".to_string()],
+            tls: false,
+            username: "".to_string(),
+            password: "".to_string(),
+            mechanism: "PLAIN".to_string(),
+            consumer_group: "chirpstack".to_string(),
+            event_topics: vec!["gateway.event".to_string()],
+            command_topic: "gateway.{{ gateway_id }}.command".to_string(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewayBackendNats {
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    pub queue_group: String,
+    pub event_subject: String,
+    pub command_subject: String,
+    pub jetstream: GatewayBackendNatsJetStream,
+}
+
+impl Default for GatewayBackendNats {
+    fn default() -> Self {
+        GatewayBackendNats {
+            server: "nats://localhost:4222".into(),
+            username: "".into(),
+            password: "".into(),
+            queue_group: "chirpstack".into(),
+            event_subject: "gateway.*.event.*".into(),
+            command_subject: "gateway.{{ gateway_id }}.command.{{ command }}".into(),
+            jetstream: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewayBackendNatsJetStream {
+    pub enabled: bool,
+    pub stream: String,
+    pub durable_name: String,
+    pub storage: String,
+    pub replicas: usize,
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+    #[serde(with = "humantime_serde")]
+    pub ack_wait: Duration,
+}
+
+impl Default for GatewayBackendNatsJetStream {
+    fn default() -> Self {
+        GatewayBackendNatsJetStream {
+            enabled: false,
+            stream: "GATEWAY_EVENTS".into(),
+            durable_name: "chirpstack".into(),
+            storage: "file".into(),
+            replicas: 1,
+            max_age: Duration::from_secs(60 * 60),
+            ack_wait: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewayBackendBasicStation {
+    pub bind: String,
+    pub tls_cert: String,
+    pub tls_key: String,
+    pub ca_cert: String,
+    pub auth_token: String,
+    pub router_uri: String,
+    #[serde(with = "humantime_serde")]
+    pub ping_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub stats_interval: Duration,
+    pub cups: GatewayBackendBasicStationCups,
+}
+
+impl Default for GatewayBackendBasicStation {
+    fn default() -> Self {
+        GatewayBackendBasicStation {
+            bind: "0.0.0.0:3001".into(),
+            tls_cert: "".into(),
+            tls_key: "".into(),
+            ca_cert: "".into(),
+            auth_token: "".into(),
+            router_uri: "".into(),
+            ping_interval: Duration::from_secs(60),
+            stats_interval: Duration::from_secs(30),
+            cups: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GatewayBackendBasicStationCups {
+    pub enabled: bool,
+    pub tc_uri: String,
+    pub tc_trust: String,
+}
+
+// Defines the behavior when the MQTT broker does not support shared subscriptions.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SharedSubscriptionFallback {
+    // Subscribe without shared subscription. Note that when multiple instances are connected
+    // to the same broker, each instance will receive (and process) every gateway event.
+    #[default]
+    Subscribe,
+    // Do not subscribe to the gateway events.
+    Skip,
+}
+
 #[derive(Serialize, Deserialize, Clone, Hash)]
 #[allow(non_camel_case_types)]
 #[allow(clippy::upper_case_acronyms)]
@@ -779,6 +1531,14 @@ impl Default for GatewayChannel {
     }
 }
 
+#[derive(Default, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GatewaySubBand {
+    pub name: String,
+    pub frequency_min: u32,
+    pub frequency_max: u32,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct UI {
@@ -796,8 +1556,15 @@ impl Default for UI {
     }
 }
 
-pub fn load(config_dir: &Path) -> Result<()> {
-    let mut content: String = String::new();
+// Configuration file, with the environment variables substituted.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+// Reads the .toml files within the given configuration directory.
+pub fn read_files(config_dir: &Path) -> Result<Vec<ConfigFile>> {
+    let mut out = Vec::new();
 
     let paths = fs::read_dir(config_dir)?;
     for path in paths {
@@ -805,21 +1572,67 @@ pub fn load(config_dir: &Path) -> Result<()> {
 
         if let Some(ext) = path.extension() {
             if ext == "toml" {
-                content.push_str(
-                    &fs::read_to_string(&path)
-                        .context(format!("Read config file: {}", path.display()))?,
-                );
+                let mut content = fs::read_to_string(&path)
+                    .context(format!("Read config file: {}", path.display()))?;
+
+                // substitute environment variables in config file
+                for (k, v) in env::vars() {
+                    content = content.replace(&format!("${}", k), &v);
+                }
+
+                out.push(ConfigFile { path, content });
             }
         }
     }
 
-    // substitute environment variables in config file
-    for (k, v) in env::vars() {
-        content = content.replace(&format!("${}", k), &v);
+    Ok(out)
+}
+
+pub async fn load(config_dir: &Path) -> Result<()> {
+    set(read(config_dir).await?);
+    Ok(())
+}
+
+// Reads the configuration from the given directory, without setting it.
+pub async fn read(config_dir: &Path) -> Result<Configuration> {
+    let content: String = read_files(config_dir)?
+        .iter()
+        .map(|f| f.content.as_str())
+        .collect();
+
+    // resolve secret references (e.g. env://, file://, vault:// and enc://)
+    let mut value: toml::Value = toml::from_str(&content)?;
+    secrets::resolve(&mut value)
+        .await
+        .context("Resolve configuration secrets")?;
+
+    let conf: Configuration = value.try_into()?;
+    validate(&conf)?;
+
+    Ok(conf)
+}
+
+// Validates the configuration. Negative durations are already rejected when parsing the
+// configuration.
+pub fn validate(conf: &Configuration) -> Result<()> {
+    if conf.network.device_session_ttl.is_zero() {
+        return Err(anyhow!("network.device_session_ttl must be greater than 0"));
     }
 
-    let conf: Configuration = toml::from_str(&content)?;
-    set(conf);
+    // For these TTLs, 0 means that the default (derived from the network configuration) is used.
+    // The other TTLs must be set.
+    let ttl = &conf.redis.ttl;
+    for (name, v) in [
+        ("backend_async_answer", ttl.backend_async_answer),
+        ("metrics_minute", ttl.metrics_minute),
+        ("metrics_hour", ttl.metrics_hour),
+        ("metrics_day", ttl.metrics_day),
+        ("metrics_month", ttl.metrics_month),
+    ] {
+        if v.is_zero() {
+            return Err(anyhow!("redis.ttl.{} must be greater than 0", name));
+        }
+    }
 
     Ok(())
 }
@@ -867,6 +1680,16 @@ pub fn get_region_gateway(region_id: &str) -> Result<RegionGateway> {
     Err(anyhow!("Region ID '{}' not found", region_id))
 }
 
+// Returns the given device related TTL, or the network device_session_ttl in case the TTL is
+// not set (0).
+pub fn get_device_ttl(ttl: Duration) -> Duration {
+    if ttl.is_zero() {
+        get().network.device_session_ttl
+    } else {
+        ttl
+    }
+}
+
 pub fn get_required_snr_for_sf(sf: u8) -> Result<f32> {
     Ok(match sf {
         6 => -5.0,
@@ -881,3 +1704,62 @@ pub fn get_required_snr_for_sf(sf: u8) -> Result<f32> {
         }
     })
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let conf = Configuration::default();
+        assert!(validate(&conf).is_ok());
+
+        let mut conf = Configuration::default();
+        conf.network.device_session_ttl = Duration::ZERO;
+        assert_eq!(
+            "network.device_session_ttl must be greater than 0",
+            validate(&conf).unwrap_err().to_string()
+        );
+
+        let mut conf = Configuration::default();
+        conf.redis.ttl.metrics_hour = Duration::ZERO;
+        assert_eq!(
+            "redis.ttl.metrics_hour must be greater than 0",
+            validate(&conf).unwrap_err().to_string()
+        );
+
+        // 0 means the default is used.
+        let mut conf = Configuration::default();
+        conf.redis.ttl.device_gateway_rx_info = Duration::ZERO;
+        assert!(validate(&conf).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_read_negative_ttl() {
+        let dir = env::temp_dir().join(format!("chirpstack-config-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(
+            dir.join("chirpstack.toml"),
+            "[redis.ttl]\nmetrics_hour=\"-1h\"\n",
+        )
+        .unwrap();
+        assert!(read(&dir).await.is_err());
+
+        fs::write(
+            dir.join("chirpstack.toml"),
+            "[redis.ttl]\nmetrics_hour=\"0s\"\n",
+        )
+        .unwrap();
+        assert!(read(&dir).await.is_err());
+
+        fs::write(
+            dir.join("chirpstack.toml"),
+            "[redis.ttl]\nmetrics_hour=\"1h\"\n",
+        )
+        .unwrap();
+        assert!(read(&dir).await.is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::{env, fs};
+
+use aes_kw::KekAes256;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::Deserialize;
+use toml::Value;
+
+// Secret references. A configuration value is only resolved in case the complete value is a
+// reference, e.g. password="env://MQTT_PASSWORD".
+const ENV_PREFIX: &str = "env://";
+const FILE_PREFIX: &str = "file://";
+const VAULT_PREFIX: &str = "vault://";
+const ENCRYPTED_PREFIX: &str = "enc://";
+
+// Environment variable containing the (hex encoded) AES-256 key for decrypting the encrypted
+// configuration values.
+pub const KEY_ENV_VAR: &str = "CHIRPSTACK_SECRETS_KEY";
+
+#[derive(Deserialize)]
+struct VaultResponse {
+    data: VaultData,
+}
+
+#[derive(Deserialize)]
+struct VaultData {
+    data: HashMap<String, serde_json::Value>,
+}
+
+// Resolves the secret references within the given configuration.
+pub async fn resolve(value: &mut Value) -> Result<()> {
+    // As the configuration is resolved recursively, the Vault secrets are retrieved upfront.
+    let mut vault_refs = Vec::new();
+    collect_vault_refs(value, &mut vault_refs);
+
+    let mut vault_secrets: HashMap<String, String> = HashMap::new();
+    if !vault_refs.is_empty() {
+        let address = env::var("VAULT_ADDR").context("VAULT_ADDR is not set")?;
+        let token = env::var("VAULT_TOKEN").context("VAULT_TOKEN is not set")?;
+
+        for r in vault_refs {
+            if let Entry::Vacant(e) = vault_secrets.entry(r) {
+                let secret = get_vault_secret(&address, &token, e.key())
+                    .await
+                    .context(format!("Get Vault secret: {}", e.key()))?;
+                e.insert(secret);
+            }
+        }
+    }
+
+    resolve_value(value, &vault_secrets)
+}
+
+// Encrypts the given secret such that it can be used as encrypted configuration value.
+pub fn encrypt(secret: &str) -> Result<String> {
+    if secret.is_empty() {
+        return Err(anyhow!("Secret must not be empty"));
+    }
+
+    let key = get_key()?;
+    let mut out: Vec<u8> = vec![0; secret.len().div_ceil(8) * 8 + 8];
+    KekAes256::from(key)
+        .wrap_with_padding(secret.as_bytes(), &mut out)
+        .map_err(|e| anyhow!("Encrypt secret error: {}", e))?;
+
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        general_purpose::STANDARD.encode(&out)
+    ))
+}
+
+fn decrypt(s: &str) -> Result<String> {
+    let key = get_key()?;
+    let b = general_purpose::STANDARD
+        .decode(s)
+        .context("Decode encrypted secret")?;
+    if b.len() < 16 {
+        return Err(anyhow!("Invalid encrypted secret length"));
+    }
+
+    let mut out: Vec<u8> = vec![0; b.len() - 8];
+    let secret = KekAes256::from(key)
+        .unwrap_with_padding(&b, &mut out)
+        .map_err(|e| anyhow!("Decrypt secret error: {}", e))?;
+
+    Ok(String::from_utf8(secret.to_vec())?)
+}
+
+fn get_key() -> Result<[u8; 32]> {
+    let key = env::var(KEY_ENV_VAR).context(format!("{} is not set", KEY_ENV_VAR))?;
+    hex::decode(key.trim())
+        .map_err(|e| anyhow!("Decode {} error: {}", KEY_ENV_VAR, e))?
+        .try_into()
+        .map_err(|_| anyhow!("{} must be 32 bytes", KEY_ENV_VAR))
+}
+
+fn collect_vault_refs(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) if s.starts_with(VAULT_PREFIX) => out.push(s.clone()),
+        Value::Array(items) => {
+            for v in items {
+                collect_vault_refs(v, out);
+            }
+        }
+        Value::Table(t) => {
+            for v in t.values() {
+                collect_vault_refs(v, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn resolve_value(value: &mut Value, vault_secrets: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            if let Some(secret) = resolve_str(s, vault_secrets)? {
+                *s = secret;
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                resolve_value(v, vault_secrets)?;
+            }
+        }
+        Value::Table(t) => {
+            for (_, v) in t.iter_mut() {
+                resolve_value(v, vault_secrets)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn resolve_str(s: &str, vault_secrets: &HashMap<String, String>) -> Result<Option<String>> {
+    if let Some(name) = s.strip_prefix(ENV_PREFIX) {
+        return Ok(Some(env::var(name).context(format!(
+            "Read secret from environment variable: {}",
+            name
+        ))?));
+    }
+
+    if let Some(path) = s.strip_prefix(FILE_PREFIX) {
+        let secret =
+            fs::read_to_string(path).context(format!("Read secret from file: {}", path))?;
+        return Ok(Some(secret.trim_end_matches(['\r', '\n']).to_string()));
+    }
+
+    if s.starts_with(VAULT_PREFIX) {
+        return Ok(vault_secrets.get(s).cloned());
+    }
+
+    if let Some(encrypted) = s.strip_prefix(ENCRYPTED_PREFIX) {
+        return Ok(Some(decrypt(encrypted).context("Decrypt secret")?));
+    }
+
+    Ok(None)
+}
+
+// Retrieves the secret from the Vault KV (version 2) secrets engine. The reference must be in
+// the format vault://MOUNT/PATH#FIELD.
+async fn get_vault_secret(address: &str, token: &str, reference: &str) -> Result<String> {
+    let reference = reference.strip_prefix(VAULT_PREFIX).unwrap_or(reference);
+    let (path, field) = reference
+        .split_once('#')
+        .ok_or_else(|| anyhow!("Reference must be in format vault://MOUNT/PATH#FIELD"))?;
+    let (mount, path) = path
+        .split_once('/')
+        .ok_or_else(|| anyhow!("Reference must be in format vault://MOUNT/PATH#FIELD"))?;
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?;
+    let resp: VaultResponse = client
+        .get(format!(
+            "{}/v1/{}/data/{}",
+            address.trim_end_matches('/'),
+            mount,
+            path
+        ))
+        .header("X-Vault-Token", token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    match resp.data.data.get(field) {
+        Some(serde_json::Value::String(v)) => Ok(v.clone()),
+        Some(_) => Err(anyhow!("Field {} is not a string", field)),
+        None => Err(anyhow!("Field {} does not exist", field)),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn test_resolve() {
+        env::set_var(
+            KEY_ENV_VAR,
+            "0102030405060708010203040506070801020304050607080102030405060708",
+        );
+        env::set_var("CHIRPSTACK_TEST_SECRET", "env-secret");
+
+        let path = env::temp_dir().join("chirpstack_test_secret");
+        fs::write(&path, "file-secret\n").unwrap();
+
+        let encrypted = encrypt("encrypted-secret").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+
+        let mut value: Value = toml::from_str(&format!(
+            r#"
+            plain="plain-secret"
+            env="env://CHIRPSTACK_TEST_SECRET"
+            file="file://{}"
+            encrypted="{}"
+
+            [nested]
+            items=["env://CHIRPSTACK_TEST_SECRET"]
+            "#,
+            path.display(),
+            encrypted
+        ))
+        .unwrap();
+        resolve(&mut value).await.unwrap();
+
+        assert_eq!("plain-secret", value["plain"].as_str().unwrap());
+        assert_eq!("env-secret", value["env"].as_str().unwrap());
+        assert_eq!("file-secret", value["file"].as_str().unwrap());
+        assert_eq!("encrypted-secret", value["encrypted"].as_str().unwrap());
+        assert_eq!("env-secret", value["nested"]["items"][0].as_str().unwrap());
+
+        // Missing environment variable.
+        let mut value: Value =
+            toml::from_str(r#"env="env://CHIRPSTACK_TEST_SECRET_MISSING""#).unwrap();
+        assert!(resolve(&mut value).await.is_err());
+
+        // Invalid encrypted value.
+        let mut value: Value = toml::from_str(r#"encrypted="enc://AQID""#).unwrap();
+        assert!(resolve(&mut value).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_vault_secret() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/secret/data/chirpstack/mqtt")
+                .header("X-Vault-Token", "token");
+            then.status(200).json_body(serde_json::json!({
+                "data": {
+                    "data": {
+                        "password": "vault-secret",
+                    },
+                },
+            }));
+        });
+
+        assert_eq!(
+            "vault-secret",
+            get_vault_secret(
+                &server.url(""),
+                "token",
+                "vault://secret/chirpstack/mqtt#password"
+            )
+            .await
+            .unwrap()
+        );
+        assert!(get_vault_secret(
+            &server.url(""),
+            "token",
+            "vault://secret/chirpstack/mqtt#username"
+        )
+        .await
+        .is_err());
+        assert!(
+            get_vault_secret(&server.url(""), "token", "vault://secret/chirpstack/mqtt")
+                .await
+                .is_err()
+        );
+        mock.assert_hits(2);
+    }
+}
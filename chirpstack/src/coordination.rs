@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+use std::future::Future;
+#[cfg(feature = "postgres")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "postgres")]
+use anyhow::Context;
+use anyhow::Result;
+use tokio::time::MissedTickBehavior;
+use tracing::{trace, warn};
+
+use crate::helpers::errors::PrintFullError;
+#[cfg(feature = "postgres")]
+use crate::{config, storage};
+
+// Interval at which the holder of a lock validates that the lock is still held. This is also
+// used as timeout for the lock queries.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    // Names of the locks held by this instance. The PostgreSQL advisory locks are re-entrant
+    // within the same session, therefore this instance must track these itself.
+    static ref HELD: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+#[cfg(feature = "postgres")]
+lazy_static! {
+    // Dedicated PostgreSQL session on which the advisory locks of this instance are acquired.
+    // This connection is not part of the connection-pool, as a pooled connection would be
+    // returned to the pool while still holding the locks.
+    static ref SESSION: tokio::sync::Mutex<Option<Arc<tokio_postgres::Client>>> =
+        tokio::sync::Mutex::new(None);
+}
+
+// Lock represents a distributed lock, shared by all ChirpStack instances using the same
+// PostgreSQL database.
+//
+// The lock is a PostgreSQL session-level advisory lock, which is held by the database session
+// of this instance for the whole critical section. Unlike a lease, the lock does not expire
+// while the holder is running, no matter how long it takes or stalls. It is only released by
+// PostgreSQL when the holder releases it or when the session is closed. In the latter case,
+// the holder must stop its work, which is detected by check (with_lock does this
+// automatically).
+//
+// As SQLite does not support multiple instances sharing the same database, the SQLite build
+// only locks within this instance.
+pub struct Lock {
+    name: String,
+    #[cfg(feature = "postgres")]
+    client: Arc<tokio_postgres::Client>,
+    #[cfg(feature = "postgres")]
+    released: bool,
+}
+
+impl Lock {
+    // Validates that the lock is still held. This returns false in case the database session
+    // holding the lock was closed, in which case PostgreSQL has released the lock.
+    #[cfg(feature = "postgres")]
+    pub async fn check(&self) -> Result<bool> {
+        if self.client.is_closed() {
+            return Ok(false);
+        }
+
+        let res = tokio::time::timeout(CHECK_INTERVAL, self.client.simple_query("select 1"))
+            .await
+            .context("Check lock timeout")?;
+        Ok(res.is_ok())
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub async fn check(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    // Releases the lock.
+    #[cfg(feature = "postgres")]
+    pub async fn release(mut self) -> Result<()> {
+        if !self.client.is_closed() {
+            unlock(&self.client, &self.name).await?;
+        }
+
+        self.released = true;
+        trace!(lock = %self.name, "Lock released");
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub async fn release(self) -> Result<()> {
+        trace!(lock = %self.name, "Lock released");
+        Ok(())
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // The lock was not released, e.g. because the holder was cancelled.
+        #[cfg(feature = "postgres")]
+        {
+            if !self.released && !self.client.is_closed() {
+                let client = self.client.clone();
+                let name = self.name.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = unlock(&client, &name).await {
+                        warn!(lock = %name, error = %e.full(), "Releasing lock failed");
+                    }
+                });
+            }
+        }
+
+        HELD.lock().unwrap().remove(&self.name);
+    }
+}
+
+// Tries to acquire the lock with the given name. In case the lock is already held, by this or
+// an other instance, this returns None.
+pub async fn try_lock(name: &str) -> Result<Option<Lock>> {
+    if !HELD.lock().unwrap().insert(name.to_string()) {
+        trace!(lock = %name, "Lock is already held by this instance");
+        return Ok(None);
+    }
+
+    let lock = acquire(name).await;
+    if !matches!(lock, Ok(Some(_))) {
+        HELD.lock().unwrap().remove(name);
+    }
+
+    lock
+}
+
+#[cfg(feature = "postgres")]
+async fn acquire(name: &str) -> Result<Option<Lock>> {
+    let client = get_client().await?;
+    let row = tokio::time::timeout(
+        CHECK_INTERVAL,
+        client.query_one(
+            "select pg_try_advisory_lock(hashtextextended($1, 0))",
+            &[&name],
+        ),
+    )
+    .await
+    .context("Acquire lock timeout")?
+    .context("Acquire lock")?;
+
+    let locked: bool = row.get(0);
+    if !locked {
+        trace!(lock = %name, "Lock is already held");
+        return Ok(None);
+    }
+
+    trace!(lock = %name, "Lock acquired");
+
+    Ok(Some(Lock {
+        name: name.to_string(),
+        client,
+        released: false,
+    }))
+}
+
+#[cfg(feature = "sqlite")]
+async fn acquire(name: &str) -> Result<Option<Lock>> {
+    trace!(lock = %name, "Lock acquired");
+
+    Ok(Some(Lock {
+        name: name.to_string(),
+    }))
+}
+
+// Executes the given future, if the lock with the given name could be acquired. It returns
+// None if the lock is already held, else the output of the future. While the future is running,
+// the lock is checked every CHECK_INTERVAL. In case the lock was lost, the future is cancelled
+// and an error is returned. The lock is released after the future has completed.
+pub async fn with_lock<F, T>(name: &str, f: F) -> Result<Option<T>>
+where
+    F: Future<Output = T>,
+{
+    let lock = match try_lock(name).await? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    tokio::pin!(f);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately.
+    interval.tick().await;
+
+    let out = loop {
+        tokio::select! {
+            out = &mut f => break out,
+            _ = interval.tick() => {
+                match lock.check().await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        return Err(anyhow!("Lock was lost: {}", name));
+                    }
+                    // As long as the session is not closed, the lock is still held.
+                    Err(e) => {
+                        warn!(lock = %name, error = %e.full(), "Checking lock failed");
+                    }
+                }
+            }
+        }
+    };
+
+    if let Err(e) = lock.release().await {
+        warn!(error = %e.full(), "Releasing lock failed");
+    }
+
+    Ok(Some(out))
+}
+
+#[cfg(feature = "postgres")]
+async fn unlock(client: &tokio_postgres::Client, name: &str) -> Result<()> {
+    tokio::time::timeout(
+        CHECK_INTERVAL,
+        client.execute(
+            "select pg_advisory_unlock(hashtextextended($1, 0))",
+            &[&name],
+        ),
+    )
+    .await
+    .context("Release lock timeout")?
+    .context("Release lock")?;
+    Ok(())
+}
+
+// Returns the PostgreSQL session on which the locks are acquired. In case the session was
+// closed, a new session is created. The locks held by the closed session have been released
+// by PostgreSQL.
+#[cfg(feature = "postgres")]
+async fn get_client() -> Result<Arc<tokio_postgres::Client>> {
+    let mut session = SESSION.lock().await;
+    if let Some(client) = session.as_ref() {
+        if !client.is_closed() {
+            return Ok(client.clone());
+        }
+    }
+
+    let conf = config::get();
+    let tls = storage::get_tls_connector()?;
+    let (client, conn) = tokio::time::timeout(
+        CHECK_INTERVAL,
+        tokio_postgres::connect(&conf.postgresql.dsn, tls),
+    )
+    .await
+    .context("Connect lock session timeout")?
+    .context("Connect lock session")?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            warn!(error = %e, "PostgreSQL lock session error");
+        }
+    });
+
+    let client = Arc::new(client);
+    *session = Some(client.clone());
+    Ok(client)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_lock() {
+        let _guard = test::prepare().await;
+
+        let lock = try_lock("test").await.unwrap().unwrap();
+        assert!(lock.check().await.unwrap());
+
+        // Lock is already held by this instance.
+        assert!(try_lock("test").await.unwrap().is_none());
+
+        // Release and acquire again.
+        lock.release().await.unwrap();
+        let lock = try_lock("test").await.unwrap().unwrap();
+
+        // Dropping the lock (e.g. on cancellation) releases it.
+        drop(lock);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // with_lock
+        let lock = try_lock("test").await.unwrap().unwrap();
+        let out = with_lock("test", async { 1 }).await.unwrap();
+        assert!(out.is_none());
+
+        lock.release().await.unwrap();
+        let out = with_lock("test", async { 1 }).await.unwrap();
+        assert_eq!(Some(1), out);
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_lock_other_instance() {
+        let _guard = test::prepare().await;
+
+        // Session of an other instance.
+        let conf = config::get();
+        let (other, conn) =
+            tokio_postgres::connect(&conf.postgresql.dsn, storage::get_tls_connector().unwrap())
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let lock = try_lock("test-other").await.unwrap().unwrap();
+        let row = other
+            .query_one(
+                "select pg_try_advisory_lock(hashtextextended($1, 0))",
+                &[&"test-other"],
+            )
+            .await
+            .unwrap();
+        assert!(!row.get::<_, bool>(0));
+
+        // The lock does not expire while it is held, the other instance acquires it after it
+        // has been released.
+        lock.release().await.unwrap();
+        let row = other
+            .query_one(
+                "select pg_try_advisory_lock(hashtextextended($1, 0))",
+                &[&"test-other"],
+            )
+            .await
+            .unwrap();
+        assert!(row.get::<_, bool>(0));
+
+        // Held by the other instance.
+        assert!(try_lock("test-other").await.unwrap().is_none());
+
+        // The lock is released when the session of the other instance is closed.
+        drop(other);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let lock = try_lock("test-other").await.unwrap().unwrap();
+        lock.release().await.unwrap();
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_with_lock_lost() {
+        let _guard = test::prepare().await;
+
+        // The session holding the lock is terminated, the future must be cancelled.
+        let out = with_lock("test-lost", async {
+            let (client, conn) = tokio_postgres::connect(
+                &config::get().postgresql.dsn,
+                storage::get_tls_connector().unwrap(),
+            )
+            .await
+            .unwrap();
+            tokio::spawn(conn);
+            client
+                .execute(
+                    r#"
+                        select pg_terminate_backend(pid)
+                        from pg_locks
+                        where locktype = 'advisory' and granted and pid <> pg_backend_pid()
+                    "#,
+                    &[],
+                )
+                .await
+                .unwrap();
+
+            tokio::time::sleep(CHECK_INTERVAL * 2).await;
+            1
+        })
+        .await;
+        assert!(out.is_err());
+    }
+}
@@ -5,7 +5,10 @@ use anyhow::Result;
 use chrono::Duration;
 use tracing::debug;
 
-use lrwn::DevAddr;
+use crate::gateway::airtime;
+use crate::storage::ping_slot;
+use chirpstack_api::gw;
+use lrwn::{DevAddr, EUI64};
 
 lazy_static! {
     static ref BEACON_PERIOD: Duration = Duration::try_seconds(128).unwrap();
@@ -16,6 +19,10 @@ lazy_static! {
     static ref SLOT_LEN: Duration = Duration::try_milliseconds(30).unwrap();
 }
 
+// Max. number of ping-slots to try when searching for a ping-slot which does not conflict with
+// the ping-slots reserved for the gateway(s).
+const MAX_PING_SLOT_ATTEMPTS: usize = 32;
+
 pub fn get_beacon_start(ts: Duration) -> Duration {
     Duration::try_seconds(ts.num_seconds() - (ts.num_seconds() % BEACON_PERIOD.num_seconds()))
         .unwrap_or_default()
@@ -81,10 +88,54 @@ pub fn get_next_ping_slot_after(
     }
 }
 
+// Reserves the next ping-slot after the given timestamp of which the transmission does not
+// overlap with the ping-slots reserved for the given gateways. This avoids that unicast and
+// multicast Class-B downlinks are scheduled at the same time for the same gateway.
+pub async fn reserve_next_free_ping_slot_after(
+    after_gps_epoch_ts: Duration,
+    dev_addr: &DevAddr,
+    ping_nb: usize,
+    gateway_ids: &[EUI64],
+    duration: Duration,
+) -> Result<ping_slot::Reservation> {
+    let mut after_gps_epoch_ts = after_gps_epoch_ts;
+
+    for _ in 0..MAX_PING_SLOT_ATTEMPTS {
+        let ping_slot_ts = get_next_ping_slot_after(after_gps_epoch_ts, dev_addr, ping_nb)?;
+        if let Some(reservation) = ping_slot::reserve(gateway_ids, ping_slot_ts, duration).await? {
+            return Ok(reservation);
+        }
+
+        debug!(
+            dev_addr = %dev_addr,
+            ping_slot_ts_ms = ping_slot_ts.num_milliseconds(),
+            "Ping-slot conflicts with reserved ping-slot, trying next ping-slot"
+        );
+        after_gps_epoch_ts = ping_slot_ts;
+    }
+
+    Err(anyhow!(
+        "No free ping-slot found within {} ping-slots",
+        MAX_PING_SLOT_ATTEMPTS
+    ))
+}
+
+// Returns the duration of the transmission for the given tx-info and PHYPayload size. In case
+// the airtime can not be calculated, this returns the ping-slot length.
+pub fn get_transmission_duration(tx_info: &gw::DownlinkTxInfo, size: usize) -> Duration {
+    tx_info
+        .modulation
+        .as_ref()
+        .and_then(|v| airtime::get_airtime(v, size))
+        .and_then(|v| Duration::from_std(v).ok())
+        .unwrap_or(*SLOT_LEN)
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
     use crate::gpstime::{ToDateTime, ToGpsTime};
+    use crate::test;
     use chrono::{DateTime, TimeZone, Utc};
 
     #[test]
@@ -190,4 +241,33 @@ pub mod test {
             assert_eq!(tst.expected_ping_slot_ts, ping_slot_ts);
         }
     }
+
+    #[tokio::test]
+    async fn test_reserve_next_free_ping_slot_after() {
+        let _guard = test::prepare().await;
+
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let dev_addr = DevAddr::from_be_bytes([0, 0, 0, 0]);
+        let after = Utc::now().to_gps_time();
+        let duration = Duration::try_milliseconds(100).unwrap();
+
+        // no reservations
+        let reservation =
+            reserve_next_free_ping_slot_after(after, &dev_addr, 2, &[gateway_id], duration)
+                .await
+                .unwrap();
+        assert_eq!(
+            get_next_ping_slot_after(after, &dev_addr, 2).unwrap(),
+            reservation.start
+        );
+
+        // the reserved ping-slot is skipped
+        assert_eq!(
+            get_next_ping_slot_after(reservation.start, &dev_addr, 2).unwrap(),
+            reserve_next_free_ping_slot_after(after, &dev_addr, 2, &[gateway_id], duration)
+                .await
+                .unwrap()
+                .start
+        );
+    }
 }
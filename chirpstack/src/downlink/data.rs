@@ -13,18 +13,19 @@ use crate::api::helpers::{FromProto, ToProto};
 use crate::backend::roaming;
 use crate::downlink::{classb, error::Error, helpers, tx_ack};
 use crate::gpstime::{ToDateTime, ToGpsTime};
+use crate::helpers::errors::PrintFullError;
 use crate::storage;
 use crate::storage::{
     application,
     device::{self, DeviceClass},
-    device_gateway, device_profile, device_queue, downlink_frame,
+    device_gateway, device_profile, device_queue, downlink_frame, fields,
     helpers::get_all_device_data,
-    mac_command, relay, tenant,
+    mac_command, ping_slot, relay, tenant,
 };
 use crate::uplink::{RelayContext, UplinkFrameSet};
 use crate::{adr, config, gateway, integration, maccommand, region, sensitivity};
 use chirpstack_api::{gw, integration as integration_pb, internal};
-use lrwn::{keys, AES128Key, NetID};
+use lrwn::{keys, AES128Key, NetID, EUI64};
 
 struct DownlinkFrameItem {
     downlink_frame_item: gw::DownlinkFrameItem,
@@ -334,6 +335,8 @@ impl Data {
             more_device_queue_items: false,
         };
 
+        let mut reservation: Option<ping_slot::Reservation> = None;
+
         ctx.select_downlink_gateway()?;
         if ctx._is_class_c() {
             ctx.class_c_update_scheduler_run_after().await?;
@@ -342,21 +345,42 @@ impl Data {
             ctx.set_tx_info_for_rx2()?;
         }
         if ctx._is_class_b() {
-            ctx.set_tx_info_for_class_b_and_update_scheduler_run_after()
-                .await?;
+            reservation = Some(
+                ctx.set_tx_info_for_class_b_and_update_scheduler_run_after()
+                    .await?,
+            );
         }
         if ctx._is_class_a() {
             return Err(anyhow!("Invalid device-class"));
         }
-        ctx.get_next_device_queue_item().await?;
-        if ctx._something_to_send() {
-            ctx.set_phy_payloads()?;
-            ctx.update_device_queue_item().await?;
-            ctx.save_downlink_frame().await?;
-            ctx.send_downlink_frame().await?;
+
+        let res = ctx.send_next_device_queue_item().await;
+
+        // Release the reserved ping-slot in case the downlink was not sent.
+        if !matches!(res, Ok(true)) {
+            if let Some(reservation) = reservation {
+                if let Err(e) = reservation.release().await {
+                    warn!(error = %e.full(), "Releasing ping-slot failed");
+                }
+            }
         }
 
-        Ok(())
+        res.map(|_| ())
+    }
+
+    // Sends the next device queue-item (if any). This returns true when a downlink was sent.
+    async fn send_next_device_queue_item(&mut self) -> Result<bool> {
+        self.get_next_device_queue_item().await?;
+        if !self._something_to_send() {
+            return Ok(false);
+        }
+
+        self.set_phy_payloads()?;
+        self.update_device_queue_item().await?;
+        self.save_downlink_frame().await?;
+        self.send_downlink_frame().await?;
+
+        Ok(true)
     }
 
     fn select_downlink_gateway(&mut self) -> Result<()> {
@@ -844,8 +868,9 @@ impl Data {
             )
             .context("Set downlink data MIC")?;
 
-            let b = phy.to_vec().context("Encode PHYPayload")?;
-            item.downlink_frame_item.phy_payload = b;
+            item.downlink_frame_item.phy_payload.clear();
+            phy.encode_into(&mut item.downlink_frame_item.phy_payload)
+                .context("Encode PHYPayload")?;
 
             self.downlink_frame
                 .items
@@ -997,6 +1022,7 @@ impl Data {
                 },
                 None => ds.get_a_f_cnt_down(),
             },
+            region_config_id: ds.region_config_id.clone(),
             ..Default::default()
         })
         .await
@@ -1023,6 +1049,7 @@ impl Data {
             downlink_frame: Some(self.downlink_frame.clone()),
             n_f_cnt_down: relay_ds.n_f_cnt_down,
             a_f_cnt_down: relay_ds.get_a_f_cnt_down(),
+            region_config_id: self.device.get_device_session()?.region_config_id.clone(),
             ..Default::default()
         })
         .await?;
@@ -1135,6 +1162,19 @@ impl Data {
             .xmit_data_req(backend::Role::FNS, &mut req, async_receiver)
             .await?;
 
+        roaming::log_downlink_usage(
+            net_id,
+            fields::RoamingRole::SNS,
+            Some(self.device.dev_eui),
+            self.device.get_dev_addr()?,
+            self.downlink_frame.items[0]
+                .tx_info
+                .as_ref()
+                .and_then(|v| v.modulation.as_ref()),
+            req.phy_payload.len(),
+        )
+        .await;
+
         Ok(())
     }
 
@@ -2462,8 +2502,11 @@ impl Data {
     }
 
     // The setting of tx_info and updating update_scheduler_run_after_ts is combined
-    // as we need to calculate the ping_slot_ts for the tx_info.
-    async fn set_tx_info_for_class_b_and_update_scheduler_run_after(&mut self) -> Result<()> {
+    // as we need to calculate the ping_slot_ts for the tx_info. This returns the reserved
+    // ping-slot, which must be released when the downlink is not sent.
+    async fn set_tx_info_for_class_b_and_update_scheduler_run_after(
+        &mut self,
+    ) -> Result<ping_slot::Reservation> {
         trace!("Setting tx-info for Class-B");
         let ds = self.device.get_device_session()?;
 
@@ -2491,14 +2534,47 @@ impl Data {
                 .get_downlink_tx_power_eirp(tx_info.frequency) as i32;
         }
 
+        // get remaining payload size
+        let max_pl_size = self.region_conf.get_max_payload_size(
+            ds.mac_version().from_proto(),
+            self.device_profile.reg_params_revision,
+            ds.class_b_ping_slot_dr as u8,
+        )?;
+
         // set timing
+        // The transmission duration is based on the max. payload size (MHDR + MACPayload + MIC),
+        // as the payload is not yet known at this point. The ping-slot is reserved for the
+        // gateway, such that it does not conflict with multicast Class-B downlinks.
+        let gateway_id = EUI64::from_slice(&gw_down.gateway_id)?;
+        let duration = classb::get_transmission_duration(&tx_info, max_pl_size.m + 5);
         let now_gps_ts = Utc::now().to_gps_time() + chrono::Duration::try_seconds(1).unwrap();
-        let ping_slot_ts = classb::get_next_ping_slot_after(
+        let reservation = classb::reserve_next_free_ping_slot_after(
             now_gps_ts,
             &self.device.get_dev_addr()?,
             ds.class_b_ping_slot_nb as usize,
-        )?;
-        trace!(gps_time_now_ts = %now_gps_ts, ping_slot_ts = %ping_slot_ts, "Calculated ping-slot timestamp");
+            &[gateway_id],
+            duration,
+        )
+        .await?;
+        trace!(gps_time_now_ts = %now_gps_ts, ping_slot_ts = %reservation.start, "Calculated ping-slot timestamp");
+
+        if let Err(e) = self
+            .set_tx_info_for_ping_slot(tx_info, reservation.start, max_pl_size.n)
+            .await
+        {
+            reservation.release().await?;
+            return Err(e);
+        }
+
+        Ok(reservation)
+    }
+
+    async fn set_tx_info_for_ping_slot(
+        &mut self,
+        mut tx_info: gw::DownlinkTxInfo,
+        ping_slot_ts: chrono::Duration,
+        remaining_payload_size: usize,
+    ) -> Result<()> {
         tx_info.timing = Some(gw::Timing {
             parameters: Some(gw::timing::Parameters::GpsEpoch(gw::GpsEpochTimingInfo {
                 time_since_gps_epoch: Some(pbjson_types::Duration::from(ping_slot_ts.to_std()?)),
@@ -2527,19 +2603,12 @@ impl Data {
             tx_info.frequency = freq;
         }
 
-        // get remaining payload size
-        let max_pl_size = self.region_conf.get_max_payload_size(
-            ds.mac_version().from_proto(),
-            self.device_profile.reg_params_revision,
-            ds.class_b_ping_slot_dr as u8,
-        )?;
-
         self.downlink_frame_items.push(DownlinkFrameItem {
             downlink_frame_item: gw::DownlinkFrameItem {
                 tx_info: Some(tx_info),
                 ..Default::default()
             },
-            remaining_payload_size: max_pl_size.n,
+            remaining_payload_size,
         });
 
         self.device = device;
@@ -2685,7 +2754,6 @@ fn filter_mac_commands(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::storage::fields;
     use crate::test;
     use lrwn::{DevAddr, EUI64};
     use tokio::time::sleep;
@@ -4,9 +4,10 @@ use tracing::{span, trace, Instrument, Level};
 
 use super::helpers;
 use crate::backend::roaming;
-use crate::storage::downlink_frame;
+use crate::storage::{downlink_frame, fields};
 use crate::{gateway, region};
 use chirpstack_api::{gw, internal};
+use lrwn::{DevAddr, NetID, EUI64};
 
 pub struct Data {
     region_config_id: String,
@@ -14,6 +15,7 @@ pub struct Data {
     dl_meta_data: backend::DLMetaData,
     uplink_rx_info: Vec<gw::UplinkRxInfo>,
     downlink_frame: gw::DownlinkFrame,
+    roaming_role: fields::RoamingRole,
 }
 
 impl Data {
@@ -26,10 +28,24 @@ impl Data {
             "xmit_data_req_pr",
             transaction_id = pl.base.transaction_id
         );
-        Data::_handle(pl, dl_meta).instrument(span).await
+        Data::_handle(pl, dl_meta, fields::RoamingRole::FNS)
+            .instrument(span)
+            .await
+    }
+
+    // Sends the downlink constructed by ChirpStack as sNS in case of handover-roaming.
+    pub async fn handle_handover_roaming(
+        pl: backend::XmitDataReqPayload,
+        dl_meta: backend::DLMetaData,
+    ) -> Result<()> {
+        Data::_handle(pl, dl_meta, fields::RoamingRole::SNS).await
     }
 
-    async fn _handle(pl: backend::XmitDataReqPayload, dl_meta: backend::DLMetaData) -> Result<()> {
+    async fn _handle(
+        pl: backend::XmitDataReqPayload,
+        dl_meta: backend::DLMetaData,
+        roaming_role: fields::RoamingRole,
+    ) -> Result<()> {
         let mut uplink_rx_info = roaming::dl_meta_data_to_uplink_rx_info(&dl_meta)?;
         uplink_rx_info.sort_by(|a, b| {
             if a.snr == b.snr {
@@ -57,11 +73,13 @@ impl Data {
                 downlink_id: rand::rng().random(),
                 ..Default::default()
             },
+            roaming_role,
         };
 
         ctx.set_downlink_frame()?;
         ctx.save_downlink_frame().await?;
         ctx.send_downlink_frame().await?;
+        ctx.log_roaming_usage().await?;
 
         Ok(())
     }
@@ -152,6 +170,7 @@ impl Data {
         downlink_frame::save(&internal::DownlinkFrame {
             downlink_id: self.downlink_frame.downlink_id,
             downlink_frame: Some(self.downlink_frame.clone()),
+            region_config_id: self.region_config_id.clone(),
             ..Default::default()
         })
         .await
@@ -169,4 +188,34 @@ impl Data {
 
         Ok(())
     }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+
+        let net_id = NetID::from_slice(&self.xmit_data_req.base.sender_id)?;
+        let dev_eui = EUI64::from_slice(&self.dl_meta_data.dev_eui).ok();
+        let dev_addr = match lrwn::PhyPayload::from_slice(&self.xmit_data_req.phy_payload) {
+            Ok(lrwn::PhyPayload {
+                payload: lrwn::Payload::MACPayload(pl),
+                ..
+            }) => pl.fhdr.devaddr,
+            _ => DevAddr::default(),
+        };
+
+        roaming::log_downlink_usage(
+            net_id,
+            self.roaming_role,
+            dev_eui,
+            dev_addr,
+            self.downlink_frame
+                .items
+                .first()
+                .and_then(|v| v.tx_info.as_ref())
+                .and_then(|v| v.modulation.as_ref()),
+            self.xmit_data_req.phy_payload.len(),
+        )
+        .await;
+
+        Ok(())
+    }
 }
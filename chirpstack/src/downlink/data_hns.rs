@@ -0,0 +1,192 @@
+use anyhow::Result;
+use tracing::{span, trace, Instrument, Level};
+
+use crate::api::backend::get_async_receiver;
+use crate::backend::roaming;
+use crate::storage::{device, device_queue, error::Error as StorageError, fields};
+use chirpstack_api::internal;
+use lrwn::{AES128Key, DevAddr, NetID};
+
+// Data implements the hNS side of handover-roaming for downlinks. The next device queue-item
+// is encrypted using the AppSKey and forwarded as FRMPayload to the sNS, which handles the
+// transmission.
+pub struct Data {
+    device: device::Device,
+    handover_session: internal::HandoverRoamingDeviceSession,
+    ul_meta_data: backend::ULMetaData,
+    device_queue_item: Option<device_queue::DeviceQueueItem>,
+    frm_payload: Vec<u8>,
+    f_cnt_down: u32,
+}
+
+impl Data {
+    pub async fn handle(
+        device: device::Device,
+        handover_session: internal::HandoverRoamingDeviceSession,
+        ul_meta_data: backend::ULMetaData,
+    ) -> Result<()> {
+        let span = span!(Level::INFO, "data_down_hr", dev_eui = %device.dev_eui);
+        Data::_handle(device, handover_session, ul_meta_data)
+            .instrument(span)
+            .await
+    }
+
+    async fn _handle(
+        device: device::Device,
+        handover_session: internal::HandoverRoamingDeviceSession,
+        ul_meta_data: backend::ULMetaData,
+    ) -> Result<()> {
+        let mut ctx = Data {
+            device,
+            handover_session,
+            ul_meta_data,
+            device_queue_item: None,
+            frm_payload: Vec::new(),
+            f_cnt_down: 0,
+        };
+
+        ctx.get_next_device_queue_item().await?;
+        if ctx.device_queue_item.is_none() {
+            return Ok(());
+        }
+
+        ctx.encrypt_frm_payload()?;
+        ctx.send_xmit_data_req().await?;
+        ctx.delete_device_queue_item().await?;
+        ctx.update_device().await?;
+        ctx.log_roaming_usage().await?;
+
+        Ok(())
+    }
+
+    async fn get_next_device_queue_item(&mut self) -> Result<()> {
+        trace!("Getting next device queue-item");
+
+        self.device_queue_item =
+            match device_queue::get_next_for_dev_eui(&self.device.dev_eui).await {
+                Ok((qi, _)) => Some(qi),
+                Err(StorageError::NotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            };
+
+        Ok(())
+    }
+
+    fn encrypt_frm_payload(&mut self) -> Result<()> {
+        trace!("Encrypting FRMPayload");
+        let ds = self.device.get_device_session()?;
+        let qi = self.device_queue_item.as_ref().unwrap();
+
+        if qi.is_encrypted {
+            self.f_cnt_down = qi.f_cnt_down.unwrap_or_default() as u32;
+            self.frm_payload.clone_from(&qi.data);
+            return Ok(());
+        }
+
+        let app_s_key = match &ds.app_s_key {
+            Some(v) if v.kek_label.is_empty() => AES128Key::from_slice(&v.aes_key)?,
+            _ => {
+                return Err(anyhow!(
+                    "AppSKey is not available, queue-item must be encrypted"
+                ));
+            }
+        };
+
+        self.f_cnt_down = ds.get_a_f_cnt_down();
+        self.frm_payload = lrwn::encrypt_frm_payload(
+            &app_s_key,
+            false,
+            &DevAddr::from_slice(&self.handover_session.dev_addr)?,
+            self.f_cnt_down,
+            &qi.data,
+        )?;
+
+        Ok(())
+    }
+
+    async fn send_xmit_data_req(&self) -> Result<()> {
+        trace!("Sending XmitDataReq to sNS");
+        let qi = self.device_queue_item.as_ref().unwrap();
+        let net_id = NetID::from_slice(&self.handover_session.net_id)?;
+        let client = roaming::get(&net_id).await?;
+
+        let mut req = backend::XmitDataReqPayload {
+            frm_payload: self.frm_payload.clone(),
+            dl_meta_data: Some(backend::DLMetaData {
+                dev_eui: self.device.dev_eui.to_vec(),
+                f_port: Some(qi.f_port as u8),
+                f_cnt_down: Some(self.f_cnt_down),
+                confirmed: qi.confirmed,
+                class_mode: Some("A".to_string()),
+                f_ns_ul_token: self.ul_meta_data.f_ns_ul_token.clone(),
+                gw_info: self
+                    .ul_meta_data
+                    .gw_info
+                    .iter()
+                    .map(|gw| backend::GWInfoElement {
+                        ul_token: gw.ul_token.clone(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let async_receiver = match client.is_async() {
+            false => None,
+            true => {
+                Some(get_async_receiver(req.base.transaction_id, client.get_async_timeout()).await?)
+            }
+        };
+        client
+            .xmit_data_req(backend::Role::SNS, &mut req, async_receiver)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_device_queue_item(&self) -> Result<()> {
+        trace!("Deleting device queue-item");
+        let qi = self.device_queue_item.as_ref().unwrap();
+        device_queue::delete_item(&qi.id).await?;
+        Ok(())
+    }
+
+    async fn update_device(&mut self) -> Result<()> {
+        trace!("Updating device");
+        let mut ds = self.device.get_device_session()?.clone();
+        ds.set_a_f_cnt_down(self.f_cnt_down + 1);
+
+        self.device = device::partial_update(
+            self.device.dev_eui,
+            &device::DeviceChangeset {
+                device_session: Some(Some(ds.into())),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+
+        // The sNS selects the downlink parameters, therefore the airtime is estimated using
+        // the data-rate of the uplink.
+        let tx_info = roaming::ul_meta_data_to_tx_info(&self.ul_meta_data)?;
+
+        roaming::log_downlink_usage(
+            NetID::from_slice(&self.handover_session.net_id)?,
+            fields::RoamingRole::HNS,
+            Some(self.device.dev_eui),
+            DevAddr::from_slice(&self.handover_session.dev_addr)?,
+            tx_info.modulation.as_ref(),
+            self.frm_payload.len() + roaming::DATA_FRAME_OVERHEAD,
+        )
+        .await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use tracing::{span, trace, warn, Instrument, Level};
+
+use super::data_fns;
+use crate::region;
+use crate::storage::{error::Error as StorageError, handover_roaming};
+use chirpstack_api::internal;
+use lrwn::{AES128Key, DevAddr, NetID, EUI64};
+
+// Data implements the sNS side of handover-roaming for downlinks. The hNS sends the (encrypted)
+// FRMPayload together with the DLMetaData, the sNS constructs the PHYPayload using the network
+// session-keys received from the hNS and schedules the downlink.
+pub struct Data {
+    xmit_data_req: backend::XmitDataReqPayload,
+    dl_meta_data: backend::DLMetaData,
+    device_session: internal::HandoverRoamingServingDeviceSession,
+    f_cnt_down: u32,
+    phy_payload: Vec<u8>,
+}
+
+impl Data {
+    pub async fn handle(
+        pl: backend::XmitDataReqPayload,
+        dl_meta: backend::DLMetaData,
+    ) -> Result<()> {
+        let span = span!(
+            Level::INFO,
+            "xmit_data_req_hr",
+            transaction_id = pl.base.transaction_id
+        );
+        Data::_handle(pl, dl_meta).instrument(span).await
+    }
+
+    async fn _handle(pl: backend::XmitDataReqPayload, dl_meta: backend::DLMetaData) -> Result<()> {
+        let dev_eui = EUI64::from_slice(&dl_meta.dev_eui)?;
+        let device_session = handover_roaming::get_serving(&dev_eui).await?;
+
+        let mut ctx = Data {
+            xmit_data_req: pl,
+            dl_meta_data: dl_meta,
+            device_session,
+            f_cnt_down: 0,
+            phy_payload: Vec::new(),
+        };
+
+        ctx.validate_sender()?;
+        ctx.validate_f_cnt_down()?;
+        ctx.set_phy_payload()?;
+        ctx.update_device_session().await?;
+        ctx.send_downlink().await?;
+
+        Ok(())
+    }
+
+    fn validate_sender(&self) -> Result<()> {
+        trace!("Validating sender");
+
+        // Only the hNS that has started the handover-roaming session is allowed to send
+        // downlinks for this device.
+        let sender_id = NetID::from_slice(&self.xmit_data_req.base.sender_id)?;
+        if self.device_session.net_id != sender_id.to_vec() {
+            return Err(StorageError::NotFound(
+                EUI64::from_slice(&self.device_session.dev_eui)?.to_string(),
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    fn validate_f_cnt_down(&mut self) -> Result<()> {
+        trace!("Validating downlink frame-counter");
+
+        let f_cnt_down = self
+            .dl_meta_data
+            .f_cnt_down
+            .ok_or_else(|| anyhow!("FCntDown is not set"))?;
+
+        // The device-session contains the next expected frame-counter. Re-using a frame-counter
+        // would result in the device rejecting the downlink.
+        if f_cnt_down < self.device_session.n_f_cnt_down {
+            warn!(
+                f_cnt_down = f_cnt_down,
+                expected_f_cnt_down = self.device_session.n_f_cnt_down,
+                "Downlink frame-counter is not greater than the previous frame-counter"
+            );
+            return Err(anyhow!(
+                "Invalid FCntDown, expected >= {}, got: {}",
+                self.device_session.n_f_cnt_down,
+                f_cnt_down
+            ));
+        }
+
+        self.f_cnt_down = f_cnt_down;
+
+        Ok(())
+    }
+
+    fn set_phy_payload(&mut self) -> Result<()> {
+        trace!("Setting PHYPayload");
+
+        let ds = &self.device_session;
+        let mut phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: match self.dl_meta_data.confirmed {
+                    true => lrwn::MType::ConfirmedDataDown,
+                    false => lrwn::MType::UnconfirmedDataDown,
+                },
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::MACPayload(lrwn::MACPayload {
+                fhdr: lrwn::FHDR {
+                    devaddr: DevAddr::from_slice(&ds.dev_addr)?,
+                    f_cnt: self.f_cnt_down,
+                    f_ctrl: lrwn::FCtrl {
+                        ack: ds.pending_ack,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                f_port: self.dl_meta_data.f_port,
+                frm_payload: Some(lrwn::FRMPayload::Raw(
+                    self.xmit_data_req.frm_payload.clone(),
+                )),
+            }),
+            mic: None,
+        };
+
+        if ds.lorawan_1_1 {
+            phy.set_downlink_data_mic(
+                lrwn::MACVersion::LoRaWAN1_1,
+                if ds.pending_ack { ds.conf_f_cnt } else { 0 },
+                &AES128Key::from_slice(&ds.s_nwk_s_int_key)?,
+            )?;
+        } else {
+            phy.set_downlink_data_mic(
+                lrwn::MACVersion::LoRaWAN1_0,
+                0,
+                &AES128Key::from_slice(&ds.f_nwk_s_int_key)?,
+            )?;
+        }
+
+        self.phy_payload.clear();
+        phy.encode_into(&mut self.phy_payload)?;
+
+        Ok(())
+    }
+
+    async fn update_device_session(&mut self) -> Result<()> {
+        trace!("Updating handover-roaming device-session");
+
+        let ds = &mut self.device_session;
+        ds.n_f_cnt_down = self.f_cnt_down + 1;
+        ds.pending_ack = false;
+        ds.conf_f_cnt = 0;
+
+        handover_roaming::save_serving(ds).await
+    }
+
+    async fn send_downlink(&self) -> Result<()> {
+        trace!("Sending downlink");
+
+        let ds = &self.device_session;
+        let region_conf = region::get(&ds.region_config_id)?;
+        let rx1_freq = region_conf.get_rx1_frequency_for_uplink_frequency(ds.uplink_frequency)?;
+        let rx1_dr = region_conf.get_rx1_data_rate_index(ds.dr as u8, ds.rx1_dr_offset as usize)?;
+
+        let mut pl = self.xmit_data_req.clone();
+        pl.phy_payload.clone_from(&self.phy_payload);
+        pl.frm_payload = Vec::new();
+
+        let dl_meta = backend::DLMetaData {
+            dl_freq_1: Some(rx1_freq as f64 / 1_000_000.0),
+            data_rate_1: Some(rx1_dr),
+            rx_delay_1: Some(ds.rx1_delay as usize),
+            dl_freq_2: Some(ds.rx2_frequency as f64 / 1_000_000.0),
+            data_rate_2: Some(ds.rx2_dr as u8),
+            class_mode: Some("A".to_string()),
+            ..self.dl_meta_data.clone()
+        };
+
+        data_fns::Data::handle_handover_roaming(pl, dl_meta).await
+    }
+}
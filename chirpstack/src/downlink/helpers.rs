@@ -71,6 +71,12 @@ pub fn select_downlink_gateway(
         }
     }
 
+    // In case one or multiple gateways within the margin received the uplink directly, prefer
+    // these over gateways that received the uplink through a Relay Gateway (mesh).
+    if new_items.iter().any(|i| i.relay_id.is_empty()) {
+        new_items.retain(|i| i.relay_id.is_empty());
+    }
+
     // Return a random item from the new_items slice (filtered by min_snr_margin).
     // If new_items is empty, then choose will return None and we return the first item from
     // rx_info.item.
@@ -237,6 +243,29 @@ mod tests {
                     vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04],
                 ],
             },
+            // two items within min snr, one received through relay gateway
+            Test {
+                tenant_id: None,
+                min_snr_margin: 5.0,
+                rx_info: internal::DeviceGatewayRxInfo {
+                    dr: 2, // -15 is required
+                    items: vec![
+                        internal::DeviceGatewayRxInfoItem {
+                            lora_snr: -10.0,
+                            gateway_id: vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+                            ..Default::default()
+                        },
+                        internal::DeviceGatewayRxInfoItem {
+                            lora_snr: -5.0,
+                            gateway_id: vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02],
+                            relay_id: vec![0x01, 0x02, 0x03, 0x04],
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                },
+                expected_gws: vec![vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01]],
+            },
             // is_private_down is set, first gateway matches tenant.
             Test {
                 tenant_id: Some(t.id.into()),
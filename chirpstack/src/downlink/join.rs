@@ -12,7 +12,7 @@ use super::helpers;
 use crate::api::helpers::FromProto;
 use crate::gateway::backend::send_downlink;
 use crate::storage::{device, downlink_frame, tenant};
-use crate::uplink::{RelayContext, UplinkFrameSet};
+use crate::uplink::{mesh, RelayContext, UplinkFrameSet};
 use crate::{config, region};
 use chirpstack_api::{gw, internal};
 
@@ -170,6 +170,9 @@ impl JoinAccept<'_> {
                             .get(&gw_id)
                             .map(|v| v.into_bytes().to_vec())
                             .unwrap_or_default(),
+                        relay_id: mesh::get_relay_id(rx_info)
+                            .map(|v| v.to_vec())
+                            .unwrap_or_default(),
                     }
                 })
                 .collect(),
@@ -533,6 +536,7 @@ impl JoinAccept<'_> {
             downlink_id: self.downlink_frame.downlink_id,
             downlink_frame: Some(self.downlink_frame.clone()),
             nwk_s_enc_key: ds.nwk_s_enc_key.clone(),
+            region_config_id: self.uplink_frame_set.region_config_id.clone(),
             ..Default::default()
         };
 
@@ -556,6 +560,7 @@ impl JoinAccept<'_> {
             nwk_s_enc_key: relay_ds.nwk_s_enc_key.clone(),
             a_f_cnt_down: relay_ds.get_a_f_cnt_down(),
             n_f_cnt_down: relay_ds.n_f_cnt_down,
+            region_config_id: self.uplink_frame_set.region_config_id.clone(),
             ..Default::default()
         };
 
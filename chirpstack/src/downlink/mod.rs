@@ -3,8 +3,10 @@ use tracing::info;
 pub mod classb;
 pub mod data;
 pub mod data_fns;
+pub mod data_hns;
+pub mod data_sns_hr;
 pub mod error;
-mod helpers;
+pub mod helpers;
 pub mod join;
 pub mod multicast;
 pub mod roaming;
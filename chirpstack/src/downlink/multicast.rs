@@ -8,7 +8,7 @@ use petgraph::graph::{DefaultIx, Graph, NodeIndex, UnGraph};
 use rand::Rng;
 use tracing::{span, trace, warn, Instrument, Level};
 
-use crate::downlink::{error::Error, helpers};
+use crate::downlink::{classb, error::Error, helpers};
 use crate::gateway::backend as gateway_backend;
 use crate::storage::{device_gateway, downlink_frame, gateway, multicast};
 use crate::{config, region};
@@ -167,6 +167,16 @@ impl Multicast {
 
         match self.multicast_group_queue_item.emit_at_time_since_gps_epoch {
             Some(v) => {
+                // Use the beacon-period based ping-slot frequency if not configured. As this
+                // is derived from the ping-slot timestamp, this is consistent for all gateways.
+                if mg.group_type == "B" && tx_info.frequency == 0 {
+                    let beacon_ts = classb::get_beacon_start(
+                        chrono::Duration::try_milliseconds(v).unwrap_or_default(),
+                    );
+                    tx_info.frequency =
+                        region_conf.get_ping_slot_frequency(mg.mc_addr, beacon_ts.to_std()?)?;
+                }
+
                 tx_info.timing = Some(gw::Timing {
                     parameters: Some(gw::timing::Parameters::GpsEpoch(gw::GpsEpochTimingInfo {
                         time_since_gps_epoch: Some(pbjson_types::Duration::from(
@@ -222,7 +232,9 @@ impl Multicast {
         // confirmed frame-counter
         phy.set_downlink_data_mic(lrwn::MACVersion::LoRaWAN1_1, 0, &mg.mc_nwk_s_key)?;
 
-        self.downlink_frame.items[0].phy_payload = phy.to_vec()?;
+        let b = &mut self.downlink_frame.items[0].phy_payload;
+        b.clear();
+        phy.encode_into(b).context("Encode PHYPayload")?;
 
         Ok(())
     }
@@ -239,6 +251,7 @@ impl Multicast {
                 .to_vec(),
             multicast_group_queue_item_id: self.multicast_group_queue_item.id.as_bytes().to_vec(),
             downlink_frame: Some(self.downlink_frame.clone()),
+            region_config_id: self.region_config_id.clone(),
             ..Default::default()
         })
         .await
@@ -259,21 +272,47 @@ impl Multicast {
 }
 
 pub async fn enqueue(qi: multicast::MulticastGroupQueueItem) -> Result<u32> {
+    let mg = multicast::get(&qi.multicast_group_id).await?;
+
     // Try first to get configured gateways for multicast-group.
     let mut gateway_ids = multicast::get_gateway_ids(&qi.multicast_group_id).await?;
 
-    // Fallback to automatic gateway-set detection.
-    if gateway_ids.is_empty() {
+    if !gateway_ids.is_empty() {
+        // Only use the configured gateways within the multicast-group geofence.
+        gateway_ids = multicast::filter_gateway_ids(&mg, &gateway_ids).await?;
+    } else {
+        // Fallback to automatic gateway-set detection.
+
         // get deveuis for multicast-group
         let dev_euis = multicast::get_dev_euis(&qi.multicast_group_id).await?;
 
         // get DeviceGatewayRxInfo for all devices.
-        let dev_gw_set = device_gateway::get_rx_info_for_dev_euis(&dev_euis).await?;
+        let mut dev_gw_set = device_gateway::get_rx_info_for_dev_euis(&dev_euis).await?;
+
+        // remove the gateways outside the multicast-group geofence, such that the gateway set
+        // is selected from the allowed gateways only
+        let allowed_gateway_ids: HashSet<EUI64> =
+            multicast::filter_gateway_ids(&mg, &get_gateway_set(&dev_gw_set)?)
+                .await?
+                .into_iter()
+                .collect();
+        for dev_gw in &mut dev_gw_set {
+            dev_gw.items.retain(|rx_info| {
+                EUI64::from_slice(&rx_info.gateway_id)
+                    .map(|gateway_id| allowed_gateway_ids.contains(&gateway_id))
+                    .unwrap_or_default()
+            });
+        }
+        dev_gw_set.retain(|dev_gw| !dev_gw.items.is_empty());
 
         // get minimum gateway set to cover all devices
         gateway_ids = get_minimum_gateway_set(&dev_gw_set)?;
     }
 
+    if gateway_ids.is_empty() {
+        warn!(multicast_group_id = %mg.id, "No gateways available for multicast-group");
+    }
+
     // Enqueue multicast downlink for the given gw set.
     let (_, f_cnt) = multicast::enqueue(qi, &gateway_ids).await?;
     Ok(f_cnt)
@@ -7,7 +7,7 @@ use tracing::{span, trace, Instrument, Level};
 
 use super::helpers;
 use crate::storage::downlink_frame;
-use crate::uplink::UplinkFrameSet;
+use crate::uplink::{mesh, UplinkFrameSet};
 use crate::{config, gateway, region};
 use backend::DLMetaData;
 use chirpstack_api::{gw, internal};
@@ -95,6 +95,9 @@ impl PassiveRoamingDownlink {
                             .get(&gw_id)
                             .map(|v| v.into_bytes().to_vec())
                             .unwrap_or_default(),
+                        relay_id: mesh::get_relay_id(rx_info)
+                            .map(|v| v.to_vec())
+                            .unwrap_or_default(),
                     }
                 })
                 .collect(),
@@ -222,6 +225,7 @@ impl PassiveRoamingDownlink {
         downlink_frame::save(&internal::DownlinkFrame {
             downlink_id: self.downlink_frame.downlink_id,
             downlink_frame: Some(self.downlink_frame.clone()),
+            region_config_id: self.uplink_frame_set.region_config_id.clone(),
             ..Default::default()
         })
         .await
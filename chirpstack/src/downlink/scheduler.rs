@@ -4,9 +4,9 @@ use tracing::{error, trace};
 
 use super::data;
 use super::multicast as mcast;
-use crate::config;
 use crate::helpers::errors::PrintFullError;
 use crate::storage::{device, multicast};
+use crate::{config, coordination, monitoring};
 
 pub async fn class_b_c_scheduler_loop() {
     let conf = config::get();
@@ -50,13 +50,35 @@ pub async fn schedule_device_queue_batch(size: usize) -> Result<()> {
         "Got this number of devices with schedulable queue-items"
     );
 
+    // The scheduler_run_after claim distributes the devices over the ChirpStack instances. The
+    // device lock is held while the queue-item is being scheduled, such that an other instance
+    // re-claiming the device after the claim expired skips it, instead of scheduling the same
+    // device concurrently.
     let mut handles = vec![];
 
     for dev in devices {
+        let queue_guard = monitoring::runtime::enter_queue("device_queue_scheduler");
+
         // Spawn the batch as async tasks.
         let handle = tokio::spawn(async move {
-            if let Err(e) = data::Data::handle_schedule_next_queue_item(dev).await {
-                error!(error = %e, "Schedule next queue-item for device failed");
+            let _queue_guard = queue_guard;
+            let lock_name = format!("scheduler:device:{}", dev.dev_eui);
+            match coordination::with_lock(
+                &lock_name,
+                data::Data::handle_schedule_next_queue_item(dev),
+            )
+            .await
+            {
+                Ok(Some(Err(e))) => {
+                    error!(error = %e, "Schedule next queue-item for device failed");
+                }
+                Ok(Some(Ok(_))) => {}
+                Ok(None) => {
+                    trace!(lock = %lock_name, "Device is locked by an other scheduler, skipping");
+                }
+                Err(e) => {
+                    error!(error = %e.full(), "Acquire device scheduler lock failed");
+                }
             }
         });
         handles.push(handle);
@@ -75,12 +97,34 @@ pub async fn schedule_multicast_group_queue_batch(size: usize) -> Result<()> {
         "Got this number of multicast-group queue items"
     );
 
+    // See schedule_device_queue_batch for the claim vs lock.
     let mut handles = vec![];
 
     for qi in items {
+        let queue_guard = monitoring::runtime::enter_queue("multicast_queue_scheduler");
+
         let handle = tokio::spawn(async move {
-            if let Err(e) = mcast::Multicast::handle_schedule_queue_item(qi).await {
-                error!(error = %e.full(), "Schedule multicast-group queue item failed");
+            let _queue_guard = queue_guard;
+            let lock_name = format!("scheduler:multicast:{}", qi.id);
+            match coordination::with_lock(
+                &lock_name,
+                mcast::Multicast::handle_schedule_queue_item(qi),
+            )
+            .await
+            {
+                Ok(Some(Err(e))) => {
+                    error!(error = %e.full(), "Schedule multicast-group queue item failed");
+                }
+                Ok(Some(Ok(_))) => {}
+                Ok(None) => {
+                    trace!(
+                        lock = %lock_name,
+                        "Multicast-group queue item is locked by an other scheduler, skipping"
+                    );
+                }
+                Err(e) => {
+                    error!(error = %e.full(), "Acquire multicast scheduler lock failed");
+                }
             }
         });
         handles.push(handle);
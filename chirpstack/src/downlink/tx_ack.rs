@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 use anyhow::Result;
 use chrono::{Duration, Utc};
 use tracing::{error, info, span, trace, Instrument, Level};
@@ -6,6 +8,8 @@ use uuid::Uuid;
 use lrwn::{AES128Key, MType, Payload, PhyPayload, EUI64};
 
 use crate::api::helpers::ToProto;
+use crate::gateway::airtime;
+use crate::helpers::errors::PrintFullError;
 use crate::storage::{
     application,
     device::{self, DeviceClass},
@@ -13,7 +17,7 @@ use crate::storage::{
     helpers::get_all_device_data,
     multicast, tenant,
 };
-use crate::{integration, stream};
+use crate::{integration, monitoring, stream};
 use chirpstack_api::{common, gw, integration as integration_pb, internal, stream as stream_pb};
 
 pub struct TxAck {
@@ -80,6 +84,10 @@ impl TxAck {
         ctx.get_downlink_frame().await?;
         ctx.decode_phy_payload()?;
 
+        if !ctx.is_error() {
+            ctx.save_tx_airtime().await;
+        }
+
         if ctx.is_relay_payload() {
             return ctx._handle_relayed().await;
         }
@@ -88,6 +96,7 @@ impl TxAck {
             if ctx.is_application_payload() || ctx.is_mac_only_downlink() {
                 ctx.get_device_data().await?;
                 ctx.log_tx_ack_error().await?;
+                ctx.save_sla_metrics();
             }
 
             if ctx.is_multicast_downlink() {
@@ -126,6 +135,8 @@ impl TxAck {
             // log downlink frame and meta-data.
             ctx.log_downlink_frame().await?;
             ctx.log_downlink_meta().await?;
+            ctx.save_tenant_metrics();
+            ctx.save_sla_metrics();
         }
 
         Ok(())
@@ -139,6 +150,7 @@ impl TxAck {
             // We log the tx ack error under the relay as this is the device to which the downlink
             // is sent.
             self.log_tx_ack_error().await?;
+            self.save_sla_metrics();
         } else {
             // First handle the relay frame-counter increment.
             self.increment_a_f_cnt_down()?;
@@ -175,6 +187,8 @@ impl TxAck {
             // downlink is sent.
             self.log_downlink_frame().await?;
             self.log_downlink_meta().await?;
+            self.save_tenant_metrics();
+            self.save_sla_metrics();
         }
 
         Ok(())
@@ -726,6 +740,73 @@ impl TxAck {
         stream::meta::log_downlink(&dm).await
     }
 
+    // Errors are logged as these must not prevent the tx ack from being handled.
+    async fn save_tx_airtime(&self) {
+        trace!("Saving transmitted airtime");
+
+        let df = self.downlink_frame.as_ref().unwrap();
+        let dfi = self.downlink_frame_item.as_ref().unwrap();
+
+        // Downlinks stored before the region_config_id was added to the downlink-frame.
+        if df.region_config_id.is_empty() {
+            return;
+        }
+
+        let tx_info = match &dfi.tx_info {
+            Some(v) => v,
+            None => return,
+        };
+
+        let gateway_id = match EUI64::from_str(&df.downlink_frame.as_ref().unwrap().gateway_id) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(error = %e, "Decode gateway_id error");
+                return;
+            }
+        };
+
+        if let Err(e) = airtime::save_tx_airtime(
+            &df.region_config_id,
+            &gateway_id,
+            tx_info,
+            dfi.phy_payload.len(),
+        )
+        .await
+        {
+            error!(gateway_id = %gateway_id, error = %e.full(), "Saving transmitted airtime failed");
+        }
+    }
+
+    // The tenant is not set in case of a multicast downlink.
+    fn save_tenant_metrics(&self) {
+        trace!("Saving tenant metrics");
+
+        let tenant = match &self.tenant {
+            Some(v) => v,
+            None => return,
+        };
+        let dfi = self.downlink_frame_item.as_ref().unwrap();
+
+        monitoring::tenant::inc_downlink(&tenant.id);
+
+        if let Some(airtime) = dfi
+            .tx_info
+            .as_ref()
+            .and_then(|v| v.modulation.as_ref())
+            .and_then(|v| airtime::get_airtime(v, dfi.phy_payload.len()))
+        {
+            monitoring::tenant::inc_airtime(&tenant.id, "tx", airtime);
+        }
+    }
+
+    fn save_sla_metrics(&self) {
+        trace!("Saving SLA metrics");
+
+        if let Some(tenant) = &self.tenant {
+            monitoring::sla::record_downlink(&tenant.id, !self.is_error());
+        }
+    }
+
     fn is_error(&self) -> bool {
         self.downlink_tx_ack_status != gw::TxAckStatus::Ok
     }
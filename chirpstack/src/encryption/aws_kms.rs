@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use super::KeyProvider;
+use crate::config;
+
+// Provider using AWS KMS for encrypting the data encryption keys.
+pub struct Provider {
+    client: Client,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    key_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptRequest<'a> {
+    key_id: &'a str,
+    plaintext: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptResponse {
+    ciphertext_blob: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptRequest<'a> {
+    key_id: &'a str,
+    ciphertext_blob: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+impl Provider {
+    pub fn new(conf: &config::EncryptionAwsKms) -> Provider {
+        Provider {
+            client: Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap(),
+            region: conf.region.clone(),
+            access_key_id: conf.access_key_id.clone(),
+            secret_access_key: conf.secret_access_key.clone(),
+            key_id: conf.key_id.clone(),
+        }
+    }
+
+    async fn request(&self, target: &str, body: String) -> Result<reqwest::Response> {
+        let hostname = format!("kms.{}.amazonaws.com", self.region);
+        let url = format!("https://{}/", hostname);
+        let ts = chrono::Utc::now();
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("host", hostname.parse()?);
+        headers.insert(
+            "X-Amz-Date",
+            ts.format("%Y%m%dT%H%M%SZ").to_string().parse()?,
+        );
+        headers.insert("X-Amz-Target", target.parse()?);
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            "application/x-amz-json-1.1".parse()?,
+        );
+
+        let s = aws_sign_v4::AwsSign::new(
+            "POST",
+            &url,
+            &ts,
+            &headers,
+            &self.region,
+            &self.access_key_id,
+            &self.secret_access_key,
+            "kms",
+            &body,
+        )
+        .sign();
+
+        headers.insert(reqwest::header::AUTHORIZATION, s.parse()?);
+
+        Ok(self
+            .client
+            .post(url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?)
+    }
+}
+
+#[async_trait]
+impl KeyProvider for Provider {
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        trace!(key_id = %self.key_id, "Encrypting using AWS KMS");
+
+        let body = serde_json::to_string(&EncryptRequest {
+            key_id: &self.key_id,
+            plaintext: general_purpose::STANDARD.encode(plaintext),
+        })?;
+        let resp: EncryptResponse = self
+            .request("TrentService.Encrypt", body)
+            .await?
+            .json()
+            .await?;
+
+        Ok(general_purpose::STANDARD.decode(resp.ciphertext_blob)?)
+    }
+
+    async fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        trace!(key_id = %self.key_id, "Decrypting using AWS KMS");
+
+        let body = serde_json::to_string(&DecryptRequest {
+            key_id: &self.key_id,
+            ciphertext_blob: general_purpose::STANDARD.encode(ciphertext),
+        })?;
+        let resp: DecryptResponse = self
+            .request("TrentService.Decrypt", body)
+            .await?
+            .json()
+            .await?;
+
+        Ok(general_purpose::STANDARD.decode(resp.plaintext)?)
+    }
+}
@@ -0,0 +1,69 @@
+use aes_kw::KekAes256;
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::KeyProvider;
+use crate::config;
+
+// Provider using a local (configured) master key for wrapping the data encryption keys.
+pub struct Provider {
+    master_key: [u8; 32],
+}
+
+impl Provider {
+    pub fn new(conf: &config::EncryptionLocal) -> Result<Provider> {
+        let master_key: [u8; 32] = hex::decode(&conf.master_key)
+            .map_err(|e| anyhow!("Decode master_key error: {}", e))?
+            .try_into()
+            .map_err(|_| anyhow!("master_key must be 32 bytes"))?;
+
+        Ok(Provider { master_key })
+    }
+}
+
+#[async_trait]
+impl KeyProvider for Provider {
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut out: Vec<u8> = vec![0; plaintext.len() + 8];
+        KekAes256::from(self.master_key)
+            .wrap(plaintext, &mut out)
+            .map_err(|e| anyhow!("Wrap key error: {}", e))?;
+        Ok(out)
+    }
+
+    async fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < 16 {
+            return Err(anyhow!("Invalid ciphertext length"));
+        }
+
+        let mut out: Vec<u8> = vec![0; ciphertext.len() - 8];
+        KekAes256::from(self.master_key)
+            .unwrap(ciphertext, &mut out)
+            .map_err(|e| anyhow!("Unwrap key error: {}", e))?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_provider() {
+        let p = Provider::new(&config::EncryptionLocal {
+            master_key: "0102030405060708010203040506070801020304050607080102030405060708".into(),
+        })
+        .unwrap();
+
+        let key: [u8; 32] = [2; 32];
+        let b = p.encrypt(&key).await.unwrap();
+        assert_eq!(40, b.len());
+        assert_eq!(key.to_vec(), p.decrypt(&b).await.unwrap());
+
+        // Invalid master key.
+        assert!(Provider::new(&config::EncryptionLocal {
+            master_key: "0102".into(),
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use aes_kw::KekAes256;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use rand::RngCore;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::config;
+use crate::storage::{cache, data_encryption_key};
+
+mod aws_kms;
+mod local;
+mod vault_transit;
+
+// Version byte of the encrypted key format:
+//   version (1 byte) | data encryption key ID (4 bytes, BE) | AES key-wrapped key
+const FORMAT_VERSION: u8 = 0x01;
+const HEADER_LEN: usize = 5;
+
+lazy_static! {
+    static ref DATA_KEYS: RwLock<DataKeys> = RwLock::new(Default::default());
+}
+
+#[derive(Default)]
+struct DataKeys {
+    active: Option<i32>,
+    // Active data encryption key by tenant, in case per-tenant keys are enabled.
+    tenants: HashMap<Uuid, i32>,
+    keys: HashMap<i32, [u8; 32]>,
+}
+
+// KeyProvider implements the encryption and decryption of the data encryption keys (DEKs),
+// using a key that is managed by the provider (KMS).
+#[async_trait]
+pub trait KeyProvider {
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+    async fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+}
+
+fn get_provider(conf: &config::Encryption) -> Result<Box<dyn KeyProvider + Sync + Send>> {
+    Ok(match conf.backend.as_ref() {
+        "local" => Box::new(local::Provider::new(&conf.local)?),
+        "vault_transit" => Box::new(vault_transit::Provider::new(&conf.vault_transit)),
+        "aws_kms" => Box::new(aws_kms::Provider::new(&conf.aws_kms)),
+        _ => return Err(anyhow!("Unexpected encryption backend: {}", conf.backend)),
+    })
+}
+
+pub async fn setup() -> Result<()> {
+    let conf = config::get();
+    if conf.encryption.backend.is_empty() {
+        *DATA_KEYS.write().unwrap() = Default::default();
+        return Ok(());
+    }
+
+    info!(
+        backend = %conf.encryption.backend,
+        per_tenant_keys = conf.encryption.per_tenant_keys,
+        "Setting up encryption of stored key material"
+    );
+    let provider = get_provider(&conf.encryption)?;
+
+    let deks = data_encryption_key::list().await?;
+    if !deks.iter().any(|dek| dek.tenant_id.is_none()) {
+        create_data_key(provider.as_ref(), None).await?;
+    }
+
+    if conf.encryption.per_tenant_keys {
+        for tenant_id in data_encryption_key::get_tenant_ids().await? {
+            if !deks
+                .iter()
+                .any(|dek| dek.tenant_id.as_deref() == Some(&tenant_id))
+            {
+                create_data_key(provider.as_ref(), Some(tenant_id)).await?;
+            }
+        }
+    }
+
+    // The keys are listed again, as the keys created by this or an other instance which is
+    // set up at the same time must be loaded in the order of their database assigned IDs.
+    let deks = data_encryption_key::list().await?;
+    load_data_keys(provider.as_ref(), &deks).await
+}
+
+// Reloads the data encryption keys, e.g. after an other instance created or rotated a data
+// encryption key.
+pub async fn reload() -> Result<()> {
+    let conf = config::get();
+    if conf.encryption.backend.is_empty() {
+        return Ok(());
+    }
+
+    let provider = get_provider(&conf.encryption)?;
+    let deks = data_encryption_key::list().await?;
+    load_data_keys(provider.as_ref(), &deks).await
+}
+
+// Returns true when per-tenant data encryption keys are enabled.
+pub fn per_tenant_keys() -> bool {
+    let conf = config::get();
+    !conf.encryption.backend.is_empty() && conf.encryption.per_tenant_keys
+}
+
+async fn load_data_keys(
+    provider: &(dyn KeyProvider + Sync + Send),
+    deks: &[data_encryption_key::DataEncryptionKey],
+) -> Result<()> {
+    let conf = config::get();
+    let mut data_keys = DataKeys::default();
+
+    // The keys are ordered by ID, thus the last key is the active key.
+    for dek in deks {
+        if dek.backend != conf.encryption.backend {
+            return Err(anyhow!(
+                "Data encryption key {} was encrypted using the {} backend",
+                dek.id,
+                dek.backend
+            ));
+        }
+
+        let key = provider
+            .decrypt(&dek.encrypted_key)
+            .await
+            .with_context(|| format!("Decrypt data encryption key {}", dek.id))?;
+        let key: [u8; 32] = key
+            .try_into()
+            .map_err(|_| anyhow!("Data encryption key {} must be 32 bytes", dek.id))?;
+
+        data_keys.keys.insert(dek.id, key);
+
+        // Tenant keys are always loaded for decryption, but only used for encryption in case
+        // per-tenant keys are enabled.
+        match dek.tenant_id {
+            Some(tenant_id) => {
+                if conf.encryption.per_tenant_keys {
+                    data_keys.tenants.insert(*tenant_id, dek.id);
+                }
+            }
+            None => data_keys.active = Some(dek.id),
+        }
+    }
+
+    *DATA_KEYS.write().unwrap() = data_keys;
+
+    Ok(())
+}
+
+// Creates a new data encryption key and makes it the active key for encryption. When a tenant
+// ID is given, the key is created for the given tenant.
+pub async fn rotate_data_key(tenant_id: Option<Uuid>) -> Result<i32> {
+    let conf = config::get();
+    if conf.encryption.backend.is_empty() {
+        return Err(anyhow!("Encryption is not enabled"));
+    }
+    if tenant_id.is_some() && !conf.encryption.per_tenant_keys {
+        return Err(anyhow!("Per-tenant encryption keys are not enabled"));
+    }
+
+    let provider = get_provider(&conf.encryption)?;
+    let dek = create_data_key(provider.as_ref(), tenant_id).await?;
+    activate_data_key(tenant_id).await?;
+
+    Ok(dek.id)
+}
+
+// Generates a new data encryption key for the given tenant, encrypted by the configured
+// backend. The key is not stored, this is up to the caller (e.g. within the transaction which
+// creates the tenant). After it has been stored, activate_data_key must be called.
+pub async fn new_data_key(
+    tenant_id: Option<Uuid>,
+) -> Result<data_encryption_key::DataEncryptionKey> {
+    let conf = config::get();
+    let provider = get_provider(&conf.encryption)?;
+    generate_data_key(provider.as_ref(), tenant_id).await
+}
+
+// Reloads the data keys after a data encryption key has been created, also on the other
+// instances.
+pub async fn activate_data_key(tenant_id: Option<Uuid>) -> Result<()> {
+    reload().await?;
+    cache::invalidate(
+        cache::Kind::DataEncryptionKeys,
+        &tenant_id.unwrap_or_default(),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn create_data_key(
+    provider: &(dyn KeyProvider + Sync + Send),
+    tenant_id: Option<Uuid>,
+) -> Result<data_encryption_key::DataEncryptionKey> {
+    let dek = generate_data_key(provider, tenant_id).await?;
+    Ok(data_encryption_key::create(dek).await?)
+}
+
+// The ID of the returned key is assigned by the database when storing the key.
+async fn generate_data_key(
+    provider: &(dyn KeyProvider + Sync + Send),
+    tenant_id: Option<Uuid>,
+) -> Result<data_encryption_key::DataEncryptionKey> {
+    let conf = config::get();
+
+    let mut key: [u8; 32] = [0; 32];
+    rand::rng().fill_bytes(&mut key);
+
+    let encrypted_key = provider
+        .encrypt(&key)
+        .await
+        .context("Encrypt data encryption key")?;
+
+    Ok(data_encryption_key::DataEncryptionKey {
+        id: 0,
+        created_at: Utc::now(),
+        backend: conf.encryption.backend.clone(),
+        encrypted_key,
+        tenant_id: tenant_id.map(|v| v.into()),
+    })
+}
+
+// Encrypts the given key using the active global data encryption key. In case encryption is
+// not enabled, the key is returned as-is.
+pub fn encrypt_key(key: &[u8; 16]) -> Result<Vec<u8>> {
+    encrypt_tenant_key(None, key)
+}
+
+// Encrypts the given key using the active data encryption key of the given tenant. When the
+// tenant does not have a data encryption key, the global data encryption key is used. In case
+// encryption is not enabled, the key is returned as-is.
+pub fn encrypt_tenant_key(tenant_id: Option<Uuid>, key: &[u8; 16]) -> Result<Vec<u8>> {
+    let data_keys = DATA_KEYS.read().unwrap();
+    let id = match tenant_id
+        .and_then(|v| data_keys.tenants.get(&v).cloned())
+        .or(data_keys.active)
+    {
+        Some(v) => v,
+        None => return Ok(key.to_vec()),
+    };
+    let dek = data_keys
+        .keys
+        .get(&id)
+        .ok_or_else(|| anyhow!("Data encryption key {} does not exist", id))?;
+
+    let mut out: Vec<u8> = vec![0; HEADER_LEN + 16 + 8];
+    out[0] = FORMAT_VERSION;
+    out[1..HEADER_LEN].copy_from_slice(&id.to_be_bytes());
+    KekAes256::from(*dek)
+        .wrap(key, &mut out[HEADER_LEN..])
+        .map_err(|e| anyhow!("Wrap key error: {}", e))?;
+
+    Ok(out)
+}
+
+// Decrypts the given key. Plaintext keys (16 bytes) are returned as-is, such that key material
+// which was stored before encryption was enabled can still be read.
+pub fn decrypt_key(b: &[u8]) -> Result<[u8; 16]> {
+    if b.len() == 16 {
+        let mut out: [u8; 16] = [0; 16];
+        out.copy_from_slice(b);
+        return Ok(out);
+    }
+
+    if b.len() != HEADER_LEN + 16 + 8 || b[0] != FORMAT_VERSION {
+        return Err(anyhow!("Invalid encrypted key format"));
+    }
+
+    let mut id_b: [u8; 4] = [0; 4];
+    id_b.copy_from_slice(&b[1..HEADER_LEN]);
+    let id = i32::from_be_bytes(id_b);
+
+    let data_keys = DATA_KEYS.read().unwrap();
+    let dek = data_keys
+        .keys
+        .get(&id)
+        .ok_or_else(|| anyhow!("Data encryption key {} does not exist", id))?;
+
+    let mut out: [u8; 16] = [0; 16];
+    KekAes256::from(*dek)
+        .unwrap(&b[HEADER_LEN..], &mut out)
+        .map_err(|e| anyhow!("Unwrap key error: {}", e))?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{storage, test};
+
+    #[tokio::test]
+    async fn test_encryption() {
+        let _guard = test::prepare().await;
+
+        // Encryption disabled.
+        let key: [u8; 16] = [1; 16];
+        assert_eq!(key.to_vec(), encrypt_key(&key).unwrap());
+        assert_eq!(key, decrypt_key(&key).unwrap());
+
+        // Local backend.
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "local".into();
+        conf.encryption.local.master_key =
+            "0102030405060708010203040506070801020304050607080102030405060708".into();
+        config::set(conf);
+        setup().await.unwrap();
+
+        let b = encrypt_key(&key).unwrap();
+        assert_eq!(HEADER_LEN + 24, b.len());
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 1], &b[..HEADER_LEN]);
+        assert_eq!(key, decrypt_key(&b).unwrap());
+
+        // Plaintext keys can still be read.
+        assert_eq!(key, decrypt_key(&key).unwrap());
+
+        // Rotate, keys encrypted with the previous data key can still be decrypted.
+        assert_eq!(2, rotate_data_key(None).await.unwrap());
+        let b2 = encrypt_key(&key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 2], &b2[..HEADER_LEN]);
+        assert_eq!(key, decrypt_key(&b).unwrap());
+        assert_eq!(key, decrypt_key(&b2).unwrap());
+
+        // Disable, the data keys must be cleared.
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "".into();
+        config::set(conf);
+        setup().await.unwrap();
+        assert_eq!(key.to_vec(), encrypt_key(&key).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_per_tenant_keys() {
+        let _guard = test::prepare().await;
+
+        // Tenant created before enabling per-tenant keys.
+        let t1 = storage::tenant::test::create_tenant().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "local".into();
+        conf.encryption.local.master_key =
+            "0102030405060708010203040506070801020304050607080102030405060708".into();
+        conf.encryption.per_tenant_keys = true;
+        config::set(conf);
+        setup().await.unwrap();
+
+        // The global data key and the data key of the tenant are created on setup.
+        let key: [u8; 16] = [1; 16];
+        let b = encrypt_key(&key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 1], &b[..HEADER_LEN]);
+
+        let b1 = encrypt_tenant_key(Some(*t1.id), &key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 2], &b1[..HEADER_LEN]);
+        assert_eq!(key, decrypt_key(&b1).unwrap());
+
+        // Tenant created after enabling per-tenant keys.
+        let t2 = storage::tenant::test::create_tenant().await;
+        let b2 = encrypt_tenant_key(Some(*t2.id), &key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 3], &b2[..HEADER_LEN]);
+
+        // Rotate the data key of the first tenant.
+        assert_eq!(4, rotate_data_key(Some(*t1.id)).await.unwrap());
+        let b1_rotated = encrypt_tenant_key(Some(*t1.id), &key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 4], &b1_rotated[..HEADER_LEN]);
+        assert_eq!(key, decrypt_key(&b1).unwrap());
+        assert_eq!(key, decrypt_key(&b1_rotated).unwrap());
+
+        // Unknown tenant, the global data key is used.
+        let b = encrypt_tenant_key(Some(Uuid::new_v4()), &key).unwrap();
+        assert_eq!(&[FORMAT_VERSION, 0, 0, 0, 1], &b[..HEADER_LEN]);
+
+        // Disable, the data keys must be cleared.
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "".into();
+        conf.encryption.per_tenant_keys = false;
+        config::set(conf);
+        setup().await.unwrap();
+        assert_eq!(key.to_vec(), encrypt_key(&key).unwrap());
+    }
+}
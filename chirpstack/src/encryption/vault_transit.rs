@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use super::KeyProvider;
+use crate::config;
+
+// Provider using the HashiCorp Vault transit secrets engine for encrypting the data
+// encryption keys.
+pub struct Provider {
+    client: Client,
+    address: String,
+    token: String,
+    mount: String,
+    key_name: String,
+}
+
+#[derive(Serialize)]
+struct EncryptRequest {
+    plaintext: String,
+}
+
+#[derive(Serialize)]
+struct DecryptRequest {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct EncryptResponse {
+    ciphertext: String,
+}
+
+#[derive(Deserialize)]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+impl Provider {
+    pub fn new(conf: &config::EncryptionVaultTransit) -> Provider {
+        Provider {
+            client: Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap(),
+            address: conf.address.trim_end_matches('/').to_string(),
+            token: conf.token.clone(),
+            mount: conf.mount.clone(),
+            key_name: conf.key_name.clone(),
+        }
+    }
+
+    fn url(&self, op: &str) -> String {
+        format!(
+            "{}/v1/{}/{}/{}",
+            self.address, self.mount, op, self.key_name
+        )
+    }
+}
+
+#[async_trait]
+impl KeyProvider for Provider {
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        trace!(key_name = %self.key_name, "Encrypting using Vault transit");
+
+        let resp: Response<EncryptResponse> = self
+            .client
+            .post(self.url("encrypt"))
+            .header("X-Vault-Token", &self.token)
+            .json(&EncryptRequest {
+                plaintext: general_purpose::STANDARD.encode(plaintext),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // The ciphertext is stored as returned by Vault (vault:v1:...).
+        Ok(resp.data.ciphertext.into_bytes())
+    }
+
+    async fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        trace!(key_name = %self.key_name, "Decrypting using Vault transit");
+
+        let resp: Response<DecryptResponse> = self
+            .client
+            .post(self.url("decrypt"))
+            .header("X-Vault-Token", &self.token)
+            .json(&DecryptRequest {
+                ciphertext: String::from_utf8(ciphertext.to_vec())?,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(general_purpose::STANDARD.decode(resp.data.plaintext)?)
+    }
+}
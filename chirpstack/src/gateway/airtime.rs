@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use tracing::trace;
+
+use crate::config;
+use crate::storage::metrics;
+use chirpstack_api::gw;
+use lrwn::EUI64;
+
+// Sub-band under which the airtime is tracked for frequencies outside the configured sub-bands.
+const OTHER_SUB_BAND: &str = "other";
+
+// Saves the received airtime of the uplink for each of the given gateways.
+pub async fn save_rx_airtime(
+    region_config_id: &str,
+    gateway_ids: &[EUI64],
+    tx_info: &gw::UplinkTxInfo,
+    size: usize,
+) -> Result<()> {
+    let record = match get_airtime_record(
+        "rx",
+        region_config_id,
+        tx_info.frequency,
+        tx_info.modulation.as_ref(),
+        size,
+    )? {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let records: Vec<(String, metrics::Record)> = gateway_ids
+        .iter()
+        .map(|gateway_id| (format!("gw:{}", gateway_id), record.clone()))
+        .collect();
+
+    metrics::save_batch(&records, &metrics::Aggregation::default_aggregations()).await
+}
+
+// Saves the transmitted airtime of the downlink for the given gateway.
+pub async fn save_tx_airtime(
+    region_config_id: &str,
+    gateway_id: &EUI64,
+    tx_info: &gw::DownlinkTxInfo,
+    size: usize,
+) -> Result<()> {
+    let record = match get_airtime_record(
+        "tx",
+        region_config_id,
+        tx_info.frequency,
+        tx_info.modulation.as_ref(),
+        size,
+    )? {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    metrics::save(
+        &format!("gw:{}", gateway_id),
+        &record,
+        &metrics::Aggregation::default_aggregations(),
+    )
+    .await
+}
+
+fn get_airtime_record(
+    prefix: &str,
+    region_config_id: &str,
+    frequency: u32,
+    modulation: Option<&gw::Modulation>,
+    size: usize,
+) -> Result<Option<metrics::Record>> {
+    let airtime = match modulation.and_then(|v| get_airtime(v, size)) {
+        Some(v) => v,
+        None => {
+            trace!(
+                frequency = frequency,
+                "Unable to calculate airtime, skipping"
+            );
+            return Ok(None);
+        }
+    };
+
+    let sub_band = get_sub_band(region_config_id, frequency)?;
+
+    let mut record = metrics::Record {
+        time: Local::now(),
+        kind: metrics::Kind::ABSOLUTE,
+        metrics: HashMap::new(),
+    };
+    record.metrics.insert(
+        format!("{}_airtime_{}", prefix, sub_band),
+        airtime.as_secs_f64() * 1000.0,
+    );
+
+    Ok(Some(record))
+}
+
+// Returns the name of the configured sub-band matching the given frequency.
+fn get_sub_band(region_config_id: &str, frequency: u32) -> Result<String> {
+    let gw_conf = config::get_region_gateway(region_config_id)?;
+
+    Ok(gw_conf
+        .sub_bands
+        .iter()
+        .find(|sb| frequency >= sb.frequency_min && frequency < sb.frequency_max)
+        .map(|sb| sb.name.clone())
+        .unwrap_or_else(|| OTHER_SUB_BAND.to_string()))
+}
+
+// Returns the airtime for the given modulation and PHYPayload size. None is returned when the
+// airtime can not be calculated (e.g. for LR-FHSS).
+pub fn get_airtime(modulation: &gw::Modulation, size: usize) -> Option<Duration> {
+    match modulation.parameters.as_ref()? {
+        gw::modulation::Parameters::Lora(v) => {
+            if v.spreading_factor == 0 || v.bandwidth == 0 {
+                return None;
+            }
+
+            let cr = match v.code_rate() {
+                gw::CodeRate::Cr46 | gw::CodeRate::CrLi46 => 2,
+                gw::CodeRate::Cr47 => 3,
+                gw::CodeRate::Cr48 | gw::CodeRate::CrLi48 => 4,
+                _ => 1,
+            };
+
+            Some(get_lora_airtime(
+                v.spreading_factor,
+                v.bandwidth,
+                cr,
+                if v.preamble == 0 { 8 } else { v.preamble },
+                !v.no_crc,
+                size,
+            ))
+        }
+        gw::modulation::Parameters::Fsk(v) => {
+            if v.datarate == 0 {
+                return None;
+            }
+
+            // Preamble (5), sync-word (3), length (1) and CRC (2) bytes.
+            let bits = (size + 11) * 8;
+            Some(Duration::from_secs_f64(bits as f64 / v.datarate as f64))
+        }
+        gw::modulation::Parameters::LrFhss(_) => None,
+    }
+}
+
+// Implements the LoRa time-on-air formula (explicit header), the cr argument is the
+// code-rate denominator - 4 (e.g. 1 for 4/5).
+fn get_lora_airtime(
+    sf: u32,
+    bandwidth: u32,
+    cr: u32,
+    preamble: u32,
+    crc: bool,
+    size: usize,
+) -> Duration {
+    let t_sym = (1u64 << sf) as f64 / bandwidth as f64;
+    let t_preamble = (preamble as f64 + 4.25) * t_sym;
+
+    // Low data-rate optimization is enabled when the symbol duration exceeds 16ms.
+    let de = if t_sym > 0.016 { 1.0 } else { 0.0 };
+    let crc = if crc { 1.0 } else { 0.0 };
+    let sf = sf as f64;
+
+    let payload_symb = 8.0
+        + (((8.0 * size as f64 - 4.0 * sf + 28.0 + 16.0 * crc) / (4.0 * (sf - 2.0 * de))).ceil()
+            * (cr as f64 + 4.0))
+            .max(0.0);
+
+    Duration::from_secs_f64(t_preamble + payload_symb * t_sym)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    fn lora(sf: u32, bandwidth: u32) -> gw::Modulation {
+        gw::Modulation {
+            parameters: Some(gw::modulation::Parameters::Lora(gw::LoraModulationInfo {
+                bandwidth,
+                spreading_factor: sf,
+                code_rate: gw::CodeRate::Cr45.into(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn test_get_airtime() {
+        struct Test {
+            name: String,
+            modulation: gw::Modulation,
+            size: usize,
+            expected_ms: Option<f64>,
+        }
+
+        let tests = vec![
+            Test {
+                name: "SF7 125kHz".into(),
+                modulation: lora(7, 125000),
+                size: 13,
+                expected_ms: Some(46.336),
+            },
+            Test {
+                name: "SF12 125kHz (low data-rate optimization)".into(),
+                modulation: lora(12, 125000),
+                size: 13,
+                expected_ms: Some(1155.072),
+            },
+            Test {
+                name: "SF9 125kHz".into(),
+                modulation: lora(9, 125000),
+                size: 51,
+                expected_ms: Some(328.704),
+            },
+            Test {
+                name: "FSK 50kbps".into(),
+                modulation: gw::Modulation {
+                    parameters: Some(gw::modulation::Parameters::Fsk(gw::FskModulationInfo {
+                        datarate: 50000,
+                        ..Default::default()
+                    })),
+                },
+                size: 14,
+                expected_ms: Some(4.0),
+            },
+            Test {
+                name: "LR-FHSS".into(),
+                modulation: gw::Modulation {
+                    parameters: Some(gw::modulation::Parameters::LrFhss(
+                        gw::LrFhssModulationInfo::default(),
+                    )),
+                },
+                size: 13,
+                expected_ms: None,
+            },
+        ];
+
+        for tst in &tests {
+            println!("> {}", tst.name);
+            let airtime = get_airtime(&tst.modulation, tst.size)
+                .map(|v| (v.as_secs_f64() * 1000000.0).round() / 1000.0);
+            assert_eq!(tst.expected_ms, airtime);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_sub_band() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.regions[0].gateway.sub_bands = vec![
+            config::GatewaySubBand {
+                name: "g1".into(),
+                frequency_min: 868000000,
+                frequency_max: 868600000,
+            },
+            config::GatewaySubBand {
+                name: "g3".into(),
+                frequency_min: 869400000,
+                frequency_max: 869650000,
+            },
+        ];
+        config::set(conf);
+
+        assert_eq!("g1", get_sub_band("eu868", 868100000).unwrap());
+        assert_eq!("g3", get_sub_band("eu868", 869525000).unwrap());
+        assert_eq!("other", get_sub_band("eu868", 868600000).unwrap());
+    }
+}
@@ -0,0 +1,727 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use chirpstack_api::gw;
+use lrwn::region::{DataRateModulation, FskDataRate, LoraDataRate, LrFhssDataRate, Region};
+use lrwn::EUI64;
+
+// Messages as defined by the LoRa Basics Station LNS protocol. See:
+// https://doc.sm.tc/station/tcproto.html
+
+#[derive(Deserialize)]
+pub struct MessageType {
+    pub msgtype: String,
+}
+
+#[derive(Deserialize)]
+pub struct RouterInfoRequest {
+    pub router: RouterId,
+}
+
+#[derive(Serialize)]
+pub struct RouterInfoResponse {
+    pub router: String,
+    pub muxs: String,
+    pub uri: String,
+}
+
+// The router ID is either sent as integer, EUI-64 or as id6 string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RouterId {
+    Int(u64),
+    Str(String),
+}
+
+impl RouterId {
+    pub fn to_eui64(&self) -> Result<EUI64> {
+        match self {
+            RouterId::Int(v) => Ok(EUI64::from_be_bytes(v.to_be_bytes())),
+            RouterId::Str(v) => parse_router_id(v),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Version {
+    pub station: String,
+    #[serde(default)]
+    pub firmware: Option<String>,
+    #[serde(default)]
+    pub package: Option<String>,
+    pub model: String,
+    pub protocol: u32,
+    #[serde(default)]
+    pub features: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpInfo {
+    pub rctx: i64,
+    pub xtime: i64,
+    #[serde(default)]
+    pub gpstime: i64,
+    pub rssi: f32,
+    pub snr: f32,
+    #[serde(default)]
+    pub rxtime: f64,
+}
+
+#[derive(Deserialize)]
+pub struct JoinRequest {
+    #[serde(rename = "MHdr")]
+    pub mhdr: u8,
+    #[serde(rename = "JoinEui")]
+    pub join_eui: String,
+    #[serde(rename = "DevEui")]
+    pub dev_eui: String,
+    #[serde(rename = "DevNonce")]
+    pub dev_nonce: u16,
+    #[serde(rename = "MIC")]
+    pub mic: i32,
+    #[serde(rename = "DR")]
+    pub dr: u8,
+    #[serde(rename = "Freq")]
+    pub freq: u32,
+    pub upinfo: UpInfo,
+}
+
+impl JoinRequest {
+    pub fn phy_payload(&self) -> Result<Vec<u8>> {
+        let mut b = vec![self.mhdr];
+        b.extend_from_slice(&parse_eui(&self.join_eui)?.to_le_bytes());
+        b.extend_from_slice(&parse_eui(&self.dev_eui)?.to_le_bytes());
+        b.extend_from_slice(&self.dev_nonce.to_le_bytes());
+        b.extend_from_slice(&self.mic.to_le_bytes());
+        Ok(b)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UplinkDataFrame {
+    #[serde(rename = "MHdr")]
+    pub mhdr: u8,
+    #[serde(rename = "DevAddr")]
+    pub dev_addr: i32,
+    #[serde(rename = "FCtrl")]
+    pub f_ctrl: u8,
+    #[serde(rename = "FCnt")]
+    pub f_cnt: u16,
+    #[serde(rename = "FOpts")]
+    pub f_opts: String,
+    #[serde(rename = "FPort")]
+    pub f_port: i16,
+    #[serde(rename = "FRMPayload")]
+    pub frm_payload: String,
+    #[serde(rename = "MIC")]
+    pub mic: i32,
+    #[serde(rename = "DR")]
+    pub dr: u8,
+    #[serde(rename = "Freq")]
+    pub freq: u32,
+    pub upinfo: UpInfo,
+}
+
+impl UplinkDataFrame {
+    pub fn phy_payload(&self) -> Result<Vec<u8>> {
+        let mut b = vec![self.mhdr];
+        b.extend_from_slice(&self.dev_addr.to_le_bytes());
+        b.push(self.f_ctrl);
+        b.extend_from_slice(&self.f_cnt.to_le_bytes());
+        b.extend_from_slice(&hex::decode(&self.f_opts)?);
+        if self.f_port >= 0 {
+            b.push(self.f_port as u8);
+            b.extend_from_slice(&hex::decode(&self.frm_payload)?);
+        }
+        b.extend_from_slice(&self.mic.to_le_bytes());
+        Ok(b)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DownlinkTransmitted {
+    pub diid: i64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TimeSync {
+    pub msgtype: String,
+    pub txtime: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpstime: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateInfoRequest {
+    pub router: RouterId,
+    #[serde(rename = "tcUri", default)]
+    pub tc_uri: String,
+}
+
+// Returns the CUPS update-info response. Only the LNS URI and credentials are updated, the
+// CUPS URI, CUPS credentials and firmware are left unchanged.
+pub fn get_update_info_response(tc_uri: Option<&str>, tc_cred: Option<&[u8]>) -> Result<Vec<u8>> {
+    let tc_uri = tc_uri.unwrap_or_default();
+    let tc_cred = tc_cred.unwrap_or_default();
+
+    let mut b = Vec::new();
+
+    // CUPS URI
+    b.push(0);
+
+    // TC URI
+    b.push(u8::try_from(tc_uri.len()).map_err(|_| anyhow!("tc_uri exceeds max. length"))?);
+    b.extend_from_slice(tc_uri.as_bytes());
+
+    // CUPS credentials
+    b.extend_from_slice(&0u16.to_le_bytes());
+
+    // TC credentials
+    b.extend_from_slice(
+        &u16::try_from(tc_cred.len())
+            .map_err(|_| anyhow!("tc_cred exceeds max. length"))?
+            .to_le_bytes(),
+    );
+    b.extend_from_slice(tc_cred);
+
+    // Signature
+    b.extend_from_slice(&0u32.to_le_bytes());
+
+    // Update data
+    b.extend_from_slice(&0u32.to_le_bytes());
+
+    Ok(b)
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct DownlinkMessage {
+    pub msgtype: String,
+    #[serde(rename = "DevEui")]
+    pub dev_eui: String,
+    #[serde(rename = "dC")]
+    pub device_class: u8,
+    pub diid: i64,
+    pub pdu: String,
+    pub priority: u8,
+    #[serde(rename = "RxDelay", skip_serializing_if = "Option::is_none")]
+    pub rx_delay: Option<u8>,
+    #[serde(rename = "RX1DR", skip_serializing_if = "Option::is_none")]
+    pub rx1_dr: Option<u8>,
+    #[serde(rename = "RX1Freq", skip_serializing_if = "Option::is_none")]
+    pub rx1_freq: Option<u32>,
+    #[serde(rename = "RX2DR", skip_serializing_if = "Option::is_none")]
+    pub rx2_dr: Option<u8>,
+    #[serde(rename = "RX2Freq", skip_serializing_if = "Option::is_none")]
+    pub rx2_freq: Option<u32>,
+    #[serde(rename = "DR", skip_serializing_if = "Option::is_none")]
+    pub dr: Option<u8>,
+    #[serde(rename = "Freq", skip_serializing_if = "Option::is_none")]
+    pub freq: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xtime: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rctx: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpstime: Option<i64>,
+}
+
+// Returns the UplinkFrame for the given PHYPayload and meta-data.
+pub fn get_uplink_frame(
+    region: &(dyn Region + Sync + Send),
+    gateway_id: &EUI64,
+    phy_payload: Vec<u8>,
+    dr: u8,
+    freq: u32,
+    upinfo: &UpInfo,
+) -> Result<gw::UplinkFrame> {
+    let modulation = match region.get_data_rate(dr)? {
+        DataRateModulation::Lora(v) => gw::modulation::Parameters::Lora(gw::LoraModulationInfo {
+            bandwidth: v.bandwidth,
+            spreading_factor: v.spreading_factor as u32,
+            code_rate: gw::CodeRate::from_str(&v.coding_rate)
+                .map_err(|e| anyhow!("{}", e))?
+                .into(),
+            ..Default::default()
+        }),
+        DataRateModulation::Fsk(v) => gw::modulation::Parameters::Fsk(gw::FskModulationInfo {
+            datarate: v.bitrate,
+            ..Default::default()
+        }),
+        DataRateModulation::LrFhss(_) => {
+            return Err(anyhow!("LR-FHSS is not supported"));
+        }
+    };
+
+    Ok(gw::UplinkFrame {
+        phy_payload,
+        tx_info: Some(gw::UplinkTxInfo {
+            frequency: freq,
+            modulation: Some(gw::Modulation {
+                parameters: Some(modulation),
+            }),
+        }),
+        rx_info: Some(gw::UplinkRxInfo {
+            gateway_id: gateway_id.to_string(),
+            uplink_id: rand::rng().random(),
+            gw_time: if upinfo.rxtime > 0.0 {
+                DateTime::<Utc>::from_timestamp_micros((upinfo.rxtime * 1_000_000.0) as i64)
+                    .map(|v| v.into())
+            } else {
+                None
+            },
+            time_since_gps_epoch: if upinfo.gpstime > 0 {
+                Some(Duration::from_micros(upinfo.gpstime as u64).into())
+            } else {
+                None
+            },
+            rssi: upinfo.rssi as i32,
+            snr: upinfo.snr,
+            context: get_context(upinfo.rctx, upinfo.xtime),
+            crc_status: gw::CrcStatus::CrcOk.into(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+// Returns the DownlinkMessage for the given DownlinkFrame. The first item is used for RX1 and the
+// second item (if set) for RX2 in case of a Class-A downlink.
+pub fn get_downlink_message(
+    region: &(dyn Region + Sync + Send),
+    df: &gw::DownlinkFrame,
+) -> Result<DownlinkMessage> {
+    let first = df
+        .items
+        .first()
+        .ok_or_else(|| anyhow!("DownlinkFrame does not contain any items"))?;
+    let tx_info = first
+        .tx_info
+        .as_ref()
+        .ok_or_else(|| anyhow!("tx_info must not be None"))?;
+
+    let mut msg = DownlinkMessage {
+        msgtype: "dnmsg".into(),
+        // The DevEUI is not known, but Basics Station will drop pending downlinks with the same
+        // DevEUI. Therefore a DevEUI is derived from the downlink ID.
+        dev_eui: format_eui(&EUI64::from_be_bytes((df.downlink_id as u64).to_be_bytes())),
+        diid: df.downlink_id as i64,
+        pdu: hex::encode(&first.phy_payload),
+        ..Default::default()
+    };
+
+    let timing = tx_info
+        .timing
+        .as_ref()
+        .and_then(|v| v.parameters.as_ref())
+        .ok_or_else(|| anyhow!("timing must not be None"))?;
+
+    match timing {
+        gw::timing::Parameters::Delay(v) => {
+            let (rctx, xtime) = parse_context(&tx_info.context)?;
+            let delay = v
+                .delay
+                .as_ref()
+                .map(|v| v.seconds)
+                .ok_or_else(|| anyhow!("delay must not be None"))?;
+
+            msg.device_class = 0;
+            msg.rx_delay = Some(delay as u8);
+            msg.rx1_dr = Some(get_downlink_dr(region, tx_info)?);
+            msg.rx1_freq = Some(tx_info.frequency);
+            msg.rctx = Some(rctx);
+            msg.xtime = Some(xtime);
+
+            if let Some(tx_info) = df.items.get(1).and_then(|v| v.tx_info.as_ref()) {
+                msg.rx2_dr = Some(get_downlink_dr(region, tx_info)?);
+                msg.rx2_freq = Some(tx_info.frequency);
+            }
+        }
+        gw::timing::Parameters::GpsEpoch(v) => {
+            let gps_time = v
+                .time_since_gps_epoch
+                .as_ref()
+                .ok_or_else(|| anyhow!("time_since_gps_epoch must not be None"))?;
+
+            msg.device_class = 1;
+            msg.dr = Some(get_downlink_dr(region, tx_info)?);
+            msg.freq = Some(tx_info.frequency);
+            msg.gpstime = Some(gps_time.seconds * 1_000_000 + (gps_time.nanos / 1_000) as i64);
+        }
+        gw::timing::Parameters::Immediately(_) => {
+            msg.device_class = 2;
+            msg.rx2_dr = Some(get_downlink_dr(region, tx_info)?);
+            msg.rx2_freq = Some(tx_info.frequency);
+        }
+    }
+
+    Ok(msg)
+}
+
+fn get_downlink_dr(
+    region: &(dyn Region + Sync + Send),
+    tx_info: &gw::DownlinkTxInfo,
+) -> Result<u8> {
+    let params = tx_info
+        .modulation
+        .as_ref()
+        .and_then(|v| v.parameters.as_ref())
+        .ok_or_else(|| anyhow!("modulation must not be None"))?;
+
+    let dr_modulation = match params {
+        gw::modulation::Parameters::Lora(v) => DataRateModulation::Lora(LoraDataRate {
+            spreading_factor: v.spreading_factor as u8,
+            bandwidth: v.bandwidth,
+            coding_rate: v.code_rate().into(),
+        }),
+        gw::modulation::Parameters::Fsk(v) => DataRateModulation::Fsk(FskDataRate {
+            bitrate: v.datarate,
+        }),
+        gw::modulation::Parameters::LrFhss(v) => DataRateModulation::LrFhss(LrFhssDataRate {
+            coding_rate: v.code_rate().into(),
+            occupied_channel_width: v.operating_channel_width,
+        }),
+    };
+
+    region.get_data_rate_index(false, &dr_modulation)
+}
+
+// The uplink context contains the rctx and xtime values, which are needed for scheduling a
+// Class-A downlink.
+fn get_context(rctx: i64, xtime: i64) -> Vec<u8> {
+    let mut b = Vec::with_capacity(16);
+    b.extend_from_slice(&rctx.to_be_bytes());
+    b.extend_from_slice(&xtime.to_be_bytes());
+    b
+}
+
+fn parse_context(b: &[u8]) -> Result<(i64, i64)> {
+    if b.len() != 16 {
+        return Err(anyhow!("Context must be exactly 16 bytes"));
+    }
+
+    let mut rctx: [u8; 8] = [0; 8];
+    let mut xtime: [u8; 8] = [0; 8];
+    rctx.copy_from_slice(&b[..8]);
+    xtime.copy_from_slice(&b[8..]);
+
+    Ok((i64::from_be_bytes(rctx), i64::from_be_bytes(xtime)))
+}
+
+// Parses the EUI-64 in the 01-02-03-04-05-06-07-08 format.
+fn parse_eui(s: &str) -> Result<EUI64> {
+    Ok(EUI64::from_str(&s.replace('-', ""))?)
+}
+
+fn format_eui(eui: &EUI64) -> String {
+    eui.to_be_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+// Parses the router ID, which is either an EUI-64 (with or without dashes) or an id6 string.
+pub fn parse_router_id(s: &str) -> Result<EUI64> {
+    if s.contains(':') {
+        parse_id6(s)
+    } else {
+        parse_eui(s)
+    }
+}
+
+// Parses the id6 format, which is similar to the IPv6 notation, e.g. ::1 or 1:2:3:4.
+fn parse_id6(s: &str) -> Result<EUI64> {
+    let parse_groups = |s: &str| -> Result<Vec<u16>> {
+        if s.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        s.split(':')
+            .map(|g| u16::from_str_radix(g, 16).map_err(|e| anyhow!("Invalid id6: {}", e)))
+            .collect()
+    };
+
+    let groups = match s.split_once("::") {
+        Some((head, tail)) => {
+            let mut head = parse_groups(head)?;
+            let tail = parse_groups(tail)?;
+            if head.len() + tail.len() > 3 {
+                return Err(anyhow!("Invalid id6: {}", s));
+            }
+            head.resize(4 - tail.len(), 0);
+            head.extend(tail);
+            head
+        }
+        None => parse_groups(s)?,
+    };
+
+    if groups.len() != 4 {
+        return Err(anyhow!("Invalid id6: {}", s));
+    }
+
+    let mut b: [u8; 8] = [0; 8];
+    for (i, g) in groups.iter().enumerate() {
+        b[i * 2..i * 2 + 2].copy_from_slice(&g.to_be_bytes());
+    }
+
+    Ok(EUI64::from_be_bytes(b))
+}
+
+pub fn format_id6(eui: &EUI64) -> String {
+    eui.to_be_bytes()
+        .chunks(2)
+        .map(|c| format!("{:x}", u16::from_be_bytes([c[0], c[1]])))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use lrwn::region::CommonName;
+
+    #[test]
+    fn test_router_id() {
+        let eui = EUI64::from_str("0102030405060708").unwrap();
+
+        assert_eq!(eui, parse_router_id("0102030405060708").unwrap());
+        assert_eq!(eui, parse_router_id("01-02-03-04-05-06-07-08").unwrap());
+        assert_eq!(eui, parse_router_id("102:304:506:708").unwrap());
+        assert_eq!(
+            EUI64::from_str("0000000000000001").unwrap(),
+            parse_router_id("::1").unwrap()
+        );
+        assert_eq!(
+            EUI64::from_str("0001000000000002").unwrap(),
+            parse_router_id("1::2").unwrap()
+        );
+        assert!(parse_router_id("1:2:3:4:5").is_err());
+        assert_eq!(eui, RouterId::Int(0x0102030405060708).to_eui64().unwrap());
+
+        assert_eq!("102:304:506:708", format_id6(&eui));
+        assert_eq!("01-02-03-04-05-06-07-08", format_eui(&eui));
+    }
+
+    #[test]
+    fn test_update_info_response() {
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            get_update_info_response(None, None).unwrap()
+        );
+
+        assert_eq!(
+            vec![
+                0, 6, b'w', b's', b':', b'/', b'/', b'x', 0, 0, 2, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0
+            ],
+            get_update_info_response(Some("ws://x"), Some(&[1, 2])).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_uplink_data_frame() {
+        let updf: UplinkDataFrame = serde_json::from_str(
+            r#"{
+                "msgtype": "updf",
+                "MHdr": 64,
+                "DevAddr": 16909060,
+                "FCtrl": 128,
+                "FCnt": 10,
+                "FOpts": "0203",
+                "FPort": 1,
+                "FRMPayload": "0506",
+                "MIC": -1,
+                "DR": 5,
+                "Freq": 868100000,
+                "upinfo": {
+                    "rctx": 0,
+                    "xtime": 12345,
+                    "gpstime": 0,
+                    "rssi": -50,
+                    "snr": 9.5,
+                    "rxtime": 1700000000.5
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                0x40, 0x04, 0x03, 0x02, 0x01, 0x80, 0x0a, 0x00, 0x02, 0x03, 0x01, 0x05, 0x06, 0xff,
+                0xff, 0xff, 0xff
+            ],
+            updf.phy_payload().unwrap()
+        );
+
+        let region = lrwn::region::get(CommonName::EU868, false, false);
+        let gateway_id = EUI64::from_str("0102030405060708").unwrap();
+        let uf = get_uplink_frame(
+            region.as_ref(),
+            &gateway_id,
+            updf.phy_payload().unwrap(),
+            updf.dr,
+            updf.freq,
+            &updf.upinfo,
+        )
+        .unwrap();
+
+        let tx_info = uf.tx_info.unwrap();
+        assert_eq!(868100000, tx_info.frequency);
+        assert_eq!(
+            Some(gw::Modulation {
+                parameters: Some(gw::modulation::Parameters::Lora(gw::LoraModulationInfo {
+                    bandwidth: 125000,
+                    spreading_factor: 7,
+                    code_rate: gw::CodeRate::Cr45.into(),
+                    ..Default::default()
+                }))
+            }),
+            tx_info.modulation
+        );
+
+        let rx_info = uf.rx_info.unwrap();
+        assert_eq!("0102030405060708", rx_info.gateway_id);
+        assert_eq!(-50, rx_info.rssi);
+        assert_eq!(9.5, rx_info.snr);
+        assert_eq!((0, 12345), parse_context(&rx_info.context).unwrap());
+    }
+
+    #[test]
+    fn test_join_request() {
+        let jreq: JoinRequest = serde_json::from_str(
+            r#"{
+                "msgtype": "jreq",
+                "MHdr": 0,
+                "JoinEui": "01-02-03-04-05-06-07-08",
+                "DevEui": "08-07-06-05-04-03-02-01",
+                "DevNonce": 258,
+                "MIC": 16909060,
+                "DR": 0,
+                "Freq": 868100000,
+                "upinfo": {
+                    "rctx": 0,
+                    "xtime": 12345,
+                    "rssi": -50,
+                    "snr": 9.5
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                0x00, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x01, 0x02, 0x03, 0x04, 0x05,
+                0x06, 0x07, 0x08, 0x02, 0x01, 0x04, 0x03, 0x02, 0x01
+            ],
+            jreq.phy_payload().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_downlink_message() {
+        let region = lrwn::region::get(CommonName::EU868, false, false);
+
+        let get_tx_info =
+            |frequency: u32, sf: u32, timing: gw::timing::Parameters| gw::DownlinkTxInfo {
+                frequency,
+                modulation: Some(gw::Modulation {
+                    parameters: Some(gw::modulation::Parameters::Lora(gw::LoraModulationInfo {
+                        bandwidth: 125000,
+                        spreading_factor: sf,
+                        code_rate: gw::CodeRate::Cr45.into(),
+                        polarization_inversion: true,
+                        ..Default::default()
+                    })),
+                }),
+                timing: Some(gw::Timing {
+                    parameters: Some(timing),
+                }),
+                context: get_context(1, 12345),
+                ..Default::default()
+            };
+
+        // Class-A
+        let df = gw::DownlinkFrame {
+            downlink_id: 123,
+            items: vec![
+                gw::DownlinkFrameItem {
+                    phy_payload: vec![1, 2, 3],
+                    tx_info: Some(get_tx_info(
+                        868100000,
+                        7,
+                        gw::timing::Parameters::Delay(gw::DelayTimingInfo {
+                            delay: Some(Duration::from_secs(1).into()),
+                        }),
+                    )),
+                    ..Default::default()
+                },
+                gw::DownlinkFrameItem {
+                    phy_payload: vec![1, 2, 3],
+                    tx_info: Some(get_tx_info(
+                        869525000,
+                        12,
+                        gw::timing::Parameters::Delay(gw::DelayTimingInfo {
+                            delay: Some(Duration::from_secs(2).into()),
+                        }),
+                    )),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            DownlinkMessage {
+                msgtype: "dnmsg".into(),
+                dev_eui: "00-00-00-00-00-00-00-7b".into(),
+                device_class: 0,
+                diid: 123,
+                pdu: "010203".into(),
+                priority: 0,
+                rx_delay: Some(1),
+                rx1_dr: Some(5),
+                rx1_freq: Some(868100000),
+                rx2_dr: Some(0),
+                rx2_freq: Some(869525000),
+                xtime: Some(12345),
+                rctx: Some(1),
+                ..Default::default()
+            },
+            get_downlink_message(region.as_ref(), &df).unwrap()
+        );
+
+        // Class-C
+        let df = gw::DownlinkFrame {
+            downlink_id: 123,
+            items: vec![gw::DownlinkFrameItem {
+                phy_payload: vec![1, 2, 3],
+                tx_info: Some(get_tx_info(
+                    869525000,
+                    12,
+                    gw::timing::Parameters::Immediately(gw::ImmediatelyTimingInfo {}),
+                )),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            DownlinkMessage {
+                msgtype: "dnmsg".into(),
+                dev_eui: "00-00-00-00-00-00-00-7b".into(),
+                device_class: 2,
+                diid: 123,
+                pdu: "010203".into(),
+                priority: 0,
+                rx2_dr: Some(0),
+                rx2_freq: Some(869525000),
+                ..Default::default()
+            },
+            get_downlink_message(region.as_ref(), &df).unwrap()
+        );
+    }
+}
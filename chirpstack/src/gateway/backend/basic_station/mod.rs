@@ -0,0 +1,886 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    http::{header, Extensions, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use rustls::{
+    server::{NoClientAuth, WebPkiClientVerifier},
+    ServerConfig,
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::interval;
+use tracing::{debug, error, info, warn};
+
+use super::GatewayBackend;
+use crate::config::{self, GatewayBackendBasicStation};
+use crate::gpstime::ToGpsTime;
+use crate::helpers::errors::PrintFullError;
+use crate::helpers::tasks::Tasks;
+use crate::helpers::tls::{get_root_certs, load_cert, load_key};
+use crate::monitoring::prometheus;
+use crate::{downlink, region, uplink};
+use chirpstack_api::gw;
+use lrwn::region::CommonName;
+use lrwn::EUI64;
+
+mod messages;
+mod router_config;
+mod tls;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct EventLabels {
+    event: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct CommandLabels {
+    command: String,
+}
+
+lazy_static! {
+    static ref EVENT_COUNTER: Family<EventLabels, Counter> = {
+        let counter = Family::<EventLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_basic_station_events",
+            "Number of events received",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref COMMAND_COUNTER: Family<CommandLabels, Counter> = {
+        let counter = Family::<CommandLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_basic_station_commands",
+            "Number of commands sent",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+}
+
+type Connections = Arc<RwLock<HashMap<EUI64, Connection>>>;
+
+struct Connection {
+    // The connection ID is used to make sure that a connection is not removed by a previous
+    // connection of the same gateway on disconnect.
+    id: u64,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Clone)]
+struct Handler {
+    region_config_id: String,
+    region_common_name: CommonName,
+    conf: GatewayBackendBasicStation,
+    tls: bool,
+    // Gateways authenticate using a client-certificate, of which the Common Name must match the
+    // gateway ID.
+    mtls: bool,
+    connections: Connections,
+}
+
+pub struct BasicStationBackend {
+    region_config_id: String,
+    connections: Connections,
+    server_handle: axum_server::Handle,
+    tasks: Tasks,
+}
+
+impl BasicStationBackend {
+    pub async fn new(
+        region_config_id: &str,
+        region_common_name: CommonName,
+        conf: &GatewayBackendBasicStation,
+    ) -> Result<BasicStationBackend> {
+        let addr: SocketAddr = conf.bind.parse()?;
+        let connections: Connections = Arc::new(RwLock::new(HashMap::new()));
+        let tls = !conf.tls_cert.is_empty() || !conf.tls_key.is_empty();
+        let mtls = tls && !conf.ca_cert.is_empty();
+
+        if conf.auth_token.is_empty() && !mtls {
+            return Err(anyhow!(
+                "Basics Station backend requires an auth_token or client-certificate authentication (tls_cert, tls_key and ca_cert)"
+            ));
+        }
+
+        let handler = Handler {
+            region_config_id: region_config_id.to_string(),
+            region_common_name,
+            conf: conf.clone(),
+            tls,
+            mtls,
+            connections: connections.clone(),
+        };
+
+        let mut app = Router::new()
+            .route("/router-info", get(router_info))
+            .route("/gateway/{router}", get(gateway));
+        if conf.cups.enabled {
+            app = app.route("/update-info", post(update_info));
+        }
+        let app = app.with_state(handler);
+
+        info!(region_id = %region_config_id, bind = %conf.bind, tls = tls, "Setting up Basics Station LNS endpoint");
+
+        let server_handle = axum_server::Handle::new();
+        let tasks = Tasks::default();
+
+        if tls {
+            let mut server_config = ServerConfig::builder()
+                .with_client_cert_verifier(if conf.ca_cert.is_empty() {
+                    Arc::new(NoClientAuth)
+                } else {
+                    let root_certs = get_root_certs(Some(conf.ca_cert.clone()))?;
+                    WebPkiClientVerifier::builder(root_certs.into()).build()?
+                })
+                .with_single_cert(
+                    load_cert(&conf.tls_cert).await?,
+                    load_key(&conf.tls_key).await?,
+                )?;
+            server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+            let server = axum_server::bind(addr)
+                .acceptor(tls::ClientCertificateAcceptor::new(
+                    axum_server::tls_rustls::RustlsAcceptor::new(
+                        axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(server_config)),
+                    ),
+                ))
+                .handle(server_handle.clone());
+
+            tasks.push(tokio::spawn({
+                let region_config_id = region_config_id.to_string();
+
+                async move {
+                    if let Err(e) = server.serve(app.into_make_service()).await {
+                        error!(region_id = %region_config_id, error = %e, "Basics Station LNS endpoint error");
+                    }
+                }
+            }));
+        } else {
+            let server = axum_server::bind(addr).handle(server_handle.clone());
+
+            tasks.push(tokio::spawn({
+                let region_config_id = region_config_id.to_string();
+
+                async move {
+                    if let Err(e) = server.serve(app.into_make_service()).await {
+                        error!(region_id = %region_config_id, error = %e, "Basics Station LNS endpoint error");
+                    }
+                }
+            }));
+        }
+
+        Ok(BasicStationBackend {
+            region_config_id: region_config_id.to_string(),
+            connections,
+            server_handle,
+            tasks,
+        })
+    }
+}
+
+#[async_trait]
+impl GatewayBackend for BasicStationBackend {
+    async fn send_downlink(&self, df: &gw::DownlinkFrame) -> Result<()> {
+        let gateway_id = EUI64::from_str(&df.gateway_id)?;
+        let region_conf = region::get(&self.region_config_id)?;
+        let msg = messages::get_downlink_message(&**region_conf, df)?;
+
+        COMMAND_COUNTER
+            .get_or_create(&CommandLabels {
+                command: "dnmsg".to_string(),
+            })
+            .inc();
+
+        info!(region_id = %self.region_config_id, gateway_id = %gateway_id, downlink_id = df.downlink_id, "Sending downlink to gateway");
+
+        let connections = self.connections.read().await;
+        let conn = connections
+            .get(&gateway_id)
+            .ok_or_else(|| anyhow!("Gateway is not connected, gateway_id: {}", gateway_id))?;
+        conn.tx
+            .send(serde_json::to_string(&msg)?)
+            .map_err(|_| anyhow!("Gateway connection closed, gateway_id: {}", gateway_id))?;
+
+        Ok(())
+    }
+
+    // The Basics Station is configured using the router_config message, which is sent after the
+    // gateway has connected.
+    async fn send_configuration(&self, _gw_conf: &gw::GatewayConfiguration) -> Result<()> {
+        Ok(())
+    }
+
+    // This closes the gateway connections and stops listening, such that the endpoint can be
+    // re-created using the same bind address.
+    async fn close(&self) -> Result<()> {
+        info!(region_id = %self.region_config_id, "Closing Basics Station gateway backend");
+        self.server_handle.shutdown();
+        self.tasks.abort().await;
+        Ok(())
+    }
+}
+
+impl Handler {
+    // Validates the auth token. In case no auth token is configured, the gateway must
+    // authenticate using a client-certificate, which is validated by authorize_gateway.
+    fn authorize(&self, headers: &HeaderMap) -> bool {
+        if self.conf.auth_token.is_empty() {
+            return self.mtls;
+        }
+
+        headers
+            .get(header::AUTHORIZATION)
+            .map(|v| bool::from(v.as_bytes().ct_eq(self.conf.auth_token.as_bytes())))
+            .unwrap_or(false)
+    }
+
+    // Validates that the Common Name of the client-certificate matches the gateway ID.
+    fn authorize_gateway(
+        &self,
+        client_cert: Option<&tls::ClientCertificate>,
+        gateway_id: &EUI64,
+    ) -> bool {
+        if !self.mtls {
+            return true;
+        }
+
+        client_cert
+            .and_then(|v| v.common_name.as_ref())
+            .and_then(|v| EUI64::from_str(v).ok())
+            .map(|v| v == *gateway_id)
+            .unwrap_or(false)
+    }
+
+    // Returns the router URI, either from the configuration or based on the Host header.
+    fn get_router_uri(&self, headers: &HeaderMap) -> String {
+        if !self.conf.router_uri.is_empty() {
+            return self.conf.router_uri.trim_end_matches('/').to_string();
+        }
+
+        let host = headers
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or(&self.conf.bind);
+
+        format!("{}://{}", if self.tls { "wss" } else { "ws" }, host)
+    }
+
+    async fn handle_router_info(
+        &self,
+        mut socket: WebSocket,
+        router_uri: String,
+        client_cert: Option<tls::ClientCertificate>,
+    ) {
+        let err = async {
+            let msg = match socket.recv().await {
+                Some(Ok(Message::Text(v))) => v,
+                Some(Ok(_)) => return Err(anyhow!("Expected text message")),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            };
+
+            let req: messages::RouterInfoRequest = serde_json::from_str(msg.as_str())?;
+            let gateway_id = req.router.to_eui64()?;
+
+            if !self.authorize_gateway(client_cert.as_ref(), &gateway_id) {
+                socket.send(Message::Close(None)).await?;
+                return Err(anyhow!(
+                    "Client-certificate does not match gateway ID, gateway_id: {}",
+                    gateway_id
+                ));
+            }
+
+            info!(region_id = %self.region_config_id, gateway_id = %gateway_id, "Router-info request received");
+
+            let resp = messages::RouterInfoResponse {
+                router: messages::format_id6(&gateway_id),
+                muxs: "::0".into(),
+                uri: format!("{}/gateway/{}", router_uri, gateway_id),
+            };
+            socket
+                .send(Message::Text(serde_json::to_string(&resp)?.into()))
+                .await?;
+            socket.send(Message::Close(None)).await?;
+
+            Ok::<(), anyhow::Error>(())
+        }
+        .await
+        .err();
+
+        if let Some(e) = err {
+            warn!(region_id = %self.region_config_id, error = %e.full(), "Handling router-info request failed");
+        }
+    }
+
+    async fn handle_connection(&self, gateway_id: EUI64, socket: WebSocket) {
+        let (mut sender, mut receiver) = socket.split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let conn_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+
+        self.connections
+            .write()
+            .await
+            .insert(gateway_id, Connection { id: conn_id, tx });
+
+        info!(region_id = %self.region_config_id, gateway_id = %gateway_id, "Gateway connected");
+
+        let mut ping_interval = interval(self.conf.ping_interval);
+        let mut stats_interval = interval(self.conf.stats_interval);
+        let mut stats = gw::GatewayStats::default();
+
+        loop {
+            tokio::select! {
+                msg = receiver.next() => match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        match self.handle_message(&gateway_id, text.as_str(), &mut stats) {
+                            Ok(Some(resp)) => {
+                                if let Err(e) = sender.send(Message::Text(resp.into())).await {
+                                    warn!(region_id = %self.region_config_id, gateway_id = %gateway_id, error = %e, "Sending message to gateway failed");
+                                    break;
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                error!(region_id = %self.region_config_id, gateway_id = %gateway_id, error = %e.full(), "Handling gateway message failed");
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        warn!(region_id = %self.region_config_id, gateway_id = %gateway_id, error = %e, "Reading message from gateway failed");
+                        break;
+                    }
+                },
+                Some(msg) = rx.recv() => {
+                    stats.tx_packets_received += 1;
+                    if let Err(e) = sender.send(Message::Text(msg.into())).await {
+                        warn!(region_id = %self.region_config_id, gateway_id = %gateway_id, error = %e, "Sending message to gateway failed");
+                        break;
+                    }
+                },
+                _ = ping_interval.tick() => {
+                    if let Err(e) = sender.send(Message::Ping(Bytes::new())).await {
+                        warn!(region_id = %self.region_config_id, gateway_id = %gateway_id, error = %e, "Sending ping to gateway failed");
+                        break;
+                    }
+                },
+                _ = stats_interval.tick() => {
+                    self.handle_stats(&gateway_id, std::mem::take(&mut stats));
+                },
+            }
+        }
+
+        let mut connections = self.connections.write().await;
+        if connections.get(&gateway_id).map(|c| c.id) == Some(conn_id) {
+            connections.remove(&gateway_id);
+        }
+
+        info!(region_id = %self.region_config_id, gateway_id = %gateway_id, "Gateway disconnected");
+    }
+
+    // Handles the given message and returns the response (if any) which must be sent to the
+    // gateway.
+    fn handle_message(
+        &self,
+        gateway_id: &EUI64,
+        msg: &str,
+        stats: &mut gw::GatewayStats,
+    ) -> Result<Option<String>> {
+        let msg_type: messages::MessageType = serde_json::from_str(msg)?;
+
+        EVENT_COUNTER
+            .get_or_create(&EventLabels {
+                event: msg_type.msgtype.clone(),
+            })
+            .inc();
+
+        match msg_type.msgtype.as_ref() {
+            "version" => {
+                let version: messages::Version = serde_json::from_str(msg)?;
+                info!(
+                    region_id = %self.region_config_id,
+                    gateway_id = %gateway_id,
+                    station = %version.station,
+                    firmware = ?version.firmware,
+                    package = ?version.package,
+                    model = %version.model,
+                    protocol = version.protocol,
+                    features = ?version.features,
+                    "Version message received, sending router_config"
+                );
+
+                let conf = config::get();
+                let gateway_channels = conf
+                    .regions
+                    .iter()
+                    .find(|r| r.id == self.region_config_id)
+                    .map(|r| r.gateway.channels.clone())
+                    .unwrap_or_default();
+                let region_conf = region::get(&self.region_config_id)?;
+
+                let router_config = router_config::get_router_config(
+                    self.region_common_name,
+                    &**region_conf,
+                    &gateway_channels,
+                )?;
+
+                Ok(Some(router_config.to_string()))
+            }
+            "jreq" => {
+                let jreq: messages::JoinRequest = serde_json::from_str(msg)?;
+                self.handle_uplink(
+                    gateway_id,
+                    jreq.phy_payload()?,
+                    jreq.dr,
+                    jreq.freq,
+                    &jreq.upinfo,
+                    stats,
+                )?;
+                Ok(None)
+            }
+            "updf" => {
+                let updf: messages::UplinkDataFrame = serde_json::from_str(msg)?;
+                self.handle_uplink(
+                    gateway_id,
+                    updf.phy_payload()?,
+                    updf.dr,
+                    updf.freq,
+                    &updf.upinfo,
+                    stats,
+                )?;
+                Ok(None)
+            }
+            "dntxed" => {
+                let dntxed: messages::DownlinkTransmitted = serde_json::from_str(msg)?;
+                stats.tx_packets_emitted += 1;
+
+                // Basics Station only reports successful transmissions, it does not report
+                // which of the downlink items was transmitted.
+                tokio::spawn(downlink::tx_ack::TxAck::handle(gw::DownlinkTxAck {
+                    gateway_id: gateway_id.to_string(),
+                    downlink_id: dntxed.diid as u32,
+                    items: vec![gw::DownlinkTxAckItem {
+                        status: gw::TxAckStatus::Ok.into(),
+                    }],
+                    ..Default::default()
+                }));
+
+                Ok(None)
+            }
+            "timesync" => {
+                let ts: messages::TimeSync = serde_json::from_str(msg)?;
+                let gps_time = Utc::now().to_gps_time();
+
+                Ok(Some(serde_json::to_string(&messages::TimeSync {
+                    msgtype: "timesync".into(),
+                    txtime: ts.txtime,
+                    gpstime: gps_time.num_microseconds(),
+                })?))
+            }
+            _ => {
+                debug!(region_id = %self.region_config_id, gateway_id = %gateway_id, msgtype = %msg_type.msgtype, "Ignoring message");
+                Ok(None)
+            }
+        }
+    }
+
+    fn handle_uplink(
+        &self,
+        gateway_id: &EUI64,
+        phy_payload: Vec<u8>,
+        dr: u8,
+        freq: u32,
+        upinfo: &messages::UpInfo,
+        stats: &mut gw::GatewayStats,
+    ) -> Result<()> {
+        stats.rx_packets_received += 1;
+        stats.rx_packets_received_ok += 1;
+
+        let region_conf = region::get(&self.region_config_id)?;
+        let mut uf =
+            messages::get_uplink_frame(&**region_conf, gateway_id, phy_payload, dr, freq, upinfo)?;
+        if let Some(rx_info) = &mut uf.rx_info {
+            rx_info.ns_time = Some(Utc::now().into());
+        }
+
+        tokio::spawn(uplink::deduplicate_uplink(
+            self.region_common_name,
+            self.region_config_id.clone(),
+            uf,
+        ));
+
+        Ok(())
+    }
+
+    fn handle_stats(&self, gateway_id: &EUI64, mut stats: gw::GatewayStats) {
+        stats.gateway_id = gateway_id.to_string();
+        stats.time = Some(Utc::now().into());
+        stats.metadata.insert(
+            "region_config_id".to_string(),
+            self.region_config_id.clone(),
+        );
+        stats.metadata.insert(
+            "region_common_name".to_string(),
+            self.region_common_name.to_string(),
+        );
+
+        tokio::spawn(uplink::stats::Stats::handle(stats));
+    }
+}
+
+async fn router_info(
+    State(handler): State<Handler>,
+    extensions: Extensions,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !handler.authorize(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let router_uri = handler.get_router_uri(&headers);
+    let client_cert = extensions.get::<tls::ClientCertificate>().cloned();
+    ws.on_upgrade(move |socket| async move {
+        handler
+            .handle_router_info(socket, router_uri, client_cert)
+            .await
+    })
+}
+
+async fn gateway(
+    State(handler): State<Handler>,
+    Path(router): Path<String>,
+    extensions: Extensions,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if !handler.authorize(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let gateway_id = match messages::parse_router_id(&router) {
+        Ok(v) => v,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    };
+
+    if !handler.authorize_gateway(extensions.get(), &gateway_id) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    ws.on_upgrade(move |socket| async move { handler.handle_connection(gateway_id, socket).await })
+}
+
+async fn update_info(
+    State(handler): State<Handler>,
+    extensions: Extensions,
+    headers: HeaderMap,
+    Json(req): Json<messages::UpdateInfoRequest>,
+) -> Response {
+    if !handler.authorize(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    match req.router.to_eui64() {
+        Ok(gateway_id) => {
+            if !handler.authorize_gateway(extensions.get(), &gateway_id) {
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+        }
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    }
+
+    match handle_update_info(&handler, &headers, &req).await {
+        Ok(v) => v.into_response(),
+        Err(e) => {
+            error!(region_id = %handler.region_config_id, error = %e.full(), "Handling update-info request failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+// Handles the CUPS update-info request. The LNS URI and credentials are only returned when the
+// LNS URI of the gateway does not match the configured LNS URI.
+async fn handle_update_info(
+    handler: &Handler,
+    headers: &HeaderMap,
+    req: &messages::UpdateInfoRequest,
+) -> Result<Vec<u8>> {
+    let gateway_id = req.router.to_eui64()?;
+    let tc_uri = if handler.conf.cups.tc_uri.is_empty() {
+        handler.get_router_uri(headers)
+    } else {
+        handler.conf.cups.tc_uri.trim_end_matches('/').to_string()
+    };
+
+    info!(region_id = %handler.region_config_id, gateway_id = %gateway_id, tc_uri = %req.tc_uri, "Update-info request received");
+
+    if req.tc_uri.trim_end_matches('/') == tc_uri {
+        return messages::get_update_info_response(None, None);
+    }
+
+    let mut tc_cred: Vec<u8> = Vec::new();
+    if !handler.conf.cups.tc_trust.is_empty() {
+        let certs = load_cert(&handler.conf.cups.tc_trust)
+            .await
+            .context("Load LNS trust certificate")?;
+        let cert = certs
+            .first()
+            .ok_or_else(|| anyhow!("LNS trust certificate is empty"))?;
+        tc_cred.extend_from_slice(cert.as_ref());
+
+        if !handler.conf.auth_token.is_empty() {
+            tc_cred.extend_from_slice(
+                format!("Authorization: {}\r\n", handler.conf.auth_token).as_bytes(),
+            );
+        }
+    }
+
+    messages::get_update_info_response(
+        Some(&tc_uri),
+        if tc_cred.is_empty() {
+            None
+        } else {
+            Some(&tc_cred)
+        },
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn get_handler(conf: GatewayBackendBasicStation, mtls: bool) -> Handler {
+        Handler {
+            region_config_id: "eu868".into(),
+            region_common_name: CommonName::EU868,
+            conf,
+            tls: mtls,
+            mtls,
+            connections: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    fn upgrade_request(
+        client: &reqwest::Client,
+        url: &str,
+        auth_token: Option<&str>,
+    ) -> reqwest::RequestBuilder {
+        let mut req = client
+            .get(url)
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==");
+        if let Some(v) = auth_token {
+            req = req.header(header::AUTHORIZATION, v);
+        }
+        req
+    }
+
+    #[test]
+    fn test_authorize() {
+        let handler = get_handler(
+            GatewayBackendBasicStation {
+                auth_token: "Bearer secret".into(),
+                ..Default::default()
+            },
+            false,
+        );
+
+        let mut headers = HeaderMap::new();
+        assert!(!handler.authorize(&headers));
+
+        headers.insert(header::AUTHORIZATION, "Bearer secre".parse().unwrap());
+        assert!(!handler.authorize(&headers));
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(handler.authorize(&headers));
+
+        // Without auth token, only client-certificate authentication is accepted.
+        let handler = get_handler(Default::default(), false);
+        assert!(!handler.authorize(&HeaderMap::new()));
+
+        let handler = get_handler(Default::default(), true);
+        assert!(handler.authorize(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_authorize_gateway() {
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let handler = get_handler(Default::default(), false);
+        assert!(handler.authorize_gateway(None, &gateway_id));
+
+        let handler = get_handler(Default::default(), true);
+        assert!(!handler.authorize_gateway(None, &gateway_id));
+        assert!(!handler.authorize_gateway(Some(&Default::default()), &gateway_id));
+        assert!(!handler.authorize_gateway(
+            Some(&tls::ClientCertificate {
+                common_name: Some("0807060504030201".into()),
+            }),
+            &gateway_id
+        ));
+        assert!(handler.authorize_gateway(
+            Some(&tls::ClientCertificate {
+                common_name: Some("0102030405060708".into()),
+            }),
+            &gateway_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_new_without_authentication() {
+        let out = BasicStationBackend::new(
+            "eu868",
+            CommonName::EU868,
+            &GatewayBackendBasicStation {
+                bind: "127.0.0.1:0".into(),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(out.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_auth_token() {
+        let backend = BasicStationBackend::new(
+            "eu868",
+            CommonName::EU868,
+            &GatewayBackendBasicStation {
+                bind: "127.0.0.1:0".into(),
+                auth_token: "Bearer secret".into(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let addr = backend.server_handle.listening().await.unwrap();
+        let url = format!("http://{}/gateway/0102030405060708", addr);
+        let client = reqwest::Client::builder().http1_only().build().unwrap();
+
+        let resp = upgrade_request(&client, &url, None).send().await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        let resp = upgrade_request(&client, &url, Some("Bearer invalid"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        let resp = upgrade_request(&client, &url, Some("Bearer secret"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::SWITCHING_PROTOCOLS, resp.status());
+
+        backend.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_client_certificate() {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = rcgen::KeyPair::generate().unwrap();
+        let server_cert = rcgen::CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .signed_by(&server_key, &ca_cert, &ca_key)
+            .unwrap();
+
+        let client_key = rcgen::KeyPair::generate().unwrap();
+        let mut client_params = rcgen::CertificateParams::new(vec![]).unwrap();
+        client_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "0102030405060708");
+        let client_cert = client_params
+            .signed_by(&client_key, &ca_cert, &ca_key)
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("chirpstack-bs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let write = |name: &str, pem: String| -> String {
+            let path = dir.join(name);
+            std::fs::write(&path, pem).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+
+        let backend = BasicStationBackend::new(
+            "eu868",
+            CommonName::EU868,
+            &GatewayBackendBasicStation {
+                bind: "127.0.0.1:0".into(),
+                tls_cert: write("server.pem", server_cert.pem()),
+                tls_key: write("server-key.pem", server_key.serialize_pem()),
+                ca_cert: write("ca.pem", ca_cert.pem()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let addr = backend.server_handle.listening().await.unwrap();
+
+        let client = reqwest::Client::builder()
+            .http1_only()
+            .resolve("localhost", addr)
+            .add_root_certificate(reqwest::Certificate::from_pem(ca_cert.pem().as_bytes()).unwrap())
+            .identity(
+                reqwest::Identity::from_pem(
+                    format!("{}{}", client_cert.pem(), client_key.serialize_pem()).as_bytes(),
+                )
+                .unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        // The Common Name of the client-certificate does not match the gateway ID.
+        let url = format!("https://localhost:{}/gateway/0807060504030201", addr.port());
+        let resp = upgrade_request(&client, &url, None).send().await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        let url = format!("https://localhost:{}/gateway/0102030405060708", addr.port());
+        let resp = upgrade_request(&client, &url, None).send().await.unwrap();
+        assert_eq!(StatusCode::SWITCHING_PROTOCOLS, resp.status());
+
+        // Without client-certificate, the TLS handshake fails.
+        let client = reqwest::Client::builder()
+            .http1_only()
+            .resolve("localhost", addr)
+            .add_root_certificate(reqwest::Certificate::from_pem(ca_cert.pem().as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        assert!(upgrade_request(&client, &url, None).send().await.is_err());
+
+        backend.close().await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,521 @@
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+
+use crate::config::{GatewayChannel, GatewayChannelModulation};
+use lrwn::region::{CommonName, DataRateModulation, Region};
+
+// Max. bandwidth (Hz) which can be covered by a single radio.
+const RADIO_BANDWIDTH: u32 = 925000;
+
+// Max. IF offset (Hz) of the LoRa std. and FSK channels.
+const MAX_IF: i64 = 500000;
+
+// Max. number of multi-SF channels per SX1301 board.
+const MAX_MULTI_SF_CHANNELS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Channel {
+    MultiSf {
+        frequency: u32,
+    },
+    LoraStd {
+        frequency: u32,
+        bandwidth: u32,
+        spreading_factor: u32,
+    },
+    Fsk {
+        frequency: u32,
+        bandwidth: u32,
+    },
+}
+
+impl Channel {
+    fn frequency(&self) -> u32 {
+        match self {
+            Channel::MultiSf { frequency } => *frequency,
+            Channel::LoraStd { frequency, .. } => *frequency,
+            Channel::Fsk { frequency, .. } => *frequency,
+        }
+    }
+
+    // Returns the lowest and highest frequency used by the channel.
+    fn edges(&self) -> (u32, u32) {
+        let bandwidth = match self {
+            Channel::MultiSf { .. } => 125000,
+            Channel::LoraStd { bandwidth, .. } => *bandwidth,
+            Channel::Fsk { bandwidth, .. } => *bandwidth,
+        };
+
+        (
+            self.frequency() - bandwidth / 2,
+            self.frequency() + bandwidth / 2,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Radio {
+    min: u32,
+    max: u32,
+}
+
+impl Radio {
+    // Returns true when the multi-SF channel fits within the bandwidth of the radio.
+    fn fits(&self, c: &Channel) -> bool {
+        let (min, max) = c.edges();
+        max.max(self.max) - min.min(self.min) <= RADIO_BANDWIDTH
+    }
+
+    // Returns true when the LoRa std. or FSK channel is within the IF range of the radio.
+    fn fits_if(&self, c: &Channel) -> bool {
+        (c.frequency() as i64 - self.center() as i64).abs() <= MAX_IF
+    }
+
+    fn add(&mut self, c: &Channel) {
+        let (min, max) = c.edges();
+        self.min = self.min.min(min);
+        self.max = self.max.max(max);
+    }
+
+    fn center(&self) -> u32 {
+        self.min + (self.max - self.min) / 2
+    }
+}
+
+#[derive(Default)]
+struct Board {
+    radios: Vec<Radio>,
+    multi_sf: Vec<(usize, Channel)>,
+    lora_std: Option<(usize, Channel)>,
+    fsk: Option<(usize, Channel)>,
+}
+
+impl Board {
+    // Adds the channel to the board. This returns false when the channel does not fit.
+    // Note that all multi-SF channels must be added first, as the radio frequencies must be
+    // known when adding the LoRa std. and FSK channels.
+    fn add(&mut self, c: &Channel) -> bool {
+        match c {
+            Channel::MultiSf { .. } => {
+                if self.multi_sf.len() >= MAX_MULTI_SF_CHANNELS {
+                    return false;
+                }
+
+                let radio = match self.radios.iter().position(|r| r.fits(c)) {
+                    Some(v) => v,
+                    None => match self.add_radio(c) {
+                        Some(v) => v,
+                        None => return false,
+                    },
+                };
+
+                self.radios[radio].add(c);
+                self.multi_sf.push((radio, c.clone()));
+            }
+            Channel::LoraStd { .. } | Channel::Fsk { .. } => {
+                let taken = match c {
+                    Channel::LoraStd { .. } => self.lora_std.is_some(),
+                    _ => self.fsk.is_some(),
+                };
+                if taken {
+                    return false;
+                }
+
+                let radio = match self.radios.iter().position(|r| r.fits_if(c)) {
+                    Some(v) => v,
+                    None => match self.add_radio(c) {
+                        Some(v) => v,
+                        None => return false,
+                    },
+                };
+
+                match c {
+                    Channel::LoraStd { .. } => self.lora_std = Some((radio, c.clone())),
+                    _ => self.fsk = Some((radio, c.clone())),
+                }
+            }
+        }
+
+        true
+    }
+
+    // Adds a new radio for the given channel (max. two radios per board).
+    fn add_radio(&mut self, c: &Channel) -> Option<usize> {
+        if self.radios.len() >= 2 {
+            return None;
+        }
+
+        let (min, max) = c.edges();
+        self.radios.push(Radio { min, max });
+        Some(self.radios.len() - 1)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut out = Map::new();
+
+        for (i, r) in self.radios.iter().enumerate() {
+            out.insert(
+                format!("radio_{}", i),
+                json!({"enable": true, "freq": r.center()}),
+            );
+        }
+
+        for (i, (radio, c)) in self.multi_sf.iter().enumerate() {
+            out.insert(
+                format!("chan_multiSF_{}", i),
+                json!({
+                    "enable": true,
+                    "radio": radio,
+                    "if": self.get_if(*radio, c),
+                }),
+            );
+        }
+
+        if let Some((radio, c)) = &self.lora_std {
+            if let Channel::LoraStd {
+                bandwidth,
+                spreading_factor,
+                ..
+            } = c
+            {
+                out.insert(
+                    "chan_Lora_std".into(),
+                    json!({
+                        "enable": true,
+                        "radio": radio,
+                        "if": self.get_if(*radio, c),
+                        "bandwidth": bandwidth,
+                        "spread_factor": spreading_factor,
+                    }),
+                );
+            }
+        }
+
+        if let Some((radio, c)) = &self.fsk {
+            out.insert(
+                "chan_FSK".into(),
+                json!({
+                    "enable": true,
+                    "radio": radio,
+                    "if": self.get_if(*radio, c),
+                }),
+            );
+        }
+
+        Value::Object(out)
+    }
+
+    fn get_if(&self, radio: usize, c: &Channel) -> i64 {
+        c.frequency() as i64 - self.radios[radio].center() as i64
+    }
+}
+
+// Returns the router_config message for the given region.
+pub fn get_router_config(
+    common_name: CommonName,
+    region: &(dyn Region + Sync + Send),
+    gateway_channels: &[GatewayChannel],
+) -> Result<Value> {
+    let (region_name, freq_range) = get_region_name_and_freq_range(common_name)?;
+
+    let channels = if gateway_channels.is_empty() {
+        get_region_channels(region)?
+    } else {
+        get_gateway_channels(gateway_channels)?
+    };
+    let sx1301_conf = get_sx1301_conf(channels)?;
+
+    Ok(json!({
+        "msgtype": "router_config",
+        "NetID": null,
+        "JoinEui": null,
+        "region": region_name,
+        "hwspec": format!("sx1301/{}", sx1301_conf.len()),
+        "freq_range": freq_range,
+        "DRs": get_data_rates(region),
+        "sx1301_conf": sx1301_conf,
+        // Duty-cycle, dwell-time and LBT are handled by ChirpStack.
+        "nocca": true,
+        "nodc": true,
+        "nodwell": true,
+    }))
+}
+
+fn get_region_name_and_freq_range(common_name: CommonName) -> Result<(&'static str, [u32; 2])> {
+    Ok(match common_name {
+        CommonName::EU868 => ("EU863", [863000000, 870000000]),
+        CommonName::US915 => ("US902", [902000000, 928000000]),
+        CommonName::AS923 => ("AS923-1", [915000000, 928000000]),
+        CommonName::AS923_2 => ("AS923-2", [915000000, 928000000]),
+        CommonName::AS923_3 => ("AS923-3", [915000000, 928000000]),
+        CommonName::AS923_4 => ("AS923-4", [917000000, 920000000]),
+        CommonName::AU915 => ("AU915", [915000000, 928000000]),
+        CommonName::CN470 => ("CN470", [470000000, 510000000]),
+        CommonName::IN865 => ("IN865", [865000000, 867000000]),
+        CommonName::KR920 => ("KR920", [920900000, 923300000]),
+        _ => {
+            return Err(anyhow!(
+                "Region {} is not supported by Basics Station",
+                common_name
+            ));
+        }
+    })
+}
+
+// Returns the data-rates as [SF, BW (kHz), DNONLY]. FSK is encoded as SF 0 and undefined
+// data-rates as SF -1.
+fn get_data_rates(region: &(dyn Region + Sync + Send)) -> Vec<[i32; 3]> {
+    (0..16)
+        .map(|dr| match region.get_data_rate(dr) {
+            Ok(m) => {
+                let dnonly = match region.get_data_rate_index(true, &m) {
+                    Ok(v) => (v != dr) as i32,
+                    Err(_) => 1,
+                };
+
+                match m {
+                    DataRateModulation::Lora(v) => [
+                        v.spreading_factor as i32,
+                        (v.bandwidth / 1000) as i32,
+                        dnonly,
+                    ],
+                    DataRateModulation::Fsk(_) => [0, 0, dnonly],
+                    DataRateModulation::LrFhss(_) => [-1, 0, 0],
+                }
+            }
+            Err(_) => [-1, 0, 0],
+        })
+        .collect()
+}
+
+// Returns the channels based on the enabled uplink channels of the region.
+fn get_region_channels(region: &(dyn Region + Sync + Send)) -> Result<Vec<Channel>> {
+    let mut out = Vec::new();
+
+    for i in region.get_enabled_uplink_channel_indices() {
+        let ch = region.get_uplink_channel(i)?;
+        let mut multi_sf = false;
+
+        for dr in ch.min_dr..=ch.max_dr {
+            match region.get_data_rate(dr)? {
+                DataRateModulation::Lora(v) if v.bandwidth == 125000 => {
+                    multi_sf = true;
+                }
+                DataRateModulation::Lora(v) => {
+                    out.push(Channel::LoraStd {
+                        frequency: ch.frequency,
+                        bandwidth: v.bandwidth,
+                        spreading_factor: v.spreading_factor as u32,
+                    });
+                }
+                DataRateModulation::Fsk(v) => {
+                    out.push(Channel::Fsk {
+                        frequency: ch.frequency,
+                        bandwidth: 125000.max(v.bitrate * 2),
+                    });
+                }
+                DataRateModulation::LrFhss(_) => {}
+            }
+        }
+
+        if multi_sf {
+            out.push(Channel::MultiSf {
+                frequency: ch.frequency,
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+// Returns the channels based on the gateway channels configuration of the region.
+fn get_gateway_channels(gateway_channels: &[GatewayChannel]) -> Result<Vec<Channel>> {
+    gateway_channels
+        .iter()
+        .map(|c| {
+            Ok(match c.modulation {
+                GatewayChannelModulation::LORA => {
+                    if c.spreading_factors.len() == 1 && c.bandwidth != 125000 {
+                        Channel::LoraStd {
+                            frequency: c.frequency,
+                            bandwidth: c.bandwidth,
+                            spreading_factor: c.spreading_factors[0],
+                        }
+                    } else if c.bandwidth == 125000 {
+                        Channel::MultiSf {
+                            frequency: c.frequency,
+                        }
+                    } else {
+                        return Err(anyhow!(
+                            "Unsupported LoRa channel configuration, frequency: {}",
+                            c.frequency
+                        ));
+                    }
+                }
+                GatewayChannelModulation::FSK => Channel::Fsk {
+                    frequency: c.frequency,
+                    bandwidth: c.bandwidth,
+                },
+            })
+        })
+        .collect()
+}
+
+// Returns the SX1301 configuration for the given channels. The multi-SF channels are allocated
+// first in order of frequency, followed by the LoRa std. and FSK channels. A new board is added
+// when a channel does not fit any of the existing boards.
+fn get_sx1301_conf(mut channels: Vec<Channel>) -> Result<Vec<Value>> {
+    if channels.is_empty() {
+        return Err(anyhow!("No channels configured"));
+    }
+
+    channels.sort_by_key(|c| (!matches!(c, Channel::MultiSf { .. }), c.frequency()));
+    channels.dedup();
+
+    let mut boards: Vec<Board> = vec![Board::default()];
+
+    for c in &channels {
+        if boards.iter_mut().any(|b| b.add(c)) {
+            continue;
+        }
+
+        let mut b = Board::default();
+        if !b.add(c) {
+            return Err(anyhow!(
+                "Channel can not be allocated, frequency: {}",
+                c.frequency()
+            ));
+        }
+        boards.push(b);
+    }
+
+    Ok(boards.iter().map(|b| b.to_json()).collect())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_data_rates() {
+        let region = lrwn::region::get(CommonName::EU868, false, false);
+        let drs = get_data_rates(region.as_ref());
+
+        assert_eq!(16, drs.len());
+        assert_eq!([12, 125, 0], drs[0]);
+        assert_eq!([7, 125, 0], drs[5]);
+        assert_eq!([7, 250, 0], drs[6]);
+        assert_eq!([0, 0, 0], drs[7]);
+        assert_eq!([-1, 0, 0], drs[15]);
+
+        let region = lrwn::region::get(CommonName::US915, false, false);
+        let drs = get_data_rates(region.as_ref());
+
+        assert_eq!([10, 125, 0], drs[0]);
+        assert_eq!([8, 500, 0], drs[4]);
+        assert_eq!([-1, 0, 0], drs[7]);
+        assert_eq!([12, 500, 1], drs[8]);
+        assert_eq!([7, 500, 1], drs[13]);
+    }
+
+    #[test]
+    fn test_get_router_config_eu868() {
+        let region = lrwn::region::get(CommonName::EU868, false, false);
+        let rc = get_router_config(CommonName::EU868, region.as_ref(), &[]).unwrap();
+
+        assert_eq!("EU863", rc["region"]);
+        assert_eq!("sx1301/1", rc["hwspec"]);
+        assert_eq!(json!([863000000, 870000000]), rc["freq_range"]);
+        assert_eq!(
+            json!([{
+                "radio_0": {"enable": true, "freq": 868300000},
+                "chan_multiSF_0": {"enable": true, "radio": 0, "if": -200000},
+                "chan_multiSF_1": {"enable": true, "radio": 0, "if": 0},
+                "chan_multiSF_2": {"enable": true, "radio": 0, "if": 200000},
+            }]),
+            rc["sx1301_conf"]
+        );
+    }
+
+    #[test]
+    fn test_get_router_config_gateway_channels() {
+        let region = lrwn::region::get(CommonName::EU868, false, false);
+        let mut channels: Vec<GatewayChannel> = [
+            868100000, 868300000, 868500000, 867100000, 867300000, 867500000, 867700000, 867900000,
+        ]
+        .iter()
+        .map(|f| GatewayChannel {
+            frequency: *f,
+            bandwidth: 125000,
+            modulation: GatewayChannelModulation::LORA,
+            spreading_factors: vec![7, 8, 9, 10, 11, 12],
+            ..Default::default()
+        })
+        .collect();
+        channels.push(GatewayChannel {
+            frequency: 868300000,
+            bandwidth: 250000,
+            modulation: GatewayChannelModulation::LORA,
+            spreading_factors: vec![7],
+            ..Default::default()
+        });
+        channels.push(GatewayChannel {
+            frequency: 868800000,
+            bandwidth: 125000,
+            modulation: GatewayChannelModulation::FSK,
+            datarate: 50000,
+            ..Default::default()
+        });
+
+        let rc = get_router_config(CommonName::EU868, region.as_ref(), &channels).unwrap();
+        assert_eq!("sx1301/1", rc["hwspec"]);
+        assert_eq!(
+            json!([{
+                "radio_0": {"enable": true, "freq": 867500000},
+                "radio_1": {"enable": true, "freq": 868300000},
+                "chan_multiSF_0": {"enable": true, "radio": 0, "if": -400000},
+                "chan_multiSF_1": {"enable": true, "radio": 0, "if": -200000},
+                "chan_multiSF_2": {"enable": true, "radio": 0, "if": 0},
+                "chan_multiSF_3": {"enable": true, "radio": 0, "if": 200000},
+                "chan_multiSF_4": {"enable": true, "radio": 0, "if": 400000},
+                "chan_multiSF_5": {"enable": true, "radio": 1, "if": -200000},
+                "chan_multiSF_6": {"enable": true, "radio": 1, "if": 0},
+                "chan_multiSF_7": {"enable": true, "radio": 1, "if": 200000},
+                "chan_Lora_std": {"enable": true, "radio": 1, "if": 0, "bandwidth": 250000, "spread_factor": 7},
+                "chan_FSK": {"enable": true, "radio": 1, "if": 500000},
+            }]),
+            rc["sx1301_conf"]
+        );
+    }
+
+    #[test]
+    fn test_get_router_config_us915() {
+        let mut region = lrwn::region::get(CommonName::US915, false, false);
+        for i in region.get_enabled_uplink_channel_indices() {
+            region.disable_uplink_channel_index(i).unwrap();
+        }
+        for i in (8..16).chain([65]) {
+            region.enable_uplink_channel_index(i).unwrap();
+        }
+
+        let rc = get_router_config(CommonName::US915, region.as_ref(), &[]).unwrap();
+        assert_eq!("US902", rc["region"]);
+        assert_eq!("sx1301/1", rc["hwspec"]);
+        assert_eq!(
+            json!([{
+                "radio_0": {"enable": true, "freq": 904300000},
+                "radio_1": {"enable": true, "freq": 905100000},
+                "chan_multiSF_0": {"enable": true, "radio": 0, "if": -400000},
+                "chan_multiSF_1": {"enable": true, "radio": 0, "if": -200000},
+                "chan_multiSF_2": {"enable": true, "radio": 0, "if": 0},
+                "chan_multiSF_3": {"enable": true, "radio": 0, "if": 200000},
+                "chan_multiSF_4": {"enable": true, "radio": 0, "if": 400000},
+                "chan_multiSF_5": {"enable": true, "radio": 1, "if": -200000},
+                "chan_multiSF_6": {"enable": true, "radio": 1, "if": 0},
+                "chan_multiSF_7": {"enable": true, "radio": 1, "if": 200000},
+                "chan_Lora_std": {"enable": true, "radio": 0, "if": 300000, "bandwidth": 500000, "spread_factor": 8},
+            }]),
+            rc["sx1301_conf"]
+        );
+    }
+}
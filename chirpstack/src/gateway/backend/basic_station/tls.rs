@@ -0,0 +1,72 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+
+use anyhow::Result;
+use axum::middleware::AddExtension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::RustlsAcceptor;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+
+// Client-certificate presented by the gateway during the TLS handshake. This is added as
+// extension to each request of the connection.
+#[derive(Clone, Default)]
+pub struct ClientCertificate {
+    pub common_name: Option<String>,
+}
+
+// Acceptor wrapping the RustlsAcceptor, which exposes the client-certificate of the connection
+// to the request handlers.
+#[derive(Clone)]
+pub struct ClientCertificateAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertificateAcceptor {
+    pub fn new(inner: RustlsAcceptor) -> Self {
+        ClientCertificateAcceptor { inner }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertificateAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, ClientCertificate>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let (stream, service) = inner.accept(stream, service).await?;
+            let common_name = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| get_common_name(cert.as_ref()).ok());
+
+            Ok((
+                stream,
+                axum::Extension(ClientCertificate { common_name }).layer(service),
+            ))
+        })
+    }
+}
+
+fn get_common_name(der: &[u8]) -> Result<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|v| v.as_str().ok())
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("Client-certificate does not contain a Common Name"));
+    common_name
+}
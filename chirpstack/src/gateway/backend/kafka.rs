@@ -0,0 +1,336 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use handlebars::Handlebars;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prost::Message;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, Headers, Message as KafkaMessage, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde::Serialize;
+use tokio::time::sleep;
+use tracing::{error, info, trace};
+
+use super::{gateway_is_json, handle_event, Event, GatewayBackend};
+use crate::config::GatewayBackendKafka;
+use crate::helpers::tasks::Tasks;
+use crate::monitoring::prometheus;
+use lrwn::region::CommonName;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct EventLabels {
+    event: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct CommandLabels {
+    command: String,
+}
+
+lazy_static! {
+    static ref EVENT_COUNTER: Family<EventLabels, Counter> = {
+        let counter = Family::<EventLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_kafka_events",
+            "Number of events received",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref COMMAND_COUNTER: Family<CommandLabels, Counter> = {
+        let counter = Family::<CommandLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_kafka_commands",
+            "Number of commands sent",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+pub struct KafkaBackend<'a> {
+    producer: FutureProducer,
+    templates: Handlebars<'a>,
+    region_config_id: String,
+    tasks: Tasks,
+}
+
+#[derive(Serialize)]
+struct CommandTopicContext {
+    pub gateway_id: String,
+    pub command: String,
+}
+
+impl<'a> KafkaBackend<'a> {
+    pub fn new(
+        region_config_id: &str,
+        region_common_name: CommonName,
+        conf: &GatewayBackendKafka,
+    ) -> Result<KafkaBackend<'a>> {
+        // topic templates
+        let mut templates = Handlebars::new();
+        templates.register_escape_fn(handlebars::no_escape);
+        templates.register_template_string("command_topic", &conf.command_topic)?;
+
+        let producer: FutureProducer = get_client_config(conf)?
+            .set("message.timeout.ms", "5000")
+            .set("allow.auto.create.topics", "true")
+            .create()?;
+
+        let consumer: StreamConsumer = get_client_config(conf)?
+            .set("group.id", &conf.consumer_group)
+            .set("enable.auto.commit", "true")
+            .set("auto.offset.reset", "latest")
+            .create()?;
+
+        let topics: Vec<&str> = conf.event_topics.iter().map(|v| v.as_str()).collect();
+        consumer.subscribe(&topics)?;
+
+        info!(region_id = %region_config_id, brokers = %conf.brokers.join(","), consumer_group = %conf.consumer_group, event_topics = ?conf.event_topics, "Consuming gateway events from Kafka");
+
+        // Consumer loop
+        let tasks = Tasks::default();
+        tasks.push(tokio::spawn({
+            let region_config_id = region_config_id.to_string();
+
+            async move {
+                loop {
+                    match consumer.recv().await {
+                        Ok(m) => message_callback(&region_config_id, region_common_name, &m),
+                        Err(e) => {
+                            error!(region_id = %region_config_id, error = %e, "Kafka consumer error");
+                            sleep(Duration::from_secs(1)).await
+                        }
+                    }
+                }
+            }
+        }));
+
+        Ok(KafkaBackend {
+            producer,
+            templates,
+            region_config_id: region_config_id.to_string(),
+            tasks,
+        })
+    }
+
+    fn get_command_topic(&self, gateway_id: &str, command: &str) -> Result<String> {
+        Ok(self.templates.render(
+            "command_topic",
+            &CommandTopicContext {
+                gateway_id: gateway_id.to_string(),
+                command: command.to_string(),
+            },
+        )?)
+    }
+
+    async fn publish_command(&self, gateway_id: &str, command: &str, b: &[u8]) -> Result<()> {
+        COMMAND_COUNTER
+            .get_or_create(&CommandLabels {
+                command: command.to_string(),
+            })
+            .inc();
+        let topic = self.get_command_topic(gateway_id, command)?;
+
+        info!(region_id = %self.region_config_id, gateway_id = %gateway_id, topic = %topic, command = %command, "Sending command to gateway");
+
+        let res = self
+            .producer
+            .send(
+                FutureRecord::to(&topic)
+                    .key(gateway_id)
+                    .headers(OwnedHeaders::new().insert(Header {
+                        key: "command",
+                        value: Some(command),
+                    }))
+                    .payload(b),
+                Duration::from_secs(0),
+            )
+            .await;
+
+        if let Err(e) = res {
+            error!(error = ?e, "Publishing command error");
+            return Err(anyhow!("{:?}", e));
+        }
+
+        trace!("Message published");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GatewayBackend for KafkaBackend<'_> {
+    async fn send_downlink(&self, df: &chirpstack_api::gw::DownlinkFrame) -> Result<()> {
+        let b = match gateway_is_json(&df.gateway_id) {
+            true => serde_json::to_vec(&df)?,
+            false => df.encode_to_vec(),
+        };
+
+        self.publish_command(&df.gateway_id, "down", &b).await
+    }
+
+    async fn send_configuration(
+        &self,
+        gw_conf: &chirpstack_api::gw::GatewayConfiguration,
+    ) -> Result<()> {
+        let b = match gateway_is_json(&gw_conf.gateway_id) {
+            true => serde_json::to_vec(&gw_conf)?,
+            false => gw_conf.encode_to_vec(),
+        };
+
+        self.publish_command(&gw_conf.gateway_id, "config", &b)
+            .await
+    }
+
+    async fn close(&self) -> Result<()> {
+        info!(region_id = %self.region_config_id, "Closing Kafka gateway backend");
+        self.tasks.abort().await;
+        Ok(())
+    }
+}
+
+fn message_callback(region_config_id: &str, region_common_name: CommonName, m: &BorrowedMessage) {
+    let topic = m.topic();
+
+    let err = || -> Result<()> {
+        let event_header = m.headers().and_then(|headers| {
+            headers
+                .iter()
+                .find(|h| h.key == "event")
+                .and_then(|h| h.value)
+        });
+        let event = get_event_type(event_header, topic)?;
+        info!(
+            region_id = region_config_id,
+            topic = %topic,
+            event = %event,
+            "Message received from gateway"
+        );
+
+        EVENT_COUNTER
+            .get_or_create(&EventLabels {
+                event: event.to_string(),
+            })
+            .inc();
+
+        handle_event(
+            region_config_id,
+            region_common_name,
+            false,
+            event,
+            m.payload().unwrap_or_default(),
+        )
+    }()
+    .err();
+
+    if let Some(err) = err {
+        error!(
+            region_id = %region_config_id,
+            topic = %topic,
+            "Processing gateway event error: {}",
+            err
+        );
+    }
+}
+
+// Returns the event type. This is read from the "event" header, or when not set, from the
+// last segment of the topic (e.g. gateway.event.up).
+fn get_event_type(event_header: Option<&[u8]>, topic: &str) -> Result<Event> {
+    if let Some(v) = event_header {
+        return Event::from_str(&String::from_utf8_lossy(v));
+    }
+
+    Event::from_str(topic.rsplit('.').next().unwrap_or_default())
+}
+
+fn get_client_config(conf: &GatewayBackendKafka) -> Result<ClientConfig> {
+    let mut client_config = ClientConfig::new();
+    client_config.set("bootstrap.servers", conf.brokers.join(","));
+    client_config.set(
+        "security.protocol",
+        match (conf.tls, conf.username.is_empty()) {
+            (false, true) => "plaintext",
+            (true, true) => "ssl",
+            (false, false) => "sasl_plaintext",
+            (true, false) => "sasl_ssl",
+        },
+    );
+
+    if !conf.username.is_empty() {
+        client_config
+            .set(
+                "sasl.mechanism",
+                match conf.mechanism.as_ref() {
+                    "PLAIN" => "PLAIN",
+                    "SCRAM-SHA-256" => "SCRAM-SHA-256",
+                    "SCRAM-SHA-512" => "SCRAM-SHA-512",
+                    _ => {
+                        return Err(anyhow!(
+                            "mechanism must be PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512"
+                        ));
+                    }
+                },
+            )
+            .set("sasl.username", &conf.username)
+            .set("sasl.password", &conf.password);
+    }
+
+    Ok(client_config)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_event_type() {
+        // From header.
+        assert_eq!(
+            Event::Stats,
+            get_event_type(Some(b"stats"), "gateway.event.up").unwrap()
+        );
+
+        // From topic.
+        assert_eq!(Event::Up, get_event_type(None, "gateway.event.up").unwrap());
+        assert_eq!(
+            Event::MeshHeartbeat,
+            get_event_type(None, "gateway.event.mesh-heartbeat").unwrap()
+        );
+
+        // Unknown event.
+        assert!(get_event_type(Some(b"foo"), "gateway.event.up").is_err());
+        assert!(get_event_type(None, "gateway.event.foo").is_err());
+    }
+
+    #[test]
+    fn test_get_client_config() {
+        let conf = GatewayBackendKafka::default();
+        let c = get_client_config(&conf).unwrap();
+        assert_eq!(Some("plaintext"), c.get("security.protocol"));
+        assert_eq!(None, c.get("sasl.mechanism"));
+
+        let conf = GatewayBackendKafka {
+            tls: true,
+            username: "user".into(),
+            password: "secret".into(),
+            mechanism: "SCRAM-SHA-256".into(),
+            ..Default::default()
+        };
+        let c = get_client_config(&conf).unwrap();
+        assert_eq!(Some("sasl_ssl"), c.get("security.protocol"));
+        assert_eq!(Some("SCRAM-SHA-256"), c.get("sasl.mechanism"));
+        assert_eq!(Some("user"), c.get("sasl.username"));
+
+        let conf = GatewayBackendKafka {
+            username: "user".into(),
+            mechanism: "FOO".into(),
+            ..Default::default()
+        };
+        assert!(get_client_config(&conf).is_err());
+    }
+}
@@ -1,19 +1,73 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+use std::sync::RwLock as StdRwLock;
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::try_join_all;
+use prost::Message;
 use tokio::sync::RwLock;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::config;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{error::Error as StorageError, gateway};
+use crate::{downlink, region, uplink};
+use lrwn::region::CommonName;
+use lrwn::EUI64;
 
+mod basic_station;
+mod kafka;
 #[cfg(test)]
 pub mod mock;
 mod mqtt;
+mod nats;
 
 lazy_static! {
     static ref BACKENDS: RwLock<HashMap<String, Box<dyn GatewayBackend + Sync + Send>>> =
         RwLock::new(HashMap::new());
+    static ref GATEWAY_JSON: StdRwLock<HashMap<String, bool>> = StdRwLock::new(HashMap::new());
+}
+
+// Event types which are received from the gateways.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Up,
+    Stats,
+    Ack,
+    MeshHeartbeat,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Event::Up => "up",
+                Event::Stats => "stats",
+                Event::Ack => "ack",
+                Event::MeshHeartbeat => "mesh-heartbeat",
+            }
+        )
+    }
+}
+
+impl FromStr for Event {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "up" => Event::Up,
+            "stats" => Event::Stats,
+            "ack" => Event::Ack,
+            "mesh-heartbeat" => Event::MeshHeartbeat,
+            _ => return Err(anyhow!("Unknown event type")),
+        })
+    }
 }
 
 #[async_trait]
@@ -23,37 +77,181 @@ pub trait GatewayBackend {
         &self,
         gw_conf: &chirpstack_api::gw::GatewayConfiguration,
     ) -> Result<()>;
+
+    // Stops receiving events, e.g. before the backend is replaced on configuration reload.
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub async fn setup() -> Result<()> {
     let conf = config::get();
 
     info!("Setting up gateway backends for the different regions");
-    for region in &conf.regions {
-        if !conf.network.enabled_regions.contains(&region.id) {
-            continue;
+
+    // The backends are set up concurrently, as each backend connects to its broker.
+    let backends = try_join_all(
+        conf.regions
+            .iter()
+            .filter(|region| conf.network.enabled_regions.contains(&region.id))
+            .map(|region| async move {
+                Ok::<_, anyhow::Error>((region.id.clone(), new_backend(region).await?))
+            }),
+    )
+    .await?;
+
+    for (region_id, backend) in backends {
+        set_backend(&region_id, backend).await;
+    }
+
+    Ok(())
+}
+
+// Re-creates the gateway backends of the enabled regions for which the gateway backend
+// configuration has changed. The new backends are set up before the current backends are
+// replaced, such that the current backends are kept in case a new backend can not be set up.
+// Uplinks which are already received are still processed, as these are handled in their own
+// tasks.
+pub async fn reload(old: &config::Configuration, new: &config::Configuration) -> Result<()> {
+    let mut changed: Vec<(&config::Region, Option<&config::Region>)> = Vec::new();
+    for region in new
+        .regions
+        .iter()
+        .filter(|region| new.network.enabled_regions.contains(&region.id))
+    {
+        let old_region = old
+            .regions
+            .iter()
+            .find(|r| r.id == region.id && old.network.enabled_regions.contains(&r.id));
+
+        if let Some(old_region) = old_region {
+            if old_region.common_name == region.common_name
+                && serde_json::to_value(&old_region.gateway.backend)?
+                    == serde_json::to_value(&region.gateway.backend)?
+            {
+                continue;
+            }
+
+            info!(region_id = %region.id, "Gateway backend configuration changed, reloading gateway backend");
         }
 
-        info!(
-            region_id = %region.id,
-            region_common_name = %region.common_name,
-            "Setting up gateway backend for region"
-        );
+        changed.push((region, old_region));
+    }
+
+    let mut backends: Vec<(String, Box<dyn GatewayBackend + Sync + Send>)> = Vec::new();
+    let mut in_place: Vec<(&config::Region, &config::Region)> = Vec::new();
+    for (region, old_region) in changed {
+        let current = has_backend(&region.id).await;
+        match (new_backend(region).await, old_region) {
+            (Ok(b), _) => backends.push((region.id.clone(), b)),
+            // The new backend might conflict with the current backend (e.g. both use the same
+            // bind address), in which case it is set up after closing the current backend.
+            (Err(_), Some(old_region)) if current => in_place.push((region, old_region)),
+            (Err(e), _) => {
+                for (_, b) in backends {
+                    b.close().await?;
+                }
+                return Err(e);
+            }
+        }
+    }
 
-        let backend =
-            mqtt::MqttBackend::new(&region.id, region.common_name, &region.gateway.backend.mqtt)
-                .await
-                .context("New MQTT gateway backend error")?;
+    for (region_config_id, b) in backends {
+        if let Some(old_b) = set_backend(&region_config_id, b).await {
+            old_b.close().await?;
+        }
+    }
 
-        set_backend(&region.id, Box::new(backend)).await;
+    for (region, old_region) in in_place {
+        remove_backend(&region.id).await?;
+
+        match new_backend(region).await {
+            Ok(b) => {
+                set_backend(&region.id, b).await;
+            }
+            Err(e) => {
+                warn!(region_id = %region.id, error = %e.full(), "Setting up gateway backend failed, restoring previous gateway backend");
+                set_backend(&region.id, new_backend(old_region).await?).await;
+                return Err(e);
+            }
+        }
+    }
+
+    for region_config_id in &old.network.enabled_regions {
+        if !new.network.enabled_regions.contains(region_config_id) {
+            info!(region_id = %region_config_id, "Region disabled, closing gateway backend");
+            remove_backend(region_config_id).await?;
+        }
     }
 
     Ok(())
 }
 
-pub async fn set_backend(region_config_id: &str, b: Box<dyn GatewayBackend + Sync + Send>) {
+async fn has_backend(region_config_id: &str) -> bool {
+    BACKENDS.read().await.contains_key(region_config_id)
+}
+
+async fn remove_backend(region_config_id: &str) -> Result<()> {
+    let b = BACKENDS.write().await.remove(region_config_id);
+    if let Some(b) = b {
+        b.close().await?;
+    }
+
+    Ok(())
+}
+
+async fn new_backend(region: &config::Region) -> Result<Box<dyn GatewayBackend + Sync + Send>> {
+    info!(
+        region_id = %region.id,
+        region_common_name = %region.common_name,
+        "Setting up gateway backend for region"
+    );
+
+    Ok(match region.gateway.backend.enabled.as_ref() {
+        // For backwards compatibility, MQTT is used when not configured.
+        "" | "mqtt" => Box::new(
+            mqtt::MqttBackend::new(&region.id, region.common_name, &region.gateway.backend.mqtt)
+                .await
+                .context("New MQTT gateway backend error")?,
+        ),
+        "kafka" => Box::new(
+            kafka::KafkaBackend::new(
+                &region.id,
+                region.common_name,
+                &region.gateway.backend.kafka,
+            )
+            .context("New Kafka gateway backend error")?,
+        ),
+        "nats" => Box::new(
+            nats::NatsBackend::new(&region.id, region.common_name, &region.gateway.backend.nats)
+                .await
+                .context("New NATS gateway backend error")?,
+        ),
+        "basic_station" => Box::new(
+            basic_station::BasicStationBackend::new(
+                &region.id,
+                region.common_name,
+                &region.gateway.backend.basic_station,
+            )
+            .await
+            .context("New Basics Station gateway backend error")?,
+        ),
+        _ => {
+            return Err(anyhow!(
+                "Unexpected gateway backend: {}",
+                region.gateway.backend.enabled
+            ));
+        }
+    })
+}
+
+// Sets the backend for the given region and returns the previous backend (if any).
+pub async fn set_backend(
+    region_config_id: &str,
+    b: Box<dyn GatewayBackend + Sync + Send>,
+) -> Option<Box<dyn GatewayBackend + Sync + Send>> {
     let mut b_w = BACKENDS.write().await;
-    b_w.insert(region_config_id.to_string(), b);
+    b_w.insert(region_config_id.to_string(), b)
 }
 
 pub async fn send_downlink(
@@ -68,11 +266,55 @@ pub async fn send_downlink(
         )
     })?;
 
-    b.send_downlink(df).await?;
+    let df = set_tx_power(region_config_id, df).await?;
+    b.send_downlink(&df).await?;
 
     Ok(())
 }
 
+// The TX power of the downlink frame items is the radiated power (EIRP). This returns a copy of
+// the downlink frame with the TX power at the radio of the gateway, taking the antenna gain and
+// cable loss of the gateway into account.
+async fn set_tx_power(
+    region_config_id: &str,
+    df: &chirpstack_api::gw::DownlinkFrame,
+) -> Result<chirpstack_api::gw::DownlinkFrame> {
+    let mut df = df.clone();
+
+    let gateway_id = EUI64::from_str(&df.gateway_id).context("Gateway ID")?;
+    let antenna = match gateway::get_antenna(&gateway_id).await {
+        Ok(v) => v,
+        // The gateway might not exist in case allow_unknown_gateways is set.
+        Err(StorageError::NotFound(_)) => return Ok(df),
+        Err(e) => return Err(e).context("Get gateway antenna"),
+    };
+
+    if antenna.antenna_gain == 0.0 && antenna.cable_loss == 0.0 {
+        return Ok(df);
+    }
+
+    let region_conf = region::get(region_config_id)?;
+
+    for item in &mut df.items {
+        if let Some(tx_info) = &mut item.tx_info {
+            tx_info.power = get_tx_power(
+                tx_info.power,
+                antenna.antenna_gain,
+                antenna.cable_loss,
+                region_conf.get_downlink_tx_power_eirp(tx_info.frequency) as i32,
+            );
+        }
+    }
+
+    Ok(df)
+}
+
+// Returns the TX power (dBm) for the given EIRP, rounded down so that the EIRP is never
+// exceeded. The TX power is clamped between 0 and the max. EIRP of the region.
+fn get_tx_power(eirp: i32, antenna_gain: f32, cable_loss: f32, max_eirp: i32) -> i32 {
+    ((eirp as f32 - antenna_gain + cable_loss).floor() as i32).clamp(0, max_eirp.max(0))
+}
+
 pub async fn send_configuration(
     region_config_id: &str,
     gw_conf: &chirpstack_api::gw::GatewayConfiguration,
@@ -89,3 +331,234 @@ pub async fn send_configuration(
 
     Ok(())
 }
+
+// Handles the given gateway event. The payload is either JSON or Protobuf encoded.
+fn handle_event(
+    region_config_id: &str,
+    region_common_name: CommonName,
+    v4_migrate: bool,
+    event: Event,
+    payload: &[u8],
+) -> Result<()> {
+    let json = payload_is_json(payload);
+
+    match event {
+        Event::Up => {
+            let mut event = match json {
+                true => serde_json::from_slice(payload)?,
+                false => chirpstack_api::gw::UplinkFrame::decode(&mut Cursor::new(payload))?,
+            };
+
+            if v4_migrate {
+                event.v4_migrate();
+            }
+
+            if let Some(rx_info) = &mut event.rx_info {
+                set_gateway_json(&rx_info.gateway_id, json);
+                rx_info.ns_time = Some(Utc::now().into());
+            }
+
+            tokio::spawn(uplink::deduplicate_uplink(
+                region_common_name,
+                region_config_id.to_string(),
+                event,
+            ));
+        }
+        Event::Stats => {
+            let mut event = match json {
+                true => serde_json::from_slice(payload)?,
+                false => chirpstack_api::gw::GatewayStats::decode(&mut Cursor::new(payload))?,
+            };
+
+            if v4_migrate {
+                event.v4_migrate();
+            }
+
+            event
+                .metadata
+                .insert("region_config_id".to_string(), region_config_id.to_string());
+            event.metadata.insert(
+                "region_common_name".to_string(),
+                region_common_name.to_string(),
+            );
+            set_gateway_json(&event.gateway_id, json);
+            tokio::spawn(uplink::stats::Stats::handle(event));
+        }
+        Event::Ack => {
+            let mut event = match json {
+                true => serde_json::from_slice(payload)?,
+                false => chirpstack_api::gw::DownlinkTxAck::decode(&mut Cursor::new(payload))?,
+            };
+
+            if v4_migrate {
+                event.v4_migrate();
+            }
+
+            set_gateway_json(&event.gateway_id, json);
+            tokio::spawn(downlink::tx_ack::TxAck::handle(event));
+        }
+        Event::MeshHeartbeat => {
+            let event = match json {
+                true => serde_json::from_slice(payload)?,
+                false => chirpstack_api::gw::MeshHeartbeat::decode(&mut Cursor::new(payload))?,
+            };
+
+            tokio::spawn(uplink::mesh::MeshHeartbeat::handle(event));
+        }
+    }
+
+    Ok(())
+}
+
+fn gateway_is_json(gateway_id: &str) -> bool {
+    let gw_json_r = GATEWAY_JSON.read().unwrap();
+    gw_json_r.get(gateway_id).cloned().unwrap_or(false)
+}
+
+fn set_gateway_json(gateway_id: &str, is_json: bool) {
+    let mut gw_json_w = GATEWAY_JSON.write().unwrap();
+    gw_json_w.insert(gateway_id.to_string(), is_json);
+}
+
+fn payload_is_json(b: &[u8]) -> bool {
+    String::from_utf8_lossy(b).contains("gatewayId")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_tx_power() {
+        assert_eq!(16, get_tx_power(16, 0.0, 0.0, 16));
+        assert_eq!(11, get_tx_power(16, 6.0, 1.0, 16));
+        assert_eq!(10, get_tx_power(16, 6.0, 0.5, 16));
+        assert_eq!(19, get_tx_power(16, -2.0, 1.0, 27));
+
+        // Clamped to the max. EIRP of the region.
+        assert_eq!(16, get_tx_power(16, -2.0, 1.0, 16));
+        assert_eq!(0, get_tx_power(16, 20.0, 0.0, 16));
+    }
+
+    #[test]
+    fn test_event() {
+        for (s, e) in [
+            ("up", Event::Up),
+            ("stats", Event::Stats),
+            ("ack", Event::Ack),
+            ("mesh-heartbeat", Event::MeshHeartbeat),
+        ] {
+            assert_eq!(e, Event::from_str(s).unwrap());
+            assert_eq!(s, e.to_string());
+        }
+
+        assert!(Event::from_str("foo").is_err());
+    }
+
+    #[test]
+    fn test_payload_is_json() {
+        assert!(payload_is_json(br#"{"gatewayId":"0102030405060708"}"#));
+        assert!(!payload_is_json(
+            &chirpstack_api::gw::GatewayStats {
+                gateway_id: "0102030405060708".into(),
+                ..Default::default()
+            }
+            .encode_to_vec()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handle_event() {
+        // Invalid payload.
+        assert!(handle_event("eu868", CommonName::EU868, false, Event::Up, b"foo").is_err());
+
+        // The encoding of the gateway is stored, such that commands use the same encoding.
+        let gateway_id = "0807060504030201";
+        assert!(!gateway_is_json(gateway_id));
+
+        let pl = chirpstack_api::gw::DownlinkTxAck {
+            gateway_id: gateway_id.into(),
+            ..Default::default()
+        };
+        handle_event(
+            "eu868",
+            CommonName::EU868,
+            false,
+            Event::Ack,
+            &serde_json::to_vec(&pl).unwrap(),
+        )
+        .unwrap();
+        assert!(gateway_is_json(gateway_id));
+
+        handle_event(
+            "eu868",
+            CommonName::EU868,
+            false,
+            Event::Ack,
+            &pl.encode_to_vec(),
+        )
+        .unwrap();
+        assert!(!gateway_is_json(gateway_id));
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_backends_on_error() {
+        let basic_station_region = |id: &str, auth_token: &str| config::Region {
+            id: id.into(),
+            gateway: config::RegionGateway {
+                backend: config::GatewayBackend {
+                    enabled: "basic_station".into(),
+                    basic_station: config::GatewayBackendBasicStation {
+                        bind: "127.0.0.1:0".into(),
+                        auth_token: auth_token.into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        set_backend("reload_a", Box::new(mock::Backend {})).await;
+
+        let old = config::Configuration {
+            regions: vec![basic_station_region("reload_a", "token-a")],
+            network: config::Network {
+                enabled_regions: vec!["reload_a".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // The backend of reload_b can not be set up, as it has no authentication configured.
+        let new = config::Configuration {
+            regions: vec![
+                basic_station_region("reload_a", "token-b"),
+                basic_station_region("reload_b", ""),
+            ],
+            network: config::Network {
+                enabled_regions: vec!["reload_a".into(), "reload_b".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(reload(&old, &new).await.is_err());
+
+        // The current backend of reload_a has not been replaced.
+        let _ = mock::get_gateway_configurations().await;
+        BACKENDS
+            .read()
+            .await
+            .get("reload_a")
+            .unwrap()
+            .send_configuration(&Default::default())
+            .await
+            .unwrap();
+        assert_eq!(1, mock::get_gateway_configurations().await.len());
+        assert!(!has_backend("reload_b").await);
+
+        remove_backend("reload_a").await.unwrap();
+    }
+}
@@ -1,11 +1,8 @@
-use std::collections::HashMap;
-use std::io::Cursor;
-use std::sync::RwLock;
+use std::str::FromStr;
 use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use chrono::Utc;
 use handlebars::Handlebars;
 use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::counter::Counter;
@@ -14,18 +11,18 @@ use prost::Message;
 use rand::Rng;
 use rumqttc::tokio_rustls::rustls;
 use rumqttc::v5::mqttbytes::v5::{ConnectReturnCode, Publish};
-use rumqttc::v5::{mqttbytes::QoS, AsyncClient, Event, Incoming, MqttOptions};
+use rumqttc::v5::{mqttbytes::QoS, AsyncClient, Event as MqttEvent, Incoming, MqttOptions};
 use rumqttc::Transport;
 use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
-use super::GatewayBackend;
-use crate::config::GatewayBackendMqtt;
+use super::{gateway_is_json, handle_event, payload_is_json, Event, GatewayBackend};
+use crate::config::{GatewayBackendMqtt, SharedSubscriptionFallback};
+use crate::helpers::tasks::Tasks;
 use crate::helpers::tls22::{get_root_certs, load_cert, load_key};
 use crate::monitoring::prometheus;
-use crate::{downlink, uplink};
 use lrwn::region::CommonName;
 
 #[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
@@ -57,7 +54,6 @@ lazy_static! {
         );
         counter
     };
-    static ref GATEWAY_JSON: RwLock<HashMap<String, bool>> = RwLock::new(HashMap::new());
 }
 
 pub struct MqttBackend<'a> {
@@ -66,6 +62,7 @@ pub struct MqttBackend<'a> {
     qos: QoS,
     v4_migrate: bool,
     region_config_id: String,
+    tasks: Tasks,
 }
 
 #[derive(Serialize)]
@@ -165,13 +162,14 @@ impl<'a> MqttBackend<'a> {
             templates,
             v4_migrate: conf.v4_migrate,
             region_config_id: region_config_id.to_string(),
+            tasks: Tasks::default(),
         };
 
         // connect
         info!(region_id = %region_config_id, server_uri = %conf.server, clean_session = conf.clean_session, client_id = %client_id, "Connecting to MQTT broker");
 
         // (Re)subscribe loop
-        tokio::spawn({
+        b.tasks.push(tokio::spawn({
             let client = b.client.clone();
             let qos = b.qos;
             let region_config_id = region_config_id.to_string();
@@ -186,25 +184,37 @@ impl<'a> MqttBackend<'a> {
                 conf.event_topic.clone()
             };
             let share_name = conf.share_name.clone();
+            let fallback = conf.shared_subscription_fallback;
 
             async move {
                 while let Some(shared_sub_support) = connect_rx.recv().await {
-                    let event_topic = if shared_sub_support {
-                        format!("$share/{}/{}", share_name, event_topic)
-                    } else {
-                        event_topic.clone()
+                    let event_topic = match get_subscription_topic(
+                        &event_topic,
+                        &share_name,
+                        shared_sub_support,
+                        fallback,
+                    ) {
+                        Some(v) => v,
+                        None => {
+                            error!(region_id = %region_config_id, "MQTT broker does not support shared subscriptions, not subscribing to gateway event topic");
+                            continue;
+                        }
                     };
 
+                    if !share_name.is_empty() && !shared_sub_support {
+                        warn!(region_id = %region_config_id, "MQTT broker does not support shared subscriptions, falling back to non-shared subscription");
+                    }
+
                     info!(region_id = %region_config_id, event_topic = %event_topic, "Subscribing to gateway event topic");
                     if let Err(e) = client.subscribe(&event_topic, qos).await {
                         error!(region_id = %region_config_id, event_topic = %event_topic, error = %e, "MQTT subscribe error");
                     }
                 }
             }
-        });
+        }));
 
         // Eventloop
-        tokio::spawn({
+        b.tasks.push(tokio::spawn({
             let region_config_id = region_config_id.to_string();
             let v4_migrate = conf.v4_migrate;
 
@@ -217,7 +227,7 @@ impl<'a> MqttBackend<'a> {
                             trace!(event = ?v, "MQTT event");
 
                             match v {
-                                Event::Incoming(Incoming::Publish(p)) => {
+                                MqttEvent::Incoming(Incoming::Publish(p)) => {
                                     message_callback(
                                         v4_migrate,
                                         &region_config_id,
@@ -226,7 +236,7 @@ impl<'a> MqttBackend<'a> {
                                     )
                                     .await
                                 }
-                                Event::Incoming(Incoming::ConnAck(v)) => {
+                                MqttEvent::Incoming(Incoming::ConnAck(v)) => {
                                     if v.code == ConnectReturnCode::Success {
                                         // Per specification:
                                         // A value of 1 means Shared Subscriptions are supported. If not present, then Shared Subscriptions are supported.
@@ -257,7 +267,7 @@ impl<'a> MqttBackend<'a> {
                     }
                 }
             }
-        });
+        }));
 
         // return backend
         Ok(b)
@@ -324,6 +334,12 @@ impl GatewayBackend for MqttBackend<'_> {
 
         Ok(())
     }
+
+    async fn close(&self) -> Result<()> {
+        info!(region_id = %self.region_config_id, "Closing MQTT gateway backend");
+        self.tasks.abort().await;
+        Ok(())
+    }
 }
 
 async fn message_callback(
@@ -345,89 +361,20 @@ async fn message_callback(
             "Message received from gateway"
         );
 
-        if topic.ends_with("/up") {
-            EVENT_COUNTER
-                .get_or_create(&EventLabels {
-                    event: "up".to_string(),
-                })
-                .inc();
-            let mut event = match json {
-                true => serde_json::from_slice(&p.payload)?,
-                false => chirpstack_api::gw::UplinkFrame::decode(&mut Cursor::new(&p.payload))?,
-            };
-
-            if v4_migrate {
-                event.v4_migrate();
-            }
-
-            if let Some(rx_info) = &mut event.rx_info {
-                set_gateway_json(&rx_info.gateway_id, json);
-                rx_info.ns_time = Some(Utc::now().into());
-            }
-
-            tokio::spawn(uplink::deduplicate_uplink(
-                region_common_name,
-                region_config_id.to_string(),
-                event,
-            ));
-        } else if topic.ends_with("/stats") {
-            EVENT_COUNTER
-                .get_or_create(&EventLabels {
-                    event: "stats".to_string(),
-                })
-                .inc();
-            let mut event = match json {
-                true => serde_json::from_slice(&p.payload)?,
-                false => chirpstack_api::gw::GatewayStats::decode(&mut Cursor::new(&p.payload))?,
-            };
-
-            if v4_migrate {
-                event.v4_migrate();
-            }
-
-            event
-                .metadata
-                .insert("region_config_id".to_string(), region_config_id.to_string());
-            event.metadata.insert(
-                "region_common_name".to_string(),
-                region_common_name.to_string(),
-            );
-            set_gateway_json(&event.gateway_id, json);
-            tokio::spawn(uplink::stats::Stats::handle(event));
-        } else if topic.ends_with("/ack") {
-            EVENT_COUNTER
-                .get_or_create(&EventLabels {
-                    event: "ack".to_string(),
-                })
-                .inc();
-            let mut event = match json {
-                true => serde_json::from_slice(&p.payload)?,
-                false => chirpstack_api::gw::DownlinkTxAck::decode(&mut Cursor::new(&p.payload))?,
-            };
-
-            if v4_migrate {
-                event.v4_migrate();
-            }
-
-            set_gateway_json(&event.gateway_id, json);
-            tokio::spawn(downlink::tx_ack::TxAck::handle(event));
-        } else if topic.ends_with("/mesh-heartbeat") {
-            EVENT_COUNTER
-                .get_or_create(&EventLabels {
-                    event: "mesh-heartbeat".to_string(),
-                })
-                .inc();
-            let event = match json {
-                true => serde_json::from_slice(&p.payload)?,
-                false => chirpstack_api::gw::MeshHeartbeat::decode(&mut Cursor::new(&p.payload))?,
-            };
-
-            tokio::spawn(uplink::mesh::MeshHeartbeat::handle(event));
-        } else {
-            return Err(anyhow!("Unknown event type"));
-        }
+        let event = Event::from_str(topic.rsplit('/').next().unwrap_or_default())?;
+        EVENT_COUNTER
+            .get_or_create(&EventLabels {
+                event: event.to_string(),
+            })
+            .inc();
 
-        Ok(())
+        handle_event(
+            region_config_id,
+            region_common_name,
+            v4_migrate,
+            event,
+            &p.payload,
+        )
     }()
     .err();
 
@@ -442,16 +389,63 @@ async fn message_callback(
     }
 }
 
-fn gateway_is_json(gateway_id: &str) -> bool {
-    let gw_json_r = GATEWAY_JSON.read().unwrap();
-    gw_json_r.get(gateway_id).cloned().unwrap_or(false)
-}
+// Returns the topic to subscribe to. In case shared subscriptions are not supported by the
+// MQTT broker, this returns None if the fallback is set to skip the subscription.
+fn get_subscription_topic(
+    event_topic: &str,
+    share_name: &str,
+    shared_sub_support: bool,
+    fallback: SharedSubscriptionFallback,
+) -> Option<String> {
+    if share_name.is_empty() {
+        return Some(event_topic.to_string());
+    }
 
-fn set_gateway_json(gateway_id: &str, is_json: bool) {
-    let mut gw_json_w = GATEWAY_JSON.write().unwrap();
-    gw_json_w.insert(gateway_id.to_string(), is_json);
+    if shared_sub_support {
+        return Some(format!("$share/{}/{}", share_name, event_topic));
+    }
+
+    match fallback {
+        SharedSubscriptionFallback::Subscribe => Some(event_topic.to_string()),
+        SharedSubscriptionFallback::Skip => None,
+    }
 }
 
-fn payload_is_json(b: &[u8]) -> bool {
-    String::from_utf8_lossy(b).contains("gatewayId")
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_subscription_topic() {
+        let topic = "eu868/gateway/+/event/+";
+
+        // Shared subscription.
+        assert_eq!(
+            Some("$share/chirpstack/eu868/gateway/+/event/+".to_string()),
+            get_subscription_topic(topic, "chirpstack", true, Default::default())
+        );
+
+        // Shared subscriptions disabled.
+        assert_eq!(
+            Some(topic.to_string()),
+            get_subscription_topic(topic, "", true, Default::default())
+        );
+
+        // Not supported by broker, fallback to non-shared subscription.
+        assert_eq!(
+            Some(topic.to_string()),
+            get_subscription_topic(
+                topic,
+                "chirpstack",
+                false,
+                SharedSubscriptionFallback::Subscribe
+            )
+        );
+
+        // Not supported by broker, skip subscription.
+        assert_eq!(
+            None,
+            get_subscription_topic(topic, "chirpstack", false, SharedSubscriptionFallback::Skip)
+        );
+    }
 }
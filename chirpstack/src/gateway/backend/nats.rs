@@ -0,0 +1,374 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use async_nats::jetstream;
+use async_nats::HeaderMap;
+use async_trait::async_trait;
+use futures::StreamExt;
+use handlebars::Handlebars;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prost::Message;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{error, info, trace};
+
+use super::{gateway_is_json, handle_event, Event, GatewayBackend};
+use crate::config::{GatewayBackendNats, GatewayBackendNatsJetStream};
+use crate::helpers::tasks::Tasks;
+use crate::monitoring::prometheus;
+use lrwn::region::CommonName;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct EventLabels {
+    event: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct CommandLabels {
+    command: String,
+}
+
+lazy_static! {
+    static ref EVENT_COUNTER: Family<EventLabels, Counter> = {
+        let counter = Family::<EventLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_nats_events",
+            "Number of events received",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref COMMAND_COUNTER: Family<CommandLabels, Counter> = {
+        let counter = Family::<CommandLabels, Counter>::default();
+        prometheus::register(
+            "gateway_backend_nats_commands",
+            "Number of commands sent",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+pub struct NatsBackend<'a> {
+    client: async_nats::Client,
+    templates: Handlebars<'a>,
+    region_config_id: String,
+    tasks: Tasks,
+}
+
+#[derive(Serialize)]
+struct CommandSubjectContext {
+    pub gateway_id: String,
+    pub command: String,
+}
+
+impl<'a> NatsBackend<'a> {
+    pub async fn new(
+        region_config_id: &str,
+        region_common_name: CommonName,
+        conf: &GatewayBackendNats,
+    ) -> Result<NatsBackend<'a>> {
+        // subject templates
+        let mut templates = Handlebars::new();
+        templates.register_escape_fn(handlebars::no_escape);
+        templates.register_template_string("command_subject", &conf.command_subject)?;
+
+        info!(region_id = %region_config_id, server = %conf.server, "Connecting to NATS server");
+
+        let mut opts = async_nats::ConnectOptions::new();
+        if !conf.username.is_empty() || !conf.password.is_empty() {
+            opts = opts.user_and_password(conf.username.clone(), conf.password.clone());
+        }
+        let client = opts.connect(&conf.server).await?;
+
+        let tasks = Tasks::default();
+        tasks.push(if conf.jetstream.enabled {
+            subscribe_jetstream(
+                client.clone(),
+                region_config_id,
+                region_common_name,
+                &conf.event_subject,
+                &conf.jetstream,
+            )
+            .await?
+        } else {
+            subscribe(
+                client.clone(),
+                region_config_id,
+                region_common_name,
+                &conf.event_subject,
+                &conf.queue_group,
+            )
+            .await?
+        });
+
+        Ok(NatsBackend {
+            client,
+            templates,
+            region_config_id: region_config_id.to_string(),
+            tasks,
+        })
+    }
+
+    fn get_command_subject(&self, gateway_id: &str, command: &str) -> Result<String> {
+        Ok(self.templates.render(
+            "command_subject",
+            &CommandSubjectContext {
+                gateway_id: gateway_id.to_string(),
+                command: command.to_string(),
+            },
+        )?)
+    }
+
+    async fn publish_command(&self, gateway_id: &str, command: &str, b: Vec<u8>) -> Result<()> {
+        COMMAND_COUNTER
+            .get_or_create(&CommandLabels {
+                command: command.to_string(),
+            })
+            .inc();
+        let subject = self.get_command_subject(gateway_id, command)?;
+
+        info!(region_id = %self.region_config_id, gateway_id = %gateway_id, subject = %subject, command = %command, "Sending command to gateway");
+        self.client.publish(subject, b.into()).await?;
+        trace!("Message published");
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GatewayBackend for NatsBackend<'_> {
+    async fn send_downlink(&self, df: &chirpstack_api::gw::DownlinkFrame) -> Result<()> {
+        let b = match gateway_is_json(&df.gateway_id) {
+            true => serde_json::to_vec(&df)?,
+            false => df.encode_to_vec(),
+        };
+
+        self.publish_command(&df.gateway_id, "down", b).await
+    }
+
+    async fn send_configuration(
+        &self,
+        gw_conf: &chirpstack_api::gw::GatewayConfiguration,
+    ) -> Result<()> {
+        let b = match gateway_is_json(&gw_conf.gateway_id) {
+            true => serde_json::to_vec(&gw_conf)?,
+            false => gw_conf.encode_to_vec(),
+        };
+
+        self.publish_command(&gw_conf.gateway_id, "config", b).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        info!(region_id = %self.region_config_id, "Closing NATS gateway backend");
+        self.tasks.abort().await;
+        Ok(())
+    }
+}
+
+// Subscribes to the gateway events using a (core NATS) subscription. When a queue group is
+// configured, each event is delivered to only one of the subscribers within the group.
+async fn subscribe(
+    client: async_nats::Client,
+    region_config_id: &str,
+    region_common_name: CommonName,
+    event_subject: &str,
+    queue_group: &str,
+) -> Result<JoinHandle<()>> {
+    info!(region_id = %region_config_id, event_subject = %event_subject, queue_group = %queue_group, "Subscribing to gateway event subject");
+
+    let mut sub = if queue_group.is_empty() {
+        client.subscribe(event_subject.to_string()).await?
+    } else {
+        client
+            .queue_subscribe(event_subject.to_string(), queue_group.to_string())
+            .await?
+    };
+
+    Ok(tokio::spawn({
+        let region_config_id = region_config_id.to_string();
+
+        async move {
+            while let Some(msg) = sub.next().await {
+                let _ = message_callback(
+                    &region_config_id,
+                    region_common_name,
+                    &msg.subject,
+                    msg.headers.as_ref(),
+                    &msg.payload,
+                );
+            }
+
+            error!(region_id = %region_config_id, "NATS subscription closed");
+        }
+    }))
+}
+
+// Consumes the gateway events using a durable JetStream consumer. Instances using the same
+// durable name share the consumer, thus each event is delivered to one instance only. Events
+// are acknowledged after they have been handled. Events that can not be handled (e.g. because
+// they can not be decoded) are terminated, as a redelivery would fail again.
+async fn subscribe_jetstream(
+    client: async_nats::Client,
+    region_config_id: &str,
+    region_common_name: CommonName,
+    event_subject: &str,
+    conf: &GatewayBackendNatsJetStream,
+) -> Result<JoinHandle<()>> {
+    info!(region_id = %region_config_id, event_subject = %event_subject, stream = %conf.stream, durable_name = %conf.durable_name, "Consuming gateway events from JetStream");
+
+    let js = jetstream::new(client);
+    let stream = js
+        .get_or_create_stream(jetstream::stream::Config {
+            name: conf.stream.clone(),
+            subjects: vec![event_subject.to_string()],
+            storage: match conf.storage.as_ref() {
+                "file" => jetstream::stream::StorageType::File,
+                "memory" => jetstream::stream::StorageType::Memory,
+                _ => return Err(anyhow!("storage must be file or memory")),
+            },
+            num_replicas: conf.replicas,
+            max_age: conf.max_age,
+            ..Default::default()
+        })
+        .await?;
+
+    let consumer = stream
+        .get_or_create_consumer(
+            &conf.durable_name,
+            jetstream::consumer::pull::Config {
+                durable_name: Some(conf.durable_name.clone()),
+                ack_wait: conf.ack_wait,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut messages = consumer.messages().await?;
+
+    Ok(tokio::spawn({
+        let region_config_id = region_config_id.to_string();
+
+        async move {
+            while let Some(msg) = messages.next().await {
+                match msg {
+                    Ok(msg) => {
+                        let ack_kind = match message_callback(
+                            &region_config_id,
+                            region_common_name,
+                            &msg.subject,
+                            msg.headers.as_ref(),
+                            &msg.payload,
+                        ) {
+                            Ok(_) => jetstream::AckKind::Ack,
+                            Err(_) => jetstream::AckKind::Term,
+                        };
+
+                        if let Err(e) = msg.ack_with(ack_kind).await {
+                            error!(region_id = %region_config_id, error = %e, "JetStream ack error");
+                        }
+                    }
+                    Err(e) => {
+                        error!(region_id = %region_config_id, error = %e, "JetStream consumer error");
+                    }
+                }
+            }
+
+            error!(region_id = %region_config_id, "JetStream consumer closed");
+        }
+    }))
+}
+
+fn message_callback(
+    region_config_id: &str,
+    region_common_name: CommonName,
+    subject: &str,
+    headers: Option<&HeaderMap>,
+    payload: &[u8],
+) -> Result<()> {
+    let res = || -> Result<()> {
+        let event = get_event_type(subject, headers)?;
+        info!(
+            region_id = region_config_id,
+            subject = %subject,
+            event = %event,
+            "Message received from gateway"
+        );
+
+        EVENT_COUNTER
+            .get_or_create(&EventLabels {
+                event: event.to_string(),
+            })
+            .inc();
+
+        handle_event(region_config_id, region_common_name, false, event, payload)
+    }();
+
+    if let Err(err) = &res {
+        error!(
+            region_id = %region_config_id,
+            subject = %subject,
+            "Processing gateway event error: {}",
+            err
+        );
+    }
+
+    res
+}
+
+// Returns the event type. This is read from the "event" header, or when not set, from the
+// last token of the subject (e.g. gateway.0102030405060708.event.up).
+fn get_event_type(subject: &str, headers: Option<&HeaderMap>) -> Result<Event> {
+    if let Some(v) = headers.and_then(|h| h.get("event")) {
+        return Event::from_str(v.as_str());
+    }
+
+    Event::from_str(subject.rsplit('.').next().unwrap_or_default())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_event_type() {
+        assert_eq!(
+            Event::Up,
+            get_event_type("gateway.0102030405060708.event.up", None).unwrap()
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("event", "stats");
+        assert_eq!(
+            Event::Stats,
+            get_event_type("gateway.events", Some(&headers)).unwrap()
+        );
+
+        assert!(get_event_type("gateway.0102030405060708.event.foo", None).is_err());
+    }
+
+    #[test]
+    fn test_message_callback_error() {
+        // Unknown event type.
+        assert!(message_callback(
+            "eu868",
+            CommonName::EU868,
+            "gateway.0102030405060708.event.foo",
+            None,
+            &[],
+        )
+        .is_err());
+
+        // Invalid payload.
+        assert!(message_callback(
+            "eu868",
+            CommonName::EU868,
+            "gateway.0102030405060708.event.up",
+            None,
+            &[0xff, 0xff, 0xff],
+        )
+        .is_err());
+    }
+}
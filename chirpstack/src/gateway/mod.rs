@@ -1 +1,4 @@
+pub mod airtime;
 pub mod backend;
+pub mod noise_floor;
+pub mod state;
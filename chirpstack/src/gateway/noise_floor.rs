@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Timelike};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use crate::helpers::errors::PrintFullError;
+use crate::storage::metrics;
+use chirpstack_api::gw;
+use lrwn::EUI64;
+
+// Interval in which the pending noise-floor metrics are saved.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+// The noise-floor metrics are aggregated in memory by gateway and hour, and periodically saved
+// by the flush loop, such that the uplink handling does not add any storage round-trips.
+type Pending = HashMap<(EUI64, DateTime<Local>), HashMap<String, f64>>;
+
+lazy_static! {
+    static ref PENDING: Mutex<Pending> = Mutex::new(HashMap::new());
+}
+
+pub async fn setup() {
+    info!("Setting up gateway noise-floor flush loop");
+    tokio::spawn(async move {
+        loop {
+            sleep(FLUSH_INTERVAL).await;
+            flush().await;
+        }
+    });
+}
+
+// Records the noise-floor estimate of the uplink for each of the given gateways. The noise-floor
+// is stored as sum and count per frequency, such that the average can be calculated for each
+// aggregation interval.
+pub fn record_rx_noise_floor(
+    gateway_ids: &[EUI64],
+    tx_info: &gw::UplinkTxInfo,
+    rx_info_set: &[gw::UplinkRxInfo],
+) {
+    let now = Local::now();
+    let hour = match now
+        .with_minute(0)
+        .and_then(|v| v.with_second(0))
+        .and_then(|v| v.with_nanosecond(0))
+    {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut pending = PENDING.lock().unwrap();
+
+    for rx_info in rx_info_set {
+        let gateway_id = match EUI64::from_str(&rx_info.gateway_id) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        if !gateway_ids.contains(&gateway_id) {
+            continue;
+        }
+
+        let m = pending.entry((gateway_id, hour)).or_default();
+        *m.entry(format!("noise_floor_sum_{}", tx_info.frequency))
+            .or_default() += get_noise_floor(rx_info.rssi, rx_info.snr);
+        *m.entry(format!("noise_floor_count_{}", tx_info.frequency))
+            .or_default() += 1.0;
+    }
+}
+
+// Saves the pending noise-floor metrics. When saving fails, the metrics are added back to the
+// pending metrics, such that these are saved on the next flush.
+pub async fn flush() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+
+    let records: Vec<(String, metrics::Record)> = pending
+        .iter()
+        .map(|((gateway_id, hour), m)| {
+            (
+                format!("gw:{}", gateway_id),
+                metrics::Record {
+                    time: *hour,
+                    kind: metrics::Kind::ABSOLUTE,
+                    metrics: m.clone(),
+                },
+            )
+        })
+        .collect();
+
+    if let Err(e) =
+        metrics::save_batch(&records, &metrics::Aggregation::default_aggregations()).await
+    {
+        error!(error = %e.full(), "Saving noise-floor failed");
+
+        let mut pending_w = PENDING.lock().unwrap();
+        for (k, m) in pending {
+            let pm = pending_w.entry(k).or_default();
+            for (mk, v) in m {
+                *pm.entry(mk).or_default() += v;
+            }
+        }
+    }
+}
+
+// Returns the noise-floor estimate (dBm) given the RSSI (dBm) and SNR (dB). The RSSI is the
+// power of the signal plus the noise, thus the noise is RSSI - 10 * log10(1 + SNR) (linear SNR).
+pub fn get_noise_floor(rssi: i32, snr: f32) -> f64 {
+    let snr_linear = 10f64.powf(snr as f64 / 10.0);
+    rssi as f64 - 10.0 * (1.0 + snr_linear).log10()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_rx_noise_floor() {
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let tx_info = gw::UplinkTxInfo {
+            frequency: 868100000,
+            ..Default::default()
+        };
+        let rx_info_set = vec![
+            gw::UplinkRxInfo {
+                gateway_id: gateway_id.to_string(),
+                rssi: -100,
+                snr: 0.0,
+                ..Default::default()
+            },
+            // Unknown gateway.
+            gw::UplinkRxInfo {
+                gateway_id: "0807060504030201".into(),
+                rssi: -100,
+                snr: 0.0,
+                ..Default::default()
+            },
+        ];
+
+        record_rx_noise_floor(&[gateway_id], &tx_info, &rx_info_set);
+        record_rx_noise_floor(&[gateway_id], &tx_info, &rx_info_set);
+
+        let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+        assert_eq!(1, pending.len());
+
+        let ((id, _), m) = pending.into_iter().next().unwrap();
+        assert_eq!(gateway_id, id);
+        assert_eq!(Some(&2.0), m.get("noise_floor_count_868100000"));
+        assert_eq!(-206.0, m.get("noise_floor_sum_868100000").unwrap().round());
+    }
+
+    #[test]
+    fn test_get_noise_floor() {
+        struct Test {
+            rssi: i32,
+            snr: f32,
+            expected: f64,
+        }
+
+        let tests = vec![
+            // SNR 0 dB: signal and noise are equal, thus the noise is 3 dB below the RSSI.
+            Test {
+                rssi: -100,
+                snr: 0.0,
+                expected: -103.0,
+            },
+            // High SNR: the RSSI is dominated by the signal.
+            Test {
+                rssi: -60,
+                snr: 10.0,
+                expected: -70.4,
+            },
+            // Low SNR: the RSSI is dominated by the noise.
+            Test {
+                rssi: -117,
+                snr: -15.0,
+                expected: -117.1,
+            },
+        ];
+
+        for tst in &tests {
+            let nf = (get_noise_floor(tst.rssi, tst.snr) * 10.0).round() / 10.0;
+            assert_eq!(tst.expected, nf);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use tokio::time::sleep;
+use tracing::{error, info, trace};
+
+use crate::helpers::errors::PrintFullError;
+use crate::storage::gateway;
+use crate::{config, integration};
+use chirpstack_api::integration as integration_pb;
+use lrwn::EUI64;
+
+pub async fn setup() {
+    let conf = config::get();
+    if !conf.gateway.offline_detection.enabled {
+        return;
+    }
+
+    info!("Setting up gateway offline detection loop");
+    tokio::spawn(async move {
+        offline_detection_loop().await;
+    });
+}
+
+async fn offline_detection_loop() {
+    let conf = config::get();
+
+    loop {
+        trace!("Starting gateway offline detection run");
+
+        if let Err(e) = handle_offline_gateways().await {
+            error!(error = %e.full(), "Gateway offline detection error");
+        }
+
+        sleep(conf.gateway.offline_detection.interval).await;
+    }
+}
+
+// Marks the gateways offline for which no stats were received within the configured silence
+// period and sends the offline event for each of them.
+pub async fn handle_offline_gateways() -> Result<()> {
+    let conf = config::get();
+
+    let gws = gateway::set_offline(conf.gateway.offline_detection.missed_stats_intervals)
+        .await
+        .context("Set gateways offline")?;
+
+    for gw in &gws {
+        integration::gateway_status_event(&get_status_event(gw, false)).await;
+    }
+
+    Ok(())
+}
+
+// Clears the offline state of the given gateways (e.g. on receiving stats) and sends the online
+// event for each gateway that was previously marked offline.
+pub async fn handle_online_gateways(gateway_ids: &[EUI64]) -> Result<()> {
+    let conf = config::get();
+    if !conf.gateway.offline_detection.enabled || gateway_ids.is_empty() {
+        return Ok(());
+    }
+
+    let gws = gateway::set_online(gateway_ids)
+        .await
+        .context("Set gateways online")?;
+
+    for gw in &gws {
+        integration::gateway_status_event(&get_status_event(gw, true)).await;
+    }
+
+    Ok(())
+}
+
+fn get_status_event(gw: &gateway::Gateway, online: bool) -> integration_pb::GatewayStatusEvent {
+    integration_pb::GatewayStatusEvent {
+        time: Some(Utc::now().into()),
+        tenant_id: gw.tenant_id.to_string(),
+        gateway_id: gw.gateway_id.to_string(),
+        gateway_name: gw.name.clone(),
+        online,
+        last_seen_at: gw.last_seen_at.map(|v| v.into()),
+        tags: gw.tags.into_hashmap(),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::integration::mock;
+    use crate::storage::tenant;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_offline_detection() {
+        let _guard = test::prepare().await;
+        integration::set_mock().await;
+        integration::mock::reset().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.gateway.offline_detection.enabled = true;
+        conf.gateway.offline_detection.missed_stats_intervals = 2;
+        config::set(conf);
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            can_have_gateways: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Gateway that has been silent for more than two stats intervals.
+        let gw_offline = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            tenant_id: t.id,
+            name: "gw-offline".into(),
+            stats_interval_secs: 30,
+            last_seen_at: Some(Utc::now() - chrono::Duration::seconds(90)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Gateway that is still within its stats interval.
+        let _ = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([2, 2, 3, 4, 5, 6, 7, 8]),
+            tenant_id: t.id,
+            name: "gw-online".into(),
+            stats_interval_secs: 30,
+            last_seen_at: Some(Utc::now()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Gateway that has never been seen.
+        let _ = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([3, 2, 3, 4, 5, 6, 7, 8]),
+            tenant_id: t.id,
+            name: "gw-never-seen".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Only the silent gateway is marked offline.
+        handle_offline_gateways().await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+
+        let events = mock::get_gateway_status_events().await;
+        assert_eq!(1, events.len());
+        assert_eq!(gw_offline.gateway_id.to_string(), events[0].gateway_id);
+        assert_eq!(t.id.to_string(), events[0].tenant_id);
+        assert!(!events[0].online);
+
+        let gw = gateway::get(&gw_offline.gateway_id).await.unwrap();
+        assert!(gw.offline_at.is_some());
+
+        // The gateway has already been marked offline.
+        handle_offline_gateways().await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        assert!(mock::get_gateway_status_events().await.is_empty());
+
+        // Gateway comes back online.
+        handle_online_gateways(&[
+            gw_offline.gateway_id,
+            EUI64::from_be_bytes([2, 2, 3, 4, 5, 6, 7, 8]),
+        ])
+        .await
+        .unwrap();
+        sleep(Duration::from_millis(100)).await;
+
+        let events = mock::get_gateway_status_events().await;
+        assert_eq!(1, events.len());
+        assert_eq!(gw_offline.gateway_id.to_string(), events[0].gateway_id);
+        assert!(events[0].online);
+
+        let gw = gateway::get(&gw_offline.gateway_id).await.unwrap();
+        assert!(gw.offline_at.is_none());
+    }
+}
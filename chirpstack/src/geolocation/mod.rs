@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use chirpstack_api::{common, gw};
+
+mod rssi;
+mod tdoa;
+
+// Mean earth radius (meters).
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+// At least 3 gateways are needed to resolve a (2D) location.
+const MIN_GATEWAYS: usize = 3;
+
+// Position (in meters) on the local plane, x pointing east and y pointing north.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn distance(&self, other: &Point) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+// Gateway which received the uplink.
+struct Receiver {
+    point: Point,
+    altitude: f64,
+    rssi: i32,
+    // Fine-timestamp (nanoseconds since GPS epoch).
+    fine_time_ns: Option<i64>,
+}
+
+// Solver returning the resolved point and its accuracy (in meters), given the receivers and
+// the minimum number of gateways.
+type Solver = fn(&[Receiver], usize) -> Option<(Point, f64)>;
+
+// Equirectangular projection, centered at the given coordinate. Within the range of the gateways
+// receiving a single uplink, the projection error can be neglected.
+struct Projection {
+    latitude: f64,
+    longitude: f64,
+}
+
+impl Projection {
+    fn to_point(&self, latitude: f64, longitude: f64) -> Point {
+        Point {
+            x: (longitude - self.longitude).to_radians()
+                * EARTH_RADIUS
+                * self.latitude.to_radians().cos(),
+            y: (latitude - self.latitude).to_radians() * EARTH_RADIUS,
+        }
+    }
+
+    fn to_coordinate(&self, p: &Point) -> (f64, f64) {
+        (
+            self.latitude + (p.y / EARTH_RADIUS).to_degrees(),
+            self.longitude + (p.x / (EARTH_RADIUS * self.latitude.to_radians().cos())).to_degrees(),
+        )
+    }
+}
+
+// Returns the great-circle distance (meters) between the given coordinates (haversine formula).
+pub fn get_distance(latitude_a: f64, longitude_a: f64, latitude_b: f64, longitude_b: f64) -> f64 {
+    let d_lat = (latitude_b - latitude_a).to_radians();
+    let d_lon = (longitude_b - longitude_a).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + latitude_a.to_radians().cos()
+            * latitude_b.to_radians().cos()
+            * (d_lon / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+// Resolves the device location using the fine-timestamps of the receiving gateways (TDOA).
+pub fn resolve_tdoa(rx_info: &[gw::UplinkRxInfo], min_gateways: usize) -> Option<common::Location> {
+    resolve(
+        rx_info,
+        min_gateways,
+        common::LocationSource::GeoResolverTdoa,
+        tdoa::solve,
+    )
+}
+
+// Resolves the device location using the RSSI of the receiving gateways.
+pub fn resolve_rssi(rx_info: &[gw::UplinkRxInfo], min_gateways: usize) -> Option<common::Location> {
+    resolve(
+        rx_info,
+        min_gateways,
+        common::LocationSource::GeoResolverRssi,
+        rssi::solve,
+    )
+}
+
+fn resolve(
+    rx_info: &[gw::UplinkRxInfo],
+    min_gateways: usize,
+    source: common::LocationSource,
+    solve: Solver,
+) -> Option<common::Location> {
+    let min_gateways = min_gateways.max(MIN_GATEWAYS);
+
+    let (projection, receivers) = get_receivers(rx_info);
+    if receivers.len() < min_gateways {
+        return None;
+    }
+
+    let (p, accuracy) = solve(&receivers, min_gateways)?;
+    let (latitude, longitude) = projection.to_coordinate(&p);
+
+    Some(common::Location {
+        latitude,
+        longitude,
+        altitude: receivers.iter().map(|r| r.altitude).sum::<f64>() / receivers.len() as f64,
+        source: source.into(),
+        accuracy: accuracy as f32,
+    })
+}
+
+// Returns the receiving gateways with a known location, projected on a plane centered at the
+// mean gateway location. In case the uplink was received by multiple antennas or boards of the
+// same gateway, the reception with the highest RSSI is used.
+fn get_receivers(rx_info: &[gw::UplinkRxInfo]) -> (Projection, Vec<Receiver>) {
+    let mut rx_by_gw: HashMap<&str, &gw::UplinkRxInfo> = HashMap::new();
+
+    for rx in rx_info {
+        if let Some(loc) = &rx.location {
+            if loc.latitude == 0.0 && loc.longitude == 0.0 {
+                continue;
+            }
+
+            rx_by_gw
+                .entry(&rx.gateway_id)
+                .and_modify(|v| {
+                    if rx.rssi > v.rssi {
+                        *v = rx;
+                    }
+                })
+                .or_insert(rx);
+        }
+    }
+
+    let locations: Vec<&common::Location> = rx_by_gw
+        .values()
+        .filter_map(|rx| rx.location.as_ref())
+        .collect();
+    let count = locations.len().max(1) as f64;
+
+    let projection = Projection {
+        latitude: locations.iter().map(|l| l.latitude).sum::<f64>() / count,
+        longitude: locations.iter().map(|l| l.longitude).sum::<f64>() / count,
+    };
+
+    let receivers = rx_by_gw
+        .values()
+        .filter_map(|rx| {
+            rx.location.as_ref().map(|loc| Receiver {
+                point: projection.to_point(loc.latitude, loc.longitude),
+                altitude: loc.altitude,
+                rssi: rx.rssi,
+                fine_time_ns: rx
+                    .fine_time_since_gps_epoch
+                    .as_ref()
+                    .map(|v| v.seconds * 1_000_000_000 + v.nanos as i64),
+            })
+        })
+        .collect();
+
+    (projection, receivers)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    // Speed of light (m/s).
+    const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+    // Returns the rx-info of the given gateway locations for a device at the given location.
+    fn get_rx_info(device: (f64, f64), gateways: &[(f64, f64)]) -> Vec<gw::UplinkRxInfo> {
+        let projection = Projection {
+            latitude: device.0,
+            longitude: device.1,
+        };
+
+        gateways
+            .iter()
+            .enumerate()
+            .map(|(i, (lat, lon))| {
+                let distance = projection
+                    .to_point(*lat, *lon)
+                    .distance(&Point { x: 0.0, y: 0.0 });
+                let tof_ns = (distance / SPEED_OF_LIGHT * 1e9).round() as i32;
+
+                gw::UplinkRxInfo {
+                    gateway_id: format!("010203040506070{}", i),
+                    rssi: (-40.0 - 27.0 * distance.log10()).round() as i32,
+                    location: Some(common::Location {
+                        latitude: *lat,
+                        longitude: *lon,
+                        altitude: 10.0,
+                        ..Default::default()
+                    }),
+                    fine_time_since_gps_epoch: Some(pbjson_types::Duration {
+                        seconds: 1_000_000_000,
+                        nanos: 500_000_000 + tof_ns,
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    fn distance(a: &common::Location, b: (f64, f64)) -> f64 {
+        Projection {
+            latitude: b.0,
+            longitude: b.1,
+        }
+        .to_point(a.latitude, a.longitude)
+        .distance(&Point { x: 0.0, y: 0.0 })
+    }
+
+    #[test]
+    fn test_projection() {
+        let projection = Projection {
+            latitude: 52.37,
+            longitude: 4.89,
+        };
+
+        let p = projection.to_point(52.38, 4.91);
+        assert!((p.y - 1111.95).abs() < 0.1);
+        assert!((p.x - 1357.82).abs() < 0.1);
+
+        let (lat, lon) = projection.to_coordinate(&p);
+        assert!((lat - 52.38).abs() < 1e-9);
+        assert!((lon - 4.91).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_get_distance() {
+        assert_eq!(0.0, get_distance(52.37, 4.89, 52.37, 4.89));
+
+        // Amsterdam - Paris
+        let d = get_distance(52.3676, 4.9041, 48.8566, 2.3522);
+        assert!((d - 430_000.0).abs() < 5_000.0);
+
+        // Within the range of a single uplink, this equals the projected distance.
+        let d = get_distance(52.38, 4.91, 52.37, 4.89);
+        assert!(
+            (d - distance(
+                &common::Location {
+                    latitude: 52.38,
+                    longitude: 4.91,
+                    ..Default::default()
+                },
+                (52.37, 4.89)
+            ))
+            .abs()
+                < 1.0
+        );
+    }
+
+    #[test]
+    fn test_resolve_tdoa() {
+        let device = (52.371, 4.892);
+        let gateways = [(52.38, 4.88), (52.36, 4.88), (52.37, 4.91), (52.375, 4.90)];
+
+        // Not enough gateways.
+        let rx_info = get_rx_info(device, &gateways[..2]);
+        assert!(resolve_tdoa(&rx_info, 3).is_none());
+
+        // Min gateways not met.
+        let rx_info = get_rx_info(device, &gateways[..3]);
+        assert!(resolve_tdoa(&rx_info, 4).is_none());
+
+        // Three gateways.
+        let loc = resolve_tdoa(&rx_info, 3).unwrap();
+        assert!(distance(&loc, device) < 5.0);
+        assert_eq!(common::LocationSource::GeoResolverTdoa, loc.source());
+        assert_eq!(10.0, loc.altitude);
+
+        // Four gateways.
+        let rx_info = get_rx_info(device, &gateways);
+        let loc = resolve_tdoa(&rx_info, 3).unwrap();
+        assert!(distance(&loc, device) < 5.0);
+        assert!(loc.accuracy > 0.0);
+
+        // Without fine-timestamp.
+        let mut rx_info = get_rx_info(device, &gateways[..3]);
+        rx_info[0].fine_time_since_gps_epoch = None;
+        assert!(resolve_tdoa(&rx_info, 3).is_none());
+
+        // Without gateway location.
+        let mut rx_info = get_rx_info(device, &gateways[..3]);
+        rx_info[0].location = None;
+        assert!(resolve_tdoa(&rx_info, 3).is_none());
+    }
+
+    #[test]
+    fn test_resolve_rssi() {
+        let device = (52.371, 4.892);
+        let gateways = [(52.38, 4.88), (52.36, 4.88), (52.37, 4.91)];
+
+        let rx_info = get_rx_info(device, &gateways[..2]);
+        assert!(resolve_rssi(&rx_info, 3).is_none());
+
+        let rx_info = get_rx_info(device, &gateways);
+        let loc = resolve_rssi(&rx_info, 3).unwrap();
+        assert_eq!(common::LocationSource::GeoResolverRssi, loc.source());
+
+        // The RSSI based location is an estimate, it must be closer to the device than the
+        // mean gateway location.
+        let (_, receivers) = get_receivers(&rx_info);
+        let centroid = Projection {
+            latitude: gateways.iter().map(|v| v.0).sum::<f64>() / 3.0,
+            longitude: gateways.iter().map(|v| v.1).sum::<f64>() / 3.0,
+        };
+        assert_eq!(3, receivers.len());
+        assert!(
+            distance(&loc, device)
+                < distance(
+                    &common::Location {
+                        latitude: centroid.latitude,
+                        longitude: centroid.longitude,
+                        ..Default::default()
+                    },
+                    device
+                )
+        );
+    }
+
+    #[test]
+    fn test_get_receivers_same_gateway() {
+        let device = (52.371, 4.892);
+        let gateways = [(52.38, 4.88), (52.36, 4.88), (52.37, 4.91)];
+
+        let mut rx_info = get_rx_info(device, &gateways);
+        let mut rx = rx_info[0].clone();
+        rx.rssi -= 10;
+        rx.antenna = 1;
+        rx_info.push(rx);
+
+        let (_, receivers) = get_receivers(&rx_info);
+        assert_eq!(3, receivers.len());
+        assert!(receivers.iter().any(|r| r.rssi == rx_info[0].rssi));
+    }
+}
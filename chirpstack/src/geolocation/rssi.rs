@@ -0,0 +1,48 @@
+use super::{Point, Receiver};
+
+// Path-loss exponent of the log-distance path-loss model. This is 2.0 in free space and higher
+// in (sub)urban environments.
+const PATH_LOSS_EXPONENT: f64 = 2.7;
+
+// Resolves the location as the weighted centroid of the receivers. The weight of each receiver
+// is inversely proportional to its distance to the device, as estimated from the RSSI using the
+// log-distance path-loss model.
+pub fn solve(receivers: &[Receiver], min_gateways: usize) -> Option<(Point, f64)> {
+    if receivers.len() < min_gateways {
+        return None;
+    }
+
+    // The distances are relative to the receiver with the highest RSSI, which has weight 1.
+    let max_rssi = receivers.iter().map(|r| r.rssi).max()?;
+    let weights: Vec<f64> = receivers
+        .iter()
+        .map(|r| 10f64.powf((r.rssi - max_rssi) as f64 / (10.0 * PATH_LOSS_EXPONENT)))
+        .collect();
+    let total: f64 = weights.iter().sum();
+
+    let p = Point {
+        x: receivers
+            .iter()
+            .zip(&weights)
+            .map(|(r, w)| r.point.x * w)
+            .sum::<f64>()
+            / total,
+        y: receivers
+            .iter()
+            .zip(&weights)
+            .map(|(r, w)| r.point.y * w)
+            .sum::<f64>()
+            / total,
+    };
+
+    // The accuracy is the weighted RMS distance between the location and the receivers.
+    let accuracy = (receivers
+        .iter()
+        .zip(&weights)
+        .map(|(r, w)| w * p.distance(&r.point).powi(2))
+        .sum::<f64>()
+        / total)
+        .sqrt();
+
+    Some((p, accuracy))
+}
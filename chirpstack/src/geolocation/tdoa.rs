@@ -0,0 +1,111 @@
+use super::{Point, Receiver};
+
+// Speed of light (m/s).
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+// Expected standard deviation of the fine-timestamps (ns). This is used as lower bound for the
+// ranging error when estimating the accuracy.
+const FINE_TIMESTAMP_STD_DEV_NS: f64 = 20.0;
+
+const MAX_ITERATIONS: usize = 50;
+
+// The solver has converged when the position update is smaller than this distance (meters).
+const CONVERGENCE_DISTANCE: f64 = 0.01;
+
+// Resolves the location using the time-difference of arrival. The receiver with the earliest
+// fine-timestamp is used as reference and the location is solved (non-linear least squares,
+// using the Gauss-Newton method) from the range-differences to this reference receiver.
+pub fn solve(receivers: &[Receiver], min_gateways: usize) -> Option<(Point, f64)> {
+    let mut receivers: Vec<(Point, i64)> = receivers
+        .iter()
+        .filter_map(|r| r.fine_time_ns.map(|t| (r.point, t)))
+        .collect();
+    receivers.sort_by_key(|(_, t)| *t);
+
+    let (reference, reference_t) = *receivers.first()?;
+
+    // The range-difference can't exceed the distance between both receivers. If it does, the
+    // clock of the receiver is not in sync and the receiver is ignored.
+    let tolerance = 3.0 * FINE_TIMESTAMP_STD_DEV_NS * SPEED_OF_LIGHT / 1e9;
+    let measurements: Vec<(Point, f64)> = receivers[1..]
+        .iter()
+        .map(|(p, t)| (*p, (t - reference_t) as f64 * SPEED_OF_LIGHT / 1e9))
+        .filter(|(p, range_diff)| *range_diff <= p.distance(&reference) + tolerance)
+        .collect();
+
+    if measurements.len() + 1 < min_gateways {
+        return None;
+    }
+
+    // Start at the centroid of the receivers.
+    let count = (measurements.len() + 1) as f64;
+    let mut p = Point {
+        x: (reference.x + measurements.iter().map(|(g, _)| g.x).sum::<f64>()) / count,
+        y: (reference.y + measurements.iter().map(|(g, _)| g.y).sum::<f64>()) / count,
+    };
+
+    for _ in 0..MAX_ITERATIONS {
+        let (jtj, jtr, _) = linearize(&p, &reference, &measurements);
+        let det = jtj[0][0] * jtj[1][1] - jtj[0][1] * jtj[1][0];
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let dx = -(jtj[1][1] * jtr[0] - jtj[0][1] * jtr[1]) / det;
+        let dy = -(jtj[0][0] * jtr[1] - jtj[1][0] * jtr[0]) / det;
+        p.x += dx;
+        p.y += dy;
+
+        if dx.hypot(dy) < CONVERGENCE_DISTANCE {
+            let (jtj, _, sse) = linearize(&p, &reference, &measurements);
+            let det = jtj[0][0] * jtj[1][1] - jtj[0][1] * jtj[1][0];
+            if det.abs() < 1e-12 {
+                return None;
+            }
+
+            // The covariance of the solution is sigma^2 * (J^T J)^-1, sigma being the ranging
+            // error. The accuracy is the square root of the trace of this covariance.
+            let sigma = (sse / measurements.len() as f64)
+                .sqrt()
+                .max(FINE_TIMESTAMP_STD_DEV_NS * SPEED_OF_LIGHT / 1e9);
+            let accuracy = sigma * ((jtj[0][0] + jtj[1][1]) / det).sqrt();
+
+            return Some((p, accuracy));
+        }
+    }
+
+    None
+}
+
+// Returns J^T J, J^T r and the sum of the squared residuals at the given position, J being the
+// Jacobian of the range-difference residuals r.
+fn linearize(
+    p: &Point,
+    reference: &Point,
+    measurements: &[(Point, f64)],
+) -> ([[f64; 2]; 2], [f64; 2], f64) {
+    let mut jtj = [[0.0; 2]; 2];
+    let mut jtr = [0.0; 2];
+    let mut sse = 0.0;
+
+    let d_ref = p.distance(reference).max(1.0);
+
+    for (g, range_diff) in measurements {
+        let d = p.distance(g).max(1.0);
+        let r = d - d_ref - range_diff;
+        let j = [
+            (p.x - g.x) / d - (p.x - reference.x) / d_ref,
+            (p.y - g.y) / d - (p.y - reference.y) / d_ref,
+        ];
+
+        jtj[0][0] += j[0] * j[0];
+        jtj[0][1] += j[0] * j[1];
+        jtj[1][0] += j[1] * j[0];
+        jtj[1][1] += j[1] * j[1];
+        jtr[0] += j[0] * r;
+        jtr[1] += j[1] * r;
+        sse += r * r;
+    }
+
+    (jtj, jtr, sse)
+}
@@ -1,3 +1,4 @@
 pub mod errors;
+pub mod tasks;
 pub mod tls;
 pub mod tls22; // rustls 0.22
@@ -0,0 +1,43 @@
+use std::sync::Mutex;
+
+use tokio::task::JoinHandle;
+
+// Background tasks (e.g. the receive loop of a backend) which must be stopped when their owner
+// is closed.
+#[derive(Default)]
+pub struct Tasks(Mutex<Vec<JoinHandle<()>>>);
+
+impl Tasks {
+    pub fn push(&self, handle: JoinHandle<()>) {
+        self.0.lock().unwrap().push(handle);
+    }
+
+    // Aborts the tasks and waits until they have been terminated.
+    pub async fn abort(&self) {
+        let handles: Vec<JoinHandle<()>> = self.0.lock().unwrap().drain(..).collect();
+        for handle in handles {
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_tasks() {
+        let tasks = Tasks::default();
+        let handle = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        let abort_handle = handle.abort_handle();
+        tasks.push(handle);
+
+        assert!(!abort_handle.is_finished());
+        tasks.abort().await;
+        assert!(abort_handle.is_finished());
+    }
+}
@@ -0,0 +1,298 @@
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use tokio::task;
+use tracing::info;
+
+use crate::config;
+use lrwn::{keys, AES128Key, JoinType, NetID, Payload, PhyPayload, EUI64};
+
+lazy_static! {
+    static ref SESSION: Mutex<Option<Session>> = Mutex::new(None);
+}
+
+// Root-key of the device, stored in the HSM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKey {
+    NwkKey,
+    AppKey,
+}
+
+// Session-keys derived by the HSM on join.
+pub struct SessionKeys {
+    pub f_nwk_s_int_key: AES128Key,
+    pub s_nwk_s_int_key: AES128Key,
+    pub nwk_s_enc_key: AES128Key,
+    pub app_s_key: AES128Key,
+}
+
+pub fn setup() -> Result<()> {
+    let conf = config::get();
+    if conf.hsm.library_path.is_empty() {
+        *SESSION.lock().unwrap() = None;
+        return Ok(());
+    }
+
+    info!(library_path = %conf.hsm.library_path, slot = conf.hsm.slot, "Setting up PKCS#11 HSM session");
+
+    let pkcs11 = Pkcs11::new(&conf.hsm.library_path).context("Load PKCS#11 library")?;
+    pkcs11
+        .initialize(CInitializeArgs::OsThreads)
+        .context("Initialize PKCS#11 library")?;
+
+    let slot = pkcs11
+        .get_slots_with_token()
+        .context("Get PKCS#11 slots")?
+        .into_iter()
+        .find(|s| s.id() == conf.hsm.slot)
+        .ok_or_else(|| anyhow!("PKCS#11 slot {} does not exist", conf.hsm.slot))?;
+
+    let session = pkcs11
+        .open_ro_session(slot)
+        .context("Open PKCS#11 session")?;
+    session
+        .login(UserType::User, Some(&AuthPin::new(conf.hsm.pin.clone())))
+        .context("PKCS#11 login")?;
+
+    *SESSION.lock().unwrap() = Some(session);
+
+    Ok(())
+}
+
+// Returns true when the HSM has been configured.
+pub fn enabled() -> bool {
+    SESSION.lock().unwrap().is_some()
+}
+
+// Returns the label of the root-key object of the given device. The root-keys must be
+// provisioned in the HSM as AES secret-key objects using this label.
+pub fn get_key_label(dev_eui: &EUI64, root_key: RootKey) -> String {
+    format!(
+        "{}:{}",
+        match root_key {
+            RootKey::NwkKey => "nwk_key",
+            RootKey::AppKey => "app_key",
+        },
+        dev_eui
+    )
+}
+
+// Returns the label of the KEK object with the given label. KEKs used for unwrapping wrapped
+// root-keys must be provisioned in the HSM as AES secret-key objects using this label.
+pub fn get_kek_label(label: &str) -> String {
+    format!("kek:{}", label)
+}
+
+// Unwraps the given (RFC 3394) wrapped key using the KEK stored in the HSM.
+pub fn unwrap_key(kek_label: &str, wrapped: &[u8]) -> Result<AES128Key> {
+    let b = with_key_label(&get_kek_label(kek_label), |session, key| {
+        session.decrypt(&Mechanism::AesKeyWrap, key, wrapped)
+    })?;
+
+    Ok(AES128Key::from_slice(&b)?)
+}
+
+// Validates the join-request MIC using the NwkKey stored in the HSM.
+pub async fn validate_join_request_mic(dev_eui: &EUI64, phy: &PhyPayload) -> Result<bool> {
+    let mic = match phy.mic {
+        Some(v) => v,
+        None => return Ok(false),
+    };
+
+    let dev_eui = *dev_eui;
+    let mic_bytes = phy.join_request_mic_bytes()?;
+    let hash = task::spawn_blocking(move || {
+        with_key(&dev_eui, RootKey::NwkKey, |session, key| {
+            session.sign(&Mechanism::AesCMac, key, &mic_bytes)
+        })
+    })
+    .await??;
+
+    Ok(hash.len() >= 4 && hash[0..4] == mic)
+}
+
+// Sets the join-accept MIC, encrypts the join-accept and derives the session-keys using the
+// root-keys stored in the HSM.
+pub async fn set_join_accept_and_get_session_keys(
+    dev_eui: &EUI64,
+    join_eui: &EUI64,
+    dev_nonce: u16,
+    net_id: &NetID,
+    join_nonce: u32,
+    opt_neg: bool,
+    mut phy: PhyPayload,
+) -> Result<(PhyPayload, SessionKeys)> {
+    let dev_eui = *dev_eui;
+    let join_eui = *join_eui;
+    let net_id = *net_id;
+
+    task::spawn_blocking(move || {
+        set_join_accept_mic(&dev_eui, &mut phy, JoinType::Join, &join_eui, dev_nonce)?;
+        encrypt_join_accept_payload(&dev_eui, &mut phy)?;
+
+        let get_s_key = |typ: u8, root_key: RootKey| -> Result<AES128Key> {
+            get_key(
+                &dev_eui,
+                root_key,
+                &keys::get_s_key_block(opt_neg, typ, &net_id, &join_eui, join_nonce, dev_nonce),
+            )
+        };
+
+        // For LoRaWAN 1.0.x: SNwkSIntKey = NwkSEncKey = FNwkSIntKey = NwkSKey and the AppSKey
+        // is derived from the NwkKey.
+        let session_keys = SessionKeys {
+            f_nwk_s_int_key: get_s_key(0x01, RootKey::NwkKey)?,
+            s_nwk_s_int_key: get_s_key(if opt_neg { 0x03 } else { 0x01 }, RootKey::NwkKey)?,
+            nwk_s_enc_key: get_s_key(if opt_neg { 0x04 } else { 0x01 }, RootKey::NwkKey)?,
+            app_s_key: get_s_key(
+                0x02,
+                if opt_neg {
+                    RootKey::AppKey
+                } else {
+                    RootKey::NwkKey
+                },
+            )?,
+        };
+
+        Ok((phy, session_keys))
+    })
+    .await?
+}
+
+// Sets the join-accept MIC. For LoRaWAN 1.0.x, the MIC is calculated by the HSM using the
+// NwkKey. For LoRaWAN 1.1, the MIC is calculated using the JSIntKey which is derived from the
+// NwkKey by the HSM.
+fn set_join_accept_mic(
+    dev_eui: &EUI64,
+    phy: &mut PhyPayload,
+    join_req_type: JoinType,
+    join_eui: &EUI64,
+    dev_nonce: u16,
+) -> Result<()> {
+    let opt_neg = match &phy.payload {
+        Payload::JoinAccept(pl) => pl.dl_settings.opt_neg,
+        _ => return Err(anyhow!("Payload must be of type JoinAcceptPayload")),
+    };
+
+    if opt_neg {
+        let js_int_key = get_key(
+            dev_eui,
+            RootKey::NwkKey,
+            &keys::get_js_key_block(0x06, dev_eui),
+        )?;
+        return phy.set_join_accept_mic(join_req_type, join_eui, dev_nonce, &js_int_key);
+    }
+
+    let mic_bytes = phy.join_accept_mic_bytes(join_req_type, join_eui, dev_nonce)?;
+    let hash = with_key(dev_eui, RootKey::NwkKey, |session, key| {
+        session.sign(&Mechanism::AesCMac, key, &mic_bytes)
+    })?;
+    if hash.len() < 4 {
+        return Err(anyhow!("Hash is less than 4 bytes"));
+    }
+
+    let mut mic: [u8; 4] = [0; 4];
+    mic.copy_from_slice(&hash[0..4]);
+    phy.mic = Some(mic);
+
+    Ok(())
+}
+
+// Encrypts the join-accept payload using the NwkKey stored in the HSM. Note that the MIC must
+// be set first, as the MIC is part of the encrypted payload.
+fn encrypt_join_accept_payload(dev_eui: &EUI64, phy: &mut PhyPayload) -> Result<()> {
+    let mic = phy.mic.ok_or_else(|| anyhow!("MIC must be set first"))?;
+    let mut pt = match &phy.payload {
+        Payload::JoinAccept(pl) => pl.to_vec()?,
+        _ => return Err(anyhow!("Payload must be of type JoinAcceptPayload")),
+    };
+    pt.extend_from_slice(&mic);
+
+    if pt.len() % 16 != 0 {
+        return Err(anyhow!("Plaintext must be a multiple of 16 bytes"));
+    }
+
+    // The join-accept is encrypted using the AES decrypt operation, such that the end-device
+    // only needs to implement the AES encrypt operation.
+    let ct = with_key(dev_eui, RootKey::NwkKey, |session, key| {
+        session.decrypt(&Mechanism::AesEcb, key, &pt)
+    })?;
+    if ct.len() != pt.len() {
+        return Err(anyhow!("Unexpected ciphertext length"));
+    }
+
+    let mut mic: [u8; 4] = [0; 4];
+    mic.copy_from_slice(&ct[ct.len() - 4..]);
+    phy.payload = Payload::Raw(ct[..ct.len() - 4].to_vec());
+    phy.mic = Some(mic);
+
+    Ok(())
+}
+
+// Returns the key derived by the HSM, by encrypting the given key-derivation block using the
+// root-key. See lrwn::keys::get_s_key_block and lrwn::keys::get_js_key_block.
+fn get_key(dev_eui: &EUI64, root_key: RootKey, block: &[u8; 16]) -> Result<AES128Key> {
+    let b = with_key(dev_eui, root_key, |session, key| {
+        session.encrypt(&Mechanism::AesEcb, key, block)
+    })?;
+
+    Ok(AES128Key::from_slice(&b)?)
+}
+
+fn with_key<T, F>(dev_eui: &EUI64, root_key: RootKey, f: F) -> Result<T>
+where
+    F: FnOnce(&Session, ObjectHandle) -> Result<T, cryptoki::error::Error>,
+{
+    with_key_label(&get_key_label(dev_eui, root_key), f)
+}
+
+fn with_key_label<T, F>(label: &str, f: F) -> Result<T>
+where
+    F: FnOnce(&Session, ObjectHandle) -> Result<T, cryptoki::error::Error>,
+{
+    let session = SESSION.lock().unwrap();
+    let session = session
+        .as_ref()
+        .ok_or_else(|| anyhow!("PKCS#11 HSM is not configured"))?;
+
+    let key = session
+        .find_objects(&[
+            Attribute::Class(ObjectClass::SECRET_KEY),
+            Attribute::Label(label.as_bytes().to_vec()),
+        ])
+        .context("Find HSM key object")?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("HSM key object {} does not exist", label))?;
+
+    f(session, key).with_context(|| format!("HSM operation using key {}", label))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_key_label() {
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(
+            "nwk_key:0102030405060708",
+            get_key_label(&dev_eui, RootKey::NwkKey)
+        );
+        assert_eq!(
+            "app_key:0102030405060708",
+            get_key_label(&dev_eui, RootKey::AppKey)
+        );
+    }
+
+    #[test]
+    fn test_get_kek_label() {
+        assert_eq!("kek:se-kek", get_kek_label("se-kek"));
+    }
+}
@@ -11,7 +11,7 @@ use serde::Serialize;
 use tokio::sync::RwLock;
 use tracing::{error, info};
 
-use super::Integration as IntegrationTrait;
+use super::{get_fuota_event_name, Integration as IntegrationTrait};
 use crate::config::AmqpIntegration as Config;
 use chirpstack_api::integration;
 
@@ -37,6 +37,20 @@ struct EventRoutingKeyContext {
     pub event: String,
 }
 
+#[derive(Serialize)]
+struct GatewayEventRoutingKeyContext {
+    pub tenant_id: String,
+    pub gateway_id: String,
+    pub event: String,
+}
+
+#[derive(Serialize)]
+struct FuotaEventRoutingKeyContext {
+    pub application_id: String,
+    pub deployment_id: String,
+    pub event: String,
+}
+
 impl<'a> Integration<'a> {
     pub async fn new(conf: &Config) -> Result<Integration<'a>> {
         info!("Initializing AMQP integration");
@@ -45,6 +59,12 @@ impl<'a> Integration<'a> {
         let mut templates = Handlebars::new();
         templates.register_escape_fn(handlebars::no_escape);
         templates.register_template_string("event_routing_key", &conf.event_routing_key)?;
+        templates.register_template_string(
+            "gateway_event_routing_key",
+            &conf.gateway_event_routing_key,
+        )?;
+        templates
+            .register_template_string("fuota_event_routing_key", &conf.fuota_event_routing_key)?;
 
         let i = Integration {
             templates,
@@ -125,6 +145,38 @@ impl<'a> Integration<'a> {
             },
         )?)
     }
+
+    fn get_fuota_routing_key(
+        &self,
+        application_id: &str,
+        deployment_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "fuota_event_routing_key",
+            &FuotaEventRoutingKeyContext {
+                application_id: application_id.to_string(),
+                deployment_id: deployment_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
+
+    fn get_gateway_routing_key(
+        &self,
+        tenant_id: &str,
+        gateway_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "gateway_event_routing_key",
+            &GatewayEventRoutingKeyContext {
+                tenant_id: tenant_id.to_string(),
+                gateway_id: gateway_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
 }
 
 #[async_trait]
@@ -240,6 +292,36 @@ impl IntegrationTrait for Integration<'_> {
         };
         self.publish_event(key, &b).await
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        let key = self.get_fuota_routing_key(
+            &pl.application_id,
+            &pl.deployment_id,
+            &get_fuota_event_name(pl),
+        )?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+        self.publish_event(key, &b).await
+    }
+
+    async fn gateway_status_event(&self, pl: &integration::GatewayStatusEvent) -> Result<()> {
+        let key = self.get_gateway_routing_key(
+            &pl.tenant_id,
+            &pl.gateway_id,
+            if pl.online { "online" } else { "offline" },
+        )?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+        self.publish_event(key, &b).await
+    }
 }
 
 #[cfg(all(test, feature = "test-integration-amqp"))]
@@ -266,6 +348,10 @@ pub mod test {
             json: true,
             event_routing_key: "application.{{application_id}}.device.{{dev_eui}}.event.{{event}}"
                 .to_string(),
+            gateway_event_routing_key:
+                "tenant.{{tenant_id}}.gateway.{{gateway_id}}.event.{{event}}".to_string(),
+            fuota_event_routing_key:
+                "application.{{application_id}}.fuota.{{deployment_id}}.event.{{event}}".to_string(),
         };
 
         let conn = loop {
@@ -233,6 +233,26 @@ impl IntegrationTrait for Integration {
         self.publish("integration", &di.application_id, &di.dev_eui, &pl)
             .await
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        // The DevEUI is only set for device events.
+        let dev_eui = pl
+            .device_info
+            .as_ref()
+            .map(|v| v.dev_eui.clone())
+            .unwrap_or_default();
+        let b = match self.json {
+            true => serde_json::to_string(&pl)?,
+            false => general_purpose::STANDARD.encode(pl.encode_to_vec()),
+        };
+
+        self.publish("fuota", &pl.application_id, &dev_eui, &b)
+            .await
+    }
 }
 
 type HmacSha256 = Hmac<Sha256>;
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use tracing::{info, trace};
+use uuid::Uuid;
+
+use super::Integration as IntegrationTrait;
+use crate::geolocation;
+use crate::integration::location_event;
+use crate::storage::application::GeolocationConfiguration;
+use chirpstack_api::integration;
+
+pub struct Integration {
+    config: GeolocationConfiguration,
+}
+
+impl Integration {
+    pub fn new(conf: &GeolocationConfiguration) -> Integration {
+        trace!("Initializing Geolocation integration");
+
+        Integration {
+            config: conf.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl IntegrationTrait for Integration {
+    async fn uplink_event(
+        &self,
+        vars: &HashMap<String, String>,
+        pl: &integration::UplinkEvent,
+    ) -> Result<()> {
+        let min_gateways = self.config.min_gateways as usize;
+
+        // TDOA is more accurate than RSSI, RSSI is used as fallback.
+        let mut loc = None;
+        if self.config.tdoa {
+            loc = geolocation::resolve_tdoa(&pl.rx_info, min_gateways);
+        }
+        if loc.is_none() && self.config.rssi {
+            loc = geolocation::resolve_rssi(&pl.rx_info, min_gateways);
+        }
+
+        if let Some(v) = loc {
+            let di = pl.device_info.as_ref().unwrap();
+            info!(dev_eui = %di.dev_eui, source = ?v.source(), accuracy = v.accuracy, "Device location resolved");
+
+            let loc_pl = integration::LocationEvent {
+                deduplication_id: pl.deduplication_id.clone(),
+                time: Some(Utc::now().into()),
+                device_info: pl.device_info.clone(),
+                location: Some(v),
+            };
+
+            location_event(Uuid::from_str(&di.application_id)?, vars, &loc_pl).await;
+        }
+
+        Ok(())
+    }
+
+    async fn join_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::JoinEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn ack_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::AckEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn txack_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::TxAckEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn log_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::LogEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn status_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::StatusEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn location_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::LocationEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn integration_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::IntegrationEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::integration::{self as int, mock};
+    use crate::test;
+    use chirpstack_api::{common, gw};
+    use tokio::time::{sleep, Duration};
+
+    #[tokio::test]
+    async fn test_geolocation() {
+        let _guard = test::prepare().await;
+        int::set_mock().await;
+
+        let pl = integration::UplinkEvent {
+            deduplication_id: Uuid::new_v4().to_string(),
+            device_info: Some(integration::DeviceInfo {
+                application_id: Uuid::new_v4().to_string(),
+                dev_eui: "0102030405060708".into(),
+                ..Default::default()
+            }),
+            rx_info: [(52.38, 4.88, -90), (52.36, 4.88, -110), (52.37, 4.91, -105)]
+                .iter()
+                .enumerate()
+                .map(|(i, (lat, lon, rssi))| gw::UplinkRxInfo {
+                    gateway_id: format!("010203040506070{}", i),
+                    rssi: *rssi,
+                    location: Some(common::Location {
+                        latitude: *lat,
+                        longitude: *lon,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        // TDOA only, the uplink has no fine-timestamps.
+        mock::reset().await;
+        let i = Integration::new(&GeolocationConfiguration {
+            tdoa: true,
+            rssi: false,
+            min_gateways: 3,
+        });
+        i.uplink_event(&HashMap::new(), &pl).await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        assert!(mock::get_location_events().await.is_empty());
+
+        // TDOA with RSSI fallback.
+        mock::reset().await;
+        let i = Integration::new(&GeolocationConfiguration {
+            tdoa: true,
+            rssi: true,
+            min_gateways: 3,
+        });
+        i.uplink_event(&HashMap::new(), &pl).await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+
+        let events = mock::get_location_events().await;
+        assert_eq!(1, events.len());
+        assert_eq!(pl.deduplication_id, events[0].deduplication_id);
+
+        let loc = events[0].location.as_ref().unwrap();
+        assert_eq!(common::LocationSource::GeoResolverRssi, loc.source());
+
+        // The location is closest to the gateway with the highest RSSI.
+        assert!(loc.latitude > 52.37);
+        assert!(loc.longitude < 4.89);
+
+        // Min gateways not met.
+        mock::reset().await;
+        let i = Integration::new(&GeolocationConfiguration {
+            tdoa: false,
+            rssi: true,
+            min_gateways: 4,
+        });
+        i.uplink_event(&HashMap::new(), &pl).await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        assert!(mock::get_location_events().await.is_empty());
+    }
+}
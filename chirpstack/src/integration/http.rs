@@ -10,6 +10,7 @@ use reqwest::Client;
 use tracing::{info, trace, warn};
 
 use super::Integration as IntegrationTrait;
+use crate::monitoring;
 use crate::storage::application::HttpConfiguration;
 use chirpstack_api::integration;
 
@@ -60,6 +61,11 @@ impl Integration {
             headers.insert(CONTENT_TYPE, "application/octet-stream".parse().unwrap());
         }
 
+        // Propagate the trace context (if enabled).
+        monitoring::otel::inject_context(&mut headers);
+
+        let mut failed = 0;
+
         for url in &self.endpoints {
             info!(event = %event, url = %url, "Posting event");
             let res = get_client()
@@ -76,14 +82,25 @@ impl Integration {
                     Ok(_) => {}
                     Err(e) => {
                         warn!(event = %event, url = %url, error = %e, "Posting event failed");
+                        failed += 1;
                     }
                 },
                 Err(e) => {
                     warn!(event = %event, url = %url, error = %e, "Posting event failed");
+                    failed += 1;
                 }
             }
         }
 
+        // Return an error, such that the failure is reflected in the integration metrics.
+        if failed != 0 {
+            return Err(anyhow!(
+                "Posting event failed for {} of {} endpoints",
+                failed,
+                self.endpoints.len()
+            ));
+        }
+
         Ok(())
     }
 }
@@ -193,6 +210,19 @@ impl IntegrationTrait for Integration {
 
         self.post_event("integration", b).await
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+
+        self.post_event("fuota", b).await
+    }
 }
 
 #[cfg(test)]
@@ -354,5 +384,16 @@ pub mod test {
         i.integration_event(&HashMap::new(), &pl).await.unwrap();
         mock.assert();
         mock.delete();
+
+        // endpoint returns an error
+        let pl: integration::UplinkEvent = Default::default();
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/").query_param("event", "up");
+
+            then.status(500);
+        });
+        assert!(i.uplink_event(&HashMap::new(), &pl).await.is_err());
+        mock.assert();
+        mock.delete();
     }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::io::Cursor;
 use std::time::Duration;
 
 use anyhow::Result;
@@ -6,13 +7,17 @@ use async_trait::async_trait;
 use handlebars::Handlebars;
 use prost::Message;
 use rdkafka::config::ClientConfig;
-use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, Message as KafkaMessage, OwnedHeaders};
 use rdkafka::producer::{FutureProducer, FutureRecord};
+use regex::Regex;
 use serde::Serialize;
-use tracing::{error, info};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
 
-use super::Integration as IntegrationTrait;
+use super::{get_fuota_event_name, Integration as IntegrationTrait};
 use crate::config::KafkaIntegration as Config;
+use crate::helpers::tasks::Tasks;
 use chirpstack_api::integration;
 
 pub struct Integration<'a> {
@@ -20,6 +25,7 @@ pub struct Integration<'a> {
     topic: String,
     json: bool,
     producer: FutureProducer,
+    tasks: Tasks,
 }
 
 #[derive(Serialize)]
@@ -29,6 +35,34 @@ struct EventKeyContext {
     pub event: String,
 }
 
+#[derive(Serialize)]
+struct GatewayEventKeyContext {
+    pub tenant_id: String,
+    pub gateway_id: String,
+    pub event: String,
+}
+
+#[derive(Serialize)]
+struct FuotaEventKeyContext {
+    pub application_id: String,
+    pub deployment_id: String,
+    pub event: String,
+}
+
+#[derive(Serialize)]
+struct CommandKeyContext {
+    pub application_id: String,
+    pub dev_eui: String,
+    pub command: String,
+}
+
+#[derive(Serialize)]
+struct MulticastCommandKeyContext {
+    pub application_id: String,
+    pub multicast_group_id: String,
+    pub command: String,
+}
+
 impl<'a> Integration<'a> {
     pub fn new(conf: &Config) -> Result<Integration<'a>> {
         info!("Initializing Kafka integration");
@@ -37,6 +71,10 @@ impl<'a> Integration<'a> {
         let mut templates = Handlebars::new();
         templates.register_escape_fn(handlebars::no_escape);
         templates.register_template_string("event_key", &conf.event_key)?;
+        templates.register_template_string("gateway_event_key", &conf.gateway_event_key)?;
+        templates.register_template_string("fuota_event_key", &conf.fuota_event_key)?;
+        templates.register_template_string("command_key", &conf.command_key)?;
+        templates.register_template_string("multicast_command_key", &conf.multicast_command_key)?;
 
         let producer: FutureProducer = ClientConfig::new()
             .set("bootstrap.servers", conf.brokers.join(","))
@@ -59,11 +97,88 @@ impl<'a> Integration<'a> {
             .set("sasl.password", &conf.password)
             .create()?;
 
+        let tasks = Tasks::default();
+        if !conf.command_topic.is_empty() {
+            let command_regex = get_command_key_regex(&templates.render(
+                "command_key",
+                &CommandKeyContext {
+                    application_id: "__application_id__".to_string(),
+                    dev_eui: "__dev_eui__".to_string(),
+                    command: "__command__".to_string(),
+                },
+            )?)?;
+            let multicast_command_regex = get_command_key_regex(&templates.render(
+                "multicast_command_key",
+                &MulticastCommandKeyContext {
+                    application_id: "__application_id__".to_string(),
+                    multicast_group_id: "__multicast_group_id__".to_string(),
+                    command: "__command__".to_string(),
+                },
+            )?)?;
+
+            let consumer: StreamConsumer = ClientConfig::new()
+                .set("bootstrap.servers", conf.brokers.join(","))
+                .set("group.id", &conf.consumer_group)
+                .set("enable.auto.commit", "false")
+                .set("auto.offset.reset", "latest")
+                .set(
+                    "sasl.mechanism",
+                    match conf.mechanism.as_ref() {
+                        "PLAIN" => "PLAIN",
+                        "SCRAM-SHA-256" => "SCRAM-SHA-256",
+                        "SCRAM-SHA-512" => "SCRAM-SHA-512",
+                        _ => {
+                            return Err(anyhow!(
+                                "mechanism must be PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512"
+                            ));
+                        }
+                    },
+                )
+                .set("sasl.username", &conf.username)
+                .set("sasl.password", &conf.password)
+                .create()?;
+            consumer.subscribe(&[&conf.command_topic])?;
+
+            info!(command_topic = %conf.command_topic, consumer_group = %conf.consumer_group, "Consuming commands from Kafka");
+
+            // Consumer loop
+            tasks.push(tokio::spawn({
+                let json = conf.json;
+
+                async move {
+                    loop {
+                        match consumer.recv().await {
+                            Ok(m) => {
+                                message_callback(
+                                    &command_regex,
+                                    &multicast_command_regex,
+                                    json,
+                                    &m,
+                                )
+                                .await;
+
+                                // The offset is committed after the command has been handled,
+                                // such that commands are not lost on a restart.
+                                if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
+                                    error!(error = %e, "Kafka commit error");
+                                }
+                            }
+                            Err(e) => {
+                                error!(error = %e, "Kafka consumer error");
+                                sleep(Duration::from_secs(1)).await
+                            }
+                        }
+                    }
+                }
+            }));
+        }
+
         let i = Integration {
             templates,
             producer,
             json: conf.json,
             topic: conf.topic.clone(),
+            tasks,
         };
 
         Ok(i)
@@ -104,6 +219,38 @@ impl<'a> Integration<'a> {
             },
         )?)
     }
+
+    fn get_fuota_event_key(
+        &self,
+        application_id: &str,
+        deployment_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "fuota_event_key",
+            &FuotaEventKeyContext {
+                application_id: application_id.to_string(),
+                deployment_id: deployment_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
+
+    fn get_gateway_event_key(
+        &self,
+        tenant_id: &str,
+        gateway_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "gateway_event_key",
+            &GatewayEventKeyContext {
+                tenant_id: tenant_id.to_string(),
+                gateway_id: gateway_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
 }
 
 #[async_trait]
@@ -219,26 +366,353 @@ impl IntegrationTrait for Integration<'_> {
         };
         self.publish_event("integration", key, &b).await
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        let event = get_fuota_event_name(pl);
+        let key = self.get_fuota_event_key(&pl.application_id, &pl.deployment_id, &event)?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+        self.publish_event(&event, key, &b).await
+    }
+
+    async fn gateway_status_event(&self, pl: &integration::GatewayStatusEvent) -> Result<()> {
+        let event = if pl.online { "online" } else { "offline" };
+        let key = self.get_gateway_event_key(&pl.tenant_id, &pl.gateway_id, event)?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+        self.publish_event(event, key, &b).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        info!("Closing Kafka integration");
+        self.tasks.abort().await;
+        Ok(())
+    }
 }
 
-#[cfg(all(test, feature = "test-integration-kafka"))]
-pub mod test {
-    use std::env;
+enum Command {
+    Down(String, integration::DownlinkCommand),
+    MulticastDown(String, integration::MulticastDownlinkCommand),
+}
+
+// Returns the regex for matching the command keys, given the rendered key template in which the
+// variables are replaced by __name__ placeholders. The regex is anchored and the rest of the
+// template is escaped, e.g. such that the dots in the default template are matched literally.
+fn get_command_key_regex(key: &str) -> Result<Regex> {
+    let mut re = regex::escape(key);
+    for (name, pattern) in [
+        ("application_id", r"[\w-]+"),
+        ("multicast_group_id", r"[\w-]+"),
+        ("dev_eui", r"\w+"),
+        ("command", r"\w+"),
+    ] {
+        re = re.replace(
+            &format!("__{}__", name),
+            &format!("(?P<{}>{})", name, pattern),
+        );
+    }
 
+    Ok(Regex::new(&format!("^{}$", re))?)
+}
+
+// Handles the command received from the command topic. The key of the message is used to
+// determine the command type and the device or multicast-group to which it applies.
+async fn message_callback(
+    command_regex: &Regex,
+    multicast_command_regex: &Regex,
+    json: bool,
+    m: &BorrowedMessage<'_>,
+) {
+    let key = String::from_utf8_lossy(m.key().unwrap_or_default()).to_string();
+
+    info!(topic = %m.topic(), key = %key, "Command received");
+
+    match parse_command(
+        command_regex,
+        multicast_command_regex,
+        json,
+        &key,
+        m.payload().unwrap_or_default(),
+    ) {
+        Ok(Command::Down(application_id, cmd)) => {
+            super::handle_down_command(application_id, cmd).await
+        }
+        Ok(Command::MulticastDown(application_id, cmd)) => {
+            super::handle_multicast_down_command(application_id, cmd).await
+        }
+        Err(err) => {
+            warn!(topic = %m.topic(), key = %key, "Processing command error: {}", err);
+        }
+    }
+}
+
+fn parse_command(
+    command_regex: &Regex,
+    multicast_command_regex: &Regex,
+    json: bool,
+    key: &str,
+    b: &[u8],
+) -> Result<Command> {
+    if let Some(caps) = multicast_command_regex.captures(key) {
+        let application_id = caps.name("application_id").map_or("", |m| m.as_str());
+        let multicast_group_id = caps.name("multicast_group_id").map_or("", |m| m.as_str());
+
+        return match caps.name("command").map_or("", |m| m.as_str()) {
+            "down" => {
+                let cmd: integration::MulticastDownlinkCommand = match json {
+                    true => serde_json::from_slice(b)?,
+                    false => integration::MulticastDownlinkCommand::decode(&mut Cursor::new(b))?,
+                };
+                if multicast_group_id != cmd.multicast_group_id {
+                    return Err(anyhow!(
+                        "Payload multicast_group_id {} does not match key multicast_group_id {}",
+                        cmd.multicast_group_id,
+                        multicast_group_id
+                    ));
+                }
+                Ok(Command::MulticastDown(application_id.to_string(), cmd))
+            }
+            _ => Err(anyhow!("Unknown command type")),
+        };
+    }
+
+    if let Some(caps) = command_regex.captures(key) {
+        let application_id = caps.name("application_id").map_or("", |m| m.as_str());
+        let dev_eui = caps.name("dev_eui").map_or("", |m| m.as_str());
+
+        return match caps.name("command").map_or("", |m| m.as_str()) {
+            "down" => {
+                let cmd: integration::DownlinkCommand = match json {
+                    true => serde_json::from_slice(b)?,
+                    false => integration::DownlinkCommand::decode(&mut Cursor::new(b))?,
+                };
+                if dev_eui != cmd.dev_eui {
+                    return Err(anyhow!(
+                        "Payload dev_eui {} does not match key dev_eui {}",
+                        cmd.dev_eui,
+                        dev_eui
+                    ));
+                }
+                Ok(Command::Down(application_id.to_string(), cmd))
+            }
+            _ => Err(anyhow!("Unknown command type")),
+        };
+    }
+
+    Err(anyhow!("Error parsing command key"))
+}
+
+#[cfg(test)]
+pub mod test {
     use super::*;
-    use crate::test;
-    use rdkafka::consumer::stream_consumer::StreamConsumer;
-    use rdkafka::consumer::Consumer;
-    use rdkafka::message::Headers;
-    use rdkafka::Message;
-    use std::time::Duration;
-    use tokio::time::sleep;
-    use tracing::trace;
 
-    use uuid::Uuid;
+    fn get_regexes() -> (Regex, Regex) {
+        let conf = Config::default();
+        let mut templates = Handlebars::new();
+        templates.register_escape_fn(handlebars::no_escape);
+        templates
+            .register_template_string("command_key", &conf.command_key)
+            .unwrap();
+        templates
+            .register_template_string("multicast_command_key", &conf.multicast_command_key)
+            .unwrap();
+
+        (
+            get_command_key_regex(
+                &templates
+                    .render(
+                        "command_key",
+                        &CommandKeyContext {
+                            application_id: "__application_id__".to_string(),
+                            dev_eui: "__dev_eui__".to_string(),
+                            command: "__command__".to_string(),
+                        },
+                    )
+                    .unwrap(),
+            )
+            .unwrap(),
+            get_command_key_regex(
+                &templates
+                    .render(
+                        "multicast_command_key",
+                        &MulticastCommandKeyContext {
+                            application_id: "__application_id__".to_string(),
+                            multicast_group_id: "__multicast_group_id__".to_string(),
+                            command: "__command__".to_string(),
+                        },
+                    )
+                    .unwrap(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_get_command_key_regex() {
+        let (command_regex, multicast_command_regex) = get_regexes();
+
+        let caps = command_regex
+            .captures(
+                "application.00000000-0000-0000-0000-000000000000.device.0102030405060708.command.down",
+            )
+            .unwrap();
+        assert_eq!(
+            "00000000-0000-0000-0000-000000000000",
+            &caps["application_id"]
+        );
+        assert_eq!("0102030405060708", &caps["dev_eui"]);
+        assert_eq!("down", &caps["command"]);
+
+        let caps = multicast_command_regex
+            .captures("application.00000000-0000-0000-0000-000000000000.multicast-group.00000000-0000-0000-0000-000000000001.command.down")
+            .unwrap();
+        assert_eq!(
+            "00000000-0000-0000-0000-000000000001",
+            &caps["multicast_group_id"]
+        );
+
+        // The dots are not matched as wildcard.
+        assert!(!command_regex.is_match(
+            "applicationX00000000-0000-0000-0000-000000000000.device.0102030405060708.command.down"
+        ));
+
+        // The regex is anchored.
+        assert!(!command_regex.is_match(
+            "foo.application.00000000-0000-0000-0000-000000000000.device.0102030405060708.command.down"
+        ));
+        assert!(!command_regex.is_match(
+            "application.00000000-0000-0000-0000-000000000000.device.0102030405060708.command.down.foo"
+        ));
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let (command_regex, multicast_command_regex) = get_regexes();
+        let app_id = "00000000-0000-0000-0000-000000000000";
 
+        // Device downlink.
+        let cmd = integration::DownlinkCommand {
+            dev_eui: "0102030405060708".into(),
+            f_port: 10,
+            ..Default::default()
+        };
+        match parse_command(
+            &command_regex,
+            &multicast_command_regex,
+            true,
+            &format!(
+                "application.{}.device.0102030405060708.command.down",
+                app_id
+            ),
+            &serde_json::to_vec(&cmd).unwrap(),
+        )
+        .unwrap()
+        {
+            Command::Down(application_id, c) => {
+                assert_eq!(app_id, application_id);
+                assert_eq!(cmd, c);
+            }
+            _ => panic!("Expected Down command"),
+        }
+
+        // Protobuf encoded.
+        assert!(matches!(
+            parse_command(
+                &command_regex,
+                &multicast_command_regex,
+                false,
+                &format!(
+                    "application.{}.device.0102030405060708.command.down",
+                    app_id
+                ),
+                &cmd.encode_to_vec(),
+            ),
+            Ok(Command::Down(_, _))
+        ));
+
+        // DevEUI mismatch.
+        assert!(parse_command(
+            &command_regex,
+            &multicast_command_regex,
+            true,
+            &format!(
+                "application.{}.device.0807060504030201.command.down",
+                app_id
+            ),
+            &serde_json::to_vec(&cmd).unwrap(),
+        )
+        .is_err());
+
+        // Unknown command.
+        assert!(parse_command(
+            &command_regex,
+            &multicast_command_regex,
+            true,
+            &format!("application.{}.device.0102030405060708.command.foo", app_id),
+            &serde_json::to_vec(&cmd).unwrap(),
+        )
+        .is_err());
+
+        // Invalid key.
+        assert!(parse_command(
+            &command_regex,
+            &multicast_command_regex,
+            true,
+            "foo",
+            &serde_json::to_vec(&cmd).unwrap(),
+        )
+        .is_err());
+
+        // Multicast downlink.
+        let cmd = integration::MulticastDownlinkCommand {
+            multicast_group_id: "00000000-0000-0000-0000-000000000001".into(),
+            f_port: 10,
+            ..Default::default()
+        };
+        match parse_command(
+            &command_regex,
+            &multicast_command_regex,
+            true,
+            &format!(
+                "application.{}.multicast-group.00000000-0000-0000-0000-000000000001.command.down",
+                app_id
+            ),
+            &serde_json::to_vec(&cmd).unwrap(),
+        )
+        .unwrap()
+        {
+            Command::MulticastDown(application_id, c) => {
+                assert_eq!(app_id, application_id);
+                assert_eq!(cmd, c);
+            }
+            _ => panic!("Expected MulticastDown command"),
+        }
+    }
+
+    #[cfg(feature = "test-integration-kafka")]
     #[tokio::test]
     async fn test_kafka() {
+        use std::env;
+
+        use crate::test;
+        use rdkafka::consumer::stream_consumer::StreamConsumer;
+        use rdkafka::consumer::Consumer;
+        use rdkafka::message::Headers;
+        use rdkafka::Message;
+        use std::time::Duration;
+        use tokio::time::sleep;
+        use tracing::trace;
+
+        use uuid::Uuid;
+
         let _guard = test::prepare().await;
 
         dotenv::dotenv().ok();
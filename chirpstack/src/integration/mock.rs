@@ -18,6 +18,9 @@ lazy_static! {
     static ref LOCATION_EVENTS: RwLock<Vec<integration::LocationEvent>> = RwLock::new(Vec::new());
     static ref INTEGRATION_EVENTS: RwLock<Vec<integration::IntegrationEvent>> =
         RwLock::new(Vec::new());
+    static ref GATEWAY_STATUS_EVENTS: RwLock<Vec<integration::GatewayStatusEvent>> =
+        RwLock::new(Vec::new());
+    static ref FUOTA_EVENTS: RwLock<Vec<integration::FuotaEvent>> = RwLock::new(Vec::new());
 }
 
 pub async fn reset() {
@@ -29,6 +32,8 @@ pub async fn reset() {
     STATUS_EVENTS.write().await.drain(..);
     LOCATION_EVENTS.write().await.drain(..);
     INTEGRATION_EVENTS.write().await.drain(..);
+    GATEWAY_STATUS_EVENTS.write().await.drain(..);
+    FUOTA_EVENTS.write().await.drain(..);
 }
 
 pub struct Integration {}
@@ -106,6 +111,20 @@ impl IntegrationTrait for Integration {
         INTEGRATION_EVENTS.write().await.push(pl.clone());
         Ok(())
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        FUOTA_EVENTS.write().await.push(pl.clone());
+        Ok(())
+    }
+
+    async fn gateway_status_event(&self, pl: &integration::GatewayStatusEvent) -> Result<()> {
+        GATEWAY_STATUS_EVENTS.write().await.push(pl.clone());
+        Ok(())
+    }
 }
 
 pub async fn get_join_event() -> Option<integration::JoinEvent> {
@@ -191,3 +210,11 @@ pub async fn get_location_events() -> Vec<integration::LocationEvent> {
 pub async fn get_integration_events() -> Vec<integration::IntegrationEvent> {
     INTEGRATION_EVENTS.write().await.drain(..).collect()
 }
+
+pub async fn get_gateway_status_events() -> Vec<integration::GatewayStatusEvent> {
+    GATEWAY_STATUS_EVENTS.write().await.drain(..).collect()
+}
+
+pub async fn get_fuota_events() -> Vec<integration::FuotaEvent> {
+    FUOTA_EVENTS.write().await.drain(..).collect()
+}
@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Instant;
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use futures::future::join_all;
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, try_join_all, BoxFuture, LocalBoxFuture};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::Histogram;
 use tokio::sync::RwLock;
-use tracing::{info, warn};
+use tokio::time::sleep;
+use tracing::{info, span, warn, Instrument, Level};
 use uuid::Uuid;
 
 use crate::helpers::errors::PrintFullError;
-use crate::storage::{application, device, device_profile, device_queue};
-use crate::{codec, config};
+use crate::monitoring::{self, prometheus};
+use crate::storage::{application, device, device_profile, device_queue, multicast};
+use crate::{codec, config, downlink, quota};
 use chirpstack_api::integration;
 use lrwn::EUI64;
 
@@ -18,6 +26,7 @@ mod amqp;
 mod aws_sns;
 mod azure_service_bus;
 mod gcp_pub_sub;
+mod geolocation;
 mod http;
 mod ifttt;
 mod influxdb;
@@ -34,49 +43,189 @@ mod redis;
 mod thingsboard;
 
 lazy_static! {
-    static ref GLOBAL_INTEGRATIONS: RwLock<Vec<Box<dyn Integration + Sync + Send>>> =
+    static ref GLOBAL_INTEGRATIONS: RwLock<Vec<(String, Box<dyn Integration + Sync + Send>)>> =
         RwLock::new(Vec::new());
     static ref MOCK_INTEGRATION: RwLock<bool> = RwLock::new(false);
+    static ref PUBLISHED_COUNTER: Family<IntegrationLabels, Counter> = {
+        let counter = Family::<IntegrationLabels, Counter>::default();
+        prometheus::register(
+            "integration_events_published",
+            "Number of events published by integration kind, application and event",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref FAILED_COUNTER: Family<IntegrationLabels, Counter> = {
+        let counter = Family::<IntegrationLabels, Counter>::default();
+        prometheus::register(
+            "integration_events_failed",
+            "Number of failed publish attempts by integration kind, application and event",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref RETRIED_COUNTER: Family<IntegrationLabels, Counter> = {
+        let counter = Family::<IntegrationLabels, Counter>::default();
+        prometheus::register(
+            "integration_events_retried",
+            "Number of publish retries by integration kind, application and event",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref DROPPED_COUNTER: Family<IntegrationLabels, Counter> = {
+        let counter = Family::<IntegrationLabels, Counter>::default();
+        prometheus::register(
+            "integration_events_dropped",
+            "Number of events dropped after all publish attempts failed by integration kind, application and event",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref PUBLISH_HISTOGRAM: Family<IntegrationLabels, Histogram> = {
+        let histogram = Family::<IntegrationLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(
+                [
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ]
+                .into_iter(),
+            )
+        });
+        prometheus::register(
+            "integration_events_publish_seconds",
+            "Duration of publish attempts by integration kind, application and event",
+            histogram.clone(),
+        );
+        histogram
+    };
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct IntegrationLabels {
+    kind: String,
+    application_id: String,
+    event: String,
 }
 
 pub async fn setup() -> Result<()> {
     info!("Setting up global integrations");
     let conf = config::get();
-    let mut integrations = GLOBAL_INTEGRATIONS.write().await;
+    let integrations = new_global_integrations(&conf.integration).await?;
+
+    let mut global_ints = GLOBAL_INTEGRATIONS.write().await;
+    *global_ints = integrations;
+
+    Ok(())
+}
 
-    integrations.push(Box::new(redis::Integration::new()));
+// Prepared reload of the global integrations, see prepare_reload.
+pub struct Reload {
+    integrations: Option<Vec<(String, Box<dyn Integration + Sync + Send>)>>,
+}
+
+impl Reload {
+    // Replaces the current global integrations by the prepared integrations and closes the
+    // replaced integrations. This can not fail, such that it can be called after all the other
+    // reload steps have succeeded.
+    pub async fn apply(self) {
+        let integrations = match self.integrations {
+            Some(v) => v,
+            None => return,
+        };
+
+        let old_integrations = {
+            let mut global_ints = GLOBAL_INTEGRATIONS.write().await;
+            std::mem::replace(&mut *global_ints, integrations)
+        };
+
+        close_integrations(old_integrations).await;
+    }
 
-    for name in &conf.integration.enabled {
+    // Closes the prepared integrations, in case an other reload step has failed. The current
+    // global integrations are not affected.
+    pub async fn abort(self) {
+        close_integrations(self.integrations.unwrap_or_default()).await;
+    }
+}
+
+// Prepares the re-creation of the global integrations in case their configuration has changed.
+// The new integrations are set up before the current integrations are replaced and closed (by
+// Reload::apply), such that no events are lost. The retry settings are read on every publish,
+// thus changing these does not require re-creating the integrations.
+pub async fn prepare_reload(
+    old: &config::Configuration,
+    new: &config::Configuration,
+) -> Result<Reload> {
+    let get_key = |c: &config::Integration| {
+        serde_json::to_value((&c.enabled, &c.mqtt, &c.postgresql, &c.amqp, &c.kafka))
+    };
+    if get_key(&old.integration)? == get_key(&new.integration)? {
+        return Ok(Reload { integrations: None });
+    }
+
+    info!("Global integrations configuration changed, reloading global integrations");
+    Ok(Reload {
+        integrations: Some(new_global_integrations(&new.integration).await?),
+    })
+}
+
+async fn close_integrations(integrations: Vec<(String, Box<dyn Integration + Sync + Send>)>) {
+    for (name, i) in integrations {
+        if let Err(e) = i.close().await {
+            warn!(integration = %name, error = %e.full(), "Closing integration failed");
+        }
+    }
+}
+
+async fn new_global_integrations(
+    conf: &config::Integration,
+) -> Result<Vec<(String, Box<dyn Integration + Sync + Send>)>> {
+    // The integrations are set up concurrently, as each integration might need to connect to
+    // an external service.
+    let mut futures: Vec<LocalBoxFuture<Result<Box<dyn Integration + Sync + Send>>>> = Vec::new();
+
+    for name in &conf.enabled {
         match name.as_ref() {
-            "mqtt" => {
-                integrations.push(Box::new(
-                    mqtt::Integration::new(&conf.integration.mqtt)
+            "mqtt" => futures.push(Box::pin(async {
+                Ok(Box::new(
+                    mqtt::Integration::new(&conf.mqtt)
                         .await
                         .context("Setup MQTT integration")?,
-                ));
-            }
+                ) as Box<dyn Integration + Sync + Send>)
+            })),
             #[cfg(feature = "postgres")]
-            "postgresql" => integrations.push(Box::new(
-                postgresql::Integration::new(&conf.integration.postgresql)
-                    .await
-                    .context("Setup PostgreSQL integration")?,
-            )),
-            "amqp" => integrations.push(Box::new(
-                amqp::Integration::new(&conf.integration.amqp)
-                    .await
-                    .context("Setup AMQP integration")?,
-            )),
-            "kafka" => integrations.push(Box::new(
-                kafka::Integration::new(&conf.integration.kafka)
-                    .context("Setup Kafka integration")?,
-            )),
+            "postgresql" => futures.push(Box::pin(async {
+                Ok(Box::new(
+                    postgresql::Integration::new(&conf.postgresql)
+                        .await
+                        .context("Setup PostgreSQL integration")?,
+                ) as Box<dyn Integration + Sync + Send>)
+            })),
+            "amqp" => futures.push(Box::pin(async {
+                Ok(Box::new(
+                    amqp::Integration::new(&conf.amqp)
+                        .await
+                        .context("Setup AMQP integration")?,
+                ) as Box<dyn Integration + Sync + Send>)
+            })),
+            "kafka" => futures.push(Box::pin(async {
+                Ok(Box::new(
+                    kafka::Integration::new(&conf.kafka).context("Setup Kafka integration")?,
+                ) as Box<dyn Integration + Sync + Send>)
+            })),
             _ => {
                 return Err(anyhow!("Unexpected integration: {}", name));
             }
         }
     }
 
-    Ok(())
+    let enabled = try_join_all(futures).await?;
+
+    let mut integrations: Vec<(String, Box<dyn Integration + Sync + Send>)> =
+        vec![("redis".to_string(), Box::new(redis::Integration::new()))];
+    integrations.extend(conf.enabled.iter().cloned().zip(enabled));
+
+    Ok(integrations)
 }
 
 #[cfg(test)]
@@ -134,23 +283,45 @@ pub trait Integration {
         vars: &HashMap<String, String>,
         pl: &integration::IntegrationEvent,
     ) -> Result<()>;
+
+    // FUOTA events are only handled by the integrations that publish the events as-is. The
+    // default implementation ignores the event.
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        _pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    // Gateway events are not scoped to an application, therefore these are only handled by
+    // the global integrations. The default implementation ignores the event.
+    async fn gateway_status_event(&self, _pl: &integration::GatewayStatusEvent) -> Result<()> {
+        Ok(())
+    }
+
+    // Stops the background tasks of the integration (e.g. consuming commands), before the
+    // integration is replaced on configuration reload.
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
-// Returns a Vec of integrations for the given Application ID.
-async fn for_application_id(id: Uuid) -> Result<Vec<Box<dyn Integration + Sync + Send>>> {
+// Returns a Vec of integrations (and their kind) for the given Application ID.
+async fn for_application_id(id: Uuid) -> Result<Vec<(String, Box<dyn Integration + Sync + Send>)>> {
     #[cfg(test)]
     {
         let m = MOCK_INTEGRATION.read().await;
         if *m {
-            return Ok(vec![Box::new(mock::Integration {})]);
+            return Ok(vec![("mock".to_string(), Box::new(mock::Integration {}))]);
         }
     }
 
-    let mut out: Vec<Box<dyn Integration + Sync + Send>> = Vec::new();
+    let mut out: Vec<(String, Box<dyn Integration + Sync + Send>)> = Vec::new();
     let integrations = application::get_integrations_for_application(&id).await?;
 
     for app_i in &integrations {
-        out.push(match &app_i.configuration {
+        let i: Box<dyn Integration + Sync + Send> = match &app_i.configuration {
             application::IntegrationConfiguration::AwsSns(conf) => {
                 Box::new(aws_sns::Integration::new(conf).await?)
             }
@@ -181,15 +352,78 @@ async fn for_application_id(id: Uuid) -> Result<Vec<Box<dyn Integration + Sync +
             application::IntegrationConfiguration::Ifttt(conf) => {
                 Box::new(ifttt::Integration::new(conf))
             }
+            application::IntegrationConfiguration::Geolocation(conf) => {
+                Box::new(geolocation::Integration::new(conf))
+            }
             _ => {
                 continue;
             }
-        })
+        };
+
+        out.push((app_i.kind.to_string().to_lowercase(), i));
     }
 
     Ok(out)
 }
 
+// Publishes an event using the given publish function. Failed attempts are retried (if
+// configured) and the delivery metrics are recorded.
+async fn publish<'a, F>(kind: &str, application_id: Option<Uuid>, event: &str, f: F) -> Result<()>
+where
+    F: Fn() -> BoxFuture<'a, Result<()>>,
+{
+    let conf = config::get();
+    let labels = IntegrationLabels {
+        kind: kind.to_string(),
+        application_id: application_id.map(|v| v.to_string()).unwrap_or_default(),
+        event: event.to_string(),
+    };
+    let mut retries = 0;
+
+    loop {
+        let start = Instant::now();
+        let res = f().await;
+        PUBLISH_HISTOGRAM
+            .get_or_create(&labels)
+            .observe(start.elapsed().as_secs_f64());
+
+        let err = match res {
+            Ok(_) => {
+                PUBLISHED_COUNTER.get_or_create(&labels).inc();
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        FAILED_COUNTER.get_or_create(&labels).inc();
+
+        if retries >= conf.integration.max_retries {
+            DROPPED_COUNTER.get_or_create(&labels).inc();
+            return Err(err.context(format!(
+                "Publish {} event using {} integration",
+                event, kind
+            )));
+        }
+
+        retries += 1;
+        RETRIED_COUNTER.get_or_create(&labels).inc();
+        warn!(kind = %kind, event = %event, retry = retries, error = %err.full(), "Publish event failed, retrying");
+        sleep(conf.integration.retry_interval).await;
+    }
+}
+
+fn inc_tenant_integration_errors(device_info: &Option<integration::DeviceInfo>, event: &str) {
+    if let Some(Ok(tenant_id)) = device_info.as_ref().map(|v| Uuid::from_str(&v.tenant_id)) {
+        monitoring::tenant::inc_integration_errors(&tenant_id, event);
+    }
+}
+
+fn record_sla_uplink(device_info: &Option<integration::DeviceInfo>, success: bool) {
+    if let Some(Ok(tenant_id)) = device_info.as_ref().map(|v| Uuid::from_str(&v.tenant_id)) {
+        monitoring::sla::record_uplink(&tenant_id, success);
+    }
+}
+
 pub async fn uplink_event(
     application_id: Uuid,
     vars: &HashMap<String, String>,
@@ -199,11 +433,20 @@ pub async fn uplink_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "up");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
-            if let Err(err) = _uplink_event(application_id, &vars, &pl).await {
+            let _queue_guard = queue_guard;
+            let res = _uplink_event(application_id, &vars, &pl).await;
+            if let Err(err) = &res {
                 warn!(application_id = %application_id, error = %err.full(), "Uplink event error");
+                inc_tenant_integration_errors(&pl.device_info, "up");
             }
+            record_sla_uplink(&pl.device_info, res.is_ok());
         }
+        .instrument(span)
     });
 }
 
@@ -216,13 +459,17 @@ async fn _uplink_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
 
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].uplink_event(vars, pl));
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "up", || {
+            i.uplink_event(vars, pl)
+        })));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].uplink_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "up", || {
+            i.uplink_event(vars, pl)
+        })));
     }
 
     for e in join_all(futures).await {
@@ -241,11 +488,18 @@ pub async fn join_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "join");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _join_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Join event error");
+                inc_tenant_integration_errors(&pl.device_info, "join");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -258,13 +512,23 @@ async fn _join_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
-
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].join_event(vars, pl));
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "join",
+            || i.join_event(vars, pl),
+        )));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].join_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "join",
+            || i.join_event(vars, pl),
+        )));
     }
 
     for e in join_all(futures).await {
@@ -283,11 +547,18 @@ pub async fn ack_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "ack");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _ack_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Ack event error");
+                inc_tenant_integration_errors(&pl.device_info, "ack");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -300,13 +571,17 @@ async fn _ack_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
 
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].ack_event(vars, pl));
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "ack", || {
+            i.ack_event(vars, pl)
+        })));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].ack_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "ack", || {
+            i.ack_event(vars, pl)
+        })));
     }
 
     for e in join_all(futures).await {
@@ -325,11 +600,18 @@ pub async fn txack_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "txack");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _txack_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Txack event error");
+                inc_tenant_integration_errors(&pl.device_info, "txack");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -342,13 +624,23 @@ async fn _txack_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
-
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].txack_event(vars, pl));
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "txack",
+            || i.txack_event(vars, pl),
+        )));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].txack_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "txack",
+            || i.txack_event(vars, pl),
+        )));
     }
 
     for e in join_all(futures).await {
@@ -367,14 +659,61 @@ pub async fn log_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "log");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
+            let pl = match rate_limit_log_event(application_id, pl).await {
+                Some(v) => v,
+                None => return,
+            };
+
             if let Err(err) = _log_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Log event error");
+                inc_tenant_integration_errors(&pl.device_info, "log");
             }
         }
+        .instrument(span)
     });
 }
 
+// Applies the per-device log rate-limit. Once the limit is reached, the log event is replaced by
+// a single RATE_LIMITED log event, after which the log events are dropped until the end of the
+// rate-limit interval.
+async fn rate_limit_log_event(
+    application_id: Uuid,
+    pl: integration::LogEvent,
+) -> Option<integration::LogEvent> {
+    let dev_eui = match pl.device_info.as_ref().map(|v| EUI64::from_str(&v.dev_eui)) {
+        Some(Ok(v)) => v,
+        _ => return Some(pl),
+    };
+
+    let app_max_events = match application::get_log_rate_limit(&application_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            warn!(application_id = %application_id, error = %e.full(), "Get application log rate-limit error");
+            0
+        }
+    };
+
+    match monitoring::device_log::check_log_event(dev_eui, app_max_events) {
+        monitoring::device_log::RateLimit::Allow => Some(pl),
+        monitoring::device_log::RateLimit::LimitReached => Some(integration::LogEvent {
+            level: integration::LogLevel::Warning.into(),
+            code: integration::LogCode::RateLimited.into(),
+            description:
+                "Log rate-limit reached, log events are dropped until the end of the interval"
+                    .into(),
+            context: HashMap::new(),
+            ..pl
+        }),
+        monitoring::device_log::RateLimit::Suppress => None,
+    }
+}
+
 async fn _log_event(
     application_id: Uuid,
     vars: &HashMap<String, String>,
@@ -384,13 +723,17 @@ async fn _log_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
 
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].log_event(vars, pl));
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "log", || {
+            i.log_event(vars, pl)
+        })));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].log_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(kind, Some(application_id), "log", || {
+            i.log_event(vars, pl)
+        })));
     }
 
     for e in join_all(futures).await {
@@ -409,11 +752,18 @@ pub async fn status_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "status");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _status_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Status event error");
+                inc_tenant_integration_errors(&pl.device_info, "status");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -426,13 +776,23 @@ async fn _status_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
-
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].status_event(vars, pl));
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "status",
+            || i.status_event(vars, pl),
+        )));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].status_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "status",
+            || i.status_event(vars, pl),
+        )));
     }
 
     for e in join_all(futures).await {
@@ -451,11 +811,18 @@ pub async fn location_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "location");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _location_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Location event error");
+                inc_tenant_integration_errors(&pl.device_info, "location");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -468,13 +835,23 @@ async fn _location_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
-
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].location_event(vars, pl));
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "location",
+            || i.location_event(vars, pl),
+        )));
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].location_event(vars, pl));
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "location",
+            || i.location_event(vars, pl),
+        )));
     }
 
     for e in join_all(futures).await {
@@ -493,11 +870,18 @@ pub async fn integration_event(
         let vars = vars.clone();
         let pl = pl.clone();
 
+        let span = span!(Level::INFO, "integration", event = "integration");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
         async move {
+            let _queue_guard = queue_guard;
             if let Err(err) = _integration_event(application_id, &vars, &pl).await {
                 warn!(application_id = %application_id, error = %err.full(), "Location event error");
+                inc_tenant_integration_errors(&pl.device_info, "integration");
             }
         }
+        .instrument(span)
     });
 }
 
@@ -510,13 +894,132 @@ async fn _integration_event(
         .await
         .context("Get integrations for application")?;
     let global_ints = GLOBAL_INTEGRATIONS.read().await;
-    let mut futures = Vec::new();
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "integration",
+            || i.integration_event(vars, pl),
+        )));
+    }
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "integration",
+            || i.integration_event(vars, pl),
+        )));
+    }
 
-    for (i, _) in app_ints.iter().enumerate() {
-        futures.push(app_ints[i].integration_event(vars, pl));
+    for e in join_all(futures).await {
+        e?;
     }
-    for (i, _) in global_ints.iter().enumerate() {
-        futures.push(global_ints[i].integration_event(vars, pl));
+
+    Ok(())
+}
+
+pub async fn fuota_event(
+    application_id: Uuid,
+    vars: &HashMap<String, String>,
+    pl: &integration::FuotaEvent,
+) {
+    tokio::spawn({
+        let vars = vars.clone();
+        let pl = pl.clone();
+
+        let span = span!(Level::INFO, "integration", event = "fuota");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
+        async move {
+            let _queue_guard = queue_guard;
+            if let Err(err) = _fuota_event(application_id, &vars, &pl).await {
+                warn!(application_id = %application_id, deployment_id = %pl.deployment_id, error = %err.full(), "FUOTA event error");
+                if let Ok(tenant_id) = Uuid::from_str(&pl.tenant_id) {
+                    monitoring::tenant::inc_integration_errors(&tenant_id, "fuota");
+                }
+            }
+        }
+        .instrument(span)
+    });
+}
+
+// Returns the event name for the given FUOTA event, e.g. device_completed.
+pub fn get_fuota_event_name(pl: &integration::FuotaEvent) -> String {
+    pl.event_type().as_str_name().to_lowercase()
+}
+
+async fn _fuota_event(
+    application_id: Uuid,
+    vars: &HashMap<String, String>,
+    pl: &integration::FuotaEvent,
+) -> Result<()> {
+    let app_ints = for_application_id(application_id)
+        .await
+        .context("Get integrations for application")?;
+    let global_ints = GLOBAL_INTEGRATIONS.read().await;
+    let mut futures: Vec<BoxFuture<Result<()>>> = Vec::new();
+
+    for (kind, i) in app_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "fuota",
+            || i.fuota_event(vars, pl),
+        )));
+    }
+    for (kind, i) in global_ints.iter() {
+        futures.push(Box::pin(publish(
+            kind,
+            Some(application_id),
+            "fuota",
+            || i.fuota_event(vars, pl),
+        )));
+    }
+
+    for e in join_all(futures).await {
+        e?;
+    }
+
+    Ok(())
+}
+
+pub async fn gateway_status_event(pl: &integration::GatewayStatusEvent) {
+    tokio::spawn({
+        let pl = pl.clone();
+
+        let span = span!(Level::INFO, "integration", event = "gateway_status");
+
+        let queue_guard = monitoring::runtime::enter_queue("integration");
+
+        async move {
+            let _queue_guard = queue_guard;
+            if let Err(err) = _gateway_status_event(&pl).await {
+                warn!(gateway_id = %pl.gateway_id, error = %err.full(), "Gateway status event error");
+            }
+        }
+        .instrument(span)
+    });
+}
+
+async fn _gateway_status_event(pl: &integration::GatewayStatusEvent) -> Result<()> {
+    #[cfg(test)]
+    {
+        let m = MOCK_INTEGRATION.read().await;
+        if *m {
+            return mock::Integration {}.gateway_status_event(pl).await;
+        }
+    }
+
+    let global_ints = GLOBAL_INTEGRATIONS.read().await;
+    let mut futures = Vec::new();
+
+    for (kind, i) in global_ints.iter() {
+        futures.push(publish(kind, None, "gateway_status", || {
+            i.gateway_status_event(pl)
+        }));
     }
 
     for e in join_all(futures).await {
@@ -547,6 +1050,7 @@ async fn handle_down_command(application_id: String, pl: integration::DownlinkCo
 
             data = codec::struct_to_binary(
                 dp.payload_codec_runtime,
+                &dp.tenant_id,
                 pl.f_port as u8,
                 &dev.variables,
                 &dp.payload_codec_script,
@@ -567,7 +1071,7 @@ async fn handle_down_command(application_id: String, pl: integration::DownlinkCo
             ..Default::default()
         };
 
-        device_queue::enqueue_item(qi).await?;
+        quota::enqueue_item(qi).await?;
 
         Ok(())
     }
@@ -578,3 +1082,190 @@ async fn handle_down_command(application_id: String, pl: integration::DownlinkCo
         warn!(dev_eui = %pl.dev_eui, error = %err.as_ref().unwrap().full(), "Handling downlink command error");
     }
 }
+
+async fn handle_multicast_down_command(
+    application_id: String,
+    pl: integration::MulticastDownlinkCommand,
+) {
+    let err = async {
+        info!(multicast_group_id = %pl.multicast_group_id, "Handling downlink command for multicast-group");
+        let mg_id = Uuid::from_str(&pl.multicast_group_id)?;
+        let app_id = Uuid::from_str(&application_id)?;
+
+        // Validate that the application_id from the topic is indeed the application ID to which
+        // the multicast-group belongs.
+        let mg = multicast::get(&mg_id).await?;
+        if Into::<Uuid>::into(mg.application_id) != app_id {
+            return Err(anyhow!(
+                "Application ID from topic does not match application ID from multicast-group"
+            ));
+        }
+
+        let qi = multicast::MulticastGroupQueueItem {
+            multicast_group_id: mg.id,
+            f_port: pl.f_port as i16,
+            data: pl.data.clone(),
+            expires_at: match &pl.expires_at {
+                Some(v) => Some(
+                    DateTime::<Utc>::from_timestamp(v.seconds, v.nanos as u32)
+                        .ok_or_else(|| anyhow!("Invalid expires_at timestamp"))?,
+                ),
+                None => None,
+            },
+            ..Default::default()
+        };
+
+        downlink::multicast::enqueue(qi).await?;
+
+        Ok(())
+    }
+    .await
+    .err();
+
+    if err.is_some() {
+        warn!(multicast_group_id = %pl.multicast_group_id, error = %err.as_ref().unwrap().full(), "Handling multicast downlink command error");
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::storage::{gateway, tenant};
+    use crate::test;
+    use lrwn::region::CommonName;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.integration.max_retries = 2;
+        conf.integration.retry_interval = Duration::from_millis(1);
+        config::set(conf);
+
+        let application_id = Uuid::new_v4();
+        let labels = IntegrationLabels {
+            kind: "test".into(),
+            application_id: application_id.to_string(),
+            event: "up".into(),
+        };
+
+        // succeeds after one retry
+        let attempts = AtomicUsize::new(0);
+        let attempts_ref = &attempts;
+        publish("test", Some(application_id), "up", move || {
+            Box::pin(async move {
+                if attempts_ref.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(anyhow!("error"))
+                } else {
+                    Ok(())
+                }
+            }) as BoxFuture<Result<()>>
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(2, attempts.load(Ordering::SeqCst));
+        assert_eq!(1, PUBLISHED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(1, FAILED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(1, RETRIED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(0, DROPPED_COUNTER.get_or_create(&labels).get());
+
+        // dropped after all retries failed
+        let attempts = AtomicUsize::new(0);
+        let attempts_ref = &attempts;
+        assert!(publish("test", Some(application_id), "up", move || {
+            Box::pin(async move {
+                attempts_ref.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow!("error"))
+            }) as BoxFuture<Result<()>>
+        })
+        .await
+        .is_err());
+
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+        assert_eq!(1, PUBLISHED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(4, FAILED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(3, RETRIED_COUNTER.get_or_create(&labels).get());
+        assert_eq!(1, DROPPED_COUNTER.get_or_create(&labels).get());
+    }
+
+    #[tokio::test]
+    async fn test_handle_multicast_down_command() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            can_have_gateways: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let gw = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            name: "test-gw".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mg = multicast::create(multicast::MulticastGroup {
+            application_id: app.id,
+            name: "test-mg".into(),
+            region: CommonName::EU868,
+            group_type: "C".into(),
+            dr: 1,
+            frequency: 868100000,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        multicast::add_gateway(&mg.id, &gw.gateway_id)
+            .await
+            .unwrap();
+
+        // application ID does not match
+        handle_multicast_down_command(
+            Uuid::new_v4().to_string(),
+            integration::MulticastDownlinkCommand {
+                multicast_group_id: mg.id.to_string(),
+                f_port: 10,
+                data: vec![1, 2, 3],
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(multicast::get_queue(&mg.id).await.unwrap().is_empty());
+
+        // enqueue
+        handle_multicast_down_command(
+            app.id.to_string(),
+            integration::MulticastDownlinkCommand {
+                multicast_group_id: mg.id.to_string(),
+                f_port: 10,
+                data: vec![1, 2, 3],
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let queue = multicast::get_queue(&mg.id).await.unwrap();
+        assert_eq!(1, queue.len());
+        assert_eq!(gw.gateway_id, queue[0].gateway_id);
+        assert_eq!(10, queue[0].f_port);
+        assert_eq!(vec![1, 2, 3], queue[0].data);
+    }
+}
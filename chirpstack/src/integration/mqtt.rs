@@ -17,8 +17,9 @@ use tokio::sync::mpsc;
 use tokio::time::sleep;
 use tracing::{error, info, trace, warn};
 
-use super::Integration as IntegrationTrait;
+use super::{get_fuota_event_name, Integration as IntegrationTrait};
 use crate::config::MqttIntegration as Config;
+use crate::helpers::tasks::Tasks;
 use crate::helpers::tls22::{get_root_certs, load_cert, load_key};
 use chirpstack_api::integration;
 
@@ -28,6 +29,8 @@ pub struct Integration<'a> {
     json: bool,
     qos: QoS,
     command_regex: Regex,
+    multicast_command_regex: Regex,
+    tasks: Tasks,
 }
 
 #[derive(Serialize)]
@@ -37,6 +40,20 @@ struct EventTopicContext {
     pub event: String,
 }
 
+#[derive(Serialize)]
+struct GatewayEventTopicContext {
+    pub tenant_id: String,
+    pub gateway_id: String,
+    pub event: String,
+}
+
+#[derive(Serialize)]
+struct FuotaEventTopicContext {
+    pub application_id: String,
+    pub deployment_id: String,
+    pub event: String,
+}
+
 #[derive(Serialize)]
 struct CommandTopicContext {
     pub application_id: String,
@@ -44,6 +61,13 @@ struct CommandTopicContext {
     pub command: String,
 }
 
+#[derive(Serialize)]
+struct MulticastCommandTopicContext {
+    pub application_id: String,
+    pub multicast_group_id: String,
+    pub command: String,
+}
+
 impl<'a> Integration<'a> {
     pub async fn new(conf: &Config) -> Result<Integration<'a>> {
         info!("Initializing MQTT integration");
@@ -52,7 +76,11 @@ impl<'a> Integration<'a> {
         let mut templates = Handlebars::new();
         templates.register_escape_fn(handlebars::no_escape);
         templates.register_template_string("event_topic", &conf.event_topic)?;
+        templates.register_template_string("gateway_event_topic", &conf.gateway_event_topic)?;
+        templates.register_template_string("fuota_event_topic", &conf.fuota_event_topic)?;
         templates.register_template_string("command_topic", &conf.command_topic)?;
+        templates
+            .register_template_string("multicast_command_topic", &conf.multicast_command_topic)?;
 
         let command_topic = templates.render(
             "command_topic",
@@ -62,6 +90,14 @@ impl<'a> Integration<'a> {
                 command: "+".into(),
             },
         )?;
+        let multicast_command_topic = templates.render(
+            "multicast_command_topic",
+            &MulticastCommandTopicContext {
+                application_id: "+".into(),
+                multicast_group_id: "+".into(),
+                command: "+".into(),
+            },
+        )?;
 
         // get client id, this will generate a random client_id when no client_id has been
         // configured.
@@ -135,17 +171,26 @@ impl<'a> Integration<'a> {
                     command: r"(?P<command>[\w]+)".to_string(),
                 },
             )?)?,
+            multicast_command_regex: Regex::new(&templates.render(
+                "multicast_command_topic",
+                &MulticastCommandTopicContext {
+                    application_id: r"(?P<application_id>[\w-]+)".to_string(),
+                    multicast_group_id: r"(?P<multicast_group_id>[\w-]+)".to_string(),
+                    command: r"(?P<command>[\w]+)".to_string(),
+                },
+            )?)?,
             qos,
             json: conf.json,
             client,
             templates,
+            tasks: Tasks::default(),
         };
 
         // connect
         info!(server_uri = %conf.server, client_id = %client_id, clean_session = conf.clean_session, "Connecting to MQTT broker");
 
         // (Re)subscribe loop
-        tokio::spawn({
+        i.tasks.push(tokio::spawn({
             let client = i.client.clone();
             let qos = i.qos;
 
@@ -155,13 +200,19 @@ impl<'a> Integration<'a> {
                     if let Err(e) = client.subscribe(&command_topic, qos).await {
                         error!(error = %e, "Subscribe to command topic error");
                     }
+
+                    info!(multicast_command_topic = %multicast_command_topic, "Subscribing to multicast command topic");
+                    if let Err(e) = client.subscribe(&multicast_command_topic, qos).await {
+                        error!(error = %e, "Subscribe to multicast command topic error");
+                    }
                 }
             }
-        });
+        }));
 
         // Eventloop
-        tokio::spawn({
+        i.tasks.push(tokio::spawn({
             let command_regex = i.command_regex.clone();
+            let multicast_command_regex = i.multicast_command_regex.clone();
             let json = i.json;
 
             async move {
@@ -175,6 +226,24 @@ impl<'a> Integration<'a> {
                             match v {
                                 Event::Incoming(Incoming::Publish(p)) => {
                                     let topic = String::from_utf8_lossy(&p.topic);
+
+                                    if let Some(caps) = multicast_command_regex.captures(&topic) {
+                                        if caps.len() != 4 {
+                                            warn!(topic = %topic, "Parsing multicast command topic returned invalid match count");
+                                            continue;
+                                        }
+
+                                        multicast_message_callback(
+                                            caps.get(1).map_or("", |m| m.as_str()).to_string(),
+                                            caps.get(2).map_or("", |m| m.as_str()).to_string(),
+                                            caps.get(3).map_or("", |m| m.as_str()).to_string(),
+                                            json,
+                                            p,
+                                        )
+                                        .await;
+                                        continue;
+                                    }
+
                                     let caps = match command_regex.captures(&topic) {
                                         Some(v) => v,
                                         None => {
@@ -217,7 +286,7 @@ impl<'a> Integration<'a> {
                     }
                 }
             }
-        });
+        }));
 
         // Return integration.
         Ok(i)
@@ -234,6 +303,38 @@ impl<'a> Integration<'a> {
         )?)
     }
 
+    fn get_gateway_event_topic(
+        &self,
+        tenant_id: &str,
+        gateway_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "gateway_event_topic",
+            &GatewayEventTopicContext {
+                tenant_id: tenant_id.to_string(),
+                gateway_id: gateway_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
+
+    fn get_fuota_event_topic(
+        &self,
+        application_id: &str,
+        deployment_id: &str,
+        event: &str,
+    ) -> Result<String> {
+        Ok(self.templates.render(
+            "fuota_event_topic",
+            &FuotaEventTopicContext {
+                application_id: application_id.to_string(),
+                deployment_id: deployment_id.to_string(),
+                event: event.to_string(),
+            },
+        )?)
+    }
+
     async fn publish_event(&self, topic: &str, b: Vec<u8>) -> Result<()> {
         info!(topic = %topic, "Publishing event");
         self.client.publish(topic, self.qos, false, b).await?;
@@ -396,6 +497,44 @@ impl IntegrationTrait for Integration<'_> {
 
         self.publish_event(&topic, b).await
     }
+
+    async fn fuota_event(
+        &self,
+        _vars: &HashMap<String, String>,
+        pl: &integration::FuotaEvent,
+    ) -> Result<()> {
+        let topic = self.get_fuota_event_topic(
+            &pl.application_id,
+            &pl.deployment_id,
+            &get_fuota_event_name(pl),
+        )?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+
+        self.publish_event(&topic, b).await
+    }
+
+    async fn gateway_status_event(&self, pl: &integration::GatewayStatusEvent) -> Result<()> {
+        let topic = self.get_gateway_event_topic(
+            &pl.tenant_id,
+            &pl.gateway_id,
+            if pl.online { "online" } else { "offline" },
+        )?;
+        let b = match self.json {
+            true => serde_json::to_vec(&pl)?,
+            false => pl.encode_to_vec(),
+        };
+
+        self.publish_event(&topic, b).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        info!("Closing MQTT integration");
+        self.tasks.abort().await;
+        Ok(())
+    }
 }
 
 async fn message_callback(
@@ -444,6 +583,54 @@ async fn message_callback(
     }
 }
 
+async fn multicast_message_callback(
+    application_id: String,
+    multicast_group_id: String,
+    command: String,
+    json: bool,
+    p: Publish,
+) {
+    let topic = String::from_utf8_lossy(&p.topic);
+
+    info!(topic = %topic, qos = ?p.qos, "Command received for multicast-group");
+
+    let err = || -> Result<()> {
+        match command.as_ref() {
+            "down" => {
+                let cmd: integration::MulticastDownlinkCommand = match json {
+                    true => serde_json::from_slice(&p.payload)?,
+                    false => {
+                        integration::MulticastDownlinkCommand::decode(&mut Cursor::new(&p.payload))?
+                    }
+                };
+                if multicast_group_id != cmd.multicast_group_id {
+                    return Err(anyhow!(
+                        "Payload multicast_group_id {} does not match topic multicast_group_id {}",
+                        cmd.multicast_group_id,
+                        multicast_group_id
+                    ));
+                }
+                tokio::spawn(super::handle_multicast_down_command(application_id, cmd));
+            }
+            _ => {
+                return Err(anyhow!("Unknown command type"));
+            }
+        }
+
+        Ok(())
+    }()
+    .err();
+
+    if err.is_some() {
+        warn!(
+            topic = %topic,
+            qos = ?p.qos,
+            "Processing multicast command error: {}",
+            err.as_ref().unwrap()
+        );
+    }
+}
+
 #[cfg(all(test, feature = "test-integration-mqtt"))]
 pub mod test {
     use std::env;
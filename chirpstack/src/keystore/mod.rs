@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::info;
+
+use crate::config;
+use lrwn::{AES128Key, EUI64};
+
+mod vault_kv;
+
+lazy_static! {
+    static ref STORE: RwLock<Option<Arc<dyn KeyStore + Sync + Send>>> = RwLock::new(None);
+    static ref CACHE: RwLock<HashMap<String, (Instant, RootKeys)>> = RwLock::new(HashMap::new());
+}
+
+// Root-keys of a device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RootKeys {
+    pub nwk_key: AES128Key,
+    pub app_key: AES128Key,
+    pub gen_app_key: AES128Key,
+}
+
+// KeyStore implements the storage of the device root-keys outside the database. The database
+// only stores the reference returned by the set method.
+#[async_trait]
+pub trait KeyStore {
+    async fn set(&self, dev_eui: &EUI64, keys: &RootKeys) -> Result<String>;
+    async fn get(&self, key_ref: &str) -> Result<RootKeys>;
+    async fn delete(&self, key_ref: &str) -> Result<()>;
+}
+
+pub fn setup() -> Result<()> {
+    let conf = config::get();
+    CACHE.write().unwrap().clear();
+
+    let store: Option<Arc<dyn KeyStore + Sync + Send>> = match conf.keystore.backend.as_ref() {
+        "" => None,
+        "vault_kv" => Some(Arc::new(vault_kv::Store::new(&conf.keystore.vault_kv))),
+        _ => {
+            return Err(anyhow!(
+                "Unexpected keystore backend: {}",
+                conf.keystore.backend
+            ))
+        }
+    };
+
+    if store.is_some() {
+        info!(backend = %conf.keystore.backend, "Setting up keystore for device root-keys");
+    }
+    *STORE.write().unwrap() = store;
+
+    Ok(())
+}
+
+// Returns true when a keystore is configured, in which case new or updated root-keys must be
+// stored in the keystore.
+pub fn enabled() -> bool {
+    STORE.read().unwrap().is_some()
+}
+
+// Stores the root-keys of the given device and returns the reference to these keys.
+pub async fn set(dev_eui: &EUI64, keys: &RootKeys) -> Result<String> {
+    let key_ref = get_store()?.set(dev_eui, keys).await?;
+    set_cache(&key_ref, Instant::now(), keys);
+    Ok(key_ref)
+}
+
+// Returns the root-keys for the given reference.
+pub async fn get(key_ref: &str) -> Result<RootKeys> {
+    if let Some(keys) = get_cache(key_ref) {
+        return Ok(keys);
+    }
+
+    // The cached_at timestamp is taken before retrieving the keys, such that the keys do not
+    // outlive the TTL in case the request takes a while.
+    let cached_at = Instant::now();
+    let keys = get_store()?.get(key_ref).await?;
+    set_cache(key_ref, cached_at, &keys);
+
+    Ok(keys)
+}
+
+// Deletes the root-keys for the given reference.
+pub async fn delete(key_ref: &str) -> Result<()> {
+    CACHE.write().unwrap().remove(key_ref);
+    get_store()?.delete(key_ref).await
+}
+
+fn get_store() -> Result<Arc<dyn KeyStore + Sync + Send>> {
+    STORE.read().unwrap().clone().ok_or_else(|| {
+        anyhow!("Device root-keys are stored in a keystore, but no keystore is configured")
+    })
+}
+
+fn get_cache(key_ref: &str) -> Option<RootKeys> {
+    let conf = config::get();
+    let cache = CACHE.read().unwrap();
+
+    match cache.get(key_ref) {
+        Some((cached_at, keys)) if cached_at.elapsed() < conf.keystore.cache_ttl => Some(*keys),
+        _ => None,
+    }
+}
+
+fn set_cache(key_ref: &str, cached_at: Instant, keys: &RootKeys) {
+    let conf = config::get();
+    if conf.keystore.cache_ttl.is_zero() {
+        return;
+    }
+
+    let mut cache = CACHE.write().unwrap();
+
+    // Remove expired items, to avoid the cache from growing unbounded.
+    cache.retain(|_, (t, _)| t.elapsed() < conf.keystore.cache_ttl);
+    cache.insert(key_ref.to_string(), (cached_at, *keys));
+}
@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use super::{KeyStore, RootKeys};
+use crate::config;
+use lrwn::{AES128Key, EUI64};
+
+// Store using the HashiCorp Vault KV (version 2) secrets engine for storing the device
+// root-keys. The reference is the path of the secret (relative to the mount).
+pub struct Store {
+    client: Client,
+    address: String,
+    token: String,
+    mount: String,
+    path_prefix: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Secret {
+    nwk_key: AES128Key,
+    app_key: AES128Key,
+    gen_app_key: AES128Key,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+impl Store {
+    pub fn new(conf: &config::KeystoreVaultKv) -> Store {
+        Store {
+            client: Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap(),
+            address: conf.address.trim_end_matches('/').to_string(),
+            token: conf.token.clone(),
+            mount: conf.mount.trim_matches('/').to_string(),
+            path_prefix: conf.path_prefix.trim_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, typ: &str, path: &str) -> String {
+        format!("{}/v1/{}/{}/{}", self.address, self.mount, typ, path)
+    }
+}
+
+#[async_trait]
+impl KeyStore for Store {
+    async fn set(&self, dev_eui: &EUI64, keys: &RootKeys) -> Result<String> {
+        let path = if self.path_prefix.is_empty() {
+            dev_eui.to_string()
+        } else {
+            format!("{}/{}", self.path_prefix, dev_eui)
+        };
+        trace!(path = %path, "Storing root-keys in Vault KV");
+
+        self.client
+            .post(self.url("data", &path))
+            .header("X-Vault-Token", &self.token)
+            .json(&Data {
+                data: Secret {
+                    nwk_key: keys.nwk_key,
+                    app_key: keys.app_key,
+                    gen_app_key: keys.gen_app_key,
+                },
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(path)
+    }
+
+    async fn get(&self, key_ref: &str) -> Result<RootKeys> {
+        trace!(path = %key_ref, "Retrieving root-keys from Vault KV");
+
+        let resp = self
+            .client
+            .get(self.url("data", key_ref))
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(anyhow!("Root-keys {} do not exist in Vault KV", key_ref));
+        }
+
+        let resp: Data<Data<Secret>> = resp.error_for_status()?.json().await?;
+        Ok(RootKeys {
+            nwk_key: resp.data.data.nwk_key,
+            app_key: resp.data.data.app_key,
+            gen_app_key: resp.data.data.gen_app_key,
+        })
+    }
+
+    async fn delete(&self, key_ref: &str) -> Result<()> {
+        trace!(path = %key_ref, "Deleting root-keys from Vault KV");
+
+        // Deleting the metadata permanently deletes all versions of the secret.
+        let resp = self
+            .client
+            .delete(self.url("metadata", key_ref))
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            resp.error_for_status()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn test_store() {
+        let server = MockServer::start();
+        let store = Store::new(&config::KeystoreVaultKv {
+            address: server.url("/"),
+            token: "secret-token".into(),
+            mount: "secret".into(),
+            path_prefix: "/chirpstack/device-keys/".into(),
+        });
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let keys = RootKeys {
+            nwk_key: AES128Key::from_bytes([1; 16]),
+            app_key: AES128Key::from_bytes([2; 16]),
+            gen_app_key: AES128Key::from_bytes([3; 16]),
+        };
+        let secret = Data {
+            data: Secret {
+                nwk_key: keys.nwk_key,
+                app_key: keys.app_key,
+                gen_app_key: keys.gen_app_key,
+            },
+        };
+
+        // set
+        let mut mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/secret/data/chirpstack/device-keys/0102030405060708")
+                .header("X-Vault-Token", "secret-token")
+                .body(serde_json::to_string(&secret).unwrap());
+            then.status(200);
+        });
+        let key_ref = store.set(&dev_eui, &keys).await.unwrap();
+        assert_eq!("chirpstack/device-keys/0102030405060708", key_ref);
+        mock.assert();
+        mock.delete();
+
+        // get
+        let mut mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/secret/data/chirpstack/device-keys/0102030405060708")
+                .header("X-Vault-Token", "secret-token");
+            then.status(200)
+                .body(serde_json::to_string(&Data { data: secret }).unwrap());
+        });
+        assert_eq!(keys, store.get(&key_ref).await.unwrap());
+        mock.assert();
+        mock.delete();
+
+        // get not found
+        let mut mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/secret/data/chirpstack/device-keys/0102030405060708");
+            then.status(404);
+        });
+        assert!(store.get(&key_ref).await.is_err());
+        mock.assert();
+        mock.delete();
+
+        // delete
+        let mut mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path("/v1/secret/metadata/chirpstack/device-keys/0102030405060708")
+                .header("X-Vault-Token", "secret-token");
+            then.status(204);
+        });
+        store.delete(&key_ref).await.unwrap();
+        mock.assert();
+        mock.delete();
+    }
+}
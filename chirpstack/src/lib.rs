@@ -0,0 +1,53 @@
+#![recursion_limit = "256"]
+
+#[macro_use]
+extern crate lazy_static;
+extern crate diesel_migrations;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate anyhow;
+
+pub mod adr;
+mod aeskey;
+mod api;
+mod applayer;
+mod backend;
+mod certificate;
+pub mod cmd;
+mod codec;
+pub mod config;
+mod coordination;
+mod devaddr;
+mod downlink;
+mod encryption;
+mod gateway;
+mod geolocation;
+mod gpstime;
+mod helpers;
+mod hsm;
+mod integration;
+mod keystore;
+mod maccommand;
+pub mod monitoring;
+mod quota;
+pub mod region;
+mod sensitivity;
+#[cfg(feature = "simulator")]
+mod simulator;
+mod storage;
+mod stream;
+#[cfg(test)]
+mod test;
+mod ttn;
+mod uplink;
+
+// Exposes the uplink phases which are benchmarked using the in-memory storage stub and the
+// codecs, which are benchmarked without storage.
+pub mod bench {
+    pub use crate::codec::js::decode as js_decode;
+    #[cfg(feature = "bench-storage")]
+    pub use crate::storage::memory::reset as reset_storage;
+    #[cfg(feature = "bench-storage")]
+    pub use crate::uplink::{deduplicate_collect, deduplicate_put};
+}
@@ -1,48 +1,17 @@
-#![recursion_limit = "256"]
-
-#[macro_use]
-extern crate lazy_static;
-extern crate diesel_migrations;
-#[macro_use]
-extern crate diesel;
-#[macro_use]
-extern crate anyhow;
-
 use std::path::Path;
 use std::str::FromStr;
+#[cfg(feature = "simulator")]
+use std::time::Duration;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use tracing::Level;
-use tracing_subscriber::{filter, prelude::*};
+use tracing_subscriber::{filter, filter::FilterExt, prelude::*};
+use uuid::Uuid;
 
+use chirpstack::{cmd, config, monitoring};
 use lrwn::EUI64;
 
-mod adr;
-mod aeskey;
-mod api;
-mod applayer;
-mod backend;
-mod certificate;
-mod cmd;
-mod codec;
-mod config;
-mod devaddr;
-mod downlink;
-mod gateway;
-mod gpstime;
-mod helpers;
-mod integration;
-mod maccommand;
-mod monitoring;
-mod region;
-mod sensitivity;
-mod storage;
-mod stream;
-#[cfg(test)]
-mod test;
-mod uplink;
-
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -59,6 +28,12 @@ enum Commands {
     /// Print the configuration template
     Configfile {},
 
+    /// Validate the configuration or show the differences with the defaults
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+
     /// Print the device-session for debugging
     PrintDs {
         /// Device EUI
@@ -80,6 +55,21 @@ enum Commands {
         dir: String,
     },
 
+    /// Import end-devices from a The Things Stack (TTN / TTI) export.
+    ///
+    /// The file must contain the end-devices as JSON (e.g. as exported using
+    /// ttn-lw-cli end-devices list --all). Devices with a session are imported as activated,
+    /// such that they keep working without re-join.
+    ImportTtn {
+        /// Application ID to import the devices into.
+        #[arg(long, value_name = "APPLICATION_ID")]
+        application_id: String,
+
+        /// Path to the export file.
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+    },
+
     /// Create global API key.
     CreateApiKey {
         /// Name.
@@ -87,14 +77,119 @@ enum Commands {
         name: String,
     },
 
+    /// Encrypt a secret (read from stdin) for usage in the configuration file.
+    ///
+    /// The secret is encrypted using the key set by the CHIRPSTACK_SECRETS_KEY environment
+    /// variable.
+    EncryptSecret {},
+
     /// Migrate device-sessions from Redis to PostgreSQL.
     MigrateDeviceSessionsToPostgres {},
+
+    /// Migrate the device root-keys from the database to the configured keystore.
+    MigrateDeviceKeysToKeystore {},
+
+    /// Migrate ChirpStack v3 device-sessions from Redis.
+    MigrateDeviceSessionsV3 {
+        /// ChirpStack v3 Redis URL.
+        #[arg(long, value_name = "URL")]
+        redis_url: String,
+
+        /// Region configuration ID of the migrated devices.
+        #[arg(long, value_name = "REGION_CONFIG_ID")]
+        region_config_id: String,
+    },
+
+    /// Re-encrypt the stored device-keys and device-sessions.
+    ReencryptKeys {
+        /// Rotate the data encryption key before re-encrypting.
+        #[arg(long)]
+        rotate_data_key: bool,
+
+        /// Only re-encrypt the key material of the given tenant (rotating the tenant data
+        /// encryption key in case of --rotate-data-key).
+        #[arg(long, value_name = "TENANT_ID")]
+        tenant_id: Option<String>,
+    },
+
+    /// Backup the database and the Redis keys holding the device state to a file.
+    #[cfg(feature = "postgres")]
+    Backup {
+        /// Path to the backup file.
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+    },
+
+    /// Restore a backup created using the backup command.
+    ///
+    /// This replaces all the data in the database. The backup must have been created by a
+    /// ChirpStack version with the same database schema.
+    #[cfg(feature = "postgres")]
+    Restore {
+        /// Path to the backup file.
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+    },
+
+    /// Simulate gateways and OTAA devices (for load-testing).
+    #[cfg(feature = "simulator")]
+    Simulate {
+        /// Region configuration ID.
+        #[arg(long, value_name = "REGION_CONFIG_ID", default_value = "eu868")]
+        region_config_id: String,
+
+        /// Number of gateways.
+        #[arg(long, value_name = "COUNT", default_value_t = 3)]
+        gateways: usize,
+
+        /// Number of devices.
+        #[arg(long, value_name = "COUNT", default_value_t = 10)]
+        devices: usize,
+
+        /// Uplink interval of each device (seconds).
+        #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+        uplink_interval: u64,
+
+        /// FPort of the uplinks.
+        #[arg(long, value_name = "FPORT", default_value_t = 1)]
+        f_port: u8,
+
+        /// Payload size of the uplinks (bytes).
+        #[arg(long, value_name = "BYTES", default_value_t = 10)]
+        payload_size: usize,
+
+        /// Data-rate of the uplinks.
+        #[arg(long, value_name = "DR", default_value_t = 0)]
+        dr: u8,
+
+        /// Duration of the simulation (seconds), 0 runs until terminated.
+        #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+        duration: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Validate the configuration files, reporting syntax errors, unknown keys and invalid values
+    Validate {},
+
+    /// Print the configuration values which differ from the default values
+    Diff {},
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    config::load(Path::new(&cli.config))?;
+
+    // These commands must not fail on loading an invalid configuration.
+    if let Some(Commands::Config { command }) = &cli.command {
+        return match command {
+            ConfigCommands::Validate {} => cmd::config::validate(Path::new(&cli.config)).await,
+            ConfigCommands::Diff {} => cmd::config::diff(Path::new(&cli.config)),
+        };
+    }
+
+    config::load(Path::new(&cli.config)).await?;
 
     let conf = config::get();
     let filter = filter::Targets::new().with_targets(vec![
@@ -103,20 +198,35 @@ async fn main() -> Result<()> {
         ("lrwn", Level::from_str(&conf.logging.level).unwrap()),
     ]);
 
+    // The filter is set per layer, as the device debug-trace layer captures the events of
+    // devices for which the debug tracing is enabled, regardless the configured log level.
+    // The per-device log rate-limit only applies to the log output.
     if conf.logging.json {
         tracing_subscriber::registry()
-            .with(tracing_subscriber::fmt::layer().json())
-            .with(filter)
+            .with(monitoring::otel::layer()?.with_filter(filter.clone()))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_filter(filter.and(monitoring::device_log::filter())),
+            )
+            .with(monitoring::device_trace::layer())
+            .with(monitoring::error_reporting::layer()?)
             .init();
     } else {
         tracing_subscriber::registry()
-            .with(tracing_subscriber::fmt::layer())
-            .with(filter)
+            .with(monitoring::otel::layer()?.with_filter(filter.clone()))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_filter(filter.and(monitoring::device_log::filter())),
+            )
+            .with(monitoring::device_trace::layer())
+            .with(monitoring::error_reporting::layer()?)
             .init();
     }
 
     match &cli.command {
         Some(Commands::Configfile {}) => cmd::configfile::run(),
+        Some(Commands::Config { .. }) => {}
         Some(Commands::PrintDs { dev_eui }) => {
             let dev_eui = EUI64::from_str(dev_eui).unwrap();
             cmd::print_ds::run(&dev_eui).await.unwrap();
@@ -131,9 +241,57 @@ async fn main() -> Result<()> {
                 .await
                 .unwrap()
         }
+        Some(Commands::ImportTtn {
+            application_id,
+            file,
+        }) => cmd::import_ttn::run(application_id, Path::new(&file)).await?,
         Some(Commands::CreateApiKey { name }) => cmd::create_api_key::run(name).await?,
+        Some(Commands::EncryptSecret {}) => cmd::encrypt_secret::run()?,
         Some(Commands::MigrateDeviceSessionsToPostgres {}) => cmd::migrate_ds_to_pg::run().await?,
-        None => cmd::root::run().await?,
+        Some(Commands::MigrateDeviceKeysToKeystore {}) => {
+            cmd::migrate_device_keys_to_keystore::run().await?
+        }
+        Some(Commands::MigrateDeviceSessionsV3 {
+            redis_url,
+            region_config_id,
+        }) => cmd::migrate_device_sessions_v3::run(redis_url, region_config_id).await?,
+        Some(Commands::ReencryptKeys {
+            rotate_data_key,
+            tenant_id,
+        }) => {
+            let tenant_id = tenant_id.as_deref().map(Uuid::from_str).transpose()?;
+            cmd::reencrypt_keys::run(*rotate_data_key, tenant_id).await?
+        }
+        #[cfg(feature = "postgres")]
+        Some(Commands::Backup { file }) => cmd::backup::backup(Path::new(&file)).await?,
+        #[cfg(feature = "postgres")]
+        Some(Commands::Restore { file }) => cmd::backup::restore(Path::new(&file)).await?,
+        #[cfg(feature = "simulator")]
+        Some(Commands::Simulate {
+            region_config_id,
+            gateways,
+            devices,
+            uplink_interval,
+            f_port,
+            payload_size,
+            dr,
+            duration,
+        }) => {
+            cmd::simulate::run(
+                &cmd::simulate::Options {
+                    region_config_id: region_config_id.clone(),
+                    gateways: *gateways,
+                    devices: *devices,
+                    uplink_interval: Duration::from_secs(*uplink_interval),
+                    f_port: *f_port,
+                    payload_size: *payload_size,
+                    dr: *dr,
+                },
+                Duration::from_secs(*duration),
+            )
+            .await?
+        }
+        None => cmd::root::run(Path::new(&cli.config)).await?,
     }
 
     Ok(())
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use tracing::Subscriber;
+use tracing_subscriber::filter;
+use tracing_subscriber::layer::Filter;
+
+use super::{device_trace, prometheus};
+use crate::config;
+use lrwn::EUI64;
+
+// Once the number of tracked devices exceeds this number, the devices of which the interval
+// has expired are removed.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct SuppressedLabels {
+    kind: String,
+}
+
+lazy_static! {
+    static ref LOG_LINES: Mutex<HashMap<EUI64, Window>> = Mutex::new(HashMap::new());
+    static ref LOG_EVENTS: Mutex<HashMap<EUI64, Window>> = Mutex::new(HashMap::new());
+    static ref SUPPRESSED_COUNTER: Family<SuppressedLabels, Counter> = {
+        let counter = Family::<SuppressedLabels, Counter>::default();
+        prometheus::register(
+            "device_log_suppressed_count",
+            "Number of per-device rate-limited log lines and log events by kind",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RateLimit {
+    // The event is within the limit.
+    Allow,
+    // The event is the first event exceeding the limit within the current interval.
+    LimitReached,
+    // The limit was already reached within the current interval.
+    Suppress,
+}
+
+struct Window {
+    start: Instant,
+    count: u32,
+}
+
+// Returns the rate-limit result for the integration log event of the given device. When the
+// application max_events is set to 0, the global configuration is used.
+pub fn check_log_event(dev_eui: EUI64, app_max_events: u32) -> RateLimit {
+    let conf = config::get();
+    let max_events = if app_max_events > 0 {
+        app_max_events
+    } else {
+        conf.logging.device_rate_limit.max_events
+    };
+
+    let res = check(
+        &mut LOG_EVENTS.lock().unwrap(),
+        dev_eui,
+        max_events,
+        conf.logging.device_rate_limit.interval,
+    );
+    if res != RateLimit::Allow {
+        inc_suppressed("log_event");
+    }
+    res
+}
+
+// Returns the filter which drops the log lines of devices exceeding the configured rate-limit.
+// The DevEUI of the log line is obtained from the span in which the event was emitted.
+pub fn filter<S>() -> impl Filter<S>
+where
+    S: Subscriber,
+{
+    filter::dynamic_filter_fn(|meta, _| {
+        if !meta.is_event() {
+            return true;
+        }
+
+        let conf = config::get();
+        if conf.logging.device_rate_limit.max_events == 0 {
+            return true;
+        }
+
+        let dev_eui = match device_trace::current_dev_eui() {
+            Some(v) => v,
+            None => return true,
+        };
+
+        let res = check(
+            &mut LOG_LINES.lock().unwrap(),
+            dev_eui,
+            conf.logging.device_rate_limit.max_events,
+            conf.logging.device_rate_limit.interval,
+        );
+        if res != RateLimit::Allow {
+            inc_suppressed("log_line");
+            return false;
+        }
+
+        true
+    })
+}
+
+fn inc_suppressed(kind: &str) {
+    SUPPRESSED_COUNTER
+        .get_or_create(&SuppressedLabels {
+            kind: kind.to_string(),
+        })
+        .inc();
+}
+
+fn check(
+    windows: &mut HashMap<EUI64, Window>,
+    dev_eui: EUI64,
+    max_events: u32,
+    interval: Duration,
+) -> RateLimit {
+    if max_events == 0 {
+        return RateLimit::Allow;
+    }
+
+    if windows.len() > PRUNE_THRESHOLD {
+        windows.retain(|_, w| w.start.elapsed() < interval);
+    }
+
+    let w = windows.entry(dev_eui).or_insert_with(|| Window {
+        start: Instant::now(),
+        count: 0,
+    });
+
+    if w.start.elapsed() >= interval {
+        w.start = Instant::now();
+        w.count = 0;
+    }
+
+    w.count = w.count.saturating_add(1);
+
+    if w.count <= max_events {
+        RateLimit::Allow
+    } else if w.count == max_events + 1 {
+        RateLimit::LimitReached
+    } else {
+        RateLimit::Suppress
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut windows: HashMap<EUI64, Window> = HashMap::new();
+        let dev_eui_a = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let dev_eui_b = EUI64::from_be_bytes([8, 7, 6, 5, 4, 3, 2, 1]);
+        let interval = Duration::from_secs(60);
+
+        // disabled
+        for _ in 0..5 {
+            assert_eq!(
+                RateLimit::Allow,
+                check(&mut windows, dev_eui_a, 0, interval)
+            );
+        }
+
+        // limited
+        assert_eq!(
+            RateLimit::Allow,
+            check(&mut windows, dev_eui_a, 2, interval)
+        );
+        assert_eq!(
+            RateLimit::Allow,
+            check(&mut windows, dev_eui_a, 2, interval)
+        );
+        assert_eq!(
+            RateLimit::LimitReached,
+            check(&mut windows, dev_eui_a, 2, interval)
+        );
+        assert_eq!(
+            RateLimit::Suppress,
+            check(&mut windows, dev_eui_a, 2, interval)
+        );
+
+        // other device
+        assert_eq!(
+            RateLimit::Allow,
+            check(&mut windows, dev_eui_b, 2, interval)
+        );
+
+        // interval expired
+        windows.get_mut(&dev_eui_a).unwrap().start = Instant::now() - interval;
+        assert_eq!(
+            RateLimit::Allow,
+            check(&mut windows, dev_eui_a, 2, interval)
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::field::{Field, Visit};
+use tracing::{error, info, span, Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::{filter, registry::LookupSpan};
+
+use crate::api::helpers;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{get_async_redis_conn, redis_key};
+use crate::stream;
+use chirpstack_api::api;
+use lrwn::EUI64;
+
+// Interval in which the enabled devices are synchronized from Redis, such that enabling the
+// debug tracing through the API of one instance is picked up by all instances.
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+// Crates of which the events are captured.
+const TARGETS: [&str; 3] = ["chirpstack", "backend", "lrwn"];
+
+lazy_static! {
+    // Devices for which the debug tracing is enabled, with the time until it is enabled.
+    static ref DEVICES: RwLock<HashMap<EUI64, DateTime<Utc>>> = RwLock::new(HashMap::new());
+}
+
+// Set to true when the debug tracing is enabled for at least one device, such that the
+// layer does not capture any events when not needed.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static SENDER: OnceLock<mpsc::UnboundedSender<(EUI64, api::LogItem)>> = OnceLock::new();
+
+thread_local! {
+    // DevEUIs of the spans entered by the current thread (None for spans without DevEUI).
+    static ENTERED: RefCell<Vec<Option<EUI64>>> = const { RefCell::new(Vec::new()) };
+}
+
+pub async fn setup() {
+    info!("Setting up device debug-trace loops");
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<(EUI64, api::LogItem)>();
+    if SENDER.set(tx).is_err() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        while let Some((dev_eui, item)) = rx.recv().await {
+            if let Err(e) = stream::device_trace::log_trace_for_device(&dev_eui, &item).await {
+                error!(dev_eui = %dev_eui, error = %e.full(), "Log device debug-trace error");
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = sync().await {
+                error!(error = %e.full(), "Sync device debug-trace devices error");
+            }
+
+            sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
+
+// Enables the debug tracing for the given device and duration.
+pub async fn enable(dev_eui: EUI64, duration: Duration) -> Result<()> {
+    let until = Utc::now() + chrono::Duration::from_std(duration)?;
+
+    () = redis::cmd("ZADD")
+        .arg(redis_key("device:debug-trace".to_string()))
+        .arg(until.timestamp_millis())
+        .arg(dev_eui.to_string())
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let mut devices = DEVICES.write().unwrap();
+    devices.insert(dev_eui, until);
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    info!(dev_eui = %dev_eui, until = %until, "Device debug-trace enabled");
+    Ok(())
+}
+
+// Disables the debug tracing for the given device.
+pub async fn disable(dev_eui: EUI64) -> Result<()> {
+    () = redis::cmd("ZREM")
+        .arg(redis_key("device:debug-trace".to_string()))
+        .arg(dev_eui.to_string())
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let mut devices = DEVICES.write().unwrap();
+    devices.remove(&dev_eui);
+    ACTIVE.store(!devices.is_empty(), Ordering::Relaxed);
+
+    info!(dev_eui = %dev_eui, "Device debug-trace disabled");
+    Ok(())
+}
+
+async fn sync() -> Result<()> {
+    let key = redis_key("device:debug-trace".to_string());
+    let now = Utc::now();
+
+    let (items,): (Vec<(String, f64)>,) = redis::pipe()
+        .atomic()
+        .cmd("ZREMRANGEBYSCORE")
+        .arg(&key)
+        .arg("-inf")
+        .arg(now.timestamp_millis())
+        .ignore()
+        .cmd("ZRANGE")
+        .arg(&key)
+        .arg(0)
+        .arg(-1)
+        .arg("WITHSCORES")
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let mut devices: HashMap<EUI64, DateTime<Utc>> = HashMap::new();
+    for (dev_eui, until) in items {
+        let dev_eui = EUI64::from_str(&dev_eui)?;
+        if let Some(until) = Utc.timestamp_millis_opt(until as i64).single() {
+            devices.insert(dev_eui, until);
+        }
+    }
+
+    let mut devices_w = DEVICES.write().unwrap();
+    ACTIVE.store(!devices.is_empty(), Ordering::Relaxed);
+    *devices_w = devices;
+
+    Ok(())
+}
+
+// Returns the DevEUI of the innermost span with DevEUI, entered by the current thread.
+pub fn current_dev_eui() -> Option<EUI64> {
+    ENTERED.with(|entered| entered.borrow().iter().rev().find_map(|v| *v))
+}
+
+fn is_enabled(dev_eui: &EUI64) -> bool {
+    DEVICES
+        .read()
+        .unwrap()
+        .get(dev_eui)
+        .map(|until| *until > Utc::now())
+        .unwrap_or(false)
+}
+
+// Returns the layer capturing the events of devices for which the debug tracing is enabled.
+// The events are captured independent of the configured log level.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    DeviceTraceLayer {}.with_filter(filter::dynamic_filter_fn(|meta, _| {
+        TARGETS.iter().any(|t| meta.target().starts_with(t))
+            && (meta.is_span() || ACTIVE.load(Ordering::Relaxed))
+    }))
+}
+
+struct DeviceTraceLayer {}
+
+// DevEUI of the span, stored in the span extensions.
+struct DevEui(EUI64);
+
+impl<S> Layer<S> for DeviceTraceLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = DevEuiVisitor::default();
+        attrs.record(&mut visitor);
+
+        if let (Some(dev_eui), Some(span)) = (visitor.dev_eui, ctx.span(id)) {
+            span.extensions_mut().insert(DevEui(dev_eui));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = DevEuiVisitor::default();
+        values.record(&mut visitor);
+
+        if let (Some(dev_eui), Some(span)) = (visitor.dev_eui, ctx.span(id)) {
+            span.extensions_mut().replace(DevEui(dev_eui));
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let dev_eui = ctx
+            .span(id)
+            .and_then(|span| span.extensions().get::<DevEui>().map(|v| v.0));
+        ENTERED.with(|entered| entered.borrow_mut().push(dev_eui));
+    }
+
+    fn on_exit(&self, _id: &span::Id, _ctx: Context<'_, S>) {
+        ENTERED.with(|entered| {
+            entered.borrow_mut().pop();
+        });
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let sender = match SENDER.get() {
+            Some(v) => v,
+            None => return,
+        };
+
+        let scope = match ctx.event_scope(event) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut span_name = "";
+        let mut dev_eui: Option<EUI64> = None;
+        for span in scope {
+            if span_name.is_empty() {
+                span_name = span.name();
+            }
+
+            if let Some(v) = span.extensions().get::<DevEui>() {
+                dev_eui = Some(v.0);
+                break;
+            }
+        }
+
+        let dev_eui = match dev_eui {
+            Some(v) if is_enabled(&v) => v,
+            _ => return,
+        };
+
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+
+        let meta = event.metadata();
+        let item = api::LogItem {
+            id: "".into(),
+            time: Some(helpers::datetime_to_prost_timestamp(&Utc::now())),
+            description: visitor.message,
+            body: serde_json::to_string(&visitor.fields).unwrap_or_default(),
+            properties: [
+                ("Level".to_string(), meta.level().to_string()),
+                ("Target".to_string(), meta.target().to_string()),
+                ("Span".to_string(), span_name.to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        };
+
+        let _ = sender.send((dev_eui, item));
+    }
+}
+
+#[derive(Default)]
+struct DevEuiVisitor {
+    dev_eui: Option<EUI64>,
+}
+
+impl Visit for DevEuiVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "dev_eui" {
+            self.dev_eui = EUI64::from_str(value).ok();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "dev_eui" {
+            self.dev_eui = EUI64::from_str(&format!("{:?}", value)).ok();
+        }
+    }
+}
+
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for EventVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_enable_disable() {
+        let _guard = test::prepare().await;
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(!is_enabled(&dev_eui));
+
+        // enable
+        enable(dev_eui, Duration::from_secs(60)).await.unwrap();
+        assert!(is_enabled(&dev_eui));
+
+        // sync from redis
+        DEVICES.write().unwrap().clear();
+        sync().await.unwrap();
+        assert!(is_enabled(&dev_eui));
+        assert!(ACTIVE.load(Ordering::Relaxed));
+
+        // disable
+        disable(dev_eui).await.unwrap();
+        assert!(!is_enabled(&dev_eui));
+        sync().await.unwrap();
+        assert!(!is_enabled(&dev_eui));
+        assert!(!ACTIVE.load(Ordering::Relaxed));
+    }
+}
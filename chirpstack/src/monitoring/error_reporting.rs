@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use chrono::{SecondsFormat, Utc};
+use reqwest::{header, Client, Url};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{span, warn, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::{filter, registry::LookupSpan};
+use uuid::Uuid;
+
+use super::device_trace;
+use crate::config;
+
+// Crates of which the error events are reported.
+const TARGETS: [&str; 3] = ["chirpstack", "backend", "lrwn"];
+
+// Span and event fields which are reported as tags, such that the reported errors can be
+// filtered by these.
+const TAG_FIELDS: [&str; 8] = [
+    "dev_eui",
+    "gateway_id",
+    "region_id",
+    "region_config_id",
+    "common_name",
+    "tenant_id",
+    "application_id",
+    "deduplication_id",
+];
+
+// Max. number of events waiting to be sent. When the queue is full (e.g. the endpoint is not
+// reachable), new events are dropped.
+const QUEUE_SIZE: usize = 100;
+
+static SENDER: OnceLock<mpsc::Sender<ErrorEvent>> = OnceLock::new();
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct ErrorEvent {
+    event_id: String,
+    timestamp: String,
+    platform: String,
+    level: String,
+    logger: String,
+    message: EventMessage,
+    release: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    environment: String,
+    tags: BTreeMap<String, String>,
+    extra: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct EventMessage {
+    formatted: String,
+}
+
+impl ErrorEvent {
+    fn new(level: &str, logger: &str, message: String) -> Self {
+        let conf = config::get();
+
+        ErrorEvent {
+            event_id: Uuid::new_v4().simple().to_string(),
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            platform: "other".into(),
+            level: level.into(),
+            logger: logger.into(),
+            message: EventMessage { formatted: message },
+            release: format!("chirpstack@{}", env!("CARGO_PKG_VERSION")),
+            environment: conf.monitoring.error_reporting.environment.clone(),
+            tags: BTreeMap::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+// Parsed Sentry DSN, in the format {SCHEME}://{PUBLIC_KEY}@{HOST}{PATH}/{PROJECT_ID}.
+#[derive(Debug, PartialEq)]
+struct Dsn {
+    public_key: String,
+    envelope_url: Url,
+}
+
+impl Dsn {
+    fn parse(s: &str) -> Result<Self> {
+        let url = Url::parse(s).context("Parse DSN")?;
+        if url.username().is_empty() {
+            return Err(anyhow!("DSN does not contain public key"));
+        }
+
+        let path = url.path().trim_end_matches('/');
+        let (prefix, project_id) = path
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("DSN does not contain project ID"))?;
+        if project_id.is_empty() {
+            return Err(anyhow!("DSN does not contain project ID"));
+        }
+
+        let mut envelope_url = url.clone();
+        envelope_url
+            .set_username("")
+            .map_err(|_| anyhow!("Set DSN username error"))?;
+        envelope_url
+            .set_password(None)
+            .map_err(|_| anyhow!("Set DSN password error"))?;
+        envelope_url.set_path(&format!("{}/api/{}/envelope/", prefix, project_id));
+
+        Ok(Dsn {
+            public_key: url.username().to_string(),
+            envelope_url,
+        })
+    }
+
+    fn auth_header(&self) -> String {
+        format!(
+            "Sentry sentry_version=7, sentry_key={}, sentry_client=chirpstack/{}",
+            self.public_key,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+}
+
+// Returns the error-reporting layer, reporting the error events (including the span context)
+// and panics to the configured Sentry-compatible endpoint. This returns None in case error
+// reporting is disabled.
+pub fn layer<S>() -> Result<Option<impl Layer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let conf = config::get();
+    if conf.monitoring.error_reporting.dsn.is_empty() {
+        return Ok(None);
+    }
+
+    let dsn = Dsn::parse(&conf.monitoring.error_reporting.dsn)?;
+    let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+
+    let (tx, mut rx) = mpsc::channel::<ErrorEvent>(QUEUE_SIZE);
+    if SENDER.set(tx).is_err() {
+        return Ok(None);
+    }
+
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            // Errors are logged as warning, as error events would be reported again.
+            if let Err(e) = send_event(&client, &dsn, &event).await {
+                warn!(error = %e, "Sending error report failed");
+            }
+        }
+    });
+
+    set_panic_hook();
+
+    Ok(Some(ErrorReportingLayer {}.with_filter(filter::filter_fn(
+        |meta| {
+            TARGETS.iter().any(|t| meta.target().starts_with(t))
+                && (meta.is_span() || *meta.level() == Level::ERROR)
+        },
+    ))))
+}
+
+// Reports the panics, after which the previous panic hook is called. Note that in case the
+// panic terminates the process, the report might not be sent.
+fn set_panic_hook() {
+    let prev_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = info.payload().downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "panic".to_string()
+        };
+
+        let mut event = ErrorEvent::new("fatal", "panic", message);
+        if let Some(location) = info.location() {
+            event.extra.insert("location".into(), location.to_string());
+        }
+        if let Some(dev_eui) = device_trace::current_dev_eui() {
+            event.tags.insert("dev_eui".into(), dev_eui.to_string());
+        }
+        capture(event);
+
+        prev_hook(info);
+    }));
+}
+
+fn capture(event: ErrorEvent) {
+    if let Some(sender) = SENDER.get() {
+        let _ = sender.try_send(event);
+    }
+}
+
+async fn send_event(client: &Client, dsn: &Dsn, event: &ErrorEvent) -> Result<()> {
+    client
+        .post(dsn.envelope_url.clone())
+        .header(header::CONTENT_TYPE, "application/x-sentry-envelope")
+        .header("X-Sentry-Auth", dsn.auth_header())
+        .body(get_envelope(event)?)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+// Returns the envelope containing the given event.
+// See: https://develop.sentry.dev/sdk/data-model/envelopes/
+fn get_envelope(event: &ErrorEvent) -> Result<String> {
+    let header = serde_json::json!({
+        "event_id": event.event_id,
+        "sent_at": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    });
+    let item_header = serde_json::json!({"type": "event"});
+
+    Ok(format!(
+        "{}\n{}\n{}\n",
+        header,
+        item_header,
+        serde_json::to_string(event)?
+    ))
+}
+
+struct ErrorReportingLayer {}
+
+// Tag fields of the span, stored in the span extensions.
+struct SpanTags(BTreeMap<String, String>);
+
+impl<S> Layer<S> for ErrorReportingLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanTags(visitor.tags));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+
+        if let Some(span) = ctx.span(id) {
+            if let Some(tags) = span.extensions_mut().get_mut::<SpanTags>() {
+                tags.0.extend(visitor.tags);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let meta = event.metadata();
+        let mut error_event = ErrorEvent::new("error", meta.target(), visitor.message);
+        error_event.extra = visitor.extra;
+        error_event.tags = visitor.tags;
+
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut spans: Vec<String> = Vec::new();
+
+            for span in scope {
+                spans.push(span.name().to_string());
+
+                // The scope is iterated from the innermost span, thus tags that are already
+                // set are not overwritten.
+                if let Some(tags) = span.extensions().get::<SpanTags>() {
+                    for (k, v) in &tags.0 {
+                        error_event
+                            .tags
+                            .entry(k.clone())
+                            .or_insert_with(|| v.clone());
+                    }
+                }
+            }
+
+            spans.reverse();
+            error_event.extra.insert("spans".into(), spans.join(" > "));
+        }
+
+        capture(error_event);
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    tags: BTreeMap<String, String>,
+    extra: BTreeMap<String, String>,
+}
+
+impl FieldVisitor {
+    fn record(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = value;
+        } else if TAG_FIELDS.contains(&field.name()) {
+            self.tags.insert(field.name().to_string(), value);
+        } else {
+            self.extra.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_dsn_parse() {
+        struct Test {
+            dsn: &'static str,
+            expected: Option<Dsn>,
+        }
+
+        let tests = vec![
+            Test {
+                dsn: "https://abc123@sentry.example.com/42",
+                expected: Some(Dsn {
+                    public_key: "abc123".into(),
+                    envelope_url: Url::parse("https://sentry.example.com/api/42/envelope/")
+                        .unwrap(),
+                }),
+            },
+            Test {
+                dsn: "http://abc123@localhost:9000/sentry/42",
+                expected: Some(Dsn {
+                    public_key: "abc123".into(),
+                    envelope_url: Url::parse("http://localhost:9000/sentry/api/42/envelope/")
+                        .unwrap(),
+                }),
+            },
+            Test {
+                dsn: "https://sentry.example.com/42",
+                expected: None,
+            },
+            Test {
+                dsn: "https://abc123@sentry.example.com/",
+                expected: None,
+            },
+        ];
+
+        for tst in &tests {
+            assert_eq!(tst.expected, Dsn::parse(tst.dsn).ok());
+        }
+    }
+
+    #[test]
+    fn test_get_envelope() {
+        let mut event = ErrorEvent::new("error", "chirpstack::uplink", "Test error".into());
+        event
+            .tags
+            .insert("dev_eui".into(), "0102030405060708".into());
+
+        let envelope = get_envelope(&event).unwrap();
+        let lines: Vec<&str> = envelope.lines().collect();
+        assert_eq!(3, lines.len());
+
+        let header: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(event.event_id, header["event_id"]);
+
+        let item_header: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!("event", item_header["type"]);
+
+        let payload: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+        assert_eq!("error", payload["level"]);
+        assert_eq!("Test error", payload["message"]["formatted"]);
+        assert_eq!("0102030405060708", payload["tags"]["dev_eui"]);
+    }
+}
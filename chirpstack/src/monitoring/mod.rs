@@ -1 +1,8 @@
+pub mod device_log;
+pub mod device_trace;
+pub mod error_reporting;
+pub mod otel;
 pub mod prometheus;
+pub mod runtime;
+pub mod sla;
+pub mod tenant;
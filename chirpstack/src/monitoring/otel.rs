@@ -0,0 +1,89 @@
+use anyhow::Result;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::config;
+
+// Returns the OpenTelemetry tracing layer, exporting the spans using OTLP. This returns None
+// in case OpenTelemetry is disabled.
+pub fn layer<S>() -> Result<Option<impl Layer<S>>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let conf = config::get();
+    if !conf.monitoring.opentelemetry.enabled {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(conf.monitoring.opentelemetry.otlp_endpoint.clone())
+        .build()?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            conf.monitoring.opentelemetry.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(conf.monitoring.opentelemetry.service_name.clone())
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer("chirpstack");
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider);
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+// Injects the trace context of the current span into the given headers. This is a no-op in
+// case OpenTelemetry is disabled.
+pub fn inject_context(headers: &mut http::HeaderMap) {
+    let cx = tracing::Span::current().context();
+    global::get_text_map_propagator(|p| p.inject_context(&cx, &mut HeaderInjector(headers)));
+}
+
+// Returns the trace context extracted from the given headers.
+pub fn extract_context(headers: &http::HeaderMap) -> opentelemetry::Context {
+    global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+
+    #[test]
+    fn test_extract_context() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+                .parse()
+                .unwrap(),
+        );
+
+        let cx = extract_context(&headers);
+        let span = cx.span();
+        let span_ctx = span.span_context();
+        assert_eq!(
+            "0af7651916cd43dd8448eb211c80319c",
+            span_ctx.trace_id().to_string()
+        );
+        assert_eq!("b7ad6b7169203331", span_ctx.span_id().to_string());
+        assert!(span_ctx.is_sampled());
+    }
+}
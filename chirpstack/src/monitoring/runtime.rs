@@ -0,0 +1,211 @@
+#[cfg(tokio_unstable)]
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+#[cfg(tokio_unstable)]
+use std::time::Instant;
+
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use tokio::runtime::Handle;
+use tokio::time::sleep;
+use tracing::info;
+
+use super::prometheus;
+
+// Interval at which the runtime metrics are updated. The worker utilization is calculated
+// over this interval.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[cfg(tokio_unstable)]
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct WorkerLabels {
+    worker: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct QueueLabels {
+    queue: String,
+}
+
+lazy_static! {
+    static ref WORKERS: Gauge = {
+        let gauge = Gauge::default();
+        prometheus::register(
+            "tokio_workers",
+            "Number of Tokio runtime worker threads",
+            gauge.clone(),
+        );
+        gauge
+    };
+    static ref ALIVE_TASKS: Gauge = {
+        let gauge = Gauge::default();
+        prometheus::register(
+            "tokio_alive_tasks",
+            "Number of alive tasks in the Tokio runtime",
+            gauge.clone(),
+        );
+        gauge
+    };
+    static ref GLOBAL_QUEUE_DEPTH: Gauge = {
+        let gauge = Gauge::default();
+        prometheus::register(
+            "tokio_global_queue_depth",
+            "Number of tasks in the Tokio runtime global queue",
+            gauge.clone(),
+        );
+        gauge
+    };
+    static ref QUEUE_DEPTH: Family<QueueLabels, Gauge> = {
+        let gauge = Family::<QueueLabels, Gauge>::default();
+        prometheus::register(
+            "internal_queue_depth",
+            "Number of pending or in-progress items in internal queues by queue",
+            gauge.clone(),
+        );
+        gauge
+    };
+}
+
+// The per-worker busy duration is only exposed by Tokio when compiled with
+// RUSTFLAGS="--cfg tokio_unstable".
+#[cfg(tokio_unstable)]
+lazy_static! {
+    static ref WORKER_UTILIZATION: Family<WorkerLabels, Gauge<f64, AtomicU64>> = {
+        let gauge = Family::<WorkerLabels, Gauge<f64, AtomicU64>>::default();
+        prometheus::register(
+            "tokio_worker_utilization",
+            "Fraction of time (0 - 1) the Tokio worker was busy by worker",
+            gauge.clone(),
+        );
+        gauge
+    };
+}
+
+// Guard tracking an item in an internal queue. The queue depth is decremented when the guard
+// is dropped.
+pub struct QueueGuard {
+    gauge: Gauge,
+}
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+// Increments the depth of the given internal queue and returns the guard decrementing it
+// again. The guard must be held until the item has been handled.
+pub fn enter_queue(queue: &str) -> QueueGuard {
+    let gauge = QUEUE_DEPTH
+        .get_or_create(&QueueLabels {
+            queue: queue.to_string(),
+        })
+        .clone();
+    gauge.inc();
+
+    QueueGuard { gauge }
+}
+
+pub async fn setup() {
+    info!("Setting up Tokio runtime metrics loop");
+
+    let handle = Handle::current();
+    tokio::spawn(async move {
+        update_loop(handle).await;
+    });
+}
+
+async fn update_loop(handle: Handle) {
+    let metrics = handle.metrics();
+    #[cfg(tokio_unstable)]
+    let mut busy: Vec<Duration> = Vec::new();
+    #[cfg(tokio_unstable)]
+    let mut last = Instant::now();
+
+    loop {
+        WORKERS.set(metrics.num_workers() as i64);
+        ALIVE_TASKS.set(metrics.num_alive_tasks() as i64);
+        GLOBAL_QUEUE_DEPTH.set(metrics.global_queue_depth() as i64);
+
+        #[cfg(tokio_unstable)]
+        {
+            let elapsed = last.elapsed();
+            last = Instant::now();
+            update_worker_utilization(&metrics, &mut busy, elapsed);
+        }
+
+        sleep(UPDATE_INTERVAL).await;
+    }
+}
+
+#[cfg(tokio_unstable)]
+fn update_worker_utilization(
+    metrics: &tokio::runtime::RuntimeMetrics,
+    busy: &mut Vec<Duration>,
+    elapsed: Duration,
+) {
+    for worker in 0..metrics.num_workers() {
+        let total_busy = metrics.worker_total_busy_duration(worker);
+
+        // The first run only captures the busy duration.
+        if let Some(prev_busy) = busy.get(worker) {
+            WORKER_UTILIZATION
+                .get_or_create(&WorkerLabels {
+                    worker: worker.to_string(),
+                })
+                .set(utilization(total_busy.saturating_sub(*prev_busy), elapsed));
+        }
+
+        if busy.len() > worker {
+            busy[worker] = total_busy;
+        } else {
+            busy.push(total_busy);
+        }
+    }
+}
+
+#[cfg(tokio_unstable)]
+fn utilization(busy: Duration, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+
+    (busy.as_secs_f64() / elapsed.as_secs_f64()).min(1.0)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[cfg(tokio_unstable)]
+    #[test]
+    fn test_utilization() {
+        assert_eq!(0.0, utilization(Duration::ZERO, Duration::ZERO));
+        assert_eq!(
+            0.5,
+            utilization(Duration::from_secs(5), Duration::from_secs(10))
+        );
+        assert_eq!(
+            1.0,
+            utilization(Duration::from_secs(11), Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_enter_queue() {
+        let labels = QueueLabels {
+            queue: "test".into(),
+        };
+
+        let guard_a = enter_queue("test");
+        let guard_b = enter_queue("test");
+        assert_eq!(2, QUEUE_DEPTH.get_or_create(&labels).get());
+
+        drop(guard_a);
+        assert_eq!(1, QUEUE_DEPTH.get_or_create(&labels).get());
+
+        drop(guard_b);
+        assert_eq!(0, QUEUE_DEPTH.get_or_create(&labels).get());
+    }
+}
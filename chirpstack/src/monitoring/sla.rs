@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, TimeZone, Utc};
+use tokio::time::sleep;
+use tracing::{error, info, trace};
+use uuid::Uuid;
+
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{metrics, tenant, tenant_sla_report};
+use crate::{config, coordination};
+
+// Page size used when iterating over the tenants.
+const TENANT_PAGE_SIZE: i64 = 100;
+
+// Interval in which the pending SLA counters are saved.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+// The SLA counters are aggregated in memory by metrics name and month, and periodically saved
+// by the flush loop, such that recording does not add any storage round-trips. The month is
+// recorded when incrementing, such that counters are never charged to the next month.
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<(String, NaiveDate), HashMap<String, f64>>> =
+        Mutex::new(HashMap::new());
+}
+
+// Records the delivery of an uplink event to the integrations of the given tenant.
+pub fn record_uplink(tenant_id: &Uuid, success: bool) {
+    record(&format!("sla:{}", tenant_id), "uplink", success);
+}
+
+// Records the tx acknowledgement of a downlink of the given tenant.
+pub fn record_downlink(tenant_id: &Uuid, success: bool) {
+    record(&format!("sla:{}", tenant_id), "downlink", success);
+}
+
+// Records an API request. A request is successful when it did not result in a server error.
+pub fn record_api_request(success: bool) {
+    record("sla:api", "api_request", success);
+}
+
+fn record(name: &str, prefix: &str, success: bool) {
+    let conf = config::get();
+    if !conf.metrics.sla.enabled {
+        return;
+    }
+
+    let now = Local::now();
+    let month = match NaiveDate::from_ymd_opt(now.year(), now.month(), 1) {
+        Some(v) => v,
+        None => return,
+    };
+
+    let mut pending = PENDING.lock().unwrap();
+    let m = pending.entry((name.to_string(), month)).or_default();
+    *m.entry(format!("{}_count", prefix)).or_default() += 1.0;
+    *m.entry(format!("{}_success_count", prefix)).or_default() += if success { 1.0 } else { 0.0 };
+}
+
+// Saves the pending SLA counters. Counters which could not be saved are added back to the
+// pending counters, such that these are saved on the next flush.
+pub async fn flush() {
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+
+    for ((name, month), m) in pending {
+        let time = match Local
+            .from_local_datetime(&month.and_hms_opt(0, 0, 0).unwrap_or_default())
+            .earliest()
+        {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let record = metrics::Record {
+            time,
+            kind: metrics::Kind::ABSOLUTE,
+            metrics: m.clone(),
+        };
+
+        if let Err(e) = metrics::save(&name, &record, &[metrics::Aggregation::MONTH]).await {
+            error!(name = %name, error = %e.full(), "Saving SLA metrics failed");
+
+            let mut pending = PENDING.lock().unwrap();
+            let pm = pending.entry((name, month)).or_default();
+            for (k, v) in m {
+                *pm.entry(k).or_default() += v;
+            }
+        }
+    }
+}
+
+// Returns the availability (percentage) given the total and successful count. When the total
+// count is 0, the availability is 100%.
+pub fn availability(count: i64, success_count: i64) -> f64 {
+    if count <= 0 {
+        return 100.0;
+    }
+
+    success_count as f64 / count as f64 * 100.0
+}
+
+pub async fn setup() {
+    let conf = config::get();
+    if !conf.metrics.sla.enabled {
+        return;
+    }
+
+    info!("Setting up SLA flush and report loops");
+    tokio::spawn(async move {
+        flush_loop().await;
+    });
+    tokio::spawn(async move {
+        report_loop().await;
+    });
+}
+
+async fn flush_loop() {
+    loop {
+        sleep(FLUSH_INTERVAL).await;
+        flush().await;
+    }
+}
+
+async fn report_loop() {
+    let conf = config::get();
+
+    loop {
+        trace!("Starting SLA report_loop run");
+
+        match coordination::with_lock("sla:report", update_reports()).await {
+            Ok(Some(Err(e))) => {
+                error!(error = %e.full(), "Updating SLA reports error");
+            }
+            Ok(Some(Ok(_))) => {}
+            Ok(None) => {
+                trace!("SLA report is locked by an other instance, skipping");
+            }
+            Err(e) => {
+                error!(error = %e.full(), "SLA report lock error");
+            }
+        }
+
+        sleep(conf.metrics.sla.interval).await;
+    }
+}
+
+// Updates the SLA reports of the previous and current month for all tenants, using the
+// monthly aggregated SLA metrics. The previous month is included, such that the counters
+// recorded between the last run and the end of the previous month are stored.
+pub async fn update_reports() -> Result<()> {
+    let now = Local::now();
+    let start = now
+        .checked_sub_months(Months::new(1))
+        .ok_or_else(|| anyhow!("Sub month error"))?;
+
+    let api = get_monthly_metrics("sla:api", start, now).await?;
+
+    let mut offset = 0;
+    loop {
+        let tenants = tenant::list(TENANT_PAGE_SIZE, offset, &tenant::Filters::default())
+            .await
+            .context("List tenants")?;
+
+        for t in &tenants {
+            let tenant_metrics = get_monthly_metrics(&format!("sla:{}", t.id), start, now).await?;
+
+            for (month, m) in &tenant_metrics {
+                let api_m = api.get(month).cloned().unwrap_or_default();
+
+                let r = tenant_sla_report::TenantSlaReport {
+                    tenant_id: t.id,
+                    month: *month,
+                    uplink_count: get(m, "uplink_count"),
+                    uplink_success_count: get(m, "uplink_success_count"),
+                    downlink_count: get(m, "downlink_count"),
+                    downlink_success_count: get(m, "downlink_success_count"),
+                    api_request_count: get(&api_m, "api_request_count"),
+                    api_success_count: get(&api_m, "api_success_count"),
+                    updated_at: Utc::now(),
+                };
+
+                // Do not create (or overwrite) reports for months without any data, e.g.
+                // because SLA reporting was not yet enabled.
+                if r.uplink_count == 0 && r.downlink_count == 0 && r.api_request_count == 0 {
+                    continue;
+                }
+
+                tenant_sla_report::upsert(r).await?;
+            }
+        }
+
+        if (tenants.len() as i64) < TENANT_PAGE_SIZE {
+            break;
+        }
+        offset += TENANT_PAGE_SIZE;
+    }
+
+    Ok(())
+}
+
+fn get(m: &HashMap<String, f64>, k: &str) -> i64 {
+    m.get(k).cloned().unwrap_or_default() as i64
+}
+
+// Returns the monthly aggregated metrics, by the first day of the month.
+async fn get_monthly_metrics(
+    name: &str,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> Result<HashMap<NaiveDate, HashMap<String, f64>>> {
+    let records = metrics::get(
+        name,
+        metrics::Kind::ABSOLUTE,
+        metrics::Aggregation::MONTH,
+        start,
+        end,
+    )
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .filter_map(|r| {
+            NaiveDate::from_ymd_opt(r.time.year(), r.time.month(), 1).map(|d| (d, r.metrics))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn test_availability() {
+        assert_eq!(100.0, availability(0, 0));
+        assert_eq!(100.0, availability(10, 10));
+        assert_eq!(90.0, availability(10, 9));
+    }
+
+    #[tokio::test]
+    async fn test_update_reports() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.metrics.sla.enabled = true;
+        config::set(conf);
+
+        let t_a = tenant::test::create_tenant().await;
+        let t_b = tenant::test::create_tenant().await;
+
+        record_uplink(&t_a.id, true);
+        record_uplink(&t_a.id, false);
+        record_downlink(&t_a.id, true);
+        record_api_request(true);
+        record_api_request(false);
+
+        // Nothing is saved until the counters are flushed.
+        update_reports().await.unwrap();
+        assert!(tenant_sla_report::list(&t_a.id, &Default::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        flush().await;
+        assert!(PENDING.lock().unwrap().is_empty());
+        update_reports().await.unwrap();
+
+        let now = Local::now();
+        let month = NaiveDate::from_ymd_opt(now.year(), now.month(), 1).unwrap();
+
+        let items = tenant_sla_report::list(&t_a.id, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(month, items[0].month);
+        assert_eq!(2, items[0].uplink_count);
+        assert_eq!(1, items[0].uplink_success_count);
+        assert_eq!(1, items[0].downlink_count);
+        assert_eq!(1, items[0].downlink_success_count);
+        assert_eq!(2, items[0].api_request_count);
+        assert_eq!(1, items[0].api_success_count);
+
+        // Tenant without traffic, this only contains the API figures.
+        let items = tenant_sla_report::list(&t_b.id, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(0, items[0].uplink_count);
+        assert_eq!(0, items[0].downlink_count);
+        assert_eq!(2, items[0].api_request_count);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use uuid::Uuid;
+
+use super::prometheus;
+use crate::config;
+
+// Label under which the metrics are aggregated once the max. number of tenants has been reached.
+const OTHER_TENANT: &str = "other";
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct UplinkLabels {
+    tenant_id: String,
+    m_type: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct TenantLabels {
+    tenant_id: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct IntegrationLabels {
+    tenant_id: String,
+    event: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct AirtimeLabels {
+    tenant_id: String,
+    direction: String,
+}
+
+lazy_static! {
+    static ref TENANTS: RwLock<HashSet<Uuid>> = RwLock::new(HashSet::new());
+    static ref UPLINK_COUNTER: Family<UplinkLabels, Counter> = {
+        let counter = Family::<UplinkLabels, Counter>::default();
+        prometheus::register(
+            "tenant_uplink_count",
+            "Number of received uplinks by tenant and message-type",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref DOWNLINK_COUNTER: Family<TenantLabels, Counter> = {
+        let counter = Family::<TenantLabels, Counter>::default();
+        prometheus::register(
+            "tenant_downlink_count",
+            "Number of transmitted downlinks by tenant",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref INTEGRATION_ERRORS_COUNTER: Family<IntegrationLabels, Counter> = {
+        let counter = Family::<IntegrationLabels, Counter>::default();
+        prometheus::register(
+            "tenant_integration_errors",
+            "Number of integration errors by tenant and event",
+            counter.clone(),
+        );
+        counter
+    };
+    static ref AIRTIME_COUNTER: Family<AirtimeLabels, Counter<f64, AtomicU64>> = {
+        let counter = Family::<AirtimeLabels, Counter<f64, AtomicU64>>::default();
+        prometheus::register(
+            "tenant_airtime_seconds",
+            "Airtime in seconds by tenant and direction",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+// Returns the tenant_id label, or None in case per-tenant metrics are disabled. Once the
+// max. number of tenants has been reached, tenants that have not been seen before are
+// aggregated under the "other" label.
+fn tenant_label(tenant_id: &Uuid) -> Option<String> {
+    let conf = config::get();
+    if !conf.monitoring.tenant_metrics.enabled {
+        return None;
+    }
+
+    if TENANTS.read().unwrap().contains(tenant_id) {
+        return Some(tenant_id.to_string());
+    }
+
+    let mut tenants = TENANTS.write().unwrap();
+    if tenants.len() < conf.monitoring.tenant_metrics.max_tenants {
+        tenants.insert(*tenant_id);
+        Some(tenant_id.to_string())
+    } else {
+        Some(OTHER_TENANT.to_string())
+    }
+}
+
+pub fn inc_uplink(tenant_id: &Uuid, m_type: lrwn::MType) {
+    if let Some(tenant_id) = tenant_label(tenant_id) {
+        UPLINK_COUNTER
+            .get_or_create(&UplinkLabels {
+                tenant_id,
+                m_type: m_type.to_string(),
+            })
+            .inc();
+    }
+}
+
+pub fn inc_downlink(tenant_id: &Uuid) {
+    if let Some(tenant_id) = tenant_label(tenant_id) {
+        DOWNLINK_COUNTER
+            .get_or_create(&TenantLabels { tenant_id })
+            .inc();
+    }
+}
+
+pub fn inc_integration_errors(tenant_id: &Uuid, event: &str) {
+    if let Some(tenant_id) = tenant_label(tenant_id) {
+        INTEGRATION_ERRORS_COUNTER
+            .get_or_create(&IntegrationLabels {
+                tenant_id,
+                event: event.to_string(),
+            })
+            .inc();
+    }
+}
+
+pub fn inc_airtime(tenant_id: &Uuid, direction: &str, airtime: Duration) {
+    if let Some(tenant_id) = tenant_label(tenant_id) {
+        AIRTIME_COUNTER
+            .get_or_create(&AirtimeLabels {
+                tenant_id,
+                direction: direction.to_string(),
+            })
+            .inc_by(airtime.as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_tenant_label() {
+        let _guard = test::prepare().await;
+
+        // disabled
+        assert_eq!(None, tenant_label(&Uuid::new_v4()));
+
+        let mut conf = (*config::get()).clone();
+        conf.monitoring.tenant_metrics.enabled = true;
+        conf.monitoring.tenant_metrics.max_tenants = 1;
+        config::set(conf);
+        TENANTS.write().unwrap().clear();
+
+        let tenant_a = Uuid::new_v4();
+        let tenant_b = Uuid::new_v4();
+
+        assert_eq!(Some(tenant_a.to_string()), tenant_label(&tenant_a));
+        assert_eq!(Some(tenant_a.to_string()), tenant_label(&tenant_a));
+        assert_eq!(Some(OTHER_TENANT.to_string()), tenant_label(&tenant_b));
+
+        TENANTS.write().unwrap().clear();
+    }
+}
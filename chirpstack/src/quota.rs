@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+use anyhow::Result;
+use chrono::{NaiveDate, Utc};
+use tokio::time::sleep;
+use tracing::{error, info, trace, warn};
+use uuid::Uuid;
+
+use crate::api::helpers::ToProto;
+use crate::config::{self, QuotaAction};
+use crate::helpers::errors::PrintFullError;
+use crate::integration;
+use crate::storage::error::Error;
+use crate::storage::{device_queue, helpers::get_all_device_data, tenant, tenant_usage};
+use chirpstack_api::integration as integration_pb;
+
+// The uplinks and downlinks are counted in memory per tenant and day. The usage accounting task
+// periodically adds these counters to the stored usage and evaluates the quotas of the tenants.
+// The uplink and downlink handling only reads the evaluated quota state, such that it does not
+// add any storage round-trips.
+
+#[derive(Default, Clone, Copy)]
+struct Counters {
+    uplink_count: i64,
+    downlink_count: i64,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+struct Exceeded {
+    uplink: bool,
+    downlink: bool,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<(Uuid, NaiveDate), Counters>> = Mutex::new(HashMap::new());
+    static ref EXCEEDED: RwLock<HashMap<Uuid, Exceeded>> = RwLock::new(HashMap::new());
+}
+
+pub async fn setup() {
+    info!("Setting up tenant usage accounting loop");
+    tokio::spawn(async move {
+        accounting_loop().await;
+    });
+}
+
+// Returns the configured action in case the tenant exceeded its uplinks per day quota.
+pub fn uplink_quota_exceeded(tenant_id: &Uuid) -> Option<QuotaAction> {
+    let exceeded = EXCEEDED.read().unwrap();
+    if exceeded.get(tenant_id).is_some_and(|v| v.uplink) {
+        Some(config::get().network.quota.uplink_action)
+    } else {
+        None
+    }
+}
+
+// Returns the configured action in case the tenant exceeded its downlinks per day quota.
+pub fn downlink_quota_exceeded(tenant_id: &Uuid) -> Option<QuotaAction> {
+    let exceeded = EXCEEDED.read().unwrap();
+    if exceeded.get(tenant_id).is_some_and(|v| v.downlink) {
+        Some(config::get().network.quota.downlink_action)
+    } else {
+        None
+    }
+}
+
+// Records an uplink of the given tenant.
+pub fn inc_uplink(tenant_id: &Uuid) {
+    let day = Utc::now().date_naive();
+    let mut pending = PENDING.lock().unwrap();
+    pending.entry((*tenant_id, day)).or_default().uplink_count += 1;
+}
+
+// Records a downlink of the given tenant.
+pub fn inc_downlink(tenant_id: &Uuid) {
+    let day = Utc::now().date_naive();
+    let mut pending = PENDING.lock().unwrap();
+    pending.entry((*tenant_id, day)).or_default().downlink_count += 1;
+}
+
+// Enqueues the given (application) downlink, taking the state of the device and the downlinks
+// per day quota of the tenant into account. When the quota has been exceeded, a log event is
+// sent to the integrations and depending on the configured action, the enqueue is rejected.
+pub async fn enqueue_item(
+    qi: device_queue::DeviceQueueItem,
+) -> Result<device_queue::DeviceQueueItem, Error> {
+    let (dev, app, t, dp) = get_all_device_data(qi.dev_eui).await?;
+
+    if dev.state.is_out_of_service() {
+        return Err(Error::NotAllowed(format!(
+            "Device is in {} state",
+            dev.state.to_string().to_lowercase()
+        )));
+    }
+
+    if let Some(action) = downlink_quota_exceeded(&t.id) {
+        warn!(
+            dev_eui = %dev.dev_eui,
+            tenant_id = %t.id,
+            action = ?action,
+            "Tenant exceeded downlinks per day quota"
+        );
+
+        let mut tags = (*app.tags).clone();
+        tags.extend((*dp.tags).clone());
+        tags.extend((*dev.tags).clone());
+
+        let pl = integration_pb::LogEvent {
+            time: Some(Utc::now().into()),
+            device_info: Some(integration_pb::DeviceInfo {
+                tenant_id: t.id.to_string(),
+                tenant_name: t.name.clone(),
+                application_id: app.id.to_string(),
+                application_name: app.name.to_string(),
+                device_profile_id: dp.id.to_string(),
+                device_profile_name: dp.name.clone(),
+                device_name: dev.name.clone(),
+                device_class_enabled: dev.enabled_class.to_proto().into(),
+                dev_eui: dev.dev_eui.to_string(),
+                tags,
+            }),
+            level: integration_pb::LogLevel::Warning.into(),
+            code: integration_pb::LogCode::QuotaExceeded.into(),
+            description: "Tenant exceeded the max. number of downlinks per day".into(),
+            context: get_log_context("downlink", t.max_downlink_count_per_day, action),
+        };
+        integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+        if action == QuotaAction::Reject {
+            return Err(Error::NotAllowed(
+                "Max number of downlinks per day exceeded for tenant".into(),
+            ));
+        }
+    }
+
+    let qi = device_queue::enqueue_item(qi).await?;
+    inc_downlink(&t.id);
+
+    Ok(qi)
+}
+
+// Returns the context of the QUOTA_EXCEEDED log event.
+pub fn get_log_context(
+    direction: &str,
+    max_count_per_day: i32,
+    action: QuotaAction,
+) -> HashMap<String, String> {
+    [
+        ("direction".to_string(), direction.to_string()),
+        (
+            "max_count_per_day".to_string(),
+            max_count_per_day.to_string(),
+        ),
+        (
+            "action".to_string(),
+            match action {
+                QuotaAction::Warn => "warn",
+                QuotaAction::Reject => "reject",
+            }
+            .to_string(),
+        ),
+    ]
+    .iter()
+    .cloned()
+    .collect()
+}
+
+async fn accounting_loop() {
+    loop {
+        let conf = config::get();
+        sleep(conf.network.quota.accounting_interval).await;
+
+        trace!("Starting tenant usage accounting_loop run");
+        if let Err(e) = update_usage().await {
+            error!(error = %e.full(), "Updating tenant usage error");
+        }
+    }
+}
+
+// Adds the pending counters to the stored usage of the day on which these were recorded and
+// evaluates the quotas of the tenants for which usage was recorded or which exceeded a quota (e.g.
+// to reset the state on the next day, or after the quota has been increased). Counters which
+// could not be stored are added back to the pending counters, such that these are retried.
+pub async fn update_usage() -> Result<()> {
+    let today = Utc::now().date_naive();
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+
+    let mut tenant_counters: HashMap<Uuid, Vec<(NaiveDate, Counters)>> = HashMap::new();
+    for ((tenant_id, day), counters) in pending {
+        tenant_counters
+            .entry(tenant_id)
+            .or_default()
+            .push((day, counters));
+    }
+    for tenant_id in EXCEEDED.read().unwrap().keys() {
+        tenant_counters.entry(*tenant_id).or_default();
+    }
+
+    for (tenant_id, mut counters) in tenant_counters {
+        if let Err(e) = update_tenant_usage(&tenant_id, today, &mut counters).await {
+            error!(tenant_id = %tenant_id, error = %e.full(), "Updating tenant usage failed");
+
+            let mut pending = PENDING.lock().unwrap();
+            for (day, c) in counters {
+                let p = pending.entry((tenant_id, day)).or_default();
+                p.uplink_count += c.uplink_count;
+                p.downlink_count += c.downlink_count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Adds the given counters to the stored usage and evaluates the quotas of the tenant, using the
+// usage of the given day. Counters are removed from the given vector once stored.
+async fn update_tenant_usage(
+    tenant_id: &Uuid,
+    today: NaiveDate,
+    counters: &mut Vec<(NaiveDate, Counters)>,
+) -> Result<()> {
+    let t = match tenant::get(tenant_id).await {
+        Ok(v) => v,
+        Err(Error::NotFound(_)) => {
+            EXCEEDED.write().unwrap().remove(tenant_id);
+            counters.clear();
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut usage = None;
+    while let Some((day, c)) = counters.last().cloned() {
+        let u = tenant_usage::increment(tenant_usage::TenantUsage {
+            tenant_id: t.id,
+            day,
+            uplink_count: c.uplink_count,
+            downlink_count: c.downlink_count,
+            updated_at: Utc::now(),
+        })
+        .await?;
+        counters.pop();
+
+        if day == today {
+            usage = Some(u);
+        }
+    }
+
+    let u = match usage {
+        Some(v) => v,
+        None => tenant_usage::get(tenant_id, today).await?,
+    };
+
+    let exceeded = Exceeded {
+        uplink: is_exceeded(t.max_uplink_count_per_day, u.uplink_count),
+        downlink: is_exceeded(t.max_downlink_count_per_day, u.downlink_count),
+    };
+
+    let mut exceeded_w = EXCEEDED.write().unwrap();
+    let prev = exceeded_w.get(tenant_id).cloned().unwrap_or_default();
+
+    if exceeded.uplink && !prev.uplink {
+        warn!(
+            tenant_id = %tenant_id,
+            uplink_count = u.uplink_count,
+            max_uplink_count_per_day = t.max_uplink_count_per_day,
+            "Tenant exceeded uplinks per day quota"
+        );
+    }
+    if exceeded.downlink && !prev.downlink {
+        warn!(
+            tenant_id = %tenant_id,
+            downlink_count = u.downlink_count,
+            max_downlink_count_per_day = t.max_downlink_count_per_day,
+            "Tenant exceeded downlinks per day quota"
+        );
+    }
+
+    if exceeded == Exceeded::default() {
+        exceeded_w.remove(tenant_id);
+    } else {
+        exceeded_w.insert(*tenant_id, exceeded);
+    }
+
+    Ok(())
+}
+
+fn is_exceeded(max_count: i32, count: i64) -> bool {
+    max_count != 0 && count >= max_count as i64
+}
+
+#[cfg(test)]
+pub fn reset() {
+    PENDING.lock().unwrap().clear();
+    EXCEEDED.write().unwrap().clear();
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::integration::mock;
+    use crate::storage::{application, device, device_profile};
+    use crate::test;
+    use lrwn::EUI64;
+
+    #[test]
+    fn test_is_exceeded() {
+        assert!(!is_exceeded(0, 100));
+        assert!(!is_exceeded(10, 9));
+        assert!(is_exceeded(10, 10));
+    }
+
+    #[tokio::test]
+    async fn test_quota() {
+        let _guard = test::prepare().await;
+        integration::set_mock().await;
+        mock::reset().await;
+        reset();
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            max_uplink_count_per_day: 2,
+            max_downlink_count_per_day: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            name: "test-dp".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dev = device::create(device::Device {
+            name: "test-device".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            application_id: app.id,
+            device_profile_id: dp.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // uplink quota
+        inc_uplink(&t.id);
+        update_usage().await.unwrap();
+        assert!(uplink_quota_exceeded(&t.id).is_none());
+
+        inc_uplink(&t.id);
+        update_usage().await.unwrap();
+        assert_eq!(Some(QuotaAction::Warn), uplink_quota_exceeded(&t.id));
+
+        // downlink quota
+        enqueue_item(device_queue::DeviceQueueItem {
+            dev_eui: dev.dev_eui,
+            f_port: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(downlink_quota_exceeded(&t.id).is_none());
+
+        update_usage().await.unwrap();
+        assert_eq!(Some(QuotaAction::Reject), downlink_quota_exceeded(&t.id));
+
+        let res = enqueue_item(device_queue::DeviceQueueItem {
+            dev_eui: dev.dev_eui,
+            f_port: 10,
+            ..Default::default()
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(
+            1,
+            device_queue::get_for_dev_eui(&dev.dev_eui)
+                .await
+                .unwrap()
+                .len()
+        );
+
+        // log event
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let events = mock::get_log_events().await;
+        assert_eq!(1, events.len());
+        assert_eq!(integration_pb::LogCode::QuotaExceeded, events[0].code());
+
+        let u = tenant_usage::get(&t.id, Utc::now().date_naive())
+            .await
+            .unwrap();
+        assert_eq!(2, u.uplink_count);
+        assert_eq!(1, u.downlink_count);
+
+        // usage recorded before midnight is charged to the day on which it was recorded
+        let yesterday = Utc::now().date_naive().pred_opt().unwrap();
+        PENDING.lock().unwrap().insert(
+            (t.id.into(), yesterday),
+            Counters {
+                uplink_count: 5,
+                downlink_count: 0,
+            },
+        );
+        update_usage().await.unwrap();
+        assert!(PENDING.lock().unwrap().is_empty());
+        let u = tenant_usage::get(&t.id, yesterday).await.unwrap();
+        assert_eq!(5, u.uplink_count);
+        let u = tenant_usage::get(&t.id, Utc::now().date_naive())
+            .await
+            .unwrap();
+        assert_eq!(2, u.uplink_count);
+
+        // raising the quota
+        let mut t = t;
+        t.max_uplink_count_per_day = 0;
+        tenant::update(t.clone()).await.unwrap();
+        update_usage().await.unwrap();
+        assert!(uplink_quota_exceeded(&t.id).is_none());
+        assert!(downlink_quota_exceeded(&t.id).is_some());
+    }
+}
@@ -5,6 +5,7 @@ use anyhow::{Context, Result};
 use tracing::{info, span, trace, Level};
 
 use crate::config;
+use crate::storage::{device, gateway};
 use lrwn::region;
 
 lazy_static! {
@@ -19,49 +20,133 @@ pub fn setup() -> Result<()> {
     reset();
 
     for r in &conf.regions {
-        let span = span!(Level::INFO, "setup", common_name = %r.common_name, region_id = %r.id);
-        let _guard = span.enter();
-
         if !conf.network.enabled_regions.contains(&r.id) {
             continue;
         }
 
-        info!("Configuring region");
+        set(&r.id, new_region(r)?);
+    }
 
-        let mut region_conf = region::get(
-            r.common_name,
-            r.network.repeater_compatible,
-            r.network.dwell_time_400ms,
-        );
+    Ok(())
+}
+
+// Region changes between the old and new configuration, which have been validated and are ready
+// to be applied.
+pub struct Reload {
+    set: Vec<(String, Box<dyn region::Region + Sync + Send>)>,
+    remove: Vec<String>,
+}
 
-        for ec in &r.network.extra_channels {
-            trace!(
-                frequency = ec.frequency,
-                min_dr = ec.min_dr,
-                max_dr = ec.max_dr,
-                "Adding extra channel"
-            );
-            region_conf
-                .add_channel(ec.frequency, ec.min_dr, ec.max_dr)
-                .context("Add channel")?;
+impl Reload {
+    // Applies the region changes. This can not fail, such that it can be called after all the
+    // other reload steps have succeeded.
+    pub fn apply(self) {
+        for (region_config_id, r) in self.set {
+            set(&region_config_id, r);
         }
 
-        if !r.network.enabled_uplink_channels.is_empty() {
-            trace!("Disabling all channels first");
-            for i in region_conf.get_enabled_uplink_channel_indices() {
-                region_conf.disable_uplink_channel_index(i)?;
-            }
+        for region_config_id in &self.remove {
+            info!(region_id = %region_config_id, "Region disabled, removing region");
+            remove(region_config_id);
+        }
+    }
+}
+
+// Prepares the region changes between the old and new configuration. Regions which are newly
+// enabled or of which the configuration has changed are (re-)configured, regions which are no
+// longer enabled are removed. Other regions are not affected. A region can not be removed while
+// it is still used by devices or relay gateways, as these would fail to find their region.
+pub async fn prepare_reload(
+    old: &config::Configuration,
+    new: &config::Configuration,
+) -> Result<Reload> {
+    let mut out = Reload {
+        set: Vec::new(),
+        remove: Vec::new(),
+    };
 
-            trace!(channels = ?r.network.enabled_uplink_channels, "Enabling channels");
-            for i in &r.network.enabled_uplink_channels {
-                region_conf.enable_uplink_channel_index(*i)?;
+    for region in new
+        .regions
+        .iter()
+        .filter(|region| new.network.enabled_regions.contains(&region.id))
+    {
+        if let Some(old_region) = old
+            .regions
+            .iter()
+            .find(|r| r.id == region.id && old.network.enabled_regions.contains(&r.id))
+        {
+            if old_region.common_name == region.common_name
+                && serde_json::to_value(&old_region.network)?
+                    == serde_json::to_value(&region.network)?
+            {
+                continue;
             }
         }
 
-        set(&r.id, region_conf);
+        info!(region_id = %region.id, "Region added or changed, reloading region");
+        out.set.push((region.id.clone(), new_region(region)?));
     }
 
-    Ok(())
+    for region in old
+        .regions
+        .iter()
+        .filter(|region| old.network.enabled_regions.contains(&region.id))
+        .filter(|region| !new.network.enabled_regions.contains(&region.id))
+    {
+        let devices = device::get_count_for_region(&region.id, region.common_name).await?;
+        let relays = gateway::get_relay_gateway_count_for_region(&region.id).await?;
+        if devices != 0 || relays != 0 {
+            return Err(anyhow!(
+                "Region {} is still used by {} device(s) and {} relay gateway(s)",
+                region.id,
+                devices,
+                relays
+            ));
+        }
+
+        out.remove.push(region.id.clone());
+    }
+
+    Ok(out)
+}
+
+fn new_region(r: &config::Region) -> Result<Box<dyn region::Region + Sync + Send>> {
+    let span = span!(Level::INFO, "setup", common_name = %r.common_name, region_id = %r.id);
+    let _guard = span.enter();
+
+    info!("Configuring region");
+
+    let mut region_conf = region::get(
+        r.common_name,
+        r.network.repeater_compatible,
+        r.network.dwell_time_400ms,
+    );
+
+    for ec in &r.network.extra_channels {
+        trace!(
+            frequency = ec.frequency,
+            min_dr = ec.min_dr,
+            max_dr = ec.max_dr,
+            "Adding extra channel"
+        );
+        region_conf
+            .add_channel(ec.frequency, ec.min_dr, ec.max_dr)
+            .context("Add channel")?;
+    }
+
+    if !r.network.enabled_uplink_channels.is_empty() {
+        trace!("Disabling all channels first");
+        for i in region_conf.get_enabled_uplink_channel_indices() {
+            region_conf.disable_uplink_channel_index(i)?;
+        }
+
+        trace!(channels = ?r.network.enabled_uplink_channels, "Enabling channels");
+        for i in &r.network.enabled_uplink_channels {
+            region_conf.enable_uplink_channel_index(*i)?;
+        }
+    }
+
+    Ok(region_conf)
 }
 
 fn reset() {
@@ -74,6 +159,11 @@ pub fn set(region_config_id: &str, r: Box<dyn region::Region + Sync + Send>) {
     regions_w.insert(region_config_id.to_string(), Arc::new(r));
 }
 
+pub fn remove(region_config_id: &str) {
+    let mut regions_w = REGIONS.write().unwrap();
+    regions_w.remove(region_config_id);
+}
+
 pub fn get(region_config_id: &str) -> Result<Arc<Box<dyn region::Region + Sync + Send>>> {
     let regions_r = REGIONS.read().unwrap();
     Ok(regions_r
@@ -103,3 +193,68 @@ pub fn get_region_config_id(common_name: region::CommonName) -> Result<String> {
         common_name
     ))
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::{storage, test};
+    use lrwn::EUI64;
+
+    #[tokio::test]
+    async fn test_reload() {
+        let _guard = test::prepare().await;
+
+        let old = config::get();
+        let mut new = (*old).clone();
+        new.network.enabled_regions = vec!["us915_0".into()];
+        new.regions.push(config::Region {
+            id: "us915_0".into(),
+            common_name: lrwn::region::CommonName::US915,
+            network: config::RegionNetwork {
+                enabled_uplink_channels: vec![0, 1, 2, 3, 4, 5, 6, 7, 64],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        prepare_reload(&old, &new).await.unwrap().apply();
+        assert!(get("eu868").is_err());
+        let r = get("us915_0").unwrap();
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 64],
+            r.get_enabled_uplink_channel_indices()
+        );
+
+        // Unchanged regions are not re-configured.
+        let old = new.clone();
+        new.network.enabled_regions.push("eu868".into());
+        prepare_reload(&old, &new).await.unwrap().apply();
+        assert!(get("eu868").is_ok());
+        assert!(Arc::ptr_eq(&r, &get("us915_0").unwrap()));
+
+        // Changed regions are re-configured.
+        let old = new.clone();
+        new.regions[1].network.enabled_uplink_channels = vec![0, 1];
+        prepare_reload(&old, &new).await.unwrap().apply();
+        assert_eq!(
+            vec![0, 1],
+            get("us915_0").unwrap().get_enabled_uplink_channel_indices()
+        );
+
+        // Regions which are still in use can not be disabled.
+        let mut dp = storage::device_profile::test::create_device_profile(None).await;
+        dp.region_config_id = Some("eu868".into());
+        let dp = storage::device_profile::update(dp).await.unwrap();
+        storage::device::test::create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            dp.id.into(),
+            None,
+        )
+        .await;
+
+        let old = new.clone();
+        new.network.enabled_regions = vec!["us915_0".into()];
+        assert!(prepare_reload(&old, &new).await.is_err());
+        assert!(get("eu868").is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use tracing::trace;
+
+use crate::downlink::tx_ack::TxAck;
+use crate::gateway::backend::GatewayBackend;
+use chirpstack_api::gw;
+
+// Gateway backend which delivers the downlinks to the simulated devices, instead of publishing
+// these to a broker. Each downlink is acknowledged, like a gateway would do.
+pub struct Backend {
+    downlinks: broadcast::Sender<gw::DownlinkFrame>,
+}
+
+impl Backend {
+    pub fn new(downlinks: broadcast::Sender<gw::DownlinkFrame>) -> Backend {
+        Backend { downlinks }
+    }
+}
+
+#[async_trait]
+impl GatewayBackend for Backend {
+    async fn send_downlink(&self, df: &gw::DownlinkFrame) -> Result<()> {
+        trace!(gateway_id = %df.gateway_id, downlink_id = df.downlink_id, "Delivering downlink to simulated devices");
+
+        // This only fails when there are no devices subscribed.
+        let _ = self.downlinks.send(df.clone());
+
+        // The first item is always emitted.
+        tokio::spawn(TxAck::handle(gw::DownlinkTxAck {
+            gateway_id: df.gateway_id.clone(),
+            downlink_id: df.downlink_id,
+            items: df
+                .items
+                .iter()
+                .enumerate()
+                .map(|(i, _)| gw::DownlinkTxAckItem {
+                    status: match i {
+                        0 => gw::TxAckStatus::Ok,
+                        _ => gw::TxAckStatus::Ignored,
+                    }
+                    .into(),
+                })
+                .collect(),
+            ..Default::default()
+        }));
+
+        Ok(())
+    }
+
+    async fn send_configuration(&self, _gw_conf: &gw::GatewayConfiguration) -> Result<()> {
+        Ok(())
+    }
+}
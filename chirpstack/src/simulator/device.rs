@@ -0,0 +1,359 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use tokio::sync::broadcast;
+use tokio::time::{interval, sleep};
+use tracing::{debug, error, info};
+
+use super::gateway::Gateway;
+use super::{Counters, Options};
+use crate::helpers::errors::PrintFullError;
+use crate::{region, uplink};
+use chirpstack_api::gw;
+use lrwn::{keys, AES128Key, DevAddr, EUI64};
+
+struct Session {
+    dev_addr: DevAddr,
+    nwk_s_key: AES128Key,
+    app_s_key: AES128Key,
+    f_cnt_up: u32,
+    // Set when a confirmed downlink must be acknowledged by the next uplink.
+    ack: bool,
+}
+
+// Simulated LoRaWAN 1.0.x OTAA device. Until the device has joined, each interval a
+// join-request is sent, after that each interval an unconfirmed uplink is sent.
+pub struct Device {
+    dev_eui: EUI64,
+    join_eui: EUI64,
+    nwk_key: AES128Key,
+    dev_nonce: u16,
+    session: Option<Session>,
+    options: Arc<Options>,
+    gateways: Arc<Vec<Gateway>>,
+    counters: Arc<Counters>,
+}
+
+impl Device {
+    pub fn new(
+        dev_eui: EUI64,
+        join_eui: EUI64,
+        nwk_key: AES128Key,
+        options: Arc<Options>,
+        gateways: Arc<Vec<Gateway>>,
+        counters: Arc<Counters>,
+    ) -> Device {
+        Device {
+            dev_eui,
+            join_eui,
+            nwk_key,
+            dev_nonce: rand::rng().random(),
+            session: None,
+            options,
+            gateways,
+            counters,
+        }
+    }
+
+    pub async fn run(mut self, mut downlinks: broadcast::Receiver<gw::DownlinkFrame>) {
+        // Spread the uplinks of the devices over the uplink interval.
+        let uplink_interval = self.options.uplink_interval;
+        sleep(uplink_interval.mul_f64(rand::rng().random())).await;
+
+        let mut ticker = interval(uplink_interval);
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let res = match self.session {
+                        None => self.send_join_request(),
+                        Some(_) => self.send_data_up(),
+                    };
+
+                    if let Err(e) = res {
+                        error!(dev_eui = %self.dev_eui, error = %e.full(), "Sending simulated uplink failed");
+                    }
+                }
+                df = downlinks.recv() => {
+                    match df {
+                        Ok(df) => {
+                            if let Err(e) = self.handle_downlink(&df) {
+                                debug!(dev_eui = %self.dev_eui, error = %e.full(), "Handling simulated downlink failed");
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    }
+                }
+            }
+        }
+    }
+
+    fn send_join_request(&mut self) -> Result<()> {
+        self.dev_nonce = self.dev_nonce.wrapping_add(1);
+
+        let mut phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: lrwn::MType::JoinRequest,
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::JoinRequest(lrwn::JoinRequestPayload {
+                join_eui: self.join_eui,
+                dev_eui: self.dev_eui,
+                dev_nonce: self.dev_nonce,
+            }),
+            mic: None,
+        };
+        phy.set_join_request_mic(&self.nwk_key)?;
+
+        self.send_uplink(&phy)?;
+        self.counters.join_requests.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn send_data_up(&mut self) -> Result<()> {
+        let ss = self
+            .session
+            .as_mut()
+            .ok_or_else(|| anyhow!("Device has not joined"))?;
+
+        let mut data = vec![0; self.options.payload_size];
+        rand::rng().fill(&mut data[..]);
+
+        let mut phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: lrwn::MType::UnconfirmedDataUp,
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::MACPayload(lrwn::MACPayload {
+                fhdr: lrwn::FHDR {
+                    devaddr: ss.dev_addr,
+                    f_cnt: ss.f_cnt_up,
+                    f_ctrl: lrwn::FCtrl {
+                        ack: ss.ack,
+                        ..Default::default()
+                    },
+                    f_opts: lrwn::MACCommandSet::new(vec![]),
+                },
+                f_port: Some(self.options.f_port),
+                frm_payload: Some(lrwn::FRMPayload::Raw(data)),
+            }),
+            mic: None,
+        };
+        phy.encrypt_frm_payload(&ss.app_s_key)?;
+        phy.set_uplink_data_mic(
+            lrwn::MACVersion::LoRaWAN1_0,
+            0,
+            0,
+            0,
+            &ss.nwk_s_key,
+            &ss.nwk_s_key,
+        )?;
+
+        ss.f_cnt_up += 1;
+        ss.ack = false;
+
+        self.send_uplink(&phy)?;
+        self.counters.uplinks.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    // Sends the uplink on a random enabled uplink channel. The uplink is received by all the
+    // simulated gateways.
+    fn send_uplink(&self, phy: &lrwn::PhyPayload) -> Result<()> {
+        let region_conf = region::get(&self.options.region_config_id)?;
+        let channel = *region_conf
+            .get_enabled_uplink_channel_indices()
+            .choose(&mut rand::rng())
+            .ok_or_else(|| anyhow!("No enabled uplink channels"))?;
+
+        let mut tx_info = gw::UplinkTxInfo {
+            frequency: region_conf.get_uplink_channel(channel)?.frequency,
+            ..Default::default()
+        };
+        uplink::helpers::set_uplink_modulation(
+            &self.options.region_config_id,
+            &mut tx_info,
+            self.options.dr,
+        )?;
+
+        let b = phy.to_vec()?;
+        for gw in self.gateways.iter() {
+            gw.send_uplink(&b, &tx_info);
+        }
+
+        Ok(())
+    }
+
+    fn handle_downlink(&mut self, df: &gw::DownlinkFrame) -> Result<()> {
+        let item = match df.items.first() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let mut phy =
+            lrwn::PhyPayload::from_slice(&item.phy_payload).context("Decode PhyPayload")?;
+
+        match phy.mhdr.m_type {
+            lrwn::MType::JoinAccept => {
+                if self.session.is_some() {
+                    return Ok(());
+                }
+
+                // The downlinks are delivered to all the devices, the MIC validation fails for
+                // join-accepts targeting an other device.
+                phy.decrypt_join_accept_payload(&self.nwk_key)?;
+                if !phy.validate_join_accept_mic(
+                    lrwn::JoinType::Join,
+                    &self.join_eui,
+                    self.dev_nonce,
+                    &self.nwk_key,
+                )? {
+                    return Ok(());
+                }
+
+                if let lrwn::Payload::JoinAccept(pl) = &phy.payload {
+                    let opt_neg = pl.dl_settings.opt_neg;
+
+                    self.session = Some(Session {
+                        dev_addr: pl.devaddr,
+                        nwk_s_key: keys::get_f_nwk_s_int_key(
+                            opt_neg,
+                            &self.nwk_key,
+                            &pl.home_netid,
+                            &self.join_eui,
+                            pl.join_nonce,
+                            self.dev_nonce,
+                        )?,
+                        app_s_key: keys::get_app_s_key(
+                            opt_neg,
+                            &self.nwk_key,
+                            &pl.home_netid,
+                            &self.join_eui,
+                            pl.join_nonce,
+                            self.dev_nonce,
+                        )?,
+                        f_cnt_up: 0,
+                        ack: false,
+                    });
+                    self.counters.join_accepts.fetch_add(1, Ordering::Relaxed);
+
+                    info!(dev_eui = %self.dev_eui, dev_addr = %pl.devaddr, "Simulated device joined");
+                }
+            }
+            lrwn::MType::UnconfirmedDataDown | lrwn::MType::ConfirmedDataDown => {
+                let confirmed = phy.mhdr.m_type == lrwn::MType::ConfirmedDataDown;
+
+                if let (Some(ss), lrwn::Payload::MACPayload(pl)) = (&mut self.session, &phy.payload)
+                {
+                    if pl.fhdr.devaddr == ss.dev_addr {
+                        ss.ack = confirmed;
+                        self.counters.downlinks.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn get_device(nwk_key: AES128Key) -> Device {
+        Device {
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            join_eui: EUI64::from_be_bytes([8, 7, 6, 5, 4, 3, 2, 1]),
+            nwk_key,
+            dev_nonce: 258,
+            session: None,
+            options: Arc::new(Options {
+                region_config_id: "eu868".into(),
+                gateways: 1,
+                devices: 1,
+                uplink_interval: Duration::from_secs(60),
+                f_port: 1,
+                payload_size: 10,
+                dr: 0,
+            }),
+            gateways: Arc::new(Vec::new()),
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    fn get_join_accept(nwk_key: &AES128Key) -> gw::DownlinkFrame {
+        let mut phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: lrwn::MType::JoinAccept,
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::JoinAccept(lrwn::JoinAcceptPayload {
+                join_nonce: 1,
+                home_netid: lrwn::NetID::from_be_bytes([0, 0, 0]),
+                devaddr: DevAddr::from_be_bytes([1, 2, 3, 4]),
+                dl_settings: lrwn::DLSettings {
+                    opt_neg: false,
+                    rx2_dr: 0,
+                    rx1_dr_offset: 0,
+                },
+                rx_delay: 1,
+                cflist: None,
+            }),
+            mic: None,
+        };
+        phy.set_join_accept_mic(
+            lrwn::JoinType::Join,
+            &EUI64::from_be_bytes([8, 7, 6, 5, 4, 3, 2, 1]),
+            258,
+            nwk_key,
+        )
+        .unwrap();
+        phy.encrypt_join_accept_payload(nwk_key).unwrap();
+
+        gw::DownlinkFrame {
+            items: vec![gw::DownlinkFrameItem {
+                phy_payload: phy.to_vec().unwrap(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_handle_join_accept() {
+        let nwk_key = AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Join-accept for an other device.
+        let mut d = get_device(nwk_key);
+        let _ = d.handle_downlink(&get_join_accept(&AES128Key::from_bytes([2; 16])));
+        assert!(d.session.is_none());
+        assert_eq!(0, d.counters.join_accepts.load(Ordering::Relaxed));
+
+        // Join-accept for this device.
+        d.handle_downlink(&get_join_accept(&nwk_key)).unwrap();
+        let ss = d.session.as_ref().unwrap();
+        assert_eq!(DevAddr::from_be_bytes([1, 2, 3, 4]), ss.dev_addr);
+        assert_eq!(
+            keys::get_f_nwk_s_int_key(
+                false,
+                &nwk_key,
+                &lrwn::NetID::from_be_bytes([0, 0, 0]),
+                &d.join_eui,
+                1,
+                258
+            )
+            .unwrap(),
+            ss.nwk_s_key
+        );
+        assert_eq!(1, d.counters.join_accepts.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::Utc;
+use rand::Rng;
+
+use crate::uplink;
+use chirpstack_api::gw;
+use lrwn::region::CommonName;
+use lrwn::EUI64;
+
+pub struct Gateway {
+    gateway_id: EUI64,
+    region_config_id: String,
+    region_common_name: CommonName,
+}
+
+impl Gateway {
+    pub fn new(
+        gateway_id: EUI64,
+        region_config_id: &str,
+        region_common_name: CommonName,
+    ) -> Gateway {
+        Gateway {
+            gateway_id,
+            region_config_id: region_config_id.to_string(),
+            region_common_name,
+        }
+    }
+
+    // Hands the uplink to the uplink pipeline, as if it was received by this gateway.
+    pub fn send_uplink(&self, phy_payload: &[u8], tx_info: &gw::UplinkTxInfo) {
+        let mut rng = rand::rng();
+
+        let event = gw::UplinkFrame {
+            phy_payload: phy_payload.to_vec(),
+            tx_info: Some(tx_info.clone()),
+            rx_info: Some(gw::UplinkRxInfo {
+                gateway_id: self.gateway_id.to_string(),
+                uplink_id: rng.random(),
+                gw_time: Some(Utc::now().into()),
+                ns_time: Some(Utc::now().into()),
+                rssi: rng.random_range(-120..-40),
+                snr: rng.random_range(-10.0..10.0),
+                context: rng.random::<[u8; 4]>().to_vec(),
+                crc_status: gw::CrcStatus::CrcOk.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        tokio::spawn(uplink::deduplicate_uplink(
+            self.region_common_name,
+            self.region_config_id.clone(),
+            event,
+        ));
+    }
+
+    pub async fn send_stats(&self) {
+        let mut stats = gw::GatewayStats {
+            gateway_id: self.gateway_id.to_string(),
+            time: Some(Utc::now().into()),
+            ..Default::default()
+        };
+        stats
+            .metadata
+            .insert("region_config_id".into(), self.region_config_id.clone());
+        stats.metadata.insert(
+            "region_common_name".into(),
+            self.region_common_name.to_string(),
+        );
+
+        uplink::stats::Stats::handle(stats).await;
+    }
+}
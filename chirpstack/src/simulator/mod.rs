@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config;
+use crate::gateway::backend as gateway_backend;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{self, application, device_keys, device_profile, tenant};
+use chirpstack_api::gw;
+use lrwn::{AES128Key, EUI64};
+
+mod backend;
+mod device;
+mod gateway;
+
+// Interval in which the simulated gateways send their stats.
+const GATEWAY_STATS_INTERVAL: Duration = Duration::from_secs(30);
+
+// Downlink channel capacity. Devices lagging behind skip the oldest downlinks.
+const DOWNLINK_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub struct Options {
+    pub region_config_id: String,
+    pub gateways: usize,
+    pub devices: usize,
+    pub uplink_interval: Duration,
+    pub f_port: u8,
+    pub payload_size: usize,
+    pub dr: u8,
+}
+
+#[derive(Default)]
+pub struct Counters {
+    pub join_requests: AtomicU64,
+    pub join_accepts: AtomicU64,
+    pub uplinks: AtomicU64,
+    pub downlinks: AtomicU64,
+}
+
+// Simulator running virtual gateways and OTAA devices against the uplink and downlink
+// pipeline. All the storage records are created under a dedicated tenant, which is deleted
+// when the simulator is stopped.
+pub struct Simulator {
+    tenant_id: Uuid,
+    counters: Arc<Counters>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Simulator {
+    // Starts the simulator. This must be called after the region configuration has been loaded
+    // and before the downlink schedulers are started, as it installs the gateway backend for
+    // the simulated region.
+    pub async fn start(opts: &Options) -> Result<Simulator> {
+        let conf = config::get();
+        let region_conf = conf
+            .regions
+            .iter()
+            .find(|r| r.id == opts.region_config_id)
+            .ok_or_else(|| anyhow!("Region {} does not exist", opts.region_config_id))?;
+
+        info!(
+            region_config_id = %opts.region_config_id,
+            gateways = opts.gateways,
+            devices = opts.devices,
+            uplink_interval = ?opts.uplink_interval,
+            "Starting simulator"
+        );
+
+        let (downlinks, _) = broadcast::channel(DOWNLINK_CHANNEL_CAPACITY);
+        gateway_backend::set_backend(
+            &opts.region_config_id,
+            Box::new(backend::Backend::new(downlinks.clone())),
+        )
+        .await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: format!("simulator-{}", Uuid::new_v4()),
+            can_have_gateways: true,
+            ..Default::default()
+        })
+        .await
+        .context("Create tenant")?;
+
+        let mut sim = Simulator {
+            tenant_id: t.id.into(),
+            counters: Arc::new(Counters::default()),
+            tasks: Vec::new(),
+        };
+
+        if let Err(e) = sim.setup(opts, region_conf, &downlinks).await {
+            sim.stop().await;
+            return Err(e);
+        }
+
+        Ok(sim)
+    }
+
+    async fn setup(
+        &mut self,
+        opts: &Options,
+        region_conf: &config::Region,
+        downlinks: &broadcast::Sender<gw::DownlinkFrame>,
+    ) -> Result<()> {
+        let app = application::create(application::Application {
+            name: "simulator".into(),
+            tenant_id: self.tenant_id.into(),
+            ..Default::default()
+        })
+        .await
+        .context("Create application")?;
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            name: "simulator".into(),
+            tenant_id: self.tenant_id.into(),
+            region: region_conf.common_name,
+            region_config_id: Some(region_conf.id.clone()),
+            mac_version: lrwn::region::MacVersion::LORAWAN_1_0_3,
+            reg_params_revision: lrwn::region::Revision::A,
+            supports_otaa: true,
+            uplink_interval: opts.uplink_interval.as_secs() as i32,
+            ..Default::default()
+        })
+        .await
+        .context("Create device-profile")?;
+
+        let mut gateways = Vec::with_capacity(opts.gateways);
+        for i in 0..opts.gateways {
+            let gw = storage::gateway::create(storage::gateway::Gateway {
+                name: format!("simulator-gateway-{}", i),
+                tenant_id: self.tenant_id.into(),
+                gateway_id: EUI64::from_be_bytes(rand::rng().random()),
+                stats_interval_secs: GATEWAY_STATS_INTERVAL.as_secs() as i32,
+                ..Default::default()
+            })
+            .await
+            .context("Create gateway")?;
+
+            gateways.push(gateway::Gateway::new(
+                gw.gateway_id,
+                &region_conf.id,
+                region_conf.common_name,
+            ));
+        }
+        let gateways = Arc::new(gateways);
+
+        for i in 0..gateways.len() {
+            let gateways = gateways.clone();
+            self.tasks.push(tokio::spawn(async move {
+                let mut ticker = interval(GATEWAY_STATS_INTERVAL);
+                loop {
+                    ticker.tick().await;
+                    gateways[i].send_stats().await;
+                }
+            }));
+        }
+
+        let opts = Arc::new(opts.clone());
+        for i in 0..opts.devices {
+            let d = storage::device::create(storage::device::Device {
+                name: format!("simulator-device-{}", i),
+                application_id: app.id,
+                device_profile_id: dp.id,
+                dev_eui: EUI64::from_be_bytes(rand::rng().random()),
+                ..Default::default()
+            })
+            .await
+            .context("Create device")?;
+
+            let dk = device_keys::create(device_keys::DeviceKeys {
+                dev_eui: d.dev_eui,
+                nwk_key: AES128Key::from_bytes(rand::rng().random()),
+                ..Default::default()
+            })
+            .await
+            .context("Create device-keys")?;
+
+            let sim_dev = device::Device::new(
+                d.dev_eui,
+                d.join_eui,
+                dk.nwk_key,
+                opts.clone(),
+                gateways.clone(),
+                self.counters.clone(),
+            );
+            self.tasks
+                .push(tokio::spawn(sim_dev.run(downlinks.subscribe())));
+        }
+
+        Ok(())
+    }
+
+    // Stops the simulated gateways and devices and deletes the simulator tenant.
+    pub async fn stop(self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+
+        info!(
+            join_requests = self.counters.join_requests.load(Ordering::Relaxed),
+            join_accepts = self.counters.join_accepts.load(Ordering::Relaxed),
+            uplinks = self.counters.uplinks.load(Ordering::Relaxed),
+            downlinks = self.counters.downlinks.load(Ordering::Relaxed),
+            "Simulator stopped"
+        );
+
+        if let Err(e) = tenant::delete(&self.tenant_id).await {
+            warn!(tenant_id = %self.tenant_id, error = %e.full(), "Deleting simulator tenant failed");
+        }
+    }
+}
@@ -60,6 +60,14 @@ pub async fn create(ak: ApiKey) -> Result<ApiKey, Error> {
     Ok(ak)
 }
 
+pub async fn get(id: &Uuid) -> Result<ApiKey, Error> {
+    api_key::dsl::api_key
+        .find(fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| error::Error::from_diesel(e, id.to_string()))
+}
+
 pub async fn delete(id: &Uuid) -> Result<(), Error> {
     let ra = diesel::delete(api_key::dsl::api_key.find(fields::Uuid::from(id)))
         .execute(&mut get_async_db_conn().await?)
@@ -116,14 +124,6 @@ pub mod test {
         offset: i64,
     }
 
-    pub async fn get(id: &Uuid) -> Result<ApiKey, Error> {
-        api_key::dsl::api_key
-            .find(fields::Uuid::from(id))
-            .first(&mut get_async_db_conn().await?)
-            .await
-            .map_err(|e| error::Error::from_diesel(e, id.to_string()))
-    }
-
     pub async fn create_api_key(is_admin: bool, is_tenant: bool) -> ApiKey {
         let ak = ApiKey {
             name: "test api key".into(),
@@ -1,6 +1,7 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::str::FromStr;
+use std::time::Instant;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -16,7 +17,7 @@ use uuid::Uuid;
 
 use super::error::Error;
 use super::schema::{application, application_integration, device, device_profile};
-use super::{fields, get_async_db_conn};
+use super::{cache, device_keys, fields, get_async_db_conn};
 
 #[derive(Clone, Queryable, Insertable, PartialEq, Eq, Debug)]
 #[diesel(table_name = application)]
@@ -29,6 +30,7 @@ pub struct Application {
     pub description: String,
     pub mqtt_tls_cert: Option<Vec<u8>>,
     pub tags: fields::KeyValue,
+    pub log_rate_limit: i32,
 }
 
 impl Application {
@@ -36,6 +38,11 @@ impl Application {
         if self.name.is_empty() {
             return Err(Error::Validation("name is not set".into()));
         }
+        if self.log_rate_limit < 0 {
+            return Err(Error::Validation(
+                "log_rate_limit must not be negative".into(),
+            ));
+        }
         Ok(())
     }
 }
@@ -53,6 +60,7 @@ impl Default for Application {
             description: "".into(),
             mqtt_tls_cert: None,
             tags: fields::KeyValue::new(HashMap::new()),
+            log_rate_limit: 0,
         }
     }
 }
@@ -85,6 +93,7 @@ pub enum IntegrationKind {
     AzureServiceBus,
     PilotThings,
     Ifttt,
+    Geolocation,
 }
 
 impl fmt::Display for IntegrationKind {
@@ -108,6 +117,7 @@ impl FromStr for IntegrationKind {
             "AzureServiceBus" => IntegrationKind::AzureServiceBus,
             "PilotThings" => IntegrationKind::PilotThings,
             "Ifttt" => IntegrationKind::Ifttt,
+            "Geolocation" => IntegrationKind::Geolocation,
             _ => {
                 return Err(anyhow!("Unexpected IntegrationKind: {}", s));
             }
@@ -158,6 +168,7 @@ pub enum IntegrationConfiguration {
     AzureServiceBus(AzureServiceBusConfiguration),
     PilotThings(PilotThingsConfiguration),
     Ifttt(IftttConfiguration),
+    Geolocation(GeolocationConfiguration),
 }
 
 #[cfg(feature = "postgres")]
@@ -290,6 +301,14 @@ pub struct IftttConfiguration {
     pub event_prefix: String,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeolocationConfiguration {
+    pub tdoa: bool,
+    pub rssi: bool,
+    pub min_gateways: u32,
+}
+
 #[derive(Clone, Queryable, Insertable, PartialEq, Eq, Debug)]
 #[diesel(table_name = application_integration)]
 pub struct Integration {
@@ -329,14 +348,39 @@ pub async fn create(a: Application) -> Result<Application, Error> {
 }
 
 pub async fn get(id: &Uuid) -> Result<Application, Error> {
-    let a = application::dsl::application
+    if let Some(a) = cache::APPLICATIONS.get(id) {
+        return Ok(a);
+    }
+
+    let cached_at = Instant::now();
+    let a: Application = application::dsl::application
         .find(fields::Uuid::from(id))
         .first(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| Error::from_diesel(e, id.to_string()))?;
+    cache::APPLICATIONS.set(*id, cached_at, a.clone());
     Ok(a)
 }
 
+// Returns the log rate-limit of the application. As this is needed for every log event, only
+// this value is cached (instead of the complete application).
+pub async fn get_log_rate_limit(id: &Uuid) -> Result<u32, Error> {
+    if let Some(v) = cache::APPLICATION_LOG_RATE_LIMITS.get(id) {
+        return Ok(v);
+    }
+
+    let cached_at = Instant::now();
+    let v: i32 = application::dsl::application
+        .select(application::log_rate_limit)
+        .find(fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.to_string()))?;
+    let v = v.max(0) as u32;
+    cache::APPLICATION_LOG_RATE_LIMITS.set(*id, cached_at, v);
+    Ok(v)
+}
+
 pub async fn update(a: Application) -> Result<Application, Error> {
     a.validate()?;
 
@@ -346,10 +390,12 @@ pub async fn update(a: Application) -> Result<Application, Error> {
             application::name.eq(&a.name),
             application::description.eq(&a.description),
             application::tags.eq(&a.tags),
+            application::log_rate_limit.eq(&a.log_rate_limit),
         ))
         .get_result(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| Error::from_diesel(e, a.id.to_string()))?;
+    cache::invalidate(cache::Kind::Application, &a.id).await;
 
     info!(
         application_id = %a.id,
@@ -366,6 +412,7 @@ pub async fn update_mqtt_cls_cert(id: &Uuid, cert: &[u8]) -> Result<Application,
             .get_result(&mut get_async_db_conn().await?)
             .await
             .map_err(|e| Error::from_diesel(e, id.to_string()))?;
+    cache::invalidate(cache::Kind::Application, id).await;
 
     info!(
         application_id = %id,
@@ -376,12 +423,20 @@ pub async fn update_mqtt_cls_cert(id: &Uuid, cert: &[u8]) -> Result<Application,
 }
 
 pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    // The device-keys are removed by the database (cascade), the root-keys in the keystore
+    // must be removed explicitly.
+    let key_refs = device_keys::get_key_refs_for_application(id).await?;
     let ra = diesel::delete(application::dsl::application.find(fields::Uuid::from(id)))
         .execute(&mut get_async_db_conn().await?)
         .await?;
     if ra == 0 {
         return Err(Error::NotFound(id.to_string()));
     }
+    for (dev_eui, key_ref) in &key_refs {
+        device_keys::delete_root_keys(dev_eui, key_ref).await;
+    }
+    cache::invalidate(cache::Kind::Application, id).await;
+    cache::invalidate(cache::Kind::ApplicationIntegrations, id).await;
 
     info!(
         application_id = %id,
@@ -459,6 +514,7 @@ pub async fn create_integration(i: Integration) -> Result<Integration, Error> {
         .get_result(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| Error::from_diesel(e, i.kind.to_string()))?;
+    cache::invalidate(cache::Kind::ApplicationIntegrations, &i.application_id).await;
 
     info!(application_id = %i.application_id, kind = %i.kind, "Integration created");
     Ok(i)
@@ -508,6 +564,7 @@ pub async fn update_integration(i: Integration) -> Result<Integration, Error> {
     .get_result(&mut get_async_db_conn().await?)
     .await
     .map_err(|e| Error::from_diesel(e, i.application_id.to_string()))?;
+    cache::invalidate(cache::Kind::ApplicationIntegrations, &i.application_id).await;
 
     info!(application_id = %i.application_id, kind = %i.kind, "Integration updated");
 
@@ -528,6 +585,7 @@ pub async fn delete_integration(application_id: &Uuid, kind: IntegrationKind) ->
     if ra == 0 {
         return Err(Error::NotFound(application_id.to_string()));
     }
+    cache::invalidate(cache::Kind::ApplicationIntegrations, application_id).await;
 
     info!(application_id = %application_id, kind = %kind, "Integration deleted");
     Ok(())
@@ -536,11 +594,17 @@ pub async fn delete_integration(application_id: &Uuid, kind: IntegrationKind) ->
 pub async fn get_integrations_for_application(
     application_id: &Uuid,
 ) -> Result<Vec<Integration>, Error> {
+    if let Some(items) = cache::APPLICATION_INTEGRATIONS.get(application_id) {
+        return Ok(items);
+    }
+
+    let cached_at = Instant::now();
     let items: Vec<Integration> = application_integration::dsl::application_integration
         .filter(application_integration::dsl::application_id.eq(fields::Uuid::from(application_id)))
         .order_by(application_integration::dsl::kind)
         .load(&mut get_async_db_conn().await?)
         .await?;
+    cache::APPLICATION_INTEGRATIONS.set(*application_id, cached_at, items.clone());
     Ok(items)
 }
 
@@ -724,11 +788,16 @@ pub mod test {
         let app_get = get(&app.id).await.unwrap();
         assert_eq!(app, app_get);
 
+        // get log rate-limit
+        assert_eq!(0, get_log_rate_limit(&app.id).await.unwrap());
+
         // update
         app.name = "update application".into();
+        app.log_rate_limit = 10;
         app = update(app).await.unwrap();
         let app_get = get(&app.id).await.unwrap();
         assert_eq!(app, app_get);
+        assert_eq!(10, get_log_rate_limit(&app.id).await.unwrap());
 
         // get count and list
         let tests = vec![
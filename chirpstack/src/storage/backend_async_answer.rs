@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::backend_async_answer;
+use super::{db_transaction, fields, get_async_db_conn};
+use crate::config;
+
+// Backend Interfaces async answer which could not be delivered to the receiver and which is
+// pending a (re-)transmission. The answer is correlated with the request using the
+// ReceiverID (the SenderID of the request) and the TransactionID.
+#[derive(Clone, Queryable, QueryableByName, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = backend_async_answer)]
+pub struct BackendAsyncAnswer {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub receiver_id: Vec<u8>,
+    pub target_role: fields::RoamingRole,
+    pub transaction_id: i64,
+    pub message_type: String,
+    pub payload: String,
+    pub attempt_count: i16,
+    pub scheduler_run_after: DateTime<Utc>,
+    pub error_msg: String,
+}
+
+impl Default for BackendAsyncAnswer {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        BackendAsyncAnswer {
+            id: Uuid::new_v4().into(),
+            created_at: now,
+            receiver_id: Vec::new(),
+            target_role: fields::RoamingRole::FNS,
+            transaction_id: 0,
+            message_type: "".into(),
+            payload: "".into(),
+            attempt_count: 0,
+            scheduler_run_after: now,
+            error_msg: "".into(),
+        }
+    }
+}
+
+// Create stores the given answer. In case an answer with the same ReceiverID, TransactionID
+// and MessageType is already pending, it will be replaced by the given answer.
+pub async fn create(a: BackendAsyncAnswer) -> Result<BackendAsyncAnswer, Error> {
+    let a: BackendAsyncAnswer = diesel::insert_into(backend_async_answer::table)
+        .values(&a)
+        .on_conflict((
+            backend_async_answer::receiver_id,
+            backend_async_answer::transaction_id,
+            backend_async_answer::message_type,
+        ))
+        .do_update()
+        .set((
+            backend_async_answer::target_role.eq(excluded(backend_async_answer::target_role)),
+            backend_async_answer::payload.eq(excluded(backend_async_answer::payload)),
+            backend_async_answer::attempt_count.eq(excluded(backend_async_answer::attempt_count)),
+            backend_async_answer::scheduler_run_after
+                .eq(excluded(backend_async_answer::scheduler_run_after)),
+            backend_async_answer::error_msg.eq(excluded(backend_async_answer::error_msg)),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, a.id.to_string()))?;
+
+    info!(id = %a.id, receiver_id = %hex::encode(&a.receiver_id), transaction_id = a.transaction_id, message_type = %a.message_type, "Backend async answer created");
+    Ok(a)
+}
+
+// Update updates the retry state of the given answer.
+pub async fn update(a: BackendAsyncAnswer) -> Result<BackendAsyncAnswer, Error> {
+    let a: BackendAsyncAnswer =
+        diesel::update(backend_async_answer::dsl::backend_async_answer.find(&a.id))
+            .set((
+                backend_async_answer::attempt_count.eq(&a.attempt_count),
+                backend_async_answer::scheduler_run_after.eq(&a.scheduler_run_after),
+                backend_async_answer::error_msg.eq(&a.error_msg),
+            ))
+            .get_result(&mut get_async_db_conn().await?)
+            .await
+            .map_err(|e| Error::from_diesel(e, a.id.to_string()))?;
+
+    info!(id = %a.id, attempt_count = a.attempt_count, scheduler_run_after = %a.scheduler_run_after, "Backend async answer updated");
+    Ok(a)
+}
+
+pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    let ra = diesel::delete(
+        backend_async_answer::dsl::backend_async_answer.find(&fields::Uuid::from(id)),
+    )
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    info!(id = %id, "Backend async answer deleted");
+    Ok(())
+}
+
+// This returns the answers that are due for (re-)transmission. The scheduler_run_after of the
+// returned answers is pushed forward, such that other instances will not pick up the same
+// answers while these are being sent.
+pub async fn get_schedulable(limit: usize) -> Result<Vec<BackendAsyncAnswer>> {
+    let mut c = get_async_db_conn().await?;
+    db_transaction::<Vec<BackendAsyncAnswer>, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let conf = config::get();
+            diesel::sql_query(if cfg!(feature = "sqlite") {
+                r#"
+                    update
+                        backend_async_answer
+                    set
+                        scheduler_run_after = ?3
+                    where
+                        id in (
+                            select
+                                id
+                            from
+                                backend_async_answer
+                            where
+                                scheduler_run_after <= ?2
+                            order by
+                                created_at
+                            limit ?1
+                        )
+                    returning *
+                "#
+            } else {
+                r#"
+                    update
+                        backend_async_answer
+                    set
+                        scheduler_run_after = $3
+                    where
+                        id in (
+                            select
+                                id
+                            from
+                                backend_async_answer
+                            where
+                                scheduler_run_after <= $2
+                            order by
+                                created_at
+                            limit $1
+                        )
+                    returning *
+                "#
+            })
+            .bind::<diesel::sql_types::Integer, _>(limit as i32)
+            .bind::<fields::sql_types::Timestamptz, _>(Utc::now())
+            .bind::<fields::sql_types::Timestamptz, _>(
+                Utc::now() + Duration::from_std(2 * conf.network.scheduler.interval).unwrap(),
+            )
+            .load(c)
+            .await
+            .map_err(|e| Error::from_diesel(e, "".into()))
+        })
+    })
+    .await
+    .context("Get schedulable backend async answers")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    pub async fn get(id: &Uuid) -> Result<BackendAsyncAnswer, Error> {
+        let a = backend_async_answer::dsl::backend_async_answer
+            .find(&fields::Uuid::from(id))
+            .first(&mut get_async_db_conn().await?)
+            .await
+            .map_err(|e| Error::from_diesel(e, id.to_string()))?;
+        Ok(a)
+    }
+
+    #[tokio::test]
+    async fn test_backend_async_answer() {
+        let _guard = test::prepare().await;
+
+        // create
+        let a = create(BackendAsyncAnswer {
+            receiver_id: vec![1, 2, 3],
+            target_role: fields::RoamingRole::FNS,
+            transaction_id: 1234,
+            message_type: "PRStartAns".into(),
+            payload: "{}".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // get
+        let a_get = get(&a.id).await.unwrap();
+        assert_eq!(a, a_get);
+
+        // create for the same transaction replaces the pending answer
+        let a_replace = create(BackendAsyncAnswer {
+            receiver_id: vec![1, 2, 3],
+            target_role: fields::RoamingRole::FNS,
+            transaction_id: 1234,
+            message_type: "PRStartAns".into(),
+            payload: "{\"foo\":\"bar\"}".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(a.id, a_replace.id);
+        assert_eq!("{\"foo\":\"bar\"}", a_replace.payload);
+
+        // get schedulable
+        let items = get_schedulable(10).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(a.id, items[0].id);
+
+        // the scheduler_run_after was pushed forward
+        let items = get_schedulable(10).await.unwrap();
+        assert_eq!(0, items.len());
+
+        // update
+        let mut a = get(&a.id).await.unwrap();
+        a.attempt_count = 1;
+        a.scheduler_run_after = Utc::now();
+        a.error_msg = "connection refused".into();
+        let a = update(a).await.unwrap();
+        assert_eq!(1, a.attempt_count);
+        assert_eq!("connection refused", a.error_msg);
+
+        let items = get_schedulable(10).await.unwrap();
+        assert_eq!(1, items.len());
+
+        // delete
+        delete(&a.id).await.unwrap();
+        assert!(delete(&a.id).await.is_err());
+        assert!(get(&a.id).await.is_err());
+    }
+}
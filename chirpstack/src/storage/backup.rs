@@ -0,0 +1,640 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::error::Error;
+use super::schema::{device, device_keys};
+use super::{
+    db_transaction, get_async_db_conn, get_async_redis_conn, key_access_log, redis_key,
+    AsyncDbPoolConnection,
+};
+use crate::config;
+use lrwn::EUI64;
+
+// Version of the backup format. This must be incremented on incompatible changes.
+const FORMAT_VERSION: u32 = 1;
+
+// Number of rows read and written per query.
+const BATCH_SIZE: usize = 1000;
+
+// Database tables in foreign-key order, a table must be listed after the tables it references.
+const TABLES: &[&str] = &[
+    "tenant",
+    "user",
+    "tenant_role",
+    "tenant_role_permission",
+    "tenant_user",
+    "user_session",
+    "api_key",
+    "data_encryption_key",
+    "codec_library",
+    "tenant_sla_report",
+    "tenant_usage",
+    "application",
+    "application_integration",
+    "device_profile",
+    "device_profile_template",
+    "device_repository_codec",
+    "device",
+    "device_keys",
+    "device_queue_item",
+    "relay_device",
+    "relay_gateway",
+    "gateway",
+    "gateway_certificate",
+    "multicast_group",
+    "multicast_group_device",
+    "multicast_group_gateway",
+    "multicast_group_queue_item",
+    "fuota_deployment",
+    "fuota_deployment_delta",
+    "fuota_deployment_device",
+    "fuota_deployment_gateway",
+    "fuota_deployment_job",
+    "join_server_route",
+    "roaming_agreement",
+    "roaming_usage",
+    "key_access_log",
+    "backend_async_answer",
+];
+
+// Redis keys (patterns, without prefix) holding state which must survive a restore. Keys holding
+// short-lived state, like the uplink de-duplication, locks and event streams, are excluded.
+const REDIS_KEYS: &[&str] = &[
+    "device:*:ds",
+    "device:*:gwrx",
+    "device:*:mac:pending:*",
+    "metrics:*",
+    "hr:dev:*",
+    "pr:dev:*",
+    "pr:devaddr:*",
+    "pr:sess:*",
+];
+
+// The backup is stored as JSON lines. The first line contains the header, the last line the
+// end record which is used to detect incomplete backups.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header {
+        version: u32,
+        created_at: DateTime<Utc>,
+        chirpstack_version: String,
+        schema_version: String,
+    },
+    Row {
+        table: String,
+        data: serde_json::Value,
+    },
+    RedisKey {
+        key: String,
+        // Remaining time to live in milliseconds, 0 = no expiration.
+        ttl: i64,
+        // Base64 encoded DUMP value.
+        value: String,
+    },
+    End {
+        row_count: usize,
+        redis_key_count: usize,
+    },
+}
+
+#[derive(QueryableByName)]
+struct JsonRow {
+    #[diesel(sql_type = Text)]
+    data: String,
+}
+
+#[derive(QueryableByName)]
+struct SchemaVersion {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub row_count: usize,
+    pub redis_key_count: usize,
+}
+
+// Writes the backup to the given path. All tables are read within a single repeatable-read
+// transaction, such that the backup reflects a consistent snapshot of the database. The Redis
+// keys are read after the database snapshot has been taken.
+pub async fn backup(path: &Path) -> Result<Summary> {
+    if config::get().redis.cluster {
+        return Err(anyhow!("Backup is not supported in Redis Cluster mode"));
+    }
+
+    // Only the references to the root-keys stored in a keystore would be included, these must
+    // be backed up using the keystore itself.
+    let keystore_count: i64 = device_keys::dsl::device_keys
+        .filter(device_keys::dsl::key_ref.ne(""))
+        .count()
+        .get_result(&mut get_async_db_conn().await?)
+        .await?;
+    if keystore_count != 0 {
+        return Err(anyhow!(
+            "Backup is not supported when device root-keys are stored in a keystore"
+        ));
+    }
+
+    // The backup contains the (encrypted) key material of the devices, the access must be
+    // logged before the key material is read.
+    log_key_access().await?;
+
+    // Write to a temporary file first, such that an aborted backup does not leave a file which
+    // looks like a complete backup.
+    let tmp_path = path.with_extension("tmp");
+    let mut w = BufWriter::new(
+        File::create(&tmp_path).context(format!("Create file: {}", tmp_path.display()))?,
+    );
+
+    write_record(
+        &mut w,
+        &Record::Header {
+            version: FORMAT_VERSION,
+            created_at: Utc::now(),
+            chirpstack_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version: get_schema_version().await?,
+        },
+    )?;
+
+    let mut summary = Summary::default();
+    let mut c = get_async_db_conn().await?;
+    let w_ref = &mut w;
+    summary.row_count = db_transaction::<usize, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            diesel::sql_query("set transaction isolation level repeatable read, read only")
+                .execute(c)
+                .await?;
+
+            let mut count = 0;
+            for table in TABLES {
+                diesel::sql_query(format!(
+                    r#"declare backup_cursor no scroll cursor for select row_to_json(t)::text as data from "{}" t"#,
+                    table
+                ))
+                .execute(c)
+                .await?;
+
+                loop {
+                    let rows: Vec<JsonRow> =
+                        diesel::sql_query(format!("fetch forward {} from backup_cursor", BATCH_SIZE))
+                            .load(c)
+                            .await?;
+                    if rows.is_empty() {
+                        break;
+                    }
+
+                    for row in rows {
+                        write_record(
+                            w_ref,
+                            &Record::Row {
+                                table: table.to_string(),
+                                data: serde_json::from_str(&row.data).context("Parse row")?,
+                            },
+                        )?;
+                        count += 1;
+                    }
+                }
+
+                diesel::sql_query("close backup_cursor").execute(c).await?;
+            }
+
+            Ok(count)
+        })
+    })
+    .await?;
+
+    // Keys are stored without prefix, such that they can be restored using a different prefix.
+    let prefix = redis_key("".into());
+    for key in get_redis_keys().await? {
+        let (ttl, value): (i64, Option<Vec<u8>>) = redis::pipe()
+            .cmd("PTTL")
+            .arg(&key)
+            .cmd("DUMP")
+            .arg(&key)
+            .query_async(&mut get_async_redis_conn().await?)
+            .await
+            .context("Dump Redis key")?;
+
+        // The key expired after the scan.
+        let value = match value {
+            Some(v) => v,
+            None => continue,
+        };
+
+        write_record(
+            &mut w,
+            &Record::RedisKey {
+                key: key.strip_prefix(&prefix).unwrap_or(&key).to_string(),
+                ttl: ttl.max(0),
+                value: general_purpose::STANDARD.encode(value),
+            },
+        )?;
+        summary.redis_key_count += 1;
+    }
+
+    write_record(
+        &mut w,
+        &Record::End {
+            row_count: summary.row_count,
+            redis_key_count: summary.redis_key_count,
+        },
+    )?;
+    w.flush().context("Write backup")?;
+    drop(w);
+
+    fs::rename(&tmp_path, path).context(format!("Rename file: {}", path.display()))?;
+
+    info!(path = %path.display(), row_count = summary.row_count, redis_key_count = summary.redis_key_count, "Backup created");
+    Ok(summary)
+}
+
+// Restores the backup from the given path. This replaces all data in the database and the
+// backed-up Redis keys. The backup must have been created using the same database schema
+// version.
+pub async fn restore(path: &Path) -> Result<Summary> {
+    // Validate the complete backup before making any changes.
+    let schema_version = verify(path)?;
+    let current_schema_version = get_schema_version().await?;
+    if schema_version != current_schema_version {
+        return Err(anyhow!(
+            "Backup schema version {} does not match database schema version {}",
+            schema_version,
+            current_schema_version
+        ));
+    }
+
+    let mut lines = open(path)?;
+    // Skip header.
+    lines.next();
+
+    // The Redis keys are restored within the database transaction, such that the database
+    // changes are rolled back in case the Redis keys can not be restored. The Redis keys are
+    // replaced atomically.
+    let mut c = get_async_db_conn().await?;
+    let lines_ref = &mut lines;
+    let summary = db_transaction::<Summary, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            for table in TABLES.iter().rev() {
+                diesel::sql_query(format!(r#"delete from "{}""#, table))
+                    .execute(c)
+                    .await?;
+            }
+
+            let mut summary = Summary::default();
+            let mut table = String::new();
+            let mut rows: Vec<serde_json::Value> = Vec::new();
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+
+            for key in get_redis_keys().await? {
+                pipe.cmd("DEL").arg(&key).ignore();
+            }
+
+            for line in lines_ref.by_ref() {
+                match parse_record(&line.context("Read backup")?)? {
+                    Record::Row {
+                        table: row_table,
+                        data,
+                    } => {
+                        if row_table != table || rows.len() >= BATCH_SIZE {
+                            insert_rows(c, &table, &rows).await?;
+                            rows.clear();
+                            table = row_table;
+                        }
+
+                        rows.push(data);
+                        summary.row_count += 1;
+                    }
+                    Record::RedisKey { key, ttl, value } => {
+                        pipe.cmd("RESTORE")
+                            .arg(redis_key(key))
+                            .arg(ttl)
+                            .arg(decode_value(&value)?)
+                            .arg("REPLACE")
+                            .ignore();
+                        summary.redis_key_count += 1;
+                    }
+                    _ => {}
+                }
+            }
+            insert_rows(c, &table, &rows).await?;
+
+            // The data encryption key IDs are assigned by an identity sequence, which must
+            // continue after the restored IDs.
+            diesel::sql_query(
+                r#"
+                    select setval(
+                        pg_get_serial_sequence('data_encryption_key', 'id'),
+                        coalesce(max(id), 0) + 1,
+                        false
+                    ) from data_encryption_key
+                "#,
+            )
+            .execute(c)
+            .await?;
+
+            () = pipe
+                .query_async(&mut get_async_redis_conn().await?)
+                .await
+                .context("Restore Redis keys")?;
+
+            Ok(summary)
+        })
+    })
+    .await?;
+
+    info!(path = %path.display(), row_count = summary.row_count, redis_key_count = summary.redis_key_count, "Backup restored");
+    Ok(summary)
+}
+
+// Verifies that the backup is complete and valid and returns the schema version.
+fn verify(path: &Path) -> Result<String> {
+    let mut lines = open(path)?;
+
+    let schema_version = match lines.next() {
+        Some(line) => match parse_record(&line.context("Read backup")?)? {
+            Record::Header {
+                version,
+                schema_version,
+                ..
+            } => {
+                if version != FORMAT_VERSION {
+                    return Err(anyhow!("Unsupported backup format version: {}", version));
+                }
+                schema_version
+            }
+            _ => return Err(anyhow!("Backup header is missing")),
+        },
+        None => return Err(anyhow!("Backup is empty")),
+    };
+
+    let mut summary = Summary::default();
+    for line in lines {
+        match parse_record(&line.context("Read backup")?)? {
+            Record::Row { table, .. } => {
+                if !TABLES.contains(&table.as_str()) {
+                    return Err(anyhow!("Unexpected table: {}", table));
+                }
+                summary.row_count += 1;
+            }
+            Record::RedisKey { value, .. } => {
+                decode_value(&value)?;
+                summary.redis_key_count += 1;
+            }
+            Record::End {
+                row_count,
+                redis_key_count,
+            } => {
+                if summary
+                    != (Summary {
+                        row_count,
+                        redis_key_count,
+                    })
+                {
+                    return Err(anyhow!("Backup record count mismatch"));
+                }
+                return Ok(schema_version);
+            }
+            Record::Header { .. } => return Err(anyhow!("Unexpected header")),
+        }
+    }
+
+    Err(anyhow!("Backup is incomplete"))
+}
+
+async fn insert_rows(
+    c: &mut AsyncDbPoolConnection,
+    table: &str,
+    rows: &[serde_json::Value],
+) -> Result<(), Error> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    // The table has been validated against TABLES by verify.
+    diesel::sql_query(format!(
+        r#"insert into "{0}" select * from json_populate_recordset(null::"{0}", $1::json)"#,
+        table
+    ))
+    .bind::<Text, _>(serde_json::to_string(rows).context("Encode rows")?)
+    .execute(c)
+    .await?;
+
+    Ok(())
+}
+
+async fn get_schema_version() -> Result<String> {
+    let v: SchemaVersion = diesel::sql_query(
+        "select version from __diesel_schema_migrations order by version desc limit 1",
+    )
+    .get_result(&mut get_async_db_conn().await?)
+    .await
+    .context("Get schema version")?;
+    Ok(v.version)
+}
+
+async fn get_redis_keys() -> Result<Vec<String>> {
+    let mut keys: Vec<String> = Vec::new();
+
+    for pattern in REDIS_KEYS {
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, mut page): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(redis_key(pattern.to_string()))
+                .arg("COUNT")
+                .arg(BATCH_SIZE)
+                .query_async(&mut get_async_redis_conn().await?)
+                .await
+                .context("Scan Redis keys")?;
+            keys.append(&mut page);
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+    }
+
+    keys.sort();
+    keys.dedup();
+    Ok(keys)
+}
+
+fn decode_value(value: &str) -> Result<Vec<u8>> {
+    general_purpose::STANDARD
+        .decode(value)
+        .context("Decode Redis value")
+}
+
+// Logs the access of the root-keys and session-keys included in the backup.
+async fn log_key_access() -> Result<()> {
+    let dev_euis: Vec<EUI64> = device_keys::dsl::device_keys
+        .select(device_keys::dsl::dev_eui)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    key_access_log::log_many(
+        &dev_euis,
+        key_access_log::KeyType::RootKeys,
+        key_access_log::Actor::System,
+        key_access_log::Purpose::Backup,
+    )
+    .await?;
+
+    let dev_euis: Vec<EUI64> = device::dsl::device
+        .select(device::dsl::dev_eui)
+        .filter(device::dsl::device_session.is_not_null())
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    key_access_log::log_many(
+        &dev_euis,
+        key_access_log::KeyType::SessionKeys,
+        key_access_log::Actor::System,
+        key_access_log::Purpose::Backup,
+    )
+    .await?;
+
+    Ok(())
+}
+
+fn open(path: &Path) -> Result<Lines<BufReader<File>>> {
+    let f = File::open(path).context(format!("Open file: {}", path.display()))?;
+    Ok(BufReader::new(f).lines())
+}
+
+fn parse_record(line: &str) -> Result<Record> {
+    serde_json::from_str(line).context("Parse backup record")
+}
+
+fn write_record<W: Write>(w: &mut W, record: &Record) -> Result<()> {
+    serde_json::to_writer(&mut *w, record).context("Encode backup record")?;
+    w.write_all(b"\n").context("Write backup")?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::{application, device_keys, tenant};
+    use crate::test;
+
+    #[derive(QueryableByName)]
+    struct TableName {
+        #[diesel(sql_type = Text)]
+        table_name: String,
+    }
+
+    #[tokio::test]
+    async fn test_tables() {
+        let _guard = test::prepare().await;
+
+        let tables: Vec<TableName> = diesel::sql_query(
+            r#"
+            select
+                table_name::text as table_name
+            from
+                information_schema.tables
+            where
+                table_schema = current_schema()
+                and table_type = 'BASE TABLE'
+                and table_name != '__diesel_schema_migrations'
+            "#,
+        )
+        .load(&mut get_async_db_conn().await.unwrap())
+        .await
+        .unwrap();
+
+        // All tables must be included in the backup.
+        for t in &tables {
+            assert!(TABLES.contains(&t.table_name.as_str()), "{}", t.table_name);
+        }
+        assert_eq!(tables.len(), TABLES.len());
+    }
+
+    #[tokio::test]
+    async fn test_backup_restore() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dk = device_keys::test::create_device_keys(None).await;
+
+        let gwrx_key = redis_key("device:{0102030405060708}:gwrx".into());
+        let frame_key = redis_key("frame:0102".into());
+        for key in [&gwrx_key, &frame_key] {
+            () = redis::cmd("SET")
+                .arg(key)
+                .arg(vec![1, 2, 3])
+                .query_async(&mut get_async_redis_conn().await.unwrap())
+                .await
+                .unwrap();
+        }
+
+        let path = std::env::temp_dir().join("chirpstack_test_backup.jsonl");
+        let summary = backup(&path).await.unwrap();
+        assert!(summary.row_count > 0);
+        assert_eq!(1, summary.redis_key_count);
+
+        // The access of the root-keys has been logged.
+        let logs = key_access_log::test::list(&dk.dev_eui).await;
+        assert_eq!(1, logs.len());
+        assert_eq!("root_keys", logs[0].key_type);
+        assert_eq!("backup", logs[0].purpose);
+
+        // Changes after the backup are reverted by the restore.
+        tenant::delete(&t.id.into()).await.unwrap();
+        () = redis::cmd("DEL")
+            .arg(&gwrx_key)
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(summary, restore(&path).await.unwrap());
+
+        let t_get = tenant::get(&t.id.into()).await.unwrap();
+        assert_eq!(t, t_get);
+        let app_get = application::get(&app.id.into()).await.unwrap();
+        assert_eq!(app, app_get);
+
+        let v: Vec<u8> = redis::cmd("GET")
+            .arg(&gwrx_key)
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], v);
+
+        // Incomplete backup.
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        fs::write(&path, lines[..lines.len() - 1].join("\n")).unwrap();
+        assert!(restore(&path).await.is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::Instant;
+
+use anyhow::Result;
+use tracing::{debug, error, trace};
+#[cfg(feature = "postgres")]
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{application, codec_library, device_profile, device_repository_codec, gateway};
+use crate::api::auth::session;
+use crate::backend::{joinserver, roaming};
+use crate::helpers::errors::PrintFullError;
+use crate::{config, encryption};
+use lrwn::EUI64;
+
+// Name of the PostgreSQL channel on which the invalidations are published.
+#[cfg(feature = "postgres")]
+const CHANNEL: &str = "chirpstack_cache_invalidate";
+
+lazy_static! {
+    pub static ref DEVICE_PROFILES: Cache<device_profile::DeviceProfile> = Cache::new();
+    pub static ref APPLICATIONS: Cache<application::Application> = Cache::new();
+    pub static ref APPLICATION_LOG_RATE_LIMITS: Cache<u32> = Cache::new();
+    pub static ref APPLICATION_INTEGRATIONS: Cache<Vec<application::Integration>> = Cache::new();
+    pub static ref CODEC_LIBRARIES: Cache<Vec<codec_library::CodecLibrary>> = Cache::new();
+    pub static ref DEVICE_REPOSITORY_CODECS: Cache<device_repository_codec::DeviceRepositoryCodec, String> =
+        Cache::new();
+    pub static ref GATEWAY_ANTENNAS: Cache<gateway::GatewayAntenna, EUI64> = Cache::new();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    DeviceProfile,
+    Application,
+    ApplicationIntegrations,
+    CodecLibraries,
+    DeviceRepositoryCodec,
+    GatewayAntenna,
+    RoamingAgreements,
+    JoinServerRoutes,
+    DataEncryptionKeys,
+    UserSessions,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Kind::DeviceProfile => "device_profile",
+                Kind::Application => "application",
+                Kind::ApplicationIntegrations => "application_integrations",
+                Kind::CodecLibraries => "codec_libraries",
+                Kind::DeviceRepositoryCodec => "device_repository_codec",
+                Kind::GatewayAntenna => "gateway_antenna",
+                Kind::RoamingAgreements => "roaming_agreements",
+                Kind::JoinServerRoutes => "join_server_routes",
+                Kind::DataEncryptionKeys => "data_encryption_keys",
+                Kind::UserSessions => "user_sessions",
+            }
+        )
+    }
+}
+
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "device_profile" => Kind::DeviceProfile,
+            "application" => Kind::Application,
+            "application_integrations" => Kind::ApplicationIntegrations,
+            "codec_libraries" => Kind::CodecLibraries,
+            "device_repository_codec" => Kind::DeviceRepositoryCodec,
+            "gateway_antenna" => Kind::GatewayAntenna,
+            "roaming_agreements" => Kind::RoamingAgreements,
+            "join_server_routes" => Kind::JoinServerRoutes,
+            "data_encryption_keys" => Kind::DataEncryptionKeys,
+            "user_sessions" => Kind::UserSessions,
+            _ => return Err(anyhow!("Unexpected cache kind: {}", s)),
+        })
+    }
+}
+
+// Cache implements a simple in-memory cache with a TTL (see the cache.ttl configuration). Items
+// are invalidated on update or delete, see the invalidate function. On invalidation, a tombstone
+// is stored such that an item which was retrieved before the invalidation is not cached.
+pub struct Cache<V: Clone, K = Uuid> {
+    items: RwLock<HashMap<K, (Instant, Option<V>)>>,
+}
+
+impl<V: Clone, K: Eq + Hash + Clone> Cache<V, K> {
+    fn new() -> Self {
+        Cache {
+            items: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, id: &K) -> Option<V> {
+        let conf = config::get();
+        let items = self.items.read().unwrap();
+
+        match items.get(id) {
+            Some((cached_at, v)) if cached_at.elapsed() < conf.cache.ttl => v.clone(),
+            _ => None,
+        }
+    }
+
+    // Caches the given item. The cached_at timestamp must be taken before the item was
+    // retrieved from the database.
+    pub fn set(&self, id: K, cached_at: Instant, v: V) {
+        let conf = config::get();
+        if conf.cache.ttl.is_zero() {
+            return;
+        }
+
+        let mut items = self.items.write().unwrap();
+
+        // Remove expired items, to avoid the cache from growing unbounded.
+        items.retain(|_, (t, _)| t.elapsed() < conf.cache.ttl);
+
+        if let Some((t, _)) = items.get(&id) {
+            if *t >= cached_at {
+                return;
+            }
+        }
+
+        items.insert(id, (cached_at, Some(v)));
+    }
+
+    fn remove(&self, id: &K) {
+        self.items
+            .write()
+            .unwrap()
+            .insert(id.clone(), (Instant::now(), None));
+    }
+
+    fn clear(&self) {
+        self.items.write().unwrap().clear();
+    }
+}
+
+// Invalidates the cached item both locally and on all other instances.
+pub async fn invalidate<K: fmt::Display>(kind: Kind, id: &K) {
+    if let Err(e) = invalidate_local(kind, &id.to_string()) {
+        error!(kind = %kind, id = %id, error = %e.full(), "Invalidating cached item failed");
+    }
+
+    #[cfg(feature = "postgres")]
+    {
+        if let Err(e) = notify(&format!("{}:{}", kind, id)).await {
+            warn!(kind = %kind, id = %id, error = %e.full(), "Publishing cache invalidation failed");
+        }
+    }
+}
+
+// Invalidates all cached items both locally and on all other instances. This is used in case
+// of cascading deletes.
+pub async fn invalidate_all() {
+    clear();
+
+    #[cfg(feature = "postgres")]
+    {
+        if let Err(e) = notify("*").await {
+            warn!(error = %e.full(), "Publishing cache invalidation failed");
+        }
+    }
+}
+
+pub fn clear() {
+    DEVICE_PROFILES.clear();
+    APPLICATIONS.clear();
+    APPLICATION_LOG_RATE_LIMITS.clear();
+    APPLICATION_INTEGRATIONS.clear();
+    CODEC_LIBRARIES.clear();
+    DEVICE_REPOSITORY_CODECS.clear();
+    GATEWAY_ANTENNAS.clear();
+}
+
+// Clears the cache and reloads the items which are not cached, but loaded by their modules.
+// This is used when invalidations might have been missed.
+#[cfg(feature = "postgres")]
+async fn resync() {
+    clear();
+    reload().await;
+}
+
+// Reloads the roaming agreements, Join Server routes, data encryption keys and session
+// revocation list. These are not cached, but loaded by their modules.
+async fn reload() {
+    if let Err(e) = roaming::reload().await {
+        error!(error = %e.full(), "Reloading roaming agreements failed");
+    }
+    if let Err(e) = joinserver::reload().await {
+        error!(error = %e.full(), "Reloading Join Server routes failed");
+    }
+    if let Err(e) = encryption::reload().await {
+        error!(error = %e.full(), "Reloading data encryption keys failed");
+    }
+    if let Err(e) = session::reload().await {
+        error!(error = %e.full(), "Reloading user session revocation list failed");
+    }
+}
+
+fn invalidate_local(kind: Kind, id: &str) -> Result<()> {
+    trace!(kind = %kind, id = %id, "Invalidating cached item");
+
+    match kind {
+        Kind::DeviceProfile => DEVICE_PROFILES.remove(&Uuid::from_str(id)?),
+        Kind::Application => {
+            let id = Uuid::from_str(id)?;
+            APPLICATIONS.remove(&id);
+            APPLICATION_LOG_RATE_LIMITS.remove(&id);
+        }
+        Kind::ApplicationIntegrations => APPLICATION_INTEGRATIONS.remove(&Uuid::from_str(id)?),
+        Kind::CodecLibraries => CODEC_LIBRARIES.remove(&Uuid::from_str(id)?),
+        Kind::DeviceRepositoryCodec => DEVICE_REPOSITORY_CODECS.remove(&id.to_string()),
+        Kind::GatewayAntenna => GATEWAY_ANTENNAS.remove(&EUI64::from_str(id)?),
+        // The roaming agreements are not cached, but applied to the roaming clients.
+        Kind::RoamingAgreements => {
+            tokio::spawn(async {
+                if let Err(e) = roaming::reload().await {
+                    error!(error = %e.full(), "Reloading roaming agreements failed");
+                }
+            });
+        }
+        // The Join Server routes are not cached, but applied to the Join Server clients.
+        Kind::JoinServerRoutes => {
+            tokio::spawn(async {
+                if let Err(e) = joinserver::reload().await {
+                    error!(error = %e.full(), "Reloading Join Server routes failed");
+                }
+            });
+        }
+        // The data encryption keys are not cached, but loaded by the encryption module.
+        Kind::DataEncryptionKeys => {
+            tokio::spawn(async {
+                if let Err(e) = encryption::reload().await {
+                    error!(error = %e.full(), "Reloading data encryption keys failed");
+                }
+            });
+        }
+        // The session revocation list is not cached, but loaded by the session module.
+        Kind::UserSessions => {
+            tokio::spawn(async {
+                if let Err(e) = session::reload().await {
+                    error!(error = %e.full(), "Reloading user session revocation list failed");
+                }
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_notification(payload: &str) -> Result<()> {
+    debug!(payload = %payload, "Cache invalidation received");
+
+    if payload == "*" {
+        clear();
+        tokio::spawn(reload());
+        return Ok(());
+    }
+
+    let (kind, id) = payload
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid cache invalidation payload: {}", payload))?;
+    invalidate_local(Kind::from_str(kind)?, id)
+}
+
+#[cfg(feature = "postgres")]
+async fn notify(payload: &str) -> Result<()> {
+    use diesel_async::RunQueryDsl;
+
+    diesel::sql_query("select pg_notify($1, $2)")
+        .bind::<diesel::sql_types::Text, _>(CHANNEL)
+        .bind::<diesel::sql_types::Text, _>(payload)
+        .execute(&mut super::get_async_db_conn().await?)
+        .await?;
+
+    Ok(())
+}
+
+// Starts listening for cache invalidations published by other instances.
+#[cfg(feature = "postgres")]
+pub async fn setup() -> Result<()> {
+    info!("Setting up cache invalidation listener");
+
+    tokio::spawn(async {
+        loop {
+            if let Err(e) = listen().await {
+                warn!(error = %e.full(), "Cache invalidation listener error");
+            }
+
+            // Invalidations might have been missed while the listener was not connected.
+            clear();
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+    });
+
+    Ok(())
+}
+
+// SQLite does not support LISTEN / NOTIFY, nor multiple instances sharing the same database.
+// Invalidations are therefore only applied locally.
+#[cfg(feature = "sqlite")]
+pub async fn setup() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+async fn listen() -> Result<()> {
+    use futures::{stream, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_postgres::AsyncMessage;
+
+    let conf = config::get();
+    let tls = super::postgres::get_tls_connector()?;
+    let (client, mut conn) = tokio_postgres::connect(&conf.postgresql.dsn, tls).await?;
+
+    // The connection must be polled to process the messages, including the notifications.
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let mut messages = stream::poll_fn(move |cx| conn.poll_message(cx));
+    tokio::spawn(async move {
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(AsyncMessage::Notification(n)) => {
+                    if tx.send(n.payload().to_string()).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(error = %e, "PostgreSQL connection error");
+                    break;
+                }
+            }
+        }
+    });
+
+    client.batch_execute(&format!("listen {}", CHANNEL)).await?;
+
+    // Invalidations might have been published before the LISTEN statement was executed.
+    resync().await;
+
+    while let Some(payload) = rx.recv().await {
+        if let Err(e) = handle_notification(&payload) {
+            warn!(error = %e.full(), "Handle cache invalidation error");
+        }
+    }
+
+    Err(anyhow!("Connection closed"))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    #[cfg(feature = "postgres")]
+    use crate::storage::{get_async_db_conn, join_server_route, schema, user, user_session};
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_cache() {
+        let _guard = test::prepare().await;
+
+        let id = Uuid::new_v4();
+        let a = application::Application {
+            id: id.into(),
+            name: "test-app".into(),
+            ..Default::default()
+        };
+
+        // Not cached.
+        assert!(APPLICATIONS.get(&id).is_none());
+
+        // Cached.
+        APPLICATIONS.set(id, Instant::now(), a.clone());
+        assert_eq!(Some(a.clone()), APPLICATIONS.get(&id));
+
+        // An item retrieved before the item was cached is not overwriting the cached item.
+        let cached_at = Instant::now();
+        APPLICATIONS.set(id, Instant::now(), a.clone());
+        APPLICATIONS.set(
+            id,
+            cached_at,
+            application::Application {
+                name: "outdated".into(),
+                ..a.clone()
+            },
+        );
+        assert_eq!(Some(a.clone()), APPLICATIONS.get(&id));
+
+        // Invalidation from an other instance.
+        APPLICATION_LOG_RATE_LIMITS.set(id, Instant::now(), 10);
+        handle_notification(&format!("application:{}", id)).unwrap();
+        assert!(APPLICATIONS.get(&id).is_none());
+        assert!(APPLICATION_LOG_RATE_LIMITS.get(&id).is_none());
+
+        // An item retrieved before the invalidation is not cached.
+        APPLICATIONS.set(id, cached_at, a.clone());
+        assert!(APPLICATIONS.get(&id).is_none());
+
+        // Invalidate all.
+        APPLICATIONS.set(id, Instant::now(), a.clone());
+        handle_notification("*").unwrap();
+        assert!(APPLICATIONS.get(&id).is_none());
+
+        // Invalid payload.
+        assert!(handle_notification("foo:bar").is_err());
+        assert!(handle_notification("gateway_antenna:foo").is_err());
+
+        // Cache keyed by gateway ID.
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let antenna = gateway::GatewayAntenna {
+            antenna_gain: 6.0,
+            cable_loss: 1.0,
+        };
+        GATEWAY_ANTENNAS.set(gateway_id, Instant::now(), antenna);
+        assert_eq!(Some(antenna), GATEWAY_ANTENNAS.get(&gateway_id));
+        handle_notification(&format!("gateway_antenna:{}", gateway_id)).unwrap();
+        assert!(GATEWAY_ANTENNAS.get(&gateway_id).is_none());
+
+        // TTL of zero disables caching.
+        let mut conf = (*config::get()).clone();
+        conf.cache.ttl = std::time::Duration::ZERO;
+        config::set(conf);
+        APPLICATIONS.set(id, Instant::now(), a.clone());
+        assert!(APPLICATIONS.get(&id).is_none());
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_resync() {
+        let _guard = test::prepare().await;
+        session::reload().await.unwrap();
+
+        let u = user::test::create_user().await;
+        let s = user_session::create(user_session::UserSession::new(
+            &u.id,
+            chrono::Duration::try_days(1).unwrap(),
+        ))
+        .await
+        .unwrap();
+
+        // Revoked while the listener was not connected, the notification is missed.
+        user_session::revoke(&u.id, &s.id).await.unwrap();
+        assert!(!session::is_revoked(&s.id));
+
+        // Join Server route created while the listener was not connected.
+        joinserver::reload().await.unwrap();
+        let join_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        {
+            use diesel_async::RunQueryDsl;
+
+            diesel::insert_into(schema::join_server_route::table)
+                .values(&join_server_route::JoinServerRoute {
+                    name: "js".into(),
+                    join_eui_prefix: "0102030405060700/56".into(),
+                    server: "https://js.example.com".into(),
+                    ..Default::default()
+                })
+                .execute(&mut get_async_db_conn().await.unwrap())
+                .await
+                .unwrap();
+        }
+        assert!(joinserver::get(join_eui).await.is_err());
+
+        // The revocation list and Join Server routes are reloaded on (re)connect.
+        resync().await;
+        assert!(session::is_revoked(&s.id));
+        assert!(joinserver::get(join_eui).await.is_ok());
+    }
+}
@@ -0,0 +1,282 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{dsl, prelude::*};
+use diesel_async::RunQueryDsl;
+use regex::Regex;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::codec_library;
+use super::{cache, fields, get_async_db_conn};
+
+lazy_static! {
+    // The library is exposed to the codec scripts as global object using its name.
+    static ref NAME_REGEX: Regex = Regex::new(r"^[a-zA-Z_$][a-zA-Z0-9_$]*$").unwrap();
+}
+
+// Codec library, containing a JS module of which the exports can be used by the codecs of the
+// device-profiles within the same tenant.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = codec_library)]
+pub struct CodecLibrary {
+    pub id: fields::Uuid,
+    pub tenant_id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub description: String,
+    pub script: String,
+}
+
+impl CodecLibrary {
+    fn validate(&self) -> Result<(), Error> {
+        if !NAME_REGEX.is_match(&self.name) {
+            return Err(Error::Validation(
+                "name must be a valid JavaScript identifier".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for CodecLibrary {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        CodecLibrary {
+            id: Uuid::new_v4().into(),
+            tenant_id: Uuid::nil().into(),
+            created_at: now,
+            updated_at: now,
+            name: "".into(),
+            description: "".into(),
+            script: "".into(),
+        }
+    }
+}
+
+#[derive(Queryable, PartialEq, Eq, Debug)]
+pub struct CodecLibraryListItem {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    pub tenant_id: Option<Uuid>,
+    pub search: Option<String>,
+}
+
+pub async fn create(cl: CodecLibrary) -> Result<CodecLibrary, Error> {
+    cl.validate()?;
+
+    let cl: CodecLibrary = diesel::insert_into(codec_library::table)
+        .values(&cl)
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, cl.id.to_string()))?;
+    cache::invalidate(cache::Kind::CodecLibraries, &cl.tenant_id).await;
+    info!(id = %cl.id, name = %cl.name, "Codec library created");
+    Ok(cl)
+}
+
+pub async fn get(id: &Uuid) -> Result<CodecLibrary, Error> {
+    codec_library::dsl::codec_library
+        .find(&fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.to_string()))
+}
+
+pub async fn update(cl: CodecLibrary) -> Result<CodecLibrary, Error> {
+    cl.validate()?;
+
+    let cl: CodecLibrary = diesel::update(codec_library::dsl::codec_library.find(&cl.id))
+        .set((
+            codec_library::updated_at.eq(Utc::now()),
+            codec_library::name.eq(&cl.name),
+            codec_library::description.eq(&cl.description),
+            codec_library::script.eq(&cl.script),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, cl.id.to_string()))?;
+    cache::invalidate(cache::Kind::CodecLibraries, &cl.tenant_id).await;
+    info!(id = %cl.id, name = %cl.name, "Codec library updated");
+    Ok(cl)
+}
+
+pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    let cl = get(id).await?;
+
+    let ra = diesel::delete(codec_library::dsl::codec_library.find(&fields::Uuid::from(id)))
+        .execute(&mut get_async_db_conn().await?)
+        .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    cache::invalidate(cache::Kind::CodecLibraries, &cl.tenant_id).await;
+    info!(id = %id, "Codec library deleted");
+    Ok(())
+}
+
+pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
+    let mut q = codec_library::dsl::codec_library
+        .select(dsl::count_star())
+        .into_boxed();
+
+    if let Some(tenant_id) = &filters.tenant_id {
+        q = q.filter(codec_library::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
+    }
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(codec_library::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(codec_library::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    Ok(q.first(&mut get_async_db_conn().await?).await?)
+}
+
+pub async fn list(
+    limit: i64,
+    offset: i64,
+    filters: &Filters,
+) -> Result<Vec<CodecLibraryListItem>, Error> {
+    let mut q = codec_library::dsl::codec_library
+        .select((
+            codec_library::id,
+            codec_library::created_at,
+            codec_library::updated_at,
+            codec_library::name,
+            codec_library::description,
+        ))
+        .into_boxed();
+
+    if let Some(tenant_id) = &filters.tenant_id {
+        q = q.filter(codec_library::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
+    }
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(codec_library::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(codec_library::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    let items = q
+        .order_by(codec_library::dsl::name)
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+// Returns all the codec libraries of the given tenant.
+pub async fn get_for_tenant(tenant_id: &Uuid) -> Result<Vec<CodecLibrary>, Error> {
+    if let Some(items) = cache::CODEC_LIBRARIES.get(tenant_id) {
+        return Ok(items);
+    }
+
+    let cached_at = Instant::now();
+    let items: Vec<CodecLibrary> = codec_library::dsl::codec_library
+        .filter(codec_library::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)))
+        .order_by(codec_library::dsl::name)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    cache::CODEC_LIBRARIES.set(*tenant_id, cached_at, items.clone());
+    Ok(items)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::tenant;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_codec_library() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::test::create_tenant().await;
+
+        // invalid name
+        assert!(create(CodecLibrary {
+            tenant_id: t.id,
+            name: "crc-16".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // create
+        let mut cl = create(CodecLibrary {
+            tenant_id: t.id,
+            name: "crc".into(),
+            script: "export function crc8(b) { return 0; }".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // name must be unique within the tenant
+        assert!(create(CodecLibrary {
+            tenant_id: t.id,
+            name: "crc".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // get
+        let cl_get = get(&cl.id).await.unwrap();
+        assert_eq!(cl, cl_get);
+
+        // get for tenant
+        assert_eq!(vec![cl.clone()], get_for_tenant(&t.id).await.unwrap());
+
+        // update
+        cl.script = "export function crc8(b) { return 1; }".into();
+        let cl = update(cl).await.unwrap();
+        assert_eq!(cl, get(&cl.id).await.unwrap());
+        assert_eq!(vec![cl.clone()], get_for_tenant(&t.id).await.unwrap());
+
+        // get count and list
+        let filters = Filters {
+            tenant_id: Some(t.id.into()),
+            search: Some("cr".into()),
+        };
+        assert_eq!(1, get_count(&filters).await.unwrap());
+        let items = list(10, 0, &filters).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(cl.id, items[0].id);
+
+        let filters = Filters {
+            tenant_id: Some(t.id.into()),
+            search: Some("foo".into()),
+        };
+        assert_eq!(0, get_count(&filters).await.unwrap());
+
+        // delete
+        delete(&cl.id).await.unwrap();
+        assert!(get(&cl.id).await.is_err());
+        assert!(get_for_tenant(&t.id).await.unwrap().is_empty());
+    }
+}
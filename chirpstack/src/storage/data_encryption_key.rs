@@ -0,0 +1,104 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::{data_encryption_key, tenant};
+use super::{fields, get_async_db_conn, AsyncDbPoolConnection};
+
+#[derive(Queryable, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = data_encryption_key)]
+pub struct DataEncryptionKey {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub backend: String,
+    pub encrypted_key: Vec<u8>,
+    // Tenant of the data encryption key. This is None for the global data encryption keys.
+    pub tenant_id: Option<fields::Uuid>,
+}
+
+// Creates the data encryption key. The ID of the given key is ignored, as the ID is assigned
+// by the database.
+pub async fn create(dek: DataEncryptionKey) -> Result<DataEncryptionKey, Error> {
+    let dek = create_tx(&mut get_async_db_conn().await?, dek).await?;
+    info!(
+        id = dek.id,
+        backend = %dek.backend,
+        tenant_id = ?dek.tenant_id.map(Uuid::from),
+        "Data encryption key created"
+    );
+    Ok(dek)
+}
+
+// Creates the data encryption key within the given transaction.
+pub(super) async fn create_tx(
+    c: &mut AsyncDbPoolConnection,
+    dek: DataEncryptionKey,
+) -> Result<DataEncryptionKey, Error> {
+    let dek: DataEncryptionKey = diesel::insert_into(data_encryption_key::table)
+        .values((
+            data_encryption_key::created_at.eq(&dek.created_at),
+            data_encryption_key::backend.eq(&dek.backend),
+            data_encryption_key::encrypted_key.eq(&dek.encrypted_key),
+            data_encryption_key::tenant_id.eq(&dek.tenant_id),
+        ))
+        .get_result(c)
+        .await?;
+    Ok(dek)
+}
+
+pub async fn list() -> Result<Vec<DataEncryptionKey>, Error> {
+    let items = data_encryption_key::dsl::data_encryption_key
+        .order_by(data_encryption_key::dsl::id)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+// Returns the IDs of all tenants, used for creating the per-tenant data encryption keys.
+pub async fn get_tenant_ids() -> Result<Vec<Uuid>, Error> {
+    let items: Vec<fields::Uuid> = tenant::dsl::tenant
+        .select(tenant::dsl::id)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items.into_iter().map(|v| v.into()).collect())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_data_encryption_key() {
+        let _guard = test::prepare().await;
+
+        let dek = create(DataEncryptionKey {
+            id: 0,
+            created_at: Utc::now(),
+            backend: "local".into(),
+            encrypted_key: vec![1, 2, 3],
+            tenant_id: None,
+        })
+        .await
+        .unwrap();
+
+        // The ID is assigned by the database.
+        let dek_2 = create(DataEncryptionKey {
+            id: 0,
+            created_at: Utc::now(),
+            backend: "local".into(),
+            encrypted_key: vec![4, 5, 6],
+            tenant_id: None,
+        })
+        .await
+        .unwrap();
+        assert!(dek_2.id > dek.id);
+
+        let items = list().await.unwrap();
+        assert_eq!(vec![dek, dek_2], items);
+    }
+}
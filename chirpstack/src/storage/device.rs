@@ -13,9 +13,11 @@ use chirpstack_api::internal;
 use lrwn::{DevAddr, EUI64};
 
 use super::schema::{application, device, device_profile, multicast_group_device, tenant};
-use super::{db_transaction, error::Error, fields, get_async_db_conn};
+use super::{
+    db_transaction, device_keys, error::Error, fields, get_async_db_conn, AsyncDbPoolConnection,
+};
 use crate::api::helpers::FromProto;
-use crate::config;
+use crate::{config, encryption};
 
 pub enum ValidationStatus {
     Ok(u32, Device),
@@ -88,6 +90,91 @@ impl serialize::ToSql<Text, diesel::sqlite::Sqlite> for DeviceClass {
     }
 }
 
+// Lifecycle state of the device.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub enum DeviceState {
+    Provisioned,
+    Active,
+    Suspended,
+    Decommissioned,
+}
+
+impl DeviceState {
+    // Returns true when uplinks and join-requests of the device must be dropped and downlinks
+    // must not be enqueued.
+    pub fn is_out_of_service(&self) -> bool {
+        matches!(self, DeviceState::Suspended | DeviceState::Decommissioned)
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DeviceState::Provisioned => "PROVISIONED",
+                DeviceState::Active => "ACTIVE",
+                DeviceState::Suspended => "SUSPENDED",
+                DeviceState::Decommissioned => "DECOMMISSIONED",
+            }
+        )
+    }
+}
+
+impl FromStr for DeviceState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "PROVISIONED" => DeviceState::Provisioned,
+            "ACTIVE" => DeviceState::Active,
+            "SUSPENDED" => DeviceState::Suspended,
+            "DECOMMISSIONED" => DeviceState::Decommissioned,
+            _ => return Err(anyhow!("Unexpected DeviceState: {}", s)),
+        })
+    }
+}
+
+impl<DB> deserialize::FromSql<Text, DB> for DeviceState
+where
+    DB: Backend,
+    *const str: deserialize::FromSql<Text, DB>,
+{
+    fn from_sql(value: <DB as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let string = <*const str>::from_sql(value)?;
+        Ok(Self::from_str(unsafe { &*string })?)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl serialize::ToSql<Text, diesel::pg::Pg> for DeviceState
+where
+    str: serialize::ToSql<Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> serialize::Result {
+        <str as serialize::ToSql<Text, diesel::pg::Pg>>::to_sql(
+            &self.to_string(),
+            &mut out.reborrow(),
+        )
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl serialize::ToSql<Text, diesel::sqlite::Sqlite> for DeviceState {
+    fn to_sql(
+        &self,
+        out: &mut serialize::Output<'_, '_, diesel::sqlite::Sqlite>,
+    ) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(serialize::IsNull::No)
+    }
+}
+
 #[derive(Queryable, QueryableByName, Insertable, PartialEq, Debug, Clone)]
 #[diesel(table_name = device)]
 pub struct Device {
@@ -117,6 +204,7 @@ pub struct Device {
     pub secondary_dev_addr: Option<DevAddr>,
     pub device_session: Option<fields::DeviceSession>,
     pub app_layer_params: fields::device::AppLayerParams,
+    pub state: DeviceState,
 }
 
 #[derive(AsChangeset, Debug, Clone, Default)]
@@ -135,6 +223,7 @@ pub struct DeviceChangeset {
     pub scheduler_run_after: Option<Option<DateTime<Utc>>>,
     pub is_disabled: Option<bool>,
     pub app_layer_params: Option<fields::device::AppLayerParams>,
+    pub state: Option<DeviceState>,
 }
 
 impl Device {
@@ -193,6 +282,7 @@ impl Default for Device {
             secondary_dev_addr: None,
             device_session: None,
             app_layer_params: Default::default(),
+            state: DeviceState::Provisioned,
         }
     }
 }
@@ -211,6 +301,7 @@ pub struct DeviceListItem {
     pub external_power_source: bool,
     pub battery_level: Option<fields::BigDecimal>,
     pub tags: fields::KeyValue,
+    pub state: DeviceState,
 }
 
 #[derive(Default, Clone)]
@@ -251,45 +342,8 @@ pub async fn create(d: Device) -> Result<Device, Error> {
     let mut c = get_async_db_conn().await?;
     let d: Device = db_transaction::<Device, Error, _>(&mut c, |c| {
         Box::pin(async move {
-            let query = tenant::dsl::tenant
-                .select((
-                    tenant::dsl::id,
-                    tenant::dsl::created_at,
-                    tenant::dsl::updated_at,
-                    tenant::dsl::name,
-                    tenant::dsl::description,
-                    tenant::dsl::can_have_gateways,
-                    tenant::dsl::max_device_count,
-                    tenant::dsl::max_gateway_count,
-                    tenant::dsl::private_gateways_up,
-                    tenant::dsl::private_gateways_down,
-                    tenant::dsl::tags,
-                ))
-                .inner_join(application::table)
-                .filter(application::dsl::id.eq(&d.application_id));
-            // use for update to lock the tenant
-            #[cfg(feature = "postgres")]
-            let query = query.for_update();
-            let t: super::tenant::Tenant = query.first(c).await?;
-
-            let dev_count: i64 = device::dsl::device
-                .select(dsl::count_star())
-                .inner_join(application::table)
-                .filter(application::dsl::tenant_id.eq(&t.id))
-                .first(c)
-                .await?;
-
-            if t.max_device_count != 0 && dev_count as i32 >= t.max_device_count {
-                return Err(Error::NotAllowed(
-                    "Max number of devices exceeded for tenant".into(),
-                ));
-            }
-
-            diesel::insert_into(device::table)
-                .values(&d)
-                .get_result(c)
-                .await
-                .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))
+            let (d, _) = create_tx(c, d).await?;
+            Ok(d)
         })
     })
     .await?;
@@ -297,6 +351,104 @@ pub async fn create(d: Device) -> Result<Device, Error> {
     Ok(d)
 }
 
+// Creates the device and its device-keys within a single transaction, such that the device is
+// not created when storing the device-keys fails.
+pub async fn create_with_keys(
+    d: Device,
+    dk: device_keys::DeviceKeys,
+) -> Result<(Device, device_keys::DeviceKeys), Error> {
+    let mut c = get_async_db_conn().await?;
+    let (d, dk) = db_transaction::<(Device, device_keys::DeviceKeys), Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let (d, tenant_id) = create_tx(c, d).await?;
+            let dk = device_keys::create_tx(
+                c,
+                device_keys::DeviceKeys {
+                    dev_eui: d.dev_eui,
+                    ..dk
+                },
+                if encryption::per_tenant_keys() {
+                    Some(tenant_id)
+                } else {
+                    None
+                },
+            )
+            .await?;
+            Ok((d, dk))
+        })
+    })
+    .await?;
+    info!(dev_eui = %d.dev_eui, "Device created");
+    info!(dev_eui = %dk.dev_eui, "Device-keys created");
+    Ok((d, device_keys::resolve_root_keys(dk).await?))
+}
+
+// Creates the device within the given transaction and returns it together with its tenant ID.
+async fn create_tx(c: &mut AsyncDbPoolConnection, d: Device) -> Result<(Device, Uuid), Error> {
+    let t = lock_tenant_and_check_device_count(c, &d.application_id).await?;
+
+    // The device-session key material is encrypted using the tenant data encryption
+    // key, thus the device-session is stored separately.
+    let ds = d
+        .device_session
+        .as_ref()
+        .map(|ds| ds.encrypt(Some(*t.id)))
+        .transpose()?;
+
+    let mut d: Device = diesel::insert_into(device::table)
+        .values(&Device {
+            device_session: None,
+            ..d.clone()
+        })
+        .get_result(c)
+        .await
+        .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))?;
+
+    if let Some(ds) = ds {
+        d = diesel::update(device::dsl::device.find(&d.dev_eui))
+            .set(device::device_session.eq(ds))
+            .get_result(c)
+            .await
+            .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))?;
+    }
+
+    Ok((d, *t.id))
+}
+
+// Locks the tenant of the given application (PostgreSQL only) and validates that the tenant
+// can have an additional device.
+async fn lock_tenant_and_check_device_count(
+    c: &mut AsyncDbPoolConnection,
+    application_id: &fields::Uuid,
+) -> Result<super::tenant::Tenant, Error> {
+    let query = tenant::dsl::tenant
+        .select(tenant::all_columns)
+        .inner_join(application::table)
+        .filter(application::dsl::id.eq(application_id));
+    // use for update to lock the tenant
+    #[cfg(feature = "postgres")]
+    let query = query.for_update();
+    let t: super::tenant::Tenant = query
+        .first(c)
+        .await
+        .map_err(|e| Error::from_diesel(e, application_id.to_string()))?;
+
+    let dev_count: i64 = device::dsl::device
+        .select(dsl::count_star())
+        .inner_join(application::table)
+        .filter(application::dsl::tenant_id.eq(&t.id))
+        .first(c)
+        .await?;
+
+    if t.max_device_count != 0 && dev_count as i32 >= t.max_device_count {
+        return Err(Error::NotAllowed(
+            "Max number of devices exceeded for tenant".into(),
+        ));
+    }
+
+    Ok(t)
+}
+
 pub async fn get(dev_eui: &EUI64) -> Result<Device, Error> {
     let d = device::dsl::device
         .find(&dev_eui)
@@ -427,8 +579,9 @@ pub async fn get_for_phypayload_and_incr_f_cnt_up(
                             let ds_f_cnt_up = ds.f_cnt_up;
                             ds.f_cnt_up = full_f_cnt + 1;
 
+                            let tenant_id = get_encryption_scope(&d.application_id).await?;
                             let _ = diesel::update(device::dsl::device.find(d.dev_eui))
-                                .set(device::device_session.eq(&ds.clone()))
+                                .set(device::device_session.eq(ds.encrypt(tenant_id)?))
                                 .execute(c)
                                 .await?;
 
@@ -543,49 +696,132 @@ pub async fn get_for_phypayload(
 pub async fn update(d: Device) -> Result<Device, Error> {
     d.validate()?;
 
-    let d: Device = diesel::update(device::dsl::device.find(&d.dev_eui))
-        .set((
-            device::updated_at.eq(Utc::now()),
-            device::application_id.eq(&d.application_id),
-            device::device_profile_id.eq(&d.device_profile_id),
-            device::name.eq(&d.name),
-            device::description.eq(&d.description),
-            device::skip_fcnt_check.eq(&d.skip_fcnt_check),
-            device::is_disabled.eq(&d.is_disabled),
-            device::tags.eq(&d.tags),
-            device::variables.eq(&d.variables),
-            device::join_eui.eq(&d.join_eui),
-            device::app_layer_params.eq(&d.app_layer_params),
-        ))
-        .get_result(&mut get_async_db_conn().await?)
-        .await
-        .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))?;
+    let mut c = get_async_db_conn().await?;
+    let d: Device = db_transaction::<Device, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            // In case the device is moved to an application of an other tenant, the max.
+            // device count of that tenant applies.
+            let tenant_id: fields::Uuid = device::dsl::device
+                .inner_join(application::table)
+                .select(application::dsl::tenant_id)
+                .filter(device::dsl::dev_eui.eq(&d.dev_eui))
+                .first(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))?;
+            let new_tenant_id: fields::Uuid = application::dsl::application
+                .find(&d.application_id)
+                .select(application::dsl::tenant_id)
+                .first(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, d.application_id.to_string()))?;
+            if tenant_id != new_tenant_id {
+                lock_tenant_and_check_device_count(c, &d.application_id).await?;
+            }
+
+            diesel::update(device::dsl::device.find(&d.dev_eui))
+                .set((
+                    device::updated_at.eq(Utc::now()),
+                    device::application_id.eq(&d.application_id),
+                    device::device_profile_id.eq(&d.device_profile_id),
+                    device::name.eq(&d.name),
+                    device::description.eq(&d.description),
+                    device::skip_fcnt_check.eq(&d.skip_fcnt_check),
+                    device::is_disabled.eq(&d.is_disabled),
+                    device::tags.eq(&d.tags),
+                    device::variables.eq(&d.variables),
+                    device::join_eui.eq(&d.join_eui),
+                    device::app_layer_params.eq(&d.app_layer_params),
+                ))
+                .get_result(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, d.dev_eui.to_string()))
+        })
+    })
+    .await?;
     info!(dev_eui = %d.dev_eui, "Device updated");
     Ok(d)
 }
 
 pub async fn partial_update(dev_eui: EUI64, d: &DeviceChangeset) -> Result<Device, Error> {
-    let d = diesel::update(device::dsl::device.find(&dev_eui))
-        .set(d)
-        .get_result::<Device>(&mut get_async_db_conn().await?)
-        .await
-        .map_err(|e| Error::from_diesel(e, dev_eui.to_string()))?;
+    // The device-session key material is encrypted using the tenant data encryption key,
+    // thus the device-session is set separately from the other changes.
+    let ds = match &d.device_session {
+        Some(Some(ds)) => Some(ds.encrypt(get_encryption_scope_for_device(&dev_eui).await?)?),
+        _ => None,
+    };
+
+    let mut c = get_async_db_conn().await?;
+    let d = match ds {
+        Some(ds) => {
+            diesel::update(device::dsl::device.find(&dev_eui))
+                .set((
+                    &DeviceChangeset {
+                        device_session: None,
+                        ..d.clone()
+                    },
+                    device::device_session.eq(ds),
+                ))
+                .get_result::<Device>(&mut c)
+                .await
+        }
+        None => {
+            diesel::update(device::dsl::device.find(&dev_eui))
+                .set(d)
+                .get_result::<Device>(&mut c)
+                .await
+        }
+    }
+    .map_err(|e| Error::from_diesel(e, dev_eui.to_string()))?;
 
     info!(dev_eui = %dev_eui, "Device partially updated");
     Ok(d)
 }
 
 pub async fn delete(dev_eui: &EUI64) -> Result<(), Error> {
+    // The device-keys are removed by the database (cascade), the root-keys in the keystore
+    // must be removed explicitly.
+    let key_ref = device_keys::get_key_ref(dev_eui).await?;
     let ra = diesel::delete(device::dsl::device.find(&dev_eui))
         .execute(&mut get_async_db_conn().await?)
         .await?;
     if ra == 0 {
         return Err(Error::NotFound(dev_eui.to_string()));
     }
+    if let Some(key_ref) = key_ref {
+        device_keys::delete_root_keys(dev_eui, &key_ref).await;
+    }
     info!(dev_eui = %dev_eui, "Device deleted");
     Ok(())
 }
 
+// Returns the tenant ID of the given application in case per-tenant data encryption keys are
+// enabled. This is used as encryption scope when storing key material.
+async fn get_encryption_scope(application_id: &Uuid) -> Result<Option<Uuid>, Error> {
+    if !encryption::per_tenant_keys() {
+        return Ok(None);
+    }
+
+    let a = super::application::get(application_id).await?;
+    Ok(Some(*a.tenant_id))
+}
+
+// Returns the tenant ID of the given device in case per-tenant data encryption keys are
+// enabled. This is used as encryption scope when storing key material.
+pub async fn get_encryption_scope_for_device(dev_eui: &EUI64) -> Result<Option<Uuid>, Error> {
+    if !encryption::per_tenant_keys() {
+        return Ok(None);
+    }
+
+    let application_id: fields::Uuid = device::dsl::device
+        .find(&dev_eui)
+        .select(device::dsl::application_id)
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, dev_eui.to_string()))?;
+
+    get_encryption_scope(&application_id).await
+}
+
 pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
     let mut q = device::dsl::device
         .select(dsl::count_star())
@@ -638,6 +874,28 @@ pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
     Ok(q.first(&mut get_async_db_conn().await?).await?)
 }
 
+// Returns the number of devices which are (or might be) using the given region configuration.
+// These are the devices of which the device-profile is pinned to the region configuration and
+// the activated devices of which the device-profile is not pinned, but has a matching region.
+pub async fn get_count_for_region(
+    region_config_id: &str,
+    common_name: lrwn::region::CommonName,
+) -> Result<i64, Error> {
+    Ok(device::dsl::device
+        .select(dsl::count_star())
+        .inner_join(device_profile::table)
+        .filter(
+            device_profile::dsl::region_config_id
+                .eq(region_config_id)
+                .or(device_profile::dsl::region_config_id
+                    .is_null()
+                    .and(device_profile::dsl::region.eq(common_name))
+                    .and(device::dsl::device_session.is_not_null())),
+        )
+        .first(&mut get_async_db_conn().await?)
+        .await?)
+}
+
 pub async fn list(
     limit: i64,
     offset: i64,
@@ -661,6 +919,7 @@ pub async fn list(
             device::external_power_source,
             device::battery_level,
             device::tags,
+            device::state,
         ))
         .distinct()
         .into_boxed();
@@ -828,9 +1087,10 @@ pub async fn get_with_class_b_c_queue_items(limit: usize) -> Result<Vec<Device>>
             //
             // This way, we do not have to keep the device records locked until the scheduler
             // finishes its batch as the same set of devices will not be returned until after
-            // the updated scheduler_run_after. Only if the scheduler takes more time than 2x the
+            // the updated scheduler_run_after. In case the scheduler takes more time than 2x the
             // interval (the scheduler is still working on processing the batch after 2 x interval)
-            // this might cause issues.
+            // the device is returned again, in which case the scheduler lock of the device (see
+            // downlink::scheduler) prevents it from being scheduled concurrently.
             // The alternative would be to keep the transaction open for a long time + keep
             // the device records locked during this time which could case issues as well.
             diesel::sql_query(if cfg!(feature = "sqlite") {
@@ -849,6 +1109,7 @@ pub async fn get_with_class_b_c_queue_items(limit: usize) -> Result<Vec<Device>>
                                 d.enabled_class in ('B', 'C')
                                 and (d.scheduler_run_after is null or d.scheduler_run_after < ?2)
                                 and d.is_disabled = FALSE
+                                and d.state not in ('SUSPENDED', 'DECOMMISSIONED')
                                 and exists (
                                     select
                                         1
@@ -882,6 +1143,7 @@ pub async fn get_with_class_b_c_queue_items(limit: usize) -> Result<Vec<Device>>
                                 d.enabled_class in ('B', 'C')
                                 and (d.scheduler_run_after is null or d.scheduler_run_after < $2)
                                 and d.is_disabled = false
+                                and d.state not in ('SUSPENDED', 'DECOMMISSIONED')
                                 and exists (
                                     select
                                         1
@@ -926,7 +1188,7 @@ pub async fn get_with_class_b_c_queue_items(limit: usize) -> Result<Vec<Device>>
 // * In case of a re-transmission, the returned frame-counter equals
 //   DeviceSession.FCntUp - 1, as the FCntUp value holds the next expected
 //   frame-counter, not the FCntUp which was last seen.
-fn get_full_f_cnt_up(next_expected_full_fcnt: u32, truncated_f_cnt: u32) -> u32 {
+pub fn get_full_f_cnt_up(next_expected_full_fcnt: u32, truncated_f_cnt: u32) -> u32 {
     // Handle re-transmission.
     if truncated_f_cnt == (((next_expected_full_fcnt % (1 << 16)) as u16).wrapping_sub(1)) as u32 {
         return next_expected_full_fcnt - 1;
@@ -1015,6 +1277,98 @@ pub mod test {
         assert!(delete(&d.dev_eui).await.is_err());
     }
 
+    #[tokio::test]
+    async fn test_device_create_with_keys() {
+        let _guard = test::prepare().await;
+        let dp = storage::device_profile::test::create_device_profile(None).await;
+        let app = storage::application::test::create_application(Some(dp.tenant_id.into())).await;
+
+        let d = Device {
+            name: "test-dev".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            application_id: app.id,
+            device_profile_id: dp.id,
+            ..Default::default()
+        };
+        let dk = device_keys::DeviceKeys {
+            nwk_key: AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]),
+            ..Default::default()
+        };
+
+        let (d, dk) = create_with_keys(d.clone(), dk.clone()).await.unwrap();
+        assert_eq!(d, get(&d.dev_eui).await.unwrap());
+        assert_eq!(dk, device_keys::get(&d.dev_eui).await.unwrap());
+
+        // A re-run fails as the device already exists.
+        assert!(matches!(
+            create_with_keys(d.clone(), dk.clone()).await,
+            Err(Error::AlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_max_device_count() {
+        let _guard = test::prepare().await;
+
+        let t_a = storage::tenant::create(storage::tenant::Tenant {
+            name: "tenant-a".into(),
+            max_device_count: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let t_b = storage::tenant::create(storage::tenant::Tenant {
+            name: "tenant-b".into(),
+            max_device_count: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp_a = storage::device_profile::test::create_device_profile(Some(t_a.id.into())).await;
+        let dp_b = storage::device_profile::test::create_device_profile(Some(t_b.id.into())).await;
+        let app_a = storage::application::test::create_application(Some(t_a.id.into())).await;
+        let app_b = storage::application::test::create_application(Some(t_b.id.into())).await;
+
+        let d_a = create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 1]),
+            dp_a.id.into(),
+            Some(app_a.id.into()),
+        )
+        .await;
+        let _ = create_device(
+            EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 2]),
+            dp_b.id.into(),
+            Some(app_b.id.into()),
+        )
+        .await;
+
+        // create exceeds max. device count
+        assert!(create(Device {
+            name: "test-dev".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 3]),
+            application_id: app_a.id,
+            device_profile_id: dp_a.id,
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // moving the device to an application of an other tenant exceeds max. device count
+        assert!(update(Device {
+            application_id: app_b.id,
+            device_profile_id: dp_b.id,
+            ..d_a.clone()
+        })
+        .await
+        .is_err());
+
+        // update within the same tenant
+        let mut d_a = d_a;
+        d_a.name = "updated".into();
+        assert!(update(d_a).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_device_list() {
         let _guard = test::prepare().await;
@@ -1272,12 +1626,27 @@ pub mod test {
         let res = get_with_class_b_c_queue_items(10).await.unwrap();
         assert_eq!(0, res.len());
 
+        // Class-C item pending, but device is suspended.
+        let d = partial_update(
+            d.dev_eui,
+            &DeviceChangeset {
+                scheduler_run_after: Some(None),
+                is_disabled: Some(false),
+                state: Some(DeviceState::Suspended),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let res = get_with_class_b_c_queue_items(10).await.unwrap();
+        assert_eq!(0, res.len());
+
         // device in class C / downlink is pending.
         let _ = partial_update(
             d.dev_eui,
             &DeviceChangeset {
                 scheduler_run_after: Some(None),
-                is_disabled: Some(false),
+                state: Some(DeviceState::Active),
                 ..Default::default()
             },
         )
@@ -13,7 +13,7 @@ pub async fn save_rx_info(rx_info: &internal::DeviceGatewayRxInfo) -> Result<()>
     let dev_eui = EUI64::from_slice(&rx_info.dev_eui)?;
     let conf = config::get();
     let key = redis_key(format!("device:{{{}}}:gwrx", dev_eui));
-    let ttl = conf.network.device_session_ttl.as_millis() as usize;
+    let ttl = config::get_device_ttl(conf.redis.ttl.device_gateway_rx_info).as_millis() as usize;
     let b = rx_info.encode_to_vec();
 
     () = redis::cmd("PSETEX")
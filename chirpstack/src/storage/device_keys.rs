@@ -2,13 +2,16 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
-use tracing::info;
+use tracing::{info, warn};
 
 use lrwn::{AES128Key, EUI64};
+use uuid::Uuid;
 
 use super::error::Error;
-use super::schema::device_keys;
-use super::{db_transaction, fields, get_async_db_conn};
+use super::schema::{application, device as device_schema, device_keys};
+use super::{db_transaction, device, fields, get_async_db_conn, AsyncDbPoolConnection};
+use crate::backend::keywrap;
+use crate::{encryption, keystore};
 
 #[derive(Queryable, Insertable, AsChangeset, PartialEq, Eq, Debug, Clone)]
 #[diesel(table_name = device_keys)]
@@ -16,11 +19,89 @@ pub struct DeviceKeys {
     pub dev_eui: EUI64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[diesel(
+        deserialize_as = fields::EncryptedAES128Key,
+        serialize_as = fields::EncryptedAES128Key
+    )]
     pub nwk_key: AES128Key,
+    #[diesel(
+        deserialize_as = fields::EncryptedAES128Key,
+        serialize_as = fields::EncryptedAES128Key
+    )]
     pub app_key: AES128Key,
     pub dev_nonces: fields::DevNonces,
     pub join_nonce: i32,
+    #[diesel(
+        deserialize_as = fields::EncryptedAES128Key,
+        serialize_as = fields::EncryptedAES128Key
+    )]
     pub gen_app_key: AES128Key,
+    // Reference to the root-keys in the keystore. When set, the root-keys are not stored in
+    // the database.
+    pub key_ref: String,
+    // Label of the KEK with which the NwkKey and AppKey have been wrapped. When set, only the
+    // wrapped keys are stored and the keys are unwrapped when used (see unwrap_root_keys).
+    pub kek_label: String,
+    pub wrapped_nwk_key: Vec<u8>,
+    pub wrapped_app_key: Vec<u8>,
+}
+
+impl DeviceKeys {
+    // Unwraps the NwkKey and AppKey in case these have been imported wrapped. The unwrapped keys
+    // must never be stored.
+    pub fn unwrap_root_keys(&mut self) -> Result<(), Error> {
+        if self.kek_label.is_empty() {
+            return Ok(());
+        }
+
+        if !self.wrapped_nwk_key.is_empty() {
+            self.nwk_key = keywrap::unwrap_root_key(&self.kek_label, &self.wrapped_nwk_key)?;
+        }
+        if !self.wrapped_app_key.is_empty() {
+            self.app_key = keywrap::unwrap_root_key(&self.kek_label, &self.wrapped_app_key)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Device-keys with the root-keys encrypted using the data encryption key of the tenant. This
+// is used for storing the device-keys, as the EncryptedAES128Key type uses the global data
+// encryption key.
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = device_keys)]
+struct TenantDeviceKeys {
+    dev_eui: EUI64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    nwk_key: Vec<u8>,
+    app_key: Vec<u8>,
+    dev_nonces: fields::DevNonces,
+    join_nonce: i32,
+    gen_app_key: Vec<u8>,
+    key_ref: String,
+    kek_label: String,
+    wrapped_nwk_key: Vec<u8>,
+    wrapped_app_key: Vec<u8>,
+}
+
+impl TenantDeviceKeys {
+    fn new(dk: &DeviceKeys, tenant_id: Option<Uuid>) -> Result<Self> {
+        Ok(TenantDeviceKeys {
+            dev_eui: dk.dev_eui,
+            created_at: dk.created_at,
+            updated_at: dk.updated_at,
+            nwk_key: encryption::encrypt_tenant_key(tenant_id, &dk.nwk_key.to_bytes())?,
+            app_key: encryption::encrypt_tenant_key(tenant_id, &dk.app_key.to_bytes())?,
+            dev_nonces: dk.dev_nonces.clone(),
+            join_nonce: dk.join_nonce,
+            gen_app_key: encryption::encrypt_tenant_key(tenant_id, &dk.gen_app_key.to_bytes())?,
+            key_ref: dk.key_ref.clone(),
+            kek_label: dk.kek_label.clone(),
+            wrapped_nwk_key: dk.wrapped_nwk_key.clone(),
+            wrapped_app_key: dk.wrapped_app_key.clone(),
+        })
+    }
 }
 
 impl Default for DeviceKeys {
@@ -36,21 +117,63 @@ impl Default for DeviceKeys {
             dev_nonces: Default::default(),
             join_nonce: 0,
             gen_app_key: Default::default(),
+            key_ref: "".into(),
+            kek_label: "".into(),
+            wrapped_nwk_key: Vec::new(),
+            wrapped_app_key: Vec::new(),
         }
     }
 }
 
 pub async fn create(dk: DeviceKeys) -> Result<DeviceKeys, Error> {
-    let dk: DeviceKeys = diesel::insert_into(device_keys::table)
-        .values(&dk)
-        .get_result(&mut get_async_db_conn().await?)
-        .await
-        .map_err(|e| Error::from_diesel(e, dk.dev_eui.to_string()))?;
+    let (dk, root_keys) = split_root_keys(dk);
+    let tenant_id = device::get_encryption_scope_for_device(&dk.dev_eui).await?;
+    let dk_db = TenantDeviceKeys::new(&dk, tenant_id)?;
+
+    let mut c = get_async_db_conn().await?;
+    let dk: DeviceKeys = db_transaction::<DeviceKeys, Error, _>(&mut c, |c| {
+        Box::pin(async move { insert(c, dk_db, root_keys).await })
+    })
+    .await?;
     info!(
         dev_eui = %dk.dev_eui,
         "Device-keys created"
     );
-    Ok(dk)
+    resolve_root_keys(dk).await
+}
+
+// Creates the device-keys within the given transaction. The returned root-keys are not resolved.
+pub(super) async fn create_tx(
+    c: &mut AsyncDbPoolConnection,
+    dk: DeviceKeys,
+    tenant_id: Option<Uuid>,
+) -> Result<DeviceKeys, Error> {
+    let (dk, root_keys) = split_root_keys(dk);
+    let dk_db = TenantDeviceKeys::new(&dk, tenant_id)?;
+    insert(c, dk_db, root_keys).await
+}
+
+async fn insert(
+    c: &mut AsyncDbPoolConnection,
+    dk_db: TenantDeviceKeys,
+    root_keys: Option<keystore::RootKeys>,
+) -> Result<DeviceKeys, Error> {
+    // The row is written before the root-keys are stored in the keystore, such that e.g. an
+    // already existing DevEUI does not overwrite the root-keys of the existing device.
+    let dk: DeviceKeys = diesel::insert_into(device_keys::table)
+        .values(&dk_db)
+        .get_result(c)
+        .await
+        .map_err(|e| Error::from_diesel(e, dk_db.dev_eui.to_string()))?;
+
+    match store_root_keys(&dk.dev_eui, root_keys).await? {
+        Some(key_ref) => diesel::update(device_keys::dsl::device_keys.find(&dk.dev_eui))
+            .set(device_keys::key_ref.eq(&key_ref))
+            .get_result(c)
+            .await
+            .map_err(|e| Error::from_diesel(e, dk.dev_eui.to_string())),
+        None => Ok(dk),
+    }
 }
 
 pub async fn get(dev_eui: &EUI64) -> Result<DeviceKeys, Error> {
@@ -59,29 +182,53 @@ pub async fn get(dev_eui: &EUI64) -> Result<DeviceKeys, Error> {
         .first(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| Error::from_diesel(e, dev_eui.to_string()))?;
-    Ok(dk)
+    resolve_root_keys(dk).await
 }
 
 pub async fn update(dk: DeviceKeys) -> Result<DeviceKeys, Error> {
-    let dk: DeviceKeys = diesel::update(device_keys::dsl::device_keys.find(&dk.dev_eui))
-        .set(&dk)
-        .get_result(&mut get_async_db_conn().await?)
-        .await
-        .map_err(|e| Error::from_diesel(e, dk.dev_eui.to_string()))?;
+    let (dk, root_keys) = split_root_keys(dk);
+    let tenant_id = device::get_encryption_scope_for_device(&dk.dev_eui).await?;
+    let dk_db = TenantDeviceKeys::new(&dk, tenant_id)?;
+
+    // See create, the root-keys are only stored once the row has been updated.
+    let mut c = get_async_db_conn().await?;
+    let dk: DeviceKeys = db_transaction::<DeviceKeys, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let dk: DeviceKeys = diesel::update(device_keys::dsl::device_keys.find(&dk_db.dev_eui))
+                .set(&dk_db)
+                .get_result(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, dk_db.dev_eui.to_string()))?;
+
+            match store_root_keys(&dk.dev_eui, root_keys).await? {
+                Some(key_ref) => diesel::update(device_keys::dsl::device_keys.find(&dk.dev_eui))
+                    .set(device_keys::key_ref.eq(&key_ref))
+                    .get_result(c)
+                    .await
+                    .map_err(|e| Error::from_diesel(e, dk.dev_eui.to_string())),
+                None => Ok(dk),
+            }
+        })
+    })
+    .await?;
     info!(
         dev_eui = %dk.dev_eui,
         "Device-keys updated"
     );
-    Ok(dk)
+    resolve_root_keys(dk).await
 }
 
 pub async fn delete(dev_eui: &EUI64) -> Result<(), Error> {
+    let key_ref = get_key_ref(dev_eui).await?;
     let ra = diesel::delete(device_keys::dsl::device_keys.find(&dev_eui))
         .execute(&mut get_async_db_conn().await?)
         .await?;
     if ra == 0 {
         return Err(Error::NotFound(dev_eui.to_string()));
     }
+    if let Some(key_ref) = key_ref {
+        delete_root_keys(dev_eui, &key_ref).await;
+    }
     info!(
         dev_eui = %dev_eui,
         "Device-keys deleted"
@@ -89,6 +236,53 @@ pub async fn delete(dev_eui: &EUI64) -> Result<(), Error> {
     Ok(())
 }
 
+// Returns the keystore reference of the device-keys, if the device-keys exist and the root-keys
+// are stored in the keystore.
+pub async fn get_key_ref(dev_eui: &EUI64) -> Result<Option<String>, Error> {
+    let key_ref: Option<String> = device_keys::dsl::device_keys
+        .find(&dev_eui)
+        .select(device_keys::dsl::key_ref)
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .optional()?;
+    Ok(key_ref.filter(|v| !v.is_empty()))
+}
+
+// Returns the keystore references of the device-keys of the given application. The device-keys
+// are removed by the database (cascade) when deleting the application, the root-keys in the
+// keystore must be removed explicitly.
+pub async fn get_key_refs_for_application(
+    application_id: &Uuid,
+) -> Result<Vec<(EUI64, String)>, Error> {
+    Ok(device_keys::dsl::device_keys
+        .inner_join(device_schema::table)
+        .select((device_keys::dsl::dev_eui, device_keys::dsl::key_ref))
+        .filter(device_schema::dsl::application_id.eq(fields::Uuid::from(application_id)))
+        .filter(device_keys::dsl::key_ref.ne(""))
+        .load(&mut get_async_db_conn().await?)
+        .await?)
+}
+
+// Returns the keystore references of the device-keys of the given tenant, see
+// get_key_refs_for_application.
+pub async fn get_key_refs_for_tenant(tenant_id: &Uuid) -> Result<Vec<(EUI64, String)>, Error> {
+    Ok(device_keys::dsl::device_keys
+        .inner_join(device_schema::table.inner_join(application::table))
+        .select((device_keys::dsl::dev_eui, device_keys::dsl::key_ref))
+        .filter(application::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)))
+        .filter(device_keys::dsl::key_ref.ne(""))
+        .load(&mut get_async_db_conn().await?)
+        .await?)
+}
+
+// Deletes the root-keys from the keystore. As this is called after the device-keys have been
+// removed from the database, errors are logged only.
+pub async fn delete_root_keys(dev_eui: &EUI64, key_ref: &str) {
+    if let Err(e) = keystore::delete(key_ref).await {
+        warn!(dev_eui = %dev_eui, key_ref = %key_ref, error = %e, "Deleting root-keys from keystore failed");
+    }
+}
+
 pub async fn set_dev_nonces(
     dev_eui: EUI64,
     nonces: &fields::DevNonces,
@@ -102,7 +296,7 @@ pub async fn set_dev_nonces(
         dev_eui = %dev_eui,
         "Dev-nonces updated"
     );
-    Ok(dk)
+    resolve_root_keys(dk).await
 }
 
 pub async fn validate_incr_join_and_store_dev_nonce(
@@ -142,6 +336,52 @@ pub async fn validate_incr_join_and_store_dev_nonce(
     .await?;
 
     info!(dev_eui = %dev_eui, dev_nonce = dev_nonce, "Device-nonce validated, join-nonce incremented and stored");
+    resolve_root_keys(dk).await
+}
+
+// Removes the root-keys from the device-keys in case a keystore is configured, as in this case
+// these are stored in the keystore (see store_root_keys).
+fn split_root_keys(mut dk: DeviceKeys) -> (DeviceKeys, Option<keystore::RootKeys>) {
+    if !keystore::enabled() {
+        return (dk, None);
+    }
+
+    let root_keys = keystore::RootKeys {
+        nwk_key: dk.nwk_key,
+        app_key: dk.app_key,
+        gen_app_key: dk.gen_app_key,
+    };
+    dk.nwk_key = AES128Key::null();
+    dk.app_key = AES128Key::null();
+    dk.gen_app_key = AES128Key::null();
+
+    (dk, Some(root_keys))
+}
+
+// Stores the root-keys in the keystore and returns the reference to these keys. This must be
+// called within the transaction writing the device-keys, such that the write is rolled back
+// when the root-keys can not be stored.
+async fn store_root_keys(
+    dev_eui: &EUI64,
+    root_keys: Option<keystore::RootKeys>,
+) -> Result<Option<String>, Error> {
+    match root_keys {
+        Some(v) => Ok(Some(keystore::set(dev_eui, &v).await?)),
+        None => Ok(None),
+    }
+}
+
+// Sets the root-keys from the keystore in case these are stored in the keystore.
+pub(super) async fn resolve_root_keys(mut dk: DeviceKeys) -> Result<DeviceKeys, Error> {
+    if dk.key_ref.is_empty() {
+        return Ok(dk);
+    }
+
+    let keys = keystore::get(&dk.key_ref).await?;
+    dk.nwk_key = keys.nwk_key;
+    dk.app_key = keys.app_key;
+    dk.gen_app_key = keys.gen_app_key;
+
     Ok(dk)
 }
 
@@ -149,7 +389,8 @@ pub async fn validate_incr_join_and_store_dev_nonce(
 pub mod test {
     use super::*;
     use crate::storage;
-    use crate::test;
+    use crate::{config, test};
+    use prost::Message;
 
     pub async fn reset_nonces(dev_eui: &EUI64) -> Result<DeviceKeys, Error> {
         let dk: DeviceKeys = diesel::update(device_keys::dsl::device_keys.find(&dev_eui))
@@ -214,4 +455,173 @@ pub mod test {
         delete(&dk.dev_eui).await.unwrap();
         assert!(delete(&dk.dev_eui).await.is_err());
     }
+
+    #[tokio::test]
+    async fn test_tenant_data_encryption_key() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "local".into();
+        conf.encryption.local.master_key =
+            "0102030405060708010203040506070801020304050607080102030405060708".into();
+        conf.encryption.per_tenant_keys = true;
+        config::set(conf);
+        encryption::setup().await.unwrap();
+
+        let dp = storage::device_profile::test::create_device_profile(None).await;
+        let a = storage::application::test::create_application(None).await;
+        let dek_id = storage::data_encryption_key::list()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|dek| dek.tenant_id == Some(a.tenant_id))
+            .map(|dek| dek.id)
+            .next_back()
+            .unwrap();
+
+        let d = storage::device::create(storage::device::Device {
+            name: "test-dev".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            application_id: a.id,
+            device_profile_id: dp.id,
+            device_session: Some(
+                chirpstack_api::internal::DeviceSession {
+                    nwk_s_enc_key: vec![1; 16],
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let nwk_key = AES128Key::from_bytes([1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]);
+        let dk = create(DeviceKeys {
+            dev_eui: d.dev_eui,
+            nwk_key,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(nwk_key, get(&dk.dev_eui).await.unwrap().nwk_key);
+
+        // The stored keys must be encrypted using the data encryption key of the tenant.
+        let stored: Vec<u8> = device_keys::dsl::device_keys
+            .find(&dk.dev_eui)
+            .select(device_keys::dsl::nwk_key)
+            .first(&mut get_async_db_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(dek_id.to_be_bytes(), stored[1..5]);
+
+        let stored: Option<Vec<u8>> = storage::schema::device::dsl::device
+            .find(&d.dev_eui)
+            .select(storage::schema::device::dsl::device_session)
+            .first(&mut get_async_db_conn().await.unwrap())
+            .await
+            .unwrap();
+        let ds = chirpstack_api::internal::DeviceSession::decode(&mut std::io::Cursor::new(
+            stored.unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(dek_id.to_be_bytes(), ds.nwk_s_enc_key[1..5]);
+
+        // Disable, the data keys must be cleared.
+        let mut conf = (*config::get()).clone();
+        conf.encryption.backend = "".into();
+        conf.encryption.per_tenant_keys = false;
+        config::set(conf);
+        encryption::setup().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unwrap_root_keys() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.keks = vec![config::Kek {
+            label: "se-kek".into(),
+            kek: AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]),
+        }];
+        config::set(conf);
+
+        let nwk_key = AES128Key::from_bytes([1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1]);
+        let app_key = AES128Key::from_bytes([2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2]);
+
+        let mut dk = create_device_keys(None).await;
+        dk.kek_label = "se-kek".into();
+        dk.wrapped_nwk_key = keywrap::wrap("se-kek", nwk_key).unwrap().aes_key;
+        dk.wrapped_app_key = keywrap::wrap("se-kek", app_key).unwrap().aes_key;
+        update(dk.clone()).await.unwrap();
+
+        // the unwrapped keys are not stored
+        let mut dk_get = get(&dk.dev_eui).await.unwrap();
+        assert_eq!(AES128Key::null(), dk_get.nwk_key);
+        assert_eq!(AES128Key::null(), dk_get.app_key);
+
+        dk_get.unwrap_root_keys().unwrap();
+        assert_eq!(nwk_key, dk_get.nwk_key);
+        assert_eq!(app_key, dk_get.app_key);
+
+        // unknown kek
+        dk_get.kek_label = "unknown-kek".into();
+        assert!(dk_get.unwrap_root_keys().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_keystore() {
+        let _guard = test::prepare().await;
+
+        let server = httpmock::MockServer::start();
+        let mut conf = (*config::get()).clone();
+        conf.keystore.backend = "vault_kv".into();
+        conf.keystore.vault_kv = config::KeystoreVaultKv {
+            address: server.url("/"),
+            token: "secret-token".into(),
+            mount: "secret".into(),
+            path_prefix: "device-keys".into(),
+        };
+        config::set(conf);
+        keystore::setup().unwrap();
+
+        let mut set_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::POST)
+                .path("/v1/secret/data/device-keys/0102030405060708");
+            then.status(200);
+        });
+        let mut delete_mock = server.mock(|when, then| {
+            when.method(httpmock::Method::DELETE)
+                .path("/v1/secret/metadata/device-keys/0102030405060708");
+            then.status(204);
+        });
+
+        let dk = create_device_keys(None).await;
+        assert_eq!("device-keys/0102030405060708", dk.key_ref);
+        set_mock.assert_hits(1);
+
+        // creating the device-keys again must not overwrite the stored root-keys
+        assert!(create(DeviceKeys {
+            dev_eui: dk.dev_eui,
+            ..Default::default()
+        })
+        .await
+        .is_err());
+        set_mock.assert_hits(1);
+
+        // deleting the application removes the root-keys from the keystore
+        let d = device::get(&dk.dev_eui).await.unwrap();
+        storage::application::delete(&d.application_id.into())
+            .await
+            .unwrap();
+        delete_mock.assert_hits(1);
+
+        set_mock.delete();
+        delete_mock.delete();
+
+        let mut conf = (*config::get()).clone();
+        conf.keystore.backend = "".into();
+        config::set(conf);
+        keystore::setup().unwrap();
+    }
 }
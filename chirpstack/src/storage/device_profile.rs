@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::Instant;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -11,7 +12,7 @@ use lrwn::region::{CommonName, MacVersion, Revision};
 
 use super::error::Error;
 use super::schema::device_profile;
-use super::{error, fields, get_async_db_conn};
+use super::{cache, error, fields, get_async_db_conn};
 use crate::api::helpers::ToProto;
 use crate::codec::Codec;
 use chirpstack_api::internal;
@@ -172,11 +173,17 @@ pub async fn create(dp: DeviceProfile) -> Result<DeviceProfile, Error> {
 }
 
 pub async fn get(id: &Uuid) -> Result<DeviceProfile, Error> {
-    let dp = device_profile::dsl::device_profile
+    if let Some(dp) = cache::DEVICE_PROFILES.get(id) {
+        return Ok(dp);
+    }
+
+    let cached_at = Instant::now();
+    let dp: DeviceProfile = device_profile::dsl::device_profile
         .find(&fields::Uuid::from(id))
         .first(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| error::Error::from_diesel(e, id.to_string()))?;
+    cache::DEVICE_PROFILES.set(*id, cached_at, dp.clone());
     Ok(dp)
 }
 
@@ -215,6 +222,7 @@ pub async fn update(dp: DeviceProfile) -> Result<DeviceProfile, Error> {
         .get_result(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| error::Error::from_diesel(e, dp.id.to_string()))?;
+    cache::invalidate(cache::Kind::DeviceProfile, &dp.id).await;
 
     info!(id = %dp.id, "Device-profile updated");
     Ok(dp)
@@ -227,6 +235,7 @@ pub async fn set_measurements(id: Uuid, m: &fields::Measurements) -> Result<Devi
             .get_result(&mut get_async_db_conn().await?)
             .await
             .map_err(|e| Error::from_diesel(e, id.to_string()))?;
+    cache::invalidate(cache::Kind::DeviceProfile, &id).await;
     info!(id = %id, "Device-profile measurements updated");
     Ok(dp)
 }
@@ -238,6 +247,7 @@ pub async fn delete(id: &Uuid) -> Result<(), Error> {
     if ra == 0 {
         return Err(error::Error::NotFound(id.to_string()));
     }
+    cache::invalidate(cache::Kind::DeviceProfile, id).await;
     info!(id = %id, "Device-profile deleted");
     Ok(())
 }
@@ -0,0 +1,194 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::info;
+
+use super::error::Error;
+use super::schema::{device_profile, device_repository_codec};
+use super::{cache, get_async_db_conn};
+use crate::codec::Codec;
+
+// Codec synced from the LoRaWAN Device Repository. The id is the codec reference as configured
+// in the device-profile, e.g. vendor/device or vendor/device@firmware_version.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = device_repository_codec)]
+pub struct DeviceRepositoryCodec {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub vendor_id: String,
+    pub device_id: String,
+    pub firmware_version: String,
+    pub codec_id: String,
+    pub script: String,
+}
+
+impl Default for DeviceRepositoryCodec {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        DeviceRepositoryCodec {
+            id: "".into(),
+            created_at: now,
+            updated_at: now,
+            vendor_id: "".into(),
+            device_id: "".into(),
+            firmware_version: "".into(),
+            codec_id: "".into(),
+            script: "".into(),
+        }
+    }
+}
+
+pub async fn upsert(c: DeviceRepositoryCodec) -> Result<DeviceRepositoryCodec, Error> {
+    let c: DeviceRepositoryCodec = diesel::insert_into(device_repository_codec::table)
+        .values(&c)
+        .on_conflict(device_repository_codec::id)
+        .do_update()
+        .set((
+            device_repository_codec::updated_at.eq(Utc::now()),
+            device_repository_codec::vendor_id.eq(&c.vendor_id),
+            device_repository_codec::device_id.eq(&c.device_id),
+            device_repository_codec::firmware_version.eq(&c.firmware_version),
+            device_repository_codec::codec_id.eq(&c.codec_id),
+            device_repository_codec::script.eq(&c.script),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, c.id.clone()))?;
+    cache::invalidate(cache::Kind::DeviceRepositoryCodec, &c.id).await;
+    info!(id = %c.id, firmware_version = %c.firmware_version, codec_id = %c.codec_id, "Device repository codec upserted");
+    Ok(c)
+}
+
+// Returns the codec. As this is used for every uplink and downlink of the device-profiles
+// referencing the codec, this is cached.
+pub async fn get(id: &str) -> Result<DeviceRepositoryCodec, Error> {
+    let id = id.to_string();
+    if let Some(c) = cache::DEVICE_REPOSITORY_CODECS.get(&id) {
+        return Ok(c);
+    }
+
+    let cached_at = Instant::now();
+    let c: DeviceRepositoryCodec = device_repository_codec::dsl::device_repository_codec
+        .find(&id)
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.clone()))?;
+    cache::DEVICE_REPOSITORY_CODECS.set(id, cached_at, c.clone());
+    Ok(c)
+}
+
+// Returns the codec references used by the device-profiles.
+pub async fn get_referenced_ids() -> Result<Vec<String>, Error> {
+    device_profile::dsl::device_profile
+        .select(device_profile::dsl::payload_codec_script)
+        .filter(device_profile::dsl::payload_codec_runtime.eq(Codec::DEVICE_REPOSITORY))
+        .distinct()
+        .load(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, "".into()))
+}
+
+// Deletes the codecs which are no longer referenced by any device-profile.
+pub async fn delete_unreferenced() -> Result<usize, Error> {
+    let ids = get_referenced_ids().await?;
+
+    let unreferenced: Vec<String> = device_repository_codec::dsl::device_repository_codec
+        .select(device_repository_codec::dsl::id)
+        .filter(device_repository_codec::dsl::id.ne_all(ids))
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+
+    let count = diesel::delete(
+        device_repository_codec::dsl::device_repository_codec
+            .filter(device_repository_codec::dsl::id.eq_any(&unreferenced)),
+    )
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+
+    for id in &unreferenced {
+        cache::invalidate(cache::Kind::DeviceRepositoryCodec, id).await;
+    }
+
+    info!(
+        count = count,
+        "Unreferenced device repository codecs deleted"
+    );
+    Ok(count)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::{device_profile, tenant};
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_device_repository_codec() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::test::create_tenant().await;
+
+        device_profile::create(device_profile::DeviceProfile {
+            tenant_id: t.id,
+            name: "test-dp".into(),
+            payload_codec_runtime: Codec::DEVICE_REPOSITORY,
+            payload_codec_script: "vendor/device-a".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // create
+        let mut c = upsert(DeviceRepositoryCodec {
+            id: "vendor/device-a".into(),
+            vendor_id: "vendor".into(),
+            device_id: "device-a".into(),
+            firmware_version: "1.0".into(),
+            codec_id: "device-a-codec".into(),
+            script: "function decodeUplink(input) {}".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        upsert(DeviceRepositoryCodec {
+            id: "vendor/device-b@1.0".into(),
+            vendor_id: "vendor".into(),
+            device_id: "device-b".into(),
+            firmware_version: "1.0".into(),
+            codec_id: "device-b-codec".into(),
+            script: "function decodeUplink(input) {}".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // get
+        let c_get = get("vendor/device-a").await.unwrap();
+        assert_eq!(c, c_get);
+
+        // upsert existing
+        c.firmware_version = "1.1".into();
+        c.script = "function encodeDownlink(input) {}".into();
+        let c_up = upsert(c.clone()).await.unwrap();
+        assert_eq!("1.1", c_up.firmware_version);
+        assert_eq!(c.script, get("vendor/device-a").await.unwrap().script);
+
+        // referenced ids
+        assert_eq!(
+            vec!["vendor/device-a".to_string()],
+            get_referenced_ids().await.unwrap()
+        );
+
+        // delete unreferenced (the cached codec must be invalidated)
+        assert!(get("vendor/device-b@1.0").await.is_ok());
+        assert_eq!(1, delete_unreferenced().await.unwrap());
+        assert!(get("vendor/device-b@1.0").await.is_err());
+        assert!(get("vendor/device-a").await.is_ok());
+    }
+}
@@ -1,23 +1,65 @@
 use std::io::Cursor;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use prost::Message;
 use tracing::info;
 
 use super::{error::Error, get_async_redis_conn, redis_key};
 use chirpstack_api::internal;
+use lrwn::EUI64;
+
+// Downlink-frames are stored until the tx acknowledgement has been received, or until the TTL
+// has expired.
+const DOWNLINK_FRAME_TTL_MS: i64 = 30_000;
+
+// PendingDownlinkFrame represents a downlink-frame that has been sent to the gateway, for which
+// no tx acknowledgement has been received yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingDownlinkFrame {
+    pub created_at: DateTime<Utc>,
+    pub downlink_frame: internal::DownlinkFrame,
+}
 
 pub async fn save(df: &internal::DownlinkFrame) -> Result<()> {
     let b = df.encode_to_vec();
     let key = redis_key(format!("frame:{}", df.downlink_id));
 
-    () = redis::cmd("SETEX")
+    () = redis::cmd("PSETEX")
         .arg(key)
-        .arg(30)
+        .arg(DOWNLINK_FRAME_TTL_MS)
         .arg(b)
         .query_async(&mut get_async_redis_conn().await?)
         .await?;
 
+    // Keep track of the pending downlink-frames per gateway. Entries older than the TTL are
+    // removed on each save, as these have either been acknowledged or have expired.
+    if let Some(gw_df) = &df.downlink_frame {
+        if !gw_df.gateway_id.is_empty() {
+            let gw_key = get_gateway_frames_key(&gw_df.gateway_id);
+            let now = Utc::now().timestamp_millis();
+
+            () = redis::pipe()
+                .atomic()
+                .cmd("ZADD")
+                .arg(&gw_key)
+                .arg(now)
+                .arg(df.downlink_id)
+                .ignore()
+                .cmd("ZREMRANGEBYSCORE")
+                .arg(&gw_key)
+                .arg("-inf")
+                .arg(now - DOWNLINK_FRAME_TTL_MS)
+                .ignore()
+                .cmd("PEXPIRE")
+                .arg(&gw_key)
+                .arg(DOWNLINK_FRAME_TTL_MS)
+                .ignore()
+                .query_async(&mut get_async_redis_conn().await?)
+                .await?;
+        }
+    }
+
     info!(downlink_id = df.downlink_id, "Downlink-frame saved");
     Ok(())
 }
@@ -32,9 +74,65 @@ pub async fn get_and_del(id: u32) -> Result<internal::DownlinkFrame, Error> {
         return Err(Error::NotFound(format!("{}", id)));
     }
     let df = internal::DownlinkFrame::decode(&mut Cursor::new(v))?;
+
+    if let Some(gw_df) = &df.downlink_frame {
+        if !gw_df.gateway_id.is_empty() {
+            () = redis::cmd("ZREM")
+                .arg(get_gateway_frames_key(&gw_df.gateway_id))
+                .arg(id)
+                .query_async(&mut get_async_redis_conn().await?)
+                .await?;
+        }
+    }
+
     Ok(df)
 }
 
+// Returns the downlink-frames that have been sent to the given gateway, for which no tx
+// acknowledgement has been received (yet). The frames are sorted by creation time.
+pub async fn get_pending_for_gateway(gateway_id: &EUI64) -> Result<Vec<PendingDownlinkFrame>> {
+    let gw_key = get_gateway_frames_key(&gateway_id.to_string());
+    let now = Utc::now().timestamp_millis();
+
+    let items: Vec<(u32, f64)> = redis::cmd("ZRANGEBYSCORE")
+        .arg(&gw_key)
+        .arg(now - DOWNLINK_FRAME_TTL_MS)
+        .arg("+inf")
+        .arg("WITHSCORES")
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // The frames are stored under different key-slots, therefore a non-atomic pipeline is used.
+    let mut pipe = redis::pipe();
+    for (id, _) in &items {
+        pipe.cmd("GET").arg(redis_key(format!("frame:{}", id)));
+    }
+    let frames: Vec<Vec<u8>> = pipe.query_async(&mut get_async_redis_conn().await?).await?;
+
+    let mut out: Vec<PendingDownlinkFrame> = Vec::with_capacity(items.len());
+    for ((_, score), b) in items.iter().zip(frames) {
+        // The frame has been acknowledged or has expired in the meantime.
+        if b.is_empty() {
+            continue;
+        }
+
+        out.push(PendingDownlinkFrame {
+            created_at: DateTime::from_timestamp_millis(*score as i64).unwrap_or_default(),
+            downlink_frame: internal::DownlinkFrame::decode(&mut Cursor::new(b))?,
+        });
+    }
+
+    Ok(out)
+}
+
+fn get_gateway_frames_key(gateway_id: &str) -> String {
+    redis_key(format!("gw:{{{}}}:frames", gateway_id))
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -56,4 +154,62 @@ pub mod test {
         let df_get = get_and_del(12345).await.unwrap();
         assert_eq!(df, df_get);
     }
+
+    #[tokio::test]
+    async fn test_pending_for_gateway() {
+        let _guard = test::prepare().await;
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let df_1 = internal::DownlinkFrame {
+            downlink_id: 1,
+            downlink_frame: Some(gw::DownlinkFrame {
+                downlink_id: 1,
+                gateway_id: gateway_id.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let df_2 = internal::DownlinkFrame {
+            downlink_id: 2,
+            downlink_frame: Some(gw::DownlinkFrame {
+                downlink_id: 2,
+                gateway_id: gateway_id.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let df_other = internal::DownlinkFrame {
+            downlink_id: 3,
+            downlink_frame: Some(gw::DownlinkFrame {
+                downlink_id: 3,
+                gateway_id: "0807060504030201".into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        save(&df_1).await.unwrap();
+        save(&df_2).await.unwrap();
+        save(&df_other).await.unwrap();
+
+        let pending = get_pending_for_gateway(&gateway_id).await.unwrap();
+        assert_eq!(
+            vec![df_1.clone(), df_2.clone()],
+            pending
+                .iter()
+                .map(|v| v.downlink_frame.clone())
+                .collect::<Vec<internal::DownlinkFrame>>()
+        );
+
+        // After the tx acknowledgement, the frame is no longer pending.
+        let _ = get_and_del(1).await.unwrap();
+        let pending = get_pending_for_gateway(&gateway_id).await.unwrap();
+        assert_eq!(
+            vec![df_2],
+            pending
+                .iter()
+                .map(|v| v.downlink_frame.clone())
+                .collect::<Vec<internal::DownlinkFrame>>()
+        );
+    }
 }
@@ -9,7 +9,9 @@ use diesel::sql_types::Binary;
 use diesel::sqlite::Sqlite;
 use diesel::{deserialize, serialize};
 use prost::Message;
+use uuid::Uuid;
 
+use crate::encryption;
 use chirpstack_api::internal;
 
 #[derive(Debug, Clone, PartialEq, AsExpression, FromSqlRow)]
@@ -20,6 +22,13 @@ impl DeviceSession {
     pub fn new(m: internal::DeviceSession) -> Self {
         DeviceSession(m)
     }
+
+    // Returns the encoded device-session with the session-keys encrypted using the data
+    // encryption key of the given tenant. This must be used when storing the device-session of
+    // a tenant device, as the ToSql implementation uses the global data encryption key.
+    pub fn encrypt(&self, tenant_id: Option<Uuid>) -> anyhow::Result<Vec<u8>> {
+        Ok(encrypt_session_keys(&self.0, tenant_id)?.encode_to_vec())
+    }
 }
 
 impl std::convert::From<internal::DeviceSession> for DeviceSession {
@@ -61,7 +70,8 @@ where
 {
     fn from_sql(value: <DB as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
         let bindata = <*const [u8] as deserialize::FromSql<Binary, DB>>::from_sql(value)?;
-        let ds = internal::DeviceSession::decode(&mut Cursor::new(unsafe { &*bindata }))?;
+        let mut ds = internal::DeviceSession::decode(&mut Cursor::new(unsafe { &*bindata }))?;
+        decrypt_session_keys(&mut ds)?;
         Ok(DeviceSession(ds))
     }
 }
@@ -69,7 +79,7 @@ where
 #[cfg(feature = "postgres")]
 impl serialize::ToSql<Binary, Pg> for DeviceSession {
     fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Pg>) -> serialize::Result {
-        let encoded = self.encode_to_vec();
+        let encoded = self.encrypt(None)?;
         <Vec<u8> as serialize::ToSql<Binary, Pg>>::to_sql(&encoded, &mut out.reborrow())
     }
 }
@@ -77,7 +87,65 @@ impl serialize::ToSql<Binary, Pg> for DeviceSession {
 #[cfg(feature = "sqlite")]
 impl serialize::ToSql<Binary, Sqlite> for DeviceSession {
     fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Sqlite>) -> serialize::Result {
-        out.set_value(self.encode_to_vec());
+        out.set_value(self.encrypt(None)?);
         Ok(serialize::IsNull::No)
     }
 }
+
+// Returns a copy of the device-session with the session-keys encrypted (see the encryption
+// module). The AppSKey is only encrypted in case it is not already wrapped using a KEK.
+fn encrypt_session_keys(
+    ds: &internal::DeviceSession,
+    tenant_id: Option<Uuid>,
+) -> anyhow::Result<internal::DeviceSession> {
+    let mut ds = ds.clone();
+
+    for key in [
+        &mut ds.f_nwk_s_int_key,
+        &mut ds.s_nwk_s_int_key,
+        &mut ds.nwk_s_enc_key,
+    ] {
+        if let Ok(b) = <[u8; 16]>::try_from(key.as_slice()) {
+            *key = encryption::encrypt_tenant_key(tenant_id, &b)?;
+        }
+    }
+
+    if let Some(app_s_key) = &mut ds.app_s_key {
+        if app_s_key.kek_label.is_empty() {
+            if let Ok(b) = <[u8; 16]>::try_from(app_s_key.aes_key.as_slice()) {
+                app_s_key.aes_key = encryption::encrypt_tenant_key(tenant_id, &b)?;
+            }
+        }
+    }
+
+    if let Some(pending) = &ds.pending_rejoin_device_session {
+        ds.pending_rejoin_device_session =
+            Some(Box::new(encrypt_session_keys(pending, tenant_id)?));
+    }
+
+    Ok(ds)
+}
+
+fn decrypt_session_keys(ds: &mut internal::DeviceSession) -> anyhow::Result<()> {
+    for key in [
+        &mut ds.f_nwk_s_int_key,
+        &mut ds.s_nwk_s_int_key,
+        &mut ds.nwk_s_enc_key,
+    ] {
+        if !key.is_empty() {
+            *key = encryption::decrypt_key(key)?.to_vec();
+        }
+    }
+
+    if let Some(app_s_key) = &mut ds.app_s_key {
+        if app_s_key.kek_label.is_empty() && !app_s_key.aes_key.is_empty() {
+            app_s_key.aes_key = encryption::decrypt_key(&app_s_key.aes_key)?.to_vec();
+        }
+    }
+
+    if let Some(pending) = &mut ds.pending_rejoin_device_session {
+        decrypt_session_keys(pending)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use diesel::backend::Backend;
+#[cfg(feature = "postgres")]
+use diesel::pg::Pg;
+use diesel::sql_types::Binary;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::Sqlite;
+use diesel::{deserialize, serialize};
+
+use crate::encryption;
+use lrwn::AES128Key;
+
+// EncryptedAES128Key is used as (de)serialization type for AES128Key columns which must be
+// encrypted at rest (see the encryption module).
+#[derive(Debug, Clone, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = diesel::sql_types::Binary)]
+pub struct EncryptedAES128Key(AES128Key);
+
+impl From<AES128Key> for EncryptedAES128Key {
+    fn from(k: AES128Key) -> Self {
+        EncryptedAES128Key(k)
+    }
+}
+
+impl From<EncryptedAES128Key> for AES128Key {
+    fn from(k: EncryptedAES128Key) -> Self {
+        k.0
+    }
+}
+
+impl<DB> deserialize::FromSql<Binary, DB> for EncryptedAES128Key
+where
+    DB: Backend,
+    *const [u8]: deserialize::FromSql<Binary, DB>,
+{
+    fn from_sql(value: <DB as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let bindata = <*const [u8] as deserialize::FromSql<Binary, DB>>::from_sql(value)?;
+        let key = encryption::decrypt_key(unsafe { &*bindata })?;
+        Ok(EncryptedAES128Key(AES128Key::from_bytes(key)))
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl serialize::ToSql<Binary, Pg> for EncryptedAES128Key {
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Pg>) -> serialize::Result {
+        let encrypted = encryption::encrypt_key(&self.0.to_bytes())?;
+        <Vec<u8> as serialize::ToSql<Binary, Pg>>::to_sql(&encrypted, &mut out.reborrow())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl serialize::ToSql<Binary, Sqlite> for EncryptedAES128Key {
+    fn to_sql<'b>(&'b self, out: &mut serialize::Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(encryption::encrypt_key(&self.0.to_bytes())?);
+        Ok(serialize::IsNull::No)
+    }
+}
@@ -3,20 +3,28 @@ mod dev_nonces;
 pub mod device;
 pub mod device_profile;
 mod device_session;
+mod encrypted_key;
 mod fuota;
 mod key_value;
 mod measurements;
 mod multicast_group_scheduling_type;
+mod permission;
+mod relay_path;
+mod roaming_role;
 mod uuid;
 
 pub use big_decimal::BigDecimal;
 pub use dev_nonces::DevNonces;
 pub use device_profile::{AbpParams, AppLayerParams, ClassBParams, ClassCParams, RelayParams};
 pub use device_session::DeviceSession;
+pub use encrypted_key::EncryptedAES128Key;
 pub use fuota::{FuotaJob, RequestFragmentationSessionStatus};
 pub use key_value::KeyValue;
 pub use measurements::*;
 pub use multicast_group_scheduling_type::MulticastGroupSchedulingType;
+pub use permission::Permission;
+pub use relay_path::{RelayPath, RelayPathItem};
+pub use roaming_role::RoamingRole;
 pub use uuid::Uuid;
 
 #[cfg(feature = "postgres")]
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use diesel::backend::Backend;
+use diesel::sql_types::Text;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::Sqlite;
+use diesel::{deserialize, serialize};
+use serde::{Deserialize, Serialize};
+
+// Permission which can be granted to a tenant role. Read permissions allow to get and list the
+// resource, write permissions allow to create, update and delete the resource.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum Permission {
+    ApplicationRead,
+    ApplicationWrite,
+    DeviceRead,
+    DeviceWrite,
+    // Device root-keys and activation (session-keys).
+    DeviceKeysRead,
+    DeviceKeysWrite,
+    // Device queue (enqueue and flush downlinks).
+    DeviceQueueRead,
+    DeviceQueueWrite,
+    DeviceProfileRead,
+    DeviceProfileWrite,
+    CodecLibraryRead,
+    CodecLibraryWrite,
+    GatewayRead,
+    GatewayWrite,
+    MulticastGroupRead,
+    MulticastGroupWrite,
+    FuotaDeploymentRead,
+    FuotaDeploymentWrite,
+    // Creating and deleting API keys is reserved to tenant admins, as tenant API keys are not
+    // restricted by a role.
+    ApiKeyRead,
+    // Tenant users and tenant roles.
+    TenantUserRead,
+    TenantUserWrite,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Permission::ApplicationRead => "application:read",
+                Permission::ApplicationWrite => "application:write",
+                Permission::DeviceRead => "device:read",
+                Permission::DeviceWrite => "device:write",
+                Permission::DeviceKeysRead => "device_keys:read",
+                Permission::DeviceKeysWrite => "device_keys:write",
+                Permission::DeviceQueueRead => "device_queue:read",
+                Permission::DeviceQueueWrite => "device_queue:write",
+                Permission::DeviceProfileRead => "device_profile:read",
+                Permission::DeviceProfileWrite => "device_profile:write",
+                Permission::CodecLibraryRead => "codec_library:read",
+                Permission::CodecLibraryWrite => "codec_library:write",
+                Permission::GatewayRead => "gateway:read",
+                Permission::GatewayWrite => "gateway:write",
+                Permission::MulticastGroupRead => "multicast_group:read",
+                Permission::MulticastGroupWrite => "multicast_group:write",
+                Permission::FuotaDeploymentRead => "fuota_deployment:read",
+                Permission::FuotaDeploymentWrite => "fuota_deployment:write",
+                Permission::ApiKeyRead => "api_key:read",
+                Permission::TenantUserRead => "tenant_user:read",
+                Permission::TenantUserWrite => "tenant_user:write",
+            }
+        )
+    }
+}
+
+impl FromStr for Permission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "application:read" => Permission::ApplicationRead,
+            "application:write" => Permission::ApplicationWrite,
+            "device:read" => Permission::DeviceRead,
+            "device:write" => Permission::DeviceWrite,
+            "device_keys:read" => Permission::DeviceKeysRead,
+            "device_keys:write" => Permission::DeviceKeysWrite,
+            "device_queue:read" => Permission::DeviceQueueRead,
+            "device_queue:write" => Permission::DeviceQueueWrite,
+            "device_profile:read" => Permission::DeviceProfileRead,
+            "device_profile:write" => Permission::DeviceProfileWrite,
+            "codec_library:read" => Permission::CodecLibraryRead,
+            "codec_library:write" => Permission::CodecLibraryWrite,
+            "gateway:read" => Permission::GatewayRead,
+            "gateway:write" => Permission::GatewayWrite,
+            "multicast_group:read" => Permission::MulticastGroupRead,
+            "multicast_group:write" => Permission::MulticastGroupWrite,
+            "fuota_deployment:read" => Permission::FuotaDeploymentRead,
+            "fuota_deployment:write" => Permission::FuotaDeploymentWrite,
+            "api_key:read" => Permission::ApiKeyRead,
+            "tenant_user:read" => Permission::TenantUserRead,
+            "tenant_user:write" => Permission::TenantUserWrite,
+            _ => {
+                return Err(anyhow!("Unexpected Permission: {}", s));
+            }
+        })
+    }
+}
+
+impl<DB> deserialize::FromSql<Text, DB> for Permission
+where
+    DB: Backend,
+    *const str: deserialize::FromSql<Text, DB>,
+{
+    fn from_sql(value: <DB as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let string = <*const str>::from_sql(value)?;
+        Ok(Self::from_str(unsafe { &*string })?)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl serialize::ToSql<Text, diesel::pg::Pg> for Permission
+where
+    str: serialize::ToSql<Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> serialize::Result {
+        <str as serialize::ToSql<Text, diesel::pg::Pg>>::to_sql(
+            &self.to_string(),
+            &mut out.reborrow(),
+        )
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl serialize::ToSql<Text, Sqlite> for Permission {
+    fn to_sql(&self, out: &mut serialize::Output<'_, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(serialize::IsNull::No)
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::str::FromStr;
+
+use diesel::backend::Backend;
+use diesel::sql_types::Text;
+#[cfg(feature = "sqlite")]
+use diesel::sqlite::Sqlite;
+use diesel::{deserialize, serialize};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, AsExpression, FromSqlRow)]
+#[allow(clippy::upper_case_acronyms)]
+#[diesel(sql_type = diesel::sql_types::Text)]
+pub enum RoamingRole {
+    // Forwarding Network Server (visited network).
+    FNS,
+    // Serving Network Server.
+    SNS,
+    // Home Network Server.
+    HNS,
+}
+
+impl fmt::Display for RoamingRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<backend::Role> for RoamingRole {
+    fn from(value: backend::Role) -> Self {
+        match value {
+            backend::Role::FNS => RoamingRole::FNS,
+            backend::Role::SNS => RoamingRole::SNS,
+            backend::Role::HNS => RoamingRole::HNS,
+        }
+    }
+}
+
+impl From<RoamingRole> for backend::Role {
+    fn from(value: RoamingRole) -> Self {
+        match value {
+            RoamingRole::FNS => backend::Role::FNS,
+            RoamingRole::SNS => backend::Role::SNS,
+            RoamingRole::HNS => backend::Role::HNS,
+        }
+    }
+}
+
+impl<DB> deserialize::FromSql<Text, DB> for RoamingRole
+where
+    DB: Backend,
+    *const str: deserialize::FromSql<Text, DB>,
+{
+    fn from_sql(value: <DB as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        let string = <*const str>::from_sql(value)?;
+        Ok(Self::from_str(unsafe { &*string })?)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl serialize::ToSql<Text, diesel::pg::Pg> for RoamingRole
+where
+    str: serialize::ToSql<Text, diesel::pg::Pg>,
+{
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> serialize::Result {
+        <str as serialize::ToSql<Text, diesel::pg::Pg>>::to_sql(
+            &self.to_string(),
+            &mut out.reborrow(),
+        )
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl serialize::ToSql<Text, Sqlite> for RoamingRole {
+    fn to_sql(&self, out: &mut serialize::Output<'_, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.to_string());
+        Ok(serialize::IsNull::No)
+    }
+}
+
+impl FromStr for RoamingRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "FNS" => RoamingRole::FNS,
+            "SNS" => RoamingRole::SNS,
+            "HNS" => RoamingRole::HNS,
+            _ => {
+                return Err(anyhow!("Unexpected RoamingRole: {}", s));
+            }
+        })
+    }
+}
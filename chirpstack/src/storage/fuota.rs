@@ -11,12 +11,22 @@ use validator::Validate;
 use crate::config;
 use crate::storage::error::Error;
 use crate::storage::schema::{
-    application, device, fuota_deployment, fuota_deployment_device, fuota_deployment_gateway,
-    fuota_deployment_job, gateway, tenant,
+    application, device, fuota_deployment, fuota_deployment_delta, fuota_deployment_device,
+    fuota_deployment_gateway, fuota_deployment_job, gateway, tenant,
 };
 use crate::storage::{self, db_transaction, device_profile, fields, get_async_db_conn};
 use lrwn::{AES128Key, DevAddr, EUI64};
 
+// The fragment index and number of fragments are encoded as 14 bit values.
+const MAX_FRAGMENTS: usize = (1 << 14) - 1;
+
+// Percentage of additional fragments needed by the devices to decode the fragmented payload.
+const FRAGMENT_DECODE_OVERHEAD: usize = 5;
+
+// The full image uses fragmentation-session index 0, the delta images use the remaining
+// fragmentation-session indices (1 - 3).
+const MAX_DELTAS: usize = 3;
+
 #[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq, Validate)]
 #[diesel(table_name = fuota_deployment)]
 pub struct FuotaDeployment {
@@ -48,6 +58,8 @@ pub struct FuotaDeployment {
     pub request_fragmentation_session_status: fields::RequestFragmentationSessionStatus,
     pub payload: Vec<u8>,
     pub on_complete_set_device_tags: fields::KeyValue,
+    #[validate(length(max = 100))]
+    pub payload_version: String,
 }
 
 impl Default for FuotaDeployment {
@@ -84,6 +96,7 @@ impl Default for FuotaDeployment {
                 fields::RequestFragmentationSessionStatus::NoRequest,
             payload: Vec::new(),
             on_complete_set_device_tags: fields::KeyValue::new(HashMap::new()),
+            payload_version: "".into(),
         }
     }
 }
@@ -110,6 +123,12 @@ pub struct FuotaDeploymentDevice {
     pub frag_session_setup_completed_at: Option<DateTime<Utc>>,
     pub frag_status_completed_at: Option<DateTime<Utc>>,
     pub error_msg: String,
+    // Firmware version of the device before the deployment.
+    pub base_version: String,
+    // Firmware version to which the device is updated.
+    pub target_version: String,
+    // The deployment contains delta images, but none for the base version of the device.
+    pub full_image_required: bool,
 }
 
 impl Default for FuotaDeploymentDevice {
@@ -126,10 +145,63 @@ impl Default for FuotaDeploymentDevice {
             frag_session_setup_completed_at: None,
             frag_status_completed_at: None,
             error_msg: "".into(),
+            base_version: "".into(),
+            target_version: "".into(),
+            full_image_required: false,
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct FuotaDeploymentDeviceCounts {
+    pub total: i64,
+    // Devices that completed the deployment without error.
+    pub completed: i64,
+    // Devices for which the deployment failed (e.g. timeout).
+    pub failed: i64,
+    pub pending: i64,
+}
+
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = fuota_deployment_delta)]
+pub struct FuotaDeploymentDelta {
+    pub fuota_deployment_id: fields::Uuid,
+    // Firmware version to which the delta image applies.
+    pub base_version: String,
+    pub created_at: DateTime<Utc>,
+    pub payload: Vec<u8>,
+}
+
+impl Default for FuotaDeploymentDelta {
+    fn default() -> Self {
+        Self {
+            fuota_deployment_id: Uuid::nil().into(),
+            base_version: "".into(),
+            created_at: Utc::now(),
+            payload: Vec::new(),
         }
     }
 }
 
+// Image that is sent to the devices of the FUOTA deployment, either the full image or a delta
+// image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuotaArtifact {
+    pub frag_index: u8,
+    // Base version to which the delta image applies, this is empty for the full image.
+    pub base_version: String,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentationParameters {
+    pub fragment_size: usize,
+    pub fragment_count: usize,
+    // Number of redundant fragments.
+    pub redundancy_count: usize,
+    pub redundancy_percentage: usize,
+}
+
 #[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
 #[diesel(table_name = fuota_deployment_gateway)]
 pub struct FuotaDeploymentGateway {
@@ -240,6 +312,7 @@ pub async fn update_deployment(d: FuotaDeployment) -> Result<FuotaDeployment, Er
                 .eq(&d.request_fragmentation_session_status),
             fuota_deployment::payload.eq(&d.payload),
             fuota_deployment::on_complete_set_device_tags.eq(&d.on_complete_set_device_tags),
+            fuota_deployment::payload_version.eq(&d.payload_version),
         ))
         .get_result(&mut get_async_db_conn().await?)
         .await
@@ -292,7 +365,19 @@ pub async fn list_deployments(
         .map_err(|e| Error::from_diesel(e, "".into()))
 }
 
-pub async fn add_devices(fuota_deployment_id: Uuid, dev_euis: Vec<EUI64>) -> Result<(), Error> {
+// Adds the given devices to the FUOTA deployment. The base version is the current firmware version
+// of the devices, which is used to select the delta image (if any).
+pub async fn add_devices(
+    fuota_deployment_id: Uuid,
+    dev_euis: Vec<EUI64>,
+    base_version: &str,
+) -> Result<(), Error> {
+    if base_version.len() > 100 {
+        return Err(Error::Validation(
+            "The base version must not exceed 100 characters".into(),
+        ));
+    }
+
     let mut errors = Vec::new();
 
     let dev_euis_filtered: Vec<EUI64> = device::dsl::device
@@ -320,6 +405,7 @@ pub async fn add_devices(fuota_deployment_id: Uuid, dev_euis: Vec<EUI64>) -> Res
             .values(&FuotaDeploymentDevice {
                 fuota_deployment_id: fuota_deployment_id.into(),
                 dev_eui,
+                base_version: base_version.to_string(),
                 ..Default::default()
             })
             .execute(&mut get_async_db_conn().await?)
@@ -386,6 +472,8 @@ pub async fn update_device(d: FuotaDeploymentDevice) -> Result<FuotaDeploymentDe
             .eq(&d.frag_session_setup_completed_at),
         fuota_deployment_device::frag_status_completed_at.eq(&d.frag_status_completed_at),
         fuota_deployment_device::error_msg.eq(&d.error_msg),
+        fuota_deployment_device::target_version.eq(&d.target_version),
+        fuota_deployment_device::full_image_required.eq(&d.full_image_required),
     ))
     .get_result(&mut get_async_db_conn().await?)
     .await
@@ -423,13 +511,51 @@ pub async fn get_device_count(fuota_deployment_id: Uuid) -> Result<i64, Error> {
         .map_err(|e| Error::from_diesel(e, "".into()))
 }
 
+// Returns the number of devices of the FUOTA deployment by completion state.
+pub async fn get_device_counts(
+    fuota_deployment_id: Uuid,
+) -> Result<FuotaDeploymentDeviceCounts, Error> {
+    let fuota_deployment_id = fields::Uuid::from(fuota_deployment_id);
+    let mut c = get_async_db_conn().await?;
+
+    let total: i64 = fuota_deployment_device::dsl::fuota_deployment_device
+        .select(dsl::count_star())
+        .filter(fuota_deployment_device::dsl::fuota_deployment_id.eq(&fuota_deployment_id))
+        .first(&mut c)
+        .await?;
+
+    let completed: i64 = fuota_deployment_device::dsl::fuota_deployment_device
+        .select(dsl::count_star())
+        .filter(fuota_deployment_device::dsl::fuota_deployment_id.eq(&fuota_deployment_id))
+        .filter(fuota_deployment_device::dsl::completed_at.is_not_null())
+        .filter(fuota_deployment_device::dsl::error_msg.eq(""))
+        .first(&mut c)
+        .await?;
+
+    let failed: i64 = fuota_deployment_device::dsl::fuota_deployment_device
+        .select(dsl::count_star())
+        .filter(fuota_deployment_device::dsl::fuota_deployment_id.eq(&fuota_deployment_id))
+        .filter(fuota_deployment_device::dsl::error_msg.ne(""))
+        .first(&mut c)
+        .await?;
+
+    Ok(FuotaDeploymentDeviceCounts {
+        total,
+        completed,
+        failed,
+        pending: total - completed - failed,
+    })
+}
+
+// This sets the timeout error for the devices that did not complete the given step(s) and returns
+// the updated devices.
 pub async fn set_device_timeout_error(
     fuota_deployment_id: Uuid,
     mc_group_setup_timeout: bool,
     mc_session_timeout: bool,
     frag_session_setup_timeout: bool,
     frag_status_timeout: bool,
-) -> Result<()> {
+) -> Result<Vec<FuotaDeploymentDevice>> {
     let fuota_deployment_id = fields::Uuid::from(fuota_deployment_id);
 
     let mut error_msg = String::new();
@@ -468,9 +594,9 @@ pub async fn set_device_timeout_error(
         q = q.filter(fuota_deployment_device::dsl::frag_status_completed_at.is_null());
     }
 
-    q.execute(&mut get_async_db_conn().await?).await?;
+    let devices = q.get_results(&mut get_async_db_conn().await?).await?;
 
-    Ok(())
+    Ok(devices)
 }
 
 pub async fn set_device_completed(
@@ -508,6 +634,135 @@ pub async fn set_device_completed(
     Ok(())
 }
 
+// Replaces the delta images of the FUOTA deployment with the given delta images.
+pub async fn set_deltas(
+    fuota_deployment_id: Uuid,
+    deltas: Vec<FuotaDeploymentDelta>,
+) -> Result<Vec<FuotaDeploymentDelta>, Error> {
+    validate_deltas(&deltas)?;
+
+    let fuota_deployment_id = fields::Uuid::from(fuota_deployment_id);
+    let mut c = get_async_db_conn().await?;
+    let deltas =
+        db_transaction::<Vec<FuotaDeploymentDelta>, Error, _>(&mut c, |c| {
+            Box::pin(async move {
+                diesel::delete(fuota_deployment_delta::table.filter(
+                    fuota_deployment_delta::dsl::fuota_deployment_id.eq(&fuota_deployment_id),
+                ))
+                .execute(c)
+                .await?;
+
+                let mut out = Vec::with_capacity(deltas.len());
+                for delta in deltas {
+                    let delta: FuotaDeploymentDelta =
+                        diesel::insert_into(fuota_deployment_delta::table)
+                            .values(&FuotaDeploymentDelta {
+                                fuota_deployment_id,
+                                ..delta
+                            })
+                            .get_result(c)
+                            .await
+                            .map_err(|e| Error::from_diesel(e, fuota_deployment_id.to_string()))?;
+                    out.push(delta);
+                }
+
+                Ok(out)
+            })
+        })
+        .await?;
+
+    info!(fuota_deployment_id = %fuota_deployment_id, count = deltas.len(), "FUOTA deployment delta images set");
+    Ok(deltas)
+}
+
+pub fn validate_deltas(deltas: &[FuotaDeploymentDelta]) -> Result<(), Error> {
+    if deltas.len() > MAX_DELTAS {
+        return Err(Error::Validation(format!(
+            "A FUOTA deployment can have max. {} delta images",
+            MAX_DELTAS
+        )));
+    }
+
+    for (i, delta) in deltas.iter().enumerate() {
+        if delta.base_version.is_empty() || delta.base_version.len() > 100 {
+            return Err(Error::Validation(
+                "The base version of a delta image must be between 1 - 100 characters".into(),
+            ));
+        }
+        if delta.payload.is_empty() {
+            return Err(Error::Validation(
+                "The payload of a delta image must not be empty".into(),
+            ));
+        }
+        if deltas[..i]
+            .iter()
+            .any(|d| d.base_version == delta.base_version)
+        {
+            return Err(Error::Validation(format!(
+                "Duplicate delta image base version: {}",
+                delta.base_version
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn get_deltas(fuota_deployment_id: Uuid) -> Result<Vec<FuotaDeploymentDelta>, Error> {
+    fuota_deployment_delta::dsl::fuota_deployment_delta
+        .filter(
+            fuota_deployment_delta::dsl::fuota_deployment_id
+                .eq(fields::Uuid::from(fuota_deployment_id)),
+        )
+        .order_by(fuota_deployment_delta::dsl::base_version)
+        .load(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, fuota_deployment_id.to_string()))
+}
+
+// Returns the images to send for the FUOTA deployment. The full image (if set) uses
+// fragmentation-session index 0, the delta images use the following indices, in the order
+// of their base version.
+pub fn get_artifacts(d: &FuotaDeployment, deltas: &[FuotaDeploymentDelta]) -> Vec<FuotaArtifact> {
+    let mut deltas: Vec<&FuotaDeploymentDelta> = deltas.iter().collect();
+    deltas.sort_by(|a, b| a.base_version.cmp(&b.base_version));
+
+    let mut out = Vec::new();
+    if !d.payload.is_empty() {
+        out.push(FuotaArtifact {
+            frag_index: 0,
+            base_version: "".into(),
+            payload: d.payload.clone(),
+        });
+    }
+
+    for (i, delta) in deltas.iter().take(MAX_DELTAS).enumerate() {
+        out.push(FuotaArtifact {
+            frag_index: (i + 1) as u8,
+            base_version: delta.base_version.clone(),
+            payload: delta.payload.clone(),
+        });
+    }
+
+    out
+}
+
+// Returns the image for a device with the given base version. This returns the delta image for
+// the base version if available, else the full image. None is returned in case the device needs
+// the full image, but the FUOTA deployment does not contain it.
+pub fn select_artifact<'a>(
+    artifacts: &'a [FuotaArtifact],
+    base_version: &str,
+) -> Option<&'a FuotaArtifact> {
+    if !base_version.is_empty() {
+        if let Some(a) = artifacts.iter().find(|a| a.base_version == base_version) {
+            return Some(a);
+        }
+    }
+
+    artifacts.iter().find(|a| a.base_version.is_empty())
+}
+
 pub async fn add_gateways(fuota_deployment_id: Uuid, gateway_ids: Vec<EUI64>) -> Result<(), Error> {
     let mut errors = Vec::new();
 
@@ -739,28 +994,113 @@ pub async fn get_schedulable_jobs(limit: usize) -> Result<Vec<FuotaDeploymentJob
     .context("Get FUOTA jobs")
 }
 
-pub async fn get_max_fragment_size(d: &FuotaDeployment) -> Result<usize> {
+pub async fn get_max_fragment_size(
+    d: &FuotaDeployment,
+    deltas: &[FuotaDeploymentDelta],
+) -> Result<usize> {
     let dp = device_profile::get(&d.device_profile_id).await?;
-    let region_conf = lrwn::region::get(dp.region, false, false);
-    let max_pl_size = region_conf
-        .get_max_payload_size(dp.mac_version, dp.reg_params_revision, d.multicast_dr as u8)?
-        .n
-        - 3;
+    let max_pl_size = get_dr_max_fragment_size(&dp, d.multicast_dr as u8)?;
+
+    // The fragment size is shared by the full and delta images.
+    let payload_len = get_artifacts(d, deltas)
+        .iter()
+        .map(|a| a.payload.len())
+        .max()
+        .unwrap_or_default();
 
-    Ok(if max_pl_size > d.payload.len() {
-        d.payload.len()
+    Ok(if max_pl_size > payload_len {
+        payload_len
     } else {
         max_pl_size
     })
 }
 
-pub fn get_multicast_timeout(d: &FuotaDeployment) -> Result<usize> {
+// Returns the fragmentation parameters for the given payload size, such that the payload can be
+// reconstructed by the devices when the expected loss rate (percentage) of fragments is lost.
+pub async fn get_fragmentation_parameters(
+    device_profile_id: &Uuid,
+    multicast_dr: u8,
+    payload_size: usize,
+    expected_loss_rate: u8,
+) -> Result<FragmentationParameters> {
+    let dp = device_profile::get(device_profile_id).await?;
+    let max_fragment_size = get_dr_max_fragment_size(&dp, multicast_dr)?;
+    calculate_fragmentation_parameters(payload_size, max_fragment_size, expected_loss_rate)
+}
+
+pub fn calculate_fragmentation_parameters(
+    payload_size: usize,
+    max_fragment_size: usize,
+    expected_loss_rate: u8,
+) -> Result<FragmentationParameters> {
+    if payload_size == 0 {
+        return Err(anyhow!("Payload size must be greater than 0"));
+    }
+    if max_fragment_size == 0 {
+        return Err(anyhow!("Max. fragment size must be greater than 0"));
+    }
+    if expected_loss_rate >= 100 {
+        return Err(anyhow!("Expected loss rate must be less than 100"));
+    }
+
+    // The fragment size is reduced to the smallest size which results in the same number of
+    // fragments, to minimize the padding of the last fragment.
+    let fragment_count = payload_size.div_ceil(max_fragment_size);
+    let fragment_size = payload_size.div_ceil(fragment_count);
+
+    // Besides the lost fragments, the devices need a few additional fragments to decode the
+    // payload.
+    let expected_loss_rate = expected_loss_rate as usize;
+    let required = fragment_count + (fragment_count * FRAGMENT_DECODE_OVERHEAD).div_ceil(100);
+    let total = (required * 100).div_ceil(100 - expected_loss_rate);
+    let redundancy_percentage = ((total - fragment_count) * 100).div_ceil(fragment_count);
+
+    // The redundancy is re-calculated from the percentage, as this is what is stored in the
+    // deployment.
+    let redundancy_count = (fragment_count * redundancy_percentage).div_ceil(100);
+
+    if fragment_count + redundancy_count > MAX_FRAGMENTS {
+        return Err(anyhow!(
+            "Max. number of fragments exceeded (fragments: {}, redundancy: {}, max: {})",
+            fragment_count,
+            redundancy_count,
+            MAX_FRAGMENTS
+        ));
+    }
+
+    Ok(FragmentationParameters {
+        fragment_size,
+        fragment_count,
+        redundancy_count,
+        redundancy_percentage,
+    })
+}
+
+fn get_dr_max_fragment_size(dp: &device_profile::DeviceProfile, dr: u8) -> Result<usize> {
+    let region_conf = lrwn::region::get(dp.region, false, false);
+
+    // The data-fragment header (FragIndex) takes 3 bytes.
+    Ok(region_conf
+        .get_max_payload_size(dp.mac_version, dp.reg_params_revision, dr)?
+        .n
+        .saturating_sub(3))
+}
+
+pub fn get_multicast_timeout(
+    d: &FuotaDeployment,
+    deltas: &[FuotaDeploymentDelta],
+) -> Result<usize> {
     let conf = config::get();
 
-    let fragments = (d.payload.len() as f32 / d.fragmentation_fragment_size as f32).ceil() as usize;
-    let redundancy =
-        (fragments as f32 * d.fragmentation_redundancy_percentage as f32 / 100.0).ceil() as usize;
-    let total_fragments = fragments + redundancy;
+    // The full and delta images are sent within the same multicast-session.
+    let mut total_fragments = 0;
+    for a in get_artifacts(d, deltas) {
+        let fragments =
+            (a.payload.len() as f32 / d.fragmentation_fragment_size as f32).ceil() as usize;
+        let redundancy = (fragments as f32 * d.fragmentation_redundancy_percentage as f32 / 100.0)
+            .ceil() as usize;
+        total_fragments += fragments + redundancy;
+    }
 
     match d.multicast_group_type.as_ref() {
         "B" => {
@@ -961,13 +1301,19 @@ mod test {
         .unwrap();
 
         // can't add devices from multiple device-profiles
-        assert!(add_devices(d.id.into(), vec![dev2.dev_eui]).await.is_err());
+        assert!(add_devices(d.id.into(), vec![dev2.dev_eui], "")
+            .await
+            .is_err());
 
         // can't add devices from other applications
-        assert!(add_devices(d.id.into(), vec![dev3.dev_eui]).await.is_err());
+        assert!(add_devices(d.id.into(), vec![dev3.dev_eui], "")
+            .await
+            .is_err());
 
         // add devices
-        add_devices(d.id.into(), vec![dev.dev_eui]).await.unwrap();
+        add_devices(d.id.into(), vec![dev.dev_eui], "1.0.0")
+            .await
+            .unwrap();
 
         // get device count
         assert_eq!(1, get_device_count(d.id.into()).await.unwrap());
@@ -977,6 +1323,7 @@ mod test {
         assert_eq!(1, devices.len());
         assert_eq!(dev.dev_eui, devices[0].dev_eui);
         assert_eq!(d.id, devices[0].fuota_deployment_id);
+        assert_eq!("1.0.0", devices[0].base_version);
 
         // get device
         let mut devices = get_devices(d.id.into(), 1, 0).await.unwrap();
@@ -984,6 +1331,17 @@ mod test {
         let fuota_d = update_device(devices[0].clone()).await.unwrap();
         assert_eq!("Error: kaboom", fuota_d.error_msg);
 
+        // get device counts
+        assert_eq!(
+            FuotaDeploymentDeviceCounts {
+                total: 1,
+                completed: 0,
+                failed: 1,
+                pending: 0,
+            },
+            get_device_counts(d.id.into()).await.unwrap()
+        );
+
         // remove devices
         remove_devices(d.id.into(), vec![dev.dev_eui])
             .await
@@ -991,6 +1349,138 @@ mod test {
         assert_eq!(0, get_device_count(d.id.into()).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_fuota_deltas() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            tenant_id: t.id,
+            name: "test-dp".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let d = create_deployment(FuotaDeployment {
+            application_id: app.id,
+            device_profile_id: dp.id,
+            name: "test-fuota-deployment".into(),
+            payload: vec![1, 2, 3, 4],
+            payload_version: "2.0.0".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // invalid deltas
+        assert!(set_deltas(
+            d.id.into(),
+            vec![FuotaDeploymentDelta {
+                base_version: "".into(),
+                payload: vec![1],
+                ..Default::default()
+            }],
+        )
+        .await
+        .is_err());
+        assert!(set_deltas(
+            d.id.into(),
+            vec![
+                FuotaDeploymentDelta {
+                    base_version: "1.0.0".into(),
+                    payload: vec![1],
+                    ..Default::default()
+                },
+                FuotaDeploymentDelta {
+                    base_version: "1.0.0".into(),
+                    payload: vec![2],
+                    ..Default::default()
+                }
+            ],
+        )
+        .await
+        .is_err());
+
+        // set deltas
+        set_deltas(
+            d.id.into(),
+            vec![
+                FuotaDeploymentDelta {
+                    base_version: "1.1.0".into(),
+                    payload: vec![2],
+                    ..Default::default()
+                },
+                FuotaDeploymentDelta {
+                    base_version: "1.0.0".into(),
+                    payload: vec![1],
+                    ..Default::default()
+                },
+            ],
+        )
+        .await
+        .unwrap();
+        let deltas = get_deltas(d.id.into()).await.unwrap();
+        assert_eq!(2, deltas.len());
+        assert_eq!("1.0.0", deltas[0].base_version);
+        assert_eq!(d.id, deltas[0].fuota_deployment_id);
+
+        // artifacts
+        let artifacts = get_artifacts(&d, &deltas);
+        assert_eq!(
+            vec![
+                FuotaArtifact {
+                    frag_index: 0,
+                    base_version: "".into(),
+                    payload: vec![1, 2, 3, 4],
+                },
+                FuotaArtifact {
+                    frag_index: 1,
+                    base_version: "1.0.0".into(),
+                    payload: vec![1],
+                },
+                FuotaArtifact {
+                    frag_index: 2,
+                    base_version: "1.1.0".into(),
+                    payload: vec![2],
+                },
+            ],
+            artifacts
+        );
+        assert_eq!(2, select_artifact(&artifacts, "1.1.0").unwrap().frag_index);
+        assert_eq!(0, select_artifact(&artifacts, "0.9.0").unwrap().frag_index);
+        assert_eq!(0, select_artifact(&artifacts, "").unwrap().frag_index);
+
+        // without full image
+        let artifacts = get_artifacts(
+            &FuotaDeployment {
+                payload: vec![],
+                ..d.clone()
+            },
+            &deltas,
+        );
+        assert_eq!(1, select_artifact(&artifacts, "1.0.0").unwrap().frag_index);
+        assert!(select_artifact(&artifacts, "0.9.0").is_none());
+
+        // replace deltas
+        set_deltas(d.id.into(), vec![]).await.unwrap();
+        assert!(get_deltas(d.id.into()).await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_fuota_gateways() {
         let _guard = test::prepare().await;
@@ -1202,7 +1692,51 @@ mod test {
         .await
         .unwrap();
 
-        assert_eq!(239, get_max_fragment_size(&d).await.unwrap());
+        assert_eq!(239, get_max_fragment_size(&d, &[]).await.unwrap());
+    }
+
+    #[test]
+    fn test_calculate_fragmentation_parameters() {
+        // no loss
+        assert_eq!(
+            FragmentationParameters {
+                fragment_size: 200,
+                fragment_count: 5,
+                redundancy_count: 1,
+                redundancy_percentage: 20,
+            },
+            calculate_fragmentation_parameters(1000, 239, 0).unwrap()
+        );
+
+        // 20% loss
+        assert_eq!(
+            FragmentationParameters {
+                fragment_size: 200,
+                fragment_count: 5,
+                redundancy_count: 3,
+                redundancy_percentage: 60,
+            },
+            calculate_fragmentation_parameters(1000, 239, 20).unwrap()
+        );
+
+        // the fragment size is reduced to minimize padding
+        assert_eq!(
+            FragmentationParameters {
+                fragment_size: 112,
+                fragment_count: 9,
+                redundancy_count: 4,
+                redundancy_percentage: 34,
+            },
+            calculate_fragmentation_parameters(1000, 120, 10).unwrap()
+        );
+
+        // invalid input
+        assert!(calculate_fragmentation_parameters(0, 239, 0).is_err());
+        assert!(calculate_fragmentation_parameters(1000, 0, 0).is_err());
+        assert!(calculate_fragmentation_parameters(1000, 239, 100).is_err());
+
+        // max. number of fragments exceeded
+        assert!(calculate_fragmentation_parameters(100_000, 8, 50).is_err());
     }
 
     #[tokio::test]
@@ -1284,7 +1818,7 @@ mod test {
 
         for t in &tests {
             println!("> {}", t.name);
-            let res = get_multicast_timeout(&t.deployment);
+            let res = get_multicast_timeout(&t.deployment, &[]);
             if let Some(err_str) = &t.expected_error {
                 assert!(res.is_err());
                 assert_eq!(err_str, &res.err().unwrap().to_string());
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::time::Instant;
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -10,11 +11,11 @@ use uuid::Uuid;
 use lrwn::{DevAddr, EUI64};
 
 use super::schema::{gateway, multicast_group_gateway, relay_gateway, tenant};
-use super::{db_transaction, error::Error, fields, get_async_db_conn};
+use super::{cache, db_transaction, error::Error, fields, get_async_db_conn};
 
 pub type RelayId = DevAddr;
 
-#[derive(Queryable, Insertable, PartialEq, Debug)]
+#[derive(Queryable, QueryableByName, Insertable, PartialEq, Debug, Clone)]
 #[diesel(table_name = gateway)]
 pub struct Gateway {
     pub gateway_id: EUI64,
@@ -31,6 +32,13 @@ pub struct Gateway {
     pub tls_certificate: Option<Vec<u8>>,
     pub tags: fields::KeyValue,
     pub properties: fields::KeyValue,
+    pub offline_at: Option<DateTime<Utc>>,
+    pub model: String,
+    pub hal_version: String,
+    pub concentratord_version: String,
+    pub gateway_bridge_version: String,
+    pub antenna_gain: f32,
+    pub cable_loss: f32,
 }
 
 impl Gateway {
@@ -38,6 +46,9 @@ impl Gateway {
         if self.name.is_empty() {
             return Err(Error::Validation("name is not set".into()));
         }
+        if self.cable_loss < 0.0 {
+            return Err(Error::Validation("cable_loss must not be negative".into()));
+        }
         Ok(())
     }
 }
@@ -61,6 +72,13 @@ impl Default for Gateway {
             stats_interval_secs: 30,
             tags: fields::KeyValue::new(HashMap::new()),
             properties: fields::KeyValue::new(HashMap::new()),
+            offline_at: None,
+            model: "".into(),
+            hal_version: "".into(),
+            concentratord_version: "".into(),
+            gateway_bridge_version: "".into(),
+            antenna_gain: 0.0,
+            cable_loss: 0.0,
         }
     }
 }
@@ -74,6 +92,10 @@ pub struct GatewayChangeset {
     pub longitude: Option<f64>,
     pub altitude: Option<f32>,
     pub tls_certificate: Option<Option<Vec<u8>>>,
+    pub model: Option<String>,
+    pub hal_version: Option<String>,
+    pub concentratord_version: Option<String>,
+    pub gateway_bridge_version: Option<String>,
 }
 
 #[derive(Queryable, PartialEq, Debug)]
@@ -90,6 +112,10 @@ pub struct GatewayListItem {
     pub altitude: f32,
     pub properties: fields::KeyValue,
     pub stats_interval_secs: i32,
+    pub model: String,
+    pub hal_version: String,
+    pub concentratord_version: String,
+    pub gateway_bridge_version: String,
 }
 
 #[derive(Queryable, PartialEq, Debug)]
@@ -101,6 +127,14 @@ pub struct GatewayMeta {
     pub altitude: f32,
     pub is_private_up: bool,
     pub is_private_down: bool,
+    pub antenna_gain: f32,
+    pub cable_loss: f32,
+}
+
+#[derive(Queryable, PartialEq, Debug, Clone, Copy)]
+pub struct GatewayAntenna {
+    pub antenna_gain: f32,
+    pub cable_loss: f32,
 }
 
 #[derive(Default, Clone)]
@@ -108,6 +142,10 @@ pub struct Filters {
     pub tenant_id: Option<Uuid>,
     pub multicast_group_id: Option<Uuid>,
     pub search: Option<String>,
+    pub model: Option<String>,
+    pub hal_version: Option<String>,
+    pub concentratord_version: Option<String>,
+    pub gateway_bridge_version: Option<String>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -140,6 +178,8 @@ pub struct RelayGateway {
     pub description: String,
     pub stats_interval_secs: i32,
     pub region_config_id: String,
+    pub border_gateway_id: Option<EUI64>,
+    pub relay_path: fields::RelayPath,
 }
 
 impl Default for RelayGateway {
@@ -156,6 +196,8 @@ impl Default for RelayGateway {
             description: "".into(),
             stats_interval_secs: 900,
             region_config_id: "".into(),
+            border_gateway_id: None,
+            relay_path: fields::RelayPath::default(),
         }
     }
 }
@@ -163,6 +205,7 @@ impl Default for RelayGateway {
 #[derive(Default, Clone)]
 pub struct RelayGatewayFilters {
     pub tenant_id: Option<Uuid>,
+    pub border_gateway_id: Option<EUI64>,
 }
 
 #[derive(Queryable, PartialEq, Debug)]
@@ -176,6 +219,8 @@ pub struct RelayGatewayListItem {
     pub description: String,
     pub stats_interval_secs: i32,
     pub region_config_id: String,
+    pub border_gateway_id: Option<EUI64>,
+    pub relay_path: fields::RelayPath,
 }
 
 pub async fn create(gw: Gateway) -> Result<Gateway, Error> {
@@ -245,10 +290,13 @@ pub async fn update(gw: Gateway) -> Result<Gateway, Error> {
             gateway::altitude.eq(&gw.altitude),
             gateway::stats_interval_secs.eq(&gw.stats_interval_secs),
             gateway::tags.eq(&gw.tags),
+            gateway::antenna_gain.eq(&gw.antenna_gain),
+            gateway::cable_loss.eq(&gw.cable_loss),
         ))
         .get_result(&mut get_async_db_conn().await?)
         .await
         .map_err(|e| Error::from_diesel(e, gw.gateway_id.to_string()))?;
+    cache::invalidate(cache::Kind::GatewayAntenna, &gw.gateway_id).await;
     info!(
         gateway_id = %gw.gateway_id,
         "Gateway updated"
@@ -267,6 +315,36 @@ pub async fn partial_update(gateway_id: EUI64, gw: &GatewayChangeset) -> Result<
     Ok(gw)
 }
 
+// Partially updates the given gateways within a single transaction. Gateways that do not
+// exist are not included in the returned list.
+pub async fn partial_update_batch(
+    items: &[(EUI64, GatewayChangeset)],
+) -> Result<Vec<Gateway>, Error> {
+    let mut c = get_async_db_conn().await?;
+    let gws: Vec<Gateway> = db_transaction::<Vec<Gateway>, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let mut out: Vec<Gateway> = Vec::with_capacity(items.len());
+
+            for (gateway_id, gw) in items {
+                if let Some(gw) = diesel::update(gateway::dsl::gateway.find(gateway_id))
+                    .set(gw)
+                    .get_result::<Gateway>(c)
+                    .await
+                    .optional()?
+                {
+                    out.push(gw);
+                }
+            }
+
+            Ok(out)
+        })
+    })
+    .await?;
+
+    info!(count = gws.len(), "Gateways partially updated");
+    Ok(gws)
+}
+
 pub async fn delete(gateway_id: &EUI64) -> Result<(), Error> {
     let ra = diesel::delete(gateway::dsl::gateway.find(&gateway_id))
         .execute(&mut get_async_db_conn().await?)
@@ -274,6 +352,7 @@ pub async fn delete(gateway_id: &EUI64) -> Result<(), Error> {
     if ra == 0 {
         return Err(Error::NotFound(gateway_id.to_string()));
     }
+    cache::invalidate(cache::Kind::GatewayAntenna, gateway_id).await;
     info!(
         gateway_id = %gateway_id,
         "Gateway deleted"
@@ -310,6 +389,22 @@ pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
         }
     }
 
+    if let Some(model) = &filters.model {
+        q = q.filter(gateway::dsl::model.eq(model));
+    }
+
+    if let Some(hal_version) = &filters.hal_version {
+        q = q.filter(gateway::dsl::hal_version.eq(hal_version));
+    }
+
+    if let Some(concentratord_version) = &filters.concentratord_version {
+        q = q.filter(gateway::dsl::concentratord_version.eq(concentratord_version));
+    }
+
+    if let Some(gateway_bridge_version) = &filters.gateway_bridge_version {
+        q = q.filter(gateway::dsl::gateway_bridge_version.eq(gateway_bridge_version));
+    }
+
     Ok(q.first(&mut get_async_db_conn().await?).await?)
 }
 
@@ -335,6 +430,10 @@ pub async fn list(
             gateway::altitude,
             gateway::properties,
             gateway::stats_interval_secs,
+            gateway::model,
+            gateway::hal_version,
+            gateway::concentratord_version,
+            gateway::gateway_bridge_version,
         ))
         .distinct()
         .into_boxed();
@@ -361,6 +460,22 @@ pub async fn list(
         );
     }
 
+    if let Some(model) = &filters.model {
+        q = q.filter(gateway::dsl::model.eq(model));
+    }
+
+    if let Some(hal_version) = &filters.hal_version {
+        q = q.filter(gateway::dsl::hal_version.eq(hal_version));
+    }
+
+    if let Some(concentratord_version) = &filters.concentratord_version {
+        q = q.filter(gateway::dsl::concentratord_version.eq(concentratord_version));
+    }
+
+    if let Some(gateway_bridge_version) = &filters.gateway_bridge_version {
+        q = q.filter(gateway::dsl::gateway_bridge_version.eq(gateway_bridge_version));
+    }
+
     q = match order_by_desc {
         true => match order_by {
             OrderBy::Name => q.order_by(gateway::dsl::name.desc()),
@@ -397,6 +512,8 @@ pub async fn get_meta(gateway_id: &EUI64) -> Result<GatewayMeta, Error> {
             gateway::altitude,
             tenant::private_gateways_up,
             tenant::private_gateways_down,
+            gateway::antenna_gain,
+            gateway::cable_loss,
         ))
         .filter(gateway::dsl::gateway_id.eq(&gateway_id))
         .first(&mut get_async_db_conn().await?)
@@ -405,6 +522,24 @@ pub async fn get_meta(gateway_id: &EUI64) -> Result<GatewayMeta, Error> {
     Ok(meta)
 }
 
+// Returns the antenna gain and cable loss of the gateway. As these are needed for every downlink,
+// these are cached.
+pub async fn get_antenna(gateway_id: &EUI64) -> Result<GatewayAntenna, Error> {
+    if let Some(v) = cache::GATEWAY_ANTENNAS.get(gateway_id) {
+        return Ok(v);
+    }
+
+    let cached_at = Instant::now();
+    let v: GatewayAntenna = gateway::dsl::gateway
+        .select((gateway::antenna_gain, gateway::cable_loss))
+        .find(&gateway_id)
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, gateway_id.to_string()))?;
+    cache::GATEWAY_ANTENNAS.set(*gateway_id, cached_at, v);
+    Ok(v)
+}
+
 #[cfg(feature = "postgres")]
 pub async fn get_counts_by_state(tenant_id: &Option<Uuid>) -> Result<GatewayCountsByState, Error> {
     let counts: GatewayCountsByState = diesel::sql_query(r#"
@@ -435,6 +570,82 @@ pub async fn get_counts_by_state(tenant_id: &Option<Uuid>) -> Result<GatewayCoun
     Ok(counts)
 }
 
+// Marks the gateways as offline for which no stats have been received within the given number
+// of stats intervals. Only the gateways that were not yet marked offline are returned, as the
+// update is atomic, concurrent callers will never return the same gateway.
+#[cfg(feature = "postgres")]
+pub async fn set_offline(missed_stats_intervals: u32) -> Result<Vec<Gateway>, Error> {
+    let gws: Vec<Gateway> = diesel::sql_query(
+        r#"
+        update
+            gateway
+        set
+            offline_at = $1
+        where
+            offline_at is null
+            and last_seen_at is not null
+            and ($1 - make_interval(secs => stats_interval_secs * $2)) > last_seen_at
+        returning *
+    "#,
+    )
+    .bind::<fields::sql_types::Timestamptz, _>(Utc::now())
+    .bind::<diesel::sql_types::Integer, _>(missed_stats_intervals as i32)
+    .load(&mut get_async_db_conn().await?)
+    .await?;
+
+    for gw in &gws {
+        info!(gateway_id = %gw.gateway_id, "Gateway marked offline");
+    }
+
+    Ok(gws)
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn set_offline(missed_stats_intervals: u32) -> Result<Vec<Gateway>, Error> {
+    let gws: Vec<Gateway> = diesel::sql_query(
+        r#"
+        update
+            gateway
+        set
+            offline_at = ?1
+        where
+            offline_at is null
+            and last_seen_at is not null
+            and (unixepoch(?1) - unixepoch(last_seen_at)) > (stats_interval_secs * ?2)
+        returning *
+    "#,
+    )
+    .bind::<fields::sql_types::Timestamptz, _>(Utc::now())
+    .bind::<diesel::sql_types::Integer, _>(missed_stats_intervals as i32)
+    .load(&mut get_async_db_conn().await?)
+    .await?;
+
+    for gw in &gws {
+        info!(gateway_id = %gw.gateway_id, "Gateway marked offline");
+    }
+
+    Ok(gws)
+}
+
+// Clears the offline state of the given gateways. Only the gateways that were marked offline
+// are returned.
+pub async fn set_online(gateway_ids: &[EUI64]) -> Result<Vec<Gateway>, Error> {
+    let gws: Vec<Gateway> = diesel::update(
+        gateway::dsl::gateway
+            .filter(gateway::dsl::gateway_id.eq_any(gateway_ids))
+            .filter(gateway::dsl::offline_at.is_not_null()),
+    )
+    .set(gateway::offline_at.eq(None::<DateTime<Utc>>))
+    .get_results(&mut get_async_db_conn().await?)
+    .await?;
+
+    for gw in &gws {
+        info!(gateway_id = %gw.gateway_id, "Gateway marked online");
+    }
+
+    Ok(gws)
+}
+
 pub async fn create_relay_gateway(relay: RelayGateway) -> Result<RelayGateway, Error> {
     let relay: RelayGateway = diesel::insert_into(relay_gateway::table)
         .values(&relay)
@@ -466,6 +677,8 @@ pub async fn update_relay_gateway(relay: RelayGateway) -> Result<RelayGateway, E
                 relay_gateway::description.eq(&relay.description),
                 relay_gateway::stats_interval_secs.eq(&relay.stats_interval_secs),
                 relay_gateway::region_config_id.eq(&relay.region_config_id),
+                relay_gateway::border_gateway_id.eq(&relay.border_gateway_id),
+                relay_gateway::relay_path.eq(&relay.relay_path),
             ))
             .get_result(&mut get_async_db_conn().await?)
             .await
@@ -485,9 +698,21 @@ pub async fn get_relay_gateway_count(filters: &RelayGatewayFilters) -> Result<i6
         q = q.filter(relay_gateway::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
     }
 
+    if let Some(border_gateway_id) = &filters.border_gateway_id {
+        q = q.filter(relay_gateway::dsl::border_gateway_id.eq(border_gateway_id));
+    }
+
     Ok(q.first(&mut get_async_db_conn().await?).await?)
 }
 
+pub async fn get_relay_gateway_count_for_region(region_config_id: &str) -> Result<i64, Error> {
+    Ok(relay_gateway::dsl::relay_gateway
+        .select(dsl::count_star())
+        .filter(relay_gateway::dsl::region_config_id.eq(region_config_id))
+        .first(&mut get_async_db_conn().await?)
+        .await?)
+}
+
 pub async fn delete_relay_gateway(tenant_id: Uuid, relay_id: RelayId) -> Result<(), Error> {
     let ra = diesel::delete(
         relay_gateway::dsl::relay_gateway.find((fields::Uuid::from(tenant_id), &relay_id)),
@@ -519,6 +744,8 @@ pub async fn list_relay_gateways(
             relay_gateway::description,
             relay_gateway::stats_interval_secs,
             relay_gateway::region_config_id,
+            relay_gateway::border_gateway_id,
+            relay_gateway::relay_path,
         ))
         .into_boxed();
 
@@ -526,6 +753,10 @@ pub async fn list_relay_gateways(
         q = q.filter(relay_gateway::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)));
     }
 
+    if let Some(border_gateway_id) = &filters.border_gateway_id {
+        q = q.filter(relay_gateway::dsl::border_gateway_id.eq(border_gateway_id));
+    }
+
     let items = q
         .order_by(relay_gateway::dsl::name)
         .limit(limit)
@@ -615,17 +846,60 @@ pub mod test {
 
         // update
         gw.name = "updated-name".into();
+        gw.antenna_gain = 6.0;
+        gw.cable_loss = 1.5;
         gw = update(gw).await.unwrap();
         let gw_get = get(&gw.gateway_id).await.unwrap();
         assert_eq!(gw, gw_get);
 
+        // partial update (inventory)
+        gw = partial_update(
+            gw.gateway_id,
+            &GatewayChangeset {
+                model: Some("rak7268".into()),
+                concentratord_version: Some("4.4.0".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!("rak7268", gw.model);
+        assert_eq!("4.4.0", gw.concentratord_version);
+        assert_eq!("", gw.hal_version);
+
         // get count and list
         let tests = vec![
+            FilterTest {
+                filters: Filters {
+                    model: Some("rak7268".into()),
+                    concentratord_version: Some("4.4.0".into()),
+                    ..Default::default()
+                },
+                gws: vec![&gw],
+                count: 1,
+                limit: 10,
+                offset: 0,
+                order: OrderBy::Name,
+                order_by_desc: false,
+            },
+            FilterTest {
+                filters: Filters {
+                    concentratord_version: Some("4.3.0".into()),
+                    ..Default::default()
+                },
+                gws: vec![],
+                count: 0,
+                limit: 10,
+                offset: 0,
+                order: OrderBy::Name,
+                order_by_desc: false,
+            },
             FilterTest {
                 filters: Filters {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -639,6 +913,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: Some("uup".into()),
+                    ..Default::default()
                 },
                 gws: vec![],
                 count: 0,
@@ -652,6 +927,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: Some("upd".into()),
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -665,6 +941,7 @@ pub mod test {
                     tenant_id: Some(gw.tenant_id.into()),
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -678,6 +955,7 @@ pub mod test {
                     tenant_id: Some(Uuid::new_v4()),
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![],
                 count: 0,
@@ -691,6 +969,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: Some(mg.id.into()),
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -704,6 +983,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: Some(Uuid::new_v4()),
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![],
                 count: 0,
@@ -717,6 +997,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -730,6 +1011,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -743,6 +1025,7 @@ pub mod test {
                     tenant_id: None,
                     multicast_group_id: None,
                     search: None,
+                    ..Default::default()
                 },
                 gws: vec![&gw],
                 count: 1,
@@ -809,6 +1092,12 @@ pub mod test {
         // update
         relay.name = "updated-relay".into();
         relay.region_config_id = "us915_0".into();
+        relay.border_gateway_id = Some(gw.gateway_id);
+        relay.relay_path = fields::RelayPath::new(vec![fields::RelayPathItem {
+            relay_id: "01020304".into(),
+            rssi: -60,
+            snr: 6,
+        }]);
         relay = update_relay_gateway(relay).await.unwrap();
         let relay_get = get_relay_gateway(relay.tenant_id.into(), relay.relay_id)
             .await
@@ -818,7 +1107,10 @@ pub mod test {
         // test count and list
         let tests = vec![
             RelayGatewayFilterTest {
-                filters: RelayGatewayFilters { tenant_id: None },
+                filters: RelayGatewayFilters {
+                    tenant_id: None,
+                    border_gateway_id: None,
+                },
                 relay_gateways: vec![&relay],
                 count: 1,
                 limit: 10,
@@ -827,6 +1119,7 @@ pub mod test {
             RelayGatewayFilterTest {
                 filters: RelayGatewayFilters {
                     tenant_id: Some(gw.tenant_id.into()),
+                    border_gateway_id: None,
                 },
                 relay_gateways: vec![&relay],
                 count: 1,
@@ -836,12 +1129,33 @@ pub mod test {
             RelayGatewayFilterTest {
                 filters: RelayGatewayFilters {
                     tenant_id: Some(gw.tenant_id.into()),
+                    border_gateway_id: None,
                 },
                 relay_gateways: vec![&relay],
                 count: 1,
                 limit: 10,
                 offset: 0,
             },
+            RelayGatewayFilterTest {
+                filters: RelayGatewayFilters {
+                    tenant_id: None,
+                    border_gateway_id: Some(gw.gateway_id),
+                },
+                relay_gateways: vec![&relay],
+                count: 1,
+                limit: 10,
+                offset: 0,
+            },
+            RelayGatewayFilterTest {
+                filters: RelayGatewayFilters {
+                    tenant_id: None,
+                    border_gateway_id: Some(EUI64::from_be_bytes([8, 7, 6, 5, 4, 3, 2, 1])),
+                },
+                relay_gateways: vec![],
+                count: 0,
+                limit: 10,
+                offset: 0,
+            },
         ];
 
         for tst in tests {
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::info;
+
+use lrwn::EUI64;
+
+use super::error::Error;
+use super::get_async_db_conn;
+use super::schema::gateway_certificate;
+
+#[derive(Queryable, Insertable, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = gateway_certificate)]
+pub struct GatewayCertificate {
+    pub serial_number: Vec<u8>,
+    pub gateway_id: EUI64,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Default for GatewayCertificate {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        GatewayCertificate {
+            serial_number: Vec::new(),
+            gateway_id: EUI64::from_be_bytes([0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            created_at: now,
+            expires_at: now,
+            revoked_at: None,
+        }
+    }
+}
+
+pub async fn create(cert: GatewayCertificate) -> Result<GatewayCertificate, Error> {
+    let cert: GatewayCertificate = diesel::insert_into(gateway_certificate::table)
+        .values(&cert)
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, hex::encode(&cert.serial_number)))?;
+
+    info!(gateway_id = %cert.gateway_id, serial_number = %hex::encode(&cert.serial_number), "Gateway certificate created");
+    Ok(cert)
+}
+
+// Returns the certificate with the latest expiration date, that has not been revoked.
+pub async fn get_latest_for_gateway(gateway_id: &EUI64) -> Result<GatewayCertificate, Error> {
+    gateway_certificate::dsl::gateway_certificate
+        .filter(gateway_certificate::dsl::gateway_id.eq(gateway_id))
+        .filter(gateway_certificate::dsl::revoked_at.is_null())
+        .order_by(gateway_certificate::dsl::expires_at.desc())
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, gateway_id.to_string()))
+}
+
+// Revokes all the (not yet expired) certificates of the given gateway. It returns the revoked
+// certificates.
+pub async fn revoke_for_gateway(gateway_id: &EUI64) -> Result<Vec<GatewayCertificate>, Error> {
+    let now = Utc::now();
+    let certs: Vec<GatewayCertificate> = diesel::update(
+        gateway_certificate::dsl::gateway_certificate
+            .filter(gateway_certificate::dsl::gateway_id.eq(gateway_id))
+            .filter(gateway_certificate::dsl::revoked_at.is_null())
+            .filter(gateway_certificate::dsl::expires_at.gt(now)),
+    )
+    .set(gateway_certificate::revoked_at.eq(Some(now)))
+    .get_results(&mut get_async_db_conn().await?)
+    .await?;
+
+    info!(gateway_id = %gateway_id, count = certs.len(), "Gateway certificates revoked");
+    Ok(certs)
+}
+
+// Returns the revoked certificates that have not yet expired. Expired certificates are no longer
+// valid, and therefore do not need to be included in the revocation list.
+pub async fn get_revoked() -> Result<Vec<GatewayCertificate>, Error> {
+    gateway_certificate::dsl::gateway_certificate
+        .filter(gateway_certificate::dsl::revoked_at.is_not_null())
+        .filter(gateway_certificate::dsl::expires_at.gt(Utc::now()))
+        .order_by(gateway_certificate::dsl::revoked_at)
+        .load(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, "".into()))
+}
+
+// Deletes the certificates that have expired.
+pub async fn delete_expired() -> Result<usize, Error> {
+    let count = diesel::delete(
+        gateway_certificate::dsl::gateway_certificate
+            .filter(gateway_certificate::dsl::expires_at.le(Utc::now())),
+    )
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+
+    info!(count = count, "Expired gateway certificates deleted");
+    Ok(count)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_gateway_certificate() {
+        let _guard = test::prepare().await;
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let cert_1 = create(GatewayCertificate {
+            serial_number: vec![1],
+            gateway_id,
+            expires_at: Utc::now() + Duration::days(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let cert_2 = create(GatewayCertificate {
+            serial_number: vec![2],
+            gateway_id,
+            expires_at: Utc::now() + Duration::days(2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let _ = create(GatewayCertificate {
+            serial_number: vec![3],
+            gateway_id,
+            expires_at: Utc::now() - Duration::days(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // latest
+        let latest = get_latest_for_gateway(&gateway_id).await.unwrap();
+        assert_eq!(cert_2, latest);
+
+        // nothing revoked
+        assert!(get_revoked().await.unwrap().is_empty());
+
+        // revoke
+        let revoked = revoke_for_gateway(&gateway_id).await.unwrap();
+        assert_eq!(2, revoked.len());
+
+        let revoked = get_revoked().await.unwrap();
+        assert_eq!(
+            vec![cert_1.serial_number.clone(), cert_2.serial_number.clone()],
+            {
+                let mut v: Vec<Vec<u8>> = revoked.iter().map(|c| c.serial_number.clone()).collect();
+                v.sort();
+                v
+            }
+        );
+        assert!(get_latest_for_gateway(&gateway_id).await.is_err());
+
+        // delete expired
+        assert_eq!(1, delete_expired().await.unwrap());
+    }
+}
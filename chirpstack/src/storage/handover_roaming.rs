@@ -0,0 +1,363 @@
+use std::io::Cursor;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use tracing::{debug, info};
+
+use super::error::Error;
+use super::{device, get_async_redis_conn, redis_key};
+use crate::config;
+use chirpstack_api::internal;
+use lrwn::{AES128Key, DevAddr, EUI64};
+
+pub async fn save(ds: &internal::HandoverRoamingDeviceSession) -> Result<()> {
+    let dev_eui = EUI64::from_slice(&ds.dev_eui)?;
+
+    let lifetime: Option<DateTime<Utc>> = match ds.lifetime {
+        Some(v) => Some(v.try_into().map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+    let ttl = match get_ttl(lifetime)? {
+        Some(v) => v,
+        None => {
+            debug!("Not saving handover-roaming device-session, lifetime of handover-roaming session expired");
+            return Ok(());
+        }
+    };
+
+    let key = redis_key(format!("hr:dev:{{{}}}", dev_eui));
+    let b = ds.encode_to_vec();
+
+    () = redis::cmd("PSETEX")
+        .arg(&key)
+        .arg(ttl.as_millis() as usize)
+        .arg(b)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    info!(dev_eui = %dev_eui, "Handover-roaming device-session saved");
+
+    Ok(())
+}
+
+pub async fn get(dev_eui: &EUI64) -> Result<internal::HandoverRoamingDeviceSession, Error> {
+    let key = redis_key(format!("hr:dev:{{{}}}", dev_eui));
+
+    let v: Vec<u8> = redis::cmd("GET")
+        .arg(key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Get handover-roaming device-session")?;
+    if v.is_empty() {
+        return Err(Error::NotFound(dev_eui.to_string()));
+    }
+    let ds = internal::HandoverRoamingDeviceSession::decode(&mut Cursor::new(v))
+        .context("Decode handover-roaming device-session")?;
+    Ok(ds)
+}
+
+pub async fn delete(dev_eui: &EUI64) -> Result<()> {
+    let key = redis_key(format!("hr:dev:{{{}}}", dev_eui));
+
+    () = redis::cmd("DEL")
+        .arg(&key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    info!(dev_eui = %dev_eui, "Handover-roaming device-session deleted");
+    Ok(())
+}
+
+// Saves the handover-roaming device-session of a device served by this (serving) network-server.
+pub async fn save_serving(ds: &internal::HandoverRoamingServingDeviceSession) -> Result<()> {
+    let dev_eui = EUI64::from_slice(&ds.dev_eui)?;
+    let dev_addr = DevAddr::from_slice(&ds.dev_addr)?;
+
+    let lifetime: Option<DateTime<Utc>> = match ds.lifetime {
+        Some(v) => Some(v.try_into().map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+    let ttl = match get_ttl(lifetime)? {
+        Some(v) => v,
+        None => {
+            debug!("Not saving handover-roaming serving device-session, lifetime of handover-roaming session expired");
+            return Ok(());
+        }
+    };
+
+    let conf = config::get();
+    let dev_addr_key = redis_key(format!("hr:sns:devaddr:{{{}}}", dev_addr));
+    let dev_eui_key = redis_key(format!("hr:sns:dev:{{{}}}", dev_eui));
+    let index_ttl = config::get_device_ttl(conf.redis.ttl.handover_roaming_session).max(ttl);
+    let b = ds.encode_to_vec();
+
+    // The DevAddr pointer is needed to lookup the session on uplink (the DevAddr is not
+    // guaranteed to be unique, thus the MIC is used to find the matching session).
+    () = redis::pipe()
+        .cmd("SADD")
+        .arg(&dev_addr_key)
+        .arg(dev_eui.to_string())
+        .ignore()
+        .cmd("PEXPIRE")
+        .arg(&dev_addr_key)
+        .arg(index_ttl.as_millis() as usize)
+        .ignore()
+        .cmd("PSETEX")
+        .arg(&dev_eui_key)
+        .arg(ttl.as_millis() as usize)
+        .arg(b)
+        .ignore()
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    info!(dev_eui = %dev_eui, dev_addr = %dev_addr, "Handover-roaming serving device-session saved");
+
+    Ok(())
+}
+
+pub async fn get_serving(
+    dev_eui: &EUI64,
+) -> Result<internal::HandoverRoamingServingDeviceSession, Error> {
+    let key = redis_key(format!("hr:sns:dev:{{{}}}", dev_eui));
+
+    let v: Vec<u8> = redis::cmd("GET")
+        .arg(key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Get handover-roaming serving device-session")?;
+    if v.is_empty() {
+        return Err(Error::NotFound(dev_eui.to_string()));
+    }
+    let ds = internal::HandoverRoamingServingDeviceSession::decode(&mut Cursor::new(v))
+        .context("Decode handover-roaming serving device-session")?;
+    Ok(ds)
+}
+
+pub async fn delete_serving(dev_eui: &EUI64) -> Result<()> {
+    let key = redis_key(format!("hr:sns:dev:{{{}}}", dev_eui));
+
+    // The DevAddr pointer expires, or is ignored on lookup when the session does not exist.
+    () = redis::cmd("DEL")
+        .arg(&key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    info!(dev_eui = %dev_eui, "Handover-roaming serving device-session deleted");
+    Ok(())
+}
+
+// Returns the serving device-session matching the MIC of the given PhyPayload, together with
+// the full (32bit) uplink frame-counter.
+pub async fn get_serving_for_phy_payload(
+    phy: &lrwn::PhyPayload,
+) -> Result<(internal::HandoverRoamingServingDeviceSession, u32), Error> {
+    // Clone the PhyPayload, as we will update the f_cnt to the full (32bit) frame-counter value
+    // for calculating the MIC.
+    let mut phy = phy.clone();
+
+    let (dev_addr, f_cnt_orig) = if let lrwn::Payload::MACPayload(v) = &phy.payload {
+        (v.fhdr.devaddr, v.fhdr.f_cnt)
+    } else {
+        return Err(Error::InvalidPayload("MacPayload".to_string()));
+    };
+
+    let sessions = get_serving_for_dev_addr(dev_addr).await?;
+    if sessions.is_empty() {
+        return Err(Error::NotFound(dev_addr.to_string()));
+    }
+
+    for ds in sessions {
+        let f_nwk_s_int_key = AES128Key::from_slice(&ds.f_nwk_s_int_key)?;
+        let full_f_cnt = device::get_full_f_cnt_up(ds.f_cnt_up, f_cnt_orig);
+
+        if let lrwn::Payload::MACPayload(pl) = &mut phy.payload {
+            pl.fhdr.f_cnt = full_f_cnt;
+        }
+
+        let mic_ok = if ds.lorawan_1_1 {
+            phy.validate_uplink_data_micf(&f_nwk_s_int_key)?
+        } else {
+            phy.validate_uplink_data_mic(
+                lrwn::MACVersion::LoRaWAN1_0,
+                0,
+                0,
+                0,
+                &f_nwk_s_int_key,
+                &f_nwk_s_int_key,
+            )?
+        };
+
+        if mic_ok {
+            return Ok((ds, full_f_cnt));
+        }
+    }
+
+    Err(Error::InvalidMIC)
+}
+
+async fn get_serving_for_dev_addr(
+    dev_addr: DevAddr,
+) -> Result<Vec<internal::HandoverRoamingServingDeviceSession>> {
+    let dev_euis: Vec<String> = redis::cmd("SMEMBERS")
+        .arg(redis_key(format!("hr:sns:devaddr:{{{}}}", dev_addr)))
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+    if dev_euis.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut pipe = redis::pipe();
+    for dev_eui in &dev_euis {
+        pipe.cmd("GET")
+            .arg(redis_key(format!("hr:sns:dev:{{{}}}", dev_eui)));
+    }
+    let values: Vec<Vec<u8>> = pipe.query_async(&mut get_async_redis_conn().await?).await?;
+
+    let mut out = Vec::new();
+    for v in values {
+        // The session has expired or has been deleted.
+        if v.is_empty() {
+            continue;
+        }
+
+        let ds = internal::HandoverRoamingServingDeviceSession::decode(&mut Cursor::new(v))
+            .context("Decode handover-roaming serving device-session")?;
+
+        // The DevAddr might have been re-assigned to the device.
+        if ds.dev_addr != dev_addr.to_vec() {
+            continue;
+        }
+
+        out.push(ds);
+    }
+
+    Ok(out)
+}
+
+// Returns the TTL for the given session lifetime, or None in case the lifetime has expired.
+fn get_ttl(lifetime: Option<DateTime<Utc>>) -> Result<Option<std::time::Duration>> {
+    match lifetime {
+        Some(v) => {
+            let ttl = v - Utc::now();
+            if ttl <= chrono::Duration::zero() {
+                return Ok(None);
+            }
+            Ok(Some(ttl.to_std()?))
+        }
+        None => {
+            let conf = config::get();
+            Ok(Some(config::get_device_ttl(
+                conf.redis.ttl.handover_roaming_session,
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_handover_roaming_session() {
+        let _guard = test::prepare().await;
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(get(&dev_eui).await.is_err());
+
+        // without lifetime
+        let ds = internal::HandoverRoamingDeviceSession {
+            dev_eui: dev_eui.to_vec(),
+            net_id: vec![1, 2, 3],
+            dev_addr: vec![1, 2, 3, 4],
+            lifetime: None,
+        };
+        save(&ds).await.unwrap();
+        assert_eq!(ds, get(&dev_eui).await.unwrap());
+
+        // expired lifetime
+        delete(&dev_eui).await.unwrap();
+        let ds = internal::HandoverRoamingDeviceSession {
+            lifetime: Some((Utc::now() - chrono::Duration::seconds(1)).into()),
+            ..ds
+        };
+        save(&ds).await.unwrap();
+        assert!(get(&dev_eui).await.is_err());
+
+        // with lifetime
+        let ds = internal::HandoverRoamingDeviceSession {
+            lifetime: Some((Utc::now() + chrono::Duration::seconds(60)).into()),
+            ..ds
+        };
+        save(&ds).await.unwrap();
+        assert_eq!(ds, get(&dev_eui).await.unwrap());
+
+        delete(&dev_eui).await.unwrap();
+        assert!(get(&dev_eui).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handover_roaming_serving_session() {
+        let _guard = test::prepare().await;
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let dev_addr = DevAddr::from_be_bytes([1, 2, 3, 4]);
+        let key = AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(get_serving(&dev_eui).await.is_err());
+
+        let ds = internal::HandoverRoamingServingDeviceSession {
+            dev_eui: dev_eui.to_vec(),
+            net_id: vec![3, 2, 1],
+            dev_addr: dev_addr.to_vec(),
+            f_nwk_s_int_key: key.to_vec(),
+            s_nwk_s_int_key: key.to_vec(),
+            nwk_s_enc_key: key.to_vec(),
+            f_cnt_up: 65536 + 10,
+            ..Default::default()
+        };
+        save_serving(&ds).await.unwrap();
+        assert_eq!(ds, get_serving(&dev_eui).await.unwrap());
+
+        let mut phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: lrwn::MType::UnconfirmedDataUp,
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::MACPayload(lrwn::MACPayload {
+                fhdr: lrwn::FHDR {
+                    devaddr: dev_addr,
+                    f_cnt: 65536 + 11,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            mic: None,
+        };
+        phy.set_uplink_data_mic(lrwn::MACVersion::LoRaWAN1_0, 0, 0, 0, &key, &key)
+            .unwrap();
+
+        // Only the 16 LSB are transmitted.
+        if let lrwn::Payload::MACPayload(pl) = &mut phy.payload {
+            pl.fhdr.f_cnt = 11;
+        }
+
+        let (ds_get, f_cnt) = get_serving_for_phy_payload(&phy).await.unwrap();
+        assert_eq!(ds, ds_get);
+        assert_eq!(65536 + 11, f_cnt);
+
+        // Invalid MIC.
+        phy.mic = Some([0, 0, 0, 0]);
+        assert!(matches!(
+            get_serving_for_phy_payload(&phy).await,
+            Err(Error::InvalidMIC)
+        ));
+
+        delete_serving(&dev_eui).await.unwrap();
+        assert!(get_serving(&dev_eui).await.is_err());
+        assert!(matches!(
+            get_serving_for_phy_payload(&phy).await,
+            Err(Error::NotFound(_))
+        ));
+    }
+}
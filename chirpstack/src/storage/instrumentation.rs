@@ -0,0 +1,192 @@
+use std::time::{Duration, Instant};
+
+use diesel::connection::{Instrumentation, InstrumentationEvent};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use tracing::warn;
+
+use crate::config;
+use crate::monitoring::prometheus;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct QueryLabels {
+    class: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct CommandLabels {
+    command: String,
+}
+
+lazy_static! {
+    static ref STORAGE_DB_QUERY: Family<QueryLabels, Histogram> = {
+        let histogram = Family::<QueryLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(exponential_buckets(0.001, 2.0, 12))
+        });
+        prometheus::register(
+            "storage_db_query_duration_seconds",
+            "Duration of database queries by query class",
+            histogram.clone(),
+        );
+        histogram
+    };
+    static ref STORAGE_REDIS_COMMAND: Family<CommandLabels, Histogram> = {
+        let histogram = Family::<CommandLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(exponential_buckets(0.001, 2.0, 12))
+        });
+        prometheus::register(
+            "storage_redis_command_duration_seconds",
+            "Duration of Redis commands by command",
+            histogram.clone(),
+        );
+        histogram
+    };
+}
+
+// Instrumentation which is set on each database connection, to observe the query durations.
+#[derive(Default)]
+pub struct QueryInstrumentation {
+    start: Option<Instant>,
+}
+
+impl Instrumentation for QueryInstrumentation {
+    fn on_connection_event(&mut self, event: InstrumentationEvent<'_>) {
+        match event {
+            InstrumentationEvent::StartQuery { .. } => {
+                self.start = Some(Instant::now());
+            }
+            InstrumentationEvent::FinishQuery { query, .. } => {
+                if let Some(start) = self.start.take() {
+                    observe_query(&query.to_string(), start.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn observe_query(query: &str, duration: Duration) {
+    // Strip the bind values, as these might contain sensitive data (e.g. keys).
+    let sql = query.split(" -- binds:").next().unwrap_or_default();
+    let class = query_class(sql);
+
+    STORAGE_DB_QUERY
+        .get_or_create(&QueryLabels {
+            class: class.clone(),
+        })
+        .observe(duration.as_secs_f64());
+
+    if is_slow(duration) {
+        warn!(class = %class, duration = ?duration, query = %sql, "Slow database query");
+    }
+}
+
+pub fn observe_redis_command(command: &str, duration: Duration) {
+    STORAGE_REDIS_COMMAND
+        .get_or_create(&CommandLabels {
+            command: command.to_string(),
+        })
+        .observe(duration.as_secs_f64());
+
+    if is_slow(duration) {
+        warn!(command = %command, duration = ?duration, "Slow Redis command");
+    }
+}
+
+// Returns the (uppercase) command name of the given Redis command.
+pub fn redis_command_name(cmd: &redis::Cmd) -> String {
+    match cmd.args_iter().next() {
+        Some(redis::Arg::Simple(b)) => String::from_utf8_lossy(b).to_uppercase(),
+        _ => "UNKNOWN".to_string(),
+    }
+}
+
+fn is_slow(duration: Duration) -> bool {
+    let conf = config::get();
+    !conf.monitoring.storage_slow_log_threshold.is_zero()
+        && duration >= conf.monitoring.storage_slow_log_threshold
+}
+
+// Returns the query class, formatted as statement:table, e.g. select:device. The table is
+// omitted for statements without table (e.g. begin, commit).
+fn query_class(sql: &str) -> String {
+    let words: Vec<&str> = sql.split_whitespace().collect();
+    let statement = match words.first() {
+        Some(v) => v.to_lowercase(),
+        None => return "unknown".to_string(),
+    };
+
+    let keyword = match statement.as_str() {
+        "select" | "delete" => "from",
+        "insert" => "into",
+        "update" => "update",
+        _ => return statement,
+    };
+
+    let table = words
+        .iter()
+        .position(|w| w.eq_ignore_ascii_case(keyword))
+        .and_then(|i| words.get(i + 1))
+        .map(|t| {
+            t.split('.')
+                .next_back()
+                .unwrap_or_default()
+                .trim_matches(|c: char| c == '"' || c == '`' || c == '(')
+                .to_string()
+        });
+
+    match table {
+        Some(t) if !t.is_empty() => format!("{}:{}", statement, t),
+        _ => statement,
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_query_class() {
+        struct Test {
+            sql: &'static str,
+            expected: &'static str,
+        }
+
+        let tests = vec![
+            Test {
+                sql: r#"SELECT "device"."dev_eui", "device"."name" FROM "device" WHERE ("device"."dev_eui" = $1)"#,
+                expected: "select:device",
+            },
+            Test {
+                sql: r#"INSERT INTO "tenant" ("id", "name") VALUES ($1, $2) RETURNING "tenant"."id""#,
+                expected: "insert:tenant",
+            },
+            Test {
+                sql: r#"UPDATE "device" SET "f_cnt_up" = $1 WHERE ("device"."dev_eui" = $2)"#,
+                expected: "update:device",
+            },
+            Test {
+                sql: r#"DELETE FROM "device_queue_item" WHERE ("device_queue_item"."id" = $1)"#,
+                expected: "delete:device_queue_item",
+            },
+            Test {
+                sql: "BEGIN",
+                expected: "begin",
+            },
+            Test {
+                sql: "",
+                expected: "unknown",
+            },
+        ];
+
+        for tst in &tests {
+            assert_eq!(tst.expected, query_class(tst.sql));
+        }
+    }
+
+    #[test]
+    fn test_redis_command_name() {
+        assert_eq!("XADD", redis_command_name(redis::cmd("xadd").arg("key")));
+    }
+}
@@ -0,0 +1,342 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{dsl, prelude::*};
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::join_server_route;
+use super::{cache, fields, get_async_db_conn};
+use crate::config;
+use lrwn::EUI64Prefix;
+
+// Join Server route, managed through the API. Join-requests of which the JoinEUI matches the
+// JoinEUI prefix are routed to the Join Server of this route.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = join_server_route)]
+pub struct JoinServerRoute {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub join_eui_prefix: String,
+    pub server: String,
+    pub async_timeout: i32,
+    pub ca_cert: String,
+    pub tls_cert: String,
+    pub tls_key: String,
+    pub authorization_header: String,
+    pub oauth2_token_url: String,
+    pub oauth2_client_id: String,
+    pub oauth2_client_secret: String,
+    pub oauth2_scope: String,
+    pub api_key_header: String,
+    pub api_key: String,
+    pub jws_algorithm: String,
+    pub jws_signing_key: String,
+    pub jws_verification_key: String,
+}
+
+impl JoinServerRoute {
+    fn validate(&self) -> Result<(), Error> {
+        match EUI64Prefix::from_str(&self.join_eui_prefix) {
+            Ok(v) if v.size() <= 64 => {}
+            _ => {
+                return Err(Error::Validation(
+                    "join_eui_prefix must be a valid EUI64 prefix, e.g. 0102030405060700/56".into(),
+                ));
+            }
+        }
+
+        if self.server.is_empty() {
+            return Err(Error::Validation("server must be set".into()));
+        }
+
+        if self.async_timeout < 0 {
+            return Err(Error::Validation(
+                "async_timeout must not be negative".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for JoinServerRoute {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        JoinServerRoute {
+            id: Uuid::new_v4().into(),
+            created_at: now,
+            updated_at: now,
+            name: "".into(),
+            join_eui_prefix: "".into(),
+            server: "".into(),
+            async_timeout: 0,
+            ca_cert: "".into(),
+            tls_cert: "".into(),
+            tls_key: "".into(),
+            authorization_header: "".into(),
+            oauth2_token_url: "".into(),
+            oauth2_client_id: "".into(),
+            oauth2_client_secret: "".into(),
+            oauth2_scope: "".into(),
+            api_key_header: "".into(),
+            api_key: "".into(),
+            jws_algorithm: "".into(),
+            jws_signing_key: "".into(),
+            jws_verification_key: "".into(),
+        }
+    }
+}
+
+impl TryFrom<&JoinServerRoute> for config::JoinServerServer {
+    type Error = anyhow::Error;
+
+    fn try_from(r: &JoinServerRoute) -> Result<Self> {
+        Ok(config::JoinServerServer {
+            join_eui_prefix: EUI64Prefix::from_str(&r.join_eui_prefix)?,
+            server: r.server.clone(),
+            async_timeout: Duration::from_secs(r.async_timeout as u64),
+            ca_cert: r.ca_cert.clone(),
+            tls_cert: r.tls_cert.clone(),
+            tls_key: r.tls_key.clone(),
+            authorization_header: r.authorization_header.clone(),
+            oauth2_token_url: r.oauth2_token_url.clone(),
+            oauth2_client_id: r.oauth2_client_id.clone(),
+            oauth2_client_secret: r.oauth2_client_secret.clone(),
+            oauth2_scope: r.oauth2_scope.clone(),
+            api_key_header: r.api_key_header.clone(),
+            api_key: r.api_key.clone(),
+            jws_algorithm: r.jws_algorithm.clone(),
+            jws_signing_key: r.jws_signing_key.clone(),
+            jws_verification_key: r.jws_verification_key.clone(),
+        })
+    }
+}
+
+#[derive(Queryable, PartialEq, Eq, Debug)]
+pub struct JoinServerRouteListItem {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub join_eui_prefix: String,
+    pub server: String,
+}
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    pub search: Option<String>,
+}
+
+pub async fn create(r: JoinServerRoute) -> Result<JoinServerRoute, Error> {
+    r.validate()?;
+
+    let r: JoinServerRoute = diesel::insert_into(join_server_route::table)
+        .values(&r)
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, r.join_eui_prefix.clone()))?;
+    cache::invalidate(cache::Kind::JoinServerRoutes, &r.id).await;
+    info!(id = %r.id, join_eui_prefix = %r.join_eui_prefix, "Join Server route created");
+    Ok(r)
+}
+
+pub async fn get(id: &Uuid) -> Result<JoinServerRoute, Error> {
+    join_server_route::dsl::join_server_route
+        .find(&fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.to_string()))
+}
+
+pub async fn update(r: JoinServerRoute) -> Result<JoinServerRoute, Error> {
+    r.validate()?;
+
+    let r: JoinServerRoute = diesel::update(join_server_route::dsl::join_server_route.find(&r.id))
+        .set((
+            join_server_route::updated_at.eq(Utc::now()),
+            join_server_route::name.eq(&r.name),
+            join_server_route::join_eui_prefix.eq(&r.join_eui_prefix),
+            join_server_route::server.eq(&r.server),
+            join_server_route::async_timeout.eq(&r.async_timeout),
+            join_server_route::ca_cert.eq(&r.ca_cert),
+            join_server_route::tls_cert.eq(&r.tls_cert),
+            join_server_route::tls_key.eq(&r.tls_key),
+            join_server_route::authorization_header.eq(&r.authorization_header),
+            join_server_route::oauth2_token_url.eq(&r.oauth2_token_url),
+            join_server_route::oauth2_client_id.eq(&r.oauth2_client_id),
+            join_server_route::oauth2_client_secret.eq(&r.oauth2_client_secret),
+            join_server_route::oauth2_scope.eq(&r.oauth2_scope),
+            join_server_route::api_key_header.eq(&r.api_key_header),
+            join_server_route::api_key.eq(&r.api_key),
+            join_server_route::jws_algorithm.eq(&r.jws_algorithm),
+            join_server_route::jws_signing_key.eq(&r.jws_signing_key),
+            join_server_route::jws_verification_key.eq(&r.jws_verification_key),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, r.id.to_string()))?;
+    cache::invalidate(cache::Kind::JoinServerRoutes, &r.id).await;
+    info!(id = %r.id, join_eui_prefix = %r.join_eui_prefix, "Join Server route updated");
+    Ok(r)
+}
+
+pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    let ra =
+        diesel::delete(join_server_route::dsl::join_server_route.find(&fields::Uuid::from(id)))
+            .execute(&mut get_async_db_conn().await?)
+            .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    cache::invalidate(cache::Kind::JoinServerRoutes, id).await;
+    info!(id = %id, "Join Server route deleted");
+    Ok(())
+}
+
+pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
+    let mut q = join_server_route::dsl::join_server_route
+        .select(dsl::count_star())
+        .into_boxed();
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(join_server_route::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(join_server_route::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    Ok(q.first(&mut get_async_db_conn().await?).await?)
+}
+
+pub async fn list(
+    limit: i64,
+    offset: i64,
+    filters: &Filters,
+) -> Result<Vec<JoinServerRouteListItem>, Error> {
+    let mut q = join_server_route::dsl::join_server_route
+        .select((
+            join_server_route::id,
+            join_server_route::created_at,
+            join_server_route::updated_at,
+            join_server_route::name,
+            join_server_route::join_eui_prefix,
+            join_server_route::server,
+        ))
+        .into_boxed();
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(join_server_route::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(join_server_route::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    let items = q
+        .order_by(join_server_route::dsl::name)
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+// Returns all the Join Server routes.
+pub async fn get_all() -> Result<Vec<JoinServerRoute>, Error> {
+    let items = join_server_route::dsl::join_server_route
+        .order_by(join_server_route::dsl::join_eui_prefix)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_join_server_route() {
+        let _guard = test::prepare().await;
+
+        // invalid prefix
+        assert!(create(JoinServerRoute {
+            name: "invalid".into(),
+            join_eui_prefix: "0102".into(),
+            server: "https://js.example.com".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // create
+        let mut r = create(JoinServerRoute {
+            name: "js-a".into(),
+            join_eui_prefix: "0102030405060700/56".into(),
+            server: "https://js-a.example.com".into(),
+            async_timeout: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // prefix must be unique
+        assert!(create(JoinServerRoute {
+            name: "js-b".into(),
+            join_eui_prefix: "0102030405060700/56".into(),
+            server: "https://js-b.example.com".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // get
+        let r_get = get(&r.id).await.unwrap();
+        assert_eq!(r, r_get);
+
+        // update
+        r.name = "js-a-updated".into();
+        r.authorization_header = "Bearer foo".into();
+        let r = update(r).await.unwrap();
+        let r_get = get(&r.id).await.unwrap();
+        assert_eq!(r, r_get);
+
+        // to join server config
+        let js = config::JoinServerServer::try_from(&r).unwrap();
+        assert_eq!("0102030405060700/56", js.join_eui_prefix.to_string());
+        assert_eq!(Duration::from_secs(1), js.async_timeout);
+        assert_eq!("Bearer foo", js.authorization_header);
+
+        // get count and list
+        let filters = Filters {
+            search: Some("js".into()),
+        };
+        assert_eq!(1, get_count(&filters).await.unwrap());
+        let items = list(10, 0, &filters).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!("js-a-updated", items[0].name);
+
+        // get all
+        assert_eq!(1, get_all().await.unwrap().len());
+
+        // delete
+        delete(&r.id).await.unwrap();
+        assert!(delete(&r.id).await.is_err());
+        assert!(get_all().await.unwrap().is_empty());
+    }
+}
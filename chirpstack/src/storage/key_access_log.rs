@@ -0,0 +1,282 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tokio::time::sleep;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use lrwn::EUI64;
+
+use super::error::Error;
+use super::schema::key_access_log;
+use super::{fields, get_async_db_conn};
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+
+// Interval at which the entries exceeding the retention are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Key material of which the access is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    // Root-keys (NwkKey, AppKey and GenAppKey).
+    RootKeys,
+    // Session-keys (NwkSEncKey, SNwkSIntKey, FNwkSIntKey and AppSKey).
+    SessionKeys,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                KeyType::RootKeys => "root_keys",
+                KeyType::SessionKeys => "session_keys",
+            }
+        )
+    }
+}
+
+// Actor accessing the key material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    User(Uuid),
+    ApiKey(Uuid),
+    // ChirpStack itself, e.g. the join processing or a CLI command.
+    System,
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Actor::User(id) => write!(f, "user:{}", id),
+            Actor::ApiKey(id) => write!(f, "api_key:{}", id),
+            Actor::System => write!(f, "system"),
+        }
+    }
+}
+
+// Purpose for which the key material is accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    // Key material returned by the API.
+    ApiExport,
+    // Root-keys used for handling a join-request.
+    Join,
+    // Root-keys used for deriving the FUOTA multicast and data-block keys.
+    Fuota,
+    // Root-keys moved to the keystore.
+    KeystoreMigration,
+    // Key material re-encrypted using the active data encryption key.
+    Reencryption,
+    // Key material included in a backup.
+    Backup,
+}
+
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Purpose::ApiExport => "api_export",
+                Purpose::Join => "join",
+                Purpose::Fuota => "fuota",
+                Purpose::KeystoreMigration => "keystore_migration",
+                Purpose::Reencryption => "reencryption",
+                Purpose::Backup => "backup",
+            }
+        )
+    }
+}
+
+#[derive(Queryable, Insertable, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = key_access_log)]
+pub struct KeyAccessLog {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub dev_eui: EUI64,
+    pub key_type: String,
+    pub actor: String,
+    pub purpose: String,
+}
+
+// Logs the access of the key material of the given device. This must be called before the key
+// material is returned or used, such that the key material is not accessed in case the access
+// could not be logged.
+pub async fn log(
+    dev_eui: &EUI64,
+    key_type: KeyType,
+    actor: Actor,
+    purpose: Purpose,
+) -> Result<(), Error> {
+    let l = KeyAccessLog {
+        id: Uuid::new_v4().into(),
+        created_at: Utc::now(),
+        dev_eui: *dev_eui,
+        key_type: key_type.to_string(),
+        actor: actor.to_string(),
+        purpose: purpose.to_string(),
+    };
+
+    diesel::insert_into(key_access_log::table)
+        .values(&l)
+        .execute(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, l.id.to_string()))?;
+    info!(dev_eui = %l.dev_eui, key_type = %l.key_type, actor = %l.actor, purpose = %l.purpose, "Key material accessed");
+    Ok(())
+}
+
+// Logs the access of the key material of the given devices, see log. This is used by bulk
+// operations like the backup.
+pub async fn log_many(
+    dev_euis: &[EUI64],
+    key_type: KeyType,
+    actor: Actor,
+    purpose: Purpose,
+) -> Result<(), Error> {
+    let now = Utc::now();
+
+    for chunk in dev_euis.chunks(1000) {
+        let items: Vec<KeyAccessLog> = chunk
+            .iter()
+            .map(|dev_eui| KeyAccessLog {
+                id: Uuid::new_v4().into(),
+                created_at: now,
+                dev_eui: *dev_eui,
+                key_type: key_type.to_string(),
+                actor: actor.to_string(),
+                purpose: purpose.to_string(),
+            })
+            .collect();
+
+        diesel::insert_into(key_access_log::table)
+            .values(&items)
+            .execute(&mut get_async_db_conn().await?)
+            .await?;
+    }
+
+    info!(count = dev_euis.len(), key_type = %key_type, actor = %actor, purpose = %purpose, "Key material accessed");
+    Ok(())
+}
+
+pub async fn setup() {
+    let conf = config::get();
+    if conf.monitoring.key_access_log_retention.is_zero() {
+        return;
+    }
+
+    info!(retention = ?conf.monitoring.key_access_log_retention, "Setting up key access log cleanup");
+    tokio::spawn(async {
+        loop {
+            let conf = config::get();
+            match delete_older_than(conf.monitoring.key_access_log_retention).await {
+                Ok(count) => info!(count = count, "Expired key access log entries deleted"),
+                Err(e) => error!(error = %e.full(), "Key access log cleanup error"),
+            }
+
+            sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
+
+// Deletes the entries older than the given retention. It returns the number of deleted entries.
+pub async fn delete_older_than(retention: Duration) -> Result<usize, Error> {
+    let retention = chrono::Duration::from_std(retention)
+        .map_err(|e| Error::Validation(format!("invalid retention: {}", e)))?;
+
+    Ok(diesel::delete(
+        key_access_log::dsl::key_access_log
+            .filter(key_access_log::dsl::created_at.lt(Utc::now() - retention)),
+    )
+    .execute(&mut get_async_db_conn().await?)
+    .await?)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    pub async fn list(dev_eui: &EUI64) -> Vec<KeyAccessLog> {
+        key_access_log::dsl::key_access_log
+            .filter(key_access_log::dsl::dev_eui.eq(dev_eui))
+            .order_by(key_access_log::dsl::created_at)
+            .load(&mut get_async_db_conn().await.unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_key_access_log() {
+        let _guard = test::prepare().await;
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let user_id = Uuid::new_v4();
+
+        log(
+            &dev_eui,
+            KeyType::RootKeys,
+            Actor::User(user_id),
+            Purpose::ApiExport,
+        )
+        .await
+        .unwrap();
+        log(&dev_eui, KeyType::RootKeys, Actor::System, Purpose::Join)
+            .await
+            .unwrap();
+
+        let items: Vec<(String, String, String)> = list(&dev_eui)
+            .await
+            .into_iter()
+            .map(|l| (l.key_type, l.actor, l.purpose))
+            .collect();
+        assert_eq!(2, items.len());
+        assert!(items.contains(&(
+            "root_keys".into(),
+            format!("user:{}", user_id),
+            "api_export".into()
+        )));
+        assert!(items.contains(&("root_keys".into(), "system".into(), "join".into())));
+    }
+
+    #[tokio::test]
+    async fn test_delete_older_than() {
+        let _guard = test::prepare().await;
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        log(&dev_eui, KeyType::RootKeys, Actor::System, Purpose::Join)
+            .await
+            .unwrap();
+        diesel::insert_into(key_access_log::table)
+            .values(&KeyAccessLog {
+                id: Uuid::new_v4().into(),
+                created_at: Utc::now() - chrono::Duration::try_days(2).unwrap(),
+                dev_eui,
+                key_type: KeyType::SessionKeys.to_string(),
+                actor: Actor::System.to_string(),
+                purpose: Purpose::ApiExport.to_string(),
+            })
+            .execute(&mut get_async_db_conn().await.unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            1,
+            delete_older_than(Duration::from_secs(60 * 60 * 24))
+                .await
+                .unwrap()
+        );
+
+        let items = list(&dev_eui).await;
+        assert_eq!(1, items.len());
+        assert_eq!("join", items[0].purpose);
+    }
+}
@@ -9,7 +9,8 @@ pub async fn set_pending(dev_eui: &EUI64, cid: lrwn::CID, set: &lrwn::MACCommand
     let conf = config::get();
 
     let key = redis_key(format!("device:{}:mac:pending:{}", dev_eui, cid.to_u8()));
-    let ttl = conf.network.device_session_ttl.as_millis() as usize;
+    let ttl =
+        config::get_device_ttl(conf.redis.ttl.device_mac_command_pending).as_millis() as usize;
     let b = set.to_vec()?;
 
     () = redis::cmd("PSETEX")
@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// In-memory stub of the Redis set and lock operations used by the uplink deduplication. This is
+// only compiled with the bench-storage feature, such that the deduplication can be benchmarked in
+// isolation, without a Redis instance.
+
+type Sets = HashMap<String, (Instant, HashSet<Vec<u8>>)>;
+
+lazy_static! {
+    static ref SETS: Mutex<Sets> = Mutex::new(HashMap::new());
+    static ref LOCKS: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+// Adds the item to the set and tries to set the lock, both expiring after the given ttl. This
+// mimics SADD + PEXPIRE + SET NX PX and returns (added, lock_set).
+pub fn set_add_and_lock(key: &str, lock_key: &str, ttl: Duration, item: Vec<u8>) -> (bool, bool) {
+    let now = Instant::now();
+    let expires_at = now + ttl;
+
+    let added = {
+        let mut sets = SETS.lock().unwrap();
+        let set = sets
+            .entry(key.to_string())
+            .or_insert_with(|| (expires_at, HashSet::new()));
+        if set.0 <= now {
+            set.1.clear();
+        }
+        set.0 = expires_at;
+        set.1.insert(item)
+    };
+
+    let lock_set = {
+        let mut locks = LOCKS.lock().unwrap();
+        match locks.get(lock_key) {
+            Some(v) if *v > now => false,
+            _ => {
+                locks.insert(lock_key.to_string(), expires_at);
+                true
+            }
+        }
+    };
+
+    (added, lock_set)
+}
+
+// Returns the (non-expired) items of the set.
+pub fn set_members(key: &str) -> Vec<Vec<u8>> {
+    let sets = SETS.lock().unwrap();
+    match sets.get(key) {
+        Some((expires_at, set)) if *expires_at > Instant::now() => set.iter().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Removes all sets and locks.
+pub fn reset() {
+    SETS.lock().unwrap().clear();
+    LOCKS.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_add_and_lock() {
+        reset();
+
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(
+            (true, true),
+            set_add_and_lock("test:set", "test:lock", ttl, vec![1])
+        );
+        assert_eq!(
+            (true, false),
+            set_add_and_lock("test:set", "test:lock", ttl, vec![2])
+        );
+        assert_eq!(
+            (false, false),
+            set_add_and_lock("test:set", "test:lock", ttl, vec![2])
+        );
+
+        let mut items = set_members("test:set");
+        items.sort();
+        assert_eq!(vec![vec![1], vec![2]], items);
+
+        // Expired sets and locks are treated as non-existing.
+        assert_eq!(
+            (true, true),
+            set_add_and_lock("test:set2", "test:lock2", Duration::ZERO, vec![1])
+        );
+        assert!(set_members("test:set2").is_empty());
+        assert_eq!(
+            (true, true),
+            set_add_and_lock("test:set2", "test:lock2", ttl, vec![1])
+        );
+    }
+}
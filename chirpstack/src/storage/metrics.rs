@@ -1,16 +1,70 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::{
     DateTime, Datelike, Duration as ChronoDuration, Local, Months, NaiveDate, NaiveDateTime,
     Timelike,
 };
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tokio::time::sleep;
+use tracing::{error, info, trace, warn};
 
+use crate::helpers::errors::PrintFullError;
 use crate::storage::{get_async_redis_conn, redis_key};
+use crate::{config, coordination};
+
+const KEY_TIME_FORMAT: &str = "%Y%m%d%H%M";
+
+// Prefix of the hourly bucket fields containing the values which have been rolled up into the
+// day and month aggregates. As these fields start with '_', these are not returned by get.
+const ROLLED_UP_PREFIX: &str = "_rolled_";
+
+// Rolls up the given hourly bucket into the day and month aggregates. Only the difference
+// between the current and the previously rolled up values is added, such that values written
+// after the bucket was rolled up (e.g. late writes) are not counted twice.
+//
+// KEYS[1]: hour key
+// KEYS[2]: day key
+// KEYS[3]: month key
+// KEYS[4]: rollup key
+// ARGV[1]: kind
+// ARGV[2]: hour (rollup key member)
+// ARGV[3]: day TTL (ms)
+// ARGV[4]: month TTL (ms)
+// ARGV[5]: hour retention (ms)
+const ROLLUP_SCRIPT: &str = r#"
+    local values = {}
+    local hgetall = redis.call("HGETALL", KEYS[1])
+    for i = 1, #hgetall, 2 do
+        values[hgetall[i]] = hgetall[i + 1]
+    end
+
+    for k, v in pairs(values) do
+        if string.sub(k, 1, 8) ~= "_rolled_" then
+            if ARGV[1] == "COUNTER" then
+                redis.call("HSET", KEYS[2], k, v)
+                redis.call("HSET", KEYS[3], k, v)
+            else
+                local delta = tonumber(v) - tonumber(values["_rolled_" .. k] or "0")
+                if delta ~= 0 then
+                    redis.call("HINCRBYFLOAT", KEYS[2], k, delta)
+                    redis.call("HINCRBYFLOAT", KEYS[3], k, delta)
+                end
+                redis.call("HSET", KEYS[1], "_rolled_" .. k, v)
+            end
+        end
+    end
+
+    redis.call("PEXPIRE", KEYS[2], ARGV[3])
+    redis.call("PEXPIRE", KEYS[3], ARGV[4])
+    redis.call("PEXPIRE", KEYS[1], ARGV[5])
+    redis.call("ZREM", KEYS[4], ARGV[2])
+
+    return 0
+"#;
 
 #[allow(clippy::upper_case_acronyms)]
 #[allow(non_camel_case_types)]
@@ -49,6 +103,19 @@ impl fmt::Display for Kind {
     }
 }
 
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "COUNTER" => Kind::COUNTER,
+            "ABSOLUTE" => Kind::ABSOLUTE,
+            "GAUGE" => Kind::GAUGE,
+            _ => return Err(anyhow!("Unexpected metrics kind: {}", s)),
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Record {
     pub time: DateTime<Local>,
@@ -57,11 +124,12 @@ pub struct Record {
 }
 
 fn get_ttl(a: Aggregation) -> Duration {
+    let conf = config::get();
     match a {
-        Aggregation::MINUTE => Duration::from_secs(60 * 60 * 2), // two hours
-        Aggregation::HOUR => Duration::from_secs(60 * 60 * 24 * 2), // two days
-        Aggregation::DAY => Duration::from_secs(60 * 60 * 24 * 31 * 2), // two months
-        Aggregation::MONTH => Duration::from_secs(60 * 60 * 24 * 365 * 2), // two years
+        Aggregation::MINUTE => conf.redis.ttl.metrics_minute,
+        Aggregation::HOUR => conf.redis.ttl.metrics_hour,
+        Aggregation::DAY => conf.redis.ttl.metrics_day,
+        Aggregation::MONTH => conf.redis.ttl.metrics_month,
     }
 }
 
@@ -70,10 +138,56 @@ fn get_key(name: &str, a: Aggregation, dt: NaiveDateTime) -> String {
         "metrics:{{{}}}:{}:{}",
         name,
         a,
-        dt.format("%Y%m%d%H%M")
+        dt.format(KEY_TIME_FORMAT)
     ))
 }
 
+// Returns the key of the sorted set containing the hourly buckets of the given metrics name
+// which must be rolled up.
+fn get_rollup_key(name: &str) -> String {
+    redis_key(format!("metrics:{{{}}}:rollup", name))
+}
+
+// Returns the key of the sorted set containing the metrics names (prefixed by their kind) which
+// have hourly buckets pending rollup.
+fn get_rollup_names_key() -> String {
+    redis_key("metrics:rollup:names".to_string())
+}
+
+// Returns the start of the bucket of the given aggregation, containing the given time.
+fn get_bucket_time<T: Datelike + Timelike>(a: Aggregation, t: &T) -> Result<NaiveDateTime> {
+    Ok(match a {
+        Aggregation::MINUTE => NaiveDate::from_ymd_opt(t.year(), t.month(), t.day())
+            .ok_or_else(|| anyhow!("Invalid date"))?
+            .and_hms_opt(t.hour(), t.minute(), 0)
+            .ok_or_else(|| anyhow!("Invalid time"))?,
+        Aggregation::HOUR => NaiveDate::from_ymd_opt(t.year(), t.month(), t.day())
+            .ok_or_else(|| anyhow!("Invalid date"))?
+            .and_hms_opt(t.hour(), 0, 0)
+            .ok_or_else(|| anyhow!("Invalid time"))?,
+        Aggregation::DAY => NaiveDate::from_ymd_opt(t.year(), t.month(), t.day())
+            .ok_or_else(|| anyhow!("Invalid date"))?
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(|| anyhow!("Invalid time"))?,
+        Aggregation::MONTH => NaiveDate::from_ymd_opt(t.year(), t.month(), 1)
+            .ok_or_else(|| anyhow!("Invalid date"))?
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(|| anyhow!("Invalid time"))?,
+    })
+}
+
+// Adds the given value to the metrics hash.
+fn aggregate_value(kind: Kind, metrics: &mut HashMap<String, f64>, k: &str, v: f64) {
+    match kind {
+        Kind::COUNTER => {
+            metrics.insert(k.to_string(), v);
+        }
+        Kind::ABSOLUTE | Kind::GAUGE => {
+            *metrics.entry(k.to_string()).or_insert(0.0) += v;
+        }
+    }
+}
+
 pub async fn save_state(name: &str, state: &str) -> Result<()> {
     let key = redis_key(format!("metrics:{{{}}}", name));
     let ttl = get_ttl(Aggregation::MONTH);
@@ -97,36 +211,90 @@ pub async fn save(name: &str, record: &Record, aggregations: &[Aggregation]) ->
     let mut pipe = redis::pipe();
     pipe.atomic();
 
-    for a in aggregations {
-        let ttl = get_ttl(*a);
+    let rollup = add_save_cmds(&mut pipe, name, record, aggregations)?;
 
-        let ts: NaiveDateTime = match a {
-            Aggregation::MINUTE => {
-                NaiveDate::from_ymd_opt(record.time.year(), record.time.month(), record.time.day())
-                    .ok_or_else(|| anyhow!("Invalid date"))?
-                    .and_hms_opt(record.time.hour(), record.time.minute(), 0)
-                    .ok_or_else(|| anyhow!("Invalid time"))?
-            }
-            Aggregation::HOUR => {
-                NaiveDate::from_ymd_opt(record.time.year(), record.time.month(), record.time.day())
-                    .ok_or_else(|| anyhow!("Invalid date"))?
-                    .and_hms_opt(record.time.hour(), 0, 0)
-                    .ok_or_else(|| anyhow!("Invalid time"))?
-            }
-            Aggregation::DAY => {
-                NaiveDate::from_ymd_opt(record.time.year(), record.time.month(), record.time.day())
-                    .ok_or_else(|| anyhow!("Invalid date"))?
-                    .and_hms_opt(0, 0, 0)
-                    .ok_or_else(|| anyhow!("Invalid time"))?
-            }
-            Aggregation::MONTH => {
-                NaiveDate::from_ymd_opt(record.time.year(), record.time.month(), 1)
-                    .ok_or_else(|| anyhow!("Invalid date"))?
-                    .and_hms_opt(0, 0, 0)
-                    .ok_or_else(|| anyhow!("Invalid time"))?
-            }
-        };
+    () = pipe.query_async(&mut get_async_redis_conn().await?).await?;
+
+    // The names index uses a different key-slot than the metrics buckets, therefore it can not
+    // be part of the atomic pipeline.
+    if rollup {
+        () = redis::cmd("ZADD")
+            .arg(get_rollup_names_key())
+            .arg(Local::now().timestamp())
+            .arg(format!("{}:{}", record.kind, name))
+            .query_async(&mut get_async_redis_conn().await?)
+            .await?;
+    }
+
+    Ok(())
+}
 
+// Saves the given metrics records using a single (non-atomic) pipeline. The records are
+// given as (name, record) tuples.
+pub async fn save_batch(records: &[(String, Record)], aggregations: &[Aggregation]) -> Result<()> {
+    if records.iter().all(|(_, r)| r.metrics.is_empty()) {
+        return Ok(());
+    }
+
+    let mut pipe = redis::pipe();
+    let mut rollup_names: Vec<String> = Vec::new();
+
+    for (name, record) in records {
+        if record.metrics.is_empty() {
+            continue;
+        }
+
+        if add_save_cmds(&mut pipe, name, record, aggregations)? {
+            rollup_names.push(format!("{}:{}", record.kind, name));
+        }
+    }
+
+    if !rollup_names.is_empty() {
+        let ts = Local::now().timestamp();
+        let names_key = get_rollup_names_key();
+        for n in rollup_names {
+            pipe.cmd("ZADD").arg(&names_key).arg(ts).arg(n).ignore();
+        }
+    }
+
+    () = pipe.query_async(&mut get_async_redis_conn().await?).await?;
+
+    Ok(())
+}
+
+// Adds the commands for saving the given record to the pipeline. It returns true in case the
+// metrics name must be added to the rollup names index.
+fn add_save_cmds(
+    pipe: &mut redis::Pipeline,
+    name: &str,
+    record: &Record,
+    aggregations: &[Aggregation],
+) -> Result<bool> {
+    let conf = config::get();
+
+    // In case rollup is enabled, the day and month aggregates are created by the rollup task
+    // from the hourly buckets.
+    let rollup = conf.metrics.rollup.enabled
+        && aggregations
+            .iter()
+            .any(|a| matches!(a, Aggregation::DAY | Aggregation::MONTH));
+    let aggregations: Vec<Aggregation> = if rollup {
+        let mut out: Vec<Aggregation> = aggregations
+            .iter()
+            .filter(|a| !matches!(a, Aggregation::DAY | Aggregation::MONTH))
+            .cloned()
+            .collect();
+        if !out.contains(&Aggregation::HOUR) {
+            out.push(Aggregation::HOUR);
+        }
+        out
+    } else {
+        aggregations.to_vec()
+    };
+
+    for a in &aggregations {
+        let ttl = get_ttl(*a);
+        let ts = get_bucket_time(*a, &record.time)?;
         let key = get_key(name, *a, ts);
 
         for (k, v) in &record.metrics {
@@ -157,12 +325,23 @@ pub async fn save(name: &str, record: &Record, aggregations: &[Aggregation]) ->
             .arg(ttl.as_millis() as usize)
             .ignore();
 
+        if rollup && *a == Aggregation::HOUR {
+            let rollup_key = get_rollup_key(name);
+            pipe.cmd("ZADD")
+                .arg(&rollup_key)
+                .arg(ts.and_utc().timestamp())
+                .arg(ts.format(KEY_TIME_FORMAT).to_string())
+                .ignore();
+            pipe.cmd("PEXPIRE")
+                .arg(&rollup_key)
+                .arg(ttl.as_millis() as usize)
+                .ignore();
+        }
+
         info!(name = %name, aggregation = %a, "Metrics saved");
     }
 
-    () = pipe.query_async(&mut get_async_redis_conn().await?).await?;
-
-    Ok(())
+    Ok(rollup)
 }
 
 pub async fn get_state(name: &str) -> Result<String> {
@@ -182,90 +361,59 @@ pub async fn get(
     start: DateTime<Local>,
     end: DateTime<Local>,
 ) -> Result<Vec<Record>> {
+    let conf = config::get();
     let mut keys: Vec<String> = Vec::new();
     let mut timestamps: Vec<NaiveDateTime> = Vec::new();
 
-    match a {
-        Aggregation::MINUTE => {
-            let mut ts = NaiveDate::from_ymd_opt(start.year(), start.month(), start.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(start.hour(), start.minute(), 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-            let end = NaiveDate::from_ymd_opt(end.year(), end.month(), end.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(end.hour(), end.minute(), 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-
-            while ts.le(&end) {
-                timestamps.push(ts);
-                keys.push(get_key(name, a, ts));
-                ts += ChronoDuration::minutes(1);
-            }
-        }
-        Aggregation::HOUR => {
-            let mut ts = NaiveDate::from_ymd_opt(start.year(), start.month(), start.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(start.hour(), 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-            let end = NaiveDate::from_ymd_opt(end.year(), end.month(), end.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(end.hour(), 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-
-            while ts.le(&end) {
-                timestamps.push(ts);
-                keys.push(get_key(name, a, ts));
-                ts += ChronoDuration::hours(1);
-            }
-        }
-        Aggregation::DAY => {
-            let mut ts = NaiveDate::from_ymd_opt(start.year(), start.month(), start.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(0, 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-            let end = NaiveDate::from_ymd_opt(end.year(), end.month(), end.day())
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(0, 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-
-            while ts.le(&end) {
-                timestamps.push(ts);
-                keys.push(get_key(name, a, ts));
-                ts += ChronoDuration::days(1);
-            }
-        }
-        Aggregation::MONTH => {
-            let mut ts = NaiveDate::from_ymd_opt(start.year(), start.month(), 1)
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(0, 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-            let end = NaiveDate::from_ymd_opt(end.year(), end.month(), 1)
-                .ok_or_else(|| anyhow!("Invalid date"))?
-                .and_hms_opt(0, 0, 0)
-                .ok_or_else(|| anyhow!("Invalid time"))?;
-
-            while ts.le(&end) {
-                timestamps.push(ts);
-                keys.push(get_key(name, a, ts));
-                ts = ts
-                    .checked_add_months(Months::new(1))
-                    .ok_or_else(|| anyhow!("Add month error"))?;
-            }
-        }
+    let mut ts = get_bucket_time(a, &start)?;
+    let end = get_bucket_time(a, &end)?;
+
+    while ts.le(&end) {
+        timestamps.push(ts);
+        keys.push(get_key(name, a, ts));
+        ts = match a {
+            Aggregation::MINUTE => ts + ChronoDuration::minutes(1),
+            Aggregation::HOUR => ts + ChronoDuration::hours(1),
+            Aggregation::DAY => ts + ChronoDuration::days(1),
+            Aggregation::MONTH => ts
+                .checked_add_months(Months::new(1))
+                .ok_or_else(|| anyhow!("Add month error"))?,
+        };
     }
 
     if keys.is_empty() {
         return Ok(Vec::new());
     }
 
+    // In case rollup is enabled, the hourly buckets which have not yet been rolled up must be
+    // added to the day and month aggregates. These are read within the same transaction as the
+    // aggregates, such that the result is consistent with a concurrent rollup.
+    let pending =
+        if conf.metrics.rollup.enabled && matches!(a, Aggregation::DAY | Aggregation::MONTH) {
+            get_pending_rollup(name, a, &timestamps).await?
+        } else {
+            Vec::new()
+        };
+
     let mut pipe = redis::pipe();
+    pipe.atomic();
 
     for k in &keys {
         pipe.cmd("HGETALL").arg(k);
     }
+    for (_, hour) in &pending {
+        pipe.cmd("HGETALL")
+            .arg(get_key(name, Aggregation::HOUR, *hour));
+    }
 
-    let res: Vec<HashMap<String, f64>> =
+    let mut res: Vec<HashMap<String, f64>> =
         pipe.query_async(&mut get_async_redis_conn().await?).await?;
+    let pending_values = res.split_off(keys.len());
+
+    for ((i, _), values) in pending.iter().zip(pending_values) {
+        add_pending_rollup(kind, &mut res[*i], &values);
+    }
+
     let mut out: Vec<Record> = Vec::new();
 
     for (i, r) in res.iter().enumerate() {
@@ -307,6 +455,187 @@ pub async fn get(
     Ok(out)
 }
 
+// Returns the hourly buckets which have not yet been rolled up, as (index, hour) tuples. The
+// index refers to the given timestamps of the day or month aggregates.
+async fn get_pending_rollup(
+    name: &str,
+    a: Aggregation,
+    timestamps: &[NaiveDateTime],
+) -> Result<Vec<(usize, NaiveDateTime)>> {
+    let pending: Vec<String> = redis::cmd("ZRANGE")
+        .arg(get_rollup_key(name))
+        .arg(0)
+        .arg(-1)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let mut out: Vec<(usize, NaiveDateTime)> = Vec::new();
+    for hour in &pending {
+        let hour = NaiveDateTime::parse_from_str(hour, KEY_TIME_FORMAT)?;
+        let bucket = get_bucket_time(a, &hour)?;
+
+        if let Some(i) = timestamps.iter().position(|ts| *ts == bucket) {
+            out.push((i, hour));
+        }
+    }
+
+    Ok(out)
+}
+
+// Adds the values of the given hourly bucket which have not yet been rolled up to the metrics.
+fn add_pending_rollup(
+    kind: Kind,
+    metrics: &mut HashMap<String, f64>,
+    values: &HashMap<String, f64>,
+) {
+    for (k, v) in values {
+        if k.starts_with(ROLLED_UP_PREFIX) {
+            continue;
+        }
+
+        let v = match kind {
+            Kind::COUNTER => *v,
+            Kind::ABSOLUTE | Kind::GAUGE => {
+                v - values
+                    .get(&format!("{}{}", ROLLED_UP_PREFIX, k))
+                    .cloned()
+                    .unwrap_or_default()
+            }
+        };
+
+        aggregate_value(kind, metrics, k, v);
+    }
+}
+
+pub async fn setup() {
+    let conf = config::get();
+    if !conf.metrics.rollup.enabled {
+        return;
+    }
+
+    info!("Setting up metrics rollup loop");
+    tokio::spawn(async move {
+        rollup_loop().await;
+    });
+}
+
+async fn rollup_loop() {
+    let conf = config::get();
+
+    loop {
+        trace!("Starting metrics rollup_loop run");
+
+        match coordination::try_lock("metrics:rollup").await {
+            Ok(Some(lock)) => {
+                if let Err(e) = rollup(&lock).await {
+                    error!(error = %e.full(), "Metrics rollup error");
+                }
+
+                if let Err(e) = lock.release().await {
+                    warn!(error = %e.full(), "Releasing lock failed");
+                }
+            }
+            Ok(None) => {
+                trace!("Metrics rollup is locked by an other instance, skipping");
+            }
+            Err(e) => {
+                error!(error = %e.full(), "Acquire metrics rollup lock failed");
+            }
+        }
+
+        sleep(conf.metrics.rollup.interval).await;
+    }
+}
+
+// Rolls up the completed hourly buckets into the day and month aggregates. The lock is checked
+// before each metrics name is rolled up and the rollup is aborted in case the lock was lost.
+pub async fn rollup(lock: &coordination::Lock) -> Result<()> {
+    let conf = config::get();
+    let names_key = get_rollup_names_key();
+    let current_hour = get_bucket_time(Aggregation::HOUR, &Local::now())?;
+
+    // Names which have not been updated within the hourly TTL no longer have buckets to
+    // roll up.
+    () = redis::cmd("ZREMRANGEBYSCORE")
+        .arg(&names_key)
+        .arg("-inf")
+        .arg(Local::now().timestamp() - conf.redis.ttl.metrics_hour.as_secs() as i64)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let names: Vec<String> = redis::cmd("ZRANGE")
+        .arg(&names_key)
+        .arg(0)
+        .arg(-1)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    for n in &names {
+        let (kind, name) = n
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid metrics name: {}", n))?;
+        let kind = Kind::from_str(kind)?;
+
+        if !lock.check().await? {
+            return Err(anyhow!("Metrics rollup lock was lost"));
+        }
+
+        rollup_name(name, kind, current_hour, conf.metrics.rollup.hour_retention)
+            .await
+            .with_context(|| format!("Rollup metrics: {}", name))?;
+    }
+
+    Ok(())
+}
+
+async fn rollup_name(
+    name: &str,
+    kind: Kind,
+    before: NaiveDateTime,
+    hour_retention: Duration,
+) -> Result<()> {
+    let rollup_key = get_rollup_key(name);
+
+    let hours: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+        .arg(&rollup_key)
+        .arg("-inf")
+        .arg(format!("({}", before.and_utc().timestamp()))
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    for hour_str in &hours {
+        let hour = NaiveDateTime::parse_from_str(hour_str, KEY_TIME_FORMAT)?;
+
+        // All the keys share the same key-slot.
+        () = redis::cmd("EVAL")
+            .arg(ROLLUP_SCRIPT)
+            .arg(4)
+            .arg(get_key(name, Aggregation::HOUR, hour))
+            .arg(get_key(
+                name,
+                Aggregation::DAY,
+                get_bucket_time(Aggregation::DAY, &hour)?,
+            ))
+            .arg(get_key(
+                name,
+                Aggregation::MONTH,
+                get_bucket_time(Aggregation::MONTH, &hour)?,
+            ))
+            .arg(&rollup_key)
+            .arg(kind.to_string())
+            .arg(hour_str)
+            .arg(get_ttl(Aggregation::DAY).as_millis() as usize)
+            .arg(get_ttl(Aggregation::MONTH).as_millis() as usize)
+            .arg(hour_retention.as_millis() as usize)
+            .query_async(&mut get_async_redis_conn().await?)
+            .await?;
+
+        info!(name = %name, hour = %hour, "Metrics rolled up");
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -762,4 +1091,147 @@ pub mod test {
             resp
         );
     }
+
+    #[tokio::test]
+    async fn test_rollup() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.metrics.rollup.enabled = true;
+        config::set(conf);
+
+        let records = vec![
+            Record {
+                time: Local.with_ymd_and_hms(2018, 1, 1, 1, 1, 0).unwrap(),
+                kind: Kind::ABSOLUTE,
+                metrics: [("foo".into(), 1.0), ("bar".into(), 2.0)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            },
+            Record {
+                time: Local.with_ymd_and_hms(2018, 1, 1, 2, 1, 0).unwrap(),
+                kind: Kind::ABSOLUTE,
+                metrics: [("foo".into(), 2.0), ("bar".into(), 4.0)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            },
+        ];
+        for r in &records {
+            save("test", r, &Aggregation::default_aggregations())
+                .await
+                .unwrap();
+        }
+
+        let expected = vec![Record {
+            time: Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+            kind: Kind::ABSOLUTE,
+            metrics: [("foo".into(), 3.0), ("bar".into(), 6.0)]
+                .iter()
+                .cloned()
+                .collect(),
+        }];
+
+        // Before rollup, the pending hourly buckets are included.
+        let resp = get(
+            "test",
+            Kind::ABSOLUTE,
+            Aggregation::DAY,
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(expected, resp);
+
+        // Rollup.
+        let lock = coordination::try_lock("metrics:rollup")
+            .await
+            .unwrap()
+            .unwrap();
+        rollup(&lock).await.unwrap();
+        lock.release().await.unwrap();
+
+        let pending: Vec<String> = redis::cmd("ZRANGE")
+            .arg(get_rollup_key("test"))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut get_async_redis_conn().await.unwrap())
+            .await
+            .unwrap();
+        assert!(pending.is_empty());
+
+        // After rollup, the day and month aggregates must be equal.
+        let resp = get(
+            "test",
+            Kind::ABSOLUTE,
+            Aggregation::DAY,
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(expected, resp);
+
+        let resp = get(
+            "test",
+            Kind::ABSOLUTE,
+            Aggregation::MONTH,
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+            Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(expected, resp);
+
+        // A late write to a rolled up hourly bucket is only counted once.
+        save(
+            "test",
+            &Record {
+                time: Local.with_ymd_and_hms(2018, 1, 1, 1, 2, 0).unwrap(),
+                kind: Kind::ABSOLUTE,
+                metrics: [("foo".into(), 1.0), ("bar".into(), 1.0)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            },
+            &Aggregation::default_aggregations(),
+        )
+        .await
+        .unwrap();
+
+        let expected = vec![Record {
+            time: Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+            kind: Kind::ABSOLUTE,
+            metrics: [("foo".into(), 4.0), ("bar".into(), 7.0)]
+                .iter()
+                .cloned()
+                .collect(),
+        }];
+
+        for i in 0..2 {
+            for a in [Aggregation::DAY, Aggregation::MONTH] {
+                let resp = get(
+                    "test",
+                    Kind::ABSOLUTE,
+                    a,
+                    Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+                    Local.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap(),
+                )
+                .await
+                .unwrap();
+                assert_eq!(expected, resp);
+            }
+
+            if i == 0 {
+                let lock = coordination::try_lock("metrics:rollup")
+                    .await
+                    .unwrap()
+                    .unwrap();
+                rollup(&lock).await.unwrap();
+                lock.release().await.unwrap();
+            }
+        }
+    }
 }
@@ -14,26 +14,43 @@ use crate::config;
 
 pub mod api_key;
 pub mod application;
+pub mod backend_async_answer;
+#[cfg(feature = "postgres")]
+pub mod backup;
+pub mod cache;
+pub mod codec_library;
+pub mod data_encryption_key;
 pub mod device;
 pub mod device_gateway;
 pub mod device_keys;
 pub mod device_profile;
 pub mod device_profile_template;
 pub mod device_queue;
+pub mod device_repository_codec;
 pub mod device_session;
 pub mod downlink_frame;
 pub mod error;
 pub mod fields;
 pub mod fuota;
 pub mod gateway;
+pub mod gateway_certificate;
+pub mod handover_roaming;
 pub mod helpers;
+mod instrumentation;
+pub mod join_server_route;
+pub mod key_access_log;
 pub mod mac_command;
+#[cfg(feature = "bench-storage")]
+pub mod memory;
 pub mod metrics;
 pub mod multicast;
 pub mod passive_roaming;
+pub mod ping_slot;
 #[cfg(feature = "postgres")]
 mod postgres;
 pub mod relay;
+pub mod roaming_agreement;
+pub mod roaming_usage;
 pub mod schema;
 #[cfg(feature = "postgres")]
 mod schema_postgres;
@@ -43,7 +60,11 @@ pub mod search;
 #[cfg(feature = "sqlite")]
 mod sqlite;
 pub mod tenant;
+pub mod tenant_role;
+pub mod tenant_sla_report;
+pub mod tenant_usage;
 pub mod user;
+pub mod user_session;
 
 use crate::monitoring::prometheus;
 
@@ -68,7 +89,8 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations_sqlit
 
 #[cfg(feature = "postgres")]
 pub use postgres::{
-    db_transaction, get_async_db_conn, AsyncPgPoolConnection as AsyncDbPoolConnection,
+    db_transaction, get_async_db_conn, get_tls_connector,
+    AsyncPgPoolConnection as AsyncDbPoolConnection,
 };
 #[cfg(feature = "sqlite")]
 pub use sqlite::{
@@ -91,10 +113,20 @@ impl ConnectionLike for AsyncRedisPoolConnection {
         &'a mut self,
         cmd: &'a redis::Cmd,
     ) -> redis::RedisFuture<'a, redis::Value> {
-        match self {
+        let fut = match self {
             AsyncRedisPoolConnection::Client(v) => v.req_packed_command(cmd),
             AsyncRedisPoolConnection::ClusterClient(v) => v.req_packed_command(cmd),
-        }
+        };
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+            instrumentation::observe_redis_command(
+                &instrumentation::redis_command_name(cmd),
+                start.elapsed(),
+            );
+            res
+        })
     }
     fn req_packed_commands<'a>(
         &'a mut self,
@@ -102,10 +134,17 @@ impl ConnectionLike for AsyncRedisPoolConnection {
         offset: usize,
         count: usize,
     ) -> redis::RedisFuture<'a, Vec<redis::Value>> {
-        match self {
+        let fut = match self {
             AsyncRedisPoolConnection::Client(v) => v.req_packed_commands(cmd, offset, count),
             AsyncRedisPoolConnection::ClusterClient(v) => v.req_packed_commands(cmd, offset, count),
-        }
+        };
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+            instrumentation::observe_redis_command("PIPELINE", start.elapsed());
+            res
+        })
     }
     fn get_db(&self) -> i64 {
         match self {
@@ -151,6 +190,9 @@ pub async fn setup() -> Result<()> {
             .clone_from(&conf.redis.key_prefix);
     }
 
+    crate::encryption::setup().await?;
+    crate::keystore::setup()?;
+
     Ok(())
 }
 
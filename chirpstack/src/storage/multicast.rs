@@ -1,23 +1,28 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
 use diesel::{dsl, prelude::*};
 use diesel_async::RunQueryDsl;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+use chirpstack_api::gw;
 use lrwn::region::CommonName;
 use lrwn::{AES128Key, DevAddr, EUI64};
 
+use super::device_profile::DeviceProfile;
 use super::error::Error;
 use super::schema::{
-    application, device, gateway, multicast_group, multicast_group_device, multicast_group_gateway,
-    multicast_group_queue_item,
+    application, device, device_profile, gateway, multicast_group, multicast_group_device,
+    multicast_group_gateway, multicast_group_queue_item,
 };
-use super::{db_transaction, fields, get_async_db_conn};
-use crate::downlink::classb;
-use crate::{config, gpstime::ToDateTime, gpstime::ToGpsTime};
+use super::{db_transaction, fields, get_async_db_conn, ping_slot};
+use crate::downlink::{classb, helpers};
+use crate::helpers::errors::PrintFullError;
+use crate::{config, geolocation, gpstime::ToDateTime, gpstime::ToGpsTime, region};
 
-#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq)]
 #[diesel(table_name = multicast_group)]
 pub struct MulticastGroup {
     pub id: fields::Uuid,
@@ -35,6 +40,10 @@ pub struct MulticastGroup {
     pub frequency: i64,
     pub class_b_ping_slot_nb_k: i16,
     pub class_c_scheduling_type: fields::MulticastGroupSchedulingType,
+    pub geofence_latitude: f64,
+    pub geofence_longitude: f64,
+    pub geofence_radius: i32,
+    pub gateway_tags: fields::KeyValue,
 }
 
 impl MulticastGroup {
@@ -42,8 +51,49 @@ impl MulticastGroup {
         if self.name.is_empty() {
             return Err(Error::Validation("name is not set".into()));
         }
+        if self.geofence_radius < 0 {
+            return Err(Error::Validation(
+                "geofence_radius must not be negative".into(),
+            ));
+        }
+        if !(-90.0..=90.0).contains(&self.geofence_latitude)
+            || !(-180.0..=180.0).contains(&self.geofence_longitude)
+        {
+            return Err(Error::Validation("Invalid geofence location".into()));
+        }
         Ok(())
     }
+
+    // Returns true when the gateway with the given location and tags may be used for
+    // transmitting the multicast downlinks. When a geofence is configured, the gateway must be
+    // within the geofence radius. Gateways without location are excluded in this case. When
+    // gateway tags are configured, the gateway must have all these tags.
+    pub fn is_gateway_allowed(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        tags: &HashMap<String, String>,
+    ) -> bool {
+        if self.geofence_radius > 0 {
+            if latitude == 0.0 && longitude == 0.0 {
+                return false;
+            }
+
+            if geolocation::get_distance(
+                self.geofence_latitude,
+                self.geofence_longitude,
+                latitude,
+                longitude,
+            ) > self.geofence_radius as f64
+            {
+                return false;
+            }
+        }
+
+        self.gateway_tags
+            .iter()
+            .all(|(k, v)| tags.get(k) == Some(v))
+    }
 }
 
 impl Default for MulticastGroup {
@@ -66,6 +116,10 @@ impl Default for MulticastGroup {
             frequency: 0,
             class_b_ping_slot_nb_k: 0,
             class_c_scheduling_type: fields::MulticastGroupSchedulingType::DELAY,
+            geofence_latitude: 0.0,
+            geofence_longitude: 0.0,
+            geofence_radius: 0,
+            gateway_tags: fields::KeyValue::new(HashMap::new()),
         }
     }
 }
@@ -169,6 +223,10 @@ pub async fn update(mg: MulticastGroup) -> Result<MulticastGroup, Error> {
             multicast_group::frequency.eq(&mg.frequency),
             multicast_group::class_b_ping_slot_nb_k.eq(&mg.class_b_ping_slot_nb_k),
             multicast_group::class_c_scheduling_type.eq(&mg.class_c_scheduling_type),
+            multicast_group::geofence_latitude.eq(&mg.geofence_latitude),
+            multicast_group::geofence_longitude.eq(&mg.geofence_longitude),
+            multicast_group::geofence_radius.eq(&mg.geofence_radius),
+            multicast_group::gateway_tags.eq(&mg.gateway_tags),
         ))
         .get_result(&mut get_async_db_conn().await?)
         .await
@@ -404,6 +462,190 @@ pub async fn get_gateway_ids(group_id: &Uuid) -> Result<Vec<EUI64>, Error> {
         .map_err(|e| Error::from_diesel(e, group_id.to_string()))
 }
 
+// Multicast parameters which a device is able to receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Parameters {
+    dr: u8,
+    frequency: u32,
+}
+
+// Returns the given gateway IDs, filtered by the geofence and gateway tags of the
+// multicast-group.
+pub async fn filter_gateway_ids(
+    mg: &MulticastGroup,
+    gateway_ids: &[EUI64],
+) -> Result<Vec<EUI64>, Error> {
+    if mg.geofence_radius == 0 && mg.gateway_tags.is_empty() {
+        return Ok(gateway_ids.to_vec());
+    }
+
+    let items: Vec<(EUI64, f64, f64, fields::KeyValue)> = gateway::dsl::gateway
+        .select((
+            gateway::dsl::gateway_id,
+            gateway::dsl::latitude,
+            gateway::dsl::longitude,
+            gateway::dsl::tags,
+        ))
+        .filter(gateway::dsl::gateway_id.eq_any(gateway_ids))
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+
+    Ok(gateway_ids
+        .iter()
+        .filter(|gateway_id| {
+            items.iter().any(|(id, latitude, longitude, tags)| {
+                id == *gateway_id && mg.is_gateway_allowed(*latitude, *longitude, tags)
+            })
+        })
+        .cloned()
+        .collect())
+}
+
+// This selects the DR, frequency and (Class-B) ping-slot periodicity of the multicast-group based
+// on the region configuration and the device-profiles of the devices within the multicast-group.
+// It returns an error in case not all devices are able to receive the selected parameters.
+pub async fn set_parameters(mg: &mut MulticastGroup) -> Result<(), Error> {
+    let dev_euis = get_dev_euis(&mg.id.into()).await?;
+    let items: Vec<(EUI64, DeviceProfile)> = device::table
+        .inner_join(device_profile::table)
+        .select((device::dsl::dev_eui, device_profile::all_columns))
+        .filter(device::dsl::dev_eui.eq_any(&dev_euis))
+        .order_by(device::dsl::dev_eui)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+
+    let mut params: Option<Parameters> = None;
+    let mut ping_slot_nb_k: Option<u8> = None;
+    let mut errors: Vec<String> = Vec::new();
+
+    for (dev_eui, dp) in &items {
+        if dp.region != mg.region {
+            errors.push(format!(
+                "{}: region {} is not supported",
+                dev_eui, mg.region
+            ));
+            continue;
+        }
+
+        let supports_group_type = match mg.group_type.as_ref() {
+            "B" => dp.supports_class_b,
+            "C" => dp.supports_class_c,
+            _ => false,
+        };
+        if !supports_group_type {
+            errors.push(format!(
+                "{}: Class-{} is not supported",
+                dev_eui, mg.group_type
+            ));
+            continue;
+        }
+
+        let p = get_device_parameters(mg, dp)?;
+        match params {
+            None => params = Some(p),
+            Some(v) if v != p => {
+                errors.push(format!(
+                    "{}: can not receive dr: {}, frequency: {}",
+                    dev_eui, v.dr, v.frequency
+                ));
+                continue;
+            }
+            _ => {}
+        }
+
+        if mg.group_type == "B" {
+            if let Some(class_b_params) = &dp.class_b_params {
+                ping_slot_nb_k = Some(
+                    ping_slot_nb_k
+                        .unwrap_or(class_b_params.ping_slot_nb_k)
+                        .min(class_b_params.ping_slot_nb_k),
+                );
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(Error::Validation(format!(
+            "Not all devices are able to receive the multicast parameters ({})",
+            errors.join(", ")
+        )));
+    }
+
+    // In case the multicast-group does not contain any devices, the parameters are based on the
+    // region configuration.
+    let p = match params {
+        Some(v) => v,
+        None => get_region_parameters(mg, &region::get_region_config_id(mg.region)?)?,
+    };
+
+    mg.dr = p.dr as i16;
+    mg.frequency = p.frequency as i64;
+    if let Some(k) = ping_slot_nb_k {
+        mg.class_b_ping_slot_nb_k = k as i16;
+    }
+
+    Ok(())
+}
+
+fn get_device_parameters(mg: &MulticastGroup, dp: &DeviceProfile) -> Result<Parameters> {
+    let region_config_id = match &dp.region_config_id {
+        Some(v) => v.clone(),
+        None => region::get_region_config_id(dp.region)?,
+    };
+    let mut p = get_region_parameters(mg, &region_config_id)?;
+
+    // The device-profile Class-B parameters override the region configuration.
+    if mg.group_type == "B" {
+        if let Some(class_b_params) = &dp.class_b_params {
+            p.dr = class_b_params.ping_slot_dr;
+            if class_b_params.ping_slot_freq != 0 {
+                p.frequency = class_b_params.ping_slot_freq;
+            }
+        }
+    }
+
+    Ok(p)
+}
+
+fn get_region_parameters(mg: &MulticastGroup, region_config_id: &str) -> Result<Parameters> {
+    let network_conf = config::get_region_network(region_config_id)?;
+    let region_conf = region::get(region_config_id)?;
+
+    Ok(match mg.group_type.as_ref() {
+        "B" => Parameters {
+            dr: network_conf.class_b.ping_slot_dr,
+            frequency: if network_conf.class_b.ping_slot_frequency != 0 {
+                network_conf.class_b.ping_slot_frequency
+            } else {
+                get_default_ping_slot_frequency(&**region_conf, mg.mc_addr)?
+            },
+        },
+        "C" => Parameters {
+            dr: network_conf.rx2_dr,
+            frequency: if network_conf.rx2_frequency != 0 {
+                network_conf.rx2_frequency
+            } else {
+                region_conf.get_defaults().rx2_frequency
+            },
+        },
+        _ => return Err(anyhow!("Invalid multicast-group type")),
+    })
+}
+
+// Returns the default ping-slot frequency of the region. In case the ping-slot frequency hops
+// per beacon-period (e.g. US915), this returns 0 as the frequency is then derived from the
+// ping-slot timestamp when the downlink is sent.
+fn get_default_ping_slot_frequency(
+    region_conf: &(dyn lrwn::region::Region + Sync + Send),
+    mc_addr: DevAddr,
+) -> Result<u32> {
+    let freq = region_conf.get_ping_slot_frequency(mc_addr, std::time::Duration::ZERO)?;
+    let next_freq =
+        region_conf.get_ping_slot_frequency(mc_addr, std::time::Duration::from_secs(128))?;
+
+    Ok(if freq == next_freq { freq } else { 0 })
+}
+
 // This enqueues a multicast-group queue item for the given gateways and returns the frame-counter
 // of the multicast downlink.
 // This function locks the multicast-group to avoid race-conditions with scheduling time and
@@ -415,7 +657,10 @@ pub async fn enqueue(
     qi.validate()?;
     let mut c = get_async_db_conn().await?;
     let conf = config::get();
-    let (ids, f_cnt) = db_transaction::<(Vec<Uuid>, u32), Error, _>(&mut c, |c| {
+    // The ping-slot reservation (Class-B) must be released when the queue item is not created.
+    let mut reservation: Option<ping_slot::Reservation> = None;
+    let reservation_ref = &mut reservation;
+    let res = db_transaction::<(Vec<Uuid>, u32), Error, _>(&mut c, |c| {
         Box::pin(async move {
             let mut ids: Vec<Uuid> = Vec::new();
             let query = multicast_group::dsl::multicast_group.find(&qi.multicast_group_id);
@@ -453,11 +698,31 @@ pub async fn enqueue(
                         .to_gps_time(),
                     };
 
-                    let emit_at_time_since_gps_epoch = classb::get_next_ping_slot_after(
-                        ping_slot_after_gps_time,
-                        &mg.mc_addr,
-                        ping_nb,
+                    // Get the duration of the transmission (MHDR + FHDR + FPort + FRMPayload
+                    // + MIC), such that the ping-slot does not conflict with other Class-B
+                    // downlinks scheduled for the same gateways.
+                    let region_conf = region::get(&region::get_region_config_id(mg.region)?)?;
+                    let mut tx_info = gw::DownlinkTxInfo::default();
+                    helpers::set_tx_info_data_rate(
+                        &mut tx_info,
+                        &region_conf.get_data_rate(mg.dr as u8)?,
                     )?;
+                    let duration = classb::get_transmission_duration(&tx_info, qi.data.len() + 13);
+
+                    // The same ping-slot is used for all gateways, such that the gateways
+                    // transmit simultaneously within the same beacon-period.
+                    let emit_at_time_since_gps_epoch = reservation_ref
+                        .insert(
+                            classb::reserve_next_free_ping_slot_after(
+                                ping_slot_after_gps_time,
+                                &mg.mc_addr,
+                                ping_nb,
+                                gateway_ids,
+                                duration,
+                            )
+                            .await?,
+                        )
+                        .start;
 
                     let scheduler_run_after_ts = emit_at_time_since_gps_epoch.to_date_time()
                         - Duration::from_std(2 * conf.network.scheduler.interval).unwrap();
@@ -585,7 +850,17 @@ pub async fn enqueue(
             Ok((ids, mg.f_cnt as u32))
         })
     })
-    .await?;
+    .await;
+
+    if res.is_err() {
+        if let Some(reservation) = reservation {
+            if let Err(e) = reservation.release().await {
+                warn!(error = %e.full(), "Releasing ping-slot failed");
+            }
+        }
+    }
+
+    let (ids, f_cnt) = res?;
     info!(multicast_group_id = %qi.multicast_group_id, f_cnt = f_cnt, "Multicast-group queue item created");
     Ok((ids, f_cnt))
 }
@@ -903,6 +1178,115 @@ pub mod test {
         assert!(dev_euis.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_set_parameters() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp = device_profile::create(device_profile::DeviceProfile {
+            tenant_id: t.id,
+            name: "test-dp".into(),
+            supports_class_b: true,
+            class_b_params: Some(fields::ClassBParams {
+                ping_slot_nb_k: 2,
+                ping_slot_dr: 3,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let dp_class_a = device_profile::create(device_profile::DeviceProfile {
+            tenant_id: t.id,
+            name: "test-dp-class-a".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let d = device::create(device::Device {
+            application_id: app.id,
+            device_profile_id: dp.id,
+            name: "test-device".into(),
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let d_class_a = device::create(device::Device {
+            application_id: app.id,
+            device_profile_id: dp_class_a.id,
+            name: "test-device-class-a".into(),
+            dev_eui: EUI64::from_be_bytes([2, 2, 3, 4, 5, 6, 7, 8]),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut mg = create(MulticastGroup {
+            application_id: app.id,
+            name: "test-mg".into(),
+            region: CommonName::EU868,
+            group_type: "B".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // without devices, the region configuration is used
+        set_parameters(&mut mg).await.unwrap();
+        assert_eq!(0, mg.dr);
+        assert_eq!(868100000, mg.frequency);
+        assert_eq!(0, mg.class_b_ping_slot_nb_k);
+
+        // the device-profile parameters are used
+        add_device(&mg.id.into(), &d.dev_eui).await.unwrap();
+        set_parameters(&mut mg).await.unwrap();
+        assert_eq!(3, mg.dr);
+        assert_eq!(868100000, mg.frequency);
+        assert_eq!(2, mg.class_b_ping_slot_nb_k);
+
+        // device not supporting Class-B
+        add_device(&mg.id.into(), &d_class_a.dev_eui).await.unwrap();
+        assert!(set_parameters(&mut mg).await.is_err());
+    }
+
+    #[test]
+    fn test_get_default_ping_slot_frequency() {
+        let mc_addr = DevAddr::from_be_bytes([1, 2, 3, 4]);
+
+        let region_conf = lrwn::region::get(CommonName::EU868, false, false);
+        assert_eq!(
+            869525000,
+            get_default_ping_slot_frequency(&*region_conf, mc_addr).unwrap()
+        );
+
+        // the ping-slot frequency hops per beacon-period
+        for common_name in [CommonName::US915, CommonName::AU915, CommonName::CN470] {
+            let region_conf = lrwn::region::get(common_name, false, false);
+            assert_eq!(
+                0,
+                get_default_ping_slot_frequency(&*region_conf, mc_addr).unwrap()
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_gateway() {
         let _guard = test::prepare().await;
@@ -961,6 +1345,141 @@ pub mod test {
         assert!(gw_ids.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_filter_gateway_ids() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            can_have_gateways: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Amsterdam
+        let gw_a = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([1, 1, 1, 1, 1, 1, 1, 1]),
+            tenant_id: t.id,
+            name: "gw-a".into(),
+            latitude: 52.3676,
+            longitude: 4.9041,
+            tags: fields::KeyValue::new(
+                [("region".to_string(), "north".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Utrecht
+        let gw_b = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([2, 2, 2, 2, 2, 2, 2, 2]),
+            tenant_id: t.id,
+            name: "gw-b".into(),
+            latitude: 52.0907,
+            longitude: 5.1214,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // Paris
+        let gw_c = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([3, 3, 3, 3, 3, 3, 3, 3]),
+            tenant_id: t.id,
+            name: "gw-c".into(),
+            latitude: 48.8566,
+            longitude: 2.3522,
+            tags: fields::KeyValue::new(
+                [("region".to_string(), "north".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // No location
+        let gw_d = gateway::create(gateway::Gateway {
+            gateway_id: EUI64::from_be_bytes([4, 4, 4, 4, 4, 4, 4, 4]),
+            tenant_id: t.id,
+            name: "gw-d".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let gateway_ids = vec![
+            gw_a.gateway_id,
+            gw_b.gateway_id,
+            gw_c.gateway_id,
+            gw_d.gateway_id,
+        ];
+
+        struct Test {
+            name: String,
+            multicast_group: MulticastGroup,
+            expected_gateway_ids: Vec<EUI64>,
+        }
+
+        let tests = vec![
+            Test {
+                name: "no filter".into(),
+                multicast_group: MulticastGroup::default(),
+                expected_gateway_ids: gateway_ids.clone(),
+            },
+            Test {
+                name: "geofence".into(),
+                multicast_group: MulticastGroup {
+                    geofence_latitude: 52.3676,
+                    geofence_longitude: 4.9041,
+                    geofence_radius: 50_000,
+                    ..Default::default()
+                },
+                expected_gateway_ids: vec![gw_a.gateway_id, gw_b.gateway_id],
+            },
+            Test {
+                name: "gateway tags".into(),
+                multicast_group: MulticastGroup {
+                    gateway_tags: fields::KeyValue::new(
+                        [("region".to_string(), "north".to_string())]
+                            .into_iter()
+                            .collect(),
+                    ),
+                    ..Default::default()
+                },
+                expected_gateway_ids: vec![gw_a.gateway_id, gw_c.gateway_id],
+            },
+            Test {
+                name: "geofence and gateway tags".into(),
+                multicast_group: MulticastGroup {
+                    geofence_latitude: 52.3676,
+                    geofence_longitude: 4.9041,
+                    geofence_radius: 50_000,
+                    gateway_tags: fields::KeyValue::new(
+                        [("region".to_string(), "north".to_string())]
+                            .into_iter()
+                            .collect(),
+                    ),
+                    ..Default::default()
+                },
+                expected_gateway_ids: vec![gw_a.gateway_id],
+            },
+        ];
+
+        for tst in &tests {
+            println!("> {}", tst.name);
+            let out = filter_gateway_ids(&tst.multicast_group, &gateway_ids)
+                .await
+                .unwrap();
+            assert_eq!(tst.expected_gateway_ids, out);
+        }
+    }
+
     #[tokio::test]
     async fn test_queue() {
         let _guard = test::prepare().await;
@@ -11,9 +11,25 @@ use super::error::Error;
 use super::{get_async_redis_conn, redis_key};
 use crate::config;
 use chirpstack_api::internal;
-use lrwn::{AES128Key, DevAddr, EUI64};
+use lrwn::{AES128Key, DevAddr, NetID, EUI64};
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    pub net_id: Option<NetID>,
+    pub dev_eui: Option<EUI64>,
+}
 
 pub async fn save(ds: &internal::PassiveRoamingDeviceSession) -> Result<()> {
+    store(ds, false).await
+}
+
+// Updates the passive-roaming device-session, only when it still exists. This makes sure that a
+// session which has been deleted (e.g. terminated through the API) is not re-created.
+pub async fn update(ds: &internal::PassiveRoamingDeviceSession) -> Result<()> {
+    store(ds, true).await
+}
+
+async fn store(ds: &internal::PassiveRoamingDeviceSession, update: bool) -> Result<()> {
     let sess_id = Uuid::from_slice(&ds.session_id)?;
     let dev_addr = DevAddr::from_slice(&ds.dev_addr)?;
     let dev_eui = if ds.dev_eui.is_empty() {
@@ -41,9 +57,11 @@ pub async fn save(ds: &internal::PassiveRoamingDeviceSession) -> Result<()> {
     let dev_addr_key = redis_key(format!("pr:devaddr:{{{}}}", dev_addr));
     let dev_eui_key = redis_key(format!("pr:dev:{{{}}}", dev_eui));
     let sess_key = redis_key(format!("pr:sess:{{{}}}", sess_id));
+    let index_key = redis_key("pr:sess:index".to_string());
     let b = ds.encode_to_vec();
-    let ttl = conf.network.device_session_ttl.as_millis() as usize;
+    let ttl = config::get_device_ttl(conf.redis.ttl.passive_roaming_session).as_millis() as usize;
     let pr_ttl = lifetime.num_milliseconds() as usize;
+    let expires_at = (Utc::now() + lifetime).timestamp_millis();
 
     // We need to store a pointer from both the DevAddr and DevEUI to the
     // passive-roaming device-session ID. This is needed:
@@ -55,31 +73,58 @@ pub async fn save(ds: &internal::PassiveRoamingDeviceSession) -> Result<()> {
     //  * We need to be able to lookup the session using the DevAddr (potentially
     //    using the MIC validation).
     //  * We need to be able to stop a passive-roaming session given a DevEUI.
-    () = redis::pipe()
-        .atomic()
-        .cmd("SADD")
-        .arg(&dev_addr_key)
-        .arg(sess_id.to_string())
-        .ignore()
-        .cmd("SADD")
-        .arg(&dev_eui_key)
-        .arg(sess_id.to_string())
-        .ignore()
-        .cmd("PEXPIRE")
+    //
+    // The index (sorted by expiration) is used for listing the active sessions.
+    //
+    // On update, the pointers are not re-added and the session and index are only written
+    // when these still exist.
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    if !update {
+        pipe.cmd("SADD")
+            .arg(&dev_addr_key)
+            .arg(sess_id.to_string())
+            .ignore()
+            .cmd("SADD")
+            .arg(&dev_eui_key)
+            .arg(sess_id.to_string())
+            .ignore();
+    }
+    pipe.cmd("PEXPIRE")
         .arg(&dev_addr_key)
         .arg(ttl)
         .ignore()
         .cmd("PEXPIRE")
         .arg(&dev_eui_key)
         .arg(ttl)
-        .ignore()
-        .cmd("PSETEX")
-        .arg(&sess_key)
-        .arg(pr_ttl)
-        .arg(b)
-        .ignore()
-        .query_async(&mut get_async_redis_conn().await?)
-        .await?;
+        .ignore();
+    if update {
+        pipe.cmd("SET")
+            .arg(&sess_key)
+            .arg(b)
+            .arg("PX")
+            .arg(pr_ttl)
+            .arg("XX")
+            .ignore()
+            .cmd("ZADD")
+            .arg(&index_key)
+            .arg("XX")
+            .arg(expires_at)
+            .arg(sess_id.to_string())
+            .ignore();
+    } else {
+        pipe.cmd("PSETEX")
+            .arg(&sess_key)
+            .arg(pr_ttl)
+            .arg(b)
+            .ignore()
+            .cmd("ZADD")
+            .arg(&index_key)
+            .arg(expires_at)
+            .arg(sess_id.to_string())
+            .ignore();
+    }
+    () = pipe.query_async(&mut get_async_redis_conn().await?).await?;
 
     info!(id = %sess_id, "Passive-roaming device-session saved");
 
@@ -104,16 +149,118 @@ pub async fn get(id: Uuid) -> Result<internal::PassiveRoamingDeviceSession, Erro
 
 pub async fn delete(id: Uuid) -> Result<()> {
     let key = redis_key(format!("pr:sess:{{{}}}", id));
+    let index_key = redis_key("pr:sess:index".to_string());
 
-    () = redis::cmd("DEL")
+    let mut pipe = redis::pipe();
+    pipe.atomic()
+        .cmd("DEL")
         .arg(&key)
-        .query_async(&mut get_async_redis_conn().await?)
-        .await?;
+        .ignore()
+        .cmd("ZREM")
+        .arg(&index_key)
+        .arg(id.to_string())
+        .ignore();
+
+    // Remove the DevAddr and DevEUI pointers, in case the session still exists.
+    if let Ok(ds) = get(id).await {
+        let dev_addr = DevAddr::from_slice(&ds.dev_addr)?;
+        let dev_eui = if ds.dev_eui.is_empty() {
+            EUI64::default()
+        } else {
+            EUI64::from_slice(&ds.dev_eui)?
+        };
+
+        pipe.cmd("SREM")
+            .arg(redis_key(format!("pr:devaddr:{{{}}}", dev_addr)))
+            .arg(id.to_string())
+            .ignore()
+            .cmd("SREM")
+            .arg(redis_key(format!("pr:dev:{{{}}}", dev_eui)))
+            .arg(id.to_string())
+            .ignore();
+    }
+
+    () = pipe.query_async(&mut get_async_redis_conn().await?).await?;
 
     info!(id = %id, "Passive-roaming device-session deleted");
     Ok(())
 }
 
+pub async fn get_count(filters: &Filters) -> Result<usize> {
+    Ok(get_sessions(filters).await?.len())
+}
+
+// Returns the active passive-roaming device-sessions, ordered by the session lifetime.
+pub async fn list(
+    limit: usize,
+    offset: usize,
+    filters: &Filters,
+) -> Result<Vec<internal::PassiveRoamingDeviceSession>> {
+    Ok(get_sessions(filters)
+        .await?
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .collect())
+}
+
+async fn get_sessions(filters: &Filters) -> Result<Vec<internal::PassiveRoamingDeviceSession>> {
+    let index_key = redis_key("pr:sess:index".to_string());
+
+    // Remove the expired sessions from the index.
+    () = redis::cmd("ZREMRANGEBYSCORE")
+        .arg(&index_key)
+        .arg("-inf")
+        .arg(Utc::now().timestamp_millis())
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    let ids: Vec<String> = redis::cmd("ZRANGE")
+        .arg(&index_key)
+        .arg(0)
+        .arg(-1)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await?;
+
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut pipe = redis::pipe();
+    for id in &ids {
+        pipe.cmd("GET")
+            .arg(redis_key(format!("pr:sess:{{{}}}", Uuid::from_str(id)?)));
+    }
+    let values: Vec<Vec<u8>> = pipe.query_async(&mut get_async_redis_conn().await?).await?;
+
+    let mut out: Vec<internal::PassiveRoamingDeviceSession> = Vec::new();
+    for v in values {
+        // The session has expired or has been deleted.
+        if v.is_empty() {
+            continue;
+        }
+
+        let ds = internal::PassiveRoamingDeviceSession::decode(&mut Cursor::new(v))
+            .context("Decode passive-roaming device-session")?;
+
+        if let Some(net_id) = &filters.net_id {
+            if ds.net_id != net_id.to_vec() {
+                continue;
+            }
+        }
+
+        if let Some(dev_eui) = &filters.dev_eui {
+            if ds.dev_eui != dev_eui.to_vec() {
+                continue;
+            }
+        }
+
+        out.push(ds);
+    }
+
+    Ok(out)
+}
+
 pub async fn get_for_phy_payload(
     phy: &lrwn::PhyPayload,
 ) -> Result<Vec<internal::PassiveRoamingDeviceSession>, Error> {
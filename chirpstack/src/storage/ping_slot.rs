@@ -0,0 +1,201 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use tracing::debug;
+
+use super::{get_async_redis_conn, redis_key};
+use crate::gpstime::{ToDateTime, ToGpsTime};
+use lrwn::EUI64;
+
+// Class-B ping-slot transmissions are reserved per gateway, such that unicast and multicast
+// Class-B downlinks scheduled for the same gateway do not overlap. The reservations are stored
+// as sorted-set, scored by the end of the transmission (milliseconds since GPS epoch), with
+// "start:end" as member.
+
+// Removes the reservations which ended already and adds the reservation, unless it overlaps
+// with an existing reservation. The expiration of the key is set to the end of the last
+// reservation.
+//
+// KEYS[1]: reservations key
+// ARGV[1]: start (ms since GPS epoch)
+// ARGV[2]: end (ms since GPS epoch)
+// ARGV[3]: now (ms since GPS epoch)
+// ARGV[4]: end (ms since Unix epoch)
+const RESERVE_SCRIPT: &str = r#"
+    redis.call("ZREMRANGEBYSCORE", KEYS[1], "-inf", ARGV[3])
+
+    for _, member in ipairs(redis.call("ZRANGEBYSCORE", KEYS[1], "(" .. ARGV[1], "+inf")) do
+        local reserved_start = tonumber(string.match(member, "^(-?%d+):"))
+        if reserved_start == nil or reserved_start < tonumber(ARGV[2]) then
+            return 0
+        end
+    end
+
+    redis.call("ZADD", KEYS[1], ARGV[2], ARGV[1] .. ":" .. ARGV[2])
+
+    local last = redis.call("ZRANGE", KEYS[1], -1, -1, "WITHSCORES")
+    if tonumber(last[2]) <= tonumber(ARGV[2]) then
+        redis.call("PEXPIREAT", KEYS[1], ARGV[4])
+    end
+
+    return 1
+"#;
+
+// Ping-slot reservation for one or multiple gateways.
+pub struct Reservation {
+    pub start: Duration,
+    end: Duration,
+    gateway_ids: Vec<EUI64>,
+}
+
+impl Reservation {
+    // Releases the reservation, e.g. when the downlink is not sent.
+    pub async fn release(self) -> Result<()> {
+        if self.gateway_ids.is_empty() {
+            return Ok(());
+        }
+
+        // The reservations are stored under different key-slots, therefore a non-atomic
+        // pipeline is used.
+        let mut pipe = redis::pipe();
+        for gateway_id in &self.gateway_ids {
+            pipe.cmd("ZREM")
+                .arg(get_key(gateway_id))
+                .arg(get_member(self.start, self.end))
+                .ignore();
+        }
+
+        () = pipe
+            .query_async(&mut get_async_redis_conn().await?)
+            .await
+            .context("Release ping-slot")?;
+
+        debug!(gateway_ids = ?self.gateway_ids, start_ms = self.start.num_milliseconds(), end_ms = self.end.num_milliseconds(), "Ping-slot released");
+        Ok(())
+    }
+}
+
+// Reserves the transmission window starting at the given ping-slot for all the given gateways.
+// The reservation is atomic per gateway. In case the window overlaps with an existing
+// reservation for any of the gateways, no reservation is made and None is returned.
+pub async fn reserve(
+    gateway_ids: &[EUI64],
+    start: Duration,
+    duration: Duration,
+) -> Result<Option<Reservation>> {
+    let end = start + duration;
+    let mut reservation = Reservation {
+        start,
+        end,
+        gateway_ids: Vec::new(),
+    };
+
+    if gateway_ids.is_empty() {
+        return Ok(Some(reservation));
+    }
+
+    // The reservations are stored under different key-slots, therefore a non-atomic pipeline
+    // is used.
+    let now = Utc::now().to_gps_time();
+    let mut pipe = redis::pipe();
+    for gateway_id in gateway_ids {
+        pipe.cmd("EVAL")
+            .arg(RESERVE_SCRIPT)
+            .arg(1)
+            .arg(get_key(gateway_id))
+            .arg(start.num_milliseconds())
+            .arg(end.num_milliseconds())
+            .arg(now.num_milliseconds())
+            .arg(end.to_date_time().timestamp_millis());
+    }
+
+    let res: Vec<i64> = pipe
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Reserve ping-slot")?;
+
+    reservation.gateway_ids = gateway_ids
+        .iter()
+        .zip(res)
+        .filter(|(_, v)| *v == 1)
+        .map(|(gateway_id, _)| *gateway_id)
+        .collect();
+
+    // Release the partial reservation.
+    if reservation.gateway_ids.len() != gateway_ids.len() {
+        reservation.release().await?;
+        return Ok(None);
+    }
+
+    debug!(gateway_ids = ?gateway_ids, start_ms = start.num_milliseconds(), end_ms = end.num_milliseconds(), "Ping-slot reserved");
+    Ok(Some(reservation))
+}
+
+fn get_member(start: Duration, end: Duration) -> String {
+    format!("{}:{}", start.num_milliseconds(), end.num_milliseconds())
+}
+
+fn get_key(gateway_id: &EUI64) -> String {
+    redis_key(format!("gw:{{{}}}:ping_slots", gateway_id))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_ping_slot() {
+        let _guard = test::prepare().await;
+
+        let gw_a = EUI64::from_be_bytes([1, 1, 1, 1, 1, 1, 1, 1]);
+        let gw_b = EUI64::from_be_bytes([2, 2, 2, 2, 2, 2, 2, 2]);
+        let start = Utc::now().to_gps_time() + Duration::try_seconds(10).unwrap();
+        let duration = Duration::try_milliseconds(500).unwrap();
+
+        let reservation = reserve(&[gw_a], start, duration).await.unwrap().unwrap();
+
+        // overlapping windows
+        assert!(reserve(&[gw_a], start, duration).await.unwrap().is_none());
+        assert!(reserve(
+            &[gw_a],
+            start - Duration::try_milliseconds(400).unwrap(),
+            duration
+        )
+        .await
+        .unwrap()
+        .is_none());
+        assert!(reserve(
+            &[gw_a],
+            start + Duration::try_milliseconds(400).unwrap(),
+            duration
+        )
+        .await
+        .unwrap()
+        .is_none());
+
+        // the partial reservation for gw_b is released
+        assert!(reserve(&[gw_b, gw_a], start, duration)
+            .await
+            .unwrap()
+            .is_none());
+        let reservation_b = reserve(&[gw_b], start, duration).await.unwrap().unwrap();
+
+        // adjacent windows
+        reserve(&[gw_a, gw_b], start - duration, duration)
+            .await
+            .unwrap()
+            .unwrap();
+        reserve(&[gw_a, gw_b], start + duration, duration)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // released windows can be reserved again
+        reservation.release().await.unwrap();
+        reservation_b.release().await.unwrap();
+        reserve(&[gw_a, gw_b], start, duration)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
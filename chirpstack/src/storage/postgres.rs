@@ -4,6 +4,7 @@ use std::time::Instant;
 use anyhow::Result;
 use tracing::{error, info};
 
+use super::instrumentation::QueryInstrumentation;
 use crate::monitoring::prometheus;
 use diesel::{ConnectionError, ConnectionResult};
 use diesel_async::pooled_connection::deadpool::{Object as DeadpoolObject, Pool as DeadpoolPool};
@@ -50,18 +51,7 @@ pub fn setup(conf: &config::Postgresql) -> Result<()> {
 // https://github.com/weiznich/diesel_async/blob/main/examples/postgres/pooled-with-rustls/src/main.rs
 fn pg_establish_connection(config: &str) -> BoxFuture<ConnectionResult<AsyncPgConnection>> {
     let fut = async {
-        let conf = config::get();
-
-        let root_certs = get_root_certs(if conf.postgresql.ca_cert.is_empty() {
-            None
-        } else {
-            Some(conf.postgresql.ca_cert.clone())
-        })
-        .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
-        let rustls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_certs)
-            .with_no_client_auth();
-        let tls = tokio_postgres_rustls::MakeRustlsConnect::new(rustls_config);
+        let tls = get_tls_connector().map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
         let (client, conn) = tokio_postgres::connect(config, tls)
             .await
             .map_err(|e| ConnectionError::BadConnection(e.to_string()))?;
@@ -70,11 +60,27 @@ fn pg_establish_connection(config: &str) -> BoxFuture<ConnectionResult<AsyncPgCo
                 error!(error = %e, "PostgreSQL connection error");
             }
         });
-        AsyncPgConnection::try_from(client).await
+        let mut conn = AsyncPgConnection::try_from(client).await?;
+        conn.set_instrumentation(QueryInstrumentation::default());
+        Ok(conn)
     };
     fut.boxed()
 }
 
+pub fn get_tls_connector() -> Result<tokio_postgres_rustls::MakeRustlsConnect> {
+    let conf = config::get();
+
+    let root_certs = get_root_certs(if conf.postgresql.ca_cert.is_empty() {
+        None
+    } else {
+        Some(conf.postgresql.ca_cert.clone())
+    })?;
+    let rustls_config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_certs)
+        .with_no_client_auth();
+    Ok(tokio_postgres_rustls::MakeRustlsConnect::new(rustls_config))
+}
+
 fn get_async_db_pool() -> Result<AsyncPgPool> {
     let pool_r = ASYNC_PG_POOL.read().unwrap();
     let pool: AsyncPgPool = pool_r
@@ -0,0 +1,377 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{dsl, prelude::*};
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::roaming_agreement;
+use super::{cache, fields, get_async_db_conn};
+use crate::config;
+use lrwn::NetID;
+
+// Roaming agreement with a roaming partner, managed through the API. These are applied
+// together with the roaming servers of the configuration file. In case both contain the same
+// NetID, the roaming agreement takes precedence.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = roaming_agreement)]
+pub struct RoamingAgreement {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub net_id: String,
+    pub server: String,
+    pub use_target_role_suffix: bool,
+    pub async_timeout: i32,
+    pub passive_roaming_lifetime: i32,
+    pub passive_roaming_kek_label: String,
+    pub passive_roaming_validate_mic: bool,
+    pub handover_roaming_lifetime: i32,
+    pub handover_roaming_kek_label: String,
+    pub ca_cert: String,
+    pub tls_cert: String,
+    pub tls_key: String,
+    pub authorization_header: String,
+    pub max_requests_per_second: i32,
+    pub oauth2_token_url: String,
+    pub oauth2_client_id: String,
+    pub oauth2_client_secret: String,
+    pub oauth2_scope: String,
+    pub api_key_header: String,
+    pub api_key: String,
+    pub jws_algorithm: String,
+    pub jws_signing_key: String,
+    pub jws_verification_key: String,
+    pub use_handover_roaming: bool,
+}
+
+impl RoamingAgreement {
+    fn validate(&self) -> Result<(), Error> {
+        if NetID::from_str(&self.net_id).is_err() {
+            return Err(Error::Validation("net_id must be a valid NetID".into()));
+        }
+
+        if self.async_timeout < 0
+            || self.passive_roaming_lifetime < 0
+            || self.handover_roaming_lifetime < 0
+            || self.max_requests_per_second < 0
+        {
+            return Err(Error::Validation(
+                "timeouts, lifetimes and max_requests_per_second must not be negative".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for RoamingAgreement {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        RoamingAgreement {
+            id: Uuid::new_v4().into(),
+            created_at: now,
+            updated_at: now,
+            name: "".into(),
+            net_id: "".into(),
+            server: "".into(),
+            use_target_role_suffix: false,
+            async_timeout: 0,
+            passive_roaming_lifetime: 0,
+            passive_roaming_kek_label: "".into(),
+            passive_roaming_validate_mic: false,
+            handover_roaming_lifetime: 0,
+            handover_roaming_kek_label: "".into(),
+            ca_cert: "".into(),
+            tls_cert: "".into(),
+            tls_key: "".into(),
+            authorization_header: "".into(),
+            max_requests_per_second: 0,
+            oauth2_token_url: "".into(),
+            oauth2_client_id: "".into(),
+            oauth2_client_secret: "".into(),
+            oauth2_scope: "".into(),
+            api_key_header: "".into(),
+            api_key: "".into(),
+            jws_algorithm: "".into(),
+            jws_signing_key: "".into(),
+            jws_verification_key: "".into(),
+            use_handover_roaming: false,
+        }
+    }
+}
+
+impl TryFrom<&RoamingAgreement> for config::RoamingServer {
+    type Error = anyhow::Error;
+
+    fn try_from(ra: &RoamingAgreement) -> Result<Self> {
+        Ok(config::RoamingServer {
+            net_id: NetID::from_str(&ra.net_id)?,
+            async_timeout: Duration::from_secs(ra.async_timeout as u64),
+            passive_roaming_lifetime: Duration::from_secs(ra.passive_roaming_lifetime as u64),
+            passive_roaming_kek_label: ra.passive_roaming_kek_label.clone(),
+            passive_roaming_validate_mic: ra.passive_roaming_validate_mic,
+            handover_roaming_lifetime: Duration::from_secs(ra.handover_roaming_lifetime as u64),
+            handover_roaming_kek_label: ra.handover_roaming_kek_label.clone(),
+            use_handover_roaming: ra.use_handover_roaming,
+            server: ra.server.clone(),
+            use_target_role_suffix: ra.use_target_role_suffix,
+            ca_cert: ra.ca_cert.clone(),
+            tls_cert: ra.tls_cert.clone(),
+            tls_key: ra.tls_key.clone(),
+            authorization_header: ra.authorization_header.clone(),
+            max_requests_per_second: ra.max_requests_per_second as u32,
+            oauth2_token_url: ra.oauth2_token_url.clone(),
+            oauth2_client_id: ra.oauth2_client_id.clone(),
+            oauth2_client_secret: ra.oauth2_client_secret.clone(),
+            oauth2_scope: ra.oauth2_scope.clone(),
+            api_key_header: ra.api_key_header.clone(),
+            api_key: ra.api_key.clone(),
+            jws_algorithm: ra.jws_algorithm.clone(),
+            jws_signing_key: ra.jws_signing_key.clone(),
+            jws_verification_key: ra.jws_verification_key.clone(),
+        })
+    }
+}
+
+#[derive(Queryable, PartialEq, Eq, Debug)]
+pub struct RoamingAgreementListItem {
+    pub id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub net_id: String,
+    pub server: String,
+}
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    pub search: Option<String>,
+}
+
+pub async fn create(ra: RoamingAgreement) -> Result<RoamingAgreement, Error> {
+    ra.validate()?;
+
+    let ra: RoamingAgreement = diesel::insert_into(roaming_agreement::table)
+        .values(&ra)
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, ra.net_id.clone()))?;
+    cache::invalidate(cache::Kind::RoamingAgreements, &ra.id).await;
+    info!(id = %ra.id, net_id = %ra.net_id, "Roaming agreement created");
+    Ok(ra)
+}
+
+pub async fn get(id: &Uuid) -> Result<RoamingAgreement, Error> {
+    roaming_agreement::dsl::roaming_agreement
+        .find(&fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.to_string()))
+}
+
+pub async fn update(ra: RoamingAgreement) -> Result<RoamingAgreement, Error> {
+    ra.validate()?;
+
+    let ra: RoamingAgreement =
+        diesel::update(roaming_agreement::dsl::roaming_agreement.find(&ra.id))
+            .set((
+                roaming_agreement::updated_at.eq(Utc::now()),
+                roaming_agreement::name.eq(&ra.name),
+                roaming_agreement::net_id.eq(&ra.net_id),
+                roaming_agreement::server.eq(&ra.server),
+                roaming_agreement::use_target_role_suffix.eq(&ra.use_target_role_suffix),
+                roaming_agreement::async_timeout.eq(&ra.async_timeout),
+                roaming_agreement::passive_roaming_lifetime.eq(&ra.passive_roaming_lifetime),
+                roaming_agreement::passive_roaming_kek_label.eq(&ra.passive_roaming_kek_label),
+                roaming_agreement::passive_roaming_validate_mic
+                    .eq(&ra.passive_roaming_validate_mic),
+                roaming_agreement::handover_roaming_lifetime.eq(&ra.handover_roaming_lifetime),
+                roaming_agreement::handover_roaming_kek_label.eq(&ra.handover_roaming_kek_label),
+                roaming_agreement::ca_cert.eq(&ra.ca_cert),
+                roaming_agreement::tls_cert.eq(&ra.tls_cert),
+                roaming_agreement::tls_key.eq(&ra.tls_key),
+                roaming_agreement::authorization_header.eq(&ra.authorization_header),
+                roaming_agreement::max_requests_per_second.eq(&ra.max_requests_per_second),
+                roaming_agreement::oauth2_token_url.eq(&ra.oauth2_token_url),
+                roaming_agreement::oauth2_client_id.eq(&ra.oauth2_client_id),
+                roaming_agreement::oauth2_client_secret.eq(&ra.oauth2_client_secret),
+                roaming_agreement::oauth2_scope.eq(&ra.oauth2_scope),
+                roaming_agreement::api_key_header.eq(&ra.api_key_header),
+                roaming_agreement::api_key.eq(&ra.api_key),
+                roaming_agreement::jws_algorithm.eq(&ra.jws_algorithm),
+                roaming_agreement::jws_signing_key.eq(&ra.jws_signing_key),
+                roaming_agreement::jws_verification_key.eq(&ra.jws_verification_key),
+                roaming_agreement::use_handover_roaming.eq(&ra.use_handover_roaming),
+            ))
+            .get_result(&mut get_async_db_conn().await?)
+            .await
+            .map_err(|e| Error::from_diesel(e, ra.id.to_string()))?;
+    cache::invalidate(cache::Kind::RoamingAgreements, &ra.id).await;
+    info!(id = %ra.id, net_id = %ra.net_id, "Roaming agreement updated");
+    Ok(ra)
+}
+
+pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    let ra =
+        diesel::delete(roaming_agreement::dsl::roaming_agreement.find(&fields::Uuid::from(id)))
+            .execute(&mut get_async_db_conn().await?)
+            .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    cache::invalidate(cache::Kind::RoamingAgreements, id).await;
+    info!(id = %id, "Roaming agreement deleted");
+    Ok(())
+}
+
+pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
+    let mut q = roaming_agreement::dsl::roaming_agreement
+        .select(dsl::count_star())
+        .into_boxed();
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(roaming_agreement::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(roaming_agreement::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    Ok(q.first(&mut get_async_db_conn().await?).await?)
+}
+
+pub async fn list(
+    limit: i64,
+    offset: i64,
+    filters: &Filters,
+) -> Result<Vec<RoamingAgreementListItem>, Error> {
+    let mut q = roaming_agreement::dsl::roaming_agreement
+        .select((
+            roaming_agreement::id,
+            roaming_agreement::created_at,
+            roaming_agreement::updated_at,
+            roaming_agreement::name,
+            roaming_agreement::net_id,
+            roaming_agreement::server,
+        ))
+        .into_boxed();
+
+    if let Some(search) = &filters.search {
+        #[cfg(feature = "postgres")]
+        {
+            q = q.filter(roaming_agreement::dsl::name.ilike(format!("%{}%", search)));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            q = q.filter(roaming_agreement::dsl::name.like(format!("%{}%", search)));
+        }
+    }
+
+    let items = q
+        .order_by(roaming_agreement::dsl::name)
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+// Returns all the roaming agreements.
+pub async fn get_all() -> Result<Vec<RoamingAgreement>, Error> {
+    let items = roaming_agreement::dsl::roaming_agreement
+        .order_by(roaming_agreement::dsl::net_id)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_roaming_agreement() {
+        let _guard = test::prepare().await;
+
+        // invalid NetID
+        assert!(create(RoamingAgreement {
+            name: "invalid".into(),
+            net_id: "foo".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // create
+        let mut ra = create(RoamingAgreement {
+            name: "partner-a".into(),
+            net_id: "010203".into(),
+            server: "https://partner-a.example.com".into(),
+            async_timeout: 1,
+            passive_roaming_lifetime: 60,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        // NetID must be unique
+        assert!(create(RoamingAgreement {
+            name: "partner-b".into(),
+            net_id: "010203".into(),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        // get
+        let ra_get = get(&ra.id).await.unwrap();
+        assert_eq!(ra, ra_get);
+
+        // update
+        ra.name = "partner-a-updated".into();
+        ra.passive_roaming_validate_mic = true;
+        let ra = update(ra).await.unwrap();
+        let ra_get = get(&ra.id).await.unwrap();
+        assert_eq!(ra, ra_get);
+
+        // to roaming server
+        let s = config::RoamingServer::try_from(&ra).unwrap();
+        assert_eq!(NetID::from_be_bytes([1, 2, 3]), s.net_id);
+        assert_eq!(Duration::from_secs(1), s.async_timeout);
+        assert_eq!(Duration::from_secs(60), s.passive_roaming_lifetime);
+        assert!(s.passive_roaming_validate_mic);
+
+        // get count and list
+        let filters = Filters {
+            search: Some("partner".into()),
+        };
+        assert_eq!(1, get_count(&filters).await.unwrap());
+        let items = list(10, 0, &filters).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!("partner-a-updated", items[0].name);
+
+        let filters = Filters {
+            search: Some("foo".into()),
+        };
+        assert_eq!(0, get_count(&filters).await.unwrap());
+
+        // get all
+        assert_eq!(1, get_all().await.unwrap().len());
+
+        // delete
+        delete(&ra.id).await.unwrap();
+        assert!(delete(&ra.id).await.is_err());
+        assert!(get_all().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{dsl, prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use tracing::trace;
+
+use super::error::Error;
+use super::schema::roaming_usage;
+use super::{fields, get_async_db_conn};
+use lrwn::{DevAddr, NetID, EUI64};
+
+// Roaming usage, aggregated per day, roaming partner (NetID), our own roaming role and device.
+// In case the DevEUI is not known (e.g. passive-roaming as fNS), the DevEUI is set to all zeros.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = roaming_usage)]
+pub struct RoamingUsage {
+    pub day: NaiveDate,
+    pub net_id: String,
+    pub role: fields::RoamingRole,
+    pub dev_eui: EUI64,
+    pub dev_addr: DevAddr,
+    pub uplink_count: i64,
+    pub downlink_count: i64,
+    pub uplink_airtime_ms: i64,
+    pub downlink_airtime_ms: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for RoamingUsage {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        RoamingUsage {
+            day: now.date_naive(),
+            net_id: NetID::default().to_string(),
+            role: fields::RoamingRole::FNS,
+            dev_eui: EUI64::default(),
+            dev_addr: DevAddr::default(),
+            uplink_count: 0,
+            downlink_count: 0,
+            uplink_airtime_ms: 0,
+            downlink_airtime_ms: 0,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    pub net_id: Option<NetID>,
+    pub dev_eui: Option<EUI64>,
+    // Start day (inclusive).
+    pub start: Option<NaiveDate>,
+    // End day (exclusive).
+    pub end: Option<NaiveDate>,
+}
+
+// Increment adds the counters of the given usage record to the stored usage record, creating
+// it when it does not yet exist.
+pub async fn increment(ru: RoamingUsage) -> Result<(), Error> {
+    diesel::insert_into(roaming_usage::table)
+        .values(&ru)
+        .on_conflict((
+            roaming_usage::day,
+            roaming_usage::net_id,
+            roaming_usage::role,
+            roaming_usage::dev_eui,
+            roaming_usage::dev_addr,
+        ))
+        .do_update()
+        .set((
+            roaming_usage::uplink_count
+                .eq(roaming_usage::uplink_count + excluded(roaming_usage::uplink_count)),
+            roaming_usage::downlink_count
+                .eq(roaming_usage::downlink_count + excluded(roaming_usage::downlink_count)),
+            roaming_usage::uplink_airtime_ms
+                .eq(roaming_usage::uplink_airtime_ms + excluded(roaming_usage::uplink_airtime_ms)),
+            roaming_usage::downlink_airtime_ms
+                .eq(roaming_usage::downlink_airtime_ms
+                    + excluded(roaming_usage::downlink_airtime_ms)),
+            roaming_usage::updated_at.eq(excluded(roaming_usage::updated_at)),
+        ))
+        .execute(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, ru.net_id.clone()))?;
+
+    trace!(net_id = %ru.net_id, role = %ru.role, dev_eui = %ru.dev_eui, "Roaming usage incremented");
+    Ok(())
+}
+
+pub async fn get_count(filters: &Filters) -> Result<i64, Error> {
+    let mut q = roaming_usage::dsl::roaming_usage
+        .select(dsl::count_star())
+        .into_boxed();
+
+    if let Some(net_id) = &filters.net_id {
+        q = q.filter(roaming_usage::dsl::net_id.eq(net_id.to_string()));
+    }
+
+    if let Some(dev_eui) = &filters.dev_eui {
+        q = q.filter(roaming_usage::dsl::dev_eui.eq(dev_eui));
+    }
+
+    if let Some(start) = &filters.start {
+        q = q.filter(roaming_usage::dsl::day.ge(start));
+    }
+
+    if let Some(end) = &filters.end {
+        q = q.filter(roaming_usage::dsl::day.lt(end));
+    }
+
+    Ok(q.first(&mut get_async_db_conn().await?).await?)
+}
+
+pub async fn list(limit: i64, offset: i64, filters: &Filters) -> Result<Vec<RoamingUsage>, Error> {
+    let mut q = roaming_usage::dsl::roaming_usage.into_boxed();
+
+    if let Some(net_id) = &filters.net_id {
+        q = q.filter(roaming_usage::dsl::net_id.eq(net_id.to_string()));
+    }
+
+    if let Some(dev_eui) = &filters.dev_eui {
+        q = q.filter(roaming_usage::dsl::dev_eui.eq(dev_eui));
+    }
+
+    if let Some(start) = &filters.start {
+        q = q.filter(roaming_usage::dsl::day.ge(start));
+    }
+
+    if let Some(end) = &filters.end {
+        q = q.filter(roaming_usage::dsl::day.lt(end));
+    }
+
+    let items = q
+        .order_by((
+            roaming_usage::dsl::day,
+            roaming_usage::dsl::net_id,
+            roaming_usage::dsl::role,
+            roaming_usage::dsl::dev_eui,
+            roaming_usage::dsl::dev_addr,
+        ))
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_roaming_usage() {
+        let _guard = test::prepare().await;
+
+        let day = NaiveDate::from_ymd_opt(2025, 4, 7).unwrap();
+        let ru = RoamingUsage {
+            day,
+            net_id: "010203".into(),
+            role: fields::RoamingRole::FNS,
+            dev_addr: DevAddr::from_be_bytes([1, 2, 3, 4]),
+            uplink_count: 1,
+            uplink_airtime_ms: 50,
+            ..Default::default()
+        };
+
+        // create
+        increment(ru.clone()).await.unwrap();
+
+        // increment
+        increment(RoamingUsage {
+            uplink_count: 0,
+            uplink_airtime_ms: 0,
+            downlink_count: 1,
+            downlink_airtime_ms: 40,
+            ..ru.clone()
+        })
+        .await
+        .unwrap();
+
+        // other role
+        increment(RoamingUsage {
+            role: fields::RoamingRole::SNS,
+            dev_eui: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            ..ru.clone()
+        })
+        .await
+        .unwrap();
+
+        // other partner and day
+        increment(RoamingUsage {
+            day: day.succ_opt().unwrap(),
+            net_id: "030201".into(),
+            ..ru.clone()
+        })
+        .await
+        .unwrap();
+
+        // list all
+        let filters = Filters::default();
+        assert_eq!(3, get_count(&filters).await.unwrap());
+        let items = list(10, 0, &filters).await.unwrap();
+        assert_eq!(3, items.len());
+        assert_eq!(fields::RoamingRole::FNS, items[0].role);
+        assert_eq!(1, items[0].uplink_count);
+        assert_eq!(50, items[0].uplink_airtime_ms);
+        assert_eq!(1, items[0].downlink_count);
+        assert_eq!(40, items[0].downlink_airtime_ms);
+
+        // filter by NetID
+        let filters = Filters {
+            net_id: Some(NetID::from_be_bytes([1, 2, 3])),
+            ..Default::default()
+        };
+        assert_eq!(2, get_count(&filters).await.unwrap());
+
+        // filter by DevEUI
+        let filters = Filters {
+            dev_eui: Some(EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8])),
+            ..Default::default()
+        };
+        let items = list(10, 0, &filters).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(fields::RoamingRole::SNS, items[0].role);
+
+        // filter by day
+        let filters = Filters {
+            start: Some(day),
+            end: day.succ_opt(),
+            ..Default::default()
+        };
+        assert_eq!(2, get_count(&filters).await.unwrap());
+    }
+}
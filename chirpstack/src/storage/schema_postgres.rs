@@ -22,6 +22,7 @@ diesel::table! {
         description -> Text,
         mqtt_tls_cert -> Nullable<Bytea>,
         tags -> Jsonb,
+        log_rate_limit -> Int4,
     }
 }
 
@@ -36,6 +37,47 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    backend_async_answer (id) {
+        id -> Uuid,
+        created_at -> Timestamptz,
+        receiver_id -> Bytea,
+        #[max_length = 3]
+        target_role -> Varchar,
+        transaction_id -> Int8,
+        #[max_length = 20]
+        message_type -> Varchar,
+        payload -> Text,
+        attempt_count -> Int2,
+        scheduler_run_after -> Timestamptz,
+        error_msg -> Text,
+    }
+}
+
+diesel::table! {
+    codec_library (id) {
+        id -> Uuid,
+        tenant_id -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 100]
+        name -> Varchar,
+        description -> Text,
+        script -> Text,
+    }
+}
+
+diesel::table! {
+    data_encryption_key (id) {
+        id -> Int4,
+        created_at -> Timestamptz,
+        #[max_length = 20]
+        backend -> Varchar,
+        encrypted_key -> Bytea,
+        tenant_id -> Nullable<Uuid>,
+    }
+}
+
 diesel::table! {
     device (dev_eui) {
         dev_eui -> Bytea,
@@ -66,6 +108,8 @@ diesel::table! {
         secondary_dev_addr -> Nullable<Bytea>,
         device_session -> Nullable<Bytea>,
         app_layer_params -> Jsonb,
+        #[max_length = 20]
+        state -> Varchar,
     }
 }
 
@@ -79,6 +123,12 @@ diesel::table! {
         dev_nonces -> Jsonb,
         join_nonce -> Int4,
         gen_app_key -> Bytea,
+        #[max_length = 100]
+        key_ref -> Varchar,
+        #[max_length = 100]
+        kek_label -> Varchar,
+        wrapped_nwk_key -> Bytea,
+        wrapped_app_key -> Bytea,
     }
 }
 
@@ -183,6 +233,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    device_repository_codec (id) {
+        id -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 100]
+        vendor_id -> Varchar,
+        #[max_length = 100]
+        device_id -> Varchar,
+        #[max_length = 100]
+        firmware_version -> Varchar,
+        #[max_length = 100]
+        codec_id -> Varchar,
+        script -> Text,
+    }
+}
+
 diesel::table! {
     fuota_deployment (id) {
         id -> Uuid,
@@ -217,6 +284,18 @@ diesel::table! {
         request_fragmentation_session_status -> Varchar,
         payload -> Bytea,
         on_complete_set_device_tags -> Jsonb,
+        #[max_length = 100]
+        payload_version -> Varchar,
+    }
+}
+
+diesel::table! {
+    fuota_deployment_delta (fuota_deployment_id, base_version) {
+        fuota_deployment_id -> Uuid,
+        #[max_length = 100]
+        base_version -> Varchar,
+        created_at -> Timestamptz,
+        payload -> Bytea,
     }
 }
 
@@ -231,6 +310,11 @@ diesel::table! {
         frag_session_setup_completed_at -> Nullable<Timestamptz>,
         frag_status_completed_at -> Nullable<Timestamptz>,
         error_msg -> Text,
+        #[max_length = 100]
+        base_version -> Varchar,
+        #[max_length = 100]
+        target_version -> Varchar,
+        full_image_required -> Bool,
     }
 }
 
@@ -274,6 +358,69 @@ diesel::table! {
         tls_certificate -> Nullable<Bytea>,
         tags -> Jsonb,
         properties -> Jsonb,
+        offline_at -> Nullable<Timestamptz>,
+        #[max_length = 100]
+        model -> Varchar,
+        #[max_length = 100]
+        hal_version -> Varchar,
+        #[max_length = 100]
+        concentratord_version -> Varchar,
+        #[max_length = 100]
+        gateway_bridge_version -> Varchar,
+        antenna_gain -> Float4,
+        cable_loss -> Float4,
+    }
+}
+
+diesel::table! {
+    gateway_certificate (serial_number) {
+        serial_number -> Bytea,
+        gateway_id -> Bytea,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    join_server_route (id) {
+        id -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 100]
+        name -> Varchar,
+        #[max_length = 20]
+        join_eui_prefix -> Varchar,
+        #[max_length = 500]
+        server -> Varchar,
+        async_timeout -> Int4,
+        ca_cert -> Text,
+        tls_cert -> Text,
+        tls_key -> Text,
+        authorization_header -> Text,
+        oauth2_token_url -> Text,
+        oauth2_client_id -> Text,
+        oauth2_client_secret -> Text,
+        oauth2_scope -> Text,
+        api_key_header -> Text,
+        api_key -> Text,
+        jws_algorithm -> Text,
+        jws_signing_key -> Text,
+        jws_verification_key -> Text,
+    }
+}
+
+diesel::table! {
+    key_access_log (id) {
+        id -> Uuid,
+        created_at -> Timestamptz,
+        dev_eui -> Bytea,
+        #[max_length = 20]
+        key_type -> Varchar,
+        #[max_length = 100]
+        actor -> Varchar,
+        #[max_length = 50]
+        purpose -> Varchar,
     }
 }
 
@@ -298,6 +445,10 @@ diesel::table! {
         class_b_ping_slot_nb_k -> Int2,
         #[max_length = 20]
         class_c_scheduling_type -> Varchar,
+        geofence_latitude -> Float8,
+        geofence_longitude -> Float8,
+        geofence_radius -> Int4,
+        gateway_tags -> Jsonb,
     }
 }
 
@@ -353,6 +504,63 @@ diesel::table! {
         stats_interval_secs -> Int4,
         #[max_length = 100]
         region_config_id -> Varchar,
+        border_gateway_id -> Nullable<Bytea>,
+        relay_path -> Jsonb,
+    }
+}
+
+diesel::table! {
+    roaming_agreement (id) {
+        id -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 100]
+        name -> Varchar,
+        #[max_length = 6]
+        net_id -> Varchar,
+        #[max_length = 500]
+        server -> Varchar,
+        use_target_role_suffix -> Bool,
+        async_timeout -> Int4,
+        passive_roaming_lifetime -> Int4,
+        #[max_length = 100]
+        passive_roaming_kek_label -> Varchar,
+        passive_roaming_validate_mic -> Bool,
+        handover_roaming_lifetime -> Int4,
+        #[max_length = 100]
+        handover_roaming_kek_label -> Varchar,
+        ca_cert -> Text,
+        tls_cert -> Text,
+        tls_key -> Text,
+        authorization_header -> Text,
+        max_requests_per_second -> Int4,
+        oauth2_token_url -> Text,
+        oauth2_client_id -> Text,
+        oauth2_client_secret -> Text,
+        oauth2_scope -> Text,
+        api_key_header -> Text,
+        api_key -> Text,
+        jws_algorithm -> Text,
+        jws_signing_key -> Text,
+        jws_verification_key -> Text,
+        use_handover_roaming -> Bool,
+    }
+}
+
+diesel::table! {
+    roaming_usage (day, net_id, role, dev_eui, dev_addr) {
+        day -> Date,
+        #[max_length = 6]
+        net_id -> Varchar,
+        #[max_length = 3]
+        role -> Varchar,
+        dev_eui -> Bytea,
+        dev_addr -> Bytea,
+        uplink_count -> Int8,
+        downlink_count -> Int8,
+        uplink_airtime_ms -> Int8,
+        downlink_airtime_ms -> Int8,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -370,6 +578,53 @@ diesel::table! {
         private_gateways_up -> Bool,
         private_gateways_down -> Bool,
         tags -> Jsonb,
+        use_hsm -> Bool,
+        max_uplink_count_per_day -> Int4,
+        max_downlink_count_per_day -> Int4,
+    }
+}
+
+diesel::table! {
+    tenant_role (id) {
+        id -> Uuid,
+        tenant_id -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 100]
+        name -> Varchar,
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    tenant_role_permission (role_id, permission) {
+        role_id -> Uuid,
+        #[max_length = 50]
+        permission -> Varchar,
+    }
+}
+
+diesel::table! {
+    tenant_sla_report (tenant_id, month) {
+        tenant_id -> Uuid,
+        month -> Date,
+        uplink_count -> Int8,
+        uplink_success_count -> Int8,
+        downlink_count -> Int8,
+        downlink_success_count -> Int8,
+        api_request_count -> Int8,
+        api_success_count -> Int8,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    tenant_usage (tenant_id, day) {
+        tenant_id -> Uuid,
+        day -> Date,
+        uplink_count -> Int8,
+        downlink_count -> Int8,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -382,6 +637,7 @@ diesel::table! {
         is_admin -> Bool,
         is_device_admin -> Bool,
         is_gateway_admin -> Bool,
+        role_id -> Nullable<Uuid>,
     }
 }
 
@@ -401,9 +657,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_session (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::joinable!(api_key -> tenant (tenant_id));
 diesel::joinable!(application -> tenant (tenant_id));
 diesel::joinable!(application_integration -> application (application_id));
+diesel::joinable!(codec_library -> tenant (tenant_id));
+diesel::joinable!(data_encryption_key -> tenant (tenant_id));
 diesel::joinable!(device -> application (application_id));
 diesel::joinable!(device -> device_profile (device_profile_id));
 diesel::joinable!(device_keys -> device (dev_eui));
@@ -411,6 +679,7 @@ diesel::joinable!(device_profile -> tenant (tenant_id));
 diesel::joinable!(device_queue_item -> device (dev_eui));
 diesel::joinable!(fuota_deployment -> application (application_id));
 diesel::joinable!(fuota_deployment -> device_profile (device_profile_id));
+diesel::joinable!(fuota_deployment_delta -> fuota_deployment (fuota_deployment_id));
 diesel::joinable!(fuota_deployment_device -> device (dev_eui));
 diesel::joinable!(fuota_deployment_device -> fuota_deployment (fuota_deployment_id));
 diesel::joinable!(fuota_deployment_gateway -> fuota_deployment (fuota_deployment_id));
@@ -425,30 +694,51 @@ diesel::joinable!(multicast_group_gateway -> multicast_group (multicast_group_id
 diesel::joinable!(multicast_group_queue_item -> gateway (gateway_id));
 diesel::joinable!(multicast_group_queue_item -> multicast_group (multicast_group_id));
 diesel::joinable!(relay_gateway -> tenant (tenant_id));
+diesel::joinable!(tenant_role -> tenant (tenant_id));
+diesel::joinable!(tenant_role_permission -> tenant_role (role_id));
+diesel::joinable!(tenant_sla_report -> tenant (tenant_id));
+diesel::joinable!(tenant_usage -> tenant (tenant_id));
 diesel::joinable!(tenant_user -> tenant (tenant_id));
+diesel::joinable!(tenant_user -> tenant_role (role_id));
 diesel::joinable!(tenant_user -> user (user_id));
+diesel::joinable!(user_session -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     api_key,
     application,
     application_integration,
+    backend_async_answer,
+    codec_library,
+    data_encryption_key,
     device,
     device_keys,
     device_profile,
     device_profile_template,
     device_queue_item,
+    device_repository_codec,
     fuota_deployment,
+    fuota_deployment_delta,
     fuota_deployment_device,
     fuota_deployment_gateway,
     fuota_deployment_job,
     gateway,
+    gateway_certificate,
+    join_server_route,
+    key_access_log,
     multicast_group,
     multicast_group_device,
     multicast_group_gateway,
     multicast_group_queue_item,
     relay_device,
     relay_gateway,
+    roaming_agreement,
+    roaming_usage,
     tenant,
+    tenant_role,
+    tenant_role_permission,
+    tenant_sla_report,
+    tenant_usage,
     tenant_user,
     user,
+    user_session,
 );
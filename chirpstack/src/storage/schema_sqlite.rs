@@ -20,6 +20,7 @@ diesel::table! {
         description -> Text,
         mqtt_tls_cert -> Nullable<Binary>,
         tags -> Text,
+        log_rate_limit -> Integer,
     }
 }
 
@@ -33,6 +34,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    backend_async_answer (id) {
+        id -> Text,
+        created_at -> TimestamptzSqlite,
+        receiver_id -> Binary,
+        target_role -> Text,
+        transaction_id -> BigInt,
+        message_type -> Text,
+        payload -> Text,
+        attempt_count -> SmallInt,
+        scheduler_run_after -> TimestamptzSqlite,
+        error_msg -> Text,
+    }
+}
+
+diesel::table! {
+    codec_library (id) {
+        id -> Text,
+        tenant_id -> Text,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        name -> Text,
+        description -> Text,
+        script -> Text,
+    }
+}
+
+diesel::table! {
+    data_encryption_key (id) {
+        id -> Integer,
+        created_at -> TimestamptzSqlite,
+        backend -> Text,
+        encrypted_key -> Binary,
+        tenant_id -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     device (dev_eui) {
         dev_eui -> Binary,
@@ -61,6 +99,7 @@ diesel::table! {
         secondary_dev_addr -> Nullable<Binary>,
         device_session -> Nullable<Binary>,
         app_layer_params -> Text,
+        state -> Text,
     }
 }
 
@@ -74,6 +113,10 @@ diesel::table! {
         dev_nonces -> Text,
         join_nonce -> Integer,
         gen_app_key -> Binary,
+        key_ref -> Text,
+        kek_label -> Text,
+        wrapped_nwk_key -> Binary,
+        wrapped_app_key -> Binary,
     }
 }
 
@@ -163,6 +206,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    device_repository_codec (id) {
+        id -> Text,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        vendor_id -> Text,
+        device_id -> Text,
+        firmware_version -> Text,
+        codec_id -> Text,
+        script -> Text,
+    }
+}
+
 diesel::table! {
     fuota_deployment (id) {
         id -> Text,
@@ -193,6 +249,16 @@ diesel::table! {
         request_fragmentation_session_status -> Text,
         payload -> Binary,
         on_complete_set_device_tags -> Text,
+        payload_version -> Text,
+    }
+}
+
+diesel::table! {
+    fuota_deployment_delta (fuota_deployment_id, base_version) {
+        fuota_deployment_id -> Text,
+        base_version -> Text,
+        created_at -> TimestamptzSqlite,
+        payload -> Binary,
     }
 }
 
@@ -207,6 +273,9 @@ diesel::table! {
         frag_session_setup_completed_at -> Nullable<TimestamptzSqlite>,
         frag_status_completed_at -> Nullable<TimestamptzSqlite>,
         error_msg -> Text,
+        base_version -> Text,
+        target_version -> Text,
+        full_image_required -> Bool,
     }
 }
 
@@ -248,6 +317,59 @@ diesel::table! {
         tls_certificate -> Nullable<Binary>,
         tags -> Text,
         properties -> Text,
+        offline_at -> Nullable<TimestamptzSqlite>,
+        model -> Text,
+        hal_version -> Text,
+        concentratord_version -> Text,
+        gateway_bridge_version -> Text,
+        antenna_gain -> Float,
+        cable_loss -> Float,
+    }
+}
+
+diesel::table! {
+    gateway_certificate (serial_number) {
+        serial_number -> Binary,
+        gateway_id -> Binary,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        revoked_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
+diesel::table! {
+    join_server_route (id) {
+        id -> Text,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        name -> Text,
+        join_eui_prefix -> Text,
+        server -> Text,
+        async_timeout -> Integer,
+        ca_cert -> Text,
+        tls_cert -> Text,
+        tls_key -> Text,
+        authorization_header -> Text,
+        oauth2_token_url -> Text,
+        oauth2_client_id -> Text,
+        oauth2_client_secret -> Text,
+        oauth2_scope -> Text,
+        api_key_header -> Text,
+        api_key -> Text,
+        jws_algorithm -> Text,
+        jws_signing_key -> Text,
+        jws_verification_key -> Text,
+    }
+}
+
+diesel::table! {
+    key_access_log (id) {
+        id -> Text,
+        created_at -> TimestamptzSqlite,
+        dev_eui -> Binary,
+        key_type -> Text,
+        actor -> Text,
+        purpose -> Text,
     }
 }
 
@@ -268,6 +390,10 @@ diesel::table! {
         frequency -> BigInt,
         class_b_ping_slot_nb_k -> SmallInt,
         class_c_scheduling_type -> Text,
+        geofence_latitude -> Double,
+        geofence_longitude -> Double,
+        geofence_radius -> Integer,
+        gateway_tags -> Text,
     }
 }
 
@@ -321,6 +447,56 @@ diesel::table! {
         description -> Text,
         stats_interval_secs -> Integer,
         region_config_id -> Text,
+        border_gateway_id -> Nullable<Binary>,
+        relay_path -> Text,
+    }
+}
+
+diesel::table! {
+    roaming_agreement (id) {
+        id -> Text,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        name -> Text,
+        net_id -> Text,
+        server -> Text,
+        use_target_role_suffix -> Bool,
+        async_timeout -> Integer,
+        passive_roaming_lifetime -> Integer,
+        passive_roaming_kek_label -> Text,
+        passive_roaming_validate_mic -> Bool,
+        handover_roaming_lifetime -> Integer,
+        handover_roaming_kek_label -> Text,
+        ca_cert -> Text,
+        tls_cert -> Text,
+        tls_key -> Text,
+        authorization_header -> Text,
+        max_requests_per_second -> Integer,
+        oauth2_token_url -> Text,
+        oauth2_client_id -> Text,
+        oauth2_client_secret -> Text,
+        oauth2_scope -> Text,
+        api_key_header -> Text,
+        api_key -> Text,
+        jws_algorithm -> Text,
+        jws_signing_key -> Text,
+        jws_verification_key -> Text,
+        use_handover_roaming -> Bool,
+    }
+}
+
+diesel::table! {
+    roaming_usage (day, net_id, role, dev_eui, dev_addr) {
+        day -> Date,
+        net_id -> Text,
+        role -> Text,
+        dev_eui -> Binary,
+        dev_addr -> Binary,
+        uplink_count -> BigInt,
+        downlink_count -> BigInt,
+        uplink_airtime_ms -> BigInt,
+        downlink_airtime_ms -> BigInt,
+        updated_at -> TimestamptzSqlite,
     }
 }
 
@@ -337,6 +513,51 @@ diesel::table! {
         private_gateways_up -> Bool,
         private_gateways_down -> Bool,
         tags -> Text,
+        use_hsm -> Bool,
+        max_uplink_count_per_day -> Integer,
+        max_downlink_count_per_day -> Integer,
+    }
+}
+
+diesel::table! {
+    tenant_role (id) {
+        id -> Text,
+        tenant_id -> Text,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        name -> Text,
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    tenant_role_permission (role_id, permission) {
+        role_id -> Text,
+        permission -> Text,
+    }
+}
+
+diesel::table! {
+    tenant_sla_report (tenant_id, month) {
+        tenant_id -> Text,
+        month -> Date,
+        uplink_count -> BigInt,
+        uplink_success_count -> BigInt,
+        downlink_count -> BigInt,
+        downlink_success_count -> BigInt,
+        api_request_count -> BigInt,
+        api_success_count -> BigInt,
+        updated_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    tenant_usage (tenant_id, day) {
+        tenant_id -> Text,
+        day -> Date,
+        uplink_count -> BigInt,
+        downlink_count -> BigInt,
+        updated_at -> TimestamptzSqlite,
     }
 }
 
@@ -349,6 +570,7 @@ diesel::table! {
         is_admin -> Bool,
         is_device_admin -> Bool,
         is_gateway_admin -> Bool,
+        role_id -> Nullable<Text>,
     }
 }
 
@@ -367,9 +589,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_session (id) {
+        id -> Text,
+        user_id -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        revoked_at -> Nullable<TimestamptzSqlite>,
+    }
+}
+
 diesel::joinable!(api_key -> tenant (tenant_id));
 diesel::joinable!(application -> tenant (tenant_id));
 diesel::joinable!(application_integration -> application (application_id));
+diesel::joinable!(codec_library -> tenant (tenant_id));
+diesel::joinable!(data_encryption_key -> tenant (tenant_id));
 diesel::joinable!(device -> application (application_id));
 diesel::joinable!(device -> device_profile (device_profile_id));
 diesel::joinable!(device_keys -> device (dev_eui));
@@ -377,6 +611,7 @@ diesel::joinable!(device_profile -> tenant (tenant_id));
 diesel::joinable!(device_queue_item -> device (dev_eui));
 diesel::joinable!(fuota_deployment -> application (application_id));
 diesel::joinable!(fuota_deployment -> device_profile (device_profile_id));
+diesel::joinable!(fuota_deployment_delta -> fuota_deployment (fuota_deployment_id));
 diesel::joinable!(fuota_deployment_device -> device (dev_eui));
 diesel::joinable!(fuota_deployment_device -> fuota_deployment (fuota_deployment_id));
 diesel::joinable!(fuota_deployment_gateway -> fuota_deployment (fuota_deployment_id));
@@ -391,30 +626,51 @@ diesel::joinable!(multicast_group_gateway -> multicast_group (multicast_group_id
 diesel::joinable!(multicast_group_queue_item -> gateway (gateway_id));
 diesel::joinable!(multicast_group_queue_item -> multicast_group (multicast_group_id));
 diesel::joinable!(relay_gateway -> tenant (tenant_id));
+diesel::joinable!(tenant_role -> tenant (tenant_id));
+diesel::joinable!(tenant_role_permission -> tenant_role (role_id));
+diesel::joinable!(tenant_sla_report -> tenant (tenant_id));
+diesel::joinable!(tenant_usage -> tenant (tenant_id));
 diesel::joinable!(tenant_user -> tenant (tenant_id));
+diesel::joinable!(tenant_user -> tenant_role (role_id));
 diesel::joinable!(tenant_user -> user (user_id));
+diesel::joinable!(user_session -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     api_key,
     application,
     application_integration,
+    backend_async_answer,
+    codec_library,
+    data_encryption_key,
     device,
     device_keys,
     device_profile,
     device_profile_template,
     device_queue_item,
+    device_repository_codec,
     fuota_deployment,
+    fuota_deployment_delta,
     fuota_deployment_device,
     fuota_deployment_gateway,
     fuota_deployment_job,
     gateway,
+    gateway_certificate,
+    join_server_route,
+    key_access_log,
     multicast_group,
     multicast_group_device,
     multicast_group_gateway,
     multicast_group_queue_item,
     relay_device,
     relay_gateway,
+    roaming_agreement,
+    roaming_usage,
     tenant,
+    tenant_role,
+    tenant_role_permission,
+    tenant_sla_report,
+    tenant_usage,
     tenant_user,
     user,
+    user_session,
 );
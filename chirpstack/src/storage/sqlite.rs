@@ -4,6 +4,7 @@ use std::time::Instant;
 use anyhow::Result;
 use tracing::info;
 
+use super::instrumentation::QueryInstrumentation;
 use crate::monitoring::prometheus;
 use diesel::sqlite::SqliteConnection;
 use diesel::{Connection, ConnectionError, ConnectionResult};
@@ -67,6 +68,7 @@ fn sqlite_establish_connection(
                 .join("");
             conn.batch_execute(&pragmas)
                 .map_err(|err| ConnectionError::BadConnection(err.to_string()))?;
+            conn.set_instrumentation(QueryInstrumentation::default());
             Ok(SyncConnectionWrapper::new(conn))
         },
     )
@@ -4,12 +4,15 @@ use anyhow::Result;
 use chrono::{DateTime, Utc};
 use diesel::{dsl, prelude::*};
 use diesel_async::RunQueryDsl;
-use tracing::info;
+use tracing::{info, warn};
 use uuid::Uuid;
 
+use super::data_encryption_key::{self, DataEncryptionKey};
 use super::error::Error;
 use super::schema::{tenant, tenant_user, user};
-use super::{fields, get_async_db_conn};
+use super::{cache, db_transaction, device_keys, fields, get_async_db_conn};
+use crate::encryption;
+use crate::helpers::errors::PrintFullError;
 
 #[derive(Queryable, Insertable, PartialEq, Eq, Debug, Clone)]
 #[diesel(table_name = tenant)]
@@ -25,6 +28,9 @@ pub struct Tenant {
     pub private_gateways_up: bool,
     pub private_gateways_down: bool,
     pub tags: fields::KeyValue,
+    pub use_hsm: bool,
+    pub max_uplink_count_per_day: i32,
+    pub max_downlink_count_per_day: i32,
 }
 
 impl Tenant {
@@ -52,12 +58,15 @@ impl Default for Tenant {
             private_gateways_up: false,
             private_gateways_down: false,
             tags: fields::KeyValue::new(HashMap::new()),
+            use_hsm: false,
+            max_uplink_count_per_day: 0,
+            max_downlink_count_per_day: 0,
         }
     }
 }
 
 #[derive(Queryable, Insertable, AsChangeset, PartialEq, Eq, Debug)]
-#[diesel(table_name = tenant_user)]
+#[diesel(table_name = tenant_user, treat_none_as_null = true)]
 pub struct TenantUser {
     pub tenant_id: fields::Uuid,
     pub user_id: fields::Uuid,
@@ -66,6 +75,9 @@ pub struct TenantUser {
     pub is_admin: bool,
     pub is_device_admin: bool,
     pub is_gateway_admin: bool,
+    // When set, the permissions of the tenant user are defined by the tenant role and the
+    // above flags are ignored.
+    pub role_id: Option<fields::Uuid>,
 }
 
 impl Default for TenantUser {
@@ -80,6 +92,7 @@ impl Default for TenantUser {
             is_admin: false,
             is_device_admin: false,
             is_gateway_admin: false,
+            role_id: None,
         }
     }
 }
@@ -94,6 +107,7 @@ pub struct TenantUserListItem {
     pub is_admin: bool,
     pub is_device_admin: bool,
     pub is_gateway_admin: bool,
+    pub role_id: Option<fields::Uuid>,
 }
 
 #[derive(Default, Clone)]
@@ -105,12 +119,44 @@ pub struct Filters {
 pub async fn create(t: Tenant) -> Result<Tenant, Error> {
     t.validate()?;
 
-    let t: Tenant = diesel::insert_into(tenant::table)
-        .values(&t)
-        .get_result(&mut get_async_db_conn().await?)
-        .await
-        .map_err(|e| Error::from_diesel(e, t.id.to_string()))?;
+    // The data encryption key of the tenant is stored within the same transaction as the
+    // tenant, such that the tenant is never stored without its key. The key is encrypted by the
+    // encryption backend before the transaction is started.
+    let dek = if encryption::per_tenant_keys() {
+        Some(encryption::new_data_key(Some(*t.id)).await?)
+    } else {
+        None
+    };
+
+    let mut c = get_async_db_conn().await?;
+    let (t, dek) = db_transaction::<(Tenant, Option<DataEncryptionKey>), Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let t: Tenant = diesel::insert_into(tenant::table)
+                .values(&t)
+                .get_result(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, t.id.to_string()))?;
+
+            let dek = match dek {
+                Some(dek) => Some(data_encryption_key::create_tx(c, dek).await?),
+                None => None,
+            };
+
+            Ok((t, dek))
+        })
+    })
+    .await?;
     info!(id = %t.id, "Tenant created");
+
+    if let Some(dek) = dek {
+        info!(id = dek.id, tenant_id = %t.id, "Data encryption key created");
+
+        // Until the key has been loaded, the global data encryption key is used.
+        if let Err(e) = encryption::activate_data_key(Some(*t.id)).await {
+            warn!(tenant_id = %t.id, error = %e.full(), "Activate data encryption key failed");
+        }
+    }
+
     Ok(t)
 }
 
@@ -137,6 +183,9 @@ pub async fn update(t: Tenant) -> Result<Tenant, Error> {
             tenant::private_gateways_up.eq(&t.private_gateways_up),
             tenant::private_gateways_down.eq(&t.private_gateways_down),
             tenant::tags.eq(&t.tags),
+            tenant::use_hsm.eq(&t.use_hsm),
+            tenant::max_uplink_count_per_day.eq(&t.max_uplink_count_per_day),
+            tenant::max_downlink_count_per_day.eq(&t.max_downlink_count_per_day),
         ))
         .get_result(&mut get_async_db_conn().await?)
         .await
@@ -146,6 +195,9 @@ pub async fn update(t: Tenant) -> Result<Tenant, Error> {
 }
 
 pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    // The device-keys are removed by the database (cascade), the root-keys in the keystore
+    // must be removed explicitly.
+    let key_refs = device_keys::get_key_refs_for_tenant(id).await?;
     let ra = diesel::delete(tenant::dsl::tenant.find(&fields::Uuid::from(id)))
         .execute(&mut get_async_db_conn().await?)
         .await
@@ -154,6 +206,11 @@ pub async fn delete(id: &Uuid) -> Result<(), Error> {
     if ra == 0 {
         return Err(Error::NotFound(id.to_string()));
     }
+    for (dev_eui, key_ref) in &key_refs {
+        device_keys::delete_root_keys(dev_eui, key_ref).await;
+    }
+    // Applications and device-profiles are deleted by cascade.
+    cache::invalidate_all().await;
     info!(id = %id, "Tenant deleted");
     Ok(())
 }
@@ -281,6 +338,7 @@ pub async fn get_users(
             tenant_user::dsl::is_admin,
             tenant_user::dsl::is_device_admin,
             tenant_user::dsl::is_gateway_admin,
+            tenant_user::dsl::role_id,
         ))
         .filter(tenant_user::dsl::tenant_id.eq(&fields::Uuid::from(tenant_id)))
         .order_by(user::dsl::email)
@@ -349,6 +407,9 @@ pub mod test {
             private_gateways_up: true,
             private_gateways_down: true,
             tags: fields::KeyValue::new(HashMap::new()),
+            use_hsm: false,
+            max_uplink_count_per_day: 1000,
+            max_downlink_count_per_day: 100,
         };
         create(t).await.unwrap()
     }
@@ -370,6 +431,8 @@ pub mod test {
 
         // update
         t.name = "new t".into();
+        t.use_hsm = true;
+        t.max_uplink_count_per_day = 2000;
         t = update(t).await.unwrap();
         let t_get = get(&t.id).await.unwrap();
         assert_eq!(t, t_get);
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{dsl, prelude::*};
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::{tenant_role, tenant_role_permission};
+use super::{db_transaction, fields, get_async_db_conn, AsyncDbPoolConnection};
+
+// Tenant role, granting a set of permissions to the tenant users to which the role has been
+// assigned. Tenant users without role fall back on the is_admin, is_device_admin and
+// is_gateway_admin flags.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = tenant_role)]
+pub struct TenantRole {
+    pub id: fields::Uuid,
+    pub tenant_id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+    pub description: String,
+}
+
+impl TenantRole {
+    fn validate(&self) -> Result<(), Error> {
+        if self.name.is_empty() {
+            return Err(Error::Validation("name is not set".into()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TenantRole {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        TenantRole {
+            id: Uuid::new_v4().into(),
+            tenant_id: Uuid::nil().into(),
+            created_at: now,
+            updated_at: now,
+            name: "".into(),
+            description: "".into(),
+        }
+    }
+}
+
+pub async fn create(
+    r: TenantRole,
+    permissions: &[fields::Permission],
+) -> Result<TenantRole, Error> {
+    r.validate()?;
+
+    let permissions = permissions.to_vec();
+    let mut c = get_async_db_conn().await?;
+    let r: TenantRole = db_transaction::<TenantRole, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let r: TenantRole = diesel::insert_into(tenant_role::table)
+                .values(&r)
+                .get_result(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, r.id.to_string()))?;
+
+            set_permissions(c, &r.id, &permissions).await?;
+
+            Ok(r)
+        })
+    })
+    .await?;
+    info!(id = %r.id, name = %r.name, "Tenant role created");
+    Ok(r)
+}
+
+pub async fn get(id: &Uuid) -> Result<TenantRole, Error> {
+    tenant_role::dsl::tenant_role
+        .find(&fields::Uuid::from(id))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, id.to_string()))
+}
+
+pub async fn get_permissions(id: &Uuid) -> Result<Vec<fields::Permission>, Error> {
+    let items = tenant_role_permission::dsl::tenant_role_permission
+        .select(tenant_role_permission::dsl::permission)
+        .filter(tenant_role_permission::dsl::role_id.eq(fields::Uuid::from(id)))
+        .order_by(tenant_role_permission::dsl::permission)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+pub async fn update(
+    r: TenantRole,
+    permissions: &[fields::Permission],
+) -> Result<TenantRole, Error> {
+    r.validate()?;
+
+    let permissions = permissions.to_vec();
+    let mut c = get_async_db_conn().await?;
+    let r: TenantRole = db_transaction::<TenantRole, Error, _>(&mut c, |c| {
+        Box::pin(async move {
+            let r: TenantRole = diesel::update(tenant_role::dsl::tenant_role.find(&r.id))
+                .set((
+                    tenant_role::updated_at.eq(Utc::now()),
+                    tenant_role::name.eq(&r.name),
+                    tenant_role::description.eq(&r.description),
+                ))
+                .get_result(c)
+                .await
+                .map_err(|e| Error::from_diesel(e, r.id.to_string()))?;
+
+            diesel::delete(
+                tenant_role_permission::dsl::tenant_role_permission
+                    .filter(tenant_role_permission::dsl::role_id.eq(&r.id)),
+            )
+            .execute(c)
+            .await?;
+            set_permissions(c, &r.id, &permissions).await?;
+
+            Ok(r)
+        })
+    })
+    .await?;
+    info!(id = %r.id, name = %r.name, "Tenant role updated");
+    Ok(r)
+}
+
+pub async fn delete(id: &Uuid) -> Result<(), Error> {
+    let ra = diesel::delete(tenant_role::dsl::tenant_role.find(&fields::Uuid::from(id)))
+        .execute(&mut get_async_db_conn().await?)
+        .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    info!(id = %id, "Tenant role deleted");
+    Ok(())
+}
+
+pub async fn get_count(tenant_id: &Uuid) -> Result<i64, Error> {
+    let count = tenant_role::dsl::tenant_role
+        .select(dsl::count_star())
+        .filter(tenant_role::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)))
+        .first(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(count)
+}
+
+pub async fn list(tenant_id: &Uuid, limit: i64, offset: i64) -> Result<Vec<TenantRole>, Error> {
+    let items = tenant_role::dsl::tenant_role
+        .filter(tenant_role::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)))
+        .order_by(tenant_role::dsl::name)
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+async fn set_permissions(
+    c: &mut AsyncDbPoolConnection,
+    role_id: &fields::Uuid,
+    permissions: &[fields::Permission],
+) -> Result<(), Error> {
+    if permissions.is_empty() {
+        return Ok(());
+    }
+
+    let values: Vec<_> = permissions
+        .iter()
+        .map(|p| {
+            (
+                tenant_role_permission::dsl::role_id.eq(role_id),
+                tenant_role_permission::dsl::permission.eq(p),
+            )
+        })
+        .collect();
+
+    diesel::insert_into(tenant_role_permission::table)
+        .values(&values)
+        .execute(c)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::tenant;
+    use crate::test;
+
+    pub async fn create_tenant_role(
+        tenant_id: &Uuid,
+        permissions: &[fields::Permission],
+    ) -> TenantRole {
+        create(
+            TenantRole {
+                tenant_id: (*tenant_id).into(),
+                name: "test role".into(),
+                ..Default::default()
+            },
+            permissions,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tenant_role() {
+        let _guard = test::prepare().await;
+        let t = tenant::test::create_tenant().await;
+
+        // create
+        let mut r = create_tenant_role(
+            &t.id,
+            &[
+                fields::Permission::DeviceRead,
+                fields::Permission::DeviceQueueWrite,
+            ],
+        )
+        .await;
+
+        // get
+        let r_get = get(&r.id).await.unwrap();
+        assert_eq!(r.id, r_get.id);
+        assert_eq!(
+            vec![
+                fields::Permission::DeviceRead,
+                fields::Permission::DeviceQueueWrite
+            ],
+            get_permissions(&r.id).await.unwrap()
+        );
+
+        // update
+        r.name = "updated role".into();
+        r = update(r, &[fields::Permission::GatewayRead]).await.unwrap();
+        assert_eq!("updated role", get(&r.id).await.unwrap().name);
+        assert_eq!(
+            vec![fields::Permission::GatewayRead],
+            get_permissions(&r.id).await.unwrap()
+        );
+
+        // count and list
+        assert_eq!(1, get_count(&t.id).await.unwrap());
+        let items = list(&t.id, 10, 0).await.unwrap();
+        assert_eq!(1, items.len());
+        assert_eq!(r.id, items[0].id);
+
+        // delete
+        delete(&r.id).await.unwrap();
+        assert!(delete(&r.id).await.is_err());
+        assert!(get_permissions(&r.id).await.unwrap().is_empty());
+    }
+}
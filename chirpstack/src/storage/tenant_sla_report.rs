@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use tracing::trace;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::tenant_sla_report;
+use super::{fields, get_async_db_conn};
+
+// SLA report of a tenant, aggregated per month. The month is stored as the first day of the
+// month. The API counters are not tenant specific, these are the counters of the ChirpStack
+// instance(s) for the given month.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = tenant_sla_report)]
+pub struct TenantSlaReport {
+    pub tenant_id: fields::Uuid,
+    pub month: NaiveDate,
+    pub uplink_count: i64,
+    pub uplink_success_count: i64,
+    pub downlink_count: i64,
+    pub downlink_success_count: i64,
+    pub api_request_count: i64,
+    pub api_success_count: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for TenantSlaReport {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        TenantSlaReport {
+            tenant_id: Uuid::nil().into(),
+            month: now.date_naive(),
+            uplink_count: 0,
+            uplink_success_count: 0,
+            downlink_count: 0,
+            downlink_success_count: 0,
+            api_request_count: 0,
+            api_success_count: 0,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Filters {
+    // Start month (inclusive).
+    pub start: Option<NaiveDate>,
+    // End month (exclusive).
+    pub end: Option<NaiveDate>,
+}
+
+// Upsert creates the given report or replaces the counters of the stored report. As the
+// counters are the totals of the month (and not the delta since the previous update), these
+// are not incremented.
+pub async fn upsert(r: TenantSlaReport) -> Result<TenantSlaReport, Error> {
+    let r: TenantSlaReport = diesel::insert_into(tenant_sla_report::table)
+        .values(&r)
+        .on_conflict((tenant_sla_report::tenant_id, tenant_sla_report::month))
+        .do_update()
+        .set((
+            tenant_sla_report::uplink_count.eq(excluded(tenant_sla_report::uplink_count)),
+            tenant_sla_report::uplink_success_count
+                .eq(excluded(tenant_sla_report::uplink_success_count)),
+            tenant_sla_report::downlink_count.eq(excluded(tenant_sla_report::downlink_count)),
+            tenant_sla_report::downlink_success_count
+                .eq(excluded(tenant_sla_report::downlink_success_count)),
+            tenant_sla_report::api_request_count.eq(excluded(tenant_sla_report::api_request_count)),
+            tenant_sla_report::api_success_count.eq(excluded(tenant_sla_report::api_success_count)),
+            tenant_sla_report::updated_at.eq(excluded(tenant_sla_report::updated_at)),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, r.tenant_id.to_string()))?;
+
+    trace!(tenant_id = %r.tenant_id, month = %r.month, "Tenant SLA report updated");
+    Ok(r)
+}
+
+pub async fn list(tenant_id: &Uuid, filters: &Filters) -> Result<Vec<TenantSlaReport>, Error> {
+    let mut q = tenant_sla_report::dsl::tenant_sla_report
+        .filter(tenant_sla_report::dsl::tenant_id.eq(fields::Uuid::from(tenant_id)))
+        .into_boxed();
+
+    if let Some(start) = &filters.start {
+        q = q.filter(tenant_sla_report::dsl::month.ge(start));
+    }
+
+    if let Some(end) = &filters.end {
+        q = q.filter(tenant_sla_report::dsl::month.lt(end));
+    }
+
+    let items = q
+        .order_by(tenant_sla_report::dsl::month)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::tenant;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_tenant_sla_report() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::test::create_tenant().await;
+        let month = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+
+        let r = TenantSlaReport {
+            tenant_id: t.id,
+            month,
+            uplink_count: 10,
+            uplink_success_count: 9,
+            ..Default::default()
+        };
+
+        // create
+        upsert(r.clone()).await.unwrap();
+
+        // update
+        upsert(TenantSlaReport {
+            uplink_count: 20,
+            uplink_success_count: 19,
+            downlink_count: 5,
+            downlink_success_count: 5,
+            ..r.clone()
+        })
+        .await
+        .unwrap();
+
+        // other month
+        upsert(TenantSlaReport {
+            month: NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+            ..r.clone()
+        })
+        .await
+        .unwrap();
+
+        // list all
+        let items = list(&t.id, &Filters::default()).await.unwrap();
+        assert_eq!(2, items.len());
+        assert_eq!(month, items[0].month);
+        assert_eq!(20, items[0].uplink_count);
+        assert_eq!(19, items[0].uplink_success_count);
+        assert_eq!(5, items[0].downlink_count);
+        assert_eq!(5, items[0].downlink_success_count);
+
+        // filter by month
+        let filters = Filters {
+            start: Some(month),
+            end: NaiveDate::from_ymd_opt(2025, 6, 1),
+        };
+        let items = list(&t.id, &filters).await.unwrap();
+        assert_eq!(1, items.len());
+
+        // other tenant
+        let items = list(&Uuid::new_v4(), &Filters::default()).await.unwrap();
+        assert!(items.is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use tracing::trace;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::tenant_usage;
+use super::{fields, get_async_db_conn};
+
+// Message usage of a tenant, aggregated per day (UTC). This is used for enforcing the uplinks and
+// downlinks per day quotas of the tenant.
+#[derive(Clone, Queryable, Insertable, Debug, PartialEq, Eq)]
+#[diesel(table_name = tenant_usage)]
+pub struct TenantUsage {
+    pub tenant_id: fields::Uuid,
+    pub day: NaiveDate,
+    pub uplink_count: i64,
+    pub downlink_count: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for TenantUsage {
+    fn default() -> Self {
+        let now = Utc::now();
+
+        TenantUsage {
+            tenant_id: Uuid::nil().into(),
+            day: now.date_naive(),
+            uplink_count: 0,
+            downlink_count: 0,
+            updated_at: now,
+        }
+    }
+}
+
+// Increment adds the counters of the given usage record to the stored usage record, creating
+// it when it does not yet exist. It returns the stored (total) usage record.
+pub async fn increment(u: TenantUsage) -> Result<TenantUsage, Error> {
+    let u: TenantUsage = diesel::insert_into(tenant_usage::table)
+        .values(&u)
+        .on_conflict((tenant_usage::tenant_id, tenant_usage::day))
+        .do_update()
+        .set((
+            tenant_usage::uplink_count
+                .eq(tenant_usage::uplink_count + excluded(tenant_usage::uplink_count)),
+            tenant_usage::downlink_count
+                .eq(tenant_usage::downlink_count + excluded(tenant_usage::downlink_count)),
+            tenant_usage::updated_at.eq(excluded(tenant_usage::updated_at)),
+        ))
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, u.tenant_id.to_string()))?;
+
+    trace!(tenant_id = %u.tenant_id, day = %u.day, "Tenant usage incremented");
+    Ok(u)
+}
+
+// Returns the usage of the given tenant and day. When no usage has been stored, a usage record
+// with zero counters is returned.
+pub async fn get(tenant_id: &Uuid, day: NaiveDate) -> Result<TenantUsage, Error> {
+    let u = tenant_usage::dsl::tenant_usage
+        .find((fields::Uuid::from(tenant_id), day))
+        .first(&mut get_async_db_conn().await?)
+        .await
+        .optional()?;
+
+    Ok(u.unwrap_or_else(|| TenantUsage {
+        tenant_id: (*tenant_id).into(),
+        day,
+        ..Default::default()
+    }))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::tenant;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_tenant_usage() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let day = NaiveDate::from_ymd_opt(2025, 8, 4).unwrap();
+
+        // no usage
+        let u = get(&t.id, day).await.unwrap();
+        assert_eq!(0, u.uplink_count);
+        assert_eq!(0, u.downlink_count);
+
+        // create
+        let u = increment(TenantUsage {
+            tenant_id: t.id,
+            day,
+            uplink_count: 10,
+            downlink_count: 1,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(10, u.uplink_count);
+        assert_eq!(1, u.downlink_count);
+
+        // increment
+        let u = increment(TenantUsage {
+            tenant_id: t.id,
+            day,
+            uplink_count: 5,
+            downlink_count: 0,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(15, u.uplink_count);
+        assert_eq!(1, u.downlink_count);
+
+        let u_get = get(&t.id, day).await.unwrap();
+        assert_eq!(u, u_get);
+
+        // other day
+        let u = get(&t.id, day.succ_opt().unwrap()).await.unwrap();
+        assert_eq!(0, u.uplink_count);
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use diesel::dsl;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use tracing::info;
+use uuid::Uuid;
+
+use super::error::Error;
+use super::schema::user_session;
+use super::{fields, get_async_db_conn};
+
+// UserSession is created for every issued user token (the session ID is used as the JWT ID),
+// such that tokens can be revoked.
+#[derive(Queryable, Insertable, PartialEq, Eq, Debug, Clone)]
+#[diesel(table_name = user_session)]
+pub struct UserSession {
+    pub id: fields::Uuid,
+    pub user_id: fields::Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl UserSession {
+    pub fn new(user_id: &Uuid, lifetime: Duration) -> Self {
+        let now = Utc::now();
+
+        UserSession {
+            id: Uuid::new_v4().into(),
+            user_id: user_id.into(),
+            created_at: now,
+            expires_at: now + lifetime,
+            revoked_at: None,
+        }
+    }
+}
+
+pub async fn create(s: UserSession) -> Result<UserSession, Error> {
+    let s: UserSession = diesel::insert_into(user_session::table)
+        .values(&s)
+        .get_result(&mut get_async_db_conn().await?)
+        .await
+        .map_err(|e| Error::from_diesel(e, s.id.to_string()))?;
+    info!(id = %s.id, user_id = %s.user_id, "User session created");
+    Ok(s)
+}
+
+// Returns the number of active (not revoked and not expired) sessions of the given user.
+pub async fn get_count(user_id: &Uuid) -> Result<i64, Error> {
+    Ok(user_session::dsl::user_session
+        .select(dsl::count_star())
+        .filter(user_session::dsl::user_id.eq(fields::Uuid::from(user_id)))
+        .filter(user_session::dsl::revoked_at.is_null())
+        .filter(user_session::dsl::expires_at.gt(Utc::now()))
+        .first(&mut get_async_db_conn().await?)
+        .await?)
+}
+
+// Returns the active (not revoked and not expired) sessions of the given user.
+pub async fn list(user_id: &Uuid, limit: i64, offset: i64) -> Result<Vec<UserSession>, Error> {
+    let items = user_session::dsl::user_session
+        .filter(user_session::dsl::user_id.eq(fields::Uuid::from(user_id)))
+        .filter(user_session::dsl::revoked_at.is_null())
+        .filter(user_session::dsl::expires_at.gt(Utc::now()))
+        .order_by(user_session::dsl::created_at.desc())
+        .limit(limit)
+        .offset(offset)
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items)
+}
+
+// Revokes the given session of the given user.
+pub async fn revoke(user_id: &Uuid, id: &Uuid) -> Result<(), Error> {
+    let ra = diesel::update(
+        user_session::dsl::user_session
+            .find(fields::Uuid::from(id))
+            .filter(user_session::dsl::user_id.eq(fields::Uuid::from(user_id)))
+            .filter(user_session::dsl::revoked_at.is_null()),
+    )
+    .set(user_session::revoked_at.eq(Some(Utc::now())))
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+    if ra == 0 {
+        return Err(Error::NotFound(id.to_string()));
+    }
+    info!(id = %id, user_id = %user_id, "User session revoked");
+    Ok(())
+}
+
+// Revokes all the sessions of the given user. It returns the number of revoked sessions.
+pub async fn revoke_all(user_id: &Uuid) -> Result<usize, Error> {
+    let ra = diesel::update(
+        user_session::dsl::user_session
+            .filter(user_session::dsl::user_id.eq(fields::Uuid::from(user_id)))
+            .filter(user_session::dsl::revoked_at.is_null())
+            .filter(user_session::dsl::expires_at.gt(Utc::now())),
+    )
+    .set(user_session::revoked_at.eq(Some(Utc::now())))
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+    info!(user_id = %user_id, count = ra, "User sessions revoked");
+    Ok(ra)
+}
+
+// Returns the IDs of the revoked sessions which are not yet expired (the revocation list).
+pub async fn get_revoked_ids() -> Result<Vec<Uuid>, Error> {
+    let items: Vec<fields::Uuid> = user_session::dsl::user_session
+        .select(user_session::dsl::id)
+        .filter(user_session::dsl::revoked_at.is_not_null())
+        .filter(user_session::dsl::expires_at.gt(Utc::now()))
+        .load(&mut get_async_db_conn().await?)
+        .await?;
+    Ok(items.into_iter().map(|v| v.into()).collect())
+}
+
+// Deletes the expired sessions. It returns the number of deleted sessions.
+pub async fn delete_expired() -> Result<usize, Error> {
+    let ra = diesel::delete(
+        user_session::dsl::user_session.filter(user_session::dsl::expires_at.le(Utc::now())),
+    )
+    .execute(&mut get_async_db_conn().await?)
+    .await?;
+    Ok(ra)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::user;
+    use crate::test;
+
+    #[tokio::test]
+    async fn test_user_session() {
+        let _guard = test::prepare().await;
+
+        let u = user::test::create_user().await;
+
+        let s1 = create(UserSession::new(&u.id, Duration::try_days(1).unwrap()))
+            .await
+            .unwrap();
+        let s2 = create(UserSession::new(&u.id, Duration::try_days(1).unwrap()))
+            .await
+            .unwrap();
+        let s_expired = create(UserSession::new(&u.id, Duration::try_days(-1).unwrap()))
+            .await
+            .unwrap();
+
+        // list, the expired session is not returned
+        assert_eq!(2, get_count(&u.id).await.unwrap());
+        let items = list(&u.id, 10, 0).await.unwrap();
+        assert_eq!(2, items.len());
+        assert!(items.contains(&s1));
+        assert!(items.contains(&s2));
+        assert!(get_revoked_ids().await.unwrap().is_empty());
+
+        // revoke
+        revoke(&u.id, &s1.id).await.unwrap();
+        assert!(revoke(&u.id, &s1.id).await.is_err());
+        assert!(revoke(&Uuid::new_v4(), &s2.id).await.is_err());
+        assert_eq!(1, get_count(&u.id).await.unwrap());
+        assert_eq!(vec![*s1.id], get_revoked_ids().await.unwrap());
+
+        // revoke all
+        assert_eq!(1, revoke_all(&u.id).await.unwrap());
+        assert_eq!(0, get_count(&u.id).await.unwrap());
+        let mut revoked = get_revoked_ids().await.unwrap();
+        revoked.sort();
+        let mut expected = vec![*s1.id, *s2.id];
+        expected.sort();
+        assert_eq!(expected, revoked);
+
+        // delete expired
+        assert_eq!(1, delete_expired().await.unwrap());
+        assert!(revoke(&u.id, &s_expired.id).await.is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::Cursor;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use prost::Message;
+use redis::streams::StreamReadReply;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::{debug, error};
+
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{get_async_redis_conn, redis_key};
+use chirpstack_api::api;
+use lrwn::EUI64;
+
+pub async fn log_trace_for_device(dev_eui: &EUI64, item: &api::LogItem) -> Result<()> {
+    let conf = config::get();
+
+    if conf.monitoring.per_device_debug_trace_max_history > 0 {
+        let key = redis_key(format!("device:{{{}}}:stream:trace", dev_eui));
+        let b = item.encode_to_vec();
+
+        () = redis::pipe()
+            .atomic()
+            .cmd("XADD")
+            .arg(&key)
+            .arg("MAXLEN")
+            .arg(conf.monitoring.per_device_debug_trace_max_history)
+            .arg("*")
+            .arg("trace")
+            .arg(&b)
+            .ignore()
+            .cmd("PEXPIRE")
+            .arg(&key)
+            .arg(conf.monitoring.per_device_debug_trace_ttl.as_millis() as usize)
+            .ignore()
+            .query_async(&mut get_async_redis_conn().await?)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_trace_logs(
+    key: String,
+    count: usize,
+    channel: mpsc::Sender<api::LogItem>,
+) -> Result<()> {
+    let mut last_id = "0".to_string();
+
+    loop {
+        if channel.is_closed() {
+            debug!("Channel has been closed, returning");
+            return Ok(());
+        }
+
+        let srr: StreamReadReply = redis::cmd("XREAD")
+            .arg("COUNT")
+            .arg(count)
+            .arg("STREAMS")
+            .arg(&key)
+            .arg(&last_id)
+            .query_async(&mut get_async_redis_conn().await?)
+            .await
+            .context("XREAD trace stream")?;
+
+        for stream_key in &srr.keys {
+            for stream_id in &stream_key.ids {
+                last_id.clone_from(&stream_id.id);
+                for (k, v) in &stream_id.map {
+                    let res = handle_stream(&last_id, &channel, k, v).await;
+
+                    if let Err(e) = res {
+                        // Return in case of channel error, in any other case we just log
+                        // the error.
+                        if e.downcast_ref::<mpsc::error::SendError<api::LogItem>>()
+                            .is_some()
+                        {
+                            return Err(e);
+                        }
+
+                        error!(key = %k, error = %e.full(), "Parsing trace-log error");
+                    }
+                }
+            }
+        }
+
+        // If we use xread with block=0, the connection can't be used by other requests. Now we
+        // check every 1 second if there are new messages, which should be sufficient.
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn handle_stream(
+    stream_id: &str,
+    channel: &mpsc::Sender<api::LogItem>,
+    k: &str,
+    v: &redis::Value,
+) -> Result<()> {
+    match k {
+        "trace" => {
+            if let redis::Value::BulkString(b) = v {
+                let mut pl = api::LogItem::decode(&mut Cursor::new(b))?;
+                pl.id = stream_id.to_string();
+                channel.send(pl).await?;
+            }
+        }
+        _ => {
+            error!(key = %k, "Unexpected key in trace-log stream");
+        }
+    }
+
+    Ok(())
+}
@@ -45,3 +45,24 @@ pub async fn log_downlink(down: &stream::DownlinkMeta) -> Result<()> {
 
     Ok(())
 }
+
+pub async fn log_uplink_dropped(pl: &stream::UplinkDropped) -> Result<()> {
+    let conf = config::get();
+
+    if conf.monitoring.meta_log_max_history > 0 && conf.monitoring.meta_log_dropped_uplinks {
+        let key = redis_key("stream:meta".to_string());
+        let b = pl.encode_to_vec();
+
+        () = redis::cmd("XADD")
+            .arg(&key)
+            .arg("MAXLEN")
+            .arg(conf.monitoring.meta_log_max_history)
+            .arg("*")
+            .arg("dropped")
+            .arg(&b)
+            .query_async(&mut get_async_redis_conn().await?)
+            .await?;
+    }
+
+    Ok(())
+}
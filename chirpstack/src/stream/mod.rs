@@ -1,5 +1,6 @@
 pub mod api_request;
 pub mod backend_interfaces;
+pub mod device_trace;
 pub mod event;
 pub mod frame;
 pub mod meta;
@@ -2,6 +2,7 @@ use std::str::FromStr;
 
 use bytes::Bytes;
 use chrono::Utc;
+use http::HeaderMap;
 use httpmock::prelude::*;
 use prost::Message;
 use uuid::Uuid;
@@ -12,7 +13,7 @@ use crate::gateway::backend as gateway_backend;
 use crate::storage::{
     application,
     device::{self, DeviceClass},
-    device_profile, device_queue, gateway, tenant,
+    device_profile, device_queue, fields, gateway, roaming_usage, tenant,
 };
 use crate::{config, test, uplink};
 use chirpstack_api::{common, gw, internal};
@@ -164,6 +165,178 @@ async fn test_fns_uplink() {
     sns_pr_start_req_mock.assert();
     sns_pr_start_req_mock.delete();
 
+    // Validate roaming usage.
+    let usage = roaming_usage::list(10, 0, &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(1, usage.len());
+    assert_eq!("000505", usage[0].net_id);
+    assert_eq!(fields::RoamingRole::FNS, usage[0].role);
+    assert_eq!(1, usage[0].uplink_count);
+    assert_eq!(0, usage[0].downlink_count);
+    assert!(usage[0].uplink_airtime_ms > 0);
+
+    joinserver::reset().await;
+}
+
+#[tokio::test]
+async fn test_fns_only_uplink() {
+    let _guard = test::prepare().await;
+
+    let sns_mock = MockServer::start();
+
+    let mut conf = (*config::get()).clone();
+
+    // Set NetID.
+    conf.network.net_id = NetID::from_str("000202").unwrap();
+
+    // Set roaming agreement with the sNS.
+    conf.roaming.servers.push(config::RoamingServer {
+        net_id: NetID::from_str("000505").unwrap(),
+        server: sns_mock.url("/"),
+        ..Default::default()
+    });
+
+    // Enable fNS-only mode.
+    conf.roaming.fns_only = config::RoamingFnsOnly {
+        enabled: true,
+        net_id: NetID::from_str("000505").unwrap(),
+    };
+
+    config::set(conf);
+    joinserver::setup().await.unwrap();
+    roaming::setup().await.unwrap();
+
+    let t = tenant::create(tenant::Tenant {
+        name: "tenant".into(),
+        can_have_gateways: true,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let gw = gateway::create(gateway::Gateway {
+        name: "gateway".into(),
+        tenant_id: t.id,
+        gateway_id: EUI64::from_str("0102030405060708").unwrap(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let recv_time = Utc::now();
+
+    let rx_info = gw::UplinkRxInfo {
+        gateway_id: gw.gateway_id.to_string(),
+        gw_time: Some(recv_time.into()),
+        location: Some(common::Location {
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude: 0.0,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let mut tx_info = gw::UplinkTxInfo {
+        frequency: 868100000,
+        ..Default::default()
+    };
+    uplink::helpers::set_uplink_modulation("eu868", &mut tx_info, 0).unwrap();
+
+    // The DevAddr does not need to match the NetID of the sNS, all uplinks are forwarded.
+    let dev_addr = lrwn::DevAddr::from_be_bytes([1, 2, 3, 4]);
+    let data_phy = lrwn::PhyPayload {
+        mhdr: lrwn::MHDR {
+            m_type: lrwn::MType::UnconfirmedDataUp,
+            major: lrwn::Major::LoRaWANR1,
+        },
+        payload: lrwn::Payload::MACPayload(lrwn::MACPayload {
+            fhdr: lrwn::FHDR {
+                devaddr: dev_addr,
+                f_ctrl: Default::default(),
+                f_cnt: 1,
+                f_opts: lrwn::MACCommandSet::new(vec![]),
+            },
+            f_port: None,
+            frm_payload: None,
+        }),
+        mic: Some([1, 2, 3, 4]),
+    };
+
+    // Setup sns mock.
+    let mut sns_xmit_data_req_mock = sns_mock.mock(|when, then| {
+        when.method(POST)
+            .path("/")
+            .json_body_obj(&backend::XmitDataReqPayload {
+                base: backend::BasePayload {
+                    sender_id: vec![0, 2, 2],
+                    receiver_id: vec![0, 5, 5],
+                    message_type: backend::MessageType::XmitDataReq,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                phy_payload: data_phy.to_vec().unwrap(),
+                ul_meta_data: Some(backend::ULMetaData {
+                    dev_addr: dev_addr.to_vec(),
+                    ul_freq: Some(868.1),
+                    data_rate: Some(0),
+                    recv_time,
+                    rf_region: "EU868".to_string(),
+                    gw_cnt: Some(1),
+                    gw_info: roaming::rx_info_to_gw_info(&[rx_info.clone()]).unwrap(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+
+        then.json_body_obj(&backend::XmitDataAnsPayload {
+            base: backend::BasePayloadResult {
+                base: backend::BasePayload {
+                    sender_id: vec![0, 5, 5],
+                    receiver_id: vec![0, 2, 2],
+                    message_type: backend::MessageType::XmitDataAns,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                result: backend::ResultPayload {
+                    result_code: backend::ResultCode::Success,
+                    ..Default::default()
+                },
+            },
+        })
+        .status(200);
+    });
+
+    gateway_backend::set_backend("eu868", Box::new(gateway_backend::mock::Backend {})).await;
+
+    // Simulate uplink
+    uplink::handle_uplink(
+        CommonName::EU868,
+        "eu868",
+        Uuid::new_v4(),
+        gw::UplinkFrameSet {
+            phy_payload: data_phy.to_vec().unwrap(),
+            tx_info: Some(tx_info),
+            rx_info: vec![rx_info],
+        },
+    )
+    .await
+    .unwrap();
+
+    sns_xmit_data_req_mock.assert();
+    sns_xmit_data_req_mock.delete();
+
+    // Validate roaming usage.
+    let usage = roaming_usage::list(10, 0, &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(1, usage.len());
+    assert_eq!("000505", usage[0].net_id);
+    assert_eq!(fields::RoamingRole::FNS, usage[0].role);
+    assert_eq!(dev_addr, usage[0].dev_addr);
+    assert_eq!(1, usage[0].uplink_count);
+
     joinserver::reset().await;
 }
 
@@ -380,9 +553,11 @@ async fn test_sns_uplink() {
         .status(200);
     });
 
-    let resp =
-        backend_api::handle_request(Bytes::from(serde_json::to_string(&pr_start_req).unwrap()))
-            .await;
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&pr_start_req).unwrap()),
+    )
+    .await;
     let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
         .await
         .unwrap();
@@ -418,6 +593,17 @@ async fn test_sns_uplink() {
 
     fns_xmit_data_req_mock.assert();
     fns_xmit_data_req_mock.delete();
+
+    // Validate roaming usage.
+    let usage = roaming_usage::list(10, 0, &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(1, usage.len());
+    assert_eq!("000202", usage[0].net_id);
+    assert_eq!(fields::RoamingRole::SNS, usage[0].role);
+    assert_eq!(dev.dev_eui, usage[0].dev_eui);
+    assert_eq!(1, usage[0].uplink_count);
+    assert_eq!(1, usage[0].downlink_count);
 }
 
 #[tokio::test]
@@ -574,9 +760,11 @@ async fn test_sns_roaming_not_allowed() {
         },
     };
 
-    let resp =
-        backend_api::handle_request(Bytes::from(serde_json::to_string(&pr_start_req).unwrap()))
-            .await;
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&pr_start_req).unwrap()),
+    )
+    .await;
     let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
         .await
         .unwrap();
@@ -687,9 +875,11 @@ async fn test_sns_dev_not_found() {
         },
     };
 
-    let resp =
-        backend_api::handle_request(Bytes::from(serde_json::to_string(&pr_start_req).unwrap()))
-            .await;
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&pr_start_req).unwrap()),
+    )
+    .await;
     let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
         .await
         .unwrap();
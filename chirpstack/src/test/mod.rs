@@ -1,6 +1,7 @@
 use std::env;
 use std::sync::{Mutex, Once};
 
+use crate::backend::roaming;
 use crate::{adr, config, region, storage};
 
 mod assert;
@@ -9,6 +10,7 @@ mod class_a_test;
 mod class_b_test;
 mod class_c_test;
 mod multicast_test;
+mod otaa_hr_test;
 mod otaa_js_test;
 mod otaa_pr_test;
 mod otaa_test;
@@ -72,7 +74,9 @@ pub async fn prepare<'a>() -> std::sync::MutexGuard<'a, ()> {
                     topic_prefix: "eu868".into(),
                     ..Default::default()
                 },
+                ..Default::default()
             },
+            ..Default::default()
         },
     }];
     config::set(conf);
@@ -83,6 +87,9 @@ pub async fn prepare<'a>() -> std::sync::MutexGuard<'a, ()> {
     // reset db
     storage::reset_db().await.unwrap();
 
+    // clear cache
+    storage::cache::clear();
+
     // flush redis db
     storage::reset_redis().await.unwrap();
 
@@ -92,5 +99,8 @@ pub async fn prepare<'a>() -> std::sync::MutexGuard<'a, ()> {
     // setup adr
     adr::setup().await.unwrap();
 
+    // reset roaming clients
+    roaming::reset().await;
+
     guard
 }
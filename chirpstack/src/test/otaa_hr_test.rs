@@ -0,0 +1,405 @@
+use std::str::FromStr;
+
+use bytes::Bytes;
+use chrono::Utc;
+use http::HeaderMap;
+use httpmock::prelude::*;
+
+use uuid::Uuid;
+
+use crate::api::backend as backend_api;
+use crate::backend::{joinserver, roaming};
+use crate::gateway::backend as gateway_backend;
+use crate::storage::{
+    application,
+    device::{self, DeviceClass},
+    device_keys, device_profile, gateway, handover_roaming, tenant,
+};
+use crate::{config, storage::fields, test, uplink};
+use chirpstack_api::gw;
+use lrwn::region::CommonName;
+use lrwn::{AES128Key, DevAddr, EUI64Prefix, NetID, EUI64};
+
+#[tokio::test]
+async fn test_hns() {
+    let _guard = test::prepare().await;
+
+    let sns_mock = MockServer::start();
+
+    let mut conf = (*config::get()).clone();
+
+    // Set NetID.
+    conf.network.net_id = NetID::from_str("010203").unwrap();
+
+    // Set roaming agreement.
+    conf.roaming.servers.push(config::RoamingServer {
+        net_id: NetID::from_str("030201").unwrap(),
+        server: sns_mock.url("/"),
+        handover_roaming_lifetime: std::time::Duration::from_secs(3600),
+        ..Default::default()
+    });
+
+    config::set(conf);
+    joinserver::setup().await.unwrap();
+    roaming::setup().await.unwrap();
+
+    let t = tenant::create(tenant::Tenant {
+        name: "tenant".into(),
+        can_have_gateways: true,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let app = application::create(application::Application {
+        name: "app".into(),
+        tenant_id: t.id,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let dp = device_profile::create(device_profile::DeviceProfile {
+        name: "dp".into(),
+        tenant_id: t.id,
+        region: lrwn::region::CommonName::EU868,
+        mac_version: lrwn::region::MacVersion::LORAWAN_1_0_2,
+        reg_params_revision: lrwn::region::Revision::A,
+        supports_otaa: true,
+        allow_roaming: true,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let dev = device::create(device::Device {
+        name: "device".into(),
+        application_id: app.id,
+        device_profile_id: dp.id,
+        dev_eui: EUI64::from_be_bytes([2, 2, 3, 4, 5, 6, 7, 8]),
+        enabled_class: DeviceClass::A,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let dk = device_keys::create(device_keys::DeviceKeys {
+        dev_eui: dev.dev_eui,
+        nwk_key: AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+        dev_nonces: fields::DevNonces::default(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let mut jr_phy = lrwn::PhyPayload {
+        mhdr: lrwn::MHDR {
+            m_type: lrwn::MType::JoinRequest,
+            major: lrwn::Major::LoRaWANR1,
+        },
+        payload: lrwn::Payload::JoinRequest(lrwn::JoinRequestPayload {
+            join_eui: EUI64::from_str("0000000000000000").unwrap(),
+            dev_eui: dev.dev_eui,
+            dev_nonce: 1,
+        }),
+        mic: None,
+    };
+    jr_phy.set_join_request_mic(&dk.nwk_key).unwrap();
+
+    let recv_time = Utc::now();
+
+    let mut rx_info = gw::UplinkRxInfo {
+        gateway_id: "0302030405060708".to_string(),
+        gw_time: Some(recv_time.into()),
+        location: Some(Default::default()),
+        ..Default::default()
+    };
+    rx_info
+        .metadata
+        .insert("region_config_id".to_string(), "eu868".to_string());
+    rx_info
+        .metadata
+        .insert("region_common_name".to_string(), "EU868".to_string());
+
+    let mut tx_info = gw::UplinkTxInfo {
+        frequency: 868100000,
+        ..Default::default()
+    };
+    uplink::helpers::set_uplink_modulation("eu868", &mut tx_info, 0).unwrap();
+
+    let hr_start_req = backend::HRStartReqPayload {
+        base: backend::BasePayload {
+            sender_id: vec![3, 2, 1],
+            receiver_id: vec![1, 2, 3],
+            message_type: backend::MessageType::HRStartReq,
+            transaction_id: 1234,
+            ..Default::default()
+        },
+        mac_version: "1.0.2".to_string(),
+        phy_payload: jr_phy.to_vec().unwrap(),
+        ul_meta_data: backend::ULMetaData {
+            dev_eui: dev.dev_eui.to_vec(),
+            ul_freq: Some(868.1),
+            data_rate: Some(0),
+            recv_time,
+            rf_region: "EU868".to_string(),
+            gw_cnt: Some(1),
+            gw_info: roaming::rx_info_to_gw_info(&[rx_info.clone()]).unwrap(),
+            ..Default::default()
+        },
+        dev_addr: vec![6, 2, 3, 4],
+        dl_settings: vec![0],
+        rx_delay: 1,
+        cf_list: vec![],
+    };
+
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&hr_start_req).unwrap()),
+    )
+    .await;
+    let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    let hr_start_ans: backend::HRStartAnsPayload = serde_json::from_slice(&resp_b).unwrap();
+    assert_eq!(
+        backend::ResultCode::Success,
+        hr_start_ans.base.result.result_code
+    );
+    assert_eq!(dev.dev_eui.to_vec(), hr_start_ans.dev_eui);
+    assert_eq!(Some(3600), hr_start_ans.lifetime);
+    assert!(hr_start_ans.nwk_s_key.is_some());
+    assert!(!hr_start_ans.phy_payload.is_empty());
+
+    // The device uses the DevAddr assigned by the sNS.
+    let d = device::get(&dev.dev_eui).await.unwrap();
+    assert_eq!(Some(DevAddr::from_be_bytes([6, 2, 3, 4])), d.dev_addr);
+
+    let sess = handover_roaming::get(&dev.dev_eui).await.unwrap();
+    assert_eq!(vec![3, 2, 1], sess.net_id);
+    assert_eq!(vec![6, 2, 3, 4], sess.dev_addr);
+
+    // Stop the handover-roaming session.
+    let hr_stop_req = backend::HRStopReqPayload {
+        base: backend::BasePayload {
+            sender_id: vec![3, 2, 1],
+            receiver_id: vec![1, 2, 3],
+            message_type: backend::MessageType::HRStopReq,
+            transaction_id: 1235,
+            ..Default::default()
+        },
+        dev_eui: dev.dev_eui.to_vec(),
+    };
+
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&hr_stop_req).unwrap()),
+    )
+    .await;
+    let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    let hr_stop_ans: backend::HRStopAnsPayload = serde_json::from_slice(&resp_b).unwrap();
+    assert_eq!(
+        backend::ResultCode::Success,
+        hr_stop_ans.base.result.result_code
+    );
+    assert!(handover_roaming::get(&dev.dev_eui).await.is_err());
+
+    joinserver::reset().await;
+}
+
+#[tokio::test]
+async fn test_sns() {
+    let _guard = test::prepare().await;
+
+    let js_mock = MockServer::start();
+    let hns_mock = MockServer::start();
+
+    let mut conf = (*config::get()).clone();
+
+    // Set NetID.
+    conf.network.net_id = NetID::from_str("010203").unwrap();
+
+    // Set Join Server.
+    conf.join_server.servers = vec![config::JoinServerServer {
+        join_eui_prefix: EUI64Prefix::new([1, 2, 3, 4, 5, 6, 7, 8], 64),
+        server: js_mock.url("/"),
+        ..Default::default()
+    }];
+
+    // Set roaming agreement.
+    conf.roaming.servers = vec![config::RoamingServer {
+        net_id: NetID::from_str("030201").unwrap(),
+        server: hns_mock.url("/"),
+        use_handover_roaming: true,
+        ..Default::default()
+    }];
+
+    config::set(conf);
+    joinserver::setup().await.unwrap();
+    roaming::setup().await.unwrap();
+
+    let t = tenant::create(tenant::Tenant {
+        name: "tenant".into(),
+        can_have_gateways: true,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let gw = gateway::create(gateway::Gateway {
+        name: "gateway".into(),
+        tenant_id: t.id,
+        gateway_id: EUI64::from_str("0102030405060708").unwrap(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let recv_time = Utc::now();
+
+    let rx_info = gw::UplinkRxInfo {
+        gateway_id: gw.gateway_id.to_string(),
+        gw_time: Some(recv_time.into()),
+        location: Some(Default::default()),
+        ..Default::default()
+    };
+
+    let mut tx_info = gw::UplinkTxInfo {
+        frequency: 868100000,
+        ..Default::default()
+    };
+    uplink::helpers::set_uplink_modulation("eu868", &mut tx_info, 0).unwrap();
+
+    let mut jr_phy = lrwn::PhyPayload {
+        mhdr: lrwn::MHDR {
+            m_type: lrwn::MType::JoinRequest,
+            major: lrwn::Major::LoRaWANR1,
+        },
+        payload: lrwn::Payload::JoinRequest(lrwn::JoinRequestPayload {
+            join_eui: EUI64::from_str("0102030405060708").unwrap(),
+            dev_eui: EUI64::from_str("0807060504030201").unwrap(),
+            dev_nonce: 123,
+        }),
+        mic: None,
+    };
+    jr_phy
+        .set_join_request_mic(&AES128Key::from_str("01020304050607080102030405060708").unwrap())
+        .unwrap();
+
+    // Setup JS mock (HomeNSReq).
+    let mut js_join_request_mock = js_mock.mock(|when, then| {
+        when.method(POST)
+            .path("/")
+            .json_body_obj(&backend::HomeNSReqPayload {
+                base: backend::BasePayload {
+                    sender_id: vec![1, 2, 3],
+                    receiver_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                    message_type: backend::MessageType::HomeNSReq,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                dev_eui: vec![8, 7, 6, 5, 4, 3, 2, 1],
+            });
+
+        then.json_body_obj(&backend::HomeNSAnsPayload {
+            base: backend::BasePayloadResult {
+                base: backend::BasePayload {
+                    receiver_id: vec![1, 2, 3],
+                    sender_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                    message_type: backend::MessageType::HomeNSAns,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                result: backend::ResultPayload {
+                    result_code: backend::ResultCode::Success,
+                    ..Default::default()
+                },
+            },
+            h_net_id: vec![3, 2, 1],
+        })
+        .status(200);
+    });
+
+    // Setup hNS mock (HRStartReq). The DevAddr is randomly assigned by the sNS.
+    let mut hns_hr_start_req_mock = hns_mock.mock(|when, then| {
+        when.method(POST)
+            .path("/")
+            .json_body_partial(r#"{"MessageType": "HRStartReq"}"#);
+
+        then.json_body_obj(&backend::HRStartAnsPayload {
+            base: backend::BasePayloadResult {
+                base: backend::BasePayload {
+                    receiver_id: vec![1, 2, 3],
+                    sender_id: vec![3, 2, 1],
+                    message_type: backend::MessageType::HRStartAns,
+                    transaction_id: 1234,
+                    ..Default::default()
+                },
+                result: backend::ResultPayload {
+                    result_code: backend::ResultCode::Success,
+                    ..Default::default()
+                },
+            },
+            phy_payload: vec![1, 2, 3, 4],
+            dev_eui: vec![8, 7, 6, 5, 4, 3, 2, 1],
+            lifetime: Some(3600),
+            nwk_s_key: Some(backend::KeyEnvelope {
+                kek_label: "".into(),
+                aes_key: vec![1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8],
+            }),
+            ..Default::default()
+        })
+        .status(200);
+    });
+
+    gateway_backend::set_backend("eu868", Box::new(gateway_backend::mock::Backend {})).await;
+    gateway_backend::mock::reset().await;
+
+    // Simulate uplink
+    uplink::handle_uplink(
+        CommonName::EU868,
+        "eu868",
+        Uuid::new_v4(),
+        gw::UplinkFrameSet {
+            phy_payload: jr_phy.to_vec().unwrap(),
+            tx_info: Some(tx_info),
+            rx_info: vec![rx_info],
+        },
+    )
+    .await
+    .unwrap();
+
+    js_join_request_mock.assert();
+    js_join_request_mock.delete();
+
+    hns_hr_start_req_mock.assert();
+    hns_hr_start_req_mock.delete();
+
+    // The join-accept returned by the hNS is sent using the region defaults.
+    let downlinks = gateway_backend::mock::get_downlink_frames().await;
+    assert_eq!(1, downlinks.len());
+    assert_eq!(vec![1, 2, 3, 4], downlinks[0].items[0].phy_payload);
+    assert_eq!(
+        868100000,
+        downlinks[0].items[0].tx_info.as_ref().unwrap().frequency
+    );
+
+    // The serving device-session contains the keys received from the hNS.
+    let dev_eui = EUI64::from_str("0807060504030201").unwrap();
+    let sess = handover_roaming::get_serving(&dev_eui).await.unwrap();
+    assert_eq!(vec![3, 2, 1], sess.net_id);
+    assert_eq!(
+        vec![1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8],
+        sess.f_nwk_s_int_key
+    );
+    assert_eq!(sess.f_nwk_s_int_key, sess.s_nwk_s_int_key);
+    assert_eq!(sess.f_nwk_s_int_key, sess.nwk_s_enc_key);
+    assert_eq!(0, sess.f_cnt_up);
+    assert_eq!(0, sess.n_f_cnt_down);
+
+    joinserver::reset().await;
+}
@@ -2,6 +2,7 @@ use std::str::FromStr;
 
 use bytes::Bytes;
 use chrono::Utc;
+use http::HeaderMap;
 use httpmock::prelude::*;
 use prost::Message;
 use uuid::Uuid;
@@ -373,9 +374,11 @@ async fn test_sns() {
         },
     };
 
-    let resp =
-        backend_api::handle_request(Bytes::from(serde_json::to_string(&pr_start_req).unwrap()))
-            .await;
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&pr_start_req).unwrap()),
+    )
+    .await;
     let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
         .await
         .unwrap();
@@ -558,9 +561,11 @@ async fn test_sns_roaming_not_allowed() {
         },
     };
 
-    let resp =
-        backend_api::handle_request(Bytes::from(serde_json::to_string(&pr_start_req).unwrap()))
-            .await;
+    let resp = backend_api::handle_request(
+        HeaderMap::new(),
+        Bytes::from(serde_json::to_string(&pr_start_req).unwrap()),
+    )
+    .await;
     let resp_b = axum::body::to_bytes(resp.into_body(), usize::MAX)
         .await
         .unwrap();
@@ -489,6 +489,7 @@ async fn test_lorawan_10() {
                 }),
                 assert::downlink_frame_saved(internal::DownlinkFrame {
                     dev_eui: vec![2, 2, 3, 4, 5, 6, 7, 8],
+                    region_config_id: "eu868".to_string(),
                     nwk_s_enc_key: vec![
                         128, 47, 168, 41, 62, 215, 212, 79, 19, 83, 183, 201, 43, 169, 125, 200,
                     ],
@@ -1105,6 +1106,7 @@ async fn test_lorawan_11() {
                 }),
                 assert::downlink_frame_saved(internal::DownlinkFrame {
                     dev_eui: vec![2, 2, 3, 4, 5, 6, 7, 8],
+                    region_config_id: "eu868".to_string(),
                     nwk_s_enc_key: vec![
                         151, 120, 115, 101, 67, 122, 194, 153, 113, 209, 134, 158, 149, 189, 192,
                         175,
@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::api::helpers::ToProto;
+use crate::storage::{application, device, device_keys, device_profile, fields};
+use crate::{config, region};
+use chirpstack_api::{common, internal};
+use lrwn::region::{CommonName, MacVersion, Revision};
+use lrwn::{AES128Key, DevAddr, EUI64};
+
+// The structs below are the (partial) JSON definitions of the end-devices as exported by The
+// Things Stack (e.g. ttn-lw-cli end-devices get --all). Fields which are not used for the import
+// are omitted.
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct EndDevice {
+    pub ids: EndDeviceIdentifiers,
+    pub name: String,
+    pub description: String,
+    pub attributes: HashMap<String, String>,
+    pub lorawan_version: String,
+    pub lorawan_phy_version: String,
+    pub frequency_plan_id: String,
+    pub supports_join: bool,
+    pub supports_class_b: bool,
+    pub supports_class_c: bool,
+    pub root_keys: Option<RootKeys>,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub last_join_nonce: u32,
+    pub mac_settings: Option<MacSettings>,
+    pub session: Option<Session>,
+    pub mac_state: Option<MacState>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct EndDeviceIdentifiers {
+    pub device_id: String,
+    pub dev_eui: String,
+    pub join_eui: String,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct RootKeys {
+    pub app_key: Option<Key>,
+    pub nwk_key: Option<Key>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Key {
+    pub key: String,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct MacSettings {
+    pub resets_f_cnt: Option<BoolValue>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct BoolValue {
+    pub value: bool,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct Session {
+    pub dev_addr: String,
+    pub keys: SessionKeys,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub last_f_cnt_up: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub last_n_f_cnt_down: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub last_a_f_cnt_down: u32,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct SessionKeys {
+    pub f_nwk_s_int_key: Option<Key>,
+    pub s_nwk_s_int_key: Option<Key>,
+    pub nwk_s_enc_key: Option<Key>,
+    pub app_s_key: Option<Key>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct MacState {
+    pub current_parameters: Option<MacParameters>,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+pub struct MacParameters {
+    #[serde(deserialize_with = "deserialize_number")]
+    pub rx1_delay: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub rx1_data_rate_offset: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub rx2_data_rate_index: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub rx2_frequency: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub adr_data_rate_index: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub adr_tx_power_index: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub adr_nb_trans: u32,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub ping_slot_frequency: u32,
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct ImportResult {
+    pub device_count: usize,
+    pub device_profile_count: usize,
+    // Errors by device ID.
+    pub errors: Vec<(String, String)>,
+}
+
+// The Things Stack encodes enum values as string (e.g. RX_DELAY_5 or DATA_RATE_3) and 64 bit
+// integers as string. This returns the number at the end of the string.
+fn deserialize_number<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(v) => v
+            .as_u64()
+            .map(|v| v as u32)
+            .ok_or_else(|| serde::de::Error::custom(format!("Invalid number: {}", v))),
+        serde_json::Value::String(v) => {
+            let digits: String = v
+                .chars()
+                .rev()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<Vec<char>>()
+                .into_iter()
+                .rev()
+                .collect();
+            digits
+                .parse()
+                .map_err(|_| serde::de::Error::custom(format!("Invalid number: {}", v)))
+        }
+        serde_json::Value::Null => Ok(0),
+        v => Err(serde::de::Error::custom(format!("Invalid number: {}", v))),
+    }
+}
+
+// Parses the given export, this can either be a JSON array or a stream of JSON objects (as
+// printed by ttn-lw-cli).
+pub fn parse(data: &str) -> Result<Vec<EndDevice>> {
+    let data = data.trim();
+    if data.starts_with('[') {
+        return serde_json::from_str(data).context("Parse end-devices");
+    }
+
+    serde_json::Deserializer::from_str(data)
+        .into_iter::<EndDevice>()
+        .map(|v| v.context("Parse end-device"))
+        .collect()
+}
+
+// Imports the given end-devices into the given application. For each unique combination of
+// frequency-plan, LoRaWAN version and device capabilities, a device-profile is created (or
+// re-used in case it already exists). Errors are returned per device, such that a single invalid
+// device does not prevent the import of the other devices.
+pub async fn import(application_id: Uuid, devices: &[EndDevice]) -> Result<ImportResult> {
+    let app = application::get(&application_id).await?;
+    let mut device_profiles: HashMap<String, Uuid> = HashMap::new();
+    let mut out = ImportResult::default();
+
+    for ed in devices {
+        match import_device(&app, ed, &mut device_profiles, &mut out).await {
+            Ok(_) => {
+                out.device_count += 1;
+            }
+            Err(e) => {
+                warn!(device_id = %ed.ids.device_id, error = %e, "Import end-device failed");
+                out.errors.push((ed.ids.device_id.clone(), e.to_string()));
+            }
+        }
+    }
+
+    info!(application_id = %application_id, device_count = out.device_count, device_profile_count = out.device_profile_count, error_count = out.errors.len(), "End-devices imported from The Things Stack");
+    Ok(out)
+}
+
+async fn import_device(
+    app: &application::Application,
+    ed: &EndDevice,
+    device_profiles: &mut HashMap<String, Uuid>,
+    out: &mut ImportResult,
+) -> Result<()> {
+    let dp = get_device_profile(ed)?;
+    let dp_id = match device_profiles.get(&dp.name) {
+        Some(v) => *v,
+        None => {
+            let id = get_or_create_device_profile(app.tenant_id.into(), dp.clone(), out).await?;
+            device_profiles.insert(dp.name.clone(), id);
+            id
+        }
+    };
+
+    let dev_eui = EUI64::from_str(&ed.ids.dev_eui).context("Parse DevEUI")?;
+    let region_config_id = region::get_region_config_id(dp.region)?;
+    let ds = match &ed.session {
+        Some(v) => Some(get_device_session(ed, v, &dp, &region_config_id)?),
+        None => None,
+    };
+
+    let d = device::Device {
+        dev_eui,
+        application_id: app.id,
+        device_profile_id: dp_id.into(),
+        name: if ed.name.is_empty() {
+            ed.ids.device_id.clone()
+        } else {
+            ed.name.clone()
+        },
+        description: ed.description.clone(),
+        skip_fcnt_check: ed
+            .mac_settings
+            .as_ref()
+            .and_then(|v| v.resets_f_cnt.as_ref())
+            .map(|v| v.value)
+            .unwrap_or_default(),
+        tags: fields::KeyValue::new(ed.attributes.clone()),
+        join_eui: if ed.ids.join_eui.is_empty() {
+            EUI64::default()
+        } else {
+            EUI64::from_str(&ed.ids.join_eui).context("Parse JoinEUI")?
+        },
+        dev_addr: match &ds {
+            Some(v) => Some(DevAddr::from_slice(&v.dev_addr)?),
+            None => None,
+        },
+        // LoRaWAN 1.1 devices send a mac-command when changing to Class-C.
+        enabled_class: if ds.is_some()
+            && dp.supports_class_c
+            && dp.mac_version.to_string().starts_with("1.0")
+        {
+            device::DeviceClass::C
+        } else {
+            device::DeviceClass::A
+        },
+        device_session: ds.map(|v| v.into()),
+        ..Default::default()
+    };
+    // The device and device-keys are created in a single transaction, such that a failure does
+    // not leave a partially imported device behind.
+    match get_device_keys(ed, &dp)? {
+        Some(dk) => {
+            device::create_with_keys(d, dk).await?;
+        }
+        None => {
+            device::create(d).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn get_or_create_device_profile(
+    tenant_id: Uuid,
+    dp: device_profile::DeviceProfile,
+    out: &mut ImportResult,
+) -> Result<Uuid> {
+    let items = device_profile::list(
+        10,
+        0,
+        &device_profile::Filters {
+            tenant_id: Some(tenant_id),
+            search: Some(dp.name.clone()),
+        },
+    )
+    .await?;
+
+    if let Some(dp) = items.iter().find(|v| v.name == dp.name) {
+        return Ok(dp.id.into());
+    }
+
+    let dp = device_profile::create(device_profile::DeviceProfile {
+        tenant_id: tenant_id.into(),
+        ..dp
+    })
+    .await?;
+    out.device_profile_count += 1;
+
+    Ok(dp.id.into())
+}
+
+fn get_device_profile(ed: &EndDevice) -> Result<device_profile::DeviceProfile> {
+    let region = get_common_name(&ed.frequency_plan_id)?;
+    let mac_version = get_mac_version(&ed.lorawan_version)?;
+    let reg_params_revision = get_revision(&ed.lorawan_phy_version)?;
+
+    let mut name = format!(
+        "TTN: {}, LoRaWAN {}, RP {}, {}",
+        ed.frequency_plan_id,
+        mac_version,
+        reg_params_revision,
+        if ed.supports_join { "OTAA" } else { "ABP" }
+    );
+    if ed.supports_class_b {
+        name.push_str(", Class-B");
+    }
+    if ed.supports_class_c {
+        name.push_str(", Class-C");
+    }
+
+    Ok(device_profile::DeviceProfile {
+        name,
+        description: "Imported from The Things Stack.".into(),
+        region,
+        mac_version,
+        reg_params_revision,
+        adr_algorithm_id: "default".into(),
+        uplink_interval: 60 * 60,
+        supports_otaa: ed.supports_join,
+        supports_class_b: ed.supports_class_b,
+        supports_class_c: ed.supports_class_c,
+        abp_params: if ed.supports_join {
+            None
+        } else {
+            let network_conf = config::get_region_network(&region::get_region_config_id(region)?)?;
+            Some(fields::AbpParams {
+                rx1_delay: network_conf.rx1_delay,
+                rx1_dr_offset: network_conf.rx1_dr_offset,
+                rx2_dr: network_conf.rx2_dr,
+                rx2_freq: network_conf.rx2_frequency,
+            })
+        },
+        ..Default::default()
+    })
+}
+
+fn get_device_keys(
+    ed: &EndDevice,
+    dp: &device_profile::DeviceProfile,
+) -> Result<Option<device_keys::DeviceKeys>> {
+    let root_keys = match &ed.root_keys {
+        Some(v) if ed.supports_join => v,
+        _ => return Ok(None),
+    };
+
+    let app_key = get_key(&root_keys.app_key).context("Parse AppKey")?;
+    let nwk_key = get_key(&root_keys.nwk_key).context("Parse NwkKey")?;
+
+    // For LoRaWAN 1.0.x devices, the AppKey is stored as NwkKey.
+    let (nwk_key, app_key) = if dp.mac_version.to_string().starts_with("1.0") {
+        (app_key.or(nwk_key), None)
+    } else {
+        (nwk_key, app_key)
+    };
+
+    let nwk_key = match nwk_key {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    Ok(Some(device_keys::DeviceKeys {
+        nwk_key,
+        app_key: app_key.unwrap_or_default(),
+        join_nonce: ed.last_join_nonce as i32,
+        ..Default::default()
+    }))
+}
+
+fn get_device_session(
+    ed: &EndDevice,
+    s: &Session,
+    dp: &device_profile::DeviceProfile,
+    region_config_id: &str,
+) -> Result<internal::DeviceSession> {
+    let region_conf = region::get(region_config_id)?;
+    let network_conf = config::get_region_network(region_config_id)?;
+
+    let f_nwk_s_int_key = get_key(&s.keys.f_nwk_s_int_key)
+        .context("Parse FNwkSIntKey")?
+        .ok_or_else(|| anyhow!("Session FNwkSIntKey is missing"))?;
+    let app_s_key = get_key(&s.keys.app_s_key)
+        .context("Parse AppSKey")?
+        .ok_or_else(|| anyhow!("Session AppSKey is missing"))?;
+
+    // For LoRaWAN 1.0.x devices, the FNwkSIntKey is used as NwkSKey.
+    let s_nwk_s_int_key = get_key(&s.keys.s_nwk_s_int_key)
+        .context("Parse SNwkSIntKey")?
+        .unwrap_or(f_nwk_s_int_key);
+    let nwk_s_enc_key = get_key(&s.keys.nwk_s_enc_key)
+        .context("Parse NwkSEncKey")?
+        .unwrap_or(f_nwk_s_int_key);
+
+    let mut ds = internal::DeviceSession {
+        region_config_id: region_config_id.to_string(),
+        dev_addr: DevAddr::from_str(&s.dev_addr)
+            .context("Parse DevAddr")?
+            .to_vec(),
+        mac_version: dp.mac_version.to_proto().into(),
+        f_nwk_s_int_key: f_nwk_s_int_key.to_vec(),
+        s_nwk_s_int_key: s_nwk_s_int_key.to_vec(),
+        nwk_s_enc_key: nwk_s_enc_key.to_vec(),
+        app_s_key: Some(common::KeyEnvelope {
+            kek_label: "".into(),
+            aes_key: app_s_key.to_vec(),
+        }),
+        // The Things Stack stores the last frame-counters, ChirpStack the next expected
+        // frame-counters. As the last uplink frame-counter is 0 when no uplink has been
+        // received yet, the frame-counter is only incremented when it is not 0.
+        f_cnt_up: if s.last_f_cnt_up > 0 {
+            s.last_f_cnt_up + 1
+        } else {
+            0
+        },
+        n_f_cnt_down: s.last_n_f_cnt_down + 1,
+        a_f_cnt_down: s.last_a_f_cnt_down + 1,
+        rx1_delay: network_conf.rx1_delay.into(),
+        rx1_dr_offset: network_conf.rx1_dr_offset.into(),
+        rx2_dr: network_conf.rx2_dr.into(),
+        rx2_frequency: if network_conf.rx2_frequency != 0 {
+            network_conf.rx2_frequency
+        } else {
+            region_conf.get_defaults().rx2_frequency
+        },
+        enabled_uplink_channel_indices: region_conf
+            .get_default_uplink_channel_indices()
+            .iter()
+            .map(|i| *i as u32)
+            .collect(),
+        nb_trans: 1,
+        ..Default::default()
+    };
+
+    if let Some(p) = ed
+        .mac_state
+        .as_ref()
+        .and_then(|v| v.current_parameters.as_ref())
+    {
+        ds.rx1_delay = p.rx1_delay;
+        ds.rx1_dr_offset = p.rx1_data_rate_offset;
+        ds.rx2_dr = p.rx2_data_rate_index;
+        if p.rx2_frequency != 0 {
+            ds.rx2_frequency = p.rx2_frequency;
+        }
+        ds.dr = p.adr_data_rate_index;
+        ds.tx_power_index = p.adr_tx_power_index;
+        ds.nb_trans = p.adr_nb_trans.max(1);
+        ds.class_b_ping_slot_freq = p.ping_slot_frequency;
+    }
+
+    Ok(ds)
+}
+
+fn get_key(key: &Option<Key>) -> Result<Option<AES128Key>> {
+    match key {
+        Some(v) if !v.key.is_empty() => Ok(Some(AES128Key::from_str(&v.key)?)),
+        _ => Ok(None),
+    }
+}
+
+fn get_common_name(frequency_plan_id: &str) -> Result<CommonName> {
+    let plans = [
+        ("EU_863_870", CommonName::EU868),
+        ("EU_433", CommonName::EU433),
+        ("US_902_928", CommonName::US915),
+        ("AU_915_928", CommonName::AU915),
+        ("CN_470_510", CommonName::CN470),
+        ("CN_779_787", CommonName::CN779),
+        ("AS_920_923", CommonName::AS923),
+        ("AS_923_925", CommonName::AS923_2),
+        ("KR_920_923", CommonName::KR920),
+        ("IN_865_867", CommonName::IN865),
+        ("RU_864_870", CommonName::RU864),
+        ("ISM_2400", CommonName::ISM2400),
+    ];
+
+    plans
+        .iter()
+        .find(|(prefix, _)| frequency_plan_id.starts_with(prefix))
+        .map(|(_, common_name)| *common_name)
+        .ok_or_else(|| anyhow!("Unsupported frequency plan: {}", frequency_plan_id))
+}
+
+fn get_mac_version(lorawan_version: &str) -> Result<MacVersion> {
+    Ok(match lorawan_version {
+        "MAC_V1_0" => MacVersion::LORAWAN_1_0_0,
+        "MAC_V1_0_1" => MacVersion::LORAWAN_1_0_1,
+        "MAC_V1_0_2" => MacVersion::LORAWAN_1_0_2,
+        "MAC_V1_0_3" => MacVersion::LORAWAN_1_0_3,
+        "MAC_V1_0_4" => MacVersion::LORAWAN_1_0_4,
+        "MAC_V1_1" => MacVersion::LORAWAN_1_1_0,
+        _ => return Err(anyhow!("Unsupported LoRaWAN version: {}", lorawan_version)),
+    })
+}
+
+fn get_revision(lorawan_phy_version: &str) -> Result<Revision> {
+    Ok(match lorawan_phy_version {
+        "PHY_V1_0" | "PHY_V1_0_1" | "PHY_V1_0_2_REV_A" | "PHY_V1_1_REV_A" | "PHY_V1_0_3_REV_A" => {
+            Revision::A
+        }
+        "PHY_V1_0_2_REV_B" | "PHY_V1_1_REV_B" => Revision::B,
+        "RP001_V1_1_REV_A" => Revision::A,
+        "RP001_V1_1_REV_B" => Revision::B,
+        "RP002_V1_0_0" => Revision::RP002_1_0_0,
+        "RP002_V1_0_1" => Revision::RP002_1_0_1,
+        "RP002_V1_0_2" => Revision::RP002_1_0_2,
+        "RP002_V1_0_3" => Revision::RP002_1_0_3,
+        "RP002_V1_0_4" => Revision::RP002_1_0_4,
+        _ => {
+            return Err(anyhow!(
+                "Unsupported regional parameters version: {}",
+                lorawan_phy_version
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage::tenant;
+    use crate::test;
+
+    const EXPORT: &str = r#"
+{
+  "ids": {
+    "device_id": "otaa-device",
+    "application_ids": {"application_id": "test-app"},
+    "dev_eui": "0102030405060708",
+    "join_eui": "0807060504030201"
+  },
+  "name": "OTAA device",
+  "attributes": {"location": "office"},
+  "lorawan_version": "MAC_V1_0_3",
+  "lorawan_phy_version": "PHY_V1_0_3_REV_A",
+  "frequency_plan_id": "EU_863_870_TTN",
+  "supports_join": true,
+  "supports_class_c": true,
+  "root_keys": {
+    "app_key": {"key": "01020304050607080102030405060708"}
+  },
+  "last_join_nonce": 3,
+  "mac_settings": {"resets_f_cnt": {"value": true}},
+  "session": {
+    "dev_addr": "01020304",
+    "keys": {
+      "f_nwk_s_int_key": {"key": "02020304050607080102030405060708"},
+      "app_s_key": {"key": "03020304050607080102030405060708"}
+    },
+    "last_f_cnt_up": 10,
+    "last_n_f_cnt_down": 5
+  },
+  "mac_state": {
+    "current_parameters": {
+      "rx1_delay": "RX_DELAY_5",
+      "rx1_data_rate_offset": "DATA_RATE_OFFSET_1",
+      "rx2_data_rate_index": "DATA_RATE_3",
+      "rx2_frequency": "869525000",
+      "adr_data_rate_index": "DATA_RATE_5",
+      "adr_tx_power_index": 1,
+      "adr_nb_trans": 1
+    }
+  }
+}
+{
+  "ids": {
+    "device_id": "invalid-device",
+    "dev_eui": "0202030405060708"
+  },
+  "lorawan_version": "MAC_V1_0_3",
+  "lorawan_phy_version": "PHY_V1_0_3_REV_A",
+  "frequency_plan_id": "UNKNOWN"
+}
+"#;
+
+    #[test]
+    fn test_parse() {
+        let devices = parse(EXPORT).unwrap();
+        assert_eq!(2, devices.len());
+        assert_eq!("otaa-device", devices[0].ids.device_id);
+
+        let p = devices[0]
+            .mac_state
+            .as_ref()
+            .unwrap()
+            .current_parameters
+            .as_ref()
+            .unwrap();
+        assert_eq!(5, p.rx1_delay);
+        assert_eq!(1, p.rx1_data_rate_offset);
+        assert_eq!(3, p.rx2_data_rate_index);
+        assert_eq!(869525000, p.rx2_frequency);
+        assert_eq!(5, p.adr_data_rate_index);
+
+        // JSON array
+        let devices = parse(&format!(
+            "[{}]",
+            serde_json::to_string(&serde_json::json!({"ids": {"device_id": "a"}})).unwrap()
+        ))
+        .unwrap();
+        assert_eq!(1, devices.len());
+        assert_eq!("a", devices[0].ids.device_id);
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let _guard = test::prepare().await;
+
+        let t = tenant::create(tenant::Tenant {
+            name: "test-tenant".into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let app = application::create(application::Application {
+            name: "test-app".into(),
+            tenant_id: t.id,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let devices = parse(EXPORT).unwrap();
+        let res = import(app.id.into(), &devices).await.unwrap();
+        assert_eq!(1, res.device_count);
+        assert_eq!(1, res.device_profile_count);
+        assert_eq!(1, res.errors.len());
+        assert_eq!("invalid-device", res.errors[0].0);
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let d = device::get(&dev_eui).await.unwrap();
+        assert_eq!("OTAA device", d.name);
+        assert_eq!(EUI64::from_be_bytes([8, 7, 6, 5, 4, 3, 2, 1]), d.join_eui);
+        assert_eq!(Some(DevAddr::from_be_bytes([1, 2, 3, 4])), d.dev_addr);
+        assert_eq!(device::DeviceClass::C, d.enabled_class);
+        assert!(d.skip_fcnt_check);
+
+        let ds = d.get_device_session().unwrap();
+        assert_eq!(vec![1, 2, 3, 4], ds.dev_addr);
+        assert_eq!(11, ds.f_cnt_up);
+        assert_eq!(6, ds.n_f_cnt_down);
+        assert_eq!(5, ds.rx1_delay);
+        assert_eq!(1, ds.rx1_dr_offset);
+        assert_eq!(3, ds.rx2_dr);
+        assert_eq!(5, ds.dr);
+        assert_eq!(ds.f_nwk_s_int_key, ds.s_nwk_s_int_key);
+        assert_eq!(ds.f_nwk_s_int_key, ds.nwk_s_enc_key);
+
+        let dk = device_keys::get(&dev_eui).await.unwrap();
+        assert_eq!(
+            AES128Key::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8]),
+            dk.nwk_key
+        );
+        assert_eq!(3, dk.join_nonce);
+
+        let dp = device_profile::get(&d.device_profile_id).await.unwrap();
+        assert_eq!(MacVersion::LORAWAN_1_0_3, dp.mac_version);
+        assert!(dp.supports_otaa);
+        assert!(dp.supports_class_c);
+
+        // The device-profile is re-used on a next import.
+        device::delete(&dev_eui).await.unwrap();
+        let res = import(app.id.into(), &devices[..1]).await.unwrap();
+        assert_eq!(1, res.device_count);
+        assert_eq!(0, res.device_profile_count);
+    }
+}
@@ -6,22 +6,26 @@ use chrono::{DateTime, Duration, Local, Utc};
 use tracing::{debug, error, info, span, trace, warn, Instrument, Level};
 
 use super::error::Error;
-use super::{data_fns, filter_rx_info_by_tenant_id, helpers, RelayContext, UplinkFrameSet};
+use super::{
+    data_fns, data_sns_hr, dropped, f_cnt_anomaly, filter_rx_info_by_tenant_id, helpers, mesh,
+    RelayContext, UplinkFrameSet,
+};
 use crate::api::helpers::ToProto;
 use crate::applayer;
 use crate::backend::roaming;
+use crate::gateway::airtime;
 use crate::helpers::errors::PrintFullError;
 use crate::storage::error::Error as StorageError;
 use crate::storage::{
     application,
     device::{self, DeviceClass},
-    device_gateway, device_profile, device_queue, fields,
+    device_gateway, device_profile, device_queue, fields, handover_roaming,
     helpers::get_all_device_data,
     metrics, tenant,
 };
-use crate::{codec, config, downlink, integration, maccommand, region, stream};
+use crate::{codec, config, downlink, integration, maccommand, monitoring, quota, region, stream};
 use chirpstack_api::{common, integration as integration_pb, internal, stream as stream_pb};
-use lrwn::{AES128Key, EUI64};
+use lrwn::{AES128Key, NetID, EUI64};
 
 pub struct Data {
     uplink_frame_set: UplinkFrameSet,
@@ -124,8 +128,11 @@ impl Data {
             ctx.filter_rx_info_by_tenant().await?;
         }
         ctx.set_device_info()?;
+        ctx.abort_on_device_state().await?;
         ctx.set_device_gateway_rx_info()?;
+        ctx.detect_f_cnt_anomaly().await;
         ctx.handle_retransmission_reset().await?;
+        ctx.check_uplink_quota().await?;
         ctx.set_scheduler_run_after().await?;
         ctx.decrypt_f_opts_mac_commands()?;
         ctx.decrypt_frm_payload()?;
@@ -150,6 +157,10 @@ impl Data {
         ctx.update_device().await?;
         ctx.handle_uplink_ack().await?;
         ctx.save_metrics().await?;
+        ctx.save_tenant_metrics();
+        if ctx._is_roaming() {
+            ctx.log_roaming_usage().await?;
+        }
 
         if ctx._is_relay() {
             ctx.handle_forward_uplink_req().await?;
@@ -188,8 +199,11 @@ impl Data {
         ctx.get_device_for_phy_payload_relayed().await?;
         ctx.get_device_data().await?;
         ctx.set_device_info()?;
+        ctx.abort_on_device_state().await?;
         ctx.set_relay_rx_info()?;
+        ctx.detect_f_cnt_anomaly().await;
         ctx.handle_retransmission_reset().await?;
+        ctx.check_uplink_quota().await?;
         ctx.decrypt_f_opts_mac_commands()?;
         ctx.decrypt_frm_payload()?;
         ctx.set_adr()?;
@@ -262,32 +276,86 @@ impl Data {
                     self.f_cnt_up_full = f_cnt;
                 }
             },
-            Err(e) => match e {
-                StorageError::NotFound(s) => {
-                    info!(dev_addr = %s, "No device-session exists for dev_addr");
+            Err(e) => {
+                if matches!(e, StorageError::NotFound(_) | StorageError::InvalidMIC)
+                    && self.handle_handover_roaming_device().await?
+                {
                     return Err(Error::Abort);
                 }
-                StorageError::InvalidMIC => {
-                    info!(dev_addr = %dev_addr, "None of the device-sessions for dev_addr resulted in valid MIC");
 
-                    // Log uplink for null DevEUI.
-                    let mut ufl: stream_pb::UplinkFrameLog = (&self.uplink_frame_set).try_into()?;
-                    ufl.dev_eui = "0000000000000000".to_string();
-                    stream::frame::log_uplink_for_device(&ufl).await?;
-
-                    return Err(Error::Abort);
-                }
-                _ => {
-                    return Err(Error::Anyhow(
-                        anyhow::Error::new(e).context("Get device-session"),
-                    ));
+                match e {
+                    StorageError::NotFound(s) => {
+                        info!(dev_addr = %s, "No device-session exists for dev_addr");
+                        dropped::log(dropped::from_uplink_frame_set(
+                            &self.uplink_frame_set,
+                            stream_pb::UplinkDropReason::UnknownDevAddr,
+                        ))
+                        .await;
+                        return Err(Error::Abort);
+                    }
+                    StorageError::InvalidMIC => {
+                        info!(dev_addr = %dev_addr, "None of the device-sessions for dev_addr resulted in valid MIC");
+                        dropped::log(dropped::from_uplink_frame_set(
+                            &self.uplink_frame_set,
+                            stream_pb::UplinkDropReason::MicFailure,
+                        ))
+                        .await;
+
+                        // Log uplink for null DevEUI.
+                        let mut ufl: stream_pb::UplinkFrameLog =
+                            (&self.uplink_frame_set).try_into()?;
+                        ufl.dev_eui = "0000000000000000".to_string();
+                        stream::frame::log_uplink_for_device(&ufl).await?;
+
+                        return Err(Error::Abort);
+                    }
+                    _ => {
+                        return Err(Error::Anyhow(
+                            anyhow::Error::new(e).context("Get device-session"),
+                        ));
+                    }
                 }
-            },
+            }
         };
 
         Ok(())
     }
 
+    // In case of handover-roaming, the DevAddr was assigned by ChirpStack (as sNS) but the
+    // device does not exist locally. Returns true in case the uplink has been handled.
+    async fn handle_handover_roaming_device(&self) -> Result<bool, Error> {
+        if !roaming::is_enabled() {
+            return Ok(false);
+        }
+
+        trace!("Handling handover-roaming device");
+        let mac = if let lrwn::Payload::MACPayload(pl) = &self.uplink_frame_set.phy_payload.payload
+        {
+            pl
+        } else {
+            return Ok(false);
+        };
+
+        match handover_roaming::get_serving_for_phy_payload(&self.uplink_frame_set.phy_payload)
+            .await
+        {
+            Ok((ds, f_cnt_up_full)) => {
+                data_sns_hr::Data::handle(
+                    self.uplink_frame_set.clone(),
+                    mac.clone(),
+                    ds,
+                    f_cnt_up_full,
+                )
+                .await;
+                Ok(true)
+            }
+            Err(StorageError::NotFound(_)) | Err(StorageError::InvalidMIC) => Ok(false),
+            Err(e) => Err(Error::Anyhow(
+                anyhow::Error::new(e).context("Get handover-roaming device-session"),
+            )),
+        }
+    }
+
     async fn get_device_for_phy_payload_relayed(&mut self) -> Result<(), Error> {
         trace!("Getting device for PhyPayload (relayed)");
 
@@ -334,10 +402,26 @@ impl Data {
             Err(e) => match e {
                 StorageError::NotFound(s) => {
                     info!(dev_addr = %s, "No device-session exists for dev_addr");
+                    dropped::log(stream_pb::UplinkDropped {
+                        dev_addr: dev_addr.to_string(),
+                        ..dropped::from_uplink_frame_set(
+                            &self.uplink_frame_set,
+                            stream_pb::UplinkDropReason::UnknownDevAddr,
+                        )
+                    })
+                    .await;
                     return Err(Error::Abort);
                 }
                 StorageError::InvalidMIC => {
                     info!(dev_addr = %dev_addr, "None of the device-sessions for dev_addr resulted in valid MIC");
+                    dropped::log(stream_pb::UplinkDropped {
+                        dev_addr: dev_addr.to_string(),
+                        ..dropped::from_uplink_frame_set(
+                            &self.uplink_frame_set,
+                            stream_pb::UplinkDropReason::MicFailure,
+                        )
+                    })
+                    .await;
                     return Err(Error::Abort);
                 }
                 _ => {
@@ -462,6 +546,9 @@ impl Data {
                             .get(&gw_id)
                             .map(|v| v.into_bytes().to_vec())
                             .unwrap_or_default(),
+                        relay_id: mesh::get_relay_id(rx_info)
+                            .map(|v| v.to_vec())
+                            .unwrap_or_default(),
                     }
                 })
                 .collect(),
@@ -470,6 +557,88 @@ impl Data {
         Ok(())
     }
 
+    // The anomaly detection must not affect the handling of the uplink, therefore errors are
+    // logged only.
+    async fn detect_f_cnt_anomaly(&self) {
+        if let Err(e) = self._detect_f_cnt_anomaly().await {
+            warn!(error = %e.full(), "Detecting frame-counter anomaly failed");
+        }
+    }
+
+    async fn _detect_f_cnt_anomaly(&self) -> Result<()> {
+        let conf = config::get();
+        if !conf.network.f_cnt_anomaly.enabled {
+            return Ok(());
+        }
+
+        let dev = self.device.as_ref().unwrap();
+        if dev.skip_fcnt_check {
+            return Ok(());
+        }
+
+        trace!("Detecting frame-counter anomalies");
+        let ds = dev.get_device_session()?;
+        let gateway_ids: Vec<EUI64> = self
+            .uplink_frame_set
+            .rx_info_set
+            .iter()
+            .filter_map(|rx_info| EUI64::from_str(&rx_info.gateway_id).ok())
+            .collect();
+
+        let alert = f_cnt_anomaly::detect(&f_cnt_anomaly::Uplink {
+            dev_eui: dev.dev_eui,
+            f_cnt_expected: ds.f_cnt_up,
+            f_cnt: self.f_cnt_up_full,
+            reset: self.reset,
+            retransmission: self.retransmission,
+            gateway_ids: if self.relay_context.is_some() {
+                None
+            } else {
+                Some(gateway_ids.as_slice())
+            },
+        })
+        .await
+        .context("Detect frame-counter anomaly")?;
+
+        let alert = match alert {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        warn!(dev_eui = %dev.dev_eui, kind = %alert.kind, count = alert.count, "Frame-counter anomaly detected");
+
+        let app = self.application.as_ref().unwrap();
+        let ts: DateTime<Utc> =
+            helpers::get_rx_timestamp(&self.uplink_frame_set.rx_info_set).into();
+
+        let pl = integration_pb::LogEvent {
+            time: Some(ts.into()),
+            device_info: self.device_info.clone(),
+            level: integration_pb::LogLevel::Warning.into(),
+            code: integration_pb::LogCode::UplinkFCntAnomaly.into(),
+            description: format!(
+                "Frame-counter anomaly detected ({}), this could indicate a cloned or malfunctioning device",
+                alert.kind
+            ),
+            context: [
+                ("kind".to_string(), alert.kind.to_string()),
+                ("count".to_string(), alert.count.to_string()),
+                ("f_cnt".to_string(), self.f_cnt_up_full.to_string()),
+                ("f_cnt_expected".to_string(), ds.f_cnt_up.to_string()),
+                (
+                    "deduplication_id".to_string(),
+                    self.uplink_frame_set.uplink_set_id.to_string(),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        };
+        integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+        Ok(())
+    }
+
     async fn handle_retransmission_reset(&self) -> Result<(), Error> {
         trace!("Handle retransmission and reset");
         let dev = self.device.as_ref().unwrap();
@@ -499,6 +668,15 @@ impl Data {
                 .collect(),
             };
             integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+            dropped::log(stream_pb::UplinkDropped {
+                dev_eui: dev.dev_eui.to_string(),
+                ..dropped::from_uplink_frame_set(
+                    &self.uplink_frame_set,
+                    stream_pb::UplinkDropReason::FCntRetransmission,
+                )
+            })
+            .await;
         }
 
         if self.reset {
@@ -517,11 +695,121 @@ impl Data {
                 .collect(),
             };
             integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+            dropped::log(stream_pb::UplinkDropped {
+                dev_eui: dev.dev_eui.to_string(),
+                ..dropped::from_uplink_frame_set(
+                    &self.uplink_frame_set,
+                    stream_pb::UplinkDropReason::FCntReset,
+                )
+            })
+            .await;
         }
 
         Err(Error::Abort)
     }
 
+    async fn abort_on_device_state(&self) -> Result<(), Error> {
+        trace!("Checking device state");
+        let dev = self.device.as_ref().unwrap();
+
+        let reason = match dev.state {
+            device::DeviceState::Suspended => stream_pb::UplinkDropReason::DeviceSuspended,
+            device::DeviceState::Decommissioned => {
+                stream_pb::UplinkDropReason::DeviceDecommissioned
+            }
+            _ => return Ok(()),
+        };
+
+        let app = self.application.as_ref().unwrap();
+        let ts: DateTime<Utc> =
+            helpers::get_rx_timestamp(&self.uplink_frame_set.rx_info_set).into();
+
+        info!(dev_eui = %dev.dev_eui, state = %dev.state, "Dropping uplink, device is out of service");
+
+        let pl = integration_pb::LogEvent {
+            time: Some(ts.into()),
+            device_info: self.device_info.clone(),
+            level: integration_pb::LogLevel::Warning.into(),
+            code: integration_pb::LogCode::DeviceState.into(),
+            description: format!(
+                "Uplink dropped, device is in {} state",
+                dev.state.to_string().to_lowercase()
+            ),
+            context: [
+                ("state".to_string(), dev.state.to_string()),
+                (
+                    "deduplication_id".to_string(),
+                    self.uplink_frame_set.uplink_set_id.to_string(),
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        };
+        integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+        dropped::log(stream_pb::UplinkDropped {
+            dev_eui: dev.dev_eui.to_string(),
+            ..dropped::from_uplink_frame_set(&self.uplink_frame_set, reason)
+        })
+        .await;
+
+        Err(Error::Abort)
+    }
+
+    async fn check_uplink_quota(&self) -> Result<(), Error> {
+        trace!("Checking uplink quota");
+        let tenant = self.tenant.as_ref().unwrap();
+
+        let action = match quota::uplink_quota_exceeded(&tenant.id) {
+            Some(v) => v,
+            None => {
+                quota::inc_uplink(&tenant.id);
+                return Ok(());
+            }
+        };
+
+        let dev = self.device.as_ref().unwrap();
+        let app = self.application.as_ref().unwrap();
+        let ts: DateTime<Utc> =
+            helpers::get_rx_timestamp(&self.uplink_frame_set.rx_info_set).into();
+
+        warn!(dev_eui = %dev.dev_eui, tenant_id = %tenant.id, action = ?action, "Tenant exceeded uplinks per day quota");
+
+        let mut context = quota::get_log_context("uplink", tenant.max_uplink_count_per_day, action);
+        context.insert(
+            "deduplication_id".to_string(),
+            self.uplink_frame_set.uplink_set_id.to_string(),
+        );
+
+        let pl = integration_pb::LogEvent {
+            time: Some(ts.into()),
+            device_info: self.device_info.clone(),
+            level: integration_pb::LogLevel::Warning.into(),
+            code: integration_pb::LogCode::QuotaExceeded.into(),
+            description: "Tenant exceeded the max. number of uplinks per day".into(),
+            context,
+        };
+        integration::log_event(app.id.into(), &dev.variables, &pl).await;
+
+        if action == config::QuotaAction::Reject {
+            dropped::log(stream_pb::UplinkDropped {
+                dev_eui: dev.dev_eui.to_string(),
+                ..dropped::from_uplink_frame_set(
+                    &self.uplink_frame_set,
+                    stream_pb::UplinkDropReason::QuotaExceeded,
+                )
+            })
+            .await;
+
+            return Err(Error::Abort);
+        }
+
+        quota::inc_uplink(&tenant.id);
+        Ok(())
+    }
+
     // For Class-B and Class-C devices, set the scheduler_run_after timestamp to avoid collisions with
     // the Class-A downlink and Class-B/C scheduler.
     async fn set_scheduler_run_after(&mut self) -> Result<()> {
@@ -632,7 +920,8 @@ impl Data {
         // self.phy_payload holds the decrypted payload.
         ufl.plaintext_f_opts = true;
         ufl.plaintext_frm_payload = true;
-        ufl.phy_payload = self.phy_payload.to_vec()?;
+        ufl.phy_payload.clear();
+        self.phy_payload.encode_into(&mut ufl.phy_payload)?;
 
         stream::frame::log_uplink_for_device(&ufl).await?;
         Ok(())
@@ -960,6 +1249,7 @@ impl Data {
         if !self._is_end_to_end_encrypted() {
             pl.object = match codec::binary_to_struct(
                 dp.payload_codec_runtime,
+                &dp.tenant_id,
                 ts,
                 mac.f_port.unwrap_or(0),
                 &dev.variables,
@@ -1253,6 +1543,50 @@ impl Data {
         Ok(())
     }
 
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+        let roaming_meta = self.uplink_frame_set.roaming_meta_data.as_ref().unwrap();
+        let net_id = NetID::from_slice(&roaming_meta.base_payload.sender_id)?;
+        let dev = self.device.as_ref().unwrap();
+
+        let dev_addr = if let lrwn::Payload::MACPayload(pl) = &self.phy_payload.payload {
+            pl.fhdr.devaddr
+        } else {
+            return Err(anyhow!("Expected MacPayload"));
+        };
+
+        roaming::log_uplink_usage(
+            net_id,
+            fields::RoamingRole::SNS,
+            Some(dev.dev_eui),
+            dev_addr,
+            self.uplink_frame_set.tx_info.modulation.as_ref(),
+            self.uplink_frame_set.phy_payload.to_vec()?.len(),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    fn save_tenant_metrics(&self) {
+        trace!("Saving tenant metrics");
+        let tenant = self.tenant.as_ref().unwrap();
+
+        monitoring::tenant::inc_uplink(&tenant.id, self.phy_payload.mhdr.m_type);
+
+        if let Some(airtime) = self
+            .uplink_frame_set
+            .tx_info
+            .modulation
+            .as_ref()
+            .and_then(|v| {
+                airtime::get_airtime(v, self.uplink_frame_set.phy_payload.to_vec().ok()?.len())
+            })
+        {
+            monitoring::tenant::inc_airtime(&tenant.id, "rx", airtime);
+        }
+    }
+
     async fn save_metrics_relayed(&self) -> Result<()> {
         trace!("Saving relayed device metrics");
         let relay_ctx = self.relay_context.as_ref().unwrap();
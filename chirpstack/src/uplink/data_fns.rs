@@ -7,7 +7,7 @@ use super::{error::Error, filter_rx_info_by_public_only, UplinkFrameSet};
 use crate::api::backend::get_async_receiver;
 use crate::backend::{keywrap, roaming};
 use crate::helpers::errors::PrintFullError;
-use crate::storage::passive_roaming;
+use crate::storage::{fields, passive_roaming};
 use crate::uplink::helpers;
 use chirpstack_api::internal;
 use lrwn::NetID;
@@ -16,6 +16,8 @@ pub struct Data {
     uplink_frame_set: UplinkFrameSet,
     mac_payload: lrwn::MACPayload,
     pr_device_sessions: Vec<internal::PassiveRoamingDeviceSession>,
+    pr_device_sessions_started: bool,
+    forwarded_net_ids: Vec<NetID>,
 }
 
 impl Data {
@@ -38,6 +40,8 @@ impl Data {
             uplink_frame_set: ufs,
             mac_payload: mac_pl,
             pr_device_sessions: Vec::new(),
+            pr_device_sessions_started: false,
+            forwarded_net_ids: Vec::new(),
         };
 
         ctx.filter_rx_info_by_public_only()?;
@@ -45,6 +49,7 @@ impl Data {
         ctx.start_pr_sessions().await?;
         ctx.forward_uplink_for_sessions().await?;
         ctx.save_pr_device_sessions().await?;
+        ctx.log_roaming_usage().await?;
 
         Ok(())
     }
@@ -62,6 +67,7 @@ impl Data {
 
         for ds in &mut self.pr_device_sessions {
             ds.f_cnt_up = self.mac_payload.fhdr.f_cnt + 1;
+            ds.uplink_count += 1;
         }
 
         trace!(
@@ -91,17 +97,21 @@ impl Data {
                 }
             };
 
+            // The PRStartReq contains the uplink.
+            self.forwarded_net_ids.push(net_id);
+
             // No need to store the device-session or call XmitDataReq when
             // lifetime is not set (stateless passive-roaming).
             if ds.lifetime.is_some() {
                 self.pr_device_sessions.push(ds);
+                self.pr_device_sessions_started = true;
             }
         }
 
         Ok(())
     }
 
-    async fn forward_uplink_for_sessions(&self) -> Result<()> {
+    async fn forward_uplink_for_sessions(&mut self) -> Result<()> {
         trace!("Forwarding uplink for passive-roaming sessions");
 
         for ds in &self.pr_device_sessions {
@@ -133,11 +143,18 @@ impl Data {
                 ),
             };
 
-            if let Err(e) = client
+            match client
                 .xmit_data_req(backend::Role::SNS, &mut req, async_receiver)
                 .await
             {
-                error!(net_id = %net_id, error = %e.full(), "XmitDataReq failed");
+                Ok(_) => {
+                    if !self.forwarded_net_ids.contains(&net_id) {
+                        self.forwarded_net_ids.push(net_id);
+                    }
+                }
+                Err(e) => {
+                    error!(net_id = %net_id, error = %e.full(), "XmitDataReq failed");
+                }
             }
         }
 
@@ -147,8 +164,33 @@ impl Data {
     async fn save_pr_device_sessions(&self) -> Result<()> {
         trace!("Saving passive-roaming device-sessions");
 
+        // Existing sessions are only updated, such that a session that was deleted while
+        // handling the uplink is not re-created.
         for ds in &self.pr_device_sessions {
-            passive_roaming::save(ds).await?;
+            if self.pr_device_sessions_started {
+                passive_roaming::save(ds).await?;
+            } else {
+                passive_roaming::update(ds).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+        let size = self.uplink_frame_set.phy_payload.to_vec()?.len();
+
+        for net_id in &self.forwarded_net_ids {
+            roaming::log_uplink_usage(
+                *net_id,
+                fields::RoamingRole::FNS,
+                None,
+                self.mac_payload.fhdr.devaddr,
+                self.uplink_frame_set.tx_info.modulation.as_ref(),
+                size,
+            )
+            .await;
         }
 
         Ok(())
@@ -209,6 +251,8 @@ impl Data {
                     Some((Utc::now() + Duration::try_seconds(lt).unwrap_or_default()).into())
                 }
             },
+            created_at: Some(Utc::now().into()),
+            uplink_count: 1,
             f_nwk_s_int_key: match &pr_start_ans.f_nwk_s_int_key {
                 Some(ke) => keywrap::unwrap(ke)?.to_vec(),
                 None => match &pr_start_ans.nwk_s_key {
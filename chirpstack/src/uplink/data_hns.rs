@@ -0,0 +1,313 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tracing::{span, trace, Instrument, Level};
+use uuid::Uuid;
+
+use crate::api::helpers::ToProto;
+use crate::backend::roaming;
+use crate::downlink;
+use crate::storage::{
+    application, device, device_profile, fields, handover_roaming, helpers::get_all_device_data,
+    tenant,
+};
+use crate::{codec, integration, region};
+use chirpstack_api::{common, integration as integration_pb};
+use lrwn::region::CommonName;
+use lrwn::{AES128Key, DevAddr, NetID, EUI64};
+
+// Data implements the hNS side of handover-roaming for uplinks. In this case the sNS handles
+// the MAC layer and forwards the FRMPayload, together with the ULMetaData, to the hNS.
+pub struct Data {
+    xmit_data_req: backend::XmitDataReqPayload,
+    ul_meta_data: backend::ULMetaData,
+
+    handover_session: Option<chirpstack_api::internal::HandoverRoamingDeviceSession>,
+    tenant: Option<tenant::Tenant>,
+    application: Option<application::Application>,
+    device_profile: Option<device_profile::DeviceProfile>,
+    device: Option<device::Device>,
+    device_info: Option<integration_pb::DeviceInfo>,
+    data: Vec<u8>,
+}
+
+impl Data {
+    pub async fn handle(
+        pl: backend::XmitDataReqPayload,
+        ul_meta_data: backend::ULMetaData,
+    ) -> Result<()> {
+        let span = span!(
+            Level::INFO,
+            "xmit_data_req_hr",
+            transaction_id = pl.base.transaction_id
+        );
+        Data::_handle(pl, ul_meta_data).instrument(span).await
+    }
+
+    async fn _handle(
+        pl: backend::XmitDataReqPayload,
+        ul_meta_data: backend::ULMetaData,
+    ) -> Result<()> {
+        let mut ctx = Data {
+            xmit_data_req: pl,
+            ul_meta_data,
+            handover_session: None,
+            tenant: None,
+            application: None,
+            device_profile: None,
+            device: None,
+            device_info: None,
+            data: Vec::new(),
+        };
+
+        ctx.get_handover_roaming_session().await?;
+        ctx.get_device_data().await?;
+        ctx.validate_f_cnt_up()?;
+        ctx.set_device_info()?;
+        ctx.decrypt_frm_payload()?;
+        ctx.send_uplink_event().await?;
+        ctx.update_device().await?;
+        ctx.log_roaming_usage().await?;
+        ctx.start_downlink_data_flow().await?;
+
+        Ok(())
+    }
+
+    async fn get_handover_roaming_session(&mut self) -> Result<()> {
+        trace!("Getting handover-roaming device-session");
+        let dev_eui = EUI64::from_slice(&self.ul_meta_data.dev_eui)?;
+        let sess = handover_roaming::get(&dev_eui).await?;
+
+        let sender_id = NetID::from_slice(&self.xmit_data_req.base.sender_id)?;
+        if sess.net_id != sender_id.to_vec() {
+            return Err(anyhow!(
+                "Handover-roaming session for dev_eui {} does not belong to net_id {}",
+                dev_eui,
+                sender_id
+            ));
+        }
+
+        self.handover_session = Some(sess);
+        Ok(())
+    }
+
+    async fn get_device_data(&mut self) -> Result<()> {
+        trace!("Getting device data");
+        let dev_eui = EUI64::from_slice(&self.ul_meta_data.dev_eui)?;
+        let (dev, app, t, dp) = get_all_device_data(dev_eui).await?;
+
+        if dev.is_disabled {
+            return Err(anyhow!("Device is disabled"));
+        }
+
+        self.tenant = Some(t);
+        self.application = Some(app);
+        self.device_profile = Some(dp);
+        self.device = Some(dev);
+
+        Ok(())
+    }
+
+    fn validate_f_cnt_up(&self) -> Result<()> {
+        trace!("Validating uplink frame-counter");
+
+        let f_cnt_up = self
+            .ul_meta_data
+            .f_cnt_up
+            .ok_or_else(|| anyhow!("FCntUp is not set"))?;
+
+        // The device-session contains the next expected frame-counter. A lower value indicates
+        // a replay of an uplink that was already handled.
+        let ds = self.device.as_ref().unwrap().get_device_session()?;
+        if f_cnt_up < ds.f_cnt_up {
+            return Err(anyhow!(
+                "Uplink frame-counter is not greater than the previous frame-counter (possible replay), expected >= {}, got: {}",
+                ds.f_cnt_up,
+                f_cnt_up
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn set_device_info(&mut self) -> Result<()> {
+        trace!("Setting device-info");
+
+        let tenant = self.tenant.as_ref().unwrap();
+        let app = self.application.as_ref().unwrap();
+        let dp = self.device_profile.as_ref().unwrap();
+        let dev = self.device.as_ref().unwrap();
+
+        let mut tags = (*app.tags).clone();
+        tags.extend((*dp.tags).clone());
+        tags.extend((*dev.tags).clone());
+
+        self.device_info = Some(integration_pb::DeviceInfo {
+            tenant_id: tenant.id.to_string(),
+            tenant_name: tenant.name.clone(),
+            application_id: app.id.to_string(),
+            application_name: app.name.to_string(),
+            device_profile_id: dp.id.to_string(),
+            device_profile_name: dp.name.clone(),
+            device_name: dev.name.clone(),
+            device_class_enabled: dev.enabled_class.to_proto().into(),
+            dev_eui: dev.dev_eui.to_string(),
+            tags,
+        });
+
+        Ok(())
+    }
+
+    fn decrypt_frm_payload(&mut self) -> Result<()> {
+        trace!("Decrypting FRMPayload");
+        let ds = self.device.as_ref().unwrap().get_device_session()?;
+        let sess = self.handover_session.as_ref().unwrap();
+
+        self.data = match &ds.app_s_key {
+            Some(app_s_key) if app_s_key.kek_label.is_empty() => lrwn::encrypt_frm_payload(
+                &AES128Key::from_slice(&app_s_key.aes_key)?,
+                true,
+                &DevAddr::from_slice(&sess.dev_addr)?,
+                self.ul_meta_data.f_cnt_up.unwrap_or_default(),
+                &self.xmit_data_req.frm_payload,
+            )?,
+            // End-to-end encrypted.
+            _ => self.xmit_data_req.frm_payload.clone(),
+        };
+
+        Ok(())
+    }
+
+    async fn send_uplink_event(&self) -> Result<()> {
+        trace!("Sending uplink event");
+
+        let ts: DateTime<Utc> = self.ul_meta_data.recv_time;
+        let app = self.application.as_ref().unwrap();
+        let dp = self.device_profile.as_ref().unwrap();
+        let dev = self.device.as_ref().unwrap();
+        let ds = dev.get_device_session()?;
+        let sess = self.handover_session.as_ref().unwrap();
+        let f_port = self.ul_meta_data.f_port.unwrap_or(0);
+        let region_common_name = CommonName::from_str(&self.ul_meta_data.rf_region)?;
+        let end_to_end_encrypted = ds
+            .app_s_key
+            .as_ref()
+            .map(|v| !v.kek_label.is_empty())
+            .unwrap_or_default();
+
+        let mut pl = integration_pb::UplinkEvent {
+            deduplication_id: Uuid::new_v4().to_string(),
+            time: Some(ts.into()),
+            device_info: self.device_info.clone(),
+            dev_addr: DevAddr::from_slice(&sess.dev_addr)?.to_string(),
+            dr: self.ul_meta_data.data_rate.unwrap_or_default() as u32,
+            f_cnt: self.ul_meta_data.f_cnt_up.unwrap_or_default(),
+            f_port: f_port as u32,
+            confirmed: self.ul_meta_data.confirmed.unwrap_or_default(),
+            data: self.data.clone(),
+            rx_info: roaming::ul_meta_data_to_rx_info(&self.ul_meta_data)?,
+            tx_info: Some(roaming::ul_meta_data_to_tx_info(&self.ul_meta_data)?),
+            join_server_context: if end_to_end_encrypted {
+                Some(common::JoinServerContext {
+                    session_key_id: hex::encode(&ds.js_session_key_id),
+                    app_s_key: ds.app_s_key.clone(),
+                })
+            } else {
+                None
+            },
+            region_config_id: region::get_region_config_id(region_common_name)?,
+            ..Default::default()
+        };
+
+        if !end_to_end_encrypted {
+            pl.object = match codec::binary_to_struct(
+                dp.payload_codec_runtime,
+                &dp.tenant_id,
+                ts,
+                f_port,
+                &dev.variables,
+                &dp.payload_codec_script,
+                &pl.data,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    integration::log_event(
+                        app.id.into(),
+                        &dev.variables,
+                        &integration_pb::LogEvent {
+                            time: Some(Utc::now().into()),
+                            device_info: self.device_info.clone(),
+                            level: integration_pb::LogLevel::Error.into(),
+                            code: integration_pb::LogCode::UplinkCodec.into(),
+                            description: format!("{:#}", e),
+                            context: [(
+                                "deduplication_id".to_string(),
+                                pl.deduplication_id.clone(),
+                            )]
+                            .iter()
+                            .cloned()
+                            .collect(),
+                        },
+                    )
+                    .await;
+                    None
+                }
+            };
+        }
+
+        integration::uplink_event(app.id.into(), &dev.variables, &pl).await;
+
+        Ok(())
+    }
+
+    async fn update_device(&mut self) -> Result<()> {
+        trace!("Updating device");
+        let d = self.device.as_mut().unwrap();
+        let mut ds = d.get_device_session()?.clone();
+        ds.f_cnt_up = self.ul_meta_data.f_cnt_up.unwrap_or_default() + 1;
+
+        *d = device::partial_update(
+            d.dev_eui,
+            &device::DeviceChangeset {
+                last_seen_at: Some(Some(Utc::now())),
+                device_session: Some(Some(ds.into())),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+        let sess = self.handover_session.as_ref().unwrap();
+        let tx_info = roaming::ul_meta_data_to_tx_info(&self.ul_meta_data)?;
+
+        roaming::log_uplink_usage(
+            NetID::from_slice(&sess.net_id)?,
+            fields::RoamingRole::HNS,
+            Some(self.device.as_ref().unwrap().dev_eui),
+            DevAddr::from_slice(&sess.dev_addr)?,
+            tx_info.modulation.as_ref(),
+            self.xmit_data_req.frm_payload.len() + roaming::DATA_FRAME_OVERHEAD,
+        )
+        .await;
+
+        Ok(())
+    }
+
+    async fn start_downlink_data_flow(&self) -> Result<()> {
+        trace!("Starting downlink data flow");
+
+        downlink::data_hns::Data::handle(
+            self.device.as_ref().cloned().unwrap(),
+            self.handover_session.as_ref().cloned().unwrap(),
+            self.ul_meta_data.clone(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::Result;
+use tracing::{error, span, trace, warn, Instrument, Level};
+
+use super::{error::Error, filter_rx_info_by_public_only, UplinkFrameSet};
+use crate::api::backend::get_async_receiver;
+use crate::backend::roaming;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::{fields, handover_roaming};
+use crate::uplink::helpers;
+use chirpstack_api::internal;
+use lrwn::{DevAddr, NetID, EUI64};
+
+// Data implements the sNS side of handover-roaming for uplinks. The sNS handles the MAC layer
+// using the network session-keys received from the hNS and forwards the (encrypted)
+// FRMPayload, together with the ULMetaData, to the hNS.
+pub struct Data {
+    uplink_frame_set: UplinkFrameSet,
+    mac_payload: lrwn::MACPayload,
+    device_session: internal::HandoverRoamingServingDeviceSession,
+    f_cnt_up_full: u32,
+    forwarded: bool,
+}
+
+impl Data {
+    pub async fn handle(
+        ufs: UplinkFrameSet,
+        mac_pl: lrwn::MACPayload,
+        ds: internal::HandoverRoamingServingDeviceSession,
+        f_cnt_up_full: u32,
+    ) {
+        let span = span!(
+            Level::INFO,
+            "data_hr",
+            dev_eui = %EUI64::from_slice(&ds.dev_eui).unwrap_or_default()
+        );
+
+        if let Err(e) = Data::_handle(ufs, mac_pl, ds, f_cnt_up_full)
+            .instrument(span)
+            .await
+        {
+            match e.downcast_ref::<Error>() {
+                Some(Error::Abort) => {
+                    // nothing to do
+                }
+                Some(_) | None => {
+                    error!(error = %e.full(), "Handle handover-roaming uplink error");
+                }
+            }
+        }
+    }
+
+    async fn _handle(
+        ufs: UplinkFrameSet,
+        mac_pl: lrwn::MACPayload,
+        ds: internal::HandoverRoamingServingDeviceSession,
+        f_cnt_up_full: u32,
+    ) -> Result<()> {
+        let mut ctx = Data {
+            uplink_frame_set: ufs,
+            mac_payload: mac_pl,
+            device_session: ds,
+            f_cnt_up_full,
+            forwarded: false,
+        };
+
+        ctx.filter_rx_info_by_public_only()?;
+        ctx.validate_f_cnt_up()?;
+        ctx.update_device_session().await?;
+        ctx.forward_uplink().await?;
+        ctx.log_roaming_usage().await?;
+
+        Ok(())
+    }
+
+    fn filter_rx_info_by_public_only(&mut self) -> Result<()> {
+        trace!("Filtering rx_info by public gateways only");
+        filter_rx_info_by_public_only(&mut self.uplink_frame_set)?;
+        Ok(())
+    }
+
+    fn validate_f_cnt_up(&self) -> Result<()> {
+        trace!("Validating uplink frame-counter");
+
+        // The device-session contains the next expected frame-counter. A lower value means that
+        // the uplink is a replay (or re-transmission) of an uplink that was already forwarded.
+        if self.f_cnt_up_full < self.device_session.f_cnt_up {
+            warn!(
+                f_cnt_up = self.f_cnt_up_full,
+                expected_f_cnt_up = self.device_session.f_cnt_up,
+                "Uplink frame-counter is not greater than the previous frame-counter, dropping uplink"
+            );
+            return Err(Error::Abort.into());
+        }
+
+        Ok(())
+    }
+
+    async fn update_device_session(&mut self) -> Result<()> {
+        trace!("Updating handover-roaming device-session");
+
+        // The device-session is updated before forwarding the uplink, as the hNS might send
+        // the downlink before it responds to the XmitDataReq.
+        let ds = &mut self.device_session;
+        ds.f_cnt_up = self.f_cnt_up_full.wrapping_add(1);
+        ds.region_config_id
+            .clone_from(&self.uplink_frame_set.region_config_id);
+        ds.uplink_frequency = self.uplink_frame_set.tx_info.frequency;
+        ds.dr = self.uplink_frame_set.dr as u32;
+        ds.pending_ack =
+            self.uplink_frame_set.phy_payload.mhdr.m_type == lrwn::MType::ConfirmedDataUp;
+        ds.conf_f_cnt = if ds.pending_ack {
+            self.f_cnt_up_full
+        } else {
+            0
+        };
+
+        handover_roaming::save_serving(ds).await
+    }
+
+    async fn forward_uplink(&mut self) -> Result<()> {
+        // The sNS handles the mac-commands, these are not forwarded to the hNS.
+        let f_port = match self.mac_payload.f_port {
+            Some(v) if v > 0 => v,
+            _ => {
+                trace!("Uplink does not contain application payload, skipping forwarding");
+                return Ok(());
+            }
+        };
+
+        trace!("Forwarding uplink to hNS");
+
+        let frm_payload = match &self.mac_payload.frm_payload {
+            Some(lrwn::FRMPayload::Raw(v)) => v.clone(),
+            _ => Vec::new(),
+        };
+
+        let mut req = backend::XmitDataReqPayload {
+            frm_payload,
+            ul_meta_data: Some(backend::ULMetaData {
+                dev_eui: self.device_session.dev_eui.clone(),
+                dev_addr: self.mac_payload.fhdr.devaddr.to_vec(),
+                f_port: Some(f_port),
+                f_cnt_up: Some(self.f_cnt_up_full),
+                confirmed: Some(self.device_session.pending_ack),
+                data_rate: Some(self.uplink_frame_set.dr),
+                ul_freq: Some((self.uplink_frame_set.tx_info.frequency as f64) / 1_000_000.0),
+                recv_time: helpers::get_rx_timestamp_chrono(&self.uplink_frame_set.rx_info_set),
+                rf_region: self
+                    .uplink_frame_set
+                    .region_common_name
+                    .to_string()
+                    .replace('_', "-"),
+                gw_cnt: Some(self.uplink_frame_set.rx_info_set.len()),
+                gw_info: roaming::rx_info_to_gw_info(&self.uplink_frame_set.rx_info_set)?,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        #[cfg(test)]
+        {
+            req.base.transaction_id = 1234;
+        }
+
+        let net_id = NetID::from_slice(&self.device_session.net_id)?;
+        let client = roaming::get(&net_id).await?;
+        let async_receiver = match client.is_async() {
+            false => None,
+            true => {
+                Some(get_async_receiver(req.base.transaction_id, client.get_async_timeout()).await?)
+            }
+        };
+
+        client
+            .xmit_data_req(backend::Role::HNS, &mut req, async_receiver)
+            .await?;
+        self.forwarded = true;
+
+        Ok(())
+    }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        if !self.forwarded {
+            return Ok(());
+        }
+
+        trace!("Logging roaming usage");
+
+        roaming::log_uplink_usage(
+            NetID::from_slice(&self.device_session.net_id)?,
+            fields::RoamingRole::SNS,
+            Some(EUI64::from_slice(&self.device_session.dev_eui)?),
+            DevAddr::from_slice(&self.device_session.dev_addr)?,
+            self.uplink_frame_set.tx_info.modulation.as_ref(),
+            self.uplink_frame_set.phy_payload.to_vec()?.len(),
+        )
+        .await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use tracing::error;
+
+use super::UplinkFrameSet;
+use crate::api::helpers::ToProto;
+use crate::helpers::errors::PrintFullError;
+use crate::monitoring::prometheus;
+use crate::stream;
+use chirpstack_api::stream as stream_pb;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct DroppedLabels {
+    reason: String,
+}
+
+lazy_static! {
+    static ref DROPPED_COUNTER: Family<DroppedLabels, Counter> = {
+        let counter = Family::<DroppedLabels, Counter>::default();
+        prometheus::register(
+            "uplink_dropped_count",
+            "Number of dropped uplinks by reason",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+// Returns the UplinkDropped for the given uplink frame-set and reason.
+pub fn from_uplink_frame_set(
+    ufs: &UplinkFrameSet,
+    reason: stream_pb::UplinkDropReason,
+) -> stream_pb::UplinkDropped {
+    stream_pb::UplinkDropped {
+        reason: reason.into(),
+        dev_addr: match &ufs.phy_payload.payload {
+            lrwn::Payload::MACPayload(pl) => pl.fhdr.devaddr.to_string(),
+            _ => "".into(),
+        },
+        tx_info: Some(ufs.tx_info.clone()),
+        rx_info: ufs.rx_info_set.clone(),
+        phy_payload_byte_count: ufs
+            .phy_payload
+            .to_vec()
+            .map(|b| b.len())
+            .unwrap_or_default() as u32,
+        message_type: ufs.phy_payload.mhdr.m_type.to_proto().into(),
+        ..Default::default()
+    }
+}
+
+// Counts the dropped uplink and logs it to the meta-log (if enabled).
+pub async fn log(pl: stream_pb::UplinkDropped) {
+    DROPPED_COUNTER
+        .get_or_create(&DroppedLabels {
+            reason: pl.reason().as_str_name().to_string(),
+        })
+        .inc();
+
+    if let Err(e) = stream::meta::log_uplink_dropped(&pl).await {
+        error!(error = %e.full(), "Log dropped uplink error");
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use chirpstack_api::{common, gw};
+    use lrwn::region::CommonName;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_from_uplink_frame_set() {
+        let phy = lrwn::PhyPayload {
+            mhdr: lrwn::MHDR {
+                m_type: lrwn::MType::UnconfirmedDataUp,
+                major: lrwn::Major::LoRaWANR1,
+            },
+            payload: lrwn::Payload::MACPayload(lrwn::MACPayload {
+                fhdr: lrwn::FHDR {
+                    devaddr: lrwn::DevAddr::from_be_bytes([1, 2, 3, 4]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            mic: Some([1, 2, 3, 4]),
+        };
+
+        let ufs = UplinkFrameSet {
+            uplink_set_id: Uuid::new_v4(),
+            dr: 0,
+            ch: 0,
+            phy_payload: phy.clone(),
+            tx_info: gw::UplinkTxInfo {
+                frequency: 868100000,
+                ..Default::default()
+            },
+            rx_info_set: vec![gw::UplinkRxInfo {
+                gateway_id: "0102030405060708".into(),
+                ..Default::default()
+            }],
+            gateway_private_up_map: HashMap::new(),
+            gateway_private_down_map: HashMap::new(),
+            gateway_tenant_id_map: HashMap::new(),
+            region_common_name: CommonName::EU868,
+            region_config_id: "eu868".into(),
+            roaming_meta_data: None,
+        };
+
+        let pl = from_uplink_frame_set(&ufs, stream_pb::UplinkDropReason::MicFailure);
+        assert_eq!(
+            stream_pb::UplinkDropped {
+                reason: stream_pb::UplinkDropReason::MicFailure.into(),
+                dev_eui: "".into(),
+                dev_addr: "01020304".into(),
+                tx_info: Some(ufs.tx_info.clone()),
+                rx_info: ufs.rx_info_set.clone(),
+                phy_payload_byte_count: phy.to_vec().unwrap().len() as u32,
+                message_type: common::MType::UnconfirmedDataUp.into(),
+            },
+            pl
+        );
+    }
+}
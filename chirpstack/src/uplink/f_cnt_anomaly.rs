@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use anyhow::{Context, Result};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+
+use crate::config;
+use crate::monitoring::prometheus;
+use crate::storage::{
+    device_gateway, error::Error as StorageError, get_async_redis_conn, redis_key,
+};
+use lrwn::EUI64;
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct AnomalyLabels {
+    kind: String,
+}
+
+lazy_static! {
+    static ref ANOMALY_COUNTER: Family<AnomalyLabels, Counter> = {
+        let counter = Family::<AnomalyLabels, Counter>::default();
+        prometheus::register(
+            "uplink_f_cnt_anomaly_count",
+            "Number of detected uplink frame-counter anomalies by kind",
+            counter.clone(),
+        );
+        counter
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    // The frame-counter was reset (or rolled over).
+    Reset,
+    // The frame-counter exceeded the expected frame-counter by more than the max. gap.
+    Jump,
+    // An already used frame-counter was received by gateways other than the gateways
+    // that received the original uplink.
+    Replay,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Kind::Reset => "reset",
+                Kind::Jump => "jump",
+                Kind::Replay => "replay",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: Kind,
+    // Number of anomalies of this kind within the current interval.
+    pub count: u32,
+}
+
+pub struct Uplink<'a> {
+    pub dev_eui: EUI64,
+    // The frame-counter that was expected (the device-session frame-counter).
+    pub f_cnt_expected: u32,
+    // The (full) frame-counter of the uplink.
+    pub f_cnt: u32,
+    pub reset: bool,
+    pub retransmission: bool,
+    // The gateways that received the uplink. This is None for relayed uplinks, as these are
+    // received by the relay.
+    pub gateway_ids: Option<&'a [EUI64]>,
+}
+
+// Detects frame-counter anomalies for the given uplink. It returns an alert when the
+// configured threshold for the detected anomaly has been reached within the current
+// interval. Only a single alert is returned per device and anomaly kind within an interval.
+pub async fn detect(up: &Uplink<'_>) -> Result<Option<Alert>> {
+    let conf = config::get();
+
+    let (kind, threshold) = if up.reset {
+        (Kind::Reset, conf.network.f_cnt_anomaly.reset_threshold)
+    } else if up.retransmission {
+        if !is_replay(up).await? {
+            return Ok(None);
+        }
+        (Kind::Replay, conf.network.f_cnt_anomaly.replay_threshold)
+    } else if up.f_cnt.saturating_sub(up.f_cnt_expected) > conf.network.f_cnt_anomaly.max_f_cnt_gap
+    {
+        (Kind::Jump, 1)
+    } else {
+        return Ok(None);
+    };
+
+    ANOMALY_COUNTER
+        .get_or_create(&AnomalyLabels {
+            kind: kind.to_string(),
+        })
+        .inc();
+
+    let count = incr_count(&up.dev_eui, kind).await?;
+    if threshold > 0 && count == threshold {
+        return Ok(Some(Alert { kind, count }));
+    }
+
+    Ok(None)
+}
+
+// A re-transmission is flagged as replay when none of the receiving gateways received the
+// previous (accepted) uplink.
+async fn is_replay(up: &Uplink<'_>) -> Result<bool> {
+    let gateway_ids = match up.gateway_ids {
+        Some(v) if !v.is_empty() => v,
+        _ => return Ok(false),
+    };
+
+    let rx_info = match device_gateway::get_rx_info(&up.dev_eui).await {
+        Ok(v) => v,
+        Err(StorageError::NotFound(_)) => return Ok(false),
+        Err(e) => return Err(anyhow::Error::new(e).context("Get gateway rx-info for device")),
+    };
+
+    let prev_gateway_ids: HashSet<EUI64> = rx_info
+        .items
+        .iter()
+        .filter_map(|i| EUI64::from_slice(&i.gateway_id).ok())
+        .collect();
+    if prev_gateway_ids.is_empty() {
+        return Ok(false);
+    }
+
+    Ok(!gateway_ids.iter().any(|id| prev_gateway_ids.contains(id)))
+}
+
+// Increments the anomaly count of the device for the current interval and returns the
+// new count.
+async fn incr_count(dev_eui: &EUI64, kind: Kind) -> Result<u32> {
+    let conf = config::get();
+    let key = redis_key(format!("device:{{{}}}:fcnt:anomaly:{}", dev_eui, kind));
+    let ttl = conf.network.f_cnt_anomaly.interval.as_millis() as usize;
+
+    // The key is only created with a TTL when it does not yet exist, such that the count
+    // expires at the end of the interval.
+    let (count,): (u32,) = redis::pipe()
+        .atomic()
+        .cmd("SET")
+        .arg(&key)
+        .arg(0)
+        .arg("PX")
+        .arg(ttl)
+        .arg("NX")
+        .ignore()
+        .cmd("INCR")
+        .arg(&key)
+        .query_async(&mut get_async_redis_conn().await?)
+        .await
+        .context("Increment frame-counter anomaly count")?;
+
+    Ok(count)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test;
+    use chirpstack_api::internal;
+
+    #[tokio::test]
+    async fn test_detect() {
+        let _guard = test::prepare().await;
+
+        let mut conf = (*config::get()).clone();
+        conf.network.f_cnt_anomaly.enabled = true;
+        conf.network.f_cnt_anomaly.reset_threshold = 2;
+        conf.network.f_cnt_anomaly.replay_threshold = 1;
+        conf.network.f_cnt_anomaly.max_f_cnt_gap = 100;
+        config::set(conf);
+
+        let dev_eui = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+        let gw_a = EUI64::from_be_bytes([1, 1, 1, 1, 1, 1, 1, 1]);
+        let gw_b = EUI64::from_be_bytes([2, 2, 2, 2, 2, 2, 2, 2]);
+
+        device_gateway::save_rx_info(&internal::DeviceGatewayRxInfo {
+            dev_eui: dev_eui.to_vec(),
+            items: vec![internal::DeviceGatewayRxInfoItem {
+                gateway_id: gw_a.to_vec(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let gateways_a = [gw_a];
+        let gateways_b = [gw_b];
+        let up = Uplink {
+            dev_eui,
+            f_cnt_expected: 10,
+            f_cnt: 10,
+            reset: false,
+            retransmission: false,
+            gateway_ids: Some(&gateways_a),
+        };
+
+        // no anomaly
+        assert_eq!(None, detect(&up).await.unwrap());
+
+        // within max gap
+        assert_eq!(None, detect(&Uplink { f_cnt: 110, ..up }).await.unwrap());
+
+        // jump, only the first jump within the interval is reported
+        assert_eq!(
+            Some(Alert {
+                kind: Kind::Jump,
+                count: 1
+            }),
+            detect(&Uplink { f_cnt: 111, ..up }).await.unwrap()
+        );
+        assert_eq!(None, detect(&Uplink { f_cnt: 500, ..up }).await.unwrap());
+
+        // reset, reported once the threshold has been reached
+        let reset = Uplink {
+            f_cnt: 0,
+            reset: true,
+            ..up
+        };
+        assert_eq!(None, detect(&reset).await.unwrap());
+        assert_eq!(
+            Some(Alert {
+                kind: Kind::Reset,
+                count: 2
+            }),
+            detect(&reset).await.unwrap()
+        );
+        assert_eq!(None, detect(&reset).await.unwrap());
+
+        // re-transmission received by the same gateway
+        let retransmission = Uplink {
+            f_cnt: 9,
+            retransmission: true,
+            ..up
+        };
+        assert_eq!(None, detect(&retransmission).await.unwrap());
+
+        // re-transmission received by an other gateway
+        assert_eq!(
+            Some(Alert {
+                kind: Kind::Replay,
+                count: 1
+            }),
+            detect(&Uplink {
+                gateway_ids: Some(&gateways_b),
+                ..retransmission
+            })
+            .await
+            .unwrap()
+        );
+
+        // relayed re-transmission
+        assert_eq!(
+            None,
+            detect(&Uplink {
+                gateway_ids: None,
+                ..retransmission
+            })
+            .await
+            .unwrap()
+        );
+    }
+}
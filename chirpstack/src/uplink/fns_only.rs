@@ -0,0 +1,143 @@
+use anyhow::Result;
+use tracing::{error, info, span, trace, Instrument, Level};
+
+use super::{error::Error, filter_rx_info_by_public_only, UplinkFrameSet};
+use crate::api::backend::get_async_receiver;
+use crate::backend::roaming;
+use crate::config;
+use crate::helpers::errors::PrintFullError;
+use crate::storage::fields;
+use crate::uplink::helpers;
+use lrwn::{DevAddr, Payload, EUI64};
+
+// Uplink implements the forwarding of uplinks in case ChirpStack is configured as fNS only
+// (functional split). All uplinks are forwarded to the configured sNS using XmitDataReq,
+// without any MAC-layer handling. Downlinks are received from the sNS using XmitDataReq
+// (containing DLMetaData), which is handled the same way as passive-roaming downlinks.
+pub struct Uplink {
+    uplink_frame_set: UplinkFrameSet,
+    dev_eui: Option<EUI64>,
+    dev_addr: Option<DevAddr>,
+}
+
+impl Uplink {
+    pub async fn handle(ufs: UplinkFrameSet) {
+        let span = span!(Level::INFO, "fns_only");
+        if let Err(e) = Uplink::_handle(ufs).instrument(span).await {
+            match e.downcast_ref::<Error>() {
+                Some(Error::Abort) => {
+                    // nothing to do
+                }
+                Some(_) | None => {
+                    error!(error = %e.full(), "Handle fNS-only uplink error");
+                }
+            }
+        }
+    }
+
+    async fn _handle(ufs: UplinkFrameSet) -> Result<()> {
+        let mut ctx = Uplink {
+            uplink_frame_set: ufs,
+            dev_eui: None,
+            dev_addr: None,
+        };
+
+        ctx.filter_rx_info_by_public_only()?;
+        ctx.set_device_identifiers()?;
+        ctx.forward_uplink().await?;
+        ctx.log_roaming_usage().await?;
+
+        Ok(())
+    }
+
+    fn filter_rx_info_by_public_only(&mut self) -> Result<()> {
+        trace!("Filtering rx_info by public gateways only");
+        filter_rx_info_by_public_only(&mut self.uplink_frame_set)?;
+        Ok(())
+    }
+
+    fn set_device_identifiers(&mut self) -> Result<()> {
+        trace!("Setting device identifiers");
+
+        match &self.uplink_frame_set.phy_payload.payload {
+            Payload::JoinRequest(pl) => {
+                self.dev_eui = Some(pl.dev_eui);
+            }
+            Payload::MACPayload(pl) => {
+                self.dev_addr = Some(pl.fhdr.devaddr);
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unexpected m_type: {}",
+                    self.uplink_frame_set.phy_payload.mhdr.m_type
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn forward_uplink(&self) -> Result<()> {
+        let conf = config::get();
+        let net_id = conf.roaming.fns_only.net_id;
+
+        info!(net_id = %net_id, m_type = %self.uplink_frame_set.phy_payload.mhdr.m_type, "Forwarding uplink to sNS");
+
+        let mut req = backend::XmitDataReqPayload {
+            phy_payload: self.uplink_frame_set.phy_payload.to_vec()?,
+            ul_meta_data: Some(backend::ULMetaData {
+                dev_eui: self.dev_eui.map(|v| v.to_vec()).unwrap_or_default(),
+                dev_addr: self.dev_addr.map(|v| v.to_vec()).unwrap_or_default(),
+                data_rate: Some(self.uplink_frame_set.dr),
+                ul_freq: Some((self.uplink_frame_set.tx_info.frequency as f64) / 1_000_000.0),
+                recv_time: helpers::get_rx_timestamp_chrono(&self.uplink_frame_set.rx_info_set),
+                rf_region: self
+                    .uplink_frame_set
+                    .region_common_name
+                    .to_string()
+                    .replace('_', "-"),
+                gw_cnt: Some(self.uplink_frame_set.rx_info_set.len()),
+                gw_info: roaming::rx_info_to_gw_info(&self.uplink_frame_set.rx_info_set)?,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        #[cfg(test)]
+        {
+            req.base.transaction_id = 1234;
+        }
+
+        let client = roaming::get(&net_id).await?;
+        let async_receiver = match client.is_async() {
+            false => None,
+            true => {
+                Some(get_async_receiver(req.base.transaction_id, client.get_async_timeout()).await?)
+            }
+        };
+
+        client
+            .xmit_data_req(backend::Role::SNS, &mut req, async_receiver)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn log_roaming_usage(&self) -> Result<()> {
+        trace!("Logging roaming usage");
+        let conf = config::get();
+        let size = self.uplink_frame_set.phy_payload.to_vec()?.len();
+
+        roaming::log_uplink_usage(
+            conf.roaming.fns_only.net_id,
+            fields::RoamingRole::FNS,
+            self.dev_eui,
+            self.dev_addr.unwrap_or_default(),
+            self.uplink_frame_set.tx_info.modulation.as_ref(),
+            size,
+        )
+        .await;
+
+        Ok(())
+    }
+}
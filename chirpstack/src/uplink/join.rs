@@ -12,10 +12,11 @@ use lrwn::{
 
 use super::error::Error;
 use super::join_fns;
-use super::{filter_rx_info_by_tenant_id, helpers, RelayContext, UplinkFrameSet};
+use super::{dropped, filter_rx_info_by_tenant_id, helpers, RelayContext, UplinkFrameSet};
 
 use crate::api::{backend::get_async_receiver, helpers::ToProto};
 use crate::backend::{joinserver, keywrap, roaming};
+use crate::gateway::airtime;
 use crate::helpers::errors::PrintFullError;
 use crate::storage::{
     application,
@@ -23,9 +24,11 @@ use crate::storage::{
     device_keys, device_profile, device_queue,
     error::Error as StorageError,
     helpers::get_all_device_data,
-    metrics, tenant,
+    key_access_log, metrics, tenant,
+};
+use crate::{
+    config, devaddr::get_random_dev_addr, downlink, hsm, integration, monitoring, region, stream,
 };
-use crate::{config, devaddr::get_random_dev_addr, downlink, integration, region, stream};
 use chirpstack_api::{common, integration as integration_pb, internal, stream as stream_pb};
 
 pub struct JoinRequest {
@@ -119,6 +122,7 @@ impl JoinRequest {
         ctx.validate_region_config_id()?;
         ctx.filter_rx_info_by_tenant()?;
         ctx.abort_on_device_is_disabled()?;
+        ctx.abort_on_device_state().await?;
         ctx.abort_on_relay_only_comm()?;
         ctx.log_uplink_frame_set().await?;
         ctx.abort_on_otaa_is_disabled()?;
@@ -130,9 +134,10 @@ impl JoinRequest {
             // Using internal keys
             ctx.validate_mic().await?;
             ctx.validate_dev_nonce_and_get_device_keys().await?;
-            ctx.construct_join_accept_and_set_keys()?;
+            ctx.construct_join_accept_and_set_keys().await?;
         }
         ctx.log_uplink_meta().await?;
+        ctx.save_tenant_metrics();
         ctx.set_device_session().await?;
         ctx.flush_device_queue().await?;
         ctx.set_device_mode().await?;
@@ -170,6 +175,7 @@ impl JoinRequest {
         ctx.set_device_info()?;
         ctx.set_relay_rx_info()?;
         ctx.abort_on_device_is_disabled()?;
+        ctx.abort_on_device_state().await?;
         ctx.abort_on_otaa_is_disabled()?;
         ctx.abort_on_relay_only_comm()?;
         ctx.set_random_dev_addr()?;
@@ -180,7 +186,7 @@ impl JoinRequest {
             // Using internal keys
             ctx.validate_mic().await?;
             ctx.validate_dev_nonce_and_get_device_keys().await?;
-            ctx.construct_join_accept_and_set_keys()?;
+            ctx.construct_join_accept_and_set_keys().await?;
         }
         ctx.set_device_session().await?;
         ctx.flush_device_queue().await?;
@@ -243,7 +249,17 @@ impl JoinRequest {
         trace!("Getting device keys");
         let jr = self.join_request.as_ref().unwrap();
         self.device_keys = match device_keys::get(&jr.dev_eui).await {
-            Ok(v) => Some(v),
+            Ok(mut v) => {
+                key_access_log::log(
+                    &jr.dev_eui,
+                    key_access_log::KeyType::RootKeys,
+                    key_access_log::Actor::System,
+                    key_access_log::Purpose::Join,
+                )
+                .await?;
+                v.unwrap_root_keys()?;
+                Some(v)
+            }
             Err(e) => {
                 if let StorageError::NotFound(_) = e {
                     None
@@ -273,8 +289,8 @@ impl JoinRequest {
                         return Err(anyhow::Error::new(Error::Abort));
                     }
 
-                    info!(dev_eui = %jr.dev_eui, join_eui = %jr.join_eui, "Unknown device, trying passive-roaming activation");
-                    join_fns::JoinRequest::start_pr(self.uplink_frame_set.clone(), *jr).await?;
+                    info!(dev_eui = %jr.dev_eui, join_eui = %jr.join_eui, "Unknown device, trying roaming activation");
+                    join_fns::JoinRequest::start(self.uplink_frame_set.clone(), *jr).await?;
                     return Err(anyhow::Error::new(Error::Abort));
                 } else {
                     return Err(anyhow::Error::new(e));
@@ -372,6 +388,56 @@ impl JoinRequest {
         Ok(())
     }
 
+    async fn abort_on_device_state(&self) -> Result<(), Error> {
+        let dev = self.device.as_ref().unwrap();
+
+        let reason = match dev.state {
+            device::DeviceState::Suspended => stream_pb::UplinkDropReason::DeviceSuspended,
+            device::DeviceState::Decommissioned => {
+                stream_pb::UplinkDropReason::DeviceDecommissioned
+            }
+            _ => return Ok(()),
+        };
+
+        let app = self.application.as_ref().unwrap();
+
+        info!(dev_eui = %dev.dev_eui, state = %dev.state, "Dropping join-request, device is out of service");
+
+        integration::log_event(
+            app.id.into(),
+            &dev.variables,
+            &integration_pb::LogEvent {
+                time: Some(Utc::now().into()),
+                device_info: self.device_info.clone(),
+                level: integration_pb::LogLevel::Warning.into(),
+                code: integration_pb::LogCode::DeviceState.into(),
+                description: format!(
+                    "Join-request dropped, device is in {} state",
+                    dev.state.to_string().to_lowercase()
+                ),
+                context: [
+                    ("state".to_string(), dev.state.to_string()),
+                    (
+                        "deduplication_id".to_string(),
+                        self.uplink_frame_set.uplink_set_id.to_string(),
+                    ),
+                ]
+                .iter()
+                .cloned()
+                .collect(),
+            },
+        )
+        .await;
+
+        dropped::log(stream_pb::UplinkDropped {
+            dev_eui: dev.dev_eui.to_string(),
+            ..dropped::from_uplink_frame_set(&self.uplink_frame_set, reason)
+        })
+        .await;
+
+        Err(Error::Abort)
+    }
+
     fn abort_on_otaa_is_disabled(&self) -> Result<()> {
         if !self.device_profile.as_ref().unwrap().supports_otaa {
             return Err(anyhow!("OTAA is disabled in device-profile"));
@@ -393,7 +459,16 @@ impl JoinRequest {
     async fn validate_mic(&self) -> Result<()> {
         let device_keys = self.device_keys.as_ref().unwrap();
 
-        if let Some(relay_ctx) = self.relay_context.as_ref() {
+        if self.tenant.as_ref().unwrap().use_hsm {
+            // The root-keys are stored in the HSM.
+            let phy = match self.relay_context.as_ref() {
+                Some(relay_ctx) => relay_ctx.req.payload.as_ref(),
+                None => &self.uplink_frame_set.phy_payload,
+            };
+            if hsm::validate_join_request_mic(&device_keys.dev_eui, phy).await? {
+                return Ok(());
+            }
+        } else if let Some(relay_ctx) = self.relay_context.as_ref() {
             if relay_ctx
                 .req
                 .payload
@@ -463,7 +538,10 @@ impl JoinRequest {
             )
             .await
             {
-                Ok(v) => v,
+                Ok(mut v) => {
+                    v.unwrap_root_keys()?;
+                    v
+                }
                 Err(v) => match v {
                     StorageError::InvalidDevNonce => {
                         integration::log_event(
@@ -609,16 +687,15 @@ impl JoinRequest {
         Ok(())
     }
 
-    fn construct_join_accept_and_set_keys(&mut self) -> Result<()> {
+    async fn construct_join_accept_and_set_keys(&mut self) -> Result<()> {
         trace!("Constructing JoinAccept payload");
 
         let conf = config::get();
         let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
         let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
-        let join_request = self.join_request.as_ref().unwrap();
 
         let d = self.device.as_ref().unwrap();
-        let dk = self.device_keys.as_mut().unwrap();
+        let dk = self.device_keys.as_ref().unwrap();
 
         let join_nonce = dk.join_nonce - 1; // this was incremented on validation
         if join_nonce == (1 << 24) - 1 {
@@ -634,7 +711,7 @@ impl JoinRequest {
             .to_string()
             .starts_with("1.0");
 
-        let mut phy = PhyPayload {
+        let phy = PhyPayload {
             mhdr: MHDR {
                 m_type: MType::JoinAccept,
                 major: Major::LoRaWANR1,
@@ -654,6 +731,25 @@ impl JoinRequest {
             mic: None, // we need to calculate this
         };
 
+        let join_nonce = join_nonce as u32;
+        if self.tenant.as_ref().unwrap().use_hsm {
+            self.set_join_accept_mic_and_keys_hsm(phy, opt_neg, join_nonce)
+                .await
+        } else {
+            self.set_join_accept_mic_and_keys(phy, opt_neg, join_nonce)
+        }
+    }
+
+    fn set_join_accept_mic_and_keys(
+        &mut self,
+        mut phy: PhyPayload,
+        opt_neg: bool,
+        join_nonce: u32,
+    ) -> Result<()> {
+        let conf = config::get();
+        let join_request = self.join_request.as_ref().unwrap();
+        let dk = self.device_keys.as_ref().unwrap();
+
         if opt_neg {
             let js_int_key = keys::get_js_int_key(&join_request.dev_eui, &dk.nwk_key)?;
             phy.set_join_accept_mic(
@@ -682,7 +778,7 @@ impl JoinRequest {
             &device_keys.nwk_key,
             &conf.network.net_id,
             &join_request.join_eui,
-            join_nonce as u32,
+            join_nonce,
             join_request.dev_nonce,
         )?);
 
@@ -692,7 +788,7 @@ impl JoinRequest {
                 &device_keys.nwk_key,
                 &conf.network.net_id,
                 &join_request.join_eui,
-                join_nonce as u32,
+                join_nonce,
                 join_request.dev_nonce,
             )?,
             false => keys::get_f_nwk_s_int_key(
@@ -700,7 +796,7 @@ impl JoinRequest {
                 &device_keys.nwk_key,
                 &conf.network.net_id,
                 &join_request.join_eui,
-                join_nonce as u32,
+                join_nonce,
                 join_request.dev_nonce,
             )?,
         });
@@ -711,7 +807,7 @@ impl JoinRequest {
                 &device_keys.nwk_key,
                 &conf.network.net_id,
                 &join_request.join_eui,
-                join_nonce as u32,
+                join_nonce,
                 join_request.dev_nonce,
             )?,
             false => keys::get_f_nwk_s_int_key(
@@ -719,7 +815,7 @@ impl JoinRequest {
                 &device_keys.nwk_key,
                 &conf.network.net_id,
                 &join_request.join_eui,
-                join_nonce as u32,
+                join_nonce,
                 join_request.dev_nonce,
             )?,
         });
@@ -732,7 +828,7 @@ impl JoinRequest {
                     &device_keys.app_key,
                     &conf.network.net_id,
                     &join_request.join_eui,
-                    join_nonce as u32,
+                    join_nonce,
                     join_request.dev_nonce,
                 )?,
                 false => keys::get_app_s_key(
@@ -740,7 +836,7 @@ impl JoinRequest {
                     &device_keys.nwk_key,
                     &conf.network.net_id,
                     &join_request.join_eui,
-                    join_nonce as u32,
+                    join_nonce,
                     join_request.dev_nonce,
                 )?,
             }
@@ -750,6 +846,41 @@ impl JoinRequest {
         Ok(())
     }
 
+    // Sets the join-accept MIC, encrypts the join-accept and derives the session-keys using the
+    // root-keys stored in the HSM.
+    async fn set_join_accept_mic_and_keys_hsm(
+        &mut self,
+        phy: PhyPayload,
+        opt_neg: bool,
+        join_nonce: u32,
+    ) -> Result<()> {
+        let conf = config::get();
+        let join_request = self.join_request.as_ref().unwrap();
+
+        trace!("Setting session-keys using HSM");
+        let (phy, session_keys) = hsm::set_join_accept_and_get_session_keys(
+            &join_request.dev_eui,
+            &join_request.join_eui,
+            join_request.dev_nonce,
+            &conf.network.net_id,
+            join_nonce,
+            opt_neg,
+            phy,
+        )
+        .await?;
+
+        self.join_accept = Some(phy);
+        self.f_nwk_s_int_key = Some(session_keys.f_nwk_s_int_key);
+        self.s_nwk_s_int_key = Some(session_keys.s_nwk_s_int_key);
+        self.nwk_s_enc_key = Some(session_keys.nwk_s_enc_key);
+        self.app_s_key = Some(common::KeyEnvelope {
+            kek_label: "".to_string(),
+            aes_key: session_keys.app_s_key.to_vec(),
+        });
+
+        Ok(())
+    }
+
     async fn log_uplink_meta(&self) -> Result<()> {
         trace!("Logging uplink meta");
 
@@ -767,6 +898,25 @@ impl JoinRequest {
         Ok(())
     }
 
+    fn save_tenant_metrics(&self) {
+        trace!("Saving tenant metrics");
+        let tenant = self.tenant.as_ref().unwrap();
+
+        monitoring::tenant::inc_uplink(&tenant.id, MType::JoinRequest);
+
+        if let Some(airtime) = self
+            .uplink_frame_set
+            .tx_info
+            .modulation
+            .as_ref()
+            .and_then(|v| {
+                airtime::get_airtime(v, self.uplink_frame_set.phy_payload.to_vec().ok()?.len())
+            })
+        {
+            monitoring::tenant::inc_airtime(&tenant.id, "rx", airtime);
+        }
+    }
+
     async fn set_device_session(&mut self) -> Result<()> {
         trace!("Setting device-session");
 
@@ -888,6 +1038,11 @@ impl JoinRequest {
                 secondary_dev_addr: Some(None),
                 join_eui: Some(req.join_eui),
                 device_session: Some(d.device_session.clone()),
+                state: if d.state == device::DeviceState::Provisioned {
+                    Some(device::DeviceState::Active)
+                } else {
+                    None
+                },
                 ..Default::default()
             },
         )
@@ -8,12 +8,13 @@ use uuid::Uuid;
 use super::{filter_rx_info_by_public_only, UplinkFrameSet};
 use crate::api::backend::get_async_receiver;
 use crate::backend::{joinserver, keywrap, roaming};
-use crate::downlink;
-use crate::storage::passive_roaming;
+use crate::devaddr::get_random_dev_addr;
+use crate::storage::{handover_roaming, passive_roaming};
 use crate::uplink::helpers;
+use crate::{config, downlink, region};
 use backend::Client;
 use chirpstack_api::internal;
-use lrwn::{JoinRequestPayload, NetID};
+use lrwn::{DevAddr, JoinRequestPayload, NetID};
 
 pub struct JoinRequest {
     uplink_frame_set: UplinkFrameSet,
@@ -21,28 +22,40 @@ pub struct JoinRequest {
     home_net_id: Option<NetID>,
     client: Option<Arc<Client>>,
     pr_start_ans: Option<backend::PRStartAnsPayload>,
+    hr_start_ans: Option<backend::HRStartAnsPayload>,
+    dev_addr: Option<DevAddr>,
 }
 
 impl JoinRequest {
-    pub async fn start_pr(ufs: UplinkFrameSet, jr: JoinRequestPayload) -> Result<()> {
-        let span = span!(Level::INFO, "start_pr");
-        JoinRequest::_start_pr(ufs, jr).instrument(span).await
+    // Starts the roaming activation of a device of a roaming partner. Depending on the roaming
+    // agreement with the hNS, this uses passive-roaming (fNS) or handover-roaming (sNS).
+    pub async fn start(ufs: UplinkFrameSet, jr: JoinRequestPayload) -> Result<()> {
+        let span = span!(Level::INFO, "start_roaming");
+        JoinRequest::_start(ufs, jr).instrument(span).await
     }
 
-    async fn _start_pr(ufs: UplinkFrameSet, jr: JoinRequestPayload) -> Result<()> {
+    async fn _start(ufs: UplinkFrameSet, jr: JoinRequestPayload) -> Result<()> {
         let mut ctx = JoinRequest {
             uplink_frame_set: ufs,
             join_request: jr,
             home_net_id: None,
             client: None,
             pr_start_ans: None,
+            hr_start_ans: None,
+            dev_addr: None,
         };
 
         ctx.filter_rx_info_by_public_only()?;
         ctx.get_home_net_id().await?;
         ctx.get_client().await?;
-        ctx.start_roaming().await?;
-        ctx.save_roaming_session().await?;
+        if roaming::get_use_handover_roaming(ctx.home_net_id.unwrap())? {
+            ctx.start_handover_roaming().await?;
+            ctx.send_join_accept().await?;
+            ctx.save_handover_roaming_session().await?;
+        } else {
+            ctx.start_roaming().await?;
+            ctx.save_roaming_session().await?;
+        }
 
         Ok(())
     }
@@ -106,20 +119,7 @@ impl JoinRequest {
 
         let mut pr_req = backend::PRStartReqPayload {
             phy_payload: self.uplink_frame_set.phy_payload.to_vec()?,
-            ul_meta_data: backend::ULMetaData {
-                dev_eui: self.join_request.dev_eui.to_vec(),
-                ul_freq: Some((self.uplink_frame_set.tx_info.frequency as f64) / 1_000_000.0),
-                data_rate: Some(self.uplink_frame_set.dr),
-                recv_time: helpers::get_rx_timestamp_chrono(&self.uplink_frame_set.rx_info_set),
-                rf_region: self
-                    .uplink_frame_set
-                    .region_common_name
-                    .to_string()
-                    .replace('_', "-"),
-                gw_cnt: Some(self.uplink_frame_set.rx_info_set.len()),
-                gw_info: roaming::rx_info_to_gw_info(&self.uplink_frame_set.rx_info_set)?,
-                ..Default::default()
-            },
+            ul_meta_data: self.get_ul_meta_data()?,
             ..Default::default()
         };
 
@@ -183,6 +183,8 @@ impl JoinRequest {
                     Some((Utc::now() + Duration::try_seconds(lt).unwrap_or_default()).into())
                 }
             },
+            created_at: Some(Utc::now().into()),
+            uplink_count: 1,
             lorawan_1_1: pr_start_ans.f_nwk_s_int_key.is_some(),
 
             f_nwk_s_int_key: match &pr_start_ans.f_nwk_s_int_key {
@@ -199,4 +201,161 @@ impl JoinRequest {
             .await
             .context("Save passive-roaming device-session")
     }
+
+    async fn start_handover_roaming(&mut self) -> Result<()> {
+        trace!("Starting handover-roaming");
+
+        // In case of handover-roaming, the sNS assigns the DevAddr and handles the MAC layer.
+        let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
+        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
+        let dev_addr = get_random_dev_addr();
+
+        let mut hr_req = backend::HRStartReqPayload {
+            phy_payload: self.uplink_frame_set.phy_payload.to_vec()?,
+            ul_meta_data: self.get_ul_meta_data()?,
+            dev_addr: dev_addr.to_vec(),
+            dl_settings: lrwn::DLSettings {
+                opt_neg: false,
+                rx2_dr: region_network.rx2_dr,
+                rx1_dr_offset: region_network.rx1_dr_offset,
+            }
+            .to_le_bytes()?
+            .to_vec(),
+            rx_delay: region_network.rx1_delay,
+            // The MAC version of the device is not known to the sNS, thus we only use the
+            // CFList which is supported by all MAC versions.
+            cf_list: match region_conf.get_cf_list(lrwn::region::MacVersion::LORAWAN_1_0_0) {
+                Some(v) => v.to_bytes()?.to_vec(),
+                None => Vec::new(),
+            },
+            ..Default::default()
+        };
+
+        #[cfg(test)]
+        {
+            hr_req.base.transaction_id = 1234;
+        }
+
+        let client = self.client.as_ref().unwrap();
+        let async_receiver = match client.is_async() {
+            false => None,
+            true => Some(
+                get_async_receiver(hr_req.base.transaction_id, client.get_async_timeout()).await?,
+            ),
+        };
+
+        let resp = client
+            .hr_start_req(backend::Role::HNS, &mut hr_req, async_receiver)
+            .await?;
+
+        if resp.phy_payload.is_empty() {
+            return Err(anyhow!("PHYPayload is not set"));
+        }
+
+        if resp.nwk_s_key.is_none() && resp.f_nwk_s_int_key.is_none() {
+            return Err(anyhow!("NwkSKey or FNwkSIntKey must be set"));
+        }
+
+        self.dev_addr = Some(dev_addr);
+        self.hr_start_ans = Some(resp);
+        Ok(())
+    }
+
+    async fn send_join_accept(&self) -> Result<()> {
+        trace!("Sending join-accept");
+
+        let hr_start_ans = self.hr_start_ans.as_ref().unwrap();
+        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
+
+        // The join-accept is sent using the default parameters of the region.
+        let dl_meta = backend::DLMetaData {
+            dev_eui: self.join_request.dev_eui.to_vec(),
+            class_mode: Some("A".to_string()),
+            dl_freq_1: {
+                let rx1_freq = region_conf.get_rx1_frequency_for_uplink_frequency(
+                    self.uplink_frame_set.tx_info.frequency,
+                )?;
+                Some(rx1_freq as f64 / 1_000_000.0)
+            },
+            data_rate_1: Some(region_conf.get_rx1_data_rate_index(self.uplink_frame_set.dr, 0)?),
+            rx_delay_1: Some(region_conf.get_defaults().join_accept_delay1.as_secs() as usize),
+            dl_freq_2: Some(region_conf.get_defaults().rx2_frequency as f64 / 1_000_000.0),
+            data_rate_2: Some(region_conf.get_defaults().rx2_dr),
+            ..Default::default()
+        };
+
+        downlink::roaming::PassiveRoamingDownlink::handle(
+            self.uplink_frame_set.clone(),
+            hr_start_ans.phy_payload.clone(),
+            dl_meta,
+        )
+        .await
+    }
+
+    async fn save_handover_roaming_session(&self) -> Result<()> {
+        trace!("Saving handover-roaming serving device-session");
+
+        let hr_start_ans = self.hr_start_ans.as_ref().unwrap();
+        let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
+        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
+
+        // LoRaWAN 1.0 devices use the NwkSKey for all the network session-keys.
+        let nwk_s_key = match &hr_start_ans.nwk_s_key {
+            Some(ke) => Some(keywrap::unwrap(ke)?.to_vec()),
+            None => None,
+        };
+        let unwrap_or_nwk_s_key = |ke: &Option<backend::KeyEnvelope>| -> Result<Vec<u8>> {
+            match ke {
+                Some(ke) => Ok(keywrap::unwrap(ke)?.to_vec()),
+                None => nwk_s_key
+                    .clone()
+                    .ok_or_else(|| anyhow!("NwkSKey is not set")),
+            }
+        };
+
+        let sess = internal::HandoverRoamingServingDeviceSession {
+            dev_eui: self.join_request.dev_eui.to_vec(),
+            net_id: self.home_net_id.unwrap().to_vec(),
+            dev_addr: self.dev_addr.unwrap().to_vec(),
+            lifetime: match hr_start_ans.lifetime {
+                Some(lt) if lt > 0 => {
+                    Some((Utc::now() + Duration::try_seconds(lt as i64).unwrap_or_default()).into())
+                }
+                _ => None,
+            },
+            lorawan_1_1: hr_start_ans.f_nwk_s_int_key.is_some(),
+            f_nwk_s_int_key: unwrap_or_nwk_s_key(&hr_start_ans.f_nwk_s_int_key)?,
+            s_nwk_s_int_key: unwrap_or_nwk_s_key(&hr_start_ans.s_nwk_s_int_key)?,
+            nwk_s_enc_key: unwrap_or_nwk_s_key(&hr_start_ans.nwk_s_enc_key)?,
+            region_config_id: self.uplink_frame_set.region_config_id.clone(),
+            uplink_frequency: self.uplink_frame_set.tx_info.frequency,
+            dr: self.uplink_frame_set.dr as u32,
+            rx1_delay: region_network.rx1_delay as u32,
+            rx1_dr_offset: region_network.rx1_dr_offset as u32,
+            rx2_dr: region_network.rx2_dr as u32,
+            rx2_frequency: region_conf.get_defaults().rx2_frequency,
+            ..Default::default()
+        };
+
+        handover_roaming::save_serving(&sess)
+            .await
+            .context("Save handover-roaming serving device-session")
+    }
+
+    fn get_ul_meta_data(&self) -> Result<backend::ULMetaData> {
+        Ok(backend::ULMetaData {
+            dev_eui: self.join_request.dev_eui.to_vec(),
+            ul_freq: Some((self.uplink_frame_set.tx_info.frequency as f64) / 1_000_000.0),
+            data_rate: Some(self.uplink_frame_set.dr),
+            recv_time: helpers::get_rx_timestamp_chrono(&self.uplink_frame_set.rx_info_set),
+            rf_region: self
+                .uplink_frame_set
+                .region_common_name
+                .to_string()
+                .replace('_', "-"),
+            gw_cnt: Some(self.uplink_frame_set.rx_info_set.len()),
+            gw_info: roaming::rx_info_to_gw_info(&self.uplink_frame_set.rx_info_set)?,
+            ..Default::default()
+        })
+    }
 }
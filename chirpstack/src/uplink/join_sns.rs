@@ -12,18 +12,21 @@ use crate::storage::{
     device::{self, DeviceClass},
     device_keys, device_profile, device_queue,
     error::Error as StorageError,
+    handover_roaming,
     helpers::get_all_device_data,
-    metrics, tenant,
+    key_access_log, metrics, tenant,
 };
-use crate::{config, devaddr::get_random_dev_addr, integration, region, stream};
-use backend::{PRStartAnsPayload, PRStartReqPayload};
+use crate::{config, devaddr::get_random_dev_addr, hsm, integration, region, stream};
+use backend::{HRStartAnsPayload, HRStartReqPayload, PRStartAnsPayload, PRStartReqPayload};
 use chirpstack_api::{common, integration as integration_pb, internal, stream as stream_pb};
 use lrwn::{keys, AES128Key, DevAddr, NetID};
 
 pub struct JoinRequest {
     uplink_frame_set: UplinkFrameSet,
-    pr_start_req: PRStartReqPayload,
+    pr_start_req: Option<PRStartReqPayload>,
     pr_start_ans: Option<PRStartAnsPayload>,
+    hr_start_req: Option<HRStartReqPayload>,
+    hr_start_ans: Option<HRStartAnsPayload>,
 
     join_request: Option<lrwn::JoinRequestPayload>,
     join_accept: Option<lrwn::PhyPayload>,
@@ -59,9 +62,11 @@ impl JoinRequest {
     ) -> Result<PRStartAnsPayload> {
         let mut ctx = JoinRequest {
             uplink_frame_set: ufs,
-            pr_start_req,
+            pr_start_req: Some(pr_start_req),
 
             pr_start_ans: None,
+            hr_start_req: None,
+            hr_start_ans: None,
             join_request: None,
             join_accept: None,
             device: None,
@@ -85,6 +90,7 @@ impl JoinRequest {
         ctx.get_device_keys_or_js_client().await?;
         ctx.set_device_info()?;
         ctx.abort_on_device_is_disabled()?;
+        ctx.abort_on_device_state()?;
         ctx.abort_on_otaa_is_disabled()?;
         ctx.get_random_dev_addr()?;
         if ctx.js_client.is_some() {
@@ -94,7 +100,7 @@ impl JoinRequest {
             // Using internal keys
             ctx.validate_mic().await?;
             ctx.validate_dev_nonce_and_get_device_keys().await?;
-            ctx.construct_join_accept_and_set_keys()?;
+            ctx.construct_join_accept_and_set_keys().await?;
         }
         ctx.log_uplink_meta().await?;
         ctx.set_device_session().await?;
@@ -107,6 +113,74 @@ impl JoinRequest {
             .ok_or_else(|| anyhow!("PRStartAnsPayload is not set"))
     }
 
+    pub async fn start_hr(
+        ufs: UplinkFrameSet,
+        hr_start_req: HRStartReqPayload,
+    ) -> Result<HRStartAnsPayload> {
+        let span = span!(Level::INFO, "start_hr");
+        JoinRequest::_start_hr(ufs, hr_start_req)
+            .instrument(span)
+            .await
+    }
+
+    async fn _start_hr(
+        ufs: UplinkFrameSet,
+        hr_start_req: HRStartReqPayload,
+    ) -> Result<HRStartAnsPayload> {
+        let mut ctx = JoinRequest {
+            uplink_frame_set: ufs,
+            hr_start_req: Some(hr_start_req),
+
+            pr_start_req: None,
+            pr_start_ans: None,
+            hr_start_ans: None,
+            join_request: None,
+            join_accept: None,
+            device: None,
+            js_client: None,
+            application: None,
+            tenant: None,
+            device_profile: None,
+            device_keys: None,
+            device_info: None,
+            dev_addr: None,
+            f_nwk_s_int_key: None,
+            s_nwk_s_int_key: None,
+            nwk_s_enc_key: None,
+            app_s_key: None,
+            js_session_key_id: "".to_string(),
+        };
+
+        ctx.get_join_request_payload()?;
+        ctx.get_device_data().await?;
+        ctx.check_roaming_allowed()?;
+        ctx.get_device_keys_or_js_client().await?;
+        ctx.set_device_info()?;
+        ctx.abort_on_device_is_disabled()?;
+        ctx.abort_on_device_state()?;
+        ctx.abort_on_otaa_is_disabled()?;
+        ctx.get_dev_addr_from_hr_start_req()?;
+        if ctx.js_client.is_some() {
+            // Using join-server
+            ctx.get_join_accept_from_js().await?;
+        } else {
+            // Using internal keys
+            ctx.validate_mic().await?;
+            ctx.validate_dev_nonce_and_get_device_keys().await?;
+            ctx.construct_join_accept_and_set_keys().await?;
+        }
+        ctx.log_uplink_meta().await?;
+        ctx.set_device_session().await?;
+        ctx.flush_device_queue().await?;
+        ctx.update_device().await?;
+        ctx.save_handover_roaming_session().await?;
+        ctx.send_join_event().await?;
+        ctx.set_hr_start_ans_payload()?;
+
+        ctx.hr_start_ans
+            .ok_or_else(|| anyhow!("HRStartAnsPayload is not set"))
+    }
+
     fn get_join_request_payload(&mut self) -> Result<()> {
         trace!("Getting JoinRequestPayload");
         self.join_request = Some(match self.uplink_frame_set.phy_payload.payload {
@@ -150,7 +224,17 @@ impl JoinRequest {
         trace!("Getting device keys");
         let jr = self.join_request.as_ref().unwrap();
         self.device_keys = match device_keys::get(&jr.dev_eui).await {
-            Ok(v) => Some(v),
+            Ok(mut v) => {
+                key_access_log::log(
+                    &jr.dev_eui,
+                    key_access_log::KeyType::RootKeys,
+                    key_access_log::Actor::System,
+                    key_access_log::Purpose::Join,
+                )
+                .await?;
+                v.unwrap_root_keys()?;
+                Some(v)
+            }
             Err(e) => {
                 if let StorageError::NotFound(_) = e {
                     None
@@ -200,6 +284,17 @@ impl JoinRequest {
         Ok(())
     }
 
+    fn abort_on_device_state(&self) -> Result<()> {
+        let dev = self.device.as_ref().unwrap();
+        if dev.state.is_out_of_service() {
+            return Err(anyhow!(
+                "Device is in {} state",
+                dev.state.to_string().to_lowercase()
+            ));
+        }
+        Ok(())
+    }
+
     fn abort_on_otaa_is_disabled(&self) -> Result<()> {
         if !self.device_profile.as_ref().unwrap().supports_otaa {
             return Err(anyhow!("OTAA is disabled in device-profile"));
@@ -212,32 +307,71 @@ impl JoinRequest {
         Ok(())
     }
 
+    // In case of handover-roaming, the DevAddr is assigned by the sNS.
+    fn get_dev_addr_from_hr_start_req(&mut self) -> Result<()> {
+        let hr_start_req = self.hr_start_req.as_ref().unwrap();
+        self.dev_addr = Some(DevAddr::from_slice(&hr_start_req.dev_addr)?);
+        Ok(())
+    }
+
+    // Returns the DLSettings, RxDelay and CFList for the join-accept. In case of
+    // handover-roaming, these are provided by the sNS as it handles the MAC layer.
+    fn get_join_accept_params(&self) -> Result<(lrwn::DLSettings, u8, Option<lrwn::CFList>)> {
+        let dp = self.device_profile.as_ref().unwrap();
+
+        // The opt_neg flag is set for devices other than 1.0.x.
+        let opt_neg = !dp.mac_version.to_string().starts_with("1.0");
+
+        if let Some(hr_start_req) = &self.hr_start_req {
+            let mut dl_settings = lrwn::DLSettings::from_le_bytes(
+                hr_start_req
+                    .dl_settings
+                    .clone()
+                    .try_into()
+                    .map_err(|_| anyhow!("DLSettings must be exactly 1 byte"))?,
+            );
+            dl_settings.opt_neg = opt_neg;
+
+            let cf_list = if hr_start_req.cf_list.is_empty() {
+                None
+            } else {
+                Some(lrwn::CFList::from_bytes(
+                    hr_start_req
+                        .cf_list
+                        .clone()
+                        .try_into()
+                        .map_err(|_| anyhow!("CFList must be exactly 16 bytes"))?,
+                )?)
+            };
+
+            return Ok((dl_settings, hr_start_req.rx_delay, cf_list));
+        }
+
+        let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
+        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
+
+        Ok((
+            lrwn::DLSettings {
+                opt_neg,
+                rx2_dr: region_network.rx2_dr,
+                rx1_dr_offset: region_network.rx1_dr_offset,
+            },
+            region_network.rx1_delay,
+            region_conf.get_cf_list(dp.mac_version),
+        ))
+    }
+
     async fn get_join_accept_from_js(&mut self) -> Result<()> {
         trace!("Getting join-accept from Join Server");
 
         let js_client = self.js_client.as_ref().unwrap();
         let jr = self.join_request.as_ref().unwrap();
-        let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
-        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
 
         let phy_b = self.uplink_frame_set.phy_payload.to_vec()?;
         let dp = self.device_profile.as_ref().unwrap();
         let dev = self.device.as_ref().unwrap();
 
-        // The opt_neg flag is set for devices other than 1.0.x.
-        let opt_neg = !self
-            .device_profile
-            .as_ref()
-            .unwrap()
-            .mac_version
-            .to_string()
-            .starts_with("1.0");
-
-        let dl_settings = lrwn::DLSettings {
-            opt_neg,
-            rx2_dr: region_network.rx2_dr,
-            rx1_dr_offset: region_network.rx1_dr_offset,
-        };
+        let (dl_settings, rx_delay, cf_list) = self.get_join_accept_params()?;
 
         let mut join_req_pl = backend::JoinReqPayload {
             mac_version: dp.mac_version.to_string(),
@@ -245,8 +379,8 @@ impl JoinRequest {
             dev_eui: dev.dev_eui.to_vec(),
             dev_addr: self.dev_addr.unwrap().to_vec(),
             dl_settings: dl_settings.to_le_bytes()?.to_vec(),
-            rx_delay: region_network.rx1_delay,
-            cf_list: match region_conf.get_cf_list(dp.mac_version) {
+            rx_delay,
+            cf_list: match cf_list {
                 Some(v) => v.to_bytes()?.to_vec(),
                 None => Vec::new(),
             },
@@ -297,7 +431,17 @@ impl JoinRequest {
     async fn validate_mic(&self) -> Result<()> {
         let device_keys = self.device_keys.as_ref().unwrap();
 
-        if self
+        if self.tenant.as_ref().unwrap().use_hsm {
+            // The root-keys are stored in the HSM.
+            if hsm::validate_join_request_mic(
+                &device_keys.dev_eui,
+                &self.uplink_frame_set.phy_payload,
+            )
+            .await?
+            {
+                return Ok(());
+            }
+        } else if self
             .uplink_frame_set
             .phy_payload
             .validate_join_request_mic(&device_keys.nwk_key)?
@@ -359,7 +503,10 @@ impl JoinRequest {
             )
             .await
             {
-                Ok(v) => v,
+                Ok(mut v) => {
+                    v.unwrap_root_keys()?;
+                    v
+                }
                 Err(v) => match v {
                     StorageError::InvalidDevNonce => {
                         integration::log_event(
@@ -405,12 +552,12 @@ impl JoinRequest {
         Ok(())
     }
 
-    fn construct_join_accept_and_set_keys(&mut self) -> Result<()> {
+    async fn construct_join_accept_and_set_keys(&mut self) -> Result<()> {
         trace!("Constructing JoinAccept payload");
 
         let conf = config::get();
-        let region_network = config::get_region_network(&self.uplink_frame_set.region_config_id)?;
-        let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
+        let (dl_settings, rx_delay, cf_list) = self.get_join_accept_params()?;
+        let opt_neg = dl_settings.opt_neg;
         let join_request = self.join_request.as_ref().unwrap();
 
         let dk = self.device_keys.as_mut().unwrap();
@@ -420,15 +567,6 @@ impl JoinRequest {
             return Err(anyhow!("Join-nonce overflow"));
         }
 
-        // The opt_neg flag is set for devices other than 1.0.x.
-        let opt_neg = !self
-            .device_profile
-            .as_ref()
-            .unwrap()
-            .mac_version
-            .to_string()
-            .starts_with("1.0");
-
         let mut phy = lrwn::PhyPayload {
             mhdr: lrwn::MHDR {
                 m_type: lrwn::MType::JoinAccept,
@@ -438,17 +576,38 @@ impl JoinRequest {
                 join_nonce: join_nonce as u32,
                 home_netid: conf.network.net_id,
                 devaddr: self.dev_addr.unwrap(),
-                dl_settings: lrwn::DLSettings {
-                    opt_neg,
-                    rx2_dr: region_network.rx2_dr,
-                    rx1_dr_offset: region_network.rx1_dr_offset,
-                },
-                rx_delay: region_network.rx1_delay,
-                cflist: region_conf.get_cf_list(self.device_profile.as_ref().unwrap().mac_version),
+                dl_settings,
+                rx_delay,
+                cflist: cf_list,
             }),
             mic: None, // we need to calculate this
         };
 
+        if self.tenant.as_ref().unwrap().use_hsm {
+            trace!("Setting session-keys using HSM");
+            let (phy, session_keys) = hsm::set_join_accept_and_get_session_keys(
+                &join_request.dev_eui,
+                &join_request.join_eui,
+                join_request.dev_nonce,
+                &conf.network.net_id,
+                join_nonce as u32,
+                opt_neg,
+                phy,
+            )
+            .await?;
+
+            self.join_accept = Some(phy);
+            self.f_nwk_s_int_key = Some(session_keys.f_nwk_s_int_key);
+            self.s_nwk_s_int_key = Some(session_keys.s_nwk_s_int_key);
+            self.nwk_s_enc_key = Some(session_keys.nwk_s_enc_key);
+            self.app_s_key = Some(common::KeyEnvelope {
+                kek_label: "".to_string(),
+                aes_key: session_keys.app_s_key.to_vec(),
+            });
+
+            return Ok(());
+        }
+
         if opt_neg {
             let js_int_key = keys::get_js_int_key(&join_request.dev_eui, &dk.nwk_key)?;
             phy.set_join_accept_mic(
@@ -661,6 +820,13 @@ impl JoinRequest {
                             DeviceClass::A
                         },
                     ),
+                    state: if self.device.as_ref().unwrap().state
+                        == device::DeviceState::Provisioned
+                    {
+                        Some(device::DeviceState::Active)
+                    } else {
+                        None
+                    },
                     ..Default::default()
                 },
             )
@@ -719,7 +885,8 @@ impl JoinRequest {
 
         let region_conf = region::get(&self.uplink_frame_set.region_config_id)?;
 
-        let sender_id = NetID::from_slice(&self.pr_start_req.base.sender_id)?;
+        let pr_start_req = self.pr_start_req.as_ref().unwrap();
+        let sender_id = NetID::from_slice(&pr_start_req.base.sender_id)?;
         let pr_lifetime = roaming::get_passive_roaming_lifetime(sender_id)?;
         let kek_label = roaming::get_passive_roaming_kek_label(sender_id)?;
 
@@ -750,8 +917,7 @@ impl JoinRequest {
         let rx2_freq = region_conf.get_defaults().rx2_frequency;
 
         self.pr_start_ans = Some(PRStartAnsPayload {
-            base: self
-                .pr_start_req
+            base: pr_start_req
                 .base
                 .to_base_payload_result(backend::ResultCode::Success, ""),
             phy_payload: self.join_accept.as_ref().unwrap().to_vec()?,
@@ -773,9 +939,8 @@ impl JoinRequest {
                 class_mode: Some("A".to_string()),
                 data_rate_1: Some(rx1_dr),
                 data_rate_2: Some(rx2_dr),
-                f_ns_ul_token: self.pr_start_req.ul_meta_data.f_ns_ul_token.clone(),
-                gw_info: self
-                    .pr_start_req
+                f_ns_ul_token: pr_start_req.ul_meta_data.f_ns_ul_token.clone(),
+                gw_info: pr_start_req
                     .ul_meta_data
                     .gw_info
                     .iter()
@@ -791,4 +956,76 @@ impl JoinRequest {
 
         Ok(())
     }
+
+    async fn save_handover_roaming_session(&self) -> Result<()> {
+        trace!("Saving handover-roaming device-session");
+        let hr_start_req = self.hr_start_req.as_ref().unwrap();
+        let sender_id = NetID::from_slice(&hr_start_req.base.sender_id)?;
+        let hr_lifetime = roaming::get_handover_roaming_lifetime(sender_id)?;
+
+        handover_roaming::save(&internal::HandoverRoamingDeviceSession {
+            dev_eui: self.device.as_ref().unwrap().dev_eui.to_vec(),
+            net_id: sender_id.to_vec(),
+            dev_addr: self.dev_addr.unwrap().to_vec(),
+            lifetime: if hr_lifetime.is_zero() {
+                None
+            } else {
+                let lifetime: DateTime<Utc> = Utc::now() + hr_lifetime;
+                Some(lifetime.into())
+            },
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    fn set_hr_start_ans_payload(&mut self) -> Result<()> {
+        trace!("Setting HRStartAnsPayload");
+        let d = self.device.as_ref().unwrap();
+        let ds = d.get_device_session()?;
+
+        let hr_start_req = self.hr_start_req.as_ref().unwrap();
+        let sender_id = NetID::from_slice(&hr_start_req.base.sender_id)?;
+        let hr_lifetime = roaming::get_handover_roaming_lifetime(sender_id)?;
+        let kek_label = roaming::get_handover_roaming_kek_label(sender_id)?;
+
+        // The sNS takes over the MAC layer, thus it receives all the network session keys.
+        let mut ans = HRStartAnsPayload {
+            base: hr_start_req
+                .base
+                .to_base_payload_result(backend::ResultCode::Success, ""),
+            phy_payload: self.join_accept.as_ref().unwrap().to_vec()?,
+            dev_eui: d.dev_eui.to_vec(),
+            lifetime: if hr_lifetime.is_zero() {
+                None
+            } else {
+                Some(hr_lifetime.as_secs() as usize)
+            },
+            ..Default::default()
+        };
+
+        if ds.mac_version().to_string().starts_with("1.0") {
+            ans.nwk_s_key = Some(keywrap::wrap(
+                &kek_label,
+                AES128Key::from_slice(&ds.nwk_s_enc_key)?,
+            )?);
+        } else {
+            ans.f_nwk_s_int_key = Some(keywrap::wrap(
+                &kek_label,
+                AES128Key::from_slice(&ds.f_nwk_s_int_key)?,
+            )?);
+            ans.s_nwk_s_int_key = Some(keywrap::wrap(
+                &kek_label,
+                AES128Key::from_slice(&ds.s_nwk_s_int_key)?,
+            )?);
+            ans.nwk_s_enc_key = Some(keywrap::wrap(
+                &kek_label,
+                AES128Key::from_slice(&ds.nwk_s_enc_key)?,
+            )?);
+        }
+
+        self.hr_start_ans = Some(ans);
+
+        Ok(())
+    }
 }
@@ -10,6 +10,7 @@ use crate::config;
 use crate::helpers::errors::PrintFullError;
 use crate::storage::{
     error::Error,
+    fields,
     gateway::{self, RelayId},
 };
 use lrwn::EUI64;
@@ -94,6 +95,8 @@ impl MeshHeartbeat {
                 }
 
                 v.last_seen_at = Some(ts);
+                v.border_gateway_id = Some(self.gateway_id);
+                v.relay_path = self.get_relay_path();
                 v.region_config_id = border_gw
                     .properties
                     .get("region_config_id")
@@ -107,6 +110,8 @@ impl MeshHeartbeat {
                     relay_id: self.relay_id,
                     name: self.relay_id.to_string(),
                     last_seen_at: Some(ts),
+                    border_gateway_id: Some(self.gateway_id),
+                    relay_path: self.get_relay_path(),
                     ..Default::default()
                 })
                 .await?;
@@ -115,4 +120,27 @@ impl MeshHeartbeat {
 
         Ok(())
     }
+
+    fn get_relay_path(&self) -> fields::RelayPath {
+        fields::RelayPath::new(
+            self.mesh_stats
+                .relay_path
+                .iter()
+                .map(|p| fields::RelayPathItem {
+                    relay_id: p.relay_id.clone(),
+                    rssi: p.rssi,
+                    snr: p.snr,
+                })
+                .collect(),
+        )
+    }
+}
+
+// Returns the Relay ID in case the uplink was received through a Relay Gateway. The Border
+// Gateway sets the relay_id metadata key in this case.
+pub fn get_relay_id(rx_info: &gw::UplinkRxInfo) -> Option<RelayId> {
+    rx_info
+        .metadata
+        .get("relay_id")
+        .and_then(|v| RelayId::from_str(v).ok())
 }
@@ -14,11 +14,13 @@ use tokio::time::sleep;
 use tracing::{debug, error, info, span, trace, warn, Instrument, Level};
 use uuid::Uuid;
 
+use crate::api::helpers::ToProto;
 use crate::config;
+use crate::gateway::{airtime, noise_floor};
 use crate::helpers::errors::PrintFullError;
-use crate::monitoring::prometheus;
+use crate::monitoring::{prometheus, runtime};
 use crate::storage::{
-    device, device_profile, error::Error as StorageError, gateway, get_async_redis_conn, redis_key,
+    self, device, device_profile, error::Error as StorageError, gateway, redis_key,
 };
 use crate::stream;
 use chirpstack_api::{common, gw, stream as stream_pb};
@@ -27,8 +29,13 @@ use lrwn::{ForwardUplinkReq, MType, PhyPayload, EUI64};
 
 mod data;
 mod data_fns;
+pub mod data_hns;
 pub mod data_sns;
+mod data_sns_hr;
+mod dropped;
 pub mod error;
+mod f_cnt_anomaly;
+mod fns_only;
 pub mod helpers;
 pub mod join;
 pub mod join_fns;
@@ -186,17 +193,39 @@ async fn _deduplicate_uplink(
         region_config_id, tx_info_str, phy_str
     ));
 
-    let dedup_delay = config::get().network.deduplication_delay;
-    let mut dedup_ttl = dedup_delay * 2;
-    if dedup_ttl < Duration::from_millis(200) {
-        dedup_ttl = Duration::from_millis(200);
-    }
+    let conf = config::get();
+    let dedup_delay = conf.network.deduplication_delay;
+    let dedup_ttl = if conf.redis.ttl.deduplication.is_zero() {
+        (dedup_delay * 2).max(Duration::from_millis(200))
+    } else {
+        conf.redis.ttl.deduplication
+    };
 
     trace!(
         key = key.as_str(),
         "Adding uplink event to deduplication set and getting lock"
     );
-    let locked = deduplicate_put(&key, &lock_key, dedup_ttl, &event).await?;
+    let (locked, duplicate) = deduplicate_put(&key, &lock_key, dedup_ttl, &event).await?;
+    if duplicate {
+        debug!(
+            key = key.as_str(),
+            "Uplink event was already received, dropping duplicate"
+        );
+
+        dropped::log(stream_pb::UplinkDropped {
+            reason: stream_pb::UplinkDropReason::DeduplicationDuplicate.into(),
+            tx_info: event.tx_info.clone(),
+            rx_info: event.rx_info.iter().cloned().collect(),
+            phy_payload_byte_count: event.phy_payload.len() as u32,
+            message_type: match PhyPayload::from_slice(&event.phy_payload) {
+                Ok(phy) => phy.mhdr.m_type.to_proto().into(),
+                Err(_) => Default::default(),
+            },
+            ..Default::default()
+        })
+        .await;
+    }
+
     if locked {
         trace!(
             lock_key = lock_key.as_str(),
@@ -209,6 +238,7 @@ async fn _deduplicate_uplink(
     }
 
     DEDUPLICATE_NO_LOCK_COUNTER.inc();
+    let _queue_guard = runtime::enter_queue("deduplication");
 
     trace!(
         key = key.as_str(),
@@ -233,20 +263,20 @@ async fn _deduplicate_uplink(
     Ok(())
 }
 
-async fn deduplicate_put(
+#[cfg(not(feature = "bench-storage"))]
+pub async fn deduplicate_put(
     collect_key: &str,
     lock_key: &str,
     ttl: Duration,
     event: &gw::UplinkFrame,
-) -> Result<bool> {
+) -> Result<(bool, bool)> {
     let event_b = event.encode_to_vec();
 
-    let (lock_set,): (bool,) = redis::pipe()
+    let (added, lock_set): (usize, bool) = redis::pipe()
         .atomic()
         .cmd("SADD")
         .arg(collect_key)
         .arg(event_b)
-        .ignore()
         .cmd("PEXPIRE")
         .arg(collect_key)
         .arg(ttl.as_millis() as usize)
@@ -257,22 +287,40 @@ async fn deduplicate_put(
         .arg("PX")
         .arg(ttl.as_millis() as usize)
         .arg("NX")
-        .query_async(&mut get_async_redis_conn().await?)
+        .query_async(&mut storage::get_async_redis_conn().await?)
         .await
         .context("Deduplication put and get lock")?;
 
     // We get true if we were able to set the lock, thus true == not yet locked.
-    Ok(!lock_set)
+    // In case the SADD did not add a new item, the exact same uplink event was already in the
+    // set, thus it is a duplicate.
+    Ok((!lock_set, added == 0))
+}
+
+// Uses the in-memory storage stub instead of Redis, see storage::memory.
+#[cfg(feature = "bench-storage")]
+pub async fn deduplicate_put(
+    collect_key: &str,
+    lock_key: &str,
+    ttl: Duration,
+    event: &gw::UplinkFrame,
+) -> Result<(bool, bool)> {
+    let (added, lock_set) =
+        storage::memory::set_add_and_lock(collect_key, lock_key, ttl, event.encode_to_vec());
+    Ok((!lock_set, !added))
 }
 
-async fn deduplicate_collect(key: &str) -> Result<gw::UplinkFrameSet> {
+pub async fn deduplicate_collect(key: &str) -> Result<gw::UplinkFrameSet> {
+    #[cfg(not(feature = "bench-storage"))]
     let items_b: Vec<Vec<u8>> = {
         redis::cmd("SMEMBERS")
             .arg(key)
-            .query_async(&mut get_async_redis_conn().await?)
+            .query_async(&mut storage::get_async_redis_conn().await?)
             .await
             .context("Deduplication collect")?
     };
+    #[cfg(feature = "bench-storage")]
+    let items_b = storage::memory::set_members(key);
 
     if items_b.is_empty() {
         return Err(anyhow!("Zero items in collect set"));
@@ -308,6 +356,7 @@ pub async fn handle_uplink(
     deduplication_id: Uuid,
     uplink: gw::UplinkFrameSet,
 ) -> Result<()> {
+    let phy_payload_size = uplink.phy_payload.len();
     let mut uplink = UplinkFrameSet {
         uplink_set_id: deduplication_id,
         region_common_name,
@@ -346,12 +395,35 @@ pub async fn handle_uplink(
         .await
         .context("Update gateway meta-data")?;
 
+    debug!("Saving received airtime for uplink frame-set");
+    save_rx_airtime(&uplink, phy_payload_size).await;
+
+    debug!("Recording noise-floor for uplink frame-set");
+    record_rx_noise_floor(&uplink);
+
     debug!("Logging uplink frame to Redis Stream");
     let ufl: stream_pb::UplinkFrameLog = (&uplink).try_into()?;
     stream::frame::log_uplink_for_gateways(&ufl)
         .await
         .context("Log uplink for gateways")?;
 
+    // In case of fNS-only mode, the uplink is forwarded to the sNS without handling the
+    // MAC-layer.
+    if config::get().roaming.fns_only.enabled {
+        match uplink.phy_payload.mhdr.m_type {
+            MType::JoinRequest | MType::UnconfirmedDataUp | MType::ConfirmedDataUp => {
+                fns_only::Uplink::handle(uplink).await;
+                return Ok(());
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unexpected m_type: {}",
+                    uplink.phy_payload.mhdr.m_type
+                ))
+            }
+        }
+    }
+
     match uplink.phy_payload.mhdr.m_type {
         MType::JoinRequest => join::JoinRequest::handle(uplink).await,
         MType::UnconfirmedDataUp | MType::ConfirmedDataUp => data::Data::handle(uplink).await,
@@ -413,6 +485,31 @@ async fn update_gateway_metadata(ufs: &mut UplinkFrameSet) -> Result<()> {
     Ok(())
 }
 
+// Saves the received airtime for the gateways known to ChirpStack. Errors are logged as these
+// must not prevent the uplink from being handled.
+async fn save_rx_airtime(ufs: &UplinkFrameSet, size: usize) {
+    let gateway_ids: Vec<EUI64> = ufs.gateway_tenant_id_map.keys().cloned().collect();
+    if gateway_ids.is_empty() {
+        return;
+    }
+
+    if let Err(e) =
+        airtime::save_rx_airtime(&ufs.region_config_id, &gateway_ids, &ufs.tx_info, size).await
+    {
+        error!(error = %e.full(), "Saving received airtime failed");
+    }
+}
+
+// Records the noise-floor estimate for the gateways known to ChirpStack.
+fn record_rx_noise_floor(ufs: &UplinkFrameSet) {
+    let gateway_ids: Vec<EUI64> = ufs.gateway_tenant_id_map.keys().cloned().collect();
+    if gateway_ids.is_empty() {
+        return;
+    }
+
+    noise_floor::record_rx_noise_floor(&gateway_ids, &ufs.tx_info, &ufs.rx_info_set);
+}
+
 fn filter_rx_info_by_tenant_id(tenant_id: Uuid, uplink: &mut UplinkFrameSet) -> Result<()> {
     let force_gws_private = config::get_force_gws_private(&uplink.region_config_id)?;
     let mut rx_info_set: Vec<gw::UplinkRxInfo> = Vec::new();
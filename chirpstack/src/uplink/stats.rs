@@ -2,19 +2,66 @@ use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use chrono::{DateTime, Local, Utc};
+use tokio::time::sleep;
 use tracing::{error, info, span, trace, warn, Instrument, Level};
 
 use crate::gateway::backend as gateway_backend;
+use crate::gateway::state as gateway_state;
 use crate::helpers::errors::PrintFullError;
 use crate::storage::{error::Error, fields, gateway, metrics};
 use crate::{config, region};
 use chirpstack_api::{common, gw};
 use lrwn::EUI64;
 
+// Max. number of stats within a batch. Once the batch is full, stats are handled individually.
+const STATS_BATCH_MAX_SIZE: usize = 10_000;
+
+lazy_static! {
+    // This is None when batching is disabled.
+    static ref STATS_BATCH: Mutex<Option<Vec<(EUI64, gw::GatewayStats)>>> = Mutex::new(None);
+}
+
+pub async fn setup() {
+    let conf = config::get();
+    if conf.gateway.stats_batch_interval.is_zero() {
+        return;
+    }
+
+    info!("Setting up gateway stats batch loop");
+    *STATS_BATCH.lock().unwrap() = Some(Vec::new());
+    tokio::spawn(async move {
+        batch_loop().await;
+    });
+}
+
+async fn batch_loop() {
+    let conf = config::get();
+
+    loop {
+        sleep(conf.gateway.stats_batch_interval).await;
+
+        let items = STATS_BATCH
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        if items.is_empty() {
+            continue;
+        }
+
+        let span = span!(Level::INFO, "stats_batch", count = items.len());
+        if let Err(e) = Stats::handle_batch(items).instrument(span).await {
+            error!(error = %e.full(), "Handle gateway stats batch error");
+        }
+    }
+}
+
 pub struct Stats {
     gateway_id: EUI64,
     stats: gw::GatewayStats,
@@ -35,6 +82,12 @@ impl Stats {
             }
         };
 
+        // In case batching is enabled, the stats will be handled by the batch_loop.
+        let s = match add_to_batch(gateway_id, s) {
+            Some(v) => v,
+            None => return,
+        };
+
         let span = span!(Level::INFO, "stats", gateway_id = %gateway_id);
 
         if let Err(e) = Stats::_handle(gateway_id, s).instrument(span).await {
@@ -63,6 +116,7 @@ impl Stats {
         };
 
         ctx.update_gateway_state().await?;
+        gateway_state::handle_online_gateways(&[gateway_id]).await?;
         ctx.save_stats().await?;
         ctx.save_duty_cycle_stats().await?;
         ctx.update_gateway_configuration().await?;
@@ -70,12 +124,119 @@ impl Stats {
         Ok(())
     }
 
-    async fn update_gateway_state(&mut self) -> Result<()> {
-        trace!("Update gateway state");
+    async fn handle_batch(items: Vec<(EUI64, gw::GatewayStats)>) -> Result<()> {
+        let conf = config::get();
+        let mut ctxs: Vec<Stats> = items
+            .into_iter()
+            .map(|(gateway_id, stats)| Stats {
+                gateway_id,
+                stats,
+                gateway: None,
+            })
+            .collect();
+
+        // Update the gateway states within a single transaction. In case the batch contains
+        // multiple stats for the same gateway, the last one wins.
+        let mut changesets: HashMap<EUI64, gateway::GatewayChangeset> = HashMap::new();
+        for ctx in &ctxs {
+            changesets.insert(ctx.gateway_id, ctx.get_gateway_changeset());
+        }
+        let changesets: Vec<(EUI64, gateway::GatewayChangeset)> = changesets.into_iter().collect();
+        let gateways: HashMap<EUI64, gateway::Gateway> =
+            match gateway::partial_update_batch(&changesets).await {
+                Ok(v) => v,
+                Err(e) => {
+                    // A single failing update fails the whole transaction, in which case the
+                    // gateways are updated individually.
+                    warn!(error = %e.full(), "Update gateway states failed, updating gateways individually");
+                    let mut out: Vec<gateway::Gateway> = Vec::with_capacity(changesets.len());
+                    for (gateway_id, gw_cs) in &changesets {
+                        match gateway::partial_update(*gateway_id, gw_cs).await {
+                            Ok(v) => out.push(v),
+                            Err(Error::NotFound(_)) => {}
+                            Err(e) => {
+                                error!(gateway_id = %gateway_id, error = %e.full(), "Update gateway state error");
+                            }
+                        }
+                    }
+                    out
+                }
+            }
+            .into_iter()
+            .map(|gw| (gw.gateway_id, gw))
+            .collect();
+
+        ctxs.retain_mut(|ctx| match gateways.get(&ctx.gateway_id) {
+            Some(gw) => {
+                ctx.gateway = Some(gw.clone());
+                true
+            }
+            None => {
+                // Only log an error in case allow_unknown_gateways is not set. Else it is
+                // expected that the gateway might not exist in the database.
+                if !conf.gateway.allow_unknown_gateways {
+                    error!(gateway_id = %ctx.gateway_id, "Handle gateway stats error, gateway does not exist");
+                }
+                false
+            }
+        });
+
+        let gateway_ids: Vec<EUI64> = gateways.keys().cloned().collect();
+        gateway_state::handle_online_gateways(&gateway_ids).await?;
+
+        // Save the metrics of all gateways using a single pipeline.
+        let mut stats_records: Vec<(String, metrics::Record)> = Vec::new();
+        let mut dc_records: Vec<(String, metrics::Record)> = Vec::new();
+        for ctx in &ctxs {
+            match ctx.get_stats_record() {
+                Ok(v) => stats_records.push((format!("gw:{}", ctx.gateway_id), v)),
+                Err(e) => {
+                    error!(gateway_id = %ctx.gateway_id, error = %e.full(), "Get gateway stats error")
+                }
+            }
 
+            match ctx.get_duty_cycle_stats_record() {
+                Ok(Some(v)) => dc_records.push((format!("gw:dc:{}", ctx.gateway_id), v)),
+                Ok(None) => {}
+                Err(e) => {
+                    error!(gateway_id = %ctx.gateway_id, error = %e.full(), "Get gateway duty-cycle stats error")
+                }
+            }
+        }
+
+        metrics::save_batch(
+            &stats_records,
+            &metrics::Aggregation::default_aggregations(),
+        )
+        .await
+        .context("Save gateway stats")?;
+        metrics::save_batch(&dc_records, &[metrics::Aggregation::MINUTE])
+            .await
+            .context("Save gateway duty-cycle stats")?;
+
+        for ctx in &ctxs {
+            if let Err(e) = ctx.update_gateway_configuration().await {
+                error!(gateway_id = %ctx.gateway_id, error = %e.full(), "Update gateway configuration error");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_gateway_changeset(&self) -> gateway::GatewayChangeset {
         let mut gw_cs = gateway::GatewayChangeset {
             last_seen_at: Some(Some(Utc::now())),
             properties: Some(fields::KeyValue::new(self.stats.metadata.clone())),
+            model: get_inventory_value(&self.stats.metadata, "model"),
+            hal_version: get_inventory_value(&self.stats.metadata, "hal_version"),
+            concentratord_version: get_inventory_value(
+                &self.stats.metadata,
+                "concentratord_version",
+            ),
+            gateway_bridge_version: get_inventory_value(
+                &self.stats.metadata,
+                "gateway_bridge_version",
+            ),
             ..Default::default()
         };
 
@@ -88,6 +249,14 @@ impl Stats {
             }
         }
 
+        gw_cs
+    }
+
+    async fn update_gateway_state(&mut self) -> Result<()> {
+        trace!("Update gateway state");
+
+        let gw_cs = self.get_gateway_changeset();
+
         self.gateway = Some(
             gateway::partial_update(self.gateway_id, &gw_cs)
                 .await
@@ -100,6 +269,20 @@ impl Stats {
     async fn save_stats(&self) -> Result<()> {
         trace!("Saving stats");
 
+        let m = self.get_stats_record()?;
+
+        metrics::save(
+            &format!("gw:{}", self.gateway_id),
+            &m,
+            &metrics::Aggregation::default_aggregations(),
+        )
+        .await
+        .context("Save gateway stats")?;
+
+        Ok(())
+    }
+
+    fn get_stats_record(&self) -> Result<metrics::Record> {
         let mut m = metrics::Record {
             time: match &self.stats.time {
                 Some(v) => DateTime::try_from(*v).map_err(anyhow::Error::msg)?.into(),
@@ -153,21 +336,13 @@ impl Stats {
             m.metrics.insert(format!("rx_dr_{}", k), *v as f64);
         }
 
-        metrics::save(
-            &format!("gw:{}", self.gateway.as_ref().unwrap().gateway_id),
-            &m,
-            &metrics::Aggregation::default_aggregations(),
-        )
-        .await
-        .context("Save gateway stats")?;
-
-        Ok(())
+        Ok(m)
     }
 
     async fn save_duty_cycle_stats(&self) -> Result<()> {
         trace!("Saving duty-cycle stats");
 
-        let duty_cycle_stats = match self.stats.duty_cycle_stats.as_ref() {
+        let m = match self.get_duty_cycle_stats_record()? {
             Some(v) => v,
             None => {
                 // No stats, nothing to do.
@@ -175,6 +350,23 @@ impl Stats {
             }
         };
 
+        metrics::save(
+            &format!("gw:dc:{}", self.gateway_id),
+            &m,
+            &[metrics::Aggregation::MINUTE],
+        )
+        .await
+        .context("Save gateway duty-cycle stats")?;
+
+        Ok(())
+    }
+
+    fn get_duty_cycle_stats_record(&self) -> Result<Option<metrics::Record>> {
+        let duty_cycle_stats = match self.stats.duty_cycle_stats.as_ref() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
         let window: Duration = duty_cycle_stats
             .window
             .map(|v| v.try_into().unwrap_or_default())
@@ -215,15 +407,7 @@ impl Stats {
             m.metrics.insert(dc_window_perc_key, dc_window_perc);
         }
 
-        metrics::save(
-            &format!("gw:dc:{}", self.gateway.as_ref().unwrap().gateway_id),
-            &m,
-            &[metrics::Aggregation::MINUTE],
-        )
-        .await
-        .context("Save gateway duty-cycle stats")?;
-
-        Ok(())
+        Ok(Some(m))
     }
 
     async fn update_gateway_configuration(&self) -> Result<()> {
@@ -365,3 +549,117 @@ fn per_modultation_to_per_dr(
 
     Ok(out)
 }
+
+// Adds the given stats to the batch. In case batching is disabled or the batch is full, the stats
+// are returned, such that these can be handled individually.
+fn add_to_batch(gateway_id: EUI64, s: gw::GatewayStats) -> Option<gw::GatewayStats> {
+    let mut batch = STATS_BATCH.lock().unwrap();
+    match batch.as_mut() {
+        Some(items) if items.len() < STATS_BATCH_MAX_SIZE => {
+            items.push((gateway_id, s));
+            None
+        }
+        _ => Some(s),
+    }
+}
+
+// Returns the inventory (software / hardware) value for the given metadata key. The value is
+// truncated to the max. length of the inventory fields. In case the key is not present, None is
+// returned so that the stored value is left unchanged.
+fn get_inventory_value(metadata: &HashMap<String, String>, key: &str) -> Option<String> {
+    metadata.get(key).map(|v| v.chars().take(100).collect())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::storage;
+    use crate::test;
+
+    #[test]
+    fn test_add_to_batch() {
+        let gateway_id = EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // batching disabled
+        *STATS_BATCH.lock().unwrap() = None;
+        assert!(add_to_batch(gateway_id, Default::default()).is_some());
+
+        // batching enabled
+        *STATS_BATCH.lock().unwrap() = Some(Vec::new());
+        assert!(add_to_batch(gateway_id, Default::default()).is_none());
+        assert_eq!(1, STATS_BATCH.lock().unwrap().as_ref().unwrap().len());
+
+        // batch is full
+        *STATS_BATCH.lock().unwrap() =
+            Some(vec![(gateway_id, Default::default()); STATS_BATCH_MAX_SIZE]);
+        assert!(add_to_batch(gateway_id, Default::default()).is_some());
+        assert_eq!(
+            STATS_BATCH_MAX_SIZE,
+            STATS_BATCH.lock().unwrap().as_ref().unwrap().len()
+        );
+
+        *STATS_BATCH.lock().unwrap() = None;
+    }
+
+    #[tokio::test]
+    async fn test_handle_batch() {
+        let _guard = test::prepare().await;
+
+        let gw =
+            storage::gateway::test::create_gateway(EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]))
+                .await;
+        assert!(gw.last_seen_at.is_none());
+
+        let stats = gw::GatewayStats {
+            rx_packets_received_ok: 10,
+            metadata: [("region_config_id".to_string(), "eu868".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        // The unknown gateway does not fail the batch.
+        Stats::handle_batch(vec![
+            (gw.gateway_id, stats.clone()),
+            (EUI64::from_be_bytes([2, 2, 3, 4, 5, 6, 7, 8]), stats),
+        ])
+        .await
+        .unwrap();
+
+        let gw = storage::gateway::get(&gw.gateway_id).await.unwrap();
+        assert!(gw.last_seen_at.is_some());
+
+        let m = metrics::get(
+            &format!("gw:{}", gw.gateway_id),
+            metrics::Kind::ABSOLUTE,
+            metrics::Aggregation::HOUR,
+            Local::now(),
+            Local::now(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(Some(&10.0), m[0].metrics.get("rx_count"));
+    }
+
+    #[test]
+    fn test_get_gateway_changeset_inventory() {
+        let mut stats = gw::GatewayStats::default();
+        stats.metadata.insert("model".into(), "rak7268".into());
+        stats
+            .metadata
+            .insert("concentratord_version".into(), "4.4.0".into());
+        stats.metadata.insert("hal_version".into(), "x".repeat(120));
+
+        let ctx = Stats {
+            gateway_id: EUI64::from_be_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            stats,
+            gateway: None,
+        };
+
+        let gw_cs = ctx.get_gateway_changeset();
+        assert_eq!(Some("rak7268".to_string()), gw_cs.model);
+        assert_eq!(Some("4.4.0".to_string()), gw_cs.concentratord_version);
+        assert_eq!(Some("x".repeat(100)), gw_cs.hal_version);
+        assert_eq!(None, gw_cs.gateway_bridge_version);
+    }
+}
@@ -0,0 +1,122 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use lrwn::*;
+
+fn uplink_phy_payload(key: &AES128Key) -> PhyPayload {
+    let mut phy = PhyPayload {
+        mhdr: MHDR {
+            m_type: MType::UnconfirmedDataUp,
+            major: Major::LoRaWANR1,
+        },
+        payload: Payload::MACPayload(MACPayload {
+            fhdr: FHDR {
+                devaddr: DevAddr::from_be_bytes([0x01, 0x02, 0x03, 0x04]),
+                f_ctrl: FCtrl {
+                    adr: true,
+                    ..Default::default()
+                },
+                f_cnt: 1024,
+                f_opts: MACCommandSet::new(vec![MACCommand::LinkCheckReq]),
+            },
+            f_port: Some(10),
+            frm_payload: Some(FRMPayload::Raw(vec![0x01; 51])),
+        }),
+        mic: None,
+    };
+
+    phy.encrypt_frm_payload(key).unwrap();
+    phy.set_uplink_data_mic(MACVersion::LoRaWAN1_0, 0, 0, 0, key, key)
+        .unwrap();
+    phy
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let key = AES128Key::from_bytes([0x01; 16]);
+    let b = uplink_phy_payload(&key).to_vec().unwrap();
+
+    c.bench_function("phy_payload_from_slice", |bench| {
+        bench.iter(|| PhyPayload::from_slice(black_box(&b)).unwrap())
+    });
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let key = AES128Key::from_bytes([0x01; 16]);
+    let phy = uplink_phy_payload(&key);
+
+    c.bench_function("phy_payload_to_vec", |bench| {
+        bench.iter(|| black_box(&phy).to_vec().unwrap())
+    });
+
+    let mut buf = Vec::with_capacity(256);
+    c.bench_function("phy_payload_encode_into", |bench| {
+        bench.iter(|| {
+            buf.clear();
+            black_box(&phy).encode_into(&mut buf).unwrap();
+        })
+    });
+}
+
+fn bench_mic(c: &mut Criterion) {
+    let key = AES128Key::from_bytes([0x01; 16]);
+    let phy = uplink_phy_payload(&key);
+
+    c.bench_function("validate_uplink_data_mic_1_0", |bench| {
+        bench.iter(|| {
+            phy.validate_uplink_data_mic(MACVersion::LoRaWAN1_0, 0, 0, 0, &key, &key)
+                .unwrap()
+        })
+    });
+
+    c.bench_function("validate_uplink_data_mic_1_1", |bench| {
+        bench.iter(|| {
+            phy.validate_uplink_data_mic(MACVersion::LoRaWAN1_1, 0, 5, 1, &key, &key)
+                .unwrap()
+        })
+    });
+}
+
+// Simulates the device-session lookup, in which the MIC is validated against each
+// device-session sharing the same DevAddr until a match has been found.
+fn bench_session_lookup(c: &mut Criterion) {
+    let key = AES128Key::from_bytes([0x01; 16]);
+    let phy = uplink_phy_payload(&key);
+
+    let mut keys: Vec<AES128Key> = (0..16)
+        .map(|i| AES128Key::from_bytes([i as u8 + 2; 16]))
+        .collect();
+    keys.push(key);
+
+    c.bench_function("session_lookup_17_candidates", |bench| {
+        bench.iter(|| {
+            keys.iter()
+                .position(|k| {
+                    phy.validate_uplink_data_mic(MACVersion::LoRaWAN1_0, 0, 0, 0, k, k)
+                        .unwrap()
+                })
+                .unwrap()
+        })
+    });
+}
+
+fn bench_decrypt(c: &mut Criterion) {
+    let key = AES128Key::from_bytes([0x01; 16]);
+    let phy = uplink_phy_payload(&key);
+
+    c.bench_function("decrypt_frm_payload", |bench| {
+        bench.iter_batched(
+            || phy.clone(),
+            |mut phy| phy.decrypt_frm_payload(&key).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_decode,
+    bench_encode,
+    bench_mic,
+    bench_session_lookup,
+    bench_decrypt
+);
+criterion_main!(benches);
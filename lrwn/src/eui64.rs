@@ -187,7 +187,7 @@ impl EUI64Prefix {
         self.0
     }
 
-    fn size(&self) -> u64 {
+    pub fn size(&self) -> u64 {
         self.1
     }
 }
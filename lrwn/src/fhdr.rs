@@ -66,21 +66,32 @@ impl FHDR {
     }
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
-        // clone FCtrl as mutable
-        let mut f_ctrl = self.f_ctrl.clone();
-
-        // get f_opts bytes and set f_opts_len to number of f_opts bytes
-        let f_opts = self.f_opts.to_vec()?;
-        f_ctrl.f_opts_len = f_opts.len() as u8;
-
-        let mut b = Vec::with_capacity(7 + f_opts.len());
+        let mut b = Vec::with_capacity(22);
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
 
+    /// Encode the FHDR, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
+        // The f_opts are encoded first (after the fixed-size fields), such that the
+        // f_opts_len can be set without an intermediate allocation.
+        let start = b.len();
         b.extend_from_slice(&self.devaddr.to_le_bytes());
-        b.extend_from_slice(&f_ctrl.to_le_bytes()?);
+        b.push(0); // placeholder for FCtrl
         b.extend_from_slice(&self.f_cnt.to_le_bytes()[0..2]); // only take the 16 lsb
-        b.extend_from_slice(&f_opts);
+        self.f_opts.encode_into(b)?;
 
-        Ok(b)
+        // clone FCtrl as mutable and set f_opts_len to number of f_opts bytes
+        let f_opts_len = b.len() - start - 7;
+        if f_opts_len > 15 {
+            return Err(anyhow!("max value of f_opts_len is 15"));
+        }
+
+        let mut f_ctrl = self.f_ctrl.clone();
+        f_ctrl.f_opts_len = f_opts_len as u8;
+        b[start + 4] = f_ctrl.to_le_bytes()?[0];
+
+        Ok(())
     }
 }
 
@@ -79,19 +79,23 @@ pub fn get_root_wor_s_key(nwk_s_enc_key: &AES128Key) -> Result<AES128Key> {
     Ok(AES128Key::from_slice(block)?)
 }
 
-fn get_s_key(
+/// Returns the key-derivation block of the session-key of the given type:
+///
+/// * 0x01: FNwkSIntKey (NwkSKey for LoRaWAN 1.0)
+/// * 0x02: AppSKey
+/// * 0x03: SNwkSIntKey
+/// * 0x04: NwkSEncKey
+///
+/// The session-key is obtained by encrypting this block (AES128 ECB) using the root-key. This
+/// can be used when the encryption is performed externally, e.g. by a HSM.
+pub fn get_s_key_block(
     opt_neg: bool,
     typ: u8,
-    nwk_key: &AES128Key,
     net_id: &NetID,
     join_eui: &EUI64,
     join_nonce: u32,
     dev_nonce: u16,
-) -> Result<AES128Key> {
-    let key_bytes = nwk_key.to_bytes();
-    let key = GenericArray::from_slice(&key_bytes);
-    let cipher = Aes128::new(key);
-
+) -> [u8; 16] {
     let mut b: [u8; 16] = [0; 16];
 
     b[0] = typ;
@@ -105,20 +109,49 @@ fn get_s_key(
         b[7..9].clone_from_slice(&dev_nonce.to_le_bytes()[0..2]);
     }
 
+    b
+}
+
+/// Returns the key-derivation block of the join-server key of the given type:
+///
+/// * 0x05: JSEncKey
+/// * 0x06: JSIntKey
+///
+/// The key is obtained by encrypting this block (AES128 ECB) using the NwkKey.
+pub fn get_js_key_block(typ: u8, dev_eui: &EUI64) -> [u8; 16] {
+    let mut b: [u8; 16] = [0; 16];
+    b[0] = typ;
+    b[1..9].clone_from_slice(&dev_eui.to_le_bytes());
+
+    b
+}
+
+fn get_s_key(
+    opt_neg: bool,
+    typ: u8,
+    nwk_key: &AES128Key,
+    net_id: &NetID,
+    join_eui: &EUI64,
+    join_nonce: u32,
+    dev_nonce: u16,
+) -> Result<AES128Key> {
+    let key_bytes = nwk_key.to_bytes();
+    let key = GenericArray::from_slice(&key_bytes);
+    let cipher = Aes128::new(key);
+
+    let mut b = get_s_key_block(opt_neg, typ, net_id, join_eui, join_nonce, dev_nonce);
+
     let block = Block::from_mut_slice(&mut b);
     cipher.encrypt_block(block);
 
     Ok(AES128Key::from_slice(block)?)
 }
-
 fn get_js_key(typ: u8, dev_eui: &EUI64, nwk_key: &AES128Key) -> Result<AES128Key> {
     let key_bytes = nwk_key.to_bytes();
     let key = GenericArray::from_slice(&key_bytes);
     let cipher = Aes128::new(key);
 
-    let mut b: [u8; 16] = [0; 16];
-    b[0] = typ;
-    b[1..9].clone_from_slice(&dev_eui.to_le_bytes());
+    let mut b = get_js_key_block(typ, dev_eui);
 
     let block = Block::from_mut_slice(&mut b);
     cipher.encrypt_block(block);
@@ -419,7 +419,12 @@ impl MACCommandSet {
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
 
+    /// Encode the mac-commands, appending the bytes to the given buffer.
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> Result<()> {
         for mac in &self.0 {
             match mac {
                 // LoRaWAN
@@ -615,7 +620,7 @@ impl MACCommandSet {
             };
         }
 
-        Ok(out)
+        Ok(())
     }
 
     pub fn decode_from_raw(&mut self, uplink: bool) -> Result<()> {
@@ -87,6 +87,20 @@ impl Payload {
             Payload::Raw(v) => Ok(v.clone()),
         }
     }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Payload::JoinRequest(v) => v.encode_into(b),
+            Payload::JoinAccept(v) => v.encode_into(b)?,
+            Payload::MACPayload(v) => v.encode_into(b)?,
+            Payload::RejoinRequestType02(v) => v.encode_into(b)?,
+            Payload::RejoinRequestType1(v) => v.encode_into(b)?,
+            Payload::Raw(v) => b.extend_from_slice(v),
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
@@ -118,11 +132,16 @@ impl JoinRequestPayload {
 
     pub fn to_vec(&self) -> Vec<u8> {
         let mut v = Vec::with_capacity(18);
-        v.append(&mut self.join_eui.to_le_bytes().to_vec());
-        v.append(&mut self.dev_eui.to_le_bytes().to_vec());
-        v.append(&mut self.dev_nonce.to_le_bytes().to_vec());
+        self.encode_into(&mut v);
         v
     }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) {
+        b.extend_from_slice(&self.join_eui.to_le_bytes());
+        b.extend_from_slice(&self.dev_eui.to_le_bytes());
+        b.extend_from_slice(&self.dev_nonce.to_le_bytes());
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -172,11 +191,17 @@ impl JoinAcceptPayload {
     }
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut b = Vec::with_capacity(28);
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         if self.rx_delay > 15 {
             return Err(anyhow!("max value of rx_delay is 15"));
         }
 
-        let mut b = Vec::with_capacity(28);
         b.extend_from_slice(&self.join_nonce.to_le_bytes()[..3]);
         b.extend_from_slice(&self.home_netid.to_le_bytes());
         b.extend_from_slice(&self.devaddr.to_le_bytes());
@@ -187,7 +212,7 @@ impl JoinAcceptPayload {
             b.extend_from_slice(&v.to_bytes()?);
         }
 
-        Ok(b)
+        Ok(())
     }
 }
 
@@ -227,6 +252,18 @@ impl FRMPayload {
             FRMPayload::ForwardDownlinkReq(v) => v.to_vec()?,
         })
     }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
+        match self {
+            FRMPayload::Raw(v) => b.extend_from_slice(v),
+            FRMPayload::MACCommandSet(v) => v.encode_into(b)?,
+            FRMPayload::ForwardUplinkReq(v) => v.encode_into(b)?,
+            FRMPayload::ForwardDownlinkReq(v) => v.encode_into(b)?,
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Default)]
@@ -269,6 +306,13 @@ impl MACPayload {
     }
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
+        let mut b = Vec::new();
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         // validation of frm_payload
         if self.frm_payload.is_some() {
             // validate that f_port is set
@@ -286,10 +330,8 @@ impl MACPayload {
             }
         }
 
-        let mut b = Vec::new();
-
         // fhdr
-        b.append(&mut self.fhdr.to_vec()?);
+        self.fhdr.encode_into(b)?;
 
         // f_port
         if let Some(v) = self.f_port {
@@ -298,10 +340,10 @@ impl MACPayload {
 
         // frm_payload
         if let Some(v) = &self.frm_payload {
-            b.append(&mut v.to_vec()?);
+            v.encode_into(b)?;
         }
 
-        Ok(b)
+        Ok(())
     }
 }
 
@@ -346,6 +388,12 @@ impl RejoinRequestType02Payload {
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         let mut b = Vec::with_capacity(14);
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         b.push(match self.rejoin_type {
             JoinType::RejoinType0 => 0x00,
             JoinType::RejoinType2 => 0x02,
@@ -358,7 +406,7 @@ impl RejoinRequestType02Payload {
         b.extend_from_slice(&self.dev_eui.to_le_bytes());
         b.extend_from_slice(&self.rj_count_0.to_le_bytes());
 
-        Ok(b)
+        Ok(())
     }
 }
 
@@ -402,6 +450,12 @@ impl RejoinRequestType1Payload {
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         let mut b = Vec::with_capacity(19);
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
+
+    /// Encode the payload, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         b.push(match self.rejoin_type {
             JoinType::RejoinType1 => 0x01,
             _ => {
@@ -413,7 +467,7 @@ impl RejoinRequestType1Payload {
         b.extend_from_slice(&self.dev_eui.to_le_bytes());
         b.extend_from_slice(&self.rj_count_1.to_le_bytes());
 
-        Ok(b)
+        Ok(())
     }
 }
 
@@ -11,17 +11,21 @@ use serde::Serialize;
 
 use super::maccommand::{MACCommand, MACCommandSet};
 use super::mhdr::{MType, MHDR};
-use super::payload::{FRMPayload, MACPayload, Payload};
+use super::payload::{FRMPayload, Payload};
 #[cfg(feature = "crypto")]
 use super::{
     aes128::AES128Key,
     devaddr::DevAddr,
     eui64::EUI64,
-    payload::{JoinAcceptPayload, JoinType},
+    payload::{JoinAcceptPayload, JoinType, MACPayload},
 };
 use crate::relay::{ForwardDownlinkReq, ForwardUplinkReq};
 use crate::LA_FPORT_RELAY;
 
+// Max. PhyPayload size (MHDR + max. MACPayload size + MIC). This is used as the initial
+// buffer capacity when encoding, to avoid re-allocations.
+const MAX_PHY_PAYLOAD_SIZE: usize = 256;
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum MACVersion {
@@ -501,16 +505,24 @@ pub struct PhyPayload {
 
 impl PhyPayload {
     pub fn to_vec(&self) -> Result<Vec<u8>> {
-        let mut b = Vec::new();
+        let mut b = Vec::with_capacity(MAX_PHY_PAYLOAD_SIZE);
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
 
+    /// Encode the PhyPayload, appending the bytes to the given buffer.
+    ///
+    /// This makes it possible to re-use a buffer when encoding multiple frames, instead of
+    /// allocating a new Vec for each (nested) field as the frame is encoded.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         b.extend_from_slice(&self.mhdr.to_le_bytes());
-        b.append(&mut self.payload.to_vec()?);
+        self.payload.encode_into(b)?;
 
         if let Some(v) = &self.mic {
-            b.extend_from_slice(&v.clone());
+            b.extend_from_slice(v);
         }
 
-        Ok(b)
+        Ok(())
     }
 
     pub fn from_slice(b: &[u8]) -> Result<Self> {
@@ -721,10 +733,11 @@ impl PhyPayload {
         }
 
         if let Payload::JoinAccept(pl) = &self.payload {
-            let mut pt = pl.to_vec()?;
-            pt.extend_from_slice(&self.mic.unwrap());
+            // The plaintext is encrypted in-place.
+            let mut b = pl.to_vec()?;
+            b.extend_from_slice(&self.mic.unwrap());
 
-            if pt.len() % 16 != 0 {
+            if b.len() % 16 != 0 {
                 return Err(anyhow!("plaintext must be a multiple of 16 bytes"));
             }
 
@@ -732,19 +745,15 @@ impl PhyPayload {
             let key = GenericArray::from_slice(&key_bytes);
             let cipher = Aes128::new(key);
 
-            let mut ct = Vec::new();
-
-            for i in 0..(pt.len() / 16) {
-                let index = i * 16;
-
-                let mut block = Block::clone_from_slice(&pt[index..index + 16]);
-                cipher.decrypt_block(&mut block);
-                ct.extend_from_slice(block.as_slice());
+            for block in b.chunks_exact_mut(16) {
+                cipher.decrypt_block(Block::from_mut_slice(block));
             }
 
-            self.payload = Payload::Raw(ct[0..ct.len() - 4].to_vec());
             let mut mic: [u8; 4] = [0; 4];
-            mic.clone_from_slice(&ct[ct.len() - 4..]);
+            mic.clone_from_slice(&b[b.len() - 4..]);
+            b.truncate(b.len() - 4);
+
+            self.payload = Payload::Raw(b);
             self.mic = Some(mic);
             return Ok(());
         }
@@ -766,11 +775,12 @@ impl PhyPayload {
         }
 
         if let Payload::Raw(pl) = &self.payload {
-            // append MIC since it is encrypted too
-            let mut ct = pl.clone();
-            ct.extend_from_slice(&self.mic.unwrap());
+            // append MIC since it is encrypted too, the ciphertext is decrypted in-place
+            let mut pt = Vec::with_capacity(pl.len() + 4);
+            pt.extend_from_slice(pl);
+            pt.extend_from_slice(&self.mic.unwrap());
 
-            if ct.len() % 16 != 0 {
+            if pt.len() % 16 != 0 {
                 return Err(anyhow!("ciphertext must be a multiple of 16 bytes"));
             }
 
@@ -778,14 +788,8 @@ impl PhyPayload {
             let key = GenericArray::from_slice(&key_bytes);
             let cipher = Aes128::new(key);
 
-            let mut pt = Vec::new();
-
-            for i in 0..(ct.len() / 16) {
-                let index = i * 16;
-
-                let mut block = Block::clone_from_slice(&ct[index..index + 16]);
-                cipher.encrypt_block(&mut block);
-                pt.extend_from_slice(block.as_slice());
+            for block in pt.chunks_exact_mut(16) {
+                cipher.encrypt_block(Block::from_mut_slice(block));
             }
 
             let mut mic: [u8; 4] = [0; 4];
@@ -861,14 +865,18 @@ impl PhyPayload {
             let uplink = is_uplink(self.mhdr.m_type);
             let f_port = pl.f_port.unwrap_or(0);
             let b = match &pl.frm_payload {
-                Some(FRMPayload::Raw(v)) => v.clone(),
+                Some(FRMPayload::Raw(v)) => v,
                 _ => {
                     // Nothing to do.
                     return Ok(());
                 }
             };
 
-            return decode_frm_payload(pl, uplink, f_port, b);
+            // The Raw payload is only replaced on success, such that it is not lost in case of
+            // a decoding error.
+            if let Some(v) = decode_frm_payload(uplink, f_port, b)? {
+                pl.frm_payload = Some(v);
+            }
         }
 
         Ok(())
@@ -884,8 +892,8 @@ impl PhyPayload {
             }
 
             let uplink = is_uplink(self.mhdr.m_type);
-            let data = pl.frm_payload.as_ref().unwrap().to_vec()?;
-            let data = encrypt_frm_payload(key, uplink, &pl.fhdr.devaddr, pl.fhdr.f_cnt, &data)?;
+            let mut data = take_frm_payload_bytes(pl)?;
+            encrypt_frm_payload_in_place(key, uplink, &pl.fhdr.devaddr, pl.fhdr.f_cnt, &mut data);
 
             pl.frm_payload = Some(FRMPayload::Raw(data));
             return Ok(());
@@ -906,10 +914,28 @@ impl PhyPayload {
             }
 
             let uplink = is_uplink(self.mhdr.m_type);
-            let data = pl.frm_payload.as_ref().unwrap().to_vec()?;
-            let data = encrypt_frm_payload(key, uplink, &pl.fhdr.devaddr, pl.fhdr.f_cnt, &data)?;
+            let mut data = take_frm_payload_bytes(pl)?;
+            encrypt_frm_payload_in_place(key, uplink, &pl.fhdr.devaddr, pl.fhdr.f_cnt, &mut data);
+
+            match decode_frm_payload(uplink, pl.f_port.unwrap_or(0), &data) {
+                Ok(Some(v)) => pl.frm_payload = Some(v),
+                Ok(None) => pl.frm_payload = Some(FRMPayload::Raw(data)),
+                Err(e) => {
+                    // Restore the encrypted payload, such that it is not lost in case of a
+                    // decoding error.
+                    encrypt_frm_payload_in_place(
+                        key,
+                        uplink,
+                        &pl.fhdr.devaddr,
+                        pl.fhdr.f_cnt,
+                        &mut data,
+                    );
+                    pl.frm_payload = Some(FRMPayload::Raw(data));
+                    return Err(e);
+                }
+            }
 
-            return decode_frm_payload(pl, uplink, pl.f_port.unwrap_or(0), data);
+            return Ok(());
         }
 
         Err(anyhow!("payload must be of type MACPayload"))
@@ -935,42 +961,22 @@ impl PhyPayload {
             // truncate to 16 lsb
             let conf_f_cnt = (conf_f_cnt % (1 << 16)) as u16;
 
-            let mut mic_bytes = Vec::new();
+            let mut mic_bytes = Vec::with_capacity(MAX_PHY_PAYLOAD_SIZE);
             mic_bytes.extend_from_slice(&self.mhdr.to_le_bytes());
-            mic_bytes.extend_from_slice(&self.payload.to_vec()?);
+            self.payload.encode_into(&mut mic_bytes)?;
 
             let mut b0: [u8; 16] = [0; 16];
-            let mut b1: [u8; 16] = [0; 16];
-
             b0[0] = 0x49;
-            b1[0] = 0x49;
 
             // devaddr
             let devaddr_b = pl.fhdr.devaddr.to_le_bytes();
             b0[6..10].clone_from_slice(&devaddr_b);
-            b1[6..10].clone_from_slice(&devaddr_b);
 
             // fcntup
             b0[10..14].clone_from_slice(&pl.fhdr.f_cnt.to_le_bytes());
-            b1[10..14].clone_from_slice(&pl.fhdr.f_cnt.to_le_bytes());
 
             // msg len
             b0[15] = mic_bytes.len() as u8;
-            b1[15] = mic_bytes.len() as u8;
-
-            // remaining b1 fields
-            b1[1..3].clone_from_slice(&conf_f_cnt.to_le_bytes());
-            b1[3] = tx_dr;
-            b1[4] = tx_ch;
-
-            let mut mac = Cmac::<Aes128>::new_from_slice(&s_nwk_s_int_key.to_bytes()).unwrap();
-            mac.update(&b1);
-            mac.update(&mic_bytes);
-
-            let cmac_s = mac.finalize().into_bytes();
-            if cmac_s.len() < 4 {
-                return Err(anyhow!("cmac_s is less than 4 bytes"));
-            }
 
             let mut mac = Cmac::<Aes128>::new_from_slice(&f_nwk_s_int_key.to_bytes()).unwrap();
             mac.update(&b0);
@@ -983,13 +989,29 @@ impl PhyPayload {
 
             let mut mic: [u8; 4] = [0; 4];
             if mac_version == MACVersion::LoRaWAN1_0 {
+                // The cmac_s is not used for LoRaWAN 1.0, thus there is no need to calculate it.
                 mic.clone_from_slice(&cmac_f[0..4]);
                 return Ok(mic);
-            } else {
-                mic[0..2].clone_from_slice(&cmac_s[0..2]);
-                mic[2..4].clone_from_slice(&cmac_f[0..2]);
-                return Ok(mic);
             }
+
+            // b1 equals b0, except for the following fields
+            let mut b1 = b0;
+            b1[1..3].clone_from_slice(&conf_f_cnt.to_le_bytes());
+            b1[3] = tx_dr;
+            b1[4] = tx_ch;
+
+            let mut mac = Cmac::<Aes128>::new_from_slice(&s_nwk_s_int_key.to_bytes()).unwrap();
+            mac.update(&b1);
+            mac.update(&mic_bytes);
+
+            let cmac_s = mac.finalize().into_bytes();
+            if cmac_s.len() < 4 {
+                return Err(anyhow!("cmac_s is less than 4 bytes"));
+            }
+
+            mic[0..2].clone_from_slice(&cmac_s[0..2]);
+            mic[2..4].clone_from_slice(&cmac_f[0..2]);
+            return Ok(mic);
         }
 
         Err(anyhow!("payload must be of type MACPayload"))
@@ -1013,9 +1035,9 @@ impl PhyPayload {
             let conf_f_cnt = (conf_f_cnt % (1 << 16)) as u16;
 
             // mic bytes
-            let mut mic_bytes = Vec::new();
+            let mut mic_bytes = Vec::with_capacity(MAX_PHY_PAYLOAD_SIZE);
             mic_bytes.extend_from_slice(&self.mhdr.to_le_bytes());
-            mic_bytes.extend_from_slice(&self.payload.to_vec()?);
+            self.payload.encode_into(&mut mic_bytes)?;
 
             // b0
             let mut b0: [u8; 16] = [0; 16];
@@ -1045,11 +1067,7 @@ impl PhyPayload {
 
     #[cfg(feature = "crypto")]
     fn calculate_upink_join_mic(&self, key: &AES128Key) -> Result<[u8; 4]> {
-        // mic bytes
-        let mut mic_bytes = Vec::new();
-
-        mic_bytes.extend_from_slice(&self.mhdr.to_le_bytes());
-        mic_bytes.extend_from_slice(&self.payload.to_vec()?);
+        let mic_bytes = self.join_request_mic_bytes()?;
 
         let mut mac = Cmac::<Aes128>::new_from_slice(&key.to_bytes()).unwrap();
         mac.update(&mic_bytes);
@@ -1072,6 +1090,42 @@ impl PhyPayload {
         dev_nonce: u16,
         key: &AES128Key,
     ) -> Result<[u8; 4]> {
+        let mic_bytes = self.join_accept_mic_bytes(join_req_type, join_eui, dev_nonce)?;
+
+        let mut mac = Cmac::<Aes128>::new_from_slice(&key.to_bytes()).unwrap();
+        mac.update(&mic_bytes);
+
+        let hash = mac.finalize().into_bytes();
+        if hash.len() < 4 {
+            return Err(anyhow!("hash is less than 4 bytes"));
+        }
+
+        let mut mic: [u8; 4] = [0; 4];
+        mic.clone_from_slice(&hash[0..4]);
+        Ok(mic)
+    }
+
+    /// Returns the bytes over which the join-request MIC is calculated (AES128 CMAC). This can
+    /// be used when the MIC is calculated externally, e.g. by a HSM.
+    #[cfg(feature = "crypto")]
+    pub fn join_request_mic_bytes(&self) -> Result<Vec<u8>> {
+        let mut mic_bytes = Vec::with_capacity(MAX_PHY_PAYLOAD_SIZE);
+
+        mic_bytes.extend_from_slice(&self.mhdr.to_le_bytes());
+        self.payload.encode_into(&mut mic_bytes)?;
+
+        Ok(mic_bytes)
+    }
+
+    /// Returns the bytes over which the join-accept MIC is calculated (AES128 CMAC). This can
+    /// be used when the MIC is calculated externally, e.g. by a HSM.
+    #[cfg(feature = "crypto")]
+    pub fn join_accept_mic_bytes(
+        &self,
+        join_req_type: JoinType,
+        join_eui: &EUI64,
+        dev_nonce: u16,
+    ) -> Result<Vec<u8>> {
         if let Payload::JoinAccept(pl) = &self.payload {
             let mut mic_bytes = Vec::new();
 
@@ -1091,19 +1145,9 @@ impl PhyPayload {
             mic_bytes.extend_from_slice(&self.mhdr.to_le_bytes());
 
             // JoinNonce | NetID | DevAddr | DLSettings | RxDelay | CFList
-            mic_bytes.extend_from_slice(&pl.to_vec()?);
-
-            let mut mac = Cmac::<Aes128>::new_from_slice(&key.to_bytes()).unwrap();
-            mac.update(&mic_bytes);
+            pl.encode_into(&mut mic_bytes)?;
 
-            let hash = mac.finalize().into_bytes();
-            if hash.len() < 4 {
-                return Err(anyhow!("hash is less than 4 bytes"));
-            }
-
-            let mut mic: [u8; 4] = [0; 4];
-            mic.clone_from_slice(&hash[0..4]);
-            return Ok(mic);
+            return Ok(mic_bytes);
         }
 
         Err(anyhow!("payload must be of type JoinAcceptPayload"))
@@ -1136,7 +1180,7 @@ pub fn encrypt_f_opts(
     let key = GenericArray::from_slice(&key_bytes);
     let cipher = Aes128::new(key);
 
-    let mut a = vec![0; 16];
+    let mut a: [u8; 16] = [0; 16];
     a[0] = 0x01;
     if a_fcnt_down {
         a[4] = 0x02;
@@ -1155,12 +1199,7 @@ pub fn encrypt_f_opts(
     let block = Block::from_mut_slice(&mut a);
     cipher.encrypt_block(block);
 
-    let mut out = vec![0; data.len()];
-    for i in 0..data.len() {
-        out[i] = data[i] ^ block[i];
-    }
-
-    Ok(out)
+    Ok(data.iter().zip(block.iter()).map(|(d, k)| d ^ k).collect())
 }
 
 /// Encrypt (and decrypt) the frm_payload.
@@ -1173,21 +1212,28 @@ pub fn encrypt_frm_payload(
     f_cnt: u32,
     data: &[u8],
 ) -> Result<Vec<u8>> {
-    use aes::cipher::KeyInit;
-
     let mut data = data.to_vec();
-    let data_len = data.len();
+    encrypt_frm_payload_in_place(key, uplink, devaddr, f_cnt, &mut data);
+    Ok(data)
+}
 
-    // make pt length multiple of 16
-    if data.len() % 16 != 0 {
-        data.append(&mut vec![0; 16 - (data.len() % 16)]);
-    }
+/// Encrypt (and decrypt) the frm_payload in-place.
+/// Unlike encrypt_frm_payload, this does not allocate a new buffer for the result.
+#[cfg(feature = "crypto")]
+pub fn encrypt_frm_payload_in_place(
+    key: &AES128Key,
+    uplink: bool,
+    devaddr: &DevAddr,
+    f_cnt: u32,
+    data: &mut [u8],
+) {
+    use aes::cipher::KeyInit;
 
     let key_bytes = key.to_bytes();
     let key = GenericArray::from_slice(&key_bytes);
     let cipher = Aes128::new(key);
 
-    let mut a = vec![0; 16];
+    let mut a: [u8; 16] = [0; 16];
     a[0] = 0x01;
     if !uplink {
         a[5] = 0x01;
@@ -1196,18 +1242,33 @@ pub fn encrypt_frm_payload(
     a[6..10].clone_from_slice(&devaddr.to_le_bytes());
     a[10..14].clone_from_slice(&f_cnt.to_le_bytes());
 
-    for i in 0..(data.len() / 16) {
+    // The last chunk might be shorter than 16 bytes, in which case only the first bytes of the
+    // key-stream block are used.
+    for (i, chunk) in data.chunks_mut(16).enumerate() {
         a[15] = (i + 1) as u8;
 
         let mut block = Block::clone_from_slice(&a);
         cipher.encrypt_block(&mut block);
 
-        for j in 0..16 {
-            data[(i * 16) + j] ^= block[j];
+        for (d, k) in chunk.iter_mut().zip(block.iter()) {
+            *d ^= k;
         }
     }
+}
 
-    Ok(data[0..data_len].to_vec())
+// Takes the frm_payload bytes out of the MACPayload. In case of a Raw payload, this re-uses the
+// existing buffer instead of encoding the payload into a new buffer.
+#[cfg(feature = "crypto")]
+fn take_frm_payload_bytes(pl: &mut MACPayload) -> Result<Vec<u8>> {
+    match pl.frm_payload.take() {
+        Some(FRMPayload::Raw(v)) => Ok(v),
+        Some(v) => {
+            let b = v.to_vec();
+            pl.frm_payload = Some(v);
+            b
+        }
+        None => Ok(Vec::new()),
+    }
 }
 
 fn is_uplink(m_type: MType) -> bool {
@@ -1221,33 +1282,35 @@ fn is_uplink(m_type: MType) -> bool {
     }
 }
 
-fn decode_frm_payload(pl: &mut MACPayload, uplink: bool, f_port: u8, b: Vec<u8>) -> Result<()> {
-    if f_port == 0 {
-        let mut macs = MACCommandSet::new(vec![MACCommand::Raw(b)]);
+// Decodes the (decrypted) frm_payload bytes based on the f_port. This returns None when the
+// payload does not need to be decoded, in which case it must be kept as Raw payload.
+fn decode_frm_payload(uplink: bool, f_port: u8, b: &[u8]) -> Result<Option<FRMPayload>> {
+    Ok(if f_port == 0 {
+        let mut macs = MACCommandSet::new(vec![MACCommand::Raw(b.to_vec())]);
         macs.decode_from_raw(uplink)?;
-        pl.frm_payload = Some(FRMPayload::MACCommandSet(macs));
+        Some(FRMPayload::MACCommandSet(macs))
     } else if f_port == LA_FPORT_RELAY && uplink {
-        pl.frm_payload = Some(FRMPayload::ForwardUplinkReq(ForwardUplinkReq::from_slice(
-            &b,
-        )?));
+        Some(FRMPayload::ForwardUplinkReq(ForwardUplinkReq::from_slice(
+            b,
+        )?))
     } else if f_port == LA_FPORT_RELAY && !uplink {
-        pl.frm_payload = Some(FRMPayload::ForwardDownlinkReq(
-            ForwardDownlinkReq::from_slice(&b)?,
-        ));
+        Some(FRMPayload::ForwardDownlinkReq(
+            ForwardDownlinkReq::from_slice(b)?,
+        ))
     } else {
-        pl.frm_payload = Some(FRMPayload::Raw(b));
-    }
-
-    Ok(())
+        None
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
+    use super::super::devaddr::DevAddr;
     use super::super::eui64::EUI64;
+    use super::super::fhdr::{FCtrl, FHDR};
     use super::super::mhdr::Major;
-    use super::super::payload::JoinRequestPayload;
+    use super::super::payload::{JoinRequestPayload, MACPayload};
     use super::*;
 
     struct PhyPayloadTest {
@@ -1315,4 +1378,117 @@ mod tests {
             assert_eq!(tst.phy, PhyPayload::from_slice(&tst.bytes).unwrap());
         }
     }
+
+    #[test]
+    fn test_encode_into() {
+        let phy = PhyPayload {
+            mhdr: MHDR {
+                m_type: MType::UnconfirmedDataUp,
+                major: Major::LoRaWANR1,
+            },
+            payload: Payload::MACPayload(MACPayload {
+                fhdr: FHDR {
+                    devaddr: DevAddr::from_be_bytes([0x01, 0x02, 0x03, 0x04]),
+                    f_ctrl: FCtrl {
+                        adr: true,
+                        ..Default::default()
+                    },
+                    f_cnt: 10,
+                    f_opts: MACCommandSet::new(vec![MACCommand::LinkCheckReq]),
+                },
+                f_port: Some(1),
+                frm_payload: Some(FRMPayload::Raw(vec![0x01, 0x02, 0x03])),
+            }),
+            mic: Some([0x01, 0x02, 0x03, 0x04]),
+        };
+
+        // The encoded bytes must be appended to the existing buffer content.
+        let mut b = vec![0xff];
+        phy.encode_into(&mut b).unwrap();
+
+        let mut expected = vec![0xff];
+        expected.extend_from_slice(&phy.to_vec().unwrap());
+        assert_eq!(expected, b);
+
+        assert_eq!(
+            vec![
+                0x40, 0x04, 0x03, 0x02, 0x01, 0x81, 0x0a, 0x00, 0x02, 0x01, 0x01, 0x02, 0x03, 0x01,
+                0x02, 0x03, 0x04
+            ],
+            phy.to_vec().unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn test_encrypt_frm_payload_in_place() {
+        let key = AES128Key::from_bytes([
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x07, 0x08,
+        ]);
+        let devaddr = DevAddr::from_be_bytes([0x01, 0x02, 0x03, 0x04]);
+
+        // Not a multiple of 16 bytes, to test the handling of the last (partial) block.
+        let pt: Vec<u8> = (0..20).collect();
+
+        let mut b = pt.clone();
+        encrypt_frm_payload_in_place(&key, true, &devaddr, 10, &mut b);
+        assert_eq!(pt.len(), b.len());
+        assert_ne!(pt, b);
+
+        // The in-place function must match the allocating function.
+        assert_eq!(
+            encrypt_frm_payload(&key, true, &devaddr, 10, &pt).unwrap(),
+            b
+        );
+
+        // Encrypting the ciphertext again must return the plaintext.
+        encrypt_frm_payload_in_place(&key, true, &devaddr, 10, &mut b);
+        assert_eq!(pt, b);
+    }
+
+    fn invalid_mac_commands_phy() -> PhyPayload {
+        PhyPayload {
+            mhdr: MHDR {
+                m_type: MType::UnconfirmedDataUp,
+                major: Major::LoRaWANR1,
+            },
+            payload: Payload::MACPayload(MACPayload {
+                fhdr: FHDR {
+                    devaddr: DevAddr::from_be_bytes([0x01, 0x02, 0x03, 0x04]),
+                    f_ctrl: FCtrl::default(),
+                    f_cnt: 10,
+                    f_opts: MACCommandSet::new(vec![]),
+                },
+                f_port: Some(0),
+                // LinkADRAns without its (1 byte) payload.
+                frm_payload: Some(FRMPayload::Raw(vec![0x03])),
+            }),
+            mic: None,
+        }
+    }
+
+    #[test]
+    fn test_decode_frm_payload_error_keeps_payload() {
+        let mut phy = invalid_mac_commands_phy();
+        assert!(phy.decode_frm_payload().is_err());
+        assert_eq!(invalid_mac_commands_phy(), phy);
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn test_decrypt_frm_payload_error_keeps_payload() {
+        let key = AES128Key::from_bytes([
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x07, 0x08,
+        ]);
+
+        let mut phy = invalid_mac_commands_phy();
+        phy.encrypt_frm_payload(&key).unwrap();
+        let encrypted = phy.clone();
+
+        // The encrypted payload must be kept on a decoding error.
+        assert!(phy.decrypt_frm_payload(&key).is_err());
+        assert_eq!(encrypted, phy);
+    }
 }
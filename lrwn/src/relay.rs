@@ -71,10 +71,15 @@ impl ForwardUplinkReq {
 
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         let mut b = Vec::new();
+        self.encode_into(&mut b)?;
+        Ok(b)
+    }
+
+    /// Encode the request, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
         b.extend_from_slice(&self.metadata.to_bytes()?);
         b.extend_from_slice(&encode_freq(self.frequency)?);
-        b.extend_from_slice(&self.payload.to_vec()?);
-        Ok(b)
+        self.payload.encode_into(b)
     }
 }
 
@@ -94,6 +99,11 @@ impl ForwardDownlinkReq {
     pub fn to_vec(&self) -> Result<Vec<u8>> {
         self.payload.to_vec()
     }
+
+    /// Encode the request, appending the bytes to the given buffer.
+    pub fn encode_into(&self, b: &mut Vec<u8>) -> Result<()> {
+        self.payload.encode_into(b)
+    }
 }
 
 #[cfg(test)]